@@ -0,0 +1,72 @@
+//! Merges of real game files, for comparing merge changes. Save a baseline
+//! before a change with `cargo bench -p uk-content --bench merge --
+//! --save-baseline before`, then compare with `--baseline before` after it.
+use criterion::{criterion_group, criterion_main, Criterion};
+use roead::{aamp::ParameterIO, byml::Byml, sarc::Sarc};
+use uk_content::{actor::params::aiprog::AIProgram, map::unit::MapUnit, prelude::Mergeable};
+
+const TEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test");
+const AIPROG: &str = "Actor/AIProgram/Guardian_A.baiprog";
+
+fn aiprog_pio(pack: &str) -> ParameterIO {
+    let pack = Sarc::new(
+        roead::yaz0::decompress(
+            std::fs::read(format!("{TEST}/Actor/Pack/{pack}.sbactorpack")).unwrap(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    ParameterIO::from_binary(pack.get_data(AIPROG).unwrap()).unwrap()
+}
+
+fn map_unit(file: &str) -> MapUnit {
+    let data =
+        roead::yaz0::decompress(std::fs::read(format!("{TEST}/Map/MainField/D-3/{file}")).unwrap())
+            .unwrap();
+    (&Byml::from_binary(data).unwrap()).try_into().unwrap()
+}
+
+fn bench_merge<T: Mergeable>(c: &mut Criterion, name: &str, base: &T, modded: &T) {
+    let mut group = c.benchmark_group(name);
+    let diff = base.diff(modded);
+    group.bench_function("merge", |b| {
+        b.iter(|| criterion::black_box(base.merge(&diff)))
+    });
+    // Merging a mod which changed nothing is common, and should cost little
+    // more than a clone
+    let empty = base.diff(base);
+    group.bench_function("merge_unchanged", |b| {
+        b.iter(|| criterion::black_box(base.merge(&empty)))
+    });
+    group.finish();
+}
+
+fn merges(c: &mut Criterion) {
+    let (base, modded) = (
+        aiprog_pio("Enemy_Guardian_A"),
+        aiprog_pio("Enemy_Guardian_A_Mod"),
+    );
+    bench_merge(c, "aamp", &base, &modded);
+    let (base, modded) = (
+        AIProgram::try_from(&base).unwrap(),
+        AIProgram::try_from(&modded).unwrap(),
+    );
+    bench_merge(c, "aiprog", &base, &modded);
+    // Every merged AI program is parsed from and written back to binary
+    let data = ParameterIO::from(modded).to_binary();
+    c.bench_function("aiprog/round_trip", |b| {
+        b.iter(|| {
+            let pio = ParameterIO::from_binary(&data).unwrap();
+            let prog = AIProgram::try_from(&pio).unwrap();
+            criterion::black_box(ParameterIO::from(prog).to_binary())
+        })
+    });
+    let (base, modded) = (
+        map_unit("D-3_Dynamic.smubin"),
+        map_unit("D-3_Dynamic.mod.smubin"),
+    );
+    bench_merge(c, "map_unit", &base, &modded);
+}
+
+criterion_group!(benches, merges);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+//! Diffs and merges of generated files at several sizes, to show how the cost
+//! grows with the size of a file. Run with `cargo bench -p uk-content
+//! --features fixtures --bench sized`.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use uk_content::{fixtures, prelude::Mergeable};
+
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+/// How often a mod changes an entry, about the same as a typical balance mod.
+const EVERY: usize = 20;
+
+fn bench_sized<T: Mergeable>(
+    c: &mut Criterion,
+    name: &str,
+    base: impl Fn(usize) -> T,
+    modded: impl Fn(usize, usize) -> T,
+) {
+    let mut group = c.benchmark_group(name);
+    for size in SIZES {
+        let (base, modded) = (base(size), modded(size, EVERY));
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("diff", size), &modded, |b, modded| {
+            b.iter(|| criterion::black_box(base.diff(modded)))
+        });
+        let diff = base.diff(&modded);
+        group.bench_with_input(BenchmarkId::new("merge", size), &diff, |b, diff| {
+            b.iter(|| criterion::black_box(base.merge(diff)))
+        });
+    }
+    group.finish();
+}
+
+fn sized(c: &mut Criterion) {
+    bench_sized(
+        c,
+        "sized_aamp",
+        fixtures::parameter_io,
+        fixtures::modded_parameter_io,
+    );
+    bench_sized(c, "sized_byml", fixtures::byml, fixtures::modded_byml);
+}
+
+criterion_group!(benches, sized);
+criterion_main!(benches);
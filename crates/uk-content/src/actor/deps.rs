@@ -0,0 +1,441 @@
+//! What files an actor needs, for working out which to ship with a custom
+//! actor. Starting from the actor link in its pack, each file it names is
+//! looked for in the pack, then in the mod's loose files, then in the game
+//! dump, and the links in whatever is found are followed in turn.
+//!
+//! Only files are followed. Actors named in drop tables, shops and the like
+//! are actors in their own right, with packs of their own.
+use std::{borrow::Cow, collections::BTreeSet};
+
+use roead::{aamp::*, sarc::Sarc, yaz0};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    info::ActorInfo,
+    params::{
+        aiprog::AIProgram, aischedule::AISchedule, animinfo::AnimationInfo, aslist::ASList,
+        atcl::AttClient, atcllist::AttClientList, aware::Awareness, bonectrl::BoneControl,
+        chemical::Chemical, damage::DamageParam, drop::DropTable, general::GeneralParamList,
+        life::LifeCondition, lod::Lod, modellist::ModelList, physics::Physics, r#as::AS,
+        recipe::Recipe, rgbw::RagdollBlendWeight, rgconfig::RagdollConfig,
+        rgconfiglist::RagdollConfigList, shop::ShopData, umii::UMii,
+    },
+    ParameterResource,
+};
+use crate::{Result, UKError};
+
+type PathFn = fn(&str) -> std::string::String;
+
+/// The files an actor link names, by the link target naming them.
+const USERS: &[(&str, PathFn)] = &[
+    ("AIProgramUser", AIProgram::path),
+    ("AIScheduleUser", AISchedule::path),
+    ("AnimationInfo", AnimationInfo::path),
+    ("ASUser", ASList::path),
+    ("AttentionUser", AttClientList::path),
+    ("AwarenessUser", Awareness::path),
+    ("BoneControlUser", BoneControl::path),
+    ("ChemicalUser", Chemical::path),
+    ("DamageParamUser", DamageParam::path),
+    ("DropTableUser", DropTable::path),
+    ("GParamUser", GeneralParamList::path),
+    ("LifeConditionUser", LifeCondition::path),
+    ("LODUser", Lod::path),
+    ("ModelUser", ModelList::path),
+    ("PhysicsUser", Physics::path),
+    ("RagdollBlendWeightUser", RagdollBlendWeight::path),
+    ("RagdollConfigListUser", RagdollConfigList::path),
+    ("RecipeUser", Recipe::path),
+    ("ShopDataUser", ShopData::path),
+    ("UMiiUser", UMii::path),
+];
+
+/// Parameters in physics files which name files of their own, with the
+/// folder those files are in.
+const PHYSICS_FILES: &[(&str, &str)] = &[
+    ("ragdoll_setup_file_path", "Physics/Ragdoll"),
+    ("support_bone_setup_file_path", "Physics/SupportBone"),
+    ("cloth_setup_file_path", "Physics/Cloth"),
+    ("setup_file_path", "Physics/RigidBody"),
+];
+
+type LinkFn = fn(&ParameterIO) -> Vec<(&'static str, std::string::String)>;
+
+/// How to find the links in each type of file, by the folder it is in.
+const RULES: &[(&str, LinkFn)] = &[
+    ("Actor/ActorLink/", actor_link),
+    ("Actor/ASList/", |pio| {
+        list_files(pio, "ASDefines", "Filename", "AS", AS::path)
+    }),
+    ("Actor/AttClientList/", |pio| {
+        list_files(pio, "AttClients", "FileName", "AttClient", AttClient::path)
+    }),
+    ("Actor/RagdollConfigList/", |pio| {
+        list_files(
+            pio,
+            "ImpulseParamList",
+            "FileName",
+            "RagdollConfig",
+            RagdollConfig::path,
+        )
+    }),
+    ("Actor/ModelList/", model_list),
+    ("Actor/Physics/", physics),
+];
+
+/// A file name from a parameter, unless it is empty or a placeholder.
+fn named(param: Option<&Parameter>) -> Option<&str> {
+    param
+        .and_then(|p| p.as_str().ok())
+        .filter(|name| !name.is_empty() && *name != "Dummy")
+}
+
+fn actor_link(pio: &ParameterIO) -> Vec<(&'static str, std::string::String)> {
+    let Some(targets) = pio.object("LinkTarget") else {
+        return vec![];
+    };
+    USERS
+        .iter()
+        .filter_map(|(user, path)| Some((*user, path(named(targets.get(*user))?))))
+        .collect()
+}
+
+/// Files named by a parameter of each object in a list.
+fn list_files(
+    pio: &ParameterIO,
+    list: &str,
+    key: &str,
+    link: &'static str,
+    path: PathFn,
+) -> Vec<(&'static str, std::string::String)> {
+    pio.list(list)
+        .map(|list| {
+            list.objects
+                .0
+                .values()
+                .filter_map(|obj| Some((link, path(named(obj.get(key))?))))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn model_list(pio: &ParameterIO) -> Vec<(&'static str, std::string::String)> {
+    pio.list("ModelData")
+        .map(|list| {
+            list.lists
+                .0
+                .values()
+                .filter_map(|data| named(data.object("Base")?.get("Folder")))
+                .map(|folder| ("Model", format!("Model/{folder}.sbfres")))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn physics(pio: &ParameterIO) -> Vec<(&'static str, std::string::String)> {
+    fn walk(list: &ParameterList, found: &mut Vec<(&'static str, std::string::String)>) {
+        for obj in list.objects.0.values() {
+            for (key, param) in obj.0.iter() {
+                let Some((name, folder)) = PHYSICS_FILES
+                    .iter()
+                    .find(|(name, _)| hash_name(name) == key.hash())
+                else {
+                    continue;
+                };
+                if let Some(file) = named(Some(param)) {
+                    found.push((*name, format!("{folder}/{file}")));
+                }
+            }
+        }
+        for list in list.lists.0.values() {
+            walk(list, found);
+        }
+    }
+    let mut found = vec![];
+    walk(&pio.param_root, &mut found);
+    found
+}
+
+/// The links in a file, if it is a type which has any.
+fn links(path: &str, data: &[u8]) -> Vec<(&'static str, std::string::String)> {
+    let Some((_, rule)) = RULES.iter().find(|(folder, _)| path.starts_with(folder)) else {
+        return vec![];
+    };
+    let data = if data.starts_with(b"Yaz0") {
+        match yaz0::decompress(data) {
+            Ok(data) => Cow::Owned(data),
+            Err(_) => return vec![],
+        }
+    } else {
+        Cow::Borrowed(data)
+    };
+    match ParameterIO::from_binary(&*data) {
+        Ok(pio) => rule(&pio),
+        Err(e) => {
+            log::warn!("Could not read {path} to follow its links: {e}");
+            vec![]
+        }
+    }
+}
+
+/// Where a file an actor depends on was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Source {
+    /// In the actor pack itself.
+    Pack,
+    /// Among the loose files of the mod.
+    Mod,
+    /// Not in the mod, but in the game dump.
+    Dump,
+    /// Nowhere to be found.
+    Missing,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Source::Pack => "in pack",
+            Source::Mod => "in mod",
+            Source::Dump => "from dump",
+            Source::Missing => "MISSING",
+        })
+    }
+}
+
+/// A file an actor depends on, with the files it depends on in turn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dependency {
+    /// What names the file, such as the link target for files named by the
+    /// actor link.
+    pub link:     std::string::String,
+    pub path:     std::string::String,
+    pub source:   Source,
+    /// The files this one names, unless it appears earlier in the graph.
+    pub children: Vec<Dependency>,
+}
+
+impl Dependency {
+    /// This file and everything under it, depth first.
+    pub fn iter(&self) -> impl Iterator<Item = &Dependency> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let next = stack.pop()?;
+            stack.extend(next.children.iter().rev());
+            Some(next)
+        })
+    }
+
+    /// The files which could not be found anywhere.
+    pub fn missing(&self) -> impl Iterator<Item = &Dependency> {
+        self.iter().filter(|dep| dep.source == Source::Missing)
+    }
+
+    fn write_tree(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} [{}] ({})",
+            "",
+            self.path,
+            self.link,
+            self.source,
+            indent = depth * 2
+        )?;
+        self.children
+            .iter()
+            .try_for_each(|child| child.write_tree(f, depth + 1))
+    }
+}
+
+impl std::fmt::Display for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+struct Resolver<'a> {
+    pack: &'a Sarc<'a>,
+    mod_files: &'a dyn Fn(&str) -> Option<Vec<u8>>,
+    dump: &'a dyn Fn(&str) -> Option<Vec<u8>>,
+    seen: BTreeSet<std::string::String>,
+}
+
+impl Resolver<'_> {
+    fn find(&self, path: &str) -> (Source, Option<Vec<u8>>) {
+        if let Some(data) = self.pack.get_data(path) {
+            (Source::Pack, Some(data.to_vec()))
+        } else if let Some(data) = (self.mod_files)(path) {
+            (Source::Mod, Some(data))
+        } else if let Some(data) = (self.dump)(path) {
+            (Source::Dump, Some(data))
+        } else {
+            (Source::Missing, None)
+        }
+    }
+
+    fn resolve(&mut self, link: &str, path: std::string::String) -> Dependency {
+        let (source, data) = self.find(&path);
+        let children = match data {
+            Some(data) if self.seen.insert(path.clone()) => {
+                links(&path, &data)
+                    .into_iter()
+                    .map(|(link, path)| self.resolve(link, path))
+                    .collect()
+            }
+            _ => vec![],
+        };
+        Dependency {
+            link: link.into(),
+            path,
+            source,
+            children,
+        }
+    }
+}
+
+/// The path of an actor's pack, if the actor info lists the actor.
+pub fn pack_path(info: &ActorInfo, name: &str) -> Option<std::string::String> {
+    info.0
+        .contains_key(hash_name(name))
+        .then(|| format!("Actor/Pack/{name}.sbactorpack"))
+}
+
+/// Finds every file the actor in a pack depends on, starting from its actor
+/// link. `mod_files` and `dump` look up loose files by their path in the game
+/// files, returning their data if they have them.
+pub fn dependencies(
+    pack: &[u8],
+    mod_files: impl Fn(&str) -> Option<Vec<u8>>,
+    dump: impl Fn(&str) -> Option<Vec<u8>>,
+) -> Result<Dependency> {
+    let pack = Sarc::new(yaz0::decompress_if(pack))?;
+    let link = pack
+        .files()
+        .filter_map(|file| file.name)
+        .find(|name| name.starts_with("Actor/ActorLink/") && name.ends_with(".bxml"))
+        .ok_or(UKError::Other("Actor pack has no actor link"))?;
+    let mut resolver = Resolver {
+        pack: &pack,
+        mod_files: &mod_files,
+        dump: &dump,
+        seen: BTreeSet::new(),
+    };
+    Ok(resolver.resolve("ActorLink", link.into()))
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use roead::{sarc::SarcWriter, Endian};
+
+    use super::*;
+
+    fn object(params: &[(&str, &str)]) -> ParameterObject {
+        params
+            .iter()
+            .fold(ParameterObject::new(), |obj, (key, value)| {
+                obj.with_parameter(*key, Parameter::StringRef((*value).into()))
+            })
+    }
+
+    /// An actor whose pack has its link, AS list and model list, but not
+    /// all of what they name.
+    fn fixture() -> Vec<u8> {
+        let link = ParameterIO::new().with_object(
+            "LinkTarget",
+            object(&[
+                ("ASUser", "Test"),
+                ("ModelUser", "Test"),
+                ("GParamUser", "Test"),
+                ("DropTableUser", "Gone"),
+                ("PhysicsUser", "Dummy"),
+                ("AIProgramUser", ""),
+            ]),
+        );
+        let aslist = ParameterIO::new().with_list(
+            "ASDefines",
+            ParameterList::new()
+                .with_object(
+                    "ASDefine_0",
+                    object(&[("Name", "Wait"), ("Filename", "Test_Wait")]),
+                )
+                .with_object(
+                    "ASDefine_1",
+                    object(&[("Name", "Run"), ("Filename", "Test_Run")]),
+                ),
+        );
+        let modellist = ParameterIO::new().with_list(
+            "ModelData",
+            ParameterList::new().with_list(
+                "ModelData_0",
+                ParameterList::new().with_object("Base", object(&[("Folder", "Test_Model")])),
+            ),
+        );
+        let mut pack = SarcWriter::new(Endian::Big);
+        pack.add_file("Actor/ActorLink/Test.bxml", link.to_binary());
+        pack.add_file("Actor/ASList/Test.baslist", aslist.to_binary());
+        pack.add_file("Actor/AS/Test_Wait.bas", b"AAMP".to_vec());
+        pack.add_file("Actor/ModelList/Test.bmodellist", modellist.to_binary());
+        pack.to_binary()
+    }
+
+    #[test]
+    fn gaps() {
+        let pack = fixture();
+        let mod_files: BTreeMap<&str, Vec<u8>> =
+            [("Actor/GeneralParamList/Test.bgparamlist", vec![])].into();
+        let dump: BTreeMap<&str, Vec<u8>> = [("Model/Test_Model.sbfres", vec![])].into();
+        let root = dependencies(
+            &pack,
+            |path| mod_files.get(path).cloned(),
+            |path| dump.get(path).cloned(),
+        )
+        .unwrap();
+        assert_eq!(root.path, "Actor/ActorLink/Test.bxml");
+        assert_eq!(root.source, Source::Pack);
+        let found: Vec<(&str, Source)> = root
+            .iter()
+            .skip(1)
+            .map(|dep| (dep.path.as_str(), dep.source))
+            .collect();
+        assert_eq!(found, vec![
+            ("Actor/ASList/Test.baslist", Source::Pack),
+            ("Actor/AS/Test_Wait.bas", Source::Pack),
+            ("Actor/AS/Test_Run.bas", Source::Missing),
+            ("Actor/DropTable/Gone.bdrop", Source::Missing),
+            ("Actor/GeneralParamList/Test.bgparamlist", Source::Mod),
+            ("Actor/ModelList/Test.bmodellist", Source::Pack),
+            ("Model/Test_Model.sbfres", Source::Dump),
+        ]);
+        let missing: Vec<&str> = root.missing().map(|dep| dep.link.as_str()).collect();
+        assert_eq!(missing, vec!["AS", "DropTableUser"]);
+        let tree = root.to_string();
+        assert!(
+            tree.contains("    Actor/AS/Test_Run.bas [AS] (MISSING)\n"),
+            "{tree}"
+        );
+        assert!(dependencies(
+            &SarcWriter::new(Endian::Big).to_binary(),
+            |_| None,
+            |_| None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn real_pack() {
+        // Everything a stock actor links to is in its pack, apart from its
+        // models and physics files
+        let pack = std::fs::read("test/Actor/Pack/Enemy_Moriblin_Junior.sbactorpack").unwrap();
+        let root = dependencies(&pack, |_| None, |_| None).unwrap();
+        for dep in root.missing() {
+            assert!(
+                dep.path.starts_with("Model/") || dep.path.starts_with("Physics/"),
+                "{}",
+                dep.path
+            );
+        }
+        assert!(root.iter().any(|dep| dep.link == "ASUser"));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod deps;
 pub mod info;
 pub mod params;
 mod prelude;
@@ -1,7 +1,4 @@
-use std::{
-    collections::{BTreeMap, HashSet},
-    hash::Hash,
-};
+use std::{collections::BTreeMap, hash::Hash};
 
 use anyhow::Context;
 use itertools::Itertools;
@@ -15,7 +12,7 @@ use uk_util::OptionResultExt;
 use crate::{
     actor::ParameterResource,
     prelude::*,
-    util::{self, HashMap, IndexMap, IndexSet},
+    util::{self, HashMap, IndexMap},
     Result, UKError,
 };
 
@@ -120,7 +117,7 @@ impl Mergeable for AIEntry {
                     self.params
                         .as_ref()
                         .map(|self_params| util::merge_pobj(self_params, diff_params))
-                        .unwrap_or_else(|| diff_params.clone())
+                        .unwrap_or_else(|| util::merge_pobj(&ParameterObject::new(), diff_params))
                 })
                 .or_else(|| self.params.clone()),
             behaviors: diff
@@ -130,11 +127,9 @@ impl Mergeable for AIEntry {
                     self.behaviors
                         .as_ref()
                         .map(|self_behaviors| {
-                            self_behaviors
-                                .iter()
-                                .chain(diff_behaviors.iter())
-                                .map(|(k, v)| (*k, *v))
-                                .collect()
+                            let mut behaviors = self_behaviors.clone();
+                            behaviors.extend(diff_behaviors.iter().map(|(k, v)| (*k, *v)));
+                            behaviors
                         })
                         .unwrap_or_else(|| diff_behaviors.clone())
                 })
@@ -146,29 +141,12 @@ impl Mergeable for AIEntry {
                     self.children
                         .as_ref()
                         .map(|self_children| {
-                            let all_keys = self_children
-                                .keys()
-                                .chain(diff_children.keys())
-                                .collect::<IndexSet<_>>();
-                            all_keys
-                                .into_iter()
-                                .map(|key| {
-                                    if let (Some(self_child), Some(diff_child)) =
-                                        (self_children.get(key), diff_children.get(key))
-                                    {
-                                        (*key, self_child.merge(diff_child))
-                                    } else {
-                                        (
-                                            *key,
-                                            self_children
-                                                .get(key)
-                                                .or_else(|| diff_children.get(key))
-                                                .cloned()
-                                                .expect("This key has to exist, nutcase"),
-                                        )
-                                    }
-                                })
-                                .collect()
+                            util::merge_keyed(
+                                self_children,
+                                diff_children,
+                                |k| self_children.contains_key(k),
+                                |k| diff_children.get(k),
+                            )
                         })
                         .unwrap_or_else(|| diff_children.clone())
                 })
@@ -693,95 +671,30 @@ impl Mergeable for AIProgram {
 
     fn merge(&self, diff: &Self) -> Self {
         Self {
-            demos:     {
-                let all_keys: HashSet<_> = self.demos.keys().chain(diff.demos.keys()).collect();
-                all_keys
-                    .into_iter()
-                    .map(|key| {
-                        if let (Some(self_demo), Some(diff_demo)) =
-                            (self.demos.get(key), diff.demos.get(key))
-                        {
-                            (*key, self_demo.merge(diff_demo))
-                        } else {
-                            (
-                                *key,
-                                self.demos
-                                    .get(key)
-                                    .or_else(|| diff.demos.get(key))
-                                    .cloned()
-                                    .expect("This key has to exist, nutcase"),
-                            )
-                        }
-                    })
-                    .collect()
-            },
-            behaviors: {
-                let all_keys: HashSet<_> =
-                    self.behaviors.keys().chain(diff.behaviors.keys()).collect();
-                all_keys
-                    .into_iter()
-                    .map(|key| {
-                        if let (Some(self_behavior), Some(diff_behavior)) =
-                            (self.behaviors.get(key), diff.behaviors.get(key))
-                        {
-                            (*key, self_behavior.merge(diff_behavior))
-                        } else {
-                            (
-                                *key,
-                                self.behaviors
-                                    .get(key)
-                                    .or_else(|| diff.behaviors.get(key))
-                                    .cloned()
-                                    .expect("This key has to exist, nutcase"),
-                            )
-                        }
-                    })
-                    .collect()
-            },
-            queries:   {
-                let all_keys: HashSet<_> = self.queries.keys().chain(diff.queries.keys()).collect();
-                all_keys
-                    .into_iter()
-                    .map(|key| {
-                        if let (Some(self_query), Some(diff_query)) =
-                            (self.queries.get(key), diff.queries.get(key))
-                        {
-                            (*key, self_query.merge(diff_query))
-                        } else {
-                            (
-                                *key,
-                                self.queries
-                                    .get(key)
-                                    .or_else(|| diff.queries.get(key))
-                                    .cloned()
-                                    .expect("This key has to exist, nutcase"),
-                            )
-                        }
-                    })
-                    .collect()
-            },
-            roots:     {
-                let all_keys: HashSet<_> = self.roots.keys().chain(diff.roots.keys()).collect();
-                all_keys
-                    .into_iter()
-                    .map(|key| {
-                        if let (Some(self_root), Some(diff_root)) =
-                            (self.roots.get(key), diff.roots.get(key))
-                        {
-                            (key.clone(), self_root.merge(diff_root))
-                        } else {
-                            (
-                                key.clone(),
-                                self.roots
-                                    .get(key)
-                                    .or_else(|| diff.roots.get(key))
-                                    .cloned()
-                                    .expect("This key has to exist, nutcase"),
-                            )
-                        }
-                    })
-                    .collect()
-            },
+            demos:     util::merge_keyed(
+                &self.demos,
+                &diff.demos,
+                |k| self.demos.contains_key(k),
+                |k| diff.demos.get(k),
+            ),
+            behaviors: util::merge_keyed(
+                &self.behaviors,
+                &diff.behaviors,
+                |k| self.behaviors.contains_key(k),
+                |k| diff.behaviors.get(k),
+            ),
+            queries:   util::merge_keyed(
+                &self.queries,
+                &diff.queries,
+                |k| self.queries.contains_key(k),
+                |k| diff.queries.get(k),
+            ),
+            roots:     util::merge_keyed(
+                &self.roots,
+                &diff.roots,
+                |k| self.roots.contains_key(k),
+                |k| diff.roots.get(k),
+            ),
         }
     }
 }
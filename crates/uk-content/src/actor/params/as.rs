@@ -140,7 +140,7 @@ impl Mergeable for Element {
                 self.extend
                     .as_ref()
                     .map(|self_extend| util::merge_plist(self_extend, diff_extend))
-                    .unwrap_or_else(|| diff_extend.clone())
+                    .unwrap_or_else(|| util::merge_plist(&ParameterList::new(), diff_extend))
             }),
         }
     }
@@ -299,7 +299,7 @@ impl Mergeable for AS {
                     self.common_params
                         .as_ref()
                         .map(|self_params| util::merge_pobj(self_params, diff_params))
-                        .unwrap_or_else(|| diff_params.clone())
+                        .unwrap_or_else(|| util::merge_pobj(&ParameterObject::new(), diff_params))
                 })
                 .or_else(|| self.common_params.clone()),
         }
@@ -122,13 +122,7 @@ impl Mergeable for ActorLink {
 
     fn merge(&self, other: &Self) -> Self {
         Self {
-            targets:  self
-                .targets
-                .0
-                .iter()
-                .chain(other.targets.0.iter())
-                .map(|(k, v)| (*k, v.clone()))
-                .collect(),
+            targets:  util::merge_pobj(&self.targets, &other.targets),
             tags:     {
                 if let Some(base_tags) = &self.tags {
                     if let Some(other_tags) = &other.tags {
@@ -281,6 +275,27 @@ mod tests {
         assert_eq!(actorlink2, merged);
     }
 
+    #[test]
+    fn merge_deleted_target() {
+        let actor = crate::tests::test_base_actorpack("Enemy_Guardian_A");
+        let pio = roead::aamp::ParameterIO::from_binary(
+            actor
+                .get_data("Actor/ActorLink/Enemy_Guardian_A.bxml")
+                .unwrap(),
+        )
+        .unwrap();
+        let actorlink = super::ActorLink::try_from(&pio).unwrap();
+        let mut actorlink2 = actorlink.clone();
+        assert!(actorlink2
+            .targets
+            .0
+            .shift_remove(&roead::aamp::hash_name("XlinkUser"))
+            .is_some());
+        // The target the mod removed is gone, not set to the deletion marker
+        let merged = actorlink.merge(&actorlink.diff(&actorlink2));
+        assert_eq!(merged, actorlink2);
+    }
+
     #[test]
     fn info() {
         use roead::byml::Byml;
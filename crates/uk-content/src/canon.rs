@@ -0,0 +1,510 @@
+//! Canonical resource paths.
+//!
+//! Every resource is identified by its canonical path: the path it would have
+//! relative to the game's romfs root, with platform prefixes removed and
+//! compressed extensions replaced by their uncompressed form. Canonical paths
+//! are used as cache keys, manifest lookups and ZIP entry names, so every
+//! producer must go through [`canonicalize`]. The rules, applied in order:
+//!
+//! 1. Unicode is normalized to NFC, the form the game's own names use, so a name written decomposed
+//!    (as macOS does) matches the dump. Bytes which are not valid UTF-8 are replaced with U+FFFD.
+//! 2. Backslashes become forward slashes.
+//! 3. Leading `/` and `./` are removed, along with any base game root (`content/`, `romfs/`,
+//!    `atmosphere/contents/`, `atmosphere/titles/`, `01007EF00011E000/romfs/`). Roots are matched
+//!    case-insensitively and removed repeatedly, so `atmosphere/contents/01007EF00011E000/romfs/`
+//!    goes away completely.
+//! 4. The DLC root is normalized: the Switch DLC title IDs become `Aoc/0010` and `aoc` or
+//!    `aoc/content` become `Aoc`.
+//! 5. Every extension which starts with `s` (the Yaz0 marker) loses it, so `Foo.sbactorpack`
+//!    becomes `Foo.bactorpack`. Extensions which are already uncompressed names starting with `s`
+//!    (`sarc`, `stera`) are kept, which makes `.ssarc` and `.sarc` agree.
+//!
+//! Nested SARC members use `//` between the parent and the member, e.g.
+//! `Pack/Bootup.pack//Ecosystem/AreaData.sbyml`. Only the parent carries a
+//! root prefix, but extension rules apply to every part. The rules are
+//! idempotent: canonicalizing a canonical path does not change it.
+pub mod platform;
+
+use std::{borrow::Cow, path::Path, sync::Mutex};
+
+use smartstring::alias::String;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::util::HashMap;
+
+/// Base game roots which are removed from the start of a path.
+const ROOTS: &[&str] = &[
+    "atmosphere/contents/",
+    "atmosphere/titles/",
+    "content/",
+    "romfs/",
+    "01007EF00011E000/romfs/",
+];
+
+/// DLC roots and their canonical replacements.
+const AOC_ROOTS: &[(&str, &str)] = &[
+    ("01007EF00011E001/romfs", "Aoc/0010"),
+    ("01007EF00011E002/romfs", "Aoc/0010"),
+    ("01007EF00011F001/romfs", "Aoc/0010"),
+    ("01007EF00011F002/romfs", "Aoc/0010"),
+    ("aoc/content", "Aoc"),
+    ("aoc", "Aoc"),
+];
+
+/// Uncompressed extensions which start with `s` and so must be kept as is.
+pub(crate) const KEEP_EXTS: &[&str] = &["sarc", "stera"];
+
+#[inline]
+fn strip_prefix_ci<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    path.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &path[prefix.len()..])
+}
+
+#[inline]
+fn strip_root(mut path: &str) -> &str {
+    loop {
+        if let Some(rest) = path.strip_prefix('/').or_else(|| path.strip_prefix("./")) {
+            path = rest;
+        } else if let Some(rest) = ROOTS.iter().find_map(|root| strip_prefix_ci(path, root)) {
+            path = rest;
+        } else {
+            return path;
+        }
+    }
+}
+
+#[inline]
+fn strip_aoc_root(path: &str) -> Option<(&'static str, &str)> {
+    AOC_ROOTS.iter().find_map(|(root, canon)| {
+        strip_prefix_ci(path, root)
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+            .map(|rest| (*canon, rest))
+    })
+}
+
+/// Normalizes a path to Unicode NFC. Paths which are already normalized,
+/// including every ASCII path, are returned as they are.
+pub fn normalize_unicode(path: &str) -> Cow<'_, str> {
+    if path.is_ascii() || is_nfc_quick(path.chars()) == IsNormalized::Yes {
+        Cow::Borrowed(path)
+    } else {
+        Cow::Owned(path.nfc().collect())
+    }
+}
+
+fn canonicalize_str(path: &str) -> String {
+    let path = normalize_unicode(path).replace('\\', "/");
+    let path = strip_root(&path);
+    let mut canon = String::new();
+    let path = match strip_aoc_root(path) {
+        Some((root, rest)) => {
+            canon.push_str(root);
+            rest
+        }
+        None => path,
+    };
+    let mut parts = path.split('.');
+    if let Some(first) = parts.next() {
+        canon.push_str(first);
+    }
+    for part in parts {
+        canon.push('.');
+        let ext = part.split('/').next().unwrap_or_default();
+        if ext.starts_with('s') && !KEEP_EXTS.contains(&ext) {
+            canon.push_str(&part[1..]);
+        } else {
+            canon.push_str(part);
+        }
+    }
+    canon
+}
+
+/// Returns the canonical form of a resource path. See the [module
+/// documentation](self) for the rules.
+pub fn canonicalize(path: impl AsRef<Path>) -> String {
+    canonicalize_str(&path.as_ref().to_string_lossy())
+}
+
+/// Returns the canonical form of a DLC resource path given relative to the
+/// DLC romfs root. Paths which already include a DLC root are not prefixed
+/// again.
+pub fn canonicalize_aoc(path: impl AsRef<Path>) -> String {
+    let canon = canonicalize(path);
+    if canon.starts_with("Aoc/") {
+        canon
+    } else {
+        ["Aoc/0010/", canon.as_str()].concat().into()
+    }
+}
+
+/// Returns the path a canonical path had before uncompressed `sarc` and
+/// `stera` extensions were kept, when they lost their `s` like any other, so
+/// `Pack/Foo.sarc` was `Pack/Foo.arc`. Mods packaged then store those files
+/// under the old path. Returns `None` if the path was the same.
+pub fn legacy_canonical(canon: &str) -> Option<String> {
+    let mut parts = canon.split('.');
+    let mut legacy = String::from(parts.next()?);
+    let mut changed = false;
+    for part in parts {
+        legacy.push('.');
+        let ext = part.split('/').next().unwrap_or_default();
+        if KEEP_EXTS.contains(&ext) {
+            legacy.push_str(&part[1..]);
+            changed = true;
+        } else {
+            legacy.push_str(part);
+        }
+    }
+    changed.then_some(legacy)
+}
+
+/// Checks whether a path is already in canonical form.
+#[inline]
+pub fn is_canonical(path: &str) -> bool {
+    canonicalize_str(path) == path
+}
+
+/// Records the raw path behind each canonical path so that different files
+/// which canonicalize to the same resource can be reported. Only active with
+/// debug assertions or when `UKMM_CANON_CHECK` is set.
+#[derive(Debug)]
+pub struct CanonTracker(Option<Mutex<HashMap<String, std::string::String>>>);
+
+impl Default for CanonTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CanonTracker {
+    pub fn new() -> Self {
+        let enabled = cfg!(debug_assertions) || std::env::var_os("UKMM_CANON_CHECK").is_some();
+        Self(enabled.then(Default::default))
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Records that `raw` canonicalizes to `canon`. If a different raw path
+    /// was already recorded for it, logs a warning and returns that path.
+    pub fn record(&self, raw: &str, canon: &str) -> Option<std::string::String> {
+        let mut seen = self.0.as_ref()?.lock().unwrap_or_else(|e| e.into_inner());
+        match seen.get(canon) {
+            Some(prev) if prev != raw => {
+                log::warn!("{prev} and {raw} both canonicalize to {canon}");
+                Some(prev.clone())
+            }
+            Some(_) => None,
+            None => {
+                seen.insert(canon.into(), raw.into());
+                None
+            }
+        }
+    }
+
+    /// Canonicalizes a path and records it.
+    pub fn canonicalize(&self, raw: &str) -> String {
+        let canon = canonicalize_str(raw);
+        self.record(raw, &canon);
+        canon
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every known path pattern and its canonical form.
+    const SPEC: &[(&str, &str)] = &[
+        (
+            "content\\Actor\\Pack\\Enemy_Lizal_Senior.sbactorpack",
+            "Actor/Pack/Enemy_Lizal_Senior.bactorpack",
+        ),
+        (
+            "Content/Actor/Pack/Enemy_Lizal_Senior.sbactorpack",
+            "Actor/Pack/Enemy_Lizal_Senior.bactorpack",
+        ),
+        (
+            "aoc/0010/Map/MainField/A-1/A-1_Dynamic.smubin",
+            "Aoc/0010/Map/MainField/A-1/A-1_Dynamic.mubin",
+        ),
+        (
+            "aoc/content/0010/Pack/AocMainField.pack",
+            "Aoc/0010/Pack/AocMainField.pack",
+        ),
+        (
+            "atmosphere/contents/01007EF00011E000/romfs/Actor/ActorInfo.product.sbyml",
+            "Actor/ActorInfo.product.byml",
+        ),
+        (
+            "atmosphere/titles/01007ef00011e000/romfs/Actor/ActorInfo.product.sbyml",
+            "Actor/ActorInfo.product.byml",
+        ),
+        (
+            "atmosphere/contents/01007EF00011F001/romfs/Pack/AocMainField.pack",
+            "Aoc/0010/Pack/AocMainField.pack",
+        ),
+        (
+            "01007ef00011e002/romfs/Pack/AocMainField.pack",
+            "Aoc/0010/Pack/AocMainField.pack",
+        ),
+        ("romfs/Pack/Bootup.pack", "Pack/Bootup.pack"),
+        ("./content/Pack/Bootup.pack", "Pack/Bootup.pack"),
+        ("/Pack/Bootup.pack", "Pack/Bootup.pack"),
+        ("Hellow/Sweetie.tardis", "Hellow/Sweetie.tardis"),
+        (
+            "Event/EventInfo.product.sbyml",
+            "Event/EventInfo.product.byml",
+        ),
+        (
+            "Message/Msg_USen.product.ssarc",
+            "Message/Msg_USen.product.sarc",
+        ),
+        (
+            "Message/Msg_USen.product.sarc",
+            "Message/Msg_USen.product.sarc",
+        ),
+        (
+            "Terrain/A/MainField/5000000000.hght.sstera",
+            "Terrain/A/MainField/5000000000.hght.stera",
+        ),
+        (
+            "Physics/StaticCompound/MainField/A-1-0.shksc",
+            "Physics/StaticCompound/MainField/A-1-0.hksc",
+        ),
+        (
+            "Pack/Bootup.pack//Ecosystem/AreaData.sbyml",
+            "Pack/Bootup.pack//Ecosystem/AreaData.byml",
+        ),
+        (
+            "content/Pack/TitleBG.pack//Actor/Pack/Foo.sbactorpack//Actor/Physics/Foo.bphysics",
+            "Pack/TitleBG.pack//Actor/Pack/Foo.bactorpack//Actor/Physics/Foo.bphysics",
+        ),
+        (
+            "Aoc/0010/Pack/AocMainField.pack",
+            "Aoc/0010/Pack/AocMainField.pack",
+        ),
+        ("aocfoo/Bar.sbyml", "aocfoo/Bar.byml"),
+    ];
+
+    #[test]
+    fn spec() {
+        for (input, canon) in SPEC {
+            assert_eq!(
+                canonicalize(input).as_str(),
+                *canon,
+                "canonicalizing {input}"
+            );
+            assert!(is_canonical(canon), "{canon} is not canonical");
+        }
+    }
+
+    #[test]
+    fn legacy() {
+        assert_eq!(
+            legacy_canonical(&canonicalize("content/Pack/Foo.sarc")).as_deref(),
+            Some("Pack/Foo.arc")
+        );
+        assert_eq!(
+            legacy_canonical(&canonicalize("Terrain/A/MainField/5000000000.stera")).as_deref(),
+            Some("Terrain/A/MainField/5000000000.tera")
+        );
+        assert_eq!(
+            legacy_canonical("Pack/Foo.sarc//Model/Bar.sarc").as_deref(),
+            Some("Pack/Foo.arc//Model/Bar.arc")
+        );
+        // A compressed SARC had its canonical path before, but the canonical
+        // path cannot tell it from an uncompressed one
+        assert_eq!(canonicalize("Pack/Foo.ssarc"), canonicalize("Pack/Foo.sarc"));
+        assert_eq!(legacy_canonical("Actor/Pack/Test.bactorpack"), None);
+    }
+
+    #[test]
+    fn aoc() {
+        assert_eq!(
+            canonicalize_aoc("Map/MainField/A-1/A-1_Dynamic.smubin").as_str(),
+            "Aoc/0010/Map/MainField/A-1/A-1_Dynamic.mubin"
+        );
+        assert_eq!(
+            canonicalize_aoc("aoc/0010/Pack/AocMainField.pack").as_str(),
+            "Aoc/0010/Pack/AocMainField.pack"
+        );
+    }
+
+    /// Small deterministic generator so the property tests are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    const PREFIXES: &[&str] = &[
+        "",
+        "content/",
+        "Content/",
+        "romfs/",
+        "./",
+        "/",
+        "atmosphere/contents/01007EF00011E000/romfs/",
+        "atmosphere/titles/01007ef00011e000/romfs/",
+        "aoc/0010/",
+        "aoc/content/0010/",
+        "01007EF00011F001/romfs/",
+        "01007ef00011e001/romfs/",
+    ];
+    const DIRS: &[&str] = &[
+        "Actor",
+        "Pack",
+        "Map",
+        "MainField",
+        "A-1",
+        "Ecosystem",
+        "Event",
+        "UI",
+        "Message",
+        "Terrain",
+        "sound",
+        "Model",
+    ];
+    const NAMES: &[&str] = &[
+        "Enemy_Lizal_Senior",
+        "ActorInfo",
+        "Bootup",
+        "A-1_Dynamic",
+        "Msg_USen",
+        "5000000000",
+        "Item_Fruit_A",
+    ];
+    const EXTS: &[&str] = &[
+        "bactorpack",
+        "byml",
+        "mubin",
+        "bfres",
+        "sarc",
+        "stera",
+        "blarc",
+        "hksc",
+        "pack",
+        "bxml",
+        "tardis",
+    ];
+
+    fn gen_path(rng: &mut Rng) -> std::string::String {
+        let mut path = rng.pick(PREFIXES).to_owned();
+        for _ in 0..=rng.below(3) {
+            path.push_str(rng.pick(DIRS));
+            path.push('/');
+        }
+        path.push_str(rng.pick(NAMES));
+        if rng.below(3) == 0 {
+            path.push_str(".product");
+        }
+        path.push('.');
+        if rng.below(2) == 0 {
+            path.push('s');
+        }
+        path.push_str(rng.pick(EXTS));
+        path
+    }
+
+    #[test]
+    fn properties() {
+        let mut rng = Rng(0x5eed_cafe_f00d);
+        for _ in 0..5000 {
+            let path = gen_path(&mut rng);
+            let canon = canonicalize(&path);
+            assert_eq!(
+                canonicalize(canon.as_str()),
+                canon,
+                "not idempotent: {path}"
+            );
+            assert_eq!(
+                canonicalize(path.replace('/', "\\")),
+                canon,
+                "separators differ: {path}"
+            );
+            assert!(!canon.contains('\\') && !canon.starts_with('/'), "{path}");
+            assert!(
+                !canon.to_lowercase().starts_with("content/")
+                    && !canon.to_lowercase().starts_with("romfs/")
+                    && !canon.starts_with("aoc"),
+                "prefix left on {path}"
+            );
+            // Only the prefix is case-insensitive, the rest keeps its case.
+            let upper = path.replacen("content/", "CONTENT/", 1);
+            assert_eq!(canonicalize(&upper), canon, "case: {path}");
+        }
+    }
+
+    #[test]
+    fn no_panic() {
+        let mut rng = Rng(0xdead_beef);
+        let chars = [
+            "a", "s", ".", "/", "\\", "é", "0", "A", "o", "c", "r", "m", "f", "ß", "romfs/",
+            "content/",
+        ];
+        for _ in 0..5000 {
+            let len = rng.below(24);
+            let path: std::string::String = (0..len).map(|_| rng.pick(&chars)).collect();
+            let canon = canonicalize(&path);
+            assert!(!canon.contains('\\'));
+        }
+    }
+
+    #[test]
+    fn unicode() {
+        let nfc = "Actor/Pack/Caf\u{e9}.bactorpack";
+        let nfd = "content/Actor/Pack/Cafe\u{301}.sbactorpack";
+        assert_eq!(canonicalize(nfd).as_str(), nfc);
+        assert!(is_canonical(nfc));
+        assert!(!is_canonical("Actor/Pack/Cafe\u{301}.bactorpack"));
+        assert!(matches!(
+            normalize_unicode("Pack/Bootup.pack"),
+            Cow::Borrowed(_)
+        ));
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            let invalid = Path::new(OsStr::from_bytes(b"content/Model/Caf\xe9.sbfres"));
+            assert_eq!(canonicalize(invalid).as_str(), "Model/Caf\u{fffd}.bfres");
+        }
+    }
+
+    #[test]
+    fn tracker() {
+        let tracker = CanonTracker(Some(Default::default()));
+        assert_eq!(
+            tracker.canonicalize("Actor/Pack/Foo.sbactorpack").as_str(),
+            "Actor/Pack/Foo.bactorpack"
+        );
+        assert_eq!(
+            tracker.record("Actor/Pack/Foo.sbactorpack", "Actor/Pack/Foo.bactorpack"),
+            None
+        );
+        assert_eq!(
+            tracker.record("Actor/Pack/Foo.bactorpack", "Actor/Pack/Foo.bactorpack"),
+            Some("Actor/Pack/Foo.sbactorpack".into())
+        );
+        let disabled = CanonTracker(None);
+        assert!(!disabled.is_enabled());
+        assert_eq!(disabled.record("a.sbyml", "a.byml"), None);
+        assert_eq!(disabled.record("a.byml", "a.byml"), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! Files the Wii U and Switch versions of the game keep under different names.
+//!
+//! Besides the content and DLC roots, which [`canonicalize`](super::canonicalize)
+//! already removes, a few kinds of files are named differently on each
+//! platform. A mod made for one platform names them the way that platform
+//! does, so they would not be found in a dump for the other and would be
+//! treated as new files rather than merged with the stock ones. The table
+//! below lists the known differences, and [`translate`] renames a path to the
+//! way a given platform has it.
+//!
+//! Entries match on the folder a file is in and the end of its name, with or
+//! without the compression marker on the extension, which is kept as it was.
+//! Nested members are matched on their own name, so `//` paths work too.
+use roead::Endian;
+
+/// A kind of file named differently on each platform.
+struct Divergence {
+    /// The folder the files are in, which can be under a DLC root.
+    dir:  &'static str,
+    /// The end of the name and the uncompressed extension on Wii U.
+    wiiu: (&'static str, &'static str),
+    /// The end of the name and the uncompressed extension on Switch.
+    nx:   (&'static str, &'static str),
+}
+
+/// The known differences. Wii U model textures are split over `Tex1` and
+/// `Tex2`, the second holding the mipmaps which Switch keeps in one `Tex`
+/// file, so only `Tex1` has a counterpart.
+const TABLE: &[Divergence] = &[Divergence {
+    dir:  "Model/",
+    wiiu: (".Tex1", "bfres"),
+    nx:   (".Tex", "bfres"),
+}];
+
+/// Splits `name` into the part before a matching suffix and whether the
+/// extension is compressed.
+fn strip_suffix<'a>(name: &'a str, (end, ext): (&str, &str)) -> Option<(&'a str, bool)> {
+    let (stem, found) = name.rsplit_once('.')?;
+    let compressed = match found.strip_prefix('s') {
+        Some(rest) if rest == ext => true,
+        _ if found == ext => false,
+        _ => return None,
+    };
+    let stem = stem.strip_suffix(end)?;
+    (!stem.is_empty() && !stem.ends_with('/')).then_some((stem, compressed))
+}
+
+/// Whether the member name is in the folder, at the root or under a prefix.
+fn in_dir(name: &str, dir: &str) -> bool {
+    name.match_indices(dir)
+        .any(|(i, _)| i == 0 || name.as_bytes()[i - 1] == b'/')
+}
+
+fn rename(path: &str, from: (&str, &str), to: (&str, &str), dir: &str) -> Option<String> {
+    let (parent, name) = match path.rfind("//") {
+        Some(i) => path.split_at(i + 2),
+        None => ("", path),
+    };
+    if !in_dir(name, dir) {
+        return None;
+    }
+    let (stem, compressed) = strip_suffix(name, from)?;
+    Some(
+        [
+            parent,
+            stem,
+            to.0,
+            ".",
+            if compressed { "s" } else { "" },
+            to.1,
+        ]
+        .concat(),
+    )
+}
+
+/// Renames a path made for the other platform to the name `to` has it under.
+/// Returns `None` if the path is not one which differs, or is already named
+/// for `to`.
+pub fn translate(path: &str, to: Endian) -> Option<String> {
+    TABLE.iter().find_map(|entry| {
+        match to {
+            Endian::Little => rename(path, entry.wiiu, entry.nx, entry.dir),
+            Endian::Big => rename(path, entry.nx, entry.wiiu, entry.dir),
+        }
+    })
+}
+
+/// The name a path has on the other platform, for lookups which do not know
+/// which platform they are for.
+pub fn counterpart(path: &str) -> Option<String> {
+    translate(path, Endian::Little).or_else(|| translate(path, Endian::Big))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wii U and Switch names of the same files.
+    const PAIRS: &[(&str, &str)] = &[
+        ("Model/Link.Tex1.sbfres", "Model/Link.Tex.sbfres"),
+        ("Model/Link.Tex1.bfres", "Model/Link.Tex.bfres"),
+        (
+            "content/Model/DgnObj_Hrl.Tex1.sbfres",
+            "content/Model/DgnObj_Hrl.Tex.sbfres",
+        ),
+        (
+            "Aoc/0010/Model/Obj_DLC_Armor.Tex1.bfres",
+            "Aoc/0010/Model/Obj_DLC_Armor.Tex.bfres",
+        ),
+        (
+            "Pack/TitleBG.pack//Model/Terrain.Tex1.sbfres",
+            "Pack/TitleBG.pack//Model/Terrain.Tex.sbfres",
+        ),
+    ];
+
+    /// Paths which are named the same on both platforms, or have no
+    /// counterpart.
+    const SAME: &[&str] = &[
+        "Model/Link.sbfres",
+        "Model/Link.Tex2.sbfres",
+        "Actor/Pack/Model.Tex1.sbactorpack",
+        "UI/Model/Foo.Tex.sbitemico",
+        "Layout/Common.sblarc",
+        "Sound/Resource/Stream/Foo.bfstm",
+        "NotModel/Foo.Tex1.sbfres",
+        "Model/.Tex1.bfres",
+        "Model/Link.Tex1.bfres/Foo.bin",
+    ];
+
+    #[test]
+    fn pairs() {
+        for (wiiu, nx) in PAIRS {
+            assert_eq!(translate(wiiu, Endian::Little).as_deref(), Some(*nx));
+            assert_eq!(translate(nx, Endian::Big).as_deref(), Some(*wiiu));
+            // Already named for the platform
+            assert_eq!(translate(wiiu, Endian::Big), None, "{wiiu}");
+            assert_eq!(translate(nx, Endian::Little), None, "{nx}");
+            assert_eq!(counterpart(wiiu).as_deref(), Some(*nx));
+            assert_eq!(counterpart(nx).as_deref(), Some(*wiiu));
+        }
+    }
+
+    #[test]
+    fn same() {
+        for path in SAME {
+            assert_eq!(counterpart(path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn canonical() {
+        // Translating agrees with canonicalizing either way round
+        for (wiiu, nx) in PAIRS {
+            let canon = crate::canonicalize(wiiu);
+            assert_eq!(
+                translate(&canon, Endian::Little).as_deref(),
+                Some(crate::canonicalize(nx).as_str())
+            );
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Which files the game expects to be Yaz0 compressed.
+//!
+//! The game looks a file up under one name only, and the name says how the
+//! file is stored: an extension starting with `s` means compressed, except for
+//! uncompressed extensions which happen to start with `s` (see
+//! [`canon`](crate::canon)). Since both names canonicalize to the same
+//! resource, a mod can provide a file under the other name, and its version
+//! would then be written where the game never reads it. Output is written
+//! under the name the game expects instead, decided by:
+//!
+//! 1. The policy for the extension, for the types the game always or never compresses.
+//! 2. The stock file, for every other type, when the game has it under either name.
+//! 3. The name the mod used, when neither knows better.
+use std::borrow::Cow;
+
+use roead::yaz0;
+
+use crate::canon::KEEP_EXTS;
+
+/// Uncompressed extensions whose files the game always (`true`) or never
+/// (`false`) stores compressed, wherever they are.
+const POLICY: &[(&str, bool)] = &[
+    ("bactorpack", true),
+    ("beventpack", true),
+    ("bfres", true),
+    ("blarc", true),
+    ("bquestpack", true),
+    ("mubin", true),
+    ("rsizetable", true),
+    ("bgdata", false),
+    ("bgsvdata", false),
+    ("msbt", false),
+    ("pack", false),
+];
+
+/// The extension of the last part of a path, ignoring folders and parents of
+/// nested files.
+fn extension(name: &str) -> Option<&str> {
+    name.rsplit('/')
+        .next()
+        .and_then(|file| file.rsplit_once('.'))
+        .map(|(_, ext)| ext)
+        .filter(|ext| !ext.is_empty())
+}
+
+/// Whether a file's name marks it as compressed.
+pub fn is_compressed_name(name: &str) -> bool {
+    extension(name).is_some_and(|ext| ext.starts_with('s') && !KEEP_EXTS.contains(&ext))
+}
+
+/// Whether the policy says a file is stored compressed, or `None` if its
+/// type is not always stored the same way.
+pub fn expected(name: &str) -> Option<bool> {
+    let ext = extension(name)?;
+    let ext = if is_compressed_name(name) {
+        &ext[1..]
+    } else {
+        ext
+    };
+    POLICY
+        .iter()
+        .find(|(policy_ext, _)| *policy_ext == ext)
+        .map(|(_, compressed)| *compressed)
+}
+
+/// A file's name changed to mark it as compressed or not.
+fn renamed(name: &str, compressed: bool) -> std::string::String {
+    let ext_start = name.len() - extension(name).map(|ext| ext.len()).unwrap_or_default();
+    let (stem, ext) = name.split_at(ext_start);
+    match (compressed, is_compressed_name(name)) {
+        (true, false) => [stem, "s", ext].concat(),
+        (false, true) => [stem, &ext[1..]].concat(),
+        _ => name.into(),
+    }
+}
+
+/// The name the game expects a file under. `in_stock` says whether the game
+/// has a file under a name, and is only asked for types the policy does not
+/// cover.
+pub fn conventional_name<'a>(name: &'a str, in_stock: impl Fn(&str) -> bool) -> Cow<'a, str> {
+    if extension(name).is_none() {
+        return Cow::Borrowed(name);
+    }
+    let compressed = is_compressed_name(name);
+    let expected = expected(name).or_else(|| {
+        if in_stock(name) {
+            Some(compressed)
+        } else {
+            in_stock(&renamed(name, !compressed)).then_some(!compressed)
+        }
+    });
+    match expected {
+        Some(expected) if expected != compressed => Cow::Owned(renamed(name, expected)),
+        _ => Cow::Borrowed(name),
+    }
+}
+
+/// Compresses or decompresses a file's data to match its name.
+pub fn compress_if<'a>(data: &'a [u8], name: &str) -> Cow<'a, [u8]> {
+    let is_yaz0 = data.starts_with(b"Yaz0");
+    match (is_compressed_name(name), is_yaz0) {
+        (true, false) => Cow::Owned(yaz0::compress(data)),
+        (false, true) => yaz0::decompress_if(data),
+        _ => Cow::Borrowed(data),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn no_stock(_: &str) -> bool {
+        false
+    }
+
+    #[test]
+    fn names() {
+        assert!(is_compressed_name(
+            "Actor/Pack/Enemy_Lizal_Senior.sbactorpack"
+        ));
+        assert!(is_compressed_name(
+            "Pack/Bootup.pack//GameData/gamedata.ssarc"
+        ));
+        assert!(is_compressed_name("Terrain/A/MainField/5000000000.sstera"));
+        assert!(!is_compressed_name("Terrain/A/MainField/5000000000.stera"));
+        assert!(!is_compressed_name("Message/Msg_USen.product.sarc"));
+        assert!(!is_compressed_name("Pack/TitleBG.pack"));
+        assert!(!is_compressed_name("Sound/Resource/Stream/Foo.bfstm"));
+        assert!(!is_compressed_name("System/Version.txt.s/README"));
+    }
+
+    #[test]
+    fn policy() {
+        // Packs are never compressed at the top level
+        assert_eq!(
+            conventional_name("Pack/Dungeon000.spack", |_| true),
+            "Pack/Dungeon000.pack"
+        );
+        assert_eq!(expected("Pack/TitleBG.pack"), Some(false));
+        // Actor packs always are, even where the stock file is missing
+        assert_eq!(
+            conventional_name("Actor/Pack/New_Actor.bactorpack", no_stock),
+            "Actor/Pack/New_Actor.sbactorpack"
+        );
+        assert_eq!(
+            conventional_name(
+                "Pack/TitleBG.pack//Actor/Pack/Enemy_Bokoblin.bactorpack",
+                no_stock
+            ),
+            "Pack/TitleBG.pack//Actor/Pack/Enemy_Bokoblin.sbactorpack"
+        );
+        assert!(matches!(
+            conventional_name("Model/Foo.sbfres", no_stock),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            conventional_name("Model/Foo.bfres", no_stock),
+            "Model/Foo.sbfres"
+        );
+        // Members of compressed SARCs are stored plain
+        assert_eq!(
+            conventional_name("GameData/Flag/bool_data_0.sbgdata", no_stock),
+            "GameData/Flag/bool_data_0.bgdata"
+        );
+        assert_eq!(
+            conventional_name("EventFlow/Demo000_0.bfevfl", |_| true),
+            "EventFlow/Demo000_0.bfevfl"
+        );
+    }
+
+    #[test]
+    fn stock_reference() {
+        let stock = [
+            "Message/Msg_USen.product.ssarc",
+            "Terrain/A/MainField/5000000000.stera",
+        ];
+        let in_stock = |name: &str| stock.contains(&name);
+        assert_eq!(
+            conventional_name("Message/Msg_USen.product.sarc", in_stock),
+            "Message/Msg_USen.product.ssarc"
+        );
+        assert_eq!(
+            conventional_name("Terrain/A/MainField/5000000000.sstera", in_stock),
+            "Terrain/A/MainField/5000000000.stera"
+        );
+        // Files new to the game keep the name they were given
+        assert_eq!(
+            conventional_name("Map/New/New.byml", in_stock),
+            "Map/New/New.byml"
+        );
+        assert_eq!(
+            conventional_name("Map/New/New.sbyml", in_stock),
+            "Map/New/New.sbyml"
+        );
+        assert_eq!(conventional_name("NoExtension", |_| true), "NoExtension");
+    }
+
+    #[test]
+    fn compress_to_match() {
+        let data = b"SARC and some more bytes to compress".to_vec();
+        let compressed = compress_if(&data, "Foo.ssarc");
+        assert!(compressed.starts_with(b"Yaz0"));
+        assert!(matches!(
+            compress_if(&compressed, "Foo.ssarc"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            compress_if(&compressed, "Foo.sarc").as_ref(),
+            data.as_slice()
+        );
+        assert!(matches!(compress_if(&data, "Foo.sarc"), Cow::Borrowed(_)));
+    }
+}
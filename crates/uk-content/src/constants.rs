@@ -62,6 +62,12 @@ impl Language {
         &self.to_str()[2..4]
     }
 
+    /// The region of the release which has the language, such as `US`.
+    #[inline(always)]
+    pub fn region(&self) -> &'static str {
+        &self.to_str()[..2]
+    }
+
     pub fn nearest<'l>(&self, langs: &'l [Self]) -> &'l Self {
         langs
             .iter()
@@ -3,42 +3,69 @@ use roead::byml::{map, Byml};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    cooking::{recipe::Recipe, single_recipe::SingleRecipe, system::System},
+    cooking::{
+        recipe::{Recipe, RecipeKey},
+        single_recipe::SingleRecipe,
+        system::System,
+    },
     prelude::*,
-    util::DeleteVec,
+    util::DeleteMap,
     Result, UKError,
 };
 
 #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 
 pub struct CookData {
-    pub recipes: DeleteVec<Recipe>,
-    pub single_recipes: DeleteVec<SingleRecipe>,
+    pub recipes: DeleteMap<RecipeKey, Recipe>,
+    pub single_recipes: DeleteMap<RecipeKey, SingleRecipe>,
     pub system: System,
 }
 
+/// Keys recipes in their original order, counting repeats of the same key so
+/// that none are lost.
+fn keyed<T: Clone + PartialEq>(
+    recipes: Vec<T>,
+    key: impl Fn(&T, usize) -> RecipeKey,
+) -> DeleteMap<RecipeKey, T> {
+    let mut map = DeleteMap::with_capacity(recipes.len());
+    for recipe in recipes {
+        let mut nth = 0;
+        while map.contains_key(key(&recipe, nth)) {
+            nth += 1;
+        }
+        map.insert(key(&recipe, nth), recipe);
+    }
+    map
+}
+
 impl TryFrom<&Byml> for CookData {
     type Error = UKError;
 
     fn try_from(byml: &Byml) -> Result<Self> {
         let hash = byml.as_map()?;
         Ok(Self {
-            recipes: hash
-                .get("Recipes")
-                .ok_or(UKError::MissingBymlKey("Cook data missing Recipes"))?
-                .as_array()
-                .map_err(|_| UKError::WrongBymlType("not an array".into(), "an array"))?
-                .iter()
-                .map(|r| Ok(Recipe::try_from(r).context("Failed to parse Recipe")?))
-                .collect::<Result<_>>()?,
-            single_recipes: hash
-                .get("SingleRecipes")
-                .ok_or(UKError::MissingBymlKey("Cook data missing SingleRecipes"))?
-                .as_array()
-                .map_err(|_| UKError::WrongBymlType("not an array".into(), "an array"))?
-                .iter()
-                .map(|sr| Ok(SingleRecipe::try_from(sr).context("Failed to parse SingleRecipe")?))
-                .collect::<Result<_>>()?,
+            recipes: keyed(
+                hash.get("Recipes")
+                    .ok_or(UKError::MissingBymlKey("Cook data missing Recipes"))?
+                    .as_array()
+                    .map_err(|_| UKError::WrongBymlType("not an array".into(), "an array"))?
+                    .iter()
+                    .map(|r| Ok(Recipe::try_from(r).context("Failed to parse Recipe")?))
+                    .collect::<Result<_>>()?,
+                Recipe::key,
+            ),
+            single_recipes: keyed(
+                hash.get("SingleRecipes")
+                    .ok_or(UKError::MissingBymlKey("Cook data missing SingleRecipes"))?
+                    .as_array()
+                    .map_err(|_| UKError::WrongBymlType("not an array".into(), "an array"))?
+                    .iter()
+                    .map(|sr| {
+                        Ok(SingleRecipe::try_from(sr).context("Failed to parse SingleRecipe")?)
+                    })
+                    .collect::<Result<_>>()?,
+                SingleRecipe::key,
+            ),
             system: hash
                 .get("System")
                 .ok_or(UKError::MissingBymlKey("Cook data missing System"))?
@@ -51,8 +78,8 @@ impl TryFrom<&Byml> for CookData {
 impl From<CookData> for Byml {
     fn from(val: CookData) -> Self {
         map!(
-            "Recipes" => val.recipes.iter().map(Byml::from).collect(),
-            "SingleRecipes" => val.single_recipes.iter().map(Byml::from).collect(),
+            "Recipes" => val.recipes.values().map(Byml::from).collect(),
+            "SingleRecipes" => val.single_recipes.values().map(Byml::from).collect(),
             "System" => val.system.into(),
         )
     }
@@ -142,6 +169,74 @@ mod tests {
         assert_eq!(merged, cookdata2);
     }
 
+    #[test]
+    fn ingredient_order() {
+        let byml = load_cookdata();
+        let cookdata = super::CookData::try_from(&byml).unwrap();
+        let mut shuffled = byml.clone();
+        if let Byml::Map(hash) = &mut shuffled {
+            if let Some(Byml::Array(recipes)) = hash.get_mut("Recipes") {
+                for recipe in recipes {
+                    if let Byml::Map(recipe) = recipe {
+                        if let Some(Byml::Array(groups)) = recipe.get_mut("Actors") {
+                            groups.reverse();
+                            for group in groups {
+                                if let Byml::Array(group) = group {
+                                    group.reverse();
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let shuffled = super::CookData::try_from(&shuffled).unwrap();
+        assert!(cookdata.diff(&shuffled).recipes.is_empty());
+        assert_eq!(cookdata, shuffled);
+    }
+
+    #[test]
+    fn combined_merge() {
+        use crate::cooking::recipe::Recipe;
+
+        let cookdata = super::CookData::try_from(&load_cookdata()).unwrap();
+        let added = Recipe {
+            actors: Some(vec![vec![1, 2], vec![3]]),
+            hb:     None,
+            recipe: 12345,
+            tags:   None,
+        };
+        let mut mod_a = cookdata.clone();
+        mod_a.recipes.insert(added.key(0), added.clone());
+        let (edit_key, mut edited) = cookdata
+            .recipes
+            .iter()
+            .next()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .unwrap();
+        edited.hb = Some(edited.hb.unwrap_or_default() + 5);
+        let delete_key = cookdata.recipes.keys().last().unwrap().clone();
+        let mut mod_b = cookdata.clone();
+        mod_b.recipes = mod_b
+            .recipes
+            .iter()
+            .filter(|(k, _)| **k != delete_key)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        mod_b.recipes.insert(edit_key.clone(), edited.clone());
+
+        let merged = cookdata
+            .merge(&cookdata.diff(&mod_a))
+            .merge(&cookdata.diff(&mod_b));
+        assert_eq!(merged.recipes.get(added.key(0)), Some(&added));
+        assert_eq!(merged.recipes.get(&edit_key), Some(&edited));
+        assert!(!merged.recipes.contains_key(&delete_key));
+        assert_eq!(merged.recipes.len(), cookdata.recipes.len());
+        assert_eq!(merged.single_recipes, cookdata.single_recipes);
+        let byml = Byml::from(merged.clone());
+        assert_eq!(super::CookData::try_from(&byml).unwrap(), merged);
+    }
+
     #[test]
     fn identify() {
         let path = std::path::Path::new("content/Pack/Bootup.pack//Cooking/CookData.sbyml");
@@ -2,18 +2,60 @@ use roead::byml::Byml;
 use serde::{Deserialize, Serialize};
 use smartstring::{LazyCompact, SmartString};
 
-use crate::{
-    util::{DeleteVec, HashMap},
-    Result, UKError,
-};
+use crate::{util::HashMap, Result, UKError};
+
+/// Identifies a recipe by the dish it makes and its ingredients. Repeated
+/// entries with the same dish and ingredients are told apart by `nth`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct RecipeKey {
+    pub recipe: i32,
+    pub actors: Vec<Vec<i32>>,
+    pub tags:   Vec<Vec<i32>>,
+    pub nth:    usize,
+}
+
+#[inline]
+pub(super) fn hash_to_byml(hash: i32) -> Byml {
+    if hash < 0 {
+        Byml::U32(hash as u32)
+    } else {
+        Byml::I32(hash)
+    }
+}
+
+pub(super) fn parse_hashes(byml: &Byml) -> Result<Vec<i32>> {
+    byml.as_array()?
+        .iter()
+        .map(|i| Ok(i.as_int::<i32>()?))
+        .collect()
+}
+
+/// Sorts each ingredient group and then the groups themselves. The game
+/// matches ingredients regardless of order, so this makes recipes which only
+/// differ in ingredient order compare equal.
+fn sort_groups(groups: &mut [Vec<i32>]) {
+    groups.iter_mut().for_each(|group| group.sort_unstable());
+    groups.sort_unstable();
+}
 
 #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 
 pub struct Recipe {
-    pub actors: Option<DeleteVec<DeleteVec<i32>>>,
+    pub actors: Option<Vec<Vec<i32>>>,
     pub hb:     Option<i32>,
     pub recipe: i32,
-    pub tags:   Option<DeleteVec<DeleteVec<i32>>>,
+    pub tags:   Option<Vec<Vec<i32>>>,
+}
+
+impl Recipe {
+    pub fn key(&self, nth: usize) -> RecipeKey {
+        RecipeKey {
+            recipe: self.recipe,
+            actors: self.actors.clone().unwrap_or_default(),
+            tags: self.tags.clone().unwrap_or_default(),
+            nth,
+        }
+    }
 }
 
 impl TryFrom<&Byml> for Recipe {
@@ -21,96 +63,52 @@ impl TryFrom<&Byml> for Recipe {
 
     fn try_from(byml: &Byml) -> Result<Self> {
         let hash = byml.as_map()?;
+        let parse_groups = |byml: &Byml| -> Result<Vec<Vec<i32>>> {
+            let mut groups = byml
+                .as_array()?
+                .iter()
+                .map(parse_hashes)
+                .collect::<Result<Vec<_>>>()?;
+            sort_groups(&mut groups);
+            Ok(groups)
+        };
         Ok(Self {
-            actors: hash
-                .get("Actors")
-                .map(|arr| -> Result<DeleteVec<DeleteVec<i32>>> {
-                    arr.as_array()?
-                        .iter()
-                        .map(|arr2| -> Result<DeleteVec<i32>> {
-                            arr2.as_array()?
-                                .iter()
-                                .map(|i| Ok(i.as_int::<i32>()?))
-                                .collect()
-                        })
-                        .collect()
-                })
-                .transpose()?,
+            actors: hash.get("Actors").map(parse_groups).transpose()?,
             hb:     hash.get("HB").map(|i| i.as_int()).transpose()?,
             recipe: hash
                 .get("Recipe")
                 .ok_or(UKError::MissingBymlKey("Recipe missing recipe actor"))?
                 .as_int::<i32>()?,
-            tags:   hash
-                .get("Tags")
-                .map(|arr| -> Result<DeleteVec<DeleteVec<i32>>> {
-                    arr.as_array()?
-                        .iter()
-                        .map(|arr2| -> Result<DeleteVec<i32>> {
-                            arr2.as_array()?
-                                .iter()
-                                .map(|i| Ok(i.as_int::<i32>()?))
-                                .collect()
-                        })
-                        .collect()
-                })
-                .transpose()?,
+            tags:   hash.get("Tags").map(parse_groups).transpose()?,
         })
     }
 }
 
 impl From<&Recipe> for Byml {
     fn from(val: &Recipe) -> Byml {
+        let groups_to_byml = |groups: &Vec<Vec<i32>>| -> Byml {
+            groups
+                .iter()
+                .map(|group| {
+                    group
+                        .iter()
+                        .map(|v| hash_to_byml(*v))
+                        .collect::<Vec<Byml>>()
+                        .into()
+                })
+                .collect::<Vec<Byml>>()
+                .into()
+        };
         let mut hash: HashMap<SmartString<LazyCompact>, Byml> = HashMap::default();
         if let Some(actors) = &val.actors {
-            hash.insert(
-                "Actors".into(),
-                actors
-                    .iter()
-                    .map(|v| {
-                        v.iter()
-                            .map(|v2| {
-                                if *v2 < 0 {
-                                    Byml::U32(*v2 as u32)
-                                } else {
-                                    Byml::I32(*v2)
-                                }
-                            })
-                            .collect::<Vec<Byml>>()
-                            .into()
-                    })
-                    .collect::<Vec<Byml>>()
-                    .into(),
-            );
+            hash.insert("Actors".into(), groups_to_byml(actors));
         }
         if let Some(hb) = val.hb {
             hash.insert("HB".into(), hb.into());
         };
-        let recipe: Byml = if val.recipe < 0 {
-            Byml::U32(val.recipe as u32)
-        } else {
-            Byml::I32(val.recipe)
-        };
-        hash.insert("Recipe".into(), recipe);
+        hash.insert("Recipe".into(), hash_to_byml(val.recipe));
         if let Some(tags) = &val.tags {
-            hash.insert(
-                "Tags".into(),
-                tags.iter()
-                    .map(|v| {
-                        v.iter()
-                            .map(|v2| {
-                                if *v2 < 0 {
-                                    Byml::U32(*v2 as u32)
-                                } else {
-                                    Byml::I32(*v2)
-                                }
-                            })
-                            .collect::<Vec<Byml>>()
-                            .into()
-                    })
-                    .collect::<Vec<Byml>>()
-                    .into(),
-            );
+            hash.insert("Tags".into(), groups_to_byml(tags));
         }
         hash.into()
     }
@@ -3,19 +3,28 @@ use roead::byml::Byml;
 use serde::{Deserialize, Serialize};
 use smartstring::{LazyCompact, SmartString};
 
-use crate::{
-    util::{DeleteVec, HashMap},
-    Result, UKError,
-};
+use super::recipe::{hash_to_byml, parse_hashes, RecipeKey};
+use crate::{util::HashMap, Result, UKError};
 
 #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 
 pub struct SingleRecipe {
-    pub actors: Option<DeleteVec<i32>>,
+    pub actors: Option<Vec<i32>>,
     pub hb:     Option<i32>,
     pub num:    i32,
     pub recipe: i32,
-    pub tags:   Option<DeleteVec<i32>>,
+    pub tags:   Option<Vec<i32>>,
+}
+
+impl SingleRecipe {
+    pub fn key(&self, nth: usize) -> RecipeKey {
+        RecipeKey {
+            recipe: self.recipe,
+            actors: self.actors.iter().cloned().collect(),
+            tags: self.tags.iter().cloned().collect(),
+            nth,
+        }
+    }
 }
 
 impl TryFrom<&Byml> for SingleRecipe {
@@ -23,16 +32,14 @@ impl TryFrom<&Byml> for SingleRecipe {
 
     fn try_from(byml: &Byml) -> Result<Self> {
         let hash = byml.as_map()?;
+        // Any of the listed actors or tags will do, so their order is irrelevant
+        let parse_sorted = |byml: &Byml| -> Result<Vec<i32>> {
+            let mut hashes = parse_hashes(byml)?;
+            hashes.sort_unstable();
+            Ok(hashes)
+        };
         Ok(Self {
-            actors: hash
-                .get("Actors")
-                .map(|arr| -> Result<_> {
-                    arr.as_array()?
-                        .iter()
-                        .map(|i| Ok(i.as_int::<i32>()?))
-                        .collect()
-                })
-                .transpose()?,
+            actors: hash.get("Actors").map(parse_sorted).transpose()?,
             hb:     hash
                 .get("HB")
                 .map(|i| i.as_i32().context("HB not int"))
@@ -45,62 +52,31 @@ impl TryFrom<&Byml> for SingleRecipe {
                 .get("Recipe")
                 .ok_or(UKError::MissingBymlKey("SingleRecipe missing recipe actor"))?
                 .as_int::<i32>()?,
-            tags:   hash
-                .get("Tags")
-                .map(|arr| -> Result<_> {
-                    arr.as_array()?
-                        .iter()
-                        .map(|i| Ok(i.as_int::<i32>()?))
-                        .collect()
-                })
-                .transpose()?,
+            tags:   hash.get("Tags").map(parse_sorted).transpose()?,
         })
     }
 }
 
 impl From<&SingleRecipe> for Byml {
     fn from(val: &SingleRecipe) -> Byml {
+        let hashes_to_byml = |hashes: &Vec<i32>| -> Byml {
+            hashes
+                .iter()
+                .map(|v| hash_to_byml(*v))
+                .collect::<Vec<Byml>>()
+                .into()
+        };
         let mut hash: HashMap<SmartString<LazyCompact>, Byml> = HashMap::default();
         if let Some(actors) = &val.actors {
-            hash.insert(
-                "Actors".into(),
-                actors
-                    .iter()
-                    .map(|v| {
-                        if *v < 0 {
-                            Byml::U32(*v as u32)
-                        } else {
-                            Byml::I32(*v)
-                        }
-                    })
-                    .collect::<Vec<Byml>>()
-                    .into(),
-            );
+            hash.insert("Actors".into(), hashes_to_byml(actors));
         }
         if let Some(hb) = val.hb {
             hash.insert("HB".into(), hb.into());
         };
         hash.insert("Num".into(), val.num.into());
-        let recipe: Byml = if val.recipe < 0 {
-            Byml::U32(val.recipe as u32)
-        } else {
-            Byml::I32(val.recipe)
-        };
-        hash.insert("Recipe".into(), recipe);
+        hash.insert("Recipe".into(), hash_to_byml(val.recipe));
         if let Some(tags) = &val.tags {
-            hash.insert(
-                "Tags".into(),
-                tags.iter()
-                    .map(|v| {
-                        if *v < 0 {
-                            Byml::U32(*v as u32)
-                        } else {
-                            Byml::I32(*v)
-                        }
-                    })
-                    .collect::<Vec<Byml>>()
-                    .into(),
-            );
+            hash.insert("Tags".into(), hashes_to_byml(tags));
         }
         hash.into()
     }
@@ -3,20 +3,21 @@ use roead::byml::{map, Byml};
 use serde::{Deserialize, Serialize};
 use smartstring::{LazyCompact, SmartString};
 
-use crate::{prelude::Mergeable, util::DeleteVec, Result, UKError};
+use crate::{prelude::Mergeable, util::DeleteMap, Result, UKError};
 
 #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 
 pub struct System {
-    pub cei:    DeleteVec<Cei>,
+    /// Cure effect info, keyed by effect type.
+    pub cei:    DeleteMap<i32, Cei>,
     pub fa:     SmartString<LazyCompact>,
     pub falr:   i32,
     pub falrmr: f32,
     pub fca:    SmartString<LazyCompact>,
     pub lrmr:   f32,
     pub mea:    SmartString<LazyCompact>,
-    pub nmmr:   DeleteVec<f32>,
-    pub nmssr:  DeleteVec<i32>,
+    pub nmmr:   DeleteMap<usize, f32>,
+    pub nmssr:  DeleteMap<usize, i32>,
     pub sfalr:  i32,
     pub ssaet:  i32,
 }
@@ -32,7 +33,10 @@ impl TryFrom<&Byml> for System {
                 .ok_or(UKError::MissingBymlKey("System missing CEI"))?
                 .as_array()?
                 .iter()
-                .map(|b| Ok(Cei::try_from(b).context("Failed to parse CEI")?))
+                .map(|b| {
+                    let cei = Cei::try_from(b).context("Failed to parse CEI")?;
+                    Ok((cei.t, cei))
+                })
                 .collect::<Result<_>>()?,
             fa:     hash
                 .get("FA")
@@ -66,14 +70,16 @@ impl TryFrom<&Byml> for System {
                 .ok_or(UKError::MissingBymlKey("System missing NMMR"))?
                 .as_array()?
                 .iter()
-                .map(|b| Ok(b.as_float()?))
+                .enumerate()
+                .map(|(i, b)| Ok((i, b.as_float()?)))
                 .collect::<Result<_>>()?,
             nmssr:  hash
                 .get("NMSSR")
                 .ok_or(UKError::MissingBymlKey("System missing NMSSR"))?
                 .as_array()?
                 .iter()
-                .map(|b| Ok(b.as_i32()?))
+                .enumerate()
+                .map(|(i, b)| Ok((i, b.as_i32()?)))
                 .collect::<Result<_>>()?,
             sfalr:  hash
                 .get("SFALR")
@@ -90,15 +96,15 @@ impl TryFrom<&Byml> for System {
 impl From<System> for Byml {
     fn from(val: System) -> Byml {
         map! {
-            "CEI" => val.cei.iter().map(Byml::from).collect(),
+            "CEI" => val.cei.values().map(Byml::from).collect(),
             "FA" => val.fa.clone().into(),
             "FALR" => val.falr.into(),
             "FALRMR" => val.falrmr.into(),
             "FCA" => val.fca.clone().into(),
             "LRMR" => val.lrmr.into(),
             "MEA" => val.mea.clone().into(),
-            "NMMR" => val.nmmr.iter().map(|n| Byml::Float(*n)).collect(),
-            "NMSSR" => val.nmssr.iter().map(|n| Byml::I32(*n)).collect(),
+            "NMMR" => val.nmmr.values().map(|n| Byml::Float(*n)).collect(),
+            "NMSSR" => val.nmssr.values().map(|n| Byml::I32(*n)).collect(),
             "SFALR" => val.sfalr.into(),
             "SSAET" => val.ssaet.into(),
         }
@@ -270,6 +270,32 @@ impl GameDataPack {
     pub const STAGES: &'static [&'static str] =
         &["MainField", "AocField", "CDungeon", "MainFieldDungeon"];
 
+    /// Iterates the names of every flag in the pack, of any type.
+    pub fn flag_names(&self) -> impl Iterator<Item = &str> {
+        [
+            &self.bool_array_data,
+            &self.bool_data,
+            &self.f32_array_data,
+            &self.f32_data,
+            &self.revival_bool_data,
+            &self.revival_s32_data,
+            &self.s32_array_data,
+            &self.s32_data,
+            &self.string32_data,
+            &self.string64_array_data,
+            &self.string64_data,
+            &self.string256_array_data,
+            &self.string256_data,
+            &self.vector2f_array_data,
+            &self.vector2f_data,
+            &self.vector3f_array_data,
+            &self.vector3f_data,
+            &self.vector4f_data,
+        ]
+        .into_iter()
+        .flat_map(|data| data.flags.keys().map(|name| name.as_str()))
+    }
+
     pub fn from_sarc_writer(sarc: &SarcWriter) -> Result<Self> {
         let source = SarcSource::Writer(sarc);
         if sarc
@@ -1,7 +1,11 @@
 use roead::byml::Byml;
 use serde::{Deserialize, Serialize};
 
-use crate::{prelude::*, util::SortedDeleteMap, Result, UKError};
+use crate::{
+    prelude::*,
+    util::{byml_key_field, diff_byml_keyed, is_keyed_diff, merge_byml_keyed, SortedDeleteMap},
+    Result, UKError,
+};
 
 #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 
@@ -36,13 +40,65 @@ impl From<AreaData> for Byml {
     }
 }
 
+/// Spawn lists are matched up by the actor or folder they spawn, so mods
+/// tweaking different entries of the same area's list both keep them.
+const SPAWN_KEYS: &[&str] = &["name", "folder_name"];
+
 impl Mergeable for AreaData {
     fn diff(&self, other: &Self) -> Self {
-        Self(self.0.deep_diff(&other.0))
+        Self(
+            other
+                .0
+                .iter()
+                .filter_map(|(num, area)| {
+                    match self.0.get(num) {
+                        Some(base_area) if base_area == area => None,
+                        Some(base_area) => {
+                            Some((
+                                *num,
+                                diff_byml_keyed(base_area, area, byml_key_field(SPAWN_KEYS)),
+                                false,
+                            ))
+                        }
+                        None => Some((*num, area.clone(), false)),
+                    }
+                })
+                .chain(
+                    self.0
+                        .iter()
+                        .filter(|(num, _)| !other.0.contains_key(*num))
+                        .map(|(num, area)| (*num, area.clone(), true)),
+                )
+                .collect(),
+        )
     }
 
     fn merge(&self, diff: &Self) -> Self {
-        Self(self.0.deep_merge(&diff.0))
+        Self(
+            self.0
+                .iter()
+                .filter(|(num, _)| diff.0.is_delete(*num) != Some(true))
+                .map(|(num, area)| {
+                    match diff.0.get(num) {
+                        Some(area_diff) if is_keyed_diff(area_diff) => {
+                            (
+                                *num,
+                                merge_byml_keyed(area, area_diff, byml_key_field(SPAWN_KEYS)),
+                            )
+                        }
+                        // Diffs made before keyed diffs hold the whole area
+                        Some(area_diff) => (*num, area_diff.clone()),
+                        None => (*num, area.clone()),
+                    }
+                })
+                .chain(
+                    diff.0
+                        .iter()
+                        .filter(|(num, _)| !self.0.contains_key(*num))
+                        .map(|(num, area)| (*num, area.clone())),
+                )
+                .collect(),
+        )
     }
 }
 
@@ -113,6 +169,44 @@ mod tests {
         assert_eq!(merged, areadata2);
     }
 
+    #[test]
+    fn merge_fields() {
+        let byml = load_areadata();
+        let areadata = super::AreaData::try_from(&byml).unwrap();
+        let (num, area) = areadata
+            .0
+            .iter()
+            .find(|(_, area)| {
+                area.as_map()
+                    .unwrap()
+                    .get("Enemy")
+                    .is_some_and(|e| e.as_array().unwrap().len() > 1)
+            })
+            .map(|(num, area)| (*num, area.clone()))
+            .unwrap();
+        // Sets the spawn count of one enemy of the area
+        let tweak = |index: usize, count: f32| {
+            let mut area = area.clone();
+            let enemies = area.as_mut_map().unwrap().get_mut("Enemy").unwrap();
+            enemies.as_mut_array().unwrap()[index]
+                .as_mut_map()
+                .unwrap()
+                .insert("num".into(), Byml::Float(count));
+            let mut modded = areadata.clone();
+            modded.0.insert(num, area);
+            modded
+        };
+        let merged = areadata
+            .merge(&areadata.diff(&tweak(0, 100.0)))
+            .merge(&areadata.diff(&tweak(1, 200.0)));
+        let area = merged.0.get(num).unwrap().as_map().unwrap();
+        let enemies = area.get("Enemy").unwrap().as_array().unwrap();
+        let count = |index: usize| enemies[index].as_map().unwrap().get("num").cloned();
+        assert_eq!(count(0), Some(Byml::Float(100.0)));
+        assert_eq!(count(1), Some(Byml::Float(200.0)));
+        assert_eq!(merged.0.len(), areadata.0.len());
+    }
+
     #[test]
     fn identify() {
         let path = std::path::Path::new("content/Pack/Bootup.pack//Ecosystem/AreaData.sbyml");
@@ -345,6 +345,34 @@ mod tests {
         assert_eq!(merged, sensor2);
     }
 
+    #[test]
+    fn merge_fields() {
+        let byml = load_sensor();
+        let sensor = super::LevelSensor::try_from(&byml).unwrap();
+        let (species, actors) = sensor
+            .enemy
+            .iter()
+            .find(|(_, actors)| actors.len() > 1)
+            .map(|(species, actors)| (species.clone(), actors.clone()))
+            .unwrap();
+        let names: Vec<_> = actors.keys().cloned().collect();
+        // Sets the value of one enemy of the species
+        let tweak = |name: &str, value: f32| {
+            let mut modded = sensor.clone();
+            let mut actors = actors.clone();
+            actors.insert(String::from(name), value);
+            modded.enemy.insert(species.clone(), actors);
+            modded
+        };
+        let merged = sensor
+            .merge(&sensor.diff(&tweak(&names[0], 100.0)))
+            .merge(&sensor.diff(&tweak(&names[1], 200.0)));
+        let merged_actors = merged.enemy.get(&species).unwrap();
+        assert_eq!(merged_actors.get(&names[0]), Some(&100.0));
+        assert_eq!(merged_actors.get(&names[1]), Some(&200.0));
+        assert_eq!(merged_actors.len(), actors.len());
+    }
+
     #[test]
     fn identify() {
         let path = std::path::Path::new("content/Pack/Bootup.pack//Ecosystem/LevelSensor.sbyml");
@@ -96,7 +96,9 @@ impl Mergeable for StatusEffectValues {
             (Self::Normal(self_values), Self::Normal(other_values)) => {
                 Self::Normal(self_values.diff(other_values))
             }
-            _ => panic!("Attempted to diff incompatible status effect types"),
+            // A change of effect kind can't be expressed per value, so the
+            // modded entry simply replaces the original.
+            _ => other.clone(),
         }
     }
 
@@ -104,9 +106,17 @@ impl Mergeable for StatusEffectValues {
         match (self, diff) {
             (Self::Special, Self::Special) => Self::Special,
             (Self::Normal(self_values), Self::Normal(diff_values)) => {
-                Self::Normal(self_values.merge(diff_values))
+                // Values are keyed by their index, so a later mod changing the
+                // same value replaces it rather than adding a duplicate.
+                let mut values: BTreeMap<i32, f32> = self_values
+                    .iter()
+                    .filter(|item| diff_values.is_delete(*item) != Some(true))
+                    .copied()
+                    .collect();
+                values.extend(diff_values.iter().copied());
+                Self::Normal(values.into_iter().collect())
             }
-            _ => panic!("Attempted to merge incompatible status effect types"),
+            _ => diff.clone(),
         }
     }
 }
@@ -147,32 +157,30 @@ impl From<StatusEffectList> for Byml {
 impl Mergeable for StatusEffectList {
     fn diff(&self, other: &Self) -> Self {
         Self(
-            self.0
+            other
+                .0
                 .iter()
-                .filter_map(|(effect, self_values)| {
-                    let other_values = &other.0[effect];
-                    (self_values != other_values)
-                        .then(|| (effect.clone(), self_values.diff(other_values)))
+                .filter_map(|(effect, other_values)| {
+                    match self.0.get(effect) {
+                        Some(self_values) if self_values == other_values => None,
+                        Some(self_values) => Some((effect.clone(), self_values.diff(other_values))),
+                        None => Some((effect.clone(), other_values.clone())),
+                    }
                 })
                 .collect(),
         )
     }
 
     fn merge(&self, diff: &Self) -> Self {
-        Self(
-            self.0
-                .iter()
-                .map(|(effect, self_values)| {
-                    (
-                        effect.clone(),
-                        diff.0
-                            .get(effect)
-                            .map(|diff_values| self_values.merge(diff_values))
-                            .unwrap_or_else(|| self_values.clone()),
-                    )
-                })
-                .collect(),
-        )
+        let mut merged = self.0.clone();
+        for (effect, diff_values) in &diff.0 {
+            let values = match self.0.get(effect) {
+                Some(self_values) => self_values.merge(diff_values),
+                None => diff_values.clone(),
+            };
+            merged.insert(effect.clone(), values);
+        }
+        Self(merged)
     }
 }
 
@@ -247,6 +255,42 @@ mod tests {
         assert_eq!(merged, status2);
     }
 
+    #[test]
+    fn merge_fields() {
+        let byml = load_status();
+        let status = super::StatusEffectList::try_from(&byml).unwrap();
+        let (effect, values) = status
+            .0
+            .iter()
+            .find(|(_, v)| matches!(v, super::StatusEffectValues::Normal(_)))
+            .unwrap();
+        let super::StatusEffectValues::Normal(values) = values else {
+            unreachable!()
+        };
+        let bump = |by: f32| {
+            super::StatusEffectValues::Normal(
+                values
+                    .iter()
+                    .map(|&(i, v)| if i == 0 { (i, v + by) } else { (i, v) })
+                    .collect(),
+            )
+        };
+        let mut modded = status.clone();
+        modded.0.insert(effect.clone(), bump(1.0));
+        modded
+            .0
+            .insert("NewEffect".into(), super::StatusEffectValues::Special);
+        let diff = status.diff(&modded);
+        assert_eq!(diff.0.len(), 2);
+        let merged = status.merge(&diff);
+        assert_eq!(merged, modded);
+
+        let mut modded2 = status.clone();
+        modded2.0.insert(effect.clone(), bump(2.0));
+        let merged = merged.merge(&status.diff(&modded2));
+        assert_eq!(merged.0[effect], bump(2.0));
+    }
+
     #[test]
     fn identify() {
         let path =
@@ -25,7 +25,11 @@ impl From<EventInfo> for Byml {
     }
 }
 
-impl_simple_byml!(EventInfo, 0);
+/// Entries of an event's lists are matched up by the flow entry, file or BGM
+/// they name, so mods adding to the same event's subfiles both keep theirs.
+const EVENT_KEYS: &[&str] = &["entry", "file", "name"];
+
+impl_simple_byml!(EventInfo, 0, EVENT_KEYS);
 
 impl Resource for EventInfo {
     fn from_binary(data: impl AsRef<[u8]>) -> crate::Result<Self> {
@@ -96,6 +100,38 @@ mod tests {
         assert_eq!(merged, eventinfo2);
     }
 
+    #[test]
+    fn merge_additions() {
+        let eventinfo = super::EventInfo::from(load_eventinfo());
+        let mod1 = super::EventInfo::from(load_mod_eventinfo());
+        let mut byml2 = load_eventinfo();
+        byml2["Demo005_1<Demo005_1>"]["subfile"]
+            .as_mut_array()
+            .unwrap()
+            .push(Byml::Map(
+                [("file".into(), Byml::String("Extra.bfevfl".into()))]
+                    .into_iter()
+                    .collect(),
+            ));
+        let mod2 = super::EventInfo::from(byml2);
+        // Both mods add a subfile to the same event, and both are kept
+        let merged = eventinfo
+            .merge(&eventinfo.diff(&mod1))
+            .merge(&eventinfo.diff(&mod2));
+        let files: Vec<_> = merged.0["Demo005_1<Demo005_1>"]["subfile"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["file"].as_string().unwrap().as_str())
+            .collect();
+        assert_eq!(files, [
+            "Aoc3Resident.bfevfl",
+            "Aoc2Resident.bfevfl",
+            "AocResident.bfevfl",
+            "Extra.bfevfl"
+        ]);
+    }
+
     #[test]
     fn identify() {
         let path = std::path::Path::new("content/Pack/TitleBG.pack//Event/EventInfo.product.sbyml");
@@ -0,0 +1,163 @@
+//! Generated game data for benchmarks. The same size always gives the same
+//! data, so timings can be compared between builds, and the work to diff or
+//! merge it grows with the size. Only built with the `fixtures` feature.
+use roead::{
+    aamp::{Parameter, ParameterIO, ParameterList, ParameterObject},
+    byml::Byml,
+};
+
+/// A fixed pseudo-random sequence, so generated data never changes between
+/// runs or platforms.
+#[derive(Debug, Clone)]
+pub struct Sequence(u64);
+
+impl Sequence {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        // The multiplier and increment from Knuth's MMIX
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 32) as u32
+    }
+
+    /// A number below `bound`.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        self.next_u32() % bound.max(1)
+    }
+}
+
+fn byml_value(seq: &mut Sequence, depth: usize) -> Byml {
+    match seq.below(6) {
+        0 => Byml::Bool(seq.below(2) == 0),
+        1 => Byml::I32(seq.below(1000) as i32),
+        2 => Byml::Float(seq.below(10000) as f32 / 100.0),
+        3 => Byml::String(format!("Value_{}", seq.below(512)).into()),
+        4 if depth > 0 => Byml::Array((0..4).map(|_| byml_value(seq, depth - 1)).collect()),
+        5 if depth > 0 => {
+            Byml::Map(
+                (0..4)
+                    .map(|i| (format!("Key{i}").into(), byml_value(seq, depth - 1)))
+                    .collect(),
+            )
+        }
+        _ => Byml::I32(seq.below(1000) as i32),
+    }
+}
+
+fn byml_entry(seq: &mut Sequence) -> Byml {
+    Byml::Map(
+        (0..6)
+            .map(|i| (format!("Field{i}").into(), byml_value(seq, 2)))
+            .collect(),
+    )
+}
+
+fn build_byml(size: usize, every: Option<usize>) -> Byml {
+    let mut seq = Sequence::new(size as u64);
+    let mut changes = Sequence::new(!(size as u64));
+    let mut entries: Vec<(String, Byml)> = (0..size)
+        .map(|i| {
+            let mut entry = byml_entry(&mut seq);
+            if every.is_some_and(|every| i % every == 0) {
+                entry = byml_entry(&mut changes);
+            }
+            (format!("Entry_{i:05}"), entry)
+        })
+        .collect();
+    if let Some(every) = every {
+        entries
+            .extend((0..size / every).map(|i| (format!("Added_{i:05}"), byml_entry(&mut changes))));
+    }
+    Byml::Map(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+}
+
+/// A BYML map of `size` entries, each a small map of mixed values, like the
+/// actor and map data the game keeps in BYML.
+pub fn byml(size: usize) -> Byml {
+    build_byml(size, None)
+}
+
+/// [`byml`] as a mod might change it, with every `every`th entry replaced and
+/// a new entry added for each one changed.
+pub fn modded_byml(size: usize, every: usize) -> Byml {
+    build_byml(size, Some(every.max(1)))
+}
+
+const FIELDS: [&str; 6] = ["Value", "Rate", "Flag", "Name", "Radius", "Count"];
+
+fn parameter(seq: &mut Sequence) -> Parameter {
+    match seq.below(4) {
+        0 => Parameter::Bool(seq.below(2) == 0),
+        1 => Parameter::I32(seq.below(1000) as i32),
+        2 => Parameter::F32(seq.below(10000) as f32 / 100.0),
+        _ => Parameter::StringRef(format!("Value_{}", seq.below(512)).into()),
+    }
+}
+
+fn parameter_object(seq: &mut Sequence) -> ParameterObject {
+    FIELDS.iter().fold(ParameterObject::new(), |obj, field| {
+        obj.with_parameter(*field, parameter(seq))
+    })
+}
+
+fn build_parameter_io(size: usize, every: Option<usize>) -> ParameterIO {
+    let mut seq = Sequence::new(size as u64);
+    let mut changes = Sequence::new(!(size as u64));
+    let mut objects: Vec<(String, ParameterObject)> = (0..size)
+        .map(|i| {
+            let mut obj = parameter_object(&mut seq);
+            if every.is_some_and(|every| i % every == 0) {
+                obj = obj.with_parameter("Value", parameter(&mut changes));
+            }
+            (format!("Entry_{i}"), obj)
+        })
+        .collect();
+    if let Some(every) = every {
+        objects.extend(
+            (0..size / every).map(|i| (format!("Added_{i}"), parameter_object(&mut changes))),
+        );
+    }
+    ParameterIO::new().with_list("Entries", ParameterList::new().with_objects(objects))
+}
+
+/// A parameter archive with a list of `size` objects, each holding a handful
+/// of parameters, like the AI and physics parameters of an actor.
+pub fn parameter_io(size: usize) -> ParameterIO {
+    build_parameter_io(size, None)
+}
+
+/// [`parameter_io`] as a mod might change it, with a parameter changed in
+/// every `every`th object and a new object added for each one changed.
+pub fn modded_parameter_io(size: usize, every: usize) -> ParameterIO {
+    build_parameter_io(size, Some(every.max(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::Mergeable;
+
+    #[test]
+    fn stable() {
+        assert_eq!(byml(50), byml(50));
+        assert_eq!(parameter_io(50), parameter_io(50));
+        assert_ne!(byml(50), modded_byml(50, 10));
+        let Byml::Map(modded) = modded_byml(50, 10) else {
+            unreachable!()
+        };
+        assert_eq!(modded.len(), 55);
+    }
+
+    #[test]
+    fn merges_back() {
+        let (base, modded) = (byml(200), modded_byml(200, 10));
+        assert_eq!(base.merge(&base.diff(&modded)), modded);
+        let (base, modded) = (parameter_io(200), modded_parameter_io(200, 10));
+        assert_eq!(base.merge(&base.diff(&modded)), modded);
+    }
+}
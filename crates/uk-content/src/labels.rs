@@ -0,0 +1,138 @@
+//! Human-friendly labels for the raw identifiers shown throughout the UI.
+//!
+//! Actor names are resolved to their in-game display names using the message
+//! pack for the current language, and name hashes (as found in AAMP files and
+//! game data) are resolved back to the names they were made from.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{data::gamedata::GameDataPack, message::MessagePack};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Labels {
+    /// Display names keyed by actor name.
+    pub actors: BTreeMap<String, String>,
+    /// Known names keyed by their CRC32 hash.
+    pub hashes: BTreeMap<u32, String>,
+}
+
+impl Labels {
+    pub fn from_parts(messages: &MessagePack, gamedata: Option<&GameDataPack>) -> Self {
+        Self {
+            actors: messages.actor_names(),
+            hashes: gamedata
+                .into_iter()
+                .flat_map(|gamedata| gamedata.flag_names())
+                .map(|name| (roead::aamp::hash_name(name), name.to_owned()))
+                .collect(),
+        }
+    }
+
+    /// Looks up a label for either a name hash (decimal or `0x` hex) or a
+    /// resource path, which is labelled by the actor its file is named for.
+    pub fn resolve_label(&self, path_or_hash: &str) -> Option<String> {
+        if let Some(hash) = parse_hash(path_or_hash) {
+            return self.hashes.get(&hash).cloned().or_else(|| {
+                roead::aamp::get_default_name_table()
+                    .get_name(hash, 0, 0)
+                    .map(|name| name.to_string())
+            });
+        }
+        let file = path_or_hash
+            .rsplit("//")
+            .next()
+            .and_then(|path| path.rsplit('/').next())?;
+        let stem = file.split('.').next()?;
+        self.actors.get(stem).cloned()
+    }
+
+    /// Formats an identifier with its label appended, if it has one.
+    pub fn describe(&self, path_or_hash: &str) -> String {
+        match self.resolve_label(path_or_hash) {
+            Some(label) => format!("{path_or_hash} ({label})"),
+            None => path_or_hash.to_owned(),
+        }
+    }
+}
+
+fn parse_hash(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        value
+            .parse::<u32>()
+            .ok()
+            .or_else(|| value.parse::<i32>().ok().map(|v| v as u32))
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use roead::sarc::Sarc;
+
+    use super::*;
+    use crate::prelude::Resource;
+
+    fn labels() -> Labels {
+        let messages =
+            MessagePack::from_binary(std::fs::read("test/Message/Msg_USen.product.ssarc").unwrap())
+                .unwrap();
+        let gamedata = GameDataPack::from_sarc(
+            &Sarc::new(std::fs::read("test/GameData/gamedata.ssarc").unwrap()).unwrap(),
+        )
+        .unwrap();
+        Labels::from_parts(&messages, Some(&gamedata))
+    }
+
+    #[test]
+    fn actors() {
+        let labels = labels();
+        assert!(!labels.actors.is_empty());
+        assert_eq!(
+            labels
+                .resolve_label("Actor/Pack/Weapon_Sword_070.sbactorpack")
+                .as_deref(),
+            Some("Master Sword")
+        );
+        assert_eq!(
+            labels
+                .resolve_label("Pack/TitleBG.pack//Actor/Pack/Weapon_Sword_070.sbactorpack")
+                .as_deref(),
+            Some("Master Sword")
+        );
+        assert_eq!(
+            labels.describe("Actor/Pack/Weapon_Sword_070.sbactorpack"),
+            "Actor/Pack/Weapon_Sword_070.sbactorpack (Master Sword)"
+        );
+        assert_eq!(
+            labels.resolve_label("Actor/Pack/NotAnActor.sbactorpack"),
+            None
+        );
+        assert_eq!(
+            labels.describe("Map/MainField/A-1/A-1_Static.smubin"),
+            "Map/MainField/A-1/A-1_Static.smubin"
+        );
+    }
+
+    #[test]
+    fn hashes() {
+        let labels = labels();
+        let name = labels.hashes.values().next().unwrap().clone();
+        let hash = roead::aamp::hash_name(&name);
+        assert_eq!(labels.resolve_label(&hash.to_string()), Some(name.clone()));
+        assert_eq!(
+            labels.resolve_label(&format!("{hash:#x}")),
+            Some(name.clone())
+        );
+        assert_eq!(
+            labels.resolve_label(&(hash as i32).to_string()),
+            Some(name.clone())
+        );
+    }
+}
@@ -1,29 +1,35 @@
 #![allow(clippy::derive_partial_eq_without_eq, unstable_name_collisions)]
 #![deny(clippy::unwrap_used)]
-use std::path::Path;
-
 use smartstring::alias::String;
 use thiserror::Error;
 
 pub mod actor;
+pub mod canon;
 pub mod chemical;
+pub mod compression;
 pub mod constants;
 pub mod cooking;
 pub mod data;
 pub mod demo;
 pub mod eco;
 pub mod event;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
 pub mod font;
+pub mod labels;
 pub mod layout;
 pub mod map;
 pub mod message;
 pub mod quest;
 pub mod resource;
+pub mod sizetable;
 pub mod sound;
 pub mod tips;
 pub mod util;
 pub mod worldmgr;
 
+pub use canon::canonicalize;
+
 #[derive(Debug, Clone)]
 pub enum ContextData {
     Parameter(roead::aamp::Parameter),
@@ -151,40 +157,6 @@ pub const fn platform_prefixes(endian: prelude::Endian) -> (&'static str, &'stat
     }
 }
 
-pub fn canonicalize(path: impl AsRef<Path>) -> String {
-    fn canonicalize(path: &Path) -> String {
-        let path = path.to_str().unwrap_or("INVALID_FILENAME");
-        let mut canon = path.replace('\\', "/");
-        for (k, v) in [
-            ("Content/", ""),
-            ("content/", ""),
-            ("atmosphere/titles/", ""),
-            ("atmosphere/contents/", ""),
-            ("01007EF00011E000/romfs/", ""),
-            ("01007ef00011e000/romfs/", ""),
-            ("01007EF00011E001/romfs", "Aoc/0010"),
-            ("01007EF00011E002/romfs", "Aoc/0010"),
-            ("01007EF00011F001/romfs", "Aoc/0010"),
-            ("01007EF00011F002/romfs", "Aoc/0010"),
-            ("01007ef00011e001/romfs", "Aoc/0010"),
-            ("01007ef00011e002/romfs", "Aoc/0010"),
-            ("01007ef00011f001/romfs", "Aoc/0010"),
-            ("01007ef00011f002/romfs", "Aoc/0010"),
-            ("romfs/", ""),
-            ("aoc/content", "Aoc"),
-            ("aoc", "Aoc"),
-        ]
-        .into_iter()
-        {
-            if canon.starts_with(k) {
-                canon = [v, canon.trim_start_matches(k)].concat();
-            }
-        }
-        canon.replace(".s", ".").into()
-    }
-    canonicalize(path.as_ref())
-}
-
 pub mod prelude {
     pub(crate) use smartstring::alias::String;
     pub type String32 = roead::types::FixedSafeString<32>;
@@ -258,6 +230,27 @@ pub mod prelude {
                 }
             }
         };
+        ($type:ty, $field:tt, $keys:expr) => {
+            impl Mergeable for $type {
+                fn diff(&self, other: &Self) -> Self {
+                    crate::util::diff_byml_keyed(
+                        &self.$field,
+                        &other.$field,
+                        crate::util::byml_key_field($keys),
+                    )
+                    .into()
+                }
+
+                fn merge(&self, diff: &Self) -> Self {
+                    crate::util::merge_byml_keyed(
+                        &self.$field,
+                        &diff.$field,
+                        crate::util::byml_key_field($keys),
+                    )
+                    .into()
+                }
+            }
+        };
     }
 
     impl Mergeable for roead::byml::Byml {
@@ -358,8 +351,6 @@ pub mod prelude {
 pub(crate) mod tests {
     use join_str::jstr;
 
-    use crate::canonicalize;
-
     pub fn test_base_actorpack(name: &str) -> roead::sarc::Sarc<'static> {
         roead::sarc::Sarc::new(
             roead::yaz0::decompress(
@@ -379,34 +370,4 @@ pub(crate) mod tests {
         )
         .unwrap()
     }
-
-    #[test]
-    fn canon_names() {
-        assert_eq!(
-            &canonicalize("content\\Actor\\Pack\\Enemy_Lizal_Senior.sbactorpack"),
-            "Actor/Pack/Enemy_Lizal_Senior.bactorpack"
-        );
-        assert_eq!(
-            &canonicalize("aoc/0010/Map/MainField/A-1/A-1_Dynamic.smubin"),
-            "Aoc/0010/Map/MainField/A-1/A-1_Dynamic.mubin"
-        );
-        assert_eq!(
-            &canonicalize(
-                "atmosphere/contents/01007EF00011E000/romfs/Actor/ActorInfo.product.sbyml"
-            ),
-            "Actor/ActorInfo.product.byml"
-        );
-        assert_eq!(
-            &canonicalize("atmosphere/contents/01007EF00011F001/romfs/Pack/AocMainField.pack"),
-            "Aoc/0010/Pack/AocMainField.pack"
-        );
-        assert_eq!(
-            &canonicalize("Hellow/Sweetie.tardis"),
-            "Hellow/Sweetie.tardis"
-        );
-        assert_eq!(
-            &canonicalize("Event/EventInfo.product.sbyml"),
-            "Event/EventInfo.product.byml"
-        )
-    }
 }
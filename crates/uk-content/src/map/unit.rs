@@ -1,7 +1,11 @@
 use roead::byml::Byml;
 use serde::{Deserialize, Serialize};
 
-use crate::{prelude::*, util::SortedDeleteMap, Result, UKError};
+use crate::{
+    prelude::*,
+    util::{byml_key_field, diff_byml_keyed, is_keyed_diff, merge_byml_keyed, SortedDeleteMap},
+    Result, UKError,
+};
 
 #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 
@@ -86,13 +90,71 @@ impl From<MapUnit> for Byml {
     }
 }
 
+/// Links between objects are matched up by the object they point to, so mods
+/// linking the same object to different ones both keep their links.
+const LINK_KEYS: &[&str] = &["DestUnitHashId"];
+
+fn diff_objects(
+    base: &SortedDeleteMap<u32, Byml>,
+    other: &SortedDeleteMap<u32, Byml>,
+) -> SortedDeleteMap<u32, Byml> {
+    other
+        .iter()
+        .filter_map(|(id, obj)| {
+            match base.get(id) {
+                Some(base_obj) if base_obj == obj => None,
+                Some(base_obj) => {
+                    Some((
+                        *id,
+                        diff_byml_keyed(base_obj, obj, byml_key_field(LINK_KEYS)),
+                        false,
+                    ))
+                }
+                None => Some((*id, obj.clone(), false)),
+            }
+        })
+        .chain(
+            base.iter()
+                .filter(|(id, _)| !other.contains_key(*id))
+                .map(|(id, obj)| (*id, obj.clone(), true)),
+        )
+        .collect()
+}
+
+fn merge_objects(
+    base: &SortedDeleteMap<u32, Byml>,
+    diff: &SortedDeleteMap<u32, Byml>,
+) -> SortedDeleteMap<u32, Byml> {
+    base.iter()
+        .filter(|(id, _)| diff.is_delete(*id) != Some(true))
+        .map(|(id, obj)| {
+            match diff.get(id) {
+                Some(obj_diff) if is_keyed_diff(obj_diff) => {
+                    (
+                        *id,
+                        merge_byml_keyed(obj, obj_diff, byml_key_field(LINK_KEYS)),
+                    )
+                }
+                // Diffs made before keyed diffs hold the whole object
+                Some(obj_diff) => (*id, obj_diff.clone()),
+                None => (*id, obj.clone()),
+            }
+        })
+        .chain(
+            diff.iter()
+                .filter(|(id, _)| !base.contains_key(*id))
+                .map(|(id, obj)| (*id, obj.clone())),
+        )
+        .collect()
+}
+
 impl Mergeable for MapUnit {
     fn diff(&self, other: &Self) -> Self {
         Self {
             pos_x:   other.pos_x,
             pos_z:   other.pos_z,
             size:    other.size,
-            objects: self.objects.diff(&other.objects),
+            objects: diff_objects(&self.objects, &other.objects),
             rails:   self.rails.diff(&other.rails),
         }
     }
@@ -102,7 +164,7 @@ impl Mergeable for MapUnit {
             pos_x:   diff.pos_x,
             pos_z:   diff.pos_z,
             size:    diff.size,
-            objects: self.objects.merge(&diff.objects),
+            objects: merge_objects(&self.objects, &diff.objects),
             rails:   self.rails.merge(&diff.rails),
         }
     }
@@ -238,6 +300,21 @@ mod tests {
         assert_eq!(merged, munt2);
     }
 
+    #[test]
+    fn merge_unkeyed() {
+        let munt = super::MapUnit::try_from(&load_cdungeon_munt()).unwrap();
+        let (id, obj) = munt.objects.iter().next().unwrap();
+        let mut obj = obj.as_map().unwrap().clone();
+        obj.remove("Translate");
+        obj.insert("UnitConfigName".into(), Byml::String("Dummy".into()));
+        // A diff from before keyed diffs replaces the whole object, so keys
+        // it lacks are not brought back
+        let mut diff = super::MapUnit::default();
+        diff.objects.insert(*id, Byml::Map(obj.clone()));
+        let merged = munt.merge(&diff);
+        assert_eq!(merged.objects.get(id), Some(&Byml::Map(obj)));
+    }
+
     #[test]
     fn identify() {
         let path = std::path::Path::new("content/Map/MainField/F-3/F-3_Dynamic.smubin");
@@ -4,7 +4,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use anyhow::Context;
 use join_str::jstr;
 pub use msyt::{
-    model::{Entry, MsbtInfo},
+    model::{Content, Entry, MsbtInfo},
     Endianness, Msyt,
 };
 use roead::sarc::{Sarc, SarcWriter};
@@ -120,6 +120,31 @@ impl TryFrom<&'_ Sarc<'_>> for MessagePack {
 }
 
 impl MessagePack {
+    /// Collects the display name of every actor with one, keyed by actor name,
+    /// from the `_Name` entries in the `ActorType` message files.
+    pub fn actor_names(&self) -> BTreeMap<std::string::String, std::string::String> {
+        self.0
+            .iter()
+            .filter(|(file, _)| file.starts_with("ActorType/"))
+            .flat_map(|(_, text)| text.entries.iter())
+            .filter_map(|(key, entry)| {
+                let actor = key.strip_suffix("_Name")?;
+                let name = entry
+                    .contents
+                    .iter()
+                    .filter_map(|c| {
+                        match c {
+                            Content::Text(text) => Some(text.as_str()),
+                            _ => None,
+                        }
+                    })
+                    .collect::<std::string::String>();
+                let name = name.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+                (!name.is_empty()).then(|| (actor.to_owned(), name.to_owned()))
+            })
+            .collect()
+    }
+
     pub fn into_sarc_writer(self, endian: Endian) -> SarcWriter {
         SarcWriter::new(endian.into()).with_files(self.0.into_iter().map(|(name, text)| {
             (
@@ -29,9 +29,10 @@ pub use crate::{
     map::{lazy::LazyTraverseList, mainfield::location::Location, static_::Static, unit::MapUnit},
     message::MessagePack,
     quest::product::QuestProduct,
+    sizetable::ResourceSizeTable,
     sound::barslist::BarslistInfo,
     tips::Tips,
-    util::SortedDeleteMap,
+    util::{is_keyed_diff, lenient::LenientAamp, SortedDeleteMap},
     worldmgr::info::WorldInfo,
 };
 use crate::{prelude::*, util::SortedDeleteSet};
@@ -80,6 +81,7 @@ pub enum MergeableResource {
     Recipe(Box<Recipe>),
     ResidentActors(Box<ResidentActors>),
     ResidentEvents(Box<ResidentEvents>),
+    ResourceSizeTable(Box<ResourceSizeTable>),
     SaveDataPack(Box<SaveDataPack>),
     ShopData(Box<ShopData>),
     ShopGameDataInfo(Box<ShopGameDataInfo>),
@@ -91,6 +93,9 @@ pub enum MergeableResource {
     GenericAamp(Box<ParameterIO>),
     GenericByml(Box<Byml>),
     BinaryOverride(Box<(Vec<u8>, String)>),
+    /// A parameter archive roead could only read leniently. See
+    /// [`LenientAamp`].
+    LenientAamp(Box<LenientAamp>),
 }
 
 impl std::fmt::Display for MergeableResource {
@@ -137,6 +142,7 @@ impl std::fmt::Display for MergeableResource {
             Self::Recipe(_) => "Recipe",
             Self::ResidentActors(_) => "ResidentActors",
             Self::ResidentEvents(_) => "ResidentEvents",
+            Self::ResourceSizeTable(_) => "ResourceSizeTable",
             Self::SaveDataPack(_) => "SaveDataPack",
             Self::ShopData(_) => "ShopData",
             Self::ShopGameDataInfo(_) => "ShopGameDataInfo",
@@ -148,6 +154,7 @@ impl std::fmt::Display for MergeableResource {
             Self::GenericAamp(_) => "GenericAamp",
             Self::GenericByml(_) => "GenericByml",
             Self::BinaryOverride(_) => "BinaryOverride",
+            Self::LenientAamp(_) => "LenientAamp",
         }
         .fmt(f)
     }
@@ -232,6 +239,7 @@ impl_from_res!(RagdollConfigList);
 impl_from_res!(Recipe);
 impl_from_res!(ResidentActors);
 impl_from_res!(ResidentEvents);
+impl_from_res!(ResourceSizeTable);
 impl_from_res!(SaveDataPack);
 impl_from_res!(ShopData);
 impl_from_res!(ShopGameDataInfo);
@@ -313,6 +321,9 @@ impl Mergeable for MergeableResource {
             (Self::ResidentEvents(a), Self::ResidentEvents(b)) => {
                 Self::ResidentEvents(Box::new(a.diff(b)))
             }
+            (Self::ResourceSizeTable(a), Self::ResourceSizeTable(b)) => {
+                Self::ResourceSizeTable(Box::new(a.diff(b)))
+            }
             (Self::SaveDataPack(a), Self::SaveDataPack(b)) => {
                 Self::SaveDataPack(Box::new(a.diff(b)))
             }
@@ -329,8 +340,19 @@ impl Mergeable for MergeableResource {
             (Self::WorldInfo(a), Self::WorldInfo(b)) => Self::WorldInfo(Box::new(a.diff(b))),
             (Self::GenericByml(a), Self::GenericByml(b)) => Self::GenericByml(Box::new(a.diff(b))),
             (Self::GenericAamp(a), Self::GenericAamp(b)) => Self::GenericAamp(Box::new(a.diff(b))),
+            (Self::LenientAamp(a), Self::LenientAamp(b)) => Self::LenientAamp(Box::new(a.diff(b))),
+            (Self::GenericAamp(a), Self::LenientAamp(b)) => {
+                Self::LenientAamp(Box::new(LenientAamp::from((**a).clone()).diff(b)))
+            }
+            (Self::LenientAamp(a), Self::GenericAamp(b)) => {
+                Self::LenientAamp(Box::new(a.diff(&LenientAamp::from((**b).clone()))))
+            }
             (Self::BinaryOverride(_), anything) => anything.clone(),
             (_anything, Self::BinaryOverride(bin)) => Self::BinaryOverride(bin.clone()),
+            // A lenient archive has no typed form to diff with, so it takes
+            // the place of a typed one whole
+            (Self::LenientAamp(_), anything) => anything.clone(),
+            (_anything, Self::LenientAamp(lenient)) => Self::LenientAamp(lenient.clone()),
             _ => {
                 panic!(
                     "Tried to diff incompatible resources: {} and {}",
@@ -411,6 +433,9 @@ impl Mergeable for MergeableResource {
             (Self::ResidentEvents(a), Self::ResidentEvents(b)) => {
                 Self::ResidentEvents(Box::new(a.merge(b)))
             }
+            (Self::ResourceSizeTable(a), Self::ResourceSizeTable(b)) => {
+                Self::ResourceSizeTable(Box::new(a.merge(b)))
+            }
             (Self::SaveDataPack(a), Self::SaveDataPack(b)) => {
                 Self::SaveDataPack(Box::new(a.merge(b)))
             }
@@ -427,8 +452,17 @@ impl Mergeable for MergeableResource {
             (Self::WorldInfo(a), Self::WorldInfo(b)) => Self::WorldInfo(Box::new(a.merge(b))),
             (Self::GenericByml(a), Self::GenericByml(b)) => Self::GenericByml(Box::new(a.merge(b))),
             (Self::GenericAamp(a), Self::GenericAamp(b)) => Self::GenericAamp(Box::new(a.merge(b))),
+            (Self::LenientAamp(a), Self::LenientAamp(b)) => Self::LenientAamp(Box::new(a.merge(b))),
+            (Self::GenericAamp(a), Self::LenientAamp(b)) => {
+                Self::LenientAamp(Box::new(LenientAamp::from((**a).clone()).merge(b)))
+            }
+            (Self::LenientAamp(a), Self::GenericAamp(b)) => {
+                Self::LenientAamp(Box::new(a.merge(&LenientAamp::from((**b).clone()))))
+            }
             (Self::BinaryOverride(bin), _anything) => Self::BinaryOverride(bin.clone()),
             (_anything, Self::BinaryOverride(bin)) => Self::BinaryOverride(bin.clone()),
+            (Self::LenientAamp(lenient), _anything) => Self::LenientAamp(lenient.clone()),
+            (_anything, Self::LenientAamp(lenient)) => Self::LenientAamp(lenient.clone()),
             _ => {
                 panic!(
                     "Tried to merge incompatible resources: {} and {}",
@@ -579,6 +613,10 @@ impl MergeableResource {
             Ok(Some(Self::ResidentEvents(Box::new(
                 ResidentEvents::from_binary(data)?,
             ))))
+        } else if ResourceSizeTable::path_matches(name) {
+            Ok(Some(Self::ResourceSizeTable(Box::new(
+                ResourceSizeTable::from_binary(data)?,
+            ))))
         } else if SaveDataPack::path_matches(name) {
             Ok(Some(Self::SaveDataPack(Box::new(
                 SaveDataPack::from_binary(data)?,
@@ -614,6 +652,16 @@ impl MergeableResource {
         };
         match result {
             Err(e) => {
+                // Parameter archives may only be rejected for types or
+                // versions roead is strict about
+                if let Some(lenient) = data
+                    .starts_with(b"AAMP")
+                    .then(|| LenientAamp::from_binary(data).ok())
+                    .flatten()
+                    .filter(|lenient| lenient.is_lenient())
+                {
+                    return Ok(Some(Self::LenientAamp(Box::new(lenient))));
+                }
                 Ok(Some(Self::BinaryOverride(Box::new((
                     data.to_vec(),
                     e.to_string().into(),
@@ -623,6 +671,23 @@ impl MergeableResource {
         }
     }
 
+    /// Whether this is a parameter archive which could only be read
+    /// leniently, with parameters kept as they are and merged whole.
+    pub fn is_lenient(&self) -> bool {
+        matches!(self, Self::LenientAamp(_))
+    }
+
+    /// Whether this is a diff with BYML matched up by key, which readers
+    /// from before keyed diffs would merge with the markers left in.
+    pub fn is_keyed(&self) -> bool {
+        match self {
+            Self::AreaData(v) => v.0.iter().any(|(_, area)| is_keyed_diff(area)),
+            Self::EventInfo(v) => is_keyed_diff(&v.0),
+            Self::MapUnit(v) => v.objects.iter().any(|(_, obj)| is_keyed_diff(obj)),
+            _ => false,
+        }
+    }
+
     pub fn into_binary(self, endian: Endian) -> Vec<u8> {
         match self {
             // Self::Actor(v) => v.into_binary(endian),
@@ -666,6 +731,7 @@ impl MergeableResource {
             Self::Recipe(v) => v.into_binary(endian),
             Self::ResidentActors(v) => v.into_binary(endian),
             Self::ResidentEvents(v) => v.into_binary(endian),
+            Self::ResourceSizeTable(v) => v.into_binary(endian),
             Self::SaveDataPack(v) => v.into_binary(endian),
             Self::ShopData(v) => v.into_binary(endian),
             Self::ShopGameDataInfo(v) => v.into_binary(endian),
@@ -680,6 +746,7 @@ impl MergeableResource {
                 let (bin, _) = *v;
                 bin
             }
+            Self::LenientAamp(v) => v.to_binary(),
         }
     }
 }
@@ -0,0 +1,205 @@
+//! The resource size table (RSTB), which tells the game how much memory to
+//! set aside for each resource it loads.
+//!
+//! UKMM computes entries for the resources it merges, but some mods ship
+//! their own table with values tuned by hand. Parsing the table into entries
+//! lets those mods be diffed and merged like any other resource, so several
+//! of them can set values side by side.
+use serde::{Deserialize, Serialize};
+
+use crate::{prelude::*, util::SortedDeleteMap, Result, UKError};
+
+/// Bytes each name takes in the name table.
+const NAME_SIZE: usize = 128;
+const HEADER_SIZE: usize = 12;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceSizeTable {
+    /// Sizes by the CRC32 hash of the resource name.
+    pub crc_table:  SortedDeleteMap<u32, u32>,
+    /// Sizes by name, for resources whose hashes collide.
+    pub name_table: SortedDeleteMap<std::string::String, u32>,
+}
+
+impl ResourceSizeTable {
+    fn read_u32(data: &[u8], offset: usize, endian: Endian) -> u32 {
+        let bytes = [
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ];
+        match endian {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        }
+    }
+
+    /// Finds the byte order whose table sizes account for exactly the whole
+    /// file, since the header does not say.
+    fn detect_endian(data: &[u8]) -> Option<Endian> {
+        [Endian::Big, Endian::Little].into_iter().find(|&endian| {
+            let crcs = Self::read_u32(data, 4, endian) as usize;
+            let names = Self::read_u32(data, 8, endian) as usize;
+            crcs.checked_mul(8)
+                .zip(names.checked_mul(NAME_SIZE + 4))
+                .and_then(|(crcs, names)| crcs.checked_add(names))
+                .map(|len| len + HEADER_SIZE)
+                == Some(data.len())
+        })
+    }
+}
+
+impl Mergeable for ResourceSizeTable {
+    fn diff(&self, other: &Self) -> Self {
+        Self {
+            crc_table:  self.crc_table.diff(&other.crc_table),
+            name_table: self.name_table.diff(&other.name_table),
+        }
+    }
+
+    fn merge(&self, diff: &Self) -> Self {
+        Self {
+            crc_table:  self.crc_table.merge(&diff.crc_table),
+            name_table: self.name_table.merge(&diff.name_table),
+        }
+    }
+}
+
+impl Resource for ResourceSizeTable {
+    fn from_binary(data: impl AsRef<[u8]>) -> Result<Self> {
+        let data = data.as_ref();
+        if data.len() < HEADER_SIZE || &data[..4] != b"RSTB" {
+            return Err(UKError::Other("Not a resource size table"));
+        }
+        let endian = Self::detect_endian(data).ok_or(UKError::Other(
+            "Resource size table is truncated or has a corrupt header",
+        ))?;
+        let crcs = Self::read_u32(data, 4, endian) as usize;
+        let names = Self::read_u32(data, 8, endian) as usize;
+        let crc_table = (0..crcs)
+            .map(|i| {
+                let offset = HEADER_SIZE + i * 8;
+                (
+                    Self::read_u32(data, offset, endian),
+                    Self::read_u32(data, offset + 4, endian),
+                )
+            })
+            .collect();
+        let name_table = (0..names)
+            .map(|i| -> Result<(std::string::String, u32)> {
+                let offset = HEADER_SIZE + crcs * 8 + i * (NAME_SIZE + 4);
+                let name = &data[offset..offset + NAME_SIZE];
+                let len = name.iter().position(|b| *b == 0).unwrap_or(NAME_SIZE);
+                let name = std::str::from_utf8(&name[..len])
+                    .map_err(|_| UKError::Other("Invalid name in resource size table"))?;
+                Ok((
+                    name.into(),
+                    Self::read_u32(data, offset + NAME_SIZE, endian),
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            crc_table,
+            name_table,
+        })
+    }
+
+    fn into_binary(self, endian: Endian) -> Vec<u8> {
+        let u32_bytes = |value: u32| {
+            match endian {
+                Endian::Big => value.to_be_bytes(),
+                Endian::Little => value.to_le_bytes(),
+            }
+        };
+        let mut data = Vec::with_capacity(
+            HEADER_SIZE + self.crc_table.len() * 8 + self.name_table.len() * (NAME_SIZE + 4),
+        );
+        data.extend_from_slice(b"RSTB");
+        data.extend_from_slice(&u32_bytes(self.crc_table.len() as u32));
+        data.extend_from_slice(&u32_bytes(self.name_table.len() as u32));
+        for (crc, size) in self.crc_table.iter() {
+            data.extend_from_slice(&u32_bytes(*crc));
+            data.extend_from_slice(&u32_bytes(*size));
+        }
+        for (name, size) in self.name_table.iter() {
+            let mut padded = [0u8; NAME_SIZE];
+            let len = name.len().min(NAME_SIZE - 1);
+            padded[..len].copy_from_slice(&name.as_bytes()[..len]);
+            data.extend_from_slice(&padded);
+            data.extend_from_slice(&u32_bytes(*size));
+        }
+        data
+    }
+
+    fn path_matches(path: impl AsRef<std::path::Path>) -> bool {
+        path.as_ref()
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                name.starts_with("ResourceSizeTable.")
+                    && (name.ends_with(".rsizetable") || name.ends_with(".srsizetable"))
+            })
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> ResourceSizeTable {
+        ResourceSizeTable {
+            crc_table:  [
+                (0x0000_0001, 1000),
+                (0x1234_5678, 2000),
+                (0xFFFF_FFFF, 3000),
+            ]
+            .into_iter()
+            .collect(),
+            name_table: [("Actor/Pack/Enemy_Lizal_Senior.bactorpack".to_string(), 4000)]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn serde() {
+        for endian in [Endian::Big, Endian::Little] {
+            let data = table().into_binary(endian);
+            assert_eq!(data.len(), HEADER_SIZE + 3 * 8 + NAME_SIZE + 4);
+            assert_eq!(ResourceSizeTable::detect_endian(&data), Some(endian));
+            assert_eq!(ResourceSizeTable::from_binary(&data).unwrap(), table());
+        }
+        assert!(ResourceSizeTable::from_binary(b"RSTB\0\0\0\x05\0\0\0\0").is_err());
+        assert!(ResourceSizeTable::from_binary(b"Yaz0 not really an RSTB").is_err());
+    }
+
+    #[test]
+    fn diff() {
+        let stock = table();
+        let mut modded = table();
+        modded.crc_table.insert(0x1234_5678u32, 2500);
+        modded.crc_table.insert(0xABCD_EF01u32, 500);
+        modded.crc_table.set_delete(0xFFFF_FFFFu32);
+        modded.crc_table.delete();
+        let diff = stock.diff(&modded);
+        assert_eq!(diff.crc_table.len(), 2);
+        assert_eq!(diff.crc_table.is_delete(0xFFFF_FFFFu32), Some(true));
+        assert!(diff.name_table.is_empty());
+        assert_eq!(stock.merge(&diff), modded);
+    }
+
+    #[test]
+    fn path() {
+        assert!(ResourceSizeTable::path_matches(
+            "System/Resource/ResourceSizeTable.product.srsizetable"
+        ));
+        assert!(ResourceSizeTable::path_matches(
+            "System/Resource/ResourceSizeTable.product.rsizetable"
+        ));
+        assert!(!ResourceSizeTable::path_matches(
+            "Actor/Pack/Test.sbactorpack"
+        ));
+    }
+}
@@ -608,12 +608,13 @@ macro_rules! impl_delete_map {
             }
 
             fn merge(&self, other: &Self) -> Self {
+                // Look up each entry's final state rather than collecting both
+                // maps into an intermediate one, so only kept entries are cloned
                 Self(
                     self.0
                         .iter()
-                        .chain(other.0.iter())
-                        .collect::<IndexMap<_, _>>()
-                        .into_iter()
+                        .map(|(k, entry)| (k, other.0.get(k).unwrap_or(entry)))
+                        .chain(other.0.iter().filter(|(k, _)| !self.0.contains_key(*k)))
                         .filter_map(|(k, (v, del))| (!*del).then(|| (k.clone(), (v.clone(), false))))
                         .collect(),
                 )
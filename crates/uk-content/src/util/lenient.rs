@@ -0,0 +1,338 @@
+//! A fallback for parameter archives which roead rejects, for parameter types
+//! it does not know or a format version other than 2. The archive structure
+//! is walked directly, and parameters of unknown types are swapped for a
+//! known type so roead can read the rest. Their data is then kept as binary
+//! buffers, and their types put back when the archive is written.
+use std::collections::BTreeMap;
+
+use roead::aamp::{Name, Parameter, ParameterIO, ParameterList};
+use serde::{Deserialize, Serialize};
+
+use crate::{prelude::Mergeable, Result, UKError};
+
+/// The last parameter type roead knows, `StringRef`.
+const MAX_KNOWN_TYPE: u8 = 20;
+/// Parameters of unknown types are read as `U32`, which only needs 4 bytes
+/// of data.
+const PLACEHOLDER_TYPE: u8 = 17;
+/// The type of `BufferBinary`, which parameters of unknown types are written
+/// as before their own type is put back.
+const BUFFER_BINARY_TYPE: u8 = 19;
+const HEADER_SIZE: usize = 0x30;
+const FORMAT_VERSION: u32 = 2;
+
+/// Where a parameter is: the hashes of the lists leading to it from the
+/// root, then the hash of its object, then its own.
+pub type ParamPath = Vec<u32>;
+
+/// A parameter archive read leniently. Parameters of unknown types are held
+/// in `pio` as `BufferBinary` parameters with their data as it was, so they
+/// merge whole, with the last mod to change one winning.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LenientAamp {
+    pub pio: ParameterIO,
+    /// The original type of each parameter roead does not know.
+    pub opaque: BTreeMap<ParamPath, u8>,
+    /// The format version in the header, which roead only reads if it is 2.
+    pub format_version: u32,
+}
+
+impl From<ParameterIO> for LenientAamp {
+    fn from(pio: ParameterIO) -> Self {
+        Self {
+            pio,
+            opaque: Default::default(),
+            format_version: FORMAT_VERSION,
+        }
+    }
+}
+
+impl LenientAamp {
+    pub fn from_binary(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(b"AAMP") {
+            return Err(UKError::Other("Not a parameter archive"));
+        }
+        let format_version = u32_at(data, 0x4)?;
+        let params = params(data)?;
+        // Data runs until the next parameter's or the end of its section, as
+        // the size of an unknown type cannot be known
+        let data_start = HEADER_SIZE
+            + u32_at(data, 0x14)? as usize
+            + u32_at(data, 0x18)? as usize * 12
+            + u32_at(data, 0x1C)? as usize * 8
+            + u32_at(data, 0x20)? as usize * 8;
+        let data_end = data_start + u32_at(data, 0x24)? as usize;
+        let string_end = data_end + u32_at(data, 0x28)? as usize;
+        let mut bounds: Vec<usize> = params.iter().map(|p| p.data).collect();
+        bounds.extend([data_end, string_end, data.len()]);
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut patched = data.to_vec();
+        patched[0x4..0x8].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        let mut opaque = vec![];
+        for param in params.into_iter().filter(|p| p.kind > MAX_KNOWN_TYPE) {
+            let end = bounds
+                .iter()
+                .copied()
+                .find(|b| *b > param.data)
+                .unwrap_or(data.len());
+            let blob = data
+                .get(param.data..end)
+                .filter(|blob| blob.len() >= 4)
+                .ok_or(UKError::Other("Invalid data for parameter of unknown type"))?;
+            patched[param.entry + 7] = PLACEHOLDER_TYPE;
+            opaque.push((param.path, param.kind, blob.to_vec()));
+        }
+
+        let mut pio = ParameterIO::from_binary(&patched)?;
+        let opaque = opaque
+            .into_iter()
+            .map(|(path, kind, blob)| {
+                *param_mut(&mut pio.param_root, &path)
+                    .ok_or(UKError::Other("Parameter of unknown type went missing"))? =
+                    Parameter::BufferBinary(blob);
+                Ok((path, kind))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            pio,
+            opaque,
+            format_version,
+        })
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut data = self.pio.to_binary();
+        // roead's own output always walks
+        for param in params(&data).unwrap_or_default() {
+            // A mod may have replaced the parameter with one of a known type
+            if let Some(kind) = self
+                .opaque
+                .get(&param.path)
+                .filter(|_| param.kind == BUFFER_BINARY_TYPE)
+            {
+                data[param.entry + 7] = *kind;
+            }
+        }
+        data[0x4..0x8].copy_from_slice(&self.format_version.to_le_bytes());
+        data
+    }
+
+    /// Whether the archive needed lenient reading at all.
+    pub fn is_lenient(&self) -> bool {
+        !self.opaque.is_empty() || self.format_version != FORMAT_VERSION
+    }
+}
+
+impl Mergeable for LenientAamp {
+    fn diff(&self, other: &Self) -> Self {
+        Self {
+            pio: self.pio.diff(&other.pio),
+            opaque: other.opaque.clone(),
+            format_version: other.format_version,
+        }
+    }
+
+    fn merge(&self, diff: &Self) -> Self {
+        let mut opaque = self.opaque.clone();
+        opaque.extend(diff.opaque.iter().map(|(path, kind)| (path.clone(), *kind)));
+        Self {
+            pio: self.pio.merge(&diff.pio),
+            opaque,
+            format_version: diff.format_version,
+        }
+    }
+}
+
+/// A parameter entry in an archive.
+struct ParamEntry {
+    path:  ParamPath,
+    /// Offset of the entry itself.
+    entry: usize,
+    kind:  u8,
+    /// Offset of the parameter's data.
+    data:  usize,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u16::from_le_bytes)
+        .ok_or(UKError::Other("Parameter archive is truncated"))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or(UKError::Other("Parameter archive is truncated"))
+}
+
+/// Every parameter entry in an archive.
+fn params(data: &[u8]) -> Result<Vec<ParamEntry>> {
+    let root = HEADER_SIZE + u32_at(data, 0x14)? as usize;
+    // A list whose children start at itself would be walked forever, so no
+    // more lists are walked than the header counts
+    let mut budget = u32_at(data, 0x18)? as usize;
+    let mut found = vec![];
+    walk_list(data, root, &mut vec![], &mut budget, &mut found)?;
+    Ok(found)
+}
+
+fn walk_list(
+    data: &[u8],
+    list: usize,
+    path: &mut ParamPath,
+    budget: &mut usize,
+    found: &mut Vec<ParamEntry>,
+) -> Result<()> {
+    *budget = budget.checked_sub(1).ok_or(UKError::Other(
+        "Parameter archive lists loop back on themselves",
+    ))?;
+    let lists = list + u16_at(data, list + 4)? as usize * 4;
+    for i in 0..u16_at(data, list + 6)? as usize {
+        let child = lists + i * 12;
+        path.push(u32_at(data, child)?);
+        walk_list(data, child, path, budget, found)?;
+        path.pop();
+    }
+    let objects = list + u16_at(data, list + 8)? as usize * 4;
+    for i in 0..u16_at(data, list + 10)? as usize {
+        let object = objects + i * 8;
+        path.push(u32_at(data, object)?);
+        let params = object + u16_at(data, object + 4)? as usize * 4;
+        for j in 0..u16_at(data, object + 6)? as usize {
+            let entry = params + j * 8;
+            let packed = u32_at(data, entry + 4)?;
+            let mut path = path.clone();
+            path.push(u32_at(data, entry)?);
+            found.push(ParamEntry {
+                path,
+                entry,
+                kind: (packed >> 24) as u8,
+                data: entry + (packed & 0xFF_FFFF) as usize * 4,
+            });
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn param_mut<'a>(root: &'a mut ParameterList, path: &[u32]) -> Option<&'a mut Parameter> {
+    let (param, path) = path.split_last()?;
+    let (object, lists) = path.split_last()?;
+    let list = lists
+        .iter()
+        .try_fold(root, |list, hash| list.lists.0.get_mut(&Name::from(*hash)))?;
+    list.objects
+        .0
+        .get_mut(&Name::from(*object))?
+        .0
+        .get_mut(&Name::from(*param))
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use roead::aamp::ParameterObject;
+
+    use super::*;
+    use crate::resource::MergeableResource;
+
+    /// The type given to the crafted parameter.
+    const UNKNOWN: u8 = 0x40;
+
+    fn path(names: &[&str]) -> ParamPath {
+        names.iter().map(|name| Name::from(*name).hash()).collect()
+    }
+
+    fn pio(known: i32, odd: u32) -> ParameterIO {
+        ParameterIO::new().with_object(
+            "Obj",
+            ParameterObject::new()
+                .with_parameter("Known", Parameter::I32(known))
+                .with_parameter("Odd", Parameter::U32(odd))
+                .with_parameter("Name", Parameter::StringRef("test".into())),
+        )
+    }
+
+    /// An archive whose `Odd` parameter has a type roead does not know.
+    fn crafted(known: i32, odd: u32) -> Vec<u8> {
+        let mut data = pio(known, odd).to_binary();
+        let entry = params(&data)
+            .unwrap()
+            .into_iter()
+            .find(|p| p.path == path(&["Obj", "Odd"]))
+            .unwrap()
+            .entry;
+        data[entry + 7] = UNKNOWN;
+        data
+    }
+
+    fn odd_data(lenient: &LenientAamp) -> &[u8] {
+        match &lenient.pio.param_root.objects.0[&Name::from("Obj")].0[&Name::from("Odd")] {
+            Parameter::BufferBinary(data) => data,
+            other => panic!("Expected a buffer, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_type() {
+        let data = crafted(7, 0x1122_3344);
+        assert!(ParameterIO::from_binary(&data).is_err());
+        let lenient = LenientAamp::from_binary(&data).unwrap();
+        assert!(lenient.is_lenient());
+        assert_eq!(lenient.opaque[&path(&["Obj", "Odd"])], UNKNOWN);
+        let obj = &lenient.pio.param_root.objects.0[&Name::from("Obj")];
+        assert_eq!(obj.0[&Name::from("Known")], Parameter::I32(7));
+        assert!(odd_data(&lenient).starts_with(&0x1122_3344u32.to_le_bytes()));
+
+        let written = lenient.to_binary();
+        let odd = params(&written)
+            .unwrap()
+            .into_iter()
+            .find(|p| p.path == path(&["Obj", "Odd"]))
+            .unwrap();
+        assert_eq!(odd.kind, UNKNOWN);
+        assert!(written[odd.data..].starts_with(odd_data(&lenient)));
+        assert_eq!(LenientAamp::from_binary(&written).unwrap(), lenient);
+    }
+
+    #[test]
+    fn format_version() {
+        let mut data = pio(7, 1).to_binary();
+        data[0x4..0x8].copy_from_slice(&3u32.to_le_bytes());
+        let lenient = LenientAamp::from_binary(&data).unwrap();
+        assert!(lenient.is_lenient());
+        assert!(lenient.opaque.is_empty());
+        assert_eq!(lenient.format_version, 3);
+        assert_eq!(u32_at(&lenient.to_binary(), 0x4).unwrap(), 3);
+    }
+
+    #[test]
+    fn merge() {
+        let name = std::path::Path::new("Test.bgeneric");
+        let base = MergeableResource::from_binary(name, &pio(7, 1).to_binary())
+            .unwrap()
+            .unwrap();
+        let mod1 = MergeableResource::from_binary(name, &crafted(8, 2))
+            .unwrap()
+            .unwrap();
+        let mod2 = MergeableResource::from_binary(name, &crafted(7, 3))
+            .unwrap()
+            .unwrap();
+        assert!(!base.is_lenient());
+        assert!(mod1.is_lenient());
+        let merged = base.merge(&base.diff(&mod1)).merge(&base.diff(&mod2));
+        let MergeableResource::LenientAamp(merged) = &merged else {
+            panic!("Expected a lenient archive, found {merged}");
+        };
+        // The known parameter changed by the first mod survives the second,
+        // while the opaque one is taken whole from the second
+        let obj = &merged.pio.param_root.objects.0[&Name::from("Obj")];
+        assert_eq!(obj.0[&Name::from("Known")], Parameter::I32(8));
+        assert!(odd_data(merged).starts_with(&3u32.to_le_bytes()));
+        assert_eq!(merged.opaque[&path(&["Obj", "Odd"])], UNKNOWN);
+    }
+}
@@ -1,15 +1,61 @@
 mod collections;
 pub mod converts;
+pub mod lenient;
 
 use std::{collections::BTreeMap, str::FromStr};
 
 pub use collections::*;
 use roead::{
     aamp::*,
-    byml::{Byml, Map},
+    byml::{map, Byml, Map},
     types::FixedSafeString,
 };
 
+use crate::prelude::Mergeable;
+
+/// Marks a parameter, object or list as deleted in a diff. Diffs are plain
+/// AAMP types, so a deletion is written as a value no game file holds: the
+/// marker string itself for a parameter, an object holding only that for an
+/// object, and a list holding only such an object for a list. Diffs from
+/// before deletions were tracked have none of these, so merge as they did.
+pub const DELETED: &str = "~UKMM_DELETED~";
+
+fn deleted_param() -> Parameter {
+    Parameter::StringRef(DELETED.into())
+}
+
+fn is_deleted_param(param: &Parameter) -> bool {
+    matches!(param, Parameter::StringRef(s) if s.as_str() == DELETED)
+}
+
+fn deleted_pobj() -> ParameterObject {
+    ParameterObject::new().with_parameter(DELETED, deleted_param())
+}
+
+fn is_deleted_pobj(obj: &ParameterObject) -> bool {
+    obj.0.len() == 1
+        && obj
+            .0
+            .get(&Name::from(DELETED))
+            .is_some_and(is_deleted_param)
+}
+
+fn deleted_plist() -> ParameterList {
+    ParameterList::new().with_object(DELETED, deleted_pobj())
+}
+
+fn is_deleted_plist(list: &ParameterList) -> bool {
+    list.lists.0.is_empty()
+        && list.objects.0.len() == 1
+        && list
+            .objects
+            .0
+            .get(&Name::from(DELETED))
+            .is_some_and(is_deleted_pobj)
+}
+
+/// Diffs two parameter lists, recursing into the objects and lists both
+/// have. Objects and lists in `base` but not `other` are marked deleted.
 pub fn diff_plist<P: ParameterListing + From<ParameterList>>(base: &P, other: &P) -> P {
     ParameterList {
         lists:   other
@@ -25,6 +71,13 @@ pub fn diff_plist<P: ParameterListing + From<ParameterList>>(base: &P, other: &P
                     None
                 }
             })
+            .chain(
+                base.lists()
+                    .0
+                    .keys()
+                    .filter(|k| !other.lists().0.contains_key(*k))
+                    .map(|k| (*k, deleted_plist())),
+            )
             .collect(),
         objects: other
             .objects()
@@ -39,11 +92,20 @@ pub fn diff_plist<P: ParameterListing + From<ParameterList>>(base: &P, other: &P
                     None
                 }
             })
+            .chain(
+                base.objects()
+                    .0
+                    .keys()
+                    .filter(|k| !other.objects().0.contains_key(*k))
+                    .map(|k| (*k, deleted_pobj())),
+            )
             .collect(),
     }
     .into()
 }
 
+/// Diffs two parameter objects. Parameters in `base` but not `other` are
+/// marked deleted.
 pub fn diff_pobj(base: &ParameterObject, other: &ParameterObject) -> ParameterObject {
     other
         .0
@@ -55,42 +117,108 @@ pub fn diff_pobj(base: &ParameterObject, other: &ParameterObject) -> ParameterOb
                 None
             }
         })
+        .chain(
+            base.0
+                .keys()
+                .filter(|k| !other.0.contains_key(*k))
+                .map(|k| (*k, deleted_param())),
+        )
         .collect()
 }
 
-pub fn merge_plist<P: ParameterListing + From<ParameterList>>(base: &P, diff: &P) -> P {
-    ParameterList {
-        objects: {
-            let mut new = base.objects().clone();
-            for (k, v) in &diff.objects().0 {
-                if !new.0.contains_key(k) {
-                    new.0.insert(*k, v.clone());
-                } else {
-                    new.0[k] = merge_pobj(&new.0[k], v);
-                }
+pub fn merge_plist<P: ParameterListing + Clone>(base: &P, diff: &P) -> P {
+    let mut new = base.clone();
+    merge_plist_into(&mut new, diff);
+    new
+}
+
+/// Merges a diff into a parameter list in place, so only the parameters the
+/// diff changes are cloned. Objects, lists and parameters the diff marks
+/// deleted are removed.
+pub fn merge_plist_into<P: ParameterListing>(base: &mut P, diff: &P) {
+    for (k, v) in &diff.objects().0 {
+        if is_deleted_pobj(v) {
+            base.objects_mut().0.shift_remove(k);
+            continue;
+        }
+        match base.objects_mut().0.get_mut(k) {
+            Some(obj) => merge_pobj_into(obj, v),
+            // Merged into nothing, so deletions within it are dropped
+            None => {
+                let mut obj = ParameterObject::new();
+                merge_pobj_into(&mut obj, v);
+                base.objects_mut().0.insert(*k, obj);
             }
-            new
-        },
-        lists:   {
-            let mut new = base.lists().clone();
-            for (k, v) in &diff.lists().0 {
-                if !new.0.contains_key(k) {
-                    new.0.insert(*k, v.clone());
-                } else {
-                    new.0[k] = merge_plist(&new.0[k], v);
-                }
+        }
+    }
+    for (k, v) in &diff.lists().0 {
+        if is_deleted_plist(v) {
+            base.lists_mut().0.shift_remove(k);
+            continue;
+        }
+        match base.lists_mut().0.get_mut(k) {
+            Some(list) => merge_plist_into(list, v),
+            None => {
+                let mut list = ParameterList::new();
+                merge_plist_into(&mut list, v);
+                base.lists_mut().0.insert(*k, list);
             }
-            new
-        },
+        }
     }
-    .into()
 }
 
 pub fn merge_pobj(base: &ParameterObject, diff: &ParameterObject) -> ParameterObject {
-    base.0
-        .iter()
-        .chain(diff.0.iter())
-        .map(|(k, v)| (*k, v.clone()))
+    let mut new = base.clone();
+    merge_pobj_into(&mut new, diff);
+    new
+}
+
+/// Merges a diff into a parameter object in place, skipping parameters the
+/// diff leaves the same and removing those it marks deleted.
+pub fn merge_pobj_into(base: &mut ParameterObject, diff: &ParameterObject) {
+    for (k, v) in &diff.0 {
+        if is_deleted_param(v) {
+            base.0.shift_remove(k);
+            continue;
+        }
+        match base.0.get_mut(k) {
+            Some(param) if param == v => (),
+            Some(param) => *param = v.clone(),
+            None => {
+                base.0.insert(*k, v.clone());
+            }
+        }
+    }
+}
+
+/// Merges two keyed collections of mergeable values, such as an `IndexMap`
+/// or a `BTreeMap`. Base keys keep their order with new keys from the diff
+/// after them, entries on one side only are cloned once, and entries on both
+/// are merged.
+pub fn merge_keyed<'a, K, V, C>(
+    base: impl IntoIterator<Item = (&'a K, &'a V)>,
+    diff: impl IntoIterator<Item = (&'a K, &'a V)>,
+    in_base: impl Fn(&K) -> bool,
+    in_diff: impl Fn(&K) -> Option<&'a V>,
+) -> C
+where
+    K: Clone + 'a,
+    V: Mergeable + Clone + 'a,
+    C: FromIterator<(K, V)>,
+{
+    base.into_iter()
+        .map(|(k, v)| {
+            let v = match in_diff(k) {
+                Some(diff) => v.merge(diff),
+                None => v.clone(),
+            };
+            (k.clone(), v)
+        })
+        .chain(
+            diff.into_iter()
+                .filter(|(k, _)| !in_base(k))
+                .map(|(k, v)| (k.clone(), v.clone())),
+        )
         .collect()
 }
 
@@ -121,11 +249,16 @@ pub fn diff_byml_shallow(base: &Byml, other: &Byml) -> Byml {
 pub fn merge_byml_shallow(base: &Byml, diff: &Byml) -> Byml {
     match (base, diff) {
         (Byml::Map(base), Byml::Map(diff)) => {
-            let mut new: Map = base
-                .iter()
-                .chain(diff.iter())
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect();
+            let mut new: Map = base.clone();
+            for (k, v) in diff {
+                match new.get_mut(k) {
+                    Some(value) if value == v => (),
+                    Some(value) => *value = v.clone(),
+                    None => {
+                        new.insert(k.clone(), v.clone());
+                    }
+                }
+            }
             new.retain(|_, v| v != &Byml::Null);
             Byml::Map(new)
         }
@@ -134,6 +267,295 @@ pub fn merge_byml_shallow(base: &Byml, diff: &Byml) -> Byml {
     }
 }
 
+/// Marks a diff of an array of hashes matched up by identity rather than by
+/// position, holding the entries added, modified and removed.
+const KEYED_DIFF: &str = "~UKMM_KEYED_DIFF~";
+
+/// Marks a whole hash diffed by [`diff_byml_keyed`], so it is not mistaken
+/// for a diff made before keyed diffs, which replaces the hash it is for.
+const KEYED: &str = "~UKMM_KEYED~";
+
+/// Marks a key removed from a hash in a keyed diff. Null is a value BYML
+/// files hold, so it cannot be taken as a removal.
+fn deleted_byml() -> Byml {
+    Byml::String(DELETED.into())
+}
+
+fn is_deleted_byml(value: &Byml) -> bool {
+    matches!(value, Byml::String(s) if s.as_str() == DELETED)
+}
+
+/// Whether a diff was made by [`diff_byml_keyed`].
+pub fn is_keyed_diff(diff: &Byml) -> bool {
+    diff.as_map()
+        .map(|diff| diff.contains_key(KEYED))
+        .unwrap_or(false)
+}
+
+/// Makes a key extractor for [`diff_byml_keyed`] which identifies a hash by
+/// the first of the given fields it has, such as `HashId` or `InstanceID`.
+pub fn byml_key_field(fields: &'static [&'static str]) -> impl Fn(&Byml) -> Option<String> {
+    move |byml| {
+        let hash = byml.as_map().ok()?;
+        fields
+            .iter()
+            .find_map(|field| hash.get(*field))
+            .and_then(|key| {
+                match key {
+                    Byml::String(s) => Some(s.to_string()),
+                    Byml::I32(v) => Some(v.to_string()),
+                    Byml::U32(v) => Some(v.to_string()),
+                    Byml::I64(v) => Some(v.to_string()),
+                    Byml::U64(v) => Some(v.to_string()),
+                    _ => None,
+                }
+            })
+    }
+}
+
+/// Keys each entry of an array, if every entry has a key and no two share
+/// one.
+fn keyed_entries<'a, K: Fn(&Byml) -> Option<String>>(
+    array: &'a [Byml],
+    key: &K,
+) -> Option<Vec<(String, &'a Byml)>> {
+    let entries = array
+        .iter()
+        .map(|entry| key(entry).map(|k| (k, entry)))
+        .collect::<Option<Vec<_>>>()?;
+    let unique: std::collections::HashSet<&str> = entries.iter().map(|(k, _)| k.as_str()).collect();
+    (unique.len() == entries.len()).then_some(entries)
+}
+
+fn diff_keyed_array<K: Fn(&Byml) -> Option<String>>(
+    base: &[Byml],
+    other: &[Byml],
+    key: &K,
+) -> Option<Byml> {
+    let base = keyed_entries(base, key)?;
+    let other = keyed_entries(other, key)?;
+    let base_keys: std::collections::HashMap<&str, &Byml> =
+        base.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    let other_keys: std::collections::HashMap<&str, &Byml> =
+        other.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    // Kept entries stay in the order of the base, so a reordering can only
+    // be carried by the whole array
+    if !base
+        .iter()
+        .filter(|(k, _)| other_keys.contains_key(k.as_str()))
+        .map(|(k, _)| k)
+        .eq(other
+            .iter()
+            .filter(|(k, _)| base_keys.contains_key(k.as_str()))
+            .map(|(k, _)| k))
+    {
+        return None;
+    }
+    // Each entry added notes the one it follows, so it can be put back in
+    // place
+    let add: Vec<Byml> = other
+        .iter()
+        .enumerate()
+        .filter(|(_, (k, _))| !base_keys.contains_key(k.as_str()))
+        .map(|(i, (_, v))| {
+            let after = match i.checked_sub(1) {
+                Some(prev) => Byml::String(other[prev].0.as_str().into()),
+                None => Byml::Null,
+            };
+            map!("after" => after, "entry" => (*v).clone())
+        })
+        .collect();
+    let modify: Map = other
+        .iter()
+        .filter_map(|(k, v)| {
+            base_keys
+                .get(k.as_str())
+                .filter(|base| **base != *v)
+                .map(|base| (k.as_str().into(), diff_byml_value(base, v, key)))
+        })
+        .collect();
+    let remove: Vec<Byml> = base
+        .iter()
+        .filter(|(k, _)| !other_keys.contains_key(k.as_str()))
+        .map(|(k, _)| Byml::String(k.as_str().into()))
+        .collect();
+    Some(map!(KEYED_DIFF => map!(
+        "add" => Byml::Array(add),
+        "modify" => Byml::Map(modify),
+        "remove" => Byml::Array(remove)
+    )))
+}
+
+fn diff_byml_value<K: Fn(&Byml) -> Option<String>>(base: &Byml, other: &Byml, key: &K) -> Byml {
+    match (base, other) {
+        (Byml::Map(base), Byml::Map(other)) => {
+            Byml::Map(
+                other
+                    .iter()
+                    .filter_map(|(k, value)| {
+                        match base.get(k) {
+                            Some(base_value) if base_value == value => None,
+                            Some(base_value) => {
+                                Some((k.clone(), diff_byml_value(base_value, value, key)))
+                            }
+                            None => Some((k.clone(), value.clone())),
+                        }
+                    })
+                    .chain(
+                        base.keys()
+                            .filter(|&k| !other.contains_key(k))
+                            .map(|k| (k.clone(), deleted_byml())),
+                    )
+                    .collect(),
+            )
+        }
+        (Byml::Array(base), Byml::Array(other)) => {
+            diff_keyed_array(base, other, key).unwrap_or_else(|| Byml::Array(other.clone()))
+        }
+        _ => other.clone(),
+    }
+}
+
+fn merge_keyed_array<K: Fn(&Byml) -> Option<String>>(
+    base: &[Byml],
+    diff: &Map,
+    key: &K,
+) -> Vec<Byml> {
+    let empty = Map::default();
+    let modify = diff
+        .get("modify")
+        .and_then(|m| m.as_map().ok())
+        .unwrap_or(&empty);
+    let remove: std::collections::HashSet<&str> = diff
+        .get("remove")
+        .and_then(|r| r.as_array().ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|k| k.as_string().ok().map(|k| k.as_str()))
+        .collect();
+    let mut merged: Vec<Byml> = base
+        .iter()
+        .filter_map(|entry| {
+            match key(entry) {
+                Some(k) if remove.contains(k.as_str()) => None,
+                Some(k) => {
+                    Some(match modify.get(k.as_str()) {
+                        Some(entry_diff) => merge_byml_value(entry, entry_diff, key),
+                        None => entry.clone(),
+                    })
+                }
+                None => Some(entry.clone()),
+            }
+        })
+        .collect();
+    let position =
+        |merged: &[Byml], k: &str| merged.iter().position(|e| key(e).as_deref() == Some(k));
+    for added in diff
+        .get("add")
+        .and_then(|a| a.as_array().ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|a| a.as_map().ok())
+    {
+        let Some(entry) = added.get("entry") else {
+            continue;
+        };
+        // An entry another mod already added under the same key is taken over
+        if let Some(i) = key(entry).and_then(|k| position(&merged, &k)) {
+            merged[i] = entry.clone();
+            continue;
+        }
+        // Otherwise it goes after the entry it followed, or at the end if
+        // that is gone
+        let index = match added.get("after") {
+            Some(Byml::String(after)) => {
+                position(&merged, after.as_str())
+                    .map(|i| i + 1)
+                    .unwrap_or(merged.len())
+            }
+            _ => 0,
+        };
+        merged.insert(index, entry.clone());
+    }
+    merged
+}
+
+fn merge_byml_value<K: Fn(&Byml) -> Option<String>>(base: &Byml, diff: &Byml, key: &K) -> Byml {
+    match (base, diff) {
+        (Byml::Array(base), Byml::Map(diff)) => {
+            match diff.get(KEYED_DIFF).and_then(|d| d.as_map().ok()) {
+                Some(keyed) => Byml::Array(merge_keyed_array(base, keyed, key)),
+                None => Byml::Map(diff.clone()),
+            }
+        }
+        (Byml::Map(base), Byml::Map(diff)) => {
+            let mut new: Map = base.clone();
+            for (k, v) in diff {
+                if is_deleted_byml(v) {
+                    new.remove(k);
+                    continue;
+                }
+                match new.get_mut(k) {
+                    Some(value) if value == v => (),
+                    Some(value) => *value = merge_byml_value(value, v, key),
+                    // A value another mod removed takes just what this one
+                    // adds
+                    None => {
+                        let empty = match v {
+                            Byml::Map(_) => Byml::Map(Map::default()),
+                            _ => Byml::Array(vec![]),
+                        };
+                        new.insert(k.clone(), merge_byml_value(&empty, v, key));
+                    }
+                }
+            }
+            Byml::Map(new)
+        }
+        _ => diff.clone(),
+    }
+}
+
+/// Diffs two BYML hashes as [`diff_byml_shallow`] does, but goes on into
+/// the values which changed. Arrays of hashes which `key` can identify every
+/// entry of are diffed as the entries added, modified and removed, so that
+/// mods changing different entries of the same array can be merged with
+/// [`merge_byml_keyed`] rather than the last one replacing it. Other arrays
+/// are still replaced whole. The diff is marked as keyed, see
+/// [`is_keyed_diff`]. If either value is not a hash, the diff is just the
+/// other value, which replaces the base when merged.
+pub fn diff_byml_keyed<K: Fn(&Byml) -> Option<String>>(base: &Byml, other: &Byml, key: K) -> Byml {
+    if !(matches!(base, Byml::Map(_)) && matches!(other, Byml::Map(_))) {
+        return other.clone();
+    }
+    let mut diff = diff_byml_value(base, other, &key);
+    if let Byml::Map(diff) = &mut diff {
+        diff.insert(KEYED.into(), Byml::Bool(true));
+    }
+    diff
+}
+
+/// Merges a diff made by [`diff_byml_keyed`], with the same key extractor.
+/// Entries a diff adds under a key already present replace the entry there.
+/// A diff which is not marked as keyed is merged as by
+/// [`merge_byml_shallow`], and one which is not a hash replaces the base.
+pub fn merge_byml_keyed<K: Fn(&Byml) -> Option<String>>(base: &Byml, diff: &Byml, key: K) -> Byml {
+    match (base, diff) {
+        (_, Byml::Map(diff)) if diff.contains_key(KEYED) => {
+            let mut diff = diff.clone();
+            diff.remove(KEYED);
+            // A base which is no longer a hash takes just what the diff adds
+            let base = match base {
+                Byml::Map(_) => base.clone(),
+                _ => Byml::Map(Map::default()),
+            };
+            merge_byml_value(&base, &Byml::Map(diff), &key)
+        }
+        (Byml::Map(_), Byml::Map(_)) => merge_byml_shallow(base, diff),
+        (Byml::Map(base), Byml::Null) => Byml::Map(base.clone()),
+        _ => diff.clone(),
+    }
+}
+
 pub fn simple_index_diff<T: Clone + PartialEq>(
     base: &BTreeMap<usize, T>,
     other: &BTreeMap<usize, T>,
@@ -400,3 +822,322 @@ where
         self.iter.size_hint()
     }
 }
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::resource::{MergeableResource, ResourceData};
+
+    // The merges as they were first written, kept as the reference for what
+    // the faster versions must produce.
+
+    fn naive_merge_pobj(base: &ParameterObject, diff: &ParameterObject) -> ParameterObject {
+        base.0
+            .iter()
+            .chain(diff.0.iter())
+            .map(|(k, v)| (*k, v.clone()))
+            .collect()
+    }
+
+    fn naive_merge_plist(base: &ParameterList, diff: &ParameterList) -> ParameterList {
+        ParameterList {
+            objects: {
+                let mut new = base.objects.clone();
+                for (k, v) in &diff.objects.0 {
+                    if !new.0.contains_key(k) {
+                        new.0.insert(*k, v.clone());
+                    } else {
+                        new.0[k] = naive_merge_pobj(&new.0[k], v);
+                    }
+                }
+                new
+            },
+            lists:   {
+                let mut new = base.lists.clone();
+                for (k, v) in &diff.lists.0 {
+                    if !new.0.contains_key(k) {
+                        new.0.insert(*k, v.clone());
+                    } else {
+                        new.0[k] = naive_merge_plist(&new.0[k], v);
+                    }
+                }
+                new
+            },
+        }
+    }
+
+    fn naive_merge_byml(base: &Byml, diff: &Byml) -> Byml {
+        let (Byml::Map(base), Byml::Map(diff)) = (base, diff) else {
+            unreachable!()
+        };
+        let mut new: Map = base
+            .iter()
+            .chain(diff.iter())
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        new.retain(|_, v| v != &Byml::Null);
+        Byml::Map(new)
+    }
+
+    fn naive_merge_delete_map(
+        base: &DeleteMap<u32, i32>,
+        diff: &DeleteMap<u32, i32>,
+    ) -> Vec<(u32, i32, bool)> {
+        base.iter_full()
+            .chain(diff.iter_full())
+            .collect::<IndexMap<_, _>>()
+            .into_iter()
+            .filter_map(|(k, (v, del))| (!*del).then_some((*k, *v, false)))
+            .collect()
+    }
+
+    fn param() -> impl Strategy<Value = Parameter> {
+        prop_oneof![
+            any::<bool>().prop_map(Parameter::Bool),
+            (-3i32..3).prop_map(Parameter::I32),
+            "[ab]{0,2}".prop_map(|s| Parameter::StringRef(s.into())),
+        ]
+    }
+
+    // Keys come from a small range so bases and diffs share plenty of them
+    fn pobj() -> impl Strategy<Value = ParameterObject> {
+        prop::collection::vec((0u32..8, param()), 0..6).prop_map(|params| {
+            params
+                .into_iter()
+                .map(|(k, v)| (Name::from(k), v))
+                .collect()
+        })
+    }
+
+    fn plist() -> impl Strategy<Value = ParameterList> {
+        let objects = || {
+            prop::collection::vec((0u32..4, pobj()), 0..4).prop_map(|objs| {
+                objs.into_iter()
+                    .map(|(k, v)| (Name::from(k), v))
+                    .collect::<ParameterObjectMap>()
+            })
+        };
+        objects()
+            .prop_map(|objects| {
+                ParameterList {
+                    objects,
+                    lists: Default::default(),
+                }
+            })
+            .prop_recursive(3, 32, 3, move |inner| {
+                (objects(), prop::collection::vec((0u32..3, inner), 0..3)).prop_map(
+                    |(objects, lists)| {
+                        ParameterList {
+                            objects,
+                            lists: lists.into_iter().map(|(k, v)| (Name::from(k), v)).collect(),
+                        }
+                    },
+                )
+            })
+    }
+
+    fn byml_map() -> impl Strategy<Value = Byml> {
+        let value = prop_oneof![
+            Just(Byml::Null),
+            any::<bool>().prop_map(Byml::Bool),
+            (-3i32..3).prop_map(Byml::I32),
+        ];
+        prop::collection::vec(("[a-f]", value), 0..8).prop_map(|entries| {
+            Byml::Map(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+        })
+    }
+
+    fn object(id: u32, value: i32) -> Byml {
+        map!("HashId" => Byml::U32(id), "Value" => Byml::I32(value))
+    }
+
+    fn objects(objs: &[(u32, i32)]) -> Byml {
+        map!("Objs" => objs.iter().map(|(id, value)| object(*id, *value)).collect::<Byml>())
+    }
+
+    #[test]
+    fn keyed_additions_merge() {
+        let base = objects(&[(1, 0), (2, 0)]);
+        let first = objects(&[(1, 0), (2, 0), (3, 0)]);
+        let second = objects(&[(1, 0), (2, 0), (4, 0)]);
+        // Diffed shallowly, the second mod's array replaces the first's
+        let shallow = merge_byml_shallow(
+            &merge_byml_shallow(&base, &diff_byml_shallow(&base, &first)),
+            &diff_byml_shallow(&base, &second),
+        );
+        assert_eq!(shallow, second);
+        // Diffed by key, both additions are kept
+        let key = || byml_key_field(&["HashId"]);
+        let merged = merge_byml_keyed(
+            &merge_byml_keyed(&base, &diff_byml_keyed(&base, &first, key()), key()),
+            &diff_byml_keyed(&base, &second, key()),
+            key(),
+        );
+        assert_eq!(merged, objects(&[(1, 0), (2, 0), (4, 0), (3, 0)]));
+    }
+
+    #[test]
+    fn keyed_changes_merge() {
+        let key = || byml_key_field(&["HashId"]);
+        let base = objects(&[(1, 0), (2, 0), (3, 0)]);
+        // Changes, removals and additions in the middle come back as they were
+        let other = objects(&[(1, 1), (5, 0), (3, 0), (4, 0)]);
+        let diff = diff_byml_keyed(&base, &other, key());
+        assert!(is_keyed_diff(&diff));
+        assert_eq!(merge_byml_keyed(&base, &diff, key()), other);
+        // Mods changing different entries both apply
+        let first = objects(&[(1, 1), (2, 0), (3, 0)]);
+        let second = objects(&[(1, 0), (2, 0), (3, 2)]);
+        let merged = merge_byml_keyed(
+            &merge_byml_keyed(&base, &diff_byml_keyed(&base, &first, key()), key()),
+            &diff_byml_keyed(&base, &second, key()),
+            key(),
+        );
+        assert_eq!(merged, objects(&[(1, 1), (2, 0), (3, 2)]));
+        // Arrays without a key for every entry are still replaced whole
+        let base = map!("Objs" => Byml::Array(vec![Byml::I32(1), Byml::I32(2)]));
+        let other = map!("Objs" => Byml::Array(vec![Byml::I32(2), Byml::I32(3)]));
+        let diff = diff_byml_keyed(&base, &other, key());
+        assert_eq!(
+            diff.as_map().unwrap().get("Objs"),
+            other.as_map().unwrap().get("Objs")
+        );
+    }
+
+    #[test]
+    fn keyed_nulls_kept() {
+        let key = || byml_key_field(&["HashId"]);
+        // Null values stay, and only keys the mod removed are dropped
+        let base = map!("A" => Byml::Null, "B" => Byml::I32(1), "C" => Byml::I32(1));
+        let other = map!("A" => Byml::Null, "B" => Byml::Null);
+        let diff = diff_byml_keyed(&base, &other, key());
+        assert_eq!(merge_byml_keyed(&base, &diff, key()), other);
+        // Values which are not hashes are replaced whole
+        let diff = diff_byml_keyed(&Byml::I32(1), &Byml::I32(2), key());
+        assert_eq!(merge_byml_keyed(&Byml::I32(1), &diff, key()), Byml::I32(2));
+    }
+
+    #[test]
+    fn unkeyed_diff_merge() {
+        let key = || byml_key_field(&["HashId"]);
+        let base = map!("Params" => map!("A" => Byml::I32(1), "B" => Byml::I32(1)));
+        let other = map!("Params" => map!("A" => Byml::I32(2)));
+        // A diff made before keyed diffs holds whole values, so merging it
+        // deeply would bring back the key it removed
+        let diff = diff_byml_shallow(&base, &other);
+        assert!(!is_keyed_diff(&diff));
+        assert_eq!(merge_byml_keyed(&base, &diff, key()), other);
+    }
+
+    fn pobj_of(params: &[(&str, i32)]) -> ParameterObject {
+        params.iter().fold(ParameterObject::new(), |obj, (k, v)| {
+            obj.with_parameter(*k, Parameter::I32(*v))
+        })
+    }
+
+    /// A list with object `A`, optionally object `B` and list `L`.
+    fn plist_of(a: &[(&str, i32)], b: Option<&[(&str, i32)]>, l: Option<i32>) -> ParameterList {
+        let mut list = ParameterList::new().with_object("A", pobj_of(a));
+        if let Some(b) = b {
+            list = list.with_object("B", pobj_of(b));
+        }
+        if let Some(w) = l {
+            list = list.with_list(
+                "L",
+                ParameterList::new().with_object("C", pobj_of(&[("W", w)])),
+            );
+        }
+        list
+    }
+
+    #[test]
+    fn deletions_stack() {
+        let base = plist_of(&[("X", 1), ("Y", 1)], Some(&[("Z", 1)]), Some(1));
+        // Deletes Y, B and L
+        let deleted = plist_of(&[("X", 1)], None, None);
+        // Adds them back, changed
+        let readded = plist_of(&[("X", 1), ("Y", 2)], Some(&[("Z", 2)]), Some(2));
+        let first = diff_plist(&base, &deleted);
+        let second = diff_plist(&base, &readded);
+        let third = diff_plist(&base, &deleted);
+        let stack = |diffs: &[&ParameterList]| {
+            diffs
+                .iter()
+                .fold(base.clone(), |merged, diff| merge_plist(&merged, diff))
+        };
+        assert_eq!(stack(&[&first]), deleted);
+        assert_eq!(stack(&[&first, &second]), readded);
+        assert_eq!(stack(&[&first, &second, &third]), deleted);
+        assert_eq!(stack(&[&first, &third, &second]), readded);
+        // A change to an object deleted before it adds it back without the
+        // deletions within it
+        let changed = diff_plist(
+            &base,
+            &plist_of(&[("X", 1), ("Y", 1)], Some(&[("Q", 5)]), Some(1)),
+        );
+        assert_eq!(
+            stack(&[&first, &changed]),
+            plist_of(&[("X", 1)], Some(&[("Q", 5)]), None)
+        );
+        // Deletions survive being stored in a mod and read back
+        let mut pio = ParameterIO::new();
+        pio.param_root = first.clone();
+        let data = minicbor_ser::to_vec(&ResourceData::Mergeable(MergeableResource::GenericAamp(
+            Box::new(pio),
+        )))
+        .unwrap();
+        let stored = match minicbor_ser::from_slice::<ResourceData>(&data)
+            .unwrap()
+            .take_mergeable()
+        {
+            Some(MergeableResource::GenericAamp(pio)) => pio.param_root,
+            _ => panic!("Stored diff is not a generic AAMP resource"),
+        };
+        assert_eq!(stored, first);
+        assert_eq!(stack(&[&stored, &second, &stored]), deleted);
+        // Diffs without deletions leave what they lack alone
+        let old = plist_of(&[("X", 2)], None, None);
+        assert_eq!(
+            merge_plist(&base, &old),
+            plist_of(&[("X", 2), ("Y", 1)], Some(&[("Z", 1)]), Some(1))
+        );
+    }
+
+    fn delete_map() -> impl Strategy<Value = DeleteMap<u32, i32>> {
+        prop::collection::vec((0u32..10, -3i32..3, prop::bool::weighted(0.2)), 0..8)
+            .prop_map(|entries| entries.into_iter().collect())
+    }
+
+    proptest! {
+        #[test]
+        fn merge_pobj_matches_naive(base in pobj(), diff in pobj()) {
+            let merged = merge_pobj(&base, &diff);
+            let naive = naive_merge_pobj(&base, &diff);
+            // Compare in order, which `IndexMap` equality ignores
+            prop_assert!(merged.0.iter().eq(naive.0.iter()));
+        }
+
+        #[test]
+        fn merge_plist_matches_naive(base in plist(), diff in plist()) {
+            prop_assert_eq!(merge_plist(&base, &diff), naive_merge_plist(&base, &diff));
+        }
+
+        #[test]
+        fn merge_byml_matches_naive(base in byml_map(), diff in byml_map()) {
+            prop_assert_eq!(merge_byml_shallow(&base, &diff), naive_merge_byml(&base, &diff));
+        }
+
+        #[test]
+        fn merge_delete_map_matches_naive(base in delete_map(), diff in delete_map()) {
+            let merged: Vec<_> = base
+                .merge(&diff)
+                .iter_full()
+                .map(|(k, (v, del))| (*k, *v, *del))
+                .collect();
+            prop_assert_eq!(merged, naive_merge_delete_map(&base, &diff));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Working out the manifest of a mod with many options for random selections
+//! of them, as previewing or toggling options does: reading it from the
+//! archive every time, against the unions cached for each selection.
+//! Save a baseline before a change with `cargo bench -p uk-manager --bench
+//! manifests -- --save-baseline before`, then compare with `--baseline
+//! before` after it.
+use std::io::Write;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use uk_content::fixtures::Sequence;
+use uk_manager::mods::Mod;
+use uk_mod::{unpack::ModReader, Manifest, Meta, ModOption, MultipleOptionGroup, OptionGroup};
+
+const OPTIONS: usize = 24;
+const FILES: usize = 200;
+const SELECTIONS: usize = 32;
+
+fn option(i: usize) -> ModOption {
+    ModOption {
+        name: format!("Option {i}").into(),
+        description: Default::default(),
+        path: format!("option{i:02}").into(),
+        requires: vec![],
+        image: None,
+    }
+}
+
+/// Writes a mod whose options each list a few hundred files.
+fn fixture(path: &std::path::Path) {
+    let meta = Meta {
+        api: env!("CARGO_PKG_VERSION").into(),
+        format: uk_mod::FORMAT_VERSION,
+        name: "Bench Options".into(),
+        version: "1.0.0".into(),
+        author: Default::default(),
+        category: Default::default(),
+        description: Default::default(),
+        platform: uk_mod::ModPlatform::Universal,
+        url: None,
+        options: vec![OptionGroup::Multiple(MultipleOptionGroup {
+            options: (0..OPTIONS).map(option).collect(),
+            ..Default::default()
+        })],
+        masters: Default::default(),
+    };
+    let manifest = |prefix: &str| {
+        let mut manifest = Manifest::default();
+        manifest
+            .content_files
+            .extend((0..FILES).map(|i| format!("Actor/Pack/{prefix}_{i:03}.sbactorpack").into()));
+        serde_yaml::to_string(&manifest).unwrap()
+    };
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    let opts = zip::write::SimpleFileOptions::default();
+    let mut files = vec![
+        (
+            "meta.yml".to_string(),
+            serde_yaml::to_string(&meta).unwrap(),
+        ),
+        ("manifest.yml".to_string(), manifest("Base")),
+    ];
+    files.extend((0..OPTIONS).map(|i| {
+        (
+            format!("options/option{i:02}/manifest.yml"),
+            manifest(&format!("Option{i}")),
+        )
+    }));
+    for (name, contents) in files {
+        zip.start_file(name, opts).unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn manifests(c: &mut Criterion) {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("bench.zip");
+    fixture(&path);
+    let mod_ = Mod::from_reader(ModReader::open_peek(&path, vec![]).unwrap());
+    let mut seq = Sequence::new(SELECTIONS as u64);
+    let selections: Vec<Vec<ModOption>> = (0..SELECTIONS)
+        .map(|_| {
+            (0..OPTIONS)
+                .filter(|_| seq.below(2) == 0)
+                .map(option)
+                .collect()
+        })
+        .collect();
+    let mut group = c.benchmark_group("manifests");
+    group.bench_function("archive", |b| {
+        b.iter(|| {
+            for options in &selections {
+                criterion::black_box(ModReader::open(&path, options.as_slice()).unwrap());
+            }
+        })
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            for options in &selections {
+                criterion::black_box(mod_.manifest_with_options(options).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, manifests);
+criterion_main!(benches);
@@ -0,0 +1,390 @@
+//! An append-only record of every change to the mod library, so that a setup
+//! which is suddenly different can be traced back to what changed and when.
+//! Events are written one JSON object per line to `audit.jsonl` in the
+//! storage folder. When the file grows past its cap it is moved aside to
+//! `audit.1.jsonl`, replacing the previous one, and a new file is started.
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow_ext::Result;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+
+use crate::{mods::Priority, settings::Settings};
+
+const FILE_NAME: &str = "audit.jsonl";
+const ROTATED_NAME: &str = "audit.1.jsonl";
+/// Size at which the log is rotated. Two files are kept, so the log takes at
+/// most about twice this.
+pub const MAX_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ModInstalled {
+        profile: String,
+        name:    String,
+        version: String,
+    },
+    ModUpdated {
+        profile:  String,
+        name:     String,
+        version:  String,
+        previous: String,
+    },
+    ModRemoved {
+        profile: String,
+        name:    String,
+        version: String,
+    },
+    ModToggled {
+        profile: String,
+        name:    String,
+        enabled: bool,
+    },
+    /// A mod was archived out of the load order, or put back.
+    ModArchived {
+        profile:  String,
+        name:     String,
+        archived: bool,
+    },
+    /// The selected options of a mod changed.
+    OptionsChanged {
+        profile: String,
+        name:    String,
+        options: Vec<String>,
+    },
+    /// Selected options of a mod were turned off or back on.
+    OptionsToggled {
+        profile:  String,
+        name:     String,
+        inactive: Vec<String>,
+    },
+    OrderChanged {
+        profile: String,
+        /// Mod names in their new load order.
+        order:   Vec<String>,
+    },
+    PriorityChanged {
+        profile:  String,
+        priority: Priority,
+    },
+    ProfileCreated {
+        profile: String,
+    },
+    ProfileChanged {
+        profile:  String,
+        previous: String,
+    },
+    /// A profile was moved to the trash.
+    ProfileDeleted {
+        profile: String,
+    },
+    ProfileRestored {
+        profile: String,
+    },
+    SettingsChanged,
+    Applied {
+        profile: String,
+        /// Whether every mod was remerged rather than only changed files.
+        remerge: bool,
+        mods:    usize,
+    },
+    Deployed {
+        profile: String,
+    },
+}
+
+impl Event {
+    /// Every event kind, as returned by [`Event::kind`].
+    pub const KINDS: [&'static str; 16] = [
+        "mod_installed",
+        "mod_updated",
+        "mod_removed",
+        "mod_toggled",
+        "mod_archived",
+        "options_changed",
+        "options_toggled",
+        "order_changed",
+        "priority_changed",
+        "profile_created",
+        "profile_changed",
+        "profile_deleted",
+        "profile_restored",
+        "settings_changed",
+        "applied",
+        "deployed",
+    ];
+
+    /// The name the event is stored under.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::ModInstalled { .. } => "mod_installed",
+            Event::ModUpdated { .. } => "mod_updated",
+            Event::ModRemoved { .. } => "mod_removed",
+            Event::ModToggled { .. } => "mod_toggled",
+            Event::ModArchived { .. } => "mod_archived",
+            Event::OptionsChanged { .. } => "options_changed",
+            Event::OptionsToggled { .. } => "options_toggled",
+            Event::OrderChanged { .. } => "order_changed",
+            Event::PriorityChanged { .. } => "priority_changed",
+            Event::ProfileCreated { .. } => "profile_created",
+            Event::ProfileChanged { .. } => "profile_changed",
+            Event::ProfileDeleted { .. } => "profile_deleted",
+            Event::ProfileRestored { .. } => "profile_restored",
+            Event::SettingsChanged => "settings_changed",
+            Event::Applied { .. } => "applied",
+            Event::Deployed { .. } => "deployed",
+        }
+    }
+
+    pub fn describe(&self) -> std::string::String {
+        match self {
+            Event::ModInstalled {
+                profile,
+                name,
+                version,
+            } => format!("Installed {name} {version} in {profile}"),
+            Event::ModUpdated {
+                profile,
+                name,
+                version,
+                previous,
+            } => format!("Updated {name} from {previous} to {version} in {profile}"),
+            Event::ModRemoved {
+                profile,
+                name,
+                version,
+            } => format!("Removed {name} {version} from {profile}"),
+            Event::ModToggled {
+                profile,
+                name,
+                enabled,
+            } => {
+                format!(
+                    "{} {name} in {profile}",
+                    if *enabled { "Enabled" } else { "Disabled" }
+                )
+            }
+            Event::ModArchived {
+                profile,
+                name,
+                archived,
+            } => {
+                format!(
+                    "{} {name} in {profile}",
+                    if *archived { "Archived" } else { "Unarchived" }
+                )
+            }
+            Event::OptionsChanged {
+                profile,
+                name,
+                options,
+            } => {
+                format!(
+                    "Set options on {name} in {profile}: {}",
+                    if options.is_empty() {
+                        "none".into()
+                    } else {
+                        options.join(", ")
+                    }
+                )
+            }
+            Event::OptionsToggled {
+                profile,
+                name,
+                inactive,
+            } => {
+                format!(
+                    "Turned off options on {name} in {profile}: {}",
+                    if inactive.is_empty() {
+                        "none".into()
+                    } else {
+                        inactive.join(", ")
+                    }
+                )
+            }
+            Event::OrderChanged { profile, order } => {
+                format!("Changed load order of {} mods in {profile}", order.len())
+            }
+            Event::PriorityChanged { profile, priority } => {
+                format!(
+                    "Changed {profile} so that {}",
+                    priority.label().to_lowercase()
+                )
+            }
+            Event::ProfileCreated { profile } => format!("Created profile {profile}"),
+            Event::ProfileChanged { profile, previous } => {
+                format!("Switched profile from {previous} to {profile}")
+            }
+            Event::ProfileDeleted { profile } => format!("Deleted profile {profile}"),
+            Event::ProfileRestored { profile } => {
+                format!("Restored profile {profile} from the trash")
+            }
+            Event::SettingsChanged => "Changed settings".into(),
+            Event::Applied {
+                profile,
+                remerge,
+                mods,
+            } => {
+                format!(
+                    "{} {profile} with {mods} enabled mods",
+                    if *remerge { "Remerged" } else { "Applied" }
+                )
+            }
+            Event::Deployed { profile } => format!("Deployed {profile}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the event happened, in milliseconds since the Unix epoch.
+    pub time:  u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    dir: PathBuf,
+    cap: u64,
+}
+
+impl AuditLog {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            cap: MAX_SIZE,
+        }
+    }
+
+    /// The log in the configured storage folder.
+    pub fn open(settings: &Settings) -> Self {
+        Self::new(&settings.storage_dir)
+    }
+
+    pub fn with_cap(mut self, cap: u64) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(FILE_NAME)
+    }
+
+    /// Appends an event to the log, rotating it first if it is full.
+    pub fn append(&self, event: Event) -> Result<()> {
+        let entry = Entry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            event,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path();
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+        if size > 0 && size + line.len() as u64 > self.cap {
+            fs::rename(&path, self.dir.join(ROTATED_NAME))?;
+        }
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+
+    /// Appends an event, logging rather than returning any error. A failure
+    /// to record a change should never stop the change itself.
+    pub fn record(&self, event: Event) {
+        log::debug!("Audit: {}", event.describe());
+        if let Err(e) = self.append(event) {
+            log::warn!("Failed to write audit log: {e:?}");
+        }
+    }
+
+    fn read(path: &Path) -> Vec<Entry> {
+        fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                serde_json::from_str(line)
+                    .inspect_err(|e| log::warn!("Skipping unreadable audit log entry: {e}"))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// All recorded events, oldest first, including the rotated file.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = Self::read(&self.dir.join(ROTATED_NAME));
+        entries.extend(Self::read(&self.path()));
+        entries
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn toggled(enabled: bool) -> Event {
+        Event::ModToggled {
+            profile: "Default".into(),
+            name: "Test Mod".into(),
+            enabled,
+        }
+    }
+
+    #[test]
+    fn format() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(tmp.path());
+        log.append(toggled(false)).unwrap();
+        log.append(Event::SettingsChanged).unwrap();
+        let text = std::fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["event"], "mod_toggled");
+        assert_eq!(value["name"], "Test Mod");
+        assert_eq!(value["enabled"], false);
+        assert!(value["time"].as_u64().unwrap() > 0);
+        let entries = log.entries();
+        assert_eq!(entries[0].event, toggled(false));
+        assert_eq!(entries[1].event.kind(), "settings_changed");
+        assert!(Event::KINDS.contains(&toggled(true).kind()));
+        assert_eq!(toggled(true).describe(), "Enabled Test Mod in Default");
+    }
+
+    #[test]
+    fn rotation() {
+        let tmp = tempfile::tempdir().unwrap();
+        let line_len = serde_json::to_vec(&Entry {
+            time:  0,
+            event: toggled(true),
+        })
+        .unwrap()
+        .len() as u64;
+        // Room for three events per file
+        let log = AuditLog::new(tmp.path()).with_cap(line_len * 3 + 48);
+        for i in 0..8 {
+            log.append(toggled(i % 2 == 0)).unwrap();
+        }
+        assert!(tmp.path().join(ROTATED_NAME).exists());
+        assert!(std::fs::metadata(log.path()).unwrap().len() <= line_len * 3 + 48);
+        // Only the current and one rotated file are kept
+        assert_eq!(log.entries().len(), 5);
+        std::fs::write(tmp.path().join(ROTATED_NAME), b"not json\n").unwrap();
+        assert_eq!(log.entries().len(), 2);
+    }
+}
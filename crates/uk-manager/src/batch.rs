@@ -0,0 +1,584 @@
+//! Batch files: lists of operations on the mod library which are run top to
+//! bottom, so a setup can be reproduced without clicking through it again.
+//! They are YAML, with each step tagged by its `op`:
+//!
+//! ```yaml
+//! on_error: continue
+//! steps:
+//!   - op: install
+//!     path: mods/Second Wind.zip
+//!     options: [Hard Mode]
+//!   - op: disable
+//!     mod: Linkle
+//!   - op: order
+//!     mods: [Second Wind, Linkle]
+//!   - op: apply
+//! ```
+//!
+//! Paths are relative to the batch file, and mods are referred to by name or
+//! by ID. A dry run checks every reference, following the changes earlier
+//! steps would make, without changing anything.
+use std::path::{Path, PathBuf};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+use uk_mod::{unpack::ModReader, Manifest, ModOption, ModOptionGroup};
+
+use crate::{core::Manager, mods::Mod};
+
+/// What to do when a step fails.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Stop at the failed step.
+    #[default]
+    Stop,
+    /// Log the failure and run the rest of the steps.
+    Continue,
+}
+
+/// A mod in the current profile, by name or by ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModRef {
+    Hash(usize),
+    Name(String),
+}
+
+impl ModRef {
+    fn matches(&self, mod_: &Mod) -> bool {
+        match self {
+            ModRef::Hash(hash) => mod_.hash() == *hash,
+            ModRef::Name(name) => {
+                mod_.meta.name == *name || name.parse::<usize>().ok() == Some(mod_.hash())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ModRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModRef::Hash(hash) => write!(f, "{hash}"),
+            ModRef::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// One operation in a batch file. All but `profile` act on the current
+/// profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Step {
+    /// Installs and enables a mod. Options are given by name or folder, and
+    /// if there are none, the mod's defaults are selected.
+    Install {
+        path:    PathBuf,
+        #[serde(default)]
+        options: Option<Vec<String>>,
+    },
+    Uninstall {
+        #[serde(rename = "mod")]
+        mod_: ModRef,
+    },
+    Enable {
+        #[serde(rename = "mod")]
+        mod_: ModRef,
+    },
+    Disable {
+        #[serde(rename = "mod")]
+        mod_: ModRef,
+    },
+    /// Replaces the selected options of a mod.
+    Options {
+        #[serde(rename = "mod")]
+        mod_:    ModRef,
+        options: Vec<String>,
+    },
+    /// Moves mods to the top of the load order, in the given order. Mods not
+    /// listed keep their order after them.
+    Order {
+        mods: Vec<ModRef>,
+    },
+    /// Switches to a profile, creating it if needed.
+    Profile {
+        name: String,
+    },
+    /// Merges the changes made so far.
+    Apply,
+    Deploy,
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Install { path, .. } => write!(f, "install {}", path.display()),
+            Step::Uninstall { mod_ } => write!(f, "uninstall {mod_}"),
+            Step::Enable { mod_ } => write!(f, "enable {mod_}"),
+            Step::Disable { mod_ } => write!(f, "disable {mod_}"),
+            Step::Options { mod_, .. } => write!(f, "set options of {mod_}"),
+            Step::Order { .. } => write!(f, "set load order"),
+            Step::Profile { name } => write!(f, "switch to profile {name}"),
+            Step::Apply => write!(f, "apply"),
+            Step::Deploy => write!(f, "deploy"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Batch {
+    #[serde(default)]
+    pub on_error: OnError,
+    pub steps:    Vec<Step>,
+}
+
+impl Batch {
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        serde_yaml::from_str(&text)
+            .with_context(|| format!("Failed to parse batch file {}", path.display()))
+    }
+}
+
+/// What running a batch did.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// How many steps succeeded, or would have in a dry run.
+    pub succeeded: usize,
+    /// Why steps failed, when carrying on past failures.
+    pub failed:    Vec<anyhow_ext::Error>,
+    /// Files changed since the last `apply` step, which still need to be
+    /// merged.
+    pub pending:   Manifest,
+}
+
+/// Options from a mod's meta, by name or folder.
+fn find_options(mod_: &Mod, names: &[String]) -> Result<Vec<ModOption>> {
+    names
+        .iter()
+        .map(|name| {
+            mod_.meta
+                .options
+                .iter()
+                .flat_map(|group| group.options())
+                .find(|option| option.name == *name || option.path == Path::new(name.as_str()))
+                .cloned()
+                .with_context(|| format!("{} has no option {name}", mod_.meta.name))
+        })
+        .collect()
+}
+
+struct Runner<'a> {
+    core:    &'a Manager,
+    /// Where paths in the batch are relative to.
+    base:    &'a Path,
+    /// The mods of the current profile as the steps so far would leave them,
+    /// when only checking the batch.
+    dry_run: Option<Vec<Mod>>,
+    pending: Manifest,
+    /// Whether everything must be merged again, as the profile has changed.
+    remerge: bool,
+}
+
+impl Runner<'_> {
+    fn mods(&self) -> Vec<Mod> {
+        match &self.dry_run {
+            Some(mods) => mods.clone(),
+            None => self.core.mod_manager().all_mods().collect(),
+        }
+    }
+
+    fn find(&self, mod_: &ModRef) -> Result<Mod> {
+        self.mods()
+            .into_iter()
+            .find(|m| mod_.matches(m))
+            .with_context(|| format!("There is no mod {mod_} in the profile"))
+    }
+
+    fn touch(&mut self, mod_: &Mod) -> Result<()> {
+        if self.dry_run.is_none() {
+            self.pending.extend(mod_.manifest()?.as_ref());
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, step: &Step) -> Result<()> {
+        match step {
+            Step::Install { path, options } => {
+                let path = self.base.join(path);
+                let reader = ModReader::open_peek(&path, vec![])
+                    .with_context(|| format!("{} is not a UKMM mod", path.display()))?;
+                let mut mod_ = Mod::from_reader(reader);
+                mod_.enabled = true;
+                match options {
+                    Some(options) => mod_.enabled_options = find_options(&mod_, options)?,
+                    None => mod_.enable_default_options(),
+                }
+                if let Some(mods) = self.dry_run.as_mut() {
+                    mods.retain(|m| m.meta.name != mod_.meta.name);
+                    mods.push(mod_);
+                    return Ok(());
+                }
+                let mod_manager = self.core.mod_manager();
+                let hash = mod_manager.add(&path, None)?.hash();
+                mod_manager.set_enabled_options(hash, mod_.enabled_options)?;
+                let mod_ = mod_manager
+                    .get_mod(hash)
+                    .context("Installed mod went missing")?;
+                drop(mod_manager);
+                self.touch(&mod_)?;
+            }
+            Step::Uninstall { mod_ } => {
+                let mod_ = self.find(mod_)?;
+                match self.dry_run.as_mut() {
+                    Some(mods) => mods.retain(|m| m.hash() != mod_.hash()),
+                    None => {
+                        let manifest = self.core.mod_manager().del(mod_.hash(), None)?;
+                        self.pending.extend(&manifest);
+                    }
+                }
+            }
+            Step::Enable { mod_ } | Step::Disable { mod_ } => {
+                let enabled = matches!(step, Step::Enable { .. });
+                let mod_ = self.find(mod_)?;
+                if self.dry_run.is_none() {
+                    let manifest =
+                        self.core
+                            .mod_manager()
+                            .set_enabled(mod_.hash(), enabled, None)?;
+                    self.pending.extend(&manifest);
+                }
+            }
+            Step::Options { mod_, options } => {
+                let mod_ = self.find(mod_)?;
+                let options = find_options(&mod_, options)?;
+                if self.dry_run.is_none() {
+                    // Both the old and new options' files need merging
+                    self.touch(&mod_)?;
+                    let manifest = self
+                        .core
+                        .mod_manager()
+                        .set_enabled_options(mod_.hash(), options)?;
+                    self.pending.extend(&manifest);
+                }
+            }
+            Step::Order { mods } => {
+                let listed = mods
+                    .iter()
+                    .map(|mod_| self.find(mod_))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut order: Vec<Mod> = listed.clone();
+                order.extend(self.mods().into_iter().filter(|m| !listed.contains(m)));
+                match self.dry_run.as_mut() {
+                    Some(mods) => *mods = order,
+                    None => {
+                        self.core
+                            .mod_manager()
+                            .set_order(order.iter().map(|m| m.hash()).collect());
+                        for mod_ in &listed {
+                            self.touch(mod_)?;
+                        }
+                    }
+                }
+            }
+            Step::Profile { name } => {
+                match self.dry_run.as_mut() {
+                    Some(mods) => {
+                        *mods = self
+                            .core
+                            .mod_manager()
+                            .profile_mods(name)
+                            .unwrap_or_default();
+                    }
+                    None => {
+                        self.core.change_profile(name)?;
+                        self.remerge = true;
+                    }
+                }
+            }
+            Step::Apply => {
+                if self.core.settings().dump().is_none() {
+                    anyhow_ext::bail!("No game dump is set up for the current platform");
+                }
+                if self.dry_run.is_none() {
+                    let pending = std::mem::take(&mut self.pending);
+                    self.core
+                        .deploy_manager()
+                        .apply((!std::mem::take(&mut self.remerge)).then_some(pending))?;
+                }
+            }
+            Step::Deploy => {
+                if self.core.settings().deploy_dir().is_none() {
+                    anyhow_ext::bail!("Deployment is not set up for the current platform");
+                }
+                if self.dry_run.is_none() {
+                    self.core.deploy_manager().deploy()?;
+                }
+            }
+        }
+        if self.dry_run.is_none() {
+            self.core.mod_manager().save()?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a batch, or with `dry_run`, checks that it could be run without
+/// changing anything. Paths in it are relative to `base`. Unless the batch
+/// says to carry on, the first failed step stops it. Either way the changes
+/// of the steps which did run are kept in the outcome.
+pub fn run(core: &Manager, batch: &Batch, base: &Path, dry_run: bool) -> Result<Outcome> {
+    let mut runner = Runner {
+        core,
+        base,
+        dry_run: dry_run.then(|| core.mod_manager().all_mods().collect()),
+        pending: Manifest::default(),
+        remerge: false,
+    };
+    let mut outcome = Outcome::default();
+    for (i, step) in batch.steps.iter().enumerate() {
+        log::info!(
+            "{} step {}: {step}",
+            if dry_run { "Checking" } else { "Running" },
+            i + 1
+        );
+        match runner
+            .step(step)
+            .with_context(|| format!("Step {} ({step}) failed", i + 1))
+        {
+            Ok(()) => outcome.succeeded += 1,
+            Err(e) => {
+                log::error!("{e:?}");
+                outcome.failed.push(e);
+                if batch.on_error == OnError::Stop {
+                    break;
+                }
+            }
+        }
+    }
+    outcome.pending = runner.pending;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use uk_content::resource::ResourceData;
+    use uk_mod::{Meta, MultipleOptionGroup, OptionGroup};
+
+    use super::*;
+    use crate::tests::wiiu_settings;
+
+    fn meta(name: &str) -> Meta {
+        Meta {
+            api: "1.0.0".into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: String::new(),
+            category: String::new(),
+            description: String::new(),
+            platform: uk_mod::ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        }
+    }
+
+    fn option(name: &str) -> ModOption {
+        ModOption {
+            name: name.into(),
+            description: String::new(),
+            path: name.to_lowercase().into(),
+            requires: vec![],
+            image: None,
+        }
+    }
+
+    /// A packed mod with one file, and an unpacked one with two options.
+    fn fixture_mods(dir: &Path) {
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert("Model/First.sbfres".into());
+        uk_mod::pack::pack_resources(dir.join("First.zip"), &meta("First"), &manifest, [(
+            "Model/First.bfres".into(),
+            ResourceData::Binary(b"First".to_vec()),
+        )])
+        .unwrap();
+
+        let mut meta = meta("Second");
+        meta.options = vec![OptionGroup::Multiple(MultipleOptionGroup {
+            name: "Difficulty".into(),
+            options: vec![option("Easy"), option("Hard")],
+            defaults: ["easy".into()].into_iter().collect(),
+            ..Default::default()
+        })];
+        let path = dir.join("Second");
+        let manifest = serde_yaml::to_string(&Manifest::default()).unwrap();
+        for option in ["easy", "hard"] {
+            fs::create_dir_all(path.join("options").join(option)).unwrap();
+            fs::write(
+                path.join("options").join(option).join("manifest.yml"),
+                &manifest,
+            )
+            .unwrap();
+        }
+        fs::write(path.join("manifest.yml"), &manifest).unwrap();
+        fs::write(path.join("meta.yml"), serde_yaml::to_string(&meta).unwrap()).unwrap();
+    }
+
+    fn core(storage: &Path) -> Manager {
+        Manager::with_settings(wiiu_settings(storage, None)).unwrap()
+    }
+
+    fn names(core: &Manager) -> Vec<(std::string::String, bool)> {
+        core.mod_manager()
+            .all_mods()
+            .map(|m| (m.meta.name.to_string(), m.enabled))
+            .collect()
+    }
+
+    #[test]
+    fn schema() {
+        let batch: Batch = serde_yaml::from_str(
+            "on_error: continue
+steps:
+  - op: install
+    path: mods/First.zip
+  - op: install
+    path: Second
+    options: [Hard]
+  - op: disable
+    mod: First
+  - op: enable
+    mod: 1234
+  - op: options
+    mod: Second
+    options: [easy]
+  - op: order
+    mods: [Second, First]
+  - op: profile
+    name: Testing
+  - op: apply
+  - op: deploy
+",
+        )
+        .unwrap();
+        assert_eq!(batch.on_error, OnError::Continue);
+        assert_eq!(batch.steps.len(), 9);
+        assert_eq!(batch.steps[1], Step::Install {
+            path:    "Second".into(),
+            options: Some(vec!["Hard".into()]),
+        });
+        assert_eq!(batch.steps[3], Step::Enable {
+            mod_: ModRef::Hash(1234),
+        });
+        assert_eq!(batch.steps[7], Step::Apply);
+        let batch: Batch = serde_yaml::from_str("steps: []").unwrap();
+        assert_eq!(batch.on_error, OnError::Stop);
+        assert!(serde_yaml::from_str::<Batch>("steps: [{op: explode}]").is_err());
+    }
+
+    #[test]
+    fn run_batch() {
+        let tmp = tempfile::tempdir().unwrap();
+        fixture_mods(tmp.path());
+        let core = core(&tmp.path().join("storage"));
+        let batch: Batch = serde_yaml::from_str(
+            "steps:
+  - op: install
+    path: First.zip
+  - op: install
+    path: Second
+    options: [Hard]
+  - op: order
+    mods: [Second]
+  - op: disable
+    mod: Second
+  - op: apply
+",
+        )
+        .unwrap();
+
+        // Checking changes nothing
+        let outcome = run(&core, &batch, tmp.path(), true).unwrap();
+        assert_eq!(outcome.succeeded, 5);
+        assert!(names(&core).is_empty());
+
+        let outcome = run(&core, &batch, tmp.path(), false).unwrap();
+        assert_eq!(outcome.succeeded, 5);
+        assert!(outcome.pending.content_files.is_empty());
+        assert_eq!(names(&core), vec![
+            ("Second".into(), false),
+            ("First".into(), true)
+        ]);
+        let second = core.mod_manager().all_mods().next().unwrap();
+        assert_eq!(second.enabled_options, vec![option("Hard")]);
+        let merged = core.settings().merged_dir();
+        assert_eq!(
+            fs::read(merged.join("content/Model/First.sbfres"))
+                .map(roead::yaz0::decompress)
+                .unwrap()
+                .unwrap(),
+            b"First"
+        );
+
+        // Changes after the last apply are left pending
+        let batch: Batch = serde_yaml::from_str(
+            "steps:
+  - op: disable
+    mod: First
+",
+        )
+        .unwrap();
+        let outcome = run(&core, &batch, tmp.path(), false).unwrap();
+        assert!(outcome.pending.content_files.contains("Model/First.sbfres"));
+    }
+
+    #[test]
+    fn failures() {
+        let tmp = tempfile::tempdir().unwrap();
+        fixture_mods(tmp.path());
+        let core = core(&tmp.path().join("storage"));
+        let mut batch: Batch = serde_yaml::from_str(
+            "steps:
+  - op: install
+    path: Missing.zip
+  - op: install
+    path: Second
+    options: [Nightmare]
+  - op: install
+    path: First.zip
+  - op: enable
+    mod: Third
+  - op: order
+    mods: [First]
+",
+        )
+        .unwrap();
+
+        // The first failure stops the batch, with the step that failed
+        let outcome = run(&core, &batch, tmp.path(), true).unwrap();
+        assert_eq!(outcome.succeeded, 0);
+        let failed: Vec<_> = outcome.failed.iter().map(|e| e.to_string()).collect();
+        assert_eq!(failed, vec!["Step 1 (install Missing.zip) failed"]);
+
+        // Or each is reported, and the rest are still checked
+        batch.on_error = OnError::Continue;
+        let outcome = run(&core, &batch, tmp.path(), true).unwrap();
+        assert_eq!(outcome.succeeded, 2);
+        let failed: Vec<_> = outcome.failed.iter().map(|e| e.to_string()).collect();
+        assert_eq!(failed, vec![
+            "Step 1 (install Missing.zip) failed",
+            "Step 2 (install Second) failed",
+            "Step 4 (enable Third) failed",
+        ]);
+        assert!(format!("{:?}", outcome.failed[1]).contains("Second has no option Nightmare"));
+        assert!(names(&core).is_empty());
+
+        let outcome = run(&core, &batch, tmp.path(), false).unwrap();
+        assert_eq!(outcome.failed.len(), 3);
+        assert_eq!(names(&core), vec![("First".into(), true)]);
+    }
+}
@@ -0,0 +1,493 @@
+//! Finding the mod which makes the game crash by bisecting the enabled mods.
+//! Starting from the enabled mods, which are assumed to crash, half of the
+//! suspects are enabled at a time and the user reports whether the game still
+//! crashes, until a single mod is left. If neither half crashes on its own the
+//! crash needs mods from both halves, so each half is searched in turn with
+//! the other held enabled, to find a pair.
+//!
+//! Pinned mods stay enabled throughout and are never suspected. A mod is
+//! never enabled without its masters, and suspects are ordered with masters
+//! before the mods which need them, so a master is always blamed before a mod
+//! which only crashes because of it.
+//!
+//! A bisect in progress is saved to `bisect.json` in the profile folder,
+//! along with the enabled state of every mod when it started, so it can carry
+//! on after a restart and the mods can be put back when it ends.
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use uk_mod::Manifest;
+
+use crate::{core::Manager, settings::Settings};
+
+const FILE_NAME: &str = "bisect.json";
+
+/// What a bisect found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Single(usize),
+    /// Two mods which only crash together.
+    Pair(usize, usize),
+    /// The smallest set of mods found to crash, when the crash needs more
+    /// than two of them. Empty if there were no mods to suspect.
+    Inconclusive(Vec<usize>),
+}
+
+impl Outcome {
+    /// The mods to blame.
+    pub fn culprits(&self) -> Vec<usize> {
+        match self {
+            Outcome::Single(hash) => vec![*hash],
+            Outcome::Pair(first, second) => vec![*first, *second],
+            Outcome::Inconclusive(hashes) => hashes.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Enable exactly these mods and report whether the game crashes.
+    Test(Vec<usize>),
+    Done(Outcome),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum Stage {
+    /// Looking for a single mod.
+    Single,
+    /// Looking for the first of a pair in one half, with the other half held
+    /// enabled.
+    PairFirst { other: Vec<usize> },
+    /// Looking for the second of a pair, with the first held enabled.
+    PairSecond { found: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Half {
+    First,
+    Second,
+}
+
+/// The bisect itself, which knows nothing of the mod manager. Mods are
+/// identified by hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bisect {
+    pinned:   Vec<usize>,
+    /// The masters of each mod.
+    deps:     BTreeMap<usize, Vec<usize>>,
+    /// The mods one of which is to blame, masters first.
+    suspects: Vec<usize>,
+    /// Mods held enabled alongside the half being tested.
+    context:  Vec<usize>,
+    stage:    Stage,
+    half:     Half,
+    outcome:  Option<Outcome>,
+    /// Results reported so far, as `(mods enabled, crashed)`.
+    history:  Vec<(usize, bool)>,
+}
+
+impl Bisect {
+    /// Starts a bisect of the enabled mods, in load order. Masters of pinned
+    /// mods are pinned too, and masters which are not enabled are ignored.
+    pub fn new(
+        enabled: impl IntoIterator<Item = usize>,
+        pinned: impl IntoIterator<Item = usize>,
+        deps: BTreeMap<usize, Vec<usize>>,
+    ) -> Self {
+        let enabled: Vec<usize> = enabled.into_iter().collect();
+        let deps: BTreeMap<usize, Vec<usize>> = deps
+            .into_iter()
+            .filter(|(hash, _)| enabled.contains(hash))
+            .map(|(hash, masters)| {
+                let masters = masters
+                    .into_iter()
+                    .filter(|m| *m != hash && enabled.contains(m))
+                    .collect();
+                (hash, masters)
+            })
+            .collect();
+        let pinned = closure(
+            &deps,
+            pinned.into_iter().filter(|hash| enabled.contains(hash)),
+        );
+        let mut suspects = Vec::with_capacity(enabled.len());
+        let mut seen = BTreeSet::new();
+        for hash in enabled.iter().filter(|hash| !pinned.contains(hash)) {
+            visit(*hash, &deps, &pinned, &mut seen, &mut suspects);
+        }
+        let mut bisect = Self {
+            pinned: pinned.into_iter().collect(),
+            deps,
+            suspects,
+            context: vec![],
+            stage: Stage::Single,
+            half: Half::First,
+            outcome: None,
+            history: vec![],
+        };
+        bisect.settle();
+        bisect
+    }
+
+    fn halves(&self) -> (&[usize], &[usize]) {
+        self.suspects.split_at(self.suspects.len() / 2)
+    }
+
+    /// The mods which must be enabled to test a set of mods.
+    fn test_set(&self, half: &[usize]) -> Vec<usize> {
+        let set = closure(
+            &self.deps,
+            half.iter()
+                .chain(self.context.iter())
+                .chain(self.pinned.iter())
+                .copied(),
+        );
+        set.into_iter().collect()
+    }
+
+    /// What to do next.
+    pub fn step(&self) -> Step {
+        if let Some(outcome) = self.outcome.as_ref() {
+            return Step::Done(outcome.clone());
+        }
+        let (first, second) = self.halves();
+        Step::Test(self.test_set(match self.half {
+            Half::First => first,
+            Half::Second => second,
+        }))
+    }
+
+    /// Records whether the game crashed with the mods from the last
+    /// [`Step::Test`].
+    pub fn report(&mut self, crashed: bool) {
+        let Step::Test(set) = self.step() else {
+            return;
+        };
+        self.history.push((set.len(), crashed));
+        let (first, second) = self.halves();
+        let (first, second) = (first.to_vec(), second.to_vec());
+        match (self.half, crashed) {
+            (Half::First, true) => self.narrow(first),
+            (Half::First, false) => self.half = Half::Second,
+            (Half::Second, true) => self.narrow(second),
+            (Half::Second, false) if self.stage == Stage::Single => {
+                self.stage = Stage::PairFirst {
+                    other: second.clone(),
+                };
+                self.context = second;
+                self.narrow(first);
+            }
+            (Half::Second, false) => {
+                // It takes more than one mod from each side, which is more
+                // than we can narrow down by halves
+                let mut culprits = self.suspects.clone();
+                culprits.extend(self.context.iter().copied());
+                self.outcome = Some(Outcome::Inconclusive(culprits));
+            }
+        }
+    }
+
+    fn narrow(&mut self, suspects: Vec<usize>) {
+        self.suspects = suspects;
+        self.half = Half::First;
+        self.settle();
+    }
+
+    /// Moves on once the suspects are down to one.
+    fn settle(&mut self) {
+        if self.suspects.len() > 1 {
+            return;
+        }
+        let Some(&found) = self.suspects.first() else {
+            self.outcome = Some(Outcome::Inconclusive(vec![]));
+            return;
+        };
+        match std::mem::replace(&mut self.stage, Stage::Single) {
+            Stage::Single => self.outcome = Some(Outcome::Single(found)),
+            Stage::PairFirst { other } => {
+                self.stage = Stage::PairSecond { found };
+                self.context = vec![found];
+                self.narrow(other);
+            }
+            Stage::PairSecond { found: first } => {
+                self.stage = Stage::PairSecond { found: first };
+                self.outcome = Some(Outcome::Pair(first, found));
+            }
+        }
+    }
+
+    pub fn pinned(&self) -> &[usize] {
+        &self.pinned
+    }
+
+    /// The mods still suspected.
+    pub fn suspects(&self) -> &[usize] {
+        &self.suspects
+    }
+
+    /// The mods held enabled while searching for the second of a pair, or
+    /// for the first alongside the other half.
+    pub fn context(&self) -> &[usize] {
+        &self.context
+    }
+
+    pub fn history(&self) -> &[(usize, bool)] {
+        &self.history
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.outcome.is_some()
+    }
+}
+
+/// Adds mods in dependency order, masters first.
+fn visit(
+    hash: usize,
+    deps: &BTreeMap<usize, Vec<usize>>,
+    pinned: &BTreeSet<usize>,
+    seen: &mut BTreeSet<usize>,
+    order: &mut Vec<usize>,
+) {
+    if pinned.contains(&hash) || !seen.insert(hash) {
+        return;
+    }
+    for master in deps.get(&hash).into_iter().flatten() {
+        visit(*master, deps, pinned, seen, order);
+    }
+    order.push(hash);
+}
+
+/// The mods along with all of their masters.
+fn closure(
+    deps: &BTreeMap<usize, Vec<usize>>,
+    mods: impl IntoIterator<Item = usize>,
+) -> BTreeSet<usize> {
+    let mut set = BTreeSet::new();
+    let mut stack: Vec<usize> = mods.into_iter().collect();
+    while let Some(hash) = stack.pop() {
+        if set.insert(hash) {
+            stack.extend(deps.get(&hash).into_iter().flatten().copied());
+        }
+    }
+    set
+}
+
+/// A bisect of the mods in a profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Whether each mod was enabled when the bisect started.
+    pub original: Vec<(usize, bool)>,
+    pub bisect:   Bisect,
+}
+
+impl Session {
+    /// Starts a bisect of the enabled mods in the current profile.
+    pub fn new(core: &Manager, pinned: impl IntoIterator<Item = usize>) -> Self {
+        let mods: Vec<_> = core.mod_manager().all_mods().collect();
+        let deps = mods
+            .iter()
+            .map(|m| (m.hash(), m.meta.masters.keys().copied().collect()))
+            .collect();
+        Self {
+            original: mods.iter().map(|m| (m.hash(), m.enabled)).collect(),
+            bisect:   Bisect::new(
+                mods.iter().filter(|m| m.enabled).map(|m| m.hash()),
+                pinned,
+                deps,
+            ),
+        }
+    }
+
+    pub fn path(settings: &Settings) -> std::path::PathBuf {
+        settings.profile_dir().join(FILE_NAME)
+    }
+
+    /// The bisect in progress for the current profile, if there is one.
+    pub fn load(settings: &Settings) -> Option<Self> {
+        let path = Self::path(settings);
+        let text = fs::read_to_string(&path).ok()?;
+        serde_json::from_str(&text)
+            .inspect_err(|e| log::warn!("Ignoring unreadable bisect state: {e}"))
+            .ok()
+    }
+
+    pub fn save(&self, settings: &Settings) -> Result<()> {
+        fs::write(Self::path(settings), serde_json::to_string(self)?)
+            .context("Failed to save bisect state")?;
+        Ok(())
+    }
+
+    /// Enables the mods for the next test, or does nothing if the bisect is
+    /// done.
+    pub fn apply_step(&self, core: &Manager) -> Result<()> {
+        if let Step::Test(set) = self.bisect.step() {
+            log::info!("Bisect: testing with {} mods enabled", set.len());
+            set_enabled(core, |hash| {
+                self.original
+                    .iter()
+                    .any(|(h, _)| *h == hash)
+                    .then(|| set.contains(&hash))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Ends the bisect, putting every mod back as it was, except that any in
+    /// `disable` are left disabled.
+    pub fn finish(self, core: &Manager, disable: &[usize]) -> Result<()> {
+        log::info!("Ending bisect");
+        let original: BTreeMap<usize, bool> = self.original.into_iter().collect();
+        set_enabled(core, |hash| {
+            original
+                .get(&hash)
+                .map(|enabled| *enabled && !disable.contains(&hash))
+        })?;
+        let path = Self::path(&core.settings());
+        if path.exists() {
+            fs::remove_file(path).context("Failed to remove bisect state")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets which mods in the current profile are enabled, and applies the
+/// change by remerging only the files of mods which were toggled. Mods for
+/// which `enabled` returns `None`, such as those added since the bisect
+/// began, are left alone.
+fn set_enabled(core: &Manager, enabled: impl Fn(usize) -> Option<bool>) -> Result<()> {
+    let mod_manager = core.mod_manager();
+    let changes: Vec<_> = mod_manager
+        .all_mods()
+        .filter_map(|m| {
+            enabled(m.hash())
+                .filter(|enable| *enable != m.enabled)
+                .map(|enable| (m.hash(), enable))
+        })
+        .collect();
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut dirty = Manifest::default();
+    for (hash, enable) in changes {
+        dirty.extend(mod_manager.set_enabled(hash, enable, None)?.as_ref());
+    }
+    mod_manager
+        .save()
+        .context("Failed to save mod configuration for current profile")?;
+    drop(mod_manager);
+    core.deploy_manager()
+        .apply(Some(dirty))
+        .context("Failed to apply bisect changes")?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// Runs a bisect to the end, crashing whenever `crashes` says so.
+    fn run(mut bisect: Bisect, crashes: impl Fn(&[usize]) -> bool) -> (Outcome, usize) {
+        loop {
+            match bisect.step() {
+                Step::Test(set) => bisect.report(crashes(&set)),
+                Step::Done(outcome) => return (outcome, bisect.history().len()),
+            }
+        }
+    }
+
+    fn bisect(count: usize) -> Bisect {
+        Bisect::new(0..count, [], Default::default())
+    }
+
+    #[test]
+    fn single() {
+        for culprit in 0..13 {
+            let (outcome, tests) = run(bisect(13), |set| set.contains(&culprit));
+            assert_eq!(outcome, Outcome::Single(culprit));
+            assert!(tests <= 8);
+        }
+        assert_eq!(run(bisect(1), |_| true), (Outcome::Single(0), 0));
+        assert_eq!(run(bisect(0), |_| true).0, Outcome::Inconclusive(vec![]));
+    }
+
+    #[test]
+    fn pair() {
+        let (outcome, _) = run(bisect(16), |set| set.contains(&3) && set.contains(&12));
+        assert_eq!(outcome, Outcome::Pair(3, 12));
+        let (outcome, _) = run(bisect(5), |set| set.contains(&0) && set.contains(&1));
+        assert_eq!(outcome, Outcome::Pair(0, 1));
+    }
+
+    #[test]
+    fn inconclusive() {
+        let (outcome, _) = run(bisect(16), |set| {
+            [1, 2, 5, 6].iter().all(|hash| set.contains(hash))
+        });
+        let Outcome::Inconclusive(mut culprits) = outcome else {
+            panic!("Expected an inconclusive bisect")
+        };
+        culprits.sort();
+        assert!([1, 2, 5, 6].iter().all(|hash| culprits.contains(hash)));
+        assert!(culprits.len() < 16);
+    }
+
+    #[test]
+    fn pinned() {
+        let bisect = Bisect::new(0..8, [2, 9], Default::default());
+        assert_eq!(bisect.pinned(), [2]);
+        assert!(!bisect.suspects().contains(&2));
+        let Step::Test(set) = bisect.step() else {
+            panic!("Expected a test")
+        };
+        assert!(set.contains(&2));
+        let (outcome, _) = run(bisect, |set| set.contains(&5));
+        assert_eq!(outcome, Outcome::Single(5));
+    }
+
+    #[test]
+    fn dependencies() {
+        // 6 needs 1, and 7 needs 6
+        let deps: BTreeMap<usize, Vec<usize>> =
+            [(6, vec![1]), (7, vec![6, 20])].into_iter().collect();
+        let bisect = Bisect::new(0..8, [], deps.clone());
+        let order = bisect.suspects();
+        let pos = |hash| order.iter().position(|h| *h == hash).unwrap();
+        assert!(pos(1) < pos(6) && pos(6) < pos(7));
+        let mut tested = bisect.clone();
+        while let Step::Test(set) = tested.step() {
+            for (hash, masters) in deps.iter() {
+                if set.contains(hash) {
+                    assert!(masters.iter().filter(|m| **m < 8).all(|m| set.contains(m)));
+                }
+            }
+            tested.report(set.contains(&1));
+        }
+        // A master which crashes is blamed rather than the mods needing it
+        assert_eq!(
+            run(bisect.clone(), |set| set.contains(&1)).0,
+            Outcome::Single(1)
+        );
+        assert_eq!(run(bisect, |set| set.contains(&7)).0, Outcome::Single(7));
+        // Pinning a mod pins its masters
+        let bisect = Bisect::new(0..8, [7], deps);
+        assert_eq!(bisect.pinned(), [1, 6, 7]);
+    }
+
+    #[test]
+    fn serde() {
+        let mut bisect = bisect(10);
+        bisect.report(false);
+        let session = Session {
+            original: (0..12).map(|h| (h, h < 10)).collect(),
+            bisect,
+        };
+        let json = serde_json::to_string(&session).unwrap();
+        let mut de: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(de, session);
+        de.bisect.report(true);
+        assert_eq!(de.bisect.history(), [(5, false), (5, true)]);
+    }
+}
@@ -15,7 +15,7 @@ use roead::{
 };
 use rustc_hash::FxHashMap;
 use uk_content::{constants::Language, resource::ResourceData};
-use uk_mod::pack::ModPacker;
+use uk_mod::{pack::ModPacker, progress::Tracker};
 use uk_reader::ResourceReader;
 use uk_util::PathExt;
 
@@ -40,6 +40,50 @@ mod texts;
 
 type AampDiffMap = FxHashMap<String, AampDiffEntry>;
 
+const EXTRACTING: &str = "Extracting";
+const LOGS: &str = "Processing logs";
+const MAPS: &str = "Merging maps";
+const TEXTS: &str = "Merging texts";
+const PACKING: &str = "Packing";
+const FINALIZING: &str = "Finalizing";
+/// The stages of converting a BNP, weighted by roughly how long each takes.
+/// Extracting and packing count bytes, the rest count logs handled.
+const STAGES: &[(&str, f64)] = &[
+    (EXTRACTING, 0.2),
+    (LOGS, 0.15),
+    (MAPS, 0.1),
+    (TEXTS, 0.05),
+    (PACKING, 0.45),
+    (FINALIZING, 0.05),
+];
+
+type Handler = fn(&BnpConverter) -> Result<()>;
+/// The log handlers for each root, with the name of the log for errors and
+/// the stage they count towards. Rebuilding the packs afterwards counts as
+/// one more log.
+const HANDLERS: &[(Handler, &str, &str)] = &[
+    (BnpConverter::handle_actorinfo, "actor info", LOGS),
+    (BnpConverter::handle_aslist, "AS list", LOGS),
+    (BnpConverter::handle_areadata, "areadata", LOGS),
+    (BnpConverter::handle_deepmerge, "deepmerge", LOGS),
+    (BnpConverter::handle_drops, "drops", LOGS),
+    (BnpConverter::handle_dungeon_static, "dungeon static", LOGS),
+    (BnpConverter::handle_events, "eventinfo", LOGS),
+    (BnpConverter::handle_gamedata, "gamedata", LOGS),
+    (
+        BnpConverter::handle_mainfield_static,
+        "mainfield static",
+        LOGS,
+    ),
+    (BnpConverter::handle_maps, "maps", MAPS),
+    (BnpConverter::handle_quests, "quests", LOGS),
+    (BnpConverter::handle_residents, "residents", LOGS),
+    (BnpConverter::handle_savedata, "savedata", LOGS),
+    (BnpConverter::handle_shops, "shops", LOGS),
+    (BnpConverter::handle_effects, "status effect", LOGS),
+    (BnpConverter::handle_texts, "texts", TEXTS),
+];
+
 pub enum AampDiffEntry {
     Sarc(AampDiffMap),
     Aamp(ParameterList),
@@ -107,6 +151,7 @@ struct BnpConverter {
     packs: Arc<DashSet<PathBuf>>,
     parent_packs: DashSet<PathBuf>,
     opt_master_cache: Arc<DashMap<PathBuf, Vec<u8>>>,
+    progress: Arc<Tracker>,
 }
 
 impl BnpConverter {
@@ -393,34 +438,10 @@ impl BnpConverter {
             }
         };
 
-        self.handle_actorinfo()
-            .context("Failed to process actor info log")?;
-        self.handle_aslist()
-            .context("Failed to process AS list log")?;
-        self.handle_areadata()
-            .context("Failed to process areadata log")?;
-        self.handle_deepmerge()
-            .context("Failed to process deepmerge log")?;
-        self.handle_drops().context("Failed to process drops log")?;
-        self.handle_dungeon_static()
-            .context("Failed to process dungeon static log")?;
-        self.handle_events()
-            .context("Failed to process eventinfo log")?;
-        self.handle_gamedata()
-            .context("Failed to process gamedata log")?;
-        self.handle_mainfield_static()
-            .context("Failed to process mainfield static log")?;
-        self.handle_maps().context("Failed to process maps log")?;
-        self.handle_quests()
-            .context("Failed to process quests log")?;
-        self.handle_residents()
-            .context("Failed to process residents log")?;
-        self.handle_savedata()
-            .context("Failed to process savedata log")?;
-        self.handle_shops().context("Failed to process shops log")?;
-        self.handle_effects()
-            .context("Failed to process status effect log")?;
-        self.handle_texts().context("Failed to process texts log")?;
+        for (handler, log, stage) in HANDLERS {
+            handler(self).with_context(|| format!("Failed to process {log} log"))?;
+            self.progress.advance(stage, 1);
+        }
 
         let packs = DashSet::clone(&self.packs);
         self.packs.clear();
@@ -449,43 +470,60 @@ impl BnpConverter {
                 fs::write(file, data)?;
                 Ok(())
             })?;
+        self.progress.advance(LOGS, 1);
         Ok(())
     }
 
     fn convert(mut self) -> Result<PathBuf> {
         let root = self.current_root.clone();
-        self.convert_root()?;
-
         let opt_dir = root.join("options");
-        if opt_dir.exists() {
-            for option in fs::read_dir(opt_dir)?.filter_map(|r| {
-                r.ok().and_then(|r| {
-                    let path = r.path();
-                    path.is_dir().then_some(path)
+        let options = if opt_dir.exists() {
+            fs::read_dir(opt_dir)?
+                .filter_map(|r| {
+                    r.ok().and_then(|r| {
+                        let path = r.path();
+                        path.is_dir().then_some(path)
+                    })
                 })
-            }) {
-                log::info!(
-                    "Processing BNP logs for option {}",
-                    option
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or_default()
-                );
-                self.current_root = option;
-                self.convert_root()?;
-            }
+                .collect()
+        } else {
+            vec![]
+        };
+        let roots = options.len() as u64 + 1;
+        for (_, _, stage) in HANDLERS {
+            self.progress.add_total(stage, roots);
+        }
+        self.progress.add_total(LOGS, roots);
+
+        self.convert_root()?;
+        for option in options {
+            log::info!(
+                "Processing BNP logs for option {}",
+                option
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or_default()
+            );
+            self.current_root = option;
+            self.convert_root()?;
         }
         Ok(root)
     }
 }
 
-pub fn unpack_bnp(core: &crate::core::Manager, path: &Path) -> Result<PathBuf> {
+pub fn unpack_bnp(
+    core: &crate::core::Manager,
+    path: &Path,
+    progress: Arc<Tracker>,
+) -> Result<PathBuf> {
     let tempdir = crate::util::get_temp_folder();
     if path.is_dir() {
         dircpy::copy_dir(path, tempdir.as_path()).context("Failed to copy files to temp folder")?;
+        progress.complete(EXTRACTING);
     } else {
         log::info!("Extracting BNP…");
-        extract_7z(path, &tempdir).context("Failed to extract BNP")?;
+        extract_7z(path, &tempdir, Some((&progress, EXTRACTING)))
+            .context("Failed to extract BNP")?;
     }
     if tempdir.join("rules.txt").exists() && !tempdir.join("info.json").exists() {
         old::Bnp2xConverter::new(&tempdir)
@@ -512,6 +550,7 @@ pub fn unpack_bnp(core: &crate::core::Manager, path: &Path) -> Result<PathBuf> {
         current_root: tempdir.clone(),
         path: tempdir.clone(),
         opt_master_cache: Default::default(),
+        progress,
     };
     let path = converter.convert()?;
     log::info!("BNP unpacked");
@@ -519,7 +558,8 @@ pub fn unpack_bnp(core: &crate::core::Manager, path: &Path) -> Result<PathBuf> {
 }
 
 pub fn convert_bnp(core: &crate::core::Manager, path: &Path) -> Result<PathBuf> {
-    let tempdir = unpack_bnp(core, path).with_context(|| {
+    let progress = Arc::new(Tracker::new(STAGES));
+    let tempdir = unpack_bnp(core, path, progress.clone()).with_context(|| {
         format!(
             "Failed to unpack {}",
             path.file_name()
@@ -534,13 +574,26 @@ pub fn convert_bnp(core: &crate::core::Manager, path: &Path) -> Result<PathBuf>
         ModPacker::parse_rules(tempdir.join("rules.txt")).context("Failed to parse BNP metadata")?
     };
     let name = meta.name.clone();
-    let new_mod = ModPacker::new(tempdir, tempfile.as_path(), Some(meta), vec![
-        core.settings()
-            .dump()
-            .context("No dump for current platform")?,
-    ])
-    .with_context(|| format!("Failed to package converted BNP for mod {}", name))?;
-    new_mod.pack()
+    progress.set_total(
+        PACKING,
+        jwalk::WalkDir::new(&tempdir)
+            .into_iter()
+            .filter_map(|e| e.ok().and_then(|e| e.metadata().ok()))
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+            .sum(),
+    );
+    let dump = core
+        .settings()
+        .dump()
+        .context("No dump for current platform")?;
+    let new_mod = ModPacker::new(tempdir, tempfile.as_path(), Some(meta), vec![dump])
+        .with_context(|| format!("Failed to package converted BNP for mod {}", name))?
+        .with_progress(progress.clone(), PACKING);
+    let path = new_mod.pack()?;
+    progress.complete(PACKING);
+    progress.complete(FINALIZING);
+    Ok(path)
 }
 
 #[cfg(test)]
@@ -550,5 +603,10 @@ fn test_convert() {
     let path = dirs2::download_dir()
         .unwrap()
         .join("clearcameraui_nodetection.bnp"); // join("rebalance.bnp"); //("SecondWindv1.9.13.bnp");
-    unpack_bnp(&super::core::Manager::init().unwrap(), path.as_ref()).unwrap();
+    unpack_bnp(
+        &super::core::Manager::init().unwrap(),
+        path.as_ref(),
+        Arc::new(Tracker::new(STAGES)),
+    )
+    .unwrap();
 }
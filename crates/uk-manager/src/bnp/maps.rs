@@ -127,3 +127,89 @@ impl BnpConverter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use uk_content::{constants::Language, prelude::Endian};
+    use uk_mod::progress::Tracker;
+    use uk_reader::{MemoryROMSource, ResourceReader};
+
+    use super::*;
+    use crate::{bnp::STAGES, settings::Platform};
+
+    #[test]
+    fn handle_maps() {
+        let tmp = tempfile::tempdir().unwrap();
+        let map = "Map/MainField/D-3/D-3_Dynamic.smubin";
+        let stock = fs::read(Path::new("../uk-content/test").join(map)).unwrap();
+        let hashes = |data: &[u8]| -> Vec<u32> {
+            let byml = Byml::from_binary(decompress(data).unwrap()).unwrap();
+            byml.as_map()
+                .unwrap()
+                .get("Objs")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|obj| {
+                    obj.as_map()
+                        .unwrap()
+                        .get("HashId")
+                        .unwrap()
+                        .as_int()
+                        .unwrap()
+                })
+                .collect()
+        };
+        let stock_hashes = hashes(&stock);
+        let deleted = stock_hashes[0];
+        let diff = Byml::Map(
+            [(
+                "D-3_Dynamic".into(),
+                Byml::Map(
+                    [(
+                        "Objs".into(),
+                        Byml::Map(
+                            [("del".into(), Byml::Array(vec![Byml::U32(deleted)]))]
+                                .into_iter()
+                                .collect(),
+                        ),
+                    )]
+                    .into_iter()
+                    .collect(),
+                ),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        fs::create_dir_all(tmp.path().join("logs")).unwrap();
+        fs::write(tmp.path().join("logs/map.yml"), diff.to_text()).unwrap();
+
+        let (content, aoc) = uk_content::platform_prefixes(Endian::Big);
+        let dump = MemoryROMSource::new(Endian::Big)
+            .with_pack("aoc/0010/Pack/AocMainField.pack", [(map, stock.as_slice())]);
+        let converter = BnpConverter {
+            dump: Arc::new(ResourceReader::from_memory(dump)),
+            game_lang: Language::USen,
+            platform: Platform::WiiU,
+            path: tmp.path().to_path_buf(),
+            current_root: tmp.path().to_path_buf(),
+            content,
+            aoc,
+            packs: Default::default(),
+            parent_packs: Default::default(),
+            opt_master_cache: Default::default(),
+            progress: Arc::new(Tracker::new(STAGES)),
+        };
+        converter.handle_maps().unwrap();
+
+        let merged = hashes(&fs::read(tmp.path().join(aoc).join(map)).unwrap());
+        assert_eq!(merged.len(), stock_hashes.len() - 1);
+        assert!(!merged.contains(&deleted));
+        let pack = fs::read(tmp.path().join(aoc).join("Pack/AocMainField.pack")).unwrap();
+        assert!(Sarc::new(pack).unwrap().get(map).is_some());
+    }
+}
@@ -0,0 +1,441 @@
+//! Comparisons of UKMM's merged output with another merged folder, such as
+//! the master mod BCML made, for confidence that a migrated setup gives the
+//! same game. Files are compared by their bytes first. Those which differ are
+//! decompressed, and AAMP and BYML documents are parsed and compared by what
+//! they hold, so differences which mean nothing to the game, like the order
+//! of hash maps or the byte order, do not count. SARCs are compared file by
+//! file, and files in them which differ are reported by their path in the
+//! pack after `//`.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use anyhow_ext::Result;
+use path_slash::PathExt;
+use rayon::prelude::*;
+use roead::{aamp::ParameterIO, byml::Byml, sarc::Sarc, yaz0};
+use serde::{Deserialize, Serialize};
+
+use crate::diffs::{hex, yaml::sort_value};
+
+/// Bytes of context shown around each change to an opaque file.
+const CONTEXT: usize = 8;
+/// Most lines of each side shown where parsed documents differ.
+const MAX_LINES: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// The same bytes.
+    Identical,
+    /// Different bytes holding the same data.
+    Equivalent,
+    /// Different data, with a preview of what differs.
+    Different(String),
+    OnlyOurs,
+    OnlyTheirs,
+}
+
+impl Outcome {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Outcome::Identical => "Identical",
+            Outcome::Equivalent => "Equivalent",
+            Outcome::Different(_) => "Different",
+            Outcome::OnlyOurs => "Only in UKMM",
+            Outcome::OnlyTheirs => "Only in other",
+        }
+    }
+
+    /// Whether the game would see the same file on both sides.
+    pub fn is_same(&self) -> bool {
+        matches!(self, Outcome::Identical | Outcome::Equivalent)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The path relative to the compared folders, with `//` before the path
+    /// of a file in a pack.
+    pub path:    String,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub ours:    PathBuf,
+    pub theirs:  PathBuf,
+    /// Every file in either folder, followed by the files in it which are
+    /// not the same if it is a pack.
+    pub entries: Vec<Entry>,
+}
+
+impl Report {
+    /// How many files in the folders, not counting those in packs, had each
+    /// outcome.
+    pub fn counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in self.entries.iter().filter(|e| !e.path.contains("//")) {
+            *counts.entry(entry.outcome.label()).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# Merge Comparison\n\nUKMM: `{}`\n\nOther: `{}`\n\n",
+            self.ours.display(),
+            self.theirs.display()
+        );
+        for (label, count) in self.counts() {
+            let _ = writeln!(out, "- {label}: {count}");
+        }
+        for entry in self.entries.iter().filter(|e| !e.outcome.is_same()) {
+            let _ = writeln!(out, "\n## {} ({})\n", entry.path, entry.outcome.label());
+            if let Outcome::Different(preview) = &entry.outcome {
+                let _ = writeln!(out, "```\n{}\n```", preview.trim_end());
+            }
+        }
+        out
+    }
+}
+
+/// YAML of a document with its maps sorted, so equal parts line up.
+fn sorted_text(text: String) -> String {
+    serde_yaml::from_str(&text)
+        .ok()
+        .and_then(|value| serde_yaml::to_string(&sort_value(value)).ok())
+        .unwrap_or(text)
+}
+
+/// Shows the lines where two texts differ, with a few lines around them.
+fn text_diff(ours: &str, theirs: &str) -> String {
+    let ours: Vec<&str> = ours.lines().collect();
+    let theirs: Vec<&str> = theirs.lines().collect();
+    let start = ours.iter().zip(&theirs).take_while(|(a, b)| a == b).count();
+    let end = ours[start..]
+        .iter()
+        .rev()
+        .zip(theirs[start..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut out = String::new();
+    for line in &ours[start.saturating_sub(3)..start] {
+        let _ = writeln!(out, "  {line}");
+    }
+    for (prefix, lines) in [
+        ('-', &theirs[start..theirs.len() - end]),
+        ('+', &ours[start..ours.len() - end]),
+    ] {
+        for line in lines.iter().take(MAX_LINES) {
+            let _ = writeln!(out, "{prefix} {line}");
+        }
+        if lines.len() > MAX_LINES {
+            let _ = writeln!(out, "{prefix} … {} more lines", lines.len() - MAX_LINES);
+        }
+    }
+    out
+}
+
+/// Compares the files in two versions of a pack. Files which are not the
+/// same are added to `nested`, each followed by what differs inside it.
+fn compare_sarcs(path: &str, ours: &Sarc, theirs: &Sarc, nested: &mut Vec<Entry>) -> Outcome {
+    let files = |sarc: &Sarc<'_>| -> BTreeMap<String, Vec<u8>> {
+        sarc.files()
+            .filter_map(|file| Some((file.name?.to_string(), file.data.to_vec())))
+            .collect()
+    };
+    let (ours, theirs) = (files(ours), files(theirs));
+    let names: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+    let mut differ = 0;
+    for name in &names {
+        let path = format!("{path}//{name}");
+        let mut inner = vec![];
+        let outcome = match (ours.get(*name), theirs.get(*name)) {
+            (Some(ours), Some(theirs)) => compare_data(&path, ours, theirs, &mut inner),
+            (Some(_), None) => Outcome::OnlyOurs,
+            _ => Outcome::OnlyTheirs,
+        };
+        if !outcome.is_same() {
+            differ += 1;
+            nested.push(Entry { path, outcome });
+            nested.extend(inner);
+        }
+    }
+    if differ == 0 {
+        Outcome::Equivalent
+    } else {
+        Outcome::Different(format!(
+            "{differ} of {} files in the pack differ",
+            names.len()
+        ))
+    }
+}
+
+/// Compares two versions of a file. If they are packs, the files in them
+/// which are not the same are added to `nested`.
+fn compare_data(path: &str, ours: &[u8], theirs: &[u8], nested: &mut Vec<Entry>) -> Outcome {
+    if ours == theirs {
+        return Outcome::Identical;
+    }
+    let (ours, theirs) = (yaz0::decompress_if(ours), yaz0::decompress_if(theirs));
+    if ours == theirs {
+        return Outcome::Equivalent;
+    }
+    let is = |magic: &[u8]| ours.starts_with(magic) && theirs.starts_with(magic);
+    let parsed = if is(b"SARC") {
+        match (Sarc::new(&ours[..]), Sarc::new(&theirs[..])) {
+            (Ok(ours), Ok(theirs)) => Some(compare_sarcs(path, &ours, &theirs, nested)),
+            _ => None,
+        }
+    } else if is(b"AAMP") {
+        match (
+            ParameterIO::from_binary(&ours[..]),
+            ParameterIO::from_binary(&theirs[..]),
+        ) {
+            (Ok(ours), Ok(theirs)) if ours == theirs => Some(Outcome::Equivalent),
+            (Ok(ours), Ok(theirs)) => {
+                Some(Outcome::Different(text_diff(
+                    &sorted_text(ours.to_text()),
+                    &sorted_text(theirs.to_text()),
+                )))
+            }
+            _ => None,
+        }
+    } else if is(b"BY") || is(b"YB") {
+        match (Byml::from_binary(&ours[..]), Byml::from_binary(&theirs[..])) {
+            (Ok(ours), Ok(theirs)) if ours == theirs => Some(Outcome::Equivalent),
+            (Ok(ours), Ok(theirs)) => {
+                Some(Outcome::Different(text_diff(
+                    &sorted_text(ours.to_text()),
+                    &sorted_text(theirs.to_text()),
+                )))
+            }
+            _ => None,
+        }
+    } else {
+        None
+    };
+    parsed.unwrap_or_else(|| Outcome::Different(hex::summarize(&theirs, &ours, CONTEXT)))
+}
+
+fn list_files(root: &Path) -> BTreeSet<String> {
+    jwalk::WalkDir::new(root)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| Some(e.path().strip_prefix(root).ok()?.to_slash_lossy().into()))
+        .collect()
+}
+
+/// Compares UKMM's merged folder with another, file by file. Both should be
+/// the folder holding the content and DLC folders. Progress is logged as it
+/// goes, and setting `cancel` stops the comparison.
+pub fn compare(ours: &Path, theirs: &Path, cancel: &AtomicBool) -> Result<Report> {
+    let files: Vec<String> = list_files(ours)
+        .into_iter()
+        .chain(list_files(theirs))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let done = AtomicUsize::new(0);
+    let entries = files
+        .par_iter()
+        .map(|path| -> Result<Vec<Entry>> {
+            if cancel.load(Ordering::Relaxed) {
+                anyhow_ext::bail!("Comparison cancelled");
+            }
+            let (ours, theirs) = (ours.join(path), theirs.join(path));
+            let mut nested = vec![];
+            let outcome = match (ours.is_file(), theirs.is_file()) {
+                (true, true) => {
+                    compare_data(
+                        path,
+                        &fs_err::read(ours)?,
+                        &fs_err::read(theirs)?,
+                        &mut nested,
+                    )
+                }
+                (true, false) => Outcome::OnlyOurs,
+                _ => Outcome::OnlyTheirs,
+            };
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if done * 100 / files.len() > (done - 1) * 100 / files.len() {
+                log::trace!(
+                    "PROGRESSComparing {} files: {}%",
+                    files.len(),
+                    done * 100 / files.len()
+                );
+            }
+            nested.insert(0, Entry {
+                path: path.clone(),
+                outcome,
+            });
+            Ok(nested)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Report {
+        ours:    ours.to_path_buf(),
+        theirs:  theirs.to_path_buf(),
+        entries: entries.into_iter().flatten().collect(),
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use roead::{
+        aamp::{Parameter, ParameterObject},
+        byml::map,
+        sarc::SarcWriter,
+        Endian,
+    };
+
+    use super::*;
+
+    fn aamp(order: &[(&str, f32)]) -> Vec<u8> {
+        let mut pio = ParameterIO::new();
+        for (name, value) in order {
+            pio = pio.with_object(
+                *name,
+                ParameterObject::new().with_parameter("Value", Parameter::F32(*value)),
+            );
+        }
+        pio.to_binary()
+    }
+
+    fn byml(life: i32) -> Byml {
+        map!(
+            "Name" => Byml::String("Test".into()),
+            "Life" => Byml::I32(life),
+            "Tags" => Byml::Array(vec![Byml::String("A".into()), Byml::String("B".into())])
+        )
+    }
+
+    fn sarc(files: &[(&str, Vec<u8>)], endian: Endian) -> Vec<u8> {
+        let mut sarc = SarcWriter::new(endian);
+        for (name, data) in files {
+            sarc.add_file(*name, data.clone());
+        }
+        sarc.to_binary()
+    }
+
+    fn outcome(ours: &[u8], theirs: &[u8]) -> (Outcome, Vec<Entry>) {
+        let mut nested = vec![];
+        let outcome = compare_data("Test", ours, theirs, &mut nested);
+        (outcome, nested)
+    }
+
+    #[test]
+    fn aamp_reordered() {
+        let ours = aamp(&[("A", 1.0), ("B", 2.0)]);
+        let theirs = aamp(&[("B", 2.0), ("A", 1.0)]);
+        assert_ne!(ours, theirs);
+        assert_eq!(outcome(&ours, &theirs).0, Outcome::Equivalent);
+        let Outcome::Different(preview) = outcome(&ours, &aamp(&[("A", 1.0), ("B", 3.0)])).0 else {
+            panic!("Changed value should differ");
+        };
+        assert!(
+            preview.contains("- ") && preview.contains("+ "),
+            "{preview}"
+        );
+        assert!(preview.contains('3'), "{preview}");
+    }
+
+    #[test]
+    fn byml_endian() {
+        let ours = byml(10).to_binary(Endian::Big);
+        let theirs = roead::yaz0::compress(byml(10).to_binary(Endian::Little));
+        assert_eq!(outcome(&ours, &ours).0, Outcome::Identical);
+        assert_eq!(outcome(&ours, &theirs).0, Outcome::Equivalent);
+        let Outcome::Different(preview) = outcome(&ours, &byml(20).to_binary(Endian::Big)).0 else {
+            panic!("Changed value should differ");
+        };
+        assert!(
+            preview.contains("20") && preview.contains("10"),
+            "{preview}"
+        );
+        assert!(!preview.contains("Tags"), "{preview}");
+    }
+
+    #[test]
+    fn sarc_descent() {
+        let inner = sarc(&[("Inner.bxml", aamp(&[("A", 1.0)]))], Endian::Big);
+        let files = [
+            ("Actor/Test.byml", byml(10).to_binary(Endian::Big)),
+            ("Pack/Inner.sarc", inner.clone()),
+            ("Raw.bin", vec![1, 2, 3]),
+        ];
+        let ours = sarc(&files, Endian::Big);
+        // The same files in a different order and byte order, with the
+        // nested pack compressed
+        let mut reordered = files.to_vec();
+        reordered.reverse();
+        reordered[1].1 = roead::yaz0::compress(&inner);
+        reordered[2].1 = byml(10).to_binary(Endian::Little);
+        let theirs = sarc(&reordered, Endian::Little);
+        let (result, nested) = outcome(&ours, &theirs);
+        assert_eq!(result, Outcome::Equivalent);
+        assert!(nested.is_empty());
+
+        let changed = sarc(
+            &[
+                ("Actor/Test.byml", byml(10).to_binary(Endian::Big)),
+                (
+                    "Pack/Inner.sarc",
+                    sarc(&[("Inner.bxml", aamp(&[("A", 2.0)]))], Endian::Big),
+                ),
+                ("Extra.bin", vec![0]),
+            ],
+            Endian::Big,
+        );
+        let (result, nested) = outcome(&changed, &ours);
+        assert!(matches!(result, Outcome::Different(_)));
+        let found: Vec<(&str, &str)> = nested
+            .iter()
+            .map(|e| (e.path.as_str(), e.outcome.label()))
+            .collect();
+        assert_eq!(found, vec![
+            ("Test//Extra.bin", "Only in UKMM"),
+            ("Test//Pack/Inner.sarc", "Different"),
+            ("Test//Pack/Inner.sarc//Inner.bxml", "Different"),
+            ("Test//Raw.bin", "Only in other"),
+        ]);
+    }
+
+    #[test]
+    fn folders() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (ours, theirs) = (tmp.path().join("ours"), tmp.path().join("theirs"));
+        let write = |root: &Path, path: &str, data: &[u8]| {
+            let path = root.join(path);
+            fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+            fs_err::write(path, data).unwrap();
+        };
+        write(&ours, "content/Same.bin", b"same");
+        write(&theirs, "content/Same.bin", b"same");
+        write(&ours, "content/Ours.bin", b"ours");
+        write(&theirs, "aoc/0010/Theirs.bin", b"theirs");
+        write(&ours, "content/Changed.bin", b"abcd");
+        write(&theirs, "content/Changed.bin", b"abed");
+        let report = compare(&ours, &theirs, &AtomicBool::new(false)).unwrap();
+        let found: Vec<(&str, &str)> = report
+            .entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.outcome.label()))
+            .collect();
+        assert_eq!(found, vec![
+            ("aoc/0010/Theirs.bin", "Only in other"),
+            ("content/Changed.bin", "Different"),
+            ("content/Ours.bin", "Only in UKMM"),
+            ("content/Same.bin", "Identical"),
+        ]);
+        assert_eq!(report.counts().get("Identical"), Some(&1));
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("## content/Changed.bin (Different)"));
+        assert!(!markdown.contains("## content/Same.bin"));
+        assert!(compare(&ours, &theirs, &AtomicBool::new(true)).is_err());
+    }
+}
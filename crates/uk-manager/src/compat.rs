@@ -0,0 +1,445 @@
+//! Reports of whether two mods are likely to work together, to check before
+//! installing one next to another. Every file both mods change, including
+//! files nested in packs, is classified by how the two versions would
+//! combine: diffs of mergeable resources merge, but a file stored whole
+//! replaces the other mod's version, so whichever mod has priority wins.
+//!
+//! Where both mods change a mergeable resource, their diffs can also be
+//! compared key by key, to tell apart mods which edit different parts of a
+//! file from those which set the same values, where priority still decides.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    ops::Bound,
+};
+
+use anyhow_ext::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use uk_content::resource::{MergeableResource, ResourceData};
+use uk_mod::unpack::ModReader;
+
+use crate::{
+    diffs::yaml::sort_value,
+    summary::{self, Change},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    /// Both mods have the same version.
+    Identical,
+    /// Both mods' changes are merged, and were not compared any further.
+    Compatible,
+    /// Both mods' changes are merged, and they change different keys.
+    Independent,
+    /// Both mods' changes are merged, but some keys are set by both, listed
+    /// with ` / ` between levels. The mod with priority wins for those.
+    Overlapping(Vec<String>),
+    /// One mod replaces the file outright, so it only keeps the other's
+    /// changes if the other has priority.
+    Priority,
+    /// Both mods replace the file, so only one version can be used.
+    Conflict,
+}
+
+impl Verdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Verdict::Identical => "Identical",
+            Verdict::Compatible => "Likely compatible",
+            Verdict::Independent => "Verified independent",
+            Verdict::Overlapping(_) => "Overlapping edits",
+            Verdict::Priority => "Priority decides",
+            Verdict::Conflict => "Hard conflict",
+        }
+    }
+
+    /// Whether both mods' changes survive together.
+    pub fn is_fine(&self) -> bool {
+        matches!(
+            self,
+            Verdict::Identical | Verdict::Compatible | Verdict::Independent
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The file, with `//` before the path of a file in a pack.
+    pub path:    String,
+    pub verdict: Verdict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub first:   String,
+    pub second:  String,
+    /// Whether merged resources were compared key by key.
+    pub deep:    bool,
+    /// Every file both mods change, apart from packs whose files are listed
+    /// themselves.
+    pub entries: Vec<Entry>,
+}
+
+impl Report {
+    /// How many files had each verdict.
+    pub fn counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in &self.entries {
+            *counts.entry(entry.verdict.label()).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# Compatibility Report\n\nFirst: {}\n\nSecond: {}\n\n",
+            self.first, self.second
+        );
+        if self.entries.is_empty() {
+            out.push_str("The mods do not change any of the same files.\n");
+            return out;
+        }
+        for (label, count) in self.counts() {
+            let _ = writeln!(out, "- {label}: {count}");
+        }
+        for entry in &self.entries {
+            let _ = writeln!(out, "\n## {} ({})", entry.path, entry.verdict.label());
+            if let Verdict::Overlapping(keys) = &entry.verdict {
+                out.push('\n');
+                for key in keys {
+                    let _ = writeln!(out, "- `{key}`");
+                }
+            }
+        }
+        out
+    }
+}
+
+/// A key in a diff tree as it is shown.
+fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(key) => key.clone(),
+        Value::Number(key) => key.to_string(),
+        Value::Bool(key) => key.to_string(),
+        key => {
+            serde_yaml::to_string(key)
+                .unwrap_or_default()
+                .trim_end()
+                .into()
+        }
+    }
+}
+
+fn collect_leaves<'a>(
+    value: &'a Value,
+    path: &mut Vec<String>,
+    leaves: &mut BTreeMap<Vec<String>, &'a Value>,
+) {
+    match value {
+        Value::Mapping(map) if !map.is_empty() => {
+            for (key, value) in map {
+                path.push(key_name(key));
+                collect_leaves(value, path, leaves);
+                path.pop();
+            }
+        }
+        value => {
+            leaves.insert(path.clone(), value);
+        }
+    }
+}
+
+/// Every value a diff tree sets, by the keys leading to it. Mappings are
+/// followed down, and anything else is one value, including lists, which
+/// diffs do not key.
+fn leaves(value: &Value) -> BTreeMap<Vec<String>, &Value> {
+    let mut leaves = BTreeMap::new();
+    collect_leaves(value, &mut vec![], &mut leaves);
+    leaves
+}
+
+fn show_path(path: &[String]) -> String {
+    if path.is_empty() {
+        "(whole file)".into()
+    } else {
+        path.join(" / ")
+    }
+}
+
+/// The keys two diff trees both change, with ` / ` between levels. A key
+/// counts if both set it to different values, or if one sets it and the
+/// other changes something inside it. Keys both set to the same value do not
+/// count, as they merge the same either way.
+pub fn overlap(first: &Value, second: &Value) -> Vec<String> {
+    let (first, second) = (leaves(first), leaves(second));
+    let mut keys = BTreeSet::new();
+    for (path, value) in &first {
+        // The second sets this key, or one containing it
+        for len in 0..=path.len() {
+            if let Some(other) = second.get(&path[..len]) {
+                if len < path.len() || other != value {
+                    keys.insert(show_path(&path[..len]));
+                }
+            }
+        }
+        // The second sets keys inside this one, which sort right after it
+        let inside = second
+            .range::<[String], _>((Bound::Excluded(path.as_slice()), Bound::Unbounded))
+            .next()
+            .is_some_and(|(other, _)| other.starts_with(path));
+        if inside {
+            keys.insert(show_path(path));
+        }
+    }
+    keys.into_iter().collect()
+}
+
+/// A mergeable resource as a tree of keys, without the resource type.
+fn diff_tree(resource: &MergeableResource) -> Result<Value> {
+    let value = serde_yaml::to_value(resource).context("Failed to serialize resource")?;
+    Ok(match sort_value(value) {
+        Value::Mapping(map) if map.len() == 1 => {
+            map.into_iter()
+                .next()
+                .map(|(_, value)| value)
+                .unwrap_or_default()
+        }
+        value => value,
+    })
+}
+
+/// Collects the changes a mod makes to the files another mod also changes.
+fn shared_changes(
+    (name, reader): &(String, ModReader),
+    other: &ModReader,
+) -> Result<BTreeMap<String, Change>> {
+    let mut changes = BTreeMap::new();
+    let mut seen = BTreeSet::new();
+    for (files, other_files, aoc) in [
+        (
+            &reader.manifest.content_files,
+            &other.manifest.content_files,
+            false,
+        ),
+        (&reader.manifest.aoc_files, &other.manifest.aoc_files, true),
+    ] {
+        for file in files.intersection(other_files) {
+            summary::walk(reader, file.to_string(), file, aoc, &mut changes, &mut seen)
+                .with_context(|| format!("Failed to read {file} from {name}"))?;
+        }
+    }
+    Ok(changes)
+}
+
+/// Checks how two mods, each given with the name to list it by, would get
+/// along. If `deep` is set, resources both mods merge are compared key by
+/// key, which reads every shared diff in full.
+pub fn check(
+    first: &(String, ModReader),
+    second: &(String, ModReader),
+    deep: bool,
+) -> Result<Report> {
+    let ours = shared_changes(first, &second.1)?;
+    let theirs = shared_changes(second, &first.1)?;
+    let mut entries = vec![];
+    for (canon, ours) in &ours {
+        let Some(theirs) = theirs.get(canon) else {
+            continue;
+        };
+        let verdict = match (&ours.data, &theirs.data) {
+            // Packs merge by file, and their files are checked themselves
+            (ResourceData::Sarc(_), ResourceData::Sarc(_)) => continue,
+            (a, b) if a == b => Verdict::Identical,
+            (ResourceData::Binary(_), ResourceData::Binary(_)) => Verdict::Conflict,
+            (ResourceData::Mergeable(a), ResourceData::Mergeable(b)) if deep => {
+                let keys = overlap(&diff_tree(a)?, &diff_tree(b)?);
+                if keys.is_empty() {
+                    Verdict::Independent
+                } else {
+                    Verdict::Overlapping(keys)
+                }
+            }
+            (ResourceData::Mergeable(_), ResourceData::Mergeable(_)) => Verdict::Compatible,
+            _ => Verdict::Priority,
+        };
+        entries.push(Entry {
+            path: ours.path.clone(),
+            verdict,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    log::info!(
+        "Checked compatibility of {} and {}: {} shared files",
+        first.0,
+        second.0,
+        entries.len()
+    );
+    Ok(Report {
+        first: first.0.clone(),
+        second: second.0.clone(),
+        deep,
+        entries,
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::path::Path;
+
+    use uk_content::canonicalize;
+    use uk_mod::{pack::pack_resources, Manifest, Meta};
+
+    use super::*;
+
+    const PACK: &str = "Pack/Test.pack";
+    const BINARY: &str = "Test/Data.bin";
+    const STATUS: &str = "Ecosystem/StatusEffectList.sbyml";
+
+    fn tree(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn disjoint() {
+        let first = tree("Guardian: {HP: 3000}\nLynel: {Attack: 50}\n");
+        let second = tree("Guardian: {Attack: 80}\nMoblin: {HP: 1}\n");
+        assert!(overlap(&first, &second).is_empty());
+        assert!(overlap(&second, &first).is_empty());
+        // Setting the same value does not count
+        let same = tree("Guardian: {HP: 3000, Speed: [1, 2]}\n");
+        let also = tree("Guardian: {HP: 3000, Speed: [1, 2]}\nLynel: {HP: 1}\n");
+        assert!(overlap(&same, &also).is_empty());
+    }
+
+    #[test]
+    fn overlapping() {
+        let first = tree("Guardian: {HP: 3000, Attack: 80}\nLynel: {HP: 1}\n");
+        let second = tree("Guardian: {HP: 2000, Attack: 80}\nMoblin: {HP: 1}\n");
+        assert_eq!(overlap(&first, &second), ["Guardian / HP"]);
+        assert_eq!(overlap(&second, &first), ["Guardian / HP"]);
+        // Lists are compared whole
+        let first = tree("Drops: [Apple, Wood]\n");
+        let second = tree("Drops: [Apple]\n");
+        assert_eq!(overlap(&first, &second), ["Drops"]);
+        // Numeric keys are shown as numbers
+        let first = tree("1: {2: a}\n");
+        let second = tree("1: {2: b}\n");
+        assert_eq!(overlap(&first, &second), ["1 / 2"]);
+    }
+
+    #[test]
+    fn nested_overlap() {
+        // Replacing a key overlaps with any change inside it, from either side
+        let first = tree("Guardian: {Params: {HP: 3000}}\n");
+        let second = tree("Guardian: {Params: null}\n");
+        assert_eq!(overlap(&first, &second), ["Guardian / Params"]);
+        assert_eq!(overlap(&second, &first), ["Guardian / Params"]);
+        let first = tree("Guardian: {Params: {HP: 3000}, Name: x}\n");
+        let second = tree("Guardian: {Params: {Extra: {Depth: 1}}}\n");
+        assert!(overlap(&first, &second).is_empty());
+        assert_eq!(overlap(&tree("5\n"), &first), ["(whole file)"]);
+    }
+
+    fn open(dir: &Path, name: &str, files: Vec<(&str, ResourceData)>) -> (String, ModReader) {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: Default::default(),
+            category: Default::default(),
+            description: Default::default(),
+            platform: uk_mod::ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        };
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert(PACK.into());
+        let sarc = uk_content::resource::SarcMap {
+            alignment: 4,
+            files:     files
+                .iter()
+                .map(|(file, _)| smartstring::alias::String::from(*file))
+                .collect(),
+        };
+        let resources = files
+            .into_iter()
+            .map(|(file, data)| (canonicalize(file), data))
+            .chain([(PACK.into(), ResourceData::Sarc(sarc))]);
+        let path =
+            pack_resources(dir.join(format!("{name}.zip")), &meta, &manifest, resources).unwrap();
+        (
+            format!("{name} 1.0.0"),
+            ModReader::open(path, vec![]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn report() {
+        let tmp = tempfile::tempdir().unwrap();
+        let status = roead::yaz0::decompress(
+            fs_err::read("../uk-content/test/Ecosystem/StatusEffectList.sbyml").unwrap(),
+        )
+        .unwrap();
+        let status = ResourceData::Mergeable(
+            MergeableResource::from_binary(STATUS.as_ref(), &status)
+                .unwrap()
+                .unwrap(),
+        );
+        let first = open(tmp.path(), "First", vec![
+            (BINARY, ResourceData::Binary(b"first".to_vec())),
+            (STATUS, status.clone()),
+        ]);
+        let second = open(tmp.path(), "Second", vec![
+            (BINARY, ResourceData::Binary(b"second".to_vec())),
+            (STATUS, ResourceData::Binary(b"status".to_vec())),
+        ]);
+        let third = open(tmp.path(), "Third", vec![
+            (BINARY, ResourceData::Binary(b"first".to_vec())),
+            (STATUS, status),
+        ]);
+
+        let report = check(&first, &second, false).unwrap();
+        assert_eq!(report.entries, [
+            Entry {
+                path:    format!("{PACK}//{STATUS}"),
+                verdict: Verdict::Priority,
+            },
+            Entry {
+                path:    format!("{PACK}//{BINARY}"),
+                verdict: Verdict::Conflict,
+            },
+        ]);
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("- Hard conflict: 1\n- Priority decides: 1\n"));
+
+        let report = check(&first, &third, true).unwrap();
+        assert!(
+            report
+                .entries
+                .iter()
+                .all(|e| e.verdict == Verdict::Identical)
+        );
+        assert_eq!(report.entries.len(), 2);
+    }
+
+    #[test]
+    fn resource_tree() {
+        let data = roead::yaz0::decompress(
+            fs_err::read("../uk-content/test/Ecosystem/StatusEffectList.sbyml").unwrap(),
+        )
+        .unwrap();
+        let resource = MergeableResource::from_binary(STATUS.as_ref(), &data)
+            .unwrap()
+            .unwrap();
+        let tree = diff_tree(&resource).unwrap();
+        // The resource type is left off
+        assert!(matches!(tree, Value::Mapping(_)));
+        assert!(tree.get("StatusEffectList").is_none());
+        assert!(overlap(&tree, &tree).is_empty());
+        assert!(!overlap(&tree, &Value::Null).is_empty());
+    }
+}
@@ -0,0 +1,710 @@
+//! A machine-readable report of the files changed by more than one enabled
+//! mod in the current profile, for tools outside UKMM which check mod lists
+//! for overlaps. Each file is listed with the mods which change it, what kind
+//! of file it is, which mod wins it, and any merge policy override deciding
+//! how it is merged.
+//!
+//! The report is JSON, laid out by the structs in this module. Their field
+//! and variant names are part of the schema, so they are renamed explicitly
+//! rather than following the names used elsewhere in UKMM. Any change which
+//! would break a reader of older reports must raise [`SCHEMA_VERSION`].
+//!
+//! The files are written one at a time as they are worked out, so a report
+//! of a large profile is never held in memory whole.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    io::{BufWriter, Write},
+};
+
+use anyhow_ext::{Context, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use smartstring::alias::String;
+use uk_content::{canon::canonicalize_aoc, canonicalize, resource::ResourceData};
+use uk_mod::{
+    policy::{MergePolicies, MergePolicy},
+    unpack::ModReader,
+};
+
+use crate::{core::Manager, mods::Mod, simulation::Ownership};
+
+/// The version of the report layout, written at the top of every report.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The platform of the current mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Platform {
+    #[serde(rename = "wiiu")]
+    WiiU,
+    Switch,
+}
+
+impl From<crate::settings::Platform> for Platform {
+    fn from(platform: crate::settings::Platform) -> Self {
+        match platform {
+            crate::settings::Platform::WiiU => Platform::WiiU,
+            crate::settings::Platform::Switch => Platform::Switch,
+        }
+    }
+}
+
+/// Which end of the load order wins when mods change the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    LowerWins,
+    HigherWins,
+}
+
+impl From<crate::mods::Priority> for Priority {
+    fn from(priority: crate::mods::Priority) -> Self {
+        match priority {
+            crate::mods::Priority::LowerWins => Priority::LowerWins,
+            crate::mods::Priority::HigherWins => Priority::HigherWins,
+        }
+    }
+}
+
+/// The profile the report was made for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Header {
+    pub schema: u32,
+    pub ukmm_version: String,
+    pub profile: String,
+    pub platform: Platform,
+    pub priority: Priority,
+}
+
+/// A mod which changes a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ModRef {
+    pub name:     String,
+    pub version:  String,
+    /// The hash identifying the mod, as accepted by batch files. It is
+    /// written as a string, since it does not fit the numbers of every JSON
+    /// reader.
+    pub hash:     String,
+    /// The place of the mod in merge order among the enabled mods, from 0.
+    /// Mods merged later win, so the highest priority wins.
+    pub priority: usize,
+}
+
+/// What kind of file a conflict is over, which decides how it is merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+    /// A resource UKMM merges by its contents.
+    Mergeable,
+    /// A SARC archive, merged by the files in it.
+    Pack,
+    /// A file which cannot be merged, so the winning copy is used whole.
+    Binary,
+    /// A file which could not be read from the mods.
+    Unknown,
+}
+
+impl Class {
+    /// The resource type name merge policies match this class by.
+    fn kind(self) -> &'static str {
+        match self {
+            Class::Pack => "Sarc",
+            Class::Binary => "Binary",
+            Class::Mergeable | Class::Unknown => "",
+        }
+    }
+}
+
+/// How a conflict is settled when mods are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// The changes of every mod are merged, with the winner's taken where
+    /// they clash.
+    Merged,
+    /// The winner's copy is used whole.
+    Replaced,
+    /// The mods are ignored and the stock file is used, or none at all if
+    /// the game has no such file.
+    Stock,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    Merge,
+    PriorityWins,
+    Skip,
+}
+
+impl From<MergePolicy> for Policy {
+    fn from(policy: MergePolicy) -> Self {
+        match policy {
+            MergePolicy::Merge => Policy::Merge,
+            MergePolicy::PriorityBinary => Policy::PriorityWins,
+            MergePolicy::Skip => Policy::Skip,
+        }
+    }
+}
+
+/// The merge policy rule in effect for a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Override {
+    pub pattern: String,
+    pub policy:  Policy,
+}
+
+/// A file changed by more than one enabled mod.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Conflict {
+    /// The canonical path of the file, under `Aoc/0010` for DLC files.
+    pub path: String,
+    pub aoc: bool,
+    pub class: Class,
+    /// The resource type of a mergeable file, such as `ActorInfo`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    /// The mods which change the file, in merge order.
+    pub mods: Vec<ModRef>,
+    /// The hash of the mod which wins the file, unless the stock file is
+    /// used.
+    pub winner: Option<String>,
+    pub resolution: Resolution,
+    #[serde(rename = "override", default, skip_serializing_if = "Option::is_none")]
+    pub override_: Option<Override>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.path)?;
+        match &self.resource {
+            Some(resource) => write!(f, "{resource}")?,
+            None => write!(f, "{:?}", self.class)?,
+        }
+        write!(f, "): ")?;
+        for (i, mod_) in self.mods.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", mod_.name)?;
+        }
+        let winner = self
+            .winner
+            .as_ref()
+            .and_then(|hash| self.mods.iter().find(|m| &m.hash == hash))
+            .map(|m| m.name.as_str())
+            .unwrap_or_default();
+        match self.resolution {
+            Resolution::Merged => write!(f, " -> merged, {winner} wins")?,
+            Resolution::Replaced => write!(f, " -> {winner} wins")?,
+            Resolution::Stock => write!(f, " -> stock")?,
+        }
+        if let Some(rule) = &self.override_ {
+            write!(f, " [{:?} by {}]", rule.policy, rule.pattern)?;
+        }
+        Ok(())
+    }
+}
+
+/// A whole report, as read back. Reports are written by [`ConflictReport`]
+/// in the same layout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    #[serde(flatten)]
+    pub header:    Header,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// The report as it is written, with the conflicts drawn from an iterator.
+#[derive(Serialize)]
+#[serde(bound(serialize = "Streamed<I>: Serialize"))]
+struct Document<'a, I> {
+    #[serde(flatten)]
+    header:    &'a Header,
+    conflicts: Streamed<I>,
+}
+
+/// A sequence serialized from an iterator as it is drawn.
+struct Streamed<I>(RefCell<Option<I>>);
+
+impl<I> Serialize for Streamed<I>
+where
+    I: Iterator,
+    I::Item: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.borrow_mut().take().into_iter().flatten())
+    }
+}
+
+/// The conflicts of a profile, worked out file by file when they are read.
+pub struct ConflictReport {
+    header: Header,
+    /// The enabled mods, in merge order.
+    mods: Vec<Mod>,
+    ownership: Ownership,
+    policies: MergePolicies,
+}
+
+impl ConflictReport {
+    /// Reads the mods and merge policies of the current profile.
+    pub fn new(core: &Manager) -> Result<Self> {
+        let settings = core.settings();
+        let mod_manager = core.mod_manager();
+        let priority = mod_manager.priority();
+        let profile = mod_manager.profile().key().clone();
+        let mods: Vec<Mod> = mod_manager.all_mods().collect();
+        let ownership = mod_manager.ownership()?;
+        let policies = MergePolicies::load(&settings.merge_policy_path())
+            .context("Failed to load merge policy overrides")?;
+        Ok(Self {
+            header: Header {
+                schema: SCHEMA_VERSION,
+                ukmm_version: env!("CARGO_PKG_VERSION").into(),
+                profile,
+                platform: settings.current_mode.into(),
+                priority: priority.into(),
+            },
+            mods: priority
+                .merge_order(mods.iter().filter(|m| m.enabled).collect())
+                .into_iter()
+                .cloned()
+                .collect(),
+            ownership,
+            policies,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Each conflict in turn, content files first. Mods are only opened once
+    /// a conflict needs them, and then kept open for the rest.
+    pub fn conflicts(&self) -> impl Iterator<Item = Conflict> + '_ {
+        let mut readers = HashMap::new();
+        self.ownership
+            .conflicts()
+            .map(move |(file, aoc, owners)| self.conflict(&mut readers, file, aoc, owners))
+    }
+
+    /// Writes the report as JSON, one conflict at a time.
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        serde_json::to_writer_pretty(&mut writer, &Document {
+            header:    &self.header,
+            conflicts: Streamed(RefCell::new(Some(self.conflicts()))),
+        })
+        .context("Failed to write conflict report")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a mod's first copy of a file, opening the mod if it is not open
+    /// yet. Mods which fail to open are only tried once.
+    fn resource(
+        &self,
+        readers: &mut HashMap<usize, Option<ModReader>>,
+        mod_: &Mod,
+        file: &str,
+    ) -> Option<ResourceData> {
+        let reader = readers.entry(mod_.hash()).or_insert_with(|| {
+            ModReader::open(&mod_.path, mod_.active_options())
+                .inspect_err(|e| log::warn!("Failed to open {}: {e:?}", mod_.meta.name))
+                .ok()
+        });
+        let data = reader
+            .as_ref()?
+            .get_versions(file.as_ref())
+            .ok()?
+            .into_iter()
+            .next()?;
+        minicbor_ser::from_slice(&data)
+            .inspect_err(|e| log::warn!("Failed to read {file} from {}: {e}", mod_.meta.name))
+            .ok()
+    }
+
+    fn conflict(
+        &self,
+        readers: &mut HashMap<usize, Option<ModReader>>,
+        file: &str,
+        aoc: bool,
+        owners: &[usize],
+    ) -> Conflict {
+        let mods: Vec<ModRef> = owners
+            .iter()
+            .filter_map(|hash| self.mods.iter().position(|m| m.hash() == *hash))
+            .map(|priority| {
+                let mod_ = &self.mods[priority];
+                ModRef {
+                    name: mod_.meta.name.clone(),
+                    version: mod_.meta.version.clone(),
+                    hash: mod_.hash().to_string().into(),
+                    priority,
+                }
+            })
+            .collect();
+        // Policies are decided by the type of the stock file or the first
+        // copy, and mods do not change the type of a file
+        let resource = owners
+            .first()
+            .and_then(|hash| self.mods.iter().find(|m| m.hash() == *hash))
+            .and_then(|mod_| self.resource(readers, mod_, file));
+        let (class, resource) = match resource {
+            Some(ResourceData::Mergeable(resource)) => {
+                (Class::Mergeable, Some(String::from(resource.to_string())))
+            }
+            Some(ResourceData::Sarc(_)) => (Class::Pack, None),
+            Some(ResourceData::Binary(_)) => (Class::Binary, None),
+            None => (Class::Unknown, None),
+        };
+        let canon = if aoc {
+            canonicalize_aoc(file)
+        } else {
+            canonicalize(file)
+        };
+        let rule = self
+            .policies
+            .resolve(&canon, resource.as_deref().unwrap_or(class.kind()));
+        let resolution = match rule.map(|rule| rule.policy) {
+            Some(MergePolicy::Skip) => Resolution::Stock,
+            Some(MergePolicy::PriorityBinary) => Resolution::Replaced,
+            _ if class == Class::Binary => Resolution::Replaced,
+            _ => Resolution::Merged,
+        };
+        Conflict {
+            path: canon.as_str().into(),
+            aoc,
+            class,
+            resource,
+            winner: mods
+                .last()
+                .filter(|_| resolution != Resolution::Stock)
+                .map(|m| m.hash.clone()),
+            mods,
+            resolution,
+            override_: rule.map(|rule| {
+                Override {
+                    pattern: rule.pattern.as_str().into(),
+                    policy:  rule.policy.into(),
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use parking_lot::RwLock;
+    use uk_content::{
+        constants::Language,
+        prelude::Endian,
+        resource::{MergeableResource, SarcMap},
+    };
+    use uk_mod::{pack::pack_resources, policy::PolicyRule, Manifest, Meta};
+    use uk_reader::{MemoryROMSource, ResourceReader};
+
+    use super::*;
+    use crate::settings::{PlatformSettings, Settings};
+
+    const BINARY: &str = "Model/Shared.sbfres";
+    const STATUS: &str = "Ecosystem/StatusEffectList.sbyml";
+    const PACK: &str = "Pack/Shared.pack";
+    const SKIPPED: &str = "Model/Skipped.sbfres";
+    const AOC: &str = "Pack/AocMainField.pack";
+
+    fn status() -> ResourceData {
+        let data = roead::yaz0::decompress(
+            fs_err::read("../uk-content/test/Ecosystem/StatusEffectList.sbyml").unwrap(),
+        )
+        .unwrap();
+        ResourceData::Mergeable(
+            MergeableResource::from_binary(STATUS.as_ref(), &data)
+                .unwrap()
+                .unwrap(),
+        )
+    }
+
+    fn pack() -> ResourceData {
+        ResourceData::Sarc(SarcMap {
+            alignment: 4,
+            files:     Default::default(),
+        })
+    }
+
+    /// Packs a mod changing some content files and DLC files.
+    fn write_mod(
+        dir: &Path,
+        name: &str,
+        files: &[(&str, ResourceData)],
+        aoc: &[(&str, ResourceData)],
+    ) -> std::path::PathBuf {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: Default::default(),
+            category: Default::default(),
+            description: Default::default(),
+            platform: uk_mod::ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        };
+        let mut manifest = Manifest::default();
+        manifest
+            .content_files
+            .extend(files.iter().map(|(file, _)| (*file).into()));
+        manifest
+            .aoc_files
+            .extend(aoc.iter().map(|(file, _)| (*file).into()));
+        let resources = files
+            .iter()
+            .chain(aoc)
+            .map(|(file, data)| (canonicalize(file), data.clone()));
+        pack_resources(dir.join(format!("{name}.zip")), &meta, &manifest, resources).unwrap()
+    }
+
+    /// A profile of three enabled mods, two sharing each kind of file, and a
+    /// disabled mod changing every file.
+    fn core(dir: &Path) -> Manager {
+        let core = Manager::with_settings(Arc::new(RwLock::new(Settings {
+            storage_dir: dir.join("storage"),
+            wiiu_config: Some(PlatformSettings {
+                language: Language::USen,
+                profile: "Testing".into(),
+                dump: Arc::new(ResourceReader::from_memory(MemoryROMSource::new(
+                    Endian::Big,
+                ))),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
+                deploy_config: None,
+            }),
+            ..Default::default()
+        })))
+        .unwrap();
+        let binary = ResourceData::Binary(b"Shared".to_vec());
+        let mods = [
+            write_mod(
+                dir,
+                "First",
+                &[
+                    (BINARY, binary.clone()),
+                    (STATUS, status()),
+                    (SKIPPED, binary.clone()),
+                ],
+                &[(AOC, pack())],
+            ),
+            write_mod(
+                dir,
+                "Second",
+                &[(BINARY, binary.clone()), (PACK, pack())],
+                &[(AOC, pack())],
+            ),
+            write_mod(
+                dir,
+                "Third",
+                &[
+                    (STATUS, status()),
+                    (PACK, pack()),
+                    (SKIPPED, binary.clone()),
+                ],
+                &[],
+            ),
+            write_mod(
+                dir,
+                "Disabled",
+                &[(BINARY, binary), (STATUS, status()), (PACK, pack())],
+                &[],
+            ),
+        ];
+        for (i, path) in mods.iter().enumerate() {
+            let mod_ = core.mod_manager().add(path, None).unwrap();
+            core.mod_manager()
+                .set_enabled(mod_.hash(), i < 3, None)
+                .unwrap();
+        }
+        core
+    }
+
+    fn report(core: &Manager) -> Report {
+        let mut json = vec![];
+        ConflictReport::new(core).unwrap().write(&mut json).unwrap();
+        serde_json::from_slice(&json).unwrap()
+    }
+
+    fn find<'a>(report: &'a Report, path: &str) -> &'a Conflict {
+        report.conflicts.iter().find(|c| c.path == path).unwrap()
+    }
+
+    fn names(conflict: &Conflict) -> Vec<&str> {
+        conflict.mods.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn profile() {
+        let tmp = tempfile::tempdir().unwrap();
+        let core = core(tmp.path());
+        let report = report(&core);
+        assert_eq!(report.header, Header {
+            schema: SCHEMA_VERSION,
+            ukmm_version: env!("CARGO_PKG_VERSION").into(),
+            profile: "Testing".into(),
+            platform: Platform::WiiU,
+            priority: Priority::LowerWins,
+        });
+        // The disabled mod and files changed by one mod are left out
+        assert_eq!(report.conflicts.len(), 5);
+        assert!(
+            report
+                .conflicts
+                .iter()
+                .all(|c| c.mods.len() == 2 && c.override_.is_none())
+        );
+
+        let binary = find(&report, "Model/Shared.bfres");
+        assert_eq!(names(binary), ["First", "Second"]);
+        assert_eq!(binary.class, Class::Binary);
+        assert_eq!(binary.resolution, Resolution::Replaced);
+        assert_eq!(binary.winner.as_ref(), Some(&binary.mods[1].hash));
+        assert_eq!(binary.mods[1].priority, 1);
+
+        let status = find(&report, "Ecosystem/StatusEffectList.byml");
+        assert_eq!(names(status), ["First", "Third"]);
+        assert_eq!(status.class, Class::Mergeable);
+        assert_eq!(status.resource.as_deref(), Some("StatusEffectList"));
+        assert_eq!(status.resolution, Resolution::Merged);
+        assert_eq!(status.mods[1].priority, 2);
+
+        assert_eq!(find(&report, PACK).class, Class::Pack);
+        let aoc = find(&report, "Aoc/0010/Pack/AocMainField.pack");
+        assert!(aoc.aoc);
+        assert_eq!(names(aoc), ["First", "Second"]);
+        assert_eq!(aoc.resolution, Resolution::Merged);
+
+        // Flipping the priority flips the merge order and the winners
+        core.mod_manager()
+            .set_priority(crate::mods::Priority::HigherWins);
+        let report = self::report(&core);
+        assert_eq!(report.header.priority, Priority::HigherWins);
+        let binary = find(&report, "Model/Shared.bfres");
+        assert_eq!(names(binary), ["Second", "First"]);
+        assert_eq!(binary.winner.as_ref(), Some(&binary.mods[1].hash));
+    }
+
+    #[test]
+    fn overrides() {
+        let tmp = tempfile::tempdir().unwrap();
+        let core = core(tmp.path());
+        let path = core.settings().merge_policy_path();
+        fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+        MergePolicies::new(vec![
+            PolicyRule {
+                pattern: "Model/Skipped.bfres".into(),
+                policy:  MergePolicy::Skip,
+            },
+            PolicyRule {
+                pattern: "StatusEffectList".into(),
+                policy:  MergePolicy::PriorityBinary,
+            },
+            PolicyRule {
+                pattern: "Pack/*.pack".into(),
+                policy:  MergePolicy::Merge,
+            },
+        ])
+        .unwrap()
+        .save(&path)
+        .unwrap();
+        let report = report(&core);
+
+        let skipped = find(&report, "Model/Skipped.bfres");
+        assert_eq!(skipped.resolution, Resolution::Stock);
+        assert_eq!(skipped.winner, None);
+        assert_eq!(
+            skipped.override_,
+            Some(Override {
+                pattern: "Model/Skipped.bfres".into(),
+                policy:  Policy::Skip,
+            })
+        );
+
+        let status = find(&report, "Ecosystem/StatusEffectList.byml");
+        assert_eq!(status.resolution, Resolution::Replaced);
+        assert_eq!(status.winner.as_ref(), Some(&status.mods[1].hash));
+        assert_eq!(
+            status.override_.as_ref().map(|o| o.policy),
+            Some(Policy::PriorityWins)
+        );
+
+        // A rule which keeps the usual merge is still reported
+        let pack = find(&report, PACK);
+        assert_eq!(pack.resolution, Resolution::Merged);
+        assert_eq!(
+            pack.override_.as_ref().map(|o| o.pattern.as_str()),
+            Some("Pack/*.pack")
+        );
+        assert!(find(&report, "Model/Shared.bfres").override_.is_none());
+    }
+
+    #[test]
+    fn schema() {
+        let tmp = tempfile::tempdir().unwrap();
+        let core = core(tmp.path());
+        let conflicts = ConflictReport::new(&core).unwrap();
+        let mut json = vec![];
+        conflicts.write(&mut json).unwrap();
+        let streamed: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        // Streaming writes the same document as serializing it whole
+        let whole = serde_json::to_value(Report {
+            header:    conflicts.header().clone(),
+            conflicts: conflicts.conflicts().collect(),
+        })
+        .unwrap();
+        assert_eq!(streamed, whole);
+
+        let keys = |value: &serde_json::Value| {
+            let mut keys: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(&streamed), [
+            "conflicts",
+            "platform",
+            "priority",
+            "profile",
+            "schema",
+            "ukmm_version"
+        ]);
+        assert_eq!(streamed["platform"], "wiiu");
+        assert_eq!(streamed["priority"], "lower_wins");
+        let status = streamed["conflicts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["path"] == "Ecosystem/StatusEffectList.byml")
+            .unwrap();
+        assert_eq!(keys(status), [
+            "aoc",
+            "class",
+            "mods",
+            "path",
+            "resolution",
+            "resource",
+            "winner"
+        ]);
+        assert_eq!(keys(&status["mods"][0]), [
+            "hash", "name", "priority", "version"
+        ]);
+        assert!(status["mods"][0]["hash"].is_string());
+        assert_eq!(status["class"], "mergeable");
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use anyhow_ext::{Context, Result};
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use crate::{deploy, mods, settings::Settings};
+use crate::{deploy, invalidate::Change, mods, recovery, settings::Settings};
 
 #[derive(Debug, Clone)]
 pub struct Manager {
@@ -17,6 +17,12 @@ impl std::panic::RefUnwindSafe for Manager {}
 impl Manager {
     pub fn init() -> Result<Self> {
         let settings = Settings::load();
+        recovery::check(&settings.read())?;
+        Self::with_settings(settings)
+    }
+
+    /// A manager using the given settings rather than the saved ones.
+    pub fn with_settings(settings: Arc<RwLock<Settings>>) -> Result<Self> {
         let mod_manager = Arc::new(RwLock::new(
             mods::Manager::init(&settings).context("Failed to initialize mod manager")?,
         ));
@@ -31,18 +37,41 @@ impl Manager {
     }
 
     pub fn reload(&self) -> Result<()> {
+        let dump_path = |settings: &Settings| {
+            settings
+                .dump()
+                .map(|dump| dump.source().host_path().to_path_buf())
+        };
+        let previous = dump_path(&self.settings.read());
         self.settings.write().reload();
         *self.mod_manager.write() =
             mods::Manager::init(&self.settings).context("Failed to initialize mod manager")?;
         *self.deploy_manager.write() = deploy::Manager::init(&self.settings, &self.mod_manager)
             .context("Failed to initialize deployment manager")?;
+        if dump_path(&self.settings.read()) != previous {
+            self.mod_manager.read().bus().publish(Change::DumpChanged);
+        }
         Ok(())
     }
 
+    /// Switches to another profile, and to the game dump it uses if that is
+    /// not the one in use.
     pub fn change_profile(&self, profile: impl AsRef<str>) -> Result<()> {
         self.mod_manager.write().set_profile(profile.as_ref())?;
         if let Some(config) = self.settings.write().platform_config_mut() {
+            let previous = config.active_dump().clone();
             config.profile = profile.as_ref().into();
+            let dump = config.active_dump();
+            if !Arc::ptr_eq(&previous, dump) {
+                log::info!(
+                    "Profile {} uses the game dump at {}",
+                    profile.as_ref(),
+                    dump.source().host_path().display()
+                );
+                // Resources parsed from the last dump would only take up
+                // memory until a profile using it is chosen again
+                previous.clear_cache();
+            }
         }
         Ok(())
     }
@@ -1,34 +1,80 @@
 #![allow(clippy::unwrap_used, unstable_name_collisions)]
 
 use std::{
-    collections::BTreeSet,
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
     path::{Path, PathBuf},
     sync::{Arc, Weak},
 };
 
 use anyhow_ext::{Context, Result};
-use dashmap::DashMap;
 use fs_err as fs;
 use join_str::jstr;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use path_slash::PathExt;
 use rayon::prelude::*;
 use roead::yaz0::{compress, decompress};
 use rstb::ResourceSizeTable;
 use serde::{Deserialize, Serialize};
 use smartstring::alias::String;
-use uk_content::{constants::Language, platform_prefixes};
+use uk_content::{compression, constants::Language, platform_prefixes};
 use uk_mod::{
-    unpack::{ModReader, ModUnpacker},
+    is_reserved_passthrough,
+    policy::MergePolicies,
+    unpack::{cache, merge_passthrough, repro::MergeFailure, ModReader, ModUnpacker, RstbUpdates},
     Manifest,
 };
 
 use crate::{
-    mods,
+    audit::{AuditLog, Event},
+    drift::{DeployedLog, Drift, DriftCheck, Resolution},
+    history, hooks,
+    invalidate::{Change, Subscriber},
+    lock::{DeployLock, Snapshot},
+    mods, preflight,
     settings::{DeployMethod, Platform, Settings},
     util,
 };
 
+pub mod staging;
+
+use staging::Staging;
+
+/// Most space the resource cache for installed mods may use before the least
+/// recently used entries are removed.
+const RESOURCE_CACHE_CAP: u64 = 4 * 1024 * 1024 * 1024;
+
+/// The resource cache files built for each mod, so those of a changed mod
+/// are removed at once rather than left to age out. Files for other options
+/// of the same mod are kept, since they are still good should the options be
+/// chosen again.
+#[derive(Debug, Default)]
+struct ResourceCaches(Mutex<HashMap<usize, BTreeSet<PathBuf>>>);
+
+impl ResourceCaches {
+    fn built(&self, hash: usize, path: PathBuf) {
+        self.0.lock().entry(hash).or_default().insert(path);
+    }
+}
+
+impl Subscriber for ResourceCaches {
+    fn invalidate(&self, change: Change) {
+        let Change::ModChanged(hash) = change else {
+            return;
+        };
+        let Some(paths) = self.0.lock().remove(&hash) else {
+            return;
+        };
+        for path in paths {
+            match fs::remove_file(&path) {
+                Ok(()) => log::debug!("Removed stale resource cache {}", path.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => log::warn!("Failed to remove stale resource cache: {e}"),
+            }
+        }
+    }
+}
+
 #[inline(always)]
 fn is_symlink(link: &Path) -> bool {
     #[cfg(windows)]
@@ -70,10 +116,263 @@ fn create_symlink(link: &Path, target: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Characters Windows, FAT32 and exFAT do not allow in file names.
+const RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+/// Device names Windows does not allow as file names, with any extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Why a file could not be written under its name, if it could not. Deploy
+/// folders are often on an SD card or shared with Windows, so names are held
+/// to what every filesystem we deploy to accepts.
+fn name_problem(file: &str) -> Option<&'static str> {
+    file.split('/').find_map(|part| {
+        let stem = part.split('.').next().unwrap_or_default();
+        if part
+            .chars()
+            .any(|c| c.is_control() || RESERVED_CHARS.contains(&c))
+        {
+            Some("contains a character which is not allowed")
+        } else if part.ends_with('.') || part.ends_with(' ') {
+            Some("ends with a dot or space")
+        } else if RESERVED_NAMES.iter().any(|n| n.eq_ignore_ascii_case(stem)) {
+            Some("is a reserved device name")
+        } else if part.encode_utf16().count() > 255 {
+            Some("is too long")
+        } else {
+            None
+        }
+    })
+}
+
+/// The name a file is found under in a folder, which is the one the game
+/// expects if a mod gave it under the other.
+fn stored_name<'a>(dir: &Path, file: &'a str) -> Cow<'a, str> {
+    compression::conventional_name(file, |name| dir.join(name).exists())
+}
+
+/// Checks the names of every file to deploy before any is written, so a bad
+/// name cannot leave a deployment half done.
+fn check_names<'a>(files: impl IntoIterator<Item = &'a String>) -> Result<()> {
+    let problems: Vec<_> = files
+        .into_iter()
+        .filter_map(|file| name_problem(file).map(|problem| format!("{file} {problem}")))
+        .collect();
+    if !problems.is_empty() {
+        anyhow_ext::bail!(
+            "{} files cannot be deployed because their names are not allowed in the deployment \
+             folder:\n{}",
+            problems.len(),
+            problems.join("\n")
+        );
+    }
+    Ok(())
+}
+
+/// Applies RSTB updates to a table. Computed values only ever raise an entry,
+/// and the entries mods set in their own RSTBs are applied last, so they win.
+fn update_table(
+    mut table: ResourceSizeTable,
+    platform: Platform,
+    updates: RstbUpdates,
+) -> Result<Vec<u8>> {
+    for (canon, size) in updates.computed {
+        match size {
+            Some(size) => {
+                if table.get(canon.as_str()).map(|s| s < size).unwrap_or(true) {
+                    table.set(canon.as_str(), size);
+                }
+            }
+            None => {
+                table.remove(canon.as_str());
+            }
+        }
+    }
+    let data = table.to_binary(platform.into());
+    match updates.explicit {
+        Some(explicit) => {
+            use uk_content::prelude::{Mergeable, Resource};
+            let table = uk_content::resource::ResourceSizeTable::from_binary(data)
+                .context("Failed to parse updated RSTB")?;
+            Ok(table.merge(&explicit).into_binary(platform.into()))
+        }
+        None => Ok(data),
+    }
+}
+
+/// The base game and DLC halves of the merged files, which can be deployed
+/// apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Half {
+    Content,
+    Aoc,
+}
+
+impl Half {
+    pub const ALL: [Self; 2] = [Self::Content, Self::Aoc];
+
+    /// The folder in the merged folder for this half on a platform.
+    pub fn prefix(self, platform: Platform) -> &'static str {
+        let (content, aoc) = platform_prefixes(platform.into());
+        match self {
+            Self::Content => content,
+            Self::Aoc => aoc,
+        }
+    }
+
+    pub fn files(self, manifest: &Manifest) -> &BTreeSet<String> {
+        match self {
+            Self::Content => &manifest.content_files,
+            Self::Aoc => &manifest.aoc_files,
+        }
+    }
+
+    fn files_mut(self, manifest: &mut Manifest) -> &mut BTreeSet<String> {
+        match self {
+            Self::Content => &mut manifest.content_files,
+            Self::Aoc => &mut manifest.aoc_files,
+        }
+    }
+}
+
+/// The files in a merged folder.
+fn merged_manifest(merged: &Path, platform: Platform) -> Manifest {
+    let list = |half: Half| -> BTreeSet<String> {
+        let root = merged.join(half.prefix(platform));
+        jwalk::WalkDir::new(&root)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                e.path()
+                    .strip_prefix(&root)
+                    .ok()
+                    .map(|p| p.to_slash_lossy().into())
+            })
+            .collect()
+    };
+    Manifest {
+        content_files: list(Half::Content),
+        aoc_files:     list(Half::Aoc),
+    }
+}
+
+/// The passthrough files staged in a merged folder: everything outside the
+/// folders for game files.
+fn merged_passthrough(merged: &Path) -> BTreeSet<String> {
+    let Ok(entries) = std::fs::read_dir(merged) else {
+        return Default::default();
+    };
+    entries
+        .filter_map(std::result::Result::ok)
+        .filter(|e| !is_reserved_passthrough(&e.file_name().to_string_lossy()))
+        .flat_map(|e| jwalk::WalkDir::new(e.path()))
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(merged)
+                .ok()
+                .map(|p| p.to_slash_lossy().into())
+        })
+        .collect()
+}
+
+/// Copies or links the pending passthrough files from the merged folder to
+/// the deployment root, and deletes those no mod provides any more. Returns
+/// the files deployed, leaving out adopted ones which are kept.
+fn deploy_passthrough(
+    pending: &PendingPassthrough,
+    merged: &Path,
+    output: &Path,
+    method: DeployMethod,
+    records: &DeployedLog,
+) -> Result<Vec<String>> {
+    for file in &pending.delete {
+        let path = output.join(file.as_str());
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete passthrough file {file}"))?;
+        }
+    }
+    let files: Vec<String> = pending
+        .files
+        .iter()
+        .filter(|f| merged.join(f.as_str()).exists() && !records.keeps(merged, f))
+        .cloned()
+        .collect();
+    files.par_iter().try_for_each(|file| -> Result<()> {
+        let from = merged.join(file.as_str());
+        let out = output.join(file.as_str());
+        if out.exists() {
+            fs::remove_file(&out)?;
+        }
+        out.parent().map(fs::create_dir_all).transpose()?;
+        match method {
+            DeployMethod::HardLink => fs::hard_link(from, &out),
+            _ => fs::copy(from, &out).map(|_| ()),
+        }
+        .with_context(|| format!("Failed to deploy passthrough file {file}"))?;
+        Ok(())
+    })?;
+    if !files.is_empty() || !pending.delete.is_empty() {
+        log::info!(
+            "Deployed {} passthrough file(s), deleted {}",
+            files.len(),
+            pending.delete.len()
+        );
+    }
+    Ok(files)
+}
+
+/// Points a symlink at `target`, replacing whatever is at `link`.
+fn refresh_symlink(link: &Path, target: &Path) -> Result<()> {
+    if !is_symlink(link) {
+        if link.exists() {
+            log::warn!("Removing old stuff from deploy folder");
+            util::remove_dir_all(link).context("Failed to remove old deployment folder")?;
+        }
+        log::info!("Creating new symlink");
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        create_symlink(link, target).context("Failed to symlink deployment folder")?;
+    } else if !is_symlink_to(link, target) {
+        log::info!("Refreshing symlink to correct profile");
+        util::remove_symlink(link)?;
+        create_symlink(link, target)?;
+    } else {
+        log::info!("Symlink exists, no deployment needed")
+    }
+    Ok(())
+}
+
+/// Passthrough files waiting to be deployed to, or deleted from, the root of
+/// the deployment.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct PendingPassthrough {
+    files:  BTreeSet<String>,
+    delete: BTreeSet<String>,
+}
+
+impl PendingPassthrough {
+    fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.delete.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.files.len() + self.delete.len()
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct PendingLog {
     files:  Manifest,
     delete: Manifest,
+    #[serde(default)]
+    passthrough: PendingPassthrough,
 }
 
 #[derive(Debug)]
@@ -82,6 +381,10 @@ pub struct Manager {
     mod_manager: Weak<RwLock<mods::Manager>>,
     pending_files: RwLock<Manifest>,
     pending_delete: RwLock<Manifest>,
+    pending_passthrough: RwLock<PendingPassthrough>,
+    lock: RwLock<Option<DeployLock>>,
+    staging: Arc<Staging>,
+    caches: Arc<ResourceCaches>,
 }
 
 impl Manager {
@@ -100,7 +403,7 @@ impl Manager {
             .and_then(|text| Ok(serde_yaml::from_str::<PendingLog>(&text)?))
         {
             Ok(log) => {
-                if !log.files.is_empty() || !log.delete.is_empty() {
+                if !log.files.is_empty() || !log.delete.is_empty() || !log.passthrough.is_empty() {
                     log::info!("Pending deployment data found");
                     log::debug!("{:#?}", &log);
                 } else {
@@ -114,17 +417,68 @@ impl Manager {
                 Default::default()
             }
         };
+        let lock = DeployLock::load(&settings.read());
+        if let Some(lock) = lock.as_ref() {
+            log::info!("Deployment is locked for profile {}", lock.profile);
+        }
+        let staging = Arc::new(Staging::default());
+        let caches = Arc::new(ResourceCaches::default());
+        {
+            let mod_manager = mod_manager.read();
+            mod_manager.bus().subscribe(&staging);
+            mod_manager.bus().subscribe(&caches);
+        }
         Ok(Self {
             settings: Arc::downgrade(settings),
             mod_manager: Arc::downgrade(mod_manager),
             pending_files: RwLock::new(pending.files),
             pending_delete: RwLock::new(pending.delete),
+            pending_passthrough: RwLock::new(pending.passthrough),
+            lock: RwLock::new(lock),
+            staging,
+            caches,
         })
     }
 
+    /// Whether the merged folder still matches the mods it was merged from.
+    #[inline]
+    pub fn staging(&self) -> &Staging {
+        &self.staging
+    }
+
+    /// The lock on the deployment, if it is locked.
+    #[inline]
+    pub fn locked(&self) -> Option<DeployLock> {
+        self.lock.read().clone()
+    }
+
+    /// Locks the deployment as it was last deployed, so that nothing can be
+    /// applied or deployed until it is unlocked.
+    pub fn lock(&self) -> Result<()> {
+        let settings = self
+            .settings
+            .upgrade()
+            .expect("YIKES, the settings manager is gone");
+        let lock = DeployLock::lock(&settings.read())?;
+        *self.lock.write() = Some(lock);
+        Ok(())
+    }
+
+    pub fn unlock(&self) -> Result<()> {
+        let settings = self
+            .settings
+            .upgrade()
+            .expect("YIKES, the settings manager is gone");
+        DeployLock::unlock(&settings.read())?;
+        *self.lock.write() = None;
+        Ok(())
+    }
+
     #[inline]
     pub fn pending(&self) -> bool {
-        !(self.pending_delete.read().is_empty() && self.pending_files.read().is_empty())
+        !(self.pending_delete.read().is_empty()
+            && self.pending_files.read().is_empty()
+            && self.pending_passthrough.read().is_empty())
     }
 
     #[inline]
@@ -135,11 +489,13 @@ impl Manager {
             + dels.aoc_files.len()
             + files.content_files.len()
             + files.aoc_files.len()
+            + self.pending_passthrough.read().len()
     }
 
     pub fn reset_pending(&self) -> Result<()> {
         self.pending_delete.write().clear();
         self.pending_files.write().clear();
+        *self.pending_passthrough.write() = Default::default();
         let settings = self
             .settings
             .upgrade()
@@ -150,12 +506,12 @@ impl Manager {
             .platform_config()
             .and_then(|c| c.deploy_config.as_ref())
             .context("No deployment config for current platform")?;
-        let dest = &config.output;
+        let roots = config.roots(settings.current_mode);
         let (content, aoc) = platform_prefixes(settings.current_mode.into());
 
         let collect_files = |root: &str| -> BTreeSet<String> {
             let source = source.join(root);
-            let dest = dest.join(root);
+            let dest = roots.for_prefix(root);
             jwalk::WalkDir::new(&source)
                 .into_iter()
                 .filter_map(|file| {
@@ -184,7 +540,7 @@ impl Manager {
 
         let collect_deletes = |root: &str| -> BTreeSet<String> {
             let source = source.join(root);
-            let dest = dest.join(root);
+            let dest = roots.for_prefix(root);
             jwalk::WalkDir::new(&source)
                 .into_iter()
                 .filter_map(|file| {
@@ -203,6 +559,30 @@ impl Manager {
             aoc_files:     collect_deletes(aoc),
         };
 
+        let records = DeployedLog::for_settings(&settings);
+        *self.pending_passthrough.write() = PendingPassthrough {
+            files:  merged_passthrough(&source)
+                .into_iter()
+                .filter(|file| {
+                    let (Ok(from), Ok(to)) = (
+                        source.join(file.as_str()).metadata(),
+                        config.output.join(file.as_str()).metadata(),
+                    ) else {
+                        return true;
+                    };
+                    matches!((to.modified(), from.modified()), (Ok(to), Ok(from)) if to < from)
+                })
+                .collect(),
+            delete: records
+                .files()
+                .keys()
+                .filter(|file| {
+                    !is_reserved_passthrough(file) && !source.join(file.as_str()).exists()
+                })
+                .cloned()
+                .collect(),
+        };
+
         Ok(())
     }
 
@@ -212,12 +592,168 @@ impl Manager {
             serde_yaml::to_string(&PendingLog {
                 delete: self.pending_delete.read().clone(),
                 files:  self.pending_files.read().clone(),
+                passthrough: self.pending_passthrough.read().clone(),
             })?,
         )?;
         Ok(())
     }
 
+    /// Compares the deployment folder against the files last deployed to it,
+    /// as far as the current platform's drift check setting asks. Symlinked
+    /// deployments cannot drift and are never checked.
+    pub fn check_drift(&self) -> Result<Vec<Drift>> {
+        let settings = self
+            .settings
+            .upgrade()
+            .expect("YIKES, the settings manager is gone");
+        let settings = settings.read();
+        let Some(config) = settings
+            .platform_config()
+            .and_then(|c| c.deploy_config.as_ref())
+            .filter(|c| c.method != DeployMethod::Symlink && c.drift_check != DriftCheck::Off)
+        else {
+            return Ok(vec![]);
+        };
+        // A locked deployment is checked against the files as they were when
+        // it was locked
+        let records = match DeployLock::load(&settings) {
+            Some(lock) => lock.records(),
+            None => DeployedLog::for_settings(&settings),
+        };
+        if records.is_empty() {
+            return Ok(vec![]);
+        }
+        // Hard links share their data with the merged files, so any file
+        // waiting to be deployed has already changed with its merged version
+        let pending: BTreeSet<std::string::String> = if config.method == DeployMethod::HardLink {
+            let (content, aoc) = platform_prefixes(settings.current_mode.into());
+            let pending = self.pending_files.read();
+            pending
+                .content_files
+                .iter()
+                .map(|f| format!("{content}/{f}"))
+                .chain(pending.aoc_files.iter().map(|f| format!("{aoc}/{f}")))
+                .chain(
+                    self.pending_passthrough
+                        .read()
+                        .files
+                        .iter()
+                        .map(|f| f.to_string()),
+                )
+                .collect()
+        } else {
+            Default::default()
+        };
+        log::info!("Checking deployed files for external changes");
+        let roots = config.roots(settings.current_mode);
+        let drift = records.check(&roots, config.drift_check, |file| pending.contains(file));
+        if !drift.is_empty() {
+            log::warn!(
+                "{} deployed file(s) were changed outside of UKMM:\n{}",
+                drift.len(),
+                drift
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        Ok(drift)
+    }
+
+    /// Settles drifted files. Restored files are queued to be deployed again,
+    /// while adopted files keep their external versions until the merged
+    /// versions change.
+    pub fn resolve_drift(&self, resolutions: &[(String, Resolution)]) -> Result<()> {
+        let settings = self
+            .settings
+            .upgrade()
+            .expect("YIKES, the settings manager is gone");
+        let settings = settings.read();
+        let config = settings
+            .platform_config()
+            .and_then(|c| c.deploy_config.as_ref())
+            .context("No deployment config for current platform")?;
+        let (content, aoc) = platform_prefixes(settings.current_mode.into());
+        let roots = config.roots(settings.current_mode);
+        let merged = settings.merged_dir();
+        let mut records = DeployedLog::for_settings(&settings);
+        let mut pending = self.pending_files.write();
+        for (file, resolution) in resolutions {
+            match resolution {
+                Resolution::Restore => {
+                    records.restore(file);
+                    if let Some(f) = file.strip_prefix(content).and_then(|f| f.strip_prefix('/')) {
+                        pending.content_files.insert(f.into());
+                    } else if let Some(f) = file.strip_prefix(aoc).and_then(|f| f.strip_prefix('/'))
+                    {
+                        pending.aoc_files.insert(f.into());
+                    } else {
+                        self.pending_passthrough.write().files.insert(file.clone());
+                    }
+                }
+                Resolution::Adopt => {
+                    records
+                        .adopt(&roots, &merged, file)
+                        .with_context(|| format!("Failed to adopt {file}"))?;
+                }
+            }
+        }
+        drop(pending);
+        records.save()?;
+        drop(settings);
+        self.save()?;
+        Ok(())
+    }
+
+    /// Deploys pending changes, then runs the deploy hooks for the current
+    /// platform.
     pub fn deploy(&self) -> Result<()> {
+        self.deploy_only(&Half::ALL)
+    }
+
+    /// Deploys the pending changes to some halves of the merged files, then
+    /// runs the deploy hooks. Changes to the other halves stay pending.
+    pub fn deploy_only(&self, halves: &[Half]) -> Result<()> {
+        DeployLock::guard(
+            &self
+                .settings
+                .upgrade()
+                .expect("YIKES, the settings manager is gone")
+                .read(),
+            "deployed",
+        )?;
+        let changed = [&*self.pending_files.read(), &*self.pending_delete.read()]
+            .into_iter()
+            .flat_map(|m| halves.iter().map(|half| half.files(m).len()))
+            .sum::<usize>()
+            + self.pending_passthrough.read().len();
+        let result = self.deploy_files(halves);
+        let settings = self
+            .settings
+            .upgrade()
+            .expect("YIKES, the settings manager is gone");
+        let settings = settings.read();
+        if let Some((profile, config)) = settings
+            .platform_config()
+            .and_then(|c| c.deploy_config.as_ref().map(|d| (c.profile.as_str(), d)))
+            .filter(|(_, config)| !config.hooks.is_empty())
+        {
+            let ctx = hooks::HookContext {
+                output: &config.output,
+                profile,
+                platform: settings.current_mode,
+                changed,
+                success: result.is_ok(),
+            };
+            let hooks = hooks::run_all(&config.hooks, &ctx);
+            result?;
+            return hooks;
+        }
+        result
+    }
+
+    fn deploy_files(&self, halves: &[Half]) -> Result<()> {
         let settings = self
             .settings
             .upgrade()
@@ -234,23 +770,31 @@ impl Manager {
             })
             .context("No deployment config for current platform")?;
         log::debug!("Deployment config:\n{:#?}", &config);
+        let roots = config.roots(settings.current_mode);
         if config.method == DeployMethod::Symlink {
             log::info!("Deploy method is symlink, checking for symlink");
-            if !is_symlink(&config.output) {
-                if config.output.exists() {
-                    log::warn!("Removing old stuff from deploy folder");
-                    util::remove_dir_all(&config.output)
-                        .context("Failed to remove old deployment folder")?;
+            let merged = settings.merged_dir();
+            if config.is_split() {
+                for half in halves {
+                    let prefix = half.prefix(settings.current_mode);
+                    refresh_symlink(roots.for_prefix(prefix), &merged.join(prefix))?;
                 }
-                log::info!("Creating new symlink");
-                create_symlink(&config.output, &settings.merged_dir())
-                    .context("Failed to symlink deployment folder")?;
-            } else if !is_symlink_to(&config.output, &settings.merged_dir()) {
-                log::info!("Refreshing symlink to correct profile");
-                util::remove_symlink(&config.output)?;
-                create_symlink(&config.output, &settings.merged_dir())?;
+                // Passthrough files go to the deployment root, which is not
+                // linked when the halves are
+                deploy_passthrough(
+                    &self.pending_passthrough.read(),
+                    &merged,
+                    &config.output,
+                    DeployMethod::Copy,
+                    &Default::default(),
+                )?;
             } else {
-                log::info!("Symlink exists, no deployment needed")
+                refresh_symlink(&config.output, &merged)?;
+            }
+            let mut records = DeployedLog::for_settings(&settings);
+            if !records.is_empty() {
+                records.clear();
+                records.save()?;
             }
         } else {
             if is_symlink(&config.output) {
@@ -264,11 +808,18 @@ impl Manager {
                 );
                 */
             }
-            let (content, aoc) = uk_content::platform_prefixes(settings.current_mode.into());
+            if config.is_split() {
+                for root in [&roots.content, &roots.aoc] {
+                    if is_symlink(root) {
+                        util::remove_symlink(root)?;
+                    }
+                }
+            }
             let deletes = self.pending_delete.read();
             log::debug!("Deployed files to delete:\n{:#?}", &deletes);
             let syncs = self.pending_files.read();
             log::debug!("Files to deploy\n{:#?}", &syncs);
+            check_names(halves.iter().flat_map(|half| half.files(&syncs)))?;
             log::info!("Deploying by {}", match config.method {
                 DeployMethod::Copy => "copy",
                 DeployMethod::HardLink => "hard links",
@@ -279,26 +830,57 @@ impl Manager {
                 !file.starts_with("Pack/Bootup_") || **file == lang.bootup_path()
             };
 
-            for (dir, dels, syncs) in [
-                (content, &deletes.content_files, &syncs.content_files),
-                (aoc, &deletes.aoc_files, &syncs.aoc_files),
-            ] {
-                let dest = config.output.join(dir);
-                let source = settings.merged_dir().join(dir);
+            let mut records = DeployedLog::for_settings(&settings);
+            let merged = settings.merged_dir();
+            let mut deployed: Vec<String> = vec![];
+            let mut removed: Vec<String> = vec![];
+            for half in halves {
+                let dir = half.prefix(settings.current_mode);
+                let (dels, syncs) = (half.files(&deletes), half.files(&syncs));
+                if dels.is_empty() && syncs.is_empty() {
+                    log::info!("No changes to deploy in {dir}, skipping");
+                    continue;
+                }
+                let dest = roots.for_prefix(dir).to_path_buf();
+                let source = merged.join(dir);
+                let kept: Vec<&String> = syncs
+                    .iter()
+                    .filter(filter_xbootup)
+                    .filter(|f| records.keeps(&merged, &format!("{dir}/{f}")))
+                    .collect();
+                if !kept.is_empty() {
+                    log::info!("Keeping {} adopted file(s) in {dir}", kept.len());
+                }
+                removed.extend(
+                    dels.iter()
+                        .filter(filter_xbootup)
+                        .map(|f| String::from(format!("{dir}/{}", stored_name(&dest, f)))),
+                );
+                deployed.extend(
+                    syncs
+                        .iter()
+                        .filter(filter_xbootup)
+                        .filter(|f| !kept.contains(f))
+                        .map(|f| String::from(format!("{dir}/{}", stored_name(&source, f)))),
+                );
                 dels.par_iter()
                     .filter(filter_xbootup)
                     .try_for_each(|f| -> Result<()> {
-                        let file = dest.join(f.as_str());
+                        let file = dest.join(&*stored_name(&dest, f));
                         if file.exists() {
                             fs::remove_file(file)?;
                         }
                         Ok(())
                     })?;
 
-                syncs.par_iter().filter(filter_xbootup).try_for_each(
-                    |f: &String| -> Result<()> {
-                        let from = source.join(f.as_str());
-                        let out = dest.join(f.as_str());
+                syncs
+                    .par_iter()
+                    .filter(filter_xbootup)
+                    .filter(|f| !kept.contains(f))
+                    .try_for_each(|f: &String| -> Result<()> {
+                        let f = stored_name(&source, f);
+                        let from = source.join(&*f);
+                        let out = dest.join(&*f);
                         if out.exists() {
                             fs::remove_file(&out)?;
                         }
@@ -329,8 +911,24 @@ impl Manager {
                             );
                             Ok(())
                         }
-                    },
-                )?;
+                    })?;
+            }
+            let passthrough = self.pending_passthrough.read();
+            deployed.extend(deploy_passthrough(
+                &passthrough,
+                &merged,
+                &config.output,
+                config.method,
+                &records,
+            )?);
+            removed.extend(passthrough.delete.iter().cloned());
+            drop(passthrough);
+            records.forget(&removed);
+            if let Err(e) = records
+                .record(&roots, &deployed)
+                .and_then(|_| records.save())
+            {
+                log::warn!("Failed to record deployed files: {e:?}");
             }
             log::info!("Deployment complete");
         }
@@ -344,9 +942,13 @@ impl Manager {
         {
             fs::write(rules_path, include_str!("../../../assets/rules.txt"))?;
         }
-        self.pending_delete.write().clear();
-        self.pending_files.write().clear();
+        for half in halves {
+            half.files_mut(&mut self.pending_delete.write()).clear();
+            half.files_mut(&mut self.pending_files.write()).clear();
+        }
+        *self.pending_passthrough.write() = Default::default();
         self.save()?;
+        AuditLog::open(&settings).record(Event::Deployed { profile });
         Ok(())
     }
 
@@ -408,17 +1010,12 @@ impl Manager {
         Ok(())
     }
 
-    fn apply_rstb(
-        &self,
-        merged: &Path,
-        platform: Platform,
-        updates: DashMap<String, Option<u32>>,
-    ) -> Result<()> {
+    fn apply_rstb(&self, merged: &Path, platform: Platform, updates: RstbUpdates) -> Result<()> {
         static RSTB_PATH: &str = "System/Resource/ResourceSizeTable.product.srsizetable";
         log::debug!("RSTB updates:\n{:#?}", &updates);
         let content = uk_content::platform_content(platform.into());
         let table_path = merged.join(content).join(RSTB_PATH);
-        let mut table = if table_path.exists() {
+        let table = if table_path.exists() {
             log::debug!("Updating existing merged RSTB");
             ResourceSizeTable::from_binary(
                 decompress(fs::read(&table_path).context("Failed to open merged RSTB")?)
@@ -429,22 +1026,10 @@ impl Manager {
             log::debug!("Creating new RSTB");
             ResourceSizeTable::new_from_stock(platform.into())
         };
-        for (canon, size) in updates {
-            match size {
-                Some(size) => {
-                    if table.get(canon.as_str()).map(|s| s < size).unwrap_or(true) {
-                        table.set(canon.as_str(), size);
-                    }
-                }
-                None => {
-                    table.remove(canon.as_str());
-                }
-            }
-        }
+        let data = update_table(table, platform, updates)?;
         log::info!("Updated RSTB");
         fs::create_dir_all(table_path.parent().unwrap())?;
-        fs::write(table_path, compress(table.to_binary(platform.into())))
-            .context("Failed to write merged RSTB")?;
+        fs::write(table_path, compress(data)).context("Failed to write merged RSTB")?;
         self.pending_files
             .write()
             .content_files
@@ -452,6 +1037,101 @@ impl Manager {
         Ok(())
     }
 
+    /// Stages the passthrough files of every enabled mod at the root of the
+    /// merged folder, and removes those which were `staged` before but no
+    /// mod provides any more. All of them are queued for deployment.
+    fn apply_passthrough(&self, out_dir: &Path, staged: BTreeSet<String>) -> Result<()> {
+        let mod_manager = self
+            .mod_manager
+            .upgrade()
+            .expect("YIKES, the mod manager system is gone");
+        let priority = mod_manager.read().priority();
+        let mods = mod_manager
+            .read()
+            .mods()
+            .map(|m| {
+                ModReader::open(&m.path, m.active_options())
+                    .with_context(|| jstr!("Failed to open mod: {&m.meta.name}"))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|mods| priority.merge_order(mods))?;
+        let merged =
+            merge_passthrough(&mods, out_dir).context("Failed to merge passthrough files")?;
+        if !merged.files.is_empty() {
+            log::info!("Staged {} passthrough file(s)", merged.files.len());
+        }
+        let stale: Vec<String> = staged
+            .into_iter()
+            .filter(|f| !merged.files.contains_key(f.as_str()))
+            .collect();
+        for file in &stale {
+            let path = out_dir.join(file.as_str());
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove stale passthrough file {file}"))?;
+            }
+        }
+        let mut pending = self.pending_passthrough.write();
+        pending
+            .delete
+            .retain(|f| !merged.files.contains_key(f.as_str()));
+        pending.delete.extend(stale);
+        pending
+            .files
+            .extend(merged.files.into_keys().map(String::from));
+        Ok(())
+    }
+
+    /// Replaces the merged files with a snapshot's, without merging anything.
+    /// Every file in the snapshot is queued to be deployed, and every file it
+    /// lacks to be deleted from the deployment.
+    pub fn restore_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        let settings = self
+            .settings
+            .upgrade()
+            .expect("YIKES, the settings manager is gone");
+        let settings = settings.read();
+        DeployLock::guard(&settings, "replaced by a snapshot")?;
+        let source = snapshot.merged();
+        if !source.exists() {
+            anyhow_ext::bail!("Snapshot {} has no merged files", snapshot.name);
+        }
+        let merged = settings.merged_dir();
+        let before = merged_manifest(&merged, settings.current_mode);
+        let passthrough_before = merged_passthrough(&merged);
+        if merged.exists() {
+            util::remove_dir_all(&merged).context("Failed to clear merged folder")?;
+        }
+        dircpy::copy_dir(&source, &merged).context("Failed to copy snapshot to merged folder")?;
+        self.staging.forget();
+        let after = merged_manifest(&merged, settings.current_mode);
+        {
+            let mut deletes = self.pending_delete.write();
+            for half in Half::ALL {
+                let removed = half.files(&before) - half.files(&after);
+                half.files_mut(&mut deletes).extend(removed);
+            }
+        }
+        self.pending_files.write().extend(&after);
+        {
+            let passthrough_after = merged_passthrough(&merged);
+            let mut pending = self.pending_passthrough.write();
+            pending
+                .delete
+                .extend(&passthrough_before - &passthrough_after);
+            pending.delete.retain(|f| !passthrough_after.contains(f));
+            pending.files.extend(passthrough_after);
+        }
+        drop(settings);
+        self.save()?;
+        log::info!(
+            "Restored snapshot {} of profile {}",
+            snapshot.name,
+            snapshot.profile
+        );
+        Ok(())
+    }
+
     pub fn apply(&self, manifest: Option<Manifest>) -> Result<()> {
         let mod_manager = self
             .mod_manager
@@ -462,23 +1142,53 @@ impl Manager {
             .upgrade()
             .expect("YIKES, the settings manager is gone");
         let settings = settings.try_read().unwrap();
+        let manifest = match manifest {
+            Some(_) if self.staging.needs_rebuild() => {
+                log::info!("Merged files are from another game dump, so remerging all mods");
+                None
+            }
+            Some(mut manifest) => {
+                let stale = self.staging.stale();
+                if !stale.is_empty() {
+                    log::info!(
+                        "Also merging {} files which changed since they were merged",
+                        stale.content_files.len() + stale.aoc_files.len()
+                    );
+                    manifest.extend(&stale);
+                }
+                Some(manifest)
+            }
+            None => None,
+        };
+        let remerge = manifest.is_none();
+        DeployLock::guard(&settings, if remerge { "remerged" } else { "changed" })?;
         let dump = settings
             .dump()
             .context("No dump available for current platform")?;
         let endian = settings.current_mode.into();
         let out_dir = settings.merged_dir();
+        let staged_passthrough = merged_passthrough(&out_dir);
+        let cache_dir = settings.resource_cache_dir();
+        let priority = mod_manager.read().priority();
+        let mut merged = vec![];
         let unpacker = if let Some(mut manifest) = manifest {
             log::info!("Manifest provided, applying limited changes");
+            preflight::check(&settings, &manifest, false)?;
             let mut total_manifest = Manifest::default();
             let mods = mod_manager
                 .read()
                 .mods_by_manifest(&manifest)
                 .map(|m| {
-                    ModReader::open(&m.path, m.enabled_options.clone())
-                        .inspect(|m| total_manifest.extend(&m.manifest))
+                    ModReader::open(&m.path, m.active_options())
+                        .map(|m| m.with_platform(endian).with_cache(&cache_dir))
+                        .inspect(|r| {
+                            total_manifest.extend(&r.manifest);
+                            self.opened(&mut merged, &m, r, &cache_dir);
+                        })
                         .with_context(|| jstr!("Failed to open mod: {&m.meta.name}"))
                 })
-                .collect::<Result<Vec<_>>>()?;
+                .collect::<Result<Vec<_>>>()
+                .map(|mods| priority.merge_order(mods))?;
             self.handle_orphans(
                 total_manifest,
                 &mut manifest,
@@ -488,7 +1198,7 @@ impl Manager {
             log::debug!("Change manifest: {:#?}", &manifest);
             self.pending_files.write().extend(&manifest);
             ModUnpacker::new(
-                dump,
+                dump.clone(),
                 endian,
                 settings.platform_config().unwrap().language,
                 mods,
@@ -502,26 +1212,340 @@ impl Manager {
                 .read()
                 .mods()
                 .map(|m| {
-                    ModReader::open(&m.path, m.enabled_options.clone())
-                        .inspect(|m| total_manifest.extend(&m.manifest))
+                    ModReader::open(&m.path, m.active_options())
+                        .map(|m| m.with_platform(endian).with_cache(&cache_dir))
+                        .inspect(|r| {
+                            total_manifest.extend(&r.manifest);
+                            self.opened(&mut merged, &m, r, &cache_dir);
+                        })
                         .with_context(|| jstr!("Failed to open mod: {&m.meta.name}"))
                 })
-                .collect::<Result<Vec<_>>>()?;
+                .collect::<Result<Vec<_>>>()
+                .map(|mods| priority.merge_order(mods))?;
+            preflight::check(&settings, &total_manifest, true)?;
             util::remove_dir_all(&out_dir).context("Failed to clear merged folder")?;
             self.pending_files.write().extend(&total_manifest);
             ModUnpacker::new(
-                dump,
+                dump.clone(),
                 endian,
                 settings.platform_config().unwrap().language,
                 mods,
                 out_dir.clone(),
             )
         };
+        let policies = MergePolicies::load(&settings.merge_policy_path())
+            .context("Failed to load merge policy overrides")?;
+        let unpacker = unpacker.with_policies(policies);
         log::info!("Applying changes");
+        let reads_before = dump.read_stats();
+        let timer = std::time::Instant::now();
         let rstb_updates = unpacker.unpack()?;
+        self.staging.merged(merged, remerge);
+        log::info!("Merged files in {:.2?}", timer.elapsed());
+        if let (Some(before), Some(after)) = (reads_before, dump.read_stats()) {
+            log::info!("Game dump: {}", after.since(&before));
+        }
+        log::debug!("Game dump cache: {}", dump.cache_stats());
+        if let Err(e) = cache::evict(&cache_dir, RESOURCE_CACHE_CAP) {
+            log::warn!("Failed to trim resource cache: {e:?}");
+        }
         self.apply_rstb(&out_dir, settings.current_mode, rstb_updates)?;
+        self.apply_passthrough(&out_dir, staged_passthrough)?;
         self.save()?;
+        let mods: Vec<_> = mod_manager.read().mods().collect();
+        if let Err(e) = history::record(&settings, &mods) {
+            log::warn!("Failed to record deployment history: {e:?}");
+        }
+        AuditLog::open(&settings).record(Event::Applied {
+            profile: settings
+                .platform_config()
+                .map(|c| c.profile.clone())
+                .unwrap_or_else(|| "Default".into()),
+            remerge,
+            mods: mods.len(),
+        });
         log::info!("All changed applied successfully");
         Ok(())
     }
+
+    /// Notes a mod opened to be merged, with the files it has and the
+    /// resource cache built for it.
+    fn opened(
+        &self,
+        merged: &mut Vec<(usize, Manifest)>,
+        mod_: &mods::Mod,
+        reader: &ModReader,
+        cache_dir: &Path,
+    ) {
+        merged.push((mod_.hash, reader.manifest.clone()));
+        if let Some(path) = reader.cache_path(cache_dir) {
+            self.caches.built(mod_.hash, path);
+        }
+    }
+
+    /// Exports a minimal repro of a failed merge to a zip at `dest`, from the
+    /// enabled mods and merge settings of the current profile. Nothing is
+    /// merged or written besides the zip.
+    pub fn export_repro(&self, failure: &MergeFailure, error: &str, dest: &Path) -> Result<()> {
+        let mod_manager = self
+            .mod_manager
+            .upgrade()
+            .expect("YIKES, the mod manager system is gone");
+        let settings = self
+            .settings
+            .upgrade()
+            .expect("YIKES, the settings manager is gone");
+        let settings = settings.read();
+        let dump = settings
+            .dump()
+            .context("No dump available for current platform")?;
+        let endian = settings.current_mode.into();
+        let cache_dir = settings.resource_cache_dir();
+        let priority = mod_manager.read().priority();
+        let mods = mod_manager
+            .read()
+            .mods()
+            .map(|m| {
+                ModReader::open(&m.path, m.active_options())
+                    .map(|m| m.with_platform(endian).with_cache(&cache_dir))
+                    .with_context(|| jstr!("Failed to open mod: {&m.meta.name}"))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|mods| priority.merge_order(mods))?;
+        let policies = MergePolicies::load(&settings.merge_policy_path())
+            .context("Failed to load merge policy overrides")?;
+        ModUnpacker::new(
+            dump,
+            endian,
+            settings
+                .platform_config()
+                .context("No platform configured")?
+                .language,
+            mods,
+            settings.merged_dir(),
+        )
+        .with_policies(policies)
+        .export_repro(&failure.file, failure.aoc, error, dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uk_content::{constants::Language, prelude::Endian, resource::ResourceData};
+    use uk_mod::Meta;
+    use uk_reader::{MemoryROMSource, ResourceReader};
+
+    use super::*;
+
+    #[test]
+    fn names() {
+        for file in [
+            "Actor/Pack/Enemy_Lizal_Senior.sbactorpack",
+            "Model/Caf\u{e9}.sbfres",
+            "Model/Caf\u{fffd}.sbfres",
+        ] {
+            assert_eq!(name_problem(file), None, "{file}");
+        }
+        for file in [
+            "Model/What?.sbfres",
+            "Model/Tab\t.sbfres",
+            "Model/Trailing./Test.sbfres",
+            "Model/aux.sbfres",
+        ] {
+            assert!(name_problem(file).is_some(), "{file}");
+        }
+        let bad: Vec<String> = vec!["Model/A:B.sbfres".into(), "Model/Fine.sbfres".into()];
+        let err = check_names(&bad).unwrap_err().to_string();
+        assert!(err.starts_with("1 files cannot be deployed"), "{err}");
+        assert!(err.contains("Model/A:B.sbfres"), "{err}");
+    }
+
+    /// A mod packed on macOS before names were normalized lists and stores
+    /// its files decomposed. It should still merge to the composed name the
+    /// dump would use, which can then be deployed.
+    #[test]
+    fn decomposed_names() {
+        let tmp = tempfile::tempdir().unwrap();
+        let extracted = tmp.path().join("extracted");
+        util::extract_zip(Path::new("test/unicode/nfd_names.zip"), &extracted).unwrap();
+        assert!(extracted.join("content/Model/Caf\u{e9}.sbfres").is_file());
+
+        let meta = Meta {
+            api: "1.0.0".into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: "Unicode Test".into(),
+            version: "1.0.0".into(),
+            author: String::new(),
+            category: String::new(),
+            description: String::new(),
+            platform: uk_mod::ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        };
+        let mut manifest = Manifest::default();
+        manifest
+            .content_files
+            .insert("Model/Cafe\u{301}.sbfres".into());
+        let path = uk_mod::pack::pack_resources(tmp.path().join("mod.zip"), &meta, &manifest, [(
+            "Model/Cafe\u{301}.bfres".into(),
+            ResourceData::Binary(b"Cafe".to_vec()),
+        )])
+        .unwrap();
+        let reader = ModReader::open(&path, vec![]).unwrap();
+        let files = reader.manifest.content_files.clone();
+        assert!(files.contains("Model/Caf\u{e9}.sbfres"));
+        let out = tmp.path().join("merged");
+        ModUnpacker::new(
+            Arc::new(ResourceReader::from_memory(MemoryROMSource::new(
+                Endian::Big,
+            ))),
+            Endian::Big,
+            Language::USen,
+            vec![reader],
+            out.clone(),
+        )
+        .unpack()
+        .unwrap();
+        let merged = fs::read(out.join("content/Model/Caf\u{e9}.sbfres")).unwrap();
+        assert_eq!(decompress(merged).unwrap(), b"Cafe");
+        check_names(&files).unwrap();
+    }
+
+    #[test]
+    fn explicit_rstb() {
+        use uk_content::{
+            prelude::{Mergeable, Resource},
+            resource::ResourceSizeTable as Table,
+        };
+
+        let platform = Platform::WiiU;
+        let stock = ResourceSizeTable::new_from_stock(platform.into());
+        let custom: Vec<std::string::String> = (0..5)
+            .map(|i| format!("Actor/Pack/Custom_{i}.bactorpack"))
+            .collect();
+        let table_with = |entries: &[(&str, u32)]| {
+            let mut table = ResourceSizeTable::new_from_stock(platform.into());
+            for (name, size) in entries {
+                table.set(*name, *size);
+            }
+            Table::from_binary(table.to_binary(platform.into())).unwrap()
+        };
+        let stock_table = table_with(&[]);
+        let first = stock_table.diff(&table_with(
+            &custom
+                .iter()
+                .map(|name| (name.as_str(), 1000))
+                .collect::<Vec<_>>(),
+        ));
+        let second = stock_table.diff(&table_with(&[(custom[0].as_str(), 2000)]));
+        // As the unpacker leaves it, with the higher priority mod last
+        let merged = stock_table.merge(&first).merge(&second);
+        let updates = RstbUpdates {
+            computed: [
+                ("Actor/Pack/Computed.bactorpack".into(), Some(500)),
+                (custom[1].as_str().into(), Some(5000)),
+            ]
+            .into_iter()
+            .collect(),
+            explicit: Some(stock_table.diff(&merged)),
+        };
+
+        let data = update_table(stock, platform, updates).unwrap();
+        let table = ResourceSizeTable::from_binary(data).unwrap();
+        assert_eq!(table.get("Actor/Pack/Computed.bactorpack"), Some(500));
+        assert_eq!(table.get(custom[0].as_str()), Some(2000));
+        // A mod's own value wins even over a larger computed one
+        for name in &custom[1..] {
+            assert_eq!(table.get(name.as_str()), Some(1000), "{name}");
+        }
+    }
+
+    #[test]
+    fn split_roots() {
+        use crate::{settings::DeployConfig, tests::wiiu_settings};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        let sd = tmp.path().join("sd");
+        let core = crate::core::Manager::with_settings(wiiu_settings(
+            &tmp.path().join("storage"),
+            Some(DeployConfig {
+                output: output.clone(),
+                content_output: Some(sd.join("content")),
+                ..Default::default()
+            }),
+        ))
+        .unwrap();
+        let merged = core.settings().merged_dir();
+        const CONTENT: &str = "Model/Test.sbfres";
+        const AOC: &str = "Pack/AocMainField.pack";
+        for file in [format!("content/{CONTENT}"), format!("aoc/0010/{AOC}")] {
+            let path = merged.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"merged").unwrap();
+        }
+        let roots = core
+            .settings()
+            .platform_config()
+            .and_then(|c| c.deploy_config.as_ref())
+            .unwrap()
+            .roots(Platform::WiiU);
+        assert_eq!(roots.content, sd.join("content"));
+        assert_eq!(roots.aoc, output.join("aoc/0010"));
+        assert_eq!(
+            crate::drift::Locate::locate(&roots, &format!("content/{CONTENT}")),
+            sd.join("content").join(CONTENT)
+        );
+
+        let deployer = core.deploy_manager();
+        deployer.pending_files.write().extend(&Manifest {
+            content_files: [CONTENT.into()].into_iter().collect(),
+            aoc_files:     [AOC.into()].into_iter().collect(),
+        });
+        deployer.deploy_only(&[Half::Aoc]).unwrap();
+        assert!(output.join("aoc/0010").join(AOC).is_file());
+        assert!(!sd.join("content").join(CONTENT).exists());
+        assert!(!output.join("content").exists());
+        assert_eq!(deployer.pending_len(), 1);
+
+        // With nothing pending for the DLC, its folder is not touched at all,
+        // so it may as well be missing
+        fs::remove_dir_all(&output).unwrap();
+        fs::write(&output, b"unplugged").unwrap();
+        deployer.deploy().unwrap();
+        assert!(sd.join("content").join(CONTENT).is_file());
+        assert!(!deployer.pending());
+    }
+    #[test]
+    fn stale_resource_caches() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = |name: &str| {
+            let path = tmp.path().join(name);
+            fs::write(&path, name).unwrap();
+            path
+        };
+        let (first, first_options, second) = (file("1a.ukrc"), file("1b.ukrc"), file("2.ukrc"));
+        let caches = ResourceCaches::default();
+        caches.built(1, first.clone());
+        caches.built(1, first_options.clone());
+        caches.built(2, second.clone());
+        // Caches for other options, profiles or dumps are still good
+        for change in [
+            Change::OptionsChanged(1),
+            Change::OrderChanged,
+            Change::DumpChanged,
+            Change::ProfileSwitched,
+        ] {
+            caches.invalidate(change);
+        }
+        assert!(first.exists() && first_options.exists() && second.exists());
+        caches.invalidate(Change::ModChanged(1));
+        assert!(!first.exists());
+        assert!(!first_options.exists());
+        assert!(second.exists());
+        // Nothing is left to remove
+        caches.invalidate(Change::ModChanged(1));
+        assert!(second.exists());
+    }
 }
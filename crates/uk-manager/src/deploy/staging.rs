@@ -0,0 +1,172 @@
+//! Whether the merged folder still matches the mods it was merged from.
+//! Applying only what changed relies on the rest of the folder being
+//! current, so each merge notes which files it took from which mod, and the
+//! changes published since mark just the files they touch as stale. Only
+//! merges since the mod manager was loaded are known of; before the first,
+//! the folder is taken to be current, as it always was.
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+use uk_mod::Manifest;
+
+use crate::invalidate::{Change, Subscriber};
+
+#[derive(Debug, Default)]
+struct State {
+    /// The files merged from each mod, by hash.
+    merged:  HashMap<usize, Manifest>,
+    /// Files whose merged versions no longer match the mods.
+    stale:   Manifest,
+    /// Whether the folder was merged from another game dump.
+    rebuild: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Staging(Mutex<State>);
+
+impl Staging {
+    /// Notes the files merged from each mod by an apply. A full remerge
+    /// replaces all that was known before, while a limited one only updates
+    /// the mods it opened.
+    pub(crate) fn merged(&self, mods: impl IntoIterator<Item = (usize, Manifest)>, remerge: bool) {
+        let mut state = self.0.lock();
+        if remerge {
+            *state = State::default();
+        }
+        state.stale.clear();
+        state.merged.extend(mods);
+    }
+
+    /// Forgets what the merged folder was merged from, for when its files are
+    /// replaced by some other means.
+    pub(crate) fn forget(&self) {
+        *self.0.lock() = State::default();
+    }
+
+    /// Files merged since which have changed in the mods.
+    pub fn stale(&self) -> Manifest {
+        self.0.lock().stale.clone()
+    }
+
+    /// Whether all of the merged folder must be merged again, since it was
+    /// merged from another game dump.
+    pub fn needs_rebuild(&self) -> bool {
+        self.0.lock().rebuild
+    }
+
+    /// Whether the merged folder matches the mods, as far as is known.
+    pub fn is_valid(&self) -> bool {
+        let state = self.0.lock();
+        !state.rebuild && state.stale.is_empty()
+    }
+}
+
+impl Subscriber for Staging {
+    fn invalidate(&self, change: Change) {
+        let mut state = self.0.lock();
+        match change {
+            Change::ModChanged(hash) | Change::OptionsChanged(hash) => {
+                if let Some(files) = state.merged.remove(&hash) {
+                    state.stale.extend(&files);
+                }
+            }
+            // Only a file merged from more than one mod can come out
+            // differently in another order
+            Change::OrderChanged => {
+                let mut seen = Manifest::default();
+                let mut shared = Manifest::default();
+                for files in state.merged.values() {
+                    shared.content_files.extend(
+                        seen.content_files
+                            .intersection(&files.content_files)
+                            .cloned(),
+                    );
+                    shared
+                        .aoc_files
+                        .extend(seen.aoc_files.intersection(&files.aoc_files).cloned());
+                    seen.extend(files);
+                }
+                state.stale.extend(&shared);
+            }
+            Change::DumpChanged => state.rebuild = true,
+            // Each profile has a merged folder of its own, of which nothing
+            // is known yet
+            Change::ProfileSwitched => *state = State::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(files: &[&str]) -> Manifest {
+        Manifest {
+            content_files: files.iter().map(|f| (*f).into()).collect(),
+            aoc_files:     Default::default(),
+        }
+    }
+
+    fn merged() -> Staging {
+        let staging = Staging::default();
+        staging.merged(
+            [
+                (
+                    1,
+                    manifest(&["Pack/Shared.pack", "Actor/Pack/A.sbactorpack"]),
+                ),
+                (
+                    2,
+                    manifest(&["Pack/Shared.pack", "Actor/Pack/B.sbactorpack"]),
+                ),
+                (3, manifest(&["Actor/Pack/C.sbactorpack"])),
+            ],
+            true,
+        );
+        staging
+    }
+
+    #[test]
+    fn changed_mod() {
+        let staging = merged();
+        assert!(staging.is_valid());
+        staging.invalidate(Change::OptionsChanged(1));
+        assert_eq!(
+            staging.stale(),
+            manifest(&["Pack/Shared.pack", "Actor/Pack/A.sbactorpack"])
+        );
+        // The files of the other mods are still current, and none of them
+        // is merged from more than one
+        staging.invalidate(Change::OrderChanged);
+        assert_eq!(
+            staging.stale(),
+            manifest(&["Pack/Shared.pack", "Actor/Pack/A.sbactorpack"])
+        );
+        // Merging the stale files makes the folder current again
+        staging.merged(
+            [(
+                1,
+                manifest(&["Pack/Shared.pack", "Actor/Pack/A.sbactorpack"]),
+            )],
+            false,
+        );
+        assert!(staging.is_valid());
+        staging.invalidate(Change::ModChanged(3));
+        assert_eq!(staging.stale(), manifest(&["Actor/Pack/C.sbactorpack"]));
+        // A mod never merged changes nothing merged
+        staging.invalidate(Change::ModChanged(4));
+        assert_eq!(staging.stale(), manifest(&["Actor/Pack/C.sbactorpack"]));
+    }
+
+    #[test]
+    fn order_and_dump() {
+        let staging = merged();
+        staging.invalidate(Change::OrderChanged);
+        assert_eq!(staging.stale(), manifest(&["Pack/Shared.pack"]));
+        assert!(!staging.needs_rebuild());
+        staging.invalidate(Change::DumpChanged);
+        assert!(staging.needs_rebuild());
+        staging.invalidate(Change::ProfileSwitched);
+        assert!(staging.is_valid());
+    }
+}
@@ -0,0 +1,304 @@
+//! Reports of exactly what mods do to one file, to attach when reporting a
+//! problem to a mod author. For the file and every file nested in it which
+//! the mods change, a report holds each mod's stored diff, the stock version
+//! and the merged result, zipped together with a header saying what was
+//! compared. Structured resources are written as YAML, and opaque files as a
+//! hex summary of how they differ from stock.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::Path,
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use uk_content::resource::ResourceData;
+use uk_mod::unpack::ModReader;
+use uk_reader::ResourceReader;
+
+use crate::{
+    core::Manager,
+    mods::Mod,
+    summary::{self, merge_resource, Change},
+};
+
+pub(crate) mod hex;
+pub(crate) mod yaml;
+
+/// Bytes of context shown around each change to an opaque file.
+const CONTEXT: usize = 16;
+
+/// What a report compares, saved in it as `report.yml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    /// The version of UKMM which made the report.
+    pub ukmm: String,
+    pub file: String,
+    pub aoc: bool,
+    /// The mods compared, with their versions, in merge order.
+    pub mods: Vec<String>,
+    /// Every resource in the report, with `//` between nested files.
+    pub resources: Vec<String>,
+    /// Whether the stock files were available to compare with.
+    pub stock: bool,
+}
+
+/// A report ready to be written, with the contents of each file in it by
+/// path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub header: Header,
+    pub files:  BTreeMap<String, String>,
+}
+
+impl Report {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+        let opts = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("report.yml", opts)?;
+        zip.write_all(serde_yaml::to_string(&self.header)?.as_bytes())?;
+        for (name, contents) in &self.files {
+            zip.start_file(name.as_str(), opts)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+/// Writes a version of a resource, with its file extension. Opaque files are
+/// summarized by how they differ from `stock`.
+fn render(data: &ResourceData, stock: Option<&ResourceData>) -> Result<(&'static str, String)> {
+    match data {
+        ResourceData::Binary(new) => {
+            let old = match stock {
+                Some(ResourceData::Binary(old)) => old.as_slice(),
+                _ => &[],
+            };
+            Ok(("txt", hex::summarize(old, new, CONTEXT)))
+        }
+        data => Ok(("yml", yaml::to_yaml(data)?)),
+    }
+}
+
+/// Builds a report for a file from mods in merge order, each given with the
+/// name to list it by. Mods which do not change the file are left out.
+pub fn build(
+    mods: &[(String, ModReader)],
+    dump: Option<&ResourceReader>,
+    file: &str,
+    aoc: bool,
+) -> Result<Report> {
+    let mut resources: BTreeMap<String, Vec<(usize, Change)>> = BTreeMap::new();
+    let mut listed = vec![];
+    for (name, reader) in mods {
+        let files = if aoc {
+            &reader.manifest.aoc_files
+        } else {
+            &reader.manifest.content_files
+        };
+        if !files.contains(file) {
+            continue;
+        }
+        let mut changes = BTreeMap::new();
+        summary::walk(
+            reader,
+            file.into(),
+            file,
+            aoc,
+            &mut changes,
+            &mut BTreeSet::new(),
+        )
+        .with_context(|| format!("Failed to read {file} from {name}"))?;
+        listed.push(name.clone());
+        for change in changes.into_values() {
+            resources
+                .entry(change.path.to_string())
+                .or_default()
+                .push((listed.len(), change));
+        }
+    }
+    let mut files = BTreeMap::new();
+    for (path, changes) in &resources {
+        let dir = path.replace("//", "/");
+        let stock = dump.and_then(|dump| changes[0].1.stock(dump));
+        let stock = stock.as_deref();
+        if let Some(stock) = stock.filter(|s| !matches!(s, ResourceData::Binary(_))) {
+            files.insert(format!("{dir}/stock.yml"), yaml::to_yaml(stock)?);
+        }
+        for (index, change) in changes {
+            let (ext, contents) = render(&change.data, stock)
+                .with_context(|| format!("Failed to write {path} from {}", listed[index - 1]))?;
+            let name = sanitise_file_name::sanitise(&listed[index - 1]);
+            files.insert(format!("{dir}/{index:02} {name}.{ext}"), contents);
+        }
+        let merged = changes
+            .iter()
+            .map(|(_, change)| change.data.clone())
+            .reduce(merge_resource)
+            .map(|diff| {
+                match stock {
+                    Some(stock) => merge_resource(stock.clone(), diff),
+                    None => diff,
+                }
+            })
+            .context("No changes to merge")?;
+        let (ext, contents) =
+            render(&merged, stock).with_context(|| format!("Failed to write merged {path}"))?;
+        files.insert(format!("{dir}/merged.{ext}"), contents);
+    }
+    Ok(Report {
+        header: Header {
+            ukmm: env!("CARGO_PKG_VERSION").into(),
+            file: file.into(),
+            aoc,
+            mods: listed,
+            resources: resources.into_keys().collect(),
+            stock: dump.is_some(),
+        },
+        files,
+    })
+}
+
+/// Exports a report for a file from mods in merge order, with their active
+/// options, comparing with the current dump.
+pub fn export(core: &Manager, mods: &[Mod], file: &str, aoc: bool, dest: &Path) -> Result<()> {
+    let settings = core.settings();
+    let endian = settings.current_mode.into();
+    let readers = mods
+        .iter()
+        .map(|m| {
+            ModReader::open(&m.path, m.active_options())
+                .map(|r| {
+                    (
+                        format!("{} {}", m.meta.name, m.meta.version),
+                        r.with_platform(endian),
+                    )
+                })
+                .with_context(|| format!("Failed to open mod: {}", m.meta.name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let report = build(&readers, settings.dump().as_deref(), file, aoc)?;
+    report.write(dest)?;
+    log::info!("Exported diff report for {file} to {}", dest.display());
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::Read;
+
+    use uk_content::{canonicalize, resource::MergeableResource};
+    use uk_mod::{pack::pack_resources, Manifest, Meta};
+
+    use super::*;
+
+    const PACK: &str = "Pack/Test.pack";
+    const BINARY: &str = "Test/Data.bin";
+    const STATUS: &str = "Ecosystem/StatusEffectList.sbyml";
+
+    fn open(dir: &Path, name: &str, files: Vec<(&str, ResourceData)>) -> (String, ModReader) {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: Default::default(),
+            category: Default::default(),
+            description: Default::default(),
+            platform: uk_mod::ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        };
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert(PACK.into());
+        let sarc = uk_content::resource::SarcMap {
+            alignment: 4,
+            files:     files
+                .iter()
+                .map(|(file, _)| smartstring::alias::String::from(*file))
+                .collect(),
+        };
+        let resources = files
+            .into_iter()
+            .map(|(file, data)| (canonicalize(file), data))
+            .chain([(PACK.into(), ResourceData::Sarc(sarc))]);
+        let path =
+            pack_resources(dir.join(format!("{name}.zip")), &meta, &manifest, resources).unwrap();
+        (
+            format!("{name} 1.0.0"),
+            ModReader::open(path, vec![]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn report() {
+        let tmp = tempfile::tempdir().unwrap();
+        let status = roead::yaz0::decompress(
+            fs::read("../uk-content/test/Ecosystem/StatusEffectList.sbyml").unwrap(),
+        )
+        .unwrap();
+        let status = ResourceData::Mergeable(
+            MergeableResource::from_binary(STATUS.as_ref(), &status)
+                .unwrap()
+                .unwrap(),
+        );
+        let mods = vec![
+            open(tmp.path(), "First", vec![(
+                BINARY,
+                ResourceData::Binary(b"first".to_vec()),
+            )]),
+            open(tmp.path(), "Second", vec![
+                (BINARY, ResourceData::Binary(b"second".to_vec())),
+                (STATUS, status),
+            ]),
+        ];
+        let report = build(&mods, None, PACK, false).unwrap();
+        assert_eq!(report.header.mods, ["First 1.0.0", "Second 1.0.0"]);
+        assert_eq!(report.header.resources, [
+            PACK.to_string(),
+            format!("{PACK}//{STATUS}"),
+            format!("{PACK}//{BINARY}"),
+        ]);
+        assert!(!report.header.stock);
+        let names: Vec<&str> = report.files.keys().map(|k| k.as_str()).collect();
+        assert_eq!(names, [
+            "Pack/Test.pack/01 First 1.0.0.yml",
+            "Pack/Test.pack/02 Second 1.0.0.yml",
+            "Pack/Test.pack/Ecosystem/StatusEffectList.sbyml/02 Second 1.0.0.yml",
+            "Pack/Test.pack/Ecosystem/StatusEffectList.sbyml/merged.yml",
+            "Pack/Test.pack/Test/Data.bin/01 First 1.0.0.txt",
+            "Pack/Test.pack/Test/Data.bin/02 Second 1.0.0.txt",
+            "Pack/Test.pack/Test/Data.bin/merged.txt",
+            "Pack/Test.pack/merged.yml",
+        ]);
+        // The last mod's version of an opaque file wins
+        let merged = &report.files["Pack/Test.pack/Test/Data.bin/merged.txt"];
+        assert!(merged.starts_with("Old: 0 bytes\nNew: 6 bytes\n"));
+        assert!(merged.contains("|second|"));
+        let sarc = &report.files["Pack/Test.pack/merged.yml"];
+        assert!(sarc.contains(BINARY) && sarc.contains(STATUS));
+
+        let zip = tmp.path().join("report.zip");
+        report.write(&zip).unwrap();
+        let mut zip = zip::ZipArchive::new(fs::File::open(&zip).unwrap()).unwrap();
+        assert_eq!(zip.len(), report.files.len() + 1);
+        let mut header = String::new();
+        zip.by_name("report.yml")
+            .unwrap()
+            .read_to_string(&mut header)
+            .unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Header>(&header).unwrap(),
+            report.header
+        );
+
+        // Mods which do not change the file are left out
+        let other = build(&mods, None, "Pack/Other.pack", false).unwrap();
+        assert!(other.header.mods.is_empty() && other.files.is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+//! Summaries of how two versions of an opaque file differ. Bytes are compared
+//! at the same offsets, so this shows patched values well, but anything
+//! inserted or removed makes the rest of the file differ.
+use std::{fmt::Write, ops::Range};
+
+/// Bytes per line of a dump.
+const WIDTH: usize = 16;
+/// Most places which differ to show before the rest are counted.
+const MAX_HUNKS: usize = 32;
+
+/// The ranges of offsets at which two versions differ, with ranges closer
+/// than `gap` bytes joined. Bytes past the end of the shorter version all
+/// differ.
+pub fn changed_ranges(old: &[u8], new: &[u8], gap: usize) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    let differs = (0..old.len().min(new.len()))
+        .filter(|i| old[*i] != new[*i])
+        .chain(old.len().min(new.len())..old.len().max(new.len()));
+    for offset in differs {
+        match ranges.last_mut() {
+            Some(last) if offset <= last.end + gap => last.end = offset + 1,
+            _ => ranges.push(offset..offset + 1),
+        }
+    }
+    ranges
+}
+
+/// The row of `data` starting at `offset`, empty past its end.
+fn row(data: &[u8], offset: usize) -> &[u8] {
+    data.get(offset..(offset + WIDTH).min(data.len()))
+        .unwrap_or_default()
+}
+
+fn dump_line(out: &mut String, prefix: char, offset: usize, data: &[u8]) {
+    let _ = write!(out, "{prefix} {offset:08x} ");
+    for i in 0..WIDTH {
+        match data.get(i) {
+            Some(byte) => {
+                let _ = write!(out, " {byte:02x}");
+            }
+            None => out.push_str("   "),
+        }
+    }
+    out.push_str("  |");
+    out.extend(data.iter().map(|b| {
+        if b.is_ascii_graphic() || *b == b' ' {
+            *b as char
+        } else {
+            '.'
+        }
+    }));
+    out.push_str("|\n");
+}
+
+/// Describes where two versions of a file differ, showing each place as a hex
+/// dump of both versions with `context` bytes around it.
+pub fn summarize(old: &[u8], new: &[u8], context: usize) -> String {
+    let ranges = changed_ranges(old, new, context * 2);
+    let changed = changed_ranges(old, new, 0)
+        .iter()
+        .map(|r| r.len())
+        .sum::<usize>();
+    let mut out = format!(
+        "Old: {} bytes\nNew: {} bytes\nChanged: {changed} bytes in {} {}\n",
+        old.len(),
+        new.len(),
+        ranges.len(),
+        if ranges.len() == 1 { "place" } else { "places" }
+    );
+    let len = old.len().max(new.len());
+    for range in ranges.iter().take(MAX_HUNKS) {
+        let start = range.start.saturating_sub(context) / WIDTH * WIDTH;
+        let end = (range.end + context)
+            .div_ceil(WIDTH)
+            .min(len.div_ceil(WIDTH))
+            * WIDTH;
+        let _ = writeln!(out, "\n@@ {:#x}..{:#x} @@", range.start, range.end);
+        for offset in (start..end).step_by(WIDTH) {
+            let (old_line, new_line) = (row(old, offset), row(new, offset));
+            if old_line == new_line {
+                dump_line(&mut out, ' ', offset, old_line);
+                continue;
+            }
+            if !old_line.is_empty() {
+                dump_line(&mut out, '-', offset, old_line);
+            }
+            if !new_line.is_empty() {
+                dump_line(&mut out, '+', offset, new_line);
+            }
+        }
+    }
+    if ranges.len() > MAX_HUNKS {
+        let _ = writeln!(out, "\n…and {} more places", ranges.len() - MAX_HUNKS);
+    }
+    out
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let old = [0u8; 64];
+        let mut new = old;
+        new[4] = 1;
+        new[6] = 1;
+        new[40] = 1;
+        assert_eq!(changed_ranges(&old, &new, 0), [4..5, 6..7, 40..41]);
+        assert_eq!(changed_ranges(&old, &new, 2), [4..7, 40..41]);
+        assert!(changed_ranges(&old, &old, 8).is_empty());
+        // Growing a file changes every byte past the old end
+        assert_eq!(
+            changed_ranges(&old[..60], &old, 0),
+            [Range { start: 60, end: 64 }]
+        );
+    }
+
+    #[test]
+    fn hunks() {
+        let old: Vec<u8> = (0..96).collect();
+        let mut new = old.clone();
+        new[0x21] = 0xff;
+        new.extend(b"AB");
+        let summary = summarize(&old, &new, 4);
+        assert!(summary.starts_with("Old: 96 bytes\nNew: 98 bytes\nChanged: 3 bytes in 2 places\n"));
+        // Each place is shown in whole lines, with the lines which are the
+        // same in both versions shown once
+        let first = summary.split("\n\n").nth(1).unwrap();
+        assert_eq!(first.lines().count(), 4, "{first}");
+        assert!(first.starts_with("@@ 0x21..0x22 @@\n  00000010 "));
+        assert!(first.contains("+ 00000020  20 ff 22"));
+        assert!(first.contains("- 00000020  20 21 22"));
+        let last = summary.split("\n\n").nth(2).unwrap();
+        assert!(last.contains("+ 00000060  41 42"));
+        assert!(last.contains("|AB|"));
+        assert!(!last.contains("- 00000060"));
+    }
+
+    #[test]
+    fn many_hunks() {
+        let old = vec![0u8; 4096];
+        let new: Vec<u8> = (0..4096).map(|i| (i % 64 == 0) as u8).collect();
+        let summary = summarize(&old, &new, 2);
+        assert!(summary.contains("Changed: 64 bytes in 64 places"));
+        assert!(summary.ends_with("…and 32 more places\n"));
+        assert_eq!(summary.matches("@@ 0x").count(), MAX_HUNKS);
+    }
+}
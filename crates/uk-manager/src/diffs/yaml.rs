@@ -0,0 +1,101 @@
+//! Stable YAML for stored diffs. Resources are serialized as they are stored,
+//! but with every mapping sorted by key, so the same diff always reads the
+//! same no matter which order its maps were built in. Enum variants are
+//! written as mappings with one key rather than YAML tags, which cannot be
+//! nested.
+use std::cmp::Ordering;
+
+use anyhow_ext::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use uk_content::resource::ResourceData;
+
+fn compare_keys(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => {
+            a.as_f64()
+                .partial_cmp(&b.as_f64())
+                .unwrap_or(Ordering::Equal)
+        }
+        _ => {
+            serde_yaml::to_string(a)
+                .unwrap_or_default()
+                .cmp(&serde_yaml::to_string(b).unwrap_or_default())
+        }
+    }
+}
+
+/// Sorts every mapping in a value by key and replaces tags with mappings.
+/// Sequences keep their order.
+pub fn sort_value(value: Value) -> Value {
+    match value {
+        Value::Mapping(map) => {
+            let mut entries: Vec<(Value, Value)> = map
+                .into_iter()
+                .map(|(k, v)| (sort_value(k), sort_value(v)))
+                .collect();
+            entries.sort_by(|(a, _), (b, _)| compare_keys(a, b));
+            Value::Mapping(entries.into_iter().collect::<Mapping>())
+        }
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(sort_value).collect()),
+        Value::Tagged(tagged) => {
+            let tag = tagged.tag.to_string();
+            let mut map = Mapping::new();
+            map.insert(
+                Value::String(tag.trim_start_matches('!').into()),
+                sort_value(tagged.value),
+            );
+            Value::Mapping(map)
+        }
+        value => value,
+    }
+}
+
+/// Serializes a resource, or a diff of one, to YAML with sorted mappings.
+pub fn to_yaml(resource: &ResourceData) -> Result<String> {
+    let value = serde_yaml::to_value(resource).context("Failed to serialize resource")?;
+    Ok(serde_yaml::to_string(&sort_value(value))?)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use uk_content::resource::MergeableResource;
+
+    use super::*;
+
+    #[test]
+    fn sorted() {
+        let a: Value = serde_yaml::from_str("b: 1\na: {z: [3, 1], y: 2}\n10: x\n9: y\n").unwrap();
+        let b: Value = serde_yaml::from_str("9: y\na: {y: 2, z: [3, 1]}\n10: x\nb: 1\n").unwrap();
+        let sorted = serde_yaml::to_string(&sort_value(a)).unwrap();
+        assert_eq!(sorted, serde_yaml::to_string(&sort_value(b)).unwrap());
+        // Numbers sort by value, and sequences are left alone
+        assert_eq!(
+            sorted,
+            "9: y\n10: x\na:\n  y: 2\n  z:\n  - 3\n  - 1\nb: 1\n"
+        );
+        assert_eq!(
+            to_yaml(&ResourceData::Binary(vec![1, 2])).unwrap(),
+            "Binary:\n- 1\n- 2\n"
+        );
+    }
+
+    #[test]
+    fn resource() {
+        let path = "Ecosystem/StatusEffectList.sbyml";
+        let data = roead::yaz0::decompress(
+            fs_err::read("../uk-content/test/Ecosystem/StatusEffectList.sbyml").unwrap(),
+        )
+        .unwrap();
+        let resource = ResourceData::Mergeable(
+            MergeableResource::from_binary(path.as_ref(), &data)
+                .unwrap()
+                .unwrap(),
+        );
+        let yaml = to_yaml(&resource).unwrap();
+        assert_eq!(yaml, to_yaml(&resource.clone()).unwrap());
+        let value: Value = serde_yaml::from_str(&yaml).unwrap();
+        assert!(!value["Mergeable"]["StatusEffectList"].is_null());
+    }
+}
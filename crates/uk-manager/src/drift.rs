@@ -0,0 +1,486 @@
+//! Detection of deployed files which were changed outside of UKMM. Shader
+//! cache tools and hand edits can leave the deployment folder out of step
+//! with what was last deployed, and a later partial deployment would then
+//! leave a mix of both. Every deployed file is recorded, and the records can
+//! be checked against the folder to find such drift.
+//!
+//! Each drifted file can be restored, so that the next deployment overwrites
+//! it, or adopted, so that the external version is kept until the merged
+//! version of the file changes.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+
+use crate::{
+    history::{hash_file, modified_secs, FileRecord},
+    settings::Settings,
+};
+
+/// Most files compared by a sampled check.
+const SAMPLE_SIZE: usize = 256;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DriftCheck {
+    Off,
+    /// Compare a few hundred files, spread over the deployment and rotated
+    /// between checks.
+    #[default]
+    Sample,
+    Full,
+}
+
+impl DriftCheck {
+    pub const ALL: [DriftCheck; 3] = [DriftCheck::Off, DriftCheck::Sample, DriftCheck::Full];
+
+    pub fn name(&self) -> &str {
+        match self {
+            DriftCheck::Off => "Off",
+            DriftCheck::Sample => "Sample",
+            DriftCheck::Full => "Full",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployedFile {
+    /// The file as it should be in the deployment folder, or `None` if an
+    /// external deletion was adopted.
+    pub record:  Option<FileRecord>,
+    /// The hash of the merged file when an external version was adopted.
+    pub adopted: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftKind {
+    Modified,
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    /// The file path relative to the deployment folder.
+    pub file: String,
+    pub kind: DriftKind,
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.file, match self.kind {
+            DriftKind::Modified => "modified",
+            DriftKind::Missing => "missing",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Deploy UKMM's version again.
+    Restore,
+    /// Keep the external version until the merged file changes.
+    Adopt,
+}
+
+fn read_record(path: &Path) -> Result<Option<FileRecord>> {
+    let meta = match std::fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Some(FileRecord {
+        hash:     hash_file(path)?,
+        size:     meta.len(),
+        modified: modified_secs(&meta),
+    }))
+}
+
+/// Finds a deployed file from its path relative to the merged folder.
+pub trait Locate: Sync {
+    fn locate(&self, file: &str) -> PathBuf;
+}
+
+impl Locate for Path {
+    fn locate(&self, file: &str) -> PathBuf {
+        self.join(file)
+    }
+}
+
+impl Locate for PathBuf {
+    fn locate(&self, file: &str) -> PathBuf {
+        self.join(file)
+    }
+}
+
+/// The files last deployed for a platform, by their path relative to the
+/// merged folder.
+#[derive(Debug, Default, Clone)]
+pub struct DeployedLog {
+    path:  PathBuf,
+    files: BTreeMap<String, DeployedFile>,
+}
+
+impl DeployedLog {
+    /// Loads the log at `path`. A missing or unreadable log is empty.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let files = match fs::read(&path) {
+            Ok(data) => {
+                serde_json::from_slice(&data)
+                    .inspect_err(|e| log::warn!("Could not read deployed file records: {e}"))
+                    .unwrap_or_default()
+            }
+            Err(_) => Default::default(),
+        };
+        Self { path, files }
+    }
+
+    /// The log for the current platform.
+    pub fn for_settings(settings: &Settings) -> Self {
+        Self::open(settings.platform_dir().join("deployed.json"))
+    }
+
+    /// A log of records kept elsewhere, such as in a deployment lock. It has
+    /// no file of its own, so it cannot be saved.
+    pub fn detached(files: BTreeMap<String, DeployedFile>) -> Self {
+        Self {
+            path: PathBuf::new(),
+            files,
+        }
+    }
+
+    #[inline]
+    pub fn files(&self) -> &BTreeMap<String, DeployedFile> {
+        &self.files
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec(&self.files)?)?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get(&self, file: &str) -> Option<&DeployedFile> {
+        self.files.get(file)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+
+    /// Records freshly deployed files as they are now in `output`. Any
+    /// adoption of them ends.
+    pub fn record<'a>(
+        &mut self,
+        output: &(impl Locate + ?Sized),
+        files: impl IntoParallelIterator<Item = &'a String>,
+    ) -> Result<()> {
+        let records = files
+            .into_par_iter()
+            .map(|file| -> Result<(String, DeployedFile)> {
+                let record = read_record(&output.locate(file))
+                    .with_context(|| format!("Failed to record deployed file {file}"))?;
+                Ok((file.clone(), DeployedFile {
+                    record,
+                    adopted: None,
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.files.extend(records);
+        Ok(())
+    }
+
+    pub fn forget<'a>(&mut self, files: impl IntoIterator<Item = &'a String>) {
+        for file in files {
+            self.files.remove(file);
+        }
+    }
+
+    /// Whether a deployment should skip a file because its external version
+    /// was adopted and the merged version has not changed since.
+    pub fn keeps(&self, merged: &Path, file: &str) -> bool {
+        self.files
+            .get(file)
+            .and_then(|f| f.adopted)
+            .is_some_and(|hash| {
+                hash_file(&merged.join(file))
+                    .map(|h| h == hash)
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Keeps the external version of a file, as it is now in `output`.
+    pub fn adopt(
+        &mut self,
+        output: &(impl Locate + ?Sized),
+        merged: &Path,
+        file: &str,
+    ) -> Result<()> {
+        let record = read_record(&output.locate(file))
+            .with_context(|| format!("Failed to read deployed file {file}"))?;
+        let adopted = read_record(&merged.join(file))
+            .with_context(|| format!("Failed to read merged file {file}"))?
+            .map(|r| r.hash)
+            .unwrap_or_default();
+        self.files.insert(file.into(), DeployedFile {
+            record,
+            adopted: Some(adopted),
+        });
+        Ok(())
+    }
+
+    /// Drops any adoption of a file, so the next deployment overwrites it.
+    pub fn restore(&mut self, file: &str) {
+        if let Some(f) = self.files.get_mut(file) {
+            f.adopted = None;
+        }
+    }
+
+    fn drifted(
+        output: &(impl Locate + ?Sized),
+        file: &str,
+        expected: &DeployedFile,
+    ) -> Option<Drift> {
+        let path = output.locate(file);
+        let kind = match (expected.record, std::fs::metadata(&path)) {
+            (None, Err(_)) => return None,
+            (None, Ok(_)) => DriftKind::Modified,
+            (Some(_), Err(_)) => DriftKind::Missing,
+            (Some(record), Ok(meta)) => {
+                if meta.len() != record.size {
+                    DriftKind::Modified
+                } else if modified_secs(&meta) == record.modified {
+                    return None;
+                } else {
+                    match hash_file(&path) {
+                        Ok(hash) if hash == record.hash => return None,
+                        Ok(_) => DriftKind::Modified,
+                        Err(e) => {
+                            log::warn!("Could not check {file} for changes: {e}");
+                            return None;
+                        }
+                    }
+                }
+            }
+        };
+        Some(Drift {
+            file: file.into(),
+            kind,
+        })
+    }
+
+    /// Compares recorded files against `output`, skipping any for which
+    /// `skip` is true. Files whose size and modification time match their
+    /// record are not read.
+    pub fn check(
+        &self,
+        output: &(impl Locate + ?Sized),
+        mode: DriftCheck,
+        skip: impl Fn(&str) -> bool + Sync,
+    ) -> Vec<Drift> {
+        let files: Vec<_> = self.files.iter().filter(|(file, _)| !skip(file)).collect();
+        let files: Vec<_> = match mode {
+            DriftCheck::Off => return vec![],
+            DriftCheck::Full => files,
+            DriftCheck::Sample if files.len() <= SAMPLE_SIZE => files,
+            DriftCheck::Sample => {
+                let step = files.len().div_ceil(SAMPLE_SIZE);
+                let offset = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as usize)
+                    .unwrap_or_default()
+                    % step;
+                files.into_iter().skip(offset).step_by(step).collect()
+            }
+        };
+        let mut drift: Vec<_> = files
+            .into_par_iter()
+            .filter_map(|(file, expected)| Self::drifted(output, file, expected))
+            .collect();
+        drift.sort_by(|a, b| a.file.cmp(&b.file));
+        drift
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        _tmp:   tempfile::TempDir,
+        merged: PathBuf,
+        output: PathBuf,
+        log:    DeployedLog,
+    }
+
+    const FILES: &[&str] = &[
+        "content/Pack/Bootup.pack",
+        "content/Actor/Pack/Enemy_Lynel.sbactorpack",
+        "aoc/0010/Pack/AocMainField.pack",
+    ];
+
+    fn write(root: &Path, file: &str, data: &[u8]) {
+        let path = root.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+
+    /// Merges and deploys every file by copy, recording the deployment.
+    fn deploy(fixture: &mut Fixture, data: &[u8]) {
+        let files: Vec<String> = FILES.iter().map(|f| (*f).into()).collect();
+        for file in &files {
+            write(&fixture.merged, file, data);
+            if !fixture.log.keeps(&fixture.merged, file) {
+                write(&fixture.output, file, data);
+                fixture.log.record(&fixture.output, vec![file]).unwrap();
+            }
+        }
+        fixture.log.save().unwrap();
+    }
+
+    fn fixture() -> Fixture {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut fixture = Fixture {
+            merged: tmp.path().join("merged"),
+            output: tmp.path().join("output"),
+            log:    DeployedLog::open(tmp.path().join("deployed.json")),
+            _tmp:   tmp,
+        };
+        deploy(&mut fixture, b"merged");
+        fixture
+    }
+
+    #[test]
+    fn detection() {
+        let mut fixture = fixture();
+        assert_eq!(fixture.log.len(), 3);
+        assert!(fixture
+            .log
+            .check(&fixture.output, DriftCheck::Full, |_| false)
+            .is_empty());
+        write(&fixture.output, FILES[0], b"edited outside");
+        // Same size, so only a hash will tell
+        write(&fixture.output, FILES[1], b"MERGED");
+        std::fs::File::options()
+            .write(true)
+            .open(fixture.output.join(FILES[1]))
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(5))
+            .unwrap();
+        std::fs::remove_file(fixture.output.join(FILES[2])).unwrap();
+        let drift = fixture
+            .log
+            .check(&fixture.output, DriftCheck::Full, |_| false);
+        assert_eq!(drift, vec![
+            Drift {
+                file: FILES[2].into(),
+                kind: DriftKind::Missing,
+            },
+            Drift {
+                file: FILES[1].into(),
+                kind: DriftKind::Modified,
+            },
+            Drift {
+                file: FILES[0].into(),
+                kind: DriftKind::Modified,
+            },
+        ]);
+        assert!(fixture
+            .log
+            .check(&fixture.output, DriftCheck::Off, |_| false)
+            .is_empty());
+        assert_eq!(
+            fixture
+                .log
+                .check(&fixture.output, DriftCheck::Sample, |f| {
+                    f.starts_with("aoc")
+                })
+                .len(),
+            2
+        );
+        // The records persist between sessions
+        fixture.log = DeployedLog::open(&fixture.log.path);
+        assert_eq!(
+            fixture
+                .log
+                .check(&fixture.output, DriftCheck::Full, |_| false)
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn restore() {
+        let mut fixture = fixture();
+        write(&fixture.output, FILES[0], b"edited outside");
+        for file in FILES {
+            fixture.log.restore(file);
+        }
+        deploy(&mut fixture, b"merged");
+        assert_eq!(
+            std::fs::read(fixture.output.join(FILES[0])).unwrap(),
+            b"merged"
+        );
+        assert!(fixture
+            .log
+            .check(&fixture.output, DriftCheck::Full, |_| false)
+            .is_empty());
+    }
+
+    #[test]
+    fn adopt() {
+        let mut fixture = fixture();
+        write(&fixture.output, FILES[0], b"edited outside");
+        std::fs::remove_file(fixture.output.join(FILES[2])).unwrap();
+        fixture
+            .log
+            .adopt(&fixture.output, &fixture.merged, FILES[0])
+            .unwrap();
+        fixture
+            .log
+            .adopt(&fixture.output, &fixture.merged, FILES[2])
+            .unwrap();
+        fixture.log.save().unwrap();
+        fixture.log = DeployedLog::open(&fixture.log.path);
+        assert!(fixture
+            .log
+            .check(&fixture.output, DriftCheck::Full, |_| false)
+            .is_empty());
+        // An unchanged merge leaves adopted files alone
+        deploy(&mut fixture, b"merged");
+        assert_eq!(
+            std::fs::read(fixture.output.join(FILES[0])).unwrap(),
+            b"edited outside"
+        );
+        assert!(!fixture.output.join(FILES[2]).exists());
+        // A new merged version ends the adoption
+        deploy(&mut fixture, b"merged again");
+        for file in FILES {
+            assert_eq!(
+                std::fs::read(fixture.output.join(file)).unwrap(),
+                b"merged again"
+            );
+            assert_eq!(fixture.log.get(file).unwrap().adopted, None);
+        }
+    }
+}
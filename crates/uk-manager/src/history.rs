@@ -0,0 +1,368 @@
+//! A short history of applied mod configurations. Each apply records the
+//! merged files it produced, with content hashes, along with the mods which
+//! were enabled, so that any two applies can be compared to see which output
+//! files changed.
+use std::{
+    collections::BTreeMap,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use path_slash::PathExt;
+use rayon::prelude::*;
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+
+use crate::{mods::Mod, settings::Settings};
+
+const EXTENSION: &str = "json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub hash:     u64,
+    pub size:     u64,
+    /// Modification time in seconds, used to skip rehashing unchanged files.
+    pub modified: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModRecord {
+    pub name:    String,
+    pub version: String,
+    pub options: Vec<String>,
+}
+
+impl From<&Mod> for ModRecord {
+    fn from(mod_: &Mod) -> Self {
+        Self {
+            name:    mod_.meta.name.clone(),
+            version: mod_.meta.version.clone(),
+            options: mod_.active_options().into_iter().map(|o| o.name).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the apply finished, in milliseconds since the Unix epoch.
+    pub time:    u64,
+    pub profile: String,
+    pub mods:    Vec<ModRecord>,
+    /// Merged files by their path relative to the merged folder.
+    pub files:   BTreeMap<String, FileRecord>,
+}
+
+impl HistoryEntry {
+    pub fn summary(&self) -> std::string::String {
+        format!(
+            "{}: {} mod{}, {} file{}",
+            self.profile,
+            self.mods.len(),
+            if self.mods.len() == 1 { "" } else { "s" },
+            self.files.len(),
+            if self.files.len() == 1 { "" } else { "s" },
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HistoryDiff {
+    pub added:   Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl HistoryDiff {
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Lists the output files added, removed, and changed going from `old` to
+/// `new`.
+pub fn compare(old: &HistoryEntry, new: &HistoryEntry) -> HistoryDiff {
+    let mut diff = HistoryDiff::default();
+    for (file, record) in &new.files {
+        match old.files.get(file) {
+            None => diff.added.push(file.clone()),
+            Some(prev) if prev.hash != record.hash || prev.size != record.size => {
+                diff.changed.push(file.clone())
+            }
+            Some(_) => (),
+        }
+    }
+    diff.removed = old
+        .files
+        .keys()
+        .filter(|file| !new.files.contains_key(*file))
+        .cloned()
+        .collect();
+    diff
+}
+
+pub(crate) fn hash_file(path: &Path) -> Result<u64> {
+    let mut hasher = FxHasher::default();
+    hasher.write(&fs::read(path)?);
+    Ok(hasher.finish())
+}
+
+pub(crate) fn modified_secs(meta: &std::fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Hashes every file in a merged folder. Files whose size and modification
+/// time match the previous entry keep their old hash without being read.
+pub fn scan(dir: &Path, previous: Option<&HistoryEntry>) -> Result<BTreeMap<String, FileRecord>> {
+    if !dir.exists() {
+        return Ok(Default::default());
+    }
+    let files: Vec<PathBuf> = jwalk::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path())
+        .collect();
+    files
+        .into_par_iter()
+        .map(|path| -> Result<(String, FileRecord)> {
+            let rel: String = path.strip_prefix(dir)?.to_slash_lossy().into();
+            let meta = fs::metadata(&path)?;
+            let size = meta.len();
+            let modified = modified_secs(&meta);
+            let hash = match previous.and_then(|p| p.files.get(&rel)) {
+                Some(prev) if prev.size == size && prev.modified == modified => prev.hash,
+                _ => {
+                    hash_file(&path)
+                        .with_context(|| format!("Failed to hash {}", path.display()))?
+                }
+            };
+            Ok((rel, FileRecord {
+                hash,
+                size,
+                modified,
+            }))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The history for the current platform.
+    pub fn open(settings: &Settings) -> Self {
+        Self::new(settings.platform_dir().join("history"))
+    }
+
+    fn files(&self) -> Vec<(u64, PathBuf)> {
+        let mut files: Vec<_> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(std::result::Result::ok)
+            .filter_map(|e| {
+                let path = e.path();
+                if path.extension()? != EXTENSION {
+                    return None;
+                }
+                let time = path.file_stem()?.to_str()?.parse().ok()?;
+                Some((time, path))
+            })
+            .collect();
+        files.sort_by_key(|(time, _)| *time);
+        files
+    }
+
+    /// All recorded entries, oldest first. Entries which cannot be read are
+    /// skipped.
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        self.files()
+            .into_iter()
+            .filter_map(|(_, path)| {
+                fs::read(&path)
+                    .map_err(anyhow_ext::Error::from)
+                    .and_then(|data| Ok(serde_json::from_slice::<HistoryEntry>(&data)?))
+                    .inspect_err(|e| log::warn!("Skipping unreadable deployment history: {e}"))
+                    .ok()
+            })
+            .collect()
+    }
+
+    pub fn latest(&self) -> Option<HistoryEntry> {
+        self.files().pop().and_then(|(_, path)| {
+            fs::read(path)
+                .ok()
+                .and_then(|data| serde_json::from_slice(&data).ok())
+        })
+    }
+
+    /// Saves an entry, then removes the oldest ones to keep at most `cap`.
+    pub fn push(&self, entry: &HistoryEntry, cap: usize) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.dir.join(format!("{}.{EXTENSION}", entry.time)),
+            serde_json::to_vec(entry)?,
+        )?;
+        let files = self.files();
+        let excess = files.len().saturating_sub(cap);
+        for (_, path) in files.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the current contents of the merged folder and the enabled mods as
+/// a new history entry.
+pub fn record<'a>(settings: &Settings, mods: impl IntoIterator<Item = &'a Mod>) -> Result<()> {
+    let cap = settings.history_len;
+    if cap == 0 {
+        return Ok(());
+    }
+    let history = History::open(settings);
+    let previous = history.latest();
+    let files = scan(&settings.merged_dir(), previous.as_ref())
+        .context("Failed to scan merged files for deployment history")?;
+    let entry = HistoryEntry {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+            .max(previous.map(|p| p.time + 1).unwrap_or_default()),
+        profile: settings
+            .platform_config()
+            .map(|c| c.profile.clone())
+            .unwrap_or_else(|| "Default".into()),
+        mods: mods.into_iter().map(ModRecord::from).collect(),
+        files,
+    };
+    history.push(&entry, cap)
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(hash: u64) -> FileRecord {
+        FileRecord {
+            hash,
+            size: 16,
+            modified: 0,
+        }
+    }
+
+    fn entry(time: u64, files: &[(&str, u64)]) -> HistoryEntry {
+        HistoryEntry {
+            time,
+            profile: "Default".into(),
+            mods: vec![ModRecord {
+                name:    "Test Mod".into(),
+                version: "1.0.0".into(),
+                options: vec![],
+            }],
+            files: files
+                .iter()
+                .map(|(file, hash)| ((*file).into(), record(*hash)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let old = entry(1, &[
+            ("content/Pack/Bootup.pack", 1),
+            ("content/Actor/Pack/Enemy_Lynel.sbactorpack", 2),
+            ("aoc/0010/Pack/AocMainField.pack", 3),
+        ]);
+        let new = entry(2, &[
+            ("content/Pack/Bootup.pack", 1),
+            ("content/Actor/Pack/Enemy_Lynel.sbactorpack", 4),
+            ("content/Pack/TitleBG.pack", 5),
+        ]);
+        let diff = compare(&old, &new);
+        assert_eq!(diff.added, vec![String::from("content/Pack/TitleBG.pack")]);
+        assert_eq!(diff.removed, vec![String::from(
+            "aoc/0010/Pack/AocMainField.pack"
+        )]);
+        assert_eq!(diff.changed, vec![String::from(
+            "content/Actor/Pack/Enemy_Lynel.sbactorpack"
+        )]);
+        assert_eq!(diff.len(), 3);
+        assert!(compare(&new, &new).is_empty());
+        assert_eq!(new.summary(), "Default: 1 mod, 3 files");
+    }
+
+    #[test]
+    fn storage() {
+        let tmp = tempfile::tempdir().unwrap();
+        let history = History::new(tmp.path().join("history"));
+        assert!(history.entries().is_empty());
+        assert!(history.latest().is_none());
+        for time in 1..=5 {
+            history
+                .push(&entry(time, &[("content/Pack/Bootup.pack", time)]), 3)
+                .unwrap();
+        }
+        let entries = history.entries();
+        assert_eq!(entries.iter().map(|e| e.time).collect::<Vec<_>>(), vec![
+            3, 4, 5
+        ]);
+        assert_eq!(history.latest().unwrap(), entries[2]);
+        std::fs::write(tmp.path().join("history/6.json"), b"not json").unwrap();
+        assert_eq!(history.entries().len(), 3);
+    }
+
+    #[test]
+    fn rescan() {
+        let tmp = tempfile::tempdir().unwrap();
+        let merged = tmp.path();
+        std::fs::create_dir_all(merged.join("content/Pack")).unwrap();
+        std::fs::write(merged.join("content/Pack/Bootup.pack"), b"bootup").unwrap();
+        std::fs::write(merged.join("content/Pack/TitleBG.pack"), b"title").unwrap();
+        let files = scan(merged, None).unwrap();
+        assert_eq!(files.len(), 2);
+        let first = HistoryEntry {
+            time: 1,
+            profile: "Default".into(),
+            mods: vec![],
+            files,
+        };
+        // An unchanged file keeps its recorded hash without being read again,
+        // so a fake hash survives while a rewritten file gets a new one
+        let mut stale = first.clone();
+        stale
+            .files
+            .get_mut("content/Pack/TitleBG.pack")
+            .unwrap()
+            .hash = 0;
+        std::fs::write(merged.join("content/Pack/Bootup.pack"), b"bootup, modded").unwrap();
+        let files = scan(merged, Some(&stale)).unwrap();
+        assert_eq!(files["content/Pack/TitleBG.pack"].hash, 0);
+        let second = HistoryEntry {
+            time: 2,
+            files,
+            ..first.clone()
+        };
+        assert_eq!(compare(&stale, &second).changed, vec![String::from(
+            "content/Pack/Bootup.pack"
+        )]);
+    }
+}
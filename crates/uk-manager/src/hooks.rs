@@ -0,0 +1,476 @@
+//! Commands run after each deployment, for syncing the output to another
+//! device, clearing an emulator cache, or anything else that should happen
+//! once the merged files are in place.
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow_ext::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Platform;
+
+/// How often a running hook is checked for having exited.
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeployHook {
+    pub name: String,
+    /// Command line, split into arguments the way the shell of the current OS
+    /// would. `{output}`, `{profile}`, `{platform}` and `{changed}` are
+    /// replaced in each argument after splitting, so values with spaces do
+    /// not need quoting.
+    pub command: String,
+    /// Skip the hook if the deployment failed.
+    pub only_on_success: bool,
+    pub cwd: Option<PathBuf>,
+    /// Seconds to let the hook run before it is killed, or 0 to wait forever.
+    pub timeout: u64,
+    /// Fail the deployment if the hook fails, instead of only warning.
+    pub required: bool,
+}
+
+impl Default for DeployHook {
+    fn default() -> Self {
+        Self {
+            name: Default::default(),
+            command: Default::default(),
+            only_on_success: true,
+            cwd: None,
+            timeout: 60,
+            required: false,
+        }
+    }
+}
+
+impl DeployHook {
+    /// The name to show for the hook, falling back to its command.
+    pub fn label(&self) -> &str {
+        if self.name.trim().is_empty() {
+            &self.command
+        } else {
+            &self.name
+        }
+    }
+}
+
+/// What the placeholders in a hook command are filled with.
+#[derive(Debug, Clone)]
+pub struct HookContext<'a> {
+    pub output:   &'a Path,
+    pub profile:  &'a str,
+    pub platform: Platform,
+    /// Number of files written or removed by the deployment.
+    pub changed:  usize,
+    pub success:  bool,
+}
+
+impl HookContext<'_> {
+    fn value(&self, name: &str) -> Option<String> {
+        match name {
+            "output" => Some(self.output.to_string_lossy().into()),
+            "profile" => Some(self.profile.into()),
+            "platform" => {
+                Some(
+                    match self.platform {
+                        Platform::WiiU => "wiiu",
+                        Platform::Switch => "switch",
+                    }
+                    .into(),
+                )
+            }
+            "changed" => Some(self.changed.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Fills the placeholders in one argument. Unknown placeholders are left
+    /// as they are, and values are not searched for further placeholders.
+    pub fn fill(&self, arg: &str) -> String {
+        let mut filled = String::with_capacity(arg.len());
+        let mut rest = arg;
+        while let Some(start) = rest.find('{') {
+            filled.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after
+                .find('}')
+                .and_then(|end| self.value(&after[..end]).map(|v| (end, v)))
+            {
+                Some((end, value)) => {
+                    filled.push_str(&value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    filled.push('{');
+                    rest = after;
+                }
+            }
+        }
+        filled.push_str(rest);
+        filled
+    }
+}
+
+/// Splits a command line following POSIX shell quoting.
+#[cfg(any(not(windows), test))]
+fn split_unix(command: &str) -> Result<Vec<String>> {
+    shlex::split(command).context("Command has an unclosed quote or trailing backslash")
+}
+
+/// Splits a command line the way Windows programs parse their arguments:
+/// backslashes are literal unless they come before a quote, and `""` inside
+/// quotes is a literal quote.
+#[cfg(any(windows, test))]
+fn split_windows(command: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let mut slashes = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    slashes += 1;
+                }
+                if chars.peek() == Some(&'"') {
+                    arg.push_str(&"\\".repeat(slashes / 2));
+                    if slashes % 2 == 1 {
+                        arg.push('"');
+                        chars.next();
+                    }
+                } else {
+                    arg.push_str(&"\\".repeat(slashes));
+                }
+                in_arg = true;
+            }
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                arg.push('"');
+                chars.next();
+            }
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            c => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quoted {
+        anyhow_ext::bail!("Command has an unclosed quote");
+    }
+    if in_arg {
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+/// Splits a hook command into arguments, following the quoting rules of the
+/// current OS.
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    #[cfg(windows)]
+    {
+        split_windows(command)
+    }
+    #[cfg(not(windows))]
+    {
+        split_unix(command)
+    }
+}
+
+/// Reads a pipe on its own thread, so a hook cannot block by filling one
+/// pipe while we wait on the other. The output read so far stays available
+/// if the hook is killed while something it started holds the pipe open.
+fn drain(
+    pipe: Option<impl Read + Send + 'static>,
+) -> (Arc<Mutex<Vec<u8>>>, thread::JoinHandle<()>) {
+    let buf = Arc::new(Mutex::new(vec![]));
+    let handle = thread::spawn({
+        let buf = buf.clone();
+        move || {
+            let Some(mut pipe) = pipe else {
+                return;
+            };
+            let mut chunk = [0; 4096];
+            while let Ok(read @ 1..) = pipe.read(&mut chunk) {
+                buf.lock().extend_from_slice(&chunk[..read]);
+            }
+        }
+    });
+    (buf, handle)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookOutput {
+    /// Exit code, if the hook exited normally.
+    pub code: Option<i32>,
+    pub success: bool,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookOutput {
+    /// Adds the captured output to the log, one entry per line.
+    pub fn log(&self, label: &str) {
+        for line in self.stdout.lines().filter(|l| !l.trim().is_empty()) {
+            log::info!("[{label}] {line}");
+        }
+        for line in self.stderr.lines().filter(|l| !l.trim().is_empty()) {
+            log::warn!("[{label}] {line}");
+        }
+    }
+
+    fn check(&self, timeout: u64) -> Result<()> {
+        if self.timed_out {
+            anyhow_ext::bail!("Timed out after {timeout} seconds");
+        } else if !self.success {
+            match self.code {
+                Some(code) => anyhow_ext::bail!("Exited with code {code}"),
+                None => anyhow_ext::bail!("Was terminated by a signal"),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn wait(child: &mut Child, timeout: u64) -> Result<(Option<std::process::ExitStatus>, bool)> {
+    let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_secs(timeout));
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((Some(status), false));
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            child.kill().ok();
+            child.wait()?;
+            return Ok((None, true));
+        }
+        thread::sleep(POLL);
+    }
+}
+
+/// Runs one hook to completion or until it times out, capturing its output.
+pub fn run_hook(hook: &DeployHook, ctx: &HookContext) -> Result<HookOutput> {
+    let args = split_command(&hook.command)?;
+    let (program, args) = args.split_first().context("Hook has no command")?;
+    let program = ctx.fill(program);
+    let mut command = Command::new(&program);
+    command
+        .args(args.iter().map(|arg| ctx.fill(arg)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = hook.cwd.as_ref().filter(|cwd| !cwd.as_os_str().is_empty()) {
+        command.current_dir(cwd);
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to start {program}"))?;
+    let (stdout, stdout_reader) = drain(child.stdout.take());
+    let (stderr, stderr_reader) = drain(child.stderr.take());
+    let (status, timed_out) = wait(&mut child, hook.timeout)?;
+    if !timed_out {
+        stdout_reader.join().ok();
+        stderr_reader.join().ok();
+    }
+    let text = |buf: &Mutex<Vec<u8>>| String::from_utf8_lossy(&buf.lock()).into_owned();
+    Ok(HookOutput {
+        code: status.and_then(|status| status.code()),
+        success: status.is_some_and(|status| status.success()),
+        timed_out,
+        stdout: text(&stdout),
+        stderr: text(&stderr),
+    })
+}
+
+/// Runs one hook, adding its output to the log, and fails if the hook did.
+pub fn run_logged(hook: &DeployHook, ctx: &HookContext) -> Result<()> {
+    let output = run_hook(hook, ctx)?;
+    output.log(hook.label());
+    output.check(hook.timeout)
+}
+
+/// Runs each hook in order after a deployment. A failed hook is reported as
+/// a warning, unless it is required, in which case the remaining hooks are
+/// skipped and the error is returned.
+pub fn run_all(hooks: &[DeployHook], ctx: &HookContext) -> Result<()> {
+    for hook in hooks {
+        let label = hook.label();
+        if hook.only_on_success && !ctx.success {
+            log::info!("Skipping deploy hook {label} because deployment failed");
+            continue;
+        }
+        log::info!("Running deploy hook {label}");
+        match run_logged(hook, ctx) {
+            Err(e) if hook.required => {
+                return Err(e.context(format!("Required deploy hook {label} failed")));
+            }
+            Err(e) => log::warn!("Deploy hook {label} failed: {e:?}"),
+            Ok(()) => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn ctx(output: &Path, success: bool) -> HookContext<'_> {
+        HookContext {
+            output,
+            profile: "My Profile",
+            platform: Platform::Switch,
+            changed: 12,
+            success,
+        }
+    }
+
+    fn hook(command: &str) -> DeployHook {
+        DeployHook {
+            command: command.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn templates() {
+        let ctx = ctx(Path::new("/tmp/out"), true);
+        assert_eq!(
+            ctx.fill("--dest={output}/{platform}"),
+            "--dest=/tmp/out/switch"
+        );
+        assert_eq!(
+            ctx.fill("{profile}: {changed} files"),
+            "My Profile: 12 files"
+        );
+        assert_eq!(ctx.fill("{unknown} {"), "{unknown} {");
+        assert_eq!(ctx.fill("{{changed}}"), "{12}");
+        // Values are not filled again
+        let ctx = HookContext {
+            profile: "{output}",
+            ..ctx
+        };
+        assert_eq!(ctx.fill("{profile}"), "{output}");
+    }
+
+    #[test]
+    fn splitting() {
+        assert_eq!(
+            split_unix(r#"rsync -a "{output}/" 'deck:/home/deck/My Mods' a\ b"#).unwrap(),
+            ["rsync", "-a", "{output}/", "deck:/home/deck/My Mods", "a b"]
+        );
+        assert!(split_unix(r#"echo "unclosed"#).is_err());
+        assert_eq!(
+            split_windows(r#"robocopy "C:\Program Files\Out" D:\Backup\ /MIR"#).unwrap(),
+            ["robocopy", r"C:\Program Files\Out", r"D:\Backup\", "/MIR"]
+        );
+        assert_eq!(split_windows(r#"a\\"b c" d\"e "f""g" "" "#).unwrap(), [
+            r"a\b c", r#"d"e"#, r#"f"g"#, ""
+        ]);
+        assert!(split_windows(r#"echo "unclosed"#).is_err());
+        assert_eq!(split_windows("  ").unwrap(), Vec::<String>::new());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ctx = ctx(tmp.path(), true);
+        let output = run_hook(
+            &hook("sh -c 'printf %s \"$1\"; echo err >&2; exit 3' sh {profile}"),
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(output.stdout, "My Profile");
+        assert_eq!(output.stderr, "err\n");
+        assert_eq!(output.code, Some(3));
+        assert!(!output.success && !output.timed_out);
+        assert!(output.check(60).is_err());
+
+        let output = run_hook(
+            &DeployHook {
+                cwd: Some(tmp.path().into()),
+                ..hook("pwd")
+            },
+            &ctx,
+        )
+        .unwrap();
+        assert!(output.success);
+        assert_eq!(
+            Path::new(output.stdout.trim()).canonicalize().unwrap(),
+            tmp.path().canonicalize().unwrap()
+        );
+        assert!(run_hook(&hook("ukmm-hook-which-does-not-exist"), &ctx).is_err());
+        assert!(run_hook(&hook(""), &ctx).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let timer = Instant::now();
+        let output = run_hook(
+            &DeployHook {
+                timeout: 1,
+                ..hook("sleep 30")
+            },
+            &ctx(tmp.path(), true),
+        )
+        .unwrap();
+        assert!(output.timed_out);
+        assert!(!output.success);
+        assert!(timer.elapsed() < Duration::from_secs(10));
+        assert!(output.check(1).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ordering_and_failures() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log = tmp.path().join("log");
+        let append = |text: &str| hook(&format!("sh -c 'echo {text} >> \"$1\"' sh {{output}}/log"));
+        let hooks = [
+            append("first"),
+            hook("false"),
+            DeployHook {
+                only_on_success: false,
+                ..append("always")
+            },
+            append("last"),
+        ];
+        run_all(&hooks, &ctx(tmp.path(), true)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "first\nalways\nlast\n"
+        );
+
+        std::fs::remove_file(&log).unwrap();
+        run_all(&hooks, &ctx(tmp.path(), false)).unwrap();
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "always\n");
+
+        std::fs::remove_file(&log).unwrap();
+        let mut hooks = hooks;
+        hooks[1].required = true;
+        let err = run_all(&hooks, &ctx(tmp.path(), true)).unwrap_err();
+        assert!(format!("{err:?}").contains("Required deploy hook false failed"));
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "first\n");
+    }
+}
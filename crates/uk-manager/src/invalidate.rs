@@ -0,0 +1,98 @@
+//! Tells the caches built from installed mods what changed, so each drops
+//! only the entries a change makes stale rather than everything it holds.
+//! Changes are published on the mod manager's [`Bus`] where they are made,
+//! and each cache subscribes with a handler which forgets just what the
+//! change involves.
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+
+/// Something which can make cached data stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Change {
+    /// A mod was installed, updated, replaced or removed, or its files were
+    /// changed in place.
+    ModChanged(usize),
+    /// Which options of a mod are active changed, or whether it is enabled,
+    /// while its files stayed the same.
+    OptionsChanged(usize),
+    /// The load order, or which end of it wins, changed.
+    OrderChanged,
+    /// The current profile was given another game dump.
+    DumpChanged,
+    /// Another profile became the current one.
+    ProfileSwitched,
+}
+
+/// A cache which drops what a change makes stale.
+pub trait Subscriber: Send + Sync {
+    fn invalidate(&self, change: Change);
+}
+
+/// Passes each change to every cache subscribed. Subscribers are held
+/// weakly, so a cache dropped with its owner needs no unsubscribing.
+#[derive(Default)]
+pub struct Bus {
+    subscribers: Mutex<Vec<Weak<dyn Subscriber>>>,
+}
+
+impl std::fmt::Debug for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bus")
+            .field("subscribers", &self.subscribers.lock().len())
+            .finish()
+    }
+}
+
+impl Bus {
+    pub fn subscribe<S: Subscriber + 'static>(&self, subscriber: &Arc<S>) {
+        let subscriber: Weak<S> = Arc::downgrade(subscriber);
+        self.subscribers.lock().push(subscriber);
+    }
+
+    /// Tells every subscriber of a change. Handlers are called with no lock
+    /// held, so they may publish or subscribe in turn.
+    pub fn publish(&self, change: Change) {
+        log::trace!("Invalidating caches for {change:?}");
+        let subscribers: Vec<Arc<dyn Subscriber>> = {
+            let mut subscribers = self.subscribers.lock();
+            subscribers.retain(|s| s.strong_count() > 0);
+            subscribers.iter().filter_map(Weak::upgrade).collect()
+        };
+        for subscriber in subscribers {
+            subscriber.invalidate(change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Record(Mutex<Vec<Change>>);
+
+    impl Subscriber for Record {
+        fn invalidate(&self, change: Change) {
+            self.0.lock().push(change);
+        }
+    }
+
+    #[test]
+    fn publish() {
+        let bus = Bus::default();
+        let kept = Arc::new(Record::default());
+        let dropped = Arc::new(Record::default());
+        bus.subscribe(&kept);
+        bus.subscribe(&dropped);
+        bus.publish(Change::ModChanged(1));
+        assert_eq!(*dropped.0.lock(), [Change::ModChanged(1)]);
+        drop(dropped);
+        bus.publish(Change::OrderChanged);
+        assert_eq!(*kept.0.lock(), [
+            Change::ModChanged(1),
+            Change::OrderChanged
+        ]);
+        assert_eq!(bus.subscribers.lock().len(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use uk_content::{
+    constants::Language, data::gamedata::GameDataPack, labels::Labels, message::MessagePack,
+    prelude::Resource,
+};
+use uk_reader::ResourceReader;
+
+const GAMEDATA: &str = "Pack/Bootup.pack//GameData/gamedata.ssarc";
+
+static CACHE: LazyLock<RwLock<HashMap<String, Arc<Labels>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+#[derive(Serialize, Deserialize)]
+struct CachedLabels {
+    key:    String,
+    labels: Labels,
+}
+
+fn cache_key(dump: &ResourceReader, lang: Language) -> String {
+    format!("{}|{}", dump.source().host_path().display(), lang)
+}
+
+/// Builds the label tables from the stock message pack and game data in a
+/// dump.
+pub fn build(dump: &ResourceReader, lang: Language) -> Result<Labels> {
+    let messages = MessagePack::from_binary(
+        dump.get_bytes_uncached(lang.message_path().as_str())
+            .with_context(|| format!("Failed to read stock {} texts", lang))?,
+    )?;
+    let gamedata = dump
+        .get_bytes_from_sarc(GAMEDATA)
+        .and_then(GameDataPack::from_binary)
+        .inspect_err(|e| log::warn!("Could not read game data flag names: {e}"))
+        .ok();
+    Ok(Labels::from_parts(&messages, gamedata.as_ref()))
+}
+
+/// Gets the labels for the current profile's dump and language, building them the first
+/// time and caching them on disk after that.
+pub fn load(core: &crate::core::Manager) -> Result<Arc<Labels>> {
+    let settings = core.settings();
+    let config = settings
+        .platform_config()
+        .context("No config for current platform")?;
+    let dump = config.active_dump();
+    let key = cache_key(dump, config.language);
+    if let Some(labels) = CACHE.read().get(&key) {
+        return Ok(labels.clone());
+    }
+    let dir = settings.platform_dir().join("labels");
+    let path = dir.join(format!("{}.json", config.language));
+    let labels = match fs::read(&path)
+        .ok()
+        .and_then(|data| serde_json::from_slice::<CachedLabels>(&data).ok())
+        .filter(|cached| cached.key == key)
+    {
+        Some(cached) => cached.labels,
+        None => {
+            log::info!("Building friendly labels for {}", config.language);
+            let cached = CachedLabels {
+                key:    key.clone(),
+                labels: build(dump, config.language)?,
+            };
+            fs::create_dir_all(&dir)?;
+            fs::write(&path, serde_json::to_vec(&cached)?)?;
+            cached.labels
+        }
+    };
+    let labels = Arc::new(labels);
+    CACHE.write().insert(key, labels.clone());
+    Ok(labels)
+}
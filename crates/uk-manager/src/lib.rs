@@ -1,8 +1,74 @@
 #![deny(clippy::unwrap_used)]
 
+pub mod audit;
+pub mod batch;
+pub mod bisect;
 pub mod bnp;
+pub mod compare;
+pub mod compat;
+pub mod conflicts;
 pub mod core;
 pub mod deploy;
+pub mod diffs;
+pub mod drift;
+pub mod history;
+pub mod hooks;
+pub mod invalidate;
+pub mod labels;
+pub mod lock;
 pub mod mods;
+pub mod preflight;
+pub mod purity;
+pub mod recovery;
 pub mod settings;
+pub mod simulation;
+pub mod summary;
+pub mod texts;
+pub mod tweaks;
+pub mod undo;
 pub mod util;
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+pub(crate) mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use parking_lot::RwLock;
+    use uk_content::{constants::Language, prelude::Endian};
+    use uk_reader::{MemoryROMSource, ResourceReader};
+
+    use crate::settings::{DeployConfig, PlatformSettings, Settings};
+
+    /// A mod manager keeping everything in `storage`, with no platform set
+    /// up. It only holds on to its settings weakly, so they are returned too.
+    pub fn manager(storage: &Path) -> (Arc<RwLock<Settings>>, crate::mods::Manager) {
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: storage.to_path_buf(),
+            ..Default::default()
+        }));
+        let manager = crate::mods::Manager::init(&settings).unwrap();
+        (settings, manager)
+    }
+
+    /// Settings for Wii U keeping everything in `storage`, with an empty dump
+    /// in memory and the Default profile.
+    pub fn wiiu_settings(
+        storage: &Path,
+        deploy_config: Option<DeployConfig>,
+    ) -> Arc<RwLock<Settings>> {
+        Arc::new(RwLock::new(Settings {
+            storage_dir: storage.to_path_buf(),
+            wiiu_config: Some(PlatformSettings {
+                language: Language::USen,
+                profile: "Default".into(),
+                dump: Arc::new(ResourceReader::from_memory(MemoryROMSource::new(
+                    Endian::Big,
+                ))),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
+                deploy_config,
+            }),
+            ..Default::default()
+        }))
+    }
+}
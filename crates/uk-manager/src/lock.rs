@@ -0,0 +1,356 @@
+//! Locked deployments and snapshots of merged files.
+//!
+//! Locking freezes the deployment as it is, say before a long play session or
+//! a speedrun, so that nothing changes it by accident. While a platform is
+//! locked, applying, remerging and deploying all fail with [`Locked`], and
+//! drift is checked against the deployed files as they were when it was
+//! locked. The deployment folder is shared by every profile of a platform, so
+//! the lock is too, though it remembers which profile was current.
+//!
+//! Snapshots are named copies of a profile's merged folder. Restoring one
+//! puts its files back as they were, ready to deploy without merging anything.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+
+use crate::{
+    drift::{DeployedFile, DeployedLog},
+    settings::Settings,
+    util,
+};
+
+const LOCK_FILE: &str = "lock.json";
+const SNAPSHOTS_DIR: &str = "snapshots";
+/// The file in a snapshot's folder which says what it is.
+const SNAPSHOT_INFO: &str = "snapshot.yml";
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "The deployment is locked for profile {profile}, so it cannot be {action}. Unlock it first if \
+     you want to change it."
+)]
+pub struct Locked {
+    pub profile: String,
+    /// What was blocked, as in "it cannot be …".
+    pub action:  &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployLock {
+    /// The profile which was current when the deployment was locked.
+    pub profile: String,
+    /// When it was locked, in milliseconds since the Unix epoch.
+    pub time: u64,
+    /// The deployed files as they were when locked.
+    files: BTreeMap<String, DeployedFile>,
+}
+
+impl DeployLock {
+    fn path(settings: &Settings) -> PathBuf {
+        settings.platform_dir().join(LOCK_FILE)
+    }
+
+    /// The lock on the current platform's deployment, if it has one. A lock
+    /// which cannot be read is treated as none, with a warning.
+    pub fn load(settings: &Settings) -> Option<Self> {
+        let data = fs::read(Self::path(settings)).ok()?;
+        serde_json::from_slice(&data)
+            .inspect_err(|e| log::warn!("Could not read deployment lock: {e}"))
+            .ok()
+    }
+
+    /// Locks the current platform's deployment as it was last deployed.
+    pub fn lock(settings: &Settings) -> Result<Self> {
+        let lock = Self {
+            profile: settings
+                .platform_config()
+                .map(|c| c.profile.clone())
+                .unwrap_or_else(|| "Default".into()),
+            time:    now_millis(),
+            files:   DeployedLog::for_settings(settings).files().clone(),
+        };
+        let path = Self::path(settings);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(&lock)?).context("Failed to save deployment lock")?;
+        log::info!("Locked deployment for profile {}", lock.profile);
+        Ok(lock)
+    }
+
+    pub fn unlock(settings: &Settings) -> Result<()> {
+        let path = Self::path(settings);
+        if path.exists() {
+            fs::remove_file(path).context("Failed to remove deployment lock")?;
+            log::info!("Unlocked deployment");
+        }
+        Ok(())
+    }
+
+    /// Fails with [`Locked`] if the current platform's deployment is locked.
+    pub fn guard(settings: &Settings, action: &'static str) -> Result<()> {
+        match Self::load(settings) {
+            Some(lock) => {
+                Err(Locked {
+                    profile: lock.profile,
+                    action,
+                }
+                .into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The deployed files as they were when locked, to check for drift.
+    pub fn records(&self) -> DeployedLog {
+        DeployedLog::detached(self.files.clone())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name:    String,
+    /// The profile whose merged files were copied.
+    pub profile: String,
+    /// When it was taken, in milliseconds since the Unix epoch.
+    pub time:    u64,
+    /// The folder holding it.
+    #[serde(skip)]
+    pub dir:     PathBuf,
+}
+
+impl Snapshot {
+    /// The copy of the merged folder.
+    pub fn merged(&self) -> PathBuf {
+        self.dir.join("merged")
+    }
+
+    fn read(dir: &Path) -> Result<Self> {
+        let mut snapshot: Self =
+            serde_yaml::from_str(&fs::read_to_string(dir.join(SNAPSHOT_INFO))?)?;
+        snapshot.dir = dir.to_path_buf();
+        Ok(snapshot)
+    }
+
+    pub fn delete(&self) -> Result<()> {
+        util::remove_dir_all(&self.dir)
+            .with_context(|| format!("Failed to delete snapshot {}", self.name))
+    }
+}
+
+/// The snapshots for the current platform, newest first. Any which cannot be
+/// read are left out.
+pub fn snapshots(settings: &Settings) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = fs::read_dir(settings.platform_dir().join(SNAPSHOTS_DIR))
+        .into_iter()
+        .flatten()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            Snapshot::read(&entry.path())
+                .inspect_err(|e| {
+                    log::warn!(
+                        "Failed to read snapshot at {}: {e:?}",
+                        entry.path().display()
+                    )
+                })
+                .ok()
+        })
+        .collect();
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.time));
+    snapshots
+}
+
+/// Copies the current profile's merged folder to a new snapshot.
+pub fn create_snapshot(settings: &Settings, name: &str) -> Result<Snapshot> {
+    let name = name.trim();
+    let folder = sanitise_file_name::sanitise(name);
+    if folder.is_empty() {
+        anyhow_ext::bail!("Snapshot name cannot be empty");
+    }
+    let dir = settings.platform_dir().join(SNAPSHOTS_DIR).join(&folder);
+    if dir.exists() {
+        anyhow_ext::bail!("A snapshot named {name} already exists");
+    }
+    let merged = settings.merged_dir();
+    if !merged.exists() {
+        anyhow_ext::bail!("There are no merged files to take a snapshot of");
+    }
+    let snapshot = Snapshot {
+        name: name.into(),
+        profile: settings
+            .platform_config()
+            .map(|c| c.profile.clone())
+            .unwrap_or_else(|| "Default".into()),
+        time: now_millis(),
+        dir,
+    };
+    log::info!("Copying merged files to snapshot {name}");
+    let copy = || -> Result<()> {
+        fs::create_dir_all(&snapshot.dir)?;
+        dircpy::copy_dir(&merged, snapshot.merged())?;
+        fs::write(
+            snapshot.dir.join(SNAPSHOT_INFO),
+            serde_yaml::to_string(&snapshot)?,
+        )?;
+        Ok(())
+    };
+    if let Err(e) = copy() {
+        util::remove_dir_all(&snapshot.dir).unwrap_or(());
+        return Err(e.context("Failed to copy merged files to snapshot"));
+    }
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use uk_mod::Manifest;
+
+    use super::*;
+    use crate::{core::Manager, drift::DriftCheck, settings::DeployConfig, tests::wiiu_settings};
+
+    const FILE: &str = "Model/Test.sbfres";
+
+    fn manager(tmp: &Path) -> Manager {
+        Manager::with_settings(wiiu_settings(
+            &tmp.join("storage"),
+            Some(DeployConfig {
+                output: tmp.join("output"),
+                drift_check: DriftCheck::Full,
+                ..Default::default()
+            }),
+        ))
+        .unwrap()
+    }
+
+    fn write_merged(core: &Manager, file: &str, data: &[u8]) {
+        let path = core.settings().merged_dir().join("content").join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    /// Queues the merged files to be deployed by restoring a snapshot of
+    /// them.
+    fn queue(core: &Manager) -> Snapshot {
+        let snapshot = create_snapshot(&core.settings(), "Queued").unwrap();
+        core.deploy_manager().restore_snapshot(&snapshot).unwrap();
+        assert!(core.deploy_manager().pending());
+        snapshot
+    }
+
+    fn blocked(result: Result<()>) -> &'static str {
+        result
+            .unwrap_err()
+            .chain()
+            .find_map(|e| e.downcast_ref::<Locked>())
+            .expect("Not blocked by the lock")
+            .action
+    }
+
+    #[test]
+    fn blocking() {
+        let tmp = tempfile::tempdir().unwrap();
+        let core = manager(tmp.path());
+        write_merged(&core, FILE, b"merged");
+        let snapshot = queue(&core);
+        let deployer = core.deploy_manager();
+        deployer.lock().unwrap();
+        assert_eq!(deployer.locked().unwrap().profile, "Default");
+
+        assert_eq!(blocked(deployer.apply(None)), "remerged");
+        assert_eq!(
+            blocked(deployer.apply(Some(Manifest::default()))),
+            "changed"
+        );
+        assert_eq!(blocked(deployer.deploy()), "deployed");
+        assert_eq!(
+            blocked(deployer.deploy_only(&[crate::deploy::Half::Aoc])),
+            "deployed"
+        );
+        assert_eq!(
+            blocked(deployer.restore_snapshot(&snapshot)),
+            "replaced by a snapshot"
+        );
+        // Nothing reached the deployment, and it is still waiting
+        assert!(!tmp.path().join("output/content").join(FILE).exists());
+        assert!(deployer.pending());
+        drop(deployer);
+
+        // The lock is kept across restarts
+        let core = manager(tmp.path());
+        assert!(core.deploy_manager().locked().is_some());
+        assert!(core.deploy_manager().deploy().is_err());
+
+        core.deploy_manager().unlock().unwrap();
+        assert!(core.deploy_manager().locked().is_none());
+        core.deploy_manager().deploy().unwrap();
+        assert!(tmp.path().join("output/content").join(FILE).is_file());
+        // Unlocking twice is harmless
+        core.deploy_manager().unlock().unwrap();
+    }
+
+    #[test]
+    fn drift_against_lock() {
+        let tmp = tempfile::tempdir().unwrap();
+        let core = manager(tmp.path());
+        write_merged(&core, FILE, b"merged");
+        queue(&core);
+        core.deploy_manager().deploy().unwrap();
+        core.deploy_manager().lock().unwrap();
+        assert!(core.deploy_manager().check_drift().unwrap().is_empty());
+
+        // Even with the deployed records gone, changes are found against the
+        // lock
+        let mut records = DeployedLog::for_settings(&core.settings());
+        records.clear();
+        records.save().unwrap();
+        fs::write(tmp.path().join("output/content").join(FILE), b"edited").unwrap();
+        let drift = core.deploy_manager().check_drift().unwrap();
+        assert_eq!(drift.len(), 1);
+        assert_eq!(drift[0].file, format!("content/{FILE}"));
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        const EXTRA: &str = "Model/Extra.sbfres";
+        let tmp = tempfile::tempdir().unwrap();
+        let core = manager(tmp.path());
+        assert!(create_snapshot(&core.settings(), "Empty").is_err());
+        write_merged(&core, FILE, b"release");
+        let snapshot = create_snapshot(&core.settings(), " Release 1 ").unwrap();
+        assert_eq!(snapshot.name, "Release 1");
+        assert!(create_snapshot(&core.settings(), "Release 1").is_err());
+        assert!(create_snapshot(&core.settings(), "  ").is_err());
+        assert_eq!(snapshots(&core.settings()), vec![snapshot.clone()]);
+
+        // Later merges change the files, then the snapshot puts them back
+        write_merged(&core, FILE, b"changed");
+        write_merged(&core, EXTRA, b"extra");
+        core.deploy_manager().restore_snapshot(&snapshot).unwrap();
+        let merged = core.settings().merged_dir().join("content");
+        assert_eq!(fs::read(merged.join(FILE)).unwrap(), b"release");
+        assert!(!merged.join(EXTRA).exists());
+        core.deploy_manager().deploy().unwrap();
+        let output = tmp.path().join("output/content");
+        assert_eq!(fs::read(output.join(FILE)).unwrap(), b"release");
+        assert!(!output.join(EXTRA).exists());
+
+        snapshot.delete().unwrap();
+        assert!(snapshots(&core.settings()).is_empty());
+    }
+}
@@ -1,37 +1,74 @@
 use std::{
     hash::{Hash, Hasher},
-    io::BufReader,
     ops::Deref,
     path::{Path, PathBuf},
-    sync::{Arc, LazyLock, Weak},
+    sync::{Arc, Weak},
 };
 
 use anyhow_ext::{Context, Result};
 use dashmap::{mapref::one::MappedRef, DashMap};
 use fs_err as fs;
 use lenient_semver::Version;
-use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use rayon::prelude::*;
 use sanitise_file_name as sfn;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use smartstring::alias::String;
 use uk_content::platform_prefixes;
-use uk_mod::{pack::ModPacker, unpack::ModReader, Manifest, Meta, ModOption};
+use uk_mod::{
+    objects::{self, ObjectStore},
+    pack::ModPacker,
+    progress::Tracker,
+    split::SplitPart,
+    unpack::{peek::PeekCache, ModReader},
+    Manifest, Meta, ModOption,
+};
 
 use crate::{
+    audit::{AuditLog, Event},
+    invalidate::{Bus, Change},
     settings::Settings,
+    simulation::{Ownership, OwnershipCache},
     util::{self, extract_7z, HashMap},
 };
 
-type ManifestCache = LazyLock<RwLock<HashMap<(usize, Vec<PathBuf>), Result<Arc<Manifest>>>>>;
+pub mod availability;
+pub mod dedup;
+mod manifests;
+mod patches;
+pub mod remap;
+pub mod trash;
+pub mod validate;
 
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Mod {
     pub meta: Meta,
+    /// Every option the user has selected, whether or not it is active.
     pub enabled_options: Vec<ModOption>,
+    /// Paths of selected options which are temporarily turned off. They are
+    /// left out when merging but stay selected, so the choice is not lost.
+    #[serde(default)]
+    pub inactive_options: Vec<PathBuf>,
     pub enabled: bool,
     pub path: PathBuf,
+    /// Generated and owned by UKMM, stored unzipped so it can be edited in
+    /// place. See [`Manager::create_local_patch`].
+    #[serde(default)]
+    pub local_patch: bool,
+    /// Names a local patch independently of its display name, so UKMM can
+    /// find the patches it keeps for itself. See [`Manager::local_patch`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch_id: Option<String>,
+    /// Kept with its files and settings but out of the load order, so it is
+    /// neither listed nor merged. See [`Manager::archive`].
+    #[serde(default)]
+    pub archived: bool,
+    /// What became of the selected options when the mod was last updated,
+    /// kept until the user has looked them over. See [`Mod::carry_options`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options_review: Option<remap::OptionRemap>,
     #[serde_as(as = "DisplayFromStr")]
     pub(crate) hash: usize,
 }
@@ -41,8 +78,13 @@ impl std::fmt::Debug for Mod {
         f.debug_struct("Mod")
             .field("meta", &self.meta)
             .field("enabled_options", &self.enabled_options)
+            .field("inactive_options", &self.inactive_options)
             .field("enabled", &self.enabled)
             .field("path", &self.path)
+            .field("local_patch", &self.local_patch)
+            .field("patch_id", &self.patch_id)
+            .field("archived", &self.archived)
+            .field("options_review", &self.options_review)
             .field("hash", &self.hash)
             .finish()
     }
@@ -68,33 +110,60 @@ impl Mod {
             hash: hasher.finish() as usize,
             meta: reader.meta,
             enabled_options: vec![],
+            inactive_options: vec![],
             path: reader.path,
             enabled: false,
+            local_patch: false,
+            patch_id: None,
+            archived: false,
+            options_review: None,
         }
     }
 
+    /// The manifest for the options which are currently active.
     pub fn manifest(&self) -> Result<Arc<Manifest>> {
-        self.manifest_with_options(&self.enabled_options)
+        self.manifest_with_options(self.active_options())
     }
 
-    pub fn manifest_with_options(&self, options: impl AsRef<[ModOption]>) -> Result<Arc<Manifest>> {
-        static MANIFEST_CACHE: ManifestCache = LazyLock::new(|| RwLock::new(HashMap::default()));
-        match MANIFEST_CACHE
-            .write()
-            .entry((
-                self.hash,
-                options.as_ref().iter().map(|o| o.path.clone()).collect(),
-            ))
-            .or_insert_with(|| {
-                ModReader::open(&self.path, options.as_ref()).map(|r| Arc::new(r.manifest))
-            }) {
-            Ok(manifest) => Ok(manifest.clone()),
-            Err(e) => Err(anyhow::format_err!("{:?}", e)),
+    /// Selected options which are not temporarily turned off.
+    pub fn active_options(&self) -> Vec<ModOption> {
+        self.enabled_options
+            .iter()
+            .filter(|o| !self.inactive_options.contains(&o.path))
+            .cloned()
+            .collect()
+    }
+
+    #[inline]
+    pub fn is_option_active(&self, option: &ModOption) -> bool {
+        self.enabled_options.contains(option) && !self.inactive_options.contains(&option.path)
+    }
+
+    /// Turns a selected option on or off without deselecting it.
+    pub fn set_option_active(&mut self, option: &ModOption, active: bool) {
+        self.inactive_options.retain(|p| p != &option.path);
+        if !active && self.enabled_options.contains(option) {
+            self.inactive_options.push(option.path.clone());
         }
     }
 
+    /// Drops inactive entries for options which are no longer selected.
+    fn prune_inactive(&mut self) {
+        let selected = &self.enabled_options;
+        self.inactive_options
+            .retain(|p| selected.iter().any(|o| &o.path == p));
+    }
+
+    /// The manifest with a set of options. Unions are cached for each set,
+    /// whatever order the options are in, until the mod's archive changes.
+    pub fn manifest_with_options(&self, options: impl AsRef<[ModOption]>) -> Result<Arc<Manifest>> {
+        manifests::with_options(self, options.as_ref())
+    }
+
     pub fn state_eq(&self, other: &Self) -> bool {
-        self.enabled == other.enabled && self.enabled_options == other.enabled_options
+        self.enabled == other.enabled
+            && self.enabled_options == other.enabled_options
+            && self.inactive_options == other.inactive_options
     }
 
     #[inline(always)]
@@ -102,6 +171,22 @@ impl Mod {
         self.hash
     }
 
+    /// Selects the options of this version which match those selected for
+    /// an older one, as far as they can be matched, and flags it for review
+    /// if any had to be remapped or dropped. A review of the older version
+    /// not yet confirmed is kept.
+    pub fn carry_options(&mut self, old: &Mod) -> remap::OptionRemap {
+        let (enabled, inactive, report) = remap::remap(old, &self.meta);
+        self.enabled_options = enabled;
+        self.inactive_options = inactive;
+        self.options_review = if report.needs_review() {
+            Some(report.clone())
+        } else {
+            old.options_review.clone()
+        };
+        report
+    }
+
     pub fn enable_default_options(&mut self) {
         if !self.meta.options.is_empty() {
             for group in self.meta.options.iter_mut() {
@@ -149,10 +234,66 @@ impl LookupMod for usize {
     }
 }
 
+/// An installed mod which matches one about to be installed.
+#[derive(Debug, Clone)]
+pub enum Duplicate {
+    /// The same contents are already installed. If `in_profile` is false, the
+    /// mod is only used by other profiles.
+    Identical { mod_: Mod, in_profile: bool },
+    /// A mod with the same name but different contents is in the current
+    /// profile.
+    Changed(Mod),
+}
+
+/// Which end of the mod list wins when mods change the same file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Priority {
+    /// Mods lower in the list are merged later and win. Profiles saved
+    /// before this setting existed all work this way.
+    #[default]
+    LowerWins,
+    HigherWins,
+}
+
+impl Priority {
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::LowerWins => "Lower in list wins",
+            Priority::HigherWins => "Higher in list wins",
+        }
+    }
+
+    /// An arrow pointing to the winning end of the list.
+    pub fn arrow(self) -> &'static str {
+        match self {
+            Priority::LowerWins => "⬇",
+            Priority::HigherWins => "⬆",
+        }
+    }
+
+    pub fn flipped(self) -> Self {
+        match self {
+            Priority::LowerWins => Priority::HigherWins,
+            Priority::HigherWins => Priority::LowerWins,
+        }
+    }
+
+    /// Puts items from list order into merge order, where each item
+    /// overrides the ones before it.
+    pub fn merge_order<T>(self, mut items: Vec<T>) -> Vec<T> {
+        if self == Priority::HigherWins {
+            items.reverse();
+        }
+        items
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Profile {
     mods: RwLock<HashMap<usize, Mod>>,
     load_order: RwLock<Vec<usize>>,
+    #[serde(default)]
+    priority: RwLock<Priority>,
 }
 
 impl Clone for Profile {
@@ -160,6 +301,7 @@ impl Clone for Profile {
         Self {
             mods: RwLock::new(self.mods.read().clone()),
             load_order: RwLock::new(self.load_order.read().clone()),
+            priority: RwLock::new(*self.priority.read()),
         }
     }
 }
@@ -173,7 +315,6 @@ impl Profile {
         self.mods.write()
     }
 
-    #[allow(unused)]
     pub fn load_order(&self) -> RwLockReadGuard<Vec<usize>> {
         self.load_order.read()
     }
@@ -182,12 +323,58 @@ impl Profile {
         self.load_order.write()
     }
 
+    pub fn priority(&self) -> Priority {
+        *self.priority.read()
+    }
+
     pub fn iter(self_: MappedRef<'_, String, Profile, Profile>) -> ModIterator<'_> {
         ModIterator {
             profile: self_,
             index:   0,
         }
     }
+
+    /// Makes mod paths relative to the storage folder, so it can be moved
+    /// without breaking them. Paths under `old_root`, where the folder used to
+    /// be, are rebased, and mods stored anywhere else are looked for by file
+    /// name in `mods_dir`. Returns how many paths changed.
+    pub(crate) fn relativize_paths(
+        &self,
+        storage: &Path,
+        old_root: &Path,
+        mods_dir: &Path,
+    ) -> usize {
+        let mut changed = 0;
+        for mod_ in self.mods.write().values_mut() {
+            if mod_.path.is_relative() {
+                continue;
+            }
+            let relative = match mod_.path.strip_prefix(old_root) {
+                Ok(relative) => Some(relative.to_path_buf()),
+                Err(_) => {
+                    mod_.path
+                        .file_name()
+                        .map(|name| mods_dir.join(name))
+                        .filter(|path| path.exists())
+                        .and_then(|path| path.strip_prefix(storage).map(Path::to_path_buf).ok())
+                }
+            };
+            if let Some(relative) = relative {
+                mod_.path = relative;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Resolves mod paths stored relative to the storage folder.
+    pub(crate) fn resolve_paths(&self, storage: &Path) {
+        for mod_ in self.mods.write().values_mut() {
+            if mod_.path.is_relative() {
+                mod_.path = storage.join(&mod_.path);
+            }
+        }
+    }
 }
 
 pub struct ModIterator<'a> {
@@ -214,9 +401,29 @@ impl<'a> Iterator for ModIterator<'a> {
 #[derive(Debug)]
 pub struct Manager {
     dir: PathBuf,
+    storage: PathBuf,
+    mods_dir: PathBuf,
+    trash_dir: PathBuf,
+    /// Where the resources of mods stored unpacked are kept.
+    objects: ObjectStore,
     profiles: DashMap<String, Profile>,
+    /// Deleted profiles, keyed by their folders in the trash.
+    trashed: DashMap<String, (trash::Trashed, Profile)>,
     current_profile: String,
     settings: Weak<RwLock<Settings>>,
+    audit: AuditLog,
+    /// Meta and manifests of stored mods, so scanning the library does not
+    /// open every archive.
+    peek: PeekCache,
+    /// Where changes to mods and profiles are published for the caches
+    /// built from them.
+    bus: Arc<Bus>,
+    ownership: Arc<OwnershipCache>,
+    /// Mods found offline when last probed. See [`availability`].
+    offline: Mutex<util::HashSet<usize>>,
+    /// Problems found in the index on load which need the user to agree to
+    /// their repairs.
+    load_issues: Mutex<Vec<validate::Issue>>,
 }
 
 impl Manager {
@@ -242,6 +449,16 @@ impl Manager {
             .map(|f| f)
     }
 
+    #[inline(always)]
+    fn profile_name(&self, profile: Option<&String>) -> String {
+        profile.unwrap_or(&self.current_profile).clone()
+    }
+
+    #[inline(always)]
+    fn is_current(&self, profile: Option<&String>) -> bool {
+        profile.map(|p| *p == self.current_profile).unwrap_or(true)
+    }
+
     pub fn create_profile_if(&self, profile: &str) -> Result<()> {
         let path = self.dir.join(profile);
         if !path.exists() {
@@ -249,13 +466,23 @@ impl Manager {
             fs::create_dir_all(path)?;
             self.profiles.insert(profile.into(), Default::default());
             self.save()?;
+            self.audit.record(Event::ProfileCreated {
+                profile: profile.into(),
+            });
         }
         Ok(())
     }
 
     pub fn set_profile(&mut self, profile: &str) -> Result<()> {
-        self.current_profile = profile.into();
+        let previous = std::mem::replace(&mut self.current_profile, profile.into());
         self.create_profile_if(profile)?;
+        if previous != profile {
+            self.bus.publish(Change::ProfileSwitched);
+            self.audit.record(Event::ProfileChanged {
+                profile: profile.into(),
+                previous,
+            });
+        }
         Ok(())
     }
 
@@ -269,37 +496,137 @@ impl Manager {
             .unwrap_or_else(|| "Default".into());
         log::info!("Current profile: {}", current_profile);
         let path = settings.read().profiles_dir();
+        let storage = settings.read().storage_dir.clone();
+        let mods_dir = settings.read().mods_dir();
+        let trash_dir = settings.read().trash_dir();
+        let objects = ObjectStore::new(settings.read().objects_dir());
+        // Stores made before they were marked are marked on load
+        if objects.root().is_dir() {
+            objects.init()?;
+        }
         let profiles = settings
             .read()
             .profiles()
             .map(|profile| {
                 let profile_path = path.join(profile.as_str()).join("profile.yml");
-                fs::read_to_string(profile_path)
+                let data: Profile = fs::read_to_string(&profile_path)
                     .context("Failed to read profile data")
-                    .and_then(|t| serde_yaml::from_str(&t).context("Failed to parse profile data"))
-                    .map(|v| (profile, v))
+                    .and_then(|t| {
+                        serde_yaml::from_str(&t).context("Failed to parse profile data")
+                    })?;
+                // Profiles saved before mod paths were stored relative to the
+                // storage folder are migrated on load
+                if data.relativize_paths(&storage, &storage, &mods_dir) > 0 {
+                    log::info!("Storing mod paths in profile {profile} relative to storage");
+                    fs::write(&profile_path, serde_yaml::to_string(&data)?)?;
+                }
+                data.resolve_paths(&storage);
+                Ok((profile, data))
             })
             .collect::<Result<_>>()?;
+        let trashed = trash::load(&trash_dir, &storage);
+        let bus = Arc::new(Bus::default());
+        let ownership = Arc::new(OwnershipCache::default());
+        bus.subscribe(&ownership);
         let self_ = Self {
             dir: path,
+            storage,
+            mods_dir,
+            trash_dir,
+            objects,
             profiles,
+            trashed,
             current_profile: current_profile.clone(),
             settings: Arc::downgrade(settings),
+            audit: AuditLog::open(&settings.read()),
+            peek: PeekCache::open(settings.read().resource_cache_dir().join(PeekCache::FILE)),
+            bus,
+            ownership,
+            offline: Mutex::new(Default::default()),
+            load_issues: Mutex::new(vec![]),
         };
         self_.create_profile_if(&current_profile)?;
+        // Offline mods are known of before anything tries to read them
+        self_.probe_availability();
+        self_.validate_on_load()?;
+        self_.migrate_local_patches()?;
+        self_.index_manifests();
         Ok(self_)
     }
 
+    /// Indexes the option manifests of the mods in the current profile from
+    /// the peek cache, so their manifests for any options are found without
+    /// opening them. Mods stored before the cache kept them are read into it
+    /// once here.
+    fn index_manifests(&self) {
+        let mods: Vec<Mod> = self
+            .profile()
+            .mods()
+            .values()
+            .filter(|m| !self.is_offline(m.hash))
+            .cloned()
+            .collect();
+        mods.par_iter().for_each(|mod_| {
+            if let Err(e) = manifests::index(mod_, &self.peek) {
+                log::warn!("Failed to index manifests of {}: {e:?}", mod_.meta.name);
+            }
+        });
+        self.save_peek_cache();
+    }
+
     pub fn save(&self) -> Result<()> {
+        self.save_peek_cache();
+        self.save_profile(&self.current_profile)
+    }
+
+    /// The cached meta and manifests of stored mods, for operations which
+    /// look at the whole library.
+    pub fn peek_cache(&self) -> &PeekCache {
+        &self.peek
+    }
+
+    /// Where changes to mods and profiles are published, for caches kept
+    /// outside the mod manager to subscribe to.
+    pub fn bus(&self) -> &Arc<Bus> {
+        &self.bus
+    }
+
+    /// Which mods change which files in the current profile.
+    pub fn ownership(&self) -> Result<Ownership> {
+        let mods: Vec<Mod> = self.all_mods().collect();
+        self.ownership.get(&mods, self.priority())
+    }
+
+    fn save_peek_cache(&self) {
+        if let Err(e) = self.peek.save() {
+            log::warn!("Failed to save mod peek cache: {e:?}");
+        }
+    }
+
+    fn save_profile(&self, name: &str) -> Result<()> {
+        let profile = self
+            .profiles
+            .get(name)
+            .context("Invalid profile")?
+            .deref()
+            .clone();
+        log::debug!("{:#?}", &profile);
+        profile.relativize_paths(&self.storage, &self.storage, &self.mods_dir);
         fs::write(
-            self.path().join("profile.yml"),
-            serde_yaml::to_string(self.profile().deref())?,
+            self.dir.join(name).join("profile.yml"),
+            serde_yaml::to_string(&profile)?,
         )?;
         log::info!("Saved profile data");
-        log::debug!("{:#?}", &self.profile());
         Ok(())
     }
 
+    /// The mods of a profile in load order, if there is such a profile.
+    pub fn profile_mods(&self, profile: &str) -> Option<Vec<Mod>> {
+        self.profiles
+            .get(profile)
+            .map(|p| Profile::iter(p.map(|p| p)).collect())
+    }
+
     /// Iterate all mods, including disabled, in load order.
     pub fn all_mods(&self) -> ModIterator<'_> {
         Profile::iter(self.profile())
@@ -331,16 +658,70 @@ impl Manager {
         })
     }
 
+    /// Look for an installed mod matching the one at the provided path,
+    /// comparing contents rather than archives so re-downloaded copies are
+    /// found too.
+    pub fn find_duplicate(&self, mod_path: &Path) -> Result<Option<Duplicate>> {
+        let incoming = ModReader::open_peek(mod_path, vec![])?;
+        let current = self.all_mods().collect::<Vec<_>>();
+        let others = self
+            .profiles
+            .iter()
+            .filter(|p| p.key() != &self.current_profile)
+            .flat_map(|p| p.value().mods().values().cloned().collect::<Vec<_>>())
+            .filter(|m| !current.contains(m))
+            .collect::<Vec<_>>();
+        let mut incoming_identity = None;
+        let mut same_name = None;
+        for (mod_, in_profile) in current
+            .iter()
+            .map(|m| (m, true))
+            .chain(others.iter().map(|m| (m, false)))
+        {
+            if in_profile && mod_.meta.name == incoming.meta.name {
+                same_name.get_or_insert_with(|| mod_.clone());
+            }
+            let reader = match ModReader::open_peek_cached(&mod_.path, vec![], &self.peek) {
+                Ok(reader) if reader.manifest == incoming.manifest => reader,
+                _ => continue,
+            };
+            let identity = match incoming_identity {
+                Some(identity) => identity,
+                None => *incoming_identity.insert(incoming.content_identity()?),
+            };
+            match reader.content_identity() {
+                Ok(other) if other == identity => {
+                    return Ok(Some(Duplicate::Identical {
+                        mod_: mod_.clone(),
+                        in_profile,
+                    }));
+                }
+                Ok(_) => (),
+                Err(e) => log::warn!("Could not check {} for duplicates: {e:?}", mod_.meta.name),
+            }
+        }
+        self.save_peek_cache();
+        Ok(same_name.map(Duplicate::Changed))
+    }
+
     /// Add a mod to the list of installed mods. This function assumes that the
     /// mod at the provided path has already been validated.
     pub fn add(&self, mod_path: &Path, profile: Option<&String>) -> Result<Mod> {
+        const COPYING: &str = "Copying mod";
+        const READING: &str = "Reading mod";
+        let progress = Tracker::new(&[(COPYING, 0.9), (READING, 0.1)]);
         let mut old_version = None;
         let mod_name = {
             let peeker = ModReader::open_peek(mod_path, vec![])?;
             let name = peeker.meta.name.as_str();
-            if let Some(mod_) =
-                Profile::iter(self.get_profile(profile)).find(|m| m.meta.name == name)
-            {
+            // Archived mods are looked for too, so they are not installed twice
+            let existing = self
+                .get_profile(profile)
+                .mods()
+                .values()
+                .find(|m| m.meta.name == name)
+                .cloned();
+            if let Some(mod_) = existing {
                 if Version::parse(peeker.meta.version.as_str())
                     .and_then(|pv| Version::parse(mod_.meta.version.as_str()).map(|mv| pv > mv))
                     .map_err(|e| anyhow_ext::anyhow!("{e}"))
@@ -348,57 +729,108 @@ impl Manager {
                 {
                     log::info!("Updating {name} to version {}", peeker.meta.version);
                     old_version = Some(mod_);
+                } else if mod_.archived {
+                    anyhow_ext::bail!(
+                        "Mod \"{}\" already installed, but archived",
+                        peeker.meta.name
+                    );
                 } else {
                     anyhow_ext::bail!("Mod \"{}\" already installed", peeker.meta.name);
                 }
             }
             peeker.meta.name
         };
-        let san_opts: sfn::Options<Option<char>> = sfn::Options {
-            url_safe: true,
-            collapse_replacements: true,
-            ..Default::default()
+        let sanitized = stored_name(&mod_name);
+        let (mods_dir, unpack) = {
+            let settings = self.settings.upgrade().expect("Settings is GONE!");
+            let settings = settings.read();
+            (settings.mods_dir(), settings.unpack_mods)
         };
-        let sanitized = sfn::sanitise_with_options(&mod_name, &san_opts);
-        let stored_path = self
-            .settings
-            .upgrade()
-            .expect("Settings is GONE!")
-            .read()
-            .mods_dir()
-            .join(sanitized + ".zip");
-        if stored_path.exists() && old_version.is_none() {
+        let stored_path = if unpack {
+            mods_dir.join(sanitized)
+        } else {
+            mods_dir.join(sanitized + ".zip")
+        };
+        let already_stored = stored_path.exists() && old_version.is_none();
+        if already_stored {
             log::debug!("Mod already stored, no need to store it");
         } else {
             stored_path.parent().map(fs::create_dir_all).transpose()?;
-            if mod_path.is_file() {
-                fs::copy(mod_path, &stored_path).context("Failed to copy mod to storage folder")?;
+            self.peek.invalidate(&stored_path);
+            if unpack {
+                if stored_path.exists() {
+                    util::remove_dir_all(&stored_path)?;
+                }
+                let stats = objects::store_mod(mod_path, &stored_path, &self.objects)
+                    .context("Failed to store mod unpacked")?;
+                log::debug!(
+                    "Stored {} resources, {} bytes of which were already stored",
+                    stats.files,
+                    stats.shared()
+                );
+            } else if mod_path.is_file() {
+                util::copy_with_progress(mod_path, &stored_path, &progress, COPYING)
+                    .context("Failed to copy mod to storage folder")?;
             } else {
                 dircpy::copy_dir(mod_path, &stored_path)
                     .context("Failed to copy mod to storage folder")?;
             }
         }
-        let reader = ModReader::open_peek(&stored_path, vec![])?;
+        progress.complete(COPYING);
+        let reader = ModReader::open_peek_cached(&stored_path, vec![], &self.peek)?;
+        progress.complete(READING);
         let mut mod_ = Mod::from_reader(reader);
+        if let Err(e) = manifests::index(&mod_, &self.peek) {
+            log::warn!("Failed to index manifests of {}: {e:?}", mod_.meta.name);
+        }
         mod_.enabled = true;
+        if let Some(old_mod) = old_version.as_ref() {
+            let report = mod_.carry_options(old_mod);
+            if report.needs_review() {
+                log::warn!(
+                    "Options of {} changed in version {}:\n{report}",
+                    mod_.meta.name,
+                    mod_.meta.version
+                );
+            }
+        }
         let profile_data = self.get_profile(profile);
         profile_data.load_order_mut().push(mod_.hash);
         profile_data.mods_mut().insert(mod_.hash, mod_.clone());
+        if !already_stored || self.is_current(profile) {
+            self.bus.publish(Change::ModChanged(mod_.hash));
+        }
         if let Some(old_mod) = old_version {
             profile_data.load_order_mut().retain(|h| *h != old_mod.hash);
             profile_data.mods_mut().remove(&old_mod.hash);
+            // Stored another way since, so not written over
+            if old_mod.path != stored_path && !self.in_use(old_mod.hash) {
+                self.delete_stored(&old_mod)?;
+            }
+            self.bus.publish(Change::ModChanged(old_mod.hash));
             log::info!(
                 "Updated mod {} in profile {} to version {}",
                 mod_.meta.name,
                 profile.unwrap_or(&self.current_profile).as_str(),
                 mod_.meta.version
             );
+            self.audit.record(Event::ModUpdated {
+                profile:  self.profile_name(profile),
+                name:     mod_.meta.name.clone(),
+                version:  mod_.meta.version.clone(),
+                previous: old_mod.meta.version,
+            });
         } else {
             log::info!(
                 "Added mod {} to profile {}",
                 mod_.meta.name,
                 profile.unwrap_or(&self.current_profile).as_str()
             );
+            self.audit.record(Event::ModInstalled {
+                profile: self.profile_name(profile),
+                name:    mod_.meta.name.clone(),
+                version: mod_.meta.version.clone(),
+            });
         }
         log::debug!("{:#?}", mod_);
         Ok(mod_)
@@ -410,24 +842,29 @@ impl Manager {
         let mod_ = profile_data.mods_mut().remove(&hash);
         if let Some(mod_) = mod_ {
             let manifest = mod_.manifest()?;
-            // Only delete the mod file if no other profiles are using it
-            if !self
-                .profiles
-                .iter()
-                .any(|p| p.value().mods().contains_key(&hash))
-            {
-                if mod_.path.is_dir() {
-                    util::remove_dir_all(&mod_.path)?;
-                } else {
-                    fs::remove_file(&mod_.path)?;
+            // Only delete the mod file if no other profiles, including those
+            // in the trash, are using it
+            let deleted = !self.in_use(hash);
+            if deleted {
+                self.delete_stored(&mod_)?;
+                if let Err(e) = self.collect_objects() {
+                    log::warn!("Failed to remove unused objects: {e:?}");
                 }
             }
             profile_data.load_order_mut().retain(|m| m != &hash);
+            if deleted || self.is_current(profile) {
+                self.bus.publish(Change::ModChanged(hash));
+            }
             log::info!(
                 "Deleted mod {} from profile {}",
                 mod_.meta.name,
                 profile.unwrap_or(&self.current_profile).as_str()
             );
+            self.audit.record(Event::ModRemoved {
+                profile: self.profile_name(profile),
+                name:    mod_.meta.name,
+                version: mod_.meta.version,
+            });
             Ok(manifest)
         } else {
             log::warn!("Mod with ID {} does not exist, doing nothing", hash);
@@ -435,6 +872,18 @@ impl Manager {
         }
     }
 
+    /// Deletes a mod from storage.
+    fn delete_stored(&self, mod_: &Mod) -> Result<()> {
+        if mod_.path.is_dir() {
+            util::remove_dir_all(&mod_.path)?;
+        } else {
+            fs::remove_file(&mod_.path)?;
+        }
+        self.peek.invalidate(&mod_.path);
+        manifests::forget(mod_.hash);
+        Ok(())
+    }
+
     pub fn replace(&self, mut mod_: Mod, old_hash: usize) -> Result<Mod> {
         let profile_data = self.profile();
         let old_mod = profile_data
@@ -443,14 +892,68 @@ impl Manager {
             .expect("The old mod has to be in the profile");
         mod_.enabled = old_mod.enabled;
         mod_.path = old_mod.path;
+        self.peek.invalidate(&mod_.path);
+        manifests::forget(old_hash);
+        manifests::forget(mod_.hash);
         profile_data.mods_mut().insert(mod_.hash, mod_.clone());
         let mut load_order = profile_data.load_order_mut();
         if let Some(idx) = load_order.iter().position(|m| *m == old_hash) {
             load_order[idx] = mod_.hash;
         }
+        self.bus.publish(Change::ModChanged(old_hash));
+        self.bus.publish(Change::ModChanged(mod_.hash));
+        self.audit.record(Event::ModUpdated {
+            profile:  self.profile_name(None),
+            name:     mod_.meta.name.clone(),
+            version:  mod_.meta.version.clone(),
+            previous: old_mod.meta.version,
+        });
         Ok(mod_)
     }
 
+    /// Replaces a mod with the parts it is split into. The parts take its
+    /// place in the load order and are enabled if it was.
+    pub fn split(&self, mod_: &Mod, parts: &[SplitPart]) -> Result<Vec<Mod>> {
+        let temp = util::get_temp_folder();
+        let paths = {
+            let reader = ModReader::open(&mod_.path, vec![])?;
+            uk_mod::split::split(&reader, parts, &temp)?
+        };
+        let mut added: Vec<Mod> = Vec::with_capacity(paths.len());
+        for path in paths {
+            match self.add(&path, None) {
+                Ok(part) => added.push(part),
+                Err(e) => {
+                    for part in added {
+                        self.del(part, None)?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let profile = self.profile();
+        for part in added.iter_mut() {
+            part.enabled = mod_.enabled;
+            if let Some(stored) = profile.mods_mut().get_mut(&part.hash) {
+                stored.enabled = mod_.enabled;
+            }
+        }
+        {
+            let hashes: Vec<usize> = added.iter().map(|part| part.hash).collect();
+            let mut load_order = profile.load_order_mut();
+            load_order.retain(|hash| !hashes.contains(hash));
+            let index = load_order
+                .iter()
+                .position(|hash| *hash == mod_.hash)
+                .unwrap_or(load_order.len());
+            load_order.splice(index..index, hashes);
+        }
+        drop(profile);
+        self.del(mod_, None)?;
+        log::info!("Split mod {} into {} parts", mod_.meta.name, added.len());
+        Ok(added)
+    }
+
     pub fn set_enabled(
         &self,
         mod_: impl LookupMod,
@@ -461,6 +964,13 @@ impl Manager {
         let manifest;
         let profile_data = self.get_profile(profile);
         if let Some(mod_) = profile_data.mods_mut().get_mut(&hash) {
+            let changed = mod_.enabled != enabled;
+            if changed && enabled && self.is_offline(hash) {
+                anyhow_ext::bail!(
+                    "{} is offline. Copy it to storage before enabling it.",
+                    mod_.meta.name
+                );
+            }
             mod_.enabled = enabled;
             manifest = mod_.manifest()?;
             log::info!(
@@ -469,6 +979,16 @@ impl Manager {
                 mod_.meta.name,
                 profile.unwrap_or(&self.current_profile).as_str()
             );
+            if changed {
+                if self.is_current(profile) {
+                    self.bus.publish(Change::OptionsChanged(hash));
+                }
+                self.audit.record(Event::ModToggled {
+                    profile: self.profile_name(profile),
+                    name: mod_.meta.name.clone(),
+                    enabled,
+                });
+            }
         } else {
             log::warn!("Mod with ID {} does not exist, doing nothing", hash);
             return Ok(Default::default());
@@ -484,8 +1004,24 @@ impl Manager {
         let hash = mod_.as_map_id();
         let manifest;
         if let Some(mod_) = self.profile().mods_mut().get_mut(&hash) {
-            manifest = mod_.manifest_with_options(&options)?;
+            let changed = mod_.enabled_options != options;
             mod_.enabled_options = options;
+            mod_.prune_inactive();
+            // Choosing options is as good as looking them over
+            mod_.options_review = None;
+            manifest = mod_.manifest()?;
+            if changed {
+                self.bus.publish(Change::OptionsChanged(hash));
+                self.audit.record(Event::OptionsChanged {
+                    profile: self.profile_name(None),
+                    name:    mod_.meta.name.clone(),
+                    options: mod_
+                        .enabled_options
+                        .iter()
+                        .map(|o| o.name.clone())
+                        .collect(),
+                });
+            }
         } else {
             log::warn!("Mod with ID {} does not exist, doing nothing", hash);
             return Ok(Default::default());
@@ -493,13 +1029,172 @@ impl Manager {
         Ok(manifest)
     }
 
+    /// Sets which selected options of a mod are temporarily turned off.
+    pub fn set_inactive_options(
+        &self,
+        mod_: impl LookupMod,
+        inactive: Vec<PathBuf>,
+    ) -> Result<Arc<Manifest>> {
+        let hash = mod_.as_map_id();
+        if let Some(mod_) = self.profile().mods_mut().get_mut(&hash) {
+            let previous = std::mem::replace(&mut mod_.inactive_options, inactive);
+            mod_.prune_inactive();
+            if previous != mod_.inactive_options {
+                self.bus.publish(Change::OptionsChanged(hash));
+                self.audit.record(Event::OptionsToggled {
+                    profile:  self.profile_name(None),
+                    name:     mod_.meta.name.clone(),
+                    inactive: mod_
+                        .inactive_options
+                        .iter()
+                        .map(|p| p.to_string_lossy().into())
+                        .collect(),
+                });
+            }
+            mod_.manifest()
+        } else {
+            log::warn!("Mod with ID {} does not exist, doing nothing", hash);
+            Ok(Default::default())
+        }
+    }
+
     pub fn set_order(&self, order: Vec<usize>) {
-        *self.profile().load_order_mut() = order;
+        let profile = self.profile();
+        if *profile.load_order() == order {
+            return;
+        }
+        let mods = profile.mods();
+        self.audit.record(Event::OrderChanged {
+            profile: self.profile_name(None),
+            order:   order
+                .iter()
+                .filter_map(|hash| mods.get(hash).map(|m| m.meta.name.clone()))
+                .collect(),
+        });
+        drop(mods);
+        *profile.load_order_mut() = order;
+        self.bus.publish(Change::OrderChanged);
+    }
+
+    /// Archived mods in the current profile, by name.
+    pub fn archived_mods(&self) -> Vec<Mod> {
+        let mut mods: Vec<Mod> = self
+            .profile()
+            .mods()
+            .values()
+            .filter(|m| m.archived)
+            .cloned()
+            .collect();
+        mods.sort_by(|a, b| a.meta.name.cmp(&b.meta.name));
+        mods
+    }
+
+    /// Takes a mod out of the load order without uninstalling it, keeping its
+    /// files and settings. Only disabled mods can be archived, since an
+    /// enabled one would stay in the merged files with nothing to remove it.
+    pub fn archive(&self, mod_: impl LookupMod) -> Result<()> {
+        let hash = mod_.as_map_id();
+        let profile = self.profile();
+        let mut mods = profile.mods_mut();
+        let Some(mod_) = mods.get_mut(&hash) else {
+            anyhow_ext::bail!("Mod with ID {hash} does not exist");
+        };
+        if mod_.enabled {
+            anyhow_ext::bail!("{} must be disabled before it is archived", mod_.meta.name);
+        }
+        if !mod_.archived {
+            mod_.archived = true;
+            profile.load_order_mut().retain(|h| *h != hash);
+            log::info!("Archived mod {}", mod_.meta.name);
+            self.audit.record(Event::ModArchived {
+                profile:  self.profile_name(None),
+                name:     mod_.meta.name.clone(),
+                archived: true,
+            });
+        }
+        Ok(())
+    }
+
+    /// Puts an archived mod back at the end of the load order. It stays
+    /// disabled until it is turned on.
+    pub fn unarchive(&self, mod_: impl LookupMod) -> Result<Mod> {
+        let hash = mod_.as_map_id();
+        let profile = self.profile();
+        let mut mods = profile.mods_mut();
+        let Some(mod_) = mods.get_mut(&hash) else {
+            anyhow_ext::bail!("Mod with ID {hash} does not exist");
+        };
+        if mod_.archived {
+            mod_.archived = false;
+            let mut load_order = profile.load_order_mut();
+            if !load_order.contains(&hash) {
+                load_order.push(hash);
+            }
+            log::info!("Unarchived mod {}", mod_.meta.name);
+            self.audit.record(Event::ModArchived {
+                profile:  self.profile_name(None),
+                name:     mod_.meta.name.clone(),
+                archived: false,
+            });
+        }
+        Ok(mod_.clone())
     }
 
     pub fn get_mod(&self, hash: usize) -> Option<Mod> {
         self.profile().mods().get(&hash).cloned()
     }
+
+    pub fn priority(&self) -> Priority {
+        self.profile().priority()
+    }
+
+    /// Sets which end of the list wins in the current profile. This changes
+    /// the result of every conflict, so everything must be remerged after.
+    pub fn set_priority(&self, priority: Priority) {
+        let profile = self.profile();
+        if profile.priority() == priority {
+            return;
+        }
+        self.audit.record(Event::PriorityChanged {
+            profile: self.profile_name(None),
+            priority,
+        });
+        *profile.priority.write() = priority;
+        self.bus.publish(Change::OrderChanged);
+    }
+}
+
+/// The file name a mod is stored under, without an extension.
+fn stored_name(name: &str) -> std::string::String {
+    let san_opts: sfn::Options<Option<char>> = sfn::Options {
+        url_safe: true,
+        collapse_replacements: true,
+        ..Default::default()
+    };
+    sfn::sanitise_with_options(name, &san_opts)
+}
+
+/// The files to remerge when a mod's options change from `old` to `new`:
+/// everything touched by either its old or its new active options.
+pub fn options_delta(old: &Mod, new: &Mod) -> Result<Manifest> {
+    delta_with(old, new, |mod_, options| {
+        mod_.manifest_with_options(options)
+    })
+}
+
+pub(crate) fn delta_with(
+    old: &Mod,
+    new: &Mod,
+    manifest: impl Fn(&Mod, Vec<ModOption>) -> Result<Arc<Manifest>>,
+) -> Result<Manifest> {
+    let old_options = old.active_options();
+    let new_options = new.active_options();
+    let mut delta = Manifest::default();
+    if old_options != new_options {
+        delta.extend(manifest(old, old_options)?.as_ref());
+        delta.extend(manifest(new, new_options)?.as_ref());
+    }
+    Ok(delta)
 }
 
 pub fn convert_gfx(
@@ -543,10 +1238,7 @@ pub fn convert_gfx(
         if ext == "ZIP" {
             log::info!("Extracting ZIP file...");
             let tmpdir = util::get_temp_folder();
-            zip::ZipArchive::new(BufReader::new(fs::File::open(path)?))
-                .context("Failed to open ZIP")?
-                .extract(&*tmpdir)
-                .context("Failed to extract ZIP")?;
+            util::extract_zip(path, &tmpdir).context("Failed to extract ZIP")?;
             if meta.is_none() {
                 find_rules(&tmpdir).context("Could not find rules.txt in extracted mod")?
             } else {
@@ -556,7 +1248,7 @@ pub fn convert_gfx(
         } else if ext == "7Z" {
             log::info!("Extracting 7Z file...");
             let tmpdir = util::get_temp_folder();
-            extract_7z(path, &tmpdir).context("Failed to extract 7Z file")?;
+            extract_7z(path, &tmpdir, None).context("Failed to extract 7Z file")?;
             if meta.is_none() {
                 find_rules(&tmpdir).context("Could not find rules.txt in extracted mod")?
             } else {
@@ -605,3 +1297,587 @@ fn san_test() {
     );
     dbg!(sanitized);
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn option(name: &str) -> ModOption {
+        ModOption {
+            name: name.into(),
+            description: String::new(),
+            path: PathBuf::from("options").join(name),
+            requires: vec![],
+            image: None,
+        }
+    }
+
+    fn test_mod() -> Mod {
+        Mod {
+            meta: Meta {
+                api: "1.0.0".into(),
+                format: uk_mod::FORMAT_VERSION,
+                name: "Test Mod".into(),
+                version: "1.0.0".into(),
+                author: String::new(),
+                category: String::new(),
+                description: String::new(),
+                platform: uk_mod::ModPlatform::Universal,
+                url: None,
+                options: vec![],
+                masters: Default::default(),
+            },
+            enabled_options: vec![],
+            inactive_options: vec![],
+            enabled: true,
+            path: "test.zip".into(),
+            local_patch: false,
+            patch_id: None,
+            archived: false,
+            options_review: None,
+            hash: 1,
+        }
+    }
+
+    /// A stand-in for reading a mod's manifest: a base file plus one actor
+    /// per option.
+    fn fake_manifest(_: &Mod, options: Vec<ModOption>) -> Result<Arc<Manifest>> {
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert("Pack/Base.pack".into());
+        for option in options {
+            manifest
+                .content_files
+                .insert(format!("Actor/Pack/{}.sbactorpack", option.name).into());
+        }
+        Ok(Arc::new(manifest))
+    }
+
+    fn touches(delta: &Manifest, option: &str) -> bool {
+        delta
+            .content_files
+            .contains(format!("Actor/Pack/{option}.sbactorpack").as_str())
+    }
+
+    #[test]
+    fn option_transitions() {
+        let opt = option("Hard");
+        let unselected = test_mod();
+        let mut active = unselected.clone();
+        active.enabled_options.push(opt.clone());
+        let mut inactive = active.clone();
+        inactive.set_option_active(&opt, false);
+        assert!(active.is_option_active(&opt));
+        assert!(!inactive.is_option_active(&opt));
+        assert!(inactive.active_options().is_empty());
+        assert_eq!(inactive.enabled_options, active.enabled_options);
+        assert!(!active.state_eq(&inactive));
+
+        for (old, new, label) in [
+            (&unselected, &active, "select"),
+            (&active, &unselected, "deselect"),
+            (&active, &inactive, "deactivate"),
+            (&inactive, &active, "activate"),
+        ] {
+            let delta = delta_with(old, new, fake_manifest).unwrap();
+            assert!(touches(&delta, "Hard"), "{label}");
+            assert!(delta.content_files.contains("Pack/Base.pack"), "{label}");
+        }
+        // Selecting or deselecting an option which is turned off changes
+        // nothing that gets merged
+        assert!(delta_with(&inactive, &unselected, fake_manifest)
+            .unwrap()
+            .is_empty());
+        assert!(delta_with(&active, &active, fake_manifest)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn inactive_pruned() {
+        let (hard, easy) = (option("Hard"), option("Easy"));
+        let mut mod_ = test_mod();
+        // Only selected options can be turned off
+        mod_.set_option_active(&hard, false);
+        assert!(mod_.inactive_options.is_empty());
+        mod_.enabled_options = vec![hard.clone(), easy.clone()];
+        mod_.set_option_active(&hard, false);
+        mod_.set_option_active(&easy, false);
+        mod_.set_option_active(&easy, true);
+        assert_eq!(mod_.inactive_options, vec![hard.path.clone()]);
+        assert_eq!(mod_.active_options(), vec![easy.clone()]);
+        mod_.enabled_options = vec![easy];
+        mod_.prune_inactive();
+        assert!(mod_.inactive_options.is_empty());
+        // Old profiles without the field still load
+        let mut value = serde_json::to_value(&mod_).unwrap();
+        value.as_object_mut().unwrap().remove("inactive_options");
+        let loaded: Mod = serde_json::from_value(value).unwrap();
+        assert!(loaded.inactive_options.is_empty());
+    }
+
+    /// Writes an extracted mod with one option to a folder.
+    fn mod_folder(dir: &Path, name: &str) -> PathBuf {
+        let hard = option("Hard");
+        let mut meta = test_mod().meta;
+        meta.name = name.into();
+        meta.options = vec![uk_mod::OptionGroup::Multiple(uk_mod::MultipleOptionGroup {
+            name: "Difficulty".into(),
+            options: vec![hard.clone()],
+            ..Default::default()
+        })];
+        let path = dir.join(name);
+        let manifest = serde_yaml::to_string(&Manifest::default()).unwrap();
+        fs::create_dir_all(path.join(hard.manifest_path()).parent().unwrap()).unwrap();
+        fs::write(path.join(hard.manifest_path()), &manifest).unwrap();
+        fs::write(path.join("manifest.yml"), &manifest).unwrap();
+        fs::write(path.join("meta.yml"), serde_yaml::to_string(&meta).unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn audit_events() {
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let log = AuditLog::open(&settings.read());
+        let mut seen = 0;
+        // Each call must add exactly one event of the given kind
+        let mut expect = |kind: &str| {
+            let entries = log.entries();
+            assert_eq!(entries.len(), seen + 1, "expected one {kind} event");
+            assert_eq!(entries[seen].event.kind(), kind);
+            seen += 1;
+        };
+        let mut manager = Manager::init(&settings).unwrap();
+        expect("profile_created");
+
+        let first = manager.add(&mod_folder(tmp.path(), "First"), None).unwrap();
+        expect("mod_installed");
+        let second = manager
+            .add(&mod_folder(tmp.path(), "Second"), None)
+            .unwrap();
+        expect("mod_installed");
+
+        manager.set_enabled(first.hash, false, None).unwrap();
+        expect("mod_toggled");
+        manager
+            .set_enabled_options(first.hash, vec![option("Hard")])
+            .unwrap();
+        expect("options_changed");
+        manager
+            .set_inactive_options(first.hash, vec![option("Hard").path])
+            .unwrap();
+        expect("options_toggled");
+        manager.set_order(vec![second.hash, first.hash]);
+        expect("order_changed");
+        manager.set_priority(Priority::HigherWins);
+        expect("priority_changed");
+        manager.archive(first.hash).unwrap();
+        expect("mod_archived");
+        manager.unarchive(first.hash).unwrap();
+        expect("mod_archived");
+        let updated = Mod {
+            hash: 42,
+            ..manager.get_mod(second.hash).unwrap()
+        };
+        manager.replace(updated, second.hash).unwrap();
+        expect("mod_updated");
+        manager.del(42, None).unwrap();
+        expect("mod_removed");
+        manager.create_profile_if("Other").unwrap();
+        expect("profile_created");
+        manager.set_profile("Other").unwrap();
+        expect("profile_changed");
+
+        // Calls which change nothing are not recorded
+        manager.set_profile("Other").unwrap();
+        manager.set_order(vec![]);
+        manager.set_profile("Default").unwrap();
+        expect("profile_changed");
+        manager.set_enabled(first.hash, false, None).unwrap();
+        manager
+            .set_enabled_options(first.hash, vec![option("Hard")])
+            .unwrap();
+        manager.set_order(vec![first.hash]);
+        manager.set_priority(Priority::HigherWins);
+        assert_eq!(log.entries().len(), seen);
+    }
+
+    #[derive(Default)]
+    struct Published(Mutex<Vec<Change>>);
+
+    impl crate::invalidate::Subscriber for Published {
+        fn invalidate(&self, change: Change) {
+            self.0.lock().push(change);
+        }
+    }
+
+    #[test]
+    fn published_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let mut manager = Manager::init(&settings).unwrap();
+        let published = Arc::new(Published::default());
+        manager.bus().subscribe(&published);
+        let take = || std::mem::take(&mut *published.0.lock());
+
+        let first = manager.add(&mod_folder(tmp.path(), "First"), None).unwrap();
+        let second = manager
+            .add(&mod_folder(tmp.path(), "Second"), None)
+            .unwrap();
+        assert_eq!(take(), [
+            Change::ModChanged(first.hash),
+            Change::ModChanged(second.hash)
+        ]);
+        manager.set_enabled(first.hash, false, None).unwrap();
+        manager
+            .set_enabled_options(second.hash, vec![option("Hard")])
+            .unwrap();
+        manager.set_order(vec![second.hash, first.hash]);
+        manager.set_priority(Priority::HigherWins);
+        assert_eq!(take(), [
+            Change::OptionsChanged(first.hash),
+            Change::OptionsChanged(second.hash),
+            Change::OrderChanged,
+            Change::OrderChanged
+        ]);
+
+        // What another profile does with a mod leaves this one's be
+        let other: String = "Other".into();
+        manager.create_profile_if(&other).unwrap();
+        manager
+            .add(&mod_folder(tmp.path(), "First"), Some(&other))
+            .unwrap();
+        manager
+            .set_enabled(first.hash, false, Some(&other))
+            .unwrap();
+        manager.del(first.hash, Some(&other)).unwrap();
+        assert!(take().is_empty());
+
+        manager.set_profile(&other).unwrap();
+        assert_eq!(take(), [Change::ProfileSwitched]);
+        manager.set_profile("Default").unwrap();
+        manager.del(second.hash, None).unwrap();
+        assert_eq!(take(), [
+            Change::ProfileSwitched,
+            Change::ModChanged(second.hash)
+        ]);
+    }
+
+    #[test]
+    fn split_in_place() {
+        use uk_content::resource::ResourceData;
+        use uk_mod::split::SplitFile;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let manager = Manager::init(&settings).unwrap();
+        let files = ["Model/Test.sbfres", "UI/Test.sbfres"];
+        let install = |name: &str, files: &[&str]| {
+            let mut meta = test_mod().meta;
+            meta.name = name.into();
+            let mut manifest = Manifest::default();
+            manifest
+                .content_files
+                .extend(files.iter().map(|f| (*f).into()));
+            let path = uk_mod::pack::pack_resources(
+                tmp.path().join(format!("{name}.zip")),
+                &meta,
+                &manifest,
+                files.iter().map(|f| {
+                    (
+                        uk_content::canonicalize(f),
+                        ResourceData::Binary(name.as_bytes().to_vec()),
+                    )
+                }),
+            )
+            .unwrap();
+            manager.add(&path, None).unwrap()
+        };
+        install("Before", &files[..1]);
+        let mega = install("Mega", &files);
+        install("After", &files[1..]);
+        manager.set_enabled(&mega, false, None).unwrap();
+        let mega = manager.get_mod(mega.hash).unwrap();
+        let parts: Vec<_> = files
+            .iter()
+            .zip(["Models", "Interface"])
+            .map(|(file, name)| {
+                uk_mod::split::SplitPart {
+                    name:  name.into(),
+                    files: [SplitFile {
+                        path: (*file).into(),
+                        aoc:  false,
+                    }]
+                    .into(),
+                }
+            })
+            .collect();
+        let split = manager.split(&mega, &parts).unwrap();
+        assert_eq!(split.len(), 2);
+        let names: Vec<_> = manager.all_mods().map(|m| m.meta.name).collect();
+        assert_eq!(names, [
+            "Before",
+            "Mega (Models)",
+            "Mega (Interface)",
+            "After"
+        ]);
+        assert!(manager
+            .all_mods()
+            .all(|m| m.meta.name.starts_with("Mega") != m.enabled));
+        assert!(manager.get_mod(mega.hash).is_none());
+        assert!(!mega.path.exists());
+
+        // A part which clashes with an installed mod undoes the whole split
+        let mega = install("Mega", &files);
+        let mut parts = parts;
+        parts[0].name = "Meshes".into();
+        assert!(manager.split(&mega, &parts).is_err());
+        let names: Vec<_> = manager.all_mods().map(|m| m.meta.name).collect();
+        assert_eq!(names, [
+            "Before",
+            "Mega (Models)",
+            "Mega (Interface)",
+            "After",
+            "Mega"
+        ]);
+    }
+
+    #[test]
+    fn priority_winners() {
+        use uk_content::{constants::Language, prelude::Endian, resource::ResourceData};
+        use uk_mod::unpack::ModUnpacker;
+        use uk_reader::{MemoryROMSource, ResourceReader};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let file = "Model/Test.sbfres";
+        let dump = Arc::new(ResourceReader::from_memory(MemoryROMSource::new(
+            Endian::Big,
+        )));
+        let open = |name: &str| {
+            let mut meta = test_mod().meta;
+            meta.name = name.into();
+            let mut manifest = Manifest::default();
+            manifest.content_files.insert(file.into());
+            let path = uk_mod::pack::pack_resources(
+                tmp.path().join(format!("{name}.zip")),
+                &meta,
+                &manifest,
+                [(
+                    "Model/Test.bfres".into(),
+                    ResourceData::Binary(name.as_bytes().to_vec()),
+                )],
+            )
+            .unwrap();
+            ModReader::open(&path, vec![]).unwrap()
+        };
+        for (priority, winner) in [
+            (Priority::LowerWins, "Bottom"),
+            (Priority::HigherWins, "Top"),
+        ] {
+            let out = tmp.path().join(format!("{priority:?}"));
+            // The mods as listed, top to bottom
+            let mods = vec![open("Top"), open("Bottom")];
+            ModUnpacker::new(
+                dump.clone(),
+                Endian::Big,
+                Language::USen,
+                priority.merge_order(mods),
+                out.clone(),
+            )
+            .unpack()
+            .unwrap();
+            let data = fs::read(out.join("content").join(file)).unwrap();
+            assert_eq!(
+                roead::yaz0::decompress_if(&data).as_ref(),
+                winner.as_bytes(),
+                "{}",
+                priority.label()
+            );
+        }
+        assert_eq!(Priority::default().flipped(), Priority::HigherWins);
+        // Profiles saved before the setting existed keep their behaviour
+        let profile: Profile = serde_yaml::from_str("mods: {}\nload_order: []\n").unwrap();
+        assert_eq!(profile.priority(), Priority::LowerWins);
+    }
+
+    #[test]
+    fn archive_transitions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let manager = Manager::init(&settings).unwrap();
+        let [first, second, third] = ["First", "Second", "Third"]
+            .map(|name| manager.add(&mod_folder(tmp.path(), name), None).unwrap());
+        let names =
+            |manager: &Manager| -> Vec<_> { manager.all_mods().map(|m| m.meta.name).collect() };
+
+        // Enabled mods have to be disabled first
+        assert!(manager.archive(second.hash).is_err());
+        assert_eq!(names(&manager), ["First", "Second", "Third"]);
+        manager.set_enabled(second.hash, false, None).unwrap();
+        manager.archive(second.hash).unwrap();
+        assert_eq!(names(&manager), ["First", "Third"]);
+        assert!(manager.mods().all(|m| m != second));
+        assert_eq!(manager.archived_mods(), std::slice::from_ref(&second));
+        assert!(manager.get_mod(second.hash).unwrap().archived);
+        assert!(manager.validate().is_empty());
+        // Archiving twice changes nothing, and the mod is not installed again
+        manager.archive(second.hash).unwrap();
+        assert_eq!(manager.profile().load_order().as_slice(), [
+            first.hash, third.hash
+        ]);
+        assert!(manager
+            .add(&mod_folder(tmp.path(), "Second"), None)
+            .is_err());
+
+        // Archived mods are still archived when loaded again
+        manager.save().unwrap();
+        let manager = Manager::init(&settings).unwrap();
+        assert_eq!(manager.archived_mods(), std::slice::from_ref(&second));
+        assert!(second.path.exists());
+
+        // Unarchived mods go to the end, still disabled
+        manager.set_order(vec![third.hash, first.hash]);
+        let unarchived = manager.unarchive(second.hash).unwrap();
+        assert!(!unarchived.archived && !unarchived.enabled);
+        assert_eq!(names(&manager), ["Third", "First", "Second"]);
+        assert!(manager.archived_mods().is_empty());
+        assert!(manager.validate().is_empty());
+    }
+
+    #[test]
+    fn offline_mods() {
+        use availability::Availability;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let manager = Manager::init(&settings).unwrap();
+        let [local, remote] = ["Local", "Remote"]
+            .map(|name| manager.add(&mod_folder(tmp.path(), name), None).unwrap());
+        manager.set_enabled(remote.hash, false, None).unwrap();
+        // The second mod is kept on a share outside storage
+        let share = tmp.path().join("share");
+        fs::create_dir_all(&share).unwrap();
+        let shared_path = share.join("Remote");
+        fs::rename(&remote.path, &shared_path).unwrap();
+        manager
+            .profile()
+            .mods_mut()
+            .get_mut(&remote.hash)
+            .unwrap()
+            .path = shared_path.clone();
+        assert!(manager.probe_availability().is_empty());
+        assert_eq!(manager.availability(local.hash), Availability::Local);
+        assert_eq!(manager.availability(remote.hash), Availability::Remote);
+
+        // A share which cannot be read is offline
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&share, std::fs::Permissions::from_mode(0o000)).unwrap();
+            // Unless running as root, which reads it anyway
+            if fs::read_dir(&shared_path).is_err() {
+                let offline = manager.probe_availability();
+                assert_eq!(offline.len(), 1);
+                assert_eq!(offline[0].hash, remote.hash);
+            }
+            fs::set_permissions(&share, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        // So is one which is disconnected
+        let away = tmp.path().join("away");
+        fs::rename(&share, &away).unwrap();
+        let offline = manager.probe_availability();
+        assert_eq!(offline.len(), 1);
+        assert_eq!(offline[0].hash, remote.hash);
+        assert!(manager.availability(remote.hash).is_offline());
+        // It is skipped by validation rather than taken to be missing, and
+        // cannot be enabled or copied to storage until it is back
+        assert!(manager.validate().is_empty());
+        assert!(manager.set_enabled(remote.hash, true, None).is_err());
+        assert!(manager.hydrate(remote.hash).is_err());
+
+        fs::rename(&away, &share).unwrap();
+        let hydrated = manager.hydrate(remote.hash).unwrap();
+        assert!(hydrated.path.starts_with(tmp.path().join("storage")));
+        assert!(hydrated.path.exists() && shared_path.exists());
+        assert_eq!(manager.availability(remote.hash), Availability::Local);
+        manager.set_enabled(remote.hash, true, None).unwrap();
+        // Every profile with it was saved pointing at the copy
+        let manager = Manager::init(&settings).unwrap();
+        assert_eq!(manager.get_mod(remote.hash).unwrap().path, hydrated.path);
+    }
+
+    #[test]
+    fn options_across_update() {
+        fn version(dir: &Path, version: &str, options: &[ModOption]) -> PathBuf {
+            let mut meta = test_mod().meta;
+            meta.version = version.into();
+            meta.options = vec![uk_mod::OptionGroup::Multiple(uk_mod::MultipleOptionGroup {
+                name: "Difficulty".into(),
+                options: options.to_vec(),
+                ..Default::default()
+            })];
+            let path = dir.join(version).join("Test Mod");
+            let manifest = serde_yaml::to_string(&Manifest::default()).unwrap();
+            for option in options {
+                fs::create_dir_all(path.join(option.manifest_path()).parent().unwrap()).unwrap();
+                fs::write(path.join(option.manifest_path()), &manifest).unwrap();
+            }
+            fs::write(path.join("manifest.yml"), &manifest).unwrap();
+            fs::write(path.join("meta.yml"), serde_yaml::to_string(&meta).unwrap()).unwrap();
+            path
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let manager = Manager::init(&settings).unwrap();
+        let old = manager
+            .add(
+                &version(tmp.path(), "1.0.0", &[option("Hard"), option("Pink")]),
+                None,
+            )
+            .unwrap();
+        manager
+            .set_enabled_options(old.hash, vec![option("Hard"), option("Pink")])
+            .unwrap();
+        // Hard moved to another folder, Pink was dropped, and Easy was added
+        let hard = ModOption {
+            path: "options/HardMode".into(),
+            ..option("Hard")
+        };
+        let new = manager
+            .add(
+                &version(tmp.path(), "1.1.0", &[hard.clone(), option("Easy")]),
+                None,
+            )
+            .unwrap();
+        assert!(manager.get_mod(old.hash).is_none());
+        let new = manager.get_mod(new.hash).unwrap();
+        assert_eq!(new.enabled_options, std::slice::from_ref(&hard));
+        let review = new.options_review.unwrap();
+        assert_eq!(review.remapped.len(), 1);
+        assert_eq!(review.unmatched, [option("Pink")]);
+        assert_eq!(review.added, [option("Easy")]);
+        // Confirming the options clears the flag
+        manager.set_enabled_options(new.hash, vec![hard]).unwrap();
+        assert!(manager.get_mod(new.hash).unwrap().options_review.is_none());
+    }
+}
@@ -0,0 +1,235 @@
+//! Whether the files of each mod can be reached right now. Mods kept outside
+//! the storage folder may be on network shares or drives which sleep or come
+//! and go, and anything touching them then stalls until the system gives up.
+//! Such mods are probed on threads of their own, and any which do not answer
+//! in time are marked offline, so the rest of the library can be worked on
+//! without them until they come back or are hydrated, copied into storage.
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use uk_mod::progress::Tracker;
+
+use super::{stored_name, LookupMod, Manager, Mod};
+use crate::util::{self, HashSet};
+
+/// How long a mod's files have to answer before it is taken to be offline.
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(750);
+
+/// Where a mod's files are, as of when they were last probed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Availability {
+    /// In the storage folder.
+    #[default]
+    Local,
+    /// Elsewhere, and reachable.
+    Remote,
+    /// Elsewhere, and unreachable or too slow to answer.
+    Offline,
+}
+
+impl Availability {
+    #[inline]
+    pub fn is_offline(self) -> bool {
+        self == Availability::Offline
+    }
+}
+
+/// Opens a mod's archive, or lists its folder, which is as much as any use
+/// of it needs first.
+fn open(path: &Path) -> std::io::Result<()> {
+    if fs::metadata(path)?.is_dir() {
+        fs::read_dir(path)?;
+    } else {
+        fs::File::open(path)?;
+    }
+    Ok(())
+}
+
+/// Runs `check` on each path at once, each on its own thread, and waits for
+/// them until `timeout` has passed. Checks still stuck on an unresponsive
+/// volume by then count as failed and are left to finish by themselves.
+fn probe_with(
+    paths: &[PathBuf],
+    timeout: Duration,
+    check: fn(&Path) -> std::io::Result<()>,
+) -> Vec<bool> {
+    let (tx, rx) = mpsc::channel();
+    for (i, path) in paths.iter().enumerate() {
+        let (tx, path) = (tx.clone(), path.clone());
+        thread::spawn(move || {
+            let result = check(&path)
+                .inspect_err(|e| log::debug!("Could not reach {}: {e}", path.display()));
+            tx.send((i, result.is_ok())).ok();
+        });
+    }
+    drop(tx);
+    let deadline = Instant::now() + timeout;
+    let mut reached = vec![false; paths.len()];
+    while let Ok((i, ok)) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        reached[i] = ok;
+    }
+    reached
+}
+
+/// Whether each path can be opened within `timeout`.
+pub fn probe(paths: &[PathBuf], timeout: Duration) -> Vec<bool> {
+    probe_with(paths, timeout, open)
+}
+
+impl Manager {
+    /// Where a mod's files were when last probed. This never touches them,
+    /// so is safe to call from the UI.
+    pub fn availability(&self, mod_: impl LookupMod) -> Availability {
+        let hash = mod_.as_map_id();
+        if self.offline.lock().contains(&hash) {
+            Availability::Offline
+        } else if self
+            .get_mod(hash)
+            .is_some_and(|m| !m.path.starts_with(&self.storage))
+        {
+            Availability::Remote
+        } else {
+            Availability::Local
+        }
+    }
+
+    /// Probes the files of every mod kept outside the storage folder, in any
+    /// profile, all at once, so this takes no longer than [`PROBE_TIMEOUT`]
+    /// however many there are. It blocks for that long, so is not for the UI
+    /// thread. Returns the mods of the current profile found offline.
+    pub fn probe_availability(&self) -> Vec<Mod> {
+        let mut remote: Vec<(usize, PathBuf)> = self
+            .profiles
+            .iter()
+            .flat_map(|profile| {
+                profile
+                    .mods()
+                    .values()
+                    .filter(|m| !m.path.starts_with(&self.storage))
+                    .map(|m| (m.hash, m.path.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        remote.sort_unstable();
+        remote.dedup();
+        let paths: Vec<PathBuf> = remote.iter().map(|(_, path)| path.clone()).collect();
+        let offline: HashSet<usize> = remote
+            .iter()
+            .zip(probe(&paths, PROBE_TIMEOUT))
+            .filter(|(_, reached)| !reached)
+            .map(|((hash, _), _)| *hash)
+            .collect();
+        if !offline.is_empty() {
+            log::warn!("{} mod(s) are offline", offline.len());
+        }
+        *self.offline.lock() = offline;
+        self.offline_mods()
+    }
+
+    /// The mods of the current profile found offline when last probed.
+    pub fn offline_mods(&self) -> Vec<Mod> {
+        let offline = self.offline.lock().clone();
+        self.all_mods()
+            .filter(|m| offline.contains(&m.hash))
+            .collect()
+    }
+
+    pub(crate) fn is_offline(&self, hash: usize) -> bool {
+        self.offline.lock().contains(&hash)
+    }
+
+    /// Copies a mod kept outside the storage folder into it, so it no longer
+    /// depends on the volume it was on, and points every profile with it at
+    /// the copy. The original is left where it was. Copying waits as long as
+    /// the volume takes to wake, so this is for a background task too.
+    pub fn hydrate(&self, mod_: impl LookupMod) -> Result<Mod> {
+        const HYDRATING: &str = "Copying mod to storage";
+        let hash = mod_.as_map_id();
+        let mod_ = self.get_mod(hash).context("Mod not found")?;
+        if mod_.path.starts_with(&self.storage) {
+            return Ok(mod_);
+        }
+        let progress = Tracker::new(&[(HYDRATING, 1.0)]);
+        let is_dir = fs::metadata(&mod_.path)
+            .with_context(|| format!("{} could not be reached", mod_.meta.name))?
+            .is_dir();
+        let name = stored_name(&mod_.meta.name);
+        let stored_path = if is_dir {
+            self.mods_dir.join(name.as_str())
+        } else {
+            self.mods_dir.join(name.clone() + ".zip")
+        };
+        if stored_path.exists() {
+            anyhow_ext::bail!("Another mod is already stored at {}", stored_path.display());
+        }
+        fs::create_dir_all(&self.mods_dir)?;
+        // Copied under another name first, so an interrupted copy is never
+        // taken for the mod
+        let partial = self.mods_dir.join(name + ".hydrating");
+        if partial.exists() {
+            if partial.is_dir() {
+                util::remove_dir_all(&partial)?;
+            } else {
+                fs::remove_file(&partial)?;
+            }
+        }
+        if is_dir {
+            dircpy::copy_dir(&mod_.path, &partial)
+                .with_context(|| format!("Failed to copy {} to storage", mod_.meta.name))?;
+        } else {
+            util::copy_with_progress(&mod_.path, &partial, &progress, HYDRATING)
+                .with_context(|| format!("Failed to copy {} to storage", mod_.meta.name))?;
+        }
+        fs::rename(&partial, &stored_path)?;
+        progress.complete(HYDRATING);
+        let mut changed = vec![];
+        for profile in self.profiles.iter() {
+            if let Some(m) = profile.mods_mut().get_mut(&hash) {
+                m.path = stored_path.clone();
+                changed.push(profile.key().clone());
+            }
+        }
+        for name in changed {
+            self.save_profile(&name)?;
+        }
+        self.offline.lock().remove(&hash);
+        log::info!(
+            "Copied {} from {} to storage",
+            mod_.meta.name,
+            mod_.path.display()
+        );
+        Ok(Mod {
+            path: stored_path,
+            ..mod_
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_timeout() {
+        fn stalled(_: &Path) -> std::io::Result<()> {
+            thread::sleep(Duration::from_secs(5));
+            Ok(())
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = [tmp.path().to_path_buf(), tmp.path().join("Missing.zip")];
+        assert_eq!(probe(&paths, PROBE_TIMEOUT), [true, false]);
+        // A volume which never answers is given up on in time
+        let start = Instant::now();
+        assert_eq!(probe_with(&paths, Duration::from_millis(50), stalled), [
+            false, false
+        ]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
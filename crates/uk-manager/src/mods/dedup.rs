@@ -0,0 +1,289 @@
+//! Mods stored unpacked, with their resources in a shared object store, so a
+//! file several mods have in common takes up space only once. See
+//! [`uk_mod::objects`]. Objects are deleted once no stored mod uses them,
+//! counting the mods of profiles in the trash and uninstalled mods in the
+//! trash, which can still be put back.
+use std::path::{Path, PathBuf};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use uk_mod::objects::{self, OBJECT_INDEX};
+
+use super::{manifests, trash, Manager, Mod};
+use crate::{
+    invalidate::Change,
+    preflight::{human_size, path_size},
+    util,
+};
+
+/// What converting the stored mods to the object store did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DedupReport {
+    /// How many mods were converted.
+    pub mods:   usize,
+    /// The space they took before.
+    pub before: u64,
+    /// The space they take now, counting the objects they added.
+    pub after:  u64,
+}
+
+impl DedupReport {
+    pub fn saved(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+/// Where a mod is stored once converted: the same place, less any `.zip`.
+fn unpacked_path(path: &Path) -> PathBuf {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+    {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+impl Manager {
+    /// Every mod in a profile, including those in the trash.
+    fn every_mod(&self) -> Vec<Mod> {
+        self.profiles
+            .iter()
+            .flat_map(|p| p.mods().values().cloned().collect::<Vec<_>>())
+            .chain(
+                self.trashed
+                    .iter()
+                    .flat_map(|t| t.value().1.mods().values().cloned().collect::<Vec<_>>()),
+            )
+            .collect()
+    }
+
+    /// Deletes the objects no stored mod uses any longer. Returns how many
+    /// bytes that freed.
+    pub fn collect_objects(&self) -> Result<u64> {
+        if !self.objects.root().exists() {
+            return Ok(0);
+        }
+        let mut paths: Vec<PathBuf> = self.every_mod().into_iter().map(|m| m.path).collect();
+        // Mods in storage but in no profile yet, or uninstalled to the trash
+        paths.extend(
+            [&self.mods_dir, &self.trash_dir.join(trash::MODS)]
+                .into_iter()
+                .flat_map(|dir| fs::read_dir(dir).into_iter().flatten())
+                .filter_map(std::result::Result::ok)
+                .map(|entry| entry.path()),
+        );
+        paths.sort();
+        paths.dedup();
+        let live = objects::live_objects(paths.iter().map(PathBuf::as_path))
+            .context("Could not tell which objects are in use")?;
+        let (count, freed) = self.objects.collect_garbage(&live)?;
+        if count > 0 {
+            log::info!(
+                "Removed {count} objects no mod uses, freeing {}",
+                human_size(freed)
+            );
+        }
+        Ok(freed)
+    }
+
+    /// Stores one mod unpacked next to where it is now, and removes the old
+    /// copy. Returns its new path and its size before and after.
+    fn store_unpacked(&self, path: &Path) -> Result<(PathBuf, u64, u64)> {
+        let dest = unpacked_path(path);
+        if dest != path && dest.exists() {
+            anyhow_ext::bail!("{} is in the way", dest.display());
+        }
+        let mut staging = dest.clone().into_os_string();
+        staging.push(".storing");
+        let staging = PathBuf::from(staging);
+        if staging.exists() {
+            util::remove_dir_all(&staging)?;
+        }
+        let stats = objects::store_mod(path, &staging, &self.objects)?;
+        let before = path_size(path);
+        let after = path_size(&staging) + stats.new_bytes;
+        if path.is_dir() {
+            util::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        fs::rename(&staging, &dest)?;
+        Ok((dest, before, after))
+    }
+
+    /// Converts the stored mods of every profile to be stored unpacked, with
+    /// their resources in the object store, and points the profiles at their
+    /// new folders. Local patches are left as they are, since they are
+    /// changed in place. A mod which cannot be converted is left as it was.
+    pub fn deduplicate(&self) -> Result<DedupReport> {
+        let mut mods: Vec<Mod> = self
+            .every_mod()
+            .into_iter()
+            .filter(|m| !m.local_patch && m.path.exists() && !m.path.join(OBJECT_INDEX).exists())
+            .collect();
+        mods.sort_by(|a, b| a.path.cmp(&b.path));
+        mods.dedup_by(|a, b| a.path == b.path);
+        let mut report = DedupReport::default();
+        let mut moved: Vec<(PathBuf, PathBuf)> = vec![];
+        for mod_ in mods {
+            match self.store_unpacked(&mod_.path) {
+                Ok((dest, before, after)) => {
+                    log::info!("Stored {} unpacked", mod_.meta.name);
+                    report.mods += 1;
+                    report.before += before;
+                    report.after += after;
+                    self.peek.invalidate(&mod_.path);
+                    manifests::forget(mod_.hash);
+                    self.bus.publish(Change::ModChanged(mod_.hash));
+                    moved.push((mod_.path, dest));
+                }
+                Err(e) => log::warn!("Could not store {} unpacked: {e:?}", mod_.meta.name),
+            }
+        }
+        let new_path = |path: &Path| {
+            moved
+                .iter()
+                .find(|(old, _)| old == path)
+                .map(|(_, new)| new.clone())
+        };
+        let mut changed = vec![];
+        for profile in self.profiles.iter() {
+            let mut found = false;
+            for mod_ in profile.mods_mut().values_mut() {
+                if let Some(path) = new_path(&mod_.path) {
+                    mod_.path = path;
+                    found = true;
+                }
+            }
+            if found {
+                changed.push(profile.key().clone());
+            }
+        }
+        for profile in changed {
+            self.save_profile(&profile)?;
+        }
+        for trashed in self.trashed.iter() {
+            let (trashed, profile) = trashed.value();
+            let mut found = false;
+            for mod_ in profile.mods_mut().values_mut() {
+                if let Some(path) = new_path(&mod_.path) {
+                    mod_.path = path;
+                    found = true;
+                }
+            }
+            if found {
+                let profile = profile.clone();
+                profile.relativize_paths(&self.storage, &self.storage, &self.mods_dir);
+                fs::write(
+                    trashed.dir.join("profile.yml"),
+                    serde_yaml::to_string(&profile)?,
+                )?;
+            }
+        }
+        self.save_peek_cache();
+        log::info!(
+            "Stored {} mods unpacked, saving {}",
+            report.mods,
+            human_size(report.saved())
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::RwLock;
+    use uk_content::resource::ResourceData;
+    use uk_mod::{pack::pack_resources, Manifest, Meta, ModPlatform};
+
+    use super::*;
+    use crate::settings::Settings;
+
+    /// Data which does not compress, so sharing it saves its whole size.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn package(dir: &Path, name: &str, data: &[u8]) -> PathBuf {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: "Test".into(),
+            category: "Other".into(),
+            description: "A test mod".into(),
+            platform: ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        };
+        let mut manifest = Manifest::default();
+        manifest
+            .content_files
+            .insert("Actor/Pack/Shared.sbactorpack".into());
+        fs::create_dir_all(dir).unwrap();
+        pack_resources(dir.join(format!("{name}.zip")), &meta, &manifest, [(
+            "Actor/Pack/Shared.bactorpack".into(),
+            ResourceData::Binary(data.to_vec()),
+        )])
+        .unwrap()
+    }
+
+    #[test]
+    fn deduplicate_then_uninstall() {
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let manager = Manager::init(&settings).unwrap();
+        let data = noise(64 * 1024);
+        let first = manager
+            .add(&package(&tmp.path().join("in"), "First", &data), None)
+            .unwrap();
+        let second = manager
+            .add(&package(&tmp.path().join("in"), "Second", &data), None)
+            .unwrap();
+        assert!(first.path.is_file());
+
+        let report = manager.deduplicate().unwrap();
+        assert_eq!(report.mods, 2);
+        assert!(report.saved() > 60 * 1024);
+        let first = manager.get_mod(first.hash).unwrap();
+        assert!(first.path.join(OBJECT_INDEX).exists());
+        assert!(!first.path.with_extension("zip").exists());
+        // Nothing is left to convert
+        assert_eq!(manager.deduplicate().unwrap().mods, 0);
+
+        // Mods installed now are stored unpacked as well
+        settings.write().unpack_mods = true;
+        let third = manager
+            .add(&package(&tmp.path().join("in"), "Third", &noise(100)), None)
+            .unwrap();
+        assert!(third.path.join(OBJECT_INDEX).exists());
+        let objects = || path_size(&settings.read().objects_dir());
+        let shared = objects();
+
+        // The shared object goes with the last mod which has it
+        manager.del(&third, None).unwrap();
+        assert!(objects() < shared);
+        manager.del(&first, None).unwrap();
+        assert!(objects() > 0);
+        manager.del(&second, None).unwrap();
+        assert_eq!(objects(), 0);
+    }
+}
@@ -0,0 +1,370 @@
+//! Manifests of installed mods for any selection of their options.
+//!
+//! The main manifest of each mod and the manifest of each of its options are
+//! read once, from the peek cache where it has them or else from the mod
+//! itself, and kept with the options indexed by their sorted paths. A
+//! selection of options is then a bitmask over that index, and the union for
+//! each mask is kept as well, so working out what a change of options
+//! touches never opens the archive. Everything kept for a mod is dropped
+//! when its archive moves or changes size or modified time.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::SystemTime,
+};
+
+use anyhow_ext::Result;
+use dashmap::DashMap;
+use uk_mod::{
+    Manifest, ModOption,
+    unpack::{peek::PeekCache, ModReader},
+};
+
+use super::Mod;
+
+static INDEX: LazyLock<DashMap<usize, Arc<OptionManifests>>> = LazyLock::new(DashMap::new);
+
+/// The size and modified time of a zipped mod. Unzipped mods have none, and
+/// are only read again once forgotten.
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let meta = path.metadata().ok().filter(|m| m.is_file())?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+/// A set of options of one mod, one bit for each option by its place in
+/// [`OptionManifests`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct OptionMask(Vec<u64>);
+
+impl OptionMask {
+    fn insert(&mut self, index: usize) {
+        let (word, bit) = (index / 64, index % 64);
+        if self.0.len() <= word {
+            self.0.resize(word + 1, 0);
+        }
+        self.0[word] |= 1 << bit;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| word * 64 + bit)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct OptionManifests {
+    path:    PathBuf,
+    stamp:   Option<(u64, SystemTime)>,
+    base:    Manifest,
+    /// Option paths, sorted, which give each option its bit.
+    paths:   Vec<PathBuf>,
+    /// The manifest of each option in the order of `paths`.
+    options: Vec<Manifest>,
+    unions:  DashMap<OptionMask, Arc<Manifest>>,
+}
+
+impl OptionManifests {
+    fn new(
+        path: &Path,
+        stamp: Option<(u64, SystemTime)>,
+        base: Manifest,
+        options: BTreeMap<PathBuf, Manifest>,
+    ) -> Self {
+        let (paths, options) = options.into_iter().unzip();
+        Self {
+            path: path.to_path_buf(),
+            stamp,
+            base,
+            paths,
+            options,
+            unions: DashMap::new(),
+        }
+    }
+
+    /// Reads the manifests of a mod from the mod itself. The stamp is taken
+    /// first, so a mod changed while being read is read again next time.
+    fn read(path: &Path) -> Result<Self> {
+        let stamp = stamp(path);
+        let reader = ModReader::open_peek(path, vec![])?;
+        let options = reader.option_manifests()?;
+        Ok(Self::new(path, stamp, reader.manifest, options))
+    }
+
+    /// Whether these are the manifests of the mod at `path` as it is now.
+    /// The same mod may be stored at more than one path, and an unzipped
+    /// copy has no stamp to tell it by.
+    fn is_current(&self, path: &Path) -> bool {
+        self.path == path && stamp(path) == self.stamp
+    }
+
+    /// The mask for a set of options, unless one of them is not in the mod.
+    pub(crate) fn mask(&self, options: &[ModOption]) -> Option<OptionMask> {
+        let mut mask = OptionMask::default();
+        for option in options {
+            mask.insert(self.paths.binary_search(&option.path).ok()?);
+        }
+        Some(mask)
+    }
+
+    /// The main manifest with those of the options in a mask.
+    pub(crate) fn union(&self, mask: &OptionMask) -> Arc<Manifest> {
+        if let Some(manifest) = self.unions.get(mask) {
+            return manifest.clone();
+        }
+        let mut manifest = self.base.clone();
+        for index in mask.iter() {
+            manifest.extend(&self.options[index]);
+        }
+        let manifest = Arc::new(manifest);
+        self.unions.insert(mask.clone(), manifest.clone());
+        manifest
+    }
+}
+
+/// The manifests of a mod, from the index while its archive is unchanged and
+/// otherwise read again.
+pub(crate) fn get(mod_: &Mod) -> Result<Arc<OptionManifests>> {
+    if let Some(manifests) = INDEX.get(&mod_.hash).map(|m| m.clone()) {
+        if manifests.is_current(&mod_.path) {
+            return Ok(manifests);
+        }
+    }
+    let manifests = Arc::new(OptionManifests::read(&mod_.path)?);
+    INDEX.insert(mod_.hash, manifests.clone());
+    Ok(manifests)
+}
+
+/// The manifest of a mod with a set of options.
+pub(crate) fn with_options(mod_: &Mod, options: &[ModOption]) -> Result<Arc<Manifest>> {
+    let manifests = get(mod_)?;
+    match manifests.mask(options) {
+        Some(mask) => Ok(manifests.union(&mask)),
+        // Options which the mod does not list are left to the reader to
+        // find, or fail on
+        None => ModReader::open(&mod_.path, options).map(|r| Arc::new(r.manifest)),
+    }
+}
+
+/// Adds the manifests of a zipped mod to the index from the peek cache,
+/// reading the mod into the cache first if it is not there yet.
+pub(crate) fn index(mod_: &Mod, peek: &PeekCache) -> Result<()> {
+    if !mod_.path.is_file() {
+        return Ok(());
+    }
+    let stamp = stamp(&mod_.path);
+    let (base, options) = match peek.manifests(&mod_.path) {
+        Some(manifests) => manifests,
+        None => {
+            ModReader::open_peek_cached(&mod_.path, vec![], peek)?;
+            peek.manifests(&mod_.path)
+                .ok_or_else(|| anyhow_ext::anyhow!("Mod manifests were not cached"))?
+        }
+    };
+    INDEX.insert(
+        mod_.hash,
+        Arc::new(OptionManifests::new(&mod_.path, stamp, base, options)),
+    );
+    Ok(())
+}
+
+/// Drops the manifests of a mod, for when it is replaced or removed.
+pub(crate) fn forget(hash: usize) {
+    INDEX.remove(&hash);
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use uk_content::fixtures::Sequence;
+    use uk_mod::{Meta, MultipleOptionGroup, OptionGroup};
+
+    use super::*;
+
+    const OPTIONS: usize = 20;
+
+    fn option(i: usize) -> ModOption {
+        ModOption {
+            name: format!("Option {i}").into(),
+            description: Default::default(),
+            path: format!("option{i:02}").into(),
+            requires: vec![],
+            image: None,
+        }
+    }
+
+    /// Writes a mod with many options, each adding a file of its own, named
+    /// for `tag`, and files shared with the main manifest or other options.
+    fn write_mod(path: &Path, name: &str, tag: &str) -> Mod {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: Default::default(),
+            category: Default::default(),
+            description: Default::default(),
+            platform: uk_mod::ModPlatform::Universal,
+            url: None,
+            options: vec![OptionGroup::Multiple(MultipleOptionGroup {
+                options: (0..OPTIONS).map(option).collect(),
+                ..Default::default()
+            })],
+            masters: Default::default(),
+        };
+        let manifest = |files: &[String], aoc: &[String]| {
+            let mut manifest = Manifest::default();
+            manifest
+                .content_files
+                .extend(files.iter().map(|f| f.as_str().into()));
+            manifest
+                .aoc_files
+                .extend(aoc.iter().map(|f| f.as_str().into()));
+            serde_yaml::to_string(&manifest).unwrap()
+        };
+        let base = [
+            "Pack/Bootup.pack".to_string(),
+            "Pack/Shared0.pack".to_string(),
+        ];
+        let mut files = vec![
+            (
+                "meta.yml".to_string(),
+                serde_yaml::to_string(&meta).unwrap(),
+            ),
+            ("manifest.yml".to_string(), manifest(&base, &[])),
+        ];
+        for i in 0..OPTIONS {
+            files.push((
+                format!("options/{}/manifest.yml", option(i).path.display()),
+                manifest(
+                    &[
+                        format!("Actor/Pack/{tag}{i}.sbactorpack"),
+                        format!("Pack/Shared{}.pack", i % 3),
+                    ],
+                    &[format!("Pack/AocMainField{}.pack", i % 4)],
+                ),
+            ));
+        }
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        let opts = zip::write::SimpleFileOptions::default();
+        for (name, contents) in files {
+            zip.start_file(name, opts).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        Mod::from_reader(ModReader::open_peek(path, vec![]).unwrap())
+    }
+
+    fn random_options(seq: &mut Sequence) -> Vec<ModOption> {
+        (0..OPTIONS)
+            .filter(|_| seq.below(2) == 0)
+            .map(option)
+            .collect()
+    }
+
+    #[test]
+    fn cached_union() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mod_ = write_mod(&tmp.path().join("Mod.zip"), "Cached Union", "Option");
+        let mut seq = Sequence::new(2004);
+        for _ in 0..64 {
+            let mut options = random_options(&mut seq);
+            let expected = ModReader::open(&mod_.path, options.as_slice())
+                .unwrap()
+                .manifest;
+            assert_eq!(*with_options(&mod_, &options).unwrap(), expected);
+            // The order options are selected in does not matter
+            options.reverse();
+            assert_eq!(*with_options(&mod_, &options).unwrap(), expected);
+        }
+        assert_eq!(
+            *with_options(&mod_, &[]).unwrap(),
+            ModReader::open(&mod_.path, vec![]).unwrap().manifest
+        );
+    }
+
+    #[test]
+    fn from_peek() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mod_ = write_mod(&tmp.path().join("Mod.zip"), "From Peek", "Option");
+        let peek = PeekCache::open(tmp.path().join(PeekCache::FILE));
+        index(&mod_, &peek).unwrap();
+        assert_eq!(peek.misses(), 1);
+        // Indexed again from the cache alone
+        index(&mod_, &peek).unwrap();
+        assert_eq!(peek.misses(), 1);
+
+        let options = random_options(&mut Sequence::new(17));
+        let expected = ModReader::open(&mod_.path, options.as_slice())
+            .unwrap()
+            .manifest;
+        let manifests = INDEX.get(&mod_.hash).unwrap().clone();
+        assert!(manifests.is_current(&mod_.path));
+        let mask = manifests.mask(&options).unwrap();
+        assert_eq!(*manifests.union(&mask), expected);
+        assert!(manifests.mask(&[option(OPTIONS)]).is_none());
+    }
+
+    #[test]
+    fn changed_archive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("Mod.zip");
+        let mod_ = write_mod(&path, "Changed Archive", "Option");
+        let options = [option(1)];
+        assert!(with_options(&mod_, &options)
+            .unwrap()
+            .content_files
+            .contains("Actor/Pack/Option1.sbactorpack"));
+
+        // The same meta, and so the same mod, but different files
+        write_mod(&path, "Changed Archive", "Update");
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(with_options(&mod_, &options)
+            .unwrap()
+            .content_files
+            .contains("Actor/Pack/Update1.sbactorpack"));
+
+        forget(mod_.hash);
+        assert!(INDEX.get(&mod_.hash).is_none());
+    }
+
+    #[test]
+    fn other_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let first = write_mod(&tmp.path().join("First.zip"), "Other Path", "First");
+        let second = write_mod(&tmp.path().join("Second.zip"), "Other Path", "Second");
+        // The same meta, and so the same mod, stored twice
+        assert_eq!(first.hash, second.hash);
+        let options = [option(1)];
+        for (mod_, file) in [
+            (&first, "Actor/Pack/First1.sbactorpack"),
+            (&second, "Actor/Pack/Second1.sbactorpack"),
+        ] {
+            assert!(with_options(mod_, &options)
+                .unwrap()
+                .content_files
+                .contains(file));
+        }
+    }
+
+    #[test]
+    fn mask() {
+        let mut mask = OptionMask::default();
+        for i in [0, 3, 63, 64, 130] {
+            mask.insert(i);
+        }
+        assert_eq!(mask.iter().collect::<Vec<_>>(), [0, 3, 63, 64, 130]);
+        assert_eq!(mask.0.len(), 3);
+    }
+}
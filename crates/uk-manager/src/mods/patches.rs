@@ -0,0 +1,434 @@
+//! Local patches are mods which UKMM generates and owns, like the one holding
+//! quick tweaks. They are stored unzipped in the mods folder so their files
+//! can be changed in place, and are left out of updates and exports.
+use std::{collections::BTreeSet, path::Path, sync::Arc};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use smartstring::alias::String;
+use uk_content::{canon::canonicalize_aoc, canonicalize, resource::ResourceData};
+use uk_mod::{unpack::ModReader, Manifest, Meta, ModPlatform};
+
+use super::{stored_name, Manager, Mod};
+use crate::{audit::Event, invalidate::Change, util};
+
+/// The author of every mod UKMM generates.
+const AUTHOR: &str = "UKMM";
+
+/// Splits the path to a resource, which may be nested in SARCs with `//`, into
+/// the name and canonical path of each level.
+fn levels(path: &str, aoc: bool) -> Vec<(&str, String)> {
+    path.split("//")
+        .enumerate()
+        .map(|(i, name)| {
+            let canon = if i == 0 && aoc {
+                canonicalize_aoc(name)
+            } else {
+                canonicalize(name)
+            };
+            (name, canon)
+        })
+        .collect()
+}
+
+fn read_resource(dir: &Path, canon: &str) -> Result<Option<ResourceData>> {
+    let path = dir.join(canon);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let resource = minicbor_ser::from_slice(&fs::read(path)?)
+        .map_err(|e| anyhow::format_err!("{:?}", e))
+        .with_context(|| format!("Failed to parse {canon} in local patch"))?;
+    Ok(Some(resource))
+}
+
+fn write_resource(dir: &Path, canon: &str, resource: &ResourceData) -> Result<()> {
+    let path = dir.join(canon);
+    path.parent().map(fs::create_dir_all).transpose()?;
+    fs::write(
+        path,
+        minicbor_ser::to_vec(resource).map_err(|e| anyhow::format_err!("{:?}", e))?,
+    )?;
+    Ok(())
+}
+
+fn manifest_files(manifest: &mut Manifest, aoc: bool) -> &mut BTreeSet<String> {
+    if aoc {
+        &mut manifest.aoc_files
+    } else {
+        &mut manifest.content_files
+    }
+}
+
+impl Manager {
+    /// The local patch with the given ID in the current profile, if any.
+    pub fn local_patch(&self, id: &str) -> Option<Mod> {
+        self.all_mods()
+            .find(|m| m.local_patch && m.patch_id.as_deref() == Some(id))
+    }
+
+    /// Adds an empty local patch to the end of the current profile's load
+    /// order. A patch with the same ID in another profile is shared rather
+    /// than created again.
+    pub fn create_local_patch(&self, id: &str, name: &str, description: &str) -> Result<Mod> {
+        if self.local_patch(id).is_some() || self.all_mods().any(|m| m.meta.name == name) {
+            anyhow_ext::bail!("Mod \"{name}\" already installed");
+        }
+        let dir = self.mods_dir.join(stored_name(id));
+        if !dir.join("meta.yml").exists() {
+            let platform = self
+                .settings
+                .upgrade()
+                .expect("Settings is GONE!")
+                .read()
+                .current_mode;
+            let meta = Meta {
+                api: env!("CARGO_PKG_VERSION").into(),
+                format: uk_mod::FORMAT_VERSION,
+                name: name.into(),
+                version: "1.0.0".into(),
+                author: AUTHOR.into(),
+                category: "Other".into(),
+                description: description.into(),
+                platform: ModPlatform::Specific(platform.into()),
+                url: None,
+                options: vec![],
+                masters: Default::default(),
+            };
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join("meta.yml"), serde_yaml::to_string(&meta)?)?;
+            fs::write(
+                dir.join("manifest.yml"),
+                serde_yaml::to_string(&Manifest::default())?,
+            )?;
+        }
+        let mut patch = Mod::from_reader(ModReader::open_peek(&dir, vec![])?);
+        patch.enabled = true;
+        patch.local_patch = true;
+        patch.patch_id = Some(id.into());
+        let profile = self.profile();
+        profile.load_order_mut().push(patch.hash);
+        profile.mods_mut().insert(patch.hash, patch.clone());
+        log::info!(
+            "Added local patch {} to profile {}",
+            name,
+            self.current_profile
+        );
+        self.audit.record(Event::ModInstalled {
+            profile: self.profile_name(None),
+            name:    patch.meta.name.clone(),
+            version: patch.meta.version.clone(),
+        });
+        Ok(patch)
+    }
+
+    /// Adds a resource to a local patch, replacing any it has at the same
+    /// path. Resources nested in SARCs are given with `//` between each level,
+    /// and the SARCs holding them are added as needed.
+    pub fn append_resource(
+        &self,
+        patch: &Mod,
+        path: &str,
+        aoc: bool,
+        resource: ResourceData,
+    ) -> Result<Mod> {
+        let levels = levels(path, aoc);
+        self.edit_patch(patch, |dir, manifest| {
+            for pair in levels.windows(2) {
+                let (parent, child) = (pair[0].1.as_str(), pair[1].0);
+                let mut sarc = match read_resource(dir, parent)? {
+                    Some(ResourceData::Sarc(sarc)) => sarc,
+                    Some(_) => anyhow_ext::bail!("{parent} in local patch is not a SARC"),
+                    None => Default::default(),
+                };
+                if !sarc.files.contains(String::from(child)) {
+                    sarc.files.insert(child.into());
+                    write_resource(dir, parent, &ResourceData::Sarc(sarc))?;
+                }
+            }
+            write_resource(dir, &levels[levels.len() - 1].1, &resource)?;
+            manifest_files(manifest, aoc).insert(levels[0].0.into());
+            Ok(())
+        })
+    }
+
+    /// Removes a resource from a local patch, along with any SARCs left
+    /// empty. A path which the patch does not have is ignored.
+    pub fn remove_resource(&self, patch: &Mod, path: &str, aoc: bool) -> Result<Mod> {
+        let levels = levels(path, aoc);
+        let canon = levels[levels.len() - 1].1.as_str();
+        if !patch.path.join(canon).exists() {
+            return Ok(patch.clone());
+        }
+        self.edit_patch(patch, |dir, manifest| {
+            fs::remove_file(dir.join(canon))?;
+            for pair in levels.windows(2).rev() {
+                let (parent, child) = (pair[0].1.as_str(), pair[1].0);
+                let Some(ResourceData::Sarc(mut sarc)) = read_resource(dir, parent)? else {
+                    return Ok(());
+                };
+                sarc.files = sarc
+                    .files
+                    .iter_full()
+                    .filter(|(file, _)| file.as_str() != child)
+                    .map(|(file, delete)| (file.clone(), *delete))
+                    .collect();
+                if !sarc.files.is_empty() {
+                    return write_resource(dir, parent, &ResourceData::Sarc(sarc));
+                }
+                fs::remove_file(dir.join(parent))?;
+            }
+            manifest_files(manifest, aoc).remove(levels[0].0);
+            Ok(())
+        })
+    }
+
+    /// Removes a local patch from every profile and deletes its files.
+    pub fn delete_patch(&self, patch: &Mod) -> Result<Arc<Manifest>> {
+        if !patch.local_patch {
+            anyhow_ext::bail!("{} is not a local patch", patch.meta.name);
+        }
+        let profiles: Vec<String> = self
+            .profiles
+            .iter()
+            .filter(|p| p.value().mods().contains_key(&patch.hash))
+            .map(|p| p.key().clone())
+            .collect();
+        let mut manifest = Default::default();
+        for profile in &profiles {
+            manifest = self.del(patch, Some(profile))?;
+            if *profile != self.current_profile {
+                self.save_profile(profile)?;
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Changes the files of a local patch in place, then bumps its version so
+    /// no cached manifest is reused, and swaps it in for the old version in
+    /// every profile, keeping its place and whether it is enabled. The
+    /// current profile is left for the caller to save.
+    fn edit_patch(
+        &self,
+        patch: &Mod,
+        edit: impl FnOnce(&Path, &mut Manifest) -> Result<()>,
+    ) -> Result<Mod> {
+        if !patch.local_patch {
+            anyhow_ext::bail!("{} is not a local patch", patch.meta.name);
+        }
+        let dir = patch.path.as_path();
+        let mut manifest: Manifest =
+            serde_yaml::from_str(&fs::read_to_string(dir.join("manifest.yml"))?)?;
+        edit(dir, &mut manifest)?;
+        fs::write(dir.join("manifest.yml"), serde_yaml::to_string(&manifest)?)?;
+        let mut meta = patch.meta.clone();
+        let build = meta
+            .version
+            .rsplit('.')
+            .next()
+            .and_then(|b| b.parse::<u32>().ok())
+            .map(|b| b + 1)
+            .unwrap_or_default();
+        meta.version = format!("1.0.{build}").into();
+        fs::write(dir.join("meta.yml"), serde_yaml::to_string(&meta)?)?;
+        let mut edited = Mod::from_reader(ModReader::open_peek(dir, vec![])?);
+        edited.local_patch = true;
+        edited.patch_id = patch.patch_id.clone();
+        edited.enabled = patch.enabled;
+        let mut changed = vec![];
+        for profile in self.profiles.iter() {
+            let Some(old) = profile.mods_mut().remove(&patch.hash) else {
+                continue;
+            };
+            profile.mods_mut().insert(edited.hash, Mod {
+                enabled: old.enabled,
+                archived: old.archived,
+                ..edited.clone()
+            });
+            for hash in profile.load_order_mut().iter_mut() {
+                if *hash == patch.hash {
+                    *hash = edited.hash;
+                }
+            }
+            if *profile.key() != self.current_profile {
+                changed.push(profile.key().clone());
+            }
+        }
+        for profile in changed {
+            self.save_profile(&profile)?;
+        }
+        self.bus.publish(Change::ModChanged(patch.hash));
+        self.bus.publish(Change::ModChanged(edited.hash));
+        log::info!(
+            "Updated local patch {} to version {}",
+            meta.name,
+            meta.version
+        );
+        Ok(self.get_mod(edited.hash).unwrap_or(edited))
+    }
+
+    /// Mods generated before local patches were tracked are stored zipped and
+    /// not marked as local patches. They are unzipped and marked on startup.
+    pub(super) fn migrate_local_patches(&self) -> Result<()> {
+        let mut zips = vec![];
+        let mut changed = vec![];
+        for profile in self.profiles.iter() {
+            let mut migrated = false;
+            for mod_ in profile.mods_mut().values_mut() {
+                if mod_.local_patch || mod_.meta.author != AUTHOR {
+                    continue;
+                }
+                if mod_.path.is_file() {
+                    let dir = mod_.path.with_extension("");
+                    if !dir.exists() {
+                        if let Err(e) = uk_mod::unpack::unzip_mod(&mod_.path, &dir) {
+                            log::warn!("Could not unzip {}: {e:?}", mod_.meta.name);
+                            util::remove_dir_all(&dir).unwrap_or(());
+                            continue;
+                        }
+                    }
+                    zips.push(std::mem::replace(&mut mod_.path, dir));
+                }
+                mod_.local_patch = true;
+                migrated = true;
+                log::info!("Marked {} as a local patch", mod_.meta.name);
+            }
+            if migrated {
+                changed.push(profile.key().clone());
+            }
+        }
+        for profile in changed {
+            self.save_profile(&profile)?;
+        }
+        for zip in zips {
+            if zip.exists() {
+                fs::remove_file(zip)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use uk_mod::pack::pack_resources;
+
+    use super::*;
+    use crate::tests::manager;
+
+    const NESTED: &str = "Pack/Bootup.pack//Ecosystem/StatusEffectList.sbyml";
+
+    fn manifest(patch: &Mod) -> Manifest {
+        ModReader::open(&patch.path, vec![]).unwrap().manifest
+    }
+
+    #[test]
+    fn patch_lifecycle() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (_settings, manager) = manager(&tmp.path().join("storage"));
+        let patch = manager.create_local_patch("patch", "Patch", "Test patch").unwrap();
+        assert!(patch.path.is_dir());
+        assert!(manager.create_local_patch("patch", "Patch", "Test patch").is_err());
+        assert_eq!(manager.local_patch("patch"), Some(patch.clone()));
+
+        let data = ResourceData::Binary(b"tweaked".to_vec());
+        let appended = manager
+            .append_resource(&patch, NESTED, false, data.clone())
+            .unwrap();
+        assert_ne!(appended.hash, patch.hash);
+        assert!(appended.local_patch && appended.enabled);
+        assert_eq!(*manager.profile().load_order(), vec![appended.hash]);
+        assert!(manager.get_mod(patch.hash).is_none());
+        assert!(manifest(&appended)
+            .content_files
+            .contains("Pack/Bootup.pack"));
+        let Some(ResourceData::Sarc(bootup)) =
+            read_resource(&appended.path, "Pack/Bootup.pack").unwrap()
+        else {
+            panic!("Bootup.pack should be a SARC");
+        };
+        assert!(bootup
+            .files
+            .contains(String::from("Ecosystem/StatusEffectList.sbyml")));
+        assert_eq!(
+            read_resource(
+                &appended.path,
+                &canonicalize("Ecosystem/StatusEffectList.sbyml")
+            )
+            .unwrap(),
+            Some(data)
+        );
+
+        // Removing the only file in a SARC removes the SARC too
+        let removed = manager.remove_resource(&appended, NESTED, false).unwrap();
+        assert!(manifest(&removed).is_empty());
+        assert!(!removed.path.join("Pack/Bootup.pack").exists());
+        assert_eq!(
+            manager.remove_resource(&removed, NESTED, false).unwrap(),
+            removed
+        );
+
+        // Only local patches can be edited in place
+        let mut other = removed.clone();
+        other.local_patch = false;
+        assert!(manager
+            .append_resource(
+                &other,
+                "Model/Test.sbfres",
+                false,
+                ResourceData::Binary(vec![])
+            )
+            .is_err());
+
+        manager.delete_patch(&removed).unwrap();
+        assert!(!removed.path.exists());
+        assert_eq!(manager.all_mods().count(), 0);
+    }
+
+    #[test]
+    fn migrate_generated() {
+        let tmp = tempfile::tempdir().unwrap();
+        let storage = tmp.path().join("storage");
+        let (settings, manager) = manager(&storage);
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: "User Tweaks".into(),
+            version: "1.0.3".into(),
+            author: AUTHOR.into(),
+            category: "Other".into(),
+            description: Default::default(),
+            platform: ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        };
+        let mut files = Manifest::default();
+        files.content_files.insert("Model/Test.sbfres".into());
+        let zip = pack_resources(tmp.path().join("tweaks.zip"), &meta, &files, [(
+            canonicalize("Model/Test.sbfres"),
+            ResourceData::Binary(b"tweaked".to_vec()),
+        )])
+        .unwrap();
+        let installed = manager.add(&zip, None).unwrap();
+        manager.save().unwrap();
+        assert!(!installed.local_patch);
+        drop(manager);
+
+        let manager = Manager::init(&settings).unwrap();
+        let migrated = manager.get_mod(installed.hash).unwrap();
+        assert!(migrated.local_patch);
+        assert!(migrated.path.is_dir());
+        assert!(!installed.path.exists());
+        assert_eq!(manifest(&migrated), files);
+        assert_eq!(
+            read_resource(&migrated.path, &canonicalize("Model/Test.sbfres")).unwrap(),
+            Some(ResourceData::Binary(b"tweaked".to_vec()))
+        );
+        // The migration is saved, so it only happens once
+        drop(manager);
+        let manager = Manager::init(&settings).unwrap();
+        assert_eq!(manager.get_mod(installed.hash).unwrap().path, migrated.path);
+    }
+}
@@ -0,0 +1,322 @@
+//! Carries the options selected for a mod over to a new version of it.
+//! Authors sometimes rename option folders between versions, which would
+//! leave old selections pointing at nothing, so their files silently stop
+//! being merged. Each selection is matched to an option of the new version by
+//! its folder, then by its name, and last by how alike the folders are.
+//! Whatever is remapped or cannot be matched is reported, and the mod is
+//! flagged until the user has looked over its options.
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uk_mod::{Meta, ModOption, ModOptionGroup};
+
+use super::Mod;
+
+/// How alike two option folders have to be to be taken for the same one.
+const SIMILARITY: f64 = 0.5;
+
+/// How a selected option was found in a new version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Match {
+    /// An option of the same name.
+    Name,
+    /// An option with a folder much like the old one.
+    SimilarPath,
+}
+
+/// What became of the selected options of a mod when it was updated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OptionRemap {
+    /// Selections whose folders are gone, with the options they now select.
+    pub remapped:  Vec<(ModOption, ModOption, Match)>,
+    /// Selections with nothing like them in the new version, so dropped.
+    pub unmatched: Vec<ModOption>,
+    /// Options of the new version like none of the old.
+    pub added:     Vec<ModOption>,
+}
+
+impl OptionRemap {
+    /// Whether any selection changed, so the user should look them over.
+    pub fn needs_review(&self) -> bool {
+        !self.remapped.is_empty() || !self.unmatched.is_empty()
+    }
+}
+
+impl std::fmt::Display for OptionRemap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines = self
+            .remapped
+            .iter()
+            .map(|(old, new, by)| {
+                format!(
+                    "{} is now {} ({} → {}, matched by {})",
+                    old.name,
+                    new.name,
+                    old.path.display(),
+                    new.path.display(),
+                    match by {
+                        Match::Name => "name",
+                        Match::SimilarPath => "folder",
+                    }
+                )
+            })
+            .chain(
+                self.unmatched
+                    .iter()
+                    .map(|old| format!("{} is no longer in the mod, so was deselected", old.name)),
+            )
+            .chain(self.added.iter().map(|new| format!("{} is new", new.name)));
+        for (i, line) in lines.enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            f.write_str(&line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of an option's folder, lowercased, to compare with others. Only
+/// the last part is compared, as options are often kept under a shared
+/// folder which would make any two look alike.
+fn folder_key(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// How alike two option folders are, from 0 to 1, by the pairs of letters
+/// their names share. A folder renamed by adding to or trimming its name
+/// keeps most of them.
+fn similarity(a: &Path, b: &Path) -> f64 {
+    fn bigrams(s: &str) -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    }
+    let (a, b) = (folder_key(a), folder_key(b));
+    if a == b {
+        return 1.0;
+    }
+    let (a, mut b) = (bigrams(&a), bigrams(&b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let total = a.len() + b.len();
+    let mut shared = 0;
+    for pair in a {
+        if let Some(i) = b.iter().position(|p| *p == pair) {
+            b.swap_remove(i);
+            shared += 1;
+        }
+    }
+    (2 * shared) as f64 / total as f64
+}
+
+/// Finds the option of a new version which an old selection meant, leaving
+/// out options already taken by another.
+fn find(
+    old: &ModOption,
+    options: &[&ModOption],
+    taken: &[&Path],
+) -> Option<(ModOption, Option<Match>)> {
+    let free = || {
+        options
+            .iter()
+            .filter(|o| !taken.contains(&o.path.as_path()))
+    };
+    if let Some(same) = free().find(|o| o.path == old.path) {
+        return Some(((*same).clone(), None));
+    }
+    let name = old.name.trim().to_lowercase();
+    if let Some(named) = free().find(|o| o.name.trim().to_lowercase() == name) {
+        return Some(((*named).clone(), Some(Match::Name)));
+    }
+    // The most alike folder is only taken if no other is as alike
+    let mut scored: Vec<(f64, &ModOption)> = free()
+        .map(|o| (similarity(&old.path, &o.path), *o))
+        .filter(|(score, _)| *score >= SIMILARITY)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    match scored.as_slice() {
+        [(_, option)] => Some(((*option).clone(), Some(Match::SimilarPath))),
+        [(best, option), (next, _), ..] if next < best => {
+            Some(((*option).clone(), Some(Match::SimilarPath)))
+        }
+        _ => None,
+    }
+}
+
+/// Matches the selected options of an old version of a mod to the options of
+/// a new one. Returns the options to select, those of them which stay turned
+/// off, and what changed.
+pub fn remap(old: &Mod, new: &Meta) -> (Vec<ModOption>, Vec<PathBuf>, OptionRemap) {
+    let options: Vec<&ModOption> = new.options.iter().flat_map(|g| g.options()).collect();
+    let mut report = OptionRemap::default();
+    let mut enabled: Vec<ModOption> = vec![];
+    let mut inactive = vec![];
+    for selected in &old.enabled_options {
+        let taken: Vec<&Path> = enabled.iter().map(|o| o.path.as_path()).collect();
+        match find(selected, &options, &taken) {
+            Some((option, by)) => {
+                if old.inactive_options.contains(&selected.path) {
+                    inactive.push(option.path.clone());
+                }
+                if let Some(by) = by {
+                    report.remapped.push((selected.clone(), option.clone(), by));
+                }
+                enabled.push(option);
+            }
+            None => report.unmatched.push(selected.clone()),
+        }
+    }
+    // New options are those no option of the old version would be taken for
+    let old_options: Vec<&ModOption> = old.meta.options.iter().flat_map(|g| g.options()).collect();
+    let mut taken: Vec<&Path> = vec![];
+    for old_option in old_options {
+        if let Some((option, _)) = find(old_option, &options, &taken) {
+            if let Some(known) = options.iter().find(|o| o.path == option.path) {
+                taken.push(known.path.as_path());
+            }
+        }
+    }
+    report.added = options
+        .into_iter()
+        .filter(|o| !taken.contains(&o.path.as_path()))
+        .cloned()
+        .collect();
+    (enabled, inactive, report)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use uk_mod::{MultipleOptionGroup, OptionGroup};
+
+    use super::*;
+
+    fn option(name: &str, path: &str) -> ModOption {
+        ModOption {
+            name: name.into(),
+            description: Default::default(),
+            path: path.into(),
+            requires: vec![],
+            image: None,
+        }
+    }
+
+    fn version(options: &[ModOption], selected: &[&str]) -> Mod {
+        let mut mod_ = Mod {
+            meta: Meta {
+                api: "1.0.0".into(),
+                format: uk_mod::FORMAT_VERSION,
+                name: "Test Mod".into(),
+                version: "1.0.0".into(),
+                author: Default::default(),
+                category: Default::default(),
+                description: Default::default(),
+                platform: uk_mod::ModPlatform::Universal,
+                url: None,
+                options: vec![OptionGroup::Multiple(MultipleOptionGroup {
+                    name: "Options".into(),
+                    options: options.to_vec(),
+                    ..Default::default()
+                })],
+                masters: Default::default(),
+            },
+            enabled_options: vec![],
+            inactive_options: vec![],
+            enabled: true,
+            path: "Test Mod.zip".into(),
+            local_patch: false,
+            patch_id: None,
+            archived: false,
+            options_review: None,
+            hash: 0,
+        };
+        mod_.enabled_options = options
+            .iter()
+            .filter(|o| selected.contains(&o.name.as_str()))
+            .cloned()
+            .collect();
+        mod_
+    }
+
+    #[test]
+    fn folder_renamed() {
+        let mut old = version(&[option("Hard", "hard"), option("Extra Loot", "loot")], &[
+            "Hard",
+            "Extra Loot",
+        ]);
+        old.inactive_options.push("loot".into());
+        // The folder of Hard was renamed, and Extra Loot was renamed entirely
+        let mut new = version(
+            &[
+                option("Hard", "hardmode"),
+                option("More Loot", "loot_extra"),
+                option("Easy", "easy"),
+            ],
+            &[],
+        );
+        let report = new.carry_options(&old);
+        assert_eq!(
+            new.enabled_options
+                .iter()
+                .map(|o| o.path.clone())
+                .collect::<Vec<_>>(),
+            [PathBuf::from("hardmode"), PathBuf::from("loot_extra")]
+        );
+        assert_eq!(new.inactive_options, [PathBuf::from("loot_extra")]);
+        assert_eq!(
+            report
+                .remapped
+                .iter()
+                .map(|(old, new, by)| (old.name.as_str(), new.name.as_str(), *by))
+                .collect::<Vec<_>>(),
+            [
+                ("Hard", "Hard", Match::Name),
+                ("Extra Loot", "More Loot", Match::SimilarPath)
+            ]
+        );
+        assert!(report.unmatched.is_empty());
+        assert_eq!(report.added, [option("Easy", "easy")]);
+        assert_eq!(new.options_review, Some(report));
+    }
+
+    #[test]
+    fn option_removed() {
+        let old = version(&[option("Hard", "hard"), option("Classic HUD", "hud")], &[
+            "Hard",
+            "Classic HUD",
+        ]);
+        let mut new = version(&[option("Hard", "hard"), option("Easy", "easy")], &[]);
+        let report = new.carry_options(&old);
+        assert_eq!(new.enabled_options, [option("Hard", "hard")]);
+        assert!(report.remapped.is_empty());
+        assert_eq!(report.unmatched, [option("Classic HUD", "hud")]);
+        assert_eq!(report.added, [option("Easy", "easy")]);
+        assert!(new.options_review.is_some());
+        // Options which stayed as they were need no review, even with new ones
+        let mut same = version(&[option("Hard", "hard"), option("Easy", "easy")], &[]);
+        let report = same.carry_options(&new);
+        assert_eq!(same.enabled_options, [option("Hard", "hard")]);
+        assert!(!report.needs_review() && report.added.is_empty());
+        // A review not yet confirmed is kept
+        assert!(same.options_review.is_some());
+    }
+
+    #[test]
+    fn ambiguous_folders() {
+        let old = version(&[option("A", "texture")], &["A"]);
+        // Two folders are just as like the old one, so neither is guessed
+        let mut new = version(&[option("B", "textures"), option("C", "texturex")], &[]);
+        let report = new.carry_options(&old);
+        assert!(new.enabled_options.is_empty());
+        assert_eq!(report.unmatched, [option("A", "texture")]);
+        assert!(similarity(Path::new("hard"), Path::new("hardmode")) >= SIMILARITY);
+        assert!(similarity(Path::new("hard"), Path::new("easy")) < SIMILARITY);
+        assert!(similarity(Path::new("options/pink"), Path::new("options/easy")) < SIMILARITY);
+    }
+}
@@ -0,0 +1,504 @@
+//! Deleted profiles are moved to the trash rather than removed, so they can be
+//! restored with their mods, options and merged files as they were. A trashed
+//! profile keeps its folder as is, with a snapshot file saying what it was
+//! called and when it was deleted. Its mods still count as in use, so they
+//! stay in storage until the trash is emptied and no profile has them left.
+//! Uninstalled mods which no profile has left go to the trash as well, so
+//! uninstalling can be undone.
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow_ext::{Context, Result};
+use dashmap::DashMap;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+
+use super::{LookupMod, Manager, Mod, Profile};
+use crate::{audit::Event, invalidate::Change, util};
+
+/// The file in a trashed profile's folder which records what it was.
+const SNAPSHOT: &str = "trashed.yml";
+/// The folder in the trash holding uninstalled mods.
+pub(super) const MODS: &str = "mods";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trashed {
+    /// The name the profile had.
+    pub profile: String,
+    /// When it was deleted, in milliseconds since the Unix epoch.
+    pub time:    u64,
+    /// The folder in the trash holding it.
+    #[serde(skip)]
+    pub dir:     PathBuf,
+}
+
+/// A mod uninstalled from the current profile, with what it takes to put it
+/// back where it was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovedMod {
+    pub mod_:    Mod,
+    /// Its position in the load order.
+    pub index:   usize,
+    /// Where its files were moved in the trash, if no profile had it left.
+    pub trashed: Option<PathBuf>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Reads the profiles in the trash, keyed by their folder names. Any which
+/// cannot be read are left out, so their mods will not be kept for them.
+pub(super) fn load(trash: &Path, storage: &Path) -> DashMap<String, (Trashed, Profile)> {
+    let read = |dir: &Path| -> Result<(Trashed, Profile)> {
+        let mut trashed: Trashed = serde_yaml::from_str(&fs::read_to_string(dir.join(SNAPSHOT))?)?;
+        trashed.dir = dir.to_path_buf();
+        let profile: Profile = serde_yaml::from_str(&fs::read_to_string(dir.join("profile.yml"))?)?;
+        profile.resolve_paths(storage);
+        Ok((trashed, profile))
+    };
+    fs::read_dir(trash)
+        .into_iter()
+        .flatten()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.path().is_dir() && entry.file_name() != MODS)
+        .filter_map(|entry| {
+            match read(&entry.path()) {
+                Ok(trashed) => Some((entry.file_name().to_string_lossy().into(), trashed)),
+                Err(e) => {
+                    log::warn!(
+                        "Failed to read trashed profile at {}: {e:?}",
+                        entry.path().display()
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+impl Manager {
+    /// The profiles in the trash, most recently deleted first.
+    pub fn trash(&self) -> Vec<Trashed> {
+        let mut trash: Vec<Trashed> = self
+            .trashed
+            .iter()
+            .map(|entry| entry.value().0.clone())
+            .collect();
+        trash.sort_by_key(|t| std::cmp::Reverse(t.time));
+        trash
+    }
+
+    /// Whether any profile, including those in the trash, has a mod.
+    pub(super) fn in_use(&self, hash: usize) -> bool {
+        self.profiles
+            .iter()
+            .any(|p| p.value().mods().contains_key(&hash))
+            || self
+                .trashed
+                .iter()
+                .any(|t| t.value().1.mods().contains_key(&hash))
+    }
+
+    /// Local patches in a profile which no other profile has. These would be
+    /// lost for good when a deleted profile is purged from the trash.
+    pub fn stranded_patches(&self, profile: &str) -> Vec<Mod> {
+        // Archived patches count too, as they are out of the load order
+        let Some(mods) = self
+            .profiles
+            .get(profile)
+            .map(|p| p.mods().values().cloned().collect::<Vec<_>>())
+        else {
+            return vec![];
+        };
+        mods.into_iter()
+            .filter(|mod_| {
+                mod_.local_patch
+                    && !self.profiles.iter().any(|p| {
+                        p.key().as_str() != profile && p.value().mods().contains_key(&mod_.hash)
+                    })
+            })
+            .collect()
+    }
+
+    /// Adds mods to the end of another profile's load order, keeping whether
+    /// they are enabled or archived. Mods the profile already has are skipped.
+    pub fn copy_mods(&self, mods: &[Mod], to: &str) -> Result<()> {
+        {
+            let profile = self
+                .profiles
+                .get(to)
+                .with_context(|| format!("No profile named {to}"))?;
+            let mut profile_mods = profile.mods_mut();
+            let mut load_order = profile.load_order_mut();
+            for mod_ in mods {
+                if profile_mods.insert(mod_.hash, mod_.clone()).is_none() && !mod_.archived {
+                    load_order.push(mod_.hash);
+                }
+            }
+        }
+        self.save_profile(to)
+    }
+
+    /// Moves a profile to the trash. The current profile cannot be deleted,
+    /// since the mod list would be left without one.
+    pub fn delete_profile(&self, name: &str) -> Result<Trashed> {
+        if self.current_profile.as_str() == name {
+            anyhow_ext::bail!("Switch to another profile before deleting {name}");
+        }
+        let profile = self
+            .profiles
+            .get(name)
+            .with_context(|| format!("No profile named {name}"))?
+            .clone();
+        self.save_profile(name)?;
+        let time = now();
+        let folder: String = format!("{time}-{name}").into();
+        let trashed = Trashed {
+            profile: name.into(),
+            time,
+            dir: self.trash_dir.join(folder.as_str()),
+        };
+        fs::create_dir_all(&self.trash_dir)?;
+        fs::rename(self.dir.join(name), &trashed.dir)
+            .with_context(|| format!("Failed to move profile {name} to the trash"))?;
+        if let Err(e) = serde_yaml::to_string(&trashed)
+            .map_err(anyhow_ext::Error::from)
+            .and_then(|text| Ok(fs::write(trashed.dir.join(SNAPSHOT), text)?))
+        {
+            fs::rename(&trashed.dir, self.dir.join(name))?;
+            return Err(e.context(format!("Failed to save snapshot of profile {name}")));
+        }
+        self.profiles.remove(name);
+        self.trashed.insert(folder, (trashed.clone(), profile));
+        log::info!("Moved profile {name} to the trash");
+        self.audit.record(Event::ProfileDeleted {
+            profile: name.into(),
+        });
+        Ok(trashed)
+    }
+
+    /// Puts a profile back from the trash under its old name, which must not
+    /// have been taken since.
+    pub fn restore_profile(&self, trashed: &Trashed) -> Result<()> {
+        let name = trashed.profile.as_str();
+        let target = self.dir.join(name);
+        if self.profiles.contains_key(name) || target.exists() {
+            anyhow_ext::bail!("A profile named {name} already exists. Rename it first.");
+        }
+        let (folder, (_, profile)) = trashed
+            .dir
+            .file_name()
+            .and_then(|folder| self.trashed.remove(folder.to_str()?))
+            .context("Profile is not in the trash")?;
+        if let Err(e) = fs::rename(&trashed.dir, &target) {
+            self.trashed.insert(folder, (trashed.clone(), profile));
+            return Err(anyhow_ext::Error::from(e)
+                .context(format!("Failed to restore profile {name} from the trash")));
+        }
+        fs::remove_file(target.join(SNAPSHOT)).unwrap_or(());
+        self.profiles.insert(name.into(), profile);
+        log::info!("Restored profile {name} from the trash");
+        self.audit.record(Event::ProfileRestored {
+            profile: name.into(),
+        });
+        Ok(())
+    }
+
+    /// Uninstalls a mod from the current profile. Unlike [`Manager::del`], a
+    /// mod no profile has left is moved to the trash rather than deleted, so
+    /// it can be put back with [`Manager::restore_mod`].
+    pub fn trash_mod(&self, mod_: impl LookupMod) -> Result<Option<RemovedMod>> {
+        let hash = mod_.as_map_id();
+        let removed = {
+            let profile = self.profile();
+            let index = profile.load_order().iter().position(|h| *h == hash);
+            let mod_ = profile.mods_mut().remove(&hash);
+            match (mod_, index) {
+                (Some(mod_), Some(index)) => {
+                    profile.load_order_mut().remove(index);
+                    Some((mod_, index))
+                }
+                (mod_, _) => {
+                    if let Some(mod_) = mod_ {
+                        profile.mods_mut().insert(hash, mod_);
+                    }
+                    None
+                }
+            }
+        };
+        let Some((mod_, index)) = removed else {
+            log::warn!("Mod with ID {} does not exist, doing nothing", hash);
+            return Ok(None);
+        };
+        let mut removed = RemovedMod {
+            mod_,
+            index,
+            trashed: None,
+        };
+        if !self.in_use(hash) {
+            let name = removed
+                .mod_
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| hash.to_string());
+            let dest = self.trash_dir.join(MODS).join(format!("{}-{name}", now()));
+            let moved = fs::create_dir_all(self.trash_dir.join(MODS))
+                .and_then(|_| fs::rename(&removed.mod_.path, &dest));
+            if let Err(e) = moved {
+                let context = format!("Failed to move mod {} to the trash", removed.mod_.meta.name);
+                let profile = self.profile();
+                profile.load_order_mut().insert(index, hash);
+                profile.mods_mut().insert(hash, removed.mod_);
+                return Err(anyhow_ext::Error::from(e).context(context));
+            }
+            self.peek.invalidate(&removed.mod_.path);
+            removed.trashed = Some(dest);
+        }
+        self.bus.publish(Change::ModChanged(hash));
+        log::info!(
+            "Uninstalled mod {} from profile {}",
+            removed.mod_.meta.name,
+            self.current_profile
+        );
+        self.audit.record(Event::ModRemoved {
+            profile: self.profile_name(None),
+            name:    removed.mod_.meta.name.clone(),
+            version: removed.mod_.meta.version.clone(),
+        });
+        Ok(Some(removed))
+    }
+
+    /// Puts an uninstalled mod back in the current profile where it was,
+    /// taking its files back out of the trash if they went there.
+    pub fn restore_mod(&self, removed: &RemovedMod) -> Result<Mod> {
+        let mod_ = &removed.mod_;
+        if self.profile().mods().contains_key(&mod_.hash) {
+            anyhow_ext::bail!("Mod \"{}\" already installed", mod_.meta.name);
+        }
+        match removed.trashed.as_ref() {
+            Some(trashed) if trashed.exists() => {
+                if mod_.path.exists() {
+                    anyhow_ext::bail!(
+                        "Mod \"{}\" has been stored again since it was uninstalled",
+                        mod_.meta.name
+                    );
+                }
+                fs::rename(trashed, &mod_.path).with_context(|| {
+                    format!("Failed to restore mod {} from the trash", mod_.meta.name)
+                })?;
+                self.peek.invalidate(&mod_.path);
+            }
+            Some(_) => {
+                anyhow_ext::bail!(
+                    "Mod \"{}\" is no longer in the trash, so it cannot be put back",
+                    mod_.meta.name
+                )
+            }
+            None if !mod_.path.exists() => {
+                anyhow_ext::bail!(
+                    "Mod \"{}\" is no longer in storage, so it cannot be put back",
+                    mod_.meta.name
+                )
+            }
+            None => (),
+        }
+        {
+            let profile = self.profile();
+            let mut load_order = profile.load_order_mut();
+            let index = removed.index.min(load_order.len());
+            load_order.insert(index, mod_.hash);
+            profile.mods_mut().insert(mod_.hash, mod_.clone());
+        }
+        self.bus.publish(Change::ModChanged(mod_.hash));
+        log::info!(
+            "Restored mod {} to profile {}",
+            mod_.meta.name,
+            self.current_profile
+        );
+        self.audit.record(Event::ModInstalled {
+            profile: self.profile_name(None),
+            name:    mod_.meta.name.clone(),
+            version: mod_.meta.version.clone(),
+        });
+        Ok(mod_.clone())
+    }
+
+    /// The space in storage taken by mods which only profiles in the trash
+    /// have, which emptying the trash would free.
+    pub fn trash_size(&self) -> u64 {
+        let mut mods: Vec<Mod> = self
+            .trashed
+            .iter()
+            .flat_map(|t| t.value().1.mods().values().cloned().collect::<Vec<_>>())
+            .filter(|mod_| {
+                !self
+                    .profiles
+                    .iter()
+                    .any(|p| p.value().mods().contains_key(&mod_.hash))
+            })
+            .collect();
+        mods.sort_by_key(|mod_| mod_.hash);
+        mods.dedup_by_key(|mod_| mod_.hash);
+        mods.iter()
+            .map(|mod_| crate::preflight::path_size(&mod_.path))
+            .sum::<u64>()
+            + crate::preflight::path_size(&self.trash_dir.join(MODS))
+    }
+
+    /// Deletes the profiles in the trash for good, along with the mods which
+    /// no remaining profile has and uninstalled mods. Returns how many mods
+    /// were deleted.
+    pub fn empty_trash(&self) -> Result<usize> {
+        let folders: Vec<String> = self.trashed.iter().map(|t| t.key().clone()).collect();
+        let mut mods: Vec<Mod> = vec![];
+        for folder in folders {
+            let Some((_, (trashed, profile))) = self.trashed.remove(&folder) else {
+                continue;
+            };
+            if let Err(e) = util::remove_dir_all(&trashed.dir) {
+                self.trashed.insert(folder, (trashed, profile));
+                return Err(e.context("Failed to empty the trash"));
+            }
+            mods.extend(profile.mods().values().cloned());
+        }
+        mods.sort_by_key(|mod_| mod_.hash);
+        mods.dedup_by_key(|mod_| mod_.hash);
+        let mut purged = 0;
+        for mod_ in mods.iter().filter(|mod_| !self.in_use(mod_.hash)) {
+            self.delete_stored(mod_)?;
+            purged += 1;
+        }
+        let uninstalled = self.trash_dir.join(MODS);
+        if uninstalled.exists() {
+            purged += fs::read_dir(&uninstalled)?.count();
+            util::remove_dir_all(&uninstalled).context("Failed to empty the trash")?;
+        }
+        if let Err(e) = self.collect_objects() {
+            log::warn!("Failed to remove unused objects: {e:?}");
+        }
+        log::info!("Emptied the trash, deleting {purged} mods no profile has");
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::tests::manager;
+
+    /// A profile named Other holding a local patch and a mod the current
+    /// profile shares.
+    fn setup(manager: &mut Manager) -> (Mod, Mod) {
+        let shared = manager
+            .create_local_patch("shared", "Shared", "Shared patch")
+            .unwrap();
+        manager.set_profile("Other").unwrap();
+        let patch = manager.create_local_patch("patch", "Patch", "Test patch").unwrap();
+        manager.save().unwrap();
+        manager.copy_mods(std::slice::from_ref(&shared), "Other").unwrap();
+        manager.set_profile("Default").unwrap();
+        (shared, patch)
+    }
+
+    #[test]
+    fn delete_then_restore() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (settings, mut manager) = manager(&tmp.path().join("storage"));
+        let (shared, patch) = setup(&mut manager);
+        assert_eq!(manager.stranded_patches("Other"), vec![patch.clone()]);
+        assert!(manager.delete_profile("Default").is_err());
+
+        let trashed = manager.delete_profile("Other").unwrap();
+        assert!(!settings.read().profiles_dir().join("Other").exists());
+        assert!(trashed.dir.join(SNAPSHOT).exists());
+        assert!(manager.profile_mods("Other").is_none());
+        assert!(manager.in_use(patch.hash) && patch.path.exists());
+        // Removing the shared mod from the current profile leaves it for the
+        // trashed one
+        manager.del(&shared, None).unwrap();
+        assert!(shared.path.exists());
+
+        // The trash survives a restart
+        drop(manager);
+        let (_, manager) = self::manager(&tmp.path().join("storage"));
+        assert_eq!(manager.trash(), vec![trashed.clone()]);
+        manager.restore_profile(&trashed).unwrap();
+        assert!(manager.trash().is_empty());
+        assert!(!settings
+            .read()
+            .profiles_dir()
+            .join("Other")
+            .join(SNAPSHOT)
+            .exists());
+        let mods = manager.profile_mods("Other").unwrap();
+        assert_eq!(mods.iter().map(|m| m.hash).collect::<Vec<_>>(), vec![
+            patch.hash,
+            shared.hash
+        ]);
+        assert!(manager.restore_profile(&trashed).is_err());
+    }
+
+    #[test]
+    fn delete_then_purge() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (_settings, mut manager) = manager(&tmp.path().join("storage"));
+        let (shared, patch) = setup(&mut manager);
+        assert_eq!(manager.trash_size(), 0);
+        let trashed = manager.delete_profile("Other").unwrap();
+        assert_eq!(
+            manager.trash_size(),
+            crate::preflight::path_size(&patch.path)
+        );
+
+        // Only the mod no remaining profile has is deleted
+        assert_eq!(manager.empty_trash().unwrap(), 1);
+        assert!(!trashed.dir.exists());
+        assert!(!patch.path.exists());
+        assert!(shared.path.exists());
+        assert!(manager.trash().is_empty());
+        assert_eq!(manager.trash_size(), 0);
+        assert!(manager.restore_profile(&trashed).is_err());
+    }
+
+    #[test]
+    fn uninstall_then_restore() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (_settings, manager) = manager(&tmp.path().join("storage"));
+        let first = manager.create_local_patch("first", "First", "Test patch").unwrap();
+        let second = manager.create_local_patch("second", "Second", "Test patch").unwrap();
+        let removed = manager.trash_mod(&first).unwrap().unwrap();
+        assert_eq!(removed.index, 0);
+        assert!(!first.path.exists());
+        assert!(removed.trashed.as_ref().unwrap().exists());
+        assert!(manager.trash_size() > 0);
+        assert!(manager.trash_mod(&first).unwrap().is_none());
+
+        // Uninstalled mods are not mistaken for trashed profiles
+        manager.save().unwrap();
+        drop(manager);
+        let (_, manager) = self::manager(&tmp.path().join("storage"));
+        assert!(manager.trash().is_empty());
+        manager.restore_mod(&removed).unwrap();
+        assert!(first.path.exists());
+        assert_eq!(
+            manager.all_mods().map(|m| m.hash).collect::<Vec<_>>(),
+            vec![first.hash, second.hash]
+        );
+        assert!(manager.restore_mod(&removed).is_err());
+
+        // Once the trash is emptied, there is nothing to put back
+        let removed = manager.trash_mod(&second).unwrap().unwrap();
+        assert_eq!(manager.empty_trash().unwrap(), 1);
+        assert!(manager.restore_mod(&removed).is_err());
+        assert_eq!(manager.trash_size(), 0);
+    }
+}
@@ -0,0 +1,644 @@
+//! Checks of the mod index, the profiles' records of which mods they have and
+//! in what order, for inconsistencies which build up over time. Every issue
+//! has a repair. Safe repairs lose nothing and are made as soon as the index
+//! is loaded, while ones which remove mods wait for the user to agree.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use lenient_semver::Version;
+use smartstring::alias::String;
+use uk_mod::ModOptionGroup;
+
+use super::{Manager, Mod, Profile};
+use crate::audit::Event;
+
+/// What is wrong with a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The load order lists a mod more than once. Only the first is kept.
+    RepeatedOrder(usize),
+    /// The load order lists a mod which is not in the profile. It is dropped.
+    DanglingOrder(usize),
+    /// A mod in the profile is not in the load order. It is added to the end.
+    Unordered { hash: usize, name: String },
+    /// A mod is stored under another ID than its own. It is moved to its own.
+    MismatchedId {
+        id:   usize,
+        hash: usize,
+        name: String,
+    },
+    /// A mod has options selected which it does not have, usually because
+    /// their folders were renamed. They are deselected.
+    UnknownOptions {
+        hash:    usize,
+        name:    String,
+        options: Vec<PathBuf>,
+    },
+    /// A mod's files are gone from storage. It is removed from the profile.
+    MissingArchive { hash: usize, name: String },
+    /// A mod is in the profile twice. The older copy is removed.
+    Duplicate {
+        keep:   usize,
+        remove: usize,
+        name:   String,
+    },
+}
+
+/// A problem found in a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub profile: String,
+    pub kind:    IssueKind,
+}
+
+impl Issue {
+    /// Whether the repair keeps every mod and selection which still works,
+    /// so it can be made without asking.
+    pub fn is_safe(&self) -> bool {
+        !matches!(
+            self.kind,
+            IssueKind::MissingArchive { .. } | IssueKind::Duplicate { .. }
+        )
+    }
+
+    /// The mod the repair removes from the profile, if any.
+    pub fn removed(&self) -> Option<usize> {
+        match self.kind {
+            IssueKind::MissingArchive { hash, .. } => Some(hash),
+            IssueKind::Duplicate { remove, .. } => Some(remove),
+            _ => None,
+        }
+    }
+
+    /// What the repair does, for showing before it is made.
+    pub fn repair(&self) -> &'static str {
+        match self.kind {
+            IssueKind::RepeatedOrder(_) => "Keep the first",
+            IssueKind::DanglingOrder(_) => "Drop from load order",
+            IssueKind::Unordered { .. } => "Add to end of load order",
+            IssueKind::MismatchedId { .. } => "Fix ID",
+            IssueKind::UnknownOptions { .. } => "Deselect options",
+            IssueKind::MissingArchive { .. } => "Remove mod",
+            IssueKind::Duplicate { .. } => "Remove older copy",
+        }
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.profile)?;
+        match &self.kind {
+            IssueKind::RepeatedOrder(hash) => {
+                write!(f, "Mod {hash} is in the load order more than once")
+            }
+            IssueKind::DanglingOrder(hash) => {
+                write!(f, "Load order lists mod {hash}, which is not installed")
+            }
+            IssueKind::Unordered { name, .. } => write!(f, "{name} is not in the load order"),
+            IssueKind::MismatchedId { name, .. } => {
+                write!(f, "{name} is stored under the wrong ID")
+            }
+            IssueKind::UnknownOptions { name, options, .. } => {
+                write!(
+                    f,
+                    "{name} has options selected which it does not have: {}",
+                    options
+                        .iter()
+                        .map(|o| o.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+            IssueKind::MissingArchive { name, .. } => {
+                write!(f, "The files for {name} are missing from storage")
+            }
+            IssueKind::Duplicate { name, .. } => write!(f, "{name} is installed twice"),
+        }
+    }
+}
+
+fn option_paths(mod_: &Mod) -> impl Iterator<Item = &PathBuf> {
+    mod_.meta
+        .options
+        .iter()
+        .flat_map(|group| group.options())
+        .map(|option| &option.path)
+}
+
+/// Whether a mod is a newer copy than another, by version, or else by when
+/// its files were last changed.
+fn is_newer(mod_: &Mod, other: &Mod) -> bool {
+    match (
+        Version::parse(mod_.meta.version.as_str()),
+        Version::parse(other.meta.version.as_str()),
+    ) {
+        (Ok(version), Ok(other_version)) if version != other_version => version > other_version,
+        _ => {
+            let modified = |path: &Path| {
+                path.metadata()
+                    .and_then(|m| m.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH)
+            };
+            modified(&mod_.path) > modified(&other.path)
+        }
+    }
+}
+
+/// Finds the issues in a profile. The files of mods which are offline are not
+/// looked at, so they are not taken to be missing.
+pub(crate) fn check(
+    name: &str,
+    profile: &Profile,
+    is_offline: impl Fn(usize) -> bool,
+) -> Vec<Issue> {
+    let mods = profile.mods();
+    let order = profile.load_order();
+    let mut kinds = vec![];
+    for (i, hash) in order.iter().enumerate() {
+        if !mods.contains_key(hash) {
+            if !order[..i].contains(hash) {
+                kinds.push(IssueKind::DanglingOrder(*hash));
+            }
+        } else if order[..i].iter().filter(|h| *h == hash).count() == 1 {
+            kinds.push(IssueKind::RepeatedOrder(*hash));
+        }
+    }
+    let mut ids: Vec<&usize> = mods.keys().collect();
+    ids.sort_by_key(|id| order.iter().position(|h| h == *id).unwrap_or(usize::MAX));
+    let mut present: Vec<(usize, &Mod)> = vec![];
+    for &id in ids {
+        let mod_ = &mods[&id];
+        let name = mod_.meta.name.clone();
+        // Archived mods are left out of the load order on purpose
+        if !order.contains(&id) && !mod_.archived {
+            kinds.push(IssueKind::Unordered { hash: id, name });
+            continue;
+        }
+        let known: Vec<&PathBuf> = option_paths(mod_).collect();
+        let mut options: Vec<PathBuf> = mod_
+            .enabled_options
+            .iter()
+            .map(|o| &o.path)
+            .chain(mod_.inactive_options.iter())
+            .filter(|path| !known.contains(path))
+            .cloned()
+            .collect();
+        options.sort();
+        options.dedup();
+        if !options.is_empty() {
+            kinds.push(IssueKind::UnknownOptions {
+                hash: id,
+                name: name.clone(),
+                options,
+            });
+        }
+        if is_offline(mod_.hash) {
+            continue;
+        }
+        if !mod_.path.exists() {
+            kinds.push(IssueKind::MissingArchive { hash: id, name });
+            continue;
+        }
+        // Copies are the same mod by hash or by name, and the newest wins
+        match present
+            .iter_mut()
+            .find(|(_, other)| other.hash == mod_.hash || other.meta.name == mod_.meta.name)
+        {
+            Some((kept, other)) => {
+                let (keep, remove) = if is_newer(mod_, other) {
+                    let older = *kept;
+                    (*kept, *other) = (id, mod_);
+                    (id, older)
+                } else {
+                    (*kept, id)
+                };
+                kinds.retain(
+                    |kind| !matches!(kind, IssueKind::MismatchedId { id, .. } if *id == remove),
+                );
+                kinds.push(IssueKind::Duplicate { keep, remove, name });
+            }
+            None => {
+                if id != mod_.hash {
+                    kinds.push(IssueKind::MismatchedId {
+                        id,
+                        hash: mod_.hash,
+                        name,
+                    });
+                }
+                present.push((id, mod_));
+            }
+        }
+    }
+    kinds
+        .into_iter()
+        .map(|kind| {
+            Issue {
+                profile: name.into(),
+                kind,
+            }
+        })
+        .collect()
+}
+
+/// Repairs an issue in a profile.
+pub(crate) fn fix(profile: &Profile, kind: &IssueKind) {
+    let mut mods = profile.mods_mut();
+    let mut order = profile.load_order_mut();
+    match kind {
+        IssueKind::RepeatedOrder(hash) => {
+            let mut seen = false;
+            order.retain(|h| h != hash || !std::mem::replace(&mut seen, true));
+        }
+        IssueKind::DanglingOrder(hash) => order.retain(|h| h != hash),
+        IssueKind::Unordered { hash, .. } => {
+            if mods.contains_key(hash) && !order.contains(hash) {
+                order.push(*hash);
+            }
+        }
+        IssueKind::MismatchedId { id, hash, .. } => {
+            if !mods.contains_key(hash) {
+                if let Some(mod_) = mods.remove(id) {
+                    mods.insert(*hash, mod_);
+                    order
+                        .iter_mut()
+                        .filter(|h| *h == id)
+                        .for_each(|h| *h = *hash);
+                }
+            }
+        }
+        IssueKind::UnknownOptions { hash, options, .. } => {
+            if let Some(mod_) = mods.get_mut(hash) {
+                mod_.enabled_options.retain(|o| !options.contains(&o.path));
+                mod_.inactive_options.retain(|p| !options.contains(p));
+                mod_.prune_inactive();
+            }
+        }
+        IssueKind::MissingArchive { hash, .. } => {
+            mods.remove(hash);
+            order.retain(|h| h != hash);
+        }
+        IssueKind::Duplicate { keep, remove, .. } => {
+            if mods.remove(remove).is_some() {
+                if order.contains(keep) || mods.get(keep).is_some_and(|m| m.archived) {
+                    order.retain(|h| h != remove);
+                } else {
+                    order
+                        .iter_mut()
+                        .filter(|h| *h == remove)
+                        .for_each(|h| *h = *keep);
+                }
+            }
+        }
+    }
+}
+
+impl Manager {
+    /// Checks every profile for inconsistencies in its mod index. Mods found
+    /// offline are skipped; see [`Manager::offline_mods`].
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut profiles: Vec<_> = self.profiles.iter().collect();
+        profiles.sort_by(|a, b| a.key().cmp(b.key()));
+        profiles
+            .into_iter()
+            .flat_map(|profile| check(profile.key(), profile.value(), |hash| self.is_offline(hash)))
+            .collect()
+    }
+
+    /// Issues found when the index was loaded whose repairs need the user to
+    /// agree, if they have not been taken already.
+    pub fn take_load_issues(&self) -> Vec<Issue> {
+        std::mem::take(&mut self.load_issues.lock())
+    }
+
+    /// Repairs issues in the mod index. Each profile which changes is backed
+    /// up first, and if any fails to save, those already saved are restored,
+    /// so the index is never left partly repaired.
+    pub fn repair(&self, issues: &[Issue]) -> Result<()> {
+        let mut repaired: BTreeMap<String, Profile> = BTreeMap::new();
+        for issue in issues {
+            if !repaired.contains_key(&issue.profile) {
+                let Some(profile) = self.profiles.get(&issue.profile) else {
+                    log::warn!("Profile {} no longer exists", issue.profile);
+                    continue;
+                };
+                repaired.insert(issue.profile.clone(), profile.value().clone());
+            }
+            fix(&repaired[&issue.profile], &issue.kind);
+        }
+        let mut saved: Vec<&str> = vec![];
+        for (name, profile) in &repaired {
+            if let Err(e) = self.save_repaired(name, profile) {
+                for name in saved {
+                    let path = self.dir.join(name).join("profile.yml");
+                    if let Err(e) = fs::copy(path.with_extension("yml.bak"), &path) {
+                        log::error!("Failed to restore profile {name} from backup: {e:?}");
+                    }
+                }
+                return Err(e).with_context(|| format!("Failed to repair profile {name}"));
+            }
+            saved.push(name);
+        }
+        for issue in issues {
+            log::info!("Repaired mod index issue: {issue}");
+            let (Some(remove), Some(profile)) =
+                (issue.removed(), self.profiles.get(&issue.profile))
+            else {
+                continue;
+            };
+            let removed = profile.mods().get(&remove).cloned();
+            if let Some(mod_) = removed {
+                self.audit.record(Event::ModRemoved {
+                    profile: issue.profile.clone(),
+                    name:    mod_.meta.name,
+                    version: mod_.meta.version,
+                });
+            }
+        }
+        for (name, profile) in repaired {
+            self.profiles.insert(name, profile);
+        }
+        Ok(())
+    }
+
+    /// Saves a repaired profile, keeping the last version as a backup.
+    fn save_repaired(&self, name: &str, profile: &Profile) -> Result<()> {
+        let path = self.dir.join(name).join("profile.yml");
+        if path.exists() {
+            fs::copy(&path, path.with_extension("yml.bak"))?;
+        }
+        let stored = profile.clone();
+        stored.relativize_paths(&self.storage, &self.storage, &self.mods_dir);
+        let tmp = path.with_extension("yml.tmp");
+        fs::write(&tmp, serde_yaml::to_string(&stored)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Checks the index as it is loaded, making the safe repairs straight
+    /// away and holding the rest for the user.
+    pub(super) fn validate_on_load(&self) -> Result<()> {
+        let (safe, destructive): (Vec<_>, Vec<_>) =
+            self.validate().into_iter().partition(Issue::is_safe);
+        if !safe.is_empty() {
+            log::warn!("Repairing {} issue(s) in the mod index", safe.len());
+            self.repair(&safe)?;
+        }
+        *self.load_issues.lock() = destructive;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use uk_mod::{Meta, ModOption, MultipleOptionGroup, OptionGroup};
+
+    use super::*;
+    use crate::{settings::Settings, tests::manager};
+
+    fn option(path: &str) -> ModOption {
+        ModOption {
+            name: path.into(),
+            description: String::new(),
+            path: path.into(),
+            requires: vec![],
+            image: None,
+        }
+    }
+
+    fn stored_mod(storage: &Path, name: &str, hash: usize, version: &str) -> Mod {
+        let path = storage.join("mods").join(format!("{name} {hash}.zip"));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"").unwrap();
+        Mod {
+            meta: Meta {
+                api: "1.0.0".into(),
+                format: uk_mod::FORMAT_VERSION,
+                name: name.into(),
+                version: version.into(),
+                author: String::new(),
+                category: String::new(),
+                description: String::new(),
+                platform: uk_mod::ModPlatform::Universal,
+                url: None,
+                options: vec![OptionGroup::Multiple(MultipleOptionGroup {
+                    name: "Extras".into(),
+                    options: vec![option("extra")],
+                    ..Default::default()
+                })],
+                masters: Default::default(),
+            },
+            enabled_options: vec![],
+            inactive_options: vec![],
+            enabled: true,
+            path,
+            local_patch: false,
+            patch_id: None,
+            archived: false,
+            options_review: None,
+            hash,
+        }
+    }
+
+    /// Corrupted indexes, each with the issues it should have.
+    fn fixtures(storage: &Path) -> Vec<(&'static str, Profile, Vec<IssueKind>)> {
+        let profile = |mods: Vec<(usize, Mod)>, order: Vec<usize>| {
+            let profile = Profile::default();
+            profile.mods_mut().extend(mods);
+            *profile.load_order_mut() = order;
+            profile
+        };
+        let first = stored_mod(storage, "First", 1, "1.0.0");
+        let second = stored_mod(storage, "Second", 2, "1.0.0");
+        let mut renamed = stored_mod(storage, "Renamed", 3, "1.0.0");
+        renamed.enabled_options = vec![option("extra"), option("old_extra")];
+        renamed.inactive_options = vec!["old_extra".into()];
+        let mut missing = stored_mod(storage, "Missing", 4, "1.0.0");
+        fs::remove_file(&missing.path).unwrap();
+        missing.enabled_options = vec![option("extra")];
+        let newer = stored_mod(storage, "First", 5, "1.1.0");
+        let mut same_hash = stored_mod(storage, "Second Copy", 2, "1.0.0");
+        same_hash.meta.version = "0.9.0".into();
+        let moved = stored_mod(storage, "Moved", 6, "1.0.0");
+        vec![
+            (
+                "repeated order",
+                profile(vec![(1, first.clone()), (2, second.clone())], vec![
+                    1, 2, 1, 1,
+                ]),
+                vec![IssueKind::RepeatedOrder(1)],
+            ),
+            (
+                "dangling order",
+                profile(vec![(1, first.clone())], vec![9, 1, 9]),
+                vec![IssueKind::DanglingOrder(9)],
+            ),
+            (
+                "unordered",
+                profile(vec![(1, first.clone()), (2, second.clone())], vec![1]),
+                vec![IssueKind::Unordered {
+                    hash: 2,
+                    name: "Second".into(),
+                }],
+            ),
+            ("mismatched id", profile(vec![(7, moved)], vec![7]), vec![
+                IssueKind::MismatchedId {
+                    id:   7,
+                    hash: 6,
+                    name: "Moved".into(),
+                },
+            ]),
+            (
+                "unknown options",
+                profile(vec![(3, renamed)], vec![3]),
+                vec![IssueKind::UnknownOptions {
+                    hash:    3,
+                    name:    "Renamed".into(),
+                    options: vec!["old_extra".into()],
+                }],
+            ),
+            (
+                "missing archive",
+                profile(vec![(1, first.clone()), (4, missing)], vec![1, 4]),
+                vec![IssueKind::MissingArchive {
+                    hash: 4,
+                    name: "Missing".into(),
+                }],
+            ),
+            (
+                "duplicate name",
+                profile(vec![(1, first.clone()), (5, newer)], vec![1, 5]),
+                vec![IssueKind::Duplicate {
+                    keep:   5,
+                    remove: 1,
+                    name:   "First".into(),
+                }],
+            ),
+            (
+                "duplicate hash",
+                profile(vec![(2, second), (8, same_hash)], vec![8, 2]),
+                vec![IssueKind::Duplicate {
+                    keep:   2,
+                    remove: 8,
+                    name:   "Second".into(),
+                }],
+            ),
+        ]
+    }
+
+    #[test]
+    fn fixtures_checked() {
+        let tmp = tempfile::tempdir().unwrap();
+        for (name, profile, expected) in fixtures(tmp.path()) {
+            let issues = check(name, &profile, |_| false);
+            assert_eq!(
+                issues.iter().map(|i| i.kind.clone()).collect::<Vec<_>>(),
+                expected,
+                "{name}"
+            );
+            for issue in &issues {
+                fix(&profile, &issue.kind);
+            }
+            assert!(
+                check(name, &profile, |_| false).is_empty(),
+                "{name} after repair"
+            );
+            // Every mod in the load order can be looked up again
+            let mods = profile.mods();
+            assert!(
+                profile.load_order().iter().all(|h| mods.contains_key(h)),
+                "{name}"
+            );
+            assert_eq!(profile.load_order().len(), mods.len(), "{name}");
+        }
+    }
+
+    #[test]
+    fn repaired_on_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let storage = tmp.path().join("storage");
+        let settings = Settings {
+            storage_dir: storage.clone(),
+            ..Default::default()
+        };
+        for (name, profile, _) in fixtures(&storage) {
+            let dir = settings.profiles_dir().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("profile.yml"),
+                serde_yaml::to_string(&profile).unwrap(),
+            )
+            .unwrap();
+        }
+        let (_settings, manager) = manager(&storage);
+        // Safe repairs are made and saved straight away
+        let dangling = settings.profiles_dir().join("dangling order");
+        assert!(dangling.join("profile.yml.bak").exists());
+        assert!(!manager
+            .get_profile(Some(&"dangling order".into()))
+            .load_order()
+            .contains(&9));
+        assert_eq!(
+            manager.get_profile(Some(&"unknown options".into())).mods()[&3].enabled_options,
+            vec![option("extra")]
+        );
+        assert!(!settings
+            .profiles_dir()
+            .join("missing archive/profile.yml.bak")
+            .exists());
+
+        // The rest wait for the user, once
+        let issues = manager.take_load_issues();
+        assert!(manager.take_load_issues().is_empty());
+        assert_eq!(issues.len(), 3);
+        assert!(issues.iter().all(|i| !i.is_safe()));
+        assert_eq!(manager.validate(), issues);
+        manager.repair(&issues).unwrap();
+        assert!(manager.validate().is_empty());
+        let (_settings, manager) = crate::tests::manager(&storage);
+        assert!(manager.validate().is_empty());
+        assert!(manager.take_load_issues().is_empty());
+        assert_eq!(
+            manager
+                .get_profile(Some(&"missing archive".into()))
+                .load_order()
+                .as_slice(),
+            &[1]
+        );
+    }
+
+    #[test]
+    fn repair_rolled_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let storage = tmp.path().join("storage");
+        let (_settings, manager) = manager(&storage);
+        let first = stored_mod(&storage, "First", 1, "1.0.0");
+        let missing = stored_mod(&storage, "Missing", 2, "1.0.0");
+        fs::remove_file(&missing.path).unwrap();
+        for name in ["A", "B"] {
+            manager.create_profile_if(name).unwrap();
+            let profile = manager.get_profile(Some(&name.into()));
+            profile.mods_mut().insert(1, first.clone());
+            profile.mods_mut().insert(2, missing.clone());
+            *profile.load_order_mut() = vec![1, 2];
+            drop(profile);
+            manager.save_profile(name).unwrap();
+        }
+        let issues = manager.validate();
+        assert_eq!(issues.len(), 2);
+        let saved_a = fs::read_to_string(manager.dir.join("A/profile.yml")).unwrap();
+        // B cannot be saved, so A must be put back as it was
+        fs::create_dir_all(manager.dir.join("B/profile.yml.tmp")).unwrap();
+        assert!(manager.repair(&issues).is_err());
+        assert_eq!(
+            fs::read_to_string(manager.dir.join("A/profile.yml")).unwrap(),
+            saved_a
+        );
+        assert_eq!(manager.validate(), issues);
+    }
+}
@@ -0,0 +1,571 @@
+//! Checks run before merging to catch output folders which cannot be written
+//! to, so that a deploy fails in seconds instead of partway through.
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use uk_content::platform_prefixes;
+use uk_mod::Manifest;
+
+use crate::settings::{DeployMethod, Settings};
+
+const SENTINEL: &str = ".ukmm_write_test";
+/// Extra room required on top of the estimate, as a fraction of it.
+const MARGIN: f64 = 0.25;
+/// Fraction of an existing merged file's size assumed to be added when it is
+/// merged again.
+const GROWTH: f64 = 0.1;
+/// Free space left over below which a volume counts as nearly full, however
+/// little is being written.
+const MIN_HEADROOM: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PreflightError {
+    #[error("UKMM does not have permission to write to {}.\n\n{hint}", path.display())]
+    NotWritable {
+        path:   PathBuf,
+        hint:   String,
+        source: io::Error,
+    },
+    #[error(
+        "There is not enough free space to merge your mods. About {} is needed in {}, but only {} \
+         is available.",
+        human_size(*required),
+        path.display(),
+        human_size(*available)
+    )]
+    NoSpace {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+}
+
+/// Formats a byte count for display.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{size:.1} {unit}")
+}
+
+/// A rough size for a merged file of a given type which does not exist yet.
+fn typical_size(file: &str) -> u64 {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    match file.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("pack") => 16 * MIB,
+        Some("ssarc") | Some("sbfres") | Some("sbitemico") | Some("sbstftex") => MIB,
+        Some("sbactorpack") | Some("sblarc") | Some("smubin") => 256 * KIB,
+        _ => 64 * KIB,
+    }
+}
+
+/// Estimates the free space needed to merge the files in a manifest into the
+/// given merged folder. Existing files are assumed to grow slightly, and new
+/// files are guessed by type. If `clean` is set, the merged folder will be
+/// cleared first, so every file counts as new.
+pub fn estimate_required_space(
+    manifest: &Manifest,
+    out_dir: &Path,
+    prefixes: (&str, &str),
+    clean: bool,
+) -> u64 {
+    let (content, aoc) = prefixes;
+    let estimate: u64 = manifest
+        .content_files
+        .iter()
+        .map(|f| (content, f))
+        .chain(manifest.aoc_files.iter().map(|f| (aoc, f)))
+        .map(|(prefix, file)| {
+            let existing = (!clean)
+                .then(|| out_dir.join(prefix).join(file.as_str()).metadata().ok())
+                .flatten()
+                .filter(|m| m.is_file());
+            match existing {
+                Some(meta) => (meta.len() as f64 * GROWTH) as u64,
+                None => typical_size(file),
+            }
+        })
+        .sum();
+    estimate + (estimate as f64 * MARGIN) as u64
+}
+
+/// The bytes taken by a file, or by every file in a folder.
+pub fn path_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        jwalk::WalkDir::new(path)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| m.len())
+            .sum()
+    } else {
+        path.metadata().map(|m| m.len()).unwrap_or_default()
+    }
+}
+
+/// The total uncompressed size of the files in a ZIP or 7z archive, read from
+/// its index without extracting anything.
+fn unpacked_size(path: &Path) -> Option<u64> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "7z" | "bnp" => {
+            let archive = sevenz_rust::Archive::open(path).ok()?;
+            Some(archive.files.iter().map(|f| f.size()).sum())
+        }
+        _ => {
+            let mut zip = zip::ZipArchive::new(std::fs::File::open(path).ok()?).ok()?;
+            Some(
+                (0..zip.len())
+                    .filter_map(|i| zip.by_index_raw(i).ok().map(|f| f.size()))
+                    .sum(),
+            )
+        }
+    }
+}
+
+/// Estimates the space a mod will take in storage. Mods are kept packed as
+/// they come, so an archive counts at its own size, unless it is going to be
+/// `unpacked`, as when converting a mod from another format, when the sizes
+/// of the files in it are added up instead. A folder counts the files in it.
+pub fn estimate_install_size(path: &Path, unpacked: bool) -> u64 {
+    unpacked
+        .then(|| path.is_file().then(|| unpacked_size(path)).flatten())
+        .flatten()
+        .unwrap_or_else(|| path_size(path))
+}
+
+/// How the free space on a volume compares with what is about to be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Room {
+    Plenty,
+    /// Enough, but with less left over than a quarter of what is written or
+    /// a gibibyte, whichever is more.
+    Thin,
+    Short,
+}
+
+pub fn room(required: u64, available: u64) -> Room {
+    let headroom = ((required as f64 * MARGIN) as u64).max(MIN_HEADROOM);
+    if available < required {
+        Room::Short
+    } else if available - required < headroom {
+        Room::Thin
+    } else {
+        Room::Plenty
+    }
+}
+
+/// Free space which is short or thin for something about to be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpaceWarning {
+    pub path: PathBuf,
+    pub required: u64,
+    pub available: u64,
+    pub room: Room,
+}
+
+impl SpaceWarning {
+    /// Describes the warning for a confirmation prompt. `action` says what
+    /// needs the space, and `trash` is how much emptying the trash would
+    /// free, if it is on the same volume.
+    pub fn message(&self, action: &str, trash: u64) -> String {
+        let mut message = match self.room {
+            Room::Short => {
+                format!(
+                    "{action} needs about {}, but only {} is free in {}. It will most likely fail \
+                     partway through.",
+                    human_size(self.required),
+                    human_size(self.available),
+                    self.path.display()
+                )
+            }
+            _ => {
+                format!(
+                    "{action} needs about {}, which would leave only {} free in {}.",
+                    human_size(self.required),
+                    human_size(self.available - self.required),
+                    self.path.display()
+                )
+            }
+        };
+        if trash > 0 {
+            message.push_str(&format!(
+                " Emptying the trash in the profile manager would free about {}.",
+                human_size(trash)
+            ));
+        }
+        message
+    }
+}
+
+/// The free space on the volume holding `path`, or its closest existing
+/// parent, if it can be found out.
+pub fn free_space(path: &Path) -> Option<u64> {
+    let dir = nearest_existing(path);
+    fs2::available_space(dir)
+        .inspect_err(|e| log::debug!("Could not check free space in {}: {e}", dir.display()))
+        .ok()
+}
+
+/// Warns if writing `required` bytes to `path` would fill its volume or come
+/// close to it. If the free space cannot be found out, there is no warning.
+pub fn warn_space(path: &Path, required: u64) -> Option<SpaceWarning> {
+    let available = free_space(path)?;
+    match room(required, available) {
+        Room::Plenty => None,
+        room => {
+            Some(SpaceWarning {
+                path: nearest_existing(path).to_path_buf(),
+                required,
+                available,
+                room,
+            })
+        }
+    }
+}
+
+/// Warns about thin or short free space for merging `manifest`, and for
+/// deploying it if deploying copies the merged files.
+pub fn apply_warnings(settings: &Settings, manifest: &Manifest) -> Vec<SpaceWarning> {
+    let prefixes = platform_prefixes(settings.current_mode.into());
+    let merged = settings.merged_dir();
+    let required = estimate_required_space(manifest, &merged, prefixes, false);
+    let mut warnings: Vec<SpaceWarning> = warn_space(&merged, required).into_iter().collect();
+    let deploy = settings
+        .platform_config()
+        .and_then(|c| c.deploy_config.as_ref())
+        .filter(|c| c.auto && c.method == DeployMethod::Copy)
+        .and_then(|c| warn_space(&c.output, required));
+    if let Some(warning) = deploy.filter(|w| !warnings.contains(w)) {
+        warnings.push(warning);
+    }
+    warnings
+}
+
+/// The closest folder to `path` which already exists, which is where the
+/// write test and space check need to happen.
+fn nearest_existing(path: &Path) -> &Path {
+    path.ancestors().find(|p| p.is_dir()).unwrap_or(path)
+}
+
+/// Tests whether a folder (or the closest existing parent, if it has not been
+/// created yet) can be written to by creating and removing a sentinel file.
+pub fn probe_writable(path: &Path) -> Result<(), PreflightError> {
+    let dir = nearest_existing(path);
+    let sentinel = dir.join(SENTINEL);
+    std::fs::write(&sentinel, b"ukmm")
+        .and_then(|_| std::fs::remove_file(&sentinel))
+        .map_err(|source| {
+            PreflightError::NotWritable {
+                path: dir.to_path_buf(),
+                hint: access_hint(dir, &source),
+                source,
+            }
+        })
+}
+
+/// Checks that the volume holding `path` has at least `required` bytes free.
+/// If the free space cannot be determined, the check passes.
+pub fn check_space(path: &Path, required: u64) -> Result<(), PreflightError> {
+    let dir = nearest_existing(path);
+    match fs2::available_space(dir) {
+        Ok(available) if available < required => {
+            Err(PreflightError::NoSpace {
+                path: dir.to_path_buf(),
+                required,
+                available,
+            })
+        }
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::warn!("Could not check free space in {}: {e}", dir.display());
+            Ok(())
+        }
+    }
+}
+
+/// Finds which of the given protected folders, if any, contains `path`.
+/// Comparison ignores case, as Windows paths do.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn protected_root<'a>(path: &Path, roots: &'a [PathBuf]) -> Option<&'a Path> {
+    let path = path.to_string_lossy().to_lowercase().replace('\\', "/");
+    roots
+        .iter()
+        .find(|root| {
+            let root = root.to_string_lossy().to_lowercase().replace('\\', "/");
+            let root = root.trim_end_matches('/');
+            !root.is_empty()
+                && path
+                    .strip_prefix(root)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .map(|root| root.as_path())
+}
+
+#[cfg(windows)]
+fn access_hint(path: &Path, _err: &io::Error) -> String {
+    let roots: Vec<PathBuf> = [
+        "ProgramFiles",
+        "ProgramFiles(x86)",
+        "ProgramW6432",
+        "SystemRoot",
+    ]
+    .into_iter()
+    .filter_map(std::env::var_os)
+    .map(PathBuf::from)
+    .collect();
+    match protected_root(path, &roots) {
+        Some(root) => {
+            format!(
+                "This folder is inside {}, which Windows protects from changes by normal \
+                 programs. Move your emulator or output folder somewhere else, such as your \
+                 Documents folder, or run UKMM as administrator.",
+                root.display()
+            )
+        }
+        None => {
+            "Check that the folder is not read-only and that no other program (such as an \
+             antivirus or cloud sync tool) is blocking access to it, or choose a different folder \
+             in the settings."
+                .into()
+        }
+    }
+}
+
+/// The owner of a folder, if it is not the current user. The current user
+/// is taken to be the owner of the home folder.
+#[cfg(unix)]
+fn other_owner(path: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let uid = path.metadata().ok()?.uid();
+    let own = dirs2::home_dir()?.metadata().ok()?.uid();
+    (uid != own).then(|| {
+        match uid {
+            0 => "root".into(),
+            uid => format!("another user (UID {uid})"),
+        }
+    })
+}
+
+#[cfg(unix)]
+fn access_hint(path: &Path, err: &io::Error) -> String {
+    let mut hint = match other_owner(path) {
+        Some(owner) => format!("This folder is owned by {owner}. "),
+        None => String::new(),
+    };
+    if err.raw_os_error() == Some(libc::EROFS) {
+        hint.push_str("It is on a read-only file system. ");
+    }
+    hint.push_str(
+        "Change its ownership or permissions so your user can write to it, or choose a different \
+         folder in the settings.",
+    );
+    hint
+}
+
+#[cfg(not(any(unix, windows)))]
+fn access_hint(_path: &Path, _err: &io::Error) -> String {
+    "Check the folder's permissions or choose a different folder in the settings.".into()
+}
+
+/// Runs every pre-flight check for merging `manifest` with the current
+/// settings: the merged folder and the deploy output (both its base and DLC
+/// folders) must be writable, and there must be room for the merged files.
+pub fn check(settings: &Settings, manifest: &Manifest, clean: bool) -> Result<(), PreflightError> {
+    let prefixes = platform_prefixes(settings.current_mode.into());
+    let merged = settings.merged_dir();
+    probe_writable(&merged)?;
+    let required = estimate_required_space(manifest, &merged, prefixes, clean);
+    log::debug!("Estimated space needed to merge: {}", human_size(required));
+    check_space(&merged, required)?;
+    if let Some(config) = settings
+        .platform_config()
+        .and_then(|c| c.deploy_config.as_ref())
+    {
+        let mut targets = vec![
+            config.output.join(prefixes.0),
+            config.output.join(prefixes.1),
+        ];
+        targets.dedup_by(|a, b| nearest_existing(a) == nearest_existing(b));
+        for target in &targets {
+            probe_writable(target)?;
+        }
+        if config.auto && config.method == DeployMethod::Copy {
+            check_space(&config.output, required)?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn estimate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert("Pack/Bootup.pack".into());
+        manifest
+            .content_files
+            .insert("Actor/Pack/Enemy_Lynel_Dark.sbactorpack".into());
+        manifest
+            .aoc_files
+            .insert("Map/MainField/Static.smubin".into());
+        let prefixes = ("content", "aoc");
+        let new = 16 * 1024 * 1024 + 2 * 256 * 1024;
+        let expected = |bytes: u64| bytes + (bytes as f64 * MARGIN) as u64;
+        assert_eq!(
+            estimate_required_space(&manifest, tmp.path(), prefixes, false),
+            expected(new)
+        );
+        let existing = tmp.path().join("content/Pack/Bootup.pack");
+        std::fs::create_dir_all(existing.parent().unwrap()).unwrap();
+        std::fs::write(&existing, vec![0; 10_000]).unwrap();
+        assert_eq!(
+            estimate_required_space(&manifest, tmp.path(), prefixes, false),
+            expected(1000 + 2 * 256 * 1024)
+        );
+        assert_eq!(
+            estimate_required_space(&manifest, tmp.path(), prefixes, true),
+            expected(new)
+        );
+        assert_eq!(
+            estimate_required_space(&Manifest::default(), tmp.path(), prefixes, false),
+            0
+        );
+    }
+
+    #[test]
+    fn install_size() {
+        use std::io::Write;
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("mod.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        let opts = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        for (name, len) in [("a.bin", 100_000), ("b/c.bin", 50_000)] {
+            zip.start_file(name, opts).unwrap();
+            zip.write_all(&vec![0; len]).unwrap();
+        }
+        zip.finish().unwrap();
+        let packed = archive.metadata().unwrap().len();
+        assert!(packed < 150_000);
+        assert_eq!(estimate_install_size(&archive, false), packed);
+        assert_eq!(estimate_install_size(&archive, true), 150_000);
+        let folder = tmp.path().join("folder");
+        std::fs::create_dir_all(folder.join("sub")).unwrap();
+        std::fs::write(folder.join("a"), vec![0; 300]).unwrap();
+        std::fs::write(folder.join("sub/b"), vec![0; 200]).unwrap();
+        assert_eq!(estimate_install_size(&folder, true), 500);
+        assert_eq!(estimate_install_size(&tmp.path().join("missing"), true), 0);
+    }
+
+    #[test]
+    fn room_left() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        assert_eq!(room(GIB, GIB - 1), Room::Short);
+        assert_eq!(room(GIB, GIB), Room::Thin);
+        // Small writes still need a gibibyte spare
+        assert_eq!(room(1024, GIB), Room::Thin);
+        assert_eq!(room(1024, GIB + 1024), Room::Plenty);
+        // Large ones need a quarter of their size
+        assert_eq!(room(8 * GIB, 10 * GIB - 1), Room::Thin);
+        assert_eq!(room(8 * GIB, 10 * GIB), Room::Plenty);
+        let warning = SpaceWarning {
+            path: PathBuf::from("storage"),
+            required: GIB,
+            available: GIB + GIB / 2,
+            room: Room::Thin,
+        };
+        let message = warning.message("Installing", 2 * GIB);
+        assert!(message.contains("leave only 512.0 MiB free"));
+        assert!(message.contains("free about 2.0 GiB"));
+        assert!(!warning.message("Installing", 0).contains("trash"));
+    }
+
+    #[test]
+    fn probe() {
+        let tmp = tempfile::tempdir().unwrap();
+        let missing = tmp.path().join("not/created/yet");
+        probe_writable(&missing).unwrap();
+        assert!(!tmp.path().join(SENTINEL).exists());
+        assert!(!missing.exists());
+        check_space(tmp.path(), 0).unwrap();
+        assert!(matches!(
+            check_space(tmp.path(), u64::MAX),
+            Err(PreflightError::NoSpace { .. })
+        ));
+        assert!(free_space(&tmp.path().join("missing")).is_some());
+        assert_eq!(
+            warn_space(tmp.path(), u64::MAX).map(|w| w.room),
+            Some(Room::Short)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probe_read_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = tempfile::tempdir().unwrap();
+        let locked = tmp.path().join("locked");
+        std::fs::create_dir(&locked).unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // Privileged users can write regardless of permissions
+        if std::fs::write(locked.join("test"), b"").is_ok() {
+            return;
+        }
+        let err = probe_writable(&locked.join("output")).unwrap_err();
+        match err {
+            PreflightError::NotWritable { path, hint, .. } => {
+                assert_eq!(path, locked);
+                assert!(hint.contains("owned by"));
+            }
+            e => panic!("Unexpected error: {e}"),
+        }
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn protected() {
+        let roots = [
+            PathBuf::from(r"C:\Program Files"),
+            PathBuf::from(r"C:\Program Files (x86)"),
+        ];
+        assert_eq!(
+            protected_root(Path::new(r"c:\program files\Cemu\graphicPacks"), &roots),
+            Some(roots[0].as_path())
+        );
+        assert_eq!(
+            protected_root(Path::new(r"C:\Program Files (x86)\Cemu"), &roots),
+            Some(roots[1].as_path())
+        );
+        assert_eq!(
+            protected_root(Path::new(r"C:\Program Files Extra\Cemu"), &roots),
+            None
+        );
+        assert_eq!(protected_root(Path::new(r"D:\Games\Cemu"), &roots), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Checks that the game dump has not had mods merged into it. A dump which
+//! BCML or hand edits have already changed makes every comparison with
+//! "stock" wrong, so merges quietly bake the old mods in.
+//!
+//! Rather than hashing the whole dump, a few files which mods change most
+//! often are compared with the stock hashes for every released version of
+//! the platform, language packs included.
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+};
+
+use botw_utils::hashes::StockHashTable;
+use dashmap::DashMap;
+use roead::yaz0::decompress_if;
+use uk_content::canonicalize;
+use uk_reader::ResourceReader;
+
+use crate::settings::{Platform, Settings};
+
+/// Files checked in every dump, besides the language packs it has.
+const SENTINELS: &[&str] = &[
+    "Actor/ActorInfo.product.sbyml",
+    "Ecosystem/AreaData.sbyml",
+    "Pack/Bootup.pack",
+    "Actor/Pack/Enemy_Bokoblin_Junior.sbactorpack",
+    "Actor/Pack/Enemy_Lynel_Dark.sbactorpack",
+    "Actor/Pack/Weapon_Sword_001.sbactorpack",
+];
+
+/// Past results, by dump, since reading the files takes a moment.
+static RESULTS: LazyLock<DashMap<PathBuf, Arc<Vec<String>>>> = LazyLock::new(Default::default);
+
+/// The files checked in a dump.
+fn sentinels(dump: &ResourceReader) -> Vec<String> {
+    SENTINELS
+        .iter()
+        .map(|f| f.to_string())
+        .chain(dump.languages().iter().map(|l| l.bootup_path().to_string()))
+        .collect()
+}
+
+/// Lists the checked files in a dump which `is_stock` does not recognize.
+/// Files missing from the dump are left out, since nothing can be said about
+/// them.
+fn check_with(dump: &ResourceReader, is_stock: impl Fn(&str, &[u8]) -> bool) -> Vec<String> {
+    sentinels(dump)
+        .into_iter()
+        .filter(|file| {
+            match dump.get_bytes_uncached(file) {
+                Ok(data) => !is_stock(&canonicalize(file), &decompress_if(&data)),
+                Err(e) => {
+                    log::debug!("Could not check {file} in the dump: {e}");
+                    false
+                }
+            }
+        })
+        .collect()
+}
+
+/// Lists the checked files in a dump which differ from every stock version.
+pub fn check(dump: &ResourceReader, platform: Platform) -> Vec<String> {
+    let table = StockHashTable::new(&match platform {
+        Platform::WiiU => botw_utils::hashes::Platform::WiiU,
+        Platform::Switch => botw_utils::hashes::Platform::Switch,
+    });
+    let modded = check_with(dump, |file, data| !uk_mod::is_file_modded(&table, file, data, false));
+    if !modded.is_empty() {
+        log::warn!(
+            "The game dump appears to be modded. These files differ from stock: {}",
+            modded.join(", ")
+        );
+    }
+    modded
+}
+
+/// Lists the modded files in a dump, checking it only the first time.
+pub fn modded_files(dump: &ResourceReader, platform: Platform) -> Arc<Vec<String>> {
+    let key = dump.source().host_path().to_path_buf();
+    if let Some(result) = RESULTS.get(&key) {
+        return result.clone();
+    }
+    let result = Arc::new(check(dump, platform));
+    RESULTS.insert(key, result.clone());
+    result
+}
+
+/// The modded files found in a dump, if it has been checked.
+pub fn cached(dump: &ResourceReader) -> Option<Arc<Vec<String>>> {
+    RESULTS
+        .get(dump.source().host_path())
+        .map(|result| result.clone())
+}
+
+/// The modded files in the current dump, unless there are none or they have
+/// been accepted already.
+pub fn unaccepted(settings: &Settings) -> Option<Arc<Vec<String>>> {
+    let dump = settings.dump()?;
+    if settings
+        .accepted_dumps
+        .iter()
+        .any(|d| d == dump.source().host_path())
+    {
+        return None;
+    }
+    Some(modded_files(&dump, settings.current_mode)).filter(|files| !files.is_empty())
+}
+
+/// Explains what a modded dump means, for a warning.
+pub fn warning(files: &[String], dump: &Path) -> String {
+    format!(
+        "The game dump at {} appears to have been modded already. These files differ from every \
+         stock version of the game:\n\n{}\n\nUKMM compares mods with the dump to merge them, so \
+         whatever was changed in it will be treated as stock. It will end up in every merge, and \
+         mods changing the same things may not merge correctly. For best results, use a clean \
+         dump of the game instead. If the dump was modded on purpose, you can use it anyway.",
+        dump.display(),
+        files.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use uk_content::prelude::Endian;
+    use uk_reader::MemoryROMSource;
+
+    use super::*;
+
+    fn dump() -> ResourceReader {
+        ResourceReader::from_memory(
+            MemoryROMSource::new(Endian::Big)
+                .with_file(
+                    "Actor/ActorInfo.product.sbyml",
+                    b"stock actor info".to_vec(),
+                )
+                .with_file("Ecosystem/AreaData.sbyml", b"tampered area data".to_vec())
+                .with_file("Pack/Bootup_USen.pack", b"tampered texts".to_vec())
+                .with_file("Pack/Bootup_EUde.pack", b"stock texts".to_vec()),
+        )
+    }
+
+    #[test]
+    fn tampered_files() {
+        let dump = dump();
+        let modded = check_with(&dump, |file, data| {
+            match file {
+                "Actor/ActorInfo.product.byml" => data == b"stock actor info",
+                _ => data == b"stock texts",
+            }
+        });
+        assert_eq!(modded, [
+            "Ecosystem/AreaData.sbyml",
+            "Pack/Bootup_USen.pack"
+        ]);
+    }
+
+    #[test]
+    fn stock_table() {
+        // Made-up contents match no stock version
+        let dump = dump();
+        let modded = modded_files(&dump, Platform::WiiU);
+        assert_eq!(modded.len(), 4);
+        assert!(modded.contains(&"Actor/ActorInfo.product.sbyml".to_string()));
+        assert_eq!(cached(&dump), Some(modded));
+        let clean = ResourceReader::from_memory(MemoryROMSource::new(Endian::Big));
+        assert!(cached(&clean).is_none());
+        assert!(modded_files(&clean, Platform::WiiU).is_empty());
+    }
+
+    #[test]
+    fn accepted() {
+        let dump = Arc::new(dump());
+        let mut settings = Settings {
+            current_mode: Platform::WiiU,
+            wiiu_config: Some(crate::settings::PlatformSettings {
+                language: uk_content::constants::Language::USen,
+                profile: "Default".into(),
+                dump: dump.clone(),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
+                deploy_config: None,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(unaccepted(&settings).map(|f| f.len()), Some(4));
+        settings
+            .accepted_dumps
+            .push(dump.source().host_path().to_path_buf());
+        assert!(unaccepted(&settings).is_none());
+        assert!(warning(&["Pack/Bootup.pack".into()], Path::new("/dump"))
+            .contains("/dump appears to have been modded"));
+    }
+}
@@ -0,0 +1,258 @@
+//! Recovery for a storage folder which can no longer be found, usually
+//! because it was moved or is on a drive which is not connected.
+use std::path::{Path, PathBuf};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+
+use crate::{
+    mods::Profile,
+    settings::{Platform, Settings},
+};
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "The storage folder at {} could not be found. It may have been moved, or it may be on a \
+     drive which is not connected.",
+    .0.display()
+)]
+pub struct StorageMissing(pub PathBuf);
+
+/// Checks that the storage folder is where the settings say. Nothing is
+/// stored before a platform is set up, so until then a missing folder is
+/// expected.
+pub fn check(settings: &Settings) -> std::result::Result<(), StorageMissing> {
+    let set_up = settings.wiiu_config.is_some() || settings.switch_config.is_some();
+    if set_up && !settings.storage_dir.is_dir() {
+        Err(StorageMissing(settings.storage_dir.clone()))
+    } else {
+        Ok(())
+    }
+}
+
+/// The outcome of pointing the settings at a moved storage folder.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Relocation {
+    /// Mod paths which referred to the old location and were fixed.
+    pub fixed:   usize,
+    /// Names of mods in any profile whose files are not in the new folder.
+    pub missing: Vec<String>,
+}
+
+/// Points the settings at the folder the storage was moved to, after checking
+/// that it holds profiles, and fixes the mod paths in them which still refer
+/// to the old location. The settings are not saved.
+pub fn relocate(settings: &mut Settings, dir: &Path) -> Result<Relocation> {
+    let mut moved = settings.clone();
+    moved.storage_dir = dir.to_path_buf();
+    let platform_dirs: Vec<PathBuf> = [Platform::WiiU, Platform::Switch]
+        .into_iter()
+        .map(|platform| moved.get_platform_dir(platform))
+        .filter(|platform_dir| platform_dir.join("profiles").is_dir())
+        .collect();
+    if platform_dirs.is_empty() {
+        anyhow_ext::bail!(
+            "{} does not look like a UKMM storage folder. It should contain a wiiu or nx folder \
+             with profiles in it.",
+            dir.display()
+        );
+    }
+    let mut relocation = Relocation::default();
+    for platform_dir in platform_dirs {
+        let mods_dir = platform_dir.join("mods");
+        for entry in fs::read_dir(platform_dir.join("profiles"))? {
+            let path = entry?.path().join("profile.yml");
+            if !path.is_file() {
+                continue;
+            }
+            let profile: Profile = serde_yaml::from_str(&fs::read_to_string(&path)?)
+                .with_context(|| format!("Failed to parse profile at {}", path.display()))?;
+            let fixed = profile.relativize_paths(dir, &settings.storage_dir, &mods_dir);
+            if fixed > 0 {
+                fs::write(&path, serde_yaml::to_string(&profile)?)?;
+                relocation.fixed += fixed;
+            }
+            relocation.missing.extend(
+                profile
+                    .mods()
+                    .values()
+                    .filter(|mod_| !dir.join(&mod_.path).exists())
+                    .map(|mod_| mod_.meta.name.to_string()),
+            );
+        }
+    }
+    relocation.missing.sort();
+    relocation.missing.dedup();
+    *settings = moved;
+    Ok(relocation)
+}
+
+/// Starts over with an empty storage folder. The settings are not saved.
+pub fn start_fresh(settings: &mut Settings, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).context("Failed to create storage folder")?;
+    settings.storage_dir = dir.to_path_buf();
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::RwLock;
+    use uk_content::{constants::Language, prelude::Endian};
+    use uk_mod::Meta;
+    use uk_reader::{MemoryROMSource, ResourceReader};
+
+    use super::*;
+    use crate::{mods::Mod, settings::PlatformSettings};
+
+    fn settings(storage: &Path) -> Settings {
+        Settings {
+            storage_dir: storage.to_path_buf(),
+            wiiu_config: Some(PlatformSettings {
+                language: Language::USen,
+                profile: "Default".into(),
+                dump: Arc::new(ResourceReader::from_memory(MemoryROMSource::new(
+                    Endian::Big,
+                ))),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
+                deploy_config: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn stored_mod(name: &str, hash: usize, path: PathBuf) -> Mod {
+        Mod {
+            meta: Meta {
+                api: "1.0.0".into(),
+                format: uk_mod::FORMAT_VERSION,
+                name: name.into(),
+                version: "1.0.0".into(),
+                author: Default::default(),
+                category: Default::default(),
+                description: Default::default(),
+                platform: uk_mod::ModPlatform::Universal,
+                url: None,
+                options: vec![],
+                masters: Default::default(),
+            },
+            enabled_options: vec![],
+            inactive_options: vec![],
+            enabled: true,
+            path,
+            local_patch: false,
+            patch_id: None,
+            archived: false,
+            options_review: None,
+            hash,
+        }
+    }
+
+    /// Writes a profile the way older versions did, with absolute mod paths.
+    fn write_profile(settings: &Settings, mods: Vec<Mod>) {
+        let profile = Profile::default();
+        for mod_ in mods {
+            if mod_.path.starts_with(&settings.storage_dir) {
+                fs::create_dir_all(mod_.path.parent().unwrap()).unwrap();
+                fs::write(&mod_.path, b"").unwrap();
+            }
+            profile.load_order_mut().push(mod_.hash);
+            profile.mods_mut().insert(mod_.hash, mod_);
+        }
+        let dir = settings.profiles_dir().join("Default");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("profile.yml"),
+            serde_yaml::to_string(&profile).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn read_profile(settings: &Settings) -> Profile {
+        let path = settings.profiles_dir().join("Default/profile.yml");
+        serde_yaml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn legacy_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = settings(&tmp.path().join("storage"));
+        let stored = settings.mods_dir().join("Test.zip");
+        write_profile(&settings, vec![stored_mod("Test", 1, stored.clone())]);
+        let settings = Arc::new(RwLock::new(settings));
+        let manager = crate::mods::Manager::init(&settings).unwrap();
+        // Paths are absolute in use but relative to the storage folder on disk
+        assert_eq!(manager.get_mod(1).unwrap().path, stored);
+        assert_eq!(
+            read_profile(&settings.read()).mods()[&1].path,
+            Path::new("wiiu/mods/Test.zip")
+        );
+        manager.save().unwrap();
+        assert_eq!(
+            read_profile(&settings.read()).mods()[&1].path,
+            Path::new("wiiu/mods/Test.zip")
+        );
+    }
+
+    #[test]
+    fn moved_storage() {
+        let tmp = tempfile::tempdir().unwrap();
+        let old = tmp.path().join("old");
+        let mut settings = settings(&old);
+        write_profile(&settings, vec![
+            stored_mod("Moved", 1, settings.mods_dir().join("Moved.zip")),
+            stored_mod("Lost", 2, settings.mods_dir().join("Lost.zip")),
+            // Stored by a copy of UKMM which used an older storage folder
+            stored_mod("Older", 3, tmp.path().join("older/wiiu/mods/Older.zip")),
+        ]);
+        fs::write(settings.mods_dir().join("Older.zip"), b"").unwrap();
+        fs::remove_file(settings.mods_dir().join("Lost.zip")).unwrap();
+        let new = tmp.path().join("new");
+        fs::rename(&old, &new).unwrap();
+        assert!(check(&settings).is_err());
+
+        // A folder without profiles is not accepted
+        let empty = tmp.path().join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(relocate(&mut settings, &empty).is_err());
+        assert_eq!(settings.storage_dir, old);
+
+        let relocation = relocate(&mut settings, &new).unwrap();
+        assert_eq!(relocation, Relocation {
+            fixed:   3,
+            missing: vec!["Lost".into()],
+        });
+        assert_eq!(settings.storage_dir, new);
+        assert!(check(&settings).is_ok());
+        let settings = Arc::new(RwLock::new(settings));
+        let manager = crate::mods::Manager::init(&settings).unwrap();
+        for (hash, file) in [(1, "Moved.zip"), (3, "Older.zip")] {
+            let path = manager.get_mod(hash).unwrap().path;
+            assert_eq!(path, new.join("wiiu/mods").join(file));
+            assert!(path.exists());
+        }
+    }
+
+    #[test]
+    fn fresh_storage() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut settings = settings(&tmp.path().join("gone"));
+        assert!(check(&settings).is_err());
+        // Before setup, nothing is expected to be stored yet
+        assert!(check(&Settings {
+            wiiu_config: None,
+            ..settings.clone()
+        })
+        .is_ok());
+        let fresh = tmp.path().join("fresh");
+        start_fresh(&mut settings, &fresh).unwrap();
+        assert!(check(&settings).is_ok());
+        let settings = Arc::new(RwLock::new(settings));
+        let manager = crate::mods::Manager::init(&settings).unwrap();
+        assert_eq!(manager.all_mods().count(), 0);
+        assert!(fresh.join("wiiu/profiles/Default").is_dir());
+    }
+}
@@ -1,6 +1,7 @@
 #![allow(clippy::unwrap_used)]
 
 use std::{
+    collections::BTreeMap,
     path::{Path, PathBuf},
     sync::{Arc, LazyLock},
 };
@@ -12,7 +13,17 @@ use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DefaultOnError};
 use smartstring::alias::String;
 use uk_content::constants::Language;
-use uk_reader::ResourceReader;
+use uk_reader::{DumpRelease, ResourceReader};
+
+use crate::{
+    audit::{AuditLog, Event},
+    drift::{DriftCheck, Locate},
+    hooks::DeployHook,
+};
+
+/// Most space the cache of parsed resources from a game dump may use before
+/// the least recently used are removed.
+const DUMP_CACHE_CAP: u64 = 2 * 1024 * 1024 * 1024;
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Platform {
@@ -31,6 +42,16 @@ impl std::fmt::Display for Platform {
     }
 }
 
+impl Platform {
+    /// The version of the game UKMM supports on the platform.
+    pub fn game_version(self) -> &'static str {
+        match self {
+            Self::WiiU => "1.5.0",
+            Self::Switch => "1.6.0",
+        }
+    }
+}
+
 impl std::str::FromStr for Platform {
     type Err = anyhow_ext::Error;
 
@@ -105,6 +126,79 @@ pub struct DeployConfig {
     pub cemu_rules: bool,
     #[serde(default)]
     pub executable: Option<std::string::String>,
+    /// Commands to run after each deployment, in order.
+    #[serde(default)]
+    pub hooks: Vec<DeployHook>,
+    /// How much of the deployment folder to check for external changes.
+    #[serde(default)]
+    pub drift_check: DriftCheck,
+    /// Where to deploy base game files instead of their usual folder in the
+    /// output folder.
+    #[serde(default)]
+    pub content_output: Option<PathBuf>,
+    /// Where to deploy DLC files instead of their usual folder in the output
+    /// folder.
+    #[serde(default)]
+    pub aoc_output: Option<PathBuf>,
+}
+
+impl DeployConfig {
+    /// Where the base game and DLC files are deployed for a platform.
+    pub fn roots(&self, platform: Platform) -> DeployRoots {
+        let prefixes = uk_content::platform_prefixes(platform.into());
+        DeployRoots {
+            content: self
+                .content_output
+                .clone()
+                .unwrap_or_else(|| self.output.join(prefixes.0)),
+            aoc: self
+                .aoc_output
+                .clone()
+                .unwrap_or_else(|| self.output.join(prefixes.1)),
+            output: self.output.clone(),
+            prefixes,
+        }
+    }
+
+    /// Whether either half of the merged files is deployed somewhere of its
+    /// own.
+    pub fn is_split(&self) -> bool {
+        self.content_output.is_some() || self.aoc_output.is_some()
+    }
+}
+
+/// The folders the two halves of the merged files are deployed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeployRoots {
+    pub content: PathBuf,
+    pub aoc: PathBuf,
+    output: PathBuf,
+    prefixes: (&'static str, &'static str),
+}
+
+impl DeployRoots {
+    /// The deployment folder for files under a folder of the merged folder.
+    pub fn for_prefix(&self, prefix: &str) -> &Path {
+        if prefix == self.prefixes.0 {
+            &self.content
+        } else if prefix == self.prefixes.1 {
+            &self.aoc
+        } else {
+            &self.output
+        }
+    }
+}
+
+impl Locate for DeployRoots {
+    fn locate(&self, file: &str) -> PathBuf {
+        [self.prefixes.0, self.prefixes.1]
+            .into_iter()
+            .find_map(|prefix| {
+                let rel = file.strip_prefix(prefix)?.strip_prefix('/')?;
+                Some(self.for_prefix(prefix).join(rel))
+            })
+            .unwrap_or_else(|| self.output.join(file))
+    }
 }
 
 impl Default for DeployConfig {
@@ -115,6 +209,10 @@ impl Default for DeployConfig {
             auto: false,
             cemu_rules: false,
             executable: None,
+            hooks: vec![],
+            drift_check: DriftCheck::default(),
+            content_output: None,
+            aoc_output: None,
         }
     }
 }
@@ -137,14 +235,97 @@ impl DeployMethod {
     }
 }
 
+/// Settings for serving the merged output over HTTP.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    /// Start serving automatically after each apply.
+    pub after_apply: bool,
+    /// Address to listen on. The default listens on every interface, so other
+    /// devices on the network can connect.
+    pub bind: String,
+    pub port: u16,
+    /// Minutes without a request before the server stops, or 0 to never stop.
+    pub idle_minutes: u64,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        ServeConfig {
+            after_apply: false,
+            bind: "0.0.0.0".into(),
+            port: 8765,
+            idle_minutes: 15,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PlatformSettings {
     pub language: Language,
     pub profile: String,
+    /// The main game dump, used by every profile which has not chosen
+    /// another.
     pub dump: Arc<ResourceReader>,
+    /// Further dumps by name, such as one of another region, which profiles
+    /// can use instead of the main one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dumps: BTreeMap<String, Arc<ResourceReader>>,
+    /// The name of the dump each profile uses, for those not using the main
+    /// one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile_dumps: BTreeMap<String, String>,
     pub deploy_config: Option<DeployConfig>,
 }
 
+impl PlatformSettings {
+    /// Checks that the dump is the release UKMM supports on the platform,
+    /// with texts in the chosen language, so a wrong folder is caught here
+    /// rather than partway through a merge.
+    pub fn check_dump(&self, platform: Platform) -> Result<()> {
+        self.dump.detect_region_and_version()?.check(
+            self.language,
+            platform.game_version(),
+            self.dump.source().host_path(),
+        )?;
+        for (name, dump) in &self.dumps {
+            // A named dump may be of another region on purpose, so only its
+            // version is checked
+            DumpRelease {
+                languages: vec![],
+                ..dump.detect_region_and_version()?
+            }
+            .check(
+                self.language,
+                platform.game_version(),
+                dump.source().host_path(),
+            )
+            .with_context(|| format!("The game dump named {name} is not usable"))?;
+        }
+        Ok(())
+    }
+
+    /// The name of the dump a profile uses, if it does not use the main one.
+    pub fn dump_name(&self, profile: &str) -> Option<&str> {
+        self.profile_dumps
+            .get(profile)
+            .map(|name| name.as_str())
+            .filter(|name| self.dumps.contains_key(*name))
+    }
+
+    /// The dump a profile uses: the one it chose, or else the main dump.
+    pub fn dump_for(&self, profile: &str) -> &Arc<ResourceReader> {
+        self.dump_name(profile)
+            .and_then(|name| self.dumps.get(name))
+            .unwrap_or(&self.dump)
+    }
+
+    /// The dump the current profile uses.
+    pub fn active_dump(&self) -> &Arc<ResourceReader> {
+        self.dump_for(&self.profile)
+    }
+}
+
 #[inline]
 fn default_storage() -> PathBuf {
     if std::env::args().any(|a| a == "--portable") {
@@ -173,7 +354,21 @@ pub struct Settings {
     #[serde(deserialize_with = "serde_with::As::<DefaultOnError>::deserialize")]
     pub check_updates: UpdatePreference,
     pub show_changelog: bool,
+    /// Keep running in the system tray when the window is closed.
+    pub minimize_to_tray: bool,
     pub last_version: Option<String>,
+    /// How many past applies to keep in the deployment history.
+    pub history_len: usize,
+    /// Store installed mods unpacked, with each resource kept once in a
+    /// shared object store however many mods have it.
+    pub unpack_mods: bool,
+    /// Locale used to sort mod names, such as `ja` or `sv`. Empty sorts by
+    /// the default Unicode collation.
+    pub sort_locale: String,
+    pub serve: ServeConfig,
+    /// Dumps found to be modded which were chosen to be used anyway, so they
+    /// are not warned about again.
+    pub accepted_dumps: Vec<PathBuf>,
     pub wiiu_config: Option<PlatformSettings>,
     pub switch_config: Option<PlatformSettings>,
 }
@@ -188,7 +383,13 @@ impl Default for Settings {
             switch_config: None,
             check_updates: UpdatePreference::Stable,
             show_changelog: true,
+            minimize_to_tray: false,
             last_version: None,
+            history_len: 20,
+            unpack_mods: false,
+            sort_locale: String::new(),
+            serve: ServeConfig::default(),
+            accepted_dumps: vec![],
         }
     }
 }
@@ -220,6 +421,7 @@ impl Settings {
             Ok(settings) => {
                 log::debug!("{:#?}", settings);
                 crate::util::USE_SZ.store(settings.system_7z, std::sync::atomic::Ordering::Release);
+                settings.use_dump_caches();
                 settings
             }
             Err(e) => {
@@ -234,6 +436,7 @@ impl Settings {
         *self = match Settings::read(Self::path()) {
             Ok(settings) => {
                 log::debug!("{:#?}", settings);
+                settings.use_dump_caches();
                 settings
             }
             Err(e) => {
@@ -265,11 +468,43 @@ impl Settings {
             std::sync::atomic::Ordering::Relaxed,
             std::sync::atomic::Ordering::Relaxed,
         );
-        fs::write(Self::path(), serde_yaml::to_string(self)?)?;
+        let text = serde_yaml::to_string(self)?;
+        let saved = fs::read_to_string(Self::path()).ok();
+        // Saving unchanged settings, as happens on exit, is not a change
+        let changed = saved.as_deref() != Some(text.as_str());
+        for (platform, config) in self.changed_configs(saved.as_deref())? {
+            config
+                .check_dump(platform)
+                .with_context(|| format!("The {platform} game dump is not usable"))?;
+        }
+        fs::write(Self::path(), text)?;
         log::info!("Settings saved");
+        if changed {
+            AuditLog::open(self).record(Event::SettingsChanged);
+        }
         Ok(())
     }
 
+    /// The platform settings which differ from those in a saved settings
+    /// file, so whose dumps need checking again. Other changes are saved
+    /// without checking dumps which were accepted before.
+    fn changed_configs(&self, saved: Option<&str>) -> Result<Vec<(Platform, &PlatformSettings)>> {
+        let current = serde_yaml::to_value(self)?;
+        let saved: Option<serde_yaml::Value> =
+            saved.and_then(|text| serde_yaml::from_str(text).ok());
+        Ok([
+            (Platform::WiiU, "wiiu_config", &self.wiiu_config),
+            (Platform::Switch, "switch_config", &self.switch_config),
+        ]
+        .into_iter()
+        .filter_map(|(platform, key, config)| {
+            let config = config.as_ref()?;
+            let before = saved.as_ref().and_then(|saved| saved.get(key));
+            (before != current.get(key)).then_some((platform, config))
+        })
+        .collect())
+    }
+
     #[inline]
     pub fn platform_dir(&self) -> PathBuf {
         self.get_platform_dir(self.current_mode)
@@ -297,6 +532,12 @@ impl Settings {
         )
     }
 
+    /// Where the merge policy overrides for the current profile are kept.
+    #[inline]
+    pub fn merge_policy_path(&self) -> PathBuf {
+        self.profile_dir().join("merge_policy.yml")
+    }
+
     #[inline]
     pub fn profiles(&self) -> impl Iterator<Item = String> {
         fs::read_dir(self.profiles_dir())
@@ -313,19 +554,76 @@ impl Settings {
             })
     }
 
+    /// Where deleted profiles are kept until the trash is emptied.
+    #[inline]
+    pub fn trash_dir(&self) -> PathBuf {
+        self.platform_dir().join("trash")
+    }
+
     #[inline]
     pub fn mods_dir(&self) -> PathBuf {
         self.platform_dir().join("mods")
     }
 
+    /// The object store of mods stored unpacked.
     #[inline]
-    pub fn dump(&self) -> Option<Arc<ResourceReader>> {
-        match self.current_mode {
-            Platform::Switch => self.switch_config.as_ref().map(|c| c.dump.clone()),
-            Platform::WiiU => self.wiiu_config.as_ref().map(|c| c.dump.clone()),
+    pub fn objects_dir(&self) -> PathBuf {
+        self.platform_dir().join(uk_mod::objects::OBJECTS_DIR)
+    }
+
+    #[inline]
+    pub fn resource_cache_dir(&self) -> PathBuf {
+        self.platform_dir().join("cache")
+    }
+
+    /// Where parsed resources from the game dump for a platform are cached.
+    #[inline]
+    pub fn dump_cache_dir(&self, platform: Platform) -> PathBuf {
+        self.get_platform_dir(platform).join("cache").join("dump")
+    }
+
+    /// Where parsed resources from a named game dump for a platform are
+    /// cached, apart from those of the main dump so switching between them
+    /// does not empty either.
+    #[inline]
+    pub fn named_dump_cache_dir(&self, platform: Platform, name: &str) -> PathBuf {
+        self.get_platform_dir(platform)
+            .join("cache")
+            .join("dumps")
+            .join(uk_mod::pack::sanitise(name))
+    }
+
+    /// Has the game dumps for both platforms keep parsed resources on disk.
+    fn use_dump_caches(&self) {
+        for (platform, config) in [
+            (Platform::WiiU, &self.wiiu_config),
+            (Platform::Switch, &self.switch_config),
+        ] {
+            if let Some(config) = config {
+                if let Err(e) = config
+                    .dump
+                    .use_disk_cache(self.dump_cache_dir(platform), DUMP_CACHE_CAP)
+                {
+                    log::warn!("Failed to open {platform} game dump cache: {e:?}");
+                }
+                for (name, dump) in &config.dumps {
+                    if let Err(e) = dump
+                        .use_disk_cache(self.named_dump_cache_dir(platform, name), DUMP_CACHE_CAP)
+                    {
+                        log::warn!("Failed to open cache for {platform} game dump {name}: {e:?}");
+                    }
+                }
+            }
         }
     }
 
+    /// The game dump the current profile uses.
+    #[inline]
+    pub fn dump(&self) -> Option<Arc<ResourceReader>> {
+        self.platform_config()
+            .map(|config| config.active_dump().clone())
+    }
+
     #[inline(always)]
     pub fn platform_config(&self) -> Option<&PlatformSettings> {
         match self.current_mode {
@@ -365,3 +663,163 @@ impl Settings {
         self.storage_dir.join("projects")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use uk_content::prelude::Endian;
+    use uk_reader::MemoryROMSource;
+
+    use super::*;
+
+    #[test]
+    fn memory_dump() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dump = MemoryROMSource::new(Endian::Big).with_file("System/Version.txt", "1.5.0");
+        let settings = Settings {
+            wiiu_config: Some(PlatformSettings {
+                language: Language::USen,
+                profile: "Default".into(),
+                dump: Arc::new(ResourceReader::from_memory(dump)),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
+                deploy_config: None,
+            }),
+            ..Default::default()
+        };
+        let path = tmp.path().join("settings.yml");
+        fs::write(&path, serde_yaml::to_string(&settings).unwrap()).unwrap();
+        let read = Settings::read(&path).unwrap();
+        assert_eq!(read, settings);
+        assert_eq!(
+            read.dump()
+                .unwrap()
+                .get_bytes_uncached("System/Version.txt")
+                .unwrap(),
+            b"1.5.0"
+        );
+    }
+
+    #[test]
+    fn changed_configs() {
+        let config = |language: Language| {
+            PlatformSettings {
+                language,
+                profile: "Default".into(),
+                dump: Arc::new(ResourceReader::from_memory(
+                    MemoryROMSource::new(Endian::Big).with_file("System/Version.txt", "1.5.0"),
+                )),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
+                deploy_config: None,
+            }
+        };
+        let changed = |settings: &Settings, saved: Option<&str>| {
+            settings
+                .changed_configs(saved)
+                .unwrap()
+                .into_iter()
+                .map(|(platform, _)| platform)
+                .collect::<Vec<_>>()
+        };
+        let mut settings = Settings {
+            wiiu_config: Some(config(Language::USen)),
+            ..Default::default()
+        };
+        assert_eq!(changed(&settings, None), [Platform::WiiU]);
+        let saved = serde_yaml::to_string(&settings).unwrap();
+        assert!(changed(&settings, Some(&saved)).is_empty());
+        // Other settings leave the dumps alone
+        settings.system_7z = !settings.system_7z;
+        assert!(changed(&settings, Some(&saved)).is_empty());
+        settings.switch_config = Some(config(Language::USen));
+        assert_eq!(changed(&settings, Some(&saved)), [Platform::Switch]);
+        if let Some(wiiu) = settings.wiiu_config.as_mut() {
+            wiiu.language = Language::EUen;
+        }
+        assert_eq!(changed(&settings, Some(&saved)), [
+            Platform::WiiU,
+            Platform::Switch
+        ]);
+    }
+
+    #[test]
+    fn check_dump() {
+        let config = |version: &str| {
+            PlatformSettings {
+                language: Language::USen,
+                profile: "Default".into(),
+                dump: Arc::new(ResourceReader::from_memory(
+                    MemoryROMSource::new(Endian::Big)
+                        .with_file("System/Version.txt", version)
+                        .with_file("Pack/Bootup_USen.pack", b"usen".to_vec()),
+                )),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
+                deploy_config: None,
+            }
+        };
+        assert!(config("1.5.0").check_dump(Platform::WiiU).is_ok());
+        assert!(config("1.5.0").check_dump(Platform::Switch).is_err());
+        let err = config("1.0.0").check_dump(Platform::WiiU).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<uk_reader::ROMError>(),
+            Some(uk_reader::ROMError::WrongVersion { .. })
+        ));
+        let mut config = config("1.5.0");
+        config.language = Language::JPja;
+        assert!(config.check_dump(Platform::WiiU).is_err());
+    }
+    #[test]
+    fn profile_dumps() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dump = |version: &str, language: &str| {
+            Arc::new(ResourceReader::from_memory(
+                MemoryROMSource::new(Endian::Big)
+                    .with_file("System/Version.txt", version)
+                    .with_file(format!("Pack/Bootup_{language}.pack"), b"pack".to_vec()),
+            ))
+        };
+        let mut settings = Settings {
+            wiiu_config: Some(PlatformSettings {
+                language: Language::USen,
+                profile: "Default".into(),
+                dump: dump("1.5.0", "USen"),
+                dumps: [("Japan".into(), dump("1.5.0", "JPja"))].into(),
+                profile_dumps: [
+                    ("Testing".into(), "Japan".into()),
+                    ("Removed".into(), "Gone".into()),
+                ]
+                .into(),
+                deploy_config: None,
+            }),
+            ..Default::default()
+        };
+        let path = tmp.path().join("settings.yml");
+        fs::write(&path, serde_yaml::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(Settings::read(&path).unwrap(), settings);
+
+        let languages = |settings: &Settings| {
+            settings
+                .dump()
+                .unwrap()
+                .detect_region_and_version()
+                .unwrap()
+                .languages
+        };
+        assert_eq!(languages(&settings), [Language::USen]);
+        let config = settings.wiiu_config.as_mut().unwrap();
+        config.profile = "Testing".into();
+        assert_eq!(config.dump_name("Testing"), Some("Japan"));
+        assert_eq!(languages(&settings), [Language::JPja]);
+        // A profile whose dump was removed goes back to the main one
+        let config = settings.wiiu_config.as_mut().unwrap();
+        assert_eq!(config.dump_name("Removed"), None);
+        assert!(Arc::ptr_eq(config.dump_for("Removed"), &config.dump));
+
+        // The named dump is of another region, which is allowed, but not of
+        // another version
+        config.check_dump(Platform::WiiU).unwrap();
+        config.dumps.insert("Old".into(), dump("1.0.0", "USen"));
+        assert!(config.check_dump(Platform::WiiU).is_err());
+    }
+}
@@ -0,0 +1,605 @@
+//! Previewing a hypothetical mod configuration. A simulation keeps a snapshot
+//! of the mod list as it stood when the simulation started, and the list can
+//! then be toggled, reordered and given different options as a working copy
+//! without anything being persisted. From the snapshot and the working copy
+//! we can work out which mods would own which files, what a single file would
+//! merge to, and which files an apply would need to rebuild for the working
+//! copy to take effect.
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use parking_lot::Mutex;
+use smartstring::alias::String;
+use uk_content::platform_prefixes;
+use uk_mod::{
+    policy::MergePolicies,
+    unpack::{ModReader, ModUnpacker},
+    Manifest, ModOption,
+};
+
+use crate::{
+    invalidate::{Change, Subscriber},
+    mods::{delta_with, Mod, Priority},
+    settings::Settings,
+};
+
+/// The mods which change each file, by hash, in merge order. The last mod
+/// listed for a file is the one whose version wins.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Ownership {
+    pub content: BTreeMap<String, Vec<usize>>,
+    pub aoc:     BTreeMap<String, Vec<usize>>,
+}
+
+impl Ownership {
+    pub fn compute(mods: &[Mod], priority: Priority) -> Result<Self> {
+        Self::compute_with(mods, priority, |m, options| {
+            m.manifest_with_options(options)
+        })
+    }
+
+    fn compute_with(
+        mods: &[Mod],
+        priority: Priority,
+        manifest: impl Fn(&Mod, Vec<ModOption>) -> Result<Arc<Manifest>>,
+    ) -> Result<Self> {
+        let mut ownership = Self::default();
+        let enabled = priority.merge_order(mods.iter().filter(|m| m.enabled).collect());
+        for mod_ in enabled {
+            let manifest = manifest(mod_, mod_.active_options())
+                .with_context(|| format!("Failed to read manifest for {}", mod_.meta.name))?;
+            for file in manifest.content_files.iter() {
+                ownership
+                    .content
+                    .entry(file.clone())
+                    .or_default()
+                    .push(mod_.hash());
+            }
+            for file in manifest.aoc_files.iter() {
+                ownership
+                    .aoc
+                    .entry(file.clone())
+                    .or_default()
+                    .push(mod_.hash());
+            }
+        }
+        Ok(ownership)
+    }
+
+    /// The mods which change a file, in merge order.
+    pub fn owners(&self, file: &str, aoc: bool) -> &[usize] {
+        let files = if aoc { &self.aoc } else { &self.content };
+        files.get(file).map(|o| o.as_slice()).unwrap_or_default()
+    }
+
+    /// The mod whose version of a file wins, if any mod changes it.
+    pub fn winner(&self, file: &str, aoc: bool) -> Option<usize> {
+        self.owners(file, aoc).last().copied()
+    }
+
+    /// Files changed by more than one mod, as `(file, aoc, owners)`.
+    pub fn conflicts(&self) -> impl Iterator<Item = (&str, bool, &[usize])> {
+        self.content
+            .iter()
+            .map(|(f, o)| (f.as_str(), false, o.as_slice()))
+            .chain(
+                self.aoc
+                    .iter()
+                    .map(|(f, o)| (f.as_str(), true, o.as_slice())),
+            )
+            .filter(|(_, _, owners)| owners.len() > 1)
+    }
+}
+
+/// The ownership of the current profile, kept between uses. A change drops
+/// only the entries for the files of the mod it involves, which are worked
+/// out again from the manifests still held for the other mods, and a new
+/// order just puts the owners of each file in it without reading anything.
+#[derive(Debug, Default)]
+pub struct OwnershipCache(Mutex<CachedOwnership>);
+
+#[derive(Debug, Default)]
+struct CachedOwnership {
+    /// The enabled mods in merge order, as of the last use.
+    order:     Vec<usize>,
+    /// The manifest of each enabled mod with its active options.
+    manifests: HashMap<usize, Arc<Manifest>>,
+    /// The owners of every file which is not stale, or nothing if none have
+    /// been worked out yet.
+    ownership: Option<Ownership>,
+    /// Files whose owners need working out again.
+    stale:     Manifest,
+}
+
+impl CachedOwnership {
+    fn forget(&mut self, hash: usize) {
+        let Some(manifest) = self.manifests.remove(&hash) else {
+            return;
+        };
+        if let Some(ownership) = self.ownership.as_mut() {
+            for file in manifest.content_files.iter() {
+                ownership.content.remove(file);
+            }
+            for file in manifest.aoc_files.iter() {
+                ownership.aoc.remove(file);
+            }
+        }
+        self.stale.extend(&manifest);
+    }
+
+    /// The enabled mods which change a file, in merge order.
+    fn owners(&self, file: &str, aoc: bool) -> Vec<usize> {
+        self.order
+            .iter()
+            .copied()
+            .filter(|hash| {
+                let manifest = &self.manifests[hash];
+                let files = if aoc {
+                    &manifest.aoc_files
+                } else {
+                    &manifest.content_files
+                };
+                files.contains(file)
+            })
+            .collect()
+    }
+
+    fn rebuild(&self) -> Ownership {
+        let mut ownership = Ownership::default();
+        for hash in self.order.iter() {
+            let manifest = &self.manifests[hash];
+            for file in manifest.content_files.iter() {
+                ownership
+                    .content
+                    .entry(file.clone())
+                    .or_default()
+                    .push(*hash);
+            }
+            for file in manifest.aoc_files.iter() {
+                ownership.aoc.entry(file.clone()).or_default().push(*hash);
+            }
+        }
+        ownership
+    }
+}
+
+fn set_owners(files: &mut BTreeMap<String, Vec<usize>>, file: &String, owners: Vec<usize>) {
+    if owners.is_empty() {
+        files.remove(file);
+    } else {
+        files.insert(file.clone(), owners);
+    }
+}
+
+impl OwnershipCache {
+    pub fn get(&self, mods: &[Mod], priority: Priority) -> Result<Ownership> {
+        self.get_with(mods, priority, |m, options| {
+            m.manifest_with_options(options)
+        })
+    }
+
+    fn get_with(
+        &self,
+        mods: &[Mod],
+        priority: Priority,
+        manifest: impl Fn(&Mod, Vec<ModOption>) -> Result<Arc<Manifest>>,
+    ) -> Result<Ownership> {
+        let mut cache = self.0.lock();
+        let enabled = priority.merge_order(mods.iter().filter(|m| m.enabled).collect());
+        let order: Vec<usize> = enabled.iter().map(|m| m.hash()).collect();
+        // Mods which have gone without a change being published still drop
+        // out, as do their files
+        let gone: Vec<usize> = cache
+            .manifests
+            .keys()
+            .filter(|hash| !order.contains(hash))
+            .copied()
+            .collect();
+        for hash in gone {
+            cache.forget(hash);
+        }
+        for mod_ in enabled {
+            if !cache.manifests.contains_key(&mod_.hash()) {
+                let manifest = manifest(mod_, mod_.active_options())
+                    .with_context(|| format!("Failed to read manifest for {}", mod_.meta.name))?;
+                cache.stale.extend(&manifest);
+                cache.manifests.insert(mod_.hash(), manifest);
+            }
+        }
+        let cache = &mut *cache;
+        if cache.order != order {
+            cache.order = order;
+            if let Some(ownership) = cache.ownership.as_mut() {
+                let place: HashMap<usize, usize> = cache
+                    .order
+                    .iter()
+                    .enumerate()
+                    .map(|(i, h)| (*h, i))
+                    .collect();
+                for owners in ownership
+                    .content
+                    .values_mut()
+                    .chain(ownership.aoc.values_mut())
+                {
+                    owners.sort_by_key(|hash| place.get(hash).copied().unwrap_or(usize::MAX));
+                }
+            }
+        }
+        let stale = std::mem::take(&mut cache.stale);
+        let ownership = match cache.ownership.take() {
+            Some(mut ownership) => {
+                for file in stale.content_files.iter() {
+                    set_owners(&mut ownership.content, file, cache.owners(file, false));
+                }
+                for file in stale.aoc_files.iter() {
+                    set_owners(&mut ownership.aoc, file, cache.owners(file, true));
+                }
+                ownership
+            }
+            None => cache.rebuild(),
+        };
+        cache.ownership = Some(ownership.clone());
+        Ok(ownership)
+    }
+}
+
+impl Subscriber for OwnershipCache {
+    fn invalidate(&self, change: Change) {
+        let mut cache = self.0.lock();
+        match change {
+            Change::ModChanged(hash) | Change::OptionsChanged(hash) => cache.forget(hash),
+            // A new order is picked up on the next use, and the dump has no
+            // say in who owns what
+            Change::OrderChanged | Change::DumpChanged => (),
+            Change::ProfileSwitched => *cache = Default::default(),
+        }
+    }
+}
+
+/// What a single file would merge to under a simulated configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preview {
+    /// The merged file, uncompressed.
+    pub data:    Vec<u8>,
+    /// Whether it differs from what is currently in the merged folder, or
+    /// `None` if the file is not there at all.
+    pub changed: Option<bool>,
+}
+
+/// A snapshot of the mod list to compare a simulated one with.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    base:     Vec<Mod>,
+    priority: Priority,
+}
+
+impl Simulation {
+    pub fn new(base: Vec<Mod>, priority: Priority) -> Self {
+        Self { base, priority }
+    }
+
+    /// The mods as they were when the simulation started.
+    pub fn base(&self) -> &[Mod] {
+        &self.base
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn ownership(&self, mods: &[Mod]) -> Result<Ownership> {
+        Ownership::compute(mods, self.priority)
+    }
+
+    /// The files which would need to be rebuilt for the simulated mods to
+    /// take effect, i.e. the pending changes the simulation amounts to.
+    pub fn changes(&self, mods: &[Mod]) -> Result<Manifest> {
+        self.changes_with(mods, |m, options| m.manifest_with_options(options))
+    }
+
+    fn changes_with(
+        &self,
+        mods: &[Mod],
+        manifest: impl Fn(&Mod, Vec<ModOption>) -> Result<Arc<Manifest>>,
+    ) -> Result<Manifest> {
+        // Only the order of mods enabled both before and after matters, as
+        // anything newly enabled or disabled is rebuilt regardless
+        let kept = |list: &[Mod], other: &[Mod]| -> Vec<usize> {
+            list.iter()
+                .filter(|m| m.enabled)
+                .filter(|m| other.iter().any(|o| o.enabled && o.hash() == m.hash()))
+                .map(|m| m.hash())
+                .collect()
+        };
+        let old_order = kept(&self.base, mods);
+        let new_order = kept(mods, &self.base);
+        let mut changes = Manifest::default();
+        for new in mods {
+            let Some(old) = self.base.iter().find(|m| m.hash() == new.hash()) else {
+                if new.enabled {
+                    changes.extend(manifest(new, new.active_options())?.as_ref());
+                }
+                continue;
+            };
+            if old.enabled != new.enabled {
+                let mod_ = if new.enabled { new } else { old };
+                changes.extend(manifest(mod_, mod_.active_options())?.as_ref());
+            } else if new.enabled {
+                let moved = old_order.iter().position(|h| *h == new.hash())
+                    != new_order.iter().position(|h| *h == new.hash());
+                if moved {
+                    changes.extend(manifest(old, old.active_options())?.as_ref());
+                    changes.extend(manifest(new, new.active_options())?.as_ref());
+                } else {
+                    changes.extend(&delta_with(old, new, &manifest)?);
+                }
+            }
+        }
+        for old in self
+            .base
+            .iter()
+            .filter(|m| m.enabled && !mods.iter().any(|n| n.hash() == m.hash()))
+        {
+            changes.extend(manifest(old, old.active_options())?.as_ref());
+        }
+        Ok(changes)
+    }
+
+    /// Merges a single file from the simulated mods, and compares it with
+    /// the current output in the merged folder. Nothing is written.
+    pub fn preview(
+        &self,
+        settings: &Settings,
+        mods: &[Mod],
+        file: &str,
+        aoc: bool,
+    ) -> Result<Preview> {
+        let dump = settings
+            .dump()
+            .context("No dump available for current platform")?;
+        let endian = settings.current_mode.into();
+        let cache_dir = settings.resource_cache_dir();
+        let ownership = self.ownership(mods)?;
+        let owners = ownership.owners(file, aoc);
+        let readers = priority_ordered(mods, self.priority)
+            .filter(|m| owners.contains(&m.hash()))
+            .map(|m| {
+                ModReader::open(&m.path, m.active_options())
+                    .map(|m| m.with_platform(endian).with_cache(&cache_dir))
+                    .with_context(|| format!("Failed to open mod: {}", m.meta.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let out_dir = settings.merged_dir();
+        let data = ModUnpacker::new(
+            dump,
+            endian,
+            settings
+                .platform_config()
+                .context("No platform configured")?
+                .language,
+            readers,
+            out_dir.clone(),
+        )
+        .with_policies(MergePolicies::load(&settings.merge_policy_path())?)
+        .build_single(file, aoc)
+        .with_context(|| format!("Failed to merge {file}"))?;
+        let (content, aoc_dir) = platform_prefixes(endian);
+        let current = out_dir.join(if aoc { aoc_dir } else { content }).join(file);
+        let changed = read_merged(&current).map(|current| current != data);
+        Ok(Preview { data, changed })
+    }
+}
+
+fn priority_ordered(mods: &[Mod], priority: Priority) -> impl Iterator<Item = &Mod> {
+    priority
+        .merge_order(mods.iter().filter(|m| m.enabled).collect())
+        .into_iter()
+}
+
+fn read_merged(path: &Path) -> Option<Vec<u8>> {
+    fs::read(path)
+        .ok()
+        .map(|data| roead::yaz0::decompress_if(&data).into_owned())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{cell::Cell, path::PathBuf};
+
+    use uk_mod::Meta;
+
+    use super::*;
+    use crate::invalidate::Bus;
+
+    fn test_mod(name: &str, hash: usize) -> Mod {
+        Mod {
+            meta: Meta {
+                api: "1.0.0".into(),
+                format: uk_mod::FORMAT_VERSION,
+                name: name.into(),
+                version: "1.0.0".into(),
+                author: String::new(),
+                category: String::new(),
+                description: String::new(),
+                platform: uk_mod::ModPlatform::Universal,
+                url: None,
+                options: vec![],
+                masters: Default::default(),
+            },
+            enabled_options: vec![],
+            inactive_options: vec![],
+            enabled: true,
+            path: format!("{name}.zip").into(),
+            local_patch: false,
+            patch_id: None,
+            archived: false,
+            options_review: None,
+            hash,
+        }
+    }
+
+    /// Every mod changes a shared file and one of its own, plus one actor
+    /// per active option.
+    fn fake_manifest(mod_: &Mod, options: Vec<ModOption>) -> Result<Arc<Manifest>> {
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert("Pack/Shared.pack".into());
+        manifest
+            .content_files
+            .insert(format!("Actor/Pack/{}.sbactorpack", mod_.meta.name).into());
+        for option in options {
+            manifest
+                .aoc_files
+                .insert(format!("Actor/Pack/{}.sbactorpack", option.name).into());
+        }
+        Ok(Arc::new(manifest))
+    }
+
+    fn mods() -> Vec<Mod> {
+        vec![test_mod("A", 1), test_mod("B", 2), test_mod("C", 3)]
+    }
+
+    fn own(name: &str) -> std::string::String {
+        format!("Actor/Pack/{name}.sbactorpack")
+    }
+
+    #[test]
+    fn ownership() {
+        let mut mods = mods();
+        mods[1].enabled = false;
+        let ownership = Ownership::compute_with(&mods, Priority::LowerWins, fake_manifest).unwrap();
+        assert_eq!(ownership.owners("Pack/Shared.pack", false), [1, 3]);
+        assert_eq!(ownership.winner("Pack/Shared.pack", false), Some(3));
+        assert_eq!(ownership.winner(&own("A"), false), Some(1));
+        assert_eq!(ownership.winner(&own("B"), false), None);
+        assert_eq!(ownership.conflicts().count(), 1);
+        let flipped = Ownership::compute_with(&mods, Priority::HigherWins, fake_manifest).unwrap();
+        assert_eq!(flipped.winner("Pack/Shared.pack", false), Some(1));
+    }
+
+    #[test]
+    fn ownership_cache() {
+        let reads = Cell::new(0);
+        let counted = |mod_: &Mod, options: Vec<ModOption>| {
+            reads.set(reads.get() + 1);
+            fake_manifest(mod_, options)
+        };
+        let fresh = |mods: &[Mod]| {
+            Ownership::compute_with(mods, Priority::LowerWins, fake_manifest).unwrap()
+        };
+        let bus = Bus::default();
+        let cache = Arc::new(OwnershipCache::default());
+        bus.subscribe(&cache);
+        let mut mods = mods();
+        let get = |mods: &[Mod]| cache.get_with(mods, Priority::LowerWins, counted).unwrap();
+        assert_eq!(get(&mods), fresh(&mods));
+        assert_eq!(reads.get(), 3);
+
+        // Only the entries involving the changed mod are dropped
+        mods[0].enabled_options.push(ModOption {
+            name: "Hard".into(),
+            description: String::new(),
+            path: PathBuf::from("options/Hard"),
+            requires: vec![],
+            image: None,
+        });
+        bus.publish(Change::OptionsChanged(1));
+        {
+            let state = cache.0.lock();
+            assert!(!state.manifests.contains_key(&1));
+            assert!(state.manifests.contains_key(&2));
+            let ownership = state.ownership.as_ref().unwrap();
+            assert!(!ownership.content.contains_key(own("A").as_str()));
+            assert!(!ownership.content.contains_key("Pack/Shared.pack"));
+            assert_eq!(ownership.owners(&own("B"), false), [2]);
+            assert_eq!(ownership.owners(&own("C"), false), [3]);
+        }
+        let ownership = get(&mods);
+        assert_eq!(ownership, fresh(&mods));
+        assert_eq!(ownership.owners(&own("Hard"), true), [1]);
+        assert_eq!(reads.get(), 4);
+
+        // A new order reads nothing again
+        mods.swap(0, 2);
+        bus.publish(Change::OrderChanged);
+        assert_eq!(get(&mods), fresh(&mods));
+        assert_eq!(get(&mods).winner("Pack/Shared.pack", false), Some(1));
+        assert_eq!(reads.get(), 4);
+
+        // Nor does a mod turned off without a change published
+        mods[1].enabled = false;
+        assert_eq!(get(&mods), fresh(&mods));
+        assert_eq!(reads.get(), 4);
+
+        bus.publish(Change::ModChanged(3));
+        bus.publish(Change::DumpChanged);
+        assert_eq!(get(&mods), fresh(&mods));
+        assert_eq!(reads.get(), 5);
+        bus.publish(Change::ProfileSwitched);
+        assert_eq!(get(&mods), fresh(&mods));
+        assert_eq!(reads.get(), 7);
+    }
+
+    #[test]
+    fn unchanged() {
+        let sim = Simulation::new(mods(), Priority::LowerWins);
+        let changes = sim.changes_with(&mods(), fake_manifest).unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn toggles() {
+        let sim = Simulation::new(mods(), Priority::LowerWins);
+        let mut mods = mods();
+        mods[1].enabled = false;
+        let changes = sim.changes_with(&mods, fake_manifest).unwrap();
+        assert!(changes.content_files.contains(own("B").as_str()));
+        assert!(changes.content_files.contains("Pack/Shared.pack"));
+        assert!(!changes.content_files.contains(own("A").as_str()));
+        assert!(!changes.content_files.contains(own("C").as_str()));
+    }
+
+    #[test]
+    fn reorders() {
+        let sim = Simulation::new(mods(), Priority::LowerWins);
+        let mut mods = mods();
+        mods.swap(1, 2);
+        let changes = sim.changes_with(&mods, fake_manifest).unwrap();
+        assert!(changes.content_files.contains(own("B").as_str()));
+        assert!(changes.content_files.contains(own("C").as_str()));
+        assert!(!changes.content_files.contains(own("A").as_str()));
+        // Moving a disabled mod changes nothing
+        let mut base = self::mods();
+        base[0].enabled = false;
+        let sim = Simulation::new(base.clone(), Priority::LowerWins);
+        base.swap(0, 2);
+        assert!(sim.changes_with(&base, fake_manifest).unwrap().is_empty());
+    }
+
+    #[test]
+    fn options() {
+        let option = ModOption {
+            name: "Hard".into(),
+            description: String::new(),
+            path: PathBuf::from("options/Hard"),
+            requires: vec![],
+            image: None,
+        };
+        let sim = Simulation::new(mods(), Priority::LowerWins);
+        let mut mods = mods();
+        mods[0].enabled_options.push(option.clone());
+        let changes = sim.changes_with(&mods, fake_manifest).unwrap();
+        assert!(changes.aoc_files.contains(own("Hard").as_str()));
+        // Options of a disabled mod are not merged, so changing them is free
+        let mut base = self::mods();
+        base[0].enabled = false;
+        let sim = Simulation::new(base.clone(), Priority::LowerWins);
+        base[0].enabled_options.push(option);
+        assert!(sim.changes_with(&base, fake_manifest).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,451 @@
+//! Readable summaries of what a mod, or a whole profile, changes in-game.
+//! Mods store most resources as diffs against the stock files, so each diff
+//! is compared with the stock file and the resource types players care most
+//! about are described in sentences, like "Guardian_A: HP 1500 → 3000".
+//! Every other change is only counted by resource type.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::Path,
+};
+
+use anyhow_ext::{Context, Result};
+use roead::{aamp::get_default_name_table, byml::Byml};
+use uk_content::{
+    canon::canonicalize_aoc,
+    canonicalize,
+    labels::Labels,
+    prelude::Mergeable,
+    resource::{MergeableResource, ResourceData},
+};
+use uk_mod::unpack::ModReader;
+use uk_reader::ResourceReader;
+
+use crate::{core::Manager, mods::Mod};
+
+mod actorinfo;
+mod map;
+mod params;
+mod text;
+
+/// Most individual changes listed in one line before the rest are counted.
+const MAX_LISTED: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    Actors,
+    Maps,
+    Text,
+    Other,
+}
+
+impl Category {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Category::Actors => "Actors",
+            Category::Maps => "Maps",
+            Category::Text => "Text",
+            Category::Other => "Other Changes",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Summary {
+    lines:    BTreeMap<Category, Vec<String>>,
+    /// Changed files which are only counted, by resource type.
+    counts:   BTreeMap<String, usize>,
+    /// Mods the profile keeps archived, whose changes are left out.
+    archived: Vec<String>,
+}
+
+impl Summary {
+    pub fn push(&mut self, category: Category, line: impl Into<String>) {
+        self.lines.entry(category).or_default().push(line.into());
+    }
+
+    pub fn count(&mut self, kind: impl Into<String>) {
+        *self.counts.entry(kind.into()).or_default() += 1;
+    }
+
+    pub fn lines(&self, category: Category) -> &[String] {
+        self.lines
+            .get(&category)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn counts(&self) -> &BTreeMap<String, usize> {
+        &self.counts
+    }
+
+    /// Notes a mod which is in the profile but archived, so a reader knows
+    /// it is missing from the summary.
+    pub fn note_archived(&mut self, name: impl Into<String>) {
+        self.archived.push(name.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.counts.is_empty()
+    }
+
+    pub fn to_markdown(&self, title: &str) -> String {
+        let mut out = format!("# {title}\n");
+        if self.is_empty() {
+            out.push_str("\nNo changes found.\n");
+        }
+        for (category, lines) in &self.lines {
+            let _ = writeln!(out, "\n## {}\n", category.title());
+            for line in lines {
+                let _ = writeln!(out, "- {line}");
+            }
+        }
+        if !self.counts.is_empty() {
+            if !self.lines.contains_key(&Category::Other) {
+                let _ = writeln!(out, "\n## {}\n", Category::Other.title());
+            }
+            for (kind, count) in &self.counts {
+                let _ = writeln!(out, "- {} {kind} {}", count, plural(*count, "file"));
+            }
+        }
+        if !self.archived.is_empty() {
+            out.push_str(
+                "\n## Archived Mods\n\nThese mods are in the profile but archived, so their \
+                 changes are not included.\n\n",
+            );
+            for name in &self.archived {
+                let _ = writeln!(out, "- {name}");
+            }
+        }
+        out
+    }
+}
+
+pub(crate) fn plural(count: usize, noun: &str) -> String {
+    match noun.strip_suffix('y') {
+        _ if count == 1 => noun.into(),
+        Some(stem) => format!("{stem}ies"),
+        None => format!("{noun}s"),
+    }
+}
+
+/// Joins a list of changes, counting the ones past the first few.
+pub(crate) fn join_listed(items: &[String]) -> String {
+    if items.len() <= MAX_LISTED {
+        items.join(", ")
+    } else {
+        format!(
+            "{} and {} more",
+            items[..MAX_LISTED].join(", "),
+            items.len() - MAX_LISTED
+        )
+    }
+}
+
+/// The actor a resource belongs to, with its in-game name if known. Files in
+/// an actor pack belong to the pack's actor, and other files are named for
+/// their file name.
+pub(crate) fn actor_name(path: &str, labels: Option<&Labels>) -> String {
+    let outer = path.split("//").next().unwrap_or(path);
+    let file = if outer.starts_with("Actor/Pack/") {
+        outer
+    } else {
+        path.rsplit("//").next().unwrap_or(path)
+    };
+    let stem = file
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.split('.').next())
+        .unwrap_or(file);
+    label(stem, labels)
+}
+
+/// An actor name with its in-game name, if known.
+pub(crate) fn label(actor: &str, labels: Option<&Labels>) -> String {
+    match labels.and_then(|labels| labels.actors.get(actor)) {
+        Some(label) => format!("{label} ({actor})"),
+        None => actor.into(),
+    }
+}
+
+/// Formats a simple BYML value, or returns `None` for containers and binary
+/// data.
+pub(crate) fn format_byml(value: &Byml) -> Option<String> {
+    Some(match value {
+        Byml::Bool(v) => v.to_string(),
+        Byml::I32(v) => v.to_string(),
+        Byml::U32(v) => v.to_string(),
+        Byml::I64(v) => v.to_string(),
+        Byml::U64(v) => v.to_string(),
+        Byml::Float(v) => v.to_string(),
+        Byml::Double(v) => v.to_string(),
+        Byml::String(v) => v.to_string(),
+        _ => return None,
+    })
+}
+
+/// Looks up a name from its hash, first in the labels and then in the
+/// default AAMP name table.
+pub(crate) fn hash_name(hash: u32, labels: Option<&Labels>) -> String {
+    labels
+        .and_then(|labels| labels.hashes.get(&hash).cloned())
+        .or_else(|| {
+            get_default_name_table()
+                .get_name(hash, 0, 0)
+                .map(|name| name.to_string())
+        })
+        .unwrap_or_else(|| format!("{hash:#010x}"))
+}
+
+fn resource_kind(resource: &ResourceData) -> String {
+    match resource {
+        ResourceData::Binary(_) => "binary".into(),
+        ResourceData::Sarc(_) => "SARC".into(),
+        ResourceData::Mergeable(resource) => resource.to_string(),
+    }
+}
+
+/// Folds a later version of a resource over an earlier one. Diffs merge, and
+/// anything else is replaced.
+pub(crate) fn merge_resource(base: ResourceData, diff: ResourceData) -> ResourceData {
+    match (&base, &diff) {
+        (ResourceData::Mergeable(base), ResourceData::Mergeable(diff)) => {
+            ResourceData::Mergeable(base.merge(diff))
+        }
+        (ResourceData::Sarc(base), ResourceData::Sarc(diff)) => {
+            ResourceData::Sarc(base.merge(diff))
+        }
+        _ => diff,
+    }
+}
+
+/// A resource changed by one or more mods.
+pub(crate) struct Change {
+    /// The path to show, with `//` between nested files.
+    pub path:  String,
+    /// The path to look the stock file up by.
+    pub name:  String,
+    pub canon: String,
+    pub data:  ResourceData,
+}
+
+impl Change {
+    /// The stock version of the resource, if the dump has it.
+    pub fn stock(&self, dump: &ResourceReader) -> Option<std::sync::Arc<ResourceData>> {
+        dump.get_data(self.name.as_str())
+            .or_else(|_| dump.get_data(self.canon.as_str()))
+            .or_else(|_| dump.get_resource(self.canon.as_str()))
+            .ok()
+    }
+}
+
+/// Collects a file a mod changes, and the files nested in it, by canonical
+/// path. Changes already collected are merged with, and files in `seen` are
+/// skipped.
+pub(crate) fn walk(
+    mod_: &ModReader,
+    path: String,
+    name: &str,
+    aoc: bool,
+    changes: &mut BTreeMap<String, Change>,
+    seen: &mut BTreeSet<String>,
+) -> Result<()> {
+    let canon = if aoc {
+        canonicalize_aoc(name)
+    } else {
+        canonicalize(name)
+    };
+    if !seen.insert(canon.to_string()) {
+        return Ok(());
+    }
+    // Mods only store the nested files they change, so the rest of a SARC's
+    // files are missing by design
+    let versions = match mod_.get_resources(Path::new(name)) {
+        Ok(versions) => versions,
+        Err(_) if path.contains("//") => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let Some(data) = versions.into_iter().reduce(merge_resource) else {
+        return Ok(());
+    };
+    if let ResourceData::Sarc(sarc) = &data {
+        for file in sarc.files.iter() {
+            walk(mod_, format!("{path}//{file}"), file, aoc, changes, seen)?;
+        }
+    }
+    match changes.remove(canon.as_str()) {
+        Some(prev) => {
+            changes.insert(canon.to_string(), Change {
+                data: merge_resource(prev.data, data),
+                ..prev
+            });
+        }
+        None => {
+            changes.insert(canon.to_string(), Change {
+                path,
+                name: name.into(),
+                canon: canon.to_string(),
+                data,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Collects every resource the mods change, including those nested in
+/// SARCs, with later mods merged over earlier ones.
+fn collect(mods: &[ModReader]) -> Result<BTreeMap<String, Change>> {
+    let mut changes = BTreeMap::new();
+    for mod_ in mods {
+        let mut seen = BTreeSet::new();
+        for file in &mod_.manifest.content_files {
+            walk(mod_, file.to_string(), file, false, &mut changes, &mut seen)?;
+        }
+        for file in &mod_.manifest.aoc_files {
+            walk(mod_, file.to_string(), file, true, &mut changes, &mut seen)?;
+        }
+    }
+    Ok(changes)
+}
+
+fn summarize_change(
+    change: &Change,
+    base: Option<&ResourceData>,
+    labels: Option<&Labels>,
+    summary: &mut Summary,
+) {
+    let base = base.and_then(ResourceData::as_mergeable);
+    let path = change.path.as_str();
+    match &change.data {
+        ResourceData::Sarc(_) => (),
+        ResourceData::Mergeable(MergeableResource::GeneralParamList(diff)) => {
+            let base = match base {
+                Some(MergeableResource::GeneralParamList(base)) => Some(base.as_ref()),
+                _ => None,
+            };
+            params::summarize(path, base, diff, labels, summary);
+        }
+        ResourceData::Mergeable(MergeableResource::ActorInfo(diff)) => {
+            let base = match base {
+                Some(MergeableResource::ActorInfo(base)) => Some(base.as_ref()),
+                _ => None,
+            };
+            actorinfo::summarize(base, diff, labels, summary);
+        }
+        ResourceData::Mergeable(MergeableResource::MapUnit(diff)) => {
+            let base = match base {
+                Some(MergeableResource::MapUnit(base)) => Some(base.as_ref()),
+                _ => None,
+            };
+            map::summarize(path, base, diff, summary);
+        }
+        ResourceData::Mergeable(MergeableResource::MessagePack(diff)) => {
+            let base = match base {
+                Some(MergeableResource::MessagePack(base)) => Some(base.as_ref()),
+                _ => None,
+            };
+            text::summarize(path, base, diff, summary);
+        }
+        data => summary.count(resource_kind(data)),
+    }
+}
+
+/// Summarizes the changes of a set of mods, in priority order, as they would
+/// merge together. Without a game dump, values cannot be compared with the
+/// stock ones, so changes are described less precisely.
+pub fn summarize(
+    mods: &[ModReader],
+    dump: Option<&ResourceReader>,
+    labels: Option<&Labels>,
+) -> Result<Summary> {
+    let mut summary = Summary::default();
+    for change in collect(mods)?.values() {
+        let base = dump.and_then(|dump| change.stock(dump));
+        summarize_change(change, base.as_deref(), labels, &mut summary);
+    }
+    Ok(summary)
+}
+
+/// Summarizes mods as they would merge in the current profile, using its
+/// dump and, when they can be loaded, its friendly names.
+pub fn summarize_mods(core: &Manager, mods: Vec<Mod>) -> Result<Summary> {
+    let labels = crate::labels::load(core)
+        .inspect_err(|e| log::warn!("Summarizing without friendly names: {e:?}"))
+        .ok();
+    let priority = core.mod_manager().priority();
+    let settings = core.settings();
+    let endian = settings.current_mode.into();
+    let readers = mods
+        .iter()
+        .map(|m| {
+            ModReader::open(&m.path, m.active_options())
+                .map(|m| m.with_platform(endian))
+                .with_context(|| format!("Failed to open mod: {}", m.meta.name))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|mods| priority.merge_order(mods))?;
+    summarize(&readers, settings.dump().as_deref(), labels.as_deref())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown() {
+        let mut summary = Summary::default();
+        assert_eq!(
+            summary.to_markdown("Empty"),
+            "# Empty\n\nNo changes found.\n"
+        );
+        summary.push(
+            Category::Text,
+            "Edited 2 text entries in 1 message file (USen)",
+        );
+        summary.push(Category::Actors, "Guardian_A: HP 1500 → 3000");
+        summary.count("binary");
+        summary.count("binary");
+        summary.count("AIProgram");
+        assert_eq!(
+            summary.to_markdown("Test Mod"),
+            "# Test Mod\n\n## Actors\n\n- Guardian_A: HP 1500 → 3000\n\n## Text\n\n- Edited 2 \
+             text entries in 1 message file (USen)\n\n## Other Changes\n\n- 1 AIProgram file\n- 2 \
+             binary files\n"
+        );
+        let mut summary = Summary::default();
+        summary.note_archived("Old Mod");
+        assert_eq!(
+            summary.to_markdown("Profile"),
+            "# Profile\n\nNo changes found.\n\n## Archived Mods\n\nThese mods are in the profile \
+             but archived, so their changes are not included.\n\n- Old Mod\n"
+        );
+    }
+
+    #[test]
+    fn names() {
+        let labels = Labels {
+            actors: [("Enemy_Guardian_A".into(), "Guardian Stalker".into())].into(),
+            hashes: Default::default(),
+        };
+        assert_eq!(
+            actor_name(
+                "Actor/Pack/Enemy_Guardian_A.sbactorpack//Actor/GeneralParamList/Guardian_A.\
+                 bgparamlist",
+                Some(&labels)
+            ),
+            "Guardian Stalker (Enemy_Guardian_A)"
+        );
+        assert_eq!(
+            actor_name(
+                "Actor/GeneralParamList/Guardian_A.bgparamlist",
+                Some(&labels)
+            ),
+            "Guardian_A"
+        );
+        assert_eq!(join_listed(&["a".into(), "b".into()]), "a, b");
+        let items: Vec<String> = (0..8).map(|i| i.to_string()).collect();
+        assert_eq!(join_listed(&items), "0, 1, 2, 3, 4, 5 and 2 more");
+        assert_eq!(hash_name(roead::aamp::hash_name("Life"), None), "Life");
+    }
+}
@@ -0,0 +1,170 @@
+use roead::byml::Byml;
+use uk_content::{actor::info::ActorInfo, labels::Labels};
+
+use super::{format_byml, hash_name, join_listed, label, plural, Category, Summary};
+
+fn entry_name(entry: &Byml) -> Option<&str> {
+    entry
+        .as_map()
+        .ok()?
+        .get("name")?
+        .as_string()
+        .ok()
+        .map(|name| name.as_str())
+}
+
+/// Lists the keys an actor's entry changes, in key order.
+fn entry_changes(base: Option<&Byml>, diff: &Byml) -> Vec<String> {
+    let base = base.and_then(|base| base.as_map().ok());
+    let Ok(diff) = diff.as_map() else {
+        return vec![];
+    };
+    let mut keys: Vec<_> = diff.keys().collect();
+    keys.sort();
+    keys.into_iter()
+        .filter_map(|key| {
+            let new = &diff[key];
+            let old = base.and_then(|base| base.get(key));
+            if old == Some(new) {
+                return None;
+            }
+            Some(match (old.and_then(format_byml), new) {
+                (_, Byml::Null) => format!("{key} removed"),
+                (Some(old), new) => {
+                    match format_byml(new) {
+                        Some(new) => format!("{key} {old} → {new}"),
+                        None => format!("{key} changed"),
+                    }
+                }
+                (None, new) => {
+                    match format_byml(new) {
+                        Some(new) => format!("{key} set to {new}"),
+                        None => format!("{key} changed"),
+                    }
+                }
+            })
+        })
+        .collect()
+}
+
+pub(super) fn summarize(
+    base: Option<&ActorInfo>,
+    diff: &ActorInfo,
+    labels: Option<&Labels>,
+    summary: &mut Summary,
+) {
+    let mut added = vec![];
+    let mut removed = vec![];
+    for (hash, (entry, delete)) in diff.0.iter_full() {
+        let base_entry = base.and_then(|base| base.0.get(hash));
+        let name = entry_name(entry)
+            .or_else(|| base_entry.and_then(entry_name))
+            .map(|name| label(name, labels))
+            .unwrap_or_else(|| hash_name(*hash, labels));
+        if *delete {
+            removed.push(name);
+        } else if base.is_some() && base_entry.is_none() {
+            added.push(name);
+        } else {
+            let changes = entry_changes(base_entry, entry);
+            if !changes.is_empty() {
+                summary.push(
+                    Category::Actors,
+                    format!("{name} actor info: {}", join_listed(&changes)),
+                );
+            }
+        }
+    }
+    if !added.is_empty() {
+        summary.push(
+            Category::Actors,
+            format!(
+                "Added actor info for {} {}: {}",
+                added.len(),
+                plural(added.len(), "actor"),
+                join_listed(&added)
+            ),
+        );
+    }
+    if !removed.is_empty() {
+        summary.push(
+            Category::Actors,
+            format!(
+                "Removed actor info for {} {}: {}",
+                removed.len(),
+                plural(removed.len(), "actor"),
+                join_listed(&removed)
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::byml::map;
+    use uk_content::prelude::Mergeable;
+
+    use super::*;
+
+    fn actor(name: &str, life: i32) -> (u32, Byml) {
+        (
+            roead::aamp::hash_name(name),
+            map!("name" => Byml::String(name.into()), "generalLife" => Byml::I32(life)),
+        )
+    }
+
+    #[test]
+    fn changes() {
+        let stock = ActorInfo(
+            [
+                actor("Enemy_Guardian_A", 1500),
+                actor("Enemy_Bokoblin_Junior", 13),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let modded = ActorInfo(
+            [
+                actor("Enemy_Guardian_A", 3000),
+                actor("Enemy_Lynel_Custom", 2000),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let labels = Labels {
+            actors: [("Enemy_Guardian_A".into(), "Guardian Stalker".into())].into(),
+            hashes: Default::default(),
+        };
+        let mut summary = Summary::default();
+        summarize(
+            Some(&stock),
+            &stock.diff(&modded),
+            Some(&labels),
+            &mut summary,
+        );
+        assert_eq!(summary.lines(Category::Actors), [
+            "Guardian Stalker (Enemy_Guardian_A) actor info: generalLife 1500 → 3000",
+            "Added actor info for 1 actor: Enemy_Lynel_Custom",
+            "Removed actor info for 1 actor: Enemy_Bokoblin_Junior",
+        ]);
+    }
+
+    #[test]
+    fn removed_key() {
+        let stock = ActorInfo([actor("Enemy_Guardian_A", 1500)].into_iter().collect());
+        let modded = ActorInfo(
+            [(
+                roead::aamp::hash_name("Enemy_Guardian_A"),
+                map!("name" => Byml::String("Enemy_Guardian_A".into())),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let mut summary = Summary::default();
+        summarize(Some(&stock), &stock.diff(&modded), None, &mut summary);
+        assert_eq!(
+            summary.lines(Category::Actors)[0],
+            "Enemy_Guardian_A actor info: generalLife removed"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use roead::byml::Byml;
+use uk_content::{map::unit::MapUnit, util::SortedDeleteMap};
+
+use super::{plural, Category, Summary};
+
+/// Friendlier names for the map folders.
+const FIELDS: &[(&str, &str)] = &[
+    ("MainField", "Hyrule Field"),
+    ("MainFieldDungeon", "Divine Beast"),
+    ("CDungeon", "Shrine"),
+    ("AocField", "Trial of the Sword"),
+];
+
+/// Names a map unit from its path, like "Hyrule Field D-3 (Dynamic)".
+fn unit_name(path: &str) -> String {
+    let file = path.rsplit("//").next().unwrap_or(path);
+    let mut parts = file.rsplit('/');
+    let stem = parts
+        .next()
+        .and_then(|name| name.split('.').next())
+        .unwrap_or(file);
+    let unit = parts.next();
+    let field = parts.next().map(|field| {
+        FIELDS
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, friendly)| *friendly)
+            .unwrap_or(field)
+    });
+    match (field, unit, stem.rsplit_once('_')) {
+        (Some(field), Some(unit), Some((_, kind))) => format!("{field} {unit} ({kind})"),
+        _ => stem.into(),
+    }
+}
+
+/// Describes how many entries were added, changed and removed.
+fn describe(
+    base: Option<&SortedDeleteMap<u32, Byml>>,
+    diff: &SortedDeleteMap<u32, Byml>,
+    noun: &str,
+    changes: &mut Vec<String>,
+) {
+    let (mut added, mut changed, mut removed) = (0, 0, 0);
+    for (id, (_, delete)) in diff.iter_full() {
+        if *delete {
+            removed += 1;
+        } else if base.map(|base| base.contains_key(id)).unwrap_or(true) {
+            changed += 1;
+        } else {
+            added += 1;
+        }
+    }
+    let verb = if base.is_some() {
+        "changed"
+    } else {
+        "added or changed"
+    };
+    for (count, verb) in [(added, "added"), (changed, verb), (removed, "removed")] {
+        if count > 0 {
+            changes.push(format!("{verb} {count} {}", plural(count, noun)));
+        }
+    }
+}
+
+pub(super) fn summarize(path: &str, base: Option<&MapUnit>, diff: &MapUnit, summary: &mut Summary) {
+    let mut changes = vec![];
+    describe(
+        base.map(|base| &base.objects),
+        &diff.objects,
+        "object",
+        &mut changes,
+    );
+    describe(
+        base.map(|base| &base.rails),
+        &diff.rails,
+        "rail",
+        &mut changes,
+    );
+    if !changes.is_empty() {
+        summary.push(
+            Category::Maps,
+            format!("{}: {}", unit_name(path), changes.join(", ")),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::byml::map;
+    use uk_content::prelude::Mergeable;
+
+    use super::*;
+
+    fn obj(id: u32, name: &str) -> (u32, Byml) {
+        (
+            id,
+            map!("HashId" => Byml::U32(id), "UnitConfigName" => Byml::String(name.into())),
+        )
+    }
+
+    fn unit(objects: impl IntoIterator<Item = (u32, Byml)>) -> MapUnit {
+        MapUnit {
+            pos_x:   None,
+            pos_z:   None,
+            size:    None,
+            objects: objects.into_iter().collect(),
+            rails:   Default::default(),
+        }
+    }
+
+    #[test]
+    fn changes() {
+        let stock = unit([
+            obj(1, "Enemy_Bokoblin_Junior"),
+            obj(2, "TBox_Field_Iron"),
+            obj(3, "Obj_Tree"),
+        ]);
+        let modded = unit([
+            obj(1, "Enemy_Bokoblin_Dark"),
+            obj(2, "TBox_Field_Iron"),
+            obj(4, "Enemy_Lynel_Dark"),
+            obj(5, "Enemy_Lynel_Dark"),
+        ]);
+        let diff = stock.diff(&modded);
+        let mut summary = Summary::default();
+        summarize(
+            "Map/MainField/D-3/D-3_Dynamic.smubin",
+            Some(&stock),
+            &diff,
+            &mut summary,
+        );
+        assert_eq!(
+            summary.lines(Category::Maps)[0],
+            "Hyrule Field D-3 (Dynamic): added 2 objects, changed 1 object, removed 1 object"
+        );
+
+        let mut summary = Summary::default();
+        summarize(
+            "Pack/Dungeon000.pack//Map/CDungeon/Dungeon000/Dungeon000_Static.smubin",
+            None,
+            &diff,
+            &mut summary,
+        );
+        assert_eq!(
+            summary.lines(Category::Maps)[0],
+            "Shrine Dungeon000 (Static): added or changed 3 objects, removed 1 object"
+        );
+    }
+}
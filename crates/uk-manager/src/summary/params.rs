@@ -0,0 +1,118 @@
+use roead::aamp::Parameter;
+use uk_content::{actor::params::general::GeneralParamList, labels::Labels};
+
+use super::{actor_name, hash_name, join_listed, Category, Summary};
+
+/// Shorter names for the parameters players know by their in-game names.
+const FRIENDLY_NAMES: &[(&str, &str, &str)] = &[
+    ("General", "Life", "HP"),
+    ("Attack", "Power", "Attack"),
+    ("WeaponCommon", "BaseAttack", "Attack"),
+    ("WeaponCommon", "GuardPower", "Guard"),
+    ("Item", "BuyingPrice", "Price"),
+    ("Item", "SellingPrice", "Sell price"),
+];
+
+fn format_param(param: &Parameter) -> Option<String> {
+    Some(match param {
+        Parameter::Bool(v) => v.to_string(),
+        Parameter::F32(v) => v.to_string(),
+        Parameter::I32(v) => v.to_string(),
+        Parameter::U32(v) => v.to_string(),
+        _ => param.as_str().ok()?.to_string(),
+    })
+}
+
+pub(super) fn summarize(
+    path: &str,
+    base: Option<&GeneralParamList>,
+    diff: &GeneralParamList,
+    labels: Option<&Labels>,
+    summary: &mut Summary,
+) {
+    let base_objects = base.map(|base| &base.0.param_root.objects.0);
+    let mut changes = vec![];
+    for (obj_key, obj) in &diff.0.param_root.objects.0 {
+        let obj_name = hash_name(obj_key.hash(), labels);
+        let base_obj = base_objects.and_then(|objects| objects.get(obj_key));
+        for (key, param) in &obj.0 {
+            let param_name = hash_name(key.hash(), labels);
+            let old = base_obj.and_then(|obj| obj.0.get(key));
+            if old == Some(param) {
+                continue;
+            }
+            let name = FRIENDLY_NAMES
+                .iter()
+                .find(|(obj, param, _)| *obj == obj_name && *param == param_name)
+                .map(|(_, _, name)| name.to_string())
+                .unwrap_or_else(|| format!("{obj_name}.{param_name}"));
+            changes.push(match (old.and_then(format_param), format_param(param)) {
+                (Some(old), Some(new)) => format!("{name} {old} → {new}"),
+                (None, Some(new)) => format!("{name} set to {new}"),
+                _ => format!("{name} changed"),
+            });
+        }
+    }
+    if !changes.is_empty() {
+        summary.push(
+            Category::Actors,
+            format!("{}: {}", actor_name(path, labels), join_listed(&changes)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::aamp::{ParameterIO, ParameterObject};
+
+    use super::*;
+
+    fn gparamlist(life: i32, power: i32) -> GeneralParamList {
+        GeneralParamList(
+            ParameterIO::new()
+                .with_object(
+                    "General",
+                    ParameterObject::new().with_parameter("Life", Parameter::I32(life)),
+                )
+                .with_object(
+                    "Attack",
+                    ParameterObject::new().with_parameter("Power", Parameter::I32(power)),
+                ),
+        )
+    }
+
+    #[test]
+    fn changes() {
+        let path = "Actor/Pack/Enemy_Guardian_A.sbactorpack//Actor/GeneralParamList/Guardian_A.\
+                    bgparamlist";
+        let diff = GeneralParamList(
+            ParameterIO::new()
+                .with_object(
+                    "General",
+                    ParameterObject::new().with_parameter("Life", Parameter::I32(3000)),
+                )
+                .with_object(
+                    "Item",
+                    ParameterObject::new().with_parameter("BuyingPrice", Parameter::I32(20)),
+                ),
+        );
+        let mut summary = Summary::default();
+        summarize(path, Some(&gparamlist(1500, 40)), &diff, None, &mut summary);
+        assert_eq!(
+            summary.lines(Category::Actors)[0],
+            "Enemy_Guardian_A: HP 1500 → 3000, Price set to 20"
+        );
+
+        let mut summary = Summary::default();
+        summarize(path, None, &gparamlist(1500, 80), None, &mut summary);
+        assert_eq!(
+            summary.lines(Category::Actors)[0],
+            "Enemy_Guardian_A: HP set to 1500, Attack set to 80"
+        );
+
+        let mut summary = Summary::default();
+        let base = gparamlist(1500, 40);
+        summarize(path, Some(&base), &base, None, &mut summary);
+        assert!(summary.is_empty());
+    }
+}
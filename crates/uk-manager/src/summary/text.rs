@@ -0,0 +1,146 @@
+use uk_content::message::MessagePack;
+
+use super::{join_listed, plural, Category, Summary};
+
+/// The language of a message pack, from a path like
+/// `Pack/Bootup_USen.pack//Message/Msg_USen.product.ssarc`.
+fn language(path: &str) -> Option<&str> {
+    path.rsplit('/')
+        .next()?
+        .strip_prefix("Msg_")?
+        .split('.')
+        .next()
+}
+
+pub(super) fn summarize(
+    path: &str,
+    base: Option<&MessagePack>,
+    diff: &MessagePack,
+    summary: &mut Summary,
+) {
+    let (mut edited, mut added) = (0, 0);
+    let mut files = vec![];
+    for (file, text) in &diff.0 {
+        let base_text = base.and_then(|base| base.0.get(file));
+        let mut changed = false;
+        for (key, entry) in &text.entries {
+            match base_text.and_then(|text| text.entries.get(key)) {
+                Some(base_entry) if base_entry == entry => continue,
+                Some(_) => edited += 1,
+                None => added += 1,
+            }
+            changed = true;
+        }
+        if changed {
+            files.push(file.to_string());
+        }
+    }
+    let entries = edited + added;
+    if entries == 0 {
+        return;
+    }
+    let what = match (base, edited, added) {
+        (None, ..) => format!("Changed {entries} text {}", plural(entries, "entry")),
+        (_, edited, 0) => format!("Edited {edited} text {}", plural(edited, "entry")),
+        (_, 0, added) => format!("Added {added} text {}", plural(added, "entry")),
+        (_, edited, added) => {
+            format!(
+                "Edited {edited} and added {added} text {}",
+                plural(entries, "entry")
+            )
+        }
+    };
+    let lang = language(path)
+        .map(|lang| format!(" ({lang})"))
+        .unwrap_or_default();
+    summary.push(
+        Category::Text,
+        format!(
+            "{what} in {} {}{lang}: {}",
+            files.len(),
+            plural(files.len(), "file"),
+            join_listed(&files)
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use uk_content::{
+        message::{Content, Entry, MsbtInfo, Msyt},
+        prelude::Mergeable,
+    };
+
+    use super::*;
+
+    fn msyt(entries: &[(&str, &str)]) -> Msyt {
+        Msyt {
+            msbt:    MsbtInfo {
+                group_count: entries.len() as u32,
+                atr1_unknown: Some(4),
+                ato1: None,
+                tsy1: None,
+                nli1: None,
+            },
+            entries: entries
+                .iter()
+                .map(|(key, text)| {
+                    ((*key).into(), Entry {
+                        attributes: None,
+                        contents:   vec![Content::Text((*text).into())],
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn changes() {
+        let path = "Pack/Bootup_USen.pack//Message/Msg_USen.product.ssarc";
+        let stock = MessagePack(
+            [
+                (
+                    "ActorType/Enemy".into(),
+                    msyt(&[("Enemy_Guardian_A_Name", "Guardian Stalker")]),
+                ),
+                (
+                    "ActorType/Item".into(),
+                    msyt(&[("Item_Fruit_A_Name", "Apple")]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let modded = MessagePack(
+            [
+                (
+                    "ActorType/Enemy".into(),
+                    msyt(&[
+                        ("Enemy_Guardian_A_Name", "Angry Guardian"),
+                        ("Enemy_Guardian_X_Name", "Guardian X"),
+                    ]),
+                ),
+                (
+                    "ActorType/Item".into(),
+                    msyt(&[("Item_Fruit_A_Name", "Apple")]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let diff = stock.diff(&modded);
+        let mut summary = Summary::default();
+        summarize(path, Some(&stock), &diff, &mut summary);
+        assert_eq!(
+            summary.lines(Category::Text)[0],
+            "Edited 1 and added 1 text entries in 1 file (USen): ActorType/Enemy"
+        );
+
+        let mut summary = Summary::default();
+        summarize(path, None, &diff, &mut summary);
+        assert_eq!(
+            summary.lines(Category::Text)[0],
+            "Changed 2 text entries in 1 file (USen): ActorType/Enemy"
+        );
+    }
+}
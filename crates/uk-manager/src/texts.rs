@@ -0,0 +1,273 @@
+//! Turns a plain folder of MSYT or MSBT files, as translators work with, into
+//! a mod which only changes game texts.
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use roead::{sarc::SarcWriter, yaz0::compress};
+use smartstring::alias::String;
+use uk_content::{
+    constants::Language,
+    message::{MessagePack, Msyt},
+    platform_prefixes,
+    prelude::{Endian, Mergeable, Resource},
+    resource::{MergeableResource, ResourceData},
+    util::IndexMap,
+};
+use uk_mod::{pack::ModPacker, Meta, ModPlatform};
+use uk_reader::ResourceReader;
+
+use crate::util;
+
+/// Files or folders which mean a folder holds a mod of some other kind.
+const MOD_MARKERS: &[&str] = &[
+    "content",
+    "aoc",
+    "romfs",
+    "RomFS",
+    "options",
+    "rules.txt",
+    "info.json",
+    "meta.yml",
+];
+
+fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("msyt") || e.eq_ignore_ascii_case("msbt"))
+        .unwrap_or(false)
+}
+
+fn text_files(path: &Path) -> impl Iterator<Item = PathBuf> {
+    jwalk::WalkDir::new(path)
+        .sort(true)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && is_text_file(p))
+}
+
+/// Whether a folder holds message files and nothing marking it as a mod of
+/// another kind.
+pub fn is_text_folder(path: &Path) -> bool {
+    path.is_dir()
+        && !MOD_MARKERS.iter().any(|marker| path.join(marker).exists())
+        && text_files(path).next().is_some()
+}
+
+/// The language a folder is for, if named for one: the language code alone,
+/// or after an underscore as in `Msg_USen.product` or `Bootup_USen`.
+fn language_of(folder: &str) -> Option<Language> {
+    let name = folder.split('.').next()?;
+    let code = name.get(name.len().checked_sub(4)?..)?;
+    let prefix = &name[..name.len() - 4];
+    (prefix.is_empty() || prefix.ends_with('_'))
+        .then(|| Language::from_str(code).ok())
+        .flatten()
+}
+
+/// The name of a message file in the stock pack for a file found at `name`,
+/// by its full path, or else by its file name if only one has it.
+fn locate(stock: &MessagePack, name: &str) -> Option<String> {
+    if stock.0.contains_key(name) {
+        return Some(name.into());
+    }
+    let file = name.rsplit('/').next()?;
+    let suffix = format!("/{file}");
+    let mut found = stock
+        .0
+        .keys()
+        .filter(|key| key.as_str() == file || key.ends_with(&suffix));
+    match (found.next(), found.next()) {
+        (Some(key), None) => Some(key.clone()),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct TextFile {
+    path: PathBuf,
+    /// The path inside the language folder, without extension.
+    name: String,
+}
+
+impl TextFile {
+    fn read(&self) -> Result<Msyt> {
+        let is_msyt = self
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("msyt"))
+            .unwrap_or(false);
+        if is_msyt {
+            Ok(serde_yaml::from_str(&fs::read_to_string(&self.path)?)
+                .with_context(|| format!("Failed to parse MSYT file {}", self.path.display()))?)
+        } else {
+            Ok(Msyt::from_msbt_bytes(&fs::read(&self.path)?)
+                .with_context(|| format!("Failed to parse MSBT file {}", self.path.display()))?)
+        }
+    }
+}
+
+/// The message files in a folder, sorted by language.
+#[derive(Debug, Default)]
+pub struct TextImport {
+    languages: IndexMap<Language, Vec<TextFile>>,
+}
+
+impl TextImport {
+    /// Finds the message files in a folder. Files in a folder named for a
+    /// language are for that one, and any others are for `default`.
+    pub fn scan(path: &Path, default: Language) -> Result<Self> {
+        let mut import = Self::default();
+        for file in text_files(path) {
+            let relative = file
+                .strip_prefix(path)
+                .context("Message file outside the folder")?
+                .with_extension("");
+            let parts: Vec<&str> = relative.iter().filter_map(|p| p.to_str()).collect();
+            let (lang, parts) = parts[..parts.len().saturating_sub(1)]
+                .iter()
+                .position(|part| language_of(part).is_some())
+                .and_then(|i| language_of(parts[i]).map(|lang| (lang, &parts[i + 1..])))
+                .unwrap_or((default, &parts[..]));
+            import.languages.entry(lang).or_default().push(TextFile {
+                name: parts.join("/").into(),
+                path: file,
+            });
+        }
+        if import.languages.is_empty() {
+            anyhow_ext::bail!("No MSYT or MSBT files in {}", path.display());
+        }
+        Ok(import)
+    }
+
+    pub fn languages(&self) -> impl Iterator<Item = &Language> {
+        self.languages.keys()
+    }
+
+    /// Merges the message files over the stock texts for each language, and
+    /// writes each language pack where a mod keeps it under `root`. Returns
+    /// the files which are not in the game and so are added as new ones.
+    pub fn write(&self, dump: &ResourceReader, root: &Path, endian: Endian) -> Result<Vec<String>> {
+        let mut added = vec![];
+        for (lang, files) in &self.languages {
+            let Some(MergeableResource::MessagePack(mut texts)) = ResourceData::clone(
+                dump.get_data(lang.message_path().as_str())
+                    .with_context(|| format!("No stock texts for {lang}"))?
+                    .deref(),
+            )
+            .take_mergeable() else {
+                anyhow_ext::bail!("Broken stock language pack for {lang}");
+            };
+            for file in files {
+                let text = file.read()?;
+                match locate(&texts, &file.name) {
+                    Some(name) => {
+                        let stock = texts.0.get(&name).context("Stock text vanished")?;
+                        let merged = stock.merge(&text);
+                        texts.0.insert(name, merged);
+                    }
+                    None => {
+                        log::warn!(
+                            "{} for {lang} is not in the game, so it is added as a new file",
+                            file.name
+                        );
+                        added.push(format!("{lang}/{}", file.name).into());
+                        texts.0.insert(file.name.clone(), text);
+                    }
+                }
+            }
+            let out = root
+                .join(platform_prefixes(endian).0)
+                .join(lang.bootup_path().as_str());
+            out.parent().map(fs::create_dir_all).transpose()?;
+            let mut sarc = SarcWriter::new(endian.into())
+                .with_file(lang.message_path(), compress(texts.into_binary(endian)));
+            fs::write(out, sarc.to_binary())?;
+        }
+        Ok(added)
+    }
+
+    /// Meta for the mod, named after the folder.
+    pub fn meta(&self, path: &Path) -> Meta {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("Text Edits");
+        let langs = self
+            .languages()
+            .map(|l| l.to_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: Default::default(),
+            category: "Other".into(),
+            description: format!("Text edits for {langs}").into(),
+            platform: ModPlatform::Universal,
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        }
+    }
+}
+
+/// Converts a folder of message files into a text-only mod, returning the
+/// path to the packaged mod. Meta is made up from the folder if not given.
+pub fn convert_texts(
+    core: &crate::core::Manager,
+    path: &Path,
+    meta: Option<Meta>,
+) -> Result<PathBuf> {
+    log::info!("Converting texts at {}", path.display());
+    let (lang, endian, dump) = {
+        let settings = core.settings();
+        let config = settings
+            .platform_config()
+            .context("No config for current platform. Have you configured your settings?")?;
+        (
+            config.language,
+            Endian::from(settings.current_mode),
+            config.active_dump().clone(),
+        )
+    };
+    let import = TextImport::scan(path, lang)?;
+    let source = util::get_temp_folder().to_path_buf();
+    let added = import.write(&dump, &source, endian)?;
+    if !added.is_empty() {
+        log::warn!(
+            "{} file(s) are not in the game and were added as new ones:\n{}",
+            added.len(),
+            added.join("\n")
+        );
+    }
+    let meta = meta.unwrap_or_else(|| import.meta(path));
+    let packer = ModPacker::new(source, std::env::temp_dir(), Some(meta), vec![dump])?;
+    let result_path = packer.pack()?;
+    log::info!("Conversion complete");
+    Ok(result_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_folders() {
+        assert_eq!(language_of("USen"), Some(Language::USen));
+        assert_eq!(language_of("Msg_EUde.product"), Some(Language::EUde));
+        assert_eq!(language_of("Msg_EUde.product.ssarc"), Some(Language::EUde));
+        assert_eq!(language_of("Bootup_JPja"), Some(Language::JPja));
+        assert_eq!(language_of("ActorType"), None);
+        assert_eq!(language_of("XUSen"), None);
+        assert_eq!(language_of("en"), None);
+    }
+}
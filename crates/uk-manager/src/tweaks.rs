@@ -0,0 +1,359 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use join_str::jstr;
+use roead::byml::Byml;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+use uk_content::{
+    prelude::{Endian, Mergeable},
+    resource::{MergeableResource, ResourceData},
+};
+use uk_reader::ResourceReader;
+
+use crate::mods::Mod;
+
+pub const BOOTUP: &str = "Pack/Bootup.pack";
+pub const TWEAKS_MOD_NAME: &str = "User Tweaks";
+/// Finds the tweak patch whatever the user has since renamed it to.
+pub const TWEAKS_PATCH_ID: &str = "user-tweaks";
+
+/// Singleton BYMLs in Bootup.pack which can be tweaked without a mod.
+pub const SINGLETONS: &[(&str, &str)] = &[
+    ("Status Effects", "Ecosystem/StatusEffectList.sbyml"),
+    ("Level Sensor", "Ecosystem/LevelSensor.sbyml"),
+    ("Area Data", "Ecosystem/AreaData.sbyml"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TweakValue {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+}
+
+impl TweakValue {
+    fn from_byml(byml: &Byml) -> Option<Self> {
+        match byml {
+            Byml::Bool(v) => Some(Self::Bool(*v)),
+            Byml::I32(v) => Some(Self::Int(*v)),
+            Byml::U32(v) => Some(Self::UInt(*v)),
+            Byml::Float(v) => Some(Self::Float(*v)),
+            _ => None,
+        }
+    }
+}
+
+impl From<TweakValue> for Byml {
+    fn from(value: TweakValue) -> Self {
+        match value {
+            TweakValue::Bool(v) => Byml::Bool(v),
+            TweakValue::Int(v) => Byml::I32(v),
+            TweakValue::UInt(v) => Byml::U32(v),
+            TweakValue::Float(v) => Byml::Float(v),
+        }
+    }
+}
+
+/// Lists every editable value in a BYML document by its slash-separated path.
+pub fn fields(byml: &Byml) -> BTreeMap<String, TweakValue> {
+    fn walk(byml: &Byml, path: &str, out: &mut BTreeMap<String, TweakValue>) {
+        match byml {
+            Byml::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    walk(item, &format!("{path}/{i}"), out);
+                }
+            }
+            Byml::Map(map) => {
+                for (key, item) in map.iter() {
+                    walk(item, &format!("{path}/{key}"), out);
+                }
+            }
+            _ => {
+                if let Some(value) = TweakValue::from_byml(byml) {
+                    out.insert(path.trim_start_matches('/').into(), value);
+                }
+            }
+        }
+    }
+    let mut out = BTreeMap::new();
+    walk(byml, "", &mut out);
+    out
+}
+
+pub fn set_field(byml: &mut Byml, path: &str, value: TweakValue) -> Result<()> {
+    let mut node = byml;
+    for key in path.split('/') {
+        node = match node {
+            Byml::Array(items) => {
+                let index: usize = key
+                    .parse()
+                    .with_context(|| jstr!("Invalid array index {key} in {path}"))?;
+                items
+                    .get_mut(index)
+                    .with_context(|| jstr!("Array index {key} out of range in {path}"))?
+            }
+            Byml::Map(map) => {
+                map.get_mut(key)
+                    .with_context(|| jstr!("Missing key {key} in {path}"))?
+            }
+            _ => anyhow_ext::bail!("{path} does not point to a value"),
+        };
+    }
+    if TweakValue::from_byml(node).is_none() {
+        anyhow_ext::bail!("{path} does not point to a value");
+    }
+    *node = value.into();
+    Ok(())
+}
+
+/// Applies the tweaked values to a stock singleton and returns the diff to
+/// store in the user tweak mod.
+pub fn patch_resource(
+    path: &str,
+    stock: &[u8],
+    values: &BTreeMap<String, TweakValue>,
+) -> Result<ResourceData> {
+    let mut byml = Byml::from_binary(stock)?;
+    for (field, value) in values {
+        set_field(&mut byml, field, *value)?;
+    }
+    let endian = if stock.starts_with(b"BY") {
+        Endian::Big
+    } else {
+        Endian::Little
+    };
+    let edited = byml.to_binary(endian.into());
+    let base = MergeableResource::from_binary(path.as_ref(), stock)?
+        .with_context(|| jstr!("{path} is not a mergeable resource"))?;
+    let edited = MergeableResource::from_binary(path.as_ref(), &edited)?
+        .with_context(|| jstr!("{path} is not a mergeable resource"))?;
+    Ok(ResourceData::Mergeable(base.diff(&edited)))
+}
+
+pub fn stock_fields(dump: &ResourceReader, path: &str) -> Result<BTreeMap<String, TweakValue>> {
+    let stock = dump
+        .get_bytes_from_sarc(&format!("{BOOTUP}//{path}"))
+        .with_context(|| format!("Failed to read stock {path}"))?;
+    Ok(fields(&Byml::from_binary(stock)?))
+}
+
+/// Tweaked values for each singleton, keyed by path inside Bootup.pack.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tweaks(pub BTreeMap<String, BTreeMap<String, TweakValue>>);
+
+impl Tweaks {
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .context("")
+            .and_then(|text| serde_yaml::from_str(&text).context(""))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.values().all(|v| v.is_empty())
+    }
+
+    /// Builds each tweaked file, by its path in Bootup.pack.
+    pub fn build(&self, dump: &ResourceReader) -> Result<BTreeMap<String, ResourceData>> {
+        let mut resources = BTreeMap::new();
+        for (path, values) in self.0.iter().filter(|(_, v)| !v.is_empty()) {
+            let stock = dump
+                .get_bytes_from_sarc(&format!("{BOOTUP}//{path}"))
+                .with_context(|| format!("Failed to read stock {path}"))?;
+            let resource = patch_resource(path, &stock, values)
+                .with_context(|| format!("Failed to apply tweaks to {path}"))?;
+            resources.insert(path.clone(), resource);
+        }
+        Ok(resources)
+    }
+}
+
+pub fn tweaks_path(core: &crate::core::Manager) -> std::path::PathBuf {
+    core.settings().platform_dir().join("tweaks.yml")
+}
+
+/// Saves the tweaks and rebuilds the user tweak mod, a local patch which is
+/// added to the end of the load order the first time.
+pub fn save(core: &crate::core::Manager, tweaks: &Tweaks) -> Result<Mod> {
+    tweaks.save(&tweaks_path(core))?;
+    let dump = core
+        .settings()
+        .dump()
+        .context("No dump for current platform. Have you configured your settings?")?;
+    let patch = write_patch(&core.mod_manager(), &dump, tweaks)?;
+    log::info!("Saved {TWEAKS_MOD_NAME}");
+    Ok(patch)
+}
+
+/// Rebuilds the user tweak patch from the tweaks, creating it if there is
+/// none yet.
+fn write_patch(mods: &crate::mods::Manager, dump: &ResourceReader, tweaks: &Tweaks) -> Result<Mod> {
+    let mut resources = tweaks.build(dump)?;
+    let mut patch = match mods.local_patch(TWEAKS_PATCH_ID) {
+        Some(patch) => patch,
+        None => {
+            mods.create_local_patch(
+                TWEAKS_PATCH_ID,
+                TWEAKS_MOD_NAME,
+                "Values edited with Quick Tweak",
+            )?
+        }
+    };
+    // Files which are no longer tweaked are dropped from the patch
+    let paths: BTreeSet<&str> = SINGLETONS
+        .iter()
+        .map(|(_, path)| *path)
+        .chain(tweaks.0.keys().map(|path| path.as_str()))
+        .collect();
+    for path in paths {
+        let file = jstr!("{BOOTUP}//{path}");
+        patch = match resources.remove(path) {
+            Some(resource) => mods.append_resource(&patch, &file, false, resource)?,
+            None => mods.remove_resource(&patch, &file, false)?,
+        };
+    }
+    mods.save()?;
+    Ok(patch)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::RwLock;
+    use roead::sarc::Sarc;
+    use uk_content::constants::Language;
+    use uk_mod::unpack::{ModReader, ModUnpacker};
+    use uk_reader::MemoryROMSource;
+
+    use super::*;
+    use crate::settings::Settings;
+
+    fn load_stock(path: &str) -> Vec<u8> {
+        roead::yaz0::decompress(fs::read(format!("../uk-content/test/{path}")).unwrap()).unwrap()
+    }
+
+    /// The first float value of a singleton, raised by 10.
+    fn first_float(stock: &[u8]) -> (String, TweakValue) {
+        let (field, value) = fields(&Byml::from_binary(stock).unwrap())
+            .into_iter()
+            .find(|(_, v)| matches!(v, TweakValue::Float(_)))
+            .unwrap();
+        let TweakValue::Float(value) = value else {
+            unreachable!()
+        };
+        (field, TweakValue::Float(value + 10.0))
+    }
+
+    /// The stock singleton with the tweaked values set, as a resource.
+    fn expected(
+        path: &str,
+        stock: &[u8],
+        values: &BTreeMap<String, TweakValue>,
+    ) -> MergeableResource {
+        let mut byml = Byml::from_binary(stock).unwrap();
+        for (field, value) in values {
+            set_field(&mut byml, field, *value).unwrap();
+        }
+        MergeableResource::from_binary(path.as_ref(), &byml.to_binary(roead::Endian::Big))
+            .unwrap()
+            .unwrap()
+    }
+
+    fn tweak_singleton(path: &str) {
+        let stock = load_stock(path);
+        let values = [first_float(&stock)].into_iter().collect();
+        let diff = patch_resource(path, &stock, &values).unwrap();
+        let base = MergeableResource::from_binary(path.as_ref(), &stock)
+            .unwrap()
+            .unwrap();
+        let merged = base.merge(diff.as_mergeable().unwrap());
+        assert_ne!(merged, base, "{path}");
+        assert_eq!(merged, expected(path, &stock, &values), "{path}");
+    }
+
+    #[test]
+    fn tweak_status() {
+        tweak_singleton("Ecosystem/StatusEffectList.sbyml");
+    }
+
+    #[test]
+    fn tweak_level_sensor() {
+        tweak_singleton("Ecosystem/LevelSensor.sbyml");
+    }
+
+    #[test]
+    fn tweak_area_data() {
+        tweak_singleton("Ecosystem/AreaData.sbyml");
+    }
+
+    /// Tweaks a value of every singleton, saves them twice to the user tweak
+    /// patch, and merges it over the dump.
+    #[test]
+    fn apply_tweaks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = Arc::new(RwLock::new(Settings {
+            storage_dir: tmp.path().join("storage"),
+            ..Default::default()
+        }));
+        let mods = crate::mods::Manager::init(&settings).unwrap();
+        let stock: BTreeMap<&str, Vec<u8>> = SINGLETONS
+            .iter()
+            .map(|(_, path)| (*path, load_stock(path)))
+            .collect();
+        let dump = Arc::new(ResourceReader::from_memory(
+            MemoryROMSource::new(Endian::Big)
+                .with_bootup_pack(stock.iter().map(|(path, data)| (*path, data.as_slice()))),
+        ));
+        let mut tweaks = Tweaks::default();
+        for (path, data) in &stock {
+            tweaks
+                .0
+                .insert((*path).into(), [first_float(data)].into_iter().collect());
+        }
+        write_patch(&mods, &dump, &tweaks).unwrap();
+        let patch = write_patch(&mods, &dump, &tweaks).unwrap();
+        assert_eq!(mods.all_mods().count(), 1);
+
+        let out = tmp.path().join("merged");
+        ModUnpacker::new(
+            dump.clone(),
+            Endian::Big,
+            Language::USen,
+            vec![ModReader::open(&patch.path, vec![]).unwrap()],
+            out.clone(),
+        )
+        .unpack()
+        .unwrap();
+        let bootup = Sarc::new(fs::read(out.join("content").join(BOOTUP)).unwrap()).unwrap();
+        for (path, data) in &stock {
+            // The merged file is the stock one with only the tweaked value set
+            let merged = roead::yaz0::decompress_if(bootup.get_data(path).unwrap());
+            assert_eq!(
+                MergeableResource::from_binary(path.as_ref(), &merged)
+                    .unwrap()
+                    .unwrap(),
+                expected(path, data, &tweaks.0[*path]),
+                "{path}"
+            );
+        }
+
+        // Untweaking every value leaves nothing to merge
+        write_patch(&mods, &dump, &Tweaks::default()).unwrap();
+        let patch = mods.local_patch(TWEAKS_PATCH_ID).unwrap();
+        let reader = ModReader::open(&patch.path, vec![]).unwrap();
+        assert!(reader.manifest.is_empty());
+    }
+}
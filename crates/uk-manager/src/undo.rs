@@ -0,0 +1,415 @@
+//! Undo for changes to the mod list made this session. Each change is kept
+//! with what it changed from and to, and reversing it gives the change which
+//! redoes it. Changes work on the pending mod list, as the front end keeps it
+//! until the next apply, and on the manager for what it saves straight away:
+//! options, installs and uninstalls. Uninstalled mods go to the trash, so
+//! they can only be put back until it is emptied.
+//!
+//! Reversing a change returns the files it touches so they can be merged
+//! again, which means a change that has already been applied can still be
+//! undone. It just becomes a pending change like any other.
+use anyhow_ext::Result;
+use uk_mod::Manifest;
+
+use crate::{
+    mods::{options_delta, trash::RemovedMod, Manager, Mod},
+    util::HashMap,
+};
+
+/// The most operations kept to undo.
+const LIMIT: usize = 100;
+
+/// A change to the mod list, as what it changed from and to.
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// The load order, as mod hashes.
+    Order {
+        before: Vec<usize>,
+        after:  Vec<usize>,
+    },
+    /// Whether each mod was enabled.
+    Enabled {
+        before: Vec<(usize, bool)>,
+        after:  Vec<(usize, bool)>,
+    },
+    /// A mod's selected and inactive options.
+    Options { before: Box<Mod>, after: Box<Mod> },
+    /// Mods installed, or put back from the trash.
+    Installed(Vec<Mod>),
+    /// Mods uninstalled to the trash.
+    Uninstalled(Vec<RemovedMod>),
+}
+
+impl Change {
+    /// A change in load order, from the mod list before and after.
+    pub fn order(before: &[Mod], after: &[Mod]) -> Self {
+        Change::Order {
+            before: before.iter().map(|m| m.hash).collect(),
+            after:  after.iter().map(|m| m.hash).collect(),
+        }
+    }
+
+    /// Mods turned on or off, from how they were before.
+    pub fn enabled(before: &[Mod], enabled: bool) -> Self {
+        Change::Enabled {
+            before: before.iter().map(|m| (m.hash, m.enabled)).collect(),
+            after:  before.iter().map(|m| (m.hash, enabled)).collect(),
+        }
+    }
+
+    /// A mod's options, from the mod before and after.
+    pub fn options(before: Mod, after: Mod) -> Self {
+        Change::Options {
+            before: Box::new(before),
+            after:  Box::new(after),
+        }
+    }
+
+    /// Puts back how things were before the change, returning the change
+    /// which redoes it and the files to merge again.
+    fn revert(self, manager: &Manager, mods: &mut Vec<Mod>) -> Result<(Change, Manifest)> {
+        let mut dirty = Manifest::default();
+        let redo = match self {
+            Change::Order { before, after } => {
+                let positions: HashMap<usize, usize> = before
+                    .iter()
+                    .enumerate()
+                    .map(|(i, hash)| (*hash, i))
+                    .collect();
+                let old: Vec<usize> = mods.iter().map(|m| m.hash).collect();
+                // Mods installed since keep their place after the rest
+                mods.sort_by_key(|m| positions.get(&m.hash).copied().unwrap_or(usize::MAX));
+                for (mod_, old) in mods.iter().zip(old) {
+                    if mod_.hash != old {
+                        dirty.extend(mod_.manifest()?.as_ref());
+                    }
+                }
+                Change::Order {
+                    before: after,
+                    after:  before,
+                }
+            }
+            Change::Enabled { before, after } => {
+                for (hash, enabled) in &before {
+                    if let Some(mod_) = mods.iter_mut().find(|m| m.hash == *hash) {
+                        mod_.enabled = *enabled;
+                        dirty.extend(mod_.manifest()?.as_ref());
+                    }
+                }
+                Change::Enabled {
+                    before: after,
+                    after:  before,
+                }
+            }
+            Change::Options { before, after } => {
+                manager.set_enabled_options(before.hash, before.enabled_options.clone())?;
+                manager.set_inactive_options(before.hash, before.inactive_options.clone())?;
+                if let Some(mod_) = mods.iter_mut().find(|m| m.hash == before.hash) {
+                    mod_.enabled_options = before.enabled_options.clone();
+                    mod_.inactive_options = before.inactive_options.clone();
+                }
+                dirty.extend(&options_delta(&after, &before)?);
+                Change::Options {
+                    before: after,
+                    after:  before,
+                }
+            }
+            Change::Installed(installed) => {
+                let mut removed = Vec::with_capacity(installed.len());
+                for mod_ in &installed {
+                    // Read before the mod's files go to the trash
+                    let manifest = mod_.manifest()?;
+                    if let Some(entry) = manager.trash_mod(mod_)? {
+                        mods.retain(|m| m.hash != mod_.hash);
+                        dirty.extend(&manifest);
+                        removed.push(entry);
+                    }
+                }
+                manager.save()?;
+                Change::Uninstalled(removed)
+            }
+            Change::Uninstalled(removed) => {
+                let mut installed = Vec::with_capacity(removed.len());
+                // Each was removed after those before it, so they go back in
+                // reverse to land where they were
+                for entry in removed.iter().rev() {
+                    let mod_ = manager.restore_mod(entry)?;
+                    mods.insert(entry.index.min(mods.len()), mod_.clone());
+                    dirty.extend(mod_.manifest()?.as_ref());
+                    installed.push(mod_);
+                }
+                installed.reverse();
+                manager.save()?;
+                Change::Installed(installed)
+            }
+        };
+        Ok((redo, dirty))
+    }
+}
+
+/// A change kept to undo or redo.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    pub description: String,
+    /// Whether the merged files have caught up with the operation since it
+    /// was last done or undone, so reversing it would be a new pending change.
+    pub applied: bool,
+    change: Change,
+}
+
+/// What undoing or redoing an operation did.
+#[derive(Debug)]
+pub struct Step {
+    pub description: String,
+    /// Whether the operation had been applied, so the merged files are now
+    /// behind until the next apply.
+    pub applied: bool,
+    /// The files to merge again.
+    pub dirty: Manifest,
+}
+
+/// The operations done to the mod list this session, and those undone since
+/// the last new one.
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    done:   Vec<Operation>,
+    undone: Vec<Operation>,
+}
+
+impl UndoStack {
+    /// Records a change which has just been made. Anything undone can no
+    /// longer be redone after this.
+    pub fn push(&mut self, description: impl Into<String>, change: Change) {
+        self.undone.clear();
+        self.done.push(Operation {
+            description: description.into(),
+            applied: false,
+            change,
+        });
+        if self.done.len() > LIMIT {
+            self.done.remove(0);
+        }
+    }
+
+    /// The operations which can be undone, most recent first.
+    pub fn done(&self) -> impl Iterator<Item = &Operation> {
+        self.done.iter().rev()
+    }
+
+    /// The operations which can be redone, most recently undone first.
+    pub fn undone(&self) -> impl Iterator<Item = &Operation> {
+        self.undone.iter().rev()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Undoes the last operation on the pending mod list `mods` and in the
+    /// manager. An operation which cannot be undone, such as putting back a
+    /// mod which has since been deleted from the trash, is dropped.
+    pub fn undo(&mut self, manager: &Manager, mods: &mut Vec<Mod>) -> Result<Option<Step>> {
+        Self::reverse(&mut self.done, &mut self.undone, manager, mods)
+    }
+
+    /// Redoes the last operation undone, like [`UndoStack::undo`].
+    pub fn redo(&mut self, manager: &Manager, mods: &mut Vec<Mod>) -> Result<Option<Step>> {
+        Self::reverse(&mut self.undone, &mut self.done, manager, mods)
+    }
+
+    fn reverse(
+        from: &mut Vec<Operation>,
+        to: &mut Vec<Operation>,
+        manager: &Manager,
+        mods: &mut Vec<Mod>,
+    ) -> Result<Option<Step>> {
+        let Some(operation) = from.pop() else {
+            return Ok(None);
+        };
+        let (change, dirty) = operation.change.revert(manager, mods)?;
+        to.push(Operation {
+            description: operation.description.clone(),
+            applied: false,
+            change,
+        });
+        Ok(Some(Step {
+            description: operation.description,
+            applied: operation.applied,
+            dirty,
+        }))
+    }
+
+    /// Notes that the pending mod list has been applied, so every operation
+    /// so far is in the merged files.
+    pub fn mark_applied(&mut self) {
+        for operation in self.done.iter_mut().chain(self.undone.iter_mut()) {
+            operation.applied = true;
+        }
+    }
+
+    /// Forgets every operation, such as when the mod list is for another
+    /// profile.
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use fs_err as fs;
+
+    use super::*;
+    use crate::tests::manager;
+
+    /// A local patch which changes one file.
+    fn patch(manager: &Manager, name: &str, file: &str) -> Mod {
+        let mod_ = manager.create_local_patch(name, name, "Test patch").unwrap();
+        let manifest = Manifest {
+            content_files: [file.into()].into(),
+            aoc_files:     Default::default(),
+        };
+        fs::write(
+            mod_.path.join("manifest.yml"),
+            serde_yaml::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+        mod_
+    }
+
+    fn names(mods: &[Mod]) -> Vec<&str> {
+        mods.iter().map(|m| m.meta.name.as_str()).collect()
+    }
+
+    fn files(manifest: &Manifest) -> Vec<&str> {
+        manifest.content_files.iter().map(|f| f.as_str()).collect()
+    }
+
+    #[test]
+    fn order_and_enabled() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (_settings, manager) = manager(&tmp.path().join("storage"));
+        patch(&manager, "Order A", "A.sbyml");
+        patch(&manager, "Order B", "B.sbyml");
+        patch(&manager, "Order C", "C.sbyml");
+        let mut mods: Vec<Mod> = manager.all_mods().collect();
+        let mut stack = UndoStack::default();
+        assert!(stack.undo(&manager, &mut mods).unwrap().is_none());
+
+        let before = mods.clone();
+        mods.rotate_right(1);
+        stack.push("Moved Order C to position 1", Change::order(&before, &mods));
+        let toggled = vec![mods[1].clone()];
+        mods[1].enabled = false;
+        stack.push("Disabled Order A", Change::enabled(&toggled, false));
+        assert_eq!(
+            stack
+                .done()
+                .map(|o| o.description.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Disabled Order A", "Moved Order C to position 1"]
+        );
+
+        let step = stack.undo(&manager, &mut mods).unwrap().unwrap();
+        assert_eq!(step.description, "Disabled Order A");
+        assert_eq!(files(&step.dirty), vec!["A.sbyml"]);
+        assert!(mods.iter().all(|m| m.enabled));
+        let step = stack.undo(&manager, &mut mods).unwrap().unwrap();
+        assert_eq!(names(&mods), vec!["Order A", "Order B", "Order C"]);
+        assert_eq!(files(&step.dirty), vec!["A.sbyml", "B.sbyml", "C.sbyml"]);
+        assert!(!stack.can_undo() && stack.can_redo());
+
+        stack.redo(&manager, &mut mods).unwrap().unwrap();
+        assert_eq!(names(&mods), vec!["Order C", "Order A", "Order B"]);
+        // A new operation drops what was left to redo
+        let before = mods.clone();
+        mods.swap(0, 1);
+        stack.push("Moved Order A to position 1", Change::order(&before, &mods));
+        assert!(!stack.can_redo());
+        let step = stack.undo(&manager, &mut mods).unwrap().unwrap();
+        assert_eq!(names(&mods), vec!["Order C", "Order A", "Order B"]);
+        assert_eq!(files(&step.dirty), vec!["A.sbyml", "C.sbyml"]);
+    }
+
+    #[test]
+    fn undo_after_apply() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (_settings, manager) = manager(&tmp.path().join("storage"));
+        patch(&manager, "Applied A", "A.sbyml");
+        patch(&manager, "Applied B", "B.sbyml");
+        let mut mods: Vec<Mod> = manager.all_mods().collect();
+        let mut stack = UndoStack::default();
+        let before = mods.clone();
+        mods.reverse();
+        stack.push(
+            "Moved Applied B to position 1",
+            Change::order(&before, &mods),
+        );
+        let toggled = vec![mods[0].clone()];
+        mods[0].enabled = false;
+        stack.push("Disabled Applied B", Change::enabled(&toggled, false));
+        stack.undo(&manager, &mut mods).unwrap();
+
+        // Applying saves the pending list, as the front end does
+        manager.set_order(mods.iter().map(|m| m.hash).collect());
+        manager.save().unwrap();
+        stack.mark_applied();
+        assert!(stack.done().chain(stack.undone()).all(|o| o.applied));
+
+        // Undoing an applied operation makes it pending again
+        let step = stack.undo(&manager, &mut mods).unwrap().unwrap();
+        assert!(step.applied);
+        assert_eq!(names(&mods), vec!["Applied A", "Applied B"]);
+        assert_eq!(files(&step.dirty), vec!["A.sbyml", "B.sbyml"]);
+        let redone = stack.undone().next().unwrap();
+        assert!(!redone.applied);
+        // What was undone before the apply is still there to redo
+        assert_eq!(stack.undone().count(), 2);
+        stack.redo(&manager, &mut mods).unwrap();
+        let step = stack.redo(&manager, &mut mods).unwrap().unwrap();
+        assert!(step.applied);
+        assert_eq!(step.description, "Disabled Applied B");
+        assert!(!mods[0].enabled);
+    }
+
+    #[test]
+    fn uninstall_and_install() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (_settings, manager) = manager(&tmp.path().join("storage"));
+        let first = patch(&manager, "Trash A", "A.sbyml");
+        let second = patch(&manager, "Trash B", "B.sbyml");
+        let third = patch(&manager, "Trash C", "C.sbyml");
+        let mut mods: Vec<Mod> = manager.all_mods().collect();
+        let mut stack = UndoStack::default();
+
+        let removed = [&first, &third]
+            .into_iter()
+            .map(|m| manager.trash_mod(m).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        mods.retain(|m| m == &second);
+        stack.push("Uninstalled 2 mods", Change::Uninstalled(removed));
+
+        let step = stack.undo(&manager, &mut mods).unwrap().unwrap();
+        assert_eq!(names(&mods), vec!["Trash A", "Trash B", "Trash C"]);
+        assert_eq!(files(&step.dirty), vec!["A.sbyml", "C.sbyml"]);
+        assert_eq!(
+            manager.all_mods().map(|m| m.hash).collect::<Vec<_>>(),
+            vec![first.hash, second.hash, third.hash]
+        );
+        assert!(third.path.exists());
+
+        stack.redo(&manager, &mut mods).unwrap().unwrap();
+        assert_eq!(names(&mods), vec!["Trash B"]);
+        assert!(!first.path.exists());
+        // Once the trash is emptied, the uninstall is for good
+        manager.empty_trash().unwrap();
+        assert!(stack.undo(&manager, &mut mods).is_err());
+        assert!(!stack.can_undo());
+        assert_eq!(names(&mods), vec!["Trash B"]);
+    }
+}
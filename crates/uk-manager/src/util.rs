@@ -1,12 +1,14 @@
 use std::{
-    path::{Path, PathBuf},
+    io::BufReader,
+    path::{Component, Path, PathBuf},
     sync::{atomic::AtomicBool, LazyLock},
 };
 
-#[cfg(windows)]
 use anyhow_ext::Context;
 use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
 pub use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use uk_content::canon::normalize_unicode;
+use uk_mod::progress::Tracker;
 
 pub fn remove_dir_all(dir: impl AsRef<std::path::Path>) -> anyhow_ext::Result<()> {
     fn inner(dir: &Path) -> anyhow_ext::Result<()> {
@@ -63,7 +65,38 @@ pub fn clear_temp() {
 
 pub static USE_SZ: AtomicBool = AtomicBool::new(true);
 
-pub fn extract_7z(file: &Path, folder: &Path) -> anyhow_ext::Result<()> {
+/// Copies a file, counting the bytes copied towards a progress stage.
+pub fn copy_with_progress(
+    from: &Path,
+    to: &Path,
+    tracker: &Tracker,
+    stage: &'static str,
+) -> anyhow_ext::Result<()> {
+    use std::io::{Read, Write};
+    let mut reader = fs_err::File::open(from)?;
+    tracker.set_total(stage, reader.metadata()?.len());
+    let mut writer = std::io::BufWriter::new(fs_err::File::create(to)?);
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])?;
+        tracker.advance(stage, read as u64);
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Extracts a 7Z archive, counting the bytes extracted towards a progress
+/// stage if given. The 7z command, when used, does not report its progress, so
+/// the stage is only completed once it finishes.
+pub fn extract_7z(
+    file: &Path,
+    folder: &Path,
+    progress: Option<(&Tracker, &'static str)>,
+) -> anyhow_ext::Result<()> {
     static SZ_EXISTS: LazyLock<bool> = LazyLock::new(|| {
         match std::process::Command::new("7z")
             .stdout(std::process::Stdio::null())
@@ -88,10 +121,130 @@ pub fn extract_7z(file: &Path, folder: &Path) -> anyhow_ext::Result<()> {
             .output()?;
         if !output.stderr.is_empty() {
             anyhow_ext::bail!("{}", std::string::String::from_utf8_lossy(&output.stderr))
-        } else {
-            Ok(())
         }
+        if let Some((tracker, stage)) = progress {
+            tracker.complete(stage);
+        }
+        Ok(())
+    } else if let Some((tracker, stage)) = progress {
+        let archive = sevenz_rust::Archive::open(file)?;
+        tracker.set_total(stage, archive.files.iter().map(|f| f.size()).sum());
+        drop(archive);
+        Ok(sevenz_rust::decompress_file_with_extract_fn(
+            file,
+            folder,
+            |entry, reader, dest| {
+                let done = sevenz_rust::default_entry_extract_fn(entry, reader, dest)?;
+                tracker.advance(stage, entry.size());
+                Ok(done)
+            },
+        )?)
     } else {
         Ok(sevenz_rust::decompress_file(file, folder)?)
     }
 }
+
+/// Works out the name of a ZIP entry. The ZIP format allows names in UTF-8,
+/// when a flag is set, or else in the legacy code page (CP437), which is what
+/// `decoded` holds when the flag is missing. Plenty of tools write UTF-8
+/// without the flag, so raw names which are valid UTF-8 are taken as such.
+/// Names flagged as UTF-8 which are not are repaired with a warning. The
+/// result is normalized to NFC, since macOS archives names decomposed.
+fn zip_entry_name(raw: &[u8], decoded: &str) -> std::string::String {
+    let name = match std::str::from_utf8(raw) {
+        Ok(name) => name.to_owned(),
+        // The code page maps every byte, so only a bad UTF-8 name is lossy
+        Err(_) if !decoded.contains(char::REPLACEMENT_CHARACTER) => decoded.to_owned(),
+        Err(_) => {
+            let name = std::string::String::from_utf8_lossy(raw).into_owned();
+            log::warn!("ZIP entry {name} does not have a valid UTF-8 name, replaced invalid bytes");
+            name
+        }
+    };
+    normalize_unicode(&name.replace('\\', "/")).into_owned()
+}
+
+/// Turns a ZIP entry name into a relative path, or `None` if it would point
+/// outside the folder it is extracted to.
+fn enclosed_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    path.components()
+        .try_fold(PathBuf::new(), |mut out, part| {
+            match part {
+                Component::Normal(part) => out.push(part),
+                Component::CurDir => (),
+                _ => return None,
+            }
+            Some(out)
+        })
+        .filter(|out| !out.as_os_str().is_empty())
+}
+
+/// Extracts a ZIP archive, working out entry names as in [`zip_entry_name`]
+/// rather than trusting the archive's own flags.
+pub fn extract_zip(file: &Path, folder: &Path) -> anyhow_ext::Result<()> {
+    let mut zip = zip::ZipArchive::new(BufReader::new(fs_err::File::open(file)?))
+        .context("Failed to open ZIP")?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let name = zip_entry_name(entry.name_raw(), entry.name());
+        let Some(path) = enclosed_path(&name) else {
+            log::warn!("Skipping ZIP entry {name}, which is outside the archive root");
+            continue;
+        };
+        let out = folder.join(path);
+        if entry.is_dir() {
+            fs_err::create_dir_all(&out)?;
+        } else {
+            out.parent().map(fs_err::create_dir_all).transpose()?;
+            std::io::copy(&mut entry, &mut fs_err::File::create(&out)?)
+                .with_context(|| format!("Failed to extract {name}"))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_names() {
+        assert_eq!(
+            zip_entry_name(b"content\\Pack\\Bootup.pack", ""),
+            "content/Pack/Bootup.pack"
+        );
+        assert_eq!(
+            zip_entry_name("Model/Cafe\u{301}.sbfres".as_bytes(), ""),
+            "Model/Caf\u{e9}.sbfres"
+        );
+        assert_eq!(
+            enclosed_path("./content/Pack"),
+            Some(PathBuf::from("content/Pack"))
+        );
+        assert_eq!(enclosed_path("../content/Pack"), None);
+        assert_eq!(enclosed_path("/content/Pack"), None);
+        assert_eq!(enclosed_path("."), None);
+    }
+
+    #[test]
+    fn legacy_names() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("out");
+        extract_zip(Path::new("test/unicode/legacy_names.zip"), &out).unwrap();
+        let model = out.join("content/Model");
+        // Flagged as UTF-8 but not, so the bad byte is replaced
+        assert_eq!(
+            fs_err::read(model.join("Caf\u{fffd}.txt")).unwrap(),
+            b"invalid"
+        );
+        // Not flagged, so read as CP437
+        assert_eq!(
+            fs_err::read(model.join("Na\u{f6}ve.txt")).unwrap(),
+            b"cp437"
+        );
+        assert!(!tmp.path().join("outside.txt").exists());
+        assert_eq!(fs_err::read_dir(&out).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Regenerates the golden digests of merged output used by the pipeline test.
+# Run after a change which is meant to alter merged files, then review and
+# commit tests/golden/pipeline.yml.
+set -e
+cd "$(dirname "$0")/.."
+UKMM_BLESS=1 cargo test --test pipeline
+git --no-pager diff --stat -- tests/golden
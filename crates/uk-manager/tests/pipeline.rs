@@ -0,0 +1,433 @@
+//! End-to-end test of installing, merging and deploying mods, with the stock
+//! file fixtures from `uk-content` standing in for a game dump.
+//!
+//! Besides checking what the merged files hold, the test compares a few of
+//! them with digests in `tests/golden/pipeline.yml`, so any change to the
+//! merged bytes is noticed. After a change which is meant to alter merged
+//! output, regenerate the digests with `tests/bless.sh` and commit them.
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use fs_err as fs;
+use parking_lot::RwLock;
+use roead::{
+    byml::Byml,
+    sarc::Sarc,
+    yaz0::{compress, decompress},
+};
+use uk_content::{
+    actor::info::ActorInfo,
+    chemical::chmres::ChemicalRes,
+    constants::Language,
+    prelude::{Endian, Mergeable, Resource},
+};
+use uk_manager::{
+    core::Manager,
+    settings::{DeployConfig, PlatformSettings, Settings},
+};
+use uk_mod::{pack::ModPacker, Meta, ModOption, ModPlatform, MultipleOptionGroup, OptionGroup};
+use uk_reader::{MemoryROMSource, ResourceReader};
+
+const FIXTURES: &str = "../uk-content/test";
+const GOLDEN: &str = "tests/golden/pipeline.yml";
+
+const ACTORINFO: &str = "Actor/ActorInfo.product.sbyml";
+const CHEMICAL: &str = "Chemical/system.bchmres";
+const PACK: &str = "Actor/Pack/Enemy_Guardian_A.sbactorpack";
+const STATUS: &str = "Ecosystem/StatusEffectList.sbyml";
+const RSTB: &str = "System/Resource/ResourceSizeTable.product.srsizetable";
+/// A new file both of the first two mods add, so the winner depends on
+/// their order.
+const SHARED: &str = "Model/Shared.sbfres";
+const ALPHA_ONLY: &str = "Model/Alpha.sbfres";
+const GAMMA_ONLY: &str = "Model/Gamma.sbfres";
+const OPTION_ONLY: &str = "Model/Option.sbfres";
+/// The key the second mod adds to the first actor in the actor info.
+const ACTOR_KEY: &str = "ukmmTest";
+
+fn fixture(file: &str) -> Vec<u8> {
+    fs::read(Path::new(FIXTURES).join(file)).unwrap()
+}
+
+fn decompress_if(data: Vec<u8>) -> Vec<u8> {
+    if data.starts_with(b"Yaz0") {
+        decompress(data).unwrap()
+    } else {
+        data
+    }
+}
+
+fn write(root: &Path, file: &str, data: &[u8]) {
+    let path = root.join("content").join(file);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, data).unwrap();
+}
+
+fn stock() -> Arc<ResourceReader> {
+    let source = MemoryROMSource::from_files(
+        Endian::Big,
+        [ACTORINFO, CHEMICAL, PACK, STATUS].map(|file| (file, fixture(file))),
+    )
+    .with_file("System/Version.txt", "1.5.0");
+    Arc::new(ResourceReader::from_memory(source))
+}
+
+fn manager(tmp: &Path, dump: &Arc<ResourceReader>) -> Manager {
+    Manager::with_settings(Arc::new(RwLock::new(Settings {
+        storage_dir: tmp.join("storage"),
+        wiiu_config: Some(PlatformSettings {
+            language: Language::USen,
+            profile: "Default".into(),
+            dump: dump.clone(),
+            dumps: Default::default(),
+            profile_dumps: Default::default(),
+            deploy_config: Some(DeployConfig {
+                output: tmp.join("output"),
+                ..Default::default()
+            }),
+        }),
+        ..Default::default()
+    })))
+    .unwrap()
+}
+
+fn meta(name: &str, options: Vec<OptionGroup>) -> Meta {
+    Meta {
+        api: "1.0.0".into(),
+        format: uk_mod::FORMAT_VERSION,
+        name: name.into(),
+        version: "1.0.0".into(),
+        author: Default::default(),
+        category: Default::default(),
+        description: Default::default(),
+        platform: ModPlatform::Specific(Endian::Big),
+        url: None,
+        options,
+        masters: Default::default(),
+    }
+}
+
+/// Packages a mod from its files and the files of each of its options.
+fn pack(
+    tmp: &Path,
+    dump: &Arc<ResourceReader>,
+    meta: Meta,
+    files: Vec<(&str, Vec<u8>)>,
+    options: Vec<(&str, &str, Vec<u8>)>,
+) -> PathBuf {
+    let source = tmp.join("source").join(meta.name.as_str());
+    for (file, data) in files {
+        write(&source, file, &data);
+    }
+    for (option, file, data) in options {
+        write(&source.join("options").join(option), file, &data);
+    }
+    let dest = tmp.join(format!("{}.zip", meta.name));
+    ModPacker::new(&source, &dest, Some(meta), vec![dump.clone()])
+        .unwrap()
+        .pack()
+        .unwrap()
+}
+
+/// The stock actor info with a new key in the first actor, returning the
+/// actor's name too.
+fn edited_actorinfo() -> (String, Vec<u8>) {
+    let mut info = Byml::from_binary(decompress(fixture(ACTORINFO)).unwrap()).unwrap();
+    let Byml::Map(map) = &mut info else {
+        panic!("Actor info is not a map")
+    };
+    let Some(Byml::Array(actors)) = map.get_mut("Actors") else {
+        panic!("Actor info has no actors")
+    };
+    let Byml::Map(actor) = &mut actors[0] else {
+        panic!("Actor is not a map")
+    };
+    let Some(Byml::String(name)) = actor.get("name") else {
+        panic!("Actor has no name")
+    };
+    let name = name.to_string();
+    actor.insert(ACTOR_KEY.into(), Byml::I32(1));
+    (name, compress(info.to_binary(roead::Endian::Big)))
+}
+
+fn merged(core: &Manager, file: &str) -> Option<Vec<u8>> {
+    let path = core.settings().merged_dir().join("content").join(file);
+    path.exists()
+        .then(|| decompress_if(fs::read(path).unwrap()))
+}
+
+fn deployed(tmp: &Path, file: &str) -> Option<Vec<u8>> {
+    let path = tmp.join("output/content").join(file);
+    path.exists()
+        .then(|| decompress_if(fs::read(path).unwrap()))
+}
+
+/// Merges mods' versions of a resource over the stock one, in order.
+fn expected<R: Resource + Mergeable + Clone>(stock: &[u8], mods: &[Vec<u8>]) -> R {
+    let stock = R::from_binary(decompress_if(stock.to_vec())).unwrap();
+    mods.iter().fold(stock.clone(), |merged, data| {
+        let modded = R::from_binary(decompress_if(data.clone())).unwrap();
+        merged.merge(&stock.diff(&modded))
+    })
+}
+
+fn sarc_names(data: &[u8]) -> BTreeSet<String> {
+    Sarc::new(data)
+        .unwrap()
+        .files()
+        .filter_map(|file| file.name.map(String::from))
+        .collect()
+}
+
+/// FNV-1a, which unlike the standard hasher is the same on every platform
+/// and release.
+fn digest(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{} bytes, {hash:016x}", data.len())
+}
+
+fn record(goldens: &mut BTreeMap<String, String>, core: &Manager, stage: &str, files: &[&str]) {
+    for file in files {
+        let data = merged(core, file).unwrap_or_else(|| panic!("{file} not merged"));
+        goldens.insert(format!("{stage}/{file}"), digest(&data));
+    }
+}
+
+/// Compares the digests with the golden ones, or writes them when blessing
+/// or when there are none yet.
+fn check_goldens(actual: BTreeMap<String, String>) {
+    let bless = std::env::var_os("UKMM_BLESS").is_some();
+    let golden = fs::read_to_string(GOLDEN)
+        .ok()
+        .map(|text| serde_yaml::from_str::<BTreeMap<String, String>>(&text).unwrap());
+    match golden {
+        Some(golden) if !bless => {
+            assert_eq!(
+                golden, actual,
+                "Merged output differs from {GOLDEN}. If that is intended, run tests/bless.sh"
+            );
+        }
+        _ => {
+            fs::create_dir_all(Path::new(GOLDEN).parent().unwrap()).unwrap();
+            fs::write(GOLDEN, serde_yaml::to_string(&actual).unwrap()).unwrap();
+            eprintln!("Wrote golden digests to {GOLDEN}");
+        }
+    }
+}
+
+#[test]
+fn pipeline() {
+    let tmp = tempfile::tempdir().unwrap();
+    let tmp = tmp.path();
+    let dump = stock();
+    let core = manager(tmp, &dump);
+    let mut goldens = BTreeMap::new();
+
+    let (actor, beta_info) = edited_actorinfo();
+    let alpha_info = fixture("Actor/ActorInfo.product.mod.sbyml");
+    let alpha_chemical = fixture("Chemical/system.mod.bchmres");
+    let alpha_pack = fixture("Actor/Pack/Enemy_Guardian_A_Mod.sbactorpack");
+    let alpha = pack(
+        tmp,
+        &dump,
+        meta("Alpha", vec![]),
+        vec![
+            (ACTORINFO, alpha_info.clone()),
+            (CHEMICAL, alpha_chemical.clone()),
+            (PACK, alpha_pack.clone()),
+            (SHARED, compress(b"Alpha")),
+            (ALPHA_ONLY, compress(b"Alpha")),
+        ],
+        vec![],
+    );
+    let beta = pack(
+        tmp,
+        &dump,
+        meta("Beta", vec![]),
+        vec![
+            (ACTORINFO, beta_info.clone()),
+            (STATUS, fixture("Ecosystem/StatusEffectList.mod.sbyml")),
+            (SHARED, compress(b"Beta")),
+        ],
+        vec![],
+    );
+    let option = ModOption {
+        name: "Extra".into(),
+        description: Default::default(),
+        path: "extra".into(),
+        requires: vec![],
+        image: None,
+    };
+    let gamma = pack(
+        tmp,
+        &dump,
+        meta("Gamma", vec![OptionGroup::Multiple(MultipleOptionGroup {
+            name: "Extras".into(),
+            description: Default::default(),
+            required: false,
+            defaults: Default::default(),
+            options: vec![option.clone()],
+        })]),
+        vec![(GAMMA_ONLY, compress(b"Gamma"))],
+        vec![("extra", OPTION_ONLY, compress(b"Option"))],
+    );
+
+    // Install all three, with the option on
+    let [alpha, beta, gamma] = [alpha, beta, gamma].map(|path| {
+        core.mod_manager()
+            .add(&path, None)
+            .unwrap_or_else(|e| panic!("Failed to install {}: {e:?}", path.display()))
+    });
+    core.mod_manager()
+        .set_enabled_options(&gamma, vec![option])
+        .unwrap();
+    core.mod_manager().save().unwrap();
+    core.deploy_manager().apply(None).unwrap();
+    core.deploy_manager().deploy().unwrap();
+
+    assert_eq!(deployed(tmp, SHARED).unwrap(), b"Beta");
+    assert_eq!(deployed(tmp, ALPHA_ONLY).unwrap(), b"Alpha");
+    assert_eq!(deployed(tmp, GAMMA_ONLY).unwrap(), b"Gamma");
+    assert_eq!(deployed(tmp, OPTION_ONLY).unwrap(), b"Option");
+    let info = ActorInfo::from_binary(merged(&core, ACTORINFO).unwrap()).unwrap();
+    assert_eq!(
+        info,
+        expected::<ActorInfo>(&fixture(ACTORINFO), &[
+            alpha_info.clone(),
+            beta_info.clone()
+        ])
+    );
+    let Some(Byml::Map(entry)) = info.0.get(roead::aamp::hash_name(&actor)) else {
+        panic!("{actor} is not a map")
+    };
+    assert_eq!(entry.get(ACTOR_KEY), Some(&Byml::I32(1)));
+    assert_eq!(
+        ChemicalRes::from_binary(merged(&core, CHEMICAL).unwrap()).unwrap(),
+        expected::<ChemicalRes>(&fixture(CHEMICAL), &[alpha_chemical])
+    );
+    let pack_names = sarc_names(&merged(&core, PACK).unwrap());
+    let mod_names = sarc_names(&decompress_if(alpha_pack));
+    assert!(
+        mod_names.is_subset(&pack_names),
+        "Missing from the merged pack: {:?}",
+        mod_names.difference(&pack_names).collect::<Vec<_>>()
+    );
+    assert!(deployed(tmp, STATUS).is_some());
+    assert!(deployed(tmp, RSTB).is_some());
+    let table = rstb::ResourceSizeTable::from_binary(merged(&core, RSTB).unwrap()).unwrap();
+    assert!(table
+        .get("Actor/Pack/Enemy_Guardian_A.bactorpack")
+        .is_some());
+    record(&mut goldens, &core, "installed", &[
+        ACTORINFO, CHEMICAL, PACK, RSTB,
+    ]);
+
+    // Disabling a mod removes the files only it has
+    let manifest = core.mod_manager().set_enabled(&gamma, false, None).unwrap();
+    core.mod_manager().save().unwrap();
+    core.deploy_manager()
+        .apply(Some((*manifest).clone()))
+        .unwrap();
+    core.deploy_manager().deploy().unwrap();
+    assert_eq!(deployed(tmp, GAMMA_ONLY), None);
+    assert_eq!(deployed(tmp, OPTION_ONLY), None);
+    assert_eq!(deployed(tmp, SHARED).unwrap(), b"Beta");
+
+    // Moving the first mod to the end lets it win
+    core.mod_manager()
+        .set_order(vec![beta.hash(), alpha.hash(), gamma.hash()]);
+    core.mod_manager().save().unwrap();
+    core.deploy_manager().apply(None).unwrap();
+    core.deploy_manager().deploy().unwrap();
+    assert_eq!(deployed(tmp, SHARED).unwrap(), b"Alpha");
+    assert_eq!(
+        ActorInfo::from_binary(merged(&core, ACTORINFO).unwrap()).unwrap(),
+        expected::<ActorInfo>(&fixture(ACTORINFO), &[beta_info.clone(), alpha_info])
+    );
+    record(&mut goldens, &core, "reordered", &[ACTORINFO, RSTB]);
+
+    // Uninstalling cleans up after the mod
+    let manifest = core.mod_manager().del(&alpha, None).unwrap();
+    core.mod_manager().save().unwrap();
+    core.deploy_manager()
+        .apply(Some((*manifest).clone()))
+        .unwrap();
+    core.deploy_manager().deploy().unwrap();
+    assert!(!alpha.path.exists());
+    for file in [ALPHA_ONLY, CHEMICAL, PACK] {
+        assert_eq!(merged(&core, file), None, "{file}");
+        assert_eq!(deployed(tmp, file), None, "{file}");
+    }
+    assert_eq!(deployed(tmp, SHARED).unwrap(), b"Beta");
+    assert_eq!(
+        ActorInfo::from_binary(merged(&core, ACTORINFO).unwrap()).unwrap(),
+        expected::<ActorInfo>(&fixture(ACTORINFO), &[beta_info])
+    );
+    assert!(!core.deploy_manager().pending());
+    record(&mut goldens, &core, "uninstalled", &[ACTORINFO, RSTB]);
+
+    check_goldens(goldens);
+}
+
+#[test]
+fn passthrough() {
+    let tmp = tempfile::tempdir().unwrap();
+    let tmp = tmp.path();
+    let dump = stock();
+    let core = manager(tmp, &dump);
+    let output = tmp.join("output");
+
+    let [alpha, beta] = [
+        ("Alpha", ALPHA_ONLY, vec![("rules.txt", "Alpha"), ("patches/alpha.txt", "Alpha")]),
+        ("Beta", SHARED, vec![("rules.txt", "Beta")]),
+    ]
+    .map(|(name, file, passthrough)| {
+        for (path, data) in passthrough {
+            let path = tmp.join("source").join(name).join("passthrough").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        let path = pack(
+            tmp,
+            &dump,
+            meta(name, vec![]),
+            vec![(file, compress(name.as_bytes()))],
+            vec![],
+        );
+        core.mod_manager().add(&path, None).unwrap()
+    });
+    core.mod_manager().save().unwrap();
+    core.deploy_manager().apply(None).unwrap();
+    core.deploy_manager().deploy().unwrap();
+
+    // Passthrough files land at the root of the deployment, with the later
+    // mod winning the conflict, and are never merged as resources
+    assert_eq!(fs::read(output.join("rules.txt")).unwrap(), b"Beta");
+    assert_eq!(fs::read(output.join("patches/alpha.txt")).unwrap(), b"Alpha");
+    assert_eq!(deployed(tmp, "rules.txt"), None);
+    assert!(!alpha.manifest().unwrap().content_files.contains("rules.txt"));
+
+    core.mod_manager().set_order(vec![beta.hash(), alpha.hash()]);
+    core.mod_manager().save().unwrap();
+    core.deploy_manager().apply(None).unwrap();
+    core.deploy_manager().deploy().unwrap();
+    assert_eq!(fs::read(output.join("rules.txt")).unwrap(), b"Alpha");
+
+    // Uninstalling removes the files only it had from the deployment
+    let manifest = core.mod_manager().del(&alpha, None).unwrap();
+    core.mod_manager().save().unwrap();
+    core.deploy_manager()
+        .apply(Some((*manifest).clone()))
+        .unwrap();
+    core.deploy_manager().deploy().unwrap();
+    assert_eq!(fs::read(output.join("rules.txt")).unwrap(), b"Beta");
+    assert!(!output.join("patches/alpha.txt").exists());
+    assert!(!core.deploy_manager().pending());
+}
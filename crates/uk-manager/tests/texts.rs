@@ -0,0 +1,185 @@
+//! End-to-end test of turning a plain folder of message files in two
+//! languages into a text-only mod, with the stock message fixture from
+//! `uk-content` standing in for the texts of each language in the dump.
+#![allow(clippy::unwrap_used)]
+
+use std::{path::Path, sync::Arc};
+
+use fs_err as fs;
+use parking_lot::RwLock;
+use roead::{
+    sarc::{Sarc, SarcWriter},
+    yaz0::decompress_if,
+};
+use uk_content::{
+    constants::Language,
+    message::{Content, Endianness, MessagePack, Msyt},
+    prelude::{Endian, Resource},
+};
+use uk_manager::{
+    core::Manager,
+    settings::{PlatformSettings, Settings},
+    texts::{self, TextImport},
+};
+use uk_mod::unpack::ModReader;
+use uk_reader::{MemoryROMSource, ResourceReader};
+
+const MESSAGES: &str = "../uk-content/test/Message/Msg_USen.product.ssarc";
+/// A message file the game does not have.
+const NEW_FILE: &str = "ActorType/UkmmTest";
+
+fn stock_texts() -> MessagePack {
+    MessagePack::from_binary(decompress_if(&fs::read(MESSAGES).unwrap())).unwrap()
+}
+
+/// A dump with the same stock texts for English and German.
+fn stock() -> Arc<ResourceReader> {
+    let messages = fs::read(MESSAGES).unwrap();
+    let source = MemoryROMSource::from_files(
+        Endian::Big,
+        [Language::USen, Language::EUde].map(|lang| {
+            let mut pack = SarcWriter::new(roead::Endian::Big)
+                .with_file(lang.message_path(), messages.clone());
+            (lang.bootup_path().to_string(), pack.to_binary())
+        }),
+    )
+    .with_file("System/Version.txt", "1.5.0");
+    Arc::new(ResourceReader::from_memory(source))
+}
+
+fn manager(tmp: &Path, dump: &Arc<ResourceReader>) -> Manager {
+    Manager::with_settings(Arc::new(RwLock::new(Settings {
+        storage_dir: tmp.join("storage"),
+        wiiu_config: Some(PlatformSettings {
+            language: Language::USen,
+            profile: "Default".into(),
+            dump: dump.clone(),
+            dumps: Default::default(),
+            profile_dumps: Default::default(),
+            deploy_config: None,
+        }),
+        ..Default::default()
+    })))
+    .unwrap()
+}
+
+/// The texts in a language pack as a mod or the merge writes it.
+fn pack_texts(path: &Path, lang: Language) -> MessagePack {
+    let pack = Sarc::new(fs::read(path).unwrap()).unwrap();
+    MessagePack::from_binary(decompress_if(
+        pack.get_data(lang.message_path().as_str()).unwrap(),
+    ))
+    .unwrap()
+}
+
+fn text(contents: &str) -> Vec<Content> {
+    vec![Content::Text(contents.into())]
+}
+
+#[test]
+fn import_texts() {
+    let tmp = tempfile::tempdir().unwrap();
+    let tmp = tmp.path();
+    let stock_texts = stock_texts();
+    // A file found by its name alone as well as by its full path
+    let (file, stock_text) = stock_texts
+        .0
+        .iter()
+        .find(|(file, text)| {
+            let name = file.rsplit('/').next().unwrap();
+            !text.entries.is_empty()
+                && stock_texts
+                    .0
+                    .keys()
+                    .filter(|key| key.rsplit('/').next() == Some(name))
+                    .count()
+                    == 1
+        })
+        .unwrap();
+    let name = file.rsplit('/').next().unwrap();
+    let key = stock_text.entries.keys().next().unwrap().clone();
+    let edit = |contents: &str| -> Msyt {
+        let mut msyt = stock_text.clone();
+        msyt.entries.get_mut(&key).unwrap().contents = text(contents);
+        msyt
+    };
+
+    // English as MSYT under its path in the pack, German as MSBT by name only
+    let folder = tmp.join("Translation");
+    let write = |path: &Path, data: Vec<u8>| {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    };
+    write(
+        &folder.join("USen").join(format!("{file}.msyt")),
+        serde_yaml::to_string(&edit("English"))
+            .unwrap()
+            .into_bytes(),
+    );
+    write(
+        &folder.join("USen").join(format!("{NEW_FILE}.msyt")),
+        serde_yaml::to_string(&edit("New")).unwrap().into_bytes(),
+    );
+    write(
+        &folder.join("Msg_EUde.product").join(format!("{name}.msbt")),
+        edit("Deutsch").into_msbt_bytes(Endianness::Big).unwrap(),
+    );
+    assert!(texts::is_text_folder(&folder));
+    // Message files in a mod of another kind are left to its own converter
+    let other = tmp.join("Other");
+    write(&other.join("rules.txt"), vec![]);
+    write(
+        &other.join("USen").join(format!("{file}.msyt")),
+        serde_yaml::to_string(stock_text).unwrap().into_bytes(),
+    );
+    assert!(!texts::is_text_folder(&other));
+
+    let dump = stock();
+    let import = TextImport::scan(&folder, Language::USen).unwrap();
+    assert_eq!(import.languages().copied().collect::<Vec<_>>(), vec![
+        Language::EUde,
+        Language::USen
+    ]);
+    let unpacked = tmp.join("unpacked");
+    let added = import.write(&dump, &unpacked, Endian::Big).unwrap();
+    assert_eq!(added.len(), 1);
+    assert_eq!(added[0].as_str(), format!("USen/{NEW_FILE}"));
+    let german = pack_texts(
+        &unpacked
+            .join("content")
+            .join(Language::EUde.bootup_path().as_str()),
+        Language::EUde,
+    );
+    assert_eq!(
+        german.0[file.as_str()].entries[&key].contents,
+        text("Deutsch")
+    );
+
+    // Packaged with made up meta, installed and merged for English
+    let core = manager(tmp, &dump);
+    let path = texts::convert_texts(&core, &folder, None).unwrap();
+    let reader = ModReader::open(&path, vec![]).unwrap();
+    assert_eq!(reader.meta.name.as_str(), "Translation");
+    assert_eq!(
+        reader.meta.description.as_str(),
+        "Text edits for EUde, USen"
+    );
+    core.mod_manager().add(&path, None).unwrap();
+    core.mod_manager().save().unwrap();
+    core.deploy_manager().apply(None).unwrap();
+    let merged = pack_texts(
+        &core
+            .settings()
+            .merged_dir()
+            .join("content")
+            .join(Language::USen.bootup_path().as_str()),
+        Language::USen,
+    );
+    let merged_text = &merged.0[file.as_str()];
+    assert_eq!(merged_text.entries[&key].contents, text("English"));
+    // The rest of the file is left as it was
+    for (other, entry) in stock_text.entries.iter().filter(|(k, _)| **k != key) {
+        assert_eq!(merged_text.entries.get(other), Some(entry));
+    }
+    assert_eq!(merged.0[NEW_FILE].entries[&key].contents, text("New"));
+}
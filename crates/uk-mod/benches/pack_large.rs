@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use uk_content::prelude::Endian;
+use uk_mod::{pack::ModPacker, Meta, ModPlatform, FORMAT_VERSION};
+
+const FILE: &str = "content/Movie/Large.mp4";
+/// Size of the generated movie, larger than most in the game.
+const SIZE: usize = 256 * 1024 * 1024;
+
+/// Writes a mod source folder holding one large movie.
+fn fixture() -> tempfile::TempDir {
+    let tmp = tempfile::tempdir().unwrap();
+    let movie = tmp.path().join("source").join(FILE);
+    std::fs::create_dir_all(movie.parent().unwrap()).unwrap();
+    let data: Vec<u8> = (0..SIZE as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    std::fs::write(movie, data).unwrap();
+    tmp
+}
+
+fn meta() -> Meta {
+    Meta {
+        api: env!("CARGO_PKG_VERSION").into(),
+        format: FORMAT_VERSION,
+        platform: ModPlatform::Specific(Endian::Big),
+        name: "Large".into(),
+        version: "1.0.0".into(),
+        category: Default::default(),
+        author: Default::default(),
+        description: Default::default(),
+        masters: Default::default(),
+        url: None,
+        options: vec![],
+    }
+}
+
+fn pack(dir: &Path, threshold: u64) {
+    ModPacker::new(
+        dir.join("source"),
+        dir.join("out.zip"),
+        Some(meta()),
+        vec![],
+    )
+    .unwrap()
+    .with_stream_threshold(threshold)
+    .pack()
+    .unwrap();
+}
+
+/// Peak resident memory in KiB since the last reset, from procfs.
+#[cfg(target_os = "linux")]
+fn peak_rss() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .unwrap()
+        .lines()
+        .find_map(|l| l.strip_prefix("VmHWM:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn reset_peak_rss() {
+    std::fs::write("/proc/self/clear_refs", "5").unwrap_or(());
+}
+
+/// Reports how far peak memory rises while packaging the movie each way.
+/// This is printed rather than measured by criterion, which only times
+/// things.
+#[cfg(target_os = "linux")]
+fn peak_memory(dir: &Path) {
+    for (label, threshold) in [
+        ("read", u64::MAX),
+        ("streamed", uk_mod::pack::STREAM_THRESHOLD),
+    ] {
+        reset_peak_rss();
+        let before = peak_rss();
+        pack(dir, threshold);
+        let after = peak_rss();
+        eprintln!(
+            "{label}: peak RSS rose by {} MiB packaging {FILE}",
+            after.saturating_sub(before) / 1024
+        );
+    }
+}
+
+fn pack_large(c: &mut Criterion) {
+    let tmp = fixture();
+    #[cfg(target_os = "linux")]
+    peak_memory(tmp.path());
+    let mut group = c.benchmark_group("pack_large_file");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SIZE as u64));
+    for (label, threshold) in [
+        ("read", u64::MAX),
+        ("streamed", uk_mod::pack::STREAM_THRESHOLD),
+    ] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(label),
+            &threshold,
+            |b, threshold| b.iter(|| pack(tmp.path(), *threshold)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pack_large);
+criterion_main!(benches);
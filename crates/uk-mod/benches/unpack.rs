@@ -0,0 +1,184 @@
+//! Deploying fixture mods over a small in-memory dump: the whole unpack, the
+//! same unpack repeated with the mods read from the resource cache, and
+//! rebuilding a pack which nests modded actor packs. Save a baseline before a
+//! change with `cargo bench -p uk-mod --bench unpack -- --save-baseline
+//! before`, then compare with `--baseline before` after it.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use roead::sarc::SarcWriter;
+use uk_content::{constants::Language, fixtures, prelude::Endian};
+use uk_mod::{
+    pack::ModPacker,
+    unpack::{ModReader, ModUnpacker},
+    Meta, ModPlatform, FORMAT_VERSION,
+};
+use uk_reader::ResourceReader;
+
+const ACTOR: &str = "Actor/Pack/Enemy_Moriblin_Junior.sbactorpack";
+const PACK: &str = "Pack/Bench.pack";
+/// Generated BYML files in the nested pack, of which the mod changes one.
+const DATA: usize = 8;
+const DATA_SIZE: usize = 1_000;
+
+fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../uk-content/test/Actor/Pack")
+            .join(name),
+    )
+    .unwrap()
+}
+
+/// The files in the nested pack, stock or modded.
+fn pack_files(modded: bool) -> Vec<(String, Vec<u8>)> {
+    let actor = if modded {
+        "Enemy_Moriblin_Junior_Mod.sbactorpack"
+    } else {
+        "Enemy_Moriblin_Junior.sbactorpack"
+    };
+    let mut files = vec![(ACTOR.to_owned(), fixture(actor))];
+    files.extend((0..DATA).map(|i| {
+        let data = if modded && i == 0 {
+            fixtures::modded_byml(DATA_SIZE, 20)
+        } else {
+            fixtures::byml(DATA_SIZE)
+        };
+        (
+            format!("Bench/Data_{i}.byml"),
+            data.to_binary(roead::Endian::Big),
+        )
+    }));
+    files
+}
+
+fn dump() -> Arc<ResourceReader> {
+    Arc::new(ResourceReader::from_memory(
+        uk_reader::MemoryROMSource::new(Endian::Big)
+            .with_file(ACTOR, fixture("Enemy_Moriblin_Junior.sbactorpack"))
+            .with_pack(PACK, pack_files(false)),
+    ))
+}
+
+fn meta(name: &str) -> Meta {
+    Meta {
+        api: env!("CARGO_PKG_VERSION").into(),
+        format: FORMAT_VERSION,
+        platform: ModPlatform::Specific(Endian::Big),
+        name: name.into(),
+        version: "1.0.0".into(),
+        category: Default::default(),
+        author: Default::default(),
+        description: Default::default(),
+        masters: Default::default(),
+        url: None,
+        options: vec![],
+    }
+}
+
+/// Packages a mod from `files`, given by their paths under `content`.
+fn package(
+    dir: &Path,
+    name: &str,
+    dump: &Arc<ResourceReader>,
+    files: &[(&str, Vec<u8>)],
+) -> PathBuf {
+    let source = dir.join(name);
+    for (file, data) in files {
+        let path = source.join("content").join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+    ModPacker::new(
+        &source,
+        dir.join(format!("{name}.zip")),
+        Some(meta(name)),
+        vec![dump.clone()],
+    )
+    .unwrap()
+    .pack()
+    .unwrap()
+}
+
+/// One mod changing a loose actor pack, and one changing the nested pack.
+fn mods(dir: &Path, dump: &Arc<ResourceReader>) -> Vec<PathBuf> {
+    let mut pack = SarcWriter::new(roead::Endian::Big);
+    for (name, data) in pack_files(true) {
+        pack.add_file(name.as_str(), data);
+    }
+    vec![
+        package(dir, "Actor", dump, &[(
+            ACTOR,
+            fixture("Enemy_Moriblin_Junior_Mod.sbactorpack"),
+        )]),
+        package(dir, "Pack", dump, &[(PACK, pack.to_binary())]),
+    ]
+}
+
+fn unpack(c: &mut Criterion) {
+    let tmp = tempfile::tempdir().unwrap();
+    let dump = dump();
+    let mods = mods(tmp.path(), &dump);
+    let open = || -> Vec<ModReader> {
+        mods.iter()
+            .map(|path| ModReader::open(path, vec![]).unwrap())
+            .collect()
+    };
+    let out = tmp.path().join("out");
+    let mut group = c.benchmark_group("unpack");
+    group.sample_size(20);
+    group.bench_function("deploy", |b| {
+        b.iter_batched(
+            || {
+                std::fs::remove_dir_all(&out).unwrap_or(());
+                open()
+            },
+            |mods| {
+                ModUnpacker::new(dump.clone(), Endian::Big, Language::USen, mods, out.clone())
+                    .unpack()
+                    .unwrap()
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    // Repeat merges read from a cache built once, as deploying again does
+    let cache = tmp.path().join("cache");
+    let open_cached = || -> Vec<ModReader> {
+        open()
+            .into_iter()
+            .map(|mod_| mod_.with_platform(Endian::Big).with_cache(&cache))
+            .collect()
+    };
+    open_cached();
+    group.bench_function("deploy_cached", |b| {
+        b.iter_batched(
+            || {
+                std::fs::remove_dir_all(&out).unwrap_or(());
+                open_cached()
+            },
+            |mods| {
+                ModUnpacker::new(dump.clone(), Endian::Big, Language::USen, mods, out.clone())
+                    .unpack()
+                    .unwrap()
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    let unpacker = ModUnpacker::new(
+        dump.clone(),
+        Endian::Big,
+        Language::USen,
+        open(),
+        out.clone(),
+    );
+    group.bench_function("build_sarc", |b| {
+        b.iter(|| criterion::black_box(unpacker.build_single(PACK, false).unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, unpack);
+criterion_main!(benches);
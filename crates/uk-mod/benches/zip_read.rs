@@ -0,0 +1,66 @@
+//! Reading entries from a mod archive in random order, as deploying does, from
+//! both an archive read into memory and a memory-mapped one. Save a baseline
+//! before a change with `cargo bench -p uk-mod --bench zip_read --
+//! --save-baseline before`, then compare with `--baseline before` after it.
+use std::io::Write;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rayon::prelude::*;
+use uk_content::fixtures::Sequence;
+use uk_mod::unpack::ParallelZipReader;
+use zip::write::SimpleFileOptions;
+
+const ENTRIES: usize = 2_000;
+const ENTRY_SIZE: usize = 16 * 1024;
+const READS: usize = 500;
+
+fn entry(i: usize) -> String {
+    format!("content/Actor/Pack/Bench_{i:04}.sbactorpack")
+}
+
+/// Writes an archive of generated entries, stored as mods are.
+fn fixture(path: &std::path::Path) {
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    let opts = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let mut seq = Sequence::new(ENTRIES as u64);
+    for i in 0..ENTRIES {
+        zip.start_file(entry(i), opts).unwrap();
+        let data: Vec<u8> = (0..ENTRY_SIZE).map(|_| seq.next_u32() as u8).collect();
+        zip.write_all(&data).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn zip_read(c: &mut Criterion) {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("bench.zip");
+    fixture(&path);
+    let mut seq = Sequence::new(READS as u64);
+    let reads: Vec<String> = (0..READS)
+        .map(|_| entry(seq.below(ENTRIES as u32) as usize))
+        .collect();
+    let mut group = c.benchmark_group("zip_read");
+    group.throughput(Throughput::Bytes((READS * ENTRY_SIZE) as u64));
+    // Small archives are read into memory, and peeked ones mapped
+    for (label, mapped) in [("owned", false), ("mapped", true)] {
+        let zip = ParallelZipReader::open(&path, mapped).unwrap();
+        group.bench_with_input(BenchmarkId::new("serial", label), &zip, |b, zip| {
+            b.iter(|| {
+                for file in &reads {
+                    criterion::black_box(zip.get_file(file).unwrap());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", label), &zip, |b, zip| {
+            b.iter(|| {
+                reads.par_iter().for_each(|file| {
+                    criterion::black_box(zip.get_file(file).unwrap());
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, zip_read);
+criterion_main!(benches);
@@ -0,0 +1,360 @@
+//! Finds resources encoded for the wrong platform before a mod is packaged.
+//! Mergeable resources are parsed and written out again for each platform,
+//! but anything stored as-is keeps the byte order it was saved with, so a
+//! Wii U file left in a Switch mod only shows up as a problem in game.
+//!
+//! BYML and SARC files follow the platform. AAMP files are little endian on
+//! both platforms, so a big endian one is always a mismatch.
+use std::{borrow::Cow, fmt, io::Read, path::Path};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use jwalk::WalkDir;
+use path_slash::PathExt;
+use rayon::prelude::*;
+use roead::{
+    byml::Byml,
+    sarc::{Sarc, SarcWriter},
+    yaz0::{compress, decompress_if},
+};
+use uk_content::prelude::Endian;
+
+use crate::{pack::detect_endian, ModPlatform};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Aamp,
+    Byml,
+    Sarc,
+}
+
+impl Format {
+    /// The byte order this format should have in a mod for a platform.
+    pub fn expected(&self, platform: Endian) -> Endian {
+        match self {
+            Format::Aamp => Endian::Little,
+            Format::Byml | Format::Sarc => platform,
+        }
+    }
+
+    /// Whether files in this format can be re-encoded for another platform.
+    /// AAMP files can only be read as little endian.
+    pub fn convertible(&self) -> bool {
+        !matches!(self, Format::Aamp)
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Aamp => "AAMP",
+            Format::Byml => "BYML",
+            Format::Sarc => "SARC",
+        })
+    }
+}
+
+/// Reads the format and byte order of a decompressed resource from its
+/// header, without parsing the rest.
+pub fn sniff(data: &[u8]) -> Option<(Format, Endian)> {
+    match data.get(..4)? {
+        [b'B', b'Y', ..] => Some((Format::Byml, Endian::Big)),
+        [b'Y', b'B', ..] => Some((Format::Byml, Endian::Little)),
+        b"SARC" => {
+            match data.get(6..8)? {
+                [0xFE, 0xFF] => Some((Format::Sarc, Endian::Big)),
+                [0xFF, 0xFE] => Some((Format::Sarc, Endian::Little)),
+                _ => None,
+            }
+        }
+        // The first flag bit marks little endian files, and lands in the first
+        // flags byte only when the flags are themselves little endian
+        b"AAMP" => {
+            Some((
+                Format::Aamp,
+                if data.get(8)? & 1 == 1 {
+                    Endian::Little
+                } else {
+                    Endian::Big
+                },
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Path of the file in the source folder, with `//` before the name of
+    /// each file inside a SARC.
+    pub path:     String,
+    pub format:   Format,
+    pub found:    Endian,
+    pub expected: Endian,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} is {}, expected {}",
+            self.path, self.format, self.found, self.expected
+        )
+    }
+}
+
+/// Checks a resource, and everything inside it if it is a SARC, against the
+/// platform a mod is for.
+pub fn audit_data(path: &str, data: &[u8], platform: Endian, found: &mut Vec<Mismatch>) {
+    let data = decompress_if(data);
+    let Some((format, endian)) = sniff(&data) else {
+        return;
+    };
+    let expected = format.expected(platform);
+    if endian != expected {
+        found.push(Mismatch {
+            path: path.into(),
+            format,
+            found: endian,
+            expected,
+        });
+    }
+    if format == Format::Sarc {
+        let Ok(sarc) = Sarc::new(data.as_ref()) else {
+            return;
+        };
+        for file in sarc.files() {
+            if let Some(name) = file.name() {
+                audit_data(&format!("{path}//{name}"), file.data, platform, found);
+            }
+        }
+    }
+}
+
+/// The platform the files in a mod source should be encoded for: the
+/// declared platform, or for each half of a dual-platform mod the one its
+/// folders are laid out for. Universal mods are not checked, since they may
+/// only hold resources which are parsed anyway.
+pub fn source_endian(source: &Path, platform: &ModPlatform) -> Option<Endian> {
+    match platform {
+        ModPlatform::Specific(endian) => Some(*endian),
+        ModPlatform::Dual => detect_endian(source).ok(),
+        ModPlatform::Universal => None,
+    }
+}
+
+/// Reads a file only if its header shows it could be a resource worth
+/// checking, so large models and textures are not read in full.
+fn read_candidate(path: &Path) -> Option<Vec<u8>> {
+    let mut header = [0; 8];
+    let mut file = fs::File::open(path).ok()?;
+    file.read_exact(&mut header).ok()?;
+    if !header.starts_with(b"Yaz0") && sniff(&header).is_none() {
+        return None;
+    }
+    fs::read(path).ok()
+}
+
+/// Checks every file in a mod source folder, including option folders.
+pub fn audit_source(source: &Path, platform: Endian) -> Vec<Mismatch> {
+    let files: Vec<_> = WalkDir::new(source)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path())
+        .collect();
+    let mut found: Vec<Mismatch> = files
+        .into_par_iter()
+        .flat_map_iter(|path| {
+            let mut found = vec![];
+            if let Some(data) = read_candidate(&path) {
+                let name = path.strip_prefix(source).unwrap_or(&path).to_slash_lossy();
+                audit_data(&name, &data, platform, &mut found);
+            }
+            found
+        })
+        .collect();
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found
+}
+
+/// Re-encodes a resource for a platform, along with anything inside it if it
+/// is a SARC, keeping Yaz0 compression if it had it. Returns `None` if
+/// nothing needed converting. AAMP files are left alone, as they cannot be
+/// read big endian.
+pub fn convert(data: &[u8], platform: Endian) -> Result<Option<Vec<u8>>> {
+    let decompressed = decompress_if(data);
+    let Some((format, endian)) = sniff(&decompressed) else {
+        return Ok(None);
+    };
+    let expected = format.expected(platform);
+    let converted = match format {
+        Format::Byml if endian != expected => {
+            Some(Byml::from_binary(&decompressed)?.to_binary(expected.into()))
+        }
+        Format::Sarc => convert_sarc(&decompressed, endian != expected, platform)?,
+        _ => None,
+    };
+    Ok(converted.map(|data| {
+        match decompressed {
+            Cow::Owned(_) => compress(data),
+            Cow::Borrowed(_) => data,
+        }
+    }))
+}
+
+fn convert_sarc(data: &[u8], mismatched: bool, platform: Endian) -> Result<Option<Vec<u8>>> {
+    let sarc = Sarc::new(data)?;
+    let mut writer = SarcWriter::from_sarc(&sarc);
+    let mut changed = mismatched;
+    for file in sarc.files() {
+        let Some(name) = file.name() else {
+            continue;
+        };
+        if let Some(data) =
+            convert(file.data, platform).with_context(|| format!("Failed to convert {name}"))?
+        {
+            writer.add_file(name, data);
+            changed = true;
+        }
+    }
+    if !changed {
+        return Ok(None);
+    }
+    writer.set_endian(platform.into());
+    Ok(Some(writer.to_binary()))
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::aamp::ParameterIO;
+
+    use super::*;
+
+    fn byml(endian: Endian) -> Vec<u8> {
+        Byml::Map([("Key".into(), Byml::I32(1))].into_iter().collect()).to_binary(endian.into())
+    }
+
+    fn aamp() -> Vec<u8> {
+        ParameterIO::new().to_binary()
+    }
+
+    fn sarc(endian: Endian, files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut writer = SarcWriter::new(endian.into());
+        for (name, data) in files {
+            writer.add_file(*name, data.clone());
+        }
+        writer.to_binary()
+    }
+
+    /// A Switch mod source with some files saved for the Wii U, one of them
+    /// inside a compressed pack.
+    fn mixed_source() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let write = |path: &str, data: Vec<u8>| {
+            let path = tmp.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        };
+        let romfs = "01007EF00011E000/romfs";
+        write(&format!("{romfs}/Map/Good.byml"), byml(Endian::Little));
+        write(&format!("{romfs}/Map/Bad.byml"), byml(Endian::Big));
+        write(
+            &format!("{romfs}/Pack/Test.spack"),
+            compress(sarc(Endian::Little, &[
+                ("Actor/Good.byml", byml(Endian::Little)),
+                ("Actor/Bad.sbyml", compress(byml(Endian::Big))),
+                ("Actor/Params.bxml", aamp()),
+            ])),
+        );
+        write(
+            &format!("{romfs}/Pack/Wrong.pack"),
+            sarc(Endian::Big, &[("Actor/Good.byml", byml(Endian::Little))]),
+        );
+        write(&format!("{romfs}/Model/Test.sbfres"), vec![0; 64]);
+        tmp
+    }
+
+    #[test]
+    fn sniffing() {
+        assert_eq!(
+            sniff(&byml(Endian::Big)),
+            Some((Format::Byml, Endian::Big))
+        );
+        assert_eq!(
+            sniff(&byml(Endian::Little)),
+            Some((Format::Byml, Endian::Little))
+        );
+        assert_eq!(
+            sniff(&sarc(Endian::Big, &[])),
+            Some((Format::Sarc, Endian::Big))
+        );
+        assert_eq!(
+            sniff(&sarc(Endian::Little, &[])),
+            Some((Format::Sarc, Endian::Little))
+        );
+        assert_eq!(sniff(&aamp()), Some((Format::Aamp, Endian::Little)));
+        let mut big_aamp = aamp();
+        big_aamp[8] = 0;
+        assert_eq!(sniff(&big_aamp), Some((Format::Aamp, Endian::Big)));
+        assert_eq!(sniff(b"Yaz0"), None);
+        assert_eq!(sniff(b"BY"), None);
+    }
+
+    #[test]
+    fn audit() {
+        let tmp = mixed_source();
+        let found = audit_source(tmp.path(), Endian::Little);
+        let paths: Vec<_> = found.iter().map(|m| m.path.as_str()).collect();
+        assert_eq!(paths, [
+            "01007EF00011E000/romfs/Map/Bad.byml",
+            "01007EF00011E000/romfs/Pack/Test.spack//Actor/Bad.sbyml",
+            "01007EF00011E000/romfs/Pack/Wrong.pack",
+        ]);
+        assert_eq!(found[0].format, Format::Byml);
+        assert_eq!(found[0].found, Endian::Big);
+        assert_eq!(found[0].expected, Endian::Little);
+        assert_eq!(found[2].format, Format::Sarc);
+        // Everything is wrong the other way round, except the AAMP
+        let found = audit_source(tmp.path(), Endian::Big);
+        assert_eq!(found.len(), 4);
+        assert!(found.iter().all(|m| m.found == Endian::Little));
+        assert_eq!(
+            source_endian(tmp.path(), &ModPlatform::Dual),
+            Some(Endian::Little)
+        );
+        assert_eq!(source_endian(tmp.path(), &ModPlatform::Universal), None);
+    }
+
+    #[test]
+    fn conversion() {
+        let tmp = mixed_source();
+        let romfs = tmp.path().join("01007EF00011E000/romfs");
+        assert_eq!(
+            convert(&byml(Endian::Little), Endian::Little).unwrap(),
+            None
+        );
+        assert_eq!(convert(&aamp(), Endian::Big).unwrap(), None);
+        for file in ["Map/Bad.byml", "Pack/Test.spack", "Pack/Wrong.pack"] {
+            let path = romfs.join(file);
+            let data = std::fs::read(&path).unwrap();
+            let converted = convert(&data, Endian::Little).unwrap().unwrap();
+            assert_eq!(
+                converted.starts_with(b"Yaz0"),
+                data.starts_with(b"Yaz0"),
+                "{file}"
+            );
+            std::fs::write(path, converted).unwrap();
+        }
+        assert_eq!(audit_source(tmp.path(), Endian::Little), vec![]);
+        let pack = std::fs::read(romfs.join("Pack/Test.spack")).unwrap();
+        let pack = decompress_if(&pack);
+        let sarc = Sarc::new(pack.as_ref()).unwrap();
+        let inner = sarc.get_data("Actor/Bad.sbyml").unwrap();
+        assert!(inner.starts_with(b"Yaz0"));
+        assert_eq!(
+            Byml::from_binary(decompress_if(inner)).unwrap(),
+            Byml::from_binary(byml(Endian::Big)).unwrap()
+        );
+    }
+}
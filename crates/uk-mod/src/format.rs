@@ -0,0 +1,277 @@
+//! How the mod package format has changed between versions, and what the
+//! packager does with a feature when asked to write a version which predates
+//! it. [`CAPABILITIES`] is the record of the format's evolution: a feature
+//! added to the packager which older readers cannot handle belongs in it,
+//! with [`FORMAT_VERSION`] raised to match.
+//!
+//! Readers refuse packages of a newer format than their own, so curators can
+//! target an older version for users who have not updated, at the cost of
+//! whatever that version cannot hold.
+use std::{collections::BTreeSet, fmt, path::Path};
+
+use anyhow_ext::Result;
+use uk_content::resource::ResourceData;
+
+use crate::{
+    is_doc_path, is_passthrough_path, unpack::ModReader, ModOptionGroup, ModPlatform,
+    FORMAT_VERSION,
+};
+
+/// A part of a mod package which not every format version can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Feature {
+    /// Wii U and Switch payloads in one package, under platform roots.
+    DualPlatform,
+    /// A README and docs folder bundled with the mod.
+    Docs,
+    /// Preview images for options, named by the `image` field of options in
+    /// the meta.
+    OptionImages,
+    /// Files copied as they are to the root of the deployment.
+    Passthrough,
+    /// Parameter archives with parameters of unknown types, stored as a
+    /// resource kind older readers cannot decode.
+    LenientParameters,
+    /// BYML diffs with arrays matched up by key, such as map unit links,
+    /// which older readers would merge with their markers left in.
+    KeyedByml,
+    /// Parameter diffs marking what the mod removed, which older readers
+    /// would write into game files as they are.
+    ParameterDeletions,
+}
+
+/// What the packager does with a feature the target version cannot hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// The files are left out of the package.
+    Omit,
+    /// The files are stored in a form the older version can read.
+    Downgrade,
+    /// The mod cannot be packaged for the older version at all.
+    Refuse,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capability {
+    pub feature:  Feature,
+    /// The first format version which can hold the feature.
+    pub since:    u32,
+    pub fallback: Fallback,
+    pub summary:  &'static str,
+}
+
+/// Every feature newer than the first format version, in the order they
+/// were added.
+pub const CAPABILITIES: &[Capability] = &[
+    Capability {
+        feature:  Feature::DualPlatform,
+        since:    2,
+        fallback: Fallback::Refuse,
+        summary:  "dual-platform payloads",
+    },
+    Capability {
+        feature:  Feature::Docs,
+        since:    3,
+        fallback: Fallback::Omit,
+        summary:  "bundled README and docs",
+    },
+    Capability {
+        feature:  Feature::OptionImages,
+        since:    3,
+        fallback: Fallback::Omit,
+        summary:  "option preview images",
+    },
+    Capability {
+        feature:  Feature::Passthrough,
+        since:    3,
+        fallback: Fallback::Omit,
+        summary:  "passthrough files",
+    },
+    Capability {
+        feature:  Feature::LenientParameters,
+        since:    3,
+        fallback: Fallback::Downgrade,
+        summary:  "parameter archives with unknown types",
+    },
+    Capability {
+        feature:  Feature::KeyedByml,
+        since:    4,
+        fallback: Fallback::Downgrade,
+        summary:  "BYML diffs matched up by key",
+    },
+    Capability {
+        feature:  Feature::ParameterDeletions,
+        since:    4,
+        fallback: Fallback::Downgrade,
+        summary:  "parameter diffs with deletions",
+    },
+];
+
+/// The features found by reading a package's resources.
+const RESOURCE_FEATURES: &[Feature] = &[
+    Feature::LenientParameters,
+    Feature::KeyedByml,
+    Feature::ParameterDeletions,
+];
+
+impl Feature {
+    pub fn capability(self) -> &'static Capability {
+        CAPABILITIES
+            .iter()
+            .find(|cap| cap.feature == self)
+            .expect("Every feature should be in the capability table")
+    }
+}
+
+/// Whether a format version can hold a feature.
+pub fn supports(format: u32, feature: Feature) -> bool {
+    format >= feature.capability().since
+}
+
+/// Checks that a format version is one the packager can write.
+pub fn check_target(format: u32) -> Result<()> {
+    if !(1..=FORMAT_VERSION).contains(&format) {
+        anyhow_ext::bail!(
+            "Cannot package for mod format version {format}, only versions 1 to {FORMAT_VERSION}"
+        );
+    }
+    Ok(())
+}
+
+/// Whether a serialized resource marks anything as deleted. No game file
+/// holds the marker, so its bytes can be looked for as they are.
+pub(crate) fn has_deletions(data: &[u8]) -> bool {
+    let marker = uk_content::util::DELETED.as_bytes();
+    data.windows(marker.len()).any(|window| window == marker)
+}
+
+/// Whether a file stored in a package is a resource, rather than the meta,
+/// a manifest, an image, or a file stored as it is.
+pub(crate) fn is_resource(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    !(name.ends_with(".yml")
+        || name.starts_with("thumb.")
+        || is_doc_path(path)
+        || is_passthrough_path(path))
+}
+
+impl ModReader {
+    /// The features used by the package which not every format version can
+    /// hold, found by looking through it rather than trusting its meta.
+    pub fn format_features(&self) -> Result<BTreeSet<Feature>> {
+        let mut features = BTreeSet::new();
+        if self.meta.platform == ModPlatform::Dual {
+            features.insert(Feature::DualPlatform);
+        }
+        if self
+            .meta
+            .options
+            .iter()
+            .flat_map(|group| group.options())
+            .any(|option| option.image.is_some())
+        {
+            features.insert(Feature::OptionImages);
+        }
+        for (path, _) in self.stored_files() {
+            if path.starts_with("platform/") {
+                features.insert(Feature::DualPlatform);
+            } else if is_doc_path(&path) {
+                features.insert(Feature::Docs);
+            } else if is_passthrough_path(&path) {
+                features.insert(Feature::Passthrough);
+            } else if is_resource(&path)
+                && !RESOURCE_FEATURES
+                    .iter()
+                    .all(|feature| features.contains(feature))
+            {
+                let Some(data) = self.read_stored(Path::new(&path)).transpose()? else {
+                    continue;
+                };
+                if has_deletions(&data) {
+                    features.insert(Feature::ParameterDeletions);
+                }
+                if let Ok(ResourceData::Mergeable(resource)) = minicbor_ser::from_slice(&data) {
+                    if resource.is_lenient() {
+                        features.insert(Feature::LenientParameters);
+                    }
+                    if resource.is_keyed() {
+                        features.insert(Feature::KeyedByml);
+                    }
+                }
+            }
+        }
+        Ok(features)
+    }
+
+    /// The oldest format version which can hold everything in the package.
+    pub fn required_format(&self) -> Result<u32> {
+        Ok(self
+            .format_features()?
+            .into_iter()
+            .map(|feature| feature.capability().since)
+            .max()
+            .unwrap_or(1))
+    }
+}
+
+/// Something the packager left out or stored differently to write an older
+/// format version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Downgrade {
+    pub feature: Feature,
+    /// The file or option affected.
+    pub subject: String,
+}
+
+impl fmt::Display for Downgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cap = self.feature.capability();
+        match cap.fallback {
+            Fallback::Omit => {
+                write!(
+                    f,
+                    "Left out {} ({}), which needs mod format version {}",
+                    self.subject, cap.summary, cap.since
+                )
+            }
+            Fallback::Downgrade => {
+                write!(
+                    f,
+                    "Stored {} whole, since {} need mod format version {}",
+                    self.subject, cap.summary, cap.since
+                )
+            }
+            Fallback::Refuse => {
+                write!(
+                    f,
+                    "{} needs mod format version {} for {}",
+                    self.subject, cap.since, cap.summary
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table() {
+        // Added in order, and never beyond the current version
+        assert!(CAPABILITIES
+            .windows(2)
+            .all(|caps| caps[0].since <= caps[1].since));
+        assert!(CAPABILITIES
+            .iter()
+            .all(|cap| (2..=FORMAT_VERSION).contains(&cap.since)));
+        for cap in CAPABILITIES {
+            assert!(supports(FORMAT_VERSION, cap.feature));
+            assert!(!supports(1, cap.feature));
+        }
+        assert!(supports(2, Feature::DualPlatform));
+        assert!(!supports(2, Feature::Passthrough));
+        assert!(check_target(0).is_err());
+        assert!(check_target(FORMAT_VERSION + 1).is_err());
+    }
+}
@@ -1,28 +1,121 @@
 #![allow(unstable_name_collisions)]
 use std::{
+    borrow::Cow,
     collections::BTreeSet,
     path::{Path, PathBuf},
 };
 
 use anyhow_ext::Context;
+use botw_utils::hashes::StockHashTable;
+use path_slash::PathExt;
 use serde::{Deserialize, Serialize};
 use smartstring::alias::String;
 use uk_content::{
+    canon::{canonicalize_aoc, legacy_canonical, normalize_unicode},
+    canonicalize,
     constants::Language,
     prelude::Endian,
     util::{HashSet, IndexMap},
 };
+pub mod endian;
+pub mod format;
+pub mod lint;
+pub mod objects;
 pub mod pack;
+pub mod policy;
+pub mod progress;
+pub mod rstb_preview;
+pub mod split;
 pub mod unpack;
 pub use zstd;
 
 static DICTIONARY: &[u8] = include_bytes!("../data/zsdic");
 
+/// Version of the mod package layout. Version 2 added platform payloads for
+/// dual-platform mods, version 3 bundled docs, option images, passthrough
+/// files, and leniently read parameter archives, and version 4 diffed BYML
+/// by key and recorded deleted parameters. See [`format::CAPABILITIES`].
+pub const FORMAT_VERSION: u32 = 4;
+/// Name of the documentation file bundled with a mod.
+pub const README: &str = "README.md";
+/// Folder holding extra documentation and images bundled with a mod.
+pub const DOCS_DIR: &str = "docs";
+
+/// Whether a path stored in a mod package is bundled documentation rather
+/// than a resource.
+pub fn is_doc_path(path: &str) -> bool {
+    path == README
+        || path
+            .strip_prefix(DOCS_DIR)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Folder holding files which are copied as they are to the root of the
+/// deployment, such as graphic pack extras, rather than merged as resources.
+pub const PASSTHROUGH_DIR: &str = "passthrough";
+
+/// Whether a path stored in a mod package is a passthrough file.
+pub fn is_passthrough_path(path: &str) -> bool {
+    path.strip_prefix(PASSTHROUGH_DIR)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether a passthrough file, relative to the deployment root, would land in
+/// the folders which hold game files on either platform. Such files are never
+/// deployed.
+pub fn is_reserved_passthrough(file: &str) -> bool {
+    let first = file.split('/').next().unwrap_or_default();
+    [Endian::Big, Endian::Little]
+        .into_iter()
+        .flat_map(|endian| {
+            let (content, aoc) = uk_content::platform_prefixes(endian);
+            [content, aoc]
+        })
+        .any(|prefix| prefix.split('/').next() == Some(first))
+}
+
+/// Checks a file against a stock hash table by its canonical path. Tables
+/// made before `sarc` and `stera` extensions were kept list uncompressed
+/// SARCs under their [legacy path](legacy_canonical), so that is checked
+/// when the table has it.
+pub fn is_file_modded(table: &StockHashTable, canon: &str, data: &[u8], flag_new: bool) -> bool {
+    match legacy_canonical(canon).filter(|legacy| !table.is_file_new(legacy)) {
+        Some(legacy) => table.is_file_modded(legacy, data, flag_new),
+        None => table.is_file_modded(canon, data, flag_new),
+    }
+}
+
+/// Folder holding the platform-specific files of a dual-platform mod, which
+/// take priority over the shared copies for the matching platform.
+pub const fn platform_root(endian: Endian) -> &'static str {
+    match endian {
+        Endian::Big => "platform/wiiu",
+        Endian::Little => "platform/nx",
+    }
+}
+
+/// Reads manifest entries normalized to NFC, so that manifests from mods
+/// packed on macOS, which may list decomposed names, compare equal to others.
+fn deserialize_files<'de, D>(deserializer: D) -> std::result::Result<BTreeSet<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(BTreeSet::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|file| {
+            match normalize_unicode(&file) {
+                Cow::Borrowed(_) => file,
+                Cow::Owned(normalized) => normalized.into(),
+            }
+        })
+        .collect())
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Manifest {
-    #[serde(rename = "content")]
+    #[serde(rename = "content", deserialize_with = "deserialize_files")]
     pub content_files: BTreeSet<String>,
-    #[serde(rename = "aoc")]
+    #[serde(rename = "aoc", deserialize_with = "deserialize_files")]
     pub aoc_files:     BTreeSet<String>,
 }
 
@@ -37,11 +130,11 @@ impl Manifest {
     pub fn resources(&self) -> impl Iterator<Item = String> + '_ {
         self.content_files
             .iter()
-            .map(|s| s.replace(".s", ".").into())
+            .map(|file| canonicalize(file.as_str()))
             .chain(
                 self.aoc_files
                     .iter()
-                    .map(|s| ["Aoc/0010/", &s.replace(".s", ".")].join("").into()),
+                    .map(|file| canonicalize_aoc(file.as_str())),
             )
     }
 
@@ -67,6 +160,9 @@ pub struct ModOption {
     pub description: String,
     pub path: PathBuf,
     pub requires: Vec<PathBuf>,
+    /// Preview image, relative to the option's folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<PathBuf>,
 }
 
 impl ModOption {
@@ -74,6 +170,17 @@ impl ModOption {
     pub fn manifest_path(&self) -> PathBuf {
         Path::new("options").join(&self.path).join("manifest.yml")
     }
+
+    /// Where the option's preview image is stored in the mod, if it has one.
+    pub fn image_path(&self) -> Option<std::string::String> {
+        self.image.as_ref().map(|image| {
+            Path::new("options")
+                .join(&self.path)
+                .join(image)
+                .to_slash_lossy()
+                .into_owned()
+        })
+    }
 }
 
 #[enum_dispatch::enum_dispatch(OptionGroup)]
@@ -222,6 +329,18 @@ pub static CATEGORIES: &[&str] = &[
 pub enum ModPlatform {
     Specific(Endian),
     Universal,
+    /// Carries separate payloads for Wii U and Switch in one package.
+    Dual,
+}
+
+impl ModPlatform {
+    #[inline]
+    pub fn supports(&self, endian: Endian) -> bool {
+        match self {
+            ModPlatform::Specific(e) => *e == endian,
+            ModPlatform::Universal | ModPlatform::Dual => true,
+        }
+    }
 }
 
 impl std::fmt::Display for ModPlatform {
@@ -230,6 +349,7 @@ impl std::fmt::Display for ModPlatform {
             ModPlatform::Specific(Endian::Big) => "Wii U".fmt(f),
             ModPlatform::Specific(Endian::Little) => "Switch".fmt(f),
             ModPlatform::Universal => "any platform".fmt(f),
+            ModPlatform::Dual => "Wii U, Switch".fmt(f),
         }
     }
 }
@@ -239,11 +359,18 @@ fn default_api() -> String {
     env!("CARGO_PKG_VERSION").into()
 }
 
+#[inline(always)]
+fn default_format() -> u32 {
+    1
+}
+
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Meta {
     #[serde(default = "default_api")]
     pub api: String,
+    #[serde(default = "default_format")]
+    pub format: u32,
     pub name: String,
     pub version: String,
     pub author: String,
@@ -299,12 +426,30 @@ mod tests {
         dbg!(Meta::from_mod("test/wiiu.zip").unwrap());
     }
 
+    #[test]
+    fn stock_hash_keys() {
+        let table = StockHashTable::new(&botw_utils::hashes::Platform::WiiU);
+        let canon = canonicalize("Message/Msg_USen.product.ssarc");
+        assert!(is_file_modded(&table, &canon, b"SARC", false));
+        assert!(!is_file_modded(&table, "Pack/Missing.sarc", b"SARC", false));
+    }
+
+    #[test]
+    fn normalized_manifest() {
+        let nfd: Manifest =
+            serde_yaml::from_str("content:\n  - Model/Cafe\u{301}.sbfres\naoc: []\n").unwrap();
+        let mut nfc = Manifest::default();
+        nfc.content_files.insert("Model/Caf\u{e9}.sbfres".into());
+        assert_eq!(nfd, nfc);
+    }
+
     #[test]
     fn create_meta() {
         println!(
             "{}",
             serde_yaml::to_string(&Meta {
                 api: env!("CARGO_PKG_VERSION").into(),
+                format: FORMAT_VERSION,
                 name: "Test Mod".into(),
                 description: "A sample UKMM mod".into(),
                 category: "Other".into(),
@@ -0,0 +1,621 @@
+//! Checks a packaged mod for problems before it is published. Each finding
+//! has a short code so authors can look up or filter specific checks.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+};
+
+use lenient_semver::Version;
+use uk_content::{
+    canon::{canonicalize_aoc, is_canonical},
+    canonicalize,
+    prelude::Endian,
+    resource::ResourceData,
+};
+
+use crate::{
+    endian, is_doc_path, is_passthrough_path, is_reserved_passthrough, platform_root,
+    unpack::ModReader, Manifest, ModOptionGroup, ModPlatform, PASSTHROUGH_DIR,
+};
+
+/// Largest thumbnail, in bytes, which does not get a warning.
+pub const MAX_THUMBNAIL: u64 = 2 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub code:     &'static str,
+    pub message:  String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LintReport {
+    pub findings: Vec<Finding>,
+    /// Number of files stored in the mod.
+    pub files:    usize,
+    /// Total size of the stored files as extracted from the archive.
+    pub size:     u64,
+}
+
+impl LintReport {
+    fn push(&mut self, severity: Severity, code: &'static str, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            code,
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, code: &'static str, message: impl Into<String>) {
+        self.push(Severity::Error, code, message)
+    }
+
+    fn warn(&mut self, code: &'static str, message: impl Into<String>) {
+        self.push(Severity::Warning, code, message)
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Whether any finding has the given code.
+    pub fn has(&self, code: &str) -> bool {
+        self.findings.iter().any(|f| f.code == code)
+    }
+}
+
+impl fmt::Display for LintReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut findings: Vec<_> = self.findings.iter().collect();
+        findings.sort_by_key(|f| f.severity);
+        for finding in findings {
+            writeln!(f, "{finding}")?;
+        }
+        write!(
+            f,
+            "{} error(s), {} warning(s), {} files, {:.2} MB uncompressed",
+            self.errors().count(),
+            self.warnings().count(),
+            self.files,
+            self.size as f64 / 1024.0 / 1024.0
+        )
+    }
+}
+
+/// Whether a stored file is package metadata or a passthrough file rather
+/// than a resource.
+fn is_meta_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    name.ends_with(".yml")
+        || name.starts_with("thumb")
+        || is_doc_path(path)
+        || is_passthrough_path(path)
+}
+
+/// Where a resource listed in a manifest may be stored: at the root of the
+/// given folder, or in either platform payload.
+fn candidates(dir: &str, canon: &str) -> [String; 3] {
+    let join = |root: &str| {
+        [root, dir, canon]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+    [
+        join(""),
+        join(platform_root(Endian::Big)),
+        join(platform_root(Endian::Little)),
+    ]
+}
+
+fn check_meta(reader: &ModReader, report: &mut LintReport) {
+    let meta = &reader.meta;
+    if meta.name.trim().is_empty() {
+        report.error("meta-name", "The mod has no name");
+    }
+    if Version::parse(&meta.version).is_err() {
+        report.error(
+            "meta-version",
+            format!(
+                "The version \"{}\" is not a valid version number",
+                meta.version
+            ),
+        );
+    }
+    for (code, field, value) in [
+        ("meta-author", "author", &meta.author),
+        ("meta-category", "category", &meta.category),
+        ("meta-description", "description", &meta.description),
+    ] {
+        if value.trim().is_empty() {
+            report.warn(code, format!("The mod has no {field}"));
+        }
+    }
+}
+
+/// The platform a stored file is for, from its payload folder or else the
+/// platform the mod declares.
+fn stored_endian(reader: &ModReader, path: &str) -> Option<Endian> {
+    [Endian::Big, Endian::Little]
+        .into_iter()
+        .find(|endian| path.starts_with(platform_root(*endian)))
+        .or(match reader.meta.platform {
+            ModPlatform::Specific(endian) => Some(endian),
+            _ => None,
+        })
+}
+
+/// Checks that every file in a manifest is stored in the mod under `dir` and
+/// decodes as a resource, and that raw resources match their platform.
+fn check_manifest(
+    reader: &ModReader,
+    manifest: &Manifest,
+    dir: &str,
+    stored: &BTreeMap<String, u64>,
+    report: &mut LintReport,
+) {
+    let files = manifest
+        .content_files
+        .iter()
+        .map(|f| (f, canonicalize(f.as_str())))
+        .chain(
+            manifest
+                .aoc_files
+                .iter()
+                .map(|f| (f, canonicalize_aoc(f.as_str()))),
+        );
+    for (file, canon) in files {
+        let paths: Vec<_> = candidates(dir, &canon)
+            .into_iter()
+            .filter(|path| stored.contains_key(path))
+            .collect();
+        if paths.is_empty() {
+            report.error(
+                "missing-file",
+                format!("{file} is in the manifest but is not stored in the mod"),
+            );
+            continue;
+        }
+        for path in paths {
+            let decoded = reader
+                .read_stored(Path::new(&path))
+                .unwrap_or_else(|| Err(anyhow_ext::anyhow!("File could not be read")))
+                .and_then(|data| {
+                    minicbor_ser::from_slice::<ResourceData>(&data)
+                        .map_err(|e| anyhow_ext::anyhow!("{e:?}"))
+                });
+            match decoded {
+                Err(e) => report.error("bad-resource", format!("{path} does not decode: {e}")),
+                Ok(ResourceData::Binary(data)) => {
+                    if let Some(endian) = stored_endian(reader, &path) {
+                        let mut mismatches = vec![];
+                        endian::audit_data(&path, &data, endian, &mut mismatches);
+                        for mismatch in mismatches {
+                            report.warn("endian-mismatch", mismatch.to_string());
+                        }
+                    }
+                }
+                Ok(ResourceData::Mergeable(resource)) if resource.is_lenient() => {
+                    report.warn(
+                        "lenient-aamp",
+                        format!(
+                            "{path} has parameters of unknown types, which are kept as they are \
+                             and merged whole"
+                        ),
+                    )
+                }
+                Ok(_) => (),
+            }
+        }
+    }
+    for file in &manifest.content_files {
+        let canon = canonicalize(file.as_str());
+        if canon.starts_with("Aoc/")
+            || canon
+                .split('/')
+                .next_back()
+                .is_some_and(|name| name.starts_with("Aoc"))
+        {
+            report.warn(
+                "aoc-in-content",
+                format!("{file} looks like a DLC file but is listed as base game content"),
+            );
+        }
+    }
+}
+
+fn check_options(reader: &ModReader, stored: &BTreeMap<String, u64>, report: &mut LintReport) {
+    let options: Vec<_> = reader
+        .meta
+        .options
+        .iter()
+        .flat_map(|group| group.options().iter())
+        .collect();
+    let paths: BTreeSet<_> = options.iter().map(|opt| opt.path.as_path()).collect();
+    for opt in &options {
+        let dir = format!("options/{}", opt.path.to_string_lossy().replace('\\', "/"));
+        let manifest_path = format!("{dir}/manifest.yml");
+        let Some(data) = reader.read_raw(&manifest_path) else {
+            report.error(
+                "option-missing",
+                format!("Option {} has no folder at {dir}", opt.name),
+            );
+            continue;
+        };
+        match serde_yaml::from_slice::<Manifest>(&data) {
+            Ok(manifest) => check_manifest(reader, &manifest, &dir, stored, report),
+            Err(e) => {
+                report.error(
+                    "option-manifest",
+                    format!("The manifest for option {} does not parse: {e}", opt.name),
+                );
+            }
+        }
+        for req in &opt.requires {
+            if !paths.contains(req.as_path()) {
+                report.error(
+                    "option-requires",
+                    format!(
+                        "Option {} requires {}, which is not an option",
+                        opt.name,
+                        req.display()
+                    ),
+                );
+            }
+        }
+    }
+    let orphans: BTreeSet<_> = stored
+        .keys()
+        .filter_map(|path| path.strip_prefix("options/")?.split('/').next())
+        .filter(|dir| !paths.contains(Path::new(dir)))
+        .collect();
+    for dir in orphans {
+        report.warn(
+            "option-orphan",
+            format!("options/{dir} does not belong to any option and will never be used"),
+        );
+    }
+}
+
+fn check_stored(stored: &BTreeMap<String, u64>, manifest: &Manifest, report: &mut LintReport) {
+    let resources: BTreeSet<String> = manifest.resources().map(|r| r.to_string()).collect();
+    for (path, size) in stored {
+        let name = path.rsplit('/').next().unwrap_or_default();
+        if name.starts_with("thumb") && *size > MAX_THUMBNAIL {
+            report.warn(
+                "thumbnail-size",
+                format!(
+                    "{path} is {:.1} MB, which is large for a thumbnail",
+                    *size as f64 / 1024.0 / 1024.0
+                ),
+            );
+        }
+        if path
+            .strip_prefix(PASSTHROUGH_DIR)
+            .and_then(|file| file.strip_prefix('/'))
+            .is_some_and(is_reserved_passthrough)
+        {
+            report.warn(
+                "passthrough-reserved",
+                format!("{path} would be deployed over game files and will never be used"),
+            );
+        }
+        if is_meta_file(path) {
+            continue;
+        }
+        let resource = path
+            .strip_prefix("platform/")
+            .and_then(|p| p.split_once('/'))
+            .map(|(_, p)| p)
+            .unwrap_or(path);
+        let resource = match resource.strip_prefix("options/") {
+            Some(rest) => rest.split_once('/').map(|(_, p)| p).unwrap_or_default(),
+            None => resource,
+        };
+        if !is_canonical(resource) {
+            report.error(
+                "non-canonical",
+                format!("{path} is not stored under its canonical path"),
+            );
+        } else if !path.starts_with("options/") && !resources.contains(resource) {
+            report.warn(
+                "unlisted-file",
+                format!("{path} is not in the manifest and will never be used"),
+            );
+        }
+    }
+}
+
+/// Checks a packaged or extracted mod. Only problems with the mod itself are
+/// reported as findings; an error is returned if it cannot be read at all.
+pub fn lint(path: impl AsRef<Path>) -> anyhow_ext::Result<LintReport> {
+    let reader = ModReader::open_peek(path.as_ref(), vec![])?;
+    let mut report = LintReport::default();
+    let stored: BTreeMap<String, u64> = reader.stored_files().into_iter().collect();
+    report.files = stored.len();
+    report.size = stored.values().sum();
+    check_meta(&reader, &mut report);
+    check_manifest(&reader, &reader.manifest, "", &stored, &mut report);
+    check_options(&reader, &stored, &mut report);
+    check_stored(&stored, &reader.manifest, &mut report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{Meta, ModOption, ModPlatform, MultipleOptionGroup, OptionGroup, FORMAT_VERSION};
+
+    const RESOURCE: &str = "Actor/Pack/Test.sbactorpack";
+    const CANON: &str = "Actor/Pack/Test.bactorpack";
+
+    fn meta() -> Meta {
+        Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: FORMAT_VERSION,
+            name: "Test Mod".into(),
+            version: "1.0.0".into(),
+            author: "Nobody".into(),
+            category: "Other".into(),
+            description: "A test mod".into(),
+            platform: ModPlatform::Specific(Endian::Big),
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        }
+    }
+
+    fn resource() -> Vec<u8> {
+        minicbor_ser::to_vec(&ResourceData::Binary(vec![1, 2, 3])).unwrap()
+    }
+
+    fn manifest(files: &[&str]) -> Manifest {
+        Manifest {
+            content_files: files.iter().map(|f| (*f).into()).collect(),
+            aoc_files:     Default::default(),
+        }
+    }
+
+    /// Writes an extracted mod to a temporary folder.
+    fn fixture(meta: &Meta, manifest: &Manifest, files: &[(&str, Vec<u8>)]) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let write = |path: &str, data: &[u8]| {
+            let path = tmp.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        };
+        write("meta.yml", serde_yaml::to_string(meta).unwrap().as_bytes());
+        write(
+            "manifest.yml",
+            serde_yaml::to_string(manifest).unwrap().as_bytes(),
+        );
+        for (path, data) in files {
+            write(path, data);
+        }
+        tmp
+    }
+
+    fn lint_fixture(meta: &Meta, manifest: &Manifest, files: &[(&str, Vec<u8>)]) -> LintReport {
+        let tmp = fixture(meta, manifest, files);
+        lint(tmp.path()).unwrap()
+    }
+
+    #[test]
+    fn clean() {
+        let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[(CANON, resource())]);
+        assert_eq!(report.findings, vec![]);
+        assert_eq!(report.files, 3);
+        assert!(!report.has_errors());
+
+        let tmp = tempfile::tempdir().unwrap();
+        let zip = crate::pack::pack_resources(
+            tmp.path().join("test.zip"),
+            &meta(),
+            &manifest(&[RESOURCE]),
+            [(CANON.into(), ResourceData::Binary(vec![1, 2, 3]))],
+        )
+        .unwrap();
+        assert_eq!(lint(zip).unwrap().findings, vec![]);
+    }
+
+    #[test]
+    fn meta_fields() {
+        let mut meta = meta();
+        meta.name = "  ".into();
+        meta.version = "banana".into();
+        meta.author = "".into();
+        let report = lint_fixture(&meta, &manifest(&[RESOURCE]), &[(CANON, resource())]);
+        assert!(report.has("meta-name"));
+        assert!(report.has("meta-version"));
+        assert!(report.has("meta-author"));
+        assert!(!report.has("meta-category"));
+        assert_eq!(report.errors().count(), 2);
+        assert_eq!(report.warnings().count(), 1);
+    }
+
+    #[test]
+    fn missing_and_unlisted() {
+        let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[(
+            "Actor/Pack/Other.bactorpack",
+            resource(),
+        )]);
+        assert!(report.has("missing-file"));
+        assert!(report.has("unlisted-file"));
+    }
+
+    #[test]
+    fn bad_resource() {
+        let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[(
+            CANON,
+            b"not a resource".to_vec(),
+        )]);
+        assert!(report.has("bad-resource"));
+        assert!(!report.has("missing-file"));
+    }
+
+    #[test]
+    fn lenient_resource() {
+        let lenient = uk_content::util::lenient::LenientAamp {
+            pio: roead::aamp::ParameterIO::new(),
+            opaque: [(vec![1, 2, 3], 0x40)].into(),
+            format_version: 2,
+        };
+        let data = minicbor_ser::to_vec(&ResourceData::Mergeable(
+            uk_content::resource::MergeableResource::LenientAamp(Box::new(lenient)),
+        ))
+        .unwrap();
+        let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[(CANON, data)]);
+        assert!(report.has("lenient-aamp"));
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn endian_mismatch() {
+        let byml = |endian: Endian| {
+            let data = roead::byml::Byml::Map(Default::default()).to_binary(endian.into());
+            minicbor_ser::to_vec(&ResourceData::Binary(data)).unwrap()
+        };
+        for (endian, mismatched) in [(Endian::Big, false), (Endian::Little, true)] {
+            let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[(CANON, byml(endian))]);
+            assert_eq!(report.has("endian-mismatch"), mismatched);
+        }
+        // Payloads are checked against their own platform
+        let mut dual = meta();
+        dual.platform = ModPlatform::Dual;
+        let (nx, wiiu) = (
+            format!("platform/nx/{CANON}"),
+            format!("platform/wiiu/{CANON}"),
+        );
+        let report = lint_fixture(&dual, &manifest(&[RESOURCE]), &[
+            (nx.as_str(), byml(Endian::Little)),
+            (wiiu.as_str(), byml(Endian::Little)),
+        ]);
+        assert_eq!(report.warnings().count(), 1);
+        assert!(report.has("endian-mismatch"));
+    }
+
+    #[test]
+    fn non_canonical() {
+        let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[
+            (CANON, resource()),
+            ("content/Actor/Pack/Test.sbactorpack", resource()),
+        ]);
+        assert!(report.has("non-canonical"));
+    }
+
+    #[test]
+    fn aoc_in_content() {
+        let report = lint_fixture(&meta(), &manifest(&["Pack/AocMainField.pack"]), &[(
+            "Pack/AocMainField.pack",
+            resource(),
+        )]);
+        assert!(report.has("aoc-in-content"));
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn options() {
+        let option = |path: &str, requires: &[&str]| {
+            ModOption {
+                name: path.into(),
+                description: Default::default(),
+                path: path.into(),
+                requires: requires.iter().map(PathBuf::from).collect(),
+                image: None,
+            }
+        };
+        let mut meta = meta();
+        meta.options = vec![OptionGroup::Multiple(MultipleOptionGroup {
+            name: "Options".into(),
+            options: vec![
+                option("good", &["missing"]),
+                option("absent", &[]),
+                option("broken", &[]),
+            ],
+            ..Default::default()
+        })];
+        let report = lint_fixture(&meta, &manifest(&[RESOURCE]), &[
+            (CANON, resource()),
+            (
+                "options/good/manifest.yml",
+                serde_yaml::to_string(&manifest(&[RESOURCE]))
+                    .unwrap()
+                    .into_bytes(),
+            ),
+            ("options/good/Actor/Pack/Test.bactorpack", resource()),
+            ("options/broken/manifest.yml", b"content: 5".to_vec()),
+            ("options/stray/Actor/Pack/Test.bactorpack", resource()),
+        ]);
+        assert!(report.has("option-requires"));
+        assert!(report.has("option-missing"));
+        assert!(report.has("option-manifest"));
+        assert!(report.has("option-orphan"));
+        assert!(!report.has("missing-file"));
+        assert_eq!(report.errors().count(), 3);
+    }
+
+    #[test]
+    fn thumbnail() {
+        let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[
+            (CANON, resource()),
+            ("thumb.png", vec![0; MAX_THUMBNAIL as usize + 1]),
+        ]);
+        assert!(report.has("thumbnail-size"));
+        assert!(!report.has("unlisted-file"));
+        assert!(report.size > MAX_THUMBNAIL);
+        let text = report.to_string();
+        assert!(text.contains("warning[thumbnail-size]"));
+        assert!(text.ends_with("0 error(s), 1 warning(s), 4 files, 2.00 MB uncompressed"));
+    }
+
+    #[test]
+    fn passthrough() {
+        let report = lint_fixture(&meta(), &manifest(&[RESOURCE]), &[
+            (CANON, resource()),
+            ("passthrough/rules.txt", b"[Definition]".to_vec()),
+            ("passthrough/content/Actor/Pack/Test.sbactorpack", resource()),
+        ]);
+        assert!(report.has("passthrough-reserved"));
+        assert!(!report.has("unlisted-file"));
+        assert!(!report.has_errors());
+    }
+}
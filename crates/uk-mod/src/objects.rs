@@ -0,0 +1,433 @@
+//! Shared storage for the resources of installed mods, so files which several
+//! mods have in common are kept on disk only once.
+//!
+//! A mod stored this way is a folder holding its meta, manifests, docs and
+//! other small files as they are, plus an index of its resources. Each
+//! resource is kept compressed, just as in a mod package, in an object store
+//! where it is named for a 128-bit xxHash of the compressed data, wide enough
+//! that two different resources will not share a name. The store a mod
+//! uses is the nearest `objects` folder above it which holds the store
+//! marker, so a stored mod still finds its objects after it is moved, for
+//! instance to the trash, but never a folder which is only named the same.
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use jwalk::WalkDir;
+use path_slash::PathExt;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use uk_content::util::HashSet;
+
+use crate::format::is_resource;
+
+/// Name of the object store folder.
+pub const OBJECTS_DIR: &str = "objects";
+/// Name of the file in a stored mod which lists its resources.
+pub const OBJECT_INDEX: &str = "objects.yml";
+/// Name of the file which marks a folder as an object store.
+pub const STORE_MARKER: &str = ".ukmm-store";
+
+/// Tells apart the temporary files of objects written at the same time.
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// The name of an object, a hash of its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub u128);
+
+impl ObjectId {
+    pub fn of(data: &[u8]) -> Self {
+        Self(xxhash_rust::xxh3::xxh3_128(data))
+    }
+}
+
+impl std::fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        u128::from_str_radix(s, 16).map(Self)
+    }
+}
+
+/// The resources of a stored mod, by slash path in the mod as it would be in
+/// its package.
+#[serde_as]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ObjectIndex(
+    #[serde_as(as = "BTreeMap<_, DisplayFromStr>")] pub BTreeMap<String, ObjectId>,
+);
+
+impl ObjectIndex {
+    /// Reads the index of a stored mod, if the mod at `dir` is stored with
+    /// its resources in an object store.
+    pub fn read(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(OBJECT_INDEX);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let index = serde_yaml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Failed to parse object index {}", path.display()))?;
+        Ok(Some(index))
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        fs::write(dir.join(OBJECT_INDEX), serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get(&self, path: &str) -> Option<ObjectId> {
+        self.0.get(path).copied()
+    }
+}
+
+/// What storing a mod in an object store took.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StoreStats {
+    /// How many resources the mod has.
+    pub files:     usize,
+    /// The compressed size of all of them.
+    pub bytes:     u64,
+    /// The compressed size of those which were not in the store yet.
+    pub new_bytes: u64,
+}
+
+impl StoreStats {
+    /// The compressed size of those which were in the store already.
+    pub fn shared(&self) -> u64 {
+        self.bytes - self.new_bytes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectStore {
+    root: PathBuf,
+}
+
+impl ObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The store for a mod stored at `dir`, the nearest marked `objects`
+    /// folder above it.
+    pub fn find(dir: &Path) -> Option<Self> {
+        dir.ancestors()
+            .skip(1)
+            .map(|parent| parent.join(OBJECTS_DIR))
+            .find(|root| root.join(STORE_MARKER).is_file())
+            .map(Self::new)
+    }
+
+    /// Creates the store folder and its marker if they are missing.
+    pub fn init(&self) -> Result<()> {
+        let marker = self.root.join(STORE_MARKER);
+        if !marker.is_file() {
+            fs::create_dir_all(&self.root)?;
+            fs::write(marker, "")?;
+        }
+        Ok(())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where an object is kept, in a folder for the first byte of its name
+    /// so no one folder gets too large.
+    pub fn path(&self, id: ObjectId) -> PathBuf {
+        let name = id.to_string();
+        self.root.join(&name[..2]).join(name)
+    }
+
+    pub fn get(&self, id: ObjectId) -> Result<Vec<u8>> {
+        fs::read(self.path(id)).with_context(|| format!("Missing object {id} from the store"))
+    }
+
+    /// Adds compressed data to the store unless it is there already. Returns
+    /// its name and whether it was new.
+    pub fn insert(&self, data: &[u8]) -> Result<(ObjectId, bool)> {
+        let id = ObjectId::of(data);
+        let path = self.path(id);
+        if path.exists() {
+            return Ok((id, false));
+        }
+        path.parent().map(fs::create_dir_all).transpose()?;
+        // Written in full before it is named, so an object is never partial
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            PENDING.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &path)?;
+        Ok((id, true))
+    }
+
+    /// Deletes every object not in `live`. Returns how many were deleted and
+    /// how many bytes that freed.
+    pub fn collect_garbage(&self, live: &HashSet<ObjectId>) -> Result<(usize, u64)> {
+        if !self.root.is_dir() {
+            return Ok((0, 0));
+        }
+        let mut count = 0;
+        let mut freed = 0;
+        for folder in fs::read_dir(&self.root)? {
+            let folder = folder?.path();
+            if !folder.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&folder)? {
+                let entry = entry?;
+                let Some(id) = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| ObjectId::from_str(name).ok())
+                else {
+                    continue;
+                };
+                if !live.contains(&id) {
+                    let size = entry.metadata().map(|m| m.len()).unwrap_or_default();
+                    fs::remove_file(entry.path())?;
+                    count += 1;
+                    freed += size;
+                }
+            }
+            // Only succeeds once the folder is empty
+            let _ = std::fs::remove_dir(&folder);
+        }
+        log::debug!("Removed {count} unused objects, freeing {freed} bytes");
+        Ok((count, freed))
+    }
+}
+
+/// Every object used by the stored mods at `dirs`. Fails if any index cannot
+/// be read, as its objects would otherwise be taken for garbage.
+pub fn live_objects<'a>(dirs: impl IntoIterator<Item = &'a Path>) -> Result<HashSet<ObjectId>> {
+    let mut live = HashSet::default();
+    for dir in dirs {
+        if let Some(index) = ObjectIndex::read(dir)? {
+            live.extend(index.0.into_values());
+        }
+    }
+    Ok(live)
+}
+
+/// Stores a mod, packaged or unpacked, as a folder at `dest` with its
+/// resources in `store`. Resources of unpacked mods are compressed as the
+/// packager would.
+pub fn store_mod(source: &Path, dest: &Path, store: &ObjectStore) -> Result<StoreStats> {
+    if dest.exists() {
+        anyhow_ext::bail!("{} is already stored", dest.display());
+    }
+    store.init()?;
+    let result = if source.is_file() {
+        store_archive(source, dest, store)
+    } else {
+        store_folder(source, dest, store)
+    };
+    if result.is_err() && dest.exists() {
+        let _ = std::fs::remove_dir_all(dest);
+    }
+    result
+}
+
+fn store_archive(source: &Path, dest: &Path, store: &ObjectStore) -> Result<StoreStats> {
+    let mut zip = zip::ZipArchive::new(BufReader::new(fs::File::open(source)?))
+        .context("Failed to open mod ZIP")?;
+    let mut index = ObjectIndex::default();
+    let mut stats = StoreStats::default();
+    fs::create_dir_all(dest)?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let Some(name) = file
+            .enclosed_name()
+            .map(|name| name.to_slash_lossy().into_owned())
+        else {
+            anyhow_ext::bail!("Mod has a file with an unsafe name: {}", file.name());
+        };
+        if file.is_dir() {
+            continue;
+        }
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        if is_resource(&name) {
+            let (id, new) = store.insert(&data)?;
+            stats.files += 1;
+            stats.bytes += data.len() as u64;
+            if new {
+                stats.new_bytes += data.len() as u64;
+            }
+            index.0.insert(name, id);
+        } else {
+            let out = dest.join(&name);
+            out.parent().map(fs::create_dir_all).transpose()?;
+            fs::write(out, data)?;
+        }
+    }
+    index.write(dest)?;
+    Ok(stats)
+}
+
+fn store_folder(source: &Path, dest: &Path, store: &ObjectStore) -> Result<StoreStats> {
+    let files: Vec<(String, PathBuf)> = WalkDir::new(source)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|f| f.file_type().is_file())
+        .filter_map(|f| {
+            let path = f.path();
+            let name = path
+                .strip_prefix(source)
+                .ok()?
+                .to_slash_lossy()
+                .into_owned();
+            Some((name, path))
+        })
+        .collect();
+    fs::create_dir_all(dest)?;
+    let stored = files
+        .into_par_iter()
+        .map_init(
+            || zstd::bulk::Compressor::with_dictionary(8, crate::DICTIONARY),
+            |compressor, (name, path)| -> Result<Option<(String, ObjectId, u64, bool)>> {
+                let data = fs::read(&path)?;
+                if !is_resource(&name) {
+                    let out = dest.join(&name);
+                    out.parent().map(fs::create_dir_all).transpose()?;
+                    fs::write(out, data)?;
+                    return Ok(None);
+                }
+                let data = compressor
+                    .as_mut()
+                    .map_err(|e| anyhow_ext::anyhow!("{e}"))?
+                    .compress(&data)
+                    .with_context(|| format!("Failed to compress {name}"))?;
+                let (id, new) = store.insert(&data)?;
+                Ok(Some((name, id, data.len() as u64, new)))
+            },
+        )
+        .collect::<Result<Vec<_>>>()?;
+    let mut index = ObjectIndex::default();
+    let mut stats = StoreStats::default();
+    for (name, id, size, new) in stored.into_iter().flatten() {
+        stats.files += 1;
+        stats.bytes += size;
+        if new {
+            stats.new_bytes += size;
+        }
+        index.0.insert(name, id);
+    }
+    index.write(dest)?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use uk_content::resource::ResourceData;
+    use uk_reader::ResourceLoader;
+
+    use super::*;
+    use crate::{unpack::ModReader, Manifest, Meta};
+
+    const RESOURCE: &str = "Actor/Pack/Test.sbactorpack";
+    const CANON: &str = "Actor/Pack/Test.bactorpack";
+
+    fn meta(name: &str) -> Meta {
+        Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: crate::FORMAT_VERSION,
+            platform: crate::ModPlatform::Universal,
+            name: name.into(),
+            version: "1.0.0".into(),
+            category: "Other".into(),
+            author: "Test".into(),
+            description: "A test mod".into(),
+            masters: Default::default(),
+            url: None,
+            options: vec![],
+        }
+    }
+
+    fn package(dir: &Path, name: &str, data: &[u8]) -> PathBuf {
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert(RESOURCE.into());
+        crate::pack::pack_resources(dir.join(name), &meta(name), &manifest, [(
+            CANON.into(),
+            ResourceData::Binary(data.to_vec()),
+        )])
+        .unwrap()
+    }
+
+    #[test]
+    fn object_names() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = ObjectStore::new(tmp.path().join(OBJECTS_DIR));
+        let (id, new) = store.insert(b"Some data").unwrap();
+        assert!(new);
+        assert_eq!(id.to_string().len(), 32);
+        assert_eq!(id.to_string().parse::<ObjectId>().unwrap(), id);
+        assert_eq!(store.insert(b"Some data").unwrap(), (id, false));
+        let (other, new) = store.insert(b"Other data").unwrap();
+        assert!(new && other != id);
+        assert_eq!(store.get(other).unwrap(), b"Other data");
+    }
+
+    #[test]
+    fn shared_objects() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tmp = tmp.path();
+        let store = ObjectStore::new(tmp.join(OBJECTS_DIR));
+        let mods = tmp.join("mods");
+        let first = package(tmp, "First", b"Shared");
+        let second = package(tmp, "Second", b"Shared");
+
+        let stats = store_mod(&first, &mods.join("First"), &store).unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.shared(), 0);
+        let stats = store_mod(&second, &mods.join("Second"), &store).unwrap();
+        assert_eq!(stats.shared(), stats.bytes);
+        assert!(stats.bytes > 0);
+
+        // Read back just as from the package
+        let packaged = ModReader::open(&first, vec![]).unwrap();
+        let stored = ModReader::open(mods.join("First"), vec![]).unwrap();
+        assert_eq!(ObjectStore::find(&stored.path), Some(store.clone()));
+        // A nearer folder which is only named like a store is passed over
+        fs::create_dir_all(mods.join(OBJECTS_DIR)).unwrap();
+        assert_eq!(ObjectStore::find(&stored.path), Some(store.clone()));
+        assert_eq!(
+            stored.get_data(Path::new(RESOURCE)).unwrap(),
+            packaged.get_data(Path::new(RESOURCE)).unwrap()
+        );
+        assert_eq!(
+            stored.content_identity().unwrap(),
+            packaged.content_identity().unwrap()
+        );
+        assert!(stored.stored_files().iter().any(|(file, _)| file == CANON));
+
+        // The shared object is kept while either mod has it
+        let live = live_objects([mods.join("Second").as_path()]).unwrap();
+        assert_eq!(store.collect_garbage(&live).unwrap().0, 0);
+        fs::remove_dir_all(mods.join("First")).unwrap();
+        fs::remove_dir_all(mods.join("Second")).unwrap();
+        let live = live_objects([mods.join("Second").as_path()]).unwrap();
+        assert_eq!(store.collect_garbage(&live).unwrap().0, 1);
+        assert_eq!(fs::read_dir(store.root()).unwrap().count(), 1);
+        assert!(store.root().join(STORE_MARKER).is_file());
+    }
+}
@@ -1,8 +1,12 @@
 use std::{
-    collections::{BTreeSet, HashSet},
+    borrow::Cow,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashSet},
     io::Write,
     path::{Path, PathBuf},
-    sync::{atomic::AtomicUsize, Arc, LazyLock},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, LazyLock,
+    },
 };
 
 use anyhow_ext::{Context, Result};
@@ -10,6 +14,7 @@ use botw_utils::hashes::StockHashTable;
 use fs_err as fs;
 use join_str::jstr;
 use jwalk::WalkDir;
+use mmap_rs::MmapOptions;
 use parking_lot::Mutex;
 use path_slash::PathExt;
 use rayon::prelude::*;
@@ -19,11 +24,12 @@ use serde::Deserialize;
 use serde_with::{serde_as, DefaultOnError};
 use smartstring::alias::String;
 use uk_content::{
+    canon::{canonicalize_aoc, is_canonical, normalize_unicode, platform, CanonTracker},
     canonicalize,
     constants::Language,
     platform_prefixes,
-    prelude::{Endian, Mergeable},
-    resource::{is_mergeable_sarc, ResourceData},
+    prelude::{Endian, Mergeable, Resource},
+    resource::{is_mergeable_sarc, MergeableResource, ResourceData, ResourceSizeTable},
 };
 use uk_util::PathExt as UkPathExt;
 use zip::{
@@ -32,11 +38,27 @@ use zip::{
 };
 
 use crate::{
+    endian,
+    format::{self, Downgrade, Feature},
+    is_file_modded, is_reserved_passthrough, platform_root,
+    progress::Tracker,
+    unpack::ZipData,
     ExclusiveOptionGroup, Manifest, Meta, ModOption, ModOptionGroup, ModPlatform,
-    MultipleOptionGroup, OptionGroup,
+    MultipleOptionGroup, OptionGroup, DOCS_DIR, FORMAT_VERSION, PASSTHROUGH_DIR, README,
 };
 
 pub type ZipWriter = Arc<Mutex<ZipW<fs::File>>>;
+/// Files held in memory while packaging one platform of a dual-platform mod,
+/// keyed by their path in the ZIP.
+type Staged = BTreeMap<std::string::String, Vec<u8>>;
+
+/// Files at least this large which are stored as they are, like movies and
+/// sound, are streamed into the package instead of read into memory.
+pub const STREAM_THRESHOLD: u64 = 32 * 1024 * 1024;
+/// How many large files are read at once while packaging.
+const LARGE_FILES_IN_FLIGHT: usize = 2;
+/// How much of a large file is encoded at a time.
+const STREAM_CHUNK: usize = 1024 * 1024;
 
 static NX_HASH_TABLE: LazyLock<StockHashTable> =
     LazyLock::new(|| StockHashTable::new(&botw_utils::hashes::Platform::Switch));
@@ -50,13 +72,203 @@ pub struct ModPacker {
     zip: ZipWriter,
     endian: Endian,
     built_resources: dashmap::DashSet<String>,
+    /// Files left out because they are the same as in the game or a master.
+    unchanged: dashmap::DashSet<String>,
     masters: Vec<Arc<uk_reader::ResourceReader>>,
     hash_table: &'static StockHashTable,
     compressor: Arc<Mutex<zstd::bulk::Compressor<'static>>>,
+    dual_source: Option<PathBuf>,
+    staged: Option<Mutex<Staged>>,
+    convert_endian: bool,
+    stream_threshold: u64,
+    cancel: Option<Arc<AtomicBool>>,
+    stage: Option<(Arc<Tracker>, &'static str)>,
+    /// The format version the package is written in.
+    target: u32,
+    /// Everything left out or stored differently for an older target.
+    downgrades: Mutex<Vec<Downgrade>>,
     _zip_opts: SimpleFileOptions,
     _out_file: PathBuf,
 }
 
+const BCML_WARNING: &str = "This mod was converted from a merged pack exported by BCML. It \
+                            contains all of the mods that were merged into it, so it can conflict \
+                            with mods you install separately. Where possible, install the \
+                            original mods instead.";
+
+#[inline]
+fn stock_hashes(endian: Endian) -> &'static StockHashTable {
+    match endian {
+        Endian::Little => &NX_HASH_TABLE,
+        Endian::Big => &WIIU_HASH_TABLE,
+    }
+}
+
+pub(crate) fn detect_endian(source: &Path) -> Result<Endian> {
+    let ((content_u, dlc_u), (content_nx, dlc_nx)) = (
+        platform_prefixes(Endian::Big),
+        platform_prefixes(Endian::Little),
+    );
+    if source.join(content_u).exists() || source.join(dlc_u).exists() {
+        Ok(Endian::Big)
+    } else if source.join(content_nx).exists() || source.join(dlc_nx).exists() {
+        Ok(Endian::Little)
+    } else {
+        anyhow_ext::bail!(
+            "No content or DLC folder found in source at {}",
+            source.display()
+        )
+    }
+}
+
+#[inline]
+fn is_manifest(path: &str) -> bool {
+    path == "manifest.yml" || path.ends_with("/manifest.yml")
+}
+
+/// Combines the staged files for both platforms of a dual-platform mod.
+/// Resources with identical content on both are stored once, the rest are
+/// moved under the platform root for their platform. Each platform's
+/// manifests are kept under its platform root too, and merged at the root.
+fn merge_payloads(first: (Endian, Staged), second: (Endian, Staged)) -> Result<Staged> {
+    let ((first_endian, mut first), (second_endian, mut second)) = (first, second);
+    let paths: BTreeSet<std::string::String> = first.keys().chain(second.keys()).cloned().collect();
+    let mut files = Staged::new();
+    for path in paths {
+        let (a, b) = (first.remove(&path), second.remove(&path));
+        if is_manifest(&path) {
+            // Each platform keeps its own manifest, while the one at the root
+            // lists the files of both for readers not set to a platform
+            let mut manifest = Manifest::default();
+            for (endian, data) in [(first_endian, a), (second_endian, b)] {
+                if let Some(data) = data {
+                    manifest.extend(&serde_yaml::from_slice(&data)?);
+                    files.insert(format!("{}/{}", platform_root(endian), path), data);
+                }
+            }
+            files.insert(path, serde_yaml::to_string(&manifest)?.into_bytes());
+            continue;
+        }
+        match (a, b) {
+            (Some(a), Some(b)) if a == b => {
+                files.insert(path, a);
+            }
+            (a, b) => {
+                for (endian, data) in [(first_endian, a), (second_endian, b)] {
+                    match data {
+                        Some(data) => {
+                            files.insert(format!("{}/{}", platform_root(endian), path), data);
+                        }
+                        None => {
+                            log::warn!(
+                                "{path} is missing from the {} files",
+                                ModPlatform::Specific(endian)
+                            )
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether a file would be stored as it is rather than parsed, judging by
+/// its magic.
+fn is_opaque(data: &[u8]) -> bool {
+    const PARSED: &[&[u8]] = &[
+        b"Yaz0",
+        b"SARC",
+        b"BY",
+        b"YB",
+        b"AAMP",
+        b"MsgStdBn",
+        b"BFEVFL",
+    ];
+    !PARSED.iter().any(|magic| data.starts_with(magic))
+}
+
+/// Encodes the start of a [`ResourceData::Binary`] holding `len` bytes, so
+/// that the bytes can follow without the whole resource being in memory.
+fn binary_header(len: usize) -> Result<Vec<u8>> {
+    let mut header = minicbor_ser::to_vec(&ResourceData::Binary(vec![]))
+        .map_err(|e| anyhow::format_err!("{:?}", e))?;
+    // An empty array takes one byte, which is replaced by the real length
+    header.pop();
+    let len = len as u64;
+    match len {
+        0..=23 => header.push(0x80 | len as u8),
+        24..=0xff => header.extend([0x98, len as u8]),
+        0x100..=0xffff => {
+            header.push(0x99);
+            header.extend((len as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            header.push(0x9a);
+            header.extend((len as u32).to_be_bytes());
+        }
+        _ => {
+            header.push(0x9b);
+            header.extend(len.to_be_bytes());
+        }
+    }
+    Ok(header)
+}
+
+/// Encodes bytes as the items of a CBOR array, the way a `Vec<u8>` is
+/// serialized.
+fn encode_bytes(data: &[u8], out: &mut Vec<u8>) {
+    for &byte in data {
+        if byte >= 24 {
+            out.push(0x18);
+        }
+        out.push(byte);
+    }
+}
+
+/// Reports packaging progress by bytes read, so that large files show
+/// movement too.
+struct Progress {
+    files: usize,
+    total: u64,
+    done:  AtomicU64,
+    /// A larger operation the bytes count towards, and the stage for them.
+    stage: Option<(Arc<Tracker>, &'static str)>,
+}
+
+impl Progress {
+    fn advance(&self, bytes: u64) {
+        if let Some((tracker, stage)) = self.stage.as_ref() {
+            tracker.advance(stage, bytes);
+        }
+        let done = self.done.fetch_add(bytes, Ordering::Relaxed);
+        let percent = |done: u64| (done * 100).checked_div(self.total).unwrap_or(100);
+        let (before, after) = (percent(done), percent(done + bytes));
+        if after > before {
+            log::trace!("PROGRESSBuilding {} files: {}%", self.files, after);
+        }
+    }
+}
+
+/// Writes merged dual-platform files into a mod ZIP, compressing everything
+/// but the manifests.
+fn write_staged(
+    zip: &mut ZipW<fs::File>,
+    compressor: &mut zstd::bulk::Compressor<'static>,
+    files: Staged,
+    opts: SimpleFileOptions,
+) -> Result<()> {
+    for (path, data) in files {
+        zip.start_file(path.as_str(), opts)?;
+        if is_manifest(&path) {
+            zip.write_all(&data)?;
+        } else {
+            zip.write_all(&compressor.compress(&data)?)?;
+        }
+    }
+    Ok(())
+}
+
 impl std::fmt::Debug for ModPacker {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ModBuilder")
@@ -109,6 +321,7 @@ impl From<BnpOption> for ModOption {
             description: opt.desc,
             path: opt.folder,
             requires: vec![],
+            image: None,
         }
     }
 }
@@ -170,6 +383,7 @@ impl ModPacker {
         rules.load(&path).map_err(|e| anyhow_ext::anyhow!(e))?;
         Ok(Meta {
             api: env!("CARGO_PKG_VERSION").into(),
+            format: FORMAT_VERSION,
             name: rules
                 .get("Definition", "name")
                 .context("rules.txt missing mod name")?
@@ -194,10 +408,33 @@ impl ModPacker {
         })
     }
 
+    /// Whether a folder is the merged graphic pack exported by BCML, rather
+    /// than an individual mod. BCML marks the rules.txt it writes, and the
+    /// pack holds every mod that was merged into it.
+    pub fn is_bcml_output(source: &Path) -> bool {
+        use configparser::ini::Ini;
+        let mut rules = Ini::new();
+        if rules.load(source.join("rules.txt")).is_err() {
+            return false;
+        }
+        let get = |key| {
+            rules
+                .get("Definition", key)
+                .unwrap_or_default()
+                .trim_matches('"')
+                .to_lowercase()
+        };
+        (get("name") == "bcml"
+            || get("path").contains("bcml")
+            || get("description").contains("merged using bcml"))
+            && detect_endian(source).is_ok()
+    }
+
     pub fn parse_info(path: PathBuf) -> Result<Meta> {
         let info: InfoJson = serde_json::from_reader(fs::File::open(path)?)?;
         Ok(Meta {
             api: env!("CARGO_PKG_VERSION").into(),
+            format: FORMAT_VERSION,
             name: info.name,
             description: info.desc,
             category: Default::default(),
@@ -245,7 +482,15 @@ impl ModPacker {
                 meta
             } else if let Some(rules) = source.join("rules.txt").exists_then() {
                 log::debug!("Attempting to parse existing rules.txt");
-                ModPacker::parse_rules(rules)?
+                let mut meta = ModPacker::parse_rules(rules)?;
+                if ModPacker::is_bcml_output(source) {
+                    log::warn!("{BCML_WARNING}");
+                    if meta.name.eq_ignore_ascii_case("BCML") {
+                        meta.name = "BCML Merged Pack".into();
+                    }
+                    meta.description = format!("{BCML_WARNING}\n\n{}", meta.description).into();
+                }
+                meta
             } else if let Some(info) = source.join("info.json").exists_then() {
                 log::debug!("Attempting to parse existing info.json");
                 log::warn!(
@@ -255,20 +500,7 @@ impl ModPacker {
             } else {
                 anyhow_ext::bail!("No meta info provided or meta file available");
             };
-            let ((content_u, dlc_u), (content_nx, dlc_nx)) = (
-                platform_prefixes(Endian::Big),
-                platform_prefixes(Endian::Little),
-            );
-            let endian = if source.join(content_u).exists() || source.join(dlc_u).exists() {
-                Endian::Big
-            } else if source.join(content_nx).exists() || source.join(dlc_nx).exists() {
-                Endian::Little
-            } else {
-                anyhow_ext::bail!(
-                    "No content or DLC folder found in source at {}",
-                    source.display()
-                );
-            };
+            let endian = detect_endian(source)?;
             let dest_file = if dest.is_dir() {
                 dest.join(sanitise(&meta.name)).with_extension("zip")
             } else {
@@ -286,15 +518,21 @@ impl ModPacker {
                 endian,
                 zip,
                 masters,
-                hash_table: match endian {
-                    Endian::Little => &NX_HASH_TABLE,
-                    Endian::Big => &WIIU_HASH_TABLE,
-                },
+                hash_table: stock_hashes(endian),
                 meta,
                 built_resources: Default::default(),
+                unchanged: Default::default(),
                 compressor: Arc::new(Mutex::new(
                     zstd::bulk::Compressor::with_dictionary(8, super::DICTIONARY).unwrap(),
                 )),
+                dual_source: None,
+                staged: None,
+                convert_endian: false,
+                stream_threshold: STREAM_THRESHOLD,
+                cancel: None,
+                stage: None,
+                target: FORMAT_VERSION,
+                downgrades: Default::default(),
                 _zip_opts: FileOptions::default()
                     .compression_method(zip::CompressionMethod::Stored),
                 _out_file: dest_file,
@@ -303,16 +541,167 @@ impl ModPacker {
         inner(source.as_ref(), dest.as_ref(), meta, masters)
     }
 
+    /// Adds the source folder for the other platform, so that both are
+    /// packaged into one dual-platform mod.
+    pub fn with_platform_source(mut self, source: impl AsRef<Path>) -> Result<Self> {
+        let source = source.as_ref();
+        let endian = detect_endian(source)?;
+        if endian == self.endian {
+            anyhow_ext::bail!(
+                "Both sources are for {}. A dual-platform mod needs one Wii U and one Switch \
+                 source.",
+                ModPlatform::Specific(endian)
+            );
+        }
+        self.meta.platform = ModPlatform::Dual;
+        self.dual_source = Some(source.to_path_buf());
+        Ok(self)
+    }
+
+    /// Re-encodes BYML and SARC files saved for the wrong platform while
+    /// packaging, instead of storing them as they are.
+    pub fn with_endian_conversion(mut self, convert: bool) -> Self {
+        self.convert_endian = convert;
+        self
+    }
+
+    /// Sets how large a file stored as it is must be to be streamed into the
+    /// package instead of read into memory.
+    pub fn with_stream_threshold(mut self, threshold: u64) -> Self {
+        self.stream_threshold = threshold;
+        self
+    }
+
+    /// Stops packaging, even partway through a file, once `cancel` is set.
+    /// The unfinished package is removed.
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Counts the bytes read towards a stage of a larger operation, such as
+    /// converting a BNP.
+    pub fn with_progress(mut self, tracker: Arc<Tracker>, stage: &'static str) -> Self {
+        self.stage = Some((tracker, stage));
+        self
+    }
+
+    /// Writes the package in an older format version, so it can be installed
+    /// with older versions of UKMM. What the version cannot hold is left out
+    /// or stored in an older form, with a warning for each, and packaging
+    /// fails if the mod cannot do without it. See [`format::CAPABILITIES`].
+    pub fn with_target_format(mut self, format: u32) -> Result<Self> {
+        format::check_target(format)?;
+        self.target = format;
+        Ok(self)
+    }
+
+    #[inline]
+    fn supports(&self, feature: Feature) -> bool {
+        format::supports(self.target, feature)
+    }
+
+    fn downgrade(&self, feature: Feature, subject: impl Into<std::string::String>) {
+        let downgrade = Downgrade {
+            feature,
+            subject: subject.into(),
+        };
+        log::warn!("{downgrade}");
+        self.downgrades.lock().push(downgrade);
+    }
+
+    /// Stores a parameter archive which could only be read leniently whole,
+    /// as a binary override, if the target format cannot hold it.
+    fn fit_resource(&self, name: &str, resource: ResourceData, data: &[u8]) -> ResourceData {
+        match resource {
+            ResourceData::Mergeable(resource)
+                if resource.is_lenient() && !self.supports(Feature::LenientParameters) =>
+            {
+                self.downgrade(Feature::LenientParameters, name);
+                ResourceData::Mergeable(MergeableResource::BinaryOverride(Box::new((
+                    data.to_vec(),
+                    "Parameters of unknown types".into(),
+                ))))
+            }
+            resource => resource,
+        }
+    }
+
+    /// Stores a resource whole, as a binary override, if its diff matches
+    /// BYML up by key or marks parameters deleted and the target format
+    /// cannot hold that.
+    fn fit_diff(
+        &self,
+        name: &str,
+        resource: &MergeableResource,
+        diff: MergeableResource,
+    ) -> MergeableResource {
+        let feature = if !self.supports(Feature::KeyedByml) && diff.is_keyed() {
+            Feature::KeyedByml
+        } else if !self.supports(Feature::ParameterDeletions)
+            && minicbor_ser::to_vec(&diff).is_ok_and(|data| format::has_deletions(&data))
+        {
+            Feature::ParameterDeletions
+        } else {
+            return diff;
+        };
+        self.downgrade(feature, name);
+        MergeableResource::BinaryOverride(Box::new((
+            resource.clone().into_binary(self.endian),
+            feature.capability().summary.into(),
+        )))
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            anyhow_ext::bail!("Packaging cancelled");
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn is_large(&self, size: u64) -> bool {
+        size > 0 && size >= self.stream_threshold
+    }
+
+    fn set_source(&mut self, source: PathBuf) -> Result<()> {
+        self.endian = detect_endian(&source)?;
+        self.hash_table = stock_hashes(self.endian);
+        self.current_root.clone_from(&source);
+        self.source_dir = source;
+        Ok(())
+    }
+
+    fn zip_path(&self, canon: &str) -> PathBuf {
+        self.current_root
+            .strip_prefix(&self.source_dir)
+            .unwrap()
+            .join(canon)
+    }
+
     fn write_resource(&self, canon: &str, resource: &ResourceData) -> Result<()> {
+        debug_assert!(is_canonical(canon), "{canon} is not canonical");
         let data = minicbor_ser::to_vec(&resource)
             .map_err(|e| anyhow::format_err!("{:?}", e))
             .with_context(|| jstr!("Failed to serialize {canon}"))?;
-        let zip_path = self
-            .current_root
-            .strip_prefix(&self.source_dir)
-            .unwrap()
-            .join(canon);
-        {
+        let zip_path = self.zip_path(canon);
+        if let Some(staged) = self.staged.as_ref() {
+            log::trace!("Staging {}", canon);
+            match staged.lock().entry(zip_path.to_slash_lossy().into()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(data);
+                }
+                Entry::Occupied(_) => {
+                    log::warn!("Attempted to duplicate resource {}, skipping", canon);
+                }
+            }
+        } else {
             log::trace!("Writing {} to ZIP", canon);
             let mut zip = self.zip.lock();
             match zip.start_file(zip_path.to_slash_lossy(), self._zip_opts) {
@@ -327,6 +716,52 @@ impl ModPacker {
         Ok(())
     }
 
+    /// Stores a large file which needs no processing by streaming it into
+    /// the package a chunk at a time, instead of building the whole resource
+    /// in memory. It reads back the same as a [`ResourceData::Binary`].
+    fn stream_binary(&self, canon: &str, data: &[u8], progress: &Progress) -> Result<()> {
+        debug_assert!(is_canonical(canon), "{canon} is not canonical");
+        let len = data.len() as u64;
+        if self.built_resources.contains(canon) {
+            log::trace!("Already processed {}, skipping", canon);
+            progress.advance(len);
+            return Ok(());
+        }
+        self.check_unmergeable(canon)?;
+        let header = binary_header(data.len())?;
+        let encoded_len = header.len() + data.len() + data.iter().filter(|b| **b >= 24).count();
+        log::trace!("Streaming {} to ZIP", canon);
+        let mut zip = self.zip.lock();
+        match zip.start_file(self.zip_path(canon).to_slash_lossy(), self._zip_opts) {
+            Ok(_) => (),
+            Err(zip::result::ZipError::InvalidArchive("Duplicate filename")) => {
+                log::warn!("Attempted to duplicate resource {}, skipping", canon);
+                progress.advance(len);
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let mut encoder =
+            zstd::stream::write::Encoder::with_dictionary(&mut *zip, 8, super::DICTIONARY)?;
+        // Recorded in the frame, so it can be decompressed in one go like the
+        // rest of the resources
+        encoder.set_pledged_src_size(Some(encoded_len as u64))?;
+        encoder.include_contentsize(true)?;
+        encoder.write_all(&header)?;
+        let mut buffer = Vec::with_capacity(STREAM_CHUNK * 2);
+        for chunk in data.chunks(STREAM_CHUNK) {
+            self.check_cancelled()?;
+            buffer.clear();
+            encode_bytes(chunk, &mut buffer);
+            encoder.write_all(&buffer)?;
+            progress.advance(chunk.len() as u64);
+        }
+        encoder.finish()?;
+        drop(zip);
+        self.built_resources.insert(canon.into());
+        Ok(())
+    }
+
     fn collect_resources(&self, root: PathBuf) -> Result<BTreeSet<String>> {
         let files = WalkDir::new(&root)
             .into_iter()
@@ -334,117 +769,248 @@ impl ModPacker {
                 f.ok()
                     .and_then(|f| f.file_type().is_file().then(|| f.path()))
             })
-            .collect::<Vec<PathBuf>>();
-        let total_files = files.len();
-        let current_file = AtomicUsize::new(0);
+            .map(|path| -> Result<(PathBuf, u64)> {
+                let size = fs::metadata(&path)?.len();
+                Ok((path, size))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let progress = Progress {
+            files: files.len(),
+            total: files.iter().map(|(_, size)| size).sum(),
+            done:  AtomicU64::new(0),
+            stage: self.stage.clone(),
+        };
+        let tracker = CanonTracker::new();
         log::debug!("Resources found in root {}:\n{:#?}", root.display(), &files);
-        Ok(files
+        // Large files are left until last and only a few are read at once, so
+        // that packaging them takes a predictable amount of memory
+        let (large, small): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|(_, size)| self.is_large(*size));
+        let collect = |(path, size): (PathBuf, u64)| {
+            self.collect_resource(&root, &path, size, &tracker, &progress)
+        };
+        let mut resources = small
             .into_par_iter()
-            .map(|path| -> Result<Option<String>> {
-                log::trace!("Processing resource at {}", path.display());
-                let name: String = path
-                    .strip_prefix(&self.current_root)
-                    .unwrap()
-                    .to_slash_lossy()
-                    .into();
-                // We know this is sound because we got `path` by iterating the contents of `root`.
-                let canon = canonicalize(name.as_str());
-                let file_data = fs::read(&path)?;
-                let file_data = decompress_if(&file_data);
-
-                if path
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .map(|s| s == "AocMainField.pack")
-                    .unwrap_or(false)
-                    && file_data.is_empty()
-                {
-                    self.write_resource(
-                        "Aoc/0010/Pack/AocMainField.pack",
-                        &ResourceData::Sarc(Default::default()),
-                    )?;
-                    return Ok(Some("Pack/AocMainField.pack".into()));
-                }
+            .map(collect)
+            .collect::<Result<Vec<_>>>()?;
+        if !large.is_empty() {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(LARGE_FILES_IN_FLIGHT)
+                .build()?;
+            resources.extend(pool.install(|| {
+                large
+                    .into_par_iter()
+                    .map(collect)
+                    .collect::<Result<Vec<_>>>()
+            })?);
+        }
+        Ok(resources.into_iter().flatten().collect())
+    }
 
-                if name.ends_with("sizetable") || file_data.len() < 4 {
-                    return Ok(None);
-                }
+    fn collect_resource(
+        &self,
+        root: &Path,
+        path: &Path,
+        size: u64,
+        tracker: &CanonTracker,
+        progress: &Progress,
+    ) -> Result<Option<String>> {
+        self.check_cancelled()?;
+        log::trace!("Processing resource at {}", path.display());
+        let mut name: String = path
+            .strip_prefix(&self.current_root)
+            .unwrap()
+            .to_slash_lossy()
+            .into();
+        // We know this is sound because we got `path` by iterating the contents of `root`.
+        let mut canon = tracker.canonicalize(name.as_str());
+        let translated = match self.native_name(&name, &canon) {
+            Some((native, native_canon)) => {
+                name = native;
+                canon = native_canon;
+                true
+            }
+            None => false,
+        };
+        // Listed as NFC, the same as the names files are stored under
+        let listed = || -> String {
+            let listed =
+                normalize_unicode(&path.strip_prefix(root).unwrap().to_slash_lossy()).into_owned();
+            match translated
+                .then(|| platform::translate(&listed, self.endian.into()))
+                .flatten()
+            {
+                Some(native) => native.into(),
+                None => listed.into(),
+            }
+        };
+        let source = if self.is_large(size) {
+            let file = fs::File::open(path)?;
+            // SAFETY: The mapping is only read from, and the source folder is
+            // not expected to change while it is packaged.
+            unsafe {
+                ZipData::Memory(
+                    MmapOptions::new(size as usize)?
+                        .with_file(file.file(), 0)
+                        .map()?,
+                )
+            }
+        } else {
+            ZipData::Owned(fs::read(path)?)
+        };
+        // Staged files for dual-platform mods are compared in memory, so
+        // they cannot be streamed
+        if self.is_large(size)
+            && self.staged.is_none()
+            && is_opaque(&source)
+            && !is_mergeable_sarc(canon.as_str(), &*source)
+        {
+            if !is_file_modded(self.hash_table, &canon, &source, true) {
+                log::trace!("Resource {} not modded, ignoring", &canon);
+                self.unchanged.insert(canon);
+                progress.advance(size);
+                return Ok(None);
+            }
+            self.stream_binary(&canon, &source, progress)
+                .with_context(|| jstr!("Failed to process resource {&canon}"))?;
+            return Ok(Some(listed()));
+        }
+        progress.advance(size);
+        let file_data = decompress_if(&source);
+        let file_data = if self.convert_endian {
+            let endian = match self.meta.platform {
+                ModPlatform::Specific(endian) => endian,
+                _ => self.endian,
+            };
+            endian::convert(&file_data, endian)
+                .with_context(|| format!("Failed to convert {name} for {endian}"))?
+                .map(Cow::Owned)
+                .unwrap_or(file_data)
+        } else {
+            file_data
+        };
 
-                if !self.hash_table.is_file_modded(&canon, &*file_data, true) {
-                    log::trace!("Resource {} not modded, ignoring", &canon);
-                    return Ok(None);
-                }
+        if path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .map(|s| s == "AocMainField.pack")
+            .unwrap_or(false)
+            && file_data.is_empty()
+        {
+            self.write_resource(
+                "Aoc/0010/Pack/AocMainField.pack",
+                &ResourceData::Sarc(Default::default()),
+            )?;
+            return Ok(Some("Pack/AocMainField.pack".into()));
+        }
 
-                let resource = ResourceData::from_binary(name.as_str(), &*file_data)
-                    .with_context(|| jstr!("Failed to parse resource {&name}"))?;
-                let is_mergeable = matches!(resource, ResourceData::Mergeable(_));
-                if let ResourceData::Mergeable(
-                    uk_content::resource::MergeableResource::BinaryOverride(v),
-                ) = &resource
-                {
-                    log::error!(
-                        "There was an error processing {name}. It will not be processed but will \
-                         be stored as-is, overriding anything else. Error details:\n{}",
-                        v.1
-                    );
-                }
-                self.process_resource(name.clone(), canon.clone(), resource, false)
-                    .with_context(|| jstr!("Failed to process resource {&canon}"))?;
-                if !is_mergeable && is_mergeable_sarc(canon.as_str(), file_data.as_ref()) {
-                    log::trace!(
-                        "Resource {} is a mergeable SARC, processing contents",
-                        &canon
-                    );
-                    self.process_sarc(
-                        Sarc::new(file_data.as_ref())?,
-                        name.as_str().as_ref(),
-                        self.hash_table.is_file_new(&canon),
-                        canon.starts_with("Aoc"),
-                    )
-                    .with_context(|| jstr!("Failed to process SARC file {&canon}"))?;
-                }
+        if file_data.len() < 4 {
+            return Ok(None);
+        }
 
-                let progress = current_file.load(std::sync::atomic::Ordering::Relaxed) + 1;
-                current_file.store(progress, std::sync::atomic::Ordering::Relaxed);
-                let percent = (progress as f64 / total_files as f64) * 100.0;
-                let fract = percent.fract();
-                if fract <= 0.1 || fract >= 0.95 {
-                    log::trace!(
-                        "PROGRESSBuilding {} files: {}%",
-                        total_files,
-                        percent as usize
-                    );
-                }
+        if !is_file_modded(self.hash_table, &canon, &file_data, true) {
+            log::trace!("Resource {} not modded, ignoring", &canon);
+            self.unchanged.insert(canon);
+            return Ok(None);
+        }
 
-                Ok(Some(
-                    path.strip_prefix(&root).unwrap().to_slash_lossy().into(),
-                ))
-            })
-            .collect::<Result<Vec<Option<_>>>>()?
-            .into_par_iter()
-            .filter_map(|x| x)
-            .collect())
+        let resource = ResourceData::from_binary(name.as_str(), &*file_data)
+            .with_context(|| jstr!("Failed to parse resource {&name}"))?;
+        let resource = self.fit_resource(&name, resource, &file_data);
+        let is_mergeable = matches!(resource, ResourceData::Mergeable(_));
+        if let ResourceData::Mergeable(uk_content::resource::MergeableResource::BinaryOverride(v)) =
+            &resource
+        {
+            // A broken RSTB would override the one UKMM builds, so it is better
+            // left out
+            if ResourceSizeTable::path_matches(canon.as_str()) {
+                log::warn!("Skipping {name}, which is not a valid RSTB: {}", v.1);
+                return Ok(None);
+            }
+            log::error!(
+                "There was an error processing {name}. It will not be processed but will be \
+                 stored as-is, overriding anything else. Error details:\n{}",
+                v.1
+            );
+        } else if let ResourceData::Mergeable(resource) = &resource {
+            if resource.is_lenient() {
+                log::warn!(
+                    "{name} has parameters of types UKMM does not know. They will be stored as \
+                     they are and merged whole."
+                );
+            }
+        }
+        let written = self
+            .process_resource(name.clone(), canon.clone(), resource, false)
+            .with_context(|| jstr!("Failed to process resource {&canon}"))?;
+        let nested = !is_mergeable && is_mergeable_sarc(canon.as_str(), file_data.as_ref());
+        // A SARC with the same files can still have changes inside them
+        if !written && !nested {
+            self.unchanged.insert(canon);
+            return Ok(None);
+        }
+        if nested {
+            log::trace!(
+                "Resource {} is a mergeable SARC, processing contents",
+                &canon
+            );
+            self.process_sarc(
+                Sarc::new(file_data.as_ref())?,
+                name.as_str().as_ref(),
+                self.hash_table.is_file_new(&canon),
+                canon.starts_with("Aoc"),
+            )
+            .with_context(|| jstr!("Failed to process SARC file {&canon}"))?;
+        }
+        Ok(Some(listed()))
+    }
+
+    /// The name and canonical path the game has a file under on this
+    /// platform, if it is named the way the other one has it. Only files
+    /// which are stock under the new name are renamed, so they are diffed
+    /// against the stock file rather than added as new ones.
+    fn native_name(&self, name: &str, canon: &str) -> Option<(String, String)> {
+        if !self.hash_table.is_file_new(canon) {
+            return None;
+        }
+        let native = platform::translate(name, self.endian.into())?;
+        let native_canon = canonicalize(&native);
+        if self.hash_table.is_file_new(native_canon.as_str()) {
+            return None;
+        }
+        log::warn!("{name} is named as on the other platform, so it will be packaged as {native}");
+        Some((native.into(), native_canon))
+    }
+
+    fn check_unmergeable(&self, canon: &str) -> Result<()> {
+        if self.meta.platform == ModPlatform::Universal {
+            anyhow_ext::bail!(
+                "The resource {} is not a mergeable asset. Cross-platform mods must consist only \
+                 of mergeable assets. While there is no ready-made comprehensive list of \
+                 mergeable assets, common unmergeable assets include models, textures, music, and \
+                 Havok physics data.",
+                canon
+            );
+        }
+        Ok(())
     }
 
+    /// Diffs a resource against its master and writes it, returning whether
+    /// it was written or left out as unchanged.
     fn process_resource(
         &self,
         name: String,
         canon: String,
         mut resource: ResourceData,
         in_new_sarc: bool,
-    ) -> Result<()> {
+    ) -> Result<bool> {
         if self.built_resources.contains(&canon) {
             log::trace!("Already processed {}, skipping", &canon);
-            return Ok(());
+            return Ok(true);
         }
-        if resource.as_binary().is_some() && self.meta.platform == ModPlatform::Universal {
-            anyhow_ext::bail!(
-                "The resource {} is not a mergeable asset. Cross-platform mods must consist only \
-                 of mergeable assets. While there is no ready-made comprehensive list of \
-                 mergeable assets, common unmergeable assets include models, textures, music, and \
-                 Havok physics data.",
-                canon
-            );
+        if resource.as_binary().is_some() {
+            self.check_unmergeable(&canon)?;
         }
         let prefixes = platform_prefixes(self.endian);
         let ref_name = name
@@ -487,25 +1053,34 @@ impl ModPacker {
         ) {
             if ref_res == res {
                 log::trace!("{} not modded, skipping", &canon);
-                return Ok(());
+                return Ok(false);
             }
             log::trace!("Diffing {}", &canon);
-            resource = ResourceData::Mergeable(ref_res.diff(res));
+            resource = ResourceData::Mergeable(self.fit_diff(&name, res, ref_res.diff(res)));
         } else if let (Some(sarc), Some(ref_sarc)) = (
             resource.as_sarc(),
             reference.as_ref().and_then(|rrd| rrd.as_sarc()),
         ) {
             if ref_sarc == sarc && !in_new_sarc {
                 log::trace!("{} not modded, skipping", &canon);
-                return Ok(());
+                return Ok(false);
             }
             log::trace!("Diffing {}", &canon);
             resource = ResourceData::Sarc(ref_sarc.diff(sarc));
+        } else if let (Some(data), Some(ref_data)) = (
+            resource.as_binary(),
+            reference.as_ref().and_then(|rrd| rrd.as_binary()),
+        ) {
+            // Not in the stock hash table, but the same as a master's copy
+            if ref_data == data && !in_new_sarc {
+                log::trace!("{} same as master, skipping", &canon);
+                return Ok(false);
+            }
         }
 
         self.write_resource(&canon, &resource)?;
 
-        Ok(())
+        Ok(true)
     }
 
     fn process_sarc(&self, sarc: Sarc, path: &Path, is_new_sarc: bool, is_aoc: bool) -> Result<()> {
@@ -516,13 +1091,14 @@ impl ModPacker {
             let name = file
                 .name()
                 .with_context(|| jstr!("File in SARC missing name"))?;
-            let mut canon = canonicalize(name);
-            if is_aoc {
-                canon.insert_str(0, "Aoc/0010/");
-            }
+            let canon = if is_aoc {
+                canonicalize_aoc(name)
+            } else {
+                canonicalize(name)
+            };
             let file_data = decompress_if(file.data);
 
-            if !self.hash_table.is_file_modded(&canon, &*file_data, true) && !is_new_sarc {
+            if !is_file_modded(self.hash_table, &canon, &file_data, true) && !is_new_sarc {
                 log::trace!("{} in SARC {} not modded, skipping", &canon, path.display());
                 continue;
             }
@@ -530,6 +1106,7 @@ impl ModPacker {
             let resource = ResourceData::from_binary(name, &*file_data).with_context(|| {
                 jstr!("Failed to parse resource {&canon} in SARC {&path.display().to_string()}")
             })?;
+            let resource = self.fit_resource(name, resource, &file_data);
             if let ResourceData::Mergeable(
                 uk_content::resource::MergeableResource::BinaryOverride(v),
             ) = &resource
@@ -539,6 +1116,13 @@ impl ModPacker {
                      stored as-is, overriding anything else. Error details:\n{}",
                     v.1
                 );
+            } else if let ResourceData::Mergeable(resource) = &resource {
+                if resource.is_lenient() {
+                    log::warn!(
+                        "{name} has parameters of types UKMM does not know. They will be stored \
+                         as they are and merged whole."
+                    );
+                }
             }
             self.process_resource(name.into(), canon.clone(), resource, is_new_sarc)?;
             if is_mergeable_sarc(canon.as_str(), file_data.as_ref()) {
@@ -618,15 +1202,19 @@ impl ModPacker {
             }
             let manifest = serde_yaml::to_string(&manifest)?;
             log::info!("Writing manifest");
-            let mut zip = self_.zip.lock();
-            zip.start_file(
-                root.strip_prefix(&self_.source_dir)
-                    .unwrap()
-                    .join("manifest.yml")
-                    .to_slash_lossy(),
-                self_._zip_opts,
-            )?;
-            zip.write_all(manifest.as_bytes())?;
+            let manifest_path = root
+                .strip_prefix(&self_.source_dir)
+                .unwrap()
+                .join("manifest.yml")
+                .to_slash_lossy()
+                .into_owned();
+            if let Some(staged) = self_.staged.as_ref() {
+                staged.lock().insert(manifest_path, manifest.into_bytes());
+            } else {
+                let mut zip = self_.zip.lock();
+                zip.start_file(manifest_path, self_._zip_opts)?;
+                zip.write_all(manifest.as_bytes())?;
+            }
             Ok(())
         }
         inner(self, root.as_ref())
@@ -643,21 +1231,143 @@ impl ModPacker {
     }
 
     fn pack_thumbnail(&self) -> Result<()> {
-        for name in ["thumb", "thumbnail", "preview"] {
-            for ext in ["jpg", "jpeg", "png", "svg"] {
-                let path = self.source_dir.join(name).with_extension(ext);
-                if path.exists() {
-                    let mut zip = self.zip.lock();
-                    zip.start_file(format!("thumb.{}", ext), self._zip_opts)?;
-                    zip.write_all(&fs::read(path)?)?;
-                    return Ok(());
+        if let Some(path) = find_thumbnail(&self.source_dir, &["jpg", "jpeg", "png", "svg"]) {
+            let ext = path.extension().unwrap_or_default().to_string_lossy();
+            let mut zip = self.zip.lock();
+            zip.start_file(format!("thumb.{}", ext), self._zip_opts)?;
+            zip.write_all(&fs::read(&path)?)?;
+        }
+        Ok(())
+    }
+
+    /// Packs a preview image for each option which has one, either named in
+    /// the option or found in its folder like the mod thumbnail. Images which
+    /// cannot be decoded are left out with a warning.
+    fn pack_option_images(&mut self) -> Result<()> {
+        let opt_root = self.source_dir.join("options");
+        if !self.supports(Feature::OptionImages) {
+            let mut omitted = vec![];
+            for opt in self.meta.options.iter_mut().flat_map(|g| g.options_mut()) {
+                let image = opt.image.take();
+                let dir = opt_root.join(&opt.path);
+                if image.is_some() || find_thumbnail(&dir, &["jpg", "jpeg", "png"]).is_some() {
+                    omitted.push(format!("the preview image for option {}", opt.name));
+                }
+            }
+            for subject in omitted {
+                self.downgrade(Feature::OptionImages, subject);
+            }
+            return Ok(());
+        }
+        let mut zip = self.zip.lock();
+        for group in self.meta.options.iter_mut() {
+            for opt in group.options_mut() {
+                let dir = opt_root.join(&opt.path);
+                let source = match opt.image.take() {
+                    Some(image) => Some(dir.join(image)),
+                    None => find_thumbnail(&dir, &["jpg", "jpeg", "png"]),
+                };
+                let Some(source) = source else {
+                    continue;
+                };
+                match prepare_option_image(&source) {
+                    Ok((data, ext)) => {
+                        opt.image = Some(format!("thumb.{ext}").into());
+                        zip.start_file(opt.image_path().expect("Image set"), self._zip_opts)?;
+                        zip.write_all(&data)?;
+                    }
+                    Err(e) => {
+                        log::warn!("Skipping preview image for option {}: {e:?}", opt.name)
+                    }
                 }
             }
         }
         Ok(())
     }
 
-    pub fn pack(mut self) -> Result<PathBuf> {
+    /// Copies the mod's README and docs folder into the package as they are,
+    /// so they can be shown to users.
+    fn pack_docs(&self) -> Result<()> {
+        let readme = ["README.md", "readme.md", "Readme.md"]
+            .into_iter()
+            .map(|name| self.source_dir.join(name))
+            .find(|path| path.is_file());
+        let docs = self.source_dir.join(DOCS_DIR);
+        if !self.supports(Feature::Docs) {
+            if readme.is_some() {
+                self.downgrade(Feature::Docs, README);
+            }
+            if docs.is_dir() {
+                self.downgrade(Feature::Docs, format!("the {DOCS_DIR} folder"));
+            }
+            return Ok(());
+        }
+        let mut zip = self.zip.lock();
+        if let Some(readme) = readme {
+            log::info!("Packing README");
+            zip.start_file(README, self._zip_opts)?;
+            zip.write_all(&fs::read(readme)?)?;
+        }
+        if docs.is_dir() {
+            log::info!("Packing documentation folder");
+            for entry in WalkDir::new(&docs)
+                .sort(true)
+                .into_iter()
+                .filter_map(std::result::Result::ok)
+                .filter(|e| e.file_type().is_file())
+            {
+                let path = entry.path();
+                let name = path
+                    .strip_prefix(&self.source_dir)
+                    .expect("Doc file should be in source folder")
+                    .to_slash_lossy();
+                zip.start_file(name.as_ref(), self._zip_opts)?;
+                zip.write_all(&fs::read(&path)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the mod's passthrough folder into the package as it is. These
+    /// files are deployed verbatim and never processed as resources.
+    fn pack_passthrough(&self) -> Result<()> {
+        let passthrough = self.source_dir.join(PASSTHROUGH_DIR);
+        if !passthrough.is_dir() {
+            return Ok(());
+        }
+        if !self.supports(Feature::Passthrough) {
+            self.downgrade(
+                Feature::Passthrough,
+                format!("the {PASSTHROUGH_DIR} folder"),
+            );
+            return Ok(());
+        }
+        log::info!("Packing passthrough files");
+        let mut zip = self.zip.lock();
+        for entry in WalkDir::new(&passthrough)
+            .sort(true)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|e| e.file_type().is_file())
+        {
+            let path = entry.path();
+            let file = path
+                .strip_prefix(&passthrough)
+                .expect("Passthrough file should be in passthrough folder")
+                .to_slash_lossy();
+            if is_reserved_passthrough(&file) {
+                log::warn!(
+                    "Skipping passthrough file {file}, which would be deployed over game files"
+                );
+                continue;
+            }
+            zip.start_file(jstr!("{PASSTHROUGH_DIR}/{&file}"), self._zip_opts)?;
+            zip.write_all(&fs::read(&path)?)?;
+        }
+        Ok(())
+    }
+
+    fn pack_roots(&mut self) -> Result<()> {
         self.pack_root(&self.source_dir).with_context(|| {
             format!(
                 "Failed to package mod root at {} for mod {}",
@@ -683,7 +1393,105 @@ impl ModPacker {
                 })?;
             }
         }
+        Ok(())
+    }
+
+    /// Packages the trees for both platforms of a dual-platform mod, then
+    /// writes their files together, storing shared files only once.
+    fn pack_dual(&mut self, other: PathBuf) -> Result<()> {
+        let source = self.source_dir.clone();
+        let masters = self.masters.len();
+        let mut payloads = Vec::with_capacity(2);
+        for root in [source.clone(), other] {
+            self.set_source(root)?;
+            log::info!("Packaging {} files", ModPlatform::Specific(self.endian));
+            self.masters.truncate(masters);
+            self.staged = Some(Default::default());
+            self.pack_roots()?;
+            let staged = self.staged.take().unwrap_or_default().into_inner();
+            payloads.push((self.endian, staged));
+        }
+        self.set_source(source)?;
+        let second = payloads.pop().unwrap();
+        let first = payloads.pop().unwrap();
+        log::info!("Combining platform files");
+        let files = merge_payloads(first, second)?;
+        write_staged(
+            &mut self.zip.lock(),
+            &mut self.compressor.lock(),
+            files,
+            self._zip_opts,
+        )
+    }
+
+    fn pack_files(&mut self) -> Result<()> {
+        let dual = self.dual_source.is_some() || self.meta.platform == ModPlatform::Dual;
+        if dual && !self.supports(Feature::DualPlatform) {
+            let cap = Feature::DualPlatform.capability();
+            anyhow_ext::bail!(
+                "{} cannot be packaged for mod format version {}, since {} need version {}",
+                self.meta.name,
+                self.target,
+                cap.summary,
+                cap.since
+            );
+        }
+        match self.dual_source.take() {
+            Some(other) => self.pack_dual(other)?,
+            None => self.pack_roots()?,
+        }
         self.pack_thumbnail()?;
+        self.pack_option_images()?;
+        self.pack_docs()?;
+        self.pack_passthrough()
+    }
+
+    /// Logs the files left out for being unchanged, so authors can clean
+    /// them out of their source.
+    fn report_unchanged(&self) {
+        if self.unchanged.is_empty() {
+            return;
+        }
+        let files: BTreeSet<String> = self.unchanged.iter().map(|f| f.key().clone()).collect();
+        log::info!(
+            "Removed {} unchanged files, which are the same as the game's or the base mod's",
+            files.len()
+        );
+        log::debug!(
+            "Unchanged files:\n{}",
+            files
+                .iter()
+                .map(|f| f.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    pub fn pack(self) -> Result<PathBuf> {
+        self.pack_report().map(|(path, _)| path)
+    }
+
+    /// Packages the mod like [`ModPacker::pack`], also returning what was
+    /// left out or stored differently for an older target format.
+    pub fn pack_report(mut self) -> Result<(PathBuf, Vec<Downgrade>)> {
+        if let Err(e) = self.pack_files() {
+            if self.is_cancelled() {
+                drop(self.zip);
+                fs::remove_file(&self._out_file)?;
+                log::info!("Packaging cancelled");
+            }
+            return Err(e);
+        }
+        self.report_unchanged();
+        self.meta.format = self.target;
+        let downgrades = self.downgrades.into_inner();
+        if !downgrades.is_empty() {
+            log::warn!(
+                "Packaged for mod format version {} with {} feature(s) left out or downgraded",
+                self.target,
+                downgrades.len()
+            );
+        }
         match Arc::try_unwrap(self.zip).map(|z| z.into_inner()) {
             Ok(mut zip) => {
                 log::info!("Writing meta");
@@ -696,10 +1504,74 @@ impl ModPacker {
             }
         };
         log::info!("Completed packaging mod");
-        Ok(self._out_file)
+        Ok((self._out_file, downgrades))
     }
 }
 
+/// Largest width or height kept for option preview images.
+const MAX_OPTION_IMAGE: u32 = 512;
+
+/// Looks for a thumbnail named `thumb`, `thumbnail`, or `preview` in a folder.
+fn find_thumbnail(dir: &Path, exts: &[&str]) -> Option<PathBuf> {
+    ["thumb", "thumbnail", "preview"]
+        .into_iter()
+        .find_map(|name| {
+            exts.iter()
+                .map(|ext| dir.join(name).with_extension(ext))
+                .find(|path| path.exists())
+        })
+}
+
+/// Checks that an option image is a PNG or JPEG which decodes, scaling it
+/// down if it is larger than needed for the option picker.
+fn prepare_option_image(path: &Path) -> Result<(Vec<u8>, &'static str)> {
+    let data = fs::read(path)?;
+    let format = image::guess_format(&data).context("Unrecognized image format")?;
+    let ext = match format {
+        image::ImageFormat::Png => "png",
+        image::ImageFormat::Jpeg => "jpg",
+        _ => anyhow_ext::bail!("Option images must be PNG or JPEG"),
+    };
+    let img =
+        image::load_from_memory_with_format(&data, format).context("Failed to decode image")?;
+    if img.width() <= MAX_OPTION_IMAGE && img.height() <= MAX_OPTION_IMAGE {
+        return Ok((data, ext));
+    }
+    let mut out = std::io::Cursor::new(vec![]);
+    img.thumbnail(MAX_OPTION_IMAGE, MAX_OPTION_IMAGE)
+        .write_to(&mut out, format)
+        .context("Failed to scale down image")?;
+    Ok((out.into_inner(), ext))
+}
+
+/// Writes already processed resources straight into a mod package. This is
+/// used for mods generated by UKMM itself, which have no source folder.
+pub fn pack_resources(
+    dest: impl AsRef<Path>,
+    meta: &Meta,
+    manifest: &Manifest,
+    resources: impl IntoIterator<Item = (String, ResourceData)>,
+) -> Result<PathBuf> {
+    let dest = dest.as_ref();
+    let opts: SimpleFileOptions =
+        FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(8, super::DICTIONARY)?;
+    let mut zip = ZipW::new(fs::File::create(dest)?);
+    for (canon, resource) in resources {
+        let data = minicbor_ser::to_vec(&resource)
+            .map_err(|e| anyhow::format_err!("{:?}", e))
+            .with_context(|| jstr!("Failed to serialize {&canon}"))?;
+        zip.start_file(canon.as_str(), opts)?;
+        zip.write_all(&compressor.compress(&data)?)?;
+    }
+    zip.start_file("manifest.yml", opts)?;
+    zip.write_all(serde_yaml::to_string(manifest)?.as_bytes())?;
+    zip.start_file("meta.yml", opts)?;
+    zip.write_all(serde_yaml::to_string(meta)?.as_bytes())?;
+    zip.finish()?;
+    Ok(dest.to_path_buf())
+}
+
 #[cfg(test)]
 mod tests {
     use indexmap::IndexMap;
@@ -721,6 +1593,7 @@ mod tests {
             dest,
             Some(Meta {
                 api: env!("CARGO_PKG_VERSION").into(),
+                format: FORMAT_VERSION,
                 platform: ModPlatform::Specific(Endian::Big),
                 name: "Test Mod".into(),
                 version: "0.1.0".into(),
@@ -738,6 +1611,7 @@ mod tests {
                         description: "An option".into(),
                         path: "option1".into(),
                         requires: vec![],
+                        image: None,
                     }]
                     .into_iter()
                     .collect(),
@@ -749,4 +1623,560 @@ mod tests {
         .unwrap();
         builder.pack().unwrap();
     }
+
+    #[test]
+    fn dual_payloads() {
+        let manifest = |file: &str| {
+            let mut manifest = Manifest::default();
+            manifest.content_files.insert(file.into());
+            serde_yaml::to_string(&manifest).unwrap().into_bytes()
+        };
+        let wiiu: Staged = [
+            ("content/manifest.yml".into(), manifest("Pack/Wiiu.pack")),
+            ("Actor/ActorInfo.product.byml".into(), b"shared".to_vec()),
+            ("Model/Test.bfres".into(), b"wiiu".to_vec()),
+        ]
+        .into_iter()
+        .collect();
+        let nx: Staged = [
+            ("content/manifest.yml".into(), manifest("Pack/Switch.pack")),
+            ("Actor/ActorInfo.product.byml".into(), b"shared".to_vec()),
+            ("Model/Test.bfres".into(), b"nx".to_vec()),
+        ]
+        .into_iter()
+        .collect();
+        let files = merge_payloads((Endian::Big, wiiu), (Endian::Little, nx)).unwrap();
+        assert_eq!(files.keys().map(|k| k.as_str()).collect::<Vec<_>>(), [
+            "Actor/ActorInfo.product.byml",
+            "content/manifest.yml",
+            "platform/nx/Model/Test.bfres",
+            "platform/nx/content/manifest.yml",
+            "platform/wiiu/Model/Test.bfres",
+            "platform/wiiu/content/manifest.yml",
+        ]);
+        assert_eq!(files["platform/wiiu/Model/Test.bfres"], b"wiiu");
+        assert_eq!(files["platform/nx/Model/Test.bfres"], b"nx");
+        assert_eq!(
+            files["platform/nx/content/manifest.yml"],
+            manifest("Pack/Switch.pack")
+        );
+        let manifest: Manifest = serde_yaml::from_slice(&files["content/manifest.yml"]).unwrap();
+        assert_eq!(manifest.content_files.len(), 2);
+    }
+
+    /// A dual-platform mod with a different file for each platform and one
+    /// only for Switch is installed for each platform in turn.
+    #[test]
+    fn dual_round_trip() {
+        use crate::unpack::{ModReader, ModUnpacker};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let write = |path: PathBuf, data: &[u8]| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        };
+        let movies = |name: &str, endian: Endian| {
+            tmp.path()
+                .join(name)
+                .join(platform_prefixes(endian).0)
+                .join("Movie")
+        };
+        for (name, endian) in [("wiiu", Endian::Big), ("nx", Endian::Little)] {
+            write(movies(name, endian).join("Shared.mp4"), b"shared");
+            write(movies(name, endian).join("Custom.mp4"), name.as_bytes());
+        }
+        write(
+            movies("nx", Endian::Little).join("Switch.mp4"),
+            b"switch only",
+        );
+        let packed = ModPacker::new(
+            tmp.path().join("wiiu"),
+            tmp.path().join("dual.zip"),
+            Some(movie_meta()),
+            vec![],
+        )
+        .unwrap()
+        .with_platform_source(tmp.path().join("nx"))
+        .unwrap()
+        .pack()
+        .unwrap();
+
+        for (name, endian) in [("wiiu", Endian::Big), ("nx", Endian::Little)] {
+            let dump = Arc::new(ResourceReader::from_memory(
+                uk_reader::MemoryROMSource::new(endian),
+            ));
+            ModUnpacker::new(
+                dump,
+                endian,
+                Language::USen,
+                vec![ModReader::open(&packed, vec![]).unwrap()],
+                tmp.path().join("merged").join(name),
+            )
+            .unpack()
+            .unwrap();
+            let merged = movies(&format!("merged/{name}"), endian);
+            assert_eq!(fs::read(merged.join("Shared.mp4")).unwrap(), b"shared");
+            assert_eq!(
+                fs::read(merged.join("Custom.mp4")).unwrap(),
+                name.as_bytes()
+            );
+            assert_eq!(merged.join("Switch.mp4").exists(), endian == Endian::Little);
+        }
+    }
+
+    #[test]
+    fn bcml_output() {
+        use uk_content::{
+            chemical::chmres::ChemicalRes, cooking::data::CookData, prelude::Resource,
+            resource::MergeableResource,
+        };
+        use uk_reader::ResourceLoader;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let fixture =
+            |name: &str| std::fs::read(Path::new("../uk-content/test").join(name)).unwrap();
+        let write = |root: &Path, file: &str, data: &[u8]| {
+            let path = root.join("content").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        };
+        let stock = uk_reader::MemoryROMSource::from_files(
+            Endian::Big,
+            [
+                "Chemical/system.bchmres",
+                "Cooking/CookData.sbyml",
+                "Tips/TipsWorld.sbyml",
+            ]
+            .map(|file| (file, fixture(file))),
+        );
+        let merged = tmp.path().join("BreathOfTheWild_BCML");
+        write(
+            &merged,
+            "Chemical/system.bchmres",
+            &fixture("Chemical/system.mod.bchmres"),
+        );
+        write(
+            &merged,
+            "Cooking/CookData.sbyml",
+            &fixture("Cooking/CookData.mod.sbyml"),
+        );
+        write(
+            &merged,
+            "Tips/TipsWorld.sbyml",
+            &fixture("Tips/TipsWorld.sbyml"),
+        );
+        write(
+            &merged,
+            "System/Resource/ResourceSizeTable.product.srsizetable",
+            b"Yaz0 not really an RSTB",
+        );
+        fs::write(
+            merged.join("rules.txt"),
+            "[Definition]\ntitleIds = 00050000101C9300,00050000101C9400,00050000101C9500\nname = \
+             BCML\npath = The Legend of Zelda: Breath of the Wild/Mods/BCML\ndescription = \
+             Complete pack of mods merged using BCML\nversion = 7\nfsPriority = 9999\n",
+        )
+        .unwrap();
+        assert!(ModPacker::is_bcml_output(&merged));
+        assert!(!ModPacker::is_bcml_output(tmp.path()));
+
+        let master = uk_reader::ResourceReader::from_memory(stock);
+        let dest = tmp.path().join("bcml.zip");
+        ModPacker::new(&merged, &dest, None, vec![Arc::new(master)])
+            .unwrap()
+            .pack()
+            .unwrap();
+
+        let reader = crate::unpack::ModReader::open(&dest, vec![]).unwrap();
+        assert_eq!(reader.meta.name, "BCML Merged Pack");
+        assert!(reader.meta.description.starts_with(BCML_WARNING));
+        assert_eq!(
+            reader
+                .manifest
+                .content_files
+                .iter()
+                .map(|f| f.as_str())
+                .collect::<Vec<_>>(),
+            ["Chemical/system.bchmres", "Cooking/CookData.sbyml"]
+        );
+        let read = |file: &str| -> MergeableResource {
+            let data = reader.get_data(Path::new(file)).unwrap();
+            match minicbor_ser::from_slice(&data).unwrap() {
+                ResourceData::Mergeable(res) => res,
+                _ => panic!("{file} was not stored as a mergeable resource"),
+            }
+        };
+        match read("Chemical/system.bchmres") {
+            MergeableResource::ChemicalRes(diff) => {
+                let stock = ChemicalRes::from_binary(fixture("Chemical/system.bchmres")).unwrap();
+                let modded =
+                    ChemicalRes::from_binary(fixture("Chemical/system.mod.bchmres")).unwrap();
+                assert_eq!(*diff, stock.diff(&modded));
+            }
+            _ => panic!("Chemical data was not parsed as ChemicalRes"),
+        }
+        match read("Cooking/CookData.sbyml") {
+            MergeableResource::CookData(diff) => {
+                let stock =
+                    CookData::from_binary(decompress_if(&fixture("Cooking/CookData.sbyml")))
+                        .unwrap();
+                let modded =
+                    CookData::from_binary(decompress_if(&fixture("Cooking/CookData.mod.sbyml")))
+                        .unwrap();
+                assert_eq!(*diff, stock.diff(&modded));
+            }
+            _ => panic!("Cooking data was not parsed as CookData"),
+        }
+    }
+
+    #[test]
+    fn strip_unchanged() {
+        use roead::byml::Byml;
+        use uk_reader::ResourceLoader;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let fixture =
+            |name: &str| std::fs::read(Path::new("../uk-content/test").join(name)).unwrap();
+        let source = tmp.path().join("source");
+        let write = |file: &str, data: &[u8]| {
+            let path = source.join("content").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        };
+        let stock = uk_reader::MemoryROMSource::from_files(
+            Endian::Big,
+            ["Chemical/system.bchmres", "Actor/ActorInfo.product.sbyml"]
+                .map(|file| (file, fixture(file))),
+        )
+        .with_file("Model/Copied.sbfres", b"stock model".to_vec());
+        write(
+            "Chemical/system.bchmres",
+            &fixture("Chemical/system.mod.bchmres"),
+        );
+        // Copied whole, and saved again by another tool
+        let info =
+            Byml::from_binary(decompress_if(&fixture("Actor/ActorInfo.product.sbyml"))).unwrap();
+        write(
+            "Actor/ActorInfo.product.sbyml",
+            &info.to_binary(roead::Endian::Big),
+        );
+        write("Model/Copied.sbfres", b"stock model");
+        write("Model/New.sbfres", b"new model");
+
+        let dest = tmp.path().join("mod.zip");
+        ModPacker::new(
+            &source,
+            &dest,
+            Some(Meta {
+                api: env!("CARGO_PKG_VERSION").into(),
+                format: FORMAT_VERSION,
+                platform: ModPlatform::Specific(Endian::Big),
+                name: "Stock Copies".into(),
+                version: "1.0.0".into(),
+                category: Default::default(),
+                author: Default::default(),
+                description: Default::default(),
+                masters: IndexMap::default(),
+                url: None,
+                options: vec![],
+            }),
+            vec![Arc::new(ResourceReader::from_memory(stock))],
+        )
+        .unwrap()
+        .pack()
+        .unwrap();
+
+        let reader = crate::unpack::ModReader::open(&dest, vec![]).unwrap();
+        assert_eq!(
+            reader
+                .manifest
+                .content_files
+                .iter()
+                .map(|f| f.as_str())
+                .collect::<Vec<_>>(),
+            ["Chemical/system.bchmres", "Model/New.sbfres"]
+        );
+        assert!(reader
+            .get_data(Path::new("Actor/ActorInfo.product.byml"))
+            .is_err());
+    }
+
+    /// Writes a source folder with one movie large enough to be streamed,
+    /// filled with bytes which CBOR encodes both ways.
+    fn movie_source(dir: &Path) -> Vec<u8> {
+        let movie: Vec<u8> = (0..3 * STREAM_CHUNK as u32 + 1234)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let path = dir.join("content/Movie/Custom.mp4");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, &movie).unwrap();
+        movie
+    }
+
+    fn movie_meta() -> Meta {
+        Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: FORMAT_VERSION,
+            platform: ModPlatform::Specific(Endian::Big),
+            name: "Movie".into(),
+            version: "1.0.0".into(),
+            category: Default::default(),
+            author: Default::default(),
+            description: Default::default(),
+            masters: Default::default(),
+            url: None,
+            options: vec![],
+        }
+    }
+
+    #[test]
+    fn binary_encoding() {
+        for len in [0, 23, 24, 255, 256, 70000] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let mut encoded = binary_header(len).unwrap();
+            encode_bytes(&data, &mut encoded);
+            assert_eq!(
+                encoded,
+                minicbor_ser::to_vec(&ResourceData::Binary(data)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn streamed_binary() {
+        use uk_reader::ResourceLoader;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("source");
+        let movie = movie_source(&source);
+        let pack = |threshold: u64, dest: &str| {
+            let packed = ModPacker::new(&source, tmp.path().join(dest), Some(movie_meta()), vec![])
+                .unwrap()
+                .with_stream_threshold(threshold)
+                .pack()
+                .unwrap();
+            crate::unpack::ModReader::open(packed, vec![]).unwrap()
+        };
+        let streamed = pack(STREAM_CHUNK as u64, "streamed.zip");
+        let read = pack(u64::MAX, "read.zip");
+        assert_eq!(streamed.manifest, read.manifest);
+        let data = streamed.get_data(Path::new("Movie/Custom.mp4")).unwrap();
+        assert_eq!(data, read.get_data(Path::new("Movie/Custom.mp4")).unwrap());
+        assert_eq!(
+            minicbor_ser::from_slice::<ResourceData>(&data).unwrap(),
+            ResourceData::Binary(movie)
+        );
+    }
+
+    #[test]
+    fn cancel_packing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("source");
+        let movie = movie_source(&source);
+        let dest = tmp.path().join("movie.zip");
+        let cancel = Arc::new(AtomicBool::new(false));
+        let packer = ModPacker::new(&source, &dest, Some(movie_meta()), vec![])
+            .unwrap()
+            .with_cancel(cancel.clone());
+        // Partway through a file
+        cancel.store(true, Ordering::Relaxed);
+        let progress = Progress {
+            files: 1,
+            total: movie.len() as u64,
+            done:  AtomicU64::new(0),
+            stage: None,
+        };
+        assert!(packer
+            .stream_binary("Movie/Custom.mp4", &movie, &progress)
+            .is_err());
+        assert!(!packer.built_resources.contains("Movie/Custom.mp4"));
+        // Or before it starts, when the unfinished package is removed
+        assert!(packer.pack().is_err());
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn option_images() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("options/red");
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(find_thumbnail(&dir, &["png"]), None);
+
+        let small = dir.join("preview.png");
+        image::RgbaImage::new(64, 32).save(&small).unwrap();
+        assert_eq!(find_thumbnail(&dir, &["jpg", "png"]), Some(small.clone()));
+        let (data, ext) = prepare_option_image(&small).unwrap();
+        assert_eq!(ext, "png");
+        assert_eq!(data, std::fs::read(&small).unwrap());
+
+        let large = dir.join("large.jpg");
+        image::RgbImage::new(2048, 1024).save(&large).unwrap();
+        let (data, ext) = prepare_option_image(&large).unwrap();
+        assert_eq!(ext, "jpg");
+        let scaled = image::load_from_memory(&data).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (512, 256));
+
+        let broken = dir.join("broken.png");
+        std::fs::write(&broken, b"not an image").unwrap();
+        assert!(prepare_option_image(&broken).is_err());
+    }
+
+    #[test]
+    fn target_format() {
+        use uk_reader::ResourceLoader;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("source");
+        for (file, data) in [
+            ("content/Movie/Custom.mp4", b"not a movie".as_slice()),
+            ("options/red/content/Movie/Red.mp4", b"not a red movie"),
+            (README, b"# Movie"),
+            ("docs/install.md", b"Install it"),
+            ("passthrough/extras/notes.txt", b"Notes"),
+        ] {
+            let path = source.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        image::RgbaImage::new(8, 8)
+            .save(source.join("options/red/preview.png"))
+            .unwrap();
+        let meta = || {
+            Meta {
+                options: vec![OptionGroup::Multiple(MultipleOptionGroup {
+                    options: vec![ModOption {
+                        name: "Red".into(),
+                        description: Default::default(),
+                        path: "red".into(),
+                        requires: vec![],
+                        image: None,
+                    }],
+                    ..Default::default()
+                })],
+                ..movie_meta()
+            }
+        };
+        let packer = |target: u32, meta: Meta, dest: &str| {
+            ModPacker::new(&source, tmp.path().join(dest), Some(meta), vec![])
+                .unwrap()
+                .with_target_format(target)
+                .unwrap()
+        };
+        let pack = |target: u32| {
+            let (path, downgrades) = packer(target, meta(), &format!("v{target}.zip"))
+                .pack_report()
+                .unwrap();
+            let reader = crate::unpack::ModReader::open(path, vec![]).unwrap();
+            let features = reader.format_features().unwrap();
+            (reader, features, downgrades)
+        };
+
+        let (current, features, downgrades) = pack(FORMAT_VERSION);
+        assert!(downgrades.is_empty());
+        assert_eq!(current.meta.format, FORMAT_VERSION);
+        assert_eq!(
+            features,
+            [Feature::Docs, Feature::OptionImages, Feature::Passthrough].into()
+        );
+        assert_eq!(current.required_format().unwrap(), 3);
+
+        // Readable by a version 2 reader, with the newer files left out
+        let (older, features, downgrades) = pack(2);
+        assert_eq!(older.meta.format, 2);
+        assert!(features.is_empty());
+        assert!(older.required_format().unwrap() <= 2);
+        assert!(older.readme().is_none());
+        assert!(older.passthrough_files().is_empty());
+        assert!(older.get_data(Path::new("Movie/Custom.mp4")).is_ok());
+        let omitted: BTreeSet<_> = downgrades.iter().map(|d| d.feature).collect();
+        assert_eq!(
+            omitted,
+            [Feature::Docs, Feature::OptionImages, Feature::Passthrough].into()
+        );
+        assert_eq!(downgrades.len(), 4);
+        assert!(downgrades[0].to_string().contains("version 3"));
+
+        // Parameter archives read leniently are stored whole, as before
+        let lenient = ResourceData::Mergeable(MergeableResource::LenientAamp(Box::new(
+            uk_content::util::lenient::LenientAamp {
+                pio: roead::aamp::ParameterIO::new(),
+                opaque: [(vec![1, 2, 3], 0x40)].into(),
+                format_version: 2,
+            },
+        )));
+        let data = b"AAMP with unknown types".to_vec();
+        let packer_v2 = packer(2, meta(), "lenient-v2.zip");
+        match packer_v2.fit_resource("Actor/Odd.bxml", lenient.clone(), &data) {
+            ResourceData::Mergeable(MergeableResource::BinaryOverride(v)) => assert_eq!(v.0, data),
+            other => panic!("Expected a binary override, found {other:?}"),
+        }
+        assert_eq!(
+            packer_v2.downgrades.lock()[0].feature,
+            Feature::LenientParameters
+        );
+        let packer_v4 = packer(FORMAT_VERSION, meta(), "lenient.zip");
+        assert_eq!(
+            packer_v4.fit_resource("Actor/Odd.bxml", lenient.clone(), &data),
+            lenient
+        );
+
+        // Resources diffed by key are stored whole for older readers
+        use roead::byml::{map, Byml};
+        let event = |files: &[&str]| {
+            let subfiles = files
+                .iter()
+                .map(|f| map!("file" => Byml::String((*f).into())))
+                .collect::<Byml>();
+            MergeableResource::EventInfo(Box::new(
+                map!("Event" => map!("subfile" => subfiles)).into(),
+            ))
+        };
+        let (base, modded) = (event(&["A.bfevfl"]), event(&["A.bfevfl", "B.bfevfl"]));
+        let diff = base.diff(&modded);
+        assert!(diff.is_keyed());
+        match packer_v2.fit_diff("Event/EventInfo.product.byml", &modded, diff.clone()) {
+            MergeableResource::BinaryOverride(v) => {
+                assert_eq!(v.0, modded.clone().into_binary(packer_v2.endian))
+            }
+            other => panic!("Expected a binary override, found {other:?}"),
+        }
+        assert_eq!(packer_v2.downgrades.lock()[1].feature, Feature::KeyedByml);
+        assert_eq!(
+            packer_v4.fit_diff("Event/EventInfo.product.byml", &modded, diff.clone()),
+            diff
+        );
+        // So are parameter diffs which delete anything
+        let params = |names: &[&str]| {
+            MergeableResource::GenericAamp(Box::new(roead::aamp::ParameterIO::new().with_object(
+                "Params",
+                names.iter().fold(roead::aamp::ParameterObject::new(), |obj, name| {
+                    obj.with_parameter(*name, roead::aamp::Parameter::I32(1))
+                }),
+            )))
+        };
+        let (base, modded) = (params(&["A", "B"]), params(&["A"]));
+        let diff = base.diff(&modded);
+        assert!(format::has_deletions(&minicbor_ser::to_vec(&diff).unwrap()));
+        assert!(matches!(
+            packer_v2.fit_diff("Actor/Odd.bxml", &modded, diff.clone()),
+            MergeableResource::BinaryOverride(_)
+        ));
+        assert_eq!(
+            packer_v2.downgrades.lock()[2].feature,
+            Feature::ParameterDeletions
+        );
+        assert_eq!(packer_v4.fit_diff("Actor/Odd.bxml", &modded, diff.clone()), diff);
+        let (base, modded) = (params(&["A"]), params(&["A", "B"]));
+        assert!(!format::has_deletions(
+            &minicbor_ser::to_vec(&base.diff(&modded)).unwrap()
+        ));
+
+        // Dual-platform mods cannot do without their payloads
+        let dual = Meta {
+            platform: ModPlatform::Dual,
+            ..meta()
+        };
+        assert!(packer(1, dual, "dual.zip").pack().is_err());
+        assert!(packer(2, meta(), "zero.zip").with_target_format(0).is_err());
+    }
 }
@@ -0,0 +1,401 @@
+//! Overrides for how particular files are merged. Structured merging is
+//! usually what users want, but a mod which replaces a whole file on purpose
+//! can be broken by fragments of other mods merged into it, so power users
+//! can choose a different policy by path or resource type.
+//!
+//! Rules are matched against canonical paths. A rule for an exact path wins
+//! over any glob, the first matching glob wins over a rule for a resource
+//! type, and files no rule matches are merged as usual.
+use std::{fmt, path::Path, str::FromStr};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use uk_content::canonicalize;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MergePolicy {
+    /// Merge every mod's changes, as if there were no override.
+    #[default]
+    Merge,
+    /// Use only the highest priority mod's copy.
+    PriorityBinary,
+    /// Ignore mods and use the stock file, leaving it out if there is none.
+    Skip,
+}
+
+impl MergePolicy {
+    pub const ALL: [MergePolicy; 3] = [
+        MergePolicy::Merge,
+        MergePolicy::PriorityBinary,
+        MergePolicy::Skip,
+    ];
+}
+
+impl fmt::Display for MergePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MergePolicy::Merge => "Merge",
+            MergePolicy::PriorityBinary => "Priority Wins",
+            MergePolicy::Skip => "Skip",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// A canonical path, a glob over canonical paths, or a resource type
+    /// name such as `AIProgram`, `Sarc` or `Binary`.
+    pub pattern: String,
+    pub policy:  MergePolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`, any one character in a folder or file name
+    One,
+    /// `*`, any run of characters in a folder or file name
+    Star,
+    /// `**`, any number of whole folders, with the slash after it
+    Globstar,
+    Class {
+        negated: bool,
+        ranges:  Vec<(char, char)>,
+    },
+}
+
+/// A parsed glob over canonical paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob(Vec<Token>);
+
+impl Glob {
+    pub fn matches(&self, canon: &str) -> bool {
+        glob_matches(&self.0, &canon.chars().collect::<Vec<_>>())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Path(String),
+    Glob(Glob),
+    Type(String),
+}
+
+fn parse_class(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Token> {
+    let negated = chars.next_if(|c| *c == '!' || *c == '^').is_some();
+    let mut ranges = vec![];
+    // A `]` straight after the opening bracket is part of the class
+    let mut first = true;
+    loop {
+        let c = chars.next().context("Unclosed [ in pattern")?;
+        match c {
+            ']' if !first => break,
+            '/' => anyhow_ext::bail!("A [ ] group cannot match a /"),
+            c => {
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    match chars.next() {
+                        Some(']') => {
+                            ranges.push((c, c));
+                            ranges.push(('-', '-'));
+                            break;
+                        }
+                        Some(end) if end >= c => ranges.push((c, end)),
+                        Some(end) => anyhow_ext::bail!("Range {c}-{end} in pattern is backwards"),
+                        None => anyhow_ext::bail!("Unclosed [ in pattern"),
+                    }
+                } else {
+                    ranges.push((c, c));
+                }
+            }
+        }
+        first = false;
+    }
+    Ok(Token::Class { negated, ranges })
+}
+
+fn parse_glob(pattern: &str) -> Result<Glob> {
+    let mut tokens = vec![];
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let whole = matches!(tokens.last(), None | Some(Token::Char('/')))
+                    && matches!(chars.peek(), None | Some('/'));
+                if !whole {
+                    anyhow_ext::bail!("** must be a whole folder name, as in Actor/**/*.baiprog");
+                }
+                chars.next();
+                tokens.push(Token::Globstar);
+            }
+            '*' => tokens.push(Token::Star),
+            '?' => tokens.push(Token::One),
+            '[' => tokens.push(parse_class(&mut chars)?),
+            ']' => anyhow_ext::bail!("Unmatched ] in pattern"),
+            c => tokens.push(Token::Char(c)),
+        }
+    }
+    Ok(Glob(tokens))
+}
+
+fn glob_matches(tokens: &[Token], path: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return path.is_empty();
+    };
+    match token {
+        Token::Star => {
+            (0..=path.len())
+                .take_while(|&i| i == 0 || path[i - 1] != '/')
+                .any(|i| glob_matches(rest, &path[i..]))
+        }
+        Token::Globstar => {
+            rest.is_empty()
+                || (0..=path.len())
+                    .filter(|&i| i == 0 || path[i - 1] == '/')
+                    .any(|i| glob_matches(rest, &path[i..]))
+        }
+        Token::One => path.first().is_some_and(|c| *c != '/') && glob_matches(rest, &path[1..]),
+        Token::Char(c) => path.first() == Some(c) && glob_matches(rest, &path[1..]),
+        Token::Class { negated, ranges } => {
+            path.first().is_some_and(|c| {
+                *c != '/' && ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(c)) != *negated
+            }) && glob_matches(rest, &path[1..])
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow_ext::Error;
+
+    /// Parses and checks a pattern. Patterns with `*`, `?` or `[` are globs,
+    /// other patterns with a `/` or `.` are paths, and anything else is a
+    /// resource type name.
+    fn from_str(pattern: &str) -> Result<Self> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            anyhow_ext::bail!("Pattern is empty");
+        } else if pattern.contains('\\') {
+            anyhow_ext::bail!("Use / to separate folders");
+        } else if pattern.starts_with('/') {
+            anyhow_ext::bail!("Patterns match canonical paths, which do not start with /");
+        }
+        if pattern.contains(['*', '?', '[', ']']) {
+            parse_glob(pattern).map(Pattern::Glob)
+        } else if pattern.contains(['/', '.']) {
+            Ok(Pattern::Path(canonicalize(pattern).into()))
+        } else if pattern.chars().all(|c| c.is_ascii_alphanumeric()) {
+            Ok(Pattern::Type(pattern.into()))
+        } else {
+            anyhow_ext::bail!("{pattern} is not a path, glob or resource type name")
+        }
+    }
+}
+
+/// A table of merge policy overrides, checked when it is created or loaded.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<PolicyRule>", into = "Vec<PolicyRule>")]
+pub struct MergePolicies {
+    rules: Vec<(PolicyRule, Pattern)>,
+}
+
+impl TryFrom<Vec<PolicyRule>> for MergePolicies {
+    type Error = anyhow_ext::Error;
+
+    fn try_from(rules: Vec<PolicyRule>) -> Result<Self> {
+        Ok(Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| {
+                    rule.pattern
+                        .parse()
+                        .with_context(|| format!("Invalid merge policy pattern {}", rule.pattern))
+                        .map(|pattern| (rule, pattern))
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl From<MergePolicies> for Vec<PolicyRule> {
+    fn from(policies: MergePolicies) -> Self {
+        policies.rules.into_iter().map(|(rule, _)| rule).collect()
+    }
+}
+
+impl MergePolicies {
+    pub fn new(rules: Vec<PolicyRule>) -> Result<Self> {
+        rules.try_into()
+    }
+
+    /// Reads a table saved with [`MergePolicies::save`]. A missing file is an
+    /// empty table.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(&fs::read_to_string(path)?)
+            .with_context(|| format!("Failed to read merge policies from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &PolicyRule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// Finds the rule which decides how a file is merged, given its
+    /// canonical path and the type of its stock or first copy.
+    pub fn resolve(&self, canon: &str, kind: &str) -> Option<&PolicyRule> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let find = |matches: &dyn Fn(&Pattern) -> bool| {
+            self.rules
+                .iter()
+                .find(|(_, pattern)| matches(pattern))
+                .map(|(rule, _)| rule)
+        };
+        find(&|pattern| matches!(pattern, Pattern::Path(p) if p == canon))
+            .or_else(|| find(&|pattern| matches!(pattern, Pattern::Glob(g) if g.matches(canon))))
+            .or_else(|| {
+                find(&|pattern| matches!(pattern, Pattern::Type(t) if t.eq_ignore_ascii_case(kind)))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, policy: MergePolicy) -> PolicyRule {
+        PolicyRule {
+            pattern: pattern.into(),
+            policy,
+        }
+    }
+
+    fn glob(pattern: &str, path: &str) -> bool {
+        let Ok(Pattern::Glob(glob)) = pattern.parse() else {
+            panic!("{pattern} is not a glob");
+        };
+        glob.matches(path)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob(
+            "Actor/*/Guardian_A.baiprog",
+            "Actor/AIProgram/Guardian_A.baiprog"
+        ));
+        assert!(!glob(
+            "Actor/*.baiprog",
+            "Actor/AIProgram/Guardian_A.baiprog"
+        ));
+        assert!(glob(
+            "Actor/**/*.baiprog",
+            "Actor/AIProgram/Guardian_A.baiprog"
+        ));
+        assert!(glob("Actor/**/*.baiprog", "Actor/Guardian_A.baiprog"));
+        assert!(glob("**/*.baiprog", "Actor/AIProgram/Guardian_A.baiprog"));
+        assert!(glob("Map/**", "Map/MainField/D-3/D-3_Dynamic.mubin"));
+        assert!(!glob("Map/**", "Mapping/Test.byml"));
+        assert!(glob(
+            "Map/MainField/?-3/*",
+            "Map/MainField/D-3/D-3_Static.mubin"
+        ));
+        assert!(!glob(
+            "Map/MainField/?-3/*",
+            "Map/MainField/DD-3/D-3_Static.mubin"
+        ));
+        assert!(glob("Map/MainField/[A-D]-[!4]/*", "Map/MainField/D-3/Test"));
+        assert!(!glob(
+            "Map/MainField/[A-D]-[!4]/*",
+            "Map/MainField/E-3/Test"
+        ));
+        assert!(!glob(
+            "Map/MainField/[A-D]-[!4]/*",
+            "Map/MainField/D-4/Test"
+        ));
+        assert!(glob("[]a]*", "]test"));
+        assert!(glob("[a-]*", "-test"));
+    }
+
+    #[test]
+    fn validation() {
+        for bad in [
+            "",
+            "  ",
+            "Actor\\AIProgram\\*.baiprog",
+            "/Actor/*",
+            "Actor/[AB",
+            "Actor/B]*",
+            "Actor/[z-a]*",
+            "Actor/Foo**/*",
+            "Actor/**Bar",
+            "Some type",
+        ] {
+            assert!(bad.parse::<Pattern>().is_err(), "{bad}");
+        }
+        assert_eq!(
+            "content/Actor/Pack/Test.sbactorpack"
+                .parse::<Pattern>()
+                .unwrap(),
+            Pattern::Path("Actor/Pack/Test.bactorpack".into())
+        );
+        assert_eq!(
+            "AIProgram".parse::<Pattern>().unwrap(),
+            Pattern::Type("AIProgram".into())
+        );
+        assert!(MergePolicies::new(vec![rule("Actor/[AB", MergePolicy::Skip)]).is_err());
+        assert!(
+            serde_yaml::from_str::<MergePolicies>("- pattern: /Actor\n  policy: Skip").is_err()
+        );
+    }
+
+    #[test]
+    fn resolution() {
+        const AIPROG: &str = "Actor/AIProgram/Guardian_A.baiprog";
+        let policies = MergePolicies::new(vec![
+            rule("AIProgram", MergePolicy::PriorityBinary),
+            rule("Actor/AIProgram/*.baiprog", MergePolicy::Skip),
+            rule("Actor/**", MergePolicy::PriorityBinary),
+            rule(AIPROG, MergePolicy::Merge),
+        ])
+        .unwrap();
+        let policy = |canon: &str, kind: &str| policies.resolve(canon, kind).map(|r| r.policy);
+        // Exact path, then the first glob, then type
+        assert_eq!(policy(AIPROG, "AIProgram"), Some(MergePolicy::Merge));
+        assert_eq!(
+            policy("Actor/AIProgram/Other.baiprog", "AIProgram"),
+            Some(MergePolicy::Skip)
+        );
+        assert_eq!(
+            policy("Actor/ActorLink/Other.bxml", "ActorLink"),
+            Some(MergePolicy::PriorityBinary)
+        );
+        assert_eq!(
+            policy("Other/Test.baiprog", "aiprogram"),
+            Some(MergePolicy::PriorityBinary)
+        );
+        assert_eq!(policy("Map/Test.smubin", "MapUnit"), None);
+        assert_eq!(MergePolicies::default().resolve(AIPROG, "AIProgram"), None);
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("merge_policy.yml");
+        assert_eq!(
+            MergePolicies::load(&path).unwrap(),
+            MergePolicies::default()
+        );
+        policies.save(&path).unwrap();
+        assert_eq!(MergePolicies::load(&path).unwrap(), policies);
+    }
+}
@@ -0,0 +1,300 @@
+//! Progress through long operations, like converting a BNP, which are made of
+//! stages of very different lengths. Each stage has a weight for its share of
+//! the whole and counts its own units of work: bytes for stages which read or
+//! write files, items for ones which work through a list. Stages keep their
+//! own counts, so work can move back and forth between them, as it does when
+//! a BNP has options.
+//!
+//! Reports are logged at trace level with a `STAGE` prefix for the busy
+//! dialog to show, along with an estimate of the time left from the smoothed
+//! rate of progress. Dropping the tracker logs `CLEARSTAGE`.
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+/// How far each new rate sample moves the estimate, so a few slow or fast
+/// files do not make it jump around.
+const SMOOTHING: f64 = 0.2;
+/// The shortest time between rate samples.
+const SAMPLE_EVERY: Duration = Duration::from_millis(500);
+/// How long to wait before estimating at all.
+const WARMUP: Duration = Duration::from_secs(3);
+/// The smallest change in overall progress worth reporting.
+const REPORT_STEP: f64 = 0.005;
+
+/// Estimates the time left from an exponentially smoothed rate of progress.
+#[derive(Debug, Default)]
+pub struct Eta {
+    start: Option<Instant>,
+    /// The last sample, as when it was taken and the fraction then done.
+    last:  Option<(Instant, f64)>,
+    /// Fraction done per second.
+    rate:  Option<f64>,
+}
+
+impl Eta {
+    /// Takes the overall fraction done at a time, returning the time left
+    /// once there has been enough progress to tell.
+    pub fn update(&mut self, fraction: f64, now: Instant) -> Option<Duration> {
+        let start = *self.start.get_or_insert(now);
+        match self.last {
+            None => self.last = Some((now, fraction)),
+            Some((time, done)) => {
+                let elapsed = now.saturating_duration_since(time);
+                if elapsed >= SAMPLE_EVERY {
+                    let sample = (fraction - done).max(0.0) / elapsed.as_secs_f64();
+                    self.rate = Some(match self.rate {
+                        Some(rate) => rate + SMOOTHING * (sample - rate),
+                        None => sample,
+                    });
+                    self.last = Some((now, fraction));
+                }
+            }
+        }
+        if now.saturating_duration_since(start) < WARMUP {
+            return None;
+        }
+        let rate = self.rate.filter(|rate| *rate > 0.0)?;
+        Duration::try_from_secs_f64((1.0 - fraction).max(0.0) / rate).ok()
+    }
+}
+
+/// Where an operation is, as logged for the busy dialog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub stage: String,
+    /// The overall fraction done, from 0 to 1.
+    pub fraction: f64,
+    pub eta: Option<Duration>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{:.4}\t", self.stage, self.fraction)?;
+        match self.eta {
+            Some(eta) => write!(f, "{}", eta.as_secs()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Report {
+    type Err = anyhow_ext::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('\t');
+        let (Some(stage), Some(fraction), Some(eta)) = (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow_ext::bail!("Bad progress report: {s}");
+        };
+        Ok(Self {
+            stage: stage.into(),
+            fraction: fraction.parse()?,
+            eta: (!eta.is_empty())
+                .then(|| eta.parse().map(Duration::from_secs))
+                .transpose()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    /// Units done and in total for each stage.
+    counts: Vec<(u64, u64)>,
+    eta: Eta,
+    /// The stage and fraction last reported.
+    reported: Option<(usize, f64)>,
+}
+
+/// Tracks progress through weighted stages.
+#[derive(Debug)]
+pub struct Tracker {
+    stages: Vec<(&'static str, f64)>,
+    state:  Mutex<State>,
+}
+
+impl Tracker {
+    /// Takes the name and weight of each stage. Weights are relative to each
+    /// other, so they need not add up to anything in particular.
+    pub fn new(stages: &[(&'static str, f64)]) -> Self {
+        Self {
+            stages: stages.to_vec(),
+            state:  Mutex::new(State {
+                counts: vec![(0, 0); stages.len()],
+                eta: Eta::default(),
+                reported: None,
+            }),
+        }
+    }
+
+    fn index(&self, stage: &str) -> Option<usize> {
+        let index = self.stages.iter().position(|(name, _)| *name == stage);
+        if index.is_none() {
+            log::debug!("No progress stage named {stage}");
+        }
+        index
+    }
+
+    fn fraction(&self, state: &State) -> f64 {
+        let total: f64 = self.stages.iter().map(|(_, weight)| weight).sum();
+        let done: f64 = self
+            .stages
+            .iter()
+            .zip(&state.counts)
+            .map(|((_, weight), (done, of))| {
+                match of {
+                    0 => 0.0,
+                    of => weight * (*done as f64 / *of as f64).min(1.0),
+                }
+            })
+            .sum();
+        if total > 0.0 {
+            done / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Updates a stage's counts, returning a report if it is worth logging.
+    fn update(&self, stage: &str, now: Instant, f: impl FnOnce(&mut (u64, u64))) -> Option<Report> {
+        let index = self.index(stage)?;
+        let mut state = self.state.lock();
+        f(&mut state.counts[index]);
+        let fraction = self.fraction(&state);
+        let eta = state.eta.update(fraction, now);
+        let due = match state.reported {
+            Some((stage, last)) => stage != index || fraction - last >= REPORT_STEP,
+            None => true,
+        };
+        due.then(|| {
+            state.reported = Some((index, fraction));
+            Report {
+                stage: stage.into(),
+                fraction,
+                eta,
+            }
+        })
+    }
+
+    fn log(report: Option<Report>) {
+        if let Some(report) = report {
+            log::trace!("STAGE{report}");
+        }
+    }
+
+    /// Sets how many units of work a stage has.
+    pub fn set_total(&self, stage: &str, total: u64) {
+        Self::log(self.update(stage, Instant::now(), |counts| counts.1 = total));
+    }
+
+    /// Adds to the work a stage has, for stages which find more as they go.
+    pub fn add_total(&self, stage: &str, more: u64) {
+        Self::log(self.update(stage, Instant::now(), |counts| counts.1 += more));
+    }
+
+    pub fn advance(&self, stage: &str, units: u64) {
+        Self::log(self.update(stage, Instant::now(), |counts| counts.0 += units));
+    }
+
+    /// Marks a stage as done, for stages whose progress cannot be measured.
+    pub fn complete(&self, stage: &str) {
+        Self::log(self.update(stage, Instant::now(), |counts| {
+            counts.1 = counts.1.max(1);
+            counts.0 = counts.1;
+        }));
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        log::trace!("CLEARSTAGE");
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights() {
+        let tracker = Tracker::new(&[("Extracting", 1.0), ("Logs", 1.0), ("Packing", 2.0)]);
+        let now = Instant::now();
+        tracker.update("Extracting", now, |c| c.1 = 1000);
+        tracker.update("Logs", now, |c| c.1 = 4);
+        tracker.update("Extracting", now, |c| c.0 = 500);
+        assert_eq!(tracker.fraction(&tracker.state.lock()), 0.125);
+        tracker.update("Extracting", now, |c| c.0 = 1000);
+        tracker.update("Logs", now, |c| c.0 = 2);
+        assert_eq!(tracker.fraction(&tracker.state.lock()), 0.375);
+        // A stage with no total yet counts for nothing
+        tracker.update("Packing", now, |c| c.0 = 10);
+        assert_eq!(tracker.fraction(&tracker.state.lock()), 0.375);
+        tracker.complete("Packing");
+        tracker.update("Logs", now, |c| c.0 = 8);
+        assert_eq!(tracker.fraction(&tracker.state.lock()), 1.0);
+        assert!(tracker.update("Unknown", now, |c| c.0 = 1).is_none());
+    }
+
+    #[test]
+    fn reports() {
+        let tracker = Tracker::new(&[("Extracting", 1.0), ("Packing", 1.0)]);
+        let now = Instant::now();
+        let report = tracker.update("Extracting", now, |c| c.1 = 1000).unwrap();
+        assert_eq!(report.stage, "Extracting");
+        // Too small a step to report
+        assert!(tracker.update("Extracting", now, |c| c.0 = 1).is_none());
+        assert!(tracker.update("Extracting", now, |c| c.0 = 20).is_some());
+        // Moving to another stage always reports
+        assert!(tracker.update("Packing", now, |c| c.1 = 1).is_some());
+        let report = Report {
+            stage: "Packing".into(),
+            fraction: 0.5,
+            eta: Some(Duration::from_secs(90)),
+        };
+        assert_eq!(report.to_string().parse::<Report>().unwrap(), report);
+        let report = Report {
+            eta: None,
+            ..report
+        };
+        assert_eq!(report.to_string().parse::<Report>().unwrap(), report);
+        assert!("Packing".parse::<Report>().is_err());
+    }
+
+    #[test]
+    fn eta_smoothing() {
+        let mut eta = Eta::default();
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        // Steady progress of 1% a second
+        for sec in 0..3 {
+            assert_eq!(eta.update(sec as f64 / 100.0, at(sec * 1000)), None);
+        }
+        let steady = eta.update(0.03, at(3000)).unwrap();
+        assert!((steady.as_secs_f64() - 97.0).abs() < 0.01, "{steady:?}");
+        // Updates closer together than a sample do not move the rate
+        let between = eta.update(0.0305, at(3100)).unwrap();
+        assert!((between.as_secs_f64() - 96.95).abs() < 0.01, "{between:?}");
+        // A sudden burst only moves the estimate part of the way
+        let burst = eta.update(0.13, at(4000)).unwrap();
+        assert!(burst < steady);
+        let jumpy = Duration::from_secs_f64(0.87 / 0.1);
+        assert!(burst > jumpy * 2, "{burst:?}");
+        // Then it settles back towards the steady rate
+        let mut fraction = 0.13;
+        let mut last = burst;
+        for sec in 5..30 {
+            fraction += 0.01;
+            last = eta.update(fraction, at(sec * 1000)).unwrap();
+        }
+        let left = (1.0 - fraction) * 100.0;
+        assert!((last.as_secs_f64() - left).abs() < left * 0.05, "{last:?}");
+        // Stalling stretches the estimate rather than ending it
+        let stalled = eta.update(fraction, at(31_000)).unwrap();
+        assert!(stalled > last);
+    }
+}
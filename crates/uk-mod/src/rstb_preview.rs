@@ -0,0 +1,216 @@
+//! RSTB values for single resources, so an edited file can be checked
+//! against the stock table before it is packaged or merged. Values are
+//! computed the same way the unpacker computes them when merging.
+use std::{collections::BTreeMap, hash::Hasher};
+
+use anyhow_ext::{Context, Result};
+use rstb::ResourceSizeTable;
+use rustc_hash::FxHasher;
+use smartstring::alias::String;
+use uk_content::{prelude::Endian, resource::ResourceData};
+
+use crate::unpack::can_rstb;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RstbImpact {
+    /// The value in the stock RSTB, if the resource has an entry.
+    pub stock:    Option<u32>,
+    /// The value needed for the resource as it is now. `None` means it cannot
+    /// be calculated and has to be set by hand.
+    pub computed: Option<u32>,
+}
+
+impl RstbImpact {
+    /// The computed value is larger than the stock entry.
+    pub fn needs_bump(&self) -> bool {
+        matches!((self.stock, self.computed), (Some(stock), Some(computed)) if computed > stock)
+    }
+
+    /// No value could be calculated, so the entry needs a manual value.
+    pub fn needs_manual(&self) -> bool {
+        self.computed.is_none()
+    }
+
+    pub fn has_warning(&self) -> bool {
+        self.needs_bump() || self.needs_manual()
+    }
+}
+
+/// Calculates the RSTB value for a resource from its binary data. Returns
+/// `Ok(None)` for resources the unpacker never writes RSTB entries for.
+pub fn compute(canon: &str, data: &[u8], endian: Endian) -> Result<Option<Option<u32>>> {
+    if !can_rstb(canon) {
+        return Ok(None);
+    }
+    let data = roead::yaz0::decompress_if(data);
+    let value = match ResourceData::from_binary(canon, data.as_ref())
+        .with_context(|| format!("Failed to parse {canon} for RSTB calculation"))?
+    {
+        ResourceData::Binary(_) => {
+            rstb::calc::estimate_from_slice_and_name(&data, canon, endian.into())
+        }
+        ResourceData::Mergeable(_) => {
+            let value = rstb::calc::estimate_from_slice_and_name(&data, canon, endian.into());
+            if canon.ends_with("bphysics") || endian == Endian::Little {
+                value.map(|v| (v as f32 * 1.25) as u32)
+            } else {
+                value
+            }
+        }
+        ResourceData::Sarc(_) => {
+            rstb::calc::calc_from_size_and_name(data.len(), canon, endian.into())
+        }
+    };
+    Ok(Some(value))
+}
+
+fn hash(data: &[u8]) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// RSTB values for every resource in a project, recomputed only for
+/// resources whose data changed since they were last checked.
+pub struct RstbPreview {
+    endian:  Endian,
+    stock:   ResourceSizeTable,
+    entries: BTreeMap<String, (u64, RstbImpact)>,
+}
+
+impl std::fmt::Debug for RstbPreview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RstbPreview")
+            .field("endian", &self.endian)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl RstbPreview {
+    pub fn new(endian: Endian) -> Self {
+        Self {
+            endian,
+            stock: ResourceSizeTable::new_from_stock(endian.into()),
+            entries: Default::default(),
+        }
+    }
+
+    pub fn stock(&self, canon: &str) -> Option<u32> {
+        self.stock.get(canon)
+    }
+
+    /// Checks a resource against the stock table, reusing the last result if
+    /// its data has not changed. Returns `None` for resources without RSTB
+    /// entries.
+    pub fn update(&mut self, canon: &str, data: &[u8]) -> Result<Option<RstbImpact>> {
+        let digest = hash(data);
+        if let Some((prev, impact)) = self.entries.get(canon) {
+            if *prev == digest {
+                return Ok(Some(*impact));
+            }
+        }
+        let Some(computed) = compute(canon, data, self.endian)? else {
+            self.entries.remove(canon);
+            return Ok(None);
+        };
+        let impact = RstbImpact {
+            stock: self.stock(canon),
+            computed,
+        };
+        self.entries.insert(canon.into(), (digest, impact));
+        Ok(Some(impact))
+    }
+
+    pub fn remove(&mut self, canon: &str) {
+        self.entries.remove(canon);
+    }
+
+    pub fn get(&self, canon: &str) -> Option<RstbImpact> {
+        self.entries.get(canon).map(|(_, impact)| *impact)
+    }
+
+    /// All checked resources, by canonical path.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, RstbImpact)> {
+        self.entries
+            .iter()
+            .map(|(canon, (_, impact))| (canon.as_str(), *impact))
+    }
+
+    /// Resources which need a larger or manual RSTB value.
+    pub fn warnings(&self) -> impl Iterator<Item = (&str, RstbImpact)> {
+        self.iter().filter(|(_, impact)| impact.has_warning())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(std::path::Path::new("../uk-content/test").join(name)).unwrap()
+    }
+
+    #[test]
+    fn impact() {
+        let impact = RstbImpact {
+            stock:    Some(100),
+            computed: Some(120),
+        };
+        assert!(impact.needs_bump());
+        assert!(!impact.needs_manual());
+        let impact = RstbImpact {
+            stock:    Some(100),
+            computed: Some(80),
+        };
+        assert!(!impact.has_warning());
+        let impact = RstbImpact {
+            stock:    None,
+            computed: None,
+        };
+        assert!(impact.needs_manual());
+        assert!(!impact.needs_bump());
+    }
+
+    #[test]
+    fn fixtures() {
+        let mut preview = RstbPreview::new(Endian::Big);
+        let canon = "Actor/Pack/Enemy_Guardian_A.bactorpack";
+        let stock = fixture("Actor/Pack/Enemy_Guardian_A.sbactorpack");
+        let impact = preview.update(canon, &stock).unwrap().unwrap();
+        assert_eq!(impact.stock, preview.stock(canon));
+        assert!(impact.stock.is_some());
+        assert!(impact.computed.is_some());
+        assert_eq!(
+            impact.computed,
+            compute(canon, &stock, Endian::Big).unwrap().unwrap()
+        );
+
+        // Unchanged data reuses the last result, edited data is recomputed
+        assert_eq!(preview.update(canon, &stock).unwrap(), Some(impact));
+        let modded = fixture("Actor/Pack/Enemy_Guardian_A_Mod.sbactorpack");
+        let modded_impact = preview.update(canon, &modded).unwrap().unwrap();
+        assert_eq!(
+            modded_impact.computed,
+            compute(canon, &modded, Endian::Big).unwrap().unwrap()
+        );
+        assert_eq!(preview.get(canon), Some(modded_impact));
+
+        let anim = "Actor/AS/Player_Wait.bas";
+        assert!(preview.update(anim, &fixture(anim)).unwrap().is_some());
+        assert_eq!(preview.iter().count(), 2);
+
+        // Excluded resources get no entry at all
+        assert_eq!(
+            preview
+                .update(
+                    "Actor/ActorInfo.product.byml",
+                    &fixture("Actor/ActorInfo.product.sbyml")
+                )
+                .unwrap(),
+            None
+        );
+        preview.remove(anim);
+        assert_eq!(preview.iter().count(), 1);
+    }
+}
@@ -0,0 +1,502 @@
+//! Splits a mod which bundles unrelated changes into several mods which can
+//! be toggled and ordered on their own. Every file the mod changes, down to
+//! files nested in SARCs, goes to exactly one part. A SARC with files in more
+//! than one part is stored in each of them, listing only that part's files,
+//! so the parts merged together give the same result as the original mod.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use uk_content::{canon::canonicalize_aoc, canonicalize, prelude::Endian, resource::ResourceData};
+use zip::{
+    write::{FileOptions, SimpleFileOptions},
+    ZipWriter,
+};
+
+use crate::{is_doc_path, pack::sanitise, platform_root, unpack::ModReader, Manifest, Meta};
+
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// Where a resource can be stored: shared, or in either platform's payload.
+const ROOTS: [&str; 3] = [
+    "",
+    platform_root(Endian::Big),
+    platform_root(Endian::Little),
+];
+
+/// A file changed by a mod.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SplitFile {
+    /// The file's path, joined to the SARCs it is nested in by `//`, like
+    /// `Pack/Bootup.pack//Actor/ActorInfo.product.sbyml`.
+    pub path: String,
+    pub aoc:  bool,
+}
+
+impl SplitFile {
+    /// The part of the game the file belongs to, for grouping files: the top
+    /// folder of the outermost file which is not a pack, like `Actor` for the
+    /// actor info in Bootup.pack.
+    pub fn area(&self) -> &str {
+        let file = self
+            .path
+            .split("//")
+            .find(|file| !file.starts_with("Pack/"))
+            .unwrap_or(&self.path);
+        file.split('/').next().unwrap_or(file)
+    }
+
+    /// The file listed in the mod's manifest which holds this one.
+    pub fn top(&self) -> &str {
+        self.path.split("//").next().unwrap_or(&self.path)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplitPart {
+    pub name:  String,
+    pub files: BTreeSet<SplitFile>,
+}
+
+#[inline]
+fn canon(name: &str, aoc: bool) -> String {
+    if aoc {
+        canonicalize_aoc(name).into()
+    } else {
+        canonicalize(name).into()
+    }
+}
+
+#[inline]
+fn stored_path(root: &str, canon: &str) -> String {
+    if root.is_empty() {
+        canon.into()
+    } else {
+        format!("{root}/{canon}")
+    }
+}
+
+/// Reads a stored file, which is compressed in zipped mods but not in
+/// unzipped ones.
+fn decode(mod_: &ModReader, data: Vec<u8>) -> Result<Vec<u8>> {
+    if data.starts_with(ZSTD_MAGIC) {
+        mod_.decompress(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Reads every stored copy of a resource, shared or for either platform.
+fn read_stored(mod_: &ModReader, canon: &str) -> Result<Vec<ResourceData>> {
+    ROOTS
+        .iter()
+        .filter_map(|root| mod_.read_raw(&stored_path(root, canon)))
+        .map(|data| {
+            minicbor_ser::from_slice(&decode(mod_, data)?)
+                .with_context(|| format!("Failed to parse {canon} from mod"))
+        })
+        .collect()
+}
+
+/// Lists every file a mod changes. Files nested in SARCs are listed on their
+/// own instead of the SARC holding them.
+pub fn files(mod_: &ModReader) -> Result<BTreeSet<SplitFile>> {
+    fn walk(
+        mod_: &ModReader,
+        path: String,
+        name: &str,
+        aoc: bool,
+        files: &mut BTreeSet<SplitFile>,
+    ) -> Result<()> {
+        let stored = read_stored(mod_, &canon(name, aoc))?;
+        // Mods only store the nested files they change
+        if stored.is_empty() && path.contains("//") {
+            return Ok(());
+        }
+        let children: BTreeSet<_> = stored
+            .iter()
+            .filter_map(|data| data.as_sarc())
+            .flat_map(|sarc| sarc.files.iter().cloned())
+            .collect();
+        let count = files.len();
+        for child in children {
+            walk(mod_, format!("{path}//{child}"), &child, aoc, files)?;
+        }
+        if files.len() == count {
+            files.insert(SplitFile { path, aoc });
+        }
+        Ok(())
+    }
+
+    let mut files = BTreeSet::new();
+    for (list, aoc) in [
+        (&mod_.manifest.content_files, false),
+        (&mod_.manifest.aoc_files, true),
+    ] {
+        for file in list {
+            walk(mod_, file.to_string(), file, aoc, &mut files)?;
+        }
+    }
+    Ok(files)
+}
+
+/// Limits a SARC to the files in `keep`. Files the SARC deletes are kept, so
+/// they stay deleted whichever parts are enabled.
+fn filter_sarc(data: Vec<u8>, keep: &BTreeSet<&str>) -> Result<Vec<u8>> {
+    let Some(mut sarc) = minicbor_ser::from_slice::<ResourceData>(&data)
+        .context("Failed to parse SARC")?
+        .take_sarc()
+    else {
+        return Ok(data);
+    };
+    sarc.files = sarc
+        .files
+        .iter_full()
+        .filter(|(file, delete)| **delete || keep.contains(file.as_str()))
+        .map(|(file, delete)| (file.clone(), *delete))
+        .collect();
+    minicbor_ser::to_vec(&ResourceData::Sarc(sarc)).map_err(|e| anyhow::format_err!("{:?}", e))
+}
+
+fn write_part(
+    mod_: &ModReader,
+    part: &SplitPart,
+    out_dir: &Path,
+    compressor: &mut zstd::bulk::Compressor<'static>,
+) -> Result<PathBuf> {
+    // Every resource the part needs, with the files to keep for SARCs which
+    // are only partly in this part
+    let mut resources: BTreeMap<String, Option<BTreeSet<&str>>> = BTreeMap::new();
+    let mut manifest = Manifest::default();
+    for file in &part.files {
+        let list = if file.aoc {
+            &mut manifest.aoc_files
+        } else {
+            &mut manifest.content_files
+        };
+        list.insert(file.top().into());
+        let names: Vec<&str> = file.path.split("//").collect();
+        for (i, name) in names.iter().enumerate() {
+            match names.get(i + 1) {
+                Some(child) => {
+                    if let Some(keep) = resources
+                        .entry(canon(name, file.aoc))
+                        .or_insert_with(|| Some(BTreeSet::new()))
+                    {
+                        keep.insert(*child);
+                    }
+                }
+                None => {
+                    resources.insert(canon(name, file.aoc), None);
+                }
+            }
+        }
+    }
+    // Map changes in the DLC need its field pack listed, as when packaging
+    let field_pack = "Pack/AocMainField.pack";
+    if mod_.manifest.aoc_files.contains(field_pack)
+        && !manifest.aoc_files.contains(field_pack)
+        && manifest
+            .aoc_files
+            .iter()
+            .any(|f| f.contains("Map/MainField"))
+    {
+        manifest.aoc_files.insert(field_pack.into());
+        resources
+            .entry(canon(field_pack, true))
+            .or_insert_with(|| Some(BTreeSet::new()));
+    }
+
+    let meta = Meta {
+        name: format!("{} ({})", mod_.meta.name, part.name.trim()).into(),
+        ..mod_.meta.clone()
+    };
+    let path = out_dir.join(format!("{}.zip", sanitise(&meta.name)));
+    let opts: SimpleFileOptions =
+        FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(fs::File::create(&path)?);
+    for (canon, keep) in &resources {
+        for root in ROOTS {
+            let stored = stored_path(root, canon);
+            let Some(data) = mod_.read_raw(&stored) else {
+                continue;
+            };
+            let mut data = decode(mod_, data)?;
+            if let Some(keep) = keep {
+                data =
+                    filter_sarc(data, keep).with_context(|| format!("Failed to split {canon}"))?;
+            }
+            zip.start_file(stored.as_str(), opts)?;
+            zip.write_all(&compressor.compress(&data)?)?;
+        }
+    }
+    // Every part keeps the documentation and thumbnail
+    for (file, _) in mod_.stored_files() {
+        if is_doc_path(&file) || file.starts_with("thumb.") {
+            if let Some(data) = mod_.read_raw(&file) {
+                zip.start_file(file.as_str(), opts)?;
+                zip.write_all(&data)?;
+            }
+        }
+    }
+    zip.start_file("manifest.yml", opts)?;
+    zip.write_all(serde_yaml::to_string(&manifest)?.as_bytes())?;
+    zip.start_file("meta.yml", opts)?;
+    zip.write_all(serde_yaml::to_string(&meta)?.as_bytes())?;
+    zip.finish()?;
+    Ok(path)
+}
+
+/// Writes each part as a mod of its own in `out_dir`, named after the
+/// original mod and the part, and returns their paths. Every file the mod
+/// changes has to be in exactly one part.
+pub fn split(mod_: &ModReader, parts: &[SplitPart], out_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !mod_.meta.options.is_empty() {
+        anyhow_ext::bail!(
+            "{} has options, and mods with options cannot be split",
+            mod_.meta.name
+        );
+    }
+    if parts.len() < 2 {
+        anyhow_ext::bail!("A mod has to be split into at least two parts");
+    }
+    let all = files(mod_)?;
+    let mut names = BTreeSet::new();
+    let mut assigned = BTreeSet::new();
+    for part in parts {
+        let name = part.name.trim();
+        if name.is_empty() {
+            anyhow_ext::bail!("Every part needs a name");
+        }
+        if !names.insert(name) {
+            anyhow_ext::bail!("There is more than one part named {name}");
+        }
+        if part.files.is_empty() {
+            anyhow_ext::bail!("The part {name} has no files");
+        }
+        for file in &part.files {
+            if !all.contains(file) {
+                anyhow_ext::bail!("{} is not changed by {}", file.path, mod_.meta.name);
+            }
+            if !assigned.insert(file) {
+                anyhow_ext::bail!("{} is in more than one part", file.path);
+            }
+        }
+    }
+    if let Some(file) = all.iter().find(|file| !assigned.contains(file)) {
+        anyhow_ext::bail!("{} is not in any part", file.path);
+    }
+    fs::create_dir_all(out_dir)?;
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(8, crate::DICTIONARY)?;
+    parts
+        .iter()
+        .map(|part| {
+            write_part(mod_, part, out_dir, &mut compressor)
+                .with_context(|| format!("Failed to write part {}", part.name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use uk_content::resource::SarcMap;
+
+    use super::*;
+    use crate::{ModPlatform, FORMAT_VERSION};
+
+    fn sarc(files: &[&str]) -> ResourceData {
+        ResourceData::Sarc(SarcMap {
+            alignment: 4,
+            files:     files.iter().map(|&f| smartstring::alias::String::from(f)).collect(),
+        })
+    }
+
+    fn file(path: &str, aoc: bool) -> SplitFile {
+        SplitFile {
+            path: path.into(),
+            aoc,
+        }
+    }
+
+    const ACTORINFO: &str = "Pack/Bootup.pack//Actor/ActorInfo.product.sbyml";
+    const GAMEDATA: &str = "Pack/Bootup.pack//GameData/gamedata.ssarc";
+    const ACTOR: &str = "Actor/Pack/Enemy_Test.sbactorpack";
+    const MAP: &str = "Map/MainField/A-1/A-1_Static.smubin";
+    const FIELD: &str = "Pack/AocMainField.pack";
+
+    /// A mod changing two files in Bootup.pack, an actor, and a DLC map.
+    fn mega_mod(dir: &Path) -> ModReader {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: FORMAT_VERSION,
+            name: "Mega Mod".into(),
+            version: "1.0.0".into(),
+            author: "Test".into(),
+            category: "Other".into(),
+            description: "A test mod".into(),
+            platform: ModPlatform::Specific(Endian::Big),
+            url: None,
+            options: vec![],
+            masters: Default::default(),
+        };
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert("Pack/Bootup.pack".into());
+        manifest.content_files.insert(ACTOR.into());
+        manifest.aoc_files.insert(MAP.into());
+        manifest.aoc_files.insert(FIELD.into());
+        let path = crate::pack::pack_resources(dir.join("mega.zip"), &meta, &manifest, [
+            (
+                "Pack/Bootup.pack".into(),
+                // The event is not stored, so it is not changed by the mod
+                sarc(&[
+                    "Actor/ActorInfo.product.sbyml",
+                    "Event/Unchanged.bfevfl",
+                    "GameData/gamedata.ssarc",
+                ]),
+            ),
+            (
+                "Actor/ActorInfo.product.byml".into(),
+                ResourceData::Binary(vec![1]),
+            ),
+            (
+                "GameData/gamedata.sarc".into(),
+                ResourceData::Binary(vec![2]),
+            ),
+            (canonicalize(ACTOR), ResourceData::Binary(vec![3])),
+            (canonicalize_aoc(MAP), ResourceData::Binary(vec![4])),
+            (canonicalize_aoc(FIELD), sarc(&[])),
+        ])
+        .unwrap();
+        ModReader::open(path, vec![]).unwrap()
+    }
+
+    #[test]
+    fn list_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let files = files(&mega_mod(tmp.path())).unwrap();
+        assert_eq!(
+            files,
+            [
+                file(ACTOR, false),
+                file(ACTORINFO, false),
+                file(GAMEDATA, false),
+                file(MAP, true),
+                file(FIELD, true),
+            ]
+            .into_iter()
+            .collect()
+        );
+        let areas: Vec<_> = files.iter().map(SplitFile::area).collect();
+        assert_eq!(areas, ["Actor", "Actor", "GameData", "Map", "Pack"]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mega = mega_mod(tmp.path());
+        let parts = [
+            SplitPart {
+                name:  "Balance".into(),
+                files: [file(ACTORINFO, false), file(ACTOR, false), file(MAP, true)].into(),
+            },
+            SplitPart {
+                name:  "Flags".into(),
+                files: [file(GAMEDATA, false), file(FIELD, true)].into(),
+            },
+        ];
+        let out = tmp.path().join("split");
+        let paths = split(&mega, &parts, &out).unwrap();
+        let readers: Vec<_> = paths
+            .iter()
+            .map(|path| ModReader::open(path, vec![]).unwrap())
+            .collect();
+        assert_eq!(readers[0].meta.name, "Mega Mod (Balance)");
+        assert_eq!(readers[1].meta.name, "Mega Mod (Flags)");
+
+        // Each part lists only its own files in the shared pack
+        let bootup = |reader: &ModReader| {
+            reader.get_resources(Path::new("Pack/Bootup.pack")).unwrap()[0]
+                .as_sarc()
+                .unwrap()
+                .files
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bootup(&readers[0]), ["Actor/ActorInfo.product.sbyml"]);
+        assert_eq!(bootup(&readers[1]), ["GameData/gamedata.ssarc"]);
+        // The DLC map keeps the field pack it needs
+        assert!(readers[0].manifest.aoc_files.contains(FIELD));
+        assert!(!readers[1].manifest.content_files.contains(ACTOR));
+
+        // Together the parts change exactly what the original did
+        let mut combined = BTreeSet::new();
+        for (reader, part) in readers.iter().zip(&parts) {
+            let files = files(reader).unwrap();
+            assert!(files.is_superset(&part.files));
+            combined.extend(files);
+        }
+        assert_eq!(combined, super::files(&mega).unwrap());
+        for (reader, part) in readers.iter().zip(&parts) {
+            for file in &part.files {
+                let name = Path::new(file.path.rsplit("//").next().unwrap());
+                assert_eq!(
+                    reader.get_versions(name).unwrap(),
+                    mega.get_versions(name).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_parts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mega = mega_mod(tmp.path());
+        let out = tmp.path().join("split");
+        let part = |name: &str, files: &[(&str, bool)]| {
+            SplitPart {
+                name:  name.into(),
+                files: files.iter().map(|(path, aoc)| file(path, *aoc)).collect(),
+            }
+        };
+        let rest = [
+            (GAMEDATA, false),
+            (ACTOR, false),
+            (MAP, true),
+            (FIELD, true),
+        ];
+        // A file left out
+        assert!(split(
+            &mega,
+            &[part("A", &[(ACTORINFO, false)]), part("B", &rest[1..])],
+            &out
+        )
+        .is_err());
+        // A file in two parts
+        assert!(split(
+            &mega,
+            &[
+                part("A", &[(ACTORINFO, false), (ACTOR, false)]),
+                part("B", &rest)
+            ],
+            &out
+        )
+        .is_err());
+        // Two parts with the same name
+        assert!(split(
+            &mega,
+            &[part("A", &[(ACTORINFO, false)]), part("A", &rest)],
+            &out
+        )
+        .is_err());
+        assert!(split(
+            &mega,
+            &[part("A", &[(ACTORINFO, false)]), part("B", &rest)],
+            &out
+        )
+        .is_ok());
+    }
+}
@@ -1,12 +1,18 @@
+pub mod cache;
 mod de;
+pub mod peek;
+pub mod repro;
+mod sarc_patch;
 use std::{
-    collections::BTreeSet,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    hash::{Hash, Hasher},
     io::{BufReader, Read, Write},
     ops::Deref,
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, OnceLock,
     },
 };
 
@@ -22,24 +28,37 @@ use ouroboros::self_referencing;
 use parking_lot::Mutex;
 use path_slash::PathExt;
 use rayon::prelude::*;
-use roead::{
-    sarc::SarcWriter,
-    yaz0::{compress, compress_if},
-};
+use roead::{sarc::SarcWriter, yaz0::compress};
+use rustc_hash::FxHasher;
 use serde::Serialize;
 use smartstring::alias::String;
 use uk_content::{
-    canonicalize,
+    canon::{canonicalize_aoc, legacy_canonical, normalize_unicode, CanonTracker},
+    canonicalize, compression,
     constants::Language,
     platform_content, platform_prefixes,
     prelude::{Endian, Mergeable, Resource},
-    resource::{MergeableResource, ResourceData, SarcMap},
+    resource::{MergeableResource, ResourceData, ResourceSizeTable, SarcMap},
     util::{HashMap, IndexSet},
 };
 use uk_reader::{ResourceLoader, ResourceReader};
 use uk_util::PathExt as UkPathExt;
+use xxhash_rust::xxh3::{xxh3_128, Xxh3};
 
-use crate::{Manifest, Meta, ModOption};
+use self::{
+    cache::{CacheWriter, ResourceCache},
+    peek::PeekCache,
+    repro::MergeFailure,
+    sarc_patch::PatchStats,
+};
+use crate::{
+    is_doc_path, is_file_modded, is_passthrough_path, is_reserved_passthrough,
+    objects::{ObjectIndex, ObjectStore, OBJECT_INDEX},
+    platform_root,
+    policy::{MergePolicies, MergePolicy},
+    Manifest, Meta, ModOption, ModOptionGroup, ModPlatform, DOCS_DIR, FORMAT_VERSION,
+    PASSTHROUGH_DIR, README,
+};
 
 pub enum ZipData {
     Owned(Vec<u8>),
@@ -130,11 +149,25 @@ impl ParallelZipReader {
         self.borrow_files().iter().map(|(f, _)| f)
     }
 
+    /// Finds an entry whose name only differs from `file` in its Unicode
+    /// normalization, as in mods packed on macOS before names were normalized.
+    fn find_normalized(&self, file: &Path) -> Option<&&piz::read::FileMetadata<'_>> {
+        if file.as_os_str().is_ascii() {
+            return None;
+        }
+        let file = file.to_str().map(normalize_unicode)?;
+        self.borrow_files()
+            .iter()
+            .find(|(name, _)| name.to_str().map(normalize_unicode).as_ref() == Some(&file))
+            .map(|(_, entry)| entry)
+    }
+
     pub fn get_file(&self, file: impl AsRef<Path>) -> Result<Vec<u8>> {
         fn inner(self_: &ParallelZipReader, file: &Path) -> Result<Vec<u8>> {
             self_
                 .borrow_files()
                 .get(file)
+                .or_else(|| self_.find_normalized(file))
                 .with_context(|| format!("File {} not found in ZIP", file.display()))
                 .and_then(|file| {
                     let mut reader = self_
@@ -167,8 +200,18 @@ pub struct ModReader {
     pub manifest: Manifest,
     #[serde(skip, default = "init_decompressor")]
     decompressor: Arc<Mutex<zstd::bulk::Decompressor<'static>>>,
+    /// Opened on first use, so mods peeked from the cache never open their
+    /// archives.
     #[serde(skip_serializing)]
-    zip: Option<ParallelZipReader>,
+    zip: OnceLock<Option<ParallelZipReader>>,
+    /// The store and index of a mod stored with its resources in an object
+    /// store, read on first use.
+    #[serde(skip)]
+    objects: OnceLock<Option<(ObjectStore, ObjectIndex)>>,
+    #[serde(skip)]
+    platform: Option<Endian>,
+    #[serde(skip)]
+    cache: Option<ResourceCache>,
 }
 
 impl std::fmt::Debug for ModReader {
@@ -180,6 +223,9 @@ impl std::fmt::Debug for ModReader {
             .field("manifest", &self.manifest)
             .field("decompressor", &"zstd::bulk::Decompressor<'static>")
             .field("zip", &self.zip)
+            .field("objects", &self.objects)
+            .field("platform", &self.platform)
+            .field("cache", &self.cache)
             .finish()
     }
 }
@@ -192,28 +238,37 @@ impl ResourceLoader for ModReader {
             || self.manifest.aoc_files.contains(name.as_ref())
     }
 
+    fn list_files(&self, prefix: Option<&Path>) -> uk_reader::Result<Vec<String>> {
+        let prefix = prefix.map(|p| p.to_slash_lossy());
+        let files: BTreeSet<String> = self
+            .manifest
+            .content_files
+            .iter()
+            .map(|file| canonicalize(file.as_str()))
+            .chain(
+                self.manifest
+                    .aoc_files
+                    .iter()
+                    .map(|file| canonicalize_aoc(file.as_str())),
+            )
+            .filter(|file| {
+                prefix
+                    .as_deref()
+                    .map(|p| file.starts_with(p))
+                    .unwrap_or(true)
+            })
+            .collect();
+        Ok(files.into_iter().collect())
+    }
+
+    fn has_aoc(&self) -> bool {
+        !self.manifest.aoc_files.is_empty()
+    }
+
     fn get_data(&self, name: &Path) -> uk_reader::Result<Vec<u8>> {
         let canon = canonicalize(name);
-        if let Some(zip) = self.zip.as_ref() {
-            if let Ok(data) = zip.get_file(canon.as_str()) {
-                return Ok(self
-                    .decompress(data.as_slice())
-                    .with_context(|| jstr!("Failed to decompress file {&canon} from mod"))?);
-            }
-        } else if let Some(path) = self.path.join(canon.as_str()).exists_then() {
-            return Ok(fs::read(path)?);
-        }
-        for opt in &self.options {
-            let path = Path::new("options").join(&opt.path).join(canon.as_str());
-            if let Some(zip) = self.zip.as_ref() {
-                if let Ok(data) = zip.get_file(path) {
-                    return Ok(self
-                        .decompress(data.as_slice())
-                        .with_context(|| jstr!("Failed to decompress file {&canon} from mod"))?);
-                }
-            } else if let Some(path) = self.path.join(path).exists_then() {
-                return Ok(fs::read(path)?);
-            }
+        if let Some(data) = self.read_resource(&canon) {
+            return Ok(data?);
         }
         self.get_aoc_file_data(name).map_err(|_| {
             anyhow_ext::anyhow!(
@@ -226,27 +281,9 @@ impl ResourceLoader for ModReader {
     }
 
     fn get_aoc_file_data(&self, name: &Path) -> uk_reader::Result<Vec<u8>> {
-        let canon = canonicalize(jstr!("Aoc/0010/{name.to_str().unwrap_or_default()}"));
-        if let Some(zip) = self.zip.as_ref() {
-            if let Ok(data) = zip.get_file(canon.as_str()) {
-                return Ok(self
-                    .decompress(data.as_slice())
-                    .with_context(|| jstr!("Failed to decompress file {&canon} from mod"))?);
-            }
-        } else if let Some(path) = self.path.join(canon.as_str()).exists_then() {
-            return Ok(fs::read(path)?);
-        }
-        for opt in &self.options {
-            let path = Path::new("options").join(&opt.path).join(canon.as_str());
-            if let Some(zip) = self.zip.as_ref() {
-                if let Ok(data) = zip.get_file(path) {
-                    return Ok(self
-                        .decompress(data.as_slice())
-                        .with_context(|| jstr!("Failed to decompress file {&canon} from mod"))?);
-                }
-            } else if let Some(path) = self.path.join(path).exists_then() {
-                return Ok(fs::read(path)?);
-            }
+        let canon = canonicalize_aoc(name);
+        if let Some(data) = self.read_resource(&canon) {
+            return Ok(data?);
         }
         Err(anyhow_ext::anyhow!(
             "Failed to read file {} (canonical path {}) from mod",
@@ -262,8 +299,200 @@ impl ResourceLoader for ModReader {
 }
 
 impl ModReader {
+    /// Reads a file stored in the mod, preferring the payload for the
+    /// selected platform if there is one. Returns `None` if the file is not
+    /// stored at all.
+    pub(crate) fn read_stored(&self, path: &Path) -> Option<Result<Vec<u8>>> {
+        self.read_stored_at(path).or_else(|| {
+            // Mods packaged before `sarc` and `stera` kept their `s` store
+            // those files under their old canonical path
+            let legacy = legacy_canonical(&path.to_slash_lossy())?;
+            self.read_stored_at(Path::new(legacy.as_str()))
+        })
+    }
+
+    fn read_stored_at(&self, path: &Path) -> Option<Result<Vec<u8>>> {
+        let platform_path = self
+            .platform
+            .map(|e| Path::new(platform_root(e)).join(path));
+        for path in platform_path.as_deref().into_iter().chain([path]) {
+            if let Some(zip) = self.zip() {
+                if let Ok(data) = zip.get_file(path) {
+                    return Some(self.decompress(data.as_slice()).with_context(|| {
+                        format!("Failed to decompress file {} from mod", path.display())
+                    }));
+                }
+            } else if let Some(data) = self.read_unzipped(path) {
+                return Some(data);
+            }
+        }
+        None
+    }
+
+    /// Reads a file from an unzipped mod, from its folder or else from the
+    /// object store, decompressed.
+    fn read_unzipped(&self, path: &Path) -> Option<Result<Vec<u8>>> {
+        if let Some(file) = self.path.join(path).exists_then() {
+            return Some(fs::read(file).map_err(Into::into));
+        }
+        let data = self.read_object(path)?;
+        Some(data.and_then(|data| {
+            self.decompress(&data)
+                .with_context(|| format!("Failed to decompress file {} from mod", path.display()))
+        }))
+    }
+
+    /// Reads a file of a mod stored in an object store, as it is stored.
+    fn read_object(&self, path: &Path) -> Option<Result<Vec<u8>>> {
+        let (store, index) = self.objects()?;
+        let id = index.get(&path.to_slash_lossy())?;
+        Some(store.get(id))
+    }
+
+    /// The object store and index of the mod, if it is unzipped and stored
+    /// with its resources in a store, read the first time they are needed.
+    fn objects(&self) -> Option<&(ObjectStore, ObjectIndex)> {
+        self.objects
+            .get_or_init(|| {
+                if !self.path.is_dir() {
+                    return None;
+                }
+                let index = ObjectIndex::read(&self.path)
+                    .inspect_err(|e| {
+                        log::warn!(
+                            "Failed to read object index of {}: {e:?}",
+                            self.path.display()
+                        )
+                    })
+                    .ok()
+                    .flatten()?;
+                let store = ObjectStore::find(&self.path);
+                if store.is_none() {
+                    log::warn!("No object store found for {}", self.path.display());
+                }
+                store.map(|store| (store, index))
+            })
+            .as_ref()
+    }
+
+    /// Reads a resource by canonical path, from the resource cache if there
+    /// is one, or else from the base files or the enabled options in order.
+    fn read_resource(&self, canon: &str) -> Option<Result<Vec<u8>>> {
+        if let Some(data) = self.cache.as_ref().and_then(|c| c.get(canon)) {
+            return Some(Ok(data.to_vec()));
+        }
+        self.read_stored(Path::new(canon)).or_else(|| {
+            self.options
+                .iter()
+                .find_map(|opt| self.read_stored(&Path::new("options").join(&opt.path).join(canon)))
+        })
+    }
+
+    /// Selects which payload to read from a dual-platform mod, and the
+    /// manifest of that platform if the mod stores one.
+    pub fn with_platform(mut self, endian: Endian) -> Self {
+        if self.platform != Some(endian) {
+            self.cache = None;
+            match self.platform_manifest(endian) {
+                Ok(Some(manifest)) => self.manifest = manifest,
+                Ok(None) => (),
+                Err(e) => {
+                    log::warn!(
+                        "Failed to read {} manifest of mod {}: {e:?}",
+                        ModPlatform::Specific(endian),
+                        self.meta.name
+                    )
+                }
+            }
+        }
+        self.platform = Some(endian);
+        self
+    }
+
+    /// The manifest of one platform of a dual-platform mod with those of the
+    /// enabled options, or `None` if the mod has no manifest for it. Options
+    /// with no files for the platform have no manifest under it.
+    fn platform_manifest(&self, endian: Endian) -> Result<Option<Manifest>> {
+        let root = Path::new(platform_root(endian));
+        let Some(data) = self.read_raw(&root.join("manifest.yml").to_slash_lossy()) else {
+            return Ok(None);
+        };
+        let mut manifest: Manifest = serde_yaml::from_slice(&data)?;
+        for option in &self.options {
+            let path = root.join(option.manifest_path());
+            if let Some(data) = self.read_raw(&path.to_slash_lossy()) {
+                let opt_manifest: Manifest = serde_yaml::from_slice(&data)?;
+                manifest.content_files.extend(opt_manifest.content_files);
+                manifest.aoc_files.extend(opt_manifest.aoc_files);
+            }
+        }
+        Ok(Some(manifest))
+    }
+
+    /// Identifies the resources this reader will serve: the archive file, its
+    /// size and modified time, the enabled options, and the platform. Mods
+    /// which are not zipped are not cached, since their files can change
+    /// without notice.
+    fn cache_key(&self) -> Option<u64> {
+        let meta = self.path.metadata().ok().filter(|m| m.is_file())?;
+        let mut hasher = FxHasher::default();
+        self.path.hash(&mut hasher);
+        meta.len().hash(&mut hasher);
+        meta.modified().ok()?.hash(&mut hasher);
+        let mut options: Vec<_> = self.options.iter().map(|o| o.path.as_path()).collect();
+        options.sort_unstable();
+        options.hash(&mut hasher);
+        self.platform.map(|e| e == Endian::Big).hash(&mut hasher);
+        FORMAT_VERSION.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    /// The file in a cache folder which holds the resources this reader
+    /// serves, if the mod can be cached at all.
+    pub fn cache_path(&self, dir: &Path) -> Option<PathBuf> {
+        self.cache_key().map(|key| ResourceCache::path(dir, key))
+    }
+
+    /// Reads resources from a cache in the given folder, building it first if
+    /// this mod has not been cached yet. Failing to build the cache is not an
+    /// error; the mod is just read from its archive as usual.
+    pub fn with_cache(mut self, dir: &Path) -> Self {
+        let Some(path) = self.cache_path(dir) else {
+            return self;
+        };
+        if !path.exists() {
+            log::debug!("Building resource cache for {}", self.meta.name);
+            // Each resource is written as soon as it is read, so only one is
+            // held in memory at a time
+            let build = || -> Result<()> {
+                let mut writer = CacheWriter::create(&path)?;
+                for canon in self.manifest.resources().collect::<BTreeSet<_>>() {
+                    if let Some(data) = self.read_resource(&canon) {
+                        writer.add(&canon, &data?)?;
+                    }
+                }
+                writer.finish()
+            };
+            if let Err(e) = build() {
+                log::warn!("Failed to cache resources for {}: {e:?}", self.meta.name);
+                return self;
+            }
+        }
+        match ResourceCache::open(&path) {
+            Ok(cache) => self.cache = Some(cache),
+            Err(e) => {
+                log::warn!(
+                    "Failed to open resource cache for {}: {e:?}",
+                    self.meta.name
+                );
+                let _ = fs::remove_file(&path);
+            }
+        }
+        self
+    }
+
     #[inline]
-    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+    pub(crate) fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
         let mut decomp = self.decompressor.lock();
         let size = zstd::bulk::Decompressor::upper_bound(data).unwrap_or(data.len() * 1024);
         decomp
@@ -279,24 +508,7 @@ impl ModReader {
             } else {
                 ModReader::open_unzipped(path, options)
             }?;
-            let name = result.meta.name.as_str();
-            let mod_api = Version::parse(&result.meta.api)
-                .map_err(|e| anyhow_ext::anyhow!("{e}"))
-                .context("Invalid API version for mod")?;
-            let current_api = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
-            if current_api.major != mod_api.major {
-                anyhow_ext::bail!("{name} build with unsupported UKMM version: {mod_api}")
-            } else if current_api.minor > mod_api.minor && current_api.major == 0 {
-                log::warn!(
-                    "{name} is from an older UKMM prerelease (v{mod_api}), compatibility not \
-                     guaranteed"
-                )
-            } else if current_api.minor < mod_api.minor {
-                log::warn!(
-                    "{name} was made with a newer UKMM version (v{mod_api}), might contain \
-                     incompatible features"
-                )
-            }
+            result.check_version()?;
             Ok(result)
         }
         inner(path.as_ref(), options.into())
@@ -310,29 +522,95 @@ impl ModReader {
             } else {
                 ModReader::open_unzipped(path, options)
             }?;
-            let name = result.meta.name.as_str();
-            let mod_api = Version::parse(&result.meta.api)
-                .map_err(|e| anyhow_ext::anyhow!("{e}"))
-                .context("Invalid API version for mod")?;
-            let current_api = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
-            if current_api.major != mod_api.major {
-                anyhow_ext::bail!("{name} build with unsupported UKMM version: {mod_api}")
-            } else if current_api.minor > mod_api.minor && current_api.major == 0 {
-                log::warn!(
-                    "{name} is from an older UKMM prerelease (v{mod_api}), compatibility not \
-                     guaranteed"
-                )
-            } else if current_api.minor < mod_api.minor {
-                log::warn!(
-                    "{name} was made with a newer UKMM version (v{mod_api}), might contain \
-                     incompatible features"
-                )
-            }
+            result.check_version()?;
             Ok(result)
         }
         inner(path.as_ref(), options.into())
     }
 
+    /// Checks that the mod's format and UKMM version are supported, warning
+    /// where they might not be fully compatible.
+    fn check_version(&self) -> Result<()> {
+        let name = self.meta.name.as_str();
+        let mod_api = Version::parse(&self.meta.api)
+            .map_err(|e| anyhow_ext::anyhow!("{e}"))
+            .context("Invalid API version for mod")?;
+        if self.meta.format > FORMAT_VERSION {
+            anyhow_ext::bail!(
+                "{name} uses a newer mod format (v{}) than this version of UKMM supports",
+                self.meta.format
+            )
+        }
+        let current_api = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+        if current_api.major != mod_api.major {
+            anyhow_ext::bail!("{name} build with unsupported UKMM version: {mod_api}")
+        } else if current_api.minor > mod_api.minor && current_api.major == 0 {
+            log::warn!(
+                "{name} is from an older UKMM prerelease (v{mod_api}), compatibility not \
+                 guaranteed"
+            )
+        } else if current_api.minor < mod_api.minor {
+            log::warn!(
+                "{name} was made with a newer UKMM version (v{mod_api}), might contain \
+                 incompatible features"
+            )
+        }
+        Ok(())
+    }
+
+    /// Like [`ModReader::open_peek`], but takes the meta and manifests of
+    /// zipped mods from a [`PeekCache`] where it has them, without opening
+    /// the archive.
+    pub fn open_peek_cached(
+        path: impl AsRef<Path>,
+        options: impl Into<Vec<ModOption>>,
+        cache: &PeekCache,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let options = options.into();
+        if !path.is_file() {
+            return Self::open_peek(path, options);
+        }
+        if let Some((meta, manifest)) = cache.get(path, &options) {
+            let result = Self {
+                path: path.to_path_buf(),
+                decompressor: init_decompressor(),
+                options,
+                meta,
+                manifest,
+                zip: OnceLock::new(),
+                objects: OnceLock::from(None),
+                platform: None,
+                cache: None,
+            };
+            result.check_version()?;
+            return Ok(result);
+        }
+        let result = Self::open_peek(path, options)?;
+        if let Err(e) = cache.fill(&result) {
+            log::warn!("Failed to cache manifests of {}: {e:?}", path.display());
+        }
+        Ok(result)
+    }
+
+    /// The mod's archive, if it is zipped, opened the first time it is
+    /// needed.
+    fn zip(&self) -> Option<&ParallelZipReader> {
+        self.zip
+            .get_or_init(|| {
+                self.path
+                    .is_file()
+                    .then(|| ParallelZipReader::open(&self.path, false))
+                    .transpose()
+                    .inspect_err(|e| {
+                        log::warn!("Failed to open mod archive {}: {e:?}", self.path.display())
+                    })
+                    .ok()
+                    .flatten()
+            })
+            .as_ref()
+    }
+
     fn open_unzipped(path: PathBuf, options: Vec<ModOption>) -> Result<Self> {
         let meta: Meta = serde_yaml::from_str(&fs::read_to_string(path.join("meta.yml"))?)?;
         let mut manifest: Manifest =
@@ -349,7 +627,10 @@ impl ModReader {
             options,
             meta,
             manifest,
-            zip: None,
+            zip: OnceLock::from(None),
+            objects: OnceLock::new(),
+            platform: None,
+            cache: None,
         })
     }
 
@@ -411,7 +692,10 @@ impl ModReader {
             options,
             meta,
             manifest,
-            zip: Some(zip),
+            zip: OnceLock::from(Some(zip)),
+            objects: OnceLock::from(None),
+            platform: None,
+            cache: None,
         })
     }
 
@@ -429,31 +713,210 @@ impl ModReader {
         &self.manifest
     }
 
+    /// Computes an identity for the contents of the mod: its manifest plus a
+    /// digest of every stored resource. Unlike a hash of the archive itself,
+    /// this is the same for a re-zipped or recompressed copy of the mod. It
+    /// is a 128-bit xxHash, wide enough that two different mods will not
+    /// share one by chance.
+    pub fn content_identity(&self) -> Result<u128> {
+        const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+        let is_resource = |name: &str| {
+            let file_name = name.rsplit('/').next().unwrap_or_default();
+            !file_name.is_empty()
+                && !file_name.ends_with(".yml")
+                && !file_name.starts_with("thumb")
+                && !is_doc_path(name)
+        };
+        let mut hasher = Xxh3::new();
+        self.manifest.hash(&mut hasher);
+        let mut files: Vec<std::string::String> = if let Some(zip) = self.zip() {
+            zip.iter()
+                .map(|f| f.to_slash_lossy().into_owned())
+                .filter(|f| is_resource(f))
+                .collect()
+        } else {
+            WalkDir::new(&self.path)
+                .into_iter()
+                .filter_map(std::result::Result::ok)
+                .filter(|f| f.file_type.is_file())
+                .filter_map(|f| {
+                    f.path()
+                        .strip_prefix(&self.path)
+                        .ok()
+                        .map(|p| p.to_slash_lossy().into_owned())
+                })
+                .chain(
+                    self.objects()
+                        .into_iter()
+                        .flat_map(|(_, index)| index.0.keys().cloned()),
+                )
+                .filter(|f| is_resource(f))
+                .collect()
+        };
+        files.sort_unstable();
+        for file in files {
+            let data = match self.zip() {
+                Some(zip) => zip.get_file(file.as_str())?,
+                None => {
+                    match self.path.join(file.as_str()).exists_then() {
+                        Some(path) => fs::read(path)?,
+                        None => {
+                            self.read_object(Path::new(&file))
+                                .with_context(|| jstr!("Missing file {&file} from mod"))??
+                        }
+                    }
+                }
+            };
+            let data = if data.starts_with(ZSTD_MAGIC) {
+                self.decompress(&data)
+                    .with_context(|| jstr!("Failed to decompress file {&file} from mod"))?
+            } else {
+                data
+            };
+            if data.is_empty() {
+                continue;
+            }
+            file.hash(&mut hasher);
+            hasher.update(&xxh3_128(&data).to_le_bytes());
+        }
+        Ok(hasher.digest128())
+    }
+
+    /// Every file stored in the mod with its size, by slash path relative to
+    /// the mod root.
+    pub(crate) fn stored_files(&self) -> Vec<(std::string::String, u64)> {
+        match self.zip() {
+            Some(zip) => {
+                zip.borrow_files()
+                    .iter()
+                    .filter(|(_, meta)| !meta.is_dir())
+                    .map(|(path, meta)| (path.to_slash_lossy().into_owned(), meta.size as u64))
+                    .collect()
+            }
+            None => {
+                let mut files: Vec<_> = WalkDir::new(&self.path)
+                    .into_iter()
+                    .filter_map(std::result::Result::ok)
+                    .filter(|e| e.file_type().is_file())
+                    .filter_map(|e| {
+                        let size = e.metadata().ok()?.len();
+                        let path = e.path();
+                        let rel = path.strip_prefix(&self.path).ok()?.to_slash_lossy();
+                        Some((rel.into_owned(), size))
+                    })
+                    .filter(|(rel, _)| rel != OBJECT_INDEX)
+                    .collect();
+                if let Some((store, index)) = self.objects() {
+                    files.extend(index.0.iter().map(|(file, id)| {
+                        let size = fs::metadata(store.path(*id))
+                            .map(|m| m.len())
+                            .unwrap_or_default();
+                        (file.clone(), size)
+                    }));
+                }
+                files
+            }
+        }
+    }
+
+    /// Reads a manifest stored in the mod, the main one or an option's.
+    pub(crate) fn read_manifest(&self, path: &Path) -> Result<Manifest> {
+        let data = self
+            .read_raw(&path.to_string_lossy().replace('\\', "/"))
+            .with_context(|| format!("Mod missing manifest file {}", path.display()))?;
+        Ok(serde_yaml::from_slice(&data)?)
+    }
+
+    /// The manifest of each of the mod's options, keyed by option path, as
+    /// stored rather than unioned into [`ModReader::manifest`].
+    pub fn option_manifests(&self) -> Result<BTreeMap<PathBuf, Manifest>> {
+        self.meta
+            .options
+            .iter()
+            .flat_map(|group| group.options())
+            .map(|option| {
+                Ok((
+                    option.path.clone(),
+                    self.read_manifest(&option.manifest_path())?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Reads a file stored as is in the mod, rather than as a compressed
+    /// resource.
+    pub(crate) fn read_raw(&self, path: &str) -> Option<Vec<u8>> {
+        match self.zip() {
+            Some(zip) => zip.get_file(path).ok(),
+            None => {
+                fs::read(self.path.join(path))
+                    .ok()
+                    .or_else(|| self.read_object(Path::new(path)).and_then(Result::ok))
+            }
+        }
+    }
+
+    /// The README bundled with the mod, if it has one. Invalid UTF-8 is
+    /// replaced rather than treated as an error.
+    pub fn readme(&self) -> Option<std::string::String> {
+        self.read_raw(README)
+            .map(|data| std::string::String::from_utf8_lossy(&data).into_owned())
+    }
+
+    /// The preview image for an option, if it has one.
+    pub fn option_image(&self, option: &ModOption) -> Option<Vec<u8>> {
+        self.read_raw(&option.image_path()?)
+    }
+
+    /// Reads an asset referenced from the mod's documentation by a relative
+    /// path, such as an image. Paths which point outside the README's folder
+    /// or docs folder, or to anything but documentation, are refused.
+    pub fn doc_asset(&self, path: &str) -> Option<Vec<u8>> {
+        let mut parts: Vec<&str> = vec![];
+        for part in path.trim_start_matches("./").split(['/', '\\']) {
+            match part {
+                "" | "." => (),
+                ".." => {
+                    parts.pop()?;
+                }
+                part => parts.push(part),
+            }
+        }
+        let path = parts.join("/");
+        if !is_doc_path(&path) || path == README {
+            return None;
+        }
+        self.read_raw(&path)
+    }
+
+    /// The passthrough files bundled with the mod, by slash path relative to
+    /// the deployment root.
+    pub fn passthrough_files(&self) -> Vec<std::string::String> {
+        let mut files: Vec<_> = self
+            .stored_files()
+            .into_iter()
+            .filter(|(path, _)| is_passthrough_path(path))
+            .map(|(path, _)| path[PASSTHROUGH_DIR.len() + 1..].to_owned())
+            .collect();
+        files.sort_unstable();
+        files
+    }
+
+    /// Reads a passthrough file by its path relative to the deployment root.
+    pub fn passthrough_data(&self, file: &str) -> Option<Vec<u8>> {
+        self.read_raw(&jstr!("{PASSTHROUGH_DIR}/{file}"))
+    }
+
     pub fn get_versions(&self, name: &Path) -> Result<Vec<Vec<u8>>> {
         let canon = canonicalize(name);
         let mut versions = Vec::with_capacity(1);
-        if let Some(zip) = self.zip.as_ref() {
-            if let Ok(data) = zip.get_file(canon.as_str()) {
-                versions.push(
-                    self.decompress(data.as_slice())
-                        .with_context(|| jstr!("Failed to decompress file {&canon} from mod"))?,
-                );
-            }
-        } else if let Some(path) = self.path.join(canon.as_str()).exists_then() {
-            versions.push(fs::read(path)?);
+        if let Some(data) = self.read_stored(Path::new(canon.as_str())) {
+            versions.push(data?);
         }
         for opt in &self.options {
             let path = Path::new("options").join(&opt.path).join(canon.as_str());
-            if let Some(zip) = self.zip.as_ref() {
-                if let Ok(data) = zip.get_file(path) {
-                    versions.push(
-                        self.decompress(data.as_slice()).with_context(|| {
-                            jstr!("Failed to decompress file {&canon} from mod")
-                        })?,
-                    );
-                }
-            } else if let Some(path) = self.path.join(path).exists_then() {
-                versions.push(fs::read(path)?);
+            if let Some(data) = self.read_stored(&path) {
+                versions.push(data?);
             }
         }
         if let Ok(data) = self.get_aoc_file_data(name) {
@@ -468,12 +931,149 @@ impl ModReader {
         }
         Ok(versions)
     }
+
+    /// Parses every version of a file the mod stores, as for
+    /// [`get_versions`](Self::get_versions).
+    pub fn get_resources(&self, name: &Path) -> Result<Vec<ResourceData>> {
+        self.get_versions(name)?
+            .into_iter()
+            .map(|data| {
+                minicbor_ser::from_slice(&data).with_context(|| {
+                    format!(
+                        "Error deserializing resource {} from mod {}",
+                        name.display(),
+                        self.meta.name
+                    )
+                })
+            })
+            .collect()
+    }
 }
 
 static RSTB_EXCLUDE_EXTS: &[&str] = &[
     "pack", "bgdata", "txt", "bgsvdata", "yml", "msbt", "bat", "ini", "png", "bfstm", "py", "sh",
 ];
-static RSTB_EXCLUDE_NAMES: &[&str] = &["ActorInfo.product.byml"];
+static RSTB_EXCLUDE_NAMES: &[&str] = &[
+    "ActorInfo.product.byml",
+    "ResourceSizeTable.product.rsizetable",
+];
+
+/// Whether merging a resource should update its RSTB entry.
+pub(crate) fn can_rstb(canon: &str) -> bool {
+    let filename = Path::new(canon);
+    !RSTB_EXCLUDE_EXTS.contains(
+        &filename
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default(),
+    ) && !RSTB_EXCLUDE_NAMES.contains(
+        &filename
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default(),
+    )
+}
+
+/// The name merge policy rules use for the type of a resource.
+fn resource_kind(resource: &ResourceData) -> std::string::String {
+    match resource {
+        ResourceData::Binary(_) => "Binary".into(),
+        ResourceData::Sarc(_) => "Sarc".into(),
+        ResourceData::Mergeable(resource) => resource.to_string(),
+    }
+}
+
+/// Passthrough files merged from a list of mods.
+#[derive(Debug, Default)]
+pub struct PassthroughFiles {
+    /// Each file written, with the name of the mod it was taken from.
+    pub files:     BTreeMap<std::string::String, String>,
+    /// Files provided by more than one mod, with every provider in merge
+    /// order, so the last one won.
+    pub conflicts: BTreeMap<std::string::String, Vec<String>>,
+}
+
+/// Writes the passthrough files of mods in merge order to `out_dir`, each
+/// taken verbatim from the last mod which provides it. Files which would land
+/// in the game file folders are skipped.
+pub fn merge_passthrough(mods: &[ModReader], out_dir: &Path) -> Result<PassthroughFiles> {
+    let mut providers: BTreeMap<std::string::String, Vec<&ModReader>> = BTreeMap::new();
+    for mod_ in mods {
+        for file in mod_.passthrough_files() {
+            if is_reserved_passthrough(&file) {
+                log::warn!(
+                    "Skipping passthrough file {file} from {}, which would be deployed over game \
+                     files",
+                    mod_.meta.name
+                );
+                continue;
+            }
+            providers.entry(file).or_default().push(mod_);
+        }
+    }
+    let mut merged = PassthroughFiles::default();
+    for (file, from) in providers {
+        let winner = from.last().expect("Passthrough file should have a provider");
+        let data = winner.passthrough_data(&file).with_context(|| {
+            jstr!("Failed to read passthrough file {&file} from {&winner.meta.name}")
+        })?;
+        let out = out_dir.join(&file);
+        out.parent().map(fs::create_dir_all).transpose()?;
+        fs::write(&out, data)
+            .with_context(|| jstr!("Failed to write passthrough file {&file}"))?;
+        if from.len() > 1 {
+            log::warn!(
+                "Passthrough file {file} is provided by {}, using the one from {}",
+                from.iter()
+                    .map(|m| m.meta.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                winner.meta.name
+            );
+            merged
+                .conflicts
+                .insert(file.clone(), from.iter().map(|m| m.meta.name.clone()).collect());
+        }
+        merged.files.insert(file, winner.meta.name.clone());
+    }
+    Ok(merged)
+}
+
+/// RSTB changes from merging mods: values computed for the merged resources,
+/// and the entries mods set in RSTBs of their own, which take precedence.
+#[derive(Debug, Default)]
+pub struct RstbUpdates {
+    pub computed: DashMap<String, Option<u32>>,
+    /// How the mods' RSTBs differ from stock, if any mod has one.
+    pub explicit: Option<ResourceSizeTable>,
+}
+
+/// The stock copy of a nested file in one of its parent packs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StockCopy {
+    /// The parent has no stock copy, as for a file a mod adds to it.
+    Absent,
+    /// A hash of the parent's stock copy.
+    Hash(u64),
+}
+
+/// A nested file merged once for every parent pack with the same stock copy
+/// of it.
+#[derive(Debug)]
+struct SharedFile {
+    stock:   StockCopy,
+    data:    Option<Vec<u8>>,
+    /// Every parent the file was written into.
+    parents: Vec<std::string::String>,
+}
+
+/// Where a nested file is built: its path through its parent packs, joined
+/// with `//` as for [`ResourceReader::get_bytes_from_sarc`], and whether the
+/// stock parent has it.
+struct Nest {
+    path:     std::string::String,
+    in_stock: bool,
+}
 
 // #[derive(Debug)]
 pub struct ModUnpacker {
@@ -483,8 +1083,18 @@ pub struct ModUnpacker {
     endian:   Endian,
     lang:     Language,
     rstb:     DashMap<String, Option<u32>>,
+    explicit: Mutex<Option<ResourceSizeTable>>,
     hashes:   StockHashTable,
     out_dir:  PathBuf,
+    packs:    PatchStats,
+    policies: MergePolicies,
+    /// Files merged differently because of a policy override, with the
+    /// policy and the pattern of the rule which chose it.
+    applied:  DashMap<String, (MergePolicy, std::string::String)>,
+    /// Modded nested files merged this run by canonical path, once for each
+    /// stock copy, so a file the game keeps in several packs comes out the
+    /// same in all of them.
+    shared:   DashMap<String, Vec<SharedFile>>,
 }
 
 impl ModUnpacker {
@@ -498,15 +1108,20 @@ impl ModUnpacker {
         Self {
             dump,
             manifest: None,
-            mods,
+            mods: mods.into_iter().map(|m| m.with_platform(endian)).collect(),
             lang,
             endian,
             rstb: DashMap::new(),
+            explicit: Mutex::new(None),
             hashes: StockHashTable::new(&match endian {
                 Endian::Little => botw_utils::hashes::Platform::Switch,
                 Endian::Big => botw_utils::hashes::Platform::WiiU,
             }),
             out_dir,
+            packs: Default::default(),
+            policies: Default::default(),
+            applied: DashMap::new(),
+            shared: DashMap::new(),
         }
     }
 
@@ -515,7 +1130,18 @@ impl ModUnpacker {
         self
     }
 
-    pub fn unpack(self) -> Result<DashMap<String, Option<u32>>> {
+    pub fn with_policies(mut self, policies: MergePolicies) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    /// Builds one merged file, uncompressed, without writing anything. This
+    /// is what an apply would write for the file, so it can be previewed.
+    pub fn build_single(&self, file: &str, aoc: bool) -> Result<Vec<u8>> {
+        Ok(self.build_file(file, aoc, None, None)?.unwrap_or_default())
+    }
+
+    pub fn unpack(self) -> Result<RstbUpdates> {
         if !self.out_dir.exists() {
             fs::create_dir_all(&self.out_dir)?;
         }
@@ -541,6 +1167,19 @@ impl ModUnpacker {
             modded_langs.insert(lang);
             content_files.remove(&lang.bootup_path());
         }
+        let tracker = CanonTracker::new();
+        if tracker.is_enabled() {
+            for file in &content_files {
+                tracker.canonicalize(file);
+            }
+            for file in &aoc_files {
+                tracker.record(file, &canonicalize_aoc(file.as_str()));
+            }
+        }
+        // The stock copies are read up front in parallel, rather than one at
+        // a time as each file is merged
+        let preload: Vec<String> = content_files.iter().map(|file| (*file).clone()).collect();
+        self.dump.preload(&preload);
         let (content, aoc) = platform_prefixes(self.endian);
         let total = content_files.len() + aoc_files.len();
         let current = AtomicUsize::new(0);
@@ -591,7 +1230,61 @@ impl ModUnpacker {
             log::trace!("CLEARPROGRESS");
             Ok(())
         })?;
-        Ok(self.rstb)
+        if !self.packs.is_empty() {
+            log::info!("Packs from the last apply: {}", self.packs);
+        }
+        if !self.applied.is_empty() {
+            let mut overrides: Vec<_> = self
+                .applied
+                .iter()
+                .map(|entry| {
+                    let (policy, pattern) = entry.value();
+                    format!("{}: {policy} (from {pattern})", entry.key())
+                })
+                .collect();
+            overrides.sort();
+            log::warn!(
+                "Merge policy overrides changed how {} files were merged:\n{}",
+                overrides.len(),
+                overrides.join("\n")
+            );
+        }
+        let mut shared: Vec<_> = self
+            .shared
+            .iter()
+            .filter_map(|entry| {
+                match entry.value().as_slice() {
+                    [build] if build.parents.len() < 2 => None,
+                    [build] => {
+                        Some(format!(
+                            "{}: merged once for {}",
+                            entry.key(),
+                            build.parents.join(", ")
+                        ))
+                    }
+                    builds => {
+                        let copies: Vec<_> = builds.iter().map(|b| b.parents.join(", ")).collect();
+                        Some(format!(
+                            "{}: stock copies differ, merged separately for {}",
+                            entry.key(),
+                            copies.join("; ")
+                        ))
+                    }
+                }
+            })
+            .collect();
+        if !shared.is_empty() {
+            shared.sort();
+            log::info!(
+                "Merged {} nested files which are in several packs:\n{}",
+                shared.len(),
+                shared.join("\n")
+            );
+        }
+        Ok(RstbUpdates {
+            computed: self.rstb,
+            explicit: self.explicit.into_inner(),
+        })
     }
 
     fn unpack_texts(&self, mut langs: IndexSet<Language>) -> Result<()> {
@@ -650,12 +1343,27 @@ impl ModUnpacker {
         current_file: &AtomicUsize,
         aoc: bool,
     ) -> Result<()> {
-        files.into_par_iter().try_for_each(|file| -> Result<()> {
-            let data = self.build_file(file.as_str(), aoc)?;
-            let out_file = dir.join(file.as_str());
-            out_file.parent().map(fs::create_dir_all).transpose()?;
-            let mut writer = std::io::BufWriter::new(fs::File::create(&out_file)?);
-            writer.write_all(&compress_if(data.as_ref(), &out_file))?;
+        // A file given under both names is only written once
+        let files: std::collections::BTreeMap<Cow<str>, &String> = files
+            .into_iter()
+            .map(|file| {
+                let name = self.output_name(file, |name| {
+                    self.dump.source().file_exists(name.as_ref())
+                });
+                (name, file)
+            })
+            .collect();
+        files.into_par_iter().try_for_each(|(name, file)| -> Result<()> {
+            let out_file = dir.join(&*name);
+            // Only a partial apply keeps the output of the last one around
+            let previous = (self.manifest.is_some() && out_file.is_file()).then_some(&out_file);
+            if let Some(data) =
+                self.build_file(file.as_str(), aoc, previous.map(|p| p.as_path()), None)?
+            {
+                out_file.parent().map(fs::create_dir_all).transpose()?;
+                let mut writer = std::io::BufWriter::new(fs::File::create(&out_file)?);
+                writer.write_all(&compression::compress_if(data.as_ref(), &name))?;
+            }
             let progress = 1 + current_file.load(Ordering::Relaxed);
             current_file.store(progress, Ordering::Relaxed);
             let percent = (progress as f64 / total_files as f64) * 100.0;
@@ -671,28 +1379,47 @@ impl ModUnpacker {
         })
     }
 
-    fn build_file(&self, file: &str, aoc: bool) -> Result<Vec<u8>> {
+    /// Builds a merged file, or a file nested in a pack if `nest` is where.
+    /// If `previous` is the output of the last apply and it is a SARC which
+    /// could be patched in place, it is updated and `None` is returned. A
+    /// failure is marked with the innermost file which failed, for
+    /// [`export_repro`](Self::export_repro).
+    fn build_file(
+        &self,
+        file: &str,
+        aoc: bool,
+        previous: Option<&Path>,
+        nest: Option<&Nest>,
+    ) -> Result<Option<Vec<u8>>> {
+        self.merge_file(file, aoc, previous, nest).map_err(|e| {
+            if e.downcast_ref::<MergeFailure>().is_some() {
+                e
+            } else {
+                e.context(MergeFailure {
+                    file: file.into(),
+                    aoc,
+                })
+            }
+        })
+    }
+
+    fn merge_file(
+        &self,
+        file: &str,
+        aoc: bool,
+        previous: Option<&Path>,
+        nest: Option<&Nest>,
+    ) -> Result<Option<Vec<u8>>> {
         let mut versions = std::collections::VecDeque::with_capacity(
             (self.mods.len() as f32 / 2.).ceil() as usize,
         );
         let canon = if aoc {
-            canonicalize(jstr!("Aoc/0010/{file}"))
+            canonicalize_aoc(file)
         } else {
             canonicalize(file)
         };
-        let filename = Path::new(canon.as_str());
         let mut rstb_val = None;
-        let can_rstb = !RSTB_EXCLUDE_EXTS.contains(
-            &filename
-                .extension()
-                .and_then(|e| e.to_str())
-                .unwrap_or_default(),
-        ) && !RSTB_EXCLUDE_NAMES.contains(
-            &filename
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or_default(),
-        );
+        let can_rstb = can_rstb(&canon);
         let mut dump_error: Vec<anyhow_ext::Error> = vec![];
         let res_result = self.dump.get_data(file).or_else(|e| {
             log::trace!("{e:?}");
@@ -703,6 +1430,7 @@ impl ModUnpacker {
                 self.dump.get_resource(canon.as_str())
             })
         });
+        let mut in_dump = res_result.is_ok();
         match res_result {
             Ok(ref_res) => versions.push_back(ref_res),
             Err(e) => {
@@ -739,6 +1467,28 @@ impl ModUnpacker {
                 }
             }
         }
+        // A modded file nested in a pack is merged on the pack's own stock
+        // copy, once for every pack with the same copy. The dump keeps only
+        // one copy of each file, which may be another pack's.
+        let mut shared = None;
+        if let Some(nest) = nest.filter(|_| versions.len() > usize::from(in_dump)) {
+            if let Some((stock, own)) = self.stock_copy(nest) {
+                if let Some(data) = self.reuse_shared(&canon, stock, nest) {
+                    return Ok(data);
+                }
+                if let Some(own) = own {
+                    let name = nest.path.rsplit("//").next().unwrap_or(file);
+                    let own = Arc::new(ResourceData::from_binary(name, own)?);
+                    if in_dump {
+                        versions[0] = own;
+                    } else {
+                        versions.push_front(own);
+                        in_dump = true;
+                    }
+                }
+                shared = Some((stock, nest));
+            }
+        }
         let base_version = versions
             .pop_front()
             .with_context(|| {
@@ -753,6 +1503,23 @@ impl ModUnpacker {
                 }
                 err
             })?;
+        if let Some(rule) = (!self.policies.is_empty())
+            .then(|| self.policies.resolve(&canon, &resource_kind(&base_version)))
+            .flatten()
+            .filter(|rule| rule.policy != MergePolicy::Merge)
+        {
+            self.applied
+                .insert(canon.clone(), (rule.policy, rule.pattern.clone()));
+            match rule.policy {
+                MergePolicy::PriorityBinary => {
+                    let top = versions.len().saturating_sub(1);
+                    versions.drain(..top);
+                }
+                MergePolicy::Skip if in_dump => versions.clear(),
+                MergePolicy::Skip => return Ok(None),
+                MergePolicy::Merge => unreachable!(),
+            }
+        }
         let is_modded = !versions.is_empty() || self.hashes.is_file_new(&canon);
         let data = match base_version.as_ref() {
             ResourceData::Binary(_) => {
@@ -764,10 +1531,10 @@ impl ModUnpacker {
                         self.endian.into(),
                     ));
                 }
-                match Arc::try_unwrap(res) {
+                Some(match Arc::try_unwrap(res) {
                     Ok(res) => res.take_binary().unwrap(),
                     Err(res) => res.as_binary().map(|b| b.to_vec()).unwrap(),
-                }
+                })
             }
             ResourceData::Mergeable(base_res) => {
                 let merged = versions
@@ -778,8 +1545,17 @@ impl ModUnpacker {
                         }
                         res
                     });
+                if let MergeableResource::ResourceSizeTable(table) = &merged {
+                    // Mods' own values are applied again after computed ones,
+                    // so they are not raised over
+                    let explicit = match base_res {
+                        MergeableResource::ResourceSizeTable(stock) if in_dump => stock.diff(table),
+                        _ => table.as_ref().clone(),
+                    };
+                    *self.explicit.lock() = Some(explicit);
+                }
                 let data = merged.into_binary(self.endian);
-                if can_rstb && (is_modded || self.hashes.is_file_modded(&canon, &data, true)) {
+                if can_rstb && (is_modded || is_file_modded(&self.hashes, &canon, &data, true)) {
                     rstb_val = Some(rstb::calc::estimate_from_slice_and_name(
                         &data,
                         &canon,
@@ -789,7 +1565,7 @@ impl ModUnpacker {
                         rstb_val = rstb_val.map(|v| v.map(|v| (v as f32 * 1.25) as u32));
                     }
                 }
-                data
+                Some(data)
             }
             ResourceData::Sarc(base_sarc) => {
                 let merged = versions
@@ -800,12 +1576,24 @@ impl ModUnpacker {
                         }
                         res
                     });
-                let data = self
-                    .build_sarc(merged, aoc)
+                let alignment = merged.alignment;
+                let path = nest.map_or(file, |nest| nest.path.as_str());
+                let files = self
+                    .build_sarc_files(merged, in_dump.then_some(base_sarc), aoc, path)
                     .with_context(|| jstr!("Failed to build SARC file {&file}"))?;
+                let (data, size) = match previous
+                    .and_then(|previous| self.patch_sarc(previous, &files, alignment))
+                {
+                    Some(size) => (None, size),
+                    None => {
+                        let data = self.write_sarc(files, alignment);
+                        let size = data.len();
+                        (Some(data), size)
+                    }
+                };
                 if can_rstb {
                     rstb_val = Some(rstb::calc::calc_from_size_and_name(
-                        data.len(),
+                        size,
                         &canon,
                         self.endian.into(),
                     ));
@@ -814,32 +1602,208 @@ impl ModUnpacker {
             }
         };
         if let Some(val) = rstb_val {
-            self.rstb.insert(canon, val);
+            self.rstb.insert(canon.clone(), val);
         }
-        Ok(data)
-    }
-
-    fn build_sarc(&self, sarc: SarcMap, aoc: bool) -> Result<Vec<u8>> {
-        let mut writer = SarcWriter::new(self.endian.into()).with_min_alignment(sarc.alignment);
-        for file in sarc.files.into_iter() {
-            let data = self
-                .build_file(&file, aoc)
-                .with_context(|| jstr!("Failed to build file {&file} for SARC"))?;
-            writer.add_file(
-                file.as_str(),
-                compress_if(data.as_ref(), file.as_str()).as_ref(),
-            );
+        Ok(match shared {
+            Some((stock, nest)) => self.share(canon, stock, nest, data),
+            None => data,
+        })
+    }
+
+    /// The stock copy of a nested file in its parent, hashed, with its data
+    /// if the parent has one. This is `None` if the copy cannot be read, so
+    /// the file is merged for that parent alone.
+    fn stock_copy(&self, nest: &Nest) -> Option<(StockCopy, Option<Vec<u8>>)> {
+        if !nest.in_stock {
+            return Some((StockCopy::Absent, None));
+        }
+        // The dump only finds files up to two packs deep
+        if nest.path.matches("//").count() > 2 {
+            return None;
+        }
+        let data = self
+            .dump
+            .get_bytes_from_sarc(&nest.path)
+            .inspect_err(|e| log::debug!("No stock copy of {}: {e}", nest.path))
+            .ok()?;
+        let mut hasher = FxHasher::default();
+        data.hash(&mut hasher);
+        Some((StockCopy::Hash(hasher.finish()), Some(data)))
+    }
+
+    /// The output of a nested file already merged on the same stock copy,
+    /// noting that it is written into another parent as well.
+    fn reuse_shared(&self, canon: &str, stock: StockCopy, nest: &Nest) -> Option<Option<Vec<u8>>> {
+        let mut builds = self.shared.get_mut(canon)?;
+        let build = builds.iter_mut().find(|build| build.stock == stock)?;
+        build.parents.push(nest_parent(&nest.path).into());
+        Some(build.data.clone())
+    }
+
+    /// Keeps the output of a nested file for other parents with the same
+    /// stock copy. If another parent merged it at the same time, its output
+    /// is used instead, so every parent has the same.
+    fn share(
+        &self,
+        canon: String,
+        stock: StockCopy,
+        nest: &Nest,
+        data: Option<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        let parent = nest_parent(&nest.path).into();
+        let mut builds = self.shared.entry(canon).or_default();
+        if let Some(build) = builds.iter_mut().find(|build| build.stock == stock) {
+            build.parents.push(parent);
+            return build.data.clone();
+        }
+        builds.push(SharedFile {
+            stock,
+            data: data.clone(),
+            parents: vec![parent],
+        });
+        data
+    }
+
+    /// The name to write a file under, which is the one the game expects if
+    /// the file was given under the other.
+    fn output_name<'a>(&self, file: &'a str, in_stock: impl Fn(&str) -> bool) -> Cow<'a, str> {
+        let name = compression::conventional_name(file, in_stock);
+        if name != file {
+            log::warn!("{file} is stored as {name} by the game, so it will be written as that");
+        }
+        name
+    }
+
+    /// Builds the files of a SARC as they will be stored in it, named as in
+    /// the stock version if there is one.
+    fn build_sarc_files(
+        &self,
+        sarc: SarcMap,
+        stock: Option<&SarcMap>,
+        aoc: bool,
+        path: &str,
+    ) -> Result<Vec<(std::string::String, Vec<u8>)>> {
+        let mut names = std::collections::HashSet::new();
+        let in_stock =
+            |name: &str| stock.is_some_and(|stock| stock.files.contains(String::from(name)));
+        sarc.files
+            .into_iter()
+            .filter_map(|file| {
+                let name = self.output_name(&file, in_stock);
+                // A file given under both names is only written once
+                if !names.insert(name.to_string()) {
+                    return None;
+                }
+                let nest = Nest {
+                    path:     format!("{path}//{name}"),
+                    in_stock: in_stock(name.as_ref()),
+                };
+                // Nested files are never patched, so there is only no data
+                // when a policy override leaves the file out
+                let data = match self
+                    .build_file(&file, aoc, None, Some(&nest))
+                    .with_context(|| jstr!("Failed to build file {&file} for SARC"))
+                {
+                    Ok(data) => data?,
+                    Err(e) => return Some(Err(e)),
+                };
+                let compressed = match compression::compress_if(data.as_ref(), &name) {
+                    Cow::Owned(compressed) => Some(compressed),
+                    Cow::Borrowed(_) => None,
+                };
+                Some(Ok((name.into_owned(), compressed.unwrap_or(data))))
+            })
+            .collect()
+    }
+
+    fn write_sarc(&self, files: Vec<(std::string::String, Vec<u8>)>, alignment: usize) -> Vec<u8> {
+        let mut writer = SarcWriter::new(self.endian.into()).with_min_alignment(alignment);
+        for (name, data) in files {
+            writer.add_file(name.as_str(), data);
+        }
+        writer.to_binary()
+    }
+
+    /// Patches a SARC left by the last apply with the files it should now
+    /// hold, returning its new size, or `None` if it has to be rebuilt.
+    fn patch_sarc(
+        &self,
+        path: &Path,
+        files: &[(std::string::String, Vec<u8>)],
+        alignment: usize,
+    ) -> Option<usize> {
+        let old = match fs::read(path) {
+            Ok(old) => old,
+            Err(e) => {
+                log::debug!("Rebuilding {}: {e}", path.display());
+                return None;
+            }
+        };
+        // Yaz0 compressed packs have to be recompressed whole anyway
+        if !old.starts_with(b"SARC") {
+            return None;
+        }
+        let plan = match sarc_patch::Plan::new(&old, files, self.endian, alignment) {
+            Ok(plan) => plan,
+            Err(reason) => {
+                log::debug!("Rebuilding {}: {reason}", path.display());
+                self.packs.rebuilt.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+        if plan.is_empty() {
+            log::debug!("{} is unchanged", path.display());
+            self.packs.unchanged.fetch_add(1, Ordering::Relaxed);
+            return Some(plan.size);
+        }
+        let result = fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|mut out| {
+                let written = plan.apply(&mut out, &old, files)?;
+                out.set_len(plan.size as u64)?;
+                Ok(written)
+            });
+        match result {
+            Ok(written) => {
+                log::debug!(
+                    "Patched {}: {} of {} files changed, {written} of {} bytes written",
+                    path.display(),
+                    plan.changed,
+                    files.len(),
+                    plan.size
+                );
+                self.packs.patched.fetch_add(1, Ordering::Relaxed);
+                self.packs
+                    .written
+                    .fetch_add(written as u64, Ordering::Relaxed);
+                self.packs
+                    .total
+                    .fetch_add(plan.size as u64, Ordering::Relaxed);
+                Some(plan.size)
+            }
+            Err(e) => {
+                // The old file may be half written, but a rebuild replaces it
+                log::warn!("Failed to patch {}, rebuilding it: {e}", path.display());
+                self.packs.rebuilt.fetch_add(1, Ordering::Relaxed);
+                None
+            }
         }
-        Ok(writer.to_binary())
     }
 }
 
+/// The path of the pack a nested file is in, from the file's nest path.
+fn nest_parent(path: &str) -> &str {
+    path.rsplit_once("//").map_or(path, |(parent, _)| parent)
+}
+
 /// Extract a zipped mod, decompressing the binary files, but otherwise
 /// leaving the format intact.
 pub fn unzip_mod(mod_path: &Path, out_path: &Path) -> anyhow_ext::Result<()> {
     let mut zip = zip::ZipArchive::new(BufReader::new(fs::File::open(mod_path)?))
         .context("Failed to open mod ZIP")?;
     zip.extract(out_path)?;
+    let decompressor = init_decompressor();
     WalkDir::new(out_path)
         .into_iter()
         .filter_map(std::result::Result::ok)
@@ -849,17 +1813,22 @@ pub fn unzip_mod(mod_path: &Path, out_path: &Path) -> anyhow_ext::Result<()> {
                     .to_str()
                     .map(|n| !n.ends_with(".yml") && !n.starts_with("thumb"))
                     .unwrap_or(true)
+                    && f.path() != out_path.join(README)
+                    && !f.path().starts_with(out_path.join(DOCS_DIR))
+                    && !f.path().starts_with(out_path.join(PASSTHROUGH_DIR))
             }
         })
         .par_bridge()
         .try_for_each(|f| -> anyhow_ext::Result<()> {
             let f = f.path();
-            let data = zstd::decode_all(
-                fs::read(&f)
-                    .with_context(|| format!("Failed to read file at {}", f.display()))?
-                    .as_slice(),
-            )
-            .with_context(|| format!("Failed to decompress file at {}", f.display()))?;
+            let data =
+                fs::read(&f).with_context(|| format!("Failed to read file at {}", f.display()))?;
+            let size = zstd::bulk::Decompressor::upper_bound(&data).unwrap_or(data.len() * 1024);
+            let data = decompressor
+                .lock()
+                .decompress(&data, size)
+                .or_else(|e| zstd::decode_all(data.as_slice()).context(e))
+                .with_context(|| format!("Failed to decompress file at {}", f.display()))?;
             fs::write(&f, data)
                 .with_context(|| format!("Failed to write unpacked file at {}", f.display()))?;
             Ok(())
@@ -877,22 +1846,499 @@ mod tests {
         dbg!(&mod_reader.manifest);
     }
 
+    fn meta() -> Meta {
+        Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: crate::FORMAT_VERSION,
+            platform: crate::ModPlatform::Specific(Endian::Big),
+            name: "Test Mod".into(),
+            version: "1.0.0".into(),
+            category: "Other".into(),
+            author: "Test".into(),
+            description: "A test mod".into(),
+            masters: Default::default(),
+            url: None,
+            options: vec![],
+        }
+    }
+
+    fn identity_fixture(dir: &Path, name: &str, data: &[u8]) -> PathBuf {
+        let mut manifest = Manifest::default();
+        manifest
+            .content_files
+            .insert("Actor/Pack/Test.sbactorpack".into());
+        crate::pack::pack_resources(dir.join(name), &meta(), &manifest, [(
+            "Actor/Pack/Test.bactorpack".into(),
+            ResourceData::Binary(data.to_vec()),
+        )])
+        .unwrap()
+    }
+
+    fn recompress(src: &Path, dest: &Path) {
+        let mut decomp = zstd::bulk::Decompressor::with_dictionary(crate::DICTIONARY).unwrap();
+        let mut comp = zstd::bulk::Compressor::with_dictionary(19, crate::DICTIONARY).unwrap();
+        let mut zip = zip::ZipArchive::new(fs::File::open(src).unwrap()).unwrap();
+        let mut out = zip::ZipWriter::new(fs::File::create(dest).unwrap());
+        let opts = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        for i in (0..zip.len()).rev() {
+            let mut file = zip.by_index(i).unwrap();
+            let mut data = vec![];
+            file.read_to_end(&mut data).unwrap();
+            if !file.name().ends_with(".yml") {
+                data = comp
+                    .compress(&decomp.decompress(&data, 1024 * 1024).unwrap())
+                    .unwrap();
+            }
+            out.start_file(file.name().to_owned(), opts).unwrap();
+            out.write_all(&data).unwrap();
+        }
+        out.finish().unwrap();
+    }
+
     #[test]
-    fn unpack_mod() {
-        let mod_reader = ModReader::open("test/wiiu.zip", vec![]).unwrap();
-        let dump = serde_yaml::from_str::<ResourceReader>(
-            &std::fs::read_to_string("../.vscode/dump.yml").unwrap(),
-        )
+    fn content_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = |path: &Path| {
+            ModReader::open(path, vec![])
+                .unwrap()
+                .content_identity()
+                .unwrap()
+        };
+        let original = identity_fixture(dir.path(), "original.zip", b"Some actor data");
+        let renamed = identity_fixture(dir.path(), "renamed.zip", b"Some actor data");
+        let recompressed = dir.path().join("recompressed.zip");
+        recompress(&original, &recompressed);
+        let different = identity_fixture(dir.path(), "different.zip", b"Other actor data");
+        assert_ne!(
+            fs::read(&original).unwrap(),
+            fs::read(&recompressed).unwrap()
+        );
+        assert_eq!(identity(&original), identity(&renamed));
+        assert_eq!(identity(&original), identity(&recompressed));
+        assert_ne!(identity(&original), identity(&different));
+    }
+
+    /// Mods packaged before uncompressed SARCs kept their extension store
+    /// them as `.arc`.
+    #[test]
+    fn legacy_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert("Pack/Test.sarc".into());
+        let path = crate::pack::pack_resources(dir.path().join("mod.zip"), &meta(), &manifest, [(
+            "Pack/Test.arc".into(),
+            ResourceData::Binary(b"Some SARC".to_vec()),
+        )])
         .unwrap();
+        let reader = ModReader::open(&path, vec![]).unwrap();
+        let data = reader.get_data(Path::new("Pack/Test.sarc")).unwrap();
+        assert_eq!(
+            minicbor_ser::from_slice::<ResourceData>(&data).unwrap(),
+            ResourceData::Binary(b"Some SARC".to_vec())
+        );
+    }
+
+    #[test]
+    fn resource_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let cache_count = || fs::read_dir(&cache_dir).unwrap().count();
+        let file = Path::new("Actor/Pack/Test.sbactorpack");
+        let path = identity_fixture(dir.path(), "mod.zip", b"Some actor data");
+        let expected = ModReader::open(&path, vec![])
+            .unwrap()
+            .get_data(file)
+            .unwrap();
+        let cached = ModReader::open(&path, vec![])
+            .unwrap()
+            .with_cache(&cache_dir);
+        assert!(cached.cache.is_some());
+        assert_eq!(cache_count(), 1);
+        let reopened = ModReader::open(&path, vec![])
+            .unwrap()
+            .with_cache(&cache_dir);
+        assert_eq!(reopened.cache.as_ref().map(|c| c.len()), Some(1));
+        assert_eq!(reopened.get_data(file).unwrap(), expected);
+        assert_eq!(cache_count(), 1);
+        // The cache is only valid for the platform it was built for
+        assert!(reopened.with_platform(Endian::Little).cache.is_none());
+        identity_fixture(dir.path(), "mod.zip", b"Other actor data");
+        let changed = ModReader::open(&path, vec![])
+            .unwrap()
+            .with_cache(&cache_dir);
+        assert_eq!(cache_count(), 2);
+        assert_eq!(
+            changed.get_data(file).unwrap(),
+            ModReader::open(&path, vec![])
+                .unwrap()
+                .get_data(file)
+                .unwrap()
+        );
+        assert_ne!(changed.get_data(file).unwrap(), expected);
+    }
+
+    #[test]
+    fn docs() {
+        let dir = tempfile::tempdir().unwrap();
+        let zipped = identity_fixture(dir.path(), "mod.zip", b"Some actor data");
+        assert_eq!(ModReader::open(&zipped, vec![]).unwrap().readme(), None);
+        let root = dir.path().join("unzipped");
+        zip::ZipArchive::new(fs::File::open(&zipped).unwrap())
+            .unwrap()
+            .extract(&root)
+            .unwrap();
+        fs::write(root.join(README), "# Test Mod\n\n![Shot](docs/shot.png)").unwrap();
+        fs::create_dir_all(root.join("docs/img")).unwrap();
+        fs::write(root.join("docs/shot.png"), b"png").unwrap();
+        fs::write(root.join("docs/img/icon.png"), b"icon").unwrap();
+        let reader = ModReader::open(&root, vec![]).unwrap();
+        assert!(reader.readme().unwrap().starts_with("# Test Mod"));
+        assert_eq!(reader.doc_asset("docs/shot.png").unwrap(), b"png");
+        assert_eq!(reader.doc_asset("./docs/img/../shot.png").unwrap(), b"png");
+        assert_eq!(reader.doc_asset("docs\\img\\icon.png").unwrap(), b"icon");
+        assert_eq!(reader.doc_asset("../unzipped/docs/shot.png"), None);
+        assert_eq!(reader.doc_asset("docs/../meta.yml"), None);
+        assert_eq!(reader.doc_asset(README), None);
+        let identity = reader.content_identity().unwrap();
+        fs::write(root.join("docs/shot.png"), b"changed").unwrap();
+        assert_eq!(reader.content_identity().unwrap(), identity);
+    }
+
+    #[test]
+    fn passthrough() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = |name: &str, files: &[(&str, &str)]| {
+            let zipped = identity_fixture(dir.path(), &format!("{name}.zip"), b"Some actor data");
+            let root = dir.path().join(name);
+            zip::ZipArchive::new(fs::File::open(&zipped).unwrap())
+                .unwrap()
+                .extract(&root)
+                .unwrap();
+            for (file, data) in files {
+                let path = root.join(PASSTHROUGH_DIR).join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, data).unwrap();
+            }
+            let mut reader = ModReader::open(&root, vec![]).unwrap();
+            reader.meta.name = name.into();
+            reader
+        };
+        let first = open("First", &[
+            ("rules.txt", "first rules"),
+            ("patches/shader.txt", "shader"),
+            ("content/Actor/Pack/Test.sbactorpack", "sneaky"),
+        ]);
+        let second = open("Second", &[("rules.txt", "second rules"), ("LICENSE", "MIT")]);
+        assert_eq!(second.passthrough_files(), ["LICENSE", "rules.txt"]);
+        let out = dir.path().join("merged");
+        let merged = merge_passthrough(&[first, second], &out).unwrap();
+        assert_eq!(
+            merged.files.keys().collect::<Vec<_>>(),
+            ["LICENSE", "patches/shader.txt", "rules.txt"]
+        );
+        assert_eq!(merged.files["rules.txt"].as_str(), "Second");
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts["rules.txt"], ["First", "Second"].map(String::from));
+        assert_eq!(fs::read(out.join("rules.txt")).unwrap(), b"second rules");
+        assert_eq!(fs::read(out.join("patches/shader.txt")).unwrap(), b"shader");
+        assert!(!out.join("content").exists());
+    }
+
+    #[test]
+    fn unpack_mod() {
+        let tmp = tempfile::tempdir().unwrap();
+        let fixture =
+            |name: &str| fs::read(Path::new("../uk-content/test/Actor/Pack").join(name)).unwrap();
+        let pack = "Actor/Pack/Enemy_Moriblin_Junior.sbactorpack";
+        let dump = Arc::new(ResourceReader::from_memory(
+            uk_reader::MemoryROMSource::new(Endian::Big)
+                .with_file(pack, fixture("Enemy_Moriblin_Junior.sbactorpack")),
+        ));
+        let modded = fixture("Enemy_Moriblin_Junior_Mod.sbactorpack");
+        let source = tmp.path().join("source");
+        let modded_path = source.join("content").join(pack);
+        fs::create_dir_all(modded_path.parent().unwrap()).unwrap();
+        fs::write(&modded_path, &modded).unwrap();
+        let packed =
+            crate::pack::ModPacker::new(&source, tmp.path().join("mod.zip"), Some(meta()), vec![
+                dump.clone(),
+            ])
+            .unwrap()
+            .pack()
+            .unwrap();
+        let out = tmp.path().join("unpacked");
         ModUnpacker::new(
-            Arc::new(dump),
+            dump,
             Endian::Big,
             Language::USen,
-            vec![mod_reader],
-            "test/wiiu_unpack".into(),
+            vec![ModReader::open(&packed, vec![]).unwrap()],
+            out.clone(),
         )
         .unpack()
         .unwrap();
+        let names = |data: &[u8]| -> BTreeSet<std::string::String> {
+            roead::sarc::Sarc::new(roead::yaz0::decompress_if(data).as_ref())
+                .unwrap()
+                .files()
+                .filter_map(|f| f.name.map(|n| n.to_owned()))
+                .collect()
+        };
+        let unpacked = fs::read(out.join("content").join(pack)).unwrap();
+        assert_eq!(names(&unpacked), names(&modded));
+    }
+
+    /// Merging from the resource cache gives the same output as merging
+    /// straight from the archive, both when the cache is built and after.
+    #[test]
+    fn cached_unpack() {
+        let tmp = tempfile::tempdir().unwrap();
+        let fixture =
+            |name: &str| fs::read(Path::new("../uk-content/test/Actor/Pack").join(name)).unwrap();
+        let pack = "Actor/Pack/Enemy_Moriblin_Junior.sbactorpack";
+        let dump = Arc::new(ResourceReader::from_memory(
+            uk_reader::MemoryROMSource::new(Endian::Big)
+                .with_file(pack, fixture("Enemy_Moriblin_Junior.sbactorpack")),
+        ));
+        let source = tmp.path().join("source");
+        for (file, data) in [
+            (pack, fixture("Enemy_Moriblin_Junior_Mod.sbactorpack")),
+            ("Movie/Custom.mp4", b"not a movie".to_vec()),
+        ] {
+            let path = source.join("content").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        let packed =
+            crate::pack::ModPacker::new(&source, tmp.path().join("mod.zip"), Some(meta()), vec![
+                dump.clone(),
+            ])
+            .unwrap()
+            .pack()
+            .unwrap();
+        let cache = tmp.path().join("cache");
+        let unpack = |name: &str, cached: bool| {
+            let mut reader = ModReader::open(&packed, vec![])
+                .unwrap()
+                .with_platform(Endian::Big);
+            if cached {
+                reader = reader.with_cache(&cache);
+                assert!(reader.cache.is_some());
+            }
+            let out = tmp.path().join(name);
+            ModUnpacker::new(
+                dump.clone(),
+                Endian::Big,
+                Language::USen,
+                vec![reader],
+                out.clone(),
+            )
+            .unpack()
+            .unwrap();
+            WalkDir::new(&out)
+                .into_iter()
+                .filter_map(std::result::Result::ok)
+                .filter(|e| e.file_type().is_file())
+                .map(|e| {
+                    let path = e.path();
+                    (
+                        path.strip_prefix(&out).unwrap().to_path_buf(),
+                        fs::read(&path).unwrap(),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        let uncached = unpack("uncached", false);
+        assert!(uncached.contains_key(Path::new("content/Movie/Custom.mp4")));
+        assert_eq!(unpack("building", true), uncached);
+        assert_eq!(unpack("cached", true), uncached);
+    }
+
+    /// Two mods change different keys of a stock BYML, and the second has
+    /// priority. One of them also adds a file the dump does not have.
+    #[test]
+    fn merge_policies() {
+        use roead::byml::Byml;
+
+        use crate::policy::{MergePolicy::*, PolicyRule};
+
+        let byml = |a: i32, b: i32| {
+            Byml::Map(
+                [("A".into(), Byml::I32(a)), ("B".into(), Byml::I32(b))]
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        let (file, new_file) = ("Test/Test.byml", "Test/New.bin");
+        let stock = byml(0, 0);
+        let dump = Arc::new(ResourceReader::from_memory(
+            uk_reader::MemoryROMSource::new(Endian::Big)
+                .with_file(file, stock.to_binary(roead::Endian::Big)),
+        ));
+        let tmp = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = [byml(1, 0), byml(0, 2)]
+            .iter()
+            .enumerate()
+            .map(|(i, modded)| {
+                let mut manifest = Manifest::default();
+                manifest.content_files.insert(file.into());
+                manifest.content_files.insert(new_file.into());
+                crate::pack::pack_resources(
+                    tmp.path().join(format!("{i}.zip")),
+                    &meta(),
+                    &manifest,
+                    [
+                        (
+                            file.into(),
+                            ResourceData::Mergeable(MergeableResource::GenericByml(Box::new(
+                                stock.diff(modded),
+                            ))),
+                        ),
+                        (new_file.into(), ResourceData::Binary(vec![i as u8])),
+                    ],
+                )
+                .unwrap()
+            })
+            .collect();
+        let build = |file: &str, rules: &[(&str, MergePolicy)]| {
+            let mods = paths
+                .iter()
+                .map(|path| ModReader::open(path, vec![]).unwrap())
+                .collect();
+            let rules = rules
+                .iter()
+                .map(|(pattern, policy)| {
+                    PolicyRule {
+                        pattern: (*pattern).into(),
+                        policy:  *policy,
+                    }
+                })
+                .collect();
+            ModUnpacker::new(
+                dump.clone(),
+                Endian::Big,
+                Language::USen,
+                mods,
+                tmp.path().join("out"),
+            )
+            .with_policies(MergePolicies::new(rules).unwrap())
+            .build_file(file, false, None, None)
+            .unwrap()
+        };
+        let merged = |rules: &[(&str, MergePolicy)]| {
+            build(file, rules).map(|data| Byml::from_binary(data).unwrap())
+        };
+        assert_eq!(merged(&[]), Some(byml(1, 2)));
+        assert_eq!(merged(&[("GenericByml", Merge)]), Some(byml(1, 2)));
+        assert_eq!(merged(&[("GenericByml", PriorityBinary)]), Some(byml(0, 2)));
+        assert_eq!(merged(&[("Test/*.byml", Skip)]), Some(stock));
+        // The exact path beats the glob, which beats the type
+        assert_eq!(
+            merged(&[
+                ("GenericByml", PriorityBinary),
+                ("Test/**", Skip),
+                (file, Merge),
+            ]),
+            Some(byml(1, 2))
+        );
+        assert_eq!(
+            merged(&[("GenericByml", PriorityBinary), ("Test/**", Merge)]),
+            Some(byml(1, 2))
+        );
+        assert_eq!(build(new_file, &[]), Some(vec![1]));
+        assert_eq!(
+            build(new_file, &[("Binary", PriorityBinary)]),
+            Some(vec![1])
+        );
+        assert_eq!(build(new_file, &[("Binary", Skip)]), None);
+    }
+
+    /// Two packs have the same stock copy of a file, which two mods change
+    /// through different packs. A third pack has a copy of its own.
+    #[test]
+    fn shared_nested_files() {
+        use roead::byml::Byml;
+
+        let byml = |keys: &[(&str, i32)]| {
+            Byml::Map(
+                keys.iter()
+                    .map(|(key, value)| ((*key).into(), Byml::I32(*value)))
+                    .collect(),
+            )
+        };
+        let member = "Test/Shared.byml";
+        let pack = |stock: &Byml| {
+            SarcWriter::new(roead::Endian::Big)
+                .with_file(member, stock.to_binary(roead::Endian::Big))
+                .to_binary()
+        };
+        let packs = ["Pack/First.pack", "Pack/Second.pack", "Pack/Third.pack"];
+        let stock = byml(&[("A", 0), ("B", 0)]);
+        let own = byml(&[("A", 0), ("B", 0), ("C", 3)]);
+        let dump = Arc::new(ResourceReader::from_memory(
+            uk_reader::MemoryROMSource::new(Endian::Big)
+                .with_file(packs[0], pack(&stock))
+                .with_file(packs[1], pack(&stock))
+                .with_file(packs[2], pack(&own)),
+        ));
+        let tmp = tempfile::tempdir().unwrap();
+        let mods = [
+            (&packs[..1], byml(&[("A", 1), ("B", 0)])),
+            (&packs[1..], byml(&[("A", 0), ("B", 2)])),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, (parents, modded))| {
+            let mut manifest = Manifest::default();
+            let mut resources = vec![(
+                member.into(),
+                ResourceData::Mergeable(MergeableResource::GenericByml(Box::new(
+                    stock.diff(modded),
+                ))),
+            )];
+            for parent in parents.iter() {
+                manifest.content_files.insert((*parent).into());
+                resources.push((
+                    (*parent).into(),
+                    ResourceData::Sarc(SarcMap::from_binary(pack(&stock)).unwrap()),
+                ));
+            }
+            let path = crate::pack::pack_resources(
+                tmp.path().join(format!("{i}.zip")),
+                &meta(),
+                &manifest,
+                resources,
+            )
+            .unwrap();
+            ModReader::open(path, vec![]).unwrap()
+        })
+        .collect();
+        let unpacker = ModUnpacker::new(
+            dump,
+            Endian::Big,
+            Language::USen,
+            mods,
+            tmp.path().join("out"),
+        );
+        let merged: Vec<Byml> = packs
+            .iter()
+            .map(|pack| {
+                let data = unpacker
+                    .build_file(pack, false, None, None)
+                    .unwrap()
+                    .unwrap();
+                let sarc = roead::sarc::Sarc::new(data).unwrap();
+                Byml::from_binary(sarc.get_data(member).unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(merged[0], byml(&[("A", 1), ("B", 2)]));
+        assert_eq!(merged[1], merged[0]);
+        // Merged on its own stock copy
+        assert_eq!(merged[2], byml(&[("A", 1), ("B", 2), ("C", 3)]));
+
+        let builds = unpacker.shared.get(member).unwrap();
+        let parents: Vec<_> = builds.iter().map(|build| build.parents.clone()).collect();
+        assert_eq!(parents, [&packs[..2], &packs[2..]]);
     }
 
     #[test]
@@ -914,7 +2360,11 @@ mod bonus {
     use rayon::prelude::*;
     use roead::sarc::Sarc;
     use smartstring::alias::String;
-    use uk_content::{resource::MergeableResource, util::HashMap};
+    use uk_content::{
+        canon::{canonicalize, canonicalize_aoc},
+        resource::MergeableResource,
+        util::HashMap,
+    };
 
     #[test]
     fn nest_map() {
@@ -954,9 +2404,9 @@ mod bonus {
                     }
                     map.lock().unwrap().insert(
                         if path.starts_with("Aoc") {
-                            String::from("Aoc/0010/") + &name.replace(".s", ".")
+                            canonicalize_aoc(name)
                         } else {
-                            name.replace(".s", ".").into()
+                            canonicalize(name)
                         },
                         full_path,
                     );
@@ -1038,9 +2488,9 @@ mod bonus {
                         MergeableResource::from_binary(name.as_ref(), file.data).unwrap()
                     {
                         let canon = if path.starts_with("Aoc") {
-                            String::from("Aoc/0010/") + &name.replace(".s", ".")
+                            canonicalize_aoc(name)
                         } else {
-                            name.replace(".s", ".").into()
+                            canonicalize(name)
                         };
                         let mut map = map.lock().unwrap();
                         map.insert(canon, res);
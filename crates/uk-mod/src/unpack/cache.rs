@@ -0,0 +1,330 @@
+//! On-disk cache of the decompressed resources of installed mods, so that
+//! merging a mod again does not need to look up and decompress every file in
+//! its archive.
+//!
+//! A cache file holds a small header, the resource data back to back, and an
+//! index of canonical paths at the end, so that it can be written one
+//! resource at a time. It is memory mapped when opened and resources are
+//! copied straight out of the map. Cache files are named for a key which
+//! covers the mod archive, its enabled options, and the selected platform, so
+//! a changed mod simply gets a new cache file and the old one ages out.
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use mmap_rs::{Mmap, MmapOptions};
+use uk_content::util::HashMap;
+
+const MAGIC: &[u8; 4] = b"UKRC";
+const VERSION: u32 = 2;
+const EXTENSION: &str = "ukrc";
+
+pub struct ResourceCache {
+    map:   Mmap,
+    index: HashMap<std::string::String, (usize, usize)>,
+}
+
+impl std::fmt::Debug for ResourceCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceCache")
+            .field("entries", &self.index.len())
+            .field("size", &self.map.len())
+            .finish()
+    }
+}
+
+#[inline]
+fn read_u32(data: &[u8], pos: &mut usize) -> Option<u32> {
+    let bytes = data.get(*pos..*pos + 4)?;
+    *pos += 4;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+#[inline]
+fn read_u64(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let bytes = data.get(*pos..*pos + 8)?;
+    *pos += 8;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn parse_index(data: &[u8]) -> Option<HashMap<std::string::String, (usize, usize)>> {
+    if data.get(..4)? != MAGIC {
+        return None;
+    }
+    let mut pos = 4;
+    if read_u32(data, &mut pos)? != VERSION {
+        return None;
+    }
+    let data_start = pos;
+    let mut end = data.len().checked_sub(8)?;
+    let index_start = read_u64(data, &mut end)? as usize;
+    let data = data.get(..data.len() - 8)?;
+    if index_start < data_start {
+        return None;
+    }
+    let mut pos = index_start;
+    let count = read_u32(data, &mut pos)? as usize;
+    let mut index = HashMap::default();
+    for _ in 0..count {
+        let len = read_u32(data, &mut pos)? as usize;
+        let path = std::str::from_utf8(data.get(pos..pos.checked_add(len)?)?).ok()?;
+        pos += len;
+        let start = read_u64(data, &mut pos)? as usize;
+        let size = read_u64(data, &mut pos)? as usize;
+        if start < data_start || start.checked_add(size)? > index_start {
+            return None;
+        }
+        index.insert(path.to_owned(), (start, size));
+    }
+    (pos == data.len()).then_some(index)
+}
+
+/// Writes a cache file one resource at a time. Nothing replaces the cache
+/// file until [`CacheWriter::finish`], so an interrupted write never leaves
+/// a partial cache behind.
+pub struct CacheWriter {
+    path:   PathBuf,
+    tmp:    PathBuf,
+    writer: BufWriter<fs::File>,
+    index:  Vec<(std::string::String, u64, u64)>,
+    offset: u64,
+}
+
+impl CacheWriter {
+    /// Starts a new cache file at the given path.
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            tmp,
+            writer,
+            index: vec![],
+            offset: (MAGIC.len() + 4) as u64,
+        })
+    }
+
+    /// Adds the data for a canonical resource path.
+    pub fn add(&mut self, canon: &str, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        self.index
+            .push((canon.to_owned(), self.offset, data.len() as u64));
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the index and moves the finished file into place.
+    pub fn finish(mut self) -> Result<()> {
+        let index_start = self.offset;
+        self.writer
+            .write_all(&(self.index.len() as u32).to_le_bytes())?;
+        for (path, offset, size) in &self.index {
+            self.writer.write_all(&(path.len() as u32).to_le_bytes())?;
+            self.writer.write_all(path.as_bytes())?;
+            self.writer.write_all(&offset.to_le_bytes())?;
+            self.writer.write_all(&size.to_le_bytes())?;
+        }
+        self.writer.write_all(&index_start.to_le_bytes())?;
+        self.writer.flush()?;
+        fs::rename(&self.tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        // Only left behind if the write was not finished
+        let _ = std::fs::remove_file(&self.tmp);
+    }
+}
+
+impl ResourceCache {
+    /// The cache file for a given key in a cache folder.
+    pub fn path(dir: &Path, key: u64) -> PathBuf {
+        dir.join(format!("{key:016x}.{EXTENSION}"))
+    }
+
+    /// Opens and maps an existing cache file, and marks it as recently used.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open resource cache {}", path.display()))?;
+        let len = file.metadata()?.len() as usize;
+        // SAFETY: The mapping is only read from. Cache files are written
+        // whole to a new path and never changed in place.
+        let map = unsafe {
+            MmapOptions::new(len)
+                .context("Bad map size")?
+                .with_file(&file, 0)
+                .map()
+                .with_context(|| format!("Failed to map resource cache {}", path.display()))?
+        };
+        let index = parse_index(map.as_slice())
+            .with_context(|| format!("Invalid resource cache {}", path.display()))?;
+        touch(path);
+        Ok(Self { map, index })
+    }
+
+    /// Writes a new cache file from canonical paths and resource data, as
+    /// with a [`CacheWriter`].
+    pub fn write(
+        path: &Path,
+        files: impl IntoIterator<Item = (std::string::String, Vec<u8>)>,
+    ) -> Result<()> {
+        let mut writer = CacheWriter::create(path)?;
+        for (canon, data) in files {
+            writer.add(&canon, &data)?;
+        }
+        writer.finish()
+    }
+
+    /// Gets the data stored for a canonical resource path.
+    #[inline]
+    pub fn get(&self, canon: &str) -> Option<&[u8]> {
+        self.index
+            .get(canon)
+            .map(|&(start, size)| &self.map.as_slice()[start..start + size])
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+}
+
+fn touch(path: &Path) {
+    if let Err(e) = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|f| f.set_modified(SystemTime::now()))
+    {
+        log::debug!("Could not update resource cache time: {e}");
+    }
+}
+
+/// Removes the least recently used cache files until the folder is at most
+/// `cap` bytes, and returns how many bytes were freed.
+pub fn evict(dir: &Path, cap: u64) -> Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == EXTENSION))
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            Some((
+                meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                meta.len(),
+                e.path(),
+            ))
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    files.sort_by_key(|(time, ..)| *time);
+    let mut freed = 0;
+    for (_, size, path) in files {
+        if total <= cap {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                total -= size;
+                freed += size;
+            }
+            Err(e) => log::warn!("Failed to remove old resource cache: {e}"),
+        }
+    }
+    if freed > 0 {
+        log::debug!("Freed {freed} bytes from resource cache");
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ResourceCache::path(dir.path(), 0xdead_beef);
+        ResourceCache::write(&path, [
+            ("Actor/Pack/Test.bactorpack".into(), b"actor".to_vec()),
+            ("Pack/Empty.pack".into(), vec![]),
+            ("Map/MainField/A-1/A-1_Static.mubin".into(), vec![7; 4096]),
+        ])
+        .unwrap();
+        let cache = ResourceCache::open(&path).unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get("Actor/Pack/Test.bactorpack"), Some(&b"actor"[..]));
+        assert_eq!(cache.get("Pack/Empty.pack"), Some(&[][..]));
+        assert_eq!(
+            cache.get("Map/MainField/A-1/A-1_Static.mubin"),
+            Some(&[7; 4096][..])
+        );
+        assert_eq!(cache.get("Actor/Pack/Missing.bactorpack"), None);
+    }
+
+    #[test]
+    fn unfinished() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ResourceCache::path(dir.path(), 2);
+        let mut writer = CacheWriter::create(&path).unwrap();
+        writer.add("Pack/Test.pack", &[1; 64]).unwrap();
+        drop(writer);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ResourceCache::path(dir.path(), 1);
+        ResourceCache::write(&path, [("Pack/Test.pack".into(), vec![1; 64])]).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &data[..data.len() - 1]).unwrap();
+        assert!(ResourceCache::open(&path).is_err());
+        std::fs::write(&path, b"not a cache").unwrap();
+        assert!(ResourceCache::open(&path).is_err());
+    }
+
+    #[test]
+    fn lru() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..3)
+            .map(|i| {
+                let path = ResourceCache::path(dir.path(), i);
+                ResourceCache::write(&path, [("Pack/Test.pack".into(), vec![0; 1000])]).unwrap();
+                let time = SystemTime::now() - Duration::from_secs(100 - i * 10);
+                std::fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(time)
+                    .unwrap();
+                path
+            })
+            .collect();
+        let size = std::fs::metadata(&paths[0]).unwrap().len();
+        // Opening the oldest makes it the most recently used
+        ResourceCache::open(&paths[0]).unwrap();
+        assert_eq!(evict(dir.path(), size * 2).unwrap(), size);
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists());
+        assert!(paths[2].exists());
+        assert_eq!(evict(dir.path(), size * 2).unwrap(), 0);
+    }
+}
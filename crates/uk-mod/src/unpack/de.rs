@@ -92,11 +92,11 @@ impl<'de> Deserialize<'de> for ModReader {
                     decompressor: super::init_decompressor(),
                     manifest,
                     options,
-                    zip: Some(
-                        super::ParallelZipReader::open(&path, false)
-                            .map_err(serde::de::Error::custom)?,
-                    ),
+                    zip: std::sync::OnceLock::new(),
+                    objects: std::sync::OnceLock::new(),
                     path,
+                    platform: None,
+                    cache: None,
                 })
             }
         }
@@ -0,0 +1,302 @@
+//! On-disk cache of the meta and manifests of mod archives, so that scanning
+//! the whole library does not need to open every archive each time.
+//!
+//! Entries are keyed by archive path and checked against the archive's size
+//! and modified time, so a replaced or updated archive is read again without
+//! any help. The manifests of every option are kept, whichever options were
+//! asked for when the entry was made, so selecting different options later
+//! still hits. Only zipped mods are cached, since the files of an unzipped
+//! mod can change without notice.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use anyhow_ext::Result;
+use fs_err as fs;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use super::ModReader;
+use crate::{Manifest, Meta, ModOption};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    size:     u64,
+    modified: SystemTime,
+    meta:     Meta,
+    manifest: Manifest,
+    options:  BTreeMap<PathBuf, Manifest>,
+}
+
+/// The size and modified time of a file, if it is one.
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let meta = path.metadata().ok().filter(|m| m.is_file())?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+#[derive(Debug, Default)]
+pub struct PeekCache {
+    path:    PathBuf,
+    entries: RwLock<BTreeMap<PathBuf, Entry>>,
+    dirty:   AtomicBool,
+    misses:  AtomicUsize,
+}
+
+impl PeekCache {
+    /// The name of the cache file in the folder it is kept in.
+    pub const FILE: &'static str = "peek.json";
+
+    /// Opens the cache saved at a path. A missing or unreadable cache starts
+    /// empty, since everything in it can be read again.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = match fs::read(&path) {
+            Ok(data) => {
+                serde_json::from_slice(&data).unwrap_or_else(|e| {
+                    log::warn!("Discarding unreadable mod peek cache: {e}");
+                    Default::default()
+                })
+            }
+            Err(_) => Default::default(),
+        };
+        Self {
+            path,
+            entries: RwLock::new(entries),
+            ..Default::default()
+        }
+    }
+
+    /// The meta and the manifest with the given options for an archive, if
+    /// the cache has them and the archive has not changed since.
+    pub(super) fn get(&self, path: &Path, options: &[ModOption]) -> Option<(Meta, Manifest)> {
+        let (size, modified) = stamp(path)?;
+        let entries = self.entries.read();
+        let entry = entries
+            .get(path)
+            .filter(|e| e.size == size && e.modified == modified)?;
+        let mut manifest = entry.manifest.clone();
+        for option in options {
+            manifest.extend(entry.options.get(&option.path)?);
+        }
+        Some((entry.meta.clone(), manifest))
+    }
+
+    /// The main manifest of an archive and the manifest of each of its
+    /// options by path, if the cache has them and the archive has not
+    /// changed since.
+    pub fn manifests(&self, path: &Path) -> Option<(Manifest, BTreeMap<PathBuf, Manifest>)> {
+        let (size, modified) = stamp(path)?;
+        let entries = self.entries.read();
+        let entry = entries
+            .get(path)
+            .filter(|e| e.size == size && e.modified == modified)?;
+        Some((entry.manifest.clone(), entry.options.clone()))
+    }
+
+    /// Records the meta and manifests of a mod just read from its archive.
+    pub(super) fn fill(&self, reader: &ModReader) -> Result<()> {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let Some((size, modified)) = stamp(&reader.path) else {
+            return Ok(());
+        };
+        let manifest = reader.read_manifest(Path::new("manifest.yml"))?;
+        let options = reader.option_manifests()?;
+        self.entries.write().insert(reader.path.clone(), Entry {
+            size,
+            modified,
+            meta: reader.meta.clone(),
+            manifest,
+            options,
+        });
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Forgets an archive, for when it is replaced or removed.
+    pub fn invalidate(&self, path: &Path) {
+        if self.entries.write().remove(path).is_some() {
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    /// How many archives have been read to fill the cache since it was
+    /// opened.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Saves the cache if it has changed, leaving out archives which no
+    /// longer exist.
+    pub fn save(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let mut entries = self.entries.write();
+        entries.retain(|path, _| path.is_file());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&*entries)?)?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, time::Instant};
+
+    use super::*;
+
+    fn write_mod(path: &Path, name: &str, files: &[&str]) {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: crate::FORMAT_VERSION,
+            name: name.into(),
+            version: "1.0.0".into(),
+            author: Default::default(),
+            category: Default::default(),
+            description: Default::default(),
+            platform: crate::ModPlatform::Universal,
+            url: None,
+            options: vec![crate::OptionGroup::Multiple(crate::MultipleOptionGroup {
+                options: vec![extra()],
+                ..Default::default()
+            })],
+            masters: Default::default(),
+        };
+        let mut manifest = Manifest::default();
+        manifest
+            .content_files
+            .extend(files.iter().map(|f| (*f).into()));
+        let mut extra = Manifest::default();
+        extra.content_files.insert("Pack/Extra.pack".into());
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        let opts = zip::write::SimpleFileOptions::default();
+        for (name, contents) in [
+            ("meta.yml", serde_yaml::to_string(&meta).unwrap()),
+            ("manifest.yml", serde_yaml::to_string(&manifest).unwrap()),
+            (
+                "options/extra/manifest.yml",
+                serde_yaml::to_string(&extra).unwrap(),
+            ),
+        ] {
+            zip.start_file(name, opts).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn extra() -> ModOption {
+        ModOption {
+            name: "Extra".into(),
+            description: Default::default(),
+            path: "extra".into(),
+            requires: vec![],
+            image: None,
+        }
+    }
+
+    #[test]
+    fn second_scan() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mods: Vec<PathBuf> = (0..100)
+            .map(|i| {
+                let path = tmp.path().join(format!("Mod {i}.zip"));
+                write_mod(&path, &format!("Mod {i}"), &["Actor/Pack/Test.bactorpack"]);
+                path
+            })
+            .collect();
+        let cache_path = tmp.path().join(PeekCache::FILE);
+        let scan = |cache: &PeekCache| {
+            let start = Instant::now();
+            for path in &mods {
+                let reader = ModReader::open_peek_cached(path, vec![], cache).unwrap();
+                assert!(reader
+                    .manifest
+                    .content_files
+                    .contains("Actor/Pack/Test.bactorpack"));
+            }
+            start.elapsed()
+        };
+
+        let cache = PeekCache::open(&cache_path);
+        let first = scan(&cache);
+        assert_eq!(cache.misses(), 100);
+        cache.save().unwrap();
+
+        // A fresh cache, as in the next session, reads no archives at all
+        let cache = PeekCache::open(&cache_path);
+        let second = scan(&cache);
+        assert_eq!(cache.misses(), 0);
+        for path in &mods {
+            let reader = ModReader::open_peek_cached(path, vec![extra()], &cache).unwrap();
+            assert!(reader.zip.get().is_none());
+            assert!(reader.manifest.content_files.contains("Pack/Extra.pack"));
+        }
+        assert_eq!(cache.misses(), 0);
+        println!("Scanned 100 mods in {first:?}, then {second:?} from the cache");
+    }
+
+    #[test]
+    fn stale() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("Mod.zip");
+        write_mod(&path, "Mod", &["Actor/Pack/Old.bactorpack"]);
+        let cache = PeekCache::open(tmp.path().join(PeekCache::FILE));
+        ModReader::open_peek_cached(&path, vec![], &cache).unwrap();
+        ModReader::open_peek_cached(&path, vec![], &cache).unwrap();
+        assert_eq!(cache.misses(), 1);
+
+        // A different size
+        write_mod(&path, "Mod", &[
+            "Actor/Pack/Old.bactorpack",
+            "Actor/Pack/New.bactorpack",
+        ]);
+        let reader = ModReader::open_peek_cached(&path, vec![], &cache).unwrap();
+        assert!(reader
+            .manifest
+            .content_files
+            .contains("Actor/Pack/New.bactorpack"));
+        assert_eq!(cache.misses(), 2);
+
+        // The same size, but modified since
+        write_mod(&path, "Mod", &[
+            "Actor/Pack/Old.bactorpack",
+            "Actor/Pack/Mew.bactorpack",
+        ]);
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let reader = ModReader::open_peek_cached(&path, vec![], &cache).unwrap();
+        assert!(reader
+            .manifest
+            .content_files
+            .contains("Actor/Pack/Mew.bactorpack"));
+        assert_eq!(cache.misses(), 3);
+
+        // Forgotten on purpose
+        cache.invalidate(&path);
+        ModReader::open_peek_cached(&path, vec![], &cache).unwrap();
+        assert_eq!(cache.misses(), 4);
+
+        // Removed archives are dropped when saving
+        cache.save().unwrap();
+        fs::remove_file(&path).unwrap();
+        cache.invalidate(Path::new("nothing"));
+        cache.dirty.store(true, Ordering::Release);
+        cache.save().unwrap();
+        assert!(PeekCache::open(tmp.path().join(PeekCache::FILE))
+            .entries
+            .read()
+            .is_empty());
+    }
+}
@@ -0,0 +1,403 @@
+//! Minimal reproductions of merge failures, small enough to attach to a bug
+//! report in place of the mods themselves.
+//!
+//! A repro holds only what merging a single file reads: the stock copy from
+//! the dump, each version of the file from the mods which change it, the
+//! names and versions of those mods, and the merge settings. A file nested in
+//! a pack comes inside copies of its parent packs which hold nothing else.
+//! The repro is written as a zip of one folder, which unpacks into
+//! `crates/uk-mod/tests` as a test target: its generated `main.rs` loads the
+//! pieces and merges the file again the same way an apply does.
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow_ext::{Context, Result};
+use fs_err as fs;
+use roead::sarc::SarcWriter;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+use uk_content::{
+    canon::canonicalize_aoc, canonicalize, compression, constants::Language, prelude::Endian,
+};
+use uk_reader::{MemoryROMSource, ResourceReader};
+use zip::write::SimpleFileOptions;
+
+use super::{ModReader, ModUnpacker};
+use crate::{policy::MergePolicies, Manifest, Meta, ModPlatform};
+
+const INFO: &str = "repro.yml";
+const POLICIES: &str = "policies.yml";
+const DUMP_DIR: &str = "dump";
+const MODS_DIR: &str = "mods";
+const TEST: &str = "main.rs";
+
+/// Marks a merge error with the file which failed, so a repro can be
+/// exported for it. Files nested in packs are marked rather than the packs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeFailure {
+    pub file: String,
+    pub aoc:  bool,
+}
+
+impl std::fmt::Display for MergeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to merge {}", self.file)
+    }
+}
+
+/// A mod in a repro, which holds one version of the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReproMod {
+    /// The folder of the mod under `mods`.
+    pub dir:     String,
+    pub name:    String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repro {
+    /// The version of UKMM which exported the repro.
+    pub ukmm: String,
+    pub file: String,
+    pub aoc: bool,
+    pub endian: Endian,
+    pub lang: Language,
+    /// The error the merge failed with when the repro was exported.
+    pub error: std::string::String,
+    /// Stock files under `dump`, by canonical path.
+    pub dump: Vec<String>,
+    /// The mods in merge order. A mod with several versions of the file,
+    /// from its options, is listed once for each.
+    pub mods: Vec<ReproMod>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Repro {
+    /// Reads an unzipped repro.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut repro: Self = serde_yaml::from_str(&fs::read_to_string(dir.join(INFO))?)
+            .context("Failed to read repro info")?;
+        repro.dir = dir.to_path_buf();
+        Ok(repro)
+    }
+
+    /// Sets up the merge the repro was exported from, with a dump of only
+    /// the stock files it holds.
+    pub fn unpacker(&self) -> Result<ModUnpacker> {
+        let files = self
+            .dump
+            .iter()
+            .map(|path| {
+                fs::read(self.dir.join(DUMP_DIR).join(path.as_str()))
+                    .map(|data| (path.as_str(), data))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let dump = MemoryROMSource::from_files(self.endian, files);
+        let mods = self
+            .mods
+            .iter()
+            .map(|mod_| ModReader::open(self.dir.join(MODS_DIR).join(mod_.dir.as_str()), vec![]))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ModUnpacker::new(
+            Arc::new(ResourceReader::from_memory(dump)),
+            self.endian,
+            self.lang,
+            mods,
+            self.dir.join("out"),
+        )
+        .with_policies(MergePolicies::load(&self.dir.join(POLICIES))?))
+    }
+
+    /// A test which merges the file again, for the repro unzipped as `name`
+    /// into `crates/uk-mod/tests`.
+    fn test_source(&self, name: &str) -> std::string::String {
+        let error: std::string::String = self
+            .error
+            .lines()
+            .map(|line| format!("//!     {line}\n"))
+            .collect();
+        let mods: std::string::String = self
+            .mods
+            .iter()
+            .map(|mod_| format!("//! - `{}`: {} {}\n", mod_.dir, mod_.name, mod_.version))
+            .collect();
+        format!(
+            r#"//! Reproduces the merge of `{file}`, exported by UKMM {ukmm}. Unzip
+//! the repro into `crates/uk-mod/tests` and run it with
+//! `cargo test -p uk-mod --test {name}`.
+//!
+//! The merge failed with:
+//!
+{error}//!
+//! The mods, in merge order:
+//!
+{mods}use uk_mod::unpack::repro::Repro;
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/{name}");
+
+#[test]
+fn merge() {{
+    let repro = Repro::open(DIR).unwrap();
+    let unpacker = repro.unpacker().unwrap();
+    unpacker.build_single(&repro.file, repro.aoc).unwrap();
+}}
+"#,
+            file = self.file,
+            ukmm = self.ukmm,
+        )
+    }
+}
+
+/// Turns a file name into one usable as the name of a test target.
+fn test_name(dest: &Path) -> std::string::String {
+    let stem = dest
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let name: std::string::String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("repro_{name}")
+    }
+}
+
+impl ModUnpacker {
+    /// The stock copy of a file as the dump has it, by canonical path. A
+    /// file nested in a pack comes inside copies of its parent packs holding
+    /// nothing else, which is all the dump needs to find it.
+    fn stock_context(&self, file: &str, canon: &str) -> Result<Option<(String, Vec<u8>)>> {
+        let source = self.dump.source();
+        if let Some(data) = [file, canon]
+            .into_iter()
+            .find_map(|name| source.get_data(name.as_ref()).ok())
+        {
+            return Ok(Some((canon.into(), data)));
+        }
+        let Some(nest_path) = self.dump.nest_path(canon) else {
+            return Ok(None);
+        };
+        let parts: Vec<&str> = nest_path.split("//").collect();
+        let mut data = self.dump.get_bytes_from_sarc(&nest_path)?;
+        // Wrapped in each parent in turn, innermost first
+        for name in parts[1..].iter().rev() {
+            let mut sarc = SarcWriter::new(self.endian.into());
+            sarc.add_file(*name, compression::compress_if(&data, name).into_owned());
+            data = sarc.to_binary();
+        }
+        Ok(Some((
+            canonicalize(parts[0]),
+            compression::compress_if(&data, parts[0]).into_owned(),
+        )))
+    }
+
+    /// Writes a repro of merging a file to a zip at `dest`, with `error` as
+    /// the failure it reproduces.
+    pub fn export_repro(&self, file: &str, aoc: bool, error: &str, dest: &Path) -> Result<()> {
+        let canon = if aoc {
+            canonicalize_aoc(file)
+        } else {
+            canonicalize(file)
+        };
+        let mut files: Vec<(std::string::String, Vec<u8>)> = vec![];
+        let mut repro = Repro {
+            ukmm: env!("CARGO_PKG_VERSION").into(),
+            file: file.into(),
+            aoc,
+            endian: self.endian,
+            lang: self.lang,
+            error: error.into(),
+            dump: vec![],
+            mods: vec![],
+            dir: Default::default(),
+        };
+        if let Some((path, data)) = self.stock_context(file, &canon)? {
+            files.push((format!("{DUMP_DIR}/{path}"), data));
+            repro.dump.push(path);
+        }
+        for mod_ in &self.mods {
+            let Ok(versions) = mod_.get_versions(file.as_ref()) else {
+                continue;
+            };
+            for data in versions {
+                let dir: String = format!("{:02}", repro.mods.len()).into();
+                // Only what tells the mod apart is kept, and its files are
+                // stored for the one platform
+                let meta = Meta {
+                    platform: ModPlatform::Specific(self.endian),
+                    author: Default::default(),
+                    category: Default::default(),
+                    description: Default::default(),
+                    url: None,
+                    options: vec![],
+                    masters: Default::default(),
+                    ..mod_.meta.clone()
+                };
+                let mut manifest = Manifest::default();
+                if aoc {
+                    manifest.aoc_files.insert(file.into());
+                } else {
+                    manifest.content_files.insert(file.into());
+                }
+                let root = format!("{MODS_DIR}/{dir}");
+                files.push((
+                    format!("{root}/meta.yml"),
+                    serde_yaml::to_string(&meta)?.into(),
+                ));
+                files.push((
+                    format!("{root}/manifest.yml"),
+                    serde_yaml::to_string(&manifest)?.into(),
+                ));
+                files.push((format!("{root}/{}", canonicalize(file)), data));
+                repro.mods.push(ReproMod {
+                    dir,
+                    name: mod_.meta.name.clone(),
+                    version: mod_.meta.version.clone(),
+                });
+            }
+        }
+        files.push((
+            POLICIES.into(),
+            serde_yaml::to_string(&self.policies)?.into(),
+        ));
+        files.push((INFO.into(), serde_yaml::to_string(&repro)?.into()));
+        let name = test_name(dest);
+        files.push((TEST.into(), repro.test_source(&name).into()));
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(dest)?);
+        let opts =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (path, data) in files {
+            zip.start_file(format!("{name}/{path}"), opts)?;
+            zip.write_all(&data)?;
+        }
+        zip.finish()?;
+        log::info!("Exported repro of {file} to {}", dest.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::sarc::Sarc;
+    use uk_content::resource::ResourceData;
+
+    use super::*;
+
+    const FILE: &str = "Attenuation/Cmn_Broad_spread.baroc";
+    const PARENT: &str = "Sound/Attenuation/Attenuation.baatarc";
+
+    /// Writes an unzipped mod storing `data` as its only version of the file.
+    fn write_mod(dir: &Path, name: &str, data: &[u8]) -> ModReader {
+        let meta = Meta {
+            api: env!("CARGO_PKG_VERSION").into(),
+            format: crate::FORMAT_VERSION,
+            platform: ModPlatform::Specific(Endian::Big),
+            name: name.into(),
+            version: "1.0.0".into(),
+            category: "Other".into(),
+            author: "Someone".into(),
+            description: "Changes a sound".into(),
+            masters: Default::default(),
+            url: None,
+            options: vec![],
+        };
+        let mut manifest = Manifest::default();
+        manifest.content_files.insert(FILE.into());
+        let dir = dir.join(name);
+        fs::create_dir_all(dir.join("Attenuation")).unwrap();
+        fs::write(dir.join("meta.yml"), serde_yaml::to_string(&meta).unwrap()).unwrap();
+        fs::write(
+            dir.join("manifest.yml"),
+            serde_yaml::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+        fs::write(dir.join(FILE), data).unwrap();
+        ModReader::open(dir, vec![]).unwrap()
+    }
+
+    #[test]
+    fn export() {
+        // The file is nested two packs deep, next to files the merge does not
+        // need
+        let mut attenuation = SarcWriter::new(roead::Endian::Big)
+            .with_file(FILE, b"stock".to_vec())
+            .with_file("Attenuation/Cmn_Level8_pri.baroc", b"other".to_vec());
+        let dump = MemoryROMSource::new(Endian::Big).with_bootup_pack([
+            (PARENT, attenuation.to_binary()),
+            ("Awareness/SensorFilter.bsft", b"sensor".to_vec()),
+        ]);
+        let tmp = tempfile::tempdir().unwrap();
+        let good = minicbor_ser::to_vec(&ResourceData::Binary(b"modded".to_vec())).unwrap();
+        let mods = vec![
+            write_mod(tmp.path(), "Good Sounds", &good),
+            write_mod(tmp.path(), "Broken Sounds", b"not a resource"),
+        ];
+        let unpacker = ModUnpacker::new(
+            Arc::new(ResourceReader::from_memory(dump)),
+            Endian::Big,
+            Language::USen,
+            mods,
+            tmp.path().join("out"),
+        );
+        let err = unpacker.build_single(FILE, false).unwrap_err();
+        let failure = err.downcast_ref::<MergeFailure>().unwrap();
+        assert_eq!(failure.file, FILE);
+        let dest = tmp.path().join("broken sounds.zip");
+        unpacker
+            .export_repro(&failure.file, failure.aoc, &format!("{err:?}"), &dest)
+            .unwrap();
+
+        let tests = tmp.path().join("tests");
+        zip::ZipArchive::new(fs::File::open(&dest).unwrap())
+            .unwrap()
+            .extract(&tests)
+            .unwrap();
+        let dir = tests.join("broken_sounds");
+        let repro = Repro::open(&dir).unwrap();
+        assert_eq!(
+            repro
+                .dump
+                .iter()
+                .map(|path| path.as_str())
+                .collect::<Vec<_>>(),
+            ["Pack/Bootup.pack"]
+        );
+        assert_eq!(
+            repro
+                .mods
+                .iter()
+                .map(|mod_| mod_.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Good Sounds", "Broken Sounds"]
+        );
+        // Only the file is left in its parents
+        let bootup = Sarc::new(fs::read(dir.join("dump/Pack/Bootup.pack")).unwrap()).unwrap();
+        assert_eq!(bootup.files().count(), 1);
+        let parent = Sarc::new(bootup.get_data(PARENT).unwrap().to_vec()).unwrap();
+        assert_eq!(parent.files().count(), 1);
+        assert_eq!(parent.get_data(FILE).unwrap(), b"stock");
+        let meta = ModReader::open(dir.join("mods/00"), vec![]).unwrap().meta;
+        assert!(meta.author.is_empty() && meta.description.is_empty());
+
+        // What the generated test does fails the same way
+        let test = fs::read_to_string(dir.join(TEST)).unwrap();
+        assert!(test.contains("cargo test -p uk-mod --test broken_sounds"));
+        assert!(test.contains(r#"concat!(env!("CARGO_MANIFEST_DIR"), "/tests/broken_sounds")"#));
+        let err = repro
+            .unpacker()
+            .unwrap()
+            .build_single(&repro.file, repro.aoc)
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<MergeFailure>(), Some(failure));
+        assert!(format!("{err:?}").contains("Error deserializing resource"));
+    }
+}
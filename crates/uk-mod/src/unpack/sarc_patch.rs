@@ -0,0 +1,519 @@
+//! In-place updates of SARC archives left in the output folder by a previous
+//! apply. When only a few files in a large pack change, the header and the
+//! changed files are written over the old archive, and files which did not
+//! change are only moved when an earlier file changed size. Anything
+//! written this way must match what [`roead::sarc::SarcWriter`] would write
+//! for the same files, so the layout rules are checked against the old
+//! archive first and any archive they do not reproduce is rebuilt instead.
+use std::{
+    fmt,
+    io::{Seek, SeekFrom, Write},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use uk_content::{prelude::Endian, util::HashMap};
+
+const SARC_HEADER: usize = 0x14;
+const SFAT_HEADER: usize = 0xC;
+const SFAT_NODE: usize = 0x10;
+const SFNT_HEADER: usize = 0x8;
+
+#[inline]
+fn align(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[inline]
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+fn read_u16(data: &[u8], offset: usize, endian: Endian) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(match endian {
+        Endian::Big => u16::from_be_bytes(bytes),
+        Endian::Little => u16::from_le_bytes(bytes),
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize, endian: Endian) -> Option<usize> {
+    let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(match endian {
+        Endian::Big => u32::from_be_bytes(bytes),
+        Endian::Little => u32::from_le_bytes(bytes),
+    } as usize)
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: usize, endian: Endian) {
+    let bytes = match endian {
+        Endian::Big => (value as u32).to_be_bytes(),
+        Endian::Little => (value as u32).to_le_bytes(),
+    };
+    data[offset..offset + 4].copy_from_slice(&bytes);
+}
+
+/// Alignment the writer gives a file type regardless of its contents.
+fn type_alignment(ext: &str, endian: Endian) -> usize {
+    match ext {
+        "ksky" | "bksky" => 8,
+        "gtx" => 0x2000,
+        "sharc" | "sharcb" => 0x1000,
+        "baglmf" => 0x80,
+        "bffnt" if endian == Endian::Big => 0x2000,
+        "bffnt" => 0x1000,
+        _ => 1,
+    }
+}
+
+/// Alignment stored in the header of newer Nintendo binary formats.
+fn binary_alignment(data: &[u8]) -> usize {
+    if data.len() <= 0x20 {
+        return 1;
+    }
+    let endian = match (data[0xC], data[0xD]) {
+        (0xFE, 0xFF) => Endian::Big,
+        (0xFF, 0xFE) => Endian::Little,
+        _ => return 1,
+    };
+    if read_u32(data, 0x1C, endian) != Some(data.len()) {
+        return 1;
+    }
+    1usize.checked_shl(data[0xE] as u32).unwrap_or(1)
+}
+
+/// Alignment of a Wii U texture, stored in its footer.
+fn bflim_alignment(data: &[u8]) -> usize {
+    if data.len() <= 0x28 || &data[data.len() - 0x28..][..4] != b"FLIM" {
+        return 1;
+    }
+    read_u16(data, data.len() - 0x8, Endian::Big)
+        .map(|a| (a as usize).max(1))
+        .unwrap_or(1)
+}
+
+fn file_alignment(name: &str, data: &[u8], endian: Endian, min_alignment: usize) -> usize {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or_default();
+    let mut alignment = lcm(min_alignment, type_alignment(ext, endian));
+    alignment = lcm(alignment, binary_alignment(data));
+    if endian == Endian::Big {
+        alignment = lcm(alignment, bflim_alignment(data));
+    }
+    alignment
+}
+
+struct Node<'a> {
+    name:  &'a str,
+    begin: usize,
+    end:   usize,
+}
+
+/// The parts of an existing archive needed to patch it.
+struct Archive<'a> {
+    endian: Endian,
+    data_start: usize,
+    /// Length of the header, file table, and name table, before padding.
+    header_len: usize,
+    nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Archive<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.get(..4)? != b"SARC" || data.get(0x14..0x18)? != b"SFAT" {
+            return None;
+        }
+        let endian = match (data[6], data[7]) {
+            (0xFE, 0xFF) => Endian::Big,
+            (0xFF, 0xFE) => Endian::Little,
+            _ => return None,
+        };
+        let data_start = read_u32(data, 0xC, endian)?;
+        let count = read_u16(data, 0x1A, endian)? as usize;
+        let sfnt = SARC_HEADER + SFAT_HEADER + count * SFAT_NODE;
+        if data.get(sfnt..sfnt + 4)? != b"SFNT" {
+            return None;
+        }
+        let names = sfnt + SFNT_HEADER;
+        let mut header_len = names;
+        let nodes = (0..count)
+            .map(|i| {
+                let node = SARC_HEADER + SFAT_HEADER + i * SFAT_NODE;
+                let attributes = read_u32(data, node + 4, endian)?;
+                if attributes >> 24 == 0 {
+                    return None;
+                }
+                let name_offset = names + (attributes & 0xFFFFFF) * 4;
+                let len = data
+                    .get(name_offset..data_start)?
+                    .iter()
+                    .position(|b| *b == 0)?;
+                header_len += align(len + 1, 4);
+                Some(Node {
+                    name:  std::str::from_utf8(&data[name_offset..name_offset + len]).ok()?,
+                    begin: read_u32(data, node + 8, endian)?,
+                    end:   read_u32(data, node + 12, endian)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if nodes
+            .iter()
+            .any(|n| n.begin > n.end || data_start + n.end > data.len())
+        {
+            return None;
+        }
+        Some(Self {
+            endian,
+            data_start,
+            header_len,
+            nodes,
+        })
+    }
+
+    fn file(&self, data: &'a [u8], index: usize) -> &'a [u8] {
+        let node = &self.nodes[index];
+        &data[self.data_start + node.begin..self.data_start + node.end]
+    }
+}
+
+/// Where each file goes, relative to the start of the data section.
+struct Layout {
+    data_start: usize,
+    spans: Vec<(usize, usize)>,
+    size: usize,
+}
+
+impl Layout {
+    fn new(header_len: usize, alignments: &[usize], lengths: &[usize]) -> Self {
+        let mut offset = 0;
+        let spans = alignments
+            .iter()
+            .zip(lengths)
+            .map(|(alignment, len)| {
+                let begin = align(offset, *alignment);
+                offset = begin + len;
+                (begin, offset)
+            })
+            .collect();
+        let data_start = align(header_len, alignments.iter().copied().fold(1, lcm));
+        Self {
+            data_start,
+            spans,
+            size: data_start + offset,
+        }
+    }
+}
+
+enum Source {
+    Old(usize),
+    New(usize),
+}
+
+/// The writes needed to turn an old archive into a new one.
+pub(crate) struct Plan {
+    header: Option<Vec<u8>>,
+    /// Padding start, file start, and where to take the file from.
+    writes: Vec<(usize, usize, Source)>,
+    pub(crate) size: usize,
+    pub(crate) changed: usize,
+}
+
+impl Plan {
+    /// Decides whether `old` can be patched into an archive holding `files`.
+    /// The error explains why a full rebuild is needed instead.
+    pub(crate) fn new(
+        old: &[u8],
+        files: &[(String, Vec<u8>)],
+        endian: Endian,
+        min_alignment: usize,
+    ) -> std::result::Result<Self, &'static str> {
+        let archive = Archive::parse(old).ok_or("the old archive could not be read")?;
+        if archive.endian != endian {
+            return Err("the old archive is for the other platform");
+        }
+        let new: HashMap<&str, usize> = files
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.as_str(), i))
+            .collect();
+        if new.len() != archive.nodes.len() || files.len() != new.len() {
+            return Err("files were added or removed");
+        }
+        let sources = archive
+            .nodes
+            .iter()
+            .map(|node| new.get(node.name).copied())
+            .collect::<Option<Vec<_>>>()
+            .ok_or("files were added or removed")?;
+
+        let old_files: Vec<&[u8]> = (0..archive.nodes.len())
+            .map(|i| archive.file(old, i))
+            .collect();
+        let mut alignments: Vec<usize> = archive
+            .nodes
+            .iter()
+            .zip(&old_files)
+            .map(|(node, data)| file_alignment(node.name, data, endian, min_alignment))
+            .collect();
+        let old_layout = Layout::new(
+            archive.header_len,
+            &alignments,
+            &old_files.iter().map(|d| d.len()).collect::<Vec<_>>(),
+        );
+        if old_layout.data_start != archive.data_start
+            || old_layout.size != old.len()
+            || old_layout
+                .spans
+                .iter()
+                .zip(&archive.nodes)
+                .any(|((begin, end), node)| *begin != node.begin || *end != node.end)
+        {
+            return Err("the old archive does not follow the expected layout");
+        }
+
+        let changed: Vec<bool> = sources
+            .iter()
+            .zip(&old_files)
+            .map(|(i, old)| files[*i].1.as_slice() != *old)
+            .collect();
+        let changed_count = changed.iter().filter(|c| **c).count();
+        if changed_count * 4 > archive.nodes.len() {
+            return Err("too many files changed");
+        }
+        for (i, _) in changed.iter().enumerate().filter(|(_, c)| **c) {
+            alignments[i] = file_alignment(
+                archive.nodes[i].name,
+                &files[sources[i]].1,
+                endian,
+                min_alignment,
+            );
+        }
+        let layout = Layout::new(
+            archive.header_len,
+            &alignments,
+            &sources
+                .iter()
+                .map(|i| files[*i].1.len())
+                .collect::<Vec<_>>(),
+        );
+        if layout.data_start != archive.data_start {
+            return Err("the data section would move");
+        }
+
+        let mut header = old[..archive.data_start].to_vec();
+        write_u32(&mut header, 0x8, layout.size, endian);
+        for (i, (begin, end)) in layout.spans.iter().enumerate() {
+            let node = SARC_HEADER + SFAT_HEADER + i * SFAT_NODE;
+            write_u32(&mut header, node + 8, *begin, endian);
+            write_u32(&mut header, node + 12, *end, endian);
+        }
+        let mut writes = vec![];
+        let mut prev_end = 0;
+        for (i, (begin, end)) in layout.spans.iter().enumerate() {
+            if changed[i] {
+                writes.push((prev_end, *begin, Source::New(sources[i])));
+            } else if *begin != archive.nodes[i].begin {
+                writes.push((prev_end, *begin, Source::Old(i)));
+            }
+            prev_end = *end;
+        }
+        Ok(Self {
+            header: (header != old[..archive.data_start]).then_some(header),
+            writes,
+            size: layout.size,
+            changed: changed_count,
+        })
+    }
+
+    /// Writes the plan over the old archive, returning the number of bytes
+    /// written. The caller must truncate or extend the output to
+    /// [`Plan::size`] afterwards.
+    pub(crate) fn apply(
+        &self,
+        out: &mut (impl Write + Seek),
+        old: &[u8],
+        files: &[(String, Vec<u8>)],
+    ) -> std::io::Result<usize> {
+        let archive = Archive::parse(old)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid SARC"))?;
+        let mut written = 0;
+        if let Some(header) = self.header.as_ref() {
+            out.seek(SeekFrom::Start(0))?;
+            out.write_all(header)?;
+            written += header.len();
+        }
+        for (pad_from, begin, source) in &self.writes {
+            let data = match source {
+                Source::Old(i) => archive.file(old, *i),
+                Source::New(i) => files[*i].1.as_slice(),
+            };
+            out.seek(SeekFrom::Start((archive.data_start + pad_from) as u64))?;
+            out.write_all(&vec![0; begin - pad_from])?;
+            out.write_all(data)?;
+            written += begin - pad_from + data.len();
+        }
+        Ok(written)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.header.is_none() && self.writes.is_empty()
+    }
+}
+
+/// Counts of how packs from a previous apply were handled.
+#[derive(Debug, Default)]
+pub(crate) struct PatchStats {
+    pub(crate) unchanged: AtomicUsize,
+    pub(crate) patched:   AtomicUsize,
+    pub(crate) rebuilt:   AtomicUsize,
+    pub(crate) written:   AtomicU64,
+    pub(crate) total:     AtomicU64,
+}
+
+impl PatchStats {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.unchanged.load(Ordering::Relaxed)
+            + self.patched.load(Ordering::Relaxed)
+            + self.rebuilt.load(Ordering::Relaxed)
+            == 0
+    }
+}
+
+impl fmt::Display for PatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} unchanged, {} patched in place ({:.1} of {:.1} MB written), {} rebuilt",
+            self.unchanged.load(Ordering::Relaxed),
+            self.patched.load(Ordering::Relaxed),
+            self.written.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0,
+            self.total.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0,
+            self.rebuilt.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use roead::sarc::{Sarc, SarcWriter};
+
+    use super::*;
+
+    const PACKS: &[&str] = &[
+        "Actor/Pack/Enemy_Guardian_A",
+        "Actor/Pack/Enemy_Moriblin_Junior",
+        "Actor/Pack/Npc_TripMaster_00",
+        "Actor/Pack/Armor_151_Upper",
+    ];
+
+    fn files(path: &str) -> (Vec<(String, Vec<u8>)>, usize) {
+        let data = std::fs::read(format!("../uk-content/test/{path}")).unwrap();
+        let sarc = Sarc::new(roead::yaz0::decompress_if(&data).to_vec()).unwrap();
+        let files = sarc
+            .files()
+            .map(|f| (f.unwrap_name().to_string(), f.data.to_vec()))
+            .collect();
+        (files, sarc.guess_min_alignment())
+    }
+
+    fn rebuild(files: &[(String, Vec<u8>)], min_alignment: usize) -> Vec<u8> {
+        let mut writer = SarcWriter::new(Endian::Big.into()).with_min_alignment(min_alignment);
+        for (name, data) in files {
+            writer.add_file(name.as_str(), data.as_slice());
+        }
+        writer.to_binary()
+    }
+
+    fn patch(old: &[u8], files: &[(String, Vec<u8>)], min_alignment: usize) -> (Vec<u8>, usize) {
+        let plan = Plan::new(old, files, Endian::Big, min_alignment).unwrap();
+        let mut out = Cursor::new(old.to_vec());
+        let written = plan.apply(&mut out, old, files).unwrap();
+        let mut out = out.into_inner();
+        out.resize(plan.size, 0);
+        (out, written)
+    }
+
+    #[test]
+    fn matches_rebuild() {
+        for pack in PACKS {
+            let (base, min_alignment) = files(&format!("{pack}.sbactorpack"));
+            let (modded, _) = files(&format!("{pack}_Mod.sbactorpack"));
+            let old = rebuild(&base, min_alignment);
+            // Take one changed file at a time from the modded pack
+            let changes = base.iter().enumerate().filter_map(|(i, (name, data))| {
+                modded
+                    .iter()
+                    .find(|(n, d)| n == name && d != data)
+                    .map(|(_, d)| (i, d.clone()))
+            });
+            let mut tested = 0;
+            for (i, data) in changes {
+                let mut new = base.clone();
+                new[i].1 = data;
+                let (patched, written) = patch(&old, &new, min_alignment);
+                assert_eq!(
+                    patched,
+                    rebuild(&new, min_alignment),
+                    "{pack}: {}",
+                    new[i].0
+                );
+                assert!(written < old.len());
+                tested += 1;
+            }
+            assert!(tested > 0, "{pack} has no changed files");
+        }
+    }
+
+    #[test]
+    fn same_size() {
+        let (base, min_alignment) = files("Actor/Pack/Enemy_Guardian_A.sbactorpack");
+        let old = rebuild(&base, min_alignment);
+        let plan = Plan::new(&old, &base, Endian::Big, min_alignment).unwrap();
+        assert_eq!(plan.changed, 0);
+        assert!(plan.is_empty());
+
+        let mut new = base.clone();
+        let (i, _) = new
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (_, d))| d.len())
+            .unwrap();
+        let last = new[i].1.len() - 1;
+        new[i].1[last] ^= 0xFF;
+        let (patched, written) = patch(&old, &new, min_alignment);
+        assert_eq!(patched, rebuild(&new, min_alignment));
+        // Nothing moved, so only the file itself is written
+        assert_eq!(written, new[i].1.len());
+    }
+
+    #[test]
+    fn rebuild_needed() {
+        let (base, min_alignment) = files("Actor/Pack/Enemy_Guardian_A.sbactorpack");
+        let old = rebuild(&base, min_alignment);
+        let mut added = base.clone();
+        added.push(("Actor/Test.bxml".into(), vec![0; 16]));
+        assert!(Plan::new(&old, &added, Endian::Big, min_alignment).is_err());
+        assert!(Plan::new(&old, &base[1..], Endian::Big, min_alignment).is_err());
+        let mut all_changed = base.clone();
+        for (_, data) in all_changed.iter_mut() {
+            data.push(0);
+        }
+        assert_eq!(
+            Plan::new(&old, &all_changed, Endian::Big, min_alignment).err(),
+            Some("too many files changed")
+        );
+        assert!(Plan::new(&old, &base, Endian::Little, min_alignment).is_err());
+        assert!(Plan::new(b"Yaz0", &base, Endian::Big, min_alignment).is_err());
+    }
+}
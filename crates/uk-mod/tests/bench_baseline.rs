@@ -0,0 +1,131 @@
+//! Compares the latest benchmark results with a baseline kept on this machine.
+//!
+//! Run the benchmarks, then record them as the baseline with
+//! `UKMM_BENCH_RECORD=1 cargo test -p uk-mod --test bench_baseline`. After a
+//! change, run the benchmarks again and the same test without the variable
+//! warns of any benchmark more than 20% slower than its baseline. It never
+//! fails, since timings vary too much between runs to block on, and it does
+//! nothing where there is no baseline, as in CI.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// How much slower than its baseline a benchmark may get before a warning.
+const TOLERANCE: f64 = 0.2;
+
+type Results = BTreeMap<String, f64>;
+
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+fn baseline_path() -> PathBuf {
+    std::env::var_os("UKMM_BENCH_BASELINE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| root().join("bench_baseline.json"))
+}
+
+fn criterion_dir() -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| root().join("target"))
+        .join("criterion")
+}
+
+/// The mean time in nanoseconds of the latest run of each benchmark, by its
+/// full ID, e.g. `sized_byml/merge/1000`.
+fn latest(dir: &Path, results: &mut Results) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if path.file_name().is_some_and(|n| n == "new") {
+            let read = |file: &str| -> Option<serde_json::Value> {
+                serde_json::from_slice(&std::fs::read(path.join(file)).ok()?).ok()
+            };
+            let (Some(benchmark), Some(estimates)) =
+                (read("benchmark.json"), read("estimates.json"))
+            else {
+                continue;
+            };
+            if let (Some(id), Some(mean)) = (
+                benchmark["full_id"].as_str(),
+                estimates["mean"]["point_estimate"].as_f64(),
+            ) {
+                results.insert(id.to_owned(), mean);
+            }
+        } else {
+            latest(&path, results);
+        }
+    }
+}
+
+/// Benchmarks slower than their baseline by more than the tolerance, with how
+/// much slower as a fraction.
+fn regressions(baseline: &Results, results: &Results) -> Vec<(String, f64)> {
+    results
+        .iter()
+        .filter_map(|(id, mean)| {
+            let base = baseline.get(id).filter(|base| **base > 0.0)?;
+            let change = mean / base - 1.0;
+            (change > TOLERANCE).then(|| (id.clone(), change))
+        })
+        .collect()
+}
+
+#[test]
+fn bench_baseline() {
+    let mut results = Results::new();
+    latest(&criterion_dir(), &mut results);
+    if results.is_empty() {
+        eprintln!("No benchmark results to compare, run `cargo bench` first");
+        return;
+    }
+    let path = baseline_path();
+    if std::env::var_os("UKMM_BENCH_RECORD").is_some() {
+        std::fs::write(&path, serde_json::to_vec_pretty(&results).unwrap()).unwrap();
+        eprintln!(
+            "Recorded {} benchmarks as the baseline at {}",
+            results.len(),
+            path.display()
+        );
+        return;
+    }
+    let Ok(baseline) = std::fs::read(&path) else {
+        eprintln!("No benchmark baseline at {}", path.display());
+        return;
+    };
+    let baseline: Results = serde_json::from_slice(&baseline).unwrap();
+    let regressions = regressions(&baseline, &results);
+    for (id, change) in &regressions {
+        eprintln!(
+            "warning: {id} is {:.0}% slower than its baseline",
+            change * 100.0
+        );
+    }
+    eprintln!(
+        "{} of {} benchmarks regressed by more than {:.0}%",
+        regressions.len(),
+        results.len(),
+        TOLERANCE * 100.0
+    );
+}
+
+#[test]
+fn finds_regressions() {
+    let baseline: Results = [("a", 100.0), ("b", 100.0), ("c", 100.0)]
+        .map(|(id, mean)| (id.to_owned(), mean))
+        .into();
+    let results: Results = [("a", 119.0), ("b", 130.0), ("c", 50.0), ("d", 1000.0)]
+        .map(|(id, mean)| (id.to_owned(), mean))
+        .into();
+    let regressions = regressions(&baseline, &results);
+    assert_eq!(regressions.len(), 1);
+    assert_eq!(regressions[0].0, "b");
+    assert!((regressions[0].1 - 0.3).abs() < 1e-9);
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use roead::{sarc::SarcWriter, Endian};
+use uk_reader::ResourceReader;
+
+const FILE: &str = "Pack/Large.pack";
+/// Members of the generated pack, each of 1 MiB.
+const MEMBERS: usize = 96;
+
+/// Writes an unpacked mod holding one large uncompressed pack, about the
+/// size of TitleBG.pack.
+fn fixture() -> tempfile::TempDir {
+    let tmp = tempfile::tempdir().unwrap();
+    let pack = tmp.path().join("content").join(FILE);
+    std::fs::create_dir_all(pack.parent().unwrap()).unwrap();
+    let mut sarc = SarcWriter::new(Endian::Big);
+    for i in 0..MEMBERS {
+        sarc.add_file(format!("Terrain/Test{i}.bin"), vec![i as u8; 1024 * 1024]);
+    }
+    std::fs::write(pack, sarc.to_binary()).unwrap();
+    tmp
+}
+
+fn reader(dir: &Path, threshold: Option<u64>) -> ResourceReader {
+    ResourceReader::from_unpacked_mod(dir)
+        .unwrap()
+        .with_mmap_threshold(threshold)
+}
+
+/// Peak resident memory in KiB since the last reset, from procfs.
+#[cfg(target_os = "linux")]
+fn peak_rss() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .unwrap()
+        .lines()
+        .find_map(|l| l.strip_prefix("VmHWM:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn reset_peak_rss() {
+    std::fs::write("/proc/self/clear_refs", "5").unwrap_or(());
+}
+
+/// Reports how far peak memory rises while parsing the pack each way. This
+/// is printed rather than measured by criterion, which only times things.
+#[cfg(target_os = "linux")]
+fn peak_memory(dir: &Path) {
+    for (label, threshold) in [("read", None), ("mapped", Some(0))] {
+        let reader = reader(dir, threshold);
+        reset_peak_rss();
+        let before = peak_rss();
+        criterion::black_box(reader.get_data(FILE).unwrap());
+        let after = peak_rss();
+        eprintln!(
+            "{label}: peak RSS rose by {} MiB parsing {FILE}",
+            after.saturating_sub(before) / 1024
+        );
+    }
+}
+
+fn mapped(c: &mut Criterion) {
+    let tmp = fixture();
+    #[cfg(target_os = "linux")]
+    peak_memory(tmp.path());
+    let size = std::fs::metadata(tmp.path().join("content").join(FILE))
+        .unwrap()
+        .len();
+    let mut group = c.benchmark_group("unpacked_large_file");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(size));
+    for (label, threshold) in [("read", None), ("mapped", Some(0))] {
+        let reader = reader(tmp.path(), threshold);
+        group.bench_with_input(BenchmarkId::from_parameter(label), &reader, |b, reader| {
+            b.iter(|| {
+                reader.clear_cache();
+                criterion::black_box(reader.get_data(FILE).unwrap());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mapped);
+criterion_main!(benches);
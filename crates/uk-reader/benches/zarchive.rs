@@ -0,0 +1,70 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use uk_reader::ResourceReader;
+
+const WUA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.wua");
+const FILE: &str = "System/Version.txt";
+const THREADS: usize = 8;
+const READS_PER_THREAD: usize = 32;
+
+/// Reads the same file from several threads at once, the way rayon workers
+/// hit the dump during a merge.
+fn parallel_reads(reader: &ResourceReader) {
+    std::thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..READS_PER_THREAD {
+                    criterion::black_box(reader.get_bytes_uncached(FILE).unwrap());
+                }
+            });
+        }
+    });
+}
+
+fn readers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wua_parallel_reads");
+    group.throughput(Throughput::Elements((THREADS * READS_PER_THREAD) as u64));
+    for readers in [1, 2, 4, 8] {
+        let reader = ResourceReader::from_zarchive_with_readers(WUA, readers).unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(readers),
+            &reader,
+            |b, reader| b.iter(|| parallel_reads(reader)),
+        );
+    }
+    group.finish();
+}
+
+/// Loads every file in the archive into a fresh reader, one at a time as a
+/// merge used to, against preloading them all at once.
+fn preload(c: &mut Criterion) {
+    let names = ResourceReader::from_zarchive(WUA)
+        .unwrap()
+        .source()
+        .list_files(None)
+        .unwrap();
+    let fresh = || ResourceReader::from_zarchive(WUA).unwrap();
+    let mut group = c.benchmark_group("wua_preload");
+    group.throughput(Throughput::Elements(names.len() as u64));
+    group.bench_function("sequential", |b| {
+        b.iter_batched(
+            fresh,
+            |reader| {
+                for name in &names {
+                    criterion::black_box(reader.get_data(name.as_str()).ok());
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("preload", |b| {
+        b.iter_batched(
+            fresh,
+            |reader| criterion::black_box(reader.preload(&names)),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, readers, preload);
+criterion_main!(benches);
@@ -0,0 +1,265 @@
+//! Optional on-disk cache of the parsed resources of a game dump, so the
+//! first merge after launching does not have to read and parse every file
+//! from the dump again, or decompress it out of a WUA.
+//!
+//! Each resource is kept in a file of its own named for its canonical path,
+//! serialized the same way as resources in mods. The folder also holds a
+//! fingerprint of the dump, made of the paths the source reads from and
+//! their modified times, and the whole cache is dropped when it no longer
+//! matches. Files are touched when read, and the least recently used are
+//! removed once the cache outgrows its size limit.
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use fs_err as fs;
+use uk_content::resource::ResourceData;
+
+use crate::{ResourceLoader, Result};
+
+const FINGERPRINT: &str = "fingerprint";
+const EXTENSION: &str = "cbor";
+
+/// Identifies the state of a dump: the files or folders it is read from and
+/// when each was last modified, plus the version which wrote the cache, since
+/// the serialized format of resources can change between versions. Sources
+/// whose roots do not exist on disk, such as those in memory, have none.
+pub(crate) fn fingerprint(source: &dyn ResourceLoader) -> Option<std::string::String> {
+    let mut print = format!("{}\n", env!("CARGO_PKG_VERSION"));
+    for root in source.roots() {
+        let modified = std::fs::metadata(root).and_then(|m| m.modified()).ok()?;
+        let time = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        writeln!(print, "{}\t{}", root.display(), time.as_nanos()).ok()?;
+    }
+    Some(print)
+}
+
+#[derive(Debug)]
+pub(crate) struct DiskCache {
+    dir: PathBuf,
+    cap: u64,
+    /// Total size of the cached resources, counted on first write.
+    size: OnceLock<AtomicU64>,
+    evicting: AtomicBool,
+}
+
+impl DiskCache {
+    /// Opens the cache in a folder, first emptying it if it was made for a
+    /// different state of the dump.
+    pub(crate) fn open(dir: &Path, fingerprint: &str, cap: u64) -> Result<Self> {
+        let print_path = dir.join(FINGERPRINT);
+        let current = std::fs::read_to_string(&print_path).ok();
+        if current.as_deref() != Some(fingerprint) {
+            if dir.exists() {
+                log::info!("Game dump has changed, clearing its resource cache");
+                fs::remove_dir_all(dir)?;
+            }
+            fs::create_dir_all(dir)?;
+            fs::write(&print_path, fingerprint)?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            cap,
+            size: OnceLock::new(),
+            evicting: AtomicBool::new(false),
+        })
+    }
+
+    fn path(&self, canon: &str) -> PathBuf {
+        self.dir.join(format!("{canon}.{EXTENSION}"))
+    }
+
+    fn size(&self) -> &AtomicU64 {
+        self.size
+            .get_or_init(|| AtomicU64::new(self.entries().iter().map(|(_, size, _)| size).sum()))
+    }
+
+    /// Every cached resource file, with when it was last used and its size.
+    fn entries(&self) -> Vec<(SystemTime, u64, PathBuf)> {
+        fn walk(dir: &Path, entries: &mut Vec<(SystemTime, u64, PathBuf)>) {
+            let Ok(read) = std::fs::read_dir(dir) else {
+                return;
+            };
+            for entry in read.filter_map(std::result::Result::ok) {
+                let path = entry.path();
+                let Ok(meta) = entry.metadata() else {
+                    continue;
+                };
+                if meta.is_dir() {
+                    walk(&path, entries);
+                } else if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                    entries.push((meta.modified().unwrap_or(UNIX_EPOCH), meta.len(), path));
+                }
+            }
+        }
+        let mut entries = vec![];
+        walk(&self.dir, &mut entries);
+        entries
+    }
+
+    /// Loads a cached resource, with the size of its entry. An entry which
+    /// cannot be read is removed, so the resource is parsed from the dump
+    /// again.
+    pub(crate) fn get(&self, canon: &str) -> Option<(ResourceData, usize)> {
+        let path = self.path(canon);
+        let data = std::fs::read(&path).ok()?;
+        match minicbor_ser::from_slice(&data) {
+            Ok(resource) => {
+                touch(&path);
+                Some((resource, data.len()))
+            }
+            Err(e) => {
+                log::debug!("Invalid cached resource {canon}: {e:?}");
+                if std::fs::remove_file(&path).is_ok() {
+                    self.size().fetch_sub(data.len() as u64, Ordering::Relaxed);
+                }
+                None
+            }
+        }
+    }
+
+    /// Stores a resource, then trims the cache if it has grown too large.
+    /// The file is written in full before it is put in place, so an
+    /// interrupted write never leaves a partial entry behind.
+    pub(crate) fn put(&self, canon: &str, resource: &ResourceData) -> Result<()> {
+        let data = minicbor_ser::to_vec(resource).map_err(|e| anyhow::format_err!("{e:?}"))?;
+        // Counted before writing, so the new entry is not counted twice
+        let total = self.size();
+        let path = self.path(canon);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &data)?;
+        fs::rename(&tmp, &path)?;
+        let size = total.fetch_add(data.len() as u64, Ordering::Relaxed) + data.len() as u64;
+        if size > self.cap {
+            self.evict();
+        }
+        Ok(())
+    }
+
+    /// Removes the least recently used resources until the cache is down to
+    /// three quarters of its limit, so it is not trimmed again on every
+    /// write.
+    fn evict(&self) {
+        if self.evicting.swap(true, Ordering::Acquire) {
+            return;
+        }
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(time, ..)| *time);
+        let target = self.cap / 4 * 3;
+        let mut freed = 0;
+        for (_, size, path) in entries {
+            if total <= target {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                total -= size;
+                freed += size;
+            }
+        }
+        self.size().store(total, Ordering::Relaxed);
+        log::debug!("Freed {freed} bytes from game dump resource cache");
+        self.evicting.store(false, Ordering::Release);
+    }
+
+    /// Removes every cached resource, keeping the fingerprint.
+    pub(crate) fn clear(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else if path.file_name().is_some_and(|name| name != FINGERPRINT) {
+                fs::remove_file(&path)?;
+            }
+        }
+        self.size().store(0, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn touch(path: &Path) {
+    if let Err(e) = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|f| f.set_modified(SystemTime::now()))
+    {
+        log::debug!("Could not update cached resource time: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::ResourceReader;
+
+    fn dump() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let content = tmp.path().join("content");
+        std::fs::create_dir_all(content.join("System")).unwrap();
+        std::fs::write(content.join("System/Version.txt"), b"1.5.0").unwrap();
+        tmp
+    }
+
+    fn set_modified(path: &Path, secs: u64) {
+        std::fs::File::open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn persists() {
+        let dump = dump();
+        let cache = tempfile::tempdir().unwrap();
+        let open = || {
+            let reader = ResourceReader::from_unpacked_mod(dump.path()).unwrap();
+            reader.use_disk_cache(cache.path(), u64::MAX).unwrap();
+            reader
+        };
+        let version = open().get_data("System/Version.txt").unwrap();
+        assert!(cache.path().join("System/Version.txt.cbor").exists());
+
+        // A new reader loads the resource from the cache rather than the dump
+        std::fs::remove_file(dump.path().join("content/System/Version.txt")).unwrap();
+        assert_eq!(open().get_data("System/Version.txt").unwrap(), version);
+
+        // Until the dump changes
+        set_modified(&dump.path().join("content"), 1000);
+        assert!(open().get_data("System/Version.txt").is_err());
+        assert!(!cache.path().join("System/Version.txt.cbor").exists());
+    }
+
+    #[test]
+    fn evict() {
+        let dir = tempfile::tempdir().unwrap();
+        let resource = ResourceData::Binary(vec![0; 1000]);
+        let size = minicbor_ser::to_vec(&resource).unwrap().len() as u64;
+        let cache = DiskCache::open(dir.path(), "test", size * 4).unwrap();
+        for i in 0..4 {
+            cache.put(&format!("Pack/{i}.pack"), &resource).unwrap();
+            set_modified(&cache.path(&format!("Pack/{i}.pack")), 1000 + i);
+        }
+        // Reading the oldest makes it the most recently used
+        assert!(cache.get("Pack/0.pack").is_some());
+        cache.put("Pack/4.pack", &resource).unwrap();
+        let left: Vec<_> = (0..5)
+            .filter(|i| cache.path(&format!("Pack/{i}.pack")).exists())
+            .collect();
+        assert_eq!(left, [0, 3, 4]);
+
+        cache.clear().unwrap();
+        assert!(cache.entries().is_empty());
+        assert!(dir.path().join(FINGERPRINT).exists());
+    }
+}
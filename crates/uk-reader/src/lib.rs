@@ -1,27 +1,48 @@
-// mod nsp;
+mod disk;
+mod memory;
+mod nsp;
 mod unpacked;
+mod validate;
 mod zarchive;
 
 use std::{
+    io::{Read, Write},
     path::{Path, PathBuf},
-    sync::{Arc, LazyLock},
-    time::Duration,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow_ext::Context;
 use dashmap::DashMap;
 use include_flate::flate;
 use join_str::jstr;
+use mmap_rs::{Mmap, MmapOptions};
 use moka::sync::Cache;
+use parking_lot::RwLock;
+use rayon::prelude::*;
 use roead::sarc::Sarc;
 use serde::{Deserialize, Serialize};
 use smartstring::alias::String;
 use uk_content::{
-    canonicalize, constants::Language, platform_prefixes, prelude::Endian, resource::*,
+    canon::{normalize_unicode, platform},
+    canonicalize,
+    constants::Language,
+    platform_prefixes,
+    prelude::Endian,
+    resource::*,
 };
 use uk_util::PathExt;
 
-use self::{unpacked::Unpacked, zarchive::ZArchive};
+pub use self::{
+    memory::MemoryROMSource,
+    unpacked::DEFAULT_MMAP_THRESHOLD,
+    validate::{DumpRelease, DumpReport},
+    zarchive::{default_zarchive_readers, ZArchiveDamage, ZArchivePart, ZArchiveReport},
+};
+use self::{disk::DiskCache, nsp::Nsp, unpacked::Unpacked, zarchive::ZArchive};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ROMError {
@@ -29,6 +50,31 @@ pub enum ROMError {
     FileNotFound(String, PathBuf),
     #[error("Missing required {0} folder in game dump\n(Using ROM at {1})")]
     MissingDumpDir(&'static str, PathBuf),
+    #[error("Game dump is damaged: {0}\n(Using ROM at {1})")]
+    DamagedArchive(String, PathBuf),
+    #[error(
+        "Game dump is version {found}, but version {required} is needed. {hint}\n(Using ROM at \
+         {path})"
+    )]
+    WrongVersion {
+        found:    String,
+        required: &'static str,
+        hint:     &'static str,
+        path:     PathBuf,
+    },
+    #[error(
+        "Game dump has no texts in {language}, only in {found}. Choose one of those languages, or \
+         set the base game and update folders to a {region} release of the game\n(Using ROM at \
+         {path})"
+    )]
+    WrongRegion {
+        language: Language,
+        region:   &'static str,
+        found:    String,
+        path:     PathBuf,
+    },
+    #[error("Missing Switch decryption keys: {0}\n(Using keys at {1})")]
+    MissingKeys(String, PathBuf),
     #[error("Invalid resource path: {0}")]
     InvalidPath(String),
     #[error(transparent)]
@@ -50,9 +96,11 @@ impl From<ROMError> for uk_content::UKError {
 }
 
 flate!(static NEST_MAP: str from "data/nest_map.json");
-type ResourceCache = Cache<String, Arc<ResourceData>>;
+type ResourceCache = Cache<String, Cached>;
 type SarcCache = Cache<String, Arc<Sarc<'static>>>;
-const CACHE_SIZE: usize = 10000;
+/// How much the memory cache of parsed resources holds by default, counted
+/// as the size of the files they were parsed from.
+pub const DEFAULT_CACHE_CAPACITY: u64 = 512 * 1024 * 1024;
 pub type Result<T> = std::result::Result<T, ROMError>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -64,23 +112,224 @@ pub enum BinType {
 #[typetag::serde(tag = "type")]
 pub trait ResourceLoader: std::fmt::Debug + Send + Sync {
     fn get_data(&self, name: &Path) -> Result<Vec<u8>>;
+    /// Like [`ResourceLoader::get_data`], for callers which only need to
+    /// look at the bytes, so sources may map large files instead of reading
+    /// them.
+    fn get_file_data(&self, name: &Path) -> Result<FileData> {
+        self.get_data(name).map(FileData::Owned)
+    }
     fn get_aoc_file_data(&self, name: &Path) -> Result<Vec<u8>>;
+    /// Opens a file to be read as it is needed, so large files can be copied
+    /// out without holding them in memory. Sources which cannot stream read
+    /// the file whole first.
+    fn open_stream(&self, name: &Path) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(std::io::Cursor::new(self.get_data(name)?)))
+    }
+    /// Copies a file into `writer` as it is read, returning its size.
+    fn copy_to(&self, name: &Path, writer: &mut dyn Write) -> Result<u64> {
+        Ok(std::io::copy(&mut self.open_stream(name)?, writer)?)
+    }
     fn file_exists(&self, name: &Path) -> bool;
+    /// Canonical paths of every file in the source, DLC files included, in
+    /// order. If `prefix` is given, only paths starting with it are listed,
+    /// e.g. `Actor/Pack` or `Aoc/0010/Pack`.
+    fn list_files(&self, prefix: Option<&Path>) -> Result<Vec<String>>;
+    /// Whether the source has DLC, whether or not it has every DLC file.
+    fn has_aoc(&self) -> bool;
     fn host_path(&self) -> &Path;
+    /// The files or folders the source reads from, whose modified times tell
+    /// a persistent cache when the dump has changed.
+    fn roots(&self) -> Vec<&Path> {
+        vec![self.host_path()]
+    }
+    /// Counters for sources which share handles between threads.
+    fn read_stats(&self) -> Option<ReadStats> {
+        None
+    }
+    /// Sets the size from which files are mapped rather than read, for
+    /// sources which can map them. `None` turns mapping off.
+    fn set_mmap_threshold(&mut self, _threshold: Option<u64>) {}
+    /// How many files the source should read at once when preloading, for
+    /// sources which slow down when read from every thread at once. `None`
+    /// reads with as many threads as rayon has.
+    fn preload_threads(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Sorts canonical paths for [`ResourceLoader::list_files`], dropping repeats
+/// and any not under `prefix`.
+fn listing(paths: impl IntoIterator<Item = String>, prefix: Option<&Path>) -> Vec<String> {
+    let prefix = prefix.map(|p| p.to_string_lossy().replace('\\', "/"));
+    let mut paths: Vec<String> = paths
+        .into_iter()
+        .filter(|path| {
+            prefix
+                .as_deref()
+                .map(|prefix| path.starts_with(prefix))
+                .unwrap_or(true)
+        })
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// The contents of a file from a source, either read into memory or mapped.
+/// A mapping is only held for as long as the handle, so it should be dropped
+/// once the file is parsed.
+#[derive(Debug)]
+pub enum FileData {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl FileData {
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, FileData::Mapped(_))
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            FileData::Owned(data) => data,
+            FileData::Mapped(map) => map.to_vec(),
+        }
+    }
+}
+
+impl std::ops::Deref for FileData {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            FileData::Owned(data) => data,
+            FileData::Mapped(map) => map,
+        }
+    }
+}
+
+impl AsRef<[u8]> for FileData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Maps the whole of a file read-only. Empty files cannot be mapped.
+///
+/// # Safety
+///
+/// The file must not be changed by anything else while the mapping is held.
+pub(crate) unsafe fn map_whole(file: &std::fs::File) -> std::io::Result<Mmap> {
+    let len = file.metadata()?.len() as usize;
+    MmapOptions::new(len)
+        // SAFETY: Upheld by the caller.
+        .and_then(|options| unsafe { options.with_file(file, 0) }.map())
+        .map_err(std::io::Error::other)
+}
+
+/// How reads from a pooled source went. Counters only ever grow, so compare
+/// two snapshots with [`ReadStats::since`] to measure a single merge.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReadStats {
+    pub handles: usize,
+    pub reads: u64,
+    /// Reads which found every handle busy and had to wait.
+    pub contended: u64,
+    /// Total time spent waiting for a handle.
+    pub wait: Duration,
+}
+
+impl ReadStats {
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            handles: self.handles,
+            reads: self.reads.saturating_sub(earlier.reads),
+            contended: self.contended.saturating_sub(earlier.contended),
+            wait: self.wait.saturating_sub(earlier.wait),
+        }
+    }
 }
 
-fn construct_res_cache() -> ResourceCache {
-    log::debug!(
-        "Initializing resource cache (up to {} resources)",
-        CACHE_SIZE
-    );
+impl std::fmt::Display for ReadStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} reads over {} handles, {} waited for a handle ({:.2?} total)",
+            self.reads, self.handles, self.contended, self.wait
+        )
+    }
+}
+
+/// How the memory cache of parsed resources is doing. Hits and misses only
+/// ever grow, while the entries and size are as of when they were taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits:     u64,
+    pub misses:   u64,
+    pub entries:  u64,
+    /// Estimated memory used, as the total size of the files the cached
+    /// resources were parsed from.
+    pub size:     u64,
+    pub capacity: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+impl std::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MIB: f64 = 1024.0 * 1024.0;
+        write!(
+            f,
+            "{} resources using {:.1} of {:.1} MiB, {:.0}% of {} lookups cached",
+            self.entries,
+            self.size as f64 / MIB,
+            self.capacity as f64 / MIB,
+            self.hit_rate() * 100.0,
+            self.hits + self.misses
+        )
+    }
+}
+
+/// A parsed resource in the memory cache, with the size of the file it was
+/// parsed from. The cache weighs entries by it, so a few huge maps cannot
+/// take up memory the way they could when it only counted entries.
+#[derive(Clone)]
+struct Cached {
+    resource: Arc<ResourceData>,
+    size:     u32,
+}
+
+impl Cached {
+    fn new(resource: impl Into<Arc<ResourceData>>, size: usize) -> Self {
+        Self {
+            resource: resource.into(),
+            // Entries weighing nothing are never evicted
+            size:     u32::try_from(size).unwrap_or(u32::MAX).max(1),
+        }
+    }
+}
+
+fn construct_res_cache(capacity: u64) -> ResourceCache {
+    log::debug!("Initializing resource cache (up to {capacity} bytes)");
     ResourceCache::builder()
-        .max_capacity(CACHE_SIZE as u64)
-        .initial_capacity(CACHE_SIZE / 10)
+        .max_capacity(capacity)
+        .weigher(|_, cached: &Cached| cached.size)
+        .initial_capacity(1000)
         .time_to_idle(Duration::from_secs(30))
         .build()
 }
 
+fn default_cache_capacity() -> u64 {
+    DEFAULT_CACHE_CAPACITY
+}
+
 fn construct_sarc_cache() -> SarcCache {
     Cache::new(100)
 }
@@ -96,17 +345,29 @@ fn init_nest_map() -> Arc<DashMap<String, Arc<str>>> {
 pub struct ResourceReader {
     bin_type: BinType,
     source: Box<dyn ResourceLoader>,
-    #[serde(skip, default = "construct_res_cache")]
-    cache: ResourceCache,
+    /// Built on first use, so a capacity read with the rest of the reader is
+    /// the one it gets.
+    #[serde(skip)]
+    cache: OnceLock<ResourceCache>,
+    #[serde(default = "default_cache_capacity")]
+    cache_capacity: u64,
+    #[serde(skip)]
+    hits: AtomicU64,
+    #[serde(skip)]
+    misses: AtomicU64,
     #[serde(skip, default = "construct_sarc_cache")]
     sarc_cache: SarcCache,
     #[serde(skip, default = "init_nest_map")]
     nest_map: Arc<DashMap<String, Arc<str>>>,
+    #[serde(skip)]
+    disk_cache: RwLock<Option<Arc<DiskCache>>>,
 }
 
 impl PartialEq for ResourceReader {
     fn eq(&self, other: &Self) -> bool {
-        self.bin_type == other.bin_type && self.source.host_path() == other.source.host_path()
+        self.bin_type == other.bin_type
+            && self.source.host_path() == other.source.host_path()
+            && self.cache_capacity == other.cache_capacity
     }
 }
 
@@ -115,14 +376,82 @@ impl std::fmt::Debug for ResourceReader {
         f.debug_struct("ResourceReader")
             .field("bin_type", &self.bin_type)
             .field("source", &self.source)
-            .field("cache_len", &self.cache.entry_count())
+            .field("cache_len", &self.cache().entry_count())
             .finish()
     }
 }
 
 impl ResourceReader {
+    fn cache(&self) -> &ResourceCache {
+        self.cache
+            .get_or_init(|| construct_res_cache(self.cache_capacity))
+    }
+
     pub fn clear_cache(&self) {
-        self.cache.invalidate_all();
+        self.cache().invalidate_all();
+    }
+
+    /// Sets how much the memory cache of parsed resources holds, in bytes of
+    /// the files they were parsed from. Anything already cached is dropped.
+    pub fn with_cache_capacity(mut self, capacity: u64) -> Self {
+        self.cache_capacity = capacity;
+        self.cache = OnceLock::new();
+        self
+    }
+
+    pub fn cache_capacity(&self) -> u64 {
+        self.cache_capacity
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        let cache = self.cache();
+        cache.run_pending_tasks();
+        CacheStats {
+            hits:     self.hits.load(Ordering::Relaxed),
+            misses:   self.misses.load(Ordering::Relaxed),
+            entries:  cache.entry_count(),
+            size:     cache.weighted_size(),
+            capacity: self.cache_capacity,
+        }
+    }
+
+    /// Keeps parsed resources in a folder as well as in memory, up to `cap`
+    /// bytes, so they survive between launches. Anything cached for an older
+    /// state of the dump is cleared first. Dumps which do not live on disk
+    /// are left uncached.
+    pub fn use_disk_cache(&self, dir: impl AsRef<Path>, cap: u64) -> Result<()> {
+        let Some(fingerprint) = disk::fingerprint(self.source.as_ref()) else {
+            log::debug!("Game dump has no files on disk, not caching it");
+            return Ok(());
+        };
+        let cache = DiskCache::open(dir.as_ref(), &fingerprint, cap)?;
+        *self.disk_cache.write() = Some(Arc::new(cache));
+        Ok(())
+    }
+
+    /// Empties the cache set up by [`ResourceReader::use_disk_cache`], along
+    /// with the one in memory.
+    pub fn clear_disk_cache(&self) -> Result<()> {
+        self.clear_cache();
+        self.sarc_cache.invalidate_all();
+        match self.disk_cache.read().as_ref() {
+            Some(cache) => cache.clear(),
+            None => Ok(()),
+        }
+    }
+
+    fn disk_cache(&self) -> Option<Arc<DiskCache>> {
+        self.disk_cache.read().clone()
+    }
+
+    /// Writes a parsed resource to the disk cache, if there is one. Failing
+    /// to is not an error, as the resource can always be read again.
+    fn persist(&self, canon: &str, resource: &ResourceData) {
+        if let Some(cache) = self.disk_cache() {
+            if let Err(e) = cache.put(canon, resource) {
+                log::debug!("Failed to cache {canon} on disk: {e:?}");
+            }
+        }
     }
 
     pub fn source(&self) -> &dyn ResourceLoader {
@@ -133,13 +462,59 @@ impl ResourceReader {
         serde_json::to_string(&self.source).unwrap()
     }
 
+    pub fn read_stats(&self) -> Option<ReadStats> {
+        self.source.read_stats()
+    }
+
+    /// Checks which parts of the game a WUA dump has and whether it is whole,
+    /// without opening it as a dump, so one missing the update can still be
+    /// reported on.
+    pub fn inspect_zarchive(archive_path: impl AsRef<Path>) -> Result<ZArchiveReport> {
+        ZArchive::inspect(archive_path)
+    }
+
     pub fn from_zarchive(archive_path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_zarchive_with_readers(archive_path, default_zarchive_readers())
+    }
+
+    /// Opens a WUA dump with `readers` independent handles, so that up to
+    /// that many files can be read from it at once.
+    pub fn from_zarchive_with_readers(
+        archive_path: impl AsRef<Path>,
+        readers: usize,
+    ) -> Result<Self> {
         Ok(Self {
-            source: Box::new(ZArchive::new(archive_path)?),
-            cache: construct_res_cache(),
+            source: Box::new(ZArchive::new(archive_path, readers)?),
+            cache: Default::default(),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            hits: Default::default(),
+            misses: Default::default(),
             sarc_cache: construct_sarc_cache(),
             bin_type: BinType::Nintendo,
             nest_map: init_nest_map(),
+            disk_cache: Default::default(),
+        })
+    }
+
+    /// Opens a Switch dump kept as NSP packages, decrypting it with the keys
+    /// at `keys_path`. The update is required, since the base game alone is
+    /// not supported.
+    pub fn from_nsp(
+        base: impl AsRef<Path>,
+        update: impl AsRef<Path>,
+        dlc: Option<impl AsRef<Path>>,
+        keys_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        Ok(Self {
+            source: Box::new(Nsp::new(base, update, dlc, keys_path)?),
+            cache: Default::default(),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            hits: Default::default(),
+            misses: Default::default(),
+            sarc_cache: construct_sarc_cache(),
+            bin_type: BinType::Nintendo,
+            nest_map: init_nest_map(),
+            disk_cache: Default::default(),
         })
     }
 
@@ -147,16 +522,63 @@ impl ResourceReader {
         content_dir: Option<impl AsRef<Path>>,
         update_dir: Option<impl AsRef<Path>>,
         aoc_dir: Option<impl AsRef<Path>>,
+    ) -> Result<Self> {
+        fn dir(dir: Option<impl AsRef<Path>>) -> Option<PathBuf> {
+            dir.map(|dir| dir.as_ref().to_path_buf())
+        }
+        // The update is looked in before the base game it updates
+        Self::from_unpacked_layers(
+            dir(update_dir)
+                .into_iter()
+                .chain(dir(content_dir))
+                .collect(),
+            dir(aoc_dir).into_iter().collect(),
+        )
+    }
+
+    /// Opens a dump unpacked to any number of content folders and DLC
+    /// folders, each listed in the order files are looked for in them. An
+    /// update kept apart from the base game should come first, and the
+    /// base game folder may already have the update merged into it.
+    pub fn from_unpacked_layers(
+        content_dirs: Vec<PathBuf>,
+        aoc_dirs: Vec<PathBuf>,
     ) -> Result<Self> {
         Ok(Self {
-            source: Box::new(Unpacked::new(content_dir, update_dir, aoc_dir, true)?),
-            cache: construct_res_cache(),
+            source: Box::new(Unpacked::new(content_dirs, aoc_dirs, true)?),
+            cache: Default::default(),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            hits: Default::default(),
+            misses: Default::default(),
             sarc_cache: construct_sarc_cache(),
             bin_type: BinType::Nintendo,
             nest_map: init_nest_map(),
+            disk_cache: Default::default(),
         })
     }
 
+    /// Wraps an in-memory dump, for tests.
+    pub fn from_memory(source: MemoryROMSource) -> Self {
+        Self {
+            source: Box::new(source),
+            cache: Default::default(),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            hits: Default::default(),
+            misses: Default::default(),
+            sarc_cache: construct_sarc_cache(),
+            bin_type: BinType::Nintendo,
+            nest_map: init_nest_map(),
+            disk_cache: Default::default(),
+        }
+    }
+
+    /// Sets the size from which uncompressed files in an unpacked dump are
+    /// mapped rather than read into memory. `None` always reads them.
+    pub fn with_mmap_threshold(mut self, threshold: Option<u64>) -> Self {
+        self.source.set_mmap_threshold(threshold);
+        self
+    }
+
     pub fn from_unpacked_mod(mod_dir: impl AsRef<Path>) -> Result<Self> {
         fn inner(mod_dir: &Path) -> Result<ResourceReader> {
             let (content_u, aoc_u) = platform_prefixes(Endian::Big);
@@ -170,30 +592,136 @@ impl ResourceReader {
                 .exists_then()
                 .or_else(|| mod_dir.join(aoc_nx).exists_then());
             Ok(ResourceReader {
-                source: Box::new(Unpacked::new(content_dir, None::<PathBuf>, aoc_dir, false)?),
-                cache: construct_res_cache(),
+                source: Box::new(Unpacked::new(
+                    content_dir.into_iter().collect(),
+                    aoc_dir.into_iter().collect(),
+                    false,
+                )?),
+                cache: Default::default(),
+                cache_capacity: DEFAULT_CACHE_CAPACITY,
+                hits: Default::default(),
+                misses: Default::default(),
                 sarc_cache: construct_sarc_cache(),
                 bin_type: BinType::Nintendo,
                 nest_map: init_nest_map(),
+                disk_cache: Default::default(),
             })
         }
         inner(mod_dir.as_ref())
     }
 
     pub fn get_resource(&self, name: impl AsRef<Path>) -> Result<Arc<ResourceData>> {
-        let name = name
+        let name: String = normalize_unicode(&name.as_ref().to_string_lossy())
             .as_ref()
-            .to_str()
-            .ok_or_else(|| ROMError::InvalidPath(name.as_ref().to_string_lossy().into()))?
             .into();
-        self.cache
+        self.cache()
             .get(&name)
+            .map(|cached| cached.resource)
             .ok_or_else(|| ROMError::FileNotFound(name, self.source.host_path().to_path_buf()))
     }
 
+    /// Gets a resource from the dump. A file named the way the other
+    /// platform has it is looked up under the name this dump uses if it is
+    /// not found as is, and a language pack the dump lacks is replaced by
+    /// that of the nearest language it has.
     pub fn get_data(&self, path: impl AsRef<Path>) -> Result<Arc<ResourceData>> {
-        let canon = canonicalize(path.as_ref());
-        Ok(self.get_or_add_resource(path.as_ref(), canon)?)
+        let path = path.as_ref();
+        let canon = canonicalize(path);
+        self.get_or_add_resource(path, canon.clone())
+            .or_else(|err| {
+                if let Some(res) = self.get_language_fallback(&canon) {
+                    return Ok(res);
+                }
+                let name = path.to_string_lossy();
+                let Some(other) = platform::counterpart(&name) else {
+                    return Err(err.into());
+                };
+                let res = self
+                    .get_or_add_resource(other.as_ref(), canonicalize(&other))
+                    .map_err(|_| err)?;
+                log::warn!("{name} is not in the dump, so its counterpart {other} was used");
+                Ok(res)
+            })
+    }
+
+    /// Looks up a language pack, or the message archive in one, for the
+    /// nearest language the dump has: the same language for another region
+    /// first, then English. What is found is cached under the name asked
+    /// for as well, so texts merged for the user's language are still
+    /// written out as that language.
+    fn get_language_fallback(&self, canon: &str) -> Option<Arc<ResourceData>> {
+        let (lang, message) = Language::iter().find_map(|lang| {
+            if canonicalize(lang.bootup_path().as_str()) == canon {
+                Some((*lang, false))
+            } else if canonicalize(lang.message_path().as_str()) == canon {
+                Some((*lang, true))
+            } else {
+                None
+            }
+        })?;
+        let available: Vec<Language> = self
+            .languages()
+            .iter()
+            .copied()
+            .filter(|l| *l != lang)
+            .collect();
+        if available.is_empty() {
+            return None;
+        }
+        let fallback = *lang.nearest(&available);
+        let path = if message {
+            fallback.message_path()
+        } else {
+            fallback.bootup_path()
+        };
+        let fallback_canon = canonicalize(path.as_str());
+        let res = self
+            .get_or_add_resource(path.as_str().as_ref(), fallback_canon.clone())
+            .ok()?;
+        log::warn!("{canon} is not in the dump, so {path} was used in its place");
+        if let Some(cached) = self.cache().get(&fallback_canon) {
+            self.cache().insert(canon.into(), cached);
+        }
+        Some(res)
+    }
+
+    /// Loads and parses files by canonical path into the cache in parallel,
+    /// so looking them up later is a hit rather than a read. Files already
+    /// cached, or not in the dump, are skipped. Preloading more than the
+    /// cache holds only evicts what was preloaded first. Returns how many
+    /// files were loaded.
+    pub fn preload(&self, names: &[String]) -> usize {
+        let names: Vec<&String> = names
+            .iter()
+            .filter(|name| !self.cache().contains_key(*name))
+            .collect();
+        if names.is_empty() {
+            return 0;
+        }
+        let timer = Instant::now();
+        let load = || {
+            names
+                .par_iter()
+                .filter(|name| {
+                    self.get_or_add_resource(name.as_str().as_ref(), String::from(name.as_str()))
+                        .is_ok()
+                })
+                .count()
+        };
+        let loaded = match self
+            .source
+            .preload_threads()
+            .map(|threads| rayon::ThreadPoolBuilder::new().num_threads(threads).build())
+        {
+            Some(Ok(pool)) => pool.install(load),
+            _ => load(),
+        };
+        log::debug!(
+            "Preloaded {loaded} of {} files from the dump in {:.2?}",
+            names.len(),
+            timer.elapsed()
+        );
+        loaded
     }
 
     pub fn get_bytes_uncached(&self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
@@ -204,6 +732,24 @@ impl ResourceReader {
         self.source().get_aoc_file_data(path.as_ref())
     }
 
+    /// Opens a file in the dump to be read as it is needed, bypassing the
+    /// cache like [`get_bytes_uncached`](Self::get_bytes_uncached).
+    pub fn open_stream(&self, path: impl AsRef<Path>) -> Result<Box<dyn Read + Send>> {
+        self.source().open_stream(path.as_ref())
+    }
+
+    /// Copies a file in the dump into `writer` without reading it whole,
+    /// returning its size.
+    pub fn copy_to(&self, path: impl AsRef<Path>, writer: &mut dyn Write) -> Result<u64> {
+        self.source().copy_to(path.as_ref(), writer)
+    }
+
+    /// Where a file nested in a pack is found in the dump, as a path through
+    /// each parent joined by `//`, if the file is one the dump nests.
+    pub fn nest_path(&self, canon: &str) -> Option<Arc<str>> {
+        self.nest_map.get(canon).map(|path| path.clone())
+    }
+
     pub fn get_bytes_from_sarc(&self, nest_path: &str) -> uk_content::Result<Vec<u8>> {
         let parts = nest_path.split("//").collect::<Vec<_>>();
         let root = self
@@ -226,14 +772,17 @@ impl ResourceReader {
             let root = root.clone();
             Some(
                 self.sarc_cache
-                    .try_get_with(canonicalize(parts[1]), || -> uk_content::Result<_> {
-                        let sarc = Sarc::new(
-                            root.get_data(parts[1])
-                                .context("Couldn't get nested SARC")?
-                                .to_vec(),
-                        )?;
-                        Ok(Arc::new(sarc))
-                    })
+                    .try_get_with(
+                        canonicalize(format!("{}//{}", parts[0], parts[1])),
+                        || -> uk_content::Result<_> {
+                            let sarc = Sarc::new(
+                                root.get_data(parts[1])
+                                    .context("Couldn't get nested SARC")?
+                                    .to_vec(),
+                            )?;
+                            Ok(Arc::new(sarc))
+                        },
+                    )
                     .map_err(|e| Arc::try_unwrap(e).expect("Eh"))?,
             )
         } else {
@@ -265,7 +814,11 @@ impl ResourceReader {
                 nest_path.split("//").last().unwrap_or_default(),
             )?;
         }
-        Ok(self.cache.get_with(canon.into(), || Arc::new(resource)))
+        self.persist(canon, &resource);
+        Ok(self
+            .cache()
+            .get_with(canon.into(), || Cached::new(resource, data.len()))
+            .resource)
     }
 
     fn get_or_add_resource(
@@ -274,17 +827,23 @@ impl ResourceReader {
         canon: String,
     ) -> uk_content::Result<Arc<ResourceData>> {
         log::trace!("Loading resource {}", &canon);
+        let mut loaded = false;
         let res_result = self
-            .cache
+            .cache()
             .try_get_with(canon.clone(), || -> uk_content::Result<_> {
+                loaded = true;
+                if let Some((resource, size)) = self.disk_cache().and_then(|c| c.get(&canon)) {
+                    log::trace!("Resource {} loaded from disk cache", &canon);
+                    return Ok(Cached::new(resource, size));
+                }
                 log::trace!("Resource {} not in cache, pulling", &canon);
                 let data = self
                     .source
-                    .get_data(path)
+                    .get_file_data(path)
                     .with_context(|| jstr!("File {&canon} not found in dump"))?;
-                let resource = match self.bin_type {
+                let (resource, size) = match self.bin_type {
                     BinType::Nintendo => {
-                        let data = roead::yaz0::decompress_if(data.as_slice());
+                        let data = roead::yaz0::decompress_if(&data);
                         let res = ResourceData::from_binary(canon.as_str(), data.as_ref())?;
                         if is_mergeable_sarc(canon.as_str(), data.as_ref()) {
                             self.process_sarc(
@@ -292,17 +851,26 @@ impl ResourceReader {
                                 path.display().to_string().as_str(),
                             )?;
                         }
-                        res
+                        (res, data.len())
                     }
                     BinType::MiniCbor => {
-                        minicbor_ser::from_slice(data.as_slice())
-                            .map_err(anyhow_ext::Error::from)?
+                        (
+                            minicbor_ser::from_slice(&data).map_err(anyhow_ext::Error::from)?,
+                            data.len(),
+                        )
                     }
                 };
-                Ok(Arc::new(resource))
+                self.persist(&canon, &resource);
+                Ok(Cached::new(resource, size))
             });
+        // A failed load shared from another thread is a miss all the same
+        if !loaded && res_result.is_ok() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
         match res_result {
-            Ok(res) => Ok(res),
+            Ok(res) => Ok(res.resource),
             Err(e) => {
                 log::trace!("Failed to get file from dump: {e}. Performing parent lookup...");
                 let nest_path = self.nest_map.get(&canon);
@@ -335,7 +903,7 @@ impl ResourceReader {
         for file in sarc.files() {
             let name = file.name().context("SARC file missing name")?.to_string();
             let canon = canonicalize(&name);
-            if !self.cache.contains_key(&canon) {
+            if !self.cache().contains_key(&canon) {
                 let data = file.data;
                 let data = roead::yaz0::decompress_if(data);
                 let resource = ResourceData::from_binary(&name, data.as_ref())
@@ -343,7 +911,9 @@ impl ResourceReader {
                 if is_mergeable_sarc(canon.as_str(), data.as_ref()) {
                     self.process_sarc(Sarc::new(data.as_ref())?, &name)?;
                 }
-                self.cache.insert(canon.clone(), Arc::new(resource));
+                self.persist(&canon, &resource);
+                self.cache()
+                    .insert(canon.clone(), Cached::new(resource, data.len()));
             }
             // if !self.nest_map.contains_key(&canon) {
             //     self.nest_map.insert(canon, sarc_path.into());
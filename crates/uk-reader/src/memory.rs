@@ -0,0 +1,354 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use roead::{sarc::SarcWriter, yaz0::compress_if};
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+use uk_content::{canon::canonicalize_aoc, canonicalize, constants::Language, prelude::Endian};
+
+use crate::{ROMError, Result};
+
+/// A game dump held entirely in memory, for tests which need a dump but not
+/// a real one. Files are stored under their canonical paths, so they can be
+/// added and looked up by canonical or physical path alike. DLC files are
+/// those under `Aoc/0010`, or added with [`MemoryROMSource::with_aoc_file`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryROMSource {
+    host_path: PathBuf,
+    endian:    Endian,
+    files:     BTreeMap<String, Vec<u8>>,
+}
+
+impl MemoryROMSource {
+    pub fn new(endian: Endian) -> Self {
+        // Each source gets its own host path, since readers are told apart
+        // by it
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            host_path: format!("memory:{}", NEXT_ID.fetch_add(1, Ordering::Relaxed)).into(),
+            endian,
+            files: Default::default(),
+        }
+    }
+
+    /// Creates a source from files keyed by canonical or physical path.
+    pub fn from_files<P: AsRef<Path>, D: Into<Vec<u8>>>(
+        endian: Endian,
+        files: impl IntoIterator<Item = (P, D)>,
+    ) -> Self {
+        files
+            .into_iter()
+            .fold(Self::new(endian), |source, (path, data)| {
+                source.with_file(path, data)
+            })
+    }
+
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Canonical paths of every file, DLC files included.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(|f| f.as_str())
+    }
+
+    pub fn insert(&mut self, path: impl AsRef<Path>, data: impl Into<Vec<u8>>) {
+        self.files.insert(canonicalize(path), data.into());
+    }
+
+    pub fn with_file(mut self, path: impl AsRef<Path>, data: impl Into<Vec<u8>>) -> Self {
+        self.insert(path, data);
+        self
+    }
+
+    /// Adds a file by its path relative to the DLC root.
+    pub fn with_aoc_file(mut self, path: impl AsRef<Path>, data: impl Into<Vec<u8>>) -> Self {
+        self.files.insert(canonicalize_aoc(path), data.into());
+        self
+    }
+
+    fn build_sarc<N: AsRef<str>, D: AsRef<[u8]>>(
+        &self,
+        path: &str,
+        files: impl IntoIterator<Item = (N, D)>,
+    ) -> Vec<u8> {
+        // Fixtures are often copied from a dump already compressed
+        fn compress_if_needed<'a>(data: &'a [u8], name: &str) -> Cow<'a, [u8]> {
+            if data.starts_with(b"Yaz0") {
+                data.into()
+            } else {
+                compress_if(data, name)
+            }
+        }
+
+        let mut sarc = SarcWriter::new(self.endian.into());
+        for (name, data) in files {
+            let name = name.as_ref();
+            sarc.add_file(name, compress_if_needed(data.as_ref(), name).as_ref());
+        }
+        compress_if(&sarc.to_binary(), path).into_owned()
+    }
+
+    /// Adds a SARC holding `files` at `path`, which may be a DLC path such as
+    /// `aoc/0010/Pack/AocMainField.pack`. Members, and the SARC itself, are
+    /// Yaz0 compressed if their extensions call for it and they are not
+    /// already.
+    pub fn with_pack<N: AsRef<str>, D: AsRef<[u8]>>(
+        mut self,
+        path: &str,
+        files: impl IntoIterator<Item = (N, D)>,
+    ) -> Self {
+        let data = self.build_sarc(path, files);
+        self.insert(path, data);
+        self
+    }
+
+    /// Adds `Actor/Pack/{name}.sbactorpack` holding `files`, which are named
+    /// as they are inside the pack, e.g. `Actor/ActorLink/{name}.bxml`.
+    pub fn with_actor_pack<N: AsRef<str>, D: AsRef<[u8]>>(
+        self,
+        name: &str,
+        files: impl IntoIterator<Item = (N, D)>,
+    ) -> Self {
+        self.with_pack(&format!("Actor/Pack/{name}.sbactorpack"), files)
+    }
+
+    /// Adds `Pack/Bootup.pack` holding `files`.
+    pub fn with_bootup_pack<N: AsRef<str>, D: AsRef<[u8]>>(
+        self,
+        files: impl IntoIterator<Item = (N, D)>,
+    ) -> Self {
+        self.with_pack("Pack/Bootup.pack", files)
+    }
+
+    /// Adds the Bootup pack for a language, which also makes the language
+    /// available to [`ResourceReader::languages`](crate::ResourceReader::languages).
+    pub fn with_language_pack<N: AsRef<str>, D: AsRef<[u8]>>(
+        self,
+        lang: Language,
+        files: impl IntoIterator<Item = (N, D)>,
+    ) -> Self {
+        self.with_pack(lang.bootup_path().as_str(), files)
+    }
+
+    fn not_found(&self, name: &Path) -> ROMError {
+        ROMError::FileNotFound(name.to_string_lossy().into(), self.host_path.clone())
+    }
+}
+
+#[typetag::serde]
+impl super::ResourceLoader for MemoryROMSource {
+    fn get_data(&self, name: &Path) -> Result<Vec<u8>> {
+        self.files
+            .get(&canonicalize(name))
+            .or_else(|| self.files.get(&canonicalize_aoc(name)))
+            .cloned()
+            .ok_or_else(|| self.not_found(name))
+    }
+
+    fn get_aoc_file_data(&self, name: &Path) -> Result<Vec<u8>> {
+        if !self.has_aoc() {
+            return Err(ROMError::MissingDumpDir("DLC", self.host_path.clone()));
+        }
+        self.files
+            .get(&canonicalize_aoc(name))
+            .cloned()
+            .ok_or_else(|| self.not_found(name))
+    }
+
+    fn file_exists(&self, name: &Path) -> bool {
+        self.files.contains_key(&canonicalize(name))
+            || self.files.contains_key(&canonicalize_aoc(name))
+    }
+
+    fn list_files(&self, prefix: Option<&Path>) -> Result<Vec<String>> {
+        Ok(crate::listing(self.files.keys().cloned(), prefix))
+    }
+
+    fn has_aoc(&self) -> bool {
+        self.files.keys().any(|f| f.starts_with("Aoc/"))
+    }
+
+    fn host_path(&self) -> &Path {
+        &self.host_path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::sarc::Sarc;
+    use uk_content::resource::ResourceData;
+
+    use super::*;
+    use crate::{ResourceLoader, ResourceReader};
+
+    fn source() -> MemoryROMSource {
+        MemoryROMSource::new(Endian::Big)
+            .with_file("content/System/Version.txt", "1.5.0")
+            .with_aoc_file("Pack/AocMainField.pack", b"aoc".to_vec())
+            .with_actor_pack("Test", [("Actor/Physics/Test.txt", b"physics")])
+            .with_bootup_pack([("Actor/Test.txt", b"bootup")])
+            .with_language_pack(Language::USen, [("Message/Msg_USen.product.txt", b"msg")])
+    }
+
+    #[test]
+    fn lookups() {
+        let source = source();
+        for path in ["System/Version.txt", "content/System/Version.txt"] {
+            assert_eq!(source.get_data(path.as_ref()).unwrap(), b"1.5.0");
+        }
+        assert!(source.file_exists("Actor/Pack/Test.bactorpack".as_ref()));
+        assert!(source.file_exists("Actor/Pack/Test.sbactorpack".as_ref()));
+        let pack = source
+            .get_data("Actor/Pack/Test.sbactorpack".as_ref())
+            .unwrap();
+        assert_eq!(&pack[..4], b"Yaz0");
+        let bootup = source.get_data("Pack/Bootup.pack".as_ref()).unwrap();
+        let bootup = Sarc::new(bootup).unwrap();
+        assert_eq!(bootup.get_data("Actor/Test.txt").unwrap(), b"bootup");
+        for path in ["Pack/AocMainField.pack", "aoc/0010/Pack/AocMainField.pack"] {
+            assert_eq!(source.get_aoc_file_data(path.as_ref()).unwrap(), b"aoc");
+        }
+        assert_eq!(
+            source
+                .get_data("Aoc/0010/Pack/AocMainField.pack".as_ref())
+                .unwrap(),
+            b"aoc"
+        );
+        assert!(matches!(
+            source.get_aoc_file_data("System/Version.txt".as_ref()),
+            Err(ROMError::FileNotFound(..))
+        ));
+        assert!(matches!(
+            MemoryROMSource::new(Endian::Big).get_aoc_file_data("Pack/AocMainField.pack".as_ref()),
+            Err(ROMError::MissingDumpDir("DLC", _))
+        ));
+        assert!(!source.file_exists("Pack/TitleBG.pack".as_ref()));
+    }
+
+    #[test]
+    fn reader() {
+        let reader = ResourceReader::from_memory(source());
+        assert!(matches!(
+            reader
+                .get_data("Actor/Pack/Test.sbactorpack")
+                .unwrap()
+                .as_ref(),
+            ResourceData::Sarc(_)
+        ));
+        // Members of a pack are cached once the pack is read
+        assert!(matches!(
+            reader.get_resource("Actor/Physics/Test.txt").unwrap().as_ref(),
+            ResourceData::Binary(data) if data == b"physics"
+        ));
+        assert_eq!(reader.languages().as_slice(), [Language::USen]);
+        assert!(
+            ResourceReader::from_memory(MemoryROMSource::new(Endian::Big))
+                .languages()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn other_platform() {
+        let reader = ResourceReader::from_memory(
+            MemoryROMSource::new(Endian::Little)
+                .with_file("Model/Link.Tex.sbfres", b"nx tex".to_vec()),
+        );
+        // Looked up under the Switch name when given the Wii U one
+        assert!(matches!(
+            reader.get_data("Model/Link.Tex1.sbfres").unwrap().as_ref(),
+            ResourceData::Binary(data) if data == b"nx tex"
+        ));
+        assert!(reader.get_data("Model/Link.Tex2.sbfres").is_err());
+    }
+
+    #[test]
+    fn language_fallback() {
+        let reader = ResourceReader::from_memory(
+            MemoryROMSource::new(Endian::Big)
+                .with_language_pack(Language::USen, [("Message/Msg_USen.product.txt", b"us")])
+                .with_language_pack(Language::EUfr, [("Message/Msg_EUfr.product.txt", b"fr")]),
+        );
+        let pack = |lang: Language| reader.get_data(lang.bootup_path().as_str()).unwrap();
+        // The same language for another region first, then English
+        assert_eq!(pack(Language::EUen), pack(Language::USen));
+        assert_eq!(pack(Language::USfr), pack(Language::EUfr));
+        assert_eq!(pack(Language::JPja), pack(Language::USen));
+        // Cached under the name asked for
+        assert!(reader.get_resource("Pack/Bootup_EUen.pack").is_ok());
+
+        let reader = ResourceReader::from_memory(MemoryROMSource::new(Endian::Big));
+        assert!(reader.get_data("Pack/Bootup_USen.pack").is_err());
+    }
+
+    #[test]
+    fn cache_stats() {
+        let source = (0..4).fold(MemoryROMSource::new(Endian::Big), |source, i| {
+            source.with_file(format!("Model/Big{i}.sbfres"), vec![0; 1000])
+        });
+        let reader = ResourceReader::from_memory(source).with_cache_capacity(2500);
+        reader.get_data("Model/Big0.sbfres").unwrap();
+        reader.get_data("Model/Big0.sbfres").unwrap();
+        for i in 1..4 {
+            reader.get_data(format!("Model/Big{i}.sbfres")).unwrap();
+        }
+        let stats = reader.cache_stats();
+        assert_eq!((stats.hits, stats.misses), (1, 4));
+        assert_eq!(stats.hit_rate(), 0.2);
+        assert_eq!(stats.capacity, 2500);
+        // Weighed by size, so no more than two of the files fit
+        assert!(stats.size <= 2500);
+        assert!(stats.entries <= 2);
+
+        reader.clear_cache();
+        assert_eq!(reader.cache_stats().entries, 0);
+    }
+
+    #[test]
+    fn preload() {
+        let source = (0..8).fold(MemoryROMSource::new(Endian::Big), |source, i| {
+            source.with_file(format!("Model/Big{i}.sbfres"), vec![0; 1000])
+        });
+        let reader = ResourceReader::from_memory(source);
+        let mut names: Vec<String> = (0..8)
+            .map(|i| format!("Model/Big{i}.sbfres").into())
+            .collect();
+        names.push("Model/Missing.sbfres".into());
+        assert_eq!(reader.preload(&names), 8);
+        let misses = reader.cache_stats().misses;
+        for name in &names[..8] {
+            reader.get_data(name.as_str()).unwrap();
+        }
+        let stats = reader.cache_stats();
+        assert_eq!((stats.hits, stats.misses), (8, misses));
+        // Nothing is loaded twice
+        assert_eq!(reader.preload(&names), 0);
+    }
+
+    #[test]
+    fn serde() {
+        let reader = ResourceReader::from_memory(source()).with_cache_capacity(1 << 20);
+        let json = serde_json::to_string(&reader).unwrap();
+        let de: ResourceReader = serde_json::from_str(&json).unwrap();
+        assert_eq!(de, reader);
+        assert_eq!(de.cache_capacity(), 1 << 20);
+        assert_eq!(de.source_ser(), reader.source_ser());
+        assert_eq!(
+            de.get_bytes_uncached("System/Version.txt").unwrap(),
+            b"1.5.0"
+        );
+    }
+}
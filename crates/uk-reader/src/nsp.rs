@@ -0,0 +1,404 @@
+//! Switch dumps kept as NSP packages, read without extracting them. The base
+//! game and update packages are both needed, since the update only holds the
+//! data it changes. Decrypting them takes the console keys, from the
+//! `prod.keys` file dumped with Lockpick, and the title keys, from the tickets
+//! in the packages or a `title.keys` file beside `prod.keys`.
+mod nca;
+mod romfs;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use mmap_rs::Mmap;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+use uk_content::{canon::canonicalize_aoc, canonicalize};
+
+use self::{
+    nca::{Nca, NcaFile, Patched, DATA, PROGRAM, PUBLIC_DATA},
+    romfs::RomFs,
+};
+use crate::{ROMError, Result};
+
+/// Something sections and file systems can be read from.
+trait Storage: Send + Sync {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<()>;
+}
+
+fn field<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ROMError::OtherMessage("NSP data ended early"))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    field(data, offset).map(u32::from_le_bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<u64> {
+    field(data, offset).map(u64::from_le_bytes)
+}
+
+fn hex(data: &[u8]) -> std::string::String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let pairs = text.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Reads `name = hex` lines, as used by both key files.
+fn parse_keys(text: &str) -> impl Iterator<Item = (std::string::String, Vec<u8>)> + '_ {
+    text.lines().filter_map(|line| {
+        let (name, value) = line.split_once('=')?;
+        Some((name.trim().to_lowercase(), parse_hex(value.trim())?))
+    })
+}
+
+/// The console keys, and any title keys kept beside them.
+struct Keys {
+    path: PathBuf,
+    keys: HashMap<std::string::String, Vec<u8>>,
+    title_keys: HashMap<[u8; 16], [u8; 16]>,
+}
+
+impl Keys {
+    fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|_| ROMError::MissingKeys("the keys file".into(), path.to_path_buf()))?;
+        let keys: HashMap<_, _> = parse_keys(&text).collect();
+        if keys.is_empty() {
+            return Err(ROMError::MissingKeys(
+                "any keys in the keys file".into(),
+                path.to_path_buf(),
+            ));
+        }
+        let title_keys = path
+            .parent()
+            .and_then(|dir| std::fs::read_to_string(dir.join("title.keys")).ok())
+            .map(|text| {
+                parse_keys(&text)
+                    .filter_map(|(rights_id, key)| {
+                        Some((
+                            parse_hex(&rights_id)?.try_into().ok()?,
+                            key.try_into().ok()?,
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            path: path.to_path_buf(),
+            keys,
+            title_keys,
+        })
+    }
+
+    fn key<const N: usize>(&self, name: &str) -> Result<[u8; N]> {
+        self.keys
+            .get(name)
+            .and_then(|key| key.as_slice().try_into().ok())
+            .ok_or_else(|| ROMError::MissingKeys(name.into(), self.path.clone()))
+    }
+}
+
+/// A mapped NSP, which is a PFS0 archive of NCAs and tickets.
+struct Package {
+    path:    PathBuf,
+    map:     Arc<Mmap>,
+    entries: Vec<(std::string::String, u64, u64)>,
+    tickets: HashMap<[u8; 16], [u8; 16]>,
+}
+
+impl Package {
+    fn open(path: &Path) -> Result<Self> {
+        log::info!("Opening NSP at {}", path.display());
+        let file = std::fs::File::open(path)?;
+        // SAFETY: The mapping is only read from. Dump packages are not
+        // expected to change while we run.
+        let map = Arc::new(unsafe { crate::map_whole(&file)? });
+        if map.get(..4) != Some(b"PFS0".as_slice()) {
+            return Err(ROMError::OtherMessage("Not an NSP file"));
+        }
+        let count = u32_at(&map, 4)? as usize;
+        let strings = 0x10 + count * 0x18;
+        let data = strings + u32_at(&map, 8)? as usize;
+        let entries = (0..count)
+            .map(|i| {
+                let entry = 0x10 + i * 0x18;
+                let name = map
+                    .get(strings + u32_at(&map, entry + 0x10)? as usize..data)
+                    .and_then(|name| name.split(|b| *b == 0).next())
+                    .ok_or(ROMError::OtherMessage("Invalid file name in NSP"))?;
+                Ok((
+                    std::string::String::from_utf8_lossy(name).into_owned(),
+                    data as u64 + u64_at(&map, entry)?,
+                    u64_at(&map, entry + 8)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Common tickets hold the title key in the clear, right after the
+        // signature
+        let tickets = entries
+            .iter()
+            .filter(|(name, ..)| name.ends_with(".tik"))
+            .filter_map(|(_, offset, _)| {
+                let ticket = map.get(*offset as usize..)?;
+                Some((field(ticket, 0x2A0).ok()?, field(ticket, 0x180).ok()?))
+            })
+            .collect();
+        Ok(Self {
+            path: path.to_path_buf(),
+            map,
+            entries,
+            tickets,
+        })
+    }
+
+    /// Opens the RomFS section of the largest NCA with one of the given
+    /// content types.
+    fn romfs(&self, keys: &Keys, content_types: &[u8]) -> Result<nca::Section> {
+        let mut ncas = vec![];
+        for (name, offset, size) in &self.entries {
+            if name.ends_with(".ncz") {
+                return Err(ROMError::OtherMessage(
+                    "Compressed NSPs are not supported. Decompress it to a plain NSP first.",
+                ));
+            } else if name.ends_with(".nca") {
+                let nca = Nca::open(
+                    NcaFile {
+                        map:    self.map.clone(),
+                        offset: *offset,
+                        size:   *size,
+                    },
+                    keys,
+                )?;
+                if !content_types.contains(&nca.content_type()) {
+                    continue;
+                }
+                if let Some(index) = nca.romfs_index() {
+                    ncas.push((nca, index));
+                }
+            }
+        }
+        let (nca, index) = ncas
+            .into_iter()
+            .max_by_key(|(nca, _)| nca.size())
+            .ok_or_else(|| ROMError::MissingDumpDir("RomFS", self.path.clone()))?;
+        nca.section(index, keys, &self.tickets)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NspPaths {
+    base:   PathBuf,
+    update: PathBuf,
+    dlc:    Option<PathBuf>,
+    keys:   PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "NspPaths")]
+pub(crate) struct Nsp {
+    base:   PathBuf,
+    update: PathBuf,
+    dlc:    Option<PathBuf>,
+    keys:   PathBuf,
+    #[serde(skip_serializing)]
+    game:   RomFs,
+    #[serde(skip_serializing)]
+    aoc:    Option<RomFs>,
+}
+
+impl TryFrom<NspPaths> for Nsp {
+    type Error = ROMError;
+
+    fn try_from(paths: NspPaths) -> Result<Self> {
+        Self::new(paths.base, paths.update, paths.dlc, paths.keys)
+    }
+}
+
+impl Nsp {
+    pub(crate) fn new(
+        base: impl AsRef<Path>,
+        update: impl AsRef<Path>,
+        dlc: Option<impl AsRef<Path>>,
+        keys: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let keys_path = keys.as_ref();
+        let keys = Keys::load(keys_path)?;
+        let base_section = Package::open(base.as_ref())?.romfs(&keys, &[PROGRAM])?;
+        let patch = Package::open(update.as_ref())?.romfs(&keys, &[PROGRAM])?;
+        let offset = patch.romfs_offset()?;
+        let game = RomFs::open(Box::new(Patched::new(base_section, patch)?), offset)?;
+        let aoc = dlc
+            .as_ref()
+            .map(|dlc| -> Result<RomFs> {
+                let section = Package::open(dlc.as_ref())?.romfs(&keys, &[DATA, PUBLIC_DATA])?;
+                let offset = section.romfs_offset()?;
+                RomFs::open(Box::new(section), offset)
+            })
+            .transpose()?;
+        Ok(Self {
+            base: base.as_ref().to_path_buf(),
+            update: update.as_ref().to_path_buf(),
+            dlc: dlc.map(|dlc| dlc.as_ref().to_path_buf()),
+            keys: keys_path.to_path_buf(),
+            game,
+            aoc,
+        })
+    }
+
+    fn not_found(&self, name: &str) -> ROMError {
+        ROMError::FileNotFound(name.into(), self.base.clone())
+    }
+}
+
+/// RomFS paths always use forward slashes.
+fn romfs_path(name: &Path) -> std::string::String {
+    name.to_string_lossy().replace('\\', "/")
+}
+
+#[typetag::serde]
+impl super::ResourceLoader for Nsp {
+    fn get_data(&self, name: &Path) -> Result<Vec<u8>> {
+        let name = romfs_path(name);
+        self.game
+            .read(&name)
+            .or_else(|| self.aoc.as_ref().and_then(|aoc| aoc.read(&name)))
+            .unwrap_or_else(|| Err(self.not_found(&name)))
+    }
+
+    fn get_aoc_file_data(&self, name: &Path) -> Result<Vec<u8>> {
+        let name = romfs_path(name);
+        let aoc = self
+            .aoc
+            .as_ref()
+            .ok_or_else(|| ROMError::MissingDumpDir("DLC", self.base.clone()))?;
+        aoc.read(&name)
+            .unwrap_or_else(|| Err(self.not_found(&name)))
+    }
+
+    fn file_exists(&self, name: &Path) -> bool {
+        let name = romfs_path(name);
+        self.game.contains(&name) || self.aoc.as_ref().is_some_and(|aoc| aoc.contains(&name))
+    }
+
+    fn list_files(&self, prefix: Option<&Path>) -> Result<Vec<String>> {
+        let game = self.game.names().map(canonicalize);
+        let aoc = self
+            .aoc
+            .iter()
+            .flat_map(|aoc| aoc.names().map(canonicalize_aoc));
+        Ok(crate::listing(game.chain(aoc), prefix))
+    }
+
+    fn has_aoc(&self) -> bool {
+        self.aoc.is_some()
+    }
+
+    fn host_path(&self) -> &Path {
+        &self.base
+    }
+
+    fn roots(&self) -> Vec<&Path> {
+        [Some(&self.base), Some(&self.update), self.dlc.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|path| path.as_path())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("prod.keys");
+        assert!(matches!(
+            Keys::load(&path),
+            Err(ROMError::MissingKeys(_, p)) if p == path
+        ));
+        std::fs::write(
+            &path,
+            "header_key = \
+             000102030405060708090a0b0c0d0e0f000102030405060708090A0B0C0D0E0F\ntitlekek_00 = \
+             ffffffffffffffffffffffffffffffff\nbad_key = xyz\n",
+        )
+        .unwrap();
+        std::fs::write(
+            tmp.path().join("title.keys"),
+            "01007ef00011e0000000000000000000 = 0123456789abcdef0123456789abcdef\n",
+        )
+        .unwrap();
+        let keys = Keys::load(&path).unwrap();
+        assert_eq!(keys.key::<32>("header_key").unwrap()[10], 10);
+        assert_eq!(keys.key::<16>("titlekek_00").unwrap(), [0xff; 16]);
+        assert!(matches!(
+            keys.key::<16>("titlekek_01"),
+            Err(ROMError::MissingKeys(name, _)) if name.as_str() == "titlekek_01"
+        ));
+        // A key of the wrong length is as good as missing
+        assert!(keys.key::<16>("header_key").is_err());
+        assert!(keys.key::<16>("bad_key").is_err());
+        let rights_id: [u8; 16] = parse_hex("01007ef00011e0000000000000000000")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(keys.title_keys[&rights_id][1], 0x23);
+        assert!(matches!(
+            Nsp::new(
+                "base.nsp",
+                "update.nsp",
+                None::<&Path>,
+                tmp.path().join("none.keys")
+            ),
+            Err(ROMError::MissingKeys(..))
+        ));
+    }
+
+    #[test]
+    fn package() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("test.nsp");
+        let names = b"a.nca\0b.tik\0";
+        let mut ticket = vec![0u8; 0x2C0];
+        ticket[0x180..0x190].copy_from_slice(&[2; 16]);
+        ticket[0x2A0..0x2B0].copy_from_slice(&[1; 16]);
+        let mut data = b"PFS0".to_vec();
+        data.extend(2u32.to_le_bytes());
+        data.extend((names.len() as u32).to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        for (offset, size, name) in [(0u64, 4u64, 0u32), (4, ticket.len() as u64, 6)] {
+            data.extend(offset.to_le_bytes());
+            data.extend(size.to_le_bytes());
+            data.extend(name.to_le_bytes());
+            data.extend(0u32.to_le_bytes());
+        }
+        data.extend(names);
+        data.extend(b"NCA!");
+        data.extend(&ticket);
+        std::fs::write(&path, &data).unwrap();
+        let package = Package::open(&path).unwrap();
+        assert_eq!(package.entries.len(), 2);
+        let (name, offset, size) = &package.entries[0];
+        assert_eq!(name, "a.nca");
+        assert_eq!(&data[*offset as usize..(*offset + *size) as usize], b"NCA!");
+        assert_eq!(package.entries[1].0, "b.tik");
+        assert_eq!(package.tickets[&[1; 16]], [2; 16]);
+        std::fs::write(&path, b"NOPE").unwrap();
+        assert!(Package::open(&path).is_err());
+    }
+}
@@ -0,0 +1,435 @@
+use std::{collections::HashMap, sync::Arc};
+
+use aes::{
+    cipher::{BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128, Block,
+};
+use anyhow_ext::Context;
+use mmap_rs::Mmap;
+
+use super::{field, u32_at, u64_at, Keys, Storage};
+use crate::{ROMError, Result};
+
+/// The size of the encrypted NCA header, including the section headers.
+const HEADER_SIZE: usize = 0xC00;
+const SECTION_HEADERS: usize = 0x400;
+const SECTION_HEADER_SIZE: usize = 0x200;
+/// Section hash type used by RomFS sections.
+const HASH_IVFC: u8 = 3;
+const CRYPT_NONE: u8 = 1;
+const CRYPT_CTR: u8 = 3;
+const CRYPT_BKTR: u8 = 4;
+/// The size of each bucket in a patch table.
+const BUCKET_SIZE: usize = 0x4000;
+
+pub(super) const PROGRAM: u8 = 0;
+pub(super) const DATA: u8 = 4;
+pub(super) const PUBLIC_DATA: u8 = 5;
+
+/// Decrypts `data` in place with Nintendo's variant of AES-XTS, which takes
+/// sector numbers as big endian tweaks.
+pub(super) fn xts_decrypt(key: &[u8; 32], data: &mut [u8], first_sector: u128) {
+    let (data_key, tweak_key) = key.split_at(16);
+    let data_key = Aes128::new(Block::from_slice(data_key));
+    let tweak_key = Aes128::new(Block::from_slice(tweak_key));
+    for (i, sector) in data.chunks_mut(0x200).enumerate() {
+        let mut tweak = Block::from((first_sector + i as u128).to_be_bytes());
+        tweak_key.encrypt_block(&mut tweak);
+        for block in sector.chunks_exact_mut(16) {
+            block
+                .iter_mut()
+                .zip(tweak.iter())
+                .for_each(|(b, t)| *b ^= t);
+            data_key.decrypt_block(Block::from_mut_slice(block));
+            block
+                .iter_mut()
+                .zip(tweak.iter())
+                .for_each(|(b, t)| *b ^= t);
+            // Multiply the tweak by x in GF(2^128)
+            let carry = tweak[15] >> 7;
+            for j in (1..16).rev() {
+                tweak[j] = (tweak[j] << 1) | (tweak[j - 1] >> 7);
+            }
+            tweak[0] = (tweak[0] << 1) ^ (carry * 0x87);
+        }
+    }
+}
+
+/// Encrypts or decrypts `data`, which starts `offset` bytes into the NCA,
+/// with AES-CTR. The upper half of the counter comes from the section and
+/// the lower half is the block number.
+pub(super) fn ctr_apply(cipher: &Aes128, upper: [u8; 8], offset: u64, data: &mut [u8]) {
+    let skip = (offset % 16) as usize;
+    let first = offset / 16;
+    let mut stream: Vec<Block> = (0..(skip + data.len()).div_ceil(16) as u64)
+        .map(|i| {
+            let mut counter = [0u8; 16];
+            counter[..8].copy_from_slice(&upper);
+            counter[8..].copy_from_slice(&(first + i).to_be_bytes());
+            Block::from(counter)
+        })
+        .collect();
+    cipher.encrypt_blocks(&mut stream);
+    data.iter_mut()
+        .zip(stream.iter().flatten().skip(skip))
+        .for_each(|(b, k)| *b ^= k);
+}
+
+fn ecb_decrypt(key: [u8; 16], data: [u8; 16]) -> [u8; 16] {
+    let mut block = Block::from(data);
+    Aes128::new(&key.into()).decrypt_block(&mut block);
+    let mut out = [0u8; 16];
+    out.copy_from_slice(&block);
+    out
+}
+
+/// An NCA inside a mapped NSP.
+#[derive(Clone)]
+pub(super) struct NcaFile {
+    pub(super) map:    Arc<Mmap>,
+    pub(super) offset: u64,
+    pub(super) size:   u64,
+}
+
+impl NcaFile {
+    fn raw(&self, offset: u64, len: usize) -> Result<&[u8]> {
+        (offset + len as u64 <= self.size)
+            .then(|| {
+                let start = (self.offset + offset) as usize;
+                self.map.get(start..start + len)
+            })
+            .flatten()
+            .ok_or(ROMError::OtherMessage("Read past the end of an NCA in NSP"))
+    }
+}
+
+pub(super) struct Nca {
+    file:   NcaFile,
+    header: Vec<u8>,
+}
+
+impl Nca {
+    pub(super) fn open(file: NcaFile, keys: &Keys) -> Result<Self> {
+        let mut header = file.raw(0, HEADER_SIZE)?.to_vec();
+        xts_decrypt(&keys.key("header_key")?, &mut header, 0);
+        if &header[0x200..0x204] != b"NCA3" {
+            return Err(ROMError::OtherMessage(
+                "Unsupported or undecryptable NCA in NSP. Check that the header key is right.",
+            ));
+        }
+        Ok(Self { file, header })
+    }
+
+    pub(super) fn size(&self) -> u64 {
+        self.file.size
+    }
+
+    pub(super) fn content_type(&self) -> u8 {
+        self.header[0x205]
+    }
+
+    fn section_header(&self, index: usize) -> &[u8] {
+        let start = SECTION_HEADERS + index * SECTION_HEADER_SIZE;
+        &self.header[start..start + SECTION_HEADER_SIZE]
+    }
+
+    /// The index of the RomFS section, if there is one.
+    pub(super) fn romfs_index(&self) -> Option<usize> {
+        (0..4).find(|&i| {
+            u32_at(&self.header, 0x240 + i * 0x10 + 4).is_ok_and(|end| end != 0)
+                && self.section_header(i)[3] == HASH_IVFC
+        })
+    }
+
+    /// The key used to decrypt the sections, from the title key if the NCA
+    /// has a rights ID, or else from its key area.
+    fn content_key(&self, keys: &Keys, tickets: &HashMap<[u8; 16], [u8; 16]>) -> Result<Aes128> {
+        let generation = self.header[0x206].max(self.header[0x220]).saturating_sub(1);
+        let rights_id: [u8; 16] = field(&self.header, 0x230)?;
+        let key = if rights_id != [0; 16] {
+            let title_key = tickets
+                .get(&rights_id)
+                .or_else(|| keys.title_keys.get(&rights_id))
+                .ok_or_else(|| {
+                    ROMError::MissingKeys(
+                        format!("title key for rights ID {}", super::hex(&rights_id)).into(),
+                        keys.path.clone(),
+                    )
+                })?;
+            ecb_decrypt(keys.key(&format!("titlekek_{generation:02x}"))?, *title_key)
+        } else {
+            let area = match self.header[0x207] {
+                0 => "application",
+                1 => "ocean",
+                _ => "system",
+            };
+            ecb_decrypt(
+                keys.key(&format!("key_area_key_{area}_{generation:02x}"))?,
+                field(&self.header, 0x320)?,
+            )
+        };
+        Ok(Aes128::new(&key.into()))
+    }
+
+    pub(super) fn section(
+        &self,
+        index: usize,
+        keys: &Keys,
+        tickets: &HashMap<[u8; 16], [u8; 16]>,
+    ) -> Result<Section> {
+        let entry = 0x240 + index * 0x10;
+        let header: [u8; SECTION_HEADER_SIZE] = field(self.section_header(index), 0)?;
+        let mut upper: [u8; 8] = field(&header, 0x140)?;
+        upper.reverse();
+        Ok(Section {
+            file: self.file.clone(),
+            start: u32_at(&self.header, entry)? as u64 * 0x200,
+            end: u32_at(&self.header, entry + 4)? as u64 * 0x200,
+            cipher: match header[4] {
+                CRYPT_NONE => None,
+                _ => Some(self.content_key(keys, tickets)?),
+            },
+            upper,
+            header,
+        })
+    }
+}
+
+/// A section of an NCA, read and decrypted from the NSP as needed.
+pub(super) struct Section {
+    file:   NcaFile,
+    start:  u64,
+    end:    u64,
+    cipher: Option<Aes128>,
+    upper:  [u8; 8],
+    header: [u8; SECTION_HEADER_SIZE],
+}
+
+impl Section {
+    fn crypt_type(&self) -> u8 {
+        self.header[4]
+    }
+
+    /// The offset of the RomFS in the section, from the last level of its
+    /// hash tree.
+    pub(super) fn romfs_offset(&self) -> Result<u64> {
+        u64_at(&self.header, 0x18 + 5 * 0x18)
+    }
+
+    fn read_ctr(&self, upper: [u8; 8], offset: u64, buf: &mut [u8]) -> Result<()> {
+        if offset + buf.len() as u64 > self.end.saturating_sub(self.start) {
+            return Err(ROMError::OtherMessage(
+                "Read past the end of an NSP section",
+            ));
+        }
+        buf.copy_from_slice(self.file.raw(self.start + offset, buf.len())?);
+        if let Some(cipher) = self.cipher.as_ref() {
+            ctr_apply(cipher, upper, self.start + offset, buf);
+        }
+        Ok(())
+    }
+}
+
+impl Storage for Section {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        match self.crypt_type() {
+            CRYPT_NONE | CRYPT_CTR => self.read_ctr(self.upper, offset, buf),
+            CRYPT_BKTR => {
+                Err(ROMError::OtherMessage(
+                    "The update NSP can only be read together with the base game",
+                ))
+            }
+            _ => Err(ROMError::OtherMessage("Unsupported NSP section encryption")),
+        }
+    }
+}
+
+struct Relocation {
+    virtual_offset: u64,
+    physical_offset: u64,
+    patched: bool,
+}
+
+struct Subsection {
+    offset:     u64,
+    generation: u32,
+}
+
+/// Reads the entries of a patch table, which are split into buckets after a
+/// header bucket.
+fn bucket_entries<T>(
+    table: &[u8],
+    size: usize,
+    parse: impl Fn(&[u8]) -> Result<T>,
+) -> Result<Vec<T>> {
+    let mut entries = vec![];
+    for bucket in 1..=u32_at(table, 4)? as usize {
+        let start = bucket * BUCKET_SIZE;
+        for i in 0..u32_at(table, start + 4)? as usize {
+            let entry = start + 0x10 + i * size;
+            entries.push(parse(table.get(entry..entry + size).ok_or(
+                ROMError::OtherMessage("Patch table in update NSP ended early"),
+            )?)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// The RomFS section of an update, which keeps only the data changed from
+/// the base game and maps the rest back to it.
+pub(super) struct Patched {
+    base: Section,
+    patch: Section,
+    relocations: Vec<Relocation>,
+    /// The size of the patched section.
+    size: u64,
+    subsections: Vec<Subsection>,
+    /// Where the patch data ends and the tables begin.
+    data_end: u64,
+}
+
+impl Patched {
+    pub(super) fn new(base: Section, patch: Section) -> Result<Self> {
+        if patch.crypt_type() != CRYPT_BKTR {
+            return Err(ROMError::OtherMessage(
+                "The update NSP does not contain a patch for the base game",
+            ));
+        }
+        let table = |at: usize| -> Result<Vec<u8>> {
+            let mut data = vec![0u8; u64_at(&patch.header, at + 8)? as usize];
+            patch.read_ctr(patch.upper, u64_at(&patch.header, at)?, &mut data)?;
+            Ok(data)
+        };
+        let relocation_table = table(0x100).context("Failed to read update NSP relocations")?;
+        let subsection_table = table(0x120).context("Failed to read update NSP subsections")?;
+        let relocations = bucket_entries(&relocation_table, 0x14, |entry| {
+            Ok(Relocation {
+                virtual_offset: u64_at(entry, 0)?,
+                physical_offset: u64_at(entry, 8)?,
+                patched: u32_at(entry, 0x10)? != 0,
+            })
+        })?;
+        let subsections = bucket_entries(&subsection_table, 0x10, |entry| {
+            Ok(Subsection {
+                offset:     u64_at(entry, 0)?,
+                generation: u32_at(entry, 0xC)?,
+            })
+        })?;
+        if relocations.is_empty() || subsections.is_empty() {
+            return Err(ROMError::OtherMessage(
+                "The update NSP has empty patch tables",
+            ));
+        }
+        Ok(Self {
+            size: u64_at(&relocation_table, 8)?,
+            data_end: u64_at(&patch.header, 0x100)?,
+            base,
+            patch,
+            relocations,
+            subsections,
+        })
+    }
+
+    /// Reads from the patch data, where each subsection has its own counter.
+    fn read_patch(&self, mut offset: u64, buf: &mut [u8]) -> Result<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let i = self
+                .subsections
+                .partition_point(|s| s.offset <= offset)
+                .saturating_sub(1);
+            let end = self
+                .subsections
+                .get(i + 1)
+                .map_or(self.data_end, |s| s.offset);
+            let len = end
+                .checked_sub(offset)
+                .filter(|len| *len > 0)
+                .ok_or(ROMError::OtherMessage(
+                    "Read past the patch data in update NSP",
+                ))?
+                .min((buf.len() - done) as u64) as usize;
+            let mut upper = self.patch.upper;
+            upper[4..].copy_from_slice(&self.subsections[i].generation.to_be_bytes());
+            self.patch
+                .read_ctr(upper, offset, &mut buf[done..done + len])?;
+            done += len;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for Patched {
+    fn read(&self, mut offset: u64, buf: &mut [u8]) -> Result<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let i = self
+                .relocations
+                .partition_point(|r| r.virtual_offset <= offset)
+                .saturating_sub(1);
+            let entry = &self.relocations[i];
+            let end = self
+                .relocations
+                .get(i + 1)
+                .map_or(self.size, |r| r.virtual_offset);
+            let len = end
+                .checked_sub(offset)
+                .filter(|len| *len > 0)
+                .ok_or(ROMError::OtherMessage(
+                    "Read past the end of update NSP RomFS",
+                ))?
+                .min((buf.len() - done) as u64) as usize;
+            let physical = entry.physical_offset + (offset - entry.virtual_offset);
+            let chunk = &mut buf[done..done + len];
+            if entry.patched {
+                self.read_patch(physical, chunk)?;
+            } else {
+                self.base.read(physical, chunk)?;
+            }
+            done += len;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctr_offsets() {
+        let cipher = Aes128::new(&[7u8; 16].into());
+        let upper = [1, 2, 3, 4, 5, 6, 7, 8];
+        let plain: Vec<u8> = (0..200u8).collect();
+        let mut whole = plain.clone();
+        ctr_apply(&cipher, upper, 0x1000, &mut whole);
+        assert_ne!(whole, plain);
+        // Decrypting in unaligned pieces gives the same result as in one go
+        let mut pieces = whole.clone();
+        for (start, end) in [(0, 5), (5, 37), (37, 200)] {
+            ctr_apply(
+                &cipher,
+                upper,
+                0x1000 + start as u64,
+                &mut pieces[start..end],
+            );
+        }
+        assert_eq!(pieces, plain);
+    }
+
+    #[test]
+    fn xts_sectors() {
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let data: Vec<u8> = (0..0x400).map(|i| (i * 7) as u8).collect();
+        let mut whole = data.clone();
+        xts_decrypt(&key, &mut whole, 0);
+        assert_ne!(whole, data);
+        // The second sector uses its own tweak
+        let mut second = data[0x200..].to_vec();
+        xts_decrypt(&key, &mut second, 1);
+        assert_eq!(second, whole[0x200..]);
+        assert_ne!(whole[..0x200], whole[0x200..]);
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use super::{u32_at, u64_at, Storage};
+use crate::{ROMError, Result};
+
+/// Marks the end of a list of directory or file entries.
+const EMPTY: u32 = u32::MAX;
+
+/// The file system of a section, with the location of every file read up
+/// front so lookups need not walk the tables.
+pub(super) struct RomFs {
+    storage: Box<dyn Storage>,
+    data_offset: u64,
+    files: HashMap<String, (u64, u64)>,
+}
+
+impl std::fmt::Debug for RomFs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RomFs")
+            .field("files", &self.files.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RomFs {
+    /// Reads the file system starting at `offset` in `storage`.
+    pub(super) fn open(storage: Box<dyn Storage>, offset: u64) -> Result<Self> {
+        let mut header = [0u8; 0x50];
+        storage.read(offset, &mut header)?;
+        let table = |at: usize| -> Result<Vec<u8>> {
+            let mut data = vec![0u8; u64_at(&header, at + 8)? as usize];
+            storage.read(offset + u64_at(&header, at)?, &mut data)?;
+            Ok(data)
+        };
+        let dirs = table(0x18)?;
+        let files = table(0x38)?;
+        let mut walker = Walker {
+            dirs:   &dirs,
+            files:  &files,
+            // Every entry is visited once, so more steps than there are
+            // bytes in the tables means a loop
+            budget: dirs.len() + files.len(),
+            found:  HashMap::new(),
+        };
+        walker.walk(0, "")?;
+        log::debug!("Found {} files in NSP RomFS", walker.found.len());
+        Ok(Self {
+            data_offset: offset + u64_at(&header, 0x48)?,
+            files: walker.found,
+            storage,
+        })
+    }
+
+    pub(super) fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+
+    /// Paths of every file, in no particular order.
+    pub(super) fn names(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(|name| name.as_str())
+    }
+
+    pub(super) fn read(&self, name: &str) -> Option<Result<Vec<u8>>> {
+        let (offset, size) = *self.files.get(name)?;
+        let mut data = vec![0u8; size as usize];
+        Some(
+            self.storage
+                .read(self.data_offset + offset, &mut data)
+                .map(|_| data),
+        )
+    }
+}
+
+struct Walker<'a> {
+    dirs:   &'a [u8],
+    files:  &'a [u8],
+    budget: usize,
+    found:  HashMap<String, (u64, u64)>,
+}
+
+impl Walker<'_> {
+    fn step(&mut self) -> Result<()> {
+        self.budget = self.budget.checked_sub(1).ok_or(ROMError::OtherMessage(
+            "NSP RomFS tables loop back on themselves",
+        ))?;
+        Ok(())
+    }
+
+    fn walk(&mut self, dir: u32, prefix: &str) -> Result<()> {
+        let dir = dir as usize;
+        let mut file = u32_at(self.dirs, dir + 0xC)?;
+        while file != EMPTY {
+            self.step()?;
+            let entry = file as usize;
+            let name = name_at(self.files, entry + 0x1C)?;
+            self.found.insert(
+                join(prefix, name),
+                (
+                    u64_at(self.files, entry + 0x8)?,
+                    u64_at(self.files, entry + 0x10)?,
+                ),
+            );
+            file = u32_at(self.files, entry + 0x4)?;
+        }
+        let mut child = u32_at(self.dirs, dir + 0x8)?;
+        while child != EMPTY {
+            self.step()?;
+            let name = join(prefix, name_at(self.dirs, child as usize + 0x14)?);
+            self.walk(child, &name)?;
+            child = u32_at(self.dirs, child as usize + 0x4)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads an entry name, which follows its length.
+fn name_at(table: &[u8], offset: usize) -> Result<&str> {
+    let len = u32_at(table, offset)? as usize;
+    table
+        .get(offset + 4..offset + 4 + len)
+        .and_then(|name| std::str::from_utf8(name).ok())
+        .ok_or(ROMError::OtherMessage("Invalid file name in NSP RomFS"))
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Storage for Vec<u8> {
+        fn read(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+            let offset = offset as usize;
+            buf.copy_from_slice(
+                self.get(offset..offset + buf.len())
+                    .ok_or(ROMError::OtherMessage("Out of bounds"))?,
+            );
+            Ok(())
+        }
+    }
+
+    fn entry(table: &mut Vec<u8>, fields: &[u8], name: &str) -> u32 {
+        let offset = table.len() as u32;
+        table.extend_from_slice(fields);
+        table.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        table.extend_from_slice(name.as_bytes());
+        table.resize(table.len().next_multiple_of(4), 0);
+        offset
+    }
+
+    fn dir(table: &mut Vec<u8>, name: &str, sibling: u32, child: u32, file: u32) -> u32 {
+        let fields: Vec<u8> = [0, sibling, child, file, EMPTY]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        entry(table, &fields, name)
+    }
+
+    fn file(table: &mut Vec<u8>, name: &str, sibling: u32, offset: u64, size: u64) -> u32 {
+        let mut fields = vec![];
+        fields.extend(0u32.to_le_bytes());
+        fields.extend(sibling.to_le_bytes());
+        fields.extend(offset.to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend(EMPTY.to_le_bytes());
+        entry(table, &fields, name)
+    }
+
+    /// A RomFS holding `Pack/Bootup.pack`, `System/Version.txt`, and
+    /// `Actor/Pack/Test.sbactorpack`, each containing its own name.
+    fn image() -> Vec<u8> {
+        let names = [
+            "Pack/Bootup.pack",
+            "System/Version.txt",
+            "Actor/Pack/Test.sbactorpack",
+        ];
+        let data: Vec<u8> = names.concat().into_bytes();
+        let offsets: Vec<u64> = names
+            .iter()
+            .scan(0, |offset, name| {
+                let start = *offset;
+                *offset += name.len() as u64;
+                Some(start)
+            })
+            .collect();
+        let mut files = vec![];
+        let mut add = |i: usize| {
+            let (_, name) = names[i].rsplit_once('/').unwrap();
+            file(&mut files, name, EMPTY, offsets[i], names[i].len() as u64)
+        };
+        let (bootup, version, test) = (add(0), add(1), add(2));
+        // Children are written before the directories which refer to them,
+        // after space for the root
+        let mut dirs = vec![0u8; 0x18];
+        let actor_pack = dir(&mut dirs, "Pack", EMPTY, EMPTY, test);
+        let system = dir(&mut dirs, "System", EMPTY, EMPTY, version);
+        let pack = dir(&mut dirs, "Pack", system, EMPTY, bootup);
+        let actor = dir(&mut dirs, "Actor", pack, actor_pack, EMPTY);
+        dirs[..0x10].copy_from_slice(
+            &[0u32, EMPTY, actor, EMPTY]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        );
+        dirs[0x10..0x14].copy_from_slice(&EMPTY.to_le_bytes());
+        let dirs_at = 0x50u64;
+        let files_at = dirs_at + dirs.len() as u64;
+        let data_at = files_at + files.len() as u64;
+        let mut image: Vec<u8> = [
+            0x50,
+            0,
+            0,
+            dirs_at,
+            dirs.len() as u64,
+            0,
+            0,
+            files_at,
+            files.len() as u64,
+            data_at,
+        ]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+        image.extend(dirs);
+        image.extend(files);
+        image.extend(data);
+        image
+    }
+
+    #[test]
+    fn read_files() {
+        // Placed after some padding, as it is in a section
+        let mut storage = vec![0xFF; 0x100];
+        storage.extend(image());
+        let romfs = RomFs::open(Box::new(storage), 0x100).unwrap();
+        assert_eq!(romfs.files.len(), 3);
+        for name in [
+            "Pack/Bootup.pack",
+            "System/Version.txt",
+            "Actor/Pack/Test.sbactorpack",
+        ] {
+            assert!(romfs.contains(name));
+            assert_eq!(romfs.read(name).unwrap().unwrap(), name.as_bytes());
+        }
+        assert!(romfs.read("Pack/Test.sbactorpack").is_none());
+        let mut names: Vec<&str> = romfs.names().collect();
+        names.sort();
+        assert_eq!(names, [
+            "Actor/Pack/Test.sbactorpack",
+            "Pack/Bootup.pack",
+            "System/Version.txt",
+        ]);
+    }
+
+    #[test]
+    fn looping_tables() {
+        let mut image = image();
+        // Make the first file its own sibling
+        let files_at = u64_at(&image, 0x38).unwrap() as usize;
+        image[files_at + 4..files_at + 8].copy_from_slice(&0u32.to_le_bytes());
+        assert!(RomFs::open(Box::new(image), 0).is_err());
+    }
+}
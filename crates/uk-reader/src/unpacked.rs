@@ -1,67 +1,187 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+use dashmap::DashMap;
 use fs_err as fs;
+use mmap_rs::Mmap;
 use serde::{Deserialize, Serialize};
+use smartstring::alias::String;
+use uk_content::{canon::canonicalize_aoc, canonicalize};
 
-use crate::{ROMError, Result};
+use crate::{FileData, ROMError, Result};
 
+/// Files at least this large are mapped rather than read, unless they are
+/// Yaz0 compressed.
+pub const DEFAULT_MMAP_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Unpacked dumps are preloaded with at most this many threads, so a
+/// spinning disk is not made to seek between every file at once.
+const PRELOAD_THREADS: usize = 4;
+
+/// Files which only a valid base game, update or DLC folder has.
+pub(crate) const CONTENT_TEST: &str = "Map/MainField/A-1/A-1.00_Clustering.sblwp";
+pub(crate) const UPDATE_TEST: &str = "Actor/Pack/Enemy_Lynel_Dark.sbactorpack";
+pub(crate) const AOC_TEST: &str = "Pack/AocMainField.pack";
+
+fn default_mmap_threshold() -> Option<u64> {
+    Some(DEFAULT_MMAP_THRESHOLD)
+}
+
+/// A dump unpacked to folders. Game files are looked for in each content
+/// folder in turn, so an update folder comes before the base game folder it
+/// updates, and DLC files in each DLC folder in turn.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "StoredUnpacked")]
 pub(crate) struct Unpacked {
-    host_path:   PathBuf,
+    host_path: PathBuf,
+    content_dirs: Vec<PathBuf>,
+    aoc_dirs: Vec<PathBuf>,
+    mmap_threshold: Option<u64>,
+    #[serde(skip)]
+    lookup: Lookup,
+}
+
+/// An unpacked dump as saved in settings, which from before dumps could
+/// have more than one folder of each kind has one content, update and DLC
+/// folder instead.
+#[derive(Deserialize)]
+struct StoredUnpacked {
+    host_path: PathBuf,
+    #[serde(default)]
+    content_dirs: Vec<PathBuf>,
+    #[serde(default)]
+    aoc_dirs: Vec<PathBuf>,
+    #[serde(default)]
     content_dir: Option<PathBuf>,
-    update_dir:  Option<PathBuf>,
-    aoc_dir:     Option<PathBuf>,
+    #[serde(default)]
+    update_dir: Option<PathBuf>,
+    #[serde(default)]
+    aoc_dir: Option<PathBuf>,
+    #[serde(default = "default_mmap_threshold")]
+    mmap_threshold: Option<u64>,
+}
+
+impl From<StoredUnpacked> for Unpacked {
+    fn from(stored: StoredUnpacked) -> Self {
+        Self {
+            host_path: stored.host_path,
+            content_dirs: stored
+                .content_dirs
+                .into_iter()
+                .chain(stored.update_dir)
+                .chain(stored.content_dir)
+                .collect(),
+            aoc_dirs: stored.aoc_dirs.into_iter().chain(stored.aoc_dir).collect(),
+            mmap_threshold: stored.mmap_threshold,
+            lookup: Lookup::default(),
+        }
+    }
+}
+
+/// Finds files by paths which use backslashes or differ in case from the
+/// files in the dump, as paths from mods and scripts often do. Directory
+/// listings and the files they lead to are remembered, as the dump is not
+/// expected to change while we run.
+#[derive(Debug, Default)]
+struct Lookup {
+    /// The entries of each directory listed, by lowercase name.
+    dirs:  DashMap<PathBuf, Arc<HashMap<std::string::String, OsString>>>,
+    found: DashMap<PathBuf, Option<PathBuf>>,
+}
+
+impl Lookup {
+    /// Finds a file in `dir`, first by its exact path with separators
+    /// normalized, then ignoring case.
+    fn find(&self, dir: &Path, name: &Path) -> Option<PathBuf> {
+        let name = name.to_string_lossy().replace('\\', "/");
+        let exact = dir.join(&name);
+        if exact.exists() {
+            return Some(exact);
+        }
+        if let Some(found) = self.found.get(&exact) {
+            return found.clone();
+        }
+        let found = name
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .try_fold(dir.to_path_buf(), |path, part| {
+                let actual = self.entries(&path)?.get(&part.to_lowercase())?.clone();
+                Some(path.join(actual))
+            })
+            .filter(|path| path.exists());
+        self.found.insert(exact, found.clone());
+        found
+    }
+
+    fn entries(&self, dir: &Path) -> Option<Arc<HashMap<std::string::String, OsString>>> {
+        if let Some(entries) = self.dirs.get(dir) {
+            return Some(entries.clone());
+        }
+        let entries: Arc<HashMap<_, _>> = Arc::new(
+            std::fs::read_dir(dir)
+                .ok()?
+                .filter_map(std::result::Result::ok)
+                .map(|entry| {
+                    let name = entry.file_name();
+                    (name.to_string_lossy().to_lowercase(), name)
+                })
+                .collect(),
+        );
+        self.dirs.insert(dir.to_path_buf(), entries.clone());
+        Some(entries)
+    }
 }
 
 impl Unpacked {
+    /// Opens a dump from its content folders and DLC folders, each in the
+    /// order they are searched. Empty paths are left out.
     pub(crate) fn new(
-        content_dir: Option<impl AsRef<Path>>,
-        update_dir: Option<impl AsRef<Path>>,
-        aoc_dir: Option<impl AsRef<Path>>,
+        content_dirs: Vec<PathBuf>,
+        aoc_dirs: Vec<PathBuf>,
         test_valid: bool,
     ) -> Result<Self> {
         log::info!("Loading unpacked game files");
-        let content_dir = content_dir
-            .as_ref()
-            .map(|c| c.as_ref())
-            .filter(|p| !p.as_os_str().is_empty());
-        let update_dir = update_dir
-            .as_ref()
-            .map(|d| d.as_ref())
-            .filter(|p| !p.as_os_str().is_empty());
-        let aoc_dir = aoc_dir
-            .as_ref()
-            .map(|a| a.as_ref())
-            .filter(|p| !p.as_os_str().is_empty());
+        let content_dirs: Vec<PathBuf> = content_dirs
+            .into_iter()
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect();
+        let aoc_dirs: Vec<PathBuf> = aoc_dirs
+            .into_iter()
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect();
         log::debug!(
-            "Folders:\n{:?}\n{:?}\n{:?}",
-            content_dir.map(|p| p.display()),
-            update_dir.map(|p| p.display()),
-            aoc_dir.map(|p| p.display())
+            "Folders:\n{:?}\n{:?}",
+            content_dirs.iter().map(|p| p.display()).collect::<Vec<_>>(),
+            aoc_dirs.iter().map(|p| p.display()).collect::<Vec<_>>()
         );
+        let lookup = Lookup::default();
         if test_valid {
-            static CONTENT_TEST: &str = "Map/MainField/A-1/A-1.00_Clustering.sblwp";
-            static UPDATE_TEST: &str = "Actor/Pack/Enemy_Lynel_Dark.sbactorpack";
-            static AOC_TEST: &str = "Pack/AocMainField.pack";
-            if let Some(content_dir) = content_dir {
-                if !content_dir.join(CONTENT_TEST).exists() {
-                    log::error!("Test file {} not found in content folder", CONTENT_TEST);
-                    return Err(ROMError::MissingDumpDir(
-                        "base game",
-                        content_dir.to_path_buf(),
-                    ));
+            let found = |dirs: &[PathBuf], test: &str| {
+                dirs.iter()
+                    .any(|dir| lookup.find(dir, test.as_ref()).is_some())
+            };
+            if let Some(first) = content_dirs.first() {
+                // The base game and update may be in one folder or split
+                // between several, so either is enough
+                if !found(&content_dirs, CONTENT_TEST) && !found(&content_dirs, UPDATE_TEST) {
+                    log::error!(
+                        "Test files {} and {} not found in content folders",
+                        CONTENT_TEST,
+                        UPDATE_TEST
+                    );
+                    return Err(ROMError::MissingDumpDir("base game", first.clone()));
                 }
-            } else if let Some(update_dir) = update_dir {
-                if !update_dir.join(UPDATE_TEST).exists() {
-                    log::error!("Test file {} not found in update folder", UPDATE_TEST);
-                    return Err(ROMError::MissingDumpDir("update", update_dir.to_path_buf()));
+            } else if let Some(first) = aoc_dirs.first() {
+                if !found(&aoc_dirs, AOC_TEST) {
+                    log::error!("Test file {} not found in DLC folders", AOC_TEST);
+                    return Err(ROMError::MissingDumpDir("DLC", first.clone()));
                 }
-            } else if let Some(aoc_dir) = aoc_dir {
-                if !aoc_dir.join(AOC_TEST).exists() {
-                    log::error!("Test file {} not found in DLC folder", AOC_TEST);
-                    return Err(ROMError::MissingDumpDir("DLC", aoc_dir.to_path_buf()));
-                }
-            } else if content_dir.is_none() && update_dir.is_none() && aoc_dir.is_none() {
+            } else {
                 return Err(ROMError::OtherMessage(
                     "No base game, update, or DLC files found",
                 ));
@@ -87,75 +207,378 @@ impl Unpacked {
             }
         }
 
+        let host_path = common_path(content_dirs.iter().chain(&aoc_dirs).map(|d| d.as_path()))
+            .or_else(|| content_dirs.iter().chain(&aoc_dirs).next().cloned())
+            .unwrap_or_default();
         Ok(Self {
-            host_path:   unsafe {
-                common_path(
-                    content_dir
-                        .as_ref()
-                        .iter()
-                        .chain(update_dir.as_ref().iter())
-                        .chain(aoc_dir.as_ref().iter())
-                        .map(|d| **d),
-                )
-                .or_else(|| {
-                    content_dir
-                        .or(update_dir)
-                        .or(aoc_dir)
-                        .map(|d| d.to_path_buf())
-                })
-                // We know this is sound because we provided an infallible `or_else()`.
-                .unwrap_unchecked()
-            },
-            content_dir: content_dir.map(|content| content.to_path_buf()),
-            update_dir:  update_dir.map(|update| update.to_path_buf()),
-            aoc_dir:     aoc_dir.map(|aoc| aoc.to_path_buf()),
+            host_path,
+            content_dirs,
+            aoc_dirs,
+            mmap_threshold: default_mmap_threshold(),
+            lookup,
         })
     }
-}
 
-#[typetag::serde]
-impl super::ResourceLoader for Unpacked {
-    fn get_data(&self, name: &Path) -> Result<Vec<u8>> {
-        self.update_dir
+    fn find(&self, name: &Path) -> Result<PathBuf> {
+        self.content_dirs
             .iter()
-            .chain(self.content_dir.iter())
-            .chain(self.aoc_dir.iter())
-            .map(|dir| dir.join(name))
-            .find(|path| path.exists())
-            .map(fs::read)
-            .transpose()?
+            .chain(&self.aoc_dirs)
+            .find_map(|dir| self.lookup.find(dir, name))
             .ok_or_else(|| {
                 ROMError::FileNotFound(name.to_string_lossy().into(), self.host_path.clone())
             })
     }
+}
 
-    fn get_aoc_file_data(&self, name: &Path) -> Result<Vec<u8>> {
-        self.aoc_dir
-            .as_ref()
-            .map(|dir| {
-                let dest_file = dir.join(name);
-                if dest_file.exists() {
-                    Ok(std::fs::read(dest_file)?)
-                } else {
-                    Err(ROMError::FileNotFound(
-                        name.to_string_lossy().into(),
-                        self.host_path.clone(),
-                    ))
+/// Adds the path of every file under `dir` to `files`, relative to `root`.
+fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(root, &path, files)?;
+        } else if let Ok(file) = path.strip_prefix(root) {
+            files.push(file.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Maps a file if it is at least `threshold` bytes and not Yaz0 compressed.
+/// A compressed file is decompressed into a new buffer anyway, so mapping it
+/// would save little.
+fn map_file(path: &Path, threshold: u64) -> std::io::Result<Option<Mmap>> {
+    let mut file = std::fs::File::open(path)?;
+    if file.metadata()?.len() < threshold.max(4) {
+        return Ok(None);
+    }
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if &magic == b"Yaz0" {
+        return Ok(None);
+    }
+    // SAFETY: The mapping is only read from, and is dropped as soon as the
+    // file is parsed. Dump files are not expected to change while we run.
+    unsafe { crate::map_whole(&file) }.map(Some)
+}
+
+#[typetag::serde]
+impl super::ResourceLoader for Unpacked {
+    fn get_data(&self, name: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(self.find(name)?)?)
+    }
+
+    fn get_file_data(&self, name: &Path) -> Result<FileData> {
+        let path = self.find(name)?;
+        if let Some(threshold) = self.mmap_threshold {
+            match map_file(&path, threshold) {
+                Ok(Some(map)) => return Ok(FileData::Mapped(map)),
+                Ok(None) => (),
+                Err(e) => {
+                    log::debug!("Could not map {}, reading instead: {e}", path.display())
                 }
-            })
-            .unwrap_or_else(|| Err(ROMError::MissingDumpDir("DLC", self.host_path.clone())))
+            }
+        }
+        Ok(FileData::Owned(fs::read(path)?))
+    }
+
+    fn open_stream(&self, name: &Path) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(self.find(name)?)?))
+    }
+
+    fn get_aoc_file_data(&self, name: &Path) -> Result<Vec<u8>> {
+        if self.aoc_dirs.is_empty() {
+            return Err(ROMError::MissingDumpDir("DLC", self.host_path.clone()));
+        }
+        match self
+            .aoc_dirs
+            .iter()
+            .find_map(|dir| self.lookup.find(dir, name))
+        {
+            Some(dest_file) => Ok(std::fs::read(dest_file)?),
+            None => {
+                Err(ROMError::FileNotFound(
+                    name.to_string_lossy().into(),
+                    self.host_path.clone(),
+                ))
+            }
+        }
     }
 
     fn file_exists(&self, name: &Path) -> bool {
-        self.update_dir
+        self.content_dirs
             .iter()
-            .chain(self.content_dir.iter())
-            .chain(self.aoc_dir.iter())
-            .map(|dir| dir.join(name))
-            .any(|path| path.exists())
+            .chain(&self.aoc_dirs)
+            .any(|dir| self.lookup.find(dir, name).is_some())
+    }
+
+    fn list_files(&self, prefix: Option<&Path>) -> Result<Vec<String>> {
+        let mut paths = vec![];
+        for dir in &self.content_dirs {
+            let mut files = vec![];
+            walk(dir, dir, &mut files)?;
+            paths.extend(files.into_iter().map(canonicalize));
+        }
+        for dir in &self.aoc_dirs {
+            let mut files = vec![];
+            walk(dir, dir, &mut files)?;
+            paths.extend(files.into_iter().map(canonicalize_aoc));
+        }
+        Ok(crate::listing(paths, prefix))
+    }
+
+    fn has_aoc(&self) -> bool {
+        !self.aoc_dirs.is_empty()
     }
 
     fn host_path(&self) -> &std::path::Path {
         &self.host_path
     }
+
+    fn roots(&self) -> Vec<&Path> {
+        self.content_dirs
+            .iter()
+            .chain(&self.aoc_dirs)
+            .map(|dir| dir.as_path())
+            .collect()
+    }
+
+    fn set_mmap_threshold(&mut self, threshold: Option<u64>) {
+        self.mmap_threshold = threshold;
+    }
+
+    fn preload_threads(&self) -> Option<usize> {
+        Some(PRELOAD_THREADS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::{sarc::SarcWriter, yaz0::compress, Endian};
+
+    use super::*;
+    use crate::{ResourceLoader, ResourceReader};
+
+    fn fixture() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let content = tmp.path().join("content");
+        std::fs::create_dir_all(content.join("Pack")).unwrap();
+        std::fs::create_dir_all(content.join("System")).unwrap();
+        let mut sarc = SarcWriter::new(Endian::Big);
+        for i in 0..64 {
+            sarc.add_file(format!("Actor/Test{i}.txt"), vec![i as u8; 4096]);
+        }
+        let sarc = sarc.to_binary();
+        std::fs::write(content.join("Pack/Test.pack"), &sarc).unwrap();
+        std::fs::write(content.join("Pack/Test.spack"), compress(&sarc)).unwrap();
+        std::fs::write(content.join("System/Version.txt"), b"1.5.0").unwrap();
+        tmp
+    }
+
+    fn loader(dir: &Path, threshold: Option<u64>) -> Unpacked {
+        let mut loader = Unpacked::new(vec![dir.join("content")], vec![], false).unwrap();
+        loader.set_mmap_threshold(threshold);
+        loader
+    }
+
+    #[test]
+    fn mapped_reads() {
+        let tmp = fixture();
+        let mapped = loader(tmp.path(), Some(1024));
+        let read = loader(tmp.path(), None);
+        for file in ["Pack/Test.pack", "Pack/Test.spack", "System/Version.txt"] {
+            let data = mapped.get_file_data(file.as_ref()).unwrap();
+            assert_eq!(data.is_mapped(), file == "Pack/Test.pack");
+            assert!(!read.get_file_data(file.as_ref()).unwrap().is_mapped());
+            assert_eq!(data.as_ref(), read.get_data(file.as_ref()).unwrap());
+        }
+        let large = loader(tmp.path(), Some(u64::MAX));
+        assert!(!large
+            .get_file_data("Pack/Test.pack".as_ref())
+            .unwrap()
+            .is_mapped());
+        assert!(matches!(
+            mapped.get_file_data("Pack/Missing.pack".as_ref()),
+            Err(ROMError::FileNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn streams() {
+        let tmp = fixture();
+        let loader = loader(tmp.path(), None);
+        for file in ["Pack/Test.spack", "pack/test.SPACK", "System/Version.txt"] {
+            let mut data = vec![];
+            loader
+                .open_stream(file.as_ref())
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, loader.get_data(file.as_ref()).unwrap());
+        }
+        let mut out = vec![];
+        assert_eq!(
+            loader
+                .copy_to("System/Version.txt".as_ref(), &mut out)
+                .unwrap(),
+            5
+        );
+        assert_eq!(out, b"1.5.0");
+        assert!(matches!(
+            loader.open_stream("Pack/Missing.pack".as_ref()),
+            Err(ROMError::FileNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn loose_paths() {
+        let tmp = fixture();
+        let actor = tmp.path().join("content/Actor/Pack");
+        std::fs::create_dir_all(&actor).unwrap();
+        std::fs::write(actor.join("Enemy_Lizalfos.sbactorpack"), b"lizalfos").unwrap();
+        let loader = loader(tmp.path(), None);
+        for name in [
+            "Actor/Pack/Enemy_Lizalfos.sbactorpack",
+            "actor/pack/Enemy_Lizalfos.sbactorpack",
+            "Actor\\Pack\\enemy_lizalfos.SBACTORPACK",
+            "ACTOR/Pack\\Enemy_Lizalfos.sbactorpack",
+        ] {
+            assert_eq!(
+                loader.get_data(name.as_ref()).unwrap(),
+                b"lizalfos",
+                "{name}"
+            );
+            assert!(loader.file_exists(name.as_ref()));
+        }
+        assert!(!loader.file_exists("actor/pack/Enemy_Lynel.sbactorpack".as_ref()));
+
+        // Further lookups are answered without listing folders again
+        let listed = loader.lookup.dirs.len();
+        let found = loader.lookup.found.len();
+        loader
+            .get_data("actor/pack/Enemy_Lizalfos.sbactorpack".as_ref())
+            .unwrap();
+        assert!(loader
+            .get_data("actor/pack/Enemy_Lynel.sbactorpack".as_ref())
+            .is_err());
+        assert_eq!(loader.lookup.dirs.len(), listed);
+        assert_eq!(loader.lookup.found.len(), found);
+    }
+
+    #[test]
+    fn list_files() {
+        let tmp = fixture();
+        let loader = loader(tmp.path(), None);
+        // The compressed and uncompressed packs share a canonical path
+        assert_eq!(loader.list_files(None).unwrap(), [
+            "Pack/Test.pack",
+            "System/Version.txt"
+        ]);
+        assert_eq!(loader.list_files(Some("System".as_ref())).unwrap(), [
+            "System/Version.txt"
+        ]);
+        assert!(!loader.has_aoc());
+    }
+
+    #[test]
+    fn layers() {
+        let tmp = tempfile::tempdir().unwrap();
+        let write = |dir: &str, file: &str, data: &[u8]| {
+            let path = tmp.path().join(dir).join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        };
+        write("base", "Actor/Pack/Shared.sbactorpack", b"base");
+        write("base", "Actor/Pack/Base.sbactorpack", b"base");
+        write("update", "Actor/Pack/Shared.sbactorpack", b"update");
+        write("0010", "Pack/AocMainField.pack", b"0010");
+        write("0010", "Map/Old.smubin", b"0010");
+        write("0011", "Pack/AocMainField.pack", b"0011");
+        let dirs =
+            |names: &[&str]| -> Vec<PathBuf> { names.iter().map(|n| tmp.path().join(n)).collect() };
+        let loader =
+            Unpacked::new(dirs(&["update", "base"]), dirs(&["0011", "0010"]), false).unwrap();
+        assert_eq!(loader.host_path, tmp.path());
+        assert_eq!(
+            loader
+                .get_data("Actor/Pack/Shared.sbactorpack".as_ref())
+                .unwrap(),
+            b"update"
+        );
+        assert!(loader.file_exists("Actor/Pack/Base.sbactorpack".as_ref()));
+        assert!(loader.file_exists("Map/Old.smubin".as_ref()));
+        assert_eq!(
+            loader
+                .get_aoc_file_data("Pack/AocMainField.pack".as_ref())
+                .unwrap(),
+            b"0011"
+        );
+        assert_eq!(
+            loader.get_aoc_file_data("Map/Old.smubin".as_ref()).unwrap(),
+            b"0010"
+        );
+        assert!(loader
+            .get_aoc_file_data("Actor/Pack/Base.sbactorpack".as_ref())
+            .is_err());
+        assert_eq!(loader.list_files(None).unwrap().len(), 4);
+        assert_eq!(loader.roots().len(), 4);
+
+        // Neither the base game nor the update is in these folders
+        assert!(matches!(
+            Unpacked::new(dirs(&["0010"]), vec![], true),
+            Err(ROMError::MissingDumpDir("base game", _))
+        ));
+        write("update", UPDATE_TEST, b"");
+        assert!(Unpacked::new(dirs(&["base", "update"]), vec![], true).is_ok());
+        assert!(Unpacked::new(vec![], dirs(&["0011"]), true).is_ok());
+        assert!(Unpacked::new(vec![], vec![], true).is_err());
+    }
+
+    #[test]
+    fn stored_dirs() {
+        // Saved before dumps could have more than one folder of each kind
+        let loader: Unpacked = serde_json::from_str(
+            r#"{"host_path":"dump","content_dir":"dump/content","update_dir":"dump/update","aoc_dir":"dump/aoc","mmap_threshold":null}"#,
+        )
+        .unwrap();
+        assert_eq!(loader.content_dirs, [
+            PathBuf::from("dump/update"),
+            PathBuf::from("dump/content")
+        ]);
+        assert_eq!(loader.aoc_dirs, [PathBuf::from("dump/aoc")]);
+        assert_eq!(loader.mmap_threshold, None);
+        let loader: Unpacked = serde_json::from_str(
+            &serde_json::to_string(
+                &Unpacked::new(
+                    vec!["dump/content".into()],
+                    vec!["dump/0010".into(), "dump/0011".into()],
+                    false,
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(loader.content_dirs, [PathBuf::from("dump/content")]);
+        assert_eq!(loader.aoc_dirs.len(), 2);
+        assert_eq!(loader.mmap_threshold, Some(DEFAULT_MMAP_THRESHOLD));
+    }
+
+    #[test]
+    fn same_resources() {
+        let tmp = fixture();
+        let mapped = ResourceReader::from_unpacked_mod(tmp.path())
+            .unwrap()
+            .with_mmap_threshold(Some(0));
+        let read = ResourceReader::from_unpacked_mod(tmp.path())
+            .unwrap()
+            .with_mmap_threshold(None);
+        for file in ["Pack/Test.pack", "Pack/Test.spack", "System/Version.txt"] {
+            assert_eq!(mapped.get_data(file).unwrap(), read.get_data(file).unwrap());
+        }
+        // Members are cached from the mapped pack before it is dropped
+        assert_eq!(
+            mapped.get_resource("Actor/Test3.txt").unwrap(),
+            read.get_resource("Actor/Test3.txt").unwrap()
+        );
+    }
 }
@@ -0,0 +1,233 @@
+use std::{fmt, path::Path};
+
+use smartstring::alias::String;
+use uk_content::constants::Language;
+
+use crate::{ROMError, ResourceReader, Result};
+
+/// Files which every dump must have, by canonical path.
+const REQUIRED: &[&str] = &["Pack/Bootup.pack", "Pack/TitleBG.pack"];
+/// The DLC pack which a dump must have if it has DLC at all.
+const AOC_REQUIRED: &str = "Aoc/0010/Pack/AocMainField.pack";
+
+/// The required files a game dump is missing, from
+/// [`ResourceReader::validate_dump`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DumpReport {
+    /// Canonical paths of the required files which are missing.
+    pub missing: Vec<String>,
+    /// Whether the dump has no Bootup pack for any language.
+    pub missing_language: bool,
+}
+
+impl DumpReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && !self.missing_language
+    }
+}
+
+impl fmt::Display for DumpReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "The game dump has every required file");
+        }
+        write!(f, "The game dump is missing required files:")?;
+        for file in &self.missing {
+            write!(f, "\n- {file}")?;
+        }
+        if self.missing_language {
+            write!(f, "\n- A language pack, e.g. Pack/Bootup_USen.pack")?;
+        }
+        Ok(())
+    }
+}
+
+/// Which release of the game a dump is, from
+/// [`ResourceReader::detect_region_and_version`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DumpRelease {
+    /// The region all of the dump's languages come from, unless they come
+    /// from several, as on Switch.
+    pub region:    Option<&'static str>,
+    /// The version in `System/Version.txt`, if the dump has it.
+    pub version:   Option<String>,
+    /// The languages the dump has a Bootup pack for.
+    pub languages: Vec<Language>,
+}
+
+impl DumpRelease {
+    /// Checks that the dump is of the version UKMM needs, and has texts in
+    /// the chosen language. A dump which has no version file or no language
+    /// packs is left for [`ResourceReader::validate_dump`] to report.
+    pub fn check(&self, language: Language, version: &'static str, path: &Path) -> Result<()> {
+        if let Some(found) = self.version.as_ref().filter(|found| *found != version) {
+            return Err(ROMError::WrongVersion {
+                found:    found.clone(),
+                required: version,
+                hint:     if found.starts_with("1.0") {
+                    "Only the base game was found, so the update folder is missing or is not set \
+                     to the update."
+                } else {
+                    "The update folder has a different update than the one UKMM supports."
+                },
+                path:     path.to_path_buf(),
+            });
+        }
+        if !self.languages.is_empty() && !self.languages.contains(&language) {
+            return Err(ROMError::WrongRegion {
+                language,
+                region: language.region(),
+                found: self
+                    .languages
+                    .iter()
+                    .map(|lang| lang.to_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    .into(),
+                path: path.to_path_buf(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ResourceReader {
+    /// Works out which release of the game the dump is from its version file
+    /// and the languages it has Bootup packs for.
+    pub fn detect_region_and_version(&self) -> Result<DumpRelease> {
+        let version = self
+            .source()
+            .get_data("System/Version.txt".as_ref())
+            .ok()
+            .map(|data| std::string::String::from_utf8_lossy(&data).trim().into());
+        let packs = self.source().list_files(Some("Pack".as_ref()))?;
+        let languages: Vec<Language> = Language::iter()
+            .copied()
+            .filter(|lang| packs.iter().any(|pack| *pack == lang.bootup_path()))
+            .collect();
+        let region = languages
+            .first()
+            .map(|lang| lang.region())
+            .filter(|region| languages.iter().all(|lang| lang.region() == *region));
+        Ok(DumpRelease {
+            region,
+            version,
+            languages,
+        })
+    }
+
+    /// Checks the dump for the files merging cannot do without: the Bootup
+    /// and TitleBG packs, the Bootup pack of at least one language, and the
+    /// main DLC pack if the dump has DLC.
+    pub fn validate_dump(&self) -> Result<DumpReport> {
+        let has_aoc = self.source().has_aoc();
+        let mut packs = self.source().list_files(Some("Pack".as_ref()))?;
+        let mut required = REQUIRED.to_vec();
+        if has_aoc {
+            packs.extend(self.source().list_files(Some("Aoc/0010/Pack".as_ref()))?);
+            required.push(AOC_REQUIRED);
+        }
+        let has = |file: &str| packs.iter().any(|pack| pack == file);
+        Ok(DumpReport {
+            missing: required
+                .into_iter()
+                .filter(|file| !has(file))
+                .map(String::from)
+                .collect(),
+            missing_language: !Language::iter().any(|lang| has(lang.bootup_path().as_str())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uk_content::prelude::Endian;
+
+    use super::*;
+    use crate::MemoryROMSource;
+
+    #[test]
+    fn validate_dump() {
+        let source = MemoryROMSource::new(Endian::Big)
+            .with_file("Pack/Bootup.pack", b"bootup".to_vec())
+            .with_file("Pack/TitleBG.pack", b"titlebg".to_vec())
+            .with_file("Pack/Bootup_USen.pack", b"usen".to_vec());
+        let report = ResourceReader::from_memory(source.clone())
+            .validate_dump()
+            .unwrap();
+        assert!(report.is_ok());
+
+        let report = ResourceReader::from_memory(
+            source
+                .clone()
+                .with_aoc_file("Map/AocField/A-1/A-1_Dynamic.smubin", b"map".to_vec()),
+        )
+        .validate_dump()
+        .unwrap();
+        assert_eq!(report.missing, [AOC_REQUIRED]);
+        assert!(!report.missing_language);
+
+        let report = ResourceReader::from_memory(MemoryROMSource::new(Endian::Big))
+            .validate_dump()
+            .unwrap();
+        assert_eq!(report.missing, REQUIRED);
+        assert!(report.missing_language);
+        assert!(report.to_string().contains("Pack/TitleBG.pack"));
+    }
+
+    #[test]
+    fn base_only() {
+        let dump = ResourceReader::from_memory(
+            MemoryROMSource::new(Endian::Big)
+                .with_file("System/Version.txt", "1.0.0\n")
+                .with_file("Pack/Bootup_USen.pack", b"usen".to_vec())
+                .with_file("Pack/Bootup_USfr.pack", b"usfr".to_vec()),
+        );
+        let release = dump.detect_region_and_version().unwrap();
+        assert_eq!(release, DumpRelease {
+            region:    Some("US"),
+            version:   Some("1.0.0".into()),
+            languages: vec![Language::USen, Language::USfr],
+        });
+        let err = release
+            .check(Language::USen, "1.5.0", "dump".as_ref())
+            .unwrap_err();
+        assert!(matches!(err, ROMError::WrongVersion { .. }));
+        assert!(err.to_string().contains("update folder is missing"));
+    }
+
+    #[test]
+    fn update_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        let update = tmp.path().join("update");
+        for (file, data) in [
+            ("Actor/Pack/Enemy_Lynel_Dark.sbactorpack", "lynel"),
+            ("System/Version.txt", "1.5.0"),
+            ("Pack/Bootup_EUen.pack", "euen"),
+            ("Pack/Bootup_EUde.pack", "eude"),
+        ] {
+            let path = update.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+        let dump = ResourceReader::from_unpacked_layers(vec![update.clone()], vec![]).unwrap();
+        let release = dump.detect_region_and_version().unwrap();
+        assert_eq!(release.region, Some("EU"));
+        assert_eq!(release.version.as_deref(), Some("1.5.0"));
+        assert_eq!(release.languages, [Language::EUen, Language::EUde]);
+        assert!(release.check(Language::EUde, "1.5.0", &update).is_ok());
+        let err = release.check(Language::USen, "1.5.0", &update).unwrap_err();
+        assert!(matches!(err, ROMError::WrongRegion { region: "US", .. }));
+        assert!(err.to_string().contains("only in EUen, EUde"));
+        // The Wii U update is not the Switch release
+        assert!(matches!(
+            release.check(Language::EUde, "1.6.0", &update),
+            Err(ROMError::WrongVersion { .. })
+        ));
+
+        // Switch dumps have languages from every region
+        std::fs::write(update.join("Pack/Bootup_JPja.pack"), "jpja").unwrap();
+        let dump = ResourceReader::from_unpacked_layers(vec![update], vec![]).unwrap();
+        assert_eq!(dump.detect_region_and_version().unwrap().region, None);
+    }
+}
@@ -1,40 +1,642 @@
-use std::path::{Path, PathBuf};
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::Instant,
+};
 
+use fs_err as fs;
+use parking_lot::Mutex;
 use serde::Serialize;
+use uk_content::{canon::canonicalize_aoc, canonicalize};
+use zarchive::reader::ZArchiveReader;
 
-use crate::{ROMError, Result};
+use crate::{
+    unpacked::{AOC_TEST, CONTENT_TEST, UPDATE_TEST},
+    ROMError, ReadStats, Result,
+};
+
+/// The number of archive handles to open when none is configured: one per
+/// core, up to 4.
+pub fn default_zarchive_readers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(4)
+}
+
+/// Independent handles to the same archive. Each read checks out whichever
+/// handle is free, so parallel merges do not queue up behind a single one.
+/// Reads only wait when every handle is busy.
+pub(crate) struct ReaderPool {
+    handles: Vec<Mutex<ZArchiveReader>>,
+    next: AtomicUsize,
+    reads: AtomicU64,
+    contended: AtomicU64,
+    wait_ns: AtomicU64,
+}
+
+impl std::fmt::Debug for ReaderPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReaderPool")
+            .field("handles", &self.handles.len())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl ReaderPool {
+    pub(crate) fn open(path: &Path, readers: usize) -> Result<Self> {
+        let handles = (0..readers.max(1))
+            .map(|_| Ok(Mutex::new(ZArchiveReader::open(path)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            handles,
+            next: AtomicUsize::new(0),
+            reads: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            wait_ns: AtomicU64::new(0),
+        })
+    }
+
+    /// Runs `f` with a free handle, waiting for one if they are all in use.
+    pub(crate) fn with<T>(&self, f: impl FnOnce(&ZArchiveReader) -> T) -> T {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let len = self.handles.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % len;
+        for i in 0..len {
+            if let Some(handle) = self.handles[(start + i) % len].try_lock() {
+                return f(&handle);
+            }
+        }
+        self.contended.fetch_add(1, Ordering::Relaxed);
+        let timer = Instant::now();
+        let handle = self.handles[start].lock();
+        self.wait_ns
+            .fetch_add(timer.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        f(&handle)
+    }
+
+    /// Reads the first of `paths` which exists, using a single checkout.
+    pub(crate) fn read_first(&self, paths: impl IntoIterator<Item = PathBuf>) -> Option<Vec<u8>> {
+        self.with(|archive| paths.into_iter().find_map(|path| archive.read_file(path)))
+    }
+
+    pub(crate) fn stats(&self) -> ReadStats {
+        ReadStats {
+            handles: self.handles.len(),
+            reads: self.reads.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            wait: std::time::Duration::from_nanos(self.wait_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The footer at the end of every archive, which locates its sections.
+const FOOTER_SIZE: u64 = 144;
+const MAGIC: u32 = 0x169F_52D6;
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Archive data is stored in blocks of this size, each compressed on its own
+/// unless that would not make it smaller.
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+/// The names of an archive's sections, in the order the footer has them.
+const SECTIONS: [&str; 6] = [
+    "compressed data",
+    "offset records",
+    "names",
+    "file tree",
+    "metadata directory",
+    "metadata",
+];
+
+/// Where each section of an archive is, as an offset and size, and how large
+/// the archive should be.
+struct Footer {
+    sections:   [(u64, u64); 6],
+    total_size: u64,
+}
+
+impl Footer {
+    /// Reads the footer, or `None` if the file does not end in one.
+    fn read(file: &mut fs::File, len: u64) -> Result<Option<Self>> {
+        let Some(at) = len.checked_sub(FOOTER_SIZE) else {
+            return Ok(None);
+        };
+        let mut footer = [0u8; FOOTER_SIZE as usize];
+        file.seek(SeekFrom::Start(at))?;
+        file.read_exact(&mut footer)?;
+        if be_u32(&footer, FOOTER_SIZE as usize - 4) != Some(MAGIC) {
+            return Ok(None);
+        }
+        let field = |offset: usize| {
+            be_u64(&footer, offset).ok_or(ROMError::OtherMessage("Invalid ZArchive footer"))
+        };
+        let mut sections = [(0, 0); 6];
+        for (i, section) in sections.iter_mut().enumerate() {
+            *section = (field(i * 16)?, field(i * 16 + 8)?);
+        }
+        // The sections are followed by a hash of the archive, then its size
+        Ok(Some(Self {
+            sections,
+            total_size: field(SECTIONS.len() * 16 + 32)?,
+        }))
+    }
+}
+
+/// The sections of an archive which locate its files. The reader can only
+/// look files up and read them whole, so these are read directly to list
+/// files and to stream them.
+struct Layout {
+    data_at:  u64,
+    data_len: u64,
+    records:  Vec<u8>,
+    names:    Vec<u8>,
+    tree:     Vec<u8>,
+}
+
+impl std::fmt::Debug for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Layout")
+            .field("records", &(self.records.len() / 40))
+            .field("entries", &(self.tree.len() / 16))
+            .finish()
+    }
+}
+
+impl Layout {
+    fn read(path: &Path) -> Result<Self> {
+        let invalid = || ROMError::OtherMessage("Invalid file tree in ZArchive");
+        let mut file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        let footer =
+            Footer::read(&mut file, len)?.ok_or(ROMError::OtherMessage("Not a ZArchive file"))?;
+        let mut read = |(offset, size): (u64, u64)| -> Result<Vec<u8>> {
+            if offset.checked_add(size).filter(|&end| end <= len).is_none() {
+                return Err(invalid());
+            }
+            let mut data = vec![0u8; size as usize];
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut data)?;
+            Ok(data)
+        };
+        let (data_at, data_len) = footer.sections[0];
+        Ok(Self {
+            data_at,
+            data_len,
+            records: read(footer.sections[1])?,
+            names: read(footer.sections[2])?,
+            tree: read(footer.sections[3])?,
+        })
+    }
+
+    /// A name is its length, in one byte or two if the first has its high
+    /// bit set, then the name itself.
+    fn name_at(&self, offset: usize) -> Option<&str> {
+        let first = *self.names.get(offset)? as usize;
+        let (len, start) = if first & 0x80 != 0 {
+            (
+                (first & 0x7F) | (*self.names.get(offset + 1)? as usize) << 7,
+                offset + 2,
+            )
+        } else {
+            (first, offset + 1)
+        };
+        std::str::from_utf8(self.names.get(start..start + len)?).ok()
+    }
+
+    /// Every entry is 16 bytes: its name offset, with the high bit set for
+    /// files, then for directories the index of their first child and how
+    /// many there are. The root is the first entry.
+    fn entry(&self, index: usize) -> Option<&[u8]> {
+        self.tree.get(index * 16..index * 16 + 16)
+    }
+
+    fn entry_name(&self, entry: &[u8]) -> Option<&str> {
+        self.name_at((be_u32(entry, 0)? & 0x7FFF_FFFF) as usize)
+    }
+
+    /// Paths of every file in the archive.
+    fn files(&self) -> Result<Vec<String>> {
+        Ok(self.entries()?.into_iter().map(|(path, _)| path).collect())
+    }
+
+    /// Every file in the archive, by its path, with its entry.
+    fn entries(&self) -> Result<Vec<(String, &[u8])>> {
+        let invalid = || ROMError::OtherMessage("Invalid file tree in ZArchive");
+        // Every entry is visited once, so more steps than there are entries
+        // means a loop
+        let mut budget = self.tree.len() / 16;
+        let mut files = vec![];
+        let mut dirs = vec![(0, String::new())];
+        while let Some((index, prefix)) = dirs.pop() {
+            let dir = self.entry(index).ok_or_else(invalid)?;
+            let start = be_u32(dir, 4).ok_or_else(invalid)? as usize;
+            let count = be_u32(dir, 8).ok_or_else(invalid)? as usize;
+            for child in start..start + count {
+                budget = budget.checked_sub(1).ok_or(ROMError::OtherMessage(
+                    "ZArchive file tree loops back on itself",
+                ))?;
+                let entry = self.entry(child).ok_or_else(invalid)?;
+                let kind = be_u32(entry, 0).ok_or_else(invalid)?;
+                let name = self.entry_name(entry).ok_or_else(invalid)?;
+                let path = if prefix.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{prefix}/{name}")
+                };
+                if kind & 0x8000_0000 != 0 {
+                    files.push((path, entry));
+                } else {
+                    dirs.push((child, path));
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Where a file is in the uncompressed data and its size, looked up
+    /// without regard to case as the reader does.
+    fn find(&self, path: &Path) -> Option<(u64, u64)> {
+        let mut entry = self.entry(0)?;
+        for part in path.components() {
+            let part = part.as_os_str().to_str()?;
+            if be_u32(entry, 0)? & 0x8000_0000 != 0 {
+                return None;
+            }
+            let start = be_u32(entry, 4)? as usize;
+            let count = be_u32(entry, 8)? as usize;
+            entry = (start..start.saturating_add(count))
+                .map_while(|child| self.entry(child))
+                .find(|child| {
+                    self.entry_name(child)
+                        .is_some_and(|name| name.eq_ignore_ascii_case(part))
+                })?;
+        }
+        if be_u32(entry, 0)? & 0x8000_0000 == 0 {
+            return None;
+        }
+        Self::file_span(entry)
+    }
+
+    /// Where a file entry's data is in the uncompressed data and its size.
+    fn file_span(entry: &[u8]) -> Option<(u64, u64)> {
+        // The last word has the high bits of the size, then of the offset
+        let high = be_u32(entry, 12)? as u64;
+        Some((
+            (high & 0xFFFF) << 32 | be_u32(entry, 4)? as u64,
+            (high >> 16) << 32 | be_u32(entry, 8)? as u64,
+        ))
+    }
+
+    /// Whether the block with a position in the uncompressed data is stored
+    /// within the compressed data.
+    fn is_stored(&self, pos: u64) -> bool {
+        self.block(pos / BLOCK_SIZE).is_some_and(|(offset, size)| {
+            offset + size as u64 <= self.data_at.saturating_add(self.data_len)
+        })
+    }
+
+    /// Where a block is stored in the archive and its stored size. Each
+    /// offset record has where a run of 16 blocks starts and the stored size
+    /// of each, less one.
+    fn block(&self, index: u64) -> Option<(u64, usize)> {
+        let at = usize::try_from(index / 16).ok()?.checked_mul(40)?;
+        let record = self.records.get(at..at + 40)?;
+        let size = |slot: u64| be_u16(record, 8 + slot as usize * 2).map(|size| size as u64 + 1);
+        let before = (0..index % 16).map(size).sum::<Option<u64>>()?;
+        Some((
+            self.data_at + be_u64(record, 0)? + before,
+            size(index % 16)? as usize,
+        ))
+    }
+}
+
+/// Damage to the structure of a WUA dump, from [`ZArchiveReport::damage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZArchiveDamage {
+    /// The file does not end in an archive footer.
+    NoFooter,
+    /// The archive is not the size its footer gives.
+    WrongSize { expected: u64, actual: u64 },
+    /// A section runs past the end of the archive.
+    Section(&'static str),
+    /// Files whose data is past the end of the compressed data.
+    Files(Vec<String>),
+}
+
+impl ZArchiveDamage {
+    /// Whether the archive cannot be opened at all.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, Self::Files(_))
+    }
+}
+
+impl std::fmt::Display for ZArchiveDamage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoFooter => {
+                write!(
+                    f,
+                    "The file does not end like a WUA archive, so it was cut short or is not one"
+                )
+            }
+            Self::WrongSize { expected, actual } => {
+                write!(
+                    f,
+                    "The archive should be {expected} bytes but is {actual}, so it was cut short \
+                     or changed after it was made"
+                )
+            }
+            Self::Section(name) => {
+                write!(
+                    f,
+                    "The archive's {name} run past its end, so it was cut short"
+                )
+            }
+            Self::Files(files) => {
+                write!(
+                    f,
+                    "{} files are past the end of the archive's data, such as {}",
+                    files.len(),
+                    files.first().map(String::as_str).unwrap_or_default()
+                )
+            }
+        }
+    }
+}
+
+/// Checks that an archive is whole: that it is the size it should be, and
+/// that its sections and the data of every file are inside it. Only the
+/// footer and file tree are read, so this is quick even for large dumps.
+fn verify(path: &Path) -> Result<Vec<ZArchiveDamage>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let Some(footer) = Footer::read(&mut file, len)? else {
+        return Ok(vec![ZArchiveDamage::NoFooter]);
+    };
+    let mut damage = vec![];
+    if footer.total_size != len {
+        damage.push(ZArchiveDamage::WrongSize {
+            expected: footer.total_size,
+            actual:   len,
+        });
+    }
+    for (name, (offset, size)) in SECTIONS.into_iter().zip(footer.sections) {
+        if offset.checked_add(size).filter(|&end| end <= len).is_none() {
+            damage.push(ZArchiveDamage::Section(name));
+        }
+    }
+    if !damage.is_empty() {
+        return Ok(damage);
+    }
+    let layout = Layout::read(path)?;
+    let outside: Vec<String> = layout
+        .entries()?
+        .into_iter()
+        .filter(|(_, entry)| {
+            Layout::file_span(entry)
+                .is_some_and(|(offset, size)| size > 0 && !layout.is_stored(offset + size - 1))
+        })
+        .map(|(path, _)| path)
+        .collect();
+    if !outside.is_empty() {
+        damage.push(ZArchiveDamage::Files(outside));
+    }
+    Ok(damage)
+}
+
+/// A file in an archive, read a block at a time as it is needed.
+struct BlockStream {
+    file:   fs::File,
+    layout: Arc<Layout>,
+    /// The position in the uncompressed data, and where the file ends.
+    pos:    u64,
+    end:    u64,
+    /// The last block read, by index.
+    block:  Option<(u64, Vec<u8>)>,
+}
+
+impl BlockStream {
+    fn read_block(&mut self, index: u64) -> std::io::Result<Vec<u8>> {
+        let (offset, size) = self.layout.block(index).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid offset records in ZArchive",
+            )
+        })?;
+        let mut data = vec![0u8; size];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut data)?;
+        if size as u64 == BLOCK_SIZE {
+            Ok(data)
+        } else {
+            zstd::bulk::decompress(&data, BLOCK_SIZE as usize)
+        }
+    }
+}
+
+impl Read for BlockStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.end || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.pos / BLOCK_SIZE;
+        let block = match self.block.take() {
+            Some((i, block)) if i == index => block,
+            _ => self.read_block(index)?,
+        };
+        let start = (self.pos % BLOCK_SIZE) as usize;
+        let len = buf
+            .len()
+            .min(block.len().saturating_sub(start))
+            .min((self.end - self.pos).try_into().unwrap_or(usize::MAX));
+        if len == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "ZArchive block is shorter than expected",
+            ));
+        }
+        buf[..len].copy_from_slice(&block[start..start + len]);
+        self.pos += len as u64;
+        self.block = Some((index, block));
+        Ok(len)
+    }
+}
+
+/// The title folders at the root of an archive, by the title ID and version
+/// in their names.
+#[derive(Debug, Default)]
+struct TitleDirs {
+    content: Option<String>,
+    update:  Option<String>,
+    aoc:     Option<String>,
+}
+
+impl TitleDirs {
+    fn find(archive: &ZArchiveReader) -> Result<Self> {
+        let mut dirs = Self::default();
+        for dir in archive.iter()? {
+            let name = dir.name();
+            if !name.starts_with("0005000") {
+                continue;
+            }
+            if name.ends_with("v0") {
+                dirs.content = Some(name.to_owned());
+            } else if name.ends_with("v208") {
+                dirs.update = Some(name.to_owned());
+            } else if name.ends_with("v80") {
+                dirs.aoc = Some(name.to_owned());
+            }
+        }
+        Ok(dirs)
+    }
+}
+
+/// The region of a Wii U release by the second half of its title ID, as in
+/// [`Language::region`](uk_content::constants::Language::region).
+fn title_region(dir: &str) -> Option<&'static str> {
+    match dir.get(8..16)?.to_ascii_lowercase().as_str() {
+        "101c9300" => Some("JP"),
+        "101c9400" => Some("US"),
+        "101c9500" => Some("EU"),
+        _ => None,
+    }
+}
+
+/// One part of the game in a WUA dump, from [`ZArchiveReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZArchivePart {
+    pub name:     &'static str,
+    /// The title folder of the part, if the archive has one.
+    pub folder:   Option<String>,
+    /// A file which only this part of the game has.
+    pub sentinel: &'static str,
+    /// Whether the folder has the sentinel file.
+    pub found:    bool,
+}
+
+impl ZArchivePart {
+    pub fn is_present(&self) -> bool {
+        self.folder.is_some() && self.found
+    }
+}
+
+impl std::fmt::Display for ZArchivePart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.folder, self.found) {
+            (Some(folder), true) => write!(f, "Found the {} in {folder}", self.name),
+            (Some(folder), false) => {
+                write!(
+                    f,
+                    "The {} folder {folder} is incomplete: it does not have {}",
+                    self.name, self.sentinel
+                )
+            }
+            (None, _) => write!(f, "The {} is missing", self.name),
+        }
+    }
+}
+
+/// Which parts of the game a WUA dump has, from
+/// [`ResourceReader::inspect_zarchive`](crate::ResourceReader::inspect_zarchive).
+/// The base game and update are required, while the DLC is optional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZArchiveReport {
+    pub host_path: PathBuf,
+    pub content: ZArchivePart,
+    pub update: ZArchivePart,
+    pub aoc: ZArchivePart,
+    /// The region of the release, from the first title folder with a known
+    /// title ID.
+    pub region: Option<&'static str>,
+    /// Damage to the archive which would make reads fail partway through a
+    /// merge.
+    pub damage: Vec<ZArchiveDamage>,
+}
+
+impl ZArchiveReport {
+    /// The required parts of the game which are missing or incomplete.
+    pub fn errors(&self) -> impl Iterator<Item = &ZArchivePart> {
+        [&self.content, &self.update]
+            .into_iter()
+            .filter(|part| !part.is_present())
+    }
+
+    /// The DLC, if it is missing or incomplete.
+    pub fn warning(&self) -> Option<&ZArchivePart> {
+        (!self.aoc.is_present()).then_some(&self.aoc)
+    }
+
+    /// Checks that the dump is whole and has the base game and update.
+    pub fn check(&self) -> Result<()> {
+        if let Some(damage) = self.damage.first() {
+            return Err(ROMError::DamagedArchive(
+                damage.to_string().into(),
+                self.host_path.clone(),
+            ));
+        }
+        let missing = match (self.content.is_present(), self.update.is_present()) {
+            (true, true) => return Ok(()),
+            (false, true) => "base game",
+            (true, false) => "update",
+            (false, false) => "base game and update",
+        };
+        Err(ROMError::MissingDumpDir(missing, self.host_path.clone()))
+    }
+}
 
 #[derive(Debug, Serialize)]
 pub(crate) struct ZArchive {
     #[serde(skip_serializing)]
-    archive:     zarchive::reader::ZArchiveReader,
+    pool: ReaderPool,
     content_dir: PathBuf,
-    update_dir:  PathBuf,
-    aoc_dir:     Option<PathBuf>,
-    host_path:   PathBuf,
+    update_dir: PathBuf,
+    aoc_dir: Option<PathBuf>,
+    host_path: PathBuf,
+    readers: usize,
+    #[serde(skip_serializing)]
+    layout: OnceLock<Arc<Layout>>,
 }
 
 impl ZArchive {
-    pub(crate) fn new(path: impl AsRef<Path>) -> Result<Self> {
-        log::info!("Opening ZArchive at {}", path.as_ref().display());
-        let archive = zarchive::reader::ZArchiveReader::open(path.as_ref())?;
-        let mut content_dir: Option<PathBuf> = None;
-        let mut update_dir: Option<PathBuf> = None;
-        let mut aoc_dir: Option<PathBuf> = None;
-        for dir in archive.iter()? {
-            if dir.name().starts_with("0005000") && dir.name().ends_with("v0") {
-                content_dir = Some(Path::new(dir.name()).join("content"));
-                log::debug!("Found content folder in ZArchive at {:?}", &content_dir);
-            } else if dir.name().starts_with("0005000") && dir.name().ends_with("v208") {
-                update_dir = Some(Path::new(dir.name()).join("content"));
-                log::debug!("Found update folder in ZArchive at {:?}", &update_dir);
-            } else if dir.name().starts_with("0005000") && dir.name().ends_with("v80") {
-                aoc_dir = Some(Path::new(dir.name()).join("content/0010"));
-                log::debug!("Found DLC folder in ZArchive at {:?}", &aoc_dir);
-            }
+    pub(crate) fn new(path: impl AsRef<Path>, readers: usize) -> Result<Self> {
+        log::info!(
+            "Opening ZArchive at {} with {readers} handles",
+            path.as_ref().display()
+        );
+        let pool = ReaderPool::open(path.as_ref(), readers)?;
+        let dirs = pool.with(TitleDirs::find)?;
+        let content_dir = dirs.content.map(|dir| Path::new(&dir).join("content"));
+        let update_dir = dirs.update.map(|dir| Path::new(&dir).join("content"));
+        let aoc_dir = dirs.aoc.map(|dir| Path::new(&dir).join("content/0010"));
+        for dir in [&content_dir, &update_dir, &aoc_dir].into_iter().flatten() {
+            log::debug!("Found title folder in ZArchive at {}", dir.display());
         }
         Ok(Self {
-            archive,
+            readers: pool.handles.len(),
+            pool,
             content_dir: content_dir.ok_or_else(|| {
                 ROMError::MissingDumpDir("base game", path.as_ref().to_path_buf())
             })?,
@@ -42,6 +644,73 @@ impl ZArchive {
                 .ok_or_else(|| ROMError::MissingDumpDir("update", path.as_ref().to_path_buf()))?,
             aoc_dir,
             host_path: path.as_ref().to_path_buf(),
+            layout: OnceLock::new(),
+        })
+    }
+
+    /// The archive's file tree, read the first time it is needed.
+    fn layout(&self) -> Result<Arc<Layout>> {
+        if let Some(layout) = self.layout.get() {
+            return Ok(layout.clone());
+        }
+        let layout = Arc::new(Layout::read(&self.host_path)?);
+        Ok(self.layout.get_or_init(|| layout).clone())
+    }
+
+    /// Opens the first of `paths` which the archive has as a stream.
+    fn open_first(&self, paths: impl IntoIterator<Item = PathBuf>) -> Result<Option<BlockStream>> {
+        let layout = self.layout()?;
+        let Some((offset, size)) = paths.into_iter().find_map(|path| layout.find(&path)) else {
+            return Ok(None);
+        };
+        Ok(Some(BlockStream {
+            file: fs::File::open(&self.host_path)?,
+            layout,
+            pos: offset,
+            end: offset + size,
+            block: None,
+        }))
+    }
+
+    /// Finds which parts of the game an archive has, by their title folders
+    /// and a file in each which only that part has.
+    pub(crate) fn inspect(path: impl AsRef<Path>) -> Result<ZArchiveReport> {
+        // The reader cannot open an archive without its footer or sections,
+        // and would only say so with a low-level error
+        let damage = verify(path.as_ref())?;
+        if let Some(fatal) = damage.iter().find(|damage| damage.is_fatal()) {
+            return Err(ROMError::DamagedArchive(
+                fatal.to_string().into(),
+                path.as_ref().to_path_buf(),
+            ));
+        }
+        let archive = ZArchiveReader::open(path.as_ref())?;
+        let dirs = TitleDirs::find(&archive)?;
+        let part =
+            |name: &'static str, folder: Option<String>, content: &str, sentinel: &'static str| {
+                let found = folder.as_ref().is_some_and(|folder| {
+                    archive
+                        .file_size(Path::new(folder).join(content).join(sentinel))
+                        .is_some()
+                });
+                ZArchivePart {
+                    name,
+                    folder,
+                    sentinel,
+                    found,
+                }
+            };
+        let region = [&dirs.content, &dirs.update, &dirs.aoc]
+            .into_iter()
+            .flatten()
+            .find_map(|dir| title_region(dir));
+        Ok(ZArchiveReport {
+            host_path: path.as_ref().to_path_buf(),
+            content: part("base game", dirs.content, "content", CONTENT_TEST),
+            update: part("update", dirs.update, "content", UPDATE_TEST),
+            aoc: part("DLC", dirs.aoc, "content/0010", AOC_TEST),
+            region,
+            damage,
         })
     }
 }
@@ -49,14 +718,17 @@ impl ZArchive {
 #[typetag::serde]
 impl super::ResourceLoader for ZArchive {
     fn get_data(&self, name: &Path) -> Result<Vec<u8>> {
-        self.archive
-            .read_file(self.update_dir.join(name))
-            .or_else(|| self.archive.read_file(self.content_dir.join(name)))
-            .or_else(|| {
-                self.aoc_dir
-                    .as_ref()
-                    .and_then(|aoc| self.archive.read_file(aoc.join(name)))
-            })
+        self.pool
+            .read_first(
+                [
+                    Some(&self.update_dir),
+                    Some(&self.content_dir),
+                    self.aoc_dir.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .map(|dir| dir.join(name)),
+            )
             .ok_or_else(|| {
                 crate::ROMError::FileNotFound(name.to_string_lossy().into(), self.host_path.clone())
             })
@@ -66,7 +738,7 @@ impl super::ResourceLoader for ZArchive {
         self.aoc_dir
             .as_ref()
             .map(|dir| {
-                self.archive.read_file(dir.join(name)).ok_or_else(|| {
+                self.pool.read_first([dir.join(name)]).ok_or_else(|| {
                     crate::ROMError::FileNotFound(
                         name.to_string_lossy().into(),
                         self.host_path.clone(),
@@ -81,22 +753,66 @@ impl super::ResourceLoader for ZArchive {
             })
     }
 
+    fn open_stream(&self, name: &Path) -> Result<Box<dyn Read + Send>> {
+        match self.open_first(
+            [
+                Some(&self.update_dir),
+                Some(&self.content_dir),
+                self.aoc_dir.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.join(name)),
+        )? {
+            Some(stream) => Ok(Box::new(stream)),
+            None => {
+                Err(crate::ROMError::FileNotFound(
+                    name.to_string_lossy().into(),
+                    self.host_path.clone(),
+                ))
+            }
+        }
+    }
+
     fn file_exists(&self, name: &Path) -> bool {
-        self.archive.file_size(self.update_dir.join(name)).is_some()
-            || self
-                .archive
-                .file_size(self.content_dir.join(name))
-                .is_some()
-            || self
-                .aoc_dir
-                .as_ref()
-                .map(|aoc| self.archive.file_size(aoc.join(name)).is_some())
-                .unwrap_or(false)
+        self.pool.with(|archive| {
+            archive.file_size(self.update_dir.join(name)).is_some()
+                || archive.file_size(self.content_dir.join(name)).is_some()
+                || self
+                    .aoc_dir
+                    .as_ref()
+                    .map(|aoc| archive.file_size(aoc.join(name)).is_some())
+                    .unwrap_or(false)
+        })
+    }
+
+    fn list_files(&self, prefix: Option<&Path>) -> Result<Vec<smartstring::alias::String>> {
+        let paths = self.layout()?.files()?.into_iter().filter_map(|file| {
+            let file = Path::new(&file);
+            match self.aoc_dir.as_ref().map(|dir| file.strip_prefix(dir)) {
+                Some(Ok(file)) => Some(canonicalize_aoc(file)),
+                _ => {
+                    [&self.update_dir, &self.content_dir]
+                        .into_iter()
+                        .find_map(|dir| file.strip_prefix(dir).ok())
+                        .map(canonicalize)
+                }
+            }
+        });
+        Ok(crate::listing(paths, prefix))
+    }
+
+    fn has_aoc(&self) -> bool {
+        self.aoc_dir.is_some()
     }
 
     fn host_path(&self) -> &Path {
         &self.host_path
     }
+
+    fn read_stats(&self) -> Option<ReadStats> {
+        Some(self.pool.stats())
+    }
 }
 
 mod de {
@@ -117,6 +833,7 @@ mod de {
                 update_dir,
                 aoc_dir,
                 host_path,
+                readers,
             }
 
             impl<'de> Deserialize<'de> for Field {
@@ -130,7 +847,10 @@ mod de {
                         type Value = Field;
 
                         fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                            write!(f, "`content_dir`, `update_dir`, `aoc_dir`, or `host_path`")
+                            write!(
+                                f,
+                                "`content_dir`, `update_dir`, `aoc_dir`, `host_path`, or `readers`"
+                            )
                         }
 
                         fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
@@ -142,6 +862,7 @@ mod de {
                                 "update_dir" => Ok(Field::update_dir),
                                 "aoc_dir" => Ok(Field::aoc_dir),
                                 "host_path" => Ok(Field::host_path),
+                                "readers" => Ok(Field::readers),
                                 _ => Err(serde::de::Error::custom(format!("unknown field: {}", v))),
                             }
                         }
@@ -167,6 +888,7 @@ mod de {
                     let mut update_dir: Option<PathBuf> = None;
                     let mut aoc_dir: Option<PathBuf> = None;
                     let mut host_path: Option<PathBuf> = None;
+                    let mut readers: Option<usize> = None;
                     while let Some(key) = map.next_key()? {
                         match key {
                             Field::content_dir => {
@@ -181,6 +903,9 @@ mod de {
                             Field::host_path => {
                                 host_path = Some(map.next_value()?);
                             }
+                            Field::readers => {
+                                readers = Some(map.next_value()?);
+                            }
                         }
                     }
                     let content_dir = content_dir
@@ -189,18 +914,29 @@ mod de {
                         update_dir.ok_or_else(|| serde::de::Error::missing_field("update_dir"))?;
                     let host_path =
                         host_path.ok_or_else(|| serde::de::Error::missing_field("host_path"))?;
+                    // Settings saved before the reader pool existed have no
+                    // handle count
+                    let readers = readers.unwrap_or_else(default_zarchive_readers).max(1);
                     Ok(ZArchive {
-                        archive: ::zarchive::reader::ZArchiveReader::open(&host_path)
+                        pool: ReaderPool::open(&host_path, readers)
                             .map_err(serde::de::Error::custom)?,
                         content_dir,
                         update_dir,
                         aoc_dir,
                         host_path,
+                        readers,
+                        layout: OnceLock::new(),
                     })
                 }
             }
 
-            const FIELDS: &[&str] = &["content_dir", "update_dir", "aoc_dir", "host_path"];
+            const FIELDS: &[&str] = &[
+                "content_dir",
+                "update_dir",
+                "aoc_dir",
+                "host_path",
+                "readers",
+            ];
             deserializer.deserialize_struct("ZArchive", FIELDS, ZArchiveVisitor)
         }
     }
@@ -208,18 +944,401 @@ mod de {
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        collections::{BTreeMap, VecDeque},
+        path::Path,
+    };
+
+    use super::{AOC_TEST, CONTENT_TEST, MAGIC, UPDATE_TEST};
     use crate::ResourceLoader;
 
+    /// Writes an archive of `files`, each block compressed on its own as the
+    /// real writer does, or stored if that is no smaller, so dumps can be
+    /// made without parts of the game.
+    fn write_archive(path: &Path, files: &[(String, Vec<u8>)]) {
+        const BLOCK: usize = 64 * 1024;
+        enum Node {
+            Dir(BTreeMap<String, Node>),
+            File(u32, u32),
+        }
+        let mut root: BTreeMap<String, Node> = BTreeMap::new();
+        let mut data = vec![];
+        for (file, contents) in files {
+            let mut parts: Vec<&str> = file.split('/').collect();
+            let name = parts.pop().unwrap();
+            let dir = parts.into_iter().fold(&mut root, |dir, part| {
+                match dir
+                    .entry(part.into())
+                    .or_insert_with(|| Node::Dir(BTreeMap::new()))
+                {
+                    Node::Dir(children) => children,
+                    Node::File(..) => panic!("{part} is a file"),
+                }
+            });
+            dir.insert(
+                name.into(),
+                Node::File(data.len() as u32, contents.len() as u32),
+            );
+            data.extend_from_slice(contents);
+        }
+        data.resize(data.len().div_ceil(BLOCK).max(1) * BLOCK, 0);
+
+        // Each offset record has where a run of 16 blocks starts and the
+        // compressed size of each, less one
+        let mut archive = vec![];
+        let mut records = vec![];
+        for (i, block) in data.chunks(BLOCK).enumerate() {
+            if i % 16 == 0 {
+                records.extend((archive.len() as u64).to_be_bytes());
+                records.extend([0; 32]);
+            }
+            let mut compressed = zstd::bulk::compress(block, 0).unwrap();
+            if compressed.len() >= BLOCK {
+                compressed = block.to_vec();
+            }
+            let at = records.len() - 32 + (i % 16) * 2;
+            records[at..at + 2].copy_from_slice(&((compressed.len() - 1) as u16).to_be_bytes());
+            archive.extend(compressed);
+        }
+
+        let mut names = vec![];
+        let mut tree = vec![[0x7FFF_FFFF, 0, 0, 0]];
+        let mut dirs = VecDeque::from([(0, &root)]);
+        while let Some((index, children)) = dirs.pop_front() {
+            tree[index][1] = tree.len() as u32;
+            tree[index][2] = children.len() as u32;
+            let mut children: Vec<_> = children.iter().collect();
+            children.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+            for (name, node) in children {
+                let name_at = names.len() as u32;
+                names.push(name.len() as u8);
+                names.extend(name.as_bytes());
+                match node {
+                    Node::Dir(children) => {
+                        dirs.push_back((tree.len(), children));
+                        tree.push([name_at, 0, 0, 0]);
+                    }
+                    Node::File(offset, size) => {
+                        tree.push([name_at | 0x8000_0000, *offset, *size, 0]);
+                    }
+                }
+            }
+        }
+        let tree: Vec<u8> = tree
+            .into_iter()
+            .flatten()
+            .flat_map(u32::to_be_bytes)
+            .collect();
+
+        let mut footer = vec![];
+        footer.extend(0u64.to_be_bytes());
+        footer.extend((archive.len() as u64).to_be_bytes());
+        for section in [records, names, tree, vec![], vec![]] {
+            footer.extend((archive.len() as u64).to_be_bytes());
+            footer.extend((section.len() as u64).to_be_bytes());
+            archive.extend(section);
+        }
+        // The integrity hash is not checked when reading
+        footer.extend([0; 32]);
+        footer.extend((archive.len() as u64 + super::FOOTER_SIZE).to_be_bytes());
+        footer.extend(0x61BF_3A01u32.to_be_bytes());
+        footer.extend(MAGIC.to_be_bytes());
+        archive.extend(footer);
+        std::fs::write(path, archive).unwrap();
+    }
+
+    /// The files of a complete dump of the release with a title ID: the base
+    /// game, the update and the DLC, each with its sentinel file.
+    fn dump(title_id: &str) -> Vec<(String, Vec<u8>)> {
+        let base = format!("00050000{title_id}_v0/content");
+        let update = format!("0005000e{title_id}_v208/content");
+        let aoc = format!("0005000c{title_id}_v80/content/0010");
+        [
+            (format!("{base}/System/Version.txt"), "1.0.0"),
+            (format!("{base}/{CONTENT_TEST}"), "base"),
+            (format!("{update}/System/Version.txt"), "1.5.0"),
+            (format!("{update}/{UPDATE_TEST}"), "update"),
+            (format!("{aoc}/{AOC_TEST}"), "aoc"),
+        ]
+        .into_iter()
+        .map(|(file, data)| (file, data.as_bytes().to_vec()))
+        .collect()
+    }
+
     #[test]
     fn test_wua() {
         use super::*;
-        let arch = ZArchive::new("test/test.wua").unwrap();
-        for dir in arch.archive.iter().unwrap() {
-            println!("{}", dir.name());
-        }
+        let arch = ZArchive::new("test/test.wua", 1).unwrap();
+        arch.pool.with(|archive| {
+            for dir in archive.iter().unwrap() {
+                println!("{}", dir.name());
+            }
+        });
         assert_eq!(
             "0.9.0".to_string(),
             String::from_utf8(arch.get_data("System/Version.txt".as_ref()).unwrap()).unwrap()
         );
     }
+
+    #[test]
+    fn list_files() {
+        use super::*;
+        let arch = ZArchive::new("test/test.wua", 1).unwrap();
+        assert_eq!(arch.list_files(None).unwrap(), [
+            "Actor/Pack/Animal_Fox_B.bactorpack",
+            "Aoc/0010/Map/AocField/A-1/A-1_Dynamic.mubin",
+            "Font/Font_US.bfarc",
+            "System/Version.txt",
+        ]);
+        assert_eq!(arch.list_files(Some("Aoc/0010/Map".as_ref())).unwrap(), [
+            "Aoc/0010/Map/AocField/A-1/A-1_Dynamic.mubin"
+        ]);
+        assert!(arch.has_aoc());
+    }
+
+    #[test]
+    fn concurrent_reads() {
+        use super::*;
+        let single = ZArchive::new("test/test.wua", 1).unwrap();
+        let expected = single.get_data("System/Version.txt".as_ref()).unwrap();
+        let pooled = ZArchive::new("test/test.wua", 4).unwrap();
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..64 {
+                        assert_eq!(
+                            pooled.get_data("System/Version.txt".as_ref()).unwrap(),
+                            expected
+                        );
+                        assert!(pooled.file_exists("System/Version.txt".as_ref()));
+                    }
+                });
+            }
+        });
+        let stats = pooled.pool.stats();
+        assert_eq!(stats.handles, 4);
+        assert_eq!(stats.reads, 8 * 64 * 2);
+        assert!(stats.contended <= stats.reads);
+    }
+
+    #[test]
+    fn readers_setting() {
+        use super::*;
+        let arch = ZArchive::new("test/test.wua", 0).unwrap();
+        assert_eq!(arch.readers, 1);
+        let json = serde_json::to_string(&arch).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let de: ZArchive = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(de.pool.stats().handles, 1);
+        value.as_object_mut().unwrap().remove("readers");
+        let de: ZArchive = serde_json::from_value(value).unwrap();
+        assert_eq!(de.readers, default_zarchive_readers());
+    }
+
+    #[test]
+    fn streams() {
+        use super::*;
+        let read_stream = |mut stream: Box<dyn Read + Send>| {
+            let mut data = vec![];
+            stream.read_to_end(&mut data).unwrap();
+            data
+        };
+        let arch = ZArchive::new("test/test.wua", 1).unwrap();
+        for file in arch.layout().unwrap().files().unwrap() {
+            let stream = arch.open_first([PathBuf::from(&file)]).unwrap().unwrap();
+            assert_eq!(
+                read_stream(Box::new(stream)),
+                arch.pool.read_first([PathBuf::from(&file)]).unwrap(),
+                "{file}"
+            );
+        }
+
+        // Files spanning several blocks, some of which do not compress
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        let noise: Vec<u8> = (0..150_000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect();
+        let pattern: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let mut files = dump("101c9400");
+        let update = "0005000e101c9400_v208/content";
+        files.push((format!("{update}/Pack/Noise.pack"), noise.clone()));
+        files.push((format!("{update}/Pack/Pattern.pack"), pattern.clone()));
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("large.wua");
+        write_archive(&path, &files);
+        let arch = ZArchive::new(&path, 1).unwrap();
+        for (file, expected) in [("Pack/Noise.pack", &noise), ("pack/PATTERN.pack", &pattern)] {
+            assert_eq!(arch.get_data(file.as_ref()).unwrap(), *expected);
+            assert_eq!(
+                read_stream(arch.open_stream(file.as_ref()).unwrap()),
+                *expected
+            );
+        }
+        // Small reads cross block boundaries
+        let mut stream = arch.open_stream("Pack/Pattern.pack".as_ref()).unwrap();
+        let mut data = vec![];
+        let mut buf = [0u8; 1000];
+        loop {
+            let read = stream.read(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..read]);
+        }
+        assert_eq!(data, pattern);
+        let mut out = vec![];
+        assert_eq!(
+            arch.copy_to("System/Version.txt".as_ref(), &mut out)
+                .unwrap(),
+            5
+        );
+        assert_eq!(out, b"1.5.0");
+        assert!(matches!(
+            arch.open_stream("Pack/Missing.pack".as_ref()),
+            Err(ROMError::FileNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn inspect() {
+        use super::*;
+        let tmp = tempfile::tempdir().unwrap();
+        let inspect = |name: &str, files: Vec<(String, Vec<u8>)>| {
+            let path = tmp.path().join(name);
+            write_archive(&path, &files);
+            ZArchive::inspect(&path).unwrap()
+        };
+        let without = |files: Vec<(String, Vec<u8>)>, skip: &str| -> Vec<_> {
+            files
+                .into_iter()
+                .filter(|(file, _)| !file.contains(skip))
+                .collect()
+        };
+
+        let report = inspect("full.wua", dump("101c9400"));
+        assert!(report.content.is_present());
+        assert!(report.update.is_present());
+        assert!(report.aoc.is_present());
+        assert_eq!(report.region, Some("US"));
+        assert_eq!(report.errors().count(), 0);
+        assert!(report.warning().is_none());
+        report.check().unwrap();
+        // The update is read over the base game
+        let arch = ZArchive::new(tmp.path().join("full.wua"), 1).unwrap();
+        assert_eq!(
+            arch.get_data("System/Version.txt".as_ref()).unwrap(),
+            b"1.5.0"
+        );
+        assert!(arch.has_aoc());
+
+        // Only the base game
+        let report = inspect(
+            "base.wua",
+            without(without(dump("101c9400"), "0005000e"), "0005000c"),
+        );
+        assert_eq!(report.errors().map(|part| part.name).collect::<Vec<_>>(), [
+            "update"
+        ]);
+        assert_eq!(report.warning().map(|part| part.name), Some("DLC"));
+        assert!(matches!(
+            report.check(),
+            Err(ROMError::MissingDumpDir("update", _))
+        ));
+        assert_eq!(report.update.to_string(), "The update is missing");
+
+        // Missing DLC is only a warning
+        let report = inspect("no_aoc.wua", without(dump("101c9500"), "0005000c"));
+        assert_eq!(report.region, Some("EU"));
+        assert_eq!(report.errors().count(), 0);
+        assert!(report.aoc.folder.is_none());
+        report.check().unwrap();
+
+        // An update folder without the update's files
+        let report = inspect("stale.wua", without(dump("101c9300"), UPDATE_TEST));
+        assert_eq!(report.region, Some("JP"));
+        assert_eq!(
+            report.update.folder.as_deref(),
+            Some("0005000e101c9300_v208")
+        );
+        assert!(!report.update.found);
+        assert!(report.update.to_string().contains("incomplete"));
+        assert!(report.check().is_err());
+
+        // Neither the base game nor the update
+        let report = inspect(
+            "aoc.wua",
+            without(without(dump("101c9400"), "00050000"), "0005000e"),
+        );
+        assert!(report.aoc.folder.is_some());
+        assert!(matches!(
+            report.check(),
+            Err(ROMError::MissingDumpDir("base game and update", _))
+        ));
+    }
+    #[test]
+    fn damage() {
+        use super::*;
+        assert!(verify(Path::new("test/test.wua")).unwrap().is_empty());
+
+        let tmp = tempfile::tempdir().unwrap();
+        let full = tmp.path().join("full.wua");
+        write_archive(&full, &dump("101c9400"));
+        let archive = std::fs::read(&full).unwrap();
+        assert!(verify(&full).unwrap().is_empty());
+        ZArchive::inspect(&full).unwrap().check().unwrap();
+        // Rewrites a field of the footer in a copy of the archive
+        let patched = |name: &str, field: usize, value: u64| {
+            let mut archive = archive.clone();
+            let at = archive.len() - FOOTER_SIZE as usize + field;
+            archive[at..at + 8].copy_from_slice(&value.to_be_bytes());
+            let path = tmp.path().join(name);
+            std::fs::write(&path, archive).unwrap();
+            path
+        };
+
+        let cut = tmp.path().join("cut.wua");
+        std::fs::write(&cut, &archive[..archive.len() - 10]).unwrap();
+        assert_eq!(verify(&cut).unwrap(), [ZArchiveDamage::NoFooter]);
+        assert!(matches!(
+            ZArchive::inspect(&cut),
+            Err(ROMError::DamagedArchive(..))
+        ));
+
+        let size = patched("size.wua", 128, archive.len() as u64 + 1);
+        assert_eq!(verify(&size).unwrap(), [ZArchiveDamage::WrongSize {
+            expected: archive.len() as u64 + 1,
+            actual:   archive.len() as u64,
+        }]);
+
+        let names = patched("names.wua", 2 * 16 + 8, u32::MAX as u64);
+        assert_eq!(verify(&names).unwrap(), [ZArchiveDamage::Section("names")]);
+
+        // The data of every file is past the end of the compressed data, but
+        // the archive still opens
+        let data = patched("data.wua", 8, 1);
+        let report = ZArchive::inspect(&data).unwrap();
+        assert!(matches!(&report.damage[..], [ZArchiveDamage::Files(files)] if files.len() == 5));
+        assert!(report.content.is_present());
+        assert!(matches!(report.check(), Err(ROMError::DamagedArchive(..))));
+        ZArchive::new(&data, 1).unwrap();
+
+        // Without the update
+        let base = tmp.path().join("base.wua");
+        write_archive(
+            &base,
+            &dump("101c9400")
+                .into_iter()
+                .filter(|(file, _)| !file.starts_with("0005000e"))
+                .collect::<Vec<_>>(),
+        );
+        assert!(matches!(
+            ZArchive::inspect(&base).unwrap().check(),
+            Err(ROMError::MissingDumpDir("update", _))
+        ));
+    }
 }
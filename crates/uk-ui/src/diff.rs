@@ -0,0 +1,136 @@
+//! A view of the differences between two versions of an AAMP parameter list
+//! or BYML document, as a tree built by the [`tree`] module. Added nodes are
+//! green, removed ones red, and changed values show the old and new value
+//! side by side. Subtrees with no changes start collapsed, and a search box
+//! filters the tree by key name.
+use std::hash::Hash;
+
+use egui::{CollapsingHeader, Id, RichText, TextEdit, Ui};
+use roead::aamp::get_default_name_table;
+
+use crate::visuals;
+
+pub mod tree;
+pub use tree::{
+    diff_byml, diff_plist, overlay_byml, overlay_plist, Content, DiffNode, Key, Status,
+};
+
+/// Looks up the name for an AAMP hash.
+pub type Names<'a> = &'a dyn Fn(u32) -> Option<String>;
+
+fn status_color(ui: &Ui, status: Status) -> egui::Color32 {
+    match status {
+        Status::Same => ui.visuals().weak_text_color(),
+        Status::Added => visuals::GREEN,
+        Status::Removed => visuals::RED,
+        Status::Changed => visuals::YELLOW,
+    }
+}
+
+/// Renders a diff tree with a search box above it.
+pub struct DiffView<'a> {
+    id:    Id,
+    nodes: &'a [DiffNode],
+    names: Option<Names<'a>>,
+}
+
+impl<'a> DiffView<'a> {
+    pub fn new(id: impl Hash, nodes: &'a [DiffNode]) -> Self {
+        Self {
+            id: Id::new(id),
+            nodes,
+            names: None,
+        }
+    }
+
+    /// Looks up hashed names here before the default name table, for callers
+    /// with more names to hand, like the labels built from the game's data.
+    pub fn names(mut self, names: Names<'a>) -> Self {
+        self.names = Some(names);
+        self
+    }
+
+    pub fn show(self, ui: &mut Ui) {
+        let table = get_default_name_table();
+        let custom = self.names;
+        let names = |hash: u32| {
+            custom
+                .and_then(|names| names(hash))
+                .or_else(|| table.get_name(hash, 0, 0).map(|name| name.to_string()))
+        };
+        let search_id = self.id.with("search");
+        let mut query: String = ui
+            .data_mut(|data| data.get_temp(search_id))
+            .unwrap_or_default();
+        ui.add(
+            TextEdit::singleline(&mut query)
+                .hint_text("Search keys")
+                .desired_width(f32::INFINITY),
+        );
+        let lowercase = query.trim().to_lowercase();
+        ui.data_mut(|data| data.insert_temp(search_id, query));
+        if self.nodes.is_empty() {
+            ui.label(RichText::new("No differences").color(ui.visuals().weak_text_color()));
+        }
+        for node in self.nodes {
+            render_node(ui, self.id, node, &lowercase, &names);
+        }
+    }
+}
+
+fn render_node(ui: &mut Ui, id: Id, node: &DiffNode, query: &str, names: Names<'_>) {
+    let name = node.key.name(names);
+    let searching = !query.is_empty();
+    // Everything under a node whose own name matches is shown
+    let query = if name.to_lowercase().contains(query) {
+        ""
+    } else if node.children().iter().any(|c| c.matches(query, names)) {
+        query
+    } else {
+        return;
+    };
+    let id = id.with(&node.key);
+    let color = status_color(ui, node.status);
+    match &node.content {
+        Content::Branch(children) => {
+            let label = match node.status {
+                Status::Same => format!("{name} · {} unchanged", node.unchanged()),
+                Status::Changed => format!("{name} · {} changed", node.changes()),
+                _ => name,
+            };
+            CollapsingHeader::new(RichText::new(label).color(color))
+                .id_source(id)
+                .default_open(node.status == Status::Changed)
+                .open(searching.then_some(true))
+                .show(ui, |ui| {
+                    for child in children {
+                        render_node(ui, id, child, query, names);
+                    }
+                });
+        }
+        Content::Leaf { old, new } => {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(RichText::new(name).color(color));
+                match (old, new) {
+                    (Some(old), Some(new)) if node.status == Status::Changed => {
+                        ui.label(
+                            RichText::new(old)
+                                .monospace()
+                                .color(visuals::RED)
+                                .strikethrough(),
+                        );
+                        ui.label("→");
+                        ui.label(RichText::new(new).monospace().color(visuals::GREEN));
+                    }
+                    (_, Some(value)) => {
+                        ui.label(RichText::new(value).monospace().color(color));
+                    }
+                    (Some(old), None) => {
+                        ui.label(RichText::new(old).monospace().color(color).strikethrough());
+                    }
+                    (None, None) => (),
+                }
+            });
+        }
+    }
+}
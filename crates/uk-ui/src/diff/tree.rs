@@ -0,0 +1,540 @@
+//! The model behind the diff view: two versions of an AAMP parameter list or
+//! a BYML document merged into one tree, with each node marked as added,
+//! removed, changed or the same. Building the tree does not touch the UI, so
+//! it can be tested on its own.
+//!
+//! Trees can be built from two full values, or from a base and a diff in the
+//! form mods store them, which holds only what the mod changes. Anything a
+//! diff leaves out is the same as the base.
+use std::{fmt::Write, hash::Hash};
+
+use roead::{
+    aamp::{Parameter, ParameterList, ParameterListing, ParameterObject},
+    byml::Byml,
+};
+use rustc_hash::FxHashMap;
+
+/// Values longer than this are cut short when shown.
+const MAX_VALUE_LEN: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Same,
+    Added,
+    Removed,
+    Changed,
+}
+
+/// What a node is called in its parent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// An AAMP name, which is stored only as its hash.
+    Hash(u32),
+    Name(String),
+    Index(usize),
+}
+
+impl Key {
+    /// The name to show for the key, looking up hashes with `names` and
+    /// falling back to the hash in hex.
+    pub fn name(&self, names: &dyn Fn(u32) -> Option<String>) -> String {
+        match self {
+            Key::Hash(hash) => names(*hash).unwrap_or_else(|| format!("{hash:#010x}")),
+            Key::Name(name) => name.clone(),
+            Key::Index(index) => format!("[{index}]"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Branch(Vec<DiffNode>),
+    /// A value, formatted for display, as it was and as it is now.
+    Leaf {
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffNode {
+    pub key:     Key,
+    pub status:  Status,
+    pub content: Content,
+}
+
+impl DiffNode {
+    /// A value, compared before formatting, since formatting can hide small
+    /// differences.
+    fn leaf(key: Key, old: Option<String>, new: Option<String>, same: bool) -> Self {
+        let status = match (&old, &new) {
+            (None, _) => Status::Added,
+            (_, None) => Status::Removed,
+            _ if same => Status::Same,
+            _ => Status::Changed,
+        };
+        Self {
+            key,
+            status,
+            content: Content::Leaf { old, new },
+        }
+    }
+
+    /// A branch whose status follows from where it is found and what its
+    /// children are.
+    fn branch(key: Key, in_base: bool, in_other: bool, children: Vec<DiffNode>) -> Self {
+        let status = match (in_base, in_other) {
+            (false, _) => Status::Added,
+            (_, false) => Status::Removed,
+            _ if children.iter().all(|c| c.status == Status::Same) => Status::Same,
+            _ => Status::Changed,
+        };
+        Self {
+            key,
+            status,
+            content: Content::Branch(children),
+        }
+    }
+
+    pub fn children(&self) -> &[DiffNode] {
+        match &self.content {
+            Content::Branch(children) => children,
+            Content::Leaf { .. } => &[],
+        }
+    }
+
+    /// How many values under the node, or the node itself if it is a value,
+    /// are the same on both sides.
+    pub fn unchanged(&self) -> usize {
+        match &self.content {
+            Content::Branch(children) => children.iter().map(Self::unchanged).sum(),
+            Content::Leaf { .. } => usize::from(self.status == Status::Same),
+        }
+    }
+
+    /// How many values under the node, or the node itself if it is a value,
+    /// differ.
+    pub fn changes(&self) -> usize {
+        match &self.content {
+            Content::Branch(children) => children.iter().map(Self::changes).sum(),
+            Content::Leaf { .. } => usize::from(self.status != Status::Same),
+        }
+    }
+
+    /// Whether the node's name or any name under it contains the query,
+    /// ignoring case. The query should already be lowercase.
+    pub fn matches(&self, query: &str, names: &dyn Fn(u32) -> Option<String>) -> bool {
+        self.key.name(names).to_lowercase().contains(query)
+            || self.children().iter().any(|c| c.matches(query, names))
+    }
+}
+
+/// Pairs up the entries of two keyed collections, in the base's order with
+/// new keys after. When `overlay` is set, the other side is a diff, so keys
+/// it leaves out are the same as the base.
+fn pair<'a, K: Clone + Eq + Hash, V>(
+    base: impl IntoIterator<Item = (K, &'a V)>,
+    other: impl IntoIterator<Item = (K, &'a V)>,
+    overlay: bool,
+) -> Vec<(K, Option<&'a V>, Option<&'a V>)> {
+    let other: Vec<(K, &V)> = other.into_iter().collect();
+    let index: FxHashMap<K, usize> = other
+        .iter()
+        .enumerate()
+        .map(|(i, (k, _))| (k.clone(), i))
+        .collect();
+    let mut used = vec![false; other.len()];
+    let mut pairs: Vec<_> = base
+        .into_iter()
+        .map(|(k, v)| {
+            let found = index.get(&k).map(|i| {
+                used[*i] = true;
+                other[*i].1
+            });
+            let theirs = found.or_else(|| overlay.then_some(v));
+            (k, Some(v), theirs)
+        })
+        .collect();
+    pairs.extend(
+        other
+            .into_iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|((k, v), _)| (k, None, Some(v))),
+    );
+    pairs
+}
+
+/// Cuts a formatted value short if it is too long to show on one line.
+fn shorten(mut value: String) -> String {
+    if value.chars().count() > MAX_VALUE_LEN {
+        let end = value
+            .char_indices()
+            .nth(MAX_VALUE_LEN - 1)
+            .map(|(i, _)| i)
+            .unwrap_or(value.len());
+        value.truncate(end);
+        value.push('…');
+    }
+    value
+}
+
+fn quoted(text: &str) -> String {
+    format!("{text:?}")
+}
+
+/// Formats a parameter value for display.
+pub fn format_param(param: &Parameter) -> String {
+    let mut out = String::new();
+    let _ = match param {
+        Parameter::String32(v) => write!(out, "{}", quoted(v)),
+        Parameter::String64(v) => write!(out, "{}", quoted(v)),
+        Parameter::String256(v) => write!(out, "{}", quoted(v)),
+        Parameter::StringRef(v) => write!(out, "{}", quoted(v)),
+        Parameter::Bool(v) => write!(out, "{v}"),
+        Parameter::F32(v) => write!(out, "{v}"),
+        Parameter::I32(v) => write!(out, "{v}"),
+        Parameter::U32(v) => write!(out, "{v}"),
+        Parameter::Vec2(v) => write!(out, "({}, {})", v.x, v.y),
+        Parameter::Vec3(v) => write!(out, "({}, {}, {})", v.x, v.y, v.z),
+        Parameter::Vec4(v) => write!(out, "({}, {}, {}, {})", v.x, v.y, v.z, v.t),
+        Parameter::Color(v) => write!(out, "rgba({}, {}, {}, {})", v.r, v.g, v.b, v.a),
+        other => write!(out, "{other:?}"),
+    };
+    shorten(out)
+}
+
+fn param_node(key: u32, base: Option<&Parameter>, other: Option<&Parameter>) -> DiffNode {
+    DiffNode::leaf(
+        Key::Hash(key),
+        base.map(format_param),
+        other.map(format_param),
+        base == other,
+    )
+}
+
+fn pobj_node(
+    key: u32,
+    base: Option<&ParameterObject>,
+    other: Option<&ParameterObject>,
+    overlay: bool,
+) -> DiffNode {
+    fn entries(obj: Option<&ParameterObject>) -> Vec<(u32, &Parameter)> {
+        obj.into_iter()
+            .flat_map(|obj| obj.0.iter().map(|(k, v)| (k.hash(), v)))
+            .collect()
+    }
+    let children = pair(entries(base), entries(other), overlay)
+        .into_iter()
+        .map(|(k, base, other)| param_node(k, base, other))
+        .collect();
+    DiffNode::branch(Key::Hash(key), base.is_some(), other.is_some(), children)
+}
+
+fn plist_children<P: ParameterListing>(
+    base: Option<&P>,
+    other: Option<&P>,
+    overlay: bool,
+) -> Vec<DiffNode> {
+    fn objects<P: ParameterListing>(list: Option<&P>) -> Vec<(u32, &ParameterObject)> {
+        list.into_iter()
+            .flat_map(|list| list.objects().0.iter().map(|(k, v)| (k.hash(), v)))
+            .collect()
+    }
+    fn lists<P: ParameterListing>(list: Option<&P>) -> Vec<(u32, &ParameterList)> {
+        list.into_iter()
+            .flat_map(|list| list.lists().0.iter().map(|(k, v)| (k.hash(), v)))
+            .collect()
+    }
+    let mut children: Vec<DiffNode> = pair(objects(base), objects(other), overlay)
+        .into_iter()
+        .map(|(k, base, other)| pobj_node(k, base, other, overlay))
+        .collect();
+    children.extend(
+        pair(lists(base), lists(other), overlay)
+            .into_iter()
+            .map(|(k, base, other)| plist_node(k, base, other, overlay)),
+    );
+    children
+}
+
+fn plist_node(
+    key: u32,
+    base: Option<&ParameterList>,
+    other: Option<&ParameterList>,
+    overlay: bool,
+) -> DiffNode {
+    DiffNode::branch(
+        Key::Hash(key),
+        base.is_some(),
+        other.is_some(),
+        plist_children(base, other, overlay),
+    )
+}
+
+/// Compares two full parameter lists, or parameter IOs, returning the
+/// objects and lists at the top.
+pub fn diff_plist<P: ParameterListing>(base: &P, other: &P) -> Vec<DiffNode> {
+    plist_children(Some(base), Some(other), false)
+}
+
+/// Shows what a diff, in the form mods store them, changes in a parameter
+/// list.
+pub fn overlay_plist<P: ParameterListing>(base: &P, diff: &P) -> Vec<DiffNode> {
+    plist_children(Some(base), Some(diff), true)
+}
+
+/// Formats a BYML value which is not a container for display.
+pub fn format_byml(value: &Byml) -> String {
+    shorten(match value {
+        Byml::Bool(v) => v.to_string(),
+        Byml::I32(v) => v.to_string(),
+        Byml::U32(v) => v.to_string(),
+        Byml::I64(v) => v.to_string(),
+        Byml::U64(v) => v.to_string(),
+        Byml::Float(v) => v.to_string(),
+        Byml::Double(v) => v.to_string(),
+        Byml::String(v) => quoted(v),
+        Byml::Null => "null".into(),
+        other => format!("{other:?}"),
+    })
+}
+
+fn byml_children(base: Option<&Byml>, other: Option<&Byml>, overlay: bool) -> Vec<DiffNode> {
+    // Both sides are the same kind of container, or one is missing
+    let array = matches!(base, Some(Byml::Array(_))) || matches!(other, Some(Byml::Array(_)));
+    if array {
+        fn items(value: Option<&Byml>) -> Vec<(usize, &Byml)> {
+            match value {
+                Some(Byml::Array(items)) => items.iter().enumerate().collect(),
+                _ => vec![],
+            }
+        }
+        // Arrays in a diff replace the base's, so items are never overlaid
+        pair(items(base), items(other), false)
+            .into_iter()
+            .map(|(i, base, other)| byml_node(Key::Index(i), base, other, false))
+            .collect()
+    } else {
+        fn entries(value: Option<&Byml>) -> Vec<(String, &Byml)> {
+            let mut entries: Vec<_> = match value {
+                Some(Byml::Map(map)) => map.iter().map(|(k, v)| (k.to_string(), v)).collect(),
+                _ => vec![],
+            };
+            // Maps have no order of their own, so keep the view steady
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries
+        }
+        pair(entries(base), entries(other), overlay)
+            .into_iter()
+            .map(|(k, base, other)| {
+                // Diffs delete keys by setting them to null
+                let other = other.filter(|v| !(overlay && matches!(v, Byml::Null)));
+                byml_node(Key::Name(k), base, other, overlay)
+            })
+            .collect()
+    }
+}
+
+fn is_container(value: Option<&Byml>) -> bool {
+    matches!(value, Some(Byml::Map(_) | Byml::Array(_)))
+}
+
+fn byml_node(key: Key, base: Option<&Byml>, other: Option<&Byml>, overlay: bool) -> DiffNode {
+    let same_kind = match (base, other) {
+        (Some(base), Some(other)) => std::mem::discriminant(base) == std::mem::discriminant(other),
+        _ => true,
+    };
+    if same_kind && (is_container(base) || is_container(other)) {
+        DiffNode::branch(
+            key,
+            base.is_some(),
+            other.is_some(),
+            byml_children(base, other, overlay),
+        )
+    } else {
+        DiffNode::leaf(
+            key,
+            base.map(format_byml),
+            other.map(format_byml),
+            base == other,
+        )
+    }
+}
+
+/// Compares two full BYML documents, returning the entries at the top.
+pub fn diff_byml(base: &Byml, other: &Byml) -> Vec<DiffNode> {
+    byml_node(Key::Index(0), Some(base), Some(other), false)
+        .children()
+        .to_vec()
+}
+
+/// Shows what a diff changes in a BYML document, taking maps in the diff to
+/// hold only the keys it changes, with null for keys it deletes, and anything
+/// else to replace the base.
+pub fn overlay_byml(base: &Byml, diff: &Byml) -> Vec<DiffNode> {
+    byml_node(Key::Index(0), Some(base), Some(diff), true)
+        .children()
+        .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use roead::aamp::hash_name;
+
+    use super::*;
+
+    fn names(hash: u32) -> Option<String> {
+        [
+            "Param", "Other", "Changed", "Added", "Removed", "Obj", "List",
+        ]
+        .into_iter()
+        .find(|n| hash_name(n) == hash)
+        .map(String::from)
+    }
+
+    fn find<'a>(nodes: &'a [DiffNode], name: &str) -> &'a DiffNode {
+        nodes
+            .iter()
+            .find(|n| n.key.name(&names) == name)
+            .unwrap_or_else(|| panic!("No node {name}"))
+    }
+
+    fn plist(changed: f32, extra: bool) -> ParameterList {
+        let mut obj = ParameterObject::new()
+            .with_parameter("Param", Parameter::I32(1))
+            .with_parameter("Changed", Parameter::F32(changed));
+        if extra {
+            obj = obj.with_parameter("Added", Parameter::Bool(true));
+        } else {
+            obj = obj.with_parameter("Removed", Parameter::Bool(false));
+        }
+        ParameterList::new().with_object("Obj", obj).with_list(
+            "List",
+            ParameterList::new().with_object(
+                "Other",
+                ParameterObject::new().with_parameter("Param", Parameter::I32(2)),
+            ),
+        )
+    }
+
+    #[test]
+    fn plists() {
+        let tree = diff_plist(&plist(1.0, false), &plist(2.5, true));
+        let obj = find(&tree, "Obj");
+        assert_eq!(obj.status, Status::Changed);
+        assert_eq!(find(obj.children(), "Param").status, Status::Same);
+        assert_eq!(find(obj.children(), "Changed"), &DiffNode {
+            key:     Key::Hash(hash_name("Changed")),
+            status:  Status::Changed,
+            content: Content::Leaf {
+                old: Some("1".into()),
+                new: Some("2.5".into()),
+            },
+        });
+        assert_eq!(find(obj.children(), "Added").status, Status::Added);
+        assert_eq!(find(obj.children(), "Removed").status, Status::Removed);
+        assert_eq!((obj.changes(), obj.unchanged()), (3, 1));
+        // The whole list is the same
+        let list = find(&tree, "List");
+        assert_eq!(list.status, Status::Same);
+        assert_eq!((list.changes(), list.unchanged()), (0, 1));
+        // Added after the base's entries
+        let keys: Vec<_> = obj.children().iter().map(|n| n.key.name(&names)).collect();
+        assert_eq!(keys, ["Param", "Changed", "Removed", "Added"]);
+    }
+
+    #[test]
+    fn plist_overlay() {
+        let base = plist(1.0, false);
+        let diff = ParameterList::new().with_object(
+            "Obj",
+            ParameterObject::new()
+                .with_parameter("Changed", Parameter::F32(3.0))
+                .with_parameter("Added", Parameter::Bool(true)),
+        );
+        let tree = overlay_plist(&base, &diff);
+        let obj = find(&tree, "Obj");
+        assert_eq!(find(obj.children(), "Changed").status, Status::Changed);
+        assert_eq!(find(obj.children(), "Added").status, Status::Added);
+        // Left out of the diff, so kept rather than removed
+        assert_eq!(find(obj.children(), "Removed").status, Status::Same);
+        assert_eq!(find(&tree, "List").status, Status::Same);
+        // The same as a comparison with the merged result
+        let mut merged = base.clone();
+        merged.objects_mut().0.insert(
+            hash_name("Obj").into(),
+            ParameterObject::new()
+                .with_parameter("Param", Parameter::I32(1))
+                .with_parameter("Changed", Parameter::F32(3.0))
+                .with_parameter("Removed", Parameter::Bool(false))
+                .with_parameter("Added", Parameter::Bool(true)),
+        );
+        assert_eq!(diff_plist(&base, &merged), tree);
+    }
+
+    #[test]
+    fn keys() {
+        let hash = hash_name("Unknown");
+        assert_eq!(Key::Hash(hash).name(&names), format!("{hash:#010x}"));
+        assert_eq!(Key::Hash(hash).name(&|_| Some("Found".into())), "Found");
+        assert_eq!(Key::Index(3).name(&names), "[3]");
+        let tree = diff_plist(&plist(1.0, false), &plist(2.0, true));
+        let list = find(&tree, "List");
+        assert!(list.matches("oth", &names));
+        assert!(list.matches("list", &names));
+        assert!(!list.matches("changed", &names));
+    }
+
+    fn byml(text: &str) -> Byml {
+        Byml::from_text(text).unwrap()
+    }
+
+    #[test]
+    fn byml_values() {
+        let base = byml("{a: 1, b: {c: foo, d: [1, 2]}, e: true}");
+        let other = byml("{a: 1, b: {c: bar, d: [1, 2, 3]}, f: 1.5}");
+        let tree = diff_byml(&base, &other);
+        let keys: Vec<_> = tree.iter().map(|n| n.key.name(&names)).collect();
+        assert_eq!(keys, ["a", "b", "e", "f"]);
+        assert_eq!(find(&tree, "a").status, Status::Same);
+        assert_eq!(find(&tree, "e").status, Status::Removed);
+        assert_eq!(find(&tree, "f").status, Status::Added);
+        let b = find(&tree, "b");
+        assert_eq!(find(b.children(), "c").content, Content::Leaf {
+            old: Some("\"foo\"".into()),
+            new: Some("\"bar\"".into()),
+        });
+        let d = find(b.children(), "d");
+        assert_eq!(d.status, Status::Changed);
+        assert_eq!(find(d.children(), "[2]").status, Status::Added);
+        // A value which changes type is shown as one change
+        let tree = diff_byml(&base, &byml("{a: [1], b: 1, e: true}"));
+        assert!(matches!(find(&tree, "a").content, Content::Leaf { .. }));
+        assert_eq!(find(&tree, "b").status, Status::Changed);
+    }
+
+    #[test]
+    fn byml_overlay() {
+        let base = byml("{a: 1, b: {c: foo, d: [1, 2]}}");
+        let tree = overlay_byml(&base, &byml("{b: {d: [3]}}"));
+        assert_eq!(find(&tree, "a").status, Status::Same);
+        let b = find(&tree, "b");
+        assert_eq!(find(b.children(), "c").status, Status::Same);
+        // Arrays are replaced whole
+        let d = find(b.children(), "d");
+        assert_eq!(find(d.children(), "[0]").status, Status::Changed);
+        assert_eq!(find(d.children(), "[1]").status, Status::Removed);
+        let tree = overlay_byml(&base, &byml("{a: null}"));
+        assert_eq!(find(&tree, "a").status, Status::Removed);
+        assert_eq!(find(&tree, "b").status, Status::Same);
+    }
+
+    #[test]
+    fn long_values() {
+        let value = format_param(&Parameter::StringRef("x".repeat(200).into()));
+        assert_eq!(value.chars().count(), MAX_VALUE_LEN);
+        assert!(value.ends_with('…'));
+        assert_eq!(format_param(&Parameter::StringRef("x".into())), "\"x\"");
+    }
+}
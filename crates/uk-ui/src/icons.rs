@@ -1,11 +1,14 @@
 #![allow(deprecated)]
-use std::sync::OnceLock;
+use std::sync::LazyLock;
 
-use egui::{Button, ImageButton, Response, Ui, WidgetText};
+use egui::{mutex::RwLock, Button, ImageButton, Response, SizeHint, Ui, WidgetText};
 use egui_extras::RetainedImage;
 use rustc_hash::FxHashMap;
 
-static ICONS: OnceLock<FxHashMap<Icon, RetainedImage>> = OnceLock::new();
+static ICONS: LazyLock<RwLock<FxHashMap<Icon, RetainedImage>>> =
+    LazyLock::new(|| RwLock::new(FxHashMap::default()));
+/// The size the icon SVGs are drawn at, in points.
+const ICON_SIZE: f32 = 24.0;
 
 static ADD: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="24" height="24" viewBox="0 0 24 24"><path stroke="white" fill="white" d="M19 13h-6v6h-2v-6H5v-2h6V5h2v6h6v2z"/></svg>"#;
 static ARROW_BACK: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="24" height="24" viewBox="0 0 24 24"><path stroke="white" fill="white" d="M20 11H7.83l5.59-5.59L12 4l-8 8 8 8 1.41-1.41L7.83 13H20v-2z"/></svg>"#;
@@ -57,101 +60,57 @@ pub enum Icon {
     Tune,
 }
 
+/// The pixel height to rasterize icons at for a given scale.
+pub fn icon_pixels(pixels_per_point: f32) -> u32 {
+    (ICON_SIZE * pixels_per_point.max(1.0)).ceil() as u32
+}
+
 pub fn load_icons() {
+    load_icons_at(1.0)
+}
+
+/// Rasterizes every icon for the given scale, replacing any loaded before.
+/// Call again whenever the pixels per point change so icons stay sharp.
+pub fn load_icons_at(pixels_per_point: f32) {
+    let size = SizeHint::Height(icon_pixels(pixels_per_point));
+    let svg = |name: &str, src: &str| {
+        RetainedImage::from_svg_bytes_with_size(name, src.as_bytes(), Some(size)).unwrap()
+    };
     let mut map = FxHashMap::default();
-    map.insert(
-        Icon::Delete,
-        RetainedImage::from_svg_str("delete", DELETE).unwrap(),
-    );
-    map.insert(
-        Icon::Cancel,
-        RetainedImage::from_svg_str("cancel", CANCEL).unwrap(),
-    );
-    map.insert(
-        Icon::List,
-        RetainedImage::from_svg_str("list", LIST).unwrap(),
-    );
-    map.insert(
-        Icon::Menu,
-        RetainedImage::from_svg_str("menu", MENU).unwrap(),
-    );
-    map.insert(
-        Icon::Check,
-        RetainedImage::from_svg_str("check", CHECK).unwrap(),
-    );
-    map.insert(Icon::Add, RetainedImage::from_svg_str("add", ADD).unwrap());
-    map.insert(
-        Icon::Import,
-        RetainedImage::from_svg_str("import", IMPORT).unwrap(),
-    );
-    map.insert(
-        Icon::Info,
-        RetainedImage::from_svg_str("info", INFO).unwrap(),
-    );
-    map.insert(
-        Icon::Help,
-        RetainedImage::from_svg_str("help", HELP).unwrap(),
-    );
-    map.insert(
-        Icon::Blank,
-        RetainedImage::from_svg_str("blank", BLANK).unwrap(),
-    );
-    map.insert(
-        Icon::Folder,
-        RetainedImage::from_svg_str("folder", FOLDER).unwrap(),
-    );
-    map.insert(
-        Icon::FolderZip,
-        RetainedImage::from_svg_str("archive", FOLDER_ZIP).unwrap(),
-    );
-    map.insert(
-        Icon::FolderOpen,
-        RetainedImage::from_svg_str("folder-open", FOLDER_OPEN).unwrap(),
-    );
-    map.insert(
-        Icon::ArrowUp,
-        RetainedImage::from_svg_str("up", ARROW_UP).unwrap(),
-    );
-    map.insert(
-        Icon::ArrowBack,
-        RetainedImage::from_svg_str("back", ARROW_BACK).unwrap(),
-    );
-    map.insert(
-        Icon::Reset,
-        RetainedImage::from_svg_str("reset", RESET).unwrap(),
-    );
-    map.insert(
-        Icon::Refresh,
-        RetainedImage::from_svg_str("refresh", REFRESH).unwrap(),
-    );
-    map.insert(
-        Icon::Save,
-        RetainedImage::from_svg_str("save", SAVE).unwrap(),
-    );
-    map.insert(
-        Icon::Settings,
-        RetainedImage::from_svg_str("settings", SETTINGS).unwrap(),
-    );
-    map.insert(
-        Icon::Tune,
-        RetainedImage::from_svg_str("tune", TUNE).unwrap(),
-    );
-    map.insert(
-        Icon::Patreon,
-        RetainedImage::from_svg_str("patreon", PATREON).unwrap(),
-    );
-    map.insert(
-        Icon::Bitcoin,
-        RetainedImage::from_svg_str("btc", BITCOIN).unwrap(),
-    );
-    unsafe { ICONS.set(map).unwrap_unchecked() }
+    map.insert(Icon::Delete, svg("delete", DELETE));
+    map.insert(Icon::Cancel, svg("cancel", CANCEL));
+    map.insert(Icon::List, svg("list", LIST));
+    map.insert(Icon::Menu, svg("menu", MENU));
+    map.insert(Icon::Check, svg("check", CHECK));
+    map.insert(Icon::Add, svg("add", ADD));
+    map.insert(Icon::Import, svg("import", IMPORT));
+    map.insert(Icon::Info, svg("info", INFO));
+    map.insert(Icon::Help, svg("help", HELP));
+    map.insert(Icon::Blank, svg("blank", BLANK));
+    map.insert(Icon::Folder, svg("folder", FOLDER));
+    map.insert(Icon::FolderZip, svg("archive", FOLDER_ZIP));
+    map.insert(Icon::FolderOpen, svg("folder-open", FOLDER_OPEN));
+    map.insert(Icon::ArrowUp, svg("up", ARROW_UP));
+    map.insert(Icon::ArrowBack, svg("back", ARROW_BACK));
+    map.insert(Icon::Reset, svg("reset", RESET));
+    map.insert(Icon::Refresh, svg("refresh", REFRESH));
+    map.insert(Icon::Save, svg("save", SAVE));
+    map.insert(Icon::Settings, svg("settings", SETTINGS));
+    map.insert(Icon::Tune, svg("tune", TUNE));
+    map.insert(Icon::Patreon, svg("patreon", PATREON));
+    map.insert(Icon::Bitcoin, svg("btc", BITCOIN));
+    *ICONS.write() = map;
 }
 
 #[inline(always)]
 pub fn get_icon(ctx: &egui::Context, icon: Icon) -> egui::load::SizedTexture {
     let width = ctx.style().spacing.icon_width;
     egui::load::SizedTexture::new(
-        unsafe { ICONS.get().unwrap_unchecked().get(&icon).unwrap_unchecked() }.texture_id(ctx),
+        ICONS
+            .read()
+            .get(&icon)
+            .expect("Icons should be loaded")
+            .texture_id(ctx),
         egui::Vec2::new(width, width),
     )
 }
@@ -1,7 +1,11 @@
+pub mod diff;
 pub mod ext;
 pub mod icons;
+pub mod params;
 mod paths;
+pub mod scale;
 pub mod syntect;
+pub mod tour;
 pub mod visuals;
 pub use egui;
 pub use egui_dock;
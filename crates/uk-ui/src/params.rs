@@ -0,0 +1,554 @@
+//! Editors for AAMP parameter values. Colours, angles, vectors and curves get
+//! purpose-built widgets, chosen by the parameter type and, where the type
+//! alone is ambiguous, by the parameter name. Every field can be switched
+//! back to the raw editor.
+//!
+//! Widgets always edit a copy of the value and only write it back when the
+//! user actually changes something, so a value which is merely displayed
+//! keeps its exact bits. Numbers are also checked against the [`Constraint`]
+//! for the parameter before they are written back.
+use std::hash::Hash;
+
+use egui::{
+    color_picker::{color_edit_button_rgb, color_edit_button_rgba, Alpha},
+    emath::Numeric,
+    pos2, Color32, DragValue, Grid, Id, Response, Rgba, Sense, Shape, Slider, Stroke, Ui, Vec2,
+};
+use roead::{
+    aamp::{get_default_name_table, Parameter, ParameterObject},
+    types::{Color, Curve, Vector3f},
+};
+
+pub mod constraint;
+pub use constraint::{Constraint, Severity, Violation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidgetKind {
+    Raw,
+    Vec3,
+    Color,
+    Angle,
+    Curve,
+}
+
+/// Name fragments which hint at what a parameter holds, checked in order.
+/// Matching is case sensitive so that, for example, "Protect" is not taken for
+/// a rotation.
+const NAME_HINTS: &[(&str, WidgetKind)] = &[
+    ("Color", WidgetKind::Color),
+    ("Colour", WidgetKind::Color),
+    ("Rot", WidgetKind::Angle),
+    ("Angle", WidgetKind::Angle),
+];
+
+/// Picks the widget to edit a parameter with.
+pub fn widget_kind(name: &str, param: &Parameter) -> WidgetKind {
+    let hint = NAME_HINTS
+        .iter()
+        .find(|(fragment, _)| name.contains(fragment))
+        .map(|(_, kind)| *kind);
+    match (param, hint) {
+        (Parameter::Color(_), _) => WidgetKind::Color,
+        (Parameter::Vec3(_), Some(WidgetKind::Color)) => WidgetKind::Color,
+        (Parameter::F32(_) | Parameter::Vec3(_), Some(WidgetKind::Angle)) => WidgetKind::Angle,
+        (Parameter::Vec3(_), _) => WidgetKind::Vec3,
+        (
+            Parameter::Curve1(_)
+            | Parameter::Curve2(_)
+            | Parameter::Curve3(_)
+            | Parameter::Curve4(_),
+            _,
+        ) => WidgetKind::Curve,
+        _ => WidgetKind::Raw,
+    }
+}
+
+/// Wraps an angle in degrees into [-180, 180).
+pub fn wrap_degrees(value: f32) -> f32 {
+    (value + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Writes `edit` into `value` if it differs bitwise, returning whether it did.
+#[inline]
+fn commit(value: &mut f32, edit: f32) -> bool {
+    if edit.to_bits() != value.to_bits() {
+        *value = edit;
+        true
+    } else {
+        false
+    }
+}
+
+/// Edits a number through a copy, writing it back only if the constraint
+/// allows it. A refused value stays in the field, outlined as an error, until
+/// it is corrected, and unusual values are outlined as a warning.
+fn checked<T: Numeric>(
+    ui: &mut Ui,
+    value: &mut T,
+    constraint: &Constraint,
+    add: impl FnOnce(&mut Ui, &mut T) -> Response,
+) -> bool {
+    let refused_id = ui.next_auto_id().with("refused");
+    let shown = ui
+        .data(|d| d.get_temp::<f64>(refused_id))
+        .unwrap_or_else(|| constraint.normalize(value.to_f64()));
+    let violation = constraint.check(shown);
+    let mut edit = T::from_f64(shown);
+    let mut response = ui
+        .scope(|ui| {
+            if let Some(violation) = &violation {
+                let color = match violation.severity {
+                    Severity::Error => ui.visuals().error_fg_color,
+                    Severity::Warning => ui.visuals().warn_fg_color,
+                };
+                let widgets = &mut ui.visuals_mut().widgets;
+                for visuals in [
+                    &mut widgets.inactive,
+                    &mut widgets.hovered,
+                    &mut widgets.active,
+                ] {
+                    visuals.bg_stroke = Stroke::new(1.0, color);
+                }
+            }
+            add(ui, &mut edit)
+        })
+        .inner;
+    if let Some(violation) = violation {
+        response = response.on_hover_text(violation.message);
+    }
+    if !response.changed() {
+        return false;
+    }
+    let edit = constraint.normalize(edit.to_f64());
+    if constraint
+        .check(edit)
+        .is_some_and(|v| v.severity == Severity::Error)
+    {
+        ui.data_mut(|d| d.insert_temp(refused_id, edit));
+        return false;
+    }
+    ui.data_mut(|d| d.remove::<f64>(refused_id));
+    if edit.to_bits() != value.to_f64().to_bits() {
+        *value = T::from_f64(edit);
+        true
+    } else {
+        false
+    }
+}
+
+fn drag_f32(ui: &mut Ui, value: &mut f32, prefix: &str, constraint: &Constraint) -> bool {
+    checked(ui, value, constraint, |ui, edit| {
+        ui.add(DragValue::new(edit).speed(0.01).prefix(prefix))
+    })
+}
+
+fn drag_degrees(ui: &mut Ui, value: &mut f32, prefix: &str, constraint: &Constraint) -> bool {
+    checked(ui, value, constraint, |ui, edit| {
+        ui.add(DragValue::new(edit).speed(0.5).prefix(prefix).suffix("°"))
+    })
+}
+
+fn vec3(ui: &mut Ui, value: &mut Vector3f, constraint: &Constraint) -> bool {
+    let mut changed = drag_f32(ui, &mut value.x, "X: ", constraint);
+    changed |= drag_f32(ui, &mut value.y, "Y: ", constraint);
+    changed |= drag_f32(ui, &mut value.z, "Z: ", constraint);
+    changed
+}
+
+fn angle(ui: &mut Ui, value: &mut f32, constraint: &Constraint) -> bool {
+    checked(ui, value, constraint, |ui, edit| {
+        ui.add(
+            Slider::new(edit, -180.0..=180.0)
+                .clamp_to_range(false)
+                .suffix("°"),
+        )
+    })
+}
+
+fn angles(ui: &mut Ui, value: &mut Vector3f, constraint: &Constraint) -> bool {
+    let mut changed = drag_degrees(ui, &mut value.x, "X: ", constraint);
+    changed |= drag_degrees(ui, &mut value.y, "Y: ", constraint);
+    changed |= drag_degrees(ui, &mut value.z, "Z: ", constraint);
+    changed
+}
+
+fn color(ui: &mut Ui, value: &mut Color) -> bool {
+    let mut edit = Rgba::from_rgba_unmultiplied(value.r, value.g, value.b, value.a);
+    if color_edit_button_rgba(ui, &mut edit, Alpha::BlendOrAdditive).changed() {
+        let [r, g, b, a] = edit.to_rgba_unmultiplied();
+        let mut changed = commit(&mut value.r, r);
+        changed |= commit(&mut value.g, g);
+        changed |= commit(&mut value.b, b);
+        changed |= commit(&mut value.a, a);
+        changed
+    } else {
+        false
+    }
+}
+
+fn color_rgb(ui: &mut Ui, value: &mut Vector3f) -> bool {
+    let mut edit = [value.x, value.y, value.z];
+    if color_edit_button_rgb(ui, &mut edit).changed() {
+        let mut changed = commit(&mut value.x, edit[0]);
+        changed |= commit(&mut value.y, edit[1]);
+        changed |= commit(&mut value.z, edit[2]);
+        changed
+    } else {
+        false
+    }
+}
+
+/// Plots the points of a curve, which can be dragged up and down.
+fn curve_plot(ui: &mut Ui, curve: &mut Curve) -> bool {
+    let size = Vec2::new(ui.available_width().clamp(120.0, 320.0), 80.0);
+    let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+    let rect = response.rect.shrink(4.0);
+    let count = curve.floats.len();
+    let (min, max) = curve
+        .floats
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), v| {
+            (min.min(*v), max.max(*v))
+        });
+    let (min, max) = if (max - min).abs() < f32::EPSILON {
+        (min - 1.0, max + 1.0)
+    } else {
+        (min, max)
+    };
+    let to_x = |i: usize| rect.left() + rect.width() * i as f32 / (count - 1) as f32;
+    let to_y = |v: f32| rect.bottom() - rect.height() * (v - min) / (max - min);
+    let mut changed = false;
+    if let Some(pos) = response
+        .interact_pointer_pos()
+        .filter(|_| response.dragged())
+    {
+        let index = (((pos.x - rect.left()) / rect.width()) * (count - 1) as f32)
+            .round()
+            .clamp(0.0, (count - 1) as f32) as usize;
+        let value = min + (rect.bottom() - pos.y) / rect.height() * (max - min);
+        changed = commit(&mut curve.floats[index], value.clamp(min, max));
+    }
+    let visuals = ui.visuals();
+    painter.rect_filled(response.rect, 2.0, visuals.extreme_bg_color);
+    let stroke = Stroke::new(1.5, visuals.selection.bg_fill);
+    let points: Vec<_> = curve
+        .floats
+        .iter()
+        .enumerate()
+        .map(|(i, v)| pos2(to_x(i), to_y(*v)))
+        .collect();
+    painter.add(Shape::line(points.clone(), stroke));
+    for point in points {
+        painter.circle_filled(point, 2.0, Color32::from_gray(200));
+    }
+    changed
+}
+
+fn curve(ui: &mut Ui, id: Id, curve: &mut Curve) -> bool {
+    let mut changed = false;
+    ui.vertical(|ui| {
+        ui.horizontal(|ui| {
+            let mut a = curve.a;
+            if ui.add(DragValue::new(&mut a).prefix("A: ")).changed() {
+                curve.a = a;
+                changed = true;
+            }
+            let mut b = curve.b;
+            if ui.add(DragValue::new(&mut b).prefix("B: ")).changed() {
+                curve.b = b;
+                changed = true;
+            }
+        });
+        changed |= curve_plot(ui, curve);
+        ui.collapsing("Points", |ui| {
+            Grid::new(id.with("points")).num_columns(5).show(ui, |ui| {
+                for (i, value) in curve.floats.iter_mut().enumerate() {
+                    changed |= drag_f32(ui, value, "", &Constraint::FLOAT);
+                    if i % 5 == 4 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    });
+    changed
+}
+
+fn curves(ui: &mut Ui, id: Id, items: &mut [Curve]) -> bool {
+    let mut changed = false;
+    ui.vertical(|ui| {
+        for (i, item) in items.iter_mut().enumerate() {
+            changed |= curve(ui, id.with(i), item);
+        }
+    });
+    changed
+}
+
+fn text<S>(ui: &mut Ui, value: &mut S) -> bool
+where
+    S: std::ops::Deref<Target = str> + for<'a> From<&'a str>,
+{
+    let mut edit = value.to_string();
+    if ui.text_edit_singleline(&mut edit).changed() && edit != **value {
+        *value = edit.as_str().into();
+        true
+    } else {
+        false
+    }
+}
+
+/// The plain editor for any parameter, with one field per component. Values
+/// are checked against the constraint for the parameter, but angles are not
+/// wrapped.
+pub fn edit_raw(ui: &mut Ui, id: impl Hash, name: &str, param: &mut Parameter) -> bool {
+    let id = Id::new(id);
+    let constraint = Constraint {
+        wrap: false,
+        ..Constraint::for_param(name, param)
+    };
+    let c = &constraint;
+    match param {
+        Parameter::Bool(value) => ui.checkbox(value, "").changed(),
+        Parameter::F32(value) => drag_f32(ui, value, "", c),
+        Parameter::I32(value) => checked(ui, value, c, |ui, edit| ui.add(DragValue::new(edit))),
+        Parameter::U32(value) => checked(ui, value, c, |ui, edit| ui.add(DragValue::new(edit))),
+        Parameter::Vec2(value) => {
+            let mut changed = drag_f32(ui, &mut value.x, "X: ", c);
+            changed |= drag_f32(ui, &mut value.y, "Y: ", c);
+            changed
+        }
+        Parameter::Vec3(value) => vec3(ui, value, c),
+        Parameter::Vec4(value) => {
+            let mut changed = drag_f32(ui, &mut value.x, "X: ", c);
+            changed |= drag_f32(ui, &mut value.y, "Y: ", c);
+            changed |= drag_f32(ui, &mut value.z, "Z: ", c);
+            changed |= drag_f32(ui, &mut value.t, "T: ", c);
+            changed
+        }
+        Parameter::Color(value) => {
+            let mut changed = drag_f32(ui, &mut value.r, "R: ", c);
+            changed |= drag_f32(ui, &mut value.g, "G: ", c);
+            changed |= drag_f32(ui, &mut value.b, "B: ", c);
+            changed |= drag_f32(ui, &mut value.a, "A: ", c);
+            changed
+        }
+        Parameter::String32(value) => text(ui, value),
+        Parameter::String64(value) => text(ui, &mut **value),
+        Parameter::String256(value) => text(ui, &mut **value),
+        Parameter::StringRef(value) => text(ui, value),
+        Parameter::Curve1(value) => curves(ui, id, value.as_mut_slice()),
+        Parameter::Curve2(value) => curves(ui, id, value.as_mut_slice()),
+        Parameter::Curve3(value) => curves(ui, id, value.as_mut_slice()),
+        Parameter::Curve4(value) => curves(ui, id, value.as_mut_slice()),
+        other => {
+            ui.weak(format!("{other:?}"));
+            false
+        }
+    }
+}
+
+/// Edits a parameter with the widget suited to it, with a toggle to switch
+/// to the raw editor. Returns whether the value changed.
+pub fn edit_parameter(ui: &mut Ui, id: impl Hash, name: &str, param: &mut Parameter) -> bool {
+    let id = Id::new(id);
+    let kind = widget_kind(name, param);
+    let constraint = Constraint::for_param(name, param);
+    let raw_id = id.with("raw");
+    let mut raw = ui.data(|d| d.get_temp::<bool>(raw_id).unwrap_or_default());
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        if kind != WidgetKind::Raw
+            && ui
+                .selectable_label(raw, "Raw")
+                .on_hover_text("Edit the plain values")
+                .clicked()
+        {
+            raw = !raw;
+            ui.data_mut(|d| d.insert_temp(raw_id, raw));
+        }
+        changed = match (kind, raw, &mut *param) {
+            (WidgetKind::Raw, ..) | (_, true, _) => edit_raw(ui, id, name, param),
+            (WidgetKind::Color, _, Parameter::Color(value)) => color(ui, value),
+            (WidgetKind::Color, _, Parameter::Vec3(value)) => color_rgb(ui, value),
+            (WidgetKind::Angle, _, Parameter::F32(value)) => angle(ui, value, &constraint),
+            (WidgetKind::Angle, _, Parameter::Vec3(value)) => angles(ui, value, &constraint),
+            (WidgetKind::Vec3, _, Parameter::Vec3(value)) => vec3(ui, value, &constraint),
+            _ => edit_raw(ui, id, name, param),
+        };
+    });
+    changed
+}
+
+/// Edits every parameter in an object, labelled by name where the name is
+/// known. Returns whether anything changed.
+pub fn edit_object(ui: &mut Ui, id: impl Hash, obj: &mut ParameterObject) -> bool {
+    let id = Id::new(id);
+    let table = get_default_name_table();
+    let mut changed = false;
+    Grid::new(id).num_columns(2).striped(true).show(ui, |ui| {
+        for (key, param) in obj.0.iter_mut() {
+            let name = table
+                .get_name(key.hash(), 0, 0)
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| format!("{:#010x}", key.hash()));
+            ui.label(name.as_str());
+            changed |= edit_parameter(ui, id.with(key.hash()), &name, param);
+            ui.end_row();
+        }
+    });
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        let vec = Parameter::Vec3(Vector3f {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        });
+        let curve = Parameter::Curve1(Box::new([Curve {
+            a: 0,
+            b: 0,
+            floats: [0.0; 30],
+        }]));
+        for (name, param, kind) in [
+            ("Scale", &vec, WidgetKind::Vec3),
+            ("Rotate", &vec, WidgetKind::Angle),
+            ("BaseColor", &vec, WidgetKind::Color),
+            ("RotSpeed", &Parameter::F32(1.0), WidgetKind::Angle),
+            ("Protect", &Parameter::F32(1.0), WidgetKind::Raw),
+            ("Life", &Parameter::I32(1), WidgetKind::Raw),
+            ("ColorNum", &Parameter::I32(1), WidgetKind::Raw),
+            ("Curve", &curve, WidgetKind::Curve),
+            (
+                "Anything",
+                &Parameter::Color(Color {
+                    r: 1.0,
+                    g: 1.0,
+                    b: 1.0,
+                    a: 1.0,
+                }),
+                WidgetKind::Color,
+            ),
+        ] {
+            assert_eq!(widget_kind(name, param), kind, "{name}");
+        }
+    }
+
+    #[test]
+    fn wrap() {
+        assert_eq!(wrap_degrees(0.0), 0.0);
+        assert_eq!(wrap_degrees(90.0), 90.0);
+        assert_eq!(wrap_degrees(180.0), -180.0);
+        assert_eq!(wrap_degrees(270.0), -90.0);
+        assert_eq!(wrap_degrees(-190.0), 170.0);
+        assert_eq!(wrap_degrees(720.0 + 45.0), 45.0);
+    }
+
+    #[test]
+    fn untouched() {
+        // Values which do not survive a trip through display precision or
+        // wrapping, none of which may change when only shown
+        let awkward = [0.1f32, 1.0e-7, 123456.79, 270.0, -0.0, 3.3, 1.5];
+        let mut params: Vec<(&str, Parameter)> = vec![
+            ("Value", Parameter::F32(awkward[0])),
+            ("Rotate", Parameter::F32(awkward[3])),
+            (
+                "Trans",
+                Parameter::Vec3(Vector3f {
+                    x: awkward[0],
+                    y: awkward[1],
+                    z: awkward[2],
+                }),
+            ),
+            (
+                "Rotate",
+                Parameter::Vec3(Vector3f {
+                    x: awkward[3],
+                    y: awkward[4],
+                    z: awkward[5],
+                }),
+            ),
+            (
+                "EmissionColor",
+                Parameter::Vec3(Vector3f {
+                    x: awkward[6],
+                    y: awkward[0],
+                    z: awkward[5],
+                }),
+            ),
+            (
+                "Color0",
+                Parameter::Color(Color {
+                    r: awkward[6],
+                    g: awkward[0],
+                    b: awkward[1],
+                    a: awkward[5],
+                }),
+            ),
+            (
+                "Curve",
+                Parameter::Curve1(Box::new([Curve {
+                    a: 1,
+                    b: 2,
+                    floats: std::array::from_fn(|i| awkward[i % awkward.len()] * i as f32),
+                }])),
+            ),
+        ];
+        let expected = params.clone();
+        let ctx = egui::Context::default();
+        for raw in [false, true] {
+            let _ = ctx.run(egui::RawInput::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    for (i, (name, param)) in params.iter_mut().enumerate() {
+                        let id = Id::new(i);
+                        ui.data_mut(|d| d.insert_temp(id.with("raw"), raw));
+                        assert!(!edit_parameter(ui, id, name, param));
+                    }
+                });
+            });
+            for ((_, param), (_, expected)) in params.iter().zip(expected.iter()) {
+                assert_eq!(format!("{param:?}"), format!("{expected:?}"));
+            }
+        }
+    }
+
+    #[test]
+    fn refused() {
+        let mut obj = ParameterObject::new().with_parameter("Life", Parameter::I32(10));
+        let constraint = Constraint::for_param("Life", &Parameter::I32(10));
+        let ctx = egui::Context::default();
+        // Runs a frame in which the field is given `input`, returning whether
+        // it was written back and what the field showed
+        let mut frame = |input: Option<i32>| {
+            let (mut changed, mut shown) = (false, 0);
+            let _ = ctx.run(egui::RawInput::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let Some(Parameter::I32(value)) = obj.0.values_mut().next() else {
+                        unreachable!()
+                    };
+                    changed = checked(ui, value, &constraint, |ui, edit| {
+                        shown = *edit;
+                        let mut response = ui.label(edit.to_string());
+                        if let Some(input) = input {
+                            *edit = input;
+                            response.mark_changed();
+                        }
+                        response
+                    });
+                });
+            });
+            (changed, shown)
+        };
+        assert_eq!(frame(Some(-5)), (false, 10));
+        // The refused value is kept in the field, but not in the parameter
+        assert_eq!(frame(None), (false, -5));
+        assert_eq!(frame(Some(25)), (true, -5));
+        assert_eq!(frame(None), (false, 25));
+        assert_eq!(obj.0.values().next(), Some(&Parameter::I32(25)));
+    }
+}
@@ -0,0 +1,211 @@
+//! Limits on the numbers a parameter can hold. Some come from the type the
+//! game stores it as, and some from what the parameter means, looked up by
+//! name. Values outside the hard limits would break the file, so the editors
+//! refuse them; values outside the soft limits are legal but likely mistakes,
+//! so they are only pointed out.
+use roead::aamp::Parameter;
+
+use super::{widget_kind, wrap_degrees, WidgetKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Allowed, but probably not intended.
+    Warning,
+    /// Not allowed.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub severity: Severity,
+    pub message:  String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraint {
+    pub min: f64,
+    pub max: f64,
+    /// The range outside of which values are flagged but still allowed.
+    pub warn_min: f64,
+    pub warn_max: f64,
+    /// Whether zero is refused too, for factors.
+    pub positive: bool,
+    /// Whether values are wrapped into [-180, 180), for angles in degrees.
+    pub wrap: bool,
+}
+
+impl Default for Constraint {
+    fn default() -> Self {
+        Self::range(f64::NEG_INFINITY, f64::INFINITY)
+    }
+}
+
+impl Constraint {
+    pub const FLOAT: Self = Self::range(f32::MIN as f64, f32::MAX as f64).warn(-1.0e7, 1.0e7);
+    pub const INT: Self = Self::range(i32::MIN as f64, i32::MAX as f64);
+    pub const NON_NEGATIVE: Self = Self::range(0.0, f64::INFINITY);
+    pub const POSITIVE: Self = Self {
+        positive: true,
+        ..Self::NON_NEGATIVE
+    };
+    pub const UINT: Self = Self::range(0.0, u32::MAX as f64);
+
+    pub const fn range(min: f64, max: f64) -> Self {
+        Self {
+            min,
+            max,
+            warn_min: f64::NEG_INFINITY,
+            warn_max: f64::INFINITY,
+            positive: false,
+            wrap: false,
+        }
+    }
+
+    pub const fn warn(self, warn_min: f64, warn_max: f64) -> Self {
+        Self {
+            warn_min,
+            warn_max,
+            ..self
+        }
+    }
+
+    /// The constraint satisfying both this and another.
+    pub fn and(self, other: Self) -> Self {
+        Self {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+            warn_min: self.warn_min.max(other.warn_min),
+            warn_max: self.warn_max.min(other.warn_max),
+            positive: self.positive || other.positive,
+            wrap: self.wrap || other.wrap,
+        }
+    }
+
+    /// The limits of the type a parameter is stored as.
+    pub fn for_type(param: &Parameter) -> Self {
+        match param {
+            Parameter::I32(_) => Self::INT,
+            Parameter::U32(_) => Self::UINT,
+            _ => Self::FLOAT,
+        }
+    }
+
+    /// The limits of a parameter, from its type and, where known, its name.
+    pub fn for_param(name: &str, param: &Parameter) -> Self {
+        let by_name = KNOWN_NAMES
+            .iter()
+            .filter(|(known, _)| *known == name)
+            .chain(
+                NAME_FRAGMENTS
+                    .iter()
+                    .filter(|(fragment, _)| name.contains(fragment)),
+            )
+            .fold(Self::default(), |constraint, (_, other)| {
+                constraint.and(*other)
+            });
+        Self {
+            wrap: widget_kind(name, param) == WidgetKind::Angle,
+            ..Self::for_type(param).and(by_name)
+        }
+    }
+
+    /// Puts a value in the form it is stored in, wrapping angles.
+    pub fn normalize(&self, value: f64) -> f64 {
+        if self.wrap {
+            wrap_degrees(value as f32) as f64
+        } else {
+            value
+        }
+    }
+
+    /// Checks a value, returning the worst problem with it.
+    pub fn check(&self, value: f64) -> Option<Violation> {
+        let error = |message: String| {
+            Some(Violation {
+                severity: Severity::Error,
+                message,
+            })
+        };
+        if !value.is_finite() {
+            error("Must be a finite number".into())
+        } else if value < self.min {
+            error(format!("Must be at least {}", self.min))
+        } else if value > self.max {
+            error(format!("Must be at most {}", self.max))
+        } else if self.positive && value <= 0.0 {
+            error("Must be more than 0".into())
+        } else if value < self.warn_min || value > self.warn_max {
+            Some(Violation {
+                severity: Severity::Warning,
+                message:  format!(
+                    "Unusual value, expected between {} and {}",
+                    self.warn_min, self.warn_max
+                ),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Limits for parameters by their exact name, on top of those of their type.
+pub static KNOWN_NAMES: &[(&str, Constraint)] = &[
+    ("Life", Constraint::NON_NEGATIVE),
+    ("Power", Constraint::NON_NEGATIVE),
+    ("AttackPower", Constraint::NON_NEGATIVE),
+    ("BaseAttack", Constraint::NON_NEGATIVE),
+    ("GuardPower", Constraint::NON_NEGATIVE),
+    ("BuyingPrice", Constraint::NON_NEGATIVE),
+    ("SellingPrice", Constraint::NON_NEGATIVE),
+];
+
+/// Limits for parameters whose name contains a fragment. As with widget
+/// hints, matching is case sensitive.
+pub static NAME_FRAGMENTS: &[(&str, Constraint)] = &[("Scale", Constraint::POSITIVE)];
+
+#[cfg(test)]
+mod tests {
+    use roead::types::Vector3f;
+
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let life = Constraint::for_param("Life", &Parameter::I32(1));
+        assert_eq!(life.min, 0.0);
+        assert_eq!(life.max, i32::MAX as f64);
+        assert_eq!(life.check(100.0), None);
+        assert_eq!(life.check(-1.0).map(|v| v.severity), Some(Severity::Error));
+
+        let count = Constraint::for_param("Count", &Parameter::U32(1));
+        assert!(count.check(-1.0).is_some());
+        assert!(count.check(u32::MAX as f64 + 1.0).is_some());
+
+        let scale = Constraint::for_param("ModelScale", &Parameter::F32(1.0));
+        assert!(scale.check(0.5).is_none());
+        assert!(scale.check(0.0).is_some());
+
+        let value = Constraint::for_param("Value", &Parameter::F32(1.0));
+        assert!(value.check(-3.5).is_none());
+        assert_eq!(
+            value.check(1.0e9).map(|v| v.severity),
+            Some(Severity::Warning)
+        );
+        assert_eq!(
+            value.check(f64::NAN).map(|v| v.severity),
+            Some(Severity::Error)
+        );
+
+        let rotate = Constraint::for_param(
+            "Rotate",
+            &Parameter::Vec3(Vector3f {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            }),
+        );
+        assert!(rotate.wrap);
+        assert_eq!(rotate.normalize(270.0), -90.0);
+        assert_eq!(value.normalize(270.0), 270.0);
+    }
+}
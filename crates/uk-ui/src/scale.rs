@@ -0,0 +1,363 @@
+//! UI scale and window placement. The user's scale is applied as egui's zoom
+//! factor on top of the scale the OS reports for the current monitor, so
+//! dragging the window to a monitor with a different DPI rescales it without
+//! any help from us. Anything derived from the final pixels per point (dock
+//! style, rasterized icons) is rebuilt when [`ScaleWatcher`] sees it change.
+//!
+//! Some panes also scale their own text on top of that, kept in
+//! [`TextScales`]. They draw with a scaled copy of the style in their own
+//! [`Ui`] rather than touching the global one, so nothing else moves.
+use std::collections::BTreeMap;
+
+use egui::{pos2, vec2, Context, Pos2, Style, Ui, Vec2, ViewportCommand, ViewportInfo};
+use serde::{Deserialize, Serialize};
+
+pub const MIN_SCALE: f32 = 0.75;
+pub const MAX_SCALE: f32 = 2.0;
+
+/// Limits a user scale to the supported range.
+pub fn clamp_scale(scale: f32) -> f32 {
+    if scale.is_finite() {
+        scale.clamp(MIN_SCALE, MAX_SCALE)
+    } else {
+        1.0
+    }
+}
+
+/// Applies a user scale on top of the native scale of the current monitor.
+pub fn apply_scale(ctx: &Context, scale: f32) {
+    ctx.set_zoom_factor(clamp_scale(scale));
+}
+
+pub const MIN_TEXT_SCALE: f32 = 0.5;
+pub const MAX_TEXT_SCALE: f32 = 3.0;
+/// How much the zoom buttons of a pane change its text scale.
+pub const TEXT_SCALE_STEP: f32 = 0.1;
+
+/// Limits a pane's text scale to the supported range.
+pub fn clamp_text_scale(scale: f32) -> f32 {
+    if scale.is_finite() {
+        scale.clamp(MIN_TEXT_SCALE, MAX_TEXT_SCALE)
+    } else {
+        1.0
+    }
+}
+
+/// A copy of a style with every text size scaled, for a pane to draw with.
+pub fn scaled_style(style: &Style, scale: f32) -> Style {
+    let mut style = style.clone();
+    for font in style.text_styles.values_mut() {
+        font.size *= scale;
+    }
+    style
+}
+
+/// The text scales of panes which set their own, by pane name. Panes
+/// without one are drawn at the scale of the rest of the UI.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TextScales(BTreeMap<String, f32>);
+
+impl TextScales {
+    pub fn get(&self, pane: &str) -> f32 {
+        self.0.get(pane).copied().map_or(1.0, clamp_text_scale)
+    }
+
+    pub fn set(&mut self, pane: &str, scale: f32) {
+        let scale = clamp_text_scale(scale);
+        if scale == 1.0 {
+            self.0.remove(pane);
+        } else {
+            self.0.insert(pane.into(), scale);
+        }
+    }
+
+    /// Changes the scale of a pane by a number of [`TEXT_SCALE_STEP`]s,
+    /// rounded to whole percents so stepping back returns to where it was.
+    pub fn step(&mut self, pane: &str, steps: i32) {
+        let scale = self.get(pane) + steps as f32 * TEXT_SCALE_STEP;
+        self.set(pane, (scale * 100.0).round() / 100.0);
+    }
+
+    pub fn reset(&mut self, pane: &str) {
+        self.0.remove(pane);
+    }
+
+    /// Scales the text of a pane drawn in `ui`. Ctrl+scroll over the pane
+    /// zooms it first, and only it.
+    pub fn apply(&mut self, pane: &str, ui: &mut Ui) {
+        if ui.ui_contains_pointer() {
+            let zoom = ui.input(|i| i.zoom_delta());
+            if zoom != 1.0 {
+                self.set(pane, self.get(pane) * zoom);
+            }
+        }
+        let style = scaled_style(ui.style(), self.get(pane));
+        ui.set_style(style);
+    }
+
+    /// Buttons to zoom a pane's text in and out, and to reset it.
+    pub fn controls(&mut self, pane: &str, ui: &mut Ui) {
+        let scale = self.get(pane);
+        if ui
+            .add_enabled(scale > MIN_TEXT_SCALE, egui::Button::new("−"))
+            .on_hover_text("Smaller text")
+            .clicked()
+        {
+            self.step(pane, -1);
+        }
+        if ui
+            .button(format!("{:.0}%", scale * 100.0))
+            .on_hover_text("Reset text size (Ctrl+scroll to zoom)")
+            .clicked()
+        {
+            self.reset(pane);
+        }
+        if ui
+            .add_enabled(scale < MAX_TEXT_SCALE, egui::Button::new("+"))
+            .on_hover_text("Larger text")
+            .clicked()
+        {
+            self.step(pane, 1);
+        }
+    }
+}
+
+/// Notices when the effective pixels per point change, whether from the user
+/// scale or from moving to another monitor.
+#[derive(Debug, Default)]
+pub struct ScaleWatcher {
+    last: Option<f32>,
+}
+
+impl ScaleWatcher {
+    /// Records the current pixels per point and returns whether they differ
+    /// from the last call. The first call always counts as a change.
+    pub fn update(&mut self, pixels_per_point: f32) -> bool {
+        let changed = !self
+            .last
+            .is_some_and(|last| (last - pixels_per_point).abs() <= f32::EPSILON);
+        self.last = Some(pixels_per_point);
+        changed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub pos:  [f32; 2],
+    pub size: [f32; 2],
+}
+
+impl WindowGeometry {
+    /// Reads the window's position and size, unless it is minimized or
+    /// maximized, which are not worth remembering.
+    pub fn from_viewport(info: &ViewportInfo) -> Option<Self> {
+        if info.minimized == Some(true) || info.maximized == Some(true) {
+            return None;
+        }
+        let outer = info.outer_rect?;
+        let inner = info.inner_rect?;
+        Some(Self {
+            pos:  [outer.min.x, outer.min.y],
+            size: [inner.width(), inner.height()],
+        })
+    }
+
+    #[inline]
+    pub fn pos(&self) -> Pos2 {
+        pos2(self.pos[0], self.pos[1])
+    }
+
+    #[inline]
+    pub fn size(&self) -> Vec2 {
+        vec2(self.size[0], self.size[1])
+    }
+
+    fn is_valid(&self) -> bool {
+        self.pos.iter().all(|v| v.is_finite())
+            && self.size.iter().all(|v| v.is_finite() && *v > 0.0)
+    }
+}
+
+/// Remembered window geometry for each monitor the window has been on.
+/// Monitors are told apart by their size and native scale, as egui does not
+/// expose anything more specific.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowState {
+    monitors: BTreeMap<String, WindowGeometry>,
+    last:     Option<String>,
+}
+
+impl WindowState {
+    pub fn monitor_key(monitor_size: Vec2, native_pixels_per_point: f32) -> String {
+        format!(
+            "{}x{}@{}",
+            monitor_size.x.round(),
+            monitor_size.y.round(),
+            (native_pixels_per_point * 100.0).round()
+        )
+    }
+
+    /// Stores the geometry for a monitor and makes it the one to restore.
+    pub fn record(&mut self, monitor: String, geometry: WindowGeometry) {
+        if geometry.is_valid() {
+            self.monitors.insert(monitor.clone(), geometry);
+            self.last = Some(monitor);
+        }
+    }
+
+    /// Records the current geometry from the viewport info, if it is known.
+    pub fn observe(&mut self, info: &ViewportInfo) {
+        if let (Some(monitor), Some(ppp), Some(geometry)) = (
+            info.monitor_size,
+            info.native_pixels_per_point,
+            WindowGeometry::from_viewport(info),
+        ) {
+            self.record(Self::monitor_key(monitor, ppp), geometry);
+        }
+    }
+
+    pub fn get(&self, monitor: &str) -> Option<WindowGeometry> {
+        self.monitors.get(monitor).copied()
+    }
+
+    /// The geometry last recorded, no smaller than `min_size`.
+    pub fn restore(&self, min_size: Vec2) -> Option<WindowGeometry> {
+        let mut geometry = self.get(self.last.as_deref()?)?;
+        geometry.size = [
+            geometry.size[0].max(min_size.x),
+            geometry.size[1].max(min_size.y),
+        ];
+        Some(geometry)
+    }
+
+    /// Moves and resizes the window to the geometry last recorded.
+    pub fn apply(&self, ctx: &Context, min_size: Vec2) {
+        if let Some(geometry) = self.restore(min_size) {
+            ctx.send_viewport_cmd(ViewportCommand::OuterPosition(geometry.pos()));
+            ctx.send_viewport_cmd(ViewportCommand::InnerSize(geometry.size()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Rect;
+
+    use super::*;
+
+    #[test]
+    fn clamp() {
+        assert_eq!(clamp_scale(1.0), 1.0);
+        assert_eq!(clamp_scale(0.5), MIN_SCALE);
+        assert_eq!(clamp_scale(3.0), MAX_SCALE);
+        assert_eq!(clamp_scale(f32::NAN), 1.0);
+    }
+
+    #[test]
+    fn zoom() {
+        let ctx = Context::default();
+        apply_scale(&ctx, 1.5);
+        assert_eq!(ctx.zoom_factor(), 1.5);
+        apply_scale(&ctx, 10.0);
+        assert_eq!(ctx.zoom_factor(), MAX_SCALE);
+    }
+
+    #[test]
+    fn text_scales() {
+        let mut scales = TextScales::default();
+        assert_eq!(scales.get("Log"), 1.0);
+        scales.step("Log", 3);
+        assert_eq!(scales.get("Log"), 1.3);
+        scales.step("Log", -3);
+        assert_eq!(scales, TextScales::default());
+        scales.step("Log", 3);
+        assert_eq!(scales.get("Other"), 1.0);
+        scales.step("Log", 100);
+        assert_eq!(scales.get("Log"), MAX_TEXT_SCALE);
+        scales.set("Log", 0.1);
+        assert_eq!(scales.get("Log"), MIN_TEXT_SCALE);
+        scales.set("Log", f32::NAN);
+        assert_eq!(scales, TextScales::default());
+        scales.set("Editor", 0.8);
+        scales.reset("Editor");
+        assert_eq!(scales, TextScales::default());
+
+        // Persisted, and clamped again if edited out of range
+        scales.set("Log", 1.5);
+        let json = serde_json::to_string(&scales).unwrap();
+        assert_eq!(serde_json::from_str::<TextScales>(&json).unwrap(), scales);
+        let edited: TextScales = serde_json::from_str(r#"{"Log":40.0}"#).unwrap();
+        assert_eq!(edited.get("Log"), MAX_TEXT_SCALE);
+
+        let style = Style::default();
+        let scaled = scaled_style(&style, 2.0);
+        for (text_style, font) in &style.text_styles {
+            assert_eq!(scaled.text_styles[text_style].size, font.size * 2.0);
+        }
+    }
+
+    #[test]
+    fn watcher() {
+        let mut watcher = ScaleWatcher::default();
+        assert!(watcher.update(1.0));
+        assert!(!watcher.update(1.0));
+        assert!(watcher.update(2.0));
+        assert!(!watcher.update(2.0));
+        assert!(watcher.update(1.25));
+    }
+
+    #[test]
+    fn geometry() {
+        let mut info = ViewportInfo {
+            monitor_size: Some(vec2(3840.0, 2160.0)),
+            native_pixels_per_point: Some(1.5),
+            outer_rect: Some(Rect::from_min_size(pos2(100.0, 50.0), vec2(1210.0, 840.0))),
+            inner_rect: Some(Rect::from_min_size(pos2(105.0, 80.0), vec2(1200.0, 800.0))),
+            ..Default::default()
+        };
+        let mut state = WindowState::default();
+        state.observe(&info);
+        let key = WindowState::monitor_key(vec2(3840.0, 2160.0), 1.5);
+        assert_eq!(key, "3840x2160@150");
+        let expected = WindowGeometry {
+            pos:  [100.0, 50.0],
+            size: [1200.0, 800.0],
+        };
+        assert_eq!(state.get(&key), Some(expected));
+        assert_eq!(state.restore(vec2(850.0, 500.0)), Some(expected));
+        assert_eq!(state.restore(vec2(1600.0, 500.0)).unwrap().size, [
+            1600.0, 800.0
+        ]);
+
+        // A second monitor keeps its own geometry and becomes the last used
+        info.monitor_size = Some(vec2(1920.0, 1080.0));
+        info.native_pixels_per_point = Some(1.0);
+        info.outer_rect = Some(Rect::from_min_size(pos2(-1800.0, 0.0), vec2(900.0, 600.0)));
+        info.inner_rect = Some(Rect::from_min_size(pos2(-1800.0, 0.0), vec2(900.0, 600.0)));
+        state.observe(&info);
+        assert_eq!(state.get(&key), Some(expected));
+        assert_eq!(state.restore(Vec2::ZERO).unwrap().pos, [-1800.0, 0.0]);
+
+        // Maximized windows are not recorded
+        info.maximized = Some(true);
+        info.outer_rect = Some(Rect::from_min_size(Pos2::ZERO, vec2(1920.0, 1080.0)));
+        state.observe(&info);
+        assert_eq!(state.restore(Vec2::ZERO).unwrap().pos, [-1800.0, 0.0]);
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<WindowState>(&json).unwrap(), state);
+        assert_eq!(
+            serde_json::from_str::<WindowState>("{}").unwrap(),
+            WindowState::default()
+        );
+    }
+
+    #[test]
+    fn icon_size() {
+        assert_eq!(crate::icons::icon_pixels(1.0), 24);
+        assert_eq!(crate::icons::icon_pixels(0.75), 24);
+        assert_eq!(crate::icons::icon_pixels(1.5), 36);
+    }
+}
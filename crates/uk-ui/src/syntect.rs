@@ -1,12 +1,14 @@
 use egui::text::LayoutJob;
 
-/// View some code with syntax highlighting and selection.
+/// View some code with syntax highlighting and selection. The text is sized
+/// by the monospace style of `ui`, so it follows a pane's own text scale.
 pub fn code_view_ui(ui: &mut egui::Ui, mut code: &str) {
     let language = "rs";
     let theme = CodeTheme::from_memory(ui.ctx());
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
 
     let mut layouter = |ui: &egui::Ui, string: &str, _wrap_width: f32| {
-        let layout_job = highlight(ui.ctx(), &theme, string, language);
+        let layout_job = highlight(ui.ctx(), &theme, string, language, &font);
         // layout_job.wrap.max_width = wrap_width; // no wrapping
         ui.fonts(|f| f.layout_job(layout_job))
     };
@@ -22,10 +24,21 @@ pub fn code_view_ui(ui: &mut egui::Ui, mut code: &str) {
 }
 
 /// Memoized Code highlighting
-pub fn highlight(ctx: &egui::Context, theme: &CodeTheme, code: &str, language: &str) -> LayoutJob {
-    impl egui::util::cache::ComputerMut<(&CodeTheme, &str, &str), LayoutJob> for Highlighter {
-        fn compute(&mut self, (theme, code, lang): (&CodeTheme, &str, &str)) -> LayoutJob {
-            self.highlight(theme, code, lang)
+pub fn highlight(
+    ctx: &egui::Context,
+    theme: &CodeTheme,
+    code: &str,
+    language: &str,
+    font: &egui::FontId,
+) -> LayoutJob {
+    impl egui::util::cache::ComputerMut<(&CodeTheme, &str, &str, &egui::FontId), LayoutJob>
+        for Highlighter
+    {
+        fn compute(
+            &mut self,
+            (theme, code, lang, font): (&CodeTheme, &str, &str, &egui::FontId),
+        ) -> LayoutJob {
+            self.highlight(theme, code, lang, font)
         }
     }
 
@@ -33,7 +46,7 @@ pub fn highlight(ctx: &egui::Context, theme: &CodeTheme, code: &str, language: &
 
     ctx.memory_mut(|m| {
         let highlight_cache = m.caches.cache::<HighlightCache>();
-        highlight_cache.get((theme, code, language))
+        highlight_cache.get((theme, code, language, font))
     })
 }
 
@@ -185,23 +198,36 @@ impl Default for Highlighter {
 
 impl Highlighter {
     #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
-    fn highlight(&self, theme: &CodeTheme, code: &str, lang: &str) -> LayoutJob {
-        self.highlight_impl(theme, code, lang).unwrap_or_else(|| {
-            // Fallback:
-            LayoutJob::simple(
-                code.into(),
-                egui::FontId::monospace(12.0),
-                if theme.dark_mode {
-                    egui::Color32::LIGHT_GRAY
-                } else {
-                    egui::Color32::DARK_GRAY
-                },
-                f32::INFINITY,
-            )
-        })
+    fn highlight(
+        &self,
+        theme: &CodeTheme,
+        code: &str,
+        lang: &str,
+        font: &egui::FontId,
+    ) -> LayoutJob {
+        self.highlight_impl(theme, code, lang, font)
+            .unwrap_or_else(|| {
+                // Fallback:
+                LayoutJob::simple(
+                    code.into(),
+                    font.clone(),
+                    if theme.dark_mode {
+                        egui::Color32::LIGHT_GRAY
+                    } else {
+                        egui::Color32::DARK_GRAY
+                    },
+                    f32::INFINITY,
+                )
+            })
     }
 
-    fn highlight_impl(&self, theme: &CodeTheme, text: &str, language: &str) -> Option<LayoutJob> {
+    fn highlight_impl(
+        &self,
+        theme: &CodeTheme,
+        text: &str,
+        language: &str,
+        font: &egui::FontId,
+    ) -> Option<LayoutJob> {
         use syntect::{easy::HighlightLines, highlighting::FontStyle, util::LinesWithEndings};
 
         let syntax = self
@@ -234,7 +260,7 @@ impl Highlighter {
                     leading_space: 0.0,
                     byte_range: as_byte_range(text, range),
                     format: TextFormat {
-                        font_id: egui::FontId::monospace(12.0),
+                        font_id: font.clone(),
                         color: text_color,
                         italics,
                         underline,
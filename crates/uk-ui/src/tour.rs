@@ -0,0 +1,235 @@
+//! Callouts pointing at widgets, for guided tours and hints. Panels report the
+//! rects of widgets worth pointing at to an [`Anchors`] registry as they are
+//! drawn, and overlays drawn later in the same frame look them up. A widget
+//! which was not drawn this frame, such as one in a hidden tab, has no anchor,
+//! and callouts for it are shown in the middle of the window instead.
+use std::{cell::RefCell, hash::Hash};
+
+use egui::{
+    vec2, Align2, Area, Color32, Context, Frame, Id, LayerId, Order, Rect, Rounding, Stroke, Ui,
+};
+use rustc_hash::FxHashMap;
+
+/// Rects of named widgets, as of the frame they were last drawn in.
+#[derive(Debug)]
+pub struct Anchors<K> {
+    rects: RefCell<FxHashMap<K, (Rect, u64)>>,
+}
+
+impl<K> Default for Anchors<K> {
+    fn default() -> Self {
+        Self {
+            rects: RefCell::new(FxHashMap::default()),
+        }
+    }
+}
+
+impl<K: Hash + Eq> Anchors<K> {
+    /// Records where a widget was drawn in a frame.
+    pub fn set(&self, key: K, rect: Rect, frame: u64) {
+        self.rects.borrow_mut().insert(key, (rect, frame));
+    }
+
+    /// Where a widget was drawn, if it was drawn in the given frame.
+    pub fn get(&self, key: &K, frame: u64) -> Option<Rect> {
+        self.rects
+            .borrow()
+            .get(key)
+            .filter(|(_, drawn)| *drawn == frame)
+            .map(|(rect, _)| *rect)
+    }
+}
+
+/// One stop on a tour.
+#[derive(Debug)]
+pub struct Step<K> {
+    pub anchor: K,
+    pub title:  &'static str,
+    pub text:   &'static str,
+}
+
+/// A sequence of callouts shown one at a time.
+#[derive(Debug)]
+pub struct Tour<K: 'static> {
+    steps:   &'static [Step<K>],
+    current: Option<usize>,
+}
+
+impl<K> Tour<K> {
+    pub const fn new(steps: &'static [Step<K>]) -> Self {
+        Self {
+            steps,
+            current: None,
+        }
+    }
+
+    /// Starts again from the first step.
+    pub fn start(&mut self) {
+        self.current = (!self.steps.is_empty()).then_some(0);
+    }
+
+    pub fn running(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// The step being shown and its index, if the tour is running.
+    pub fn current(&self) -> Option<(usize, &'static Step<K>)> {
+        let steps = self.steps;
+        self.current
+            .and_then(|i| steps.get(i).map(|step| (i, step)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Moves to the next step, and returns whether that finished the tour.
+    pub fn advance(&mut self) -> bool {
+        self.current = self
+            .current
+            .map(|i| i + 1)
+            .filter(|i| *i < self.steps.len());
+        self.current.is_none()
+    }
+
+    /// Moves back a step, staying on the first.
+    pub fn back(&mut self) {
+        self.current = self.current.map(|i| i.saturating_sub(1));
+    }
+
+    /// Ends the tour wherever it is.
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+}
+
+/// Space a callout needs to fit beside a widget.
+const ROOM: f32 = 160.0;
+
+/// Shows a callout next to a widget, outlining it, or in the middle of the
+/// window if there is no widget to point at.
+pub fn callout<R>(
+    ctx: &Context,
+    id: Id,
+    target: Option<Rect>,
+    add_contents: impl FnOnce(&mut Ui) -> R,
+) -> R {
+    let screen = ctx.screen_rect();
+    let area = Area::new(id).order(Order::Foreground).constrain(true);
+    let area = match target {
+        Some(target) => {
+            let stroke = Stroke::new(2.0, ctx.style().visuals.selection.stroke.color);
+            ctx.layer_painter(LayerId::new(Order::Foreground, id.with("outline")))
+                .rect_stroke(target.expand(4.0), Rounding::same(4.0), stroke);
+            // Below the widget if there is room, else above it, else over it
+            if target.bottom() + ROOM < screen.bottom() {
+                area.pivot(Align2::LEFT_TOP)
+                    .fixed_pos(target.left_bottom() + vec2(0.0, 10.0))
+            } else if target.top() - ROOM > screen.top() {
+                area.pivot(Align2::LEFT_BOTTOM)
+                    .fixed_pos(target.left_top() - vec2(0.0, 10.0))
+            } else {
+                area.pivot(Align2::CENTER_CENTER).fixed_pos(target.center())
+            }
+        }
+        None => area.anchor(Align2::CENTER_CENTER, vec2(0.0, 0.0)),
+    };
+    area.show(ctx, |ui| {
+        Frame::popup(ui.style())
+            .show(ui, |ui| {
+                ui.set_max_width(280.0);
+                add_contents(ui)
+            })
+            .inner
+    })
+    .inner
+}
+
+/// Draws a slow pulse around a widget to draw the eye to it.
+pub fn pulse(ctx: &Context, id: Id, rect: Rect) {
+    let phase = (ctx.input(|i| i.time) * 2.0).sin() as f32 * 0.5 + 0.5;
+    let color = ctx.style().visuals.selection.stroke.color;
+    let color = Color32::from_rgba_unmultiplied(
+        color.r(),
+        color.g(),
+        color.b(),
+        (80.0 + phase * 175.0) as u8,
+    );
+    ctx.layer_painter(LayerId::new(Order::Foreground, id))
+        .rect_stroke(
+            rect.expand(2.0 + phase * 3.0),
+            Rounding::same(4.0),
+            Stroke::new(2.0, color),
+        );
+    ctx.request_repaint_after(std::time::Duration::from_millis(50));
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::pos2;
+
+    use super::*;
+
+    static STEPS: &[Step<u8>] = &[
+        Step {
+            anchor: 1,
+            title:  "One",
+            text:   "",
+        },
+        Step {
+            anchor: 2,
+            title:  "Two",
+            text:   "",
+        },
+    ];
+
+    #[test]
+    fn anchors() {
+        let anchors = Anchors::default();
+        let rect = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 10.0));
+        anchors.set("apply", rect, 3);
+        assert_eq!(anchors.get(&"apply", 3), Some(rect));
+        // Widgets not drawn this frame are not pointed at
+        assert_eq!(anchors.get(&"apply", 4), None);
+        assert_eq!(anchors.get(&"settings", 3), None);
+        anchors.set("apply", rect.translate(vec2(5.0, 0.0)), 4);
+        assert_eq!(anchors.get(&"apply", 4).map(|r| r.left()), Some(5.0));
+    }
+
+    #[test]
+    fn steps() {
+        let mut tour = Tour::new(STEPS);
+        assert!(!tour.running());
+        assert!(tour.current().is_none());
+        tour.start();
+        assert_eq!(tour.current().map(|(i, s)| (i, s.anchor)), Some((0, 1)));
+        tour.back();
+        assert_eq!(tour.current().map(|(i, _)| i), Some(0));
+        assert!(!tour.advance());
+        assert_eq!(tour.current().map(|(i, s)| (i, s.title)), Some((1, "Two")));
+        tour.back();
+        assert_eq!(tour.current().map(|(i, _)| i), Some(0));
+        assert!(!tour.advance());
+        assert!(tour.advance());
+        assert!(!tour.running());
+        // Advancing a finished tour does not restart it
+        assert!(tour.advance());
+        tour.back();
+        assert!(!tour.running());
+        tour.start();
+        tour.stop();
+        assert!(!tour.running());
+    }
+
+    #[test]
+    fn empty() {
+        let mut tour = Tour::<u8>::new(&[]);
+        tour.start();
+        assert!(!tour.running());
+        assert!(tour.is_empty());
+    }
+}
@@ -37,6 +37,41 @@ xflags::xflags! {
             required output: PathBuf
             /// Path to the meta file for the mod
             required meta: PathBuf
+            /// Mod format version to package for, defaulting to the newest
+            optional -f, --format format: u32
+        }
+        /// Convert a folder of MSYT or MSBT files into a text-only mod
+        cmd import-texts {
+            /// Path to the folder, with a subfolder for each language if more than one
+            required path: PathBuf
+            /// Path to save the mod to instead of installing it
+            optional -o, --output output: PathBuf
+        }
+        /// Check a mod for problems before publishing
+        cmd lint {
+            /// Path to the mod archive or folder
+            required path: PathBuf
+        }
+        /// Run the operations in a batch file
+        cmd run {
+            /// Path to the batch file
+            required path: PathBuf
+            /// Check the batch file without changing anything
+            optional --dry-run
+        }
+        /// List the files an actor depends on and where each is found
+        cmd deps {
+            /// Path to the actor pack, or the name of the actor
+            required actor: String
+            /// Mod folder to look for the actor and its files in
+            optional -s, --source source: PathBuf
+        }
+        /// List the files changed by more than one enabled mod
+        cmd conflicts {
+            /// Write the report as JSON
+            optional --json
+            /// File to write the report to instead of the console
+            optional -o, --output output: PathBuf
         }
         /// Uninstall a mod
         cmd uninstall {
@@ -75,6 +110,11 @@ pub enum UkmmCmd {
     Install(Install),
     Uninstall(Uninstall),
     Package(Package),
+    ImportTexts(ImportTexts),
+    Lint(Lint),
+    Run(Run),
+    Deps(Deps),
+    Conflicts(Conflicts),
     Remerge(Remerge),
     Deploy(Deploy),
     Mode(Mode),
@@ -91,6 +131,36 @@ pub struct Package {
     pub path:   PathBuf,
     pub output: PathBuf,
     pub meta:   PathBuf,
+    pub format: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct ImportTexts {
+    pub path:   PathBuf,
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct Lint {
+    pub path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct Run {
+    pub path:    PathBuf,
+    pub dry_run: bool,
+}
+
+#[derive(Debug)]
+pub struct Deps {
+    pub actor:  String,
+    pub source: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct Conflicts {
+    pub json:   bool,
+    pub output: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -152,6 +222,12 @@ impl Runner {
     }
 
     fn check_mod(&self, path: &Path) -> Result<Option<PathBuf>> {
+        if uk_manager::texts::is_text_folder(path) {
+            let path = uk_manager::texts::convert_texts(&self.core, path, None)?;
+            let mod_ = ModReader::open(&path, vec![]).context("Failed to open converted mod")?;
+            println!("Installing {}...", mod_.meta.name);
+            return Ok(Some(path));
+        }
         let (mod_, path) = match ModReader::open(path, vec![]) {
             Ok(mod_) => (mod_, path.to_path_buf()),
             Err(e) => {
@@ -184,6 +260,33 @@ impl Runner {
         Ok(Some(path))
     }
 
+    /// Warns that merges will carry whatever was changed in a modded dump.
+    fn warn_dump(&self) {
+        let settings = self.core.settings();
+        if let (Some(files), Some(dump)) =
+            (uk_manager::purity::unaccepted(&settings), settings.dump())
+        {
+            println!(
+                "Warning: {}",
+                uk_manager::purity::warning(&files, dump.source().host_path())
+            );
+        }
+    }
+
+    fn install(&self, path: &Path, profile: Option<&String>) -> Result<()> {
+        let mods = self.core.mod_manager();
+        let mod_ = mods.add(path, profile)?;
+        mods.set_enabled(mod_.as_map_id(), true, profile)?;
+        mods.save()?;
+        println!("Applying mod to load order...");
+        let deployer = self.core.deploy_manager();
+        deployer.apply(Some(mod_.manifest()?.as_ref().clone()))?;
+        if self.cli.deploy {
+            self.deploy()?;
+        }
+        Ok(())
+    }
+
     fn deploy(&self) -> Result<()> {
         let deployer = self.core.deploy_manager();
         if deployer.pending() {
@@ -201,6 +304,16 @@ impl Runner {
             env_logger::init();
             log::set_max_level(log::LevelFilter::Debug);
         }
+        if matches!(
+            self.cli.subcommand,
+            UkmmCmd::Install(_)
+                | UkmmCmd::ImportTexts(_)
+                | UkmmCmd::Uninstall(_)
+                | UkmmCmd::Run(_)
+                | UkmmCmd::Remerge(_)
+        ) {
+            self.warn_dump();
+        }
         match &self.cli.subcommand {
             UkmmCmd::Mode(Mode { platform }) => {
                 self.core
@@ -213,31 +326,100 @@ impl Runner {
                 }
                 println!("Done!");
             }
+            UkmmCmd::ImportTexts(ImportTexts { path, output }) => {
+                if !uk_manager::texts::is_text_folder(path) {
+                    anyhow_ext::bail!("{} is not a folder of MSYT or MSBT files", path.display());
+                }
+                println!("Converting texts...");
+                let mod_path = uk_manager::texts::convert_texts(&self.core, path, None)?;
+                match output {
+                    Some(output) => {
+                        let output = if output.is_dir() {
+                            output.join(mod_path.file_name().context("Mod has no file name")?)
+                        } else {
+                            output.clone()
+                        };
+                        fs_err::copy(&mod_path, &output)?;
+                        println!("Saved text mod to {}", output.display());
+                    }
+                    None => self.install(&mod_path, None)?,
+                }
+                println!("Done!");
+            }
             UkmmCmd::Install(Install { path, profile }) => {
                 if let Some(path) = self.check_mod(path)? {
-                    let mods = self.core.mod_manager();
-                    let mod_ = mods.add(&path, profile.as_ref())?;
-                    mods.set_enabled(mod_.as_map_id(), true, profile.as_ref())?;
-                    mods.save()?;
-                    println!("Applying mod to load order...");
-                    let deployer = self.core.deploy_manager();
-                    deployer.apply(Some(mod_.manifest()?.as_ref().clone()))?;
-                    if self.cli.deploy {
-                        self.deploy()?;
-                    }
+                    self.install(&path, profile.as_ref())?;
                     println!("Done!");
                 }
             }
             UkmmCmd::Package(pkg) => {
                 println!("Packaging mod...");
+                let mut meta = Meta::parse(&pkg.meta)?;
+                meta.format = pkg.format.unwrap_or(uk_mod::FORMAT_VERSION);
                 let builder = package::ModPackerBuilder {
                     source: pkg.path.clone(),
-                    dest:   pkg.output.clone(),
-                    meta:   Meta::parse(&pkg.meta)?,
+                    alt_source: Default::default(),
+                    dest: pkg.output.clone(),
+                    meta,
+                    endian_check: package::EndianCheck::Ignore,
                 };
-                tasks::package_mod(&self.core, builder)?;
+                tasks::package_mod(&self.core, builder, Default::default())?;
                 println!("Done!");
             }
+            UkmmCmd::Lint(Lint { path }) => {
+                let report = uk_mod::lint::lint(path)?;
+                println!("{report}");
+                if report.has_errors() {
+                    anyhow_ext::bail!("{} has problems which must be fixed", path.display());
+                }
+            }
+            UkmmCmd::Run(Run { path, dry_run }) => {
+                let batch = uk_manager::batch::Batch::open(path)?;
+                let base = path.parent().unwrap_or(Path::new("."));
+                let outcome = uk_manager::batch::run(&self.core, &batch, base, *dry_run)?;
+                for e in &outcome.failed {
+                    println!("{e:?}");
+                }
+                if !outcome.failed.is_empty() {
+                    anyhow_ext::bail!("{} step(s) failed", outcome.failed.len());
+                }
+                if *dry_run {
+                    println!("All {} steps can be run", outcome.succeeded);
+                } else {
+                    if !outcome.pending.is_empty() {
+                        println!(
+                            "Some changes have not been merged. End the batch with an apply step \
+                             to merge them."
+                        );
+                    }
+                    if self.cli.deploy {
+                        self.deploy()?;
+                    }
+                    println!("Done!");
+                }
+            }
+            UkmmCmd::Deps(Deps { actor, source }) => {
+                let root = tasks::actor_deps(&self.core, actor, source.as_deref())?;
+                print!("{root}");
+                let missing = root.missing().count();
+                if missing > 0 {
+                    anyhow_ext::bail!("{missing} file(s) could not be found");
+                }
+            }
+            UkmmCmd::Conflicts(Conflicts { json, output }) => {
+                let report = uk_manager::conflicts::ConflictReport::new(&self.core)?;
+                let mut out: Box<dyn Write> = match output {
+                    Some(path) => Box::new(fs_err::File::create(path)?),
+                    None => Box::new(stdout().lock()),
+                };
+                if *json {
+                    report.write(out)?;
+                } else {
+                    for conflict in report.conflicts() {
+                        writeln!(out, "{conflict}")?;
+                    }
+                }
+            }
             UkmmCmd::Remerge(_) => {
                 println!("Remerging...");
                 tasks::apply_changes(&self.core, vec![], None)?;
@@ -1,17 +1,41 @@
+mod activity;
+mod archive;
+mod bisect;
+mod collate;
+mod compare;
+mod compat;
 mod deploy;
+mod deps;
+mod history;
 mod info;
+mod lock;
+mod logs;
 mod menus;
+mod metrics;
 mod modals;
 mod mods;
+mod offline;
 mod options;
 pub(crate) mod package;
 mod picker;
 mod profiles;
+mod purity;
+mod recovery;
+mod reveal;
 mod settings;
+mod simulation;
+mod space;
+mod split;
 mod tabs;
 pub(crate) mod tasks;
+mod tour;
+#[cfg(feature = "tray")]
+mod tray;
+mod tweaks;
+mod undo;
 mod update;
 mod util;
+mod watchdog;
 use std::{
     cell::{Cell, RefCell},
     collections::VecDeque,
@@ -19,9 +43,9 @@ use std::{
     ops::DerefMut,
     path::PathBuf,
     rc::Rc,
-    sync::Arc,
+    sync::{atomic::AtomicBool, Arc},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow_ext::{Context, Result};
@@ -34,7 +58,7 @@ use egui_notify::Toast;
 use flume::{Receiver, Sender};
 use fs_err as fs;
 use parking_lot::{
-    MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RwLockReadGuard, RwLockWriteGuard,
 };
 use picker::FilePickerState;
 use rustc_hash::FxHashSet;
@@ -45,7 +69,7 @@ use uk_manager::{
     mods::{LookupMod, Mod},
     settings::{Platform, Settings},
 };
-use uk_mod::{pack::sanitise, Manifest, Meta, ModPlatform};
+use uk_mod::{pack::sanitise, Manifest, Meta};
 pub use uk_ui::visuals;
 use uk_ui::{
     egui::{
@@ -58,7 +82,7 @@ use uk_ui::{
 };
 use uk_util::OptionResultExt;
 
-use self::{package::ModPackerBuilder, tasks::VersionResponse};
+use self::{package::ModPackerBuilder, tasks::VersionResponse, watchdog::tracked::Held};
 use crate::gui::modals::MetaInputModal;
 
 pub trait Component {
@@ -75,6 +99,25 @@ pub enum Tabs {
     Log,
     Settings,
     Package,
+    History,
+    Activity,
+    Simulation,
+}
+
+impl Tabs {
+    /// Every tab, in the order they are listed in the window menu.
+    pub const ALL: [Tabs; 10] = [
+        Tabs::Info,
+        Tabs::Install,
+        Tabs::Deploy,
+        Tabs::History,
+        Tabs::Activity,
+        Tabs::Simulation,
+        Tabs::Mods,
+        Tabs::Package,
+        Tabs::Settings,
+        Tabs::Log,
+    ];
 }
 
 impl std::fmt::Display for Tabs {
@@ -99,20 +142,22 @@ pub enum Sort {
     Priority,
 }
 
-type Orderer = dyn Fn(&(usize, Mod), &(usize, Mod)) -> std::cmp::Ordering;
+type Orderer<'a> = dyn Fn(&(usize, Mod), &(usize, Mod)) -> std::cmp::Ordering + 'a;
 
 impl Sort {
-    pub fn orderer(&self) -> Box<Orderer> {
+    pub fn orderer<'a>(&self, keys: &'a collate::ModKeys) -> Box<Orderer<'a>> {
         match self {
             Sort::Enabled => {
                 Box::new(|(_, a): &(_, Mod), (_, b): &(_, Mod)| a.enabled.cmp(&b.enabled))
             }
             Sort::Name => {
-                Box::new(|(_, a): &(_, Mod), (_, b): &(_, Mod)| a.meta.name.cmp(&b.meta.name))
+                Box::new(|(_, a): &(_, Mod), (_, b): &(_, Mod)| {
+                    keys.name(a.hash()).cmp(&keys.name(b.hash()))
+                })
             }
             Sort::Category => {
                 Box::new(|(_, a): &(_, Mod), (_, b): &(_, Mod)| {
-                    a.meta.category.cmp(&b.meta.category)
+                    keys.category(a.hash()).cmp(&keys.category(b.hash()))
                 })
             }
             Sort::Version => {
@@ -130,38 +175,87 @@ impl Sort {
 
 #[derive(Debug)]
 pub enum Message {
+    /// Stops warning that the current dump is modded, then applies pending
+    /// changes if set.
+    AcceptDump(bool),
     AddMod(Mod),
     AddToProfile(smartstring::alias::String),
     AddProfile,
     Apply,
+    Applied,
+    /// Archives mods, first disabling any which are enabled if set, or else
+    /// asking to.
+    ArchiveMods(Option<Vec<Mod>>, bool),
+    /// Mods of the profile found offline when last probed.
+    AvailabilityProbed(Vec<Mod>),
+    BatchRan(Manifest, Vec<String>),
     ChangeProfile(String),
     ChangeSort(Sort, bool),
+    CheckActorDeps,
+    /// Checks how two mods would get along, comparing the keys they both
+    /// edit if set.
+    CheckCompat(compat::Subject, compat::Subject, bool),
+    CheckDrift,
+    CheckDump,
     CheckMeta,
     ClearDrag,
+    ClearDumpCache,
     ClearSelect,
     CloseAbout,
     CloseConfirm,
     CloseError,
     CloseChangelog,
+    CloseDuplicate,
     ClosePackagingOptions,
     ClosePackagingDependencies,
     CloseProfiles,
+    CloseTweaks,
+    CompareMerged,
     Confirm(Box<Message>, String),
-    DeleteProfile(String),
+    CreateSnapshot(String),
+    /// Stores the installed mods unpacked, with their files shared.
+    DeduplicateMods,
+    /// Stored mods were converted, so their paths changed.
+    Deduplicated(uk_manager::mods::dedup::DedupReport),
+    /// Deletes a profile, first copying its stranded local patches to
+    /// another profile if one is given.
+    DeleteProfile(String, Option<String>),
+    DeleteSnapshot(uk_manager::lock::Snapshot),
     Deploy,
+    DeployOnly(uk_manager::deploy::Half),
+    /// Deployment finished, to the folder given if there is one.
+    Deployed(Option<PathBuf>),
     Deselect(usize),
     DoUpdate,
+    DumpCacheCleared,
     DuplicateProfile(String),
+    EndBisect(Vec<usize>),
+    EmptyTrash,
+    EndSimulation(bool),
     Error(anyhow_ext::Error),
+    ExportConflicts,
+    ExportDiff(String, bool),
+    /// Exports a repro of a failed merge, with the error it failed with.
+    ExportRepro(uk_mod::unpack::repro::MergeFailure, String),
+    /// Reports a file exported, remembering it to show later.
+    Exported(PathBuf),
+    ExportSummary(String),
     Extract,
     FilePickerBack,
     FilePickerSet(Option<PathBuf>),
     FilePickerUp,
     GetPackagingOptions,
     HandleMod(Mod),
+    /// Copies the offline mods among these to storage, then enables them
+    /// all.
+    HydrateMods(Vec<Mod>),
+    /// Mods were copied to storage, so their paths changed.
+    Hydrated(Vec<Mod>),
     HandleSettings,
     ImportCemu,
     InstallMod(Mod),
+    LaunchEmulator,
+    LockDeployment,
     MigrateBcml,
     ModUpdate,
     MoveSelected(usize),
@@ -170,19 +264,38 @@ pub enum Message {
     OfferUpdate(VersionResponse),
     OpenMod(PathBuf),
     PackageMod,
+    PreviewFile(String, bool),
+    ProbeAvailability,
+    Quit,
     RefreshModsDisplay,
     Remerge,
+    /// Redoes this many of the last changes undone.
+    Redo(usize),
     ReloadProfiles,
-    RemoveMods(Vec<Mod>),
+    RemoveMods(Vec<uk_manager::mods::trash::RemovedMod>),
+    RepairIndex(Option<Vec<uk_manager::mods::validate::Issue>>),
     RenameProfile(String, String),
+    ReportBisect(bool),
     RequestMeta(PathBuf),
+    /// Applies pending changes, first confirming if free space is short.
+    RequestApply,
     RequestOptions(Mod, bool),
+    RequestSplit(Mod),
     ResetMods(Option<Manifest>),
     ResetPacker,
+    ResolveDrift(Option<Vec<(smartstring::alias::String, uk_manager::drift::Resolution)>>),
+    ResolveEndianAudit(Option<package::EndianCheck>),
     ResetPending,
     ResetSettings,
+    ResolveDuplicate(Mod, Box<uk_manager::mods::Duplicate>),
     Restart,
+    /// Shows a file or folder in the system file manager.
+    Reveal(PathBuf),
+    RestoreProfile(uk_manager::mods::trash::Trashed),
+    RestoreSnapshot(uk_manager::lock::Snapshot),
+    RunBatch,
     SaveSettings,
+    SaveTweaks(uk_manager::tweaks::Tweaks),
     SelectAlso(usize),
     SelectFile,
     SelectOnly(usize),
@@ -191,17 +304,52 @@ pub enum Message {
     SetChangelog(String),
     SetDownloading(String),
     SetFocus(FocusedPane),
+    SetLabels(Option<Arc<uk_content::labels::Labels>>),
+    ShowWindow,
+    SetPriority(uk_manager::mods::Priority),
+    SetScale(f32),
+    SetSummary(usize, String),
     SetTheme(uk_ui::visuals::Theme),
     ShowAbout,
+    ShowActorDeps(uk_content::actor::deps::Dependency),
+    ShowBisect,
+    ShowCompat(uk_manager::compat::Report),
+    ShowComparison(uk_manager::compare::Report),
+    ShowDrift(Vec<uk_manager::drift::Drift>, bool),
+    ShowEndianAudit(ModPackerBuilder, Vec<uk_mod::endian::Mismatch>),
+    ShowIndexIssues(Vec<uk_manager::mods::validate::Issue>, bool),
+    ShowLabels(bool),
     ShowPackagingOptions(FxHashSet<PathBuf>),
     ShowPackagingDependencies,
+    ShowPreview(String, bool, uk_manager::simulation::Preview),
+    ShowSnapshots,
+    ShowSplit(Mod, std::collections::BTreeSet<uk_mod::split::SplitFile>),
+    ShowTweaks,
+    SplitMod(Mod, Vec<uk_mod::split::SplitPart>),
+    StartBisect(Vec<usize>),
+    StartCompat(compat::Subject),
     StartDrag(usize),
+    StartServer,
+    StartSimulation,
+    StartTour,
+    StopServer,
+    SummarizeMod(Mod),
+    SummarizeProfile,
     Toast(String),
     ToggleMods(Option<Vec<Mod>>, bool),
     DevUpdate,
     UpdatePackageMeta(Meta),
+    /// Undoes this many of the last changes to the mod list.
+    Undo(usize),
     UninstallMods(Option<Vec<Mod>>),
+    UnarchiveMod(Mod),
+    /// Unlocks the deployment, then goes ahead with the change it stopped.
+    UnlockDeployment(Option<Box<Message>>),
+    UpdateBisect(Option<uk_manager::bisect::Session>),
+    /// A mod was updated in place, changing these files.
+    UpdatedMod(Manifest),
     UpdateOptions(Mod),
+    ValidateLibrary,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -211,6 +359,12 @@ struct UiState {
     picker_state: FilePickerState,
     #[serde(default = "tabs::default_ui")]
     tree: DockState<Tabs>,
+    show_labels: bool,
+    ui_scale: f32,
+    text_scales: uk_ui::scale::TextScales,
+    window: uk_ui::scale::WindowState,
+    tour_done: bool,
+    show_metrics: bool,
 }
 
 impl Default for UiState {
@@ -219,23 +373,53 @@ impl Default for UiState {
             theme: uk_ui::visuals::Theme::Sheikah,
             picker_state: FilePickerState::default(),
             tree: tabs::default_ui(),
+            show_labels: false,
+            ui_scale: 1.0,
+            text_scales: Default::default(),
+            window: Default::default(),
+            tour_done: false,
+            show_metrics: false,
         }
     }
 }
 
+const MIN_WINDOW_SIZE: egui::Vec2 = egui::Vec2::new(850.0, 500.0);
+
 pub struct App {
     core: Arc<Manager>,
     channel: (Sender<Message>, Receiver<Message>),
     mods: Vec<Mod>,
     displayed_mods: Vec<Mod>,
+    mod_keys: collate::ModKeys,
+    mod_filter: String,
     selected: Vec<Mod>,
+    /// Mods whose options are shown inline in the mod list.
+    expanded_mods: FxHashSet<usize>,
+    /// Mods found offline when last probed, shown greyed out.
+    offline: FxHashSet<usize>,
+    /// Laid out text for the rows of the mod list.
+    row_text: mods::RowCache<Arc<egui::Galley>>,
     install_queue: VecDeque<PathBuf>,
     update_mod: Option<Mod>,
     error_queue: VecDeque<anyhow_ext::Error>,
     drag_index: Option<usize>,
     hover_index: Option<usize>,
     picker_state: FilePickerState,
+    picker_peek: picker::PeekState,
     profiles_state: RefCell<profiles::ProfileManagerState>,
+    tweak_state: RefCell<tweaks::TweakState>,
+    split_state: RefCell<split::SplitState>,
+    deps_state: RefCell<deps::DepsState>,
+    compare_state: RefCell<compare::CompareState>,
+    compat_state: RefCell<compat::CompatState>,
+    snapshot_state: RefCell<lock::SnapshotState>,
+    history_state: RefCell<history::HistoryState>,
+    /// Changes to the mod list this session, to undo and redo.
+    undo: uk_manager::undo::UndoStack,
+    activity_state: RefCell<activity::ActivityState>,
+    simulation: RefCell<Option<simulation::SimulationState>>,
+    bisect_state: RefCell<bisect::BisectState>,
+    server: Option<deploy::ServeState>,
     meta_input: modals::MetaInputModal,
     closed_tabs: HashMap<Tabs, NodeIndex>,
     tree: Rc<RefCell<DockState<Tabs>>>,
@@ -243,56 +427,112 @@ pub struct App {
     error: Option<anyhow_ext::Error>,
     new_profile: Option<String>,
     confirm: Option<(Message, String)>,
+    /// A change stopped because the deployment is locked.
+    locked_action: Option<Message>,
+    duplicate: Option<(Mod, uk_manager::mods::Duplicate)>,
     busy: Cell<bool>,
+    /// Set to stop the running task, if it can be stopped.
+    cancel: RefCell<Option<Arc<AtomicBool>>>,
     show_about: bool,
     package_builder: RefCell<ModPackerBuilder>,
     show_package_deps: bool,
     opt_folders: Option<Mutex<FxHashSet<PathBuf>>>,
-    dirty: RwLock<HashMap<String, Manifest>>,
+    endian_audit: Option<(ModPackerBuilder, Vec<uk_mod::endian::Mismatch>)>,
+    drift: Option<modals::DriftReview>,
+    index_review: Option<modals::IndexReview>,
+    policy_editor: Option<settings::PolicyEditor>,
+    dirty: watchdog::tracked::TrackedRwLock<HashMap<String, Manifest>>,
+    heartbeat: Arc<watchdog::Heartbeat>,
+    /// Measurements for the diagnostics overlay, taken only while it is shown.
+    metrics: Arc<metrics::Metrics>,
+    #[cfg(feature = "tray")]
+    tray: Option<tray::Tray>,
+    /// Whether the tray matches the setting, as last checked.
+    #[cfg(feature = "tray")]
+    tray_synced: bool,
+    /// Whether the window is hidden to the tray.
+    hidden: bool,
+    /// Set when quitting for real, rather than closing to the tray.
+    quitting: bool,
     sort: (Sort, bool),
     options_mod: Option<(Mod, bool)>,
+    option_images: options::OptionImages,
     temp_settings: Settings,
     toasts: egui_notify::Toasts,
+    /// The last report or other file exported, to show in its folder.
+    last_export: Option<PathBuf>,
     theme: uk_ui::visuals::Theme,
+    ui_scale: f32,
+    /// Text scales of the panes which zoom on their own.
+    text_scales: uk_ui::scale::TextScales,
+    window: uk_ui::scale::WindowState,
+    scale_watcher: uk_ui::scale::ScaleWatcher,
+    tour: tour::TourState,
     dock_style: uk_ui::egui_dock::Style,
     changelog: Option<String>,
     new_version: Option<VersionResponse>,
+    log_view: crate::logger::LogView,
 }
 
 impl App {
-    fn new(cc: &eframe::CreationContext) -> Self {
-        if option_env!("UPDATE_PLATFORM").unwrap_or_default() == "steamdeck" {
-            cc.egui_ctx.set_pixels_per_point(
-                std::env::var("WINIT_X11_SCALE_FACTOR")
-                    .ok()
-                    .and_then(|v| v.parse().ok())
-                    .unwrap_or(1.0),
-            );
-        }
-        uk_ui::icons::load_icons();
-        uk_ui::load_fonts(&cc.egui_ctx);
-        let core = Arc::new(Manager::init().unwrap());
+    fn new(ctx: &egui::Context, core: Arc<Manager>) -> Self {
         let ui_state: UiState = fs::read_to_string(core.settings().state_file())
             .context("")
             .and_then(|s| serde_json::from_str(&s).context(""))
             .unwrap_or_default();
-        ui_state.theme.set_theme(&cc.egui_ctx);
+        ui_state.theme.set_theme(ctx);
+        uk_ui::scale::apply_scale(ctx, ui_state.ui_scale);
+        ui_state.window.apply(ctx, MIN_WINDOW_SIZE);
         let mods: Vec<_> = core.mod_manager().all_mods().collect();
         let (send, recv) = flume::unbounded();
         tasks::ONECLICK_SENDER.set(send.clone()).unwrap_or(());
+        if ui_state.show_labels && core.settings().dump().is_some() {
+            send.send(Message::ShowLabels(true)).unwrap_or(());
+        }
+        send.send(Message::CheckDrift).unwrap_or(());
+        send.send(Message::CheckDump).unwrap_or(());
+        let offline = core.mod_manager().offline_mods();
+        if !offline.is_empty() {
+            send.send(Message::AvailabilityProbed(offline))
+                .unwrap_or(());
+        }
+        let index_issues = core.mod_manager().take_load_issues();
+        if !index_issues.is_empty() {
+            send.send(Message::ShowIndexIssues(index_issues, false))
+                .unwrap_or(());
+        }
         crate::logger::LOGGER.set_file(Settings::config_dir().join("log.txt"));
         log::info!("Logger initialized");
         let temp_settings = core.settings().clone();
         let platform = core.settings().current_mode;
+        let first_run = core.settings().last_version.is_none();
         Self {
             selected: mods.first().cloned().into_iter().collect(),
             drag_index: None,
             hover_index: None,
+            expanded_mods: Default::default(),
+            offline: Default::default(),
+            row_text: Default::default(),
             package_builder: RefCell::new(ModPackerBuilder::new(platform)),
             picker_state: ui_state.picker_state,
+            picker_peek: Default::default(),
             profiles_state: RefCell::new(profiles::ProfileManagerState::new(&core)),
+            tweak_state: RefCell::new(Default::default()),
+            split_state: RefCell::new(Default::default()),
+            deps_state: RefCell::new(Default::default()),
+            compare_state: RefCell::new(Default::default()),
+            compat_state: RefCell::new(Default::default()),
+            snapshot_state: RefCell::new(Default::default()),
+            history_state: RefCell::new(Default::default()),
+            undo: Default::default(),
+            activity_state: RefCell::new(Default::default()),
+            simulation: RefCell::new(None),
+            bisect_state: RefCell::new(bisect::BisectState::new(&core)),
+            server: None,
             meta_input: MetaInputModal::new(send.clone()),
             displayed_mods: mods.clone(),
+            mod_keys: Default::default(),
+            mod_filter: String::new(),
             mods,
             temp_settings,
             changelog: {
@@ -309,35 +549,80 @@ impl App {
                     None
                 }
             },
-            channel: (send, recv),
-            closed_tabs: Default::default(),
+            channel: (send, recv.clone()),
+            // Tabs missing from the saved layout, including any added since it was
+            // saved, count as closed so they can be reopened from the menu
+            closed_tabs: Tabs::ALL
+                .into_iter()
+                .filter(|tab| ui_state.tree.find_tab(tab).is_none())
+                .map(|tab| (tab, NodeIndex::root()))
+                .collect(),
             focused: FocusedPane::None,
             error: None,
             new_profile: None,
             confirm: None,
+            locked_action: None,
+            duplicate: None,
             show_about: false,
             show_package_deps: false,
             opt_folders: None,
+            endian_audit: None,
+            drift: None,
+            index_review: None,
+            policy_editor: None,
             busy: Cell::new(false),
+            cancel: RefCell::new(None),
             dirty: {
                 let settings = core.settings();
-                RwLock::new(
+                watchdog::tracked::TrackedRwLock::new(
+                    "dirty",
                     settings
                         .profiles()
                         .map(|p| (p.into(), Default::default()))
                         .collect(),
                 )
             },
+            heartbeat: {
+                let heartbeat = Arc::new(watchdog::Heartbeat::default());
+                watchdog::spawn(ctx.clone(), heartbeat.clone(), recv);
+                heartbeat
+            },
+            metrics: {
+                let metrics = Arc::new(metrics::Metrics::default());
+                metrics.set_enabled(ui_state.show_metrics);
+                metrics
+            },
+            #[cfg(feature = "tray")]
+            tray: None,
+            #[cfg(feature = "tray")]
+            tray_synced: false,
+            hidden: false,
+            quitting: false,
             sort: (Sort::Priority, false),
             options_mod: None,
+            option_images: Default::default(),
             tree: Rc::new(RefCell::new(ui_state.tree)),
             toasts: egui_notify::Toasts::new().with_anchor(egui_notify::Anchor::BottomRight),
+            last_export: None,
             theme: ui_state.theme,
-            dock_style: uk_ui::visuals::style_dock(&cc.egui_ctx.style()),
+            ui_scale: uk_ui::scale::clamp_scale(ui_state.ui_scale),
+            text_scales: ui_state.text_scales,
+            window: ui_state.window,
+            scale_watcher: Default::default(),
+            tour: {
+                let mut tour = tour::TourState::new(ui_state.tour_done);
+                // Shown once the introduction is closed
+                if first_run && !tour.done() {
+                    tour.start();
+                }
+                tour
+            },
+            dock_style: uk_ui::visuals::style_dock(&ctx.style()),
             install_queue: Default::default(),
             update_mod: Default::default(),
             error_queue: Default::default(),
             new_version: None,
+            log_view: crate::logger::LogView::new(),
             core,
         }
     }
@@ -348,24 +633,26 @@ impl App {
     }
 
     #[inline(always)]
-    fn dirty(&self) -> MappedRwLockReadGuard<'_, uk_mod::Manifest> {
-        let dirty = self.dirty.read();
-        RwLockReadGuard::map(dirty, |dirty| {
-            dirty
-                .get(self.core.mod_manager().profile().key().as_str())
-                .or_else(|| dirty.values().next())
-                .unwrap()
+    fn dirty(&self) -> Held<MappedRwLockReadGuard<'_, uk_mod::Manifest>> {
+        Held::map(self.dirty.read(), |dirty| {
+            RwLockReadGuard::map(dirty, |dirty| {
+                dirty
+                    .get(self.core.mod_manager().profile().key().as_str())
+                    .or_else(|| dirty.values().next())
+                    .unwrap()
+            })
         })
     }
 
     #[inline(always)]
-    fn dirty_mut(&self) -> MappedRwLockWriteGuard<'_, uk_mod::Manifest> {
-        let dirty = self.dirty.write();
-        RwLockWriteGuard::map(dirty, |dirty| {
-            dirty.get_mut(self.core.mod_manager().profile().key().as_str())
-            .map(|m| unsafe { &mut *(m as *mut _) }) // Classic Polonius situation
-            .or_else(|| dirty.values_mut().next())
-            .unwrap()
+    fn dirty_mut(&self) -> Held<MappedRwLockWriteGuard<'_, uk_mod::Manifest>> {
+        Held::map(self.dirty.write(), |dirty| {
+            RwLockWriteGuard::map(dirty, |dirty| {
+                dirty.get_mut(self.core.mod_manager().profile().key().as_str())
+                .map(|m| unsafe { &mut *(m as *mut _) }) // Classic Polonius situation
+                .or_else(|| dirty.values_mut().next())
+                .unwrap()
+            })
         })
     }
 
@@ -375,10 +662,14 @@ impl App {
             || self.busy.get()
             || self.options_mod.is_some()
             || self.confirm.is_some()
+            || self.locked_action.is_some()
             || self.show_about
             || self.new_profile.is_some()
             || self.show_package_deps
             || self.opt_folders.is_some()
+            || self.endian_audit.is_some()
+            || self.drift.is_some()
+            || self.index_review.is_some()
             || self.meta_input.is_open()
             || self.changelog.is_some()
     }
@@ -398,8 +689,15 @@ impl App {
         let sender = self.channel.0.clone();
         let core = self.core.clone();
         let task = Box::new(task);
+        let tracked = self.metrics.task(|| {
+            self.heartbeat
+                .current()
+                .unwrap_or_else(|| "Background task".into())
+        });
         self.busy.set(true);
+        self.cancel.replace(None);
         thread::spawn(move || {
+            let _tracked = tracked;
             let response = match std::panic::catch_unwind(|| task(core.clone())) {
                 Ok(Ok(msg)) => msg,
                 Ok(Err(e)) => Message::Error(e),
@@ -422,6 +720,33 @@ impl App {
         });
     }
 
+    /// Runs a task which can be stopped from the busy dialog.
+    fn do_cancellable_task(
+        &self,
+        task: impl 'static
+        + Send
+        + Sync
+        + FnOnce(Arc<Manager>, Arc<AtomicBool>) -> Result<Message>
+        + std::panic::UnwindSafe,
+    ) {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        self.do_task(move |core| task(core, flag));
+        self.cancel.replace(Some(cancel));
+    }
+
+    /// Keeps the remembered window geometry current, and rebuilds anything
+    /// sized for the old scale when the pixels per point change.
+    fn handle_scale(&mut self, ctx: &eframe::egui::Context) {
+        ctx.input(|i| self.window.observe(i.viewport()));
+        let pixels_per_point = ctx.pixels_per_point();
+        if self.scale_watcher.update(pixels_per_point) {
+            log::debug!("UI scale changed to {pixels_per_point}");
+            uk_ui::icons::load_icons_at(pixels_per_point);
+            self.dock_style = uk_ui::visuals::style_dock(&ctx.style());
+        }
+    }
+
     fn handle_drops(&mut self, ctx: &eframe::egui::Context) {
         let files = ctx.input(|i| i.raw.dropped_files.clone());
         if !(self.modal_open() || files.is_empty()) {
@@ -436,14 +761,31 @@ impl App {
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+        self.handle_metrics_key(ctx);
+        let frame_start = self.metrics.enabled().then(Instant::now);
+        self.handle_scale(ctx);
+        #[cfg(feature = "tray")]
+        self.handle_tray(ctx);
+        self.handle_reveal(ctx);
         self.handle_update(ctx, frame);
         self.render_menu(ctx, frame);
         self.render_error(ctx);
         self.render_confirm(ctx);
+        self.render_locked(ctx);
+        self.render_duplicate(ctx);
+        self.render_drift(ctx);
+        self.render_index_review(ctx);
         self.render_new_profile(ctx);
         self.render_about(ctx);
         self.render_option_picker(ctx);
         self.profiles_state.borrow_mut().render(self, ctx);
+        self.tweak_state.borrow_mut().render(self, ctx);
+        self.split_state.borrow_mut().render(self, ctx);
+        self.deps_state.borrow_mut().render(ctx);
+        self.compare_state.borrow_mut().render(self, ctx);
+        self.compat_state.borrow_mut().render(self, ctx);
+        self.snapshot_state.borrow_mut().render(self, ctx);
+        self.bisect_state.borrow_mut().render(self, ctx);
         self.render_changelog(ctx);
         self.meta_input.ui(ctx);
         let layer_id = LayerId::background();
@@ -462,8 +804,17 @@ impl eframe::App for App {
         DockArea::new(&mut Rc::clone(&self.tree).borrow_mut())
             .style(self.dock_style.clone())
             .show_inside(&mut ui, self);
+        self.render_tour(ctx);
         self.render_busy(ctx, frame);
         self.toasts.show(ctx);
+        self.heartbeat.frame(
+            self.busy.get(),
+            self.hidden || ctx.input(|i| i.viewport().minimized.unwrap_or(false)),
+        );
+        if let Some(start) = frame_start {
+            self.metrics.frame(start.elapsed());
+            self.render_metrics(ctx);
+        }
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
@@ -474,6 +825,12 @@ impl eframe::App for App {
             theme: self.theme,
             picker_state: std::mem::take(&mut self.picker_state),
             tree: std::mem::replace(&mut self.tree.borrow_mut(), tabs::default_ui()),
+            show_labels: info::LABELS.read().is_some(),
+            ui_scale: self.ui_scale,
+            text_scales: std::mem::take(&mut self.text_scales),
+            window: std::mem::take(&mut self.window),
+            tour_done: self.tour.done(),
+            show_metrics: self.metrics.enabled(),
         };
         fs::write(
             self.core.settings().state_file(),
@@ -503,12 +860,31 @@ pub fn main() -> Result<(), eframe::Error> {
                     }
                     .into(),
                 ),
-                min_inner_size: Some(egui::Vec2::new(850.0, 500.0)),
+                min_inner_size: Some(MIN_WINDOW_SIZE),
                 inner_size: Some(egui::Vec2::new(1200.0, 800.0)),
                 ..Default::default()
             },
             ..Default::default()
         },
-        Box::new(|cc| Ok(Box::new(App::new(cc)))),
+        Box::new(|cc| {
+            uk_ui::icons::load_icons();
+            uk_ui::load_fonts(&cc.egui_ctx);
+            uk_ui::egui_extras::install_image_loaders(&cc.egui_ctx);
+            match Manager::init() {
+                Ok(core) => Ok(Box::new(App::new(&cc.egui_ctx, Arc::new(core)))),
+                Err(e) => {
+                    match e
+                        .chain()
+                        .find_map(|e| e.downcast_ref::<uk_manager::recovery::StorageMissing>())
+                    {
+                        Some(missing) => {
+                            log::error!("{missing}");
+                            Ok(Box::new(recovery::Recovery::new(missing.0.clone())))
+                        }
+                        None => panic!("{e:?}"),
+                    }
+                }
+            }
+        }),
     )
 }
@@ -0,0 +1,174 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uk_manager::audit::{AuditLog, Entry, Event};
+use uk_ui::{
+    egui::{self, Align, Layout, Ui},
+    icons::{Icon, IconButtonExt},
+};
+
+use super::{history::format_age, App};
+
+const DAY: u64 = 86_400_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Since {
+    #[default]
+    AnyTime,
+    Day,
+    Week,
+    Month,
+}
+
+impl Since {
+    const ALL: [Since; 4] = [Since::AnyTime, Since::Day, Since::Week, Since::Month];
+
+    fn label(self) -> &'static str {
+        match self {
+            Since::AnyTime => "Any time",
+            Since::Day => "Last 24 hours",
+            Since::Week => "Last 7 days",
+            Since::Month => "Last 30 days",
+        }
+    }
+
+    /// The earliest time shown, in milliseconds since the Unix epoch.
+    fn cutoff(self, now: u64) -> u64 {
+        match self {
+            Since::AnyTime => 0,
+            Since::Day => now.saturating_sub(DAY),
+            Since::Week => now.saturating_sub(7 * DAY),
+            Since::Month => now.saturating_sub(30 * DAY),
+        }
+    }
+}
+
+fn kind_label(kind: &str) -> String {
+    let label = kind.replace('_', " ");
+    let mut chars = label.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// The audit log as shown in its tab. It is read when the tab is first
+/// drawn and again after each change to the mod list.
+#[derive(Debug, Default)]
+pub struct ActivityState {
+    entries: Option<Vec<Entry>>,
+    kind:    Option<&'static str>,
+    since:   Since,
+}
+
+impl ActivityState {
+    pub fn invalidate(&mut self) {
+        self.entries = None;
+    }
+
+    /// Entries matching the filters, newest first.
+    fn visible(&self, now: u64) -> Vec<&Entry> {
+        let cutoff = self.since.cutoff(now);
+        self.entries
+            .iter()
+            .flatten()
+            .rev()
+            .filter(|e| e.time >= cutoff)
+            .filter(|e| self.kind.map(|k| e.event.kind() == k).unwrap_or(true))
+            .collect()
+    }
+}
+
+impl App {
+    pub fn render_activity(&self, ui: &mut Ui) {
+        let mut state = self.activity_state.borrow_mut();
+        if state.entries.is_none() {
+            state.entries = Some(AuditLog::open(&self.core.settings()).entries());
+        }
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("activity-kind")
+                .selected_text(state.kind.map(kind_label).unwrap_or_else(|| "All".into()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.kind, None, "All");
+                    for kind in Event::KINDS {
+                        ui.selectable_value(&mut state.kind, Some(kind), kind_label(kind));
+                    }
+                });
+            egui::ComboBox::from_id_source("activity-since")
+                .selected_text(state.since.label())
+                .show_ui(ui, |ui| {
+                    for since in Since::ALL {
+                        ui.selectable_value(&mut state.since, since, since.label());
+                    }
+                });
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui
+                    .icon_button(Icon::Refresh)
+                    .on_hover_text("Reload activity")
+                    .clicked()
+                {
+                    state.invalidate();
+                }
+            });
+        });
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let visible = state.visible(now);
+        if visible.is_empty() {
+            ui.centered_and_justified(|ui| {
+                ui.label("No matching activity");
+            });
+            return;
+        }
+        egui::ScrollArea::vertical()
+            .id_source("activity-entries")
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                egui::Grid::new("activity-grid")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for entry in visible {
+                            ui.label(format_age(now, entry.time));
+                            ui.label(kind_label(entry.event.kind()));
+                            ui.label(entry.event.describe());
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: u64, event: Event) -> Entry {
+        Entry { time, event }
+    }
+
+    #[test]
+    fn filters() {
+        let now = 40 * DAY;
+        let mut state = ActivityState {
+            entries: Some(vec![
+                entry(now - 20 * DAY, Event::SettingsChanged),
+                entry(now - 3 * DAY, Event::Deployed {
+                    profile: "Default".into(),
+                }),
+                entry(now - 1000, Event::SettingsChanged),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(state.visible(now).len(), 3);
+        assert_eq!(state.visible(now)[0].time, now - 1000);
+        state.since = Since::Week;
+        assert_eq!(state.visible(now).len(), 2);
+        state.kind = Some("settings_changed");
+        assert_eq!(state.visible(now).len(), 1);
+        state.since = Since::AnyTime;
+        assert_eq!(state.visible(now).len(), 2);
+        assert_eq!(kind_label("settings_changed"), "Settings changed");
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow_ext::Result;
+use uk_manager::mods::Mod;
+use uk_ui::egui::{self, Ui};
+
+use super::{update::describe_mods, App, Message};
+
+impl App {
+    /// Archives mods, first disabling any which are enabled if `disable` is
+    /// set, or else asking to.
+    pub(super) fn archive_mods(&mut self, mods: Vec<Mod>, disable: bool) {
+        let enabled: Vec<Mod> = self
+            .mods
+            .iter()
+            .filter(|m| m.enabled && mods.contains(m))
+            .cloned()
+            .collect();
+        if !enabled.is_empty() {
+            if disable {
+                self.do_update(Message::ToggleMods(Some(enabled), false));
+                self.do_update(Message::ArchiveMods(Some(mods), true));
+            } else {
+                self.do_update(Message::Confirm(
+                    Message::ArchiveMods(Some(mods), true).into(),
+                    format!(
+                        "Archived mods are left out of merging, so {} will be disabled first. \
+                         Apply afterward to remove their changes from the merged files.",
+                        describe_mods(&enabled)
+                    ),
+                ));
+            }
+            return;
+        }
+        let manager = self.core.mod_manager();
+        let mut archived = vec![];
+        let result = mods.iter().try_for_each(|m| -> Result<()> {
+            // Disabling in the list is only pending until applied
+            manager.set_enabled(m, false, None)?;
+            manager.archive(m)?;
+            archived.push(m.clone());
+            Ok(())
+        });
+        let result = result.and(manager.save());
+        drop(manager);
+        self.mods.retain(|m| !archived.contains(m));
+        self.selected.retain(|m| !archived.contains(m));
+        self.do_update(Message::RefreshModsDisplay);
+        match result {
+            Ok(()) => {
+                self.do_update(Message::Toast(format!(
+                    "Archived {}",
+                    describe_mods(&archived)
+                )))
+            }
+            Err(e) => self.do_update(Message::Error(e)),
+        }
+    }
+
+    /// Puts an archived mod back at the end of the list.
+    pub(super) fn unarchive_mod(&mut self, mod_: Mod) {
+        let manager = self.core.mod_manager();
+        let result = manager
+            .unarchive(&mod_)
+            .and_then(|mod_| manager.save().map(|_| mod_));
+        drop(manager);
+        match result {
+            Ok(mod_) => {
+                if !self.mods.contains(&mod_) {
+                    self.mods.push(mod_);
+                }
+                self.do_update(Message::RefreshModsDisplay);
+            }
+            Err(e) => self.do_update(Message::Error(e)),
+        }
+    }
+
+    /// The archived mods of the profile, under a header which starts closed.
+    pub(super) fn render_archived(&self, ui: &mut Ui) {
+        let archived = self.core.mod_manager().archived_mods();
+        if archived.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new(format!("Archived ({})", archived.len()))
+            .id_source("archived_mods")
+            .default_open(false)
+            .show(ui, |ui| {
+                for mod_ in archived {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .small_button("Unarchive")
+                            .on_hover_text("Put back at the end of the load order, disabled")
+                            .clicked()
+                        {
+                            self.do_update(Message::UnarchiveMod(mod_.clone()));
+                        }
+                        ui.label(mod_.meta.name.as_str());
+                        ui.weak(mod_.meta.version.to_string());
+                    });
+                }
+            });
+    }
+}
@@ -0,0 +1,225 @@
+use rustc_hash::FxHashSet;
+use uk_manager::{
+    bisect::{Outcome, Session, Step},
+    core::Manager,
+};
+use uk_ui::egui::{self, Align, Align2, Layout, RichText};
+
+use super::{visuals, App, Message};
+
+#[derive(Debug, Default)]
+pub struct BisectState {
+    /// Mods to keep enabled, while choosing them before a bisect starts.
+    setup:   Option<FxHashSet<usize>>,
+    session: Option<Session>,
+}
+
+impl BisectState {
+    /// Picks up any bisect left in progress in the current profile.
+    pub fn new(core: &Manager) -> Self {
+        Self {
+            setup:   None,
+            session: Session::load(&core.settings()),
+        }
+    }
+
+    pub fn reload(&mut self, core: &Manager) {
+        *self = Self::new(core);
+    }
+
+    pub fn open(&mut self) {
+        if self.session.is_none() {
+            self.setup = Some(Default::default());
+        }
+    }
+
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    pub fn set_session(&mut self, session: Option<Session>) {
+        self.setup = None;
+        self.session = session;
+    }
+
+    fn render_setup(&mut self, app: &App, ui: &mut egui::Ui) {
+        let Some(pinned) = self.setup.as_mut() else {
+            return;
+        };
+        ui.label(
+            "Find the mod which makes the game crash. Half of the enabled mods will be turned off \
+             at a time, and after each change you launch the game and report whether it still \
+             crashes.",
+        );
+        ui.label("Tick any mods which must always stay enabled.");
+        egui::ScrollArea::vertical()
+            .id_source("bisect_pinned")
+            .max_height(240.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for mod_ in app.mods.iter().filter(|m| m.enabled) {
+                    let mut keep = pinned.contains(&mod_.hash());
+                    if ui.checkbox(&mut keep, mod_.meta.name.as_str()).changed() {
+                        if keep {
+                            pinned.insert(mod_.hash());
+                        } else {
+                            pinned.remove(&mod_.hash());
+                        }
+                    }
+                }
+            });
+        let pending = !app.dirty().is_empty();
+        if pending {
+            ui.label(
+                RichText::new("Apply or discard pending changes before starting")
+                    .color(visuals::YELLOW),
+            );
+        }
+        let mut close = false;
+        ui.horizontal(|ui| {
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui
+                    .add_enabled(!pending && !app.busy.get(), egui::Button::new("Start"))
+                    .clicked()
+                {
+                    app.do_update(Message::StartBisect(pinned.iter().copied().collect()));
+                }
+                close = ui.button("Close").clicked();
+            });
+        });
+        if close {
+            self.setup = None;
+        }
+    }
+
+    fn render_session(&self, app: &App, ui: &mut egui::Ui) {
+        let Some(session) = self.session.as_ref() else {
+            return;
+        };
+        let bisect = &session.bisect;
+        let name = |hash: &usize| {
+            app.mods
+                .iter()
+                .find(|m| m.hash() == *hash)
+                .map(|m| m.meta.name.to_string())
+                .unwrap_or_else(|| "Unknown mod".into())
+        };
+        let busy = app.busy.get();
+        match bisect.step() {
+            Step::Test(set) => {
+                ui.label(format!(
+                    "Test {}: {} mods are enabled, with {} still suspected.",
+                    bisect.history().len() + 1,
+                    set.len(),
+                    bisect.suspects().len()
+                ));
+                if !bisect.context().is_empty() {
+                    ui.label(
+                        RichText::new(
+                            "Neither half crashed on its own, so now looking for two mods which \
+                             crash together.",
+                        )
+                        .color(visuals::YELLOW),
+                    );
+                }
+                ui.label("Launch the game. Does it still crash?");
+                egui::CollapsingHeader::new("Enabled for This Test").show(ui, |ui| {
+                    for hash in set.iter() {
+                        ui.label(name(hash));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("It Worked"))
+                            .clicked()
+                        {
+                            app.do_update(Message::ReportBisect(false));
+                        }
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("It Crashed"))
+                            .clicked()
+                        {
+                            app.do_update(Message::ReportBisect(true));
+                        }
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("Cancel"))
+                            .on_hover_text("Stop and put every mod back as it was")
+                            .clicked()
+                        {
+                            app.do_update(Message::EndBisect(vec![]));
+                        }
+                    });
+                });
+            }
+            Step::Done(outcome) => {
+                let culprits = outcome.culprits();
+                ui.label(match &outcome {
+                    Outcome::Single(hash) => format!("The crash is caused by {}.", name(hash)),
+                    Outcome::Pair(first, second) => {
+                        format!(
+                            "The crash is caused by {} and {} together.",
+                            name(first),
+                            name(second)
+                        )
+                    }
+                    Outcome::Inconclusive(hashes) if hashes.is_empty() => {
+                        "There were no mods to test. The crash may be caused by a mod which was \
+                         kept enabled."
+                            .into()
+                    }
+                    Outcome::Inconclusive(_) => {
+                        "The crash needs more than two mods together. These mods crash between \
+                         them:"
+                            .into()
+                    }
+                });
+                if let Outcome::Inconclusive(hashes) = &outcome {
+                    for hash in hashes {
+                        ui.label(name(hash));
+                    }
+                }
+                ui.label(format!("Found after {} tests.", bisect.history().len()));
+                ui.horizontal(|ui| {
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui
+                            .add_enabled(
+                                !busy && !culprits.is_empty(),
+                                egui::Button::new("Leave Disabled"),
+                            )
+                            .on_hover_text("Put the other mods back and leave these disabled")
+                            .clicked()
+                        {
+                            app.do_update(Message::EndBisect(culprits));
+                        }
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("Restore All"))
+                            .on_hover_text("Put every mod back as it was")
+                            .clicked()
+                        {
+                            app.do_update(Message::EndBisect(vec![]));
+                        }
+                    });
+                });
+            }
+        }
+    }
+
+    pub fn render(&mut self, app: &App, ctx: &egui::Context) {
+        if self.setup.is_none() && self.session.is_none() {
+            return;
+        }
+        egui::Window::new("Find Crashing Mod")
+            .anchor(Align2::RIGHT_TOP, [-32.0, 48.0])
+            .collapsible(true)
+            .resizable(false)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                if self.session.is_some() {
+                    self.render_session(app, ui);
+                } else {
+                    self.render_setup(app, ui);
+                }
+            });
+    }
+}
@@ -0,0 +1,197 @@
+//! Sorting and searching mod names the way people read them rather than by
+//! their bytes. Sorting uses Unicode collation, tailored to a locale if one is
+//! set, and searching ignores case and accents, so "pokemon" finds
+//! "Pokémon". Both need work per string, so the results are kept per mod and
+//! only recomputed when the mods or the locale change.
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+use rustc_hash::FxHashMap;
+use uk_manager::mods::Mod;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Folds text for searching: case folded, compatibility decomposed, and with
+/// accents and other combining marks removed.
+pub fn fold(text: &str) -> String {
+    caseless::default_case_fold_str(text)
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+/// A collator for a locale such as `ja` or `sv`, or the root collation if the
+/// locale is empty or not recognised.
+pub fn collator(locale: &str) -> Option<Collator> {
+    let locale = match locale.trim() {
+        "" => Locale::UND,
+        tag => {
+            tag.parse().unwrap_or_else(|e| {
+                log::warn!("Unknown sorting locale {tag}, using default sorting: {e}");
+                Locale::UND
+            })
+        }
+    };
+    Collator::try_new(&(&locale).into(), CollatorOptions::new())
+        .inspect_err(|e| log::warn!("Could not load collation for {locale}: {e}"))
+        .ok()
+}
+
+/// The position of each string in collation order. Strings which collate
+/// equal share a rank.
+fn ranks(collator: Option<&Collator>, strings: &[&str]) -> Vec<usize> {
+    let compare = |a: &str, b: &str| {
+        match collator {
+            Some(collator) => collator.compare(a, b),
+            None => fold(a).cmp(&fold(b)),
+        }
+    };
+    let mut order: Vec<usize> = (0..strings.len()).collect();
+    order.sort_by(|&a, &b| compare(strings[a], strings[b]));
+    let mut ranks = vec![0; strings.len()];
+    for (i, window) in order.windows(2).enumerate() {
+        let [prev, next] = [window[0], window[1]];
+        ranks[next] = if compare(strings[prev], strings[next]).is_eq() {
+            ranks[prev]
+        } else {
+            i + 1
+        };
+    }
+    ranks
+}
+
+#[derive(Debug, Default)]
+struct Keys {
+    name:     usize,
+    category: usize,
+    /// Folded name and category, for searching.
+    search:   String,
+}
+
+/// Sort and search keys for each mod, by hash.
+#[derive(Debug, Default)]
+pub struct ModKeys {
+    locale: String,
+    keys:   FxHashMap<usize, Keys>,
+}
+
+impl ModKeys {
+    /// Recomputes the keys if the mods or the locale changed.
+    pub fn update(&mut self, mods: &[Mod], locale: &str) {
+        if self.locale == locale
+            && self.keys.len() == mods.len()
+            && mods.iter().all(|m| self.keys.contains_key(&m.hash()))
+        {
+            return;
+        }
+        let collator = collator(locale);
+        let names: Vec<&str> = mods.iter().map(|m| m.meta.name.as_str()).collect();
+        let categories: Vec<&str> = mods.iter().map(|m| m.meta.category.as_str()).collect();
+        let names = ranks(collator.as_ref(), &names);
+        let categories = ranks(collator.as_ref(), &categories);
+        self.locale = locale.into();
+        self.keys = mods
+            .iter()
+            .zip(names.into_iter().zip(categories))
+            .map(|(mod_, (name, category))| {
+                (mod_.hash(), Keys {
+                    name,
+                    category,
+                    search: fold(&format!("{}\n{}", mod_.meta.name, mod_.meta.category)),
+                })
+            })
+            .collect();
+    }
+
+    pub fn name(&self, hash: usize) -> usize {
+        self.keys.get(&hash).map(|k| k.name).unwrap_or(usize::MAX)
+    }
+
+    pub fn category(&self, hash: usize) -> usize {
+        self.keys
+            .get(&hash)
+            .map(|k| k.category)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Whether a mod's name or category contains a query, which should
+    /// already be [folded](fold).
+    pub fn matches(&self, hash: usize, query: &str) -> bool {
+        query.is_empty()
+            || self
+                .keys
+                .get(&hash)
+                .is_some_and(|k| k.search.contains(query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted<'a>(locale: &str, names: &[&'a str]) -> Vec<&'a str> {
+        let ranks = ranks(collator(locale).as_ref(), names);
+        let mut sorted: Vec<_> = names.iter().zip(ranks).collect();
+        sorted.sort_by_key(|(_, rank)| *rank);
+        sorted.into_iter().map(|(name, _)| *name).collect()
+    }
+
+    #[test]
+    fn ordering() {
+        let names = [
+            "Zelda Tweaks",
+            "ガノン Rebalance",
+            "Ёлка Textures",
+            "Élan Mod",
+            "apple Pack",
+            "éclair",
+            "Bow Mod",
+        ];
+        assert_eq!(sorted("", &names), [
+            "apple Pack",
+            "Bow Mod",
+            "éclair",
+            "Élan Mod",
+            "Zelda Tweaks",
+            "Ёлка Textures",
+            "ガノン Rebalance",
+        ]);
+        // Swedish sorts Ö after Z, where the root collation sorts it with O
+        let names = ["Zebra", "Öga", "Orm"];
+        assert_eq!(sorted("", &names), ["Öga", "Orm", "Zebra"]);
+        assert_eq!(sorted("sv", &names), ["Orm", "Zebra", "Öga"]);
+        assert_eq!(sorted("not a locale!", &names), ["Öga", "Orm", "Zebra"]);
+        let ranks = ranks(collator("").as_ref(), &["b", "a", "b"]);
+        assert_eq!(ranks, [1, 0, 1]);
+    }
+
+    #[test]
+    fn matching() {
+        assert_eq!(fold("Pokémon"), "pokemon");
+        assert_eq!(fold("POKEMON"), "pokemon");
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("Ёлка"), "елка");
+        assert_eq!(fold("ﾘﾝｸ"), "リンク");
+        let mut keys = ModKeys::default();
+        let names = ["Pokémon Overhaul", "Ёлка Textures", "Second Wind"];
+        keys.keys = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                (i, Keys {
+                    search: fold(name),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let found = |query: &str| -> Vec<usize> {
+            (0..names.len())
+                .filter(|i| keys.matches(*i, &fold(query)))
+                .collect()
+        };
+        assert_eq!(found("pokemon"), [0]);
+        assert_eq!(found("POKÉMON over"), [0]);
+        assert_eq!(found("ЕЛКА"), [1]);
+        assert_eq!(found("wind"), [2]);
+        assert_eq!(found(""), [0, 1, 2]);
+        assert!(found("zelda").is_empty());
+    }
+}
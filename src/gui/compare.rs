@@ -0,0 +1,111 @@
+use fs_err as fs;
+use uk_manager::compare::{Outcome, Report};
+use uk_ui::egui::{self, Align, Align2, Layout, RichText, TextStyle};
+
+use super::{visuals, App, Message};
+
+/// Viewer for a comparison of the merged output with another merged folder.
+#[derive(Debug, Default)]
+pub struct CompareState {
+    report:    Option<Report>,
+    /// Whether files which are the same on both sides are listed too.
+    show_same: bool,
+}
+
+impl CompareState {
+    pub fn open(&mut self, report: Report) {
+        self.report = Some(report);
+    }
+
+    fn export(report: &Report, app: &App) {
+        if let Some(dest) = rfd::FileDialog::new()
+            .add_filter("Markdown", &["md"])
+            .set_title("Export Comparison")
+            .set_file_name("comparison.md")
+            .save_file()
+        {
+            match fs::write(&dest, report.to_markdown()) {
+                Ok(()) => app.do_update(Message::Exported(dest)),
+                Err(e) => app.do_update(Message::Error(e.into())),
+            }
+        }
+    }
+
+    fn render_entries(report: &Report, show_same: bool, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical()
+            .id_source("compare_entries")
+            .max_height(360.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for entry in report
+                    .entries
+                    .iter()
+                    .filter(|e| show_same || !e.outcome.is_same())
+                {
+                    let color = match entry.outcome {
+                        Outcome::Different(_) => visuals::RED,
+                        Outcome::OnlyOurs | Outcome::OnlyTheirs => visuals::YELLOW,
+                        _ => ui.visuals().weak_text_color(),
+                    };
+                    let label =
+                        RichText::new(format!("{} ({})", entry.path, entry.outcome.label()))
+                            .color(color);
+                    match &entry.outcome {
+                        Outcome::Different(preview) => {
+                            egui::CollapsingHeader::new(label)
+                                .id_source(("compare_entry", entry.path.as_str()))
+                                .show(ui, |ui| {
+                                    ui.label(
+                                        RichText::new(preview.trim_end())
+                                            .text_style(TextStyle::Monospace),
+                                    );
+                                });
+                        }
+                        _ => {
+                            ui.label(label);
+                        }
+                    }
+                }
+            });
+    }
+
+    pub fn render(&mut self, app: &App, ctx: &egui::Context) {
+        let Some(report) = self.report.as_ref() else {
+            return;
+        };
+        let mut close = false;
+        egui::Window::new("Merge Comparison")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(true)
+            .default_size([640.0, 480.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Compared the merged files of this profile with {}. Files are the same if \
+                     they hold the same data, even if their bytes differ.",
+                    report.theirs.display()
+                ));
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    for (label, count) in report.counts() {
+                        ui.label(format!("{label}: {count}"));
+                    }
+                });
+                ui.checkbox(&mut self.show_same, "Show files which are the same");
+                ui.add_space(4.0);
+                Self::render_entries(report, self.show_same, ui);
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        close = ui.button("Close").clicked();
+                        if ui.button("Export…").clicked() {
+                            Self::export(report, app);
+                        }
+                    });
+                });
+            });
+        if close {
+            self.report = None;
+        }
+    }
+}
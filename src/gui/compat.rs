@@ -0,0 +1,239 @@
+use std::path::PathBuf;
+
+use fs_err as fs;
+use uk_manager::{
+    compat::{Report, Verdict},
+    mods::Mod,
+};
+use uk_ui::egui::{self, Align, Align2, Layout, RichText};
+
+use super::{visuals, App, Message};
+
+/// One side of a compatibility check: an installed mod, or a mod file which
+/// is not installed yet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subject {
+    Installed(Box<Mod>),
+    File(PathBuf),
+}
+
+impl Subject {
+    pub fn name(&self) -> String {
+        match self {
+            Subject::Installed(mod_) => format!("{} {}", mod_.meta.name, mod_.meta.version),
+            Subject::File(path) => {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into())
+                    .unwrap_or_else(|| path.display().to_string())
+            }
+        }
+    }
+
+    fn is_installed(&self) -> bool {
+        matches!(self, Subject::Installed(_))
+    }
+}
+
+/// Picker for the mod to check another against, then viewer for the report.
+#[derive(Debug, Default)]
+pub struct CompatState {
+    /// The mod being checked, while the other is picked.
+    first:     Option<Subject>,
+    /// Whether to compare the keys installed mods both change.
+    deep:      bool,
+    report:    Option<Report>,
+    /// Whether files both mods' changes survive in are listed too.
+    show_fine: bool,
+}
+
+impl CompatState {
+    pub fn start(&mut self, first: Subject) {
+        self.first = Some(first);
+        self.report = None;
+    }
+
+    pub fn open(&mut self, report: Report) {
+        self.report = Some(report);
+    }
+
+    fn export(report: &Report, app: &App) {
+        if let Some(dest) = rfd::FileDialog::new()
+            .add_filter("Markdown", &["md"])
+            .set_title("Export Compatibility Report")
+            .set_file_name("compatibility.md")
+            .save_file()
+        {
+            match fs::write(&dest, report.to_markdown()) {
+                Ok(()) => app.do_update(Message::Exported(dest)),
+                Err(e) => app.do_update(Message::Error(e.into())),
+            }
+        }
+    }
+
+    fn render_picker(&mut self, app: &App, ctx: &egui::Context) {
+        let Some(first) = self.first.as_ref() else {
+            return;
+        };
+        let mut second = None;
+        let mut close = false;
+        egui::Window::new("Check Compatibility")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Check {} against:", first.name()));
+                ui.add_space(4.0);
+                egui::ScrollArea::vertical()
+                    .id_source("compat_mods")
+                    .max_height(280.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        let others = app.mods.iter().filter(|m| {
+                            !matches!(first, Subject::Installed(first) if **first == **m)
+                        });
+                        for mod_ in others {
+                            if ui
+                                .selectable_label(false, mod_.meta.name.as_str())
+                                .clicked()
+                            {
+                                second = Some(Subject::Installed(Box::new(mod_.clone())));
+                            }
+                        }
+                    });
+                ui.add_space(4.0);
+                ui.add_enabled(
+                    first.is_installed(),
+                    egui::Checkbox::new(&mut self.deep, "Compare the keys both mods edit"),
+                )
+                .on_hover_text(
+                    "For files both mods merge, check whether they change the same values. This \
+                     only works when both mods are installed, and takes longer.",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Mod File…").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .set_title("Select Mod to Check")
+                            .add_filter("UKMM Mod (*.zip)", &["zip"])
+                            .pick_file()
+                        {
+                            second = Some(Subject::File(path));
+                        }
+                    }
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        close = ui.button("Cancel").clicked();
+                    });
+                });
+            });
+        if let Some(second) = second {
+            let deep = self.deep && first.is_installed() && second.is_installed();
+            app.do_update(Message::CheckCompat(first.clone(), second, deep));
+            close = true;
+        }
+        if close {
+            self.first = None;
+        }
+    }
+
+    fn render_entries(report: &Report, show_fine: bool, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical()
+            .id_source("compat_entries")
+            .max_height(360.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for entry in report
+                    .entries
+                    .iter()
+                    .filter(|e| show_fine || !e.verdict.is_fine())
+                {
+                    let color = match entry.verdict {
+                        Verdict::Conflict => visuals::RED,
+                        Verdict::Priority | Verdict::Overlapping(_) => visuals::YELLOW,
+                        Verdict::Independent => visuals::GREEN,
+                        _ => ui.visuals().weak_text_color(),
+                    };
+                    let label =
+                        RichText::new(format!("{} ({})", entry.path, entry.verdict.label()))
+                            .color(color);
+                    match &entry.verdict {
+                        Verdict::Overlapping(keys) => {
+                            egui::CollapsingHeader::new(label)
+                                .id_source(("compat_entry", entry.path.as_str()))
+                                .show(ui, |ui| {
+                                    for key in keys {
+                                        ui.label(RichText::new(key).monospace());
+                                    }
+                                });
+                        }
+                        verdict => {
+                            ui.label(label).on_hover_text(match verdict {
+                                Verdict::Identical => "Both mods have the same version",
+                                Verdict::Compatible => "Both mods' changes are merged",
+                                Verdict::Independent => {
+                                    "Both mods' changes are merged, and they edit different keys"
+                                }
+                                Verdict::Priority => {
+                                    "One mod replaces the whole file, so the other's changes are \
+                                     only kept if it has priority"
+                                }
+                                _ => "Both mods replace the whole file, so only one can be used",
+                            });
+                        }
+                    }
+                }
+            });
+    }
+
+    fn render_report(&mut self, app: &App, ctx: &egui::Context) {
+        let Some(report) = self.report.as_ref() else {
+            return;
+        };
+        let mut close = false;
+        egui::Window::new("Compatibility Report")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(true)
+            .default_size([640.0, 480.0])
+            .show(ctx, |ui| {
+                ui.label(format!("{} and {}", report.first, report.second));
+                ui.add_space(4.0);
+                if report.entries.is_empty() {
+                    ui.label("These mods do not change any of the same files.");
+                } else {
+                    ui.horizontal_wrapped(|ui| {
+                        for (label, count) in report.counts() {
+                            ui.label(format!("{label}: {count}"));
+                        }
+                    });
+                    if !report.deep {
+                        ui.label(
+                            RichText::new(
+                                "Files both mods merge were not checked for edits to the same \
+                                 keys.",
+                            )
+                            .color(ui.visuals().weak_text_color()),
+                        );
+                    }
+                    ui.checkbox(&mut self.show_fine, "Show files without problems");
+                    ui.add_space(4.0);
+                    Self::render_entries(report, self.show_fine, ui);
+                }
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        close = ui.button("Close").clicked();
+                        if ui.button("Export…").clicked() {
+                            Self::export(report, app);
+                        }
+                    });
+                });
+            });
+        if close {
+            self.report = None;
+        }
+    }
+
+    pub fn render(&mut self, app: &App, ctx: &egui::Context) {
+        self.render_picker(app, ctx);
+        self.render_report(app, ctx);
+    }
+}
@@ -1,6 +1,93 @@
+use qrcode::{Color, QrCode};
+
 use super::*;
+use crate::serve::Server;
+
+/// A running server for the merged output, with its URL as a QR code so a
+/// console or phone can open it without typing.
+pub struct ServeState {
+    server: Server,
+    qr:     Option<QrCode>,
+}
+
+impl ServeState {
+    pub fn new(server: Server) -> Self {
+        let qr = QrCode::new(server.url().as_bytes())
+            .inspect_err(|e| log::warn!("Could not make QR code for server URL: {e}"))
+            .ok();
+        Self { server, qr }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.server.is_running()
+    }
+}
+
+/// Paints a QR code, black on white with the quiet zone the spec asks for.
+fn paint_qr(ui: &mut Ui, qr: &QrCode) {
+    const QUIET: usize = 4;
+    let width = qr.width();
+    let module = 4.0;
+    let size = (width + QUIET * 2) as f32 * module;
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(size), egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, egui::Color32::WHITE);
+    for (i, color) in qr.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let (x, y) = ((i % width + QUIET) as f32, (i / width + QUIET) as f32);
+            painter.rect_filled(
+                egui::Rect::from_min_size(
+                    rect.min + Vec2::new(x * module, y * module),
+                    Vec2::splat(module),
+                ),
+                0.0,
+                egui::Color32::BLACK,
+            );
+        }
+    }
+}
 
 impl App {
+    fn render_serve(&self, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            ui.label(
+                RichText::new("Serve Merged Output").family(egui::FontFamily::Name("Bold".into())),
+            );
+            match self.server.as_ref() {
+                Some(state) if state.is_running() => {
+                    let url = state.server.url();
+                    if ui
+                        .link(url)
+                        .on_hover_text(state.server.root().to_string_lossy())
+                        .clicked()
+                    {
+                        open::that(url).unwrap_or(());
+                    }
+                    if let Some(qr) = state.qr.as_ref() {
+                        paint_qr(ui, qr);
+                    }
+                    if ui.button("Stop Serving").clicked() {
+                        self.do_update(Message::StopServer);
+                    }
+                }
+                stopped => {
+                    if stopped.is_some() {
+                        ui.label(RichText::new("The server has stopped").color(visuals::YELLOW));
+                    }
+                    if ui
+                        .button("Start Serving")
+                        .on_hover_text(
+                            "Share the merged files over HTTP with other devices on your network",
+                        )
+                        .clicked()
+                    {
+                        self.do_update(Message::StartServer);
+                    }
+                }
+            }
+        });
+    }
+
     pub fn render_deploy_tab(&self, ui: &mut Ui) {
         match self
             .core
@@ -37,6 +124,25 @@ impl App {
                                 });
                             })
                         });
+                        let lock = self.core.deploy_manager().locked();
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                RichText::new("Locked")
+                                    .family(egui::FontFamily::Name("Bold".into())),
+                            );
+                            ui.with_layout(Layout::right_to_left(Align::Max), |ui| {
+                                match lock.as_ref() {
+                                    Some(lock) => {
+                                        ui.label(
+                                            RichText::new(format!("Yes ({})", lock.profile))
+                                                .color(visuals::YELLOW),
+                                        )
+                                    }
+                                    None => ui.label("No"),
+                                };
+                            })
+                        });
+                        self.render_free_space(ui, &config.output);
                         ui.vertical(|ui| {
                             ui.label(
                                 RichText::new("Target Folder")
@@ -56,20 +162,14 @@ impl App {
                                 max_width: ui.available_size_before_wrap().x,
                                 ..Default::default()
                             };
-                            if ui
-                                .link(job)
-                                .on_hover_text(config.output.to_string_lossy())
-                                .clicked()
-                            {
+                            let res = ui.link(job).on_hover_text(config.output.to_string_lossy());
+                            if res.clicked() {
                                 ui.close_menu();
-                                open::that(if config.output.is_dir() {
-                                    &config.output
-                                } else {
-                                    config.output.parent().unwrap()
-                                })
-                                .unwrap_or(());
+                                self.do_update(Message::Reveal(config.output.clone()));
                             }
+                            res.context_menu(|ui| reveal::copy_path_button(ui, &config.output));
                         });
+                        self.render_serve(ui);
                         ui.add_space(4.);
                         ui.with_layout(
                             Layout::from_main_dir_and_cross_align(
@@ -78,17 +178,39 @@ impl App {
                             ),
                             |ui| {
                                 egui::Frame::none().show(ui, |ui| {
-                                    if let Some(ref exe) = config.executable {
+                                    if config.executable.is_some() {
                                         ui.add_space(4.);
                                         if ui.button("Open Emulator").clicked() {
-                                            let cmd = util::default_shell();
-                                            let (shell, arg) = (&cmd.0, &cmd.1);
-                                            let _ = std::process::Command::new(shell)
-                                                .args(arg.iter())
-                                                .arg(exe)
-                                                .spawn();
+                                            self.do_update(Message::LaunchEmulator);
                                         }
                                     }
+                                    ui.horizontal(|ui| {
+                                        if ui
+                                            .button("Snapshots…")
+                                            .on_hover_text(
+                                                "Keep copies of the merged files to restore later",
+                                            )
+                                            .clicked()
+                                        {
+                                            self.do_update(super::Message::ShowSnapshots);
+                                        }
+                                        if lock.is_some() {
+                                            if ui.button("Unlock Deployment").clicked() {
+                                                self.do_update(super::Message::UnlockDeployment(
+                                                    None,
+                                                ));
+                                            }
+                                        } else if ui
+                                            .button("Lock Deployment")
+                                            .on_hover_text(
+                                                "Stop anything from being applied or deployed \
+                                                 until unlocked",
+                                            )
+                                            .clicked()
+                                        {
+                                            self.do_update(super::Message::LockDeployment);
+                                        }
+                                    });
                                     if !config.auto || self.core.deploy_manager().pending() {
                                         if ui
                                             .add_enabled(pending, egui::Button::new("Deploy"))
@@ -113,8 +235,10 @@ impl App {
                 });
             }
             None => {
-                ui.centered_and_justified(|ui| {
+                egui::Frame::none().inner_margin(4.0).show(ui, |ui| {
+                    ui.spacing_mut().item_spacing.y = 8.0;
                     ui.label("No deployment config for current platform");
+                    self.render_serve(ui);
                 });
             }
         }
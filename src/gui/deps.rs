@@ -0,0 +1,82 @@
+use uk_content::actor::deps::{Dependency, Source};
+use uk_ui::egui::{self, Align2, RichText, TextStyle};
+
+use super::visuals;
+
+/// Viewer for the files an actor depends on, for checking a custom actor
+/// ships with everything it needs.
+#[derive(Debug, Default)]
+pub struct DepsState {
+    root: Option<Dependency>,
+}
+
+impl DepsState {
+    pub fn open(&mut self, root: Dependency) {
+        self.root = Some(root);
+    }
+
+    fn render_dep(dep: &Dependency, ui: &mut egui::Ui) {
+        let color = match dep.source {
+            Source::Missing => visuals::RED,
+            Source::Dump => ui.visuals().weak_text_color(),
+            _ => ui.visuals().text_color(),
+        };
+        let label = RichText::new(format!("{} ({})", dep.path, dep.source)).color(color);
+        if dep.children.is_empty() {
+            ui.label(label).on_hover_text(dep.link.as_str());
+        } else {
+            egui::CollapsingHeader::new(label)
+                .id_source(("actor_dep", dep.path.as_str()))
+                .default_open(true)
+                .show(ui, |ui| {
+                    for child in &dep.children {
+                        Self::render_dep(child, ui);
+                    }
+                })
+                .header_response
+                .on_hover_text(dep.link.as_str());
+        }
+    }
+
+    pub fn render(&mut self, ctx: &egui::Context) {
+        let Some(root) = self.root.as_ref() else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Actor Dependencies")
+            .open(&mut open)
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(true)
+            .default_size([560.0, 480.0])
+            .show(ctx, |ui| {
+                let missing = root.missing().count();
+                if missing > 0 {
+                    ui.label(
+                        RichText::new(format!(
+                            "{missing} file(s) could not be found in the pack, the mod or the \
+                             game dump"
+                        ))
+                        .color(visuals::RED),
+                    );
+                } else {
+                    ui.label("Every file the actor needs was found");
+                }
+                ui.label(
+                    RichText::new(
+                        "Files from the game dump do not need to be shipped unless they are \
+                         changed",
+                    )
+                    .text_style(TextStyle::Small),
+                );
+                ui.add_space(4.0);
+                egui::ScrollArea::vertical()
+                    .id_source("actor_deps")
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| Self::render_dep(root, ui));
+            });
+        if !open {
+            self.root = None;
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uk_manager::history::{compare, History, HistoryDiff, HistoryEntry};
+use uk_ui::{
+    egui::{self, Align, Layout, RichText, Ui},
+    icons::{Icon, IconButtonExt},
+};
+
+use super::{visuals, App};
+
+/// The deployment history as shown in its tab. Entries are loaded when the
+/// tab is first drawn and again after each apply.
+#[derive(Debug, Default)]
+pub struct HistoryState {
+    entries: Option<Vec<HistoryEntry>>,
+    /// The older and newer entry to compare, as indices into `entries`.
+    compare: Option<(usize, usize)>,
+    diff:    Option<HistoryDiff>,
+}
+
+impl HistoryState {
+    pub fn invalidate(&mut self) {
+        *self = Default::default();
+    }
+
+    fn select(&mut self, from: usize, to: usize) {
+        let entries = self.entries.as_deref().unwrap_or_default();
+        let (from, to) = (from.min(to), from.max(to));
+        self.diff =
+            (from != to && to < entries.len()).then(|| compare(&entries[from], &entries[to]));
+        self.compare = Some((from, to));
+    }
+
+    fn select_latest(&mut self) {
+        let len = self.entries.as_ref().map(|e| e.len()).unwrap_or_default();
+        if len >= 2 {
+            self.select(len - 2, len - 1);
+        } else {
+            self.compare = None;
+            self.diff = None;
+        }
+    }
+}
+
+/// Describes how long ago a history entry was recorded.
+pub(super) fn format_age(now: u64, time: u64) -> String {
+    let secs = now.saturating_sub(time) / 1000;
+    let (count, unit) = match secs {
+        0..=59 => return "Just now".into(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!("{count} {unit}{} ago", if count == 1 { "" } else { "s" })
+}
+
+fn render_files(ui: &mut Ui, label: &str, files: &[smartstring::alias::String]) {
+    egui::CollapsingHeader::new(format!("{label} ({})", files.len()))
+        .id_source(label)
+        .enabled(!files.is_empty())
+        .show(ui, |ui| {
+            for file in files {
+                if ui
+                    .selectable_label(false, file.as_str())
+                    .on_hover_text("Click to copy path")
+                    .clicked()
+                {
+                    ui.output_mut(|o| o.copied_text = file.to_string());
+                }
+            }
+        });
+}
+
+impl App {
+    pub fn render_history(&self, ui: &mut Ui) {
+        let mut state = self.history_state.borrow_mut();
+        if state.entries.is_none() {
+            state.entries = Some(History::open(&self.core.settings()).entries());
+            state.select_latest();
+        }
+        let len = state.entries.as_ref().map(|e| e.len()).unwrap_or_default();
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(len >= 2, egui::Button::new("Latest vs. Previous"))
+                .clicked()
+            {
+                state.select_latest();
+            }
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui
+                    .icon_button(Icon::Refresh)
+                    .on_hover_text("Reload history")
+                    .clicked()
+                {
+                    state.invalidate();
+                }
+            });
+        });
+        if len == 0 {
+            ui.centered_and_justified(|ui| {
+                ui.label("No changes have been applied yet");
+            });
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let (mut from, mut to) = state.compare.unwrap_or((len - 1, len - 1));
+        let mut selection_changed = false;
+        egui::ScrollArea::vertical()
+            .id_source("history-entries")
+            .max_height(ui.available_height() / 2.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                egui::Grid::new("history-grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("From")
+                            .on_hover_text("The earlier apply to compare");
+                        ui.label("To").on_hover_text("The later apply to compare");
+                        ui.label("Applied");
+                        ui.label("Configuration");
+                        ui.end_row();
+                        let entries = state.entries.as_deref().unwrap_or_default();
+                        for (i, entry) in entries.iter().enumerate().rev() {
+                            selection_changed |= ui.radio_value(&mut from, i, "").changed();
+                            selection_changed |= ui.radio_value(&mut to, i, "").changed();
+                            ui.label(format_age(now, entry.time));
+                            ui.label(entry.summary()).on_hover_ui(|ui| {
+                                for mod_ in &entry.mods {
+                                    ui.label(if mod_.options.is_empty() {
+                                        format!("{} {}", mod_.name, mod_.version)
+                                    } else {
+                                        format!(
+                                            "{} {} ({})",
+                                            mod_.name,
+                                            mod_.version,
+                                            mod_.options.join(", ")
+                                        )
+                                    });
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+        if selection_changed {
+            state.select(from, to);
+        }
+        ui.separator();
+        match state.diff.as_ref() {
+            Some(diff) if diff.is_empty() => {
+                ui.label("No output files changed between these applies");
+            }
+            Some(diff) => {
+                ui.label(
+                    RichText::new(format!("{} output files differ", diff.len()))
+                        .family(egui::FontFamily::Name("Bold".into())),
+                );
+                egui::ScrollArea::vertical()
+                    .id_source("history-diff")
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        render_files(ui, "Added", &diff.added);
+                        render_files(ui, "Removed", &diff.removed);
+                        render_files(ui, "Changed", &diff.changed);
+                    });
+            }
+            None => {
+                ui.label(
+                    RichText::new("Select two different applies to compare").color(visuals::YELLOW),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use uk_manager::history::{FileRecord, ModRecord};
+
+    use super::*;
+
+    fn entry(time: u64, files: &[(&str, u64)]) -> HistoryEntry {
+        HistoryEntry {
+            time,
+            profile: "Default".into(),
+            mods: vec![ModRecord {
+                name:    "Test Mod".into(),
+                version: "1.0.0".into(),
+                options: vec![],
+            }],
+            files: files
+                .iter()
+                .map(|(file, hash)| {
+                    ((*file).into(), FileRecord {
+                        hash:     *hash,
+                        size:     1,
+                        modified: 0,
+                    })
+                })
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn age() {
+        let now = 10 * 86_400_000;
+        assert_eq!(format_age(now, now), "Just now");
+        assert_eq!(format_age(now, now + 5000), "Just now");
+        assert_eq!(format_age(now, now - 60_000), "1 minute ago");
+        assert_eq!(format_age(now, now - 3 * 3_600_000), "3 hours ago");
+        assert_eq!(format_age(now, now - 2 * 86_400_000), "2 days ago");
+    }
+
+    #[test]
+    fn selection() {
+        let mut state = HistoryState {
+            entries: Some(vec![
+                entry(1, &[("content/Pack/Bootup.pack", 1)]),
+                entry(2, &[("content/Pack/Bootup.pack", 2)]),
+                entry(3, &[
+                    ("content/Pack/Bootup.pack", 2),
+                    ("content/Pack/TitleBG.pack", 3),
+                ]),
+            ]),
+            ..Default::default()
+        };
+        state.select_latest();
+        assert_eq!(state.compare, Some((1, 2)));
+        assert_eq!(state.diff.as_ref().unwrap().added.len(), 1);
+        // Picking the newer entry first still compares oldest to newest
+        state.select(1, 0);
+        assert_eq!(state.compare, Some((0, 1)));
+        assert_eq!(state.diff.as_ref().unwrap().changed.len(), 1);
+        state.select(2, 2);
+        assert!(state.diff.is_none());
+        state.invalidate();
+        assert!(state.entries.is_none());
+    }
+}
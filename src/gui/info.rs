@@ -7,8 +7,9 @@ use std::{
 use anyhow::Result;
 use parking_lot::{Mutex, RwLock};
 use rustc_hash::{FxHashMap, FxHasher};
+use uk_content::labels::Labels;
 use uk_manager::mods::Mod;
-use uk_mod::Manifest;
+use uk_mod::{unpack::ModReader, Manifest};
 #[allow(deprecated)]
 use uk_ui::egui_extras::RetainedImage;
 use uk_ui::{
@@ -17,10 +18,63 @@ use uk_ui::{
     PathNode,
 };
 
-use super::Component;
+use super::{reveal, Component};
 
 pub enum Message {
+    ExportSummary(String),
     RequestOptions,
+    ShowLabels(bool),
+    Summarize,
+}
+
+/// Largest README rendered as markdown. Bigger ones are shown as plain text.
+const MAX_MARKDOWN: usize = 256 * 1024;
+
+pub struct Docs {
+    text:     String,
+    markdown: bool,
+}
+
+/// Points the images in a README which refer to files bundled with the mod at
+/// the URIs returned by `resolve`. Remote images and images which cannot be
+/// resolved are left alone.
+fn rewrite_images(text: &str, mut resolve: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("![") {
+        let (before, after) = rest.split_at(start);
+        out.push_str(before);
+        let target = after.find("](").and_then(|open| {
+            let open = open + 2;
+            Some((open, open + after[open..].find(')')?))
+        });
+        let Some((open, close)) = target else {
+            out.push_str(after);
+            return out;
+        };
+        out.push_str(&after[..open]);
+        let target = &after[open..close];
+        let path_end = target.find(char::is_whitespace).unwrap_or(target.len());
+        let path = target[..path_end]
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        let uri = (!path.contains("://") && !path.starts_with("data:"))
+            .then(|| resolve(path))
+            .flatten();
+        match uri {
+            Some(uri) => {
+                out.push('<');
+                out.push_str(&uri);
+                out.push('>');
+                out.push_str(&target[path_end..]);
+            }
+            None => out.push_str(target),
+        }
+        out.push(')');
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    out
 }
 
 #[repr(transparent)]
@@ -59,6 +113,67 @@ impl ModInfo<'_> {
             })
             .clone()
     }
+
+    /// Loads the README bundled with the mod, registering any images it uses
+    /// from the mod's docs with the context so they can be displayed.
+    pub fn docs(&self, ctx: &egui::Context) -> Option<Arc<Docs>> {
+        fn load_docs(mod_: &Mod, ctx: &egui::Context) -> Result<Option<Docs>> {
+            let reader = ModReader::open_peek(&mod_.path, vec![])?;
+            let Some(text) = reader.readme() else {
+                return Ok(None);
+            };
+            if text.len() > MAX_MARKDOWN || text.contains(char::REPLACEMENT_CHARACTER) {
+                return Ok(Some(Docs {
+                    text,
+                    markdown: false,
+                }));
+            }
+            let text = rewrite_images(&text, |path| {
+                let data = reader.doc_asset(path)?;
+                let uri = format!("bytes://ukmm/{}/{}", mod_.hash(), path);
+                ctx.include_bytes(uri.clone(), data);
+                Some(uri)
+            });
+            Ok(Some(Docs {
+                text,
+                markdown: true,
+            }))
+        }
+        static DOCS: LazyLock<RwLock<FxHashMap<usize, Option<Arc<Docs>>>>> =
+            LazyLock::new(|| RwLock::new(FxHashMap::default()));
+        let mut docs = DOCS.write();
+        docs.entry(self.0.hash())
+            .or_insert_with(|| {
+                match load_docs(self.0, ctx) {
+                    Ok(docs) => docs.map(Arc::new),
+                    Err(e) => {
+                        log::error!("Error loading mod documentation: {}", e);
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+
+    /// The passthrough files bundled with the mod, which are deployed as they
+    /// are rather than merged.
+    pub fn passthrough(&self) -> Arc<Vec<String>> {
+        static PASSTHROUGH: LazyLock<RwLock<FxHashMap<usize, Arc<Vec<String>>>>> =
+            LazyLock::new(|| RwLock::new(FxHashMap::default()));
+        let mut passthrough = PASSTHROUGH.write();
+        passthrough
+            .entry(self.0.hash())
+            .or_insert_with(|| {
+                match ModReader::open_peek(&self.0.path, vec![]) {
+                    Ok(reader) => Arc::new(reader.passthrough_files()),
+                    Err(e) => {
+                        log::error!("Error loading mod passthrough files: {}", e);
+                        Default::default()
+                    }
+                }
+            })
+            .clone()
+    }
 }
 
 impl Component for ModInfo<'_> {
@@ -75,11 +190,13 @@ impl Component for ModInfo<'_> {
                 ui.add_space(8.);
             }
             let ver = mod_.meta.version.to_string();
+            let platform = mod_.meta.platform.to_string();
             [
                 ("Name", mod_.meta.name.as_str()),
                 ("Version", ver.as_str()),
                 ("Category", mod_.meta.category.as_str()),
                 ("Author", mod_.meta.author.as_str()),
+                ("Supports", platform.as_str()),
             ]
             .into_iter()
             .filter(|(_, v)| !v.is_empty())
@@ -92,6 +209,18 @@ impl Component for ModInfo<'_> {
                     })
                 });
             });
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Location").family(egui::FontFamily::Name("Bold".into())));
+                ui.add_space(8.);
+                ui.with_layout(Layout::right_to_left(Align::Max), |ui| {
+                    reveal::reveal_button(ui, &mod_.path);
+                    ui.add(
+                        Label::new(mod_.path.display().to_string())
+                            .wrap_mode(egui::TextWrapMode::Truncate),
+                    )
+                    .on_hover_text(mod_.path.display().to_string());
+                })
+            });
             ui.label(RichText::new("Description").family(egui::FontFamily::Name("Bold".into())));
             ui.add_space(4.);
             let md_cache = ui.data_mut(|d| {
@@ -106,6 +235,24 @@ impl Component for ModInfo<'_> {
                 &mod_.meta.description,
             );
             ui.add_space(4.);
+            if let Some(docs) = self.docs(ui.ctx()) {
+                egui::CollapsingHeader::new(
+                    RichText::new("Documentation").family(egui::FontFamily::Name("Bold".into())),
+                )
+                .id_source("mod_docs")
+                .show(ui, |ui| {
+                    if docs.markdown {
+                        egui_commonmark::CommonMarkViewer::new("mod_docs").show(
+                            ui,
+                            &mut md_cache.lock(),
+                            &docs.text,
+                        );
+                    } else {
+                        ui.add(Label::new(RichText::new(docs.text.as_str()).monospace()).wrap());
+                    }
+                });
+                ui.add_space(4.);
+            }
             if !mod_.meta.options.is_empty() {
                 ui.horizontal(|ui| {
                     ui.label(
@@ -123,7 +270,7 @@ impl Component for ModInfo<'_> {
                 if !mod_.enabled_options.is_empty() {
                     ui.add_enabled_ui(false, |ui| {
                         mod_.enabled_options.iter().for_each(|opt| {
-                            ui.checkbox(&mut true, opt.name.as_str());
+                            ui.checkbox(&mut mod_.is_option_active(opt), opt.name.as_str());
                         });
                     });
                 } else {
@@ -131,7 +278,55 @@ impl Component for ModInfo<'_> {
                 }
                 ui.add_space(4.0);
             }
-            ui.label(RichText::new("Manifest").family(egui::FontFamily::Name("Bold".into())));
+            egui::CollapsingHeader::new(
+                RichText::new("Change Summary").family(egui::FontFamily::Name("Bold".into())),
+            )
+            .id_source("mod_summary")
+            .show(ui, |ui| {
+                match SUMMARIES.read().get(&mod_.hash()) {
+                    Some(summary) => {
+                        ui.horizontal(|ui| {
+                            if ui.button("Copy").clicked() {
+                                ui.output_mut(|o| o.copied_text = summary.clone());
+                            }
+                            if ui.button("Export…").clicked() {
+                                msg = Some(Message::ExportSummary(summary.clone()));
+                            }
+                            if ui.button("Refresh").clicked() {
+                                msg = Some(Message::Summarize);
+                            }
+                        });
+                        egui_commonmark::CommonMarkViewer::new("mod_summary").show(
+                            ui,
+                            &mut md_cache.lock(),
+                            summary,
+                        );
+                    }
+                    None => {
+                        if ui
+                            .button("Summarize Changes")
+                            .on_hover_text("Describe what this mod changes compared to the game")
+                            .clicked()
+                        {
+                            msg = Some(Message::Summarize);
+                        }
+                    }
+                }
+            });
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Manifest").family(egui::FontFamily::Name("Bold".into())));
+                ui.with_layout(Layout::right_to_left(Align::Max), |ui| {
+                    let mut show = LABELS.read().is_some();
+                    if ui
+                        .checkbox(&mut show, "Friendly names")
+                        .on_hover_text("Show the in-game names of actors next to their files")
+                        .changed()
+                    {
+                        msg = Some(Message::ShowLabels(show));
+                    }
+                })
+            });
             match mod_.manifest() {
                 Ok(manifest) => render_manifest(&manifest, ui),
                 Err(e) => {
@@ -139,16 +334,48 @@ impl Component for ModInfo<'_> {
                     ui.label(RichText::new("FAILED TO LOAD MANIFEST").strong());
                 }
             }
+            let passthrough = self.passthrough();
+            if !passthrough.is_empty() {
+                ui.add_space(4.0);
+                egui::CollapsingHeader::new(
+                    RichText::new("Passthrough Files")
+                        .family(egui::FontFamily::Name("Bold".into())),
+                )
+                .id_source("mod_passthrough")
+                .show(ui, |ui| {
+                    ui.label("Deployed as they are to the root of the output folder");
+                    passthrough.iter().for_each(|file| {
+                        ui.label(RichText::new(file.as_str()).monospace());
+                    });
+                });
+            }
             ui.add_space(8.0);
             msg
         })
     }
 }
 
+/// Change summaries as markdown, keyed by mod hash.
+pub static SUMMARIES: LazyLock<RwLock<FxHashMap<usize, String>>> =
+    LazyLock::new(|| RwLock::new(FxHashMap::default()));
+
+/// Labels for manifest entries, present while friendly names are turned on.
+pub static LABELS: RwLock<Option<Arc<Labels>>> = RwLock::new(None);
+
 pub static ROOTS: LazyLock<RwLock<FxHashMap<u64, PathNode>>> =
     LazyLock::new(|| RwLock::new(FxHashMap::default()));
 
 pub fn render_manifest(manifest: &Manifest, ui: &mut Ui) {
+    let labels = LABELS.read().clone();
+    let segments = |file: &str| -> Vec<String> {
+        let mut segments: Vec<String> = file.split('/').map(|s| s.to_owned()).collect();
+        if let Some(label) = labels.as_ref().and_then(|l| l.resolve_label(file)) {
+            if let Some(name) = segments.last_mut() {
+                *name = format!("{name} ({label})");
+            }
+        }
+        segments
+    };
     ui.scope(|ui| {
         ui.style_mut().override_text_style = Some(egui::TextStyle::Body);
         ui.spacing_mut().item_spacing.y = 4.;
@@ -159,7 +386,7 @@ pub fn render_manifest(manifest: &Manifest, ui: &mut Ui) {
             let content_root = roots.entry(hasher.finish()).or_insert_with(|| {
                 let mut root = PathNode::dir("Base Files");
                 manifest.content_files.iter().for_each(|file| {
-                    root.build_tree(&file.split('/').map(|s| s.to_owned()).collect(), 0);
+                    root.build_tree(&segments(file.as_str()), 0);
                 });
                 root
             });
@@ -172,7 +399,7 @@ pub fn render_manifest(manifest: &Manifest, ui: &mut Ui) {
             let aoc_root = roots.entry(hasher.finish()).or_insert_with(|| {
                 let mut root = PathNode::dir("DLC Files");
                 manifest.aoc_files.iter().for_each(|file| {
-                    root.build_tree(&file.split('/').map(|s| s.to_owned()).collect(), 0);
+                    root.build_tree(&segments(file.as_str()), 0);
                 });
                 root
             });
@@ -180,3 +407,28 @@ pub fn render_manifest(manifest: &Manifest, ui: &mut Ui) {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::rewrite_images;
+
+    #[test]
+    fn images() {
+        let resolve = |path: &str| (path == "docs/shot.png").then(|| "bytes://shot".to_string());
+        assert_eq!(
+            rewrite_images("Look: ![Shot](docs/shot.png \"Title\") done", resolve),
+            "Look: ![Shot](<bytes://shot> \"Title\") done"
+        );
+        assert_eq!(
+            rewrite_images(
+                "![A](docs/missing.png) ![B](https://example.com/b.png)",
+                resolve
+            ),
+            "![A](docs/missing.png) ![B](https://example.com/b.png)"
+        );
+        assert_eq!(
+            rewrite_images("[link](docs/shot.png) and ![broken", resolve),
+            "[link](docs/shot.png) and ![broken"
+        );
+    }
+}
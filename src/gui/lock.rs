@@ -0,0 +1,190 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uk_manager::lock::Snapshot;
+use uk_ui::egui::{self, Align, Align2, Frame, Layout, RichText, Vec2};
+
+use super::{history::format_age, visuals, App, Message};
+
+impl Message {
+    /// Whether handling the message would change the deployment, which a
+    /// lock forbids.
+    pub(super) fn changes_deployment(&self) -> bool {
+        matches!(
+            self,
+            Message::Apply
+                | Message::Deploy
+                | Message::DeployOnly(_)
+                | Message::Remerge
+                | Message::RestoreSnapshot(_)
+        )
+    }
+
+    /// The label for the button which unlocks the deployment to go ahead.
+    fn unlock_label(&self) -> &'static str {
+        match self {
+            Message::Apply => "Unlock and Apply",
+            Message::Remerge => "Unlock and Remerge",
+            Message::RestoreSnapshot(_) => "Unlock and Restore",
+            _ => "Unlock and Deploy",
+        }
+    }
+}
+
+/// The list of snapshots of the merged files, with a name for a new one.
+#[derive(Debug, Default)]
+pub struct SnapshotState {
+    snapshots: Option<Vec<Snapshot>>,
+    name: String,
+}
+
+impl SnapshotState {
+    pub fn open(&mut self, snapshots: Vec<Snapshot>) {
+        self.snapshots = Some(snapshots);
+    }
+
+    pub fn render(&mut self, app: &App, ctx: &egui::Context) {
+        let Some(snapshots) = self.snapshots.as_ref() else {
+            return;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut close = false;
+        egui::Window::new("Snapshots")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(true)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Snapshots keep a copy of the merged files. Restoring one puts them back \
+                     exactly as they were, ready to deploy without merging again.",
+                );
+                ui.add_space(4.0);
+                egui::ScrollArea::vertical()
+                    .id_source("snapshot_list")
+                    .max_height(280.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        if snapshots.is_empty() {
+                            ui.label(
+                                RichText::new("No snapshots yet")
+                                    .color(ui.visuals().weak_text_color()),
+                            );
+                        }
+                        for snapshot in snapshots {
+                            ui.horizontal(|ui| {
+                                ui.vertical(|ui| {
+                                    ui.strong(snapshot.name.as_str());
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "{} · {}",
+                                            snapshot.profile,
+                                            format_age(now, snapshot.time)
+                                        ))
+                                        .color(ui.visuals().weak_text_color()),
+                                    );
+                                });
+                                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                    if ui.button("Delete").clicked() {
+                                        app.do_update(Message::Confirm(
+                                            Box::new(Message::DeleteSnapshot(snapshot.clone())),
+                                            format!(
+                                                "Delete the snapshot {}? This cannot be undone.",
+                                                snapshot.name
+                                            ),
+                                        ));
+                                    }
+                                    if ui
+                                        .button("Restore")
+                                        .on_hover_text(
+                                            "Replace the merged files with this snapshot's",
+                                        )
+                                        .clicked()
+                                    {
+                                        app.do_update(Message::RestoreSnapshot(snapshot.clone()));
+                                    }
+                                });
+                            });
+                            ui.separator();
+                        }
+                    });
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut self.name);
+                    if ui
+                        .add_enabled(
+                            !self.name.trim().is_empty(),
+                            egui::Button::new("Create Snapshot"),
+                        )
+                        .on_hover_text("Copy the current merged files to a new snapshot")
+                        .clicked()
+                    {
+                        app.do_update(Message::CreateSnapshot(std::mem::take(&mut self.name)));
+                    }
+                });
+                ui.add_space(4.0);
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    close = ui.button("Close").clicked();
+                });
+            });
+        if close {
+            self.snapshots = None;
+        }
+    }
+}
+
+impl App {
+    /// Explains why a change to a locked deployment was stopped, offering to
+    /// unlock it and go ahead.
+    pub fn render_locked(&mut self, ctx: &egui::Context) {
+        let Some(msg) = self.locked_action.as_ref() else {
+            return;
+        };
+        let Some(lock) = self.core.deploy_manager().locked() else {
+            self.locked_action = None;
+            return;
+        };
+        let mut unlock = false;
+        let mut close = false;
+        egui::Window::new("Deployment Locked")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::default())
+            .auto_sized()
+            .frame(Frame::window(&ctx.style()).inner_margin(8.))
+            .show(ctx, |ui| {
+                ui.add_space(8.);
+                ui.label(format!(
+                    "The deployment was locked for profile {} {}. While it is locked, changes are \
+                     not applied or deployed, so the game files stay exactly as they are.",
+                    lock.profile,
+                    format_age(
+                        SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_millis() as u64)
+                            .unwrap_or_default(),
+                        lock.time
+                    )
+                    .to_lowercase()
+                ));
+                ui.add_space(8.);
+                ui.label(
+                    RichText::new("Unlocking lets this and any later change through.")
+                        .color(visuals::YELLOW),
+                );
+                ui.add_space(8.);
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    close = ui.button("Cancel").clicked();
+                    unlock = ui.button(msg.unlock_label()).clicked();
+                });
+            });
+        if unlock {
+            let msg = self.locked_action.take().map(Box::new);
+            self.do_update(Message::UnlockDeployment(msg));
+        } else if close {
+            self.locked_action = None;
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use log::Level;
+use uk_ui::egui::{self, Color32, Label, Rect, RichText, Sense, TextStyle, Ui, Vec2};
+
+use super::{visuals, App};
+use crate::logger::{visible_rows, Entry, LOGGER};
+
+const LEVELS: [Level; 4] = [Level::Error, Level::Warn, Level::Info, Level::Debug];
+/// The name the log's text scale is kept under.
+const PANE: &str = "Log";
+
+fn level_color(level: Level, ui: &Ui) -> Color32 {
+    match level {
+        Level::Error => visuals::RED,
+        Level::Warn => visuals::YELLOW,
+        Level::Info => ui.visuals().text_color(),
+        Level::Debug | Level::Trace => ui.visuals().weak_text_color(),
+    }
+}
+
+impl App {
+    fn render_log_filters(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            for level in LEVELS {
+                let enabled = self.log_view.filter.level_mut(level);
+                let text = RichText::new(level.as_str()).color(level_color(level, ui));
+                if ui.selectable_label(*enabled, text).clicked() {
+                    *enabled = !*enabled;
+                }
+            }
+            ui.separator();
+            ui.add(
+                egui::TextEdit::singleline(&mut self.log_view.filter.query)
+                    .hint_text("Search log…")
+                    .desired_width(200.0),
+            );
+            ui.separator();
+            ui.checkbox(&mut self.log_view.filter.only_last_merge, "Only last merge")
+                .on_hover_text("Only show entries logged since changes were last applied");
+            ui.checkbox(&mut self.log_view.auto_scroll, "Auto-scroll");
+            ui.separator();
+            self.text_scales.controls(PANE, ui);
+        });
+    }
+
+    pub fn render_log(&mut self, ui: &mut Ui) {
+        self.render_log_filters(ui);
+        self.text_scales.apply(PANE, ui);
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + 2.0;
+        let user_scrolled = ui.ui_contains_pointer() && ui.input(|i| i.smooth_scroll_delta.y > 0.0);
+        let output = egui::ScrollArea::vertical()
+            .id_source("log-view")
+            .auto_shrink([false, false])
+            .stick_to_bottom(self.log_view.auto_scroll)
+            .show_viewport(ui, |ui, viewport| {
+                // Only the rows in view are copied out, so the logger is not
+                // kept locked while they are drawn
+                let (total, visible) = {
+                    let entries = LOGGER.entries();
+                    let rows = self.log_view.rows(&entries, LOGGER.boundary());
+                    let range =
+                        visible_rows(row_height, viewport.min.y, viewport.height(), rows.len());
+                    let visible: Vec<(usize, Entry)> =
+                        range.map(|row| (row, entries[rows[row]].clone())).collect();
+                    (rows.len(), visible)
+                };
+                ui.set_height(row_height * total as f32);
+                let top = ui.min_rect().min;
+                let width = ui.available_width();
+                for (row, entry) in visible {
+                    let rect = Rect::from_min_size(
+                        top + Vec2::new(0.0, row as f32 * row_height),
+                        Vec2::new(width, row_height),
+                    );
+                    let text = RichText::new(entry.to_string())
+                        .monospace()
+                        .color(level_color(entry.level, ui));
+                    let res = ui
+                        .put(rect, Label::new(text).sense(Sense::click()).truncate())
+                        .on_hover_text("Click to copy");
+                    if res.clicked() {
+                        ui.ctx().copy_text(entry.to_string());
+                    }
+                }
+            });
+        let at_bottom = output.state.offset.y + output.inner_rect.height()
+            >= output.content_size.y - row_height;
+        if self.log_view.auto_scroll && user_scrolled && !at_bottom {
+            self.log_view.auto_scroll = false;
+        }
+    }
+}
@@ -7,6 +7,7 @@ impl App {
             ui.add_enabled_ui(!self.modal_open(), |ui| {
                 ui.horizontal(|ui| {
                     ui.menu_button("File", |ui| self.file_menu(ui, frame));
+                    ui.menu_button("Edit", |ui| self.edit_menu(ui));
                     ui.menu_button("Tools", |ui| self.tool_menu(ui));
                     ui.menu_button("Window", |ui| self.window_menu(ui));
                     ui.menu_button("Help", |ui| self.help_menu(ui));
@@ -26,8 +27,18 @@ impl App {
             ui.close_menu();
             self.do_update(Message::SelectFile);
         }
+        if let Some(path) = self.last_export.as_ref() {
+            if ui
+                .button("Show Last Export")
+                .on_hover_text(path.display().to_string())
+                .clicked()
+            {
+                ui.close_menu();
+                self.do_update(Message::Reveal(path.clone()));
+            }
+        }
         if ui.button("Exit").clicked() {
-            ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+            self.do_update(Message::Quit);
         }
     }
 
@@ -40,6 +51,109 @@ impl App {
             ui.close_menu();
             self.do_update(Message::ResetPending);
         }
+        ui.menu_button("Deploy Only", |ui| {
+            for (half, name) in [
+                (uk_manager::deploy::Half::Content, "Base Game Files"),
+                (uk_manager::deploy::Half::Aoc, "DLC Files"),
+            ] {
+                if ui
+                    .button(name)
+                    .on_hover_text("Deploy changes to these files only, leaving the rest pending")
+                    .clicked()
+                {
+                    ui.close_menu();
+                    self.do_update(Message::DeployOnly(half));
+                }
+            }
+        });
+        if ui
+            .add_enabled(!self.simulating(), egui::Button::new("Simulate Changes"))
+            .on_hover_text("Try out changes to the mod list without saving them")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::StartSimulation);
+        }
+        if ui
+            .button("Find Crashing Mod…")
+            .on_hover_text("Turn mods off by halves to find the one which makes the game crash")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::ShowBisect);
+        }
+        if ui
+            .button("Export Profile Summary…")
+            .on_hover_text("Save a readable summary of what the enabled mods change")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::SummarizeProfile);
+        }
+        if ui
+            .button("Export Conflict Report…")
+            .on_hover_text(
+                "Save a JSON list of the files changed by more than one mod, for other tools",
+            )
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::ExportConflicts);
+        }
+        if ui
+            .button("Validate Library")
+            .on_hover_text("Check every profile for problems in its list of mods")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::ValidateLibrary);
+        }
+        if ui
+            .button("Clear Dump Cache")
+            .on_hover_text("Remove the resources from the game dump kept between launches")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::ClearDumpCache);
+        }
+        if ui
+            .button("Run Batch File…")
+            .on_hover_text("Run the operations listed in a batch file")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::RunBatch);
+        }
+        if ui
+            .button("Compare Merged Folder…")
+            .on_hover_text(
+                "Compare the merged files with another merged folder, like BCML's master mod",
+            )
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::CompareMerged);
+        }
+        if ui
+            .button("Snapshots…")
+            .on_hover_text("Keep copies of the merged files to restore later")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::ShowSnapshots);
+        }
+        if ui
+            .button("Actor Dependencies…")
+            .on_hover_text("List the files a custom actor needs and where each is found")
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::CheckActorDeps);
+        }
+        if ui.button("Quick Tweak…").clicked() {
+            ui.close_menu();
+            self.do_update(Message::ShowTweaks);
+        }
         if ui.button("Open Config Folder").clicked() {
             ui.close_menu();
             open::that(Settings::config_dir()).unwrap_or(());
@@ -68,15 +182,7 @@ impl App {
             *self.tree.borrow_mut() = tabs::default_ui();
         }
         ui.separator();
-        for tab in [
-            Tabs::Info,
-            Tabs::Install,
-            Tabs::Deploy,
-            Tabs::Mods,
-            Tabs::Package,
-            Tabs::Settings,
-            Tabs::Log,
-        ] {
+        for tab in Tabs::ALL {
             let disabled = self.closed_tabs.contains_key(&tab);
             if ui
                 .icon_text_button(
@@ -117,6 +223,10 @@ impl App {
             ui.close_menu();
             open::that("https://nicenenerd.github.io/UKMM").unwrap_or(());
         }
+        if ui.button("Take the Tour").clicked() {
+            ui.close_menu();
+            self.do_update(Message::StartTour);
+        }
         if ui.button("About").clicked() {
             ui.close_menu();
             self.do_update(Message::ShowAbout);
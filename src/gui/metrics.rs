@@ -0,0 +1,325 @@
+//! Numbers for diagnosing a slow UI remotely, shown in a corner of the window
+//! when turned on from the advanced settings or with Ctrl+Shift+F12. While the
+//! overlay is off nothing is measured: every hook checks one flag and returns.
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use uk_ui::egui::{self, Align2, Id, Key, Modifiers, Order};
+
+use super::App;
+
+/// How many frames the frame time statistics cover.
+const FRAMES: usize = 240;
+/// How often memory use is read again.
+const MEMORY_EVERY: Duration = Duration::from_secs(1);
+
+/// The last few samples of a measurement, for statistics over a rolling
+/// window.
+#[derive(Debug)]
+pub struct RollingWindow {
+    samples:  VecDeque<f32>,
+    capacity: usize,
+}
+
+impl RollingWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples:  VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Adds a sample, dropping the oldest once the window is full.
+    pub fn push(&mut self, sample: f32) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        (!self.samples.is_empty())
+            .then(|| self.samples.iter().sum::<f32>() / self.samples.len() as f32)
+    }
+
+    /// The smallest sample which at least `percent` of the samples are no
+    /// greater than.
+    pub fn percentile(&self, percent: f32) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let rank = (percent.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil() as usize;
+        sorted.get(rank.saturating_sub(1)).copied()
+    }
+}
+
+/// A background task shown in the overlay until it finishes.
+#[derive(Debug)]
+struct Task {
+    id: u64,
+    label: String,
+    started: Instant,
+}
+
+/// Notes a task as finished when dropped, however its thread ends.
+pub struct TaskGuard {
+    metrics: Arc<Metrics>,
+    id: u64,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.metrics.tasks.lock().retain(|task| task.id != self.id);
+    }
+}
+
+/// Measurements updated from the UI thread and the task runner, shared
+/// between them.
+#[derive(Debug)]
+pub struct Metrics {
+    enabled:   AtomicBool,
+    frames:    Mutex<RollingWindow>,
+    tasks:     Mutex<Vec<Task>>,
+    next_task: AtomicU64,
+    /// Resident memory in bytes, if the platform tells it cheaply, and when
+    /// it was read.
+    memory:    Mutex<Option<(Instant, Option<u64>)>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            enabled:   AtomicBool::new(false),
+            frames:    Mutex::new(RollingWindow::new(FRAMES)),
+            tasks:     Mutex::new(vec![]),
+            next_task: AtomicU64::new(0),
+            memory:    Mutex::new(None),
+        }
+    }
+}
+
+impl Metrics {
+    #[inline(always)]
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turns measuring on or off. What was measured before is dropped, so
+    /// the overlay never shows stale numbers.
+    pub fn set_enabled(&self, enabled: bool) {
+        if self.enabled.swap(enabled, Ordering::Relaxed) != enabled {
+            self.frames.lock().clear();
+            self.tasks.lock().clear();
+            self.memory.lock().take();
+        }
+    }
+
+    /// Notes how long a frame took to build.
+    pub fn frame(&self, took: Duration) {
+        self.frames.lock().push(took.as_secs_f32() * 1000.0);
+    }
+
+    /// Notes a task starting, if measuring, until the guard is dropped.
+    /// Tasks started before the overlay was turned on are not shown.
+    pub fn task(self: &Arc<Self>, label: impl FnOnce() -> String) -> Option<TaskGuard> {
+        if !self.enabled() {
+            return None;
+        }
+        let id = self.next_task.fetch_add(1, Ordering::Relaxed);
+        self.tasks.lock().push(Task {
+            id,
+            label: label(),
+            started: Instant::now(),
+        });
+        Some(TaskGuard {
+            metrics: self.clone(),
+            id,
+        })
+    }
+
+    fn memory(&self) -> Option<u64> {
+        let mut memory = self.memory.lock();
+        match *memory {
+            Some((read, bytes)) if read.elapsed() < MEMORY_EVERY => bytes,
+            _ => memory.insert((Instant::now(), resident_memory())).1,
+        }
+    }
+}
+
+/// Resident memory of the process in bytes, where it can be read without
+/// walking the heap.
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> Option<u64> {
+    None
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", elapsed.as_secs_f32())
+    }
+}
+
+impl App {
+    /// Toggles the overlay on Ctrl+Shift+F12.
+    pub(super) fn handle_metrics_key(&mut self, ctx: &egui::Context) {
+        if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::F12)) {
+            let enabled = !self.metrics.enabled();
+            self.metrics.set_enabled(enabled);
+            log::debug!(
+                "Diagnostics overlay turned {}",
+                if enabled { "on" } else { "off" }
+            );
+        }
+    }
+
+    pub(super) fn render_metrics(&self, ctx: &egui::Context) {
+        let metrics = &self.metrics;
+        let (mean, p95, count) = {
+            let frames = metrics.frames.lock();
+            (frames.mean(), frames.percentile(95.0), frames.len())
+        };
+        let pending = self.channel.1.len();
+        let cache = self.core.settings().dump().map(|dump| dump.cache_stats());
+        let memory = metrics.memory();
+        egui::Area::new(Id::new("metrics_overlay"))
+            .anchor(Align2::LEFT_BOTTOM, [8.0, -8.0])
+            .order(Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.spacing_mut().item_spacing.y = 2.0;
+                    match (mean, p95) {
+                        (Some(mean), Some(p95)) => {
+                            ui.small(format!(
+                                "Frame: {mean:.1} ms avg, {p95:.1} ms 95p ({count} frames)"
+                            ));
+                        }
+                        _ => {
+                            ui.small("Frame: no samples yet");
+                        }
+                    }
+                    ui.small(format!("Pending messages: {pending}"));
+                    let tasks = metrics.tasks.lock();
+                    if tasks.is_empty() {
+                        ui.small("Tasks: none");
+                    }
+                    for task in tasks.iter() {
+                        ui.small(format!(
+                            "Task: {} ({})",
+                            task.label,
+                            format_elapsed(task.started.elapsed())
+                        ));
+                    }
+                    if let Some(cache) = cache {
+                        ui.small(format!("Cache: {cache}"));
+                    }
+                    if let Some(memory) = memory {
+                        ui.small(format!(
+                            "Memory: {:.1} MiB",
+                            memory as f64 / (1024.0 * 1024.0)
+                        ));
+                    }
+                });
+            });
+        // Keep task times and memory current while nothing else repaints
+        ctx.request_repaint_after(Duration::from_millis(500));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_window() {
+        let mut window = RollingWindow::new(4);
+        assert_eq!(window.mean(), None);
+        assert_eq!(window.percentile(95.0), None);
+        for sample in [4.0, 1.0, 3.0, 2.0] {
+            window.push(sample);
+        }
+        assert_eq!(window.mean(), Some(2.5));
+        assert_eq!(window.percentile(50.0), Some(2.0));
+        assert_eq!(window.percentile(95.0), Some(4.0));
+        assert_eq!(window.percentile(0.0), Some(1.0));
+        // The oldest sample makes room for the next
+        window.push(10.0);
+        assert_eq!(window.len(), 4);
+        assert_eq!(window.mean(), Some(4.0));
+        assert_eq!(window.percentile(100.0), Some(10.0));
+        window.clear();
+        assert_eq!(window.mean(), None);
+    }
+
+    #[test]
+    fn percentile_of_spikes() {
+        // One slow frame in a hundred does not move the 95th percentile, five
+        // in a hundred do
+        let mut window = RollingWindow::new(100);
+        for i in 0..100 {
+            window.push(if i == 50 { 200.0 } else { 16.0 });
+        }
+        assert_eq!(window.percentile(95.0), Some(16.0));
+        for _ in 0..5 {
+            window.push(200.0);
+        }
+        assert_eq!(window.percentile(95.0), Some(200.0));
+    }
+
+    #[test]
+    fn tasks() {
+        let metrics = Arc::new(Metrics::default());
+        // Nothing is noted while off
+        assert!(metrics.task(|| unreachable!()).is_none());
+        metrics.set_enabled(true);
+        let first = metrics.task(|| "Apply".into()).unwrap();
+        let second = metrics.task(|| "Remerge".into()).unwrap();
+        assert_eq!(metrics.tasks.lock().len(), 2);
+        drop(first);
+        let tasks = metrics.tasks.lock();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].label, "Remerge");
+        drop(tasks);
+        metrics.frame(Duration::from_millis(16));
+        assert_eq!(metrics.frames.lock().len(), 1);
+        // Turning off drops what was measured
+        metrics.set_enabled(false);
+        assert_eq!(metrics.frames.lock().len(), 0);
+        drop(second);
+        assert!(metrics.tasks.lock().is_empty());
+    }
+}
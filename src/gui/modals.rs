@@ -1,8 +1,30 @@
-use uk_manager::settings::Platform;
+use std::{sync::atomic::Ordering, time::Instant};
+
+use uk_manager::{
+    drift::{Drift, Resolution},
+    mods::{validate::Issue, Duplicate},
+    settings::Platform,
+};
 use uk_mod::{Meta, CATEGORIES};
 use util::SmartStringWrapper;
 
-use super::*;
+use super::{tour::Anchor, *};
+
+/// Deployed files changed outside of UKMM, with how to resolve each.
+#[derive(Debug)]
+pub struct DriftReview {
+    pub files:  Vec<(Drift, Resolution)>,
+    /// Whether to deploy once the files are resolved.
+    pub deploy: bool,
+}
+
+/// Problems found in the mod index, with whether to repair each.
+#[derive(Debug)]
+pub struct IndexReview {
+    pub issues: Vec<(Issue, bool)>,
+    /// Whether the check was asked for, rather than run on load.
+    pub report: bool,
+}
 
 #[derive(Debug)]
 pub struct MetaInputModal {
@@ -33,6 +55,7 @@ impl MetaInputModal {
     pub fn open(&mut self, path: PathBuf, platform: Platform) {
         self.meta = Some(Meta {
             api: env!("CARGO_PKG_VERSION").into(),
+            format: uk_mod::FORMAT_VERSION,
             name: Default::default(),
             description: Default::default(),
             category: "Other".into(),
@@ -118,14 +141,25 @@ impl MetaInputModal {
 impl App {
     pub fn render_error(&mut self, ctx: &egui::Context) {
         if let Some(err) = self.error.as_ref() {
-            egui::Window::new("Error")
+            let preflight = err
+                .chain()
+                .find_map(|e| e.downcast_ref::<uk_manager::preflight::PreflightError>());
+            let title = if preflight.is_some() {
+                "Cannot Apply Changes"
+            } else {
+                "Error"
+            };
+            egui::Window::new(title)
                 .collapsible(false)
                 .anchor(Align2::CENTER_CENTER, Vec2::default())
                 .auto_sized()
                 .frame(Frame::window(&ctx.style()).inner_margin(8.))
                 .show(ctx, |ui| {
                     ui.add_space(8.);
-                    ui.label(err.to_string());
+                    match preflight {
+                        Some(e) => ui.label(e.to_string()),
+                        None => ui.label(err.to_string()),
+                    };
                     ui.add_space(8.);
                     egui::CollapsingHeader::new("Details").show(ui, |ui| {
                         err.chain().enumerate().for_each(|(i, e)| {
@@ -160,6 +194,23 @@ impl App {
                                         |ui| ui.label("Copied"),
                                     );
                                 }
+                                if let Some(failure) =
+                                    err.downcast_ref::<uk_mod::unpack::repro::MergeFailure>()
+                                {
+                                    if ui
+                                        .button("Export Repro")
+                                        .on_hover_text(
+                                            "Save a minimal copy of the failed merge to attach to \
+                                             a bug report",
+                                        )
+                                        .clicked()
+                                    {
+                                        self.do_update(Message::ExportRepro(
+                                            failure.clone(),
+                                            format!("{:?}", &err),
+                                        ));
+                                    }
+                                }
                                 ui.shrink_width_to_current();
                             },
                         );
@@ -202,6 +253,122 @@ impl App {
         }
     }
 
+    pub fn render_duplicate(&mut self, ctx: &egui::Context) {
+        let Some((new_mod, duplicate)) = self.duplicate.as_ref() else {
+            return;
+        };
+        let mut update = false;
+        let mut close = false;
+        egui::Window::new("Duplicate Mod")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::default())
+            .auto_sized()
+            .frame(Frame::window(&ctx.style()).inner_margin(8.))
+            .show(ctx, |ui| {
+                ui.add_space(8.);
+                let name = new_mod.meta.name.as_str();
+                match duplicate {
+                    Duplicate::Identical { mod_, in_profile } => {
+                        ui.label(format!(
+                            "{name} is already installed with identical contents."
+                        ));
+                        ui.label(if !in_profile {
+                            "It is only used by another profile. Add it to this profile?"
+                        } else if mod_.enabled {
+                            "It is already enabled in this profile."
+                        } else {
+                            "It is disabled in this profile. Enable it?"
+                        });
+                    }
+                    Duplicate::Changed(mod_) => {
+                        ui.label(format!(
+                            "A different copy of {name} is already installed. Update it?"
+                        ));
+                        ui.add_space(4.);
+                        egui::Grid::new("duplicate_versions")
+                            .num_columns(2)
+                            .show(ui, |ui| {
+                                ui.label("Installed version");
+                                ui.label(mod_.meta.version.as_str());
+                                ui.end_row();
+                                ui.label("New version");
+                                ui.label(new_mod.meta.version.as_str());
+                                ui.end_row();
+                            });
+                        let newer = lenient_semver::Version::parse(new_mod.meta.version.as_str())
+                            .and_then(|new| {
+                                lenient_semver::Version::parse(mod_.meta.version.as_str())
+                                    .map(|old| new > old)
+                            })
+                            .unwrap_or(true);
+                        if !newer {
+                            ui.label(
+                                RichText::new("The new copy is not a newer version.")
+                                    .color(visuals::YELLOW),
+                            );
+                        }
+                    }
+                }
+                ui.add_space(8.);
+                let width = ui.min_size().x;
+                ui.horizontal(|ui| {
+                    ui.allocate_ui_with_layout(
+                        Vec2::new(width, ui.min_size().y),
+                        Layout::right_to_left(Align::Center),
+                        |ui| {
+                            match duplicate {
+                                Duplicate::Identical {
+                                    in_profile: true,
+                                    mod_,
+                                } if mod_.enabled => {
+                                    if ui.button("OK").clicked() {
+                                        self.do_update(Message::CloseDuplicate);
+                                    }
+                                    ui.shrink_width_to_current();
+                                    return;
+                                }
+                                Duplicate::Identical {
+                                    in_profile: true,
+                                    mod_,
+                                } => {
+                                    if ui.button("Enable").clicked() {
+                                        self.do_update(Message::ToggleMods(
+                                            Some(vec![mod_.clone()]),
+                                            true,
+                                        ));
+                                        self.do_update(Message::CloseDuplicate);
+                                    }
+                                }
+                                Duplicate::Identical { mod_, .. } => {
+                                    if ui.button("Add to Profile").clicked() {
+                                        self.do_update(Message::InstallMod(mod_.clone()));
+                                        close = true;
+                                    }
+                                }
+                                Duplicate::Changed(_) => {
+                                    if ui.button("Update").clicked() {
+                                        update = true;
+                                    }
+                                }
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.do_update(Message::CloseDuplicate);
+                            }
+                            ui.shrink_width_to_current();
+                        },
+                    );
+                });
+            });
+        if close {
+            self.duplicate = None;
+        } else if update {
+            if let Some((new_mod, Duplicate::Changed(old_mod))) = self.duplicate.take() {
+                self.update_mod = Some(old_mod);
+                self.do_update(Message::HandleMod(new_mod));
+            }
+        }
+    }
+
     pub fn render_new_profile(&mut self, ctx: &egui::Context) {
         let is_open = self.new_profile.is_some();
         if is_open {
@@ -260,13 +427,41 @@ impl App {
                             ui.add(Spinner::new().size(text_height));
                             ui.add_space(8.);
                             ui.vertical(|ui| {
-                                ui.label("Processing…");
+                                match crate::logger::LOGGER.get_stage() {
+                                    Some(report) => {
+                                        ui.label(format!("{}…", report.stage));
+                                        let percent = (report.fraction * 100.0).round();
+                                        let text = match report.eta {
+                                            Some(eta) => {
+                                                format!("{percent}% · {}", format_eta(eta))
+                                            }
+                                            None => format!("{percent}%"),
+                                        };
+                                        ui.add(
+                                            egui::ProgressBar::new(report.fraction as f32)
+                                                .desired_width(max_width)
+                                                .text(text),
+                                        );
+                                    }
+                                    None => {
+                                        ui.label("Processing…");
+                                    }
+                                }
                                 if let Some(progress) = crate::logger::LOGGER.get_progress() {
                                     ui.add(
                                         Label::new(progress)
                                             .wrap_mode(egui::TextWrapMode::Truncate),
                                     );
                                 }
+                                if let Some(cancel) = self.cancel.borrow().as_ref() {
+                                    let cancelling = cancel.load(Ordering::Relaxed);
+                                    if ui
+                                        .add_enabled(!cancelling, egui::Button::new("Cancel"))
+                                        .clicked()
+                                    {
+                                        cancel.store(true, Ordering::Relaxed);
+                                    }
+                                }
                             });
                             ui.shrink_width_to_current();
                         });
@@ -333,7 +528,7 @@ impl App {
                         .platform_config()
                         .map(|c| c.profile.to_string())
                         .unwrap_or_else(|| "Default".to_owned());
-                    ComboBox::from_id_source("profiles")
+                    let profiles = ComboBox::from_id_source("profiles")
                         .selected_text(&current_profile)
                         .show_ui(ui, |ui| {
                             self.core.settings().profiles().for_each(|profile| {
@@ -351,6 +546,9 @@ impl App {
                         })
                         .response
                         .on_hover_text("Select Mod Profile");
+                    self.tour
+                        .anchors
+                        .set(Anchor::Profiles, profiles.rect, ui.ctx().frame_nr());
                     if ui
                         .icon_button(Icon::Add)
                         .on_hover_text("New Profile")
@@ -375,13 +573,28 @@ impl App {
                             ))
                             .strong(),
                         );
+                        if ui
+                            .add(
+                                egui::TextEdit::singleline(&mut self.mod_filter)
+                                    .hint_text("Filter mods…")
+                                    .desired_width(160.0),
+                            )
+                            .on_hover_text("Search mod names and categories")
+                            .changed()
+                        {
+                            self.do_update(Message::RefreshModsDisplay);
+                        }
                     });
                 });
             });
     }
 
     pub fn render_pending(&self, ui: &mut Ui) {
-        if !self.dirty().is_empty() {
+        if self.simulating() {
+            self.render_simulation_bar(ui);
+        } else if self.dirty().is_empty() {
+            self.tour.apply_hint(false, Instant::now());
+        } else {
             egui::Window::new("Pending Changes")
                 .anchor(Align2::RIGHT_BOTTOM, [-32.0, -32.0])
                 .collapsible(true)
@@ -402,8 +615,17 @@ impl App {
                         ui.add_space(8.0);
                         ui.horizontal(|ui| {
                             ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-                                if ui.icon_text_button("Apply", Icon::Check).clicked() {
-                                    self.do_update(Message::Apply);
+                                let apply = ui.icon_text_button("Apply", Icon::Check);
+                                if apply.clicked() {
+                                    self.do_update(Message::RequestApply);
+                                }
+                                self.tour.anchors.set(
+                                    Anchor::Apply,
+                                    apply.rect,
+                                    ui.ctx().frame_nr(),
+                                );
+                                if self.tour.apply_hint(true, Instant::now()) {
+                                    uk_ui::tour::pulse(ui.ctx(), apply.id, apply.rect);
                                 }
                                 if ui.icon_text_button("Cancel", Icon::Cancel).clicked() {
                                     self.do_update(Message::ResetMods(None));
@@ -479,4 +701,173 @@ impl App {
                 });
         }
     }
+
+    pub fn render_drift(&mut self, ctx: &egui::Context) {
+        let Some(review) = self.drift.as_mut() else {
+            return;
+        };
+        let mut done = None;
+        egui::Window::new("Deployed Files Changed")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::default())
+            .frame(Frame::window(&ctx.style()).inner_margin(8.))
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} deployed file(s) were changed outside of UKMM. Restore UKMM's version to \
+                     have it deployed again, or adopt the change to keep it until the merged file \
+                     changes.",
+                    review.files.len()
+                ));
+                if review.deploy {
+                    ui.label("Deployment will continue once the files are resolved.");
+                }
+                ui.add_space(4.);
+                ui.horizontal(|ui| {
+                    if ui.button("Restore All").clicked() {
+                        review
+                            .files
+                            .iter_mut()
+                            .for_each(|(_, r)| *r = Resolution::Restore);
+                    }
+                    if ui.button("Adopt All").clicked() {
+                        review
+                            .files
+                            .iter_mut()
+                            .for_each(|(_, r)| *r = Resolution::Adopt);
+                    }
+                });
+                egui::ScrollArea::vertical()
+                    .id_source("modal-drift")
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("drift_files")
+                            .num_columns(3)
+                            .show(ui, |ui| {
+                                for (drift, resolution) in review.files.iter_mut() {
+                                    ui.label(drift.to_string());
+                                    ui.radio_value(resolution, Resolution::Restore, "Restore");
+                                    ui.radio_value(resolution, Resolution::Adopt, "Adopt");
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                ui.add_space(8.);
+                let width = ui.min_size().x;
+                ui.horizontal(|ui| {
+                    ui.allocate_ui_with_layout(
+                        Vec2::new(width, ui.min_size().y),
+                        Layout::right_to_left(Align::Center),
+                        |ui| {
+                            if ui.button("Resolve").clicked() {
+                                done = Some(true);
+                            }
+                            if ui
+                                .button("Later")
+                                .on_hover_text("Leave the files as they are for now")
+                                .clicked()
+                            {
+                                done = Some(false);
+                            }
+                            ui.shrink_width_to_current();
+                        },
+                    );
+                });
+            });
+        match done {
+            Some(true) => {
+                let resolutions = review
+                    .files
+                    .iter()
+                    .map(|(drift, resolution)| (drift.file.clone(), *resolution))
+                    .collect();
+                self.do_update(Message::ResolveDrift(Some(resolutions)));
+            }
+            Some(false) => self.do_update(Message::ResolveDrift(None)),
+            None => (),
+        }
+    }
+
+    pub fn render_index_review(&mut self, ctx: &egui::Context) {
+        let Some(review) = self.index_review.as_mut() else {
+            return;
+        };
+        let mut done = None;
+        egui::Window::new("Library Problems")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::default())
+            .frame(Frame::window(&ctx.style()).inner_margin(8.))
+            .show(ctx, |ui| {
+                if review.report {
+                    ui.label(format!(
+                        "Found {} problem(s) in the mod library.",
+                        review.issues.len()
+                    ));
+                } else {
+                    ui.label(
+                        "Some problems in the mod library can only be repaired by removing mods. \
+                         Other problems were repaired when it was loaded.",
+                    );
+                }
+                ui.label("A backup of each profile is kept before it is repaired.");
+                ui.add_space(4.);
+                egui::ScrollArea::vertical()
+                    .id_source("modal-index")
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("index_issues")
+                            .num_columns(2)
+                            .show(ui, |ui| {
+                                for (issue, repair) in review.issues.iter_mut() {
+                                    ui.label(issue.to_string());
+                                    ui.checkbox(repair, issue.repair());
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                ui.add_space(8.);
+                let width = ui.min_size().x;
+                ui.horizontal(|ui| {
+                    ui.allocate_ui_with_layout(
+                        Vec2::new(width, ui.min_size().y),
+                        Layout::right_to_left(Align::Center),
+                        |ui| {
+                            if ui.button("Repair").clicked() {
+                                done = Some(true);
+                            }
+                            if ui
+                                .button("Later")
+                                .on_hover_text("Leave the library as it is for now")
+                                .clicked()
+                            {
+                                done = Some(false);
+                            }
+                            ui.shrink_width_to_current();
+                        },
+                    );
+                });
+            });
+        match done {
+            Some(true) => {
+                let issues = review
+                    .issues
+                    .iter()
+                    .filter(|(_, repair)| *repair)
+                    .map(|(issue, _)| issue.clone())
+                    .collect();
+                self.do_update(Message::RepairIndex(Some(issues)));
+            }
+            Some(false) => self.do_update(Message::RepairIndex(None)),
+            None => (),
+        }
+    }
+}
+
+/// Describes roughly how long an operation has left.
+fn format_eta(eta: std::time::Duration) -> String {
+    let mins = (eta.as_secs() + 30) / 60;
+    match mins {
+        0 => "less than a minute left".into(),
+        1..=59 => format!("about {mins} min left"),
+        _ => format!("about {} h {} min left", mins / 60, mins % 60),
+    }
 }
@@ -1,17 +1,25 @@
-use std::{process::Command, sync::OnceLock};
+use std::sync::{Arc, OnceLock};
 
 use join_str::jstr;
-use uk_manager::mods::Mod;
+use rustc_hash::FxHashMap;
+use uk_manager::mods::{Mod, Priority};
 use uk_ui::{
     egui::{
-        self, epaint::Margin, text::LayoutJob, Align, Button, Color32, CursorIcon, Id, Key,
-        LayerId, Layout, Response, Sense, TextStyle, Ui, Vec2,
+        self,
+        epaint::Margin,
+        text::{LayoutJob, TextWrapping},
+        Align, Button, Color32, CursorIcon, Galley, Id, Key, LayerId, Layout, Modifiers, Response,
+        Sense, Stroke, TextStyle, Ui, Vec2,
     },
     egui_extras::{Column, TableBuilder, TableRow},
-    ext::UiExt,
+    icons::{get_icon, Icon},
 };
 
-use super::{App, FocusedPane, Message, Sort};
+use self::rows::RowOffsets;
+pub use self::rows::RowCache;
+use super::{compat, reveal, App, FocusedPane, Message, Sort};
+
+mod rows;
 
 enum ContextMenuMessage {
     CopyToProfile(smartstring::alias::String),
@@ -19,12 +27,24 @@ enum ContextMenuMessage {
     Update,
     DevUpdate,
     Uninstall,
+    Archive,
     Toggle(bool),
     Move(usize),
+    Reveal,
+    Split,
+    CheckCompat,
 }
 
 impl App {
     pub fn render_modlist(&mut self, ui: &mut Ui) {
+        let priority = self.core.mod_manager().priority();
+        self.row_text.sweep();
+        let load_order: FxHashMap<usize, usize> = self
+            .mods
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.hash(), i))
+            .collect();
         static TEXT_HEIGHT: OnceLock<f32> = OnceLock::new();
         let text_height = TEXT_HEIGHT.get_or_init(|| ui.text_style_height(&TextStyle::Body) + 4.);
         static ICON_WIDTH: OnceLock<f32> = OnceLock::new();
@@ -34,7 +54,7 @@ impl App {
         let numeric_col_width = NUMERIC_COL_WIDTH.get_or_init(|| {
             ui.fonts(|f| {
                 f.layout_job(LayoutJob::simple_singleline(
-                    "PriorityWW".to_owned(),
+                    "⬇ PriorityWW".to_owned(),
                     ui.style()
                         .text_styles
                         .get(&TextStyle::Body)
@@ -122,6 +142,9 @@ impl App {
                                 .col(|ui| {
                                     let is_current = self.sort.0 == sort;
                                     let mut label = label.to_owned();
+                                    if sort == Sort::Priority {
+                                        label = format!("{} {label}", priority.arrow());
+                                    }
                                     if is_current {
                                         if self.sort.1 {
                                             label += " ⏷";
@@ -134,14 +157,15 @@ impl App {
                                     ui.centered_and_justified(|ui| {
                                         ui.style_mut().visuals.widgets.inactive.bg_stroke.width =
                                             0.0;
-                                        if ui
-                                            .add(
-                                                Button::new(label)
-                                                    .small()
-                                                    .fill(Color32::TRANSPARENT),
-                                            )
-                                            .clicked()
-                                        {
+                                        let res = ui.add(
+                                            Button::new(label).small().fill(Color32::TRANSPARENT),
+                                        );
+                                        let res = if sort == Sort::Priority {
+                                            self.render_priority_menu(priority, res)
+                                        } else {
+                                            res
+                                        };
+                                        if res.clicked() {
                                             self.do_update(Message::ChangeSort(
                                                 sort,
                                                 if is_current {
@@ -160,9 +184,28 @@ impl App {
                             }
                         });
                     })
-                    .body(|body| {
-                        body.rows(*text_height, self.displayed_mods.len(), |row| {
-                            self.render_mod_row(row.index(), row);
+                    .body(|mut body| {
+                        // Expanded mods get an extra line for each selected option
+                        let heights: Vec<f32> = self
+                            .displayed_mods
+                            .iter()
+                            .map(|m| {
+                                let lines = if self.expanded_mods.contains(&m.hash()) {
+                                    1 + m.enabled_options.len()
+                                } else {
+                                    1
+                                };
+                                *text_height * lines as f32
+                            })
+                            .collect();
+                        let offsets = RowOffsets::new(
+                            heights.iter().copied(),
+                            body.ui_mut().spacing().item_spacing.y,
+                        );
+                        self.track_drag(body.ui_mut(), &offsets, &load_order);
+                        // Only the rows in view are laid out
+                        body.heterogeneous_rows(heights.into_iter(), |row| {
+                            self.render_mod_row(&load_order, row);
                         });
                     });
             });
@@ -170,6 +213,11 @@ impl App {
             && self.focused == FocusedPane::ModList
             && !self.modal_open()
         {
+            if ui.input_mut(|i| i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z)) {
+                self.do_update(Message::Redo(1));
+            } else if ui.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::Z)) {
+                self.do_update(Message::Undo(1));
+            }
             if let Some((last_index, _)) = ui
                 .input(|i| i.key_pressed(Key::ArrowDown))
                 .then(|| {
@@ -204,7 +252,13 @@ impl App {
                 }
             }
         }
-        self.render_drag_state(*text_height, *icon_width, *numeric_col_width, ui);
+        self.render_drag_state(
+            *text_height,
+            *icon_width,
+            *numeric_col_width,
+            &load_order,
+            ui,
+        );
         if ui.input_mut(|i| i.pointer.any_released()) {
             ui.memory_mut(|m| m.data.insert_temp(Id::new("drag_delay_frames"), 0usize));
             if let Some((_start_index, dest_index)) = self
@@ -220,11 +274,113 @@ impl App {
         }
     }
 
+    /// Explains which end of the list wins and offers to flip it, since
+    /// users coming from other managers often expect the opposite.
+    fn render_priority_menu(&self, priority: Priority, res: Response) -> Response {
+        let res = res.on_hover_text(format!(
+            "{} ({} points to the winner). Right click to change.",
+            priority.label(),
+            priority.arrow()
+        ));
+        res.context_menu(|ui| {
+            let flipped = priority.flipped();
+            if ui.button(flipped.label()).clicked() {
+                let (now, then) = match priority {
+                    Priority::LowerWins => ("lower", "higher"),
+                    Priority::HigherWins => ("higher", "lower"),
+                };
+                self.do_update(Message::Confirm(
+                    Message::SetPriority(flipped).into(),
+                    format!(
+                        "When mods change the same files, those {now} in the list currently win. \
+                         After this change, those {then} in the list will win instead, so every \
+                         conflict between your mods will go the other way unless you also reverse \
+                         their order.\n\nAll enabled mods will be marked to be applied again."
+                    ),
+                ));
+                ui.close_menu();
+            }
+        });
+        res
+    }
+
+    /// While mods are dragged, works out where they would drop from the
+    /// pointer rather than from row hover, since the list may be scrolled past
+    /// rows which were never laid out, and scrolls when the pointer nears or
+    /// leaves either end of the list.
+    fn track_drag(
+        &mut self,
+        ui: &mut Ui,
+        offsets: &RowOffsets,
+        load_order: &FxHashMap<usize, usize>,
+    ) {
+        if self.drag_index.is_none() || ui.ctx().dragged_id().is_none() {
+            return;
+        }
+        let Some(pointer) = ui.ctx().pointer_interact_pos() else {
+            return;
+        };
+        let list_top = ui.max_rect().top();
+        let view = ui.clip_rect();
+        if let Some(row) = offsets.row_at(pointer.y - list_top) {
+            self.hover_index = self
+                .displayed_mods
+                .get(row)
+                .and_then(|m| load_order.get(&m.hash()).copied());
+        }
+        let speed = rows::auto_scroll(pointer.y, view.top(), view.bottom());
+        if speed != 0.0 {
+            let dt = ui.input(|i| i.stable_dt).min(0.1);
+            ui.scroll_with_delta(Vec2::new(0.0, -speed * dt));
+            ui.ctx().request_repaint();
+        }
+        let selected: Vec<usize> = self
+            .selected
+            .iter()
+            .filter_map(|m| load_order.get(&m.hash()).copied())
+            .collect();
+        // Dropping back where the drag started leaves everything in place
+        let Some(gap) = self
+            .hover_index
+            .filter(|dest| self.drag_index != Some(*dest))
+            .and_then(|dest| rows::drop_gap(self.mods.len(), &selected, dest))
+        else {
+            return;
+        };
+        // Mark the top of the mod the selection would land before, or the
+        // bottom of the last one
+        let (target, above) = match self.mods.get(gap) {
+            Some(target) => (target, true),
+            None => (&self.mods[gap - 1], false),
+        };
+        let Some((top, bottom)) = self
+            .displayed_mods
+            .iter()
+            .position(|m| m == target)
+            .and_then(|row| offsets.span(row))
+        else {
+            return;
+        };
+        let half_gap = ui.spacing().item_spacing.y / 2.0;
+        let edge = if above {
+            top - half_gap
+        } else {
+            bottom + half_gap
+        };
+        let layer_id = LayerId::new(egui::Order::Foreground, Id::new("mod_list_drop"));
+        ui.ctx().layer_painter(layer_id).with_clip_rect(view).hline(
+            view.x_range(),
+            list_top + edge,
+            Stroke::new(2.0, ui.visuals().selection.stroke.color),
+        );
+    }
+
     fn render_drag_state(
         &mut self,
         text_height: f32,
         icon_width: f32,
         numeric_col_width: f32,
+        load_order: &FxHashMap<usize, usize>,
         ui: &mut Ui,
     ) {
         let being_dragged = ui.ctx().dragged_id().is_some();
@@ -256,12 +412,7 @@ impl App {
                                         mod_.meta.name.as_str(),
                                         mod_.meta.category.as_str(),
                                         mod_.meta.version.to_string().as_str(),
-                                        self.mods
-                                            .iter()
-                                            .position(|m| m == mod_)
-                                            .unwrap()
-                                            .to_string()
-                                            .as_str(),
+                                        load_order[&mod_.hash()].to_string().as_str(),
                                     ] {
                                         row.col(|ui| {
                                             ui.label(label);
@@ -283,22 +434,23 @@ impl App {
         ui.memory_mut(|m| m.data.insert_temp(delay_id, delay_frames));
     }
 
-    fn render_mod_row(&mut self, index: usize, mut row: TableRow) {
-        let mod_ = unsafe { self.displayed_mods.get_mut(index).unwrap_unchecked() };
-        if let Some(index) = self.mods.iter().position(|m| m == mod_) {
+    fn render_mod_row(&mut self, load_order: &FxHashMap<usize, usize>, mut row: TableRow) {
+        let mod_ = unsafe { self.displayed_mods.get_mut(row.index()).unwrap_unchecked() };
+        if let Some(&index) = load_order.get(&mod_.hash()) {
             let selected = self.selected.contains(mod_);
             let mut clicked = false;
             let mut drag_started = false;
             let mut ctrl = false;
             let mut shift = false;
-            let mut hover = false;
             let mut toggled = false;
+            let mut expand_toggled = false;
+            let mut option_toggled = None;
+            let mut review_clicked = false;
             let mut ctx_action = None;
             let menu_mod = mod_.clone();
 
             let mut process_col_res = |res: Response| {
                 clicked = clicked || res.clicked();
-                hover = hover || res.hovered();
                 drag_started = drag_started || res.drag_started();
                 res.context_menu(|ui| {
                     if let Some(action) =
@@ -321,9 +473,95 @@ impl App {
                 })
                 .1,
             );
+            let expanded = self.expanded_mods.contains(&mod_.hash());
+            let hash = mod_.hash();
+            let offline = self.offline.contains(&hash);
+            let row_text = &mut self.row_text;
             process_col_res(
                 row.col(|ui| {
-                    ui.clipped_label(mod_.meta.name.as_str());
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            if !mod_.enabled_options.is_empty() {
+                                expand_toggled = ui
+                                    .add(
+                                        Button::new(if expanded { "⏷" } else { "⏵" })
+                                            .small()
+                                            .frame(false),
+                                    )
+                                    .on_hover_text(if expanded {
+                                        "Hide options"
+                                    } else {
+                                        "Show options"
+                                    })
+                                    .clicked();
+                            }
+                            if mod_.local_patch {
+                                ui.add(
+                                    egui::Image::new(get_icon(ui.ctx(), Icon::Tune))
+                                        .max_height(ui.text_style_height(&TextStyle::Body)),
+                                )
+                                .on_hover_text(
+                                    "Local patch, generated and kept up to date by UKMM",
+                                );
+                            }
+                            if offline {
+                                ui.visuals_mut().override_text_color =
+                                    Some(ui.visuals().weak_text_color());
+                                ui.label(
+                                    egui::RichText::new("Offline").text_style(TextStyle::Small),
+                                )
+                                .on_hover_text(
+                                    "The drive or share this mod is kept on could not be reached. \
+                                     It is left out of checks of the library, and copied into \
+                                     storage before it is enabled.",
+                                );
+                            }
+                            if let Some(review) = mod_.options_review.as_ref() {
+                                review_clicked = ui
+                                    .add(
+                                        Button::new(
+                                            egui::RichText::new("Review options")
+                                                .text_style(TextStyle::Small)
+                                                .color(uk_ui::visuals::YELLOW),
+                                        )
+                                        .small(),
+                                    )
+                                    .on_hover_text(format!(
+                                        "The options of this mod changed when it was updated, so \
+                                         those selected were matched to the new ones as well as \
+                                         they could be. Check them, then press OK to keep \
+                                         them.\n\n{review}"
+                                    ))
+                                    .clicked();
+                            }
+                            let name = mod_.meta.name.as_str();
+                            let full = row_galley(row_text, ui, hash, name, f32::INFINITY);
+                            let max_width = ui.available_width();
+                            if full.size().x > max_width {
+                                let clipped = row_galley(row_text, ui, hash, name, max_width);
+                                ui.label(clipped).on_hover_text(name);
+                            } else {
+                                ui.label(full);
+                            }
+                        });
+                        if expanded {
+                            for opt in &mod_.enabled_options {
+                                let mut active = mod_.is_option_active(opt);
+                                ui.horizontal(|ui| {
+                                    ui.add_space(ui.spacing().indent);
+                                    if ui
+                                        .checkbox(&mut active, opt.name.as_str())
+                                        .on_hover_text(
+                                            "Turn this option off for now without deselecting it",
+                                        )
+                                        .changed()
+                                    {
+                                        option_toggled = Some((opt.clone(), active));
+                                    }
+                                });
+                            }
+                        }
+                    });
                 })
                 .1,
             );
@@ -335,7 +573,7 @@ impl App {
                 process_col_res(
                     row.col(|ui| {
                         ui.centered_and_justified(|ui| {
-                            ui.label(label);
+                            ui.label(row_galley(row_text, ui, hash, label, f32::INFINITY));
                         });
                     })
                     .1,
@@ -360,17 +598,40 @@ impl App {
                             prompt,
                         ));
                     }
+                    ContextMenuMessage::Archive => {
+                        self.do_update(Message::ArchiveMods(None, false));
+                    }
                     ContextMenuMessage::Toggle(state) => {
                         self.do_update(Message::ToggleMods(None, state));
                     }
                     ContextMenuMessage::Move(dest) => {
                         self.do_update(Message::MoveSelected(dest));
                     }
+                    ContextMenuMessage::Reveal => {
+                        self.do_update(Message::Reveal(menu_mod.path.clone()));
+                    }
+                    ContextMenuMessage::Split => {
+                        self.do_update(Message::RequestSplit(menu_mod.clone()));
+                    }
+                    ContextMenuMessage::CheckCompat => {
+                        self.do_update(Message::StartCompat(compat::Subject::Installed(Box::new(
+                            menu_mod.clone(),
+                        ))));
+                    }
                 }
             }
-            if toggled {
+            if expand_toggled && !self.expanded_mods.remove(&menu_mod.hash()) {
+                self.expanded_mods.insert(menu_mod.hash());
+            }
+            if review_clicked {
+                self.do_update(Message::RequestOptions(menu_mod.clone(), true));
+            } else if let Some((opt, active)) = option_toggled {
+                let mut updated = menu_mod.clone();
+                updated.set_option_active(&opt, active);
+                self.do_update(Message::UpdateOptions(updated));
+            } else if toggled {
                 self.do_update(Message::ToggleMods(Some(vec![menu_mod.clone()]), enabled));
-            } else if clicked {
+            } else if clicked && !expand_toggled {
                 self.do_update(Message::SetFocus(FocusedPane::ModList));
                 if selected && ctrl {
                     self.do_update(Message::Deselect(index));
@@ -381,12 +642,8 @@ impl App {
                 } else {
                     self.do_update(Message::SelectOnly(index));
                 }
-            } else if drag_started {
-                if !ctrl && !shift {
-                    self.do_update(Message::StartDrag(index));
-                }
-            } else if self.drag_index != Some(index) && hover {
-                self.hover_index = Some(index);
+            } else if drag_started && !ctrl && !shift {
+                self.do_update(Message::StartDrag(index));
             }
         }
     }
@@ -409,11 +666,20 @@ impl App {
                 }
             }
         });
-        if ui.button("Update").clicked() {
+        const LOCAL_PATCH: &str = "Local patches are kept up to date by UKMM";
+        if ui
+            .add_enabled(!mod_.local_patch, Button::new("Update"))
+            .on_disabled_hover_text(LOCAL_PATCH)
+            .clicked()
+        {
             ui.close_menu();
             result = Some(ContextMenuMessage::Update);
         }
-        if ui.button("Dev Update").clicked() {
+        if ui
+            .add_enabled(!mod_.local_patch, Button::new("Dev Update"))
+            .on_disabled_hover_text(LOCAL_PATCH)
+            .clicked()
+        {
             ui.close_menu();
             result = Some(ContextMenuMessage::DevUpdate);
         }
@@ -421,6 +687,14 @@ impl App {
             ui.close_menu();
             result = Some(ContextMenuMessage::Uninstall);
         }
+        if ui
+            .button("Archive")
+            .on_hover_text("Hide from the list and leave out of merging without uninstalling")
+            .clicked()
+        {
+            ui.close_menu();
+            result = Some(ContextMenuMessage::Archive);
+        }
         if ui
             .button(if mod_.enabled { "Disable" } else { "Enable" })
             .clicked()
@@ -428,24 +702,34 @@ impl App {
             ui.close_menu();
             result = Some(ContextMenuMessage::Toggle(!mod_.enabled));
         }
-        if ui.button("View folder").clicked() {
+        if ui.button("Show in folder").clicked() {
             ui.close_menu();
-            let _ = Command::new(if cfg!(windows) {
-                "explorer"
-            } else {
-                "xdg-open"
-            })
-            .arg(if mod_.path.is_dir() {
-                &mod_.path
-            } else {
-                mod_.path.parent().unwrap()
-            })
-            .output();
+            result = Some(ContextMenuMessage::Reveal);
         }
+        reveal::copy_path_button(ui, &mod_.path);
         if ui.button("Extract").clicked() {
             ui.close_menu();
             result = Some(ContextMenuMessage::Extract);
         }
+        if ui.button("Check compatibility…").clicked() {
+            ui.close_menu();
+            result = Some(ContextMenuMessage::CheckCompat);
+        }
+        if ui
+            .add_enabled(
+                mod_.meta.options.is_empty() && !mod_.local_patch,
+                egui::Button::new("Split…"),
+            )
+            .on_disabled_hover_text(if mod_.local_patch {
+                LOCAL_PATCH
+            } else {
+                "Mods with options cannot be split"
+            })
+            .clicked()
+        {
+            ui.close_menu();
+            result = Some(ContextMenuMessage::Split);
+        }
         if ui.button("Move to start").clicked() {
             ui.close_menu();
             result = Some(ContextMenuMessage::Move(0));
@@ -457,3 +741,33 @@ impl App {
         result
     }
 }
+
+/// Lays out a line of text for a mod's row, or reuses the layout from the last
+/// frame, eliding it to fit `max_width`.
+fn row_galley(
+    cache: &mut RowCache<Arc<Galley>>,
+    ui: &Ui,
+    hash: usize,
+    text: &str,
+    max_width: f32,
+) -> Arc<Galley> {
+    let font = TextStyle::Body.resolve(ui.style());
+    let color = ui.visuals().text_color();
+    let key = (
+        hash,
+        text,
+        &font,
+        color,
+        max_width.to_bits(),
+        ui.ctx().pixels_per_point().to_bits(),
+    );
+    cache.get_or_insert_with(key, || {
+        let mut job = LayoutJob::simple_singleline(text.to_owned(), font.clone(), color);
+        job.wrap = TextWrapping {
+            max_rows: 1,
+            max_width,
+            ..Default::default()
+        };
+        ui.fonts(|f| f.layout_job(job))
+    })
+}
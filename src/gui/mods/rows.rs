@@ -0,0 +1,226 @@
+//! Bookkeeping for the mod list with hundreds of mods. The table only lays
+//! out the rows in view, so anything which needs to know about the others,
+//! such as where a drag would drop, works from the row heights instead, and
+//! row text is laid out once and kept until it changes.
+use std::hash::{Hash, Hasher};
+
+use rustc_hash::{FxHashMap, FxHasher};
+
+/// The vertical extent of every row in the mod list, relative to the top of
+/// the list, laid out the same way as the table with spacing after each row.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RowOffsets {
+    /// The top of each row, followed by the bottom of the list.
+    edges:   Vec<f32>,
+    spacing: f32,
+}
+
+impl RowOffsets {
+    pub fn new(heights: impl IntoIterator<Item = f32>, spacing: f32) -> Self {
+        let mut edges = vec![0.0];
+        let mut y = 0.0;
+        for height in heights {
+            y += height + spacing;
+            edges.push(y);
+        }
+        Self { edges, spacing }
+    }
+
+    pub fn len(&self) -> usize {
+        self.edges.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The top and bottom of a row, not counting the spacing after it.
+    pub fn span(&self, row: usize) -> Option<(f32, f32)> {
+        Some((
+            *self.edges.get(row)?,
+            *self.edges.get(row + 1)? - self.spacing,
+        ))
+    }
+
+    /// The row at `y`, counting the spacing after a row as part of it. Points
+    /// above or below the list give the first or last row.
+    pub fn row_at(&self, y: f32) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        let row = self.edges.partition_point(|edge| *edge <= y);
+        Some(row.saturating_sub(1).min(self.len() - 1))
+    }
+}
+
+/// Where `Message::MoveSelected(dest)` will put the selection, as the position
+/// in the current load order which the moved mods will end up just before, or
+/// `len` if they will end up last. `selected` holds the load order positions
+/// of the selected mods. Nothing moves if every mod is selected.
+pub fn drop_gap(len: usize, selected: &[usize], dest: usize) -> Option<usize> {
+    if len == 0 || selected.len() >= len {
+        return None;
+    }
+    // The selection is taken out first and then put back at `dest`
+    let dest = dest.min(len - 1);
+    let mut remaining = (0..len).filter(|i| !selected.contains(i));
+    Some(remaining.nth(dest).unwrap_or(len))
+}
+
+/// How far to scroll the list per second while dragging with the pointer at
+/// `y` over a list shown between `top` and `bottom`, positive to scroll down.
+/// Scrolling starts near either edge and is fastest at or past it.
+pub fn auto_scroll(y: f32, top: f32, bottom: f32) -> f32 {
+    const MARGIN: f32 = 32.0;
+    const MAX_SPEED: f32 = 900.0;
+    let margin = MARGIN.min((bottom - top) / 4.0);
+    if margin <= 0.0 {
+        return 0.0;
+    }
+    if y < top + margin {
+        -MAX_SPEED * ((top + margin - y) / margin).min(1.0)
+    } else if y > bottom - margin {
+        MAX_SPEED * ((y - (bottom - margin)) / margin).min(1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Values built for mod list rows, such as laid out text. Keys should cover
+/// everything the value depends on, so a changed mod or theme just misses,
+/// and entries which go unused for a frame are dropped.
+pub struct RowCache<T> {
+    entries: FxHashMap<u64, (T, bool)>,
+}
+
+impl<T> Default for RowCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: Default::default(),
+        }
+    }
+}
+
+impl<T: Clone> RowCache<T> {
+    pub fn get_or_insert_with(&mut self, key: impl Hash, make: impl FnOnce() -> T) -> T {
+        let mut hasher = FxHasher::default();
+        key.hash(&mut hasher);
+        let (value, used) = self
+            .entries
+            .entry(hasher.finish())
+            .or_insert_with(|| (make(), true));
+        *used = true;
+        value.clone()
+    }
+
+    /// Drops the entries not used since the last sweep. Call once a frame.
+    pub fn sweep(&mut self) {
+        self.entries.retain(|_, (_, used)| std::mem::take(used));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The same steps as `Message::MoveSelected`.
+    fn move_selected(mods: &mut Vec<usize>, selected: &[usize], dest: usize) {
+        let dest = dest.clamp(0, mods.len() - 1);
+        if selected.len() == mods.len() {
+            return;
+        }
+        mods.retain(|m| !selected.contains(m));
+        for (i, m) in selected.iter().enumerate() {
+            mods.insert((dest + i).min(mods.len()), *m);
+        }
+    }
+
+    #[test]
+    fn row_at() {
+        let offsets = RowOffsets::new([20.0, 40.0, 20.0], 4.0);
+        assert_eq!(offsets.len(), 3);
+        assert_eq!(offsets.row_at(-50.0), Some(0));
+        assert_eq!(offsets.row_at(0.0), Some(0));
+        assert_eq!(offsets.row_at(19.0), Some(0));
+        // The spacing after a row belongs to it
+        assert_eq!(offsets.row_at(22.0), Some(0));
+        assert_eq!(offsets.row_at(24.0), Some(1));
+        assert_eq!(offsets.row_at(67.9), Some(1));
+        assert_eq!(offsets.row_at(68.0), Some(2));
+        assert_eq!(offsets.row_at(1000.0), Some(2));
+        assert_eq!(offsets.span(1), Some((24.0, 64.0)));
+        assert_eq!(offsets.span(3), None);
+        assert_eq!(RowOffsets::new([], 4.0).row_at(0.0), None);
+    }
+
+    #[test]
+    fn row_at_far_down() {
+        let offsets = RowOffsets::new(vec![18.0; 500], 3.0);
+        for row in [0, 1, 249, 250, 498, 499] {
+            let (top, bottom) = offsets.span(row).unwrap();
+            assert_eq!(offsets.row_at(top), Some(row));
+            assert_eq!(offsets.row_at((top + bottom) / 2.0), Some(row));
+        }
+    }
+
+    #[test]
+    fn drop_gap_matches_move() {
+        let len = 8;
+        let selections: &[&[usize]] = &[&[0], &[3], &[7], &[2, 3], &[1, 5], &[0, 6, 7]];
+        for selected in selections {
+            for dest in 0..len + 2 {
+                let mut mods: Vec<usize> = (0..len).collect();
+                move_selected(&mut mods, selected, dest);
+                let first = mods.iter().position(|m| *m == selected[0]).unwrap();
+                let after = mods.get(first + selected.len()).copied().unwrap_or(len);
+                assert_eq!(
+                    drop_gap(len, selected, dest),
+                    Some(after),
+                    "{selected:?} to {dest}"
+                );
+            }
+        }
+        assert_eq!(drop_gap(3, &[0, 1, 2], 1), None);
+        assert_eq!(drop_gap(0, &[], 0), None);
+    }
+
+    #[test]
+    fn drag_edges() {
+        // Dragging one mod down lands it after the hovered row, and up before
+        assert_eq!(drop_gap(10, &[2], 5), Some(6));
+        assert_eq!(drop_gap(10, &[5], 2), Some(2));
+        assert_eq!(drop_gap(10, &[5], 9), Some(10));
+    }
+
+    #[test]
+    fn scroll_speed() {
+        assert_eq!(auto_scroll(300.0, 100.0, 500.0), 0.0);
+        assert!(auto_scroll(110.0, 100.0, 500.0) < 0.0);
+        assert!(auto_scroll(490.0, 100.0, 500.0) > 0.0);
+        assert!(auto_scroll(480.0, 100.0, 500.0) < auto_scroll(495.0, 100.0, 500.0));
+        assert_eq!(
+            auto_scroll(700.0, 100.0, 500.0),
+            auto_scroll(500.0, 100.0, 500.0)
+        );
+        assert_eq!(auto_scroll(50.0, 100.0, 100.0), 0.0);
+    }
+
+    #[test]
+    fn cache_sweep() {
+        let mut cache = RowCache::default();
+        let mut built = 0;
+        for _ in 0..3 {
+            cache.get_or_insert_with(("a", 1), || {
+                built += 1;
+                1
+            });
+            cache.sweep();
+        }
+        assert_eq!(built, 1);
+        cache.get_or_insert_with(("b", 1), || 2);
+        cache.sweep();
+        // Not used in the last frame
+        cache.sweep();
+        assert!(cache.entries.is_empty());
+    }
+}
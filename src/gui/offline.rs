@@ -0,0 +1,80 @@
+use uk_manager::mods::Mod;
+
+use super::{update::describe_mods, App, Message};
+
+impl App {
+    /// Probes the mods kept outside storage on a thread of its own, without
+    /// marking the app busy, so a sleeping share never holds anything up.
+    pub(super) fn probe_availability(&self) {
+        let sender = self.channel.0.clone();
+        let core = self.core.clone();
+        std::thread::spawn(move || {
+            let offline = core.mod_manager().probe_availability();
+            sender
+                .send(Message::AvailabilityProbed(offline))
+                .unwrap_or(());
+        });
+    }
+
+    /// Notes which mods are offline, telling of any which were not before.
+    pub(super) fn set_offline(&mut self, offline: Vec<Mod>) {
+        let newly: Vec<Mod> = offline
+            .iter()
+            .filter(|m| !self.offline.contains(&m.hash()))
+            .cloned()
+            .collect();
+        self.offline = offline.iter().map(Mod::hash).collect();
+        if !newly.is_empty() {
+            self.do_update(Message::Toast(format!(
+                "{} {} offline",
+                describe_mods(&newly),
+                if newly.len() == 1 { "is" } else { "are" }
+            )));
+        }
+    }
+
+    /// Whether any of these mods are disabled and offline, so would have to
+    /// be copied to storage before they can be enabled.
+    pub(super) fn needs_hydrating(&self, mods: &[Mod]) -> bool {
+        self.mods
+            .iter()
+            .any(|m| !m.enabled && self.offline.contains(&m.hash()) && mods.contains(m))
+    }
+
+    /// Asks to copy the offline mods among some about to be enabled to
+    /// storage, then enable them all.
+    pub(super) fn confirm_hydrate(&mut self, mods: Vec<Mod>) {
+        let offline: Vec<Mod> = mods
+            .iter()
+            .filter(|m| self.offline.contains(&m.hash()))
+            .cloned()
+            .collect();
+        let prompt = format!(
+            "{} {} offline, so {} cannot be merged. Copy {} into local storage first? This waits \
+             for the drive or share to wake up, if it is asleep.",
+            describe_mods(&offline),
+            if offline.len() == 1 { "is" } else { "are" },
+            if offline.len() == 1 { "it" } else { "they" },
+            if offline.len() == 1 { "it" } else { "them" },
+        );
+        self.do_update(Message::Confirm(Message::HydrateMods(mods).into(), prompt));
+    }
+
+    /// Points the list at the copies of mods hydrated into storage, then
+    /// enables them.
+    pub(super) fn hydrated(&mut self, mods: Vec<Mod>) {
+        self.busy.set(false);
+        for hydrated in &mods {
+            self.offline.remove(&hydrated.hash());
+            for m in self
+                .mods
+                .iter_mut()
+                .chain(self.selected.iter_mut())
+                .filter(|m| m.hash() == hydrated.hash())
+            {
+                m.path = hydrated.path.clone();
+            }
+        }
+        self.do_update(Message::ToggleMods(Some(mods), true));
+    }
+}
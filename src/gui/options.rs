@@ -1,24 +1,161 @@
+use std::path::PathBuf;
+
 use eframe::egui::Button;
-use uk_mod::ModOptionGroup;
+use rustc_hash::FxHashMap;
+use uk_manager::mods::Mod;
+use uk_mod::{unpack::ModReader, ModOption, ModOptionGroup};
 use uk_ui::{
-    egui::{self, Align, Checkbox, Context, Layout, Vec2},
+    egui::{self, Align, Checkbox, ColorImage, Context, Layout, TextureHandle, Ui, Vec2},
     visuals,
 };
 
 use super::{App, Message};
 
+const CARD_WIDTH: f32 = 180.0;
+const IMAGE_HEIGHT: f32 = 100.0;
+
+/// Preview images for the options of the mod in the option picker. They are
+/// decoded on a background thread and picked up as each one finishes, and
+/// their textures are dropped when the picker closes.
+#[derive(Default)]
+pub struct OptionImages {
+    mod_hash: Option<usize>,
+    receiver: Option<flume::Receiver<(PathBuf, Option<ColorImage>)>>,
+    textures: FxHashMap<PathBuf, Option<TextureHandle>>,
+}
+
+fn decode(data: &[u8]) -> anyhow::Result<ColorImage> {
+    let image = image::load_from_memory(data)?.to_rgba8();
+    Ok(ColorImage::from_rgba_unmultiplied(
+        [image.width() as usize, image.height() as usize],
+        image.as_flat_samples().as_slice(),
+    ))
+}
+
+impl OptionImages {
+    /// Starts loading the images for a mod, unless they are already loaded.
+    fn load(&mut self, ctx: &Context, mod_: &Mod) {
+        if self.mod_hash == Some(mod_.hash()) {
+            return;
+        }
+        *self = Self {
+            mod_hash: Some(mod_.hash()),
+            ..Default::default()
+        };
+        let options: Vec<ModOption> = mod_
+            .meta
+            .options
+            .iter()
+            .flat_map(|group| group.options().iter())
+            .filter(|opt| opt.image.is_some())
+            .cloned()
+            .collect();
+        if options.is_empty() {
+            return;
+        }
+        let (tx, rx) = flume::unbounded();
+        let path = mod_.path.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let reader = match ModReader::open_peek(&path, vec![]) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    log::warn!("Failed to open mod for option images: {e:?}");
+                    return;
+                }
+            };
+            for opt in options {
+                let image = reader.option_image(&opt).and_then(|data| {
+                    decode(&data)
+                        .inspect_err(|e| log::warn!("Bad image for option {}: {e}", opt.name))
+                        .ok()
+                });
+                // The picker has been closed, so nobody wants the rest
+                if tx.send((opt.path, image)).is_err() {
+                    return;
+                }
+                ctx.request_repaint();
+            }
+        });
+        self.receiver = Some(rx);
+    }
+
+    fn poll(&mut self, ctx: &Context) {
+        let Some(receiver) = self.receiver.as_ref() else {
+            return;
+        };
+        for (path, image) in receiver.try_iter() {
+            let texture = image.map(|image| {
+                ctx.load_texture(
+                    format!("option-image-{}", path.display()),
+                    image,
+                    Default::default(),
+                )
+            });
+            self.textures.insert(path, texture);
+        }
+        if receiver.is_disconnected() && receiver.is_empty() {
+            self.receiver = None;
+        }
+    }
+
+    fn render(&self, opt: &ModOption, ui: &mut Ui) {
+        if opt.image.is_none() {
+            return;
+        }
+        let size = Vec2::new(CARD_WIDTH, IMAGE_HEIGHT);
+        match self.textures.get(&opt.path) {
+            Some(Some(texture)) => {
+                ui.add(egui::Image::new(texture).max_size(size));
+            }
+            // Failed to load, so this is just a text card
+            Some(None) => (),
+            None if self.receiver.is_some() => {
+                ui.allocate_ui(size, |ui| {
+                    ui.centered_and_justified(|ui| ui.spinner());
+                });
+            }
+            None => (),
+        }
+    }
+}
+
+fn option_card(
+    images: &OptionImages,
+    opt: &ModOption,
+    ui: &mut Ui,
+    add_control: impl FnOnce(&mut Ui),
+) {
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.set_width(CARD_WIDTH);
+        ui.vertical(|ui| {
+            images.render(opt, ui);
+            add_control(ui);
+            if !opt.description.is_empty() {
+                ui.small(opt.description.as_str());
+            }
+        });
+    });
+}
+
 impl App {
     pub fn render_option_picker(&mut self, ctx: &Context) {
-        let is_opt_mod = self.options_mod.is_some();
-        if !is_opt_mod {
+        let Some((mod_, _)) = self.options_mod.as_ref() else {
+            if self.option_images.mod_hash.is_some() {
+                self.option_images = Default::default();
+            }
             return;
-        }
+        };
+        self.option_images.load(ctx, mod_);
+        self.option_images.poll(ctx);
         egui::Window::new("Select Mod Options")
             .collapsible(false)
             .scroll([false, true])
+            .default_width(CARD_WIDTH * 3.0 + 48.0)
             .anchor(egui::Align2::CENTER_CENTER, Vec2::default())
             .show(ctx, |ui| {
                 let mod_ = unsafe { &mut self.options_mod.as_mut().unwrap_unchecked().0 };
+                let images = &self.option_images;
                 let mut done = true;
                 mod_.meta.options.iter().for_each(|group| {
                     egui::CollapsingHeader::new(group.name())
@@ -44,39 +181,46 @@ impl App {
                                             mod_.enabled_options
                                                 .retain(|opt| !group.options.contains(opt));
                                         }
-                                        group.options.iter().for_each(|opt| {
-                                            if ui
-                                                .radio(
-                                                    mod_.enabled_options.contains(opt),
-                                                    opt.name.as_str(),
-                                                )
-                                                .clicked()
-                                            {
-                                                mod_.enabled_options
-                                                    .retain(|o| !group.options.contains(o));
-                                                mod_.enabled_options.push(opt.clone());
-                                            }
-                                            if !opt.description.is_empty() {
-                                                ui.small(opt.description.as_str());
-                                            }
+                                        ui.horizontal_wrapped(|ui| {
+                                            group.options.iter().for_each(|opt| {
+                                                option_card(images, opt, ui, |ui| {
+                                                    if ui
+                                                        .radio(
+                                                            mod_.enabled_options.contains(opt),
+                                                            opt.name.as_str(),
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        mod_.enabled_options
+                                                            .retain(|o| !group.options.contains(o));
+                                                        mod_.enabled_options.push(opt.clone());
+                                                    }
+                                                });
+                                            });
                                         });
                                     }
                                     uk_mod::OptionGroup::Multiple(group) => {
-                                        group.options.iter().for_each(|opt| {
-                                            let mut checked = mod_.enabled_options.contains(opt);
-                                            if ui
-                                                .add(Checkbox::new(&mut checked, opt.name.as_str()))
-                                                .clicked()
-                                            {
-                                                if checked {
-                                                    mod_.enabled_options.push(opt.clone());
-                                                } else {
-                                                    mod_.enabled_options.retain(|o| o != opt);
-                                                }
-                                            }
-                                            if !opt.description.is_empty() {
-                                                ui.small(opt.description.as_str());
-                                            }
+                                        ui.horizontal_wrapped(|ui| {
+                                            group.options.iter().for_each(|opt| {
+                                                option_card(images, opt, ui, |ui| {
+                                                    let mut checked =
+                                                        mod_.enabled_options.contains(opt);
+                                                    if ui
+                                                        .add(Checkbox::new(
+                                                            &mut checked,
+                                                            opt.name.as_str(),
+                                                        ))
+                                                        .clicked()
+                                                    {
+                                                        if checked {
+                                                            mod_.enabled_options.push(opt.clone());
+                                                        } else {
+                                                            mod_.enabled_options
+                                                                .retain(|o| o != opt);
+                                                        }
+                                                    }
+                                                });
+                                            });
                                         });
                                     }
                                 }
@@ -22,20 +22,33 @@ fn render_field(name: &str, ui: &mut Ui, add_contents: impl FnOnce(&mut Ui) -> R
     ui.horizontal(add_contents);
     ui.add_space(4.0);
 }
+/// What to do when files in the source were saved for the wrong platform.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EndianCheck {
+    #[default]
+    Ask,
+    Convert,
+    Ignore,
+}
+
 #[derive(Debug, Clone)]
 pub struct ModPackerBuilder {
     pub source: PathBuf,
-    pub dest:   PathBuf,
-    pub meta:   Meta,
+    pub alt_source: PathBuf,
+    pub dest: PathBuf,
+    pub meta: Meta,
+    pub endian_check: EndianCheck,
 }
 
 impl ModPackerBuilder {
     pub fn new(platform: Platform) -> Self {
         ModPackerBuilder {
             source: Default::default(),
-            dest:   Default::default(),
-            meta:   Meta {
+            alt_source: Default::default(),
+            dest: Default::default(),
+            meta: Meta {
                 api: env!("CARGO_PKG_VERSION").into(),
+                format: uk_mod::FORMAT_VERSION,
                 name: Default::default(),
                 version: "1.0.0".into(),
                 author: Default::default(),
@@ -46,6 +59,7 @@ impl ModPackerBuilder {
                 options: Default::default(),
                 masters: Default::default(),
             },
+            endian_check: EndianCheck::Ask,
         }
     }
 
@@ -105,6 +119,53 @@ impl ModPackerBuilder {
             });
     }
 
+    fn render_endian_audit(&self, app: &App, ctx: &Context) {
+        let Some((_, mismatches)) = app.endian_audit.as_ref() else {
+            return;
+        };
+        egui::Window::new("Platform Mismatch")
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} file(s) in the source are saved for a different platform than the mod is \
+                     for. They can be converted while packaging, or stored as they are.",
+                    mismatches.len()
+                ));
+                ui.add_space(4.0);
+                egui::ScrollArea::vertical()
+                    .id_source("modal-endian-audit")
+                    .max_height(240.0)
+                    .show_rows(
+                        ui,
+                        ui.text_style_height(&TextStyle::Body),
+                        mismatches.len(),
+                        |ui, range| {
+                            for mismatch in &mismatches[range] {
+                                ui.label(mismatch.to_string());
+                            }
+                        },
+                    );
+                if mismatches.iter().any(|m| !m.format.convertible()) {
+                    ui.label(
+                        "Big endian AAMP files cannot be converted and will be stored as they are.",
+                    );
+                }
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Convert and Package").clicked() {
+                        app.do_update(Message::ResolveEndianAudit(Some(EndianCheck::Convert)));
+                    }
+                    if ui.button("Package Anyway").clicked() {
+                        app.do_update(Message::ResolveEndianAudit(Some(EndianCheck::Ignore)));
+                    }
+                    if ui.button("Cancel").clicked() {
+                        app.do_update(Message::ResolveEndianAudit(None));
+                    }
+                });
+            });
+    }
+
     fn render_package_opts(&mut self, app: &App, ctx: &Context) {
         if let Some(ref folders) = app.opt_folders {
             egui::Window::new("Configure Mod Options")
@@ -228,6 +289,7 @@ impl ModPackerBuilder {
                                     description: Default::default(),
                                     path: Default::default(),
                                     requires: vec![],
+                                    image: None,
                                 });
                             }
                         });
@@ -324,6 +386,18 @@ impl ModPackerBuilder {
                                 }
                             }
                         });
+                    ui.label("Preview Image").on_hover_text(
+                        "An image in the option folder to show when picking options. If left \
+                         empty, a thumb, thumbnail, or preview image in the folder is used.",
+                    );
+                    let mut image = option
+                        .image
+                        .as_ref()
+                        .map(|image| image.display().to_string())
+                        .unwrap_or_default();
+                    if ui.text_edit_singleline(&mut image).changed() {
+                        option.image = (!image.is_empty()).then(|| image.into());
+                    }
                 });
         }
     }
@@ -333,6 +407,7 @@ impl ModPackerBuilder {
             let id = Id::new("packer_data");
             self.render_package_deps(app, ui.ctx());
             self.render_package_opts(app, ui.ctx());
+            self.render_endian_audit(app, ui.ctx());
             ui.horizontal(|ui| {
                 let source_set = self.source.exists();
                 ui.add_enabled_ui(source_set, |ui| {
@@ -370,6 +445,41 @@ impl ModPackerBuilder {
                     self.meta.platform = ModPlatform::Specific(app.platform().into());
                 }
             }
+            let mut dual = matches!(self.meta.platform, ModPlatform::Dual);
+            if ui
+                .checkbox(&mut dual, " Include both platforms")
+                .on_hover_text(
+                    "Package Wii U and Switch versions together, using a separate source folder \
+                     for the other platform",
+                )
+                .changed()
+            {
+                if dual {
+                    self.meta.platform = ModPlatform::Dual;
+                } else {
+                    self.meta.platform = ModPlatform::Specific(app.platform().into());
+                }
+            }
+            if dual {
+                render_field("Other Platform Source", ui, |ui| {
+                    ui.folder_picker(&mut self.alt_source)
+                });
+            }
+            render_field("Format Version", ui, |ui| {
+                egui::ComboBox::new(id.with("format"), "")
+                    .selected_text(self.meta.format.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in (1..=uk_mod::FORMAT_VERSION).rev() {
+                            ui.selectable_value(&mut self.meta.format, format, format.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Package for an older mod format so users of older versions of UKMM can \
+                         install it. Anything the older format cannot hold is left out or stored \
+                         whole, and listed in the log.",
+                    )
+            });
             render_field("Name", ui, |ui| {
                 ui.text_edit_singleline(&mut SmartStringWrapper(&mut self.meta.name))
             });
@@ -447,6 +557,7 @@ impl ModPackerBuilder {
                 self.source != PathBuf::default()
                     && self.source.exists()
                     && !self.meta.name.is_empty()
+                    && (!dual || self.alt_source.exists())
             };
             ui.add_enabled_ui(is_valid(), |ui| {
                 ui.allocate_ui_with_layout(
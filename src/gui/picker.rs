@@ -1,13 +1,25 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
+use uk_mod::{unpack::ModReader, Meta, ModOptionGroup};
 use uk_ui::{
-    egui::{self, Button, Key, Ui, Vec2},
+    egui::{self, Button, Key, RichText, TextStyle, Ui, Vec2},
     icons::{get_icon, Icon, IconButtonExt},
 };
 
-use super::{App, FocusedPane, Message};
+use super::{compat, tasks, visuals, App, FocusedPane, Message};
+
+/// How long the selection has to stay on a file before it is peeked at, so
+/// moving through a folder does not open every mod on the way.
+const PEEK_DELAY: Duration = Duration::from_millis(300);
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct FilePickerState {
@@ -304,8 +316,199 @@ impl FilePickerState {
     }
 }
 
+/// What peeking at the selected file found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Preview {
+    Mod {
+        meta:    Box<Meta>,
+        content: usize,
+        aoc:     usize,
+    },
+    /// Not a UKMM mod, but something which can be converted to one, like a
+    /// BNP or a graphic pack.
+    Convertible(&'static str),
+    Failed(String),
+}
+
+impl Preview {
+    fn peek(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        if ext == "bnp" {
+            return Preview::Convertible("BCML mod");
+        }
+        if uk_manager::texts::is_text_folder(path) {
+            return Preview::Convertible("folder of game texts");
+        }
+        match ModReader::open_peek(path, vec![]) {
+            Ok(reader) => {
+                Preview::Mod {
+                    content: reader.manifest.content_files.len(),
+                    aoc:     reader.manifest.aoc_files.len(),
+                    meta:    Box::new(reader.meta),
+                }
+            }
+            Err(e) => {
+                if tasks::is_probably_a_mod_and_has_meta(path).0 {
+                    Preview::Convertible("graphic pack")
+                } else {
+                    Preview::Failed(format!("{e:#}"))
+                }
+            }
+        }
+    }
+}
+
+/// Peeks at the mod selected in the file picker in the background, once the
+/// selection has settled. Moving on cancels the peek in progress.
+#[derive(Debug, Default)]
+pub struct PeekState {
+    /// The selected file waiting to be peeked at, and when it was selected.
+    pending:  Option<(PathBuf, Instant)>,
+    /// The file being or last peeked at.
+    path:     Option<PathBuf>,
+    cancel:   Arc<AtomicBool>,
+    receiver: Option<flume::Receiver<Preview>>,
+    preview:  Option<Preview>,
+}
+
+impl PeekState {
+    fn plausible(path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| matches!(e.to_lowercase().as_str(), "zip" | "7z" | "bnp"))
+            .unwrap_or(false)
+            || path.join("meta.yml").is_file()
+    }
+
+    /// Follows the picker's selection. Returns the file to peek at once it
+    /// has stayed selected for [`PEEK_DELAY`].
+    fn settle(&mut self, selected: Option<&Path>, now: Instant) -> Option<PathBuf> {
+        let waiting = self.pending.as_ref().map(|(path, _)| path.as_path());
+        if selected != waiting && selected != self.path.as_deref() {
+            self.cancel.store(true, Ordering::Relaxed);
+            self.receiver = None;
+            self.preview = None;
+            self.path = None;
+            self.pending = selected
+                .filter(|path| Self::plausible(path))
+                .map(|path| (path.to_path_buf(), now));
+            return None;
+        }
+        match self.pending.as_ref() {
+            Some((_, since)) if now.duration_since(*since) >= PEEK_DELAY => {
+                let (path, _) = self.pending.take()?;
+                self.path = Some(path.clone());
+                self.cancel = Arc::new(AtomicBool::new(false));
+                Some(path)
+            }
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, selected: Option<&Path>, ctx: &egui::Context) {
+        let now = Instant::now();
+        if let Some(path) = self.settle(selected, now) {
+            let (tx, rx) = flume::bounded(1);
+            let cancel = self.cancel.clone();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                if cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                let preview = Preview::peek(&path);
+                if !cancel.load(Ordering::Relaxed) && tx.send(preview).is_ok() {
+                    ctx.request_repaint();
+                }
+            });
+            self.receiver = Some(rx);
+        } else if let Some((_, since)) = self.pending.as_ref() {
+            ctx.request_repaint_after(PEEK_DELAY.saturating_sub(now.duration_since(*since)));
+        }
+        if let Some(preview) = self.receiver.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.preview = Some(preview);
+            self.receiver = None;
+        }
+    }
+}
+
 impl App {
+    fn render_peek(&mut self, path: PathBuf, ui: &mut Ui) {
+        let install = match self.picker_peek.preview.as_ref() {
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Reading mod…");
+                });
+                false
+            }
+            Some(Preview::Mod { meta, content, aoc }) => {
+                ui.label(RichText::new(meta.name.as_str()).heading());
+                ui.label(format!(
+                    "Version {} by {}",
+                    meta.version.as_str(),
+                    meta.author.as_str()
+                ));
+                ui.label(format!("For {}", meta.platform));
+                let options: usize = meta.options.iter().map(|g| g.options().len()).sum();
+                ui.label(format!(
+                    "{content} base game file(s), {aoc} DLC file(s), {options} option(s)"
+                ));
+                if !meta.description.is_empty() {
+                    egui::ScrollArea::vertical()
+                        .id_source("picker_preview_desc")
+                        .max_height(80.0)
+                        .show(ui, |ui| {
+                            ui.label(
+                                RichText::new(meta.description.as_str())
+                                    .text_style(TextStyle::Small),
+                            );
+                        });
+                }
+                ui.horizontal(|ui| {
+                    let install = ui.button("Install").clicked();
+                    if ui
+                        .button("Check compatibility…")
+                        .on_hover_text("See how it would get along with another mod first")
+                        .clicked()
+                    {
+                        self.do_update(Message::StartCompat(compat::Subject::File(path.clone())));
+                    }
+                    install
+                })
+                .inner
+            }
+            Some(Preview::Convertible(kind)) => {
+                ui.label(format!("Not a UKMM mod, but it looks like a {kind}."));
+                ui.button("Convert…")
+                    .on_hover_text("Convert it to a UKMM mod and install it")
+                    .clicked()
+            }
+            Some(Preview::Failed(err)) => {
+                ui.label(RichText::new("Not a UKMM mod").color(visuals::YELLOW))
+                    .on_hover_text(err.as_str());
+                false
+            }
+        };
+        if install {
+            self.do_update(Message::OpenMod(path));
+        }
+    }
+
     pub fn render_file_picker(&mut self, ui: &mut Ui) {
+        let selected = self.picker_state.selected.clone();
+        self.picker_peek.update(selected.as_deref(), ui.ctx());
+        if let Some(path) = self.picker_peek.path.clone() {
+            egui::TopBottomPanel::bottom("picker_preview")
+                .resizable(false)
+                .show_inside(ui, |ui| {
+                    ui.add_space(4.0);
+                    self.render_peek(path, ui);
+                });
+        }
         egui::Frame::none().inner_margin(2.0).show(ui, |ui| {
             ui.horizontal(|ui| {
                 for (icon, tooltip, cb) in [
@@ -426,3 +629,54 @@ impl App {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peek_debounce() {
+        let mut peek = PeekState::default();
+        let start = Instant::now();
+        let (a, b) = (Path::new("a.zip"), Path::new("b.bnp"));
+        assert_eq!(peek.settle(Some(a), start), None);
+        assert_eq!(peek.settle(Some(a), start + PEEK_DELAY / 2), None);
+        // Moving on before the delay starts the wait again
+        assert_eq!(peek.settle(Some(b), start + PEEK_DELAY / 2), None);
+        assert_eq!(peek.settle(Some(b), start + PEEK_DELAY), None);
+        assert_eq!(
+            peek.settle(Some(b), start + PEEK_DELAY * 3 / 2),
+            Some(b.to_path_buf())
+        );
+        // The same file is only peeked at once
+        assert_eq!(peek.settle(Some(b), start + PEEK_DELAY * 3), None);
+        // Files which cannot be mods are never peeked at
+        let txt = Path::new("readme.txt");
+        assert_eq!(peek.settle(Some(txt), start + PEEK_DELAY * 3), None);
+        assert_eq!(peek.settle(Some(txt), start + PEEK_DELAY * 5), None);
+        assert!(peek.path.is_none());
+    }
+
+    #[test]
+    fn peek_cancel() {
+        let mut peek = PeekState::default();
+        let start = Instant::now();
+        let a = Path::new("a.zip");
+        peek.settle(Some(a), start);
+        assert!(peek.settle(Some(a), start + PEEK_DELAY).is_some());
+        let cancel = peek.cancel.clone();
+        peek.preview = Some(Preview::Failed("stale".into()));
+        assert!(!cancel.load(Ordering::Relaxed));
+        // Selecting something else cancels the running peek and drops its
+        // preview
+        peek.settle(Some(Path::new("b.zip")), start + PEEK_DELAY * 2);
+        assert!(cancel.load(Ordering::Relaxed));
+        assert!(peek.preview.is_none());
+        // Clearing the selection does the same for the next one
+        peek.settle(Some(Path::new("b.zip")), start + PEEK_DELAY * 3);
+        let cancel = peek.cancel.clone();
+        peek.settle(None, start + PEEK_DELAY * 4);
+        assert!(cancel.load(Ordering::Relaxed));
+        assert!(peek.path.is_none() && peek.pending.is_none());
+    }
+}
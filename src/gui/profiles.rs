@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use fs_err as fs;
 use smartstring::alias::String as SmartString;
 use uk_content::util::HashMap;
-use uk_manager::mods::Profile as ProfileData;
+use uk_manager::mods::{trash::Trashed, Profile as ProfileData};
 use uk_ui::{
     egui::{self, text::LayoutJob, Layout, TextStyle},
     icons::IconButtonExt,
@@ -17,6 +17,10 @@ pub struct ProfileManagerState {
     pub profiles: HashMap<SmartString, ProfileData>,
     pub selected: Option<SmartString>,
     pub rename: Option<String>,
+    /// The profile to copy local patches to when deleting the only profile
+    /// with them.
+    pub keep_patches: Option<SmartString>,
+    pub trash: Vec<Trashed>,
     pub show: bool,
 }
 
@@ -38,6 +42,8 @@ impl ProfileManagerState {
             profiles,
             selected: None,
             rename: None,
+            keep_patches: None,
+            trash: core.mod_manager().trash(),
             show: false,
         }
     }
@@ -53,6 +59,8 @@ impl ProfileManagerState {
                 Some((name, data))
             })
             .collect::<_>();
+        self.trash = core.mod_manager().trash();
+        self.keep_patches = None;
     }
 
     fn render_selected_profile(&mut self, app: &App, ui: &mut egui::Ui) {
@@ -96,6 +104,9 @@ impl ProfileManagerState {
                             ui.allocate_space(ui.available_size());
                         });
                     ui.add_space(8.0);
+                    let priority = profile.priority();
+                    ui.label(format!("{} {}", priority.arrow(), priority.label()));
+                    ui.add_space(8.0);
                     if let Some(new_name) = self.rename.as_mut() {
                         ui.horizontal(|ui| {
                             ui.text_edit_singleline(new_name);
@@ -112,6 +123,38 @@ impl ProfileManagerState {
                         });
                     }
                     ui.add_space(8.0);
+                    let current = app
+                        .core
+                        .settings()
+                        .platform_config()
+                        .is_some_and(|c| c.profile.as_str() == name);
+                    let stranded = app.core.mod_manager().stranded_patches(name);
+                    if !current && !stranded.is_empty() {
+                        ui.horizontal(|ui| {
+                            ui.label("Keep local patches in").on_hover_text(format!(
+                                "No other profile has {}, so they will be lost when the trash is \
+                                 emptied",
+                                stranded
+                                    .iter()
+                                    .map(|m| m.meta.name.as_str())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ));
+                            egui::ComboBox::from_id_source("keep_patches")
+                                .selected_text(self.keep_patches.as_deref().unwrap_or("Trash Only"))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.keep_patches, None, "Trash Only");
+                                    for other in self.profiles.keys().filter(|p| p.as_str() != name)
+                                    {
+                                        ui.selectable_value(
+                                            &mut self.keep_patches,
+                                            Some(other.clone()),
+                                            other.as_str(),
+                                        );
+                                    }
+                                });
+                        });
+                    }
                     ui.horizontal(|ui| {
                         if ui.button("Rename").clicked() {
                             self.rename = Some(name.to_string());
@@ -119,10 +162,25 @@ impl ProfileManagerState {
                         if ui.button("Duplicate").clicked() {
                             app.do_update(Message::DuplicateProfile(name.to_string()));
                         }
-                        if ui.button("Delete").clicked() {
+                        if ui
+                            .add_enabled(!current, egui::Button::new("Delete"))
+                            .on_disabled_hover_text(
+                                "Switch to another profile before deleting this one",
+                            )
+                            .clicked()
+                        {
+                            let keep = self
+                                .keep_patches
+                                .as_ref()
+                                .filter(|_| !stranded.is_empty())
+                                .map(|p| p.to_string());
                             app.do_update(Message::Confirm(
-                                Message::DeleteProfile(name.to_string()).into(),
-                                format!("Are you sure you want to delete the profile {}?", name),
+                                Message::DeleteProfile(name.to_string(), keep).into(),
+                                format!(
+                                    "Are you sure you want to delete the profile {}? It will be \
+                                     kept in the trash until the trash is emptied.",
+                                    name
+                                ),
                             ));
                         }
                     });
@@ -133,6 +191,38 @@ impl ProfileManagerState {
         }
     }
 
+    fn render_trash(&self, app: &App, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("Trash ({})", self.trash.len()))
+            .id_source("profile_trash")
+            .show(ui, |ui| {
+                for trashed in &self.trash {
+                    ui.horizontal(|ui| {
+                        ui.label(trashed.profile.as_str());
+                        let taken = self.profiles.contains_key(&trashed.profile);
+                        if ui
+                            .add_enabled(!taken, egui::Button::new("Restore"))
+                            .on_disabled_hover_text("Rename the profile with this name first")
+                            .clicked()
+                        {
+                            app.do_update(Message::RestoreProfile(trashed.clone()));
+                        }
+                    });
+                }
+                if ui
+                    .button("Empty Trash")
+                    .on_hover_text("Delete these profiles for good, with mods no other has")
+                    .clicked()
+                {
+                    app.do_update(Message::Confirm(
+                        Message::EmptyTrash.into(),
+                        "Are you sure you want to empty the trash? Its profiles cannot be \
+                         restored afterwards, and mods no other profile has will be deleted."
+                            .into(),
+                    ));
+                }
+            });
+    }
+
     pub fn render(&mut self, app: &App, ctx: &egui::Context) {
         if self.show {
             egui::Window::new("Profiles")
@@ -179,6 +269,10 @@ impl ProfileManagerState {
                             });
                             self.render_selected_profile(app, ui);
                         });
+                    if !self.trash.is_empty() {
+                        ui.add_space(4.0);
+                        self.render_trash(app, ui);
+                    }
                     ui.add_space(4.0);
                     ui.horizontal(|ui| {
                         ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
@@ -0,0 +1,64 @@
+use uk_manager::purity;
+use uk_ui::{
+    egui::{RichText, Ui},
+    icons::{self, IconButtonExt},
+};
+
+use super::{App, Message};
+
+impl App {
+    /// Checks the current dump for mods in the background, so the result is
+    /// ready by the time it is needed.
+    pub(super) fn check_dump(&self) {
+        let settings = self.core.settings();
+        if let Some(dump) = settings.dump() {
+            let platform = settings.current_mode;
+            let _ = std::thread::Builder::new()
+                .name("dump check".into())
+                .spawn(move || purity::modded_files(&dump, platform));
+        }
+    }
+
+    /// Builds a confirmation prompt if the dump is modded and has not been
+    /// accepted yet.
+    pub(super) fn dump_prompt(&self) -> Option<String> {
+        let settings = self.core.settings();
+        let files = purity::unaccepted(&settings)?;
+        let dump = settings.dump()?;
+        Some(format!(
+            "{}\n\nUse this dump anyway? You will not be asked again.",
+            purity::warning(&files, dump.source().host_path())
+        ))
+    }
+
+    /// Warns at the top of the settings when the dump is modded, once it has
+    /// been checked.
+    pub(super) fn render_dump_warning(&self, ui: &mut Ui) {
+        let settings = self.core.settings();
+        let Some(dump) = settings.dump() else {
+            return;
+        };
+        let host = dump.source().host_path();
+        if settings.accepted_dumps.iter().any(|d| d == host) {
+            return;
+        }
+        let Some(files) = purity::cached(&dump).filter(|f| !f.is_empty()) else {
+            return;
+        };
+        ui.group(|ui| {
+            ui.label(
+                RichText::new("Your game dump appears to be modded")
+                    .strong()
+                    .color(ui.visuals().warn_fg_color),
+            );
+            ui.label(purity::warning(&files, host));
+            if ui
+                .icon_text_button("Use Anyway", icons::Icon::Check)
+                .clicked()
+            {
+                self.do_update(Message::AcceptDump(false));
+            }
+        });
+        ui.add_space(8.0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow_ext::Result;
+use uk_manager::{
+    core::Manager,
+    recovery::{self, Relocation},
+    settings::Settings,
+};
+use uk_ui::egui::{self, Align, Layout, RichText};
+
+use super::{App, Message};
+
+/// Shown instead of the main window when the storage folder is missing, until
+/// it is found or replaced.
+pub struct Recovery {
+    missing: PathBuf,
+    error:   Option<String>,
+    app:     Option<App>,
+}
+
+impl Recovery {
+    pub fn new(missing: PathBuf) -> Self {
+        Self {
+            missing,
+            error: None,
+            app: None,
+        }
+    }
+
+    fn locate(&mut self, ctx: &egui::Context) -> Result<()> {
+        let Some(dir) = rfd::FileDialog::new()
+            .set_title("Locate Storage Folder")
+            .pick_folder()
+        else {
+            return Ok(());
+        };
+        let mut settings = Settings::read(Settings::path())?;
+        let Relocation { fixed, missing } = recovery::relocate(&mut settings, &dir)?;
+        settings.save()?;
+        log::info!(
+            "Storage folder moved to {}, fixed {fixed} mod paths",
+            dir.display()
+        );
+        let app = self.start(ctx)?;
+        if !missing.is_empty() {
+            app.do_update(Message::Toast(format!(
+                "Some mods could not be found in the new storage folder: {}",
+                missing.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    fn start_fresh(&mut self, ctx: &egui::Context) -> Result<()> {
+        let Some(dir) = rfd::FileDialog::new()
+            .set_title("Create Storage Folder")
+            .pick_folder()
+        else {
+            return Ok(());
+        };
+        let mut settings = Settings::read(Settings::path())?;
+        recovery::start_fresh(&mut settings, &dir)?;
+        settings.save()?;
+        log::info!("Started new storage folder at {}", dir.display());
+        self.start(ctx)?;
+        Ok(())
+    }
+
+    fn start(&mut self, ctx: &egui::Context) -> Result<&App> {
+        let core = Arc::new(Manager::init()?);
+        Ok(self.app.insert(App::new(ctx, core)))
+    }
+}
+
+impl eframe::App for Recovery {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if let Some(app) = self.app.as_mut() {
+            return app.update(ctx, frame);
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                ui.add_space(ui.available_height() / 4.);
+                ui.heading("Storage Folder Not Found");
+                ui.add_space(8.);
+                ui.label(format!(
+                    "UKMM stores your mods and profiles in {}, but it could not be found. It may \
+                     have been moved, or it may be on a drive which is not connected.",
+                    self.missing.display()
+                ));
+                ui.label(
+                    "If it was moved, locate it to keep your mods. If the drive is not connected, \
+                     connect it and restart UKMM.",
+                );
+                ui.add_space(8.);
+                let locate = ui.button("Locate Moved Folder…").clicked();
+                let fresh = ui.button("Create New Storage Folder…").clicked();
+                if ui.button("Quit").clicked() {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                let result = if locate {
+                    self.locate(ctx)
+                } else if fresh {
+                    self.start_fresh(ctx)
+                } else {
+                    Ok(())
+                };
+                if let Err(e) = result {
+                    log::error!("Failed to recover storage folder: {e:?}");
+                    self.error = Some(e.to_string());
+                }
+                if let Some(error) = self.error.as_ref() {
+                    ui.add_space(8.);
+                    ui.label(RichText::new(error).color(ui.visuals().error_fg_color));
+                }
+            });
+        });
+    }
+
+    fn on_exit(&mut self, gl: Option<&eframe::glow::Context>) {
+        if let Some(app) = self.app.as_mut() {
+            app.on_exit(gl);
+        }
+    }
+}
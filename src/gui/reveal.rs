@@ -0,0 +1,174 @@
+//! Showing files in the system file manager. Where the platform can, the
+//! file is selected in its folder: Explorer on Windows, Finder on macOS, and
+//! on Linux whichever file manager answers the FileManager1 D-Bus interface.
+//! Otherwise the folder itself is opened, or the parent of a file.
+//!
+//! Widgets anywhere in the UI ask for a path to be shown through
+//! [`reveal_button`], which leaves it in egui's memory for the app to pick up
+//! on the next frame, so they need no access to the app themselves.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow_ext::{bail, Result};
+use uk_ui::{
+    egui::{self, Response, Ui},
+    icons::{Icon, IconButtonExt},
+};
+
+use super::{App, Message};
+
+fn reveal_id() -> egui::Id {
+    egui::Id::new("reveal_path")
+}
+
+/// Shows a file or folder in the system file manager, falling back to
+/// opening the folder which contains it.
+pub fn reveal(path: &Path) -> Result<()> {
+    if !path.exists() {
+        bail!("{} no longer exists", path.display());
+    }
+    if select(path) {
+        return Ok(());
+    }
+    let folder = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap_or(path)
+    };
+    open::that(folder)?;
+    Ok(())
+}
+
+/// Opens the folder of a file with the file selected, returning whether it
+/// worked.
+#[cfg(windows)]
+fn select(path: &Path) -> bool {
+    use std::os::windows::process::CommandExt;
+    // Explorer exits with an error code even when it succeeds, so only
+    // whether it started says anything
+    Command::new("explorer")
+        .raw_arg(explorer_select_arg(path))
+        .spawn()
+        .is_ok()
+}
+
+#[cfg(target_os = "macos")]
+fn select(path: &Path) -> bool {
+    Command::new("open")
+        .arg("-R")
+        .arg(path)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn select(path: &Path) -> bool {
+    Command::new("dbus-send")
+        .args([
+            "--session",
+            "--print-reply",
+            "--reply-timeout=2000",
+            "--dest=org.freedesktop.FileManager1",
+            "--type=method_call",
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1.ShowItems",
+        ])
+        .arg(format!("array:string:{}", file_uri(path)))
+        .arg("string:")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// The argument which has Explorer select a file. Explorer parses its own
+/// command line, so the path is quoted by hand and passed as it is.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn explorer_select_arg(path: &Path) -> String {
+    format!("/select,\"{}\"", path.to_string_lossy().replace('/', "\\"))
+}
+
+/// A `file://` URI for an absolute path. Everything but unreserved
+/// characters and separators is percent-encoded, commas included, since
+/// dbus-send splits array arguments on them.
+#[cfg_attr(any(windows, target_os = "macos"), allow(dead_code))]
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// A small folder button which shows a path in the file manager, with a
+/// menu to copy the path on right click.
+pub fn reveal_button(ui: &mut Ui, path: &Path) -> Response {
+    let res = ui
+        .icon_button(Icon::FolderOpen)
+        .on_hover_text("Show in folder");
+    if res.clicked() {
+        let path = path.to_path_buf();
+        ui.data_mut(|d| d.insert_temp(reveal_id(), path));
+    }
+    res.context_menu(|ui| copy_path_button(ui, path));
+    res
+}
+
+/// A menu item which copies a path to the clipboard.
+pub fn copy_path_button(ui: &mut Ui, path: &Path) {
+    if ui.button("Copy path").clicked() {
+        ui.close_menu();
+        ui.ctx().copy_text(path.display().to_string());
+    }
+}
+
+impl App {
+    /// Shows any path a [`reveal_button`] asked for last frame.
+    pub(super) fn handle_reveal(&self, ctx: &egui::Context) {
+        if let Some(path) = ctx.data_mut(|d| {
+            let path = d.get_temp::<PathBuf>(reveal_id());
+            d.remove::<PathBuf>(reveal_id());
+            path
+        }) {
+            self.do_update(Message::Reveal(path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explorer_arg() {
+        assert_eq!(
+            explorer_select_arg(Path::new(r"C:\Users\Link\UKMM\mods\Mod.zip")),
+            r#"/select,"C:\Users\Link\UKMM\mods\Mod.zip""#
+        );
+        assert_eq!(
+            explorer_select_arg(Path::new("C:/Program Files/UKMM, Mods/a.zip")),
+            r#"/select,"C:\Program Files\UKMM, Mods\a.zip""#
+        );
+    }
+
+    #[test]
+    fn uri() {
+        assert_eq!(
+            file_uri(Path::new("/home/link/.local/share/ukmm/mods/Mod.zip")),
+            "file:///home/link/.local/share/ukmm/mods/Mod.zip"
+        );
+        assert_eq!(
+            file_uri(Path::new("/home/link/My Mods, Old/ü#1.zip")),
+            "file:///home/link/My%20Mods%2C%20Old/%C3%BC%231.zip"
+        );
+    }
+}
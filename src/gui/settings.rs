@@ -1,17 +1,22 @@
 #![allow(unstable_name_collisions)]
 use std::{
+    collections::BTreeMap,
     ops::Deref,
     path::{Path, PathBuf},
     sync::{Arc, LazyLock},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use parking_lot::RwLock;
 use rustc_hash::FxHashMap;
 use serde::Deserialize;
 use uk_content::constants::Language;
-use uk_manager::settings::{DeployConfig, Platform, PlatformSettings};
-use uk_reader::ResourceReader;
+use uk_manager::{
+    hooks::{self, HookContext},
+    settings::{DeployConfig, Platform, PlatformSettings},
+};
+use uk_mod::policy::{MergePolicies, MergePolicy, Pattern, PolicyRule};
+use uk_reader::{ResourceReader, ZArchiveReport};
 use uk_ui::{
     egui::{self, Align, Checkbox, ImageButton, InnerResponse, Layout, RichText, TextStyle, Ui},
     ext::UiExt,
@@ -20,7 +25,7 @@ use uk_ui::{
 };
 use uk_util::{OptionExt, OptionResultExt};
 
-use super::{App, Message};
+use super::{reveal, App, Message};
 
 fn render_setting<R>(
     name: &str,
@@ -55,6 +60,8 @@ pub enum DumpType {
         update_dir:  PathBuf,
         aoc_dir:     Option<PathBuf>,
         host_path:   PathBuf,
+        #[serde(default = "uk_reader::default_zarchive_readers")]
+        readers:     usize,
     },
 }
 
@@ -92,9 +99,39 @@ impl DumpType {
     }
 }
 
+/// The folders of an unpacked dump, each kind listed in the order they are
+/// searched, so an update comes before the base game.
+#[derive(Deserialize)]
+struct UnpackedLayers {
+    host_path:    PathBuf,
+    content_dirs: Vec<PathBuf>,
+    aoc_dirs:     Vec<PathBuf>,
+}
+
 impl From<&ResourceReader> for DumpType {
     fn from(reader: &ResourceReader) -> Self {
-        serde_json::from_str(&reader.source_ser()).unwrap()
+        let source = reader.source_ser();
+        if let Ok(UnpackedLayers {
+            host_path,
+            content_dirs,
+            aoc_dirs,
+        }) = serde_json::from_str(&source)
+        {
+            // Only a base game and an update folder can be set here, so any
+            // further folders are shown as if they were not there
+            let (update_dir, content_dir) = match content_dirs.as_slice() {
+                [update, content, ..] => (Some(update.clone()), Some(content.clone())),
+                [content] => (None, Some(content.clone())),
+                [] => (None, None),
+            };
+            return DumpType::Unpacked {
+                host_path,
+                content_dir,
+                update_dir,
+                aoc_dir: aoc_dirs.into_iter().next(),
+            };
+        }
+        serde_json::from_str(&source).unwrap()
     }
 }
 
@@ -103,6 +140,10 @@ pub struct PlatformSettingsUI {
     pub language: Language,
     pub profile: String,
     pub dump: DumpType,
+    /// Further dumps by name, which profiles can use instead of the main one.
+    pub dumps: BTreeMap<String, DumpType>,
+    pub profile_dumps: BTreeMap<String, String>,
+    pub cache_capacity: u64,
     pub deploy_config: DeployConfig,
 }
 
@@ -117,36 +158,69 @@ impl Default for PlatformSettingsUI {
                 update_dir:  Default::default(),
                 aoc_dir:     Default::default(),
             },
+            dumps: Default::default(),
+            profile_dumps: Default::default(),
+            cache_capacity: uk_reader::DEFAULT_CACHE_CAPACITY,
             deploy_config: Default::default(),
         }
     }
 }
 
+/// Opens a game dump as set in the settings. A WUA dump is checked for the
+/// parts of the game it needs first.
+fn open_dump(dump: DumpType) -> Result<ResourceReader> {
+    Ok(match dump {
+        DumpType::Unpacked {
+            content_dir,
+            update_dir,
+            aoc_dir,
+            ..
+        } => ResourceReader::from_unpacked_dirs(content_dir, update_dir, aoc_dir)?,
+        DumpType::ZArchive {
+            host_path, readers, ..
+        } => {
+            let report = ResourceReader::inspect_zarchive(&host_path);
+            WUA_REPORTS.write().insert(
+                host_path.clone(),
+                report.as_ref().map_err(|e| e.to_string()).cloned(),
+            );
+            let report = report?;
+            report.check()?;
+            if let Some(aoc) = report.warning() {
+                log::warn!("{aoc}, so mods which edit the DLC will not work");
+            }
+            ResourceReader::from_zarchive_with_readers(host_path, readers)?
+        }
+    })
+}
+
 impl TryFrom<PlatformSettingsUI> for PlatformSettings {
     type Error = anyhow::Error;
 
     fn try_from(settings: PlatformSettingsUI) -> Result<Self> {
-        let dump = match settings.dump {
-            DumpType::Unpacked {
-                content_dir,
-                update_dir,
-                aoc_dir,
-                ..
-            } => {
-                Arc::new(ResourceReader::from_unpacked_dirs(
-                    content_dir,
-                    update_dir,
-                    aoc_dir,
-                )?)
-            }
-            DumpType::ZArchive { host_path, .. } => {
-                Arc::new(ResourceReader::from_zarchive(host_path)?)
-            }
-        };
+        let dump = open_dump(settings.dump)?;
+        let dumps = settings
+            .dumps
+            .into_iter()
+            .map(|(name, dump)| {
+                let dump = open_dump(dump)
+                    .with_context(|| format!("Could not open the game dump named {name}"))?;
+                Ok((
+                    name.as_str().into(),
+                    Arc::new(dump.with_cache_capacity(settings.cache_capacity)),
+                ))
+            })
+            .collect::<Result<_>>()?;
         Ok(Self {
             language: settings.language,
             profile: settings.profile.into(),
-            dump,
+            dump: Arc::new(dump.with_cache_capacity(settings.cache_capacity)),
+            dumps,
+            profile_dumps: settings
+                .profile_dumps
+                .into_iter()
+                .map(|(profile, name)| (profile.into(), name.into()))
+                .collect(),
             deploy_config: if settings.deploy_config.output.as_os_str().is_empty() {
                 None
             } else {
@@ -162,6 +236,17 @@ impl From<&PlatformSettings> for PlatformSettingsUI {
             language: settings.language,
             profile: settings.profile.to_string(),
             dump: settings.dump.as_ref().into(),
+            dumps: settings
+                .dumps
+                .iter()
+                .map(|(name, dump)| (name.to_string(), dump.as_ref().into()))
+                .collect(),
+            profile_dumps: settings
+                .profile_dumps
+                .iter()
+                .map(|(profile, name)| (profile.to_string(), name.to_string()))
+                .collect(),
+            cache_capacity: settings.dump.cache_capacity(),
             deploy_config: settings.deploy_config.as_ref().cloned().unwrap_or_default(),
         }
     }
@@ -172,13 +257,288 @@ impl PartialEq<PlatformSettings> for PlatformSettingsUI {
         self.language == other.language
             && other.deploy_config.contains(&self.deploy_config)
             && self.dump.host_path() == other.dump.source().host_path()
+            && self.dumps.len() == other.dumps.len()
+            && self.dumps.iter().zip(&other.dumps).all(
+                |((name, dump), (other_name, other_dump))| {
+                    name == other_name.as_str()
+                        && dump.host_path() == other_dump.source().host_path()
+                },
+            )
+            && self.profile_dumps.len() == other.profile_dumps.len()
+            && self
+                .profile_dumps
+                .iter()
+                .zip(&other.profile_dumps)
+                .all(|(a, b)| a.0 == b.0.as_str() && a.1 == b.1.as_str())
+            && self.cache_capacity == other.dump.cache_capacity()
     }
 }
 
 pub static CONFIG: LazyLock<RwLock<FxHashMap<Platform, PlatformSettingsUI>>> =
     LazyLock::new(|| RwLock::new(Default::default()));
 
-fn render_deploy_config(config: &mut DeployConfig, platform: Platform, ui: &mut Ui) -> bool {
+/// What each chosen WUA dump has, kept so an archive is only inspected again
+/// when it is saved.
+type WuaReports = FxHashMap<PathBuf, std::result::Result<ZArchiveReport, String>>;
+static WUA_REPORTS: LazyLock<RwLock<WuaReports>> =
+    LazyLock::new(|| RwLock::new(Default::default()));
+
+/// Shows which parts of the game a WUA dump is missing, and its region if it
+/// does not match the chosen language.
+fn render_wua_report(path: &Path, language: Language, ui: &mut Ui) {
+    if !path.is_file() {
+        return;
+    }
+    let mut reports = WUA_REPORTS.write();
+    let report = reports
+        .entry(path.to_path_buf())
+        .or_insert_with(|| ResourceReader::inspect_zarchive(path).map_err(|e| e.to_string()));
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            ui.label(RichText::new(e.as_str()).color(ui.visuals().error_fg_color));
+            return;
+        }
+    };
+    for damage in &report.damage {
+        ui.label(RichText::new(damage.to_string()).color(ui.visuals().error_fg_color));
+    }
+    for part in report.errors() {
+        ui.label(RichText::new(part.to_string()).color(ui.visuals().error_fg_color));
+    }
+    if let Some(aoc) = report.warning() {
+        ui.label(
+            RichText::new(format!("{aoc}, so mods which edit the DLC will not work"))
+                .color(ui.visuals().warn_fg_color),
+        );
+    }
+    match report.region {
+        Some(region) if region != language.region() => {
+            ui.label(
+                RichText::new(format!(
+                    "This is a {region} release of the game, which has no texts in {language}"
+                ))
+                .color(ui.visuals().warn_fg_color),
+            );
+        }
+        Some(region) => {
+            ui.small(format!("Region: {region}"));
+        }
+        None => (),
+    }
+}
+
+fn render_deploy_hooks(
+    config: &mut DeployConfig,
+    profile: &str,
+    platform: Platform,
+    ui: &mut Ui,
+) -> bool {
+    let mut changed = false;
+    let mut swap = None;
+    let mut remove = None;
+    let count = config.hooks.len();
+    for (i, hook) in config.hooks.iter_mut().enumerate() {
+        let title = if hook.label().trim().is_empty() {
+            "New Hook"
+        } else {
+            hook.label()
+        };
+        egui::CollapsingHeader::new(title.to_owned())
+            .id_source(("deploy-hook", platform, i))
+            .show(ui, |ui| {
+                render_setting(
+                    "Name",
+                    "A name to show for the hook in the log.",
+                    ui,
+                    |ui| {
+                        changed |= ui.text_edit_singleline(&mut hook.name).changed();
+                    },
+                );
+                render_setting(
+                    "Command",
+                    "The program to run and its arguments, quoted as in your shell. {output}, \
+                     {profile}, {platform} and {changed} are replaced with the deployment folder, \
+                     the profile name, wiiu or switch, and the number of files deployed.",
+                    ui,
+                    |ui| {
+                        changed |= ui.text_edit_singleline(&mut hook.command).changed();
+                    },
+                );
+                render_setting(
+                    "Working Folder",
+                    "The folder to run the command in. Leave empty to use the current folder.",
+                    ui,
+                    |ui| {
+                        let mut cwd = hook.cwd.clone().unwrap_or_default();
+                        if ui.folder_picker(&mut cwd).changed() {
+                            hook.cwd = (!cwd.as_os_str().is_empty()).then_some(cwd);
+                            changed = true;
+                        }
+                    },
+                );
+                render_setting(
+                    "Timeout",
+                    "How long to let the command run before stopping it. 0 waits forever.",
+                    ui,
+                    |ui| {
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut hook.timeout)
+                                    .range(0..=3600)
+                                    .suffix(" s"),
+                            )
+                            .changed();
+                    },
+                );
+                render_setting(
+                    "Only After Success",
+                    "Skip this hook if the deployment failed.",
+                    ui,
+                    |ui| {
+                        changed |= ui.checkbox(&mut hook.only_on_success, "").changed();
+                    },
+                );
+                render_setting(
+                    "Required",
+                    "Report the deployment as failed if this hook fails, and skip the hooks after \
+                     it. Otherwise a failure is only a warning.",
+                    ui,
+                    |ui| {
+                        changed |= ui.checkbox(&mut hook.required, "").changed();
+                    },
+                );
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(i > 0, egui::Button::new("Move Up"))
+                        .clicked()
+                    {
+                        swap = Some(i - 1);
+                    }
+                    if ui
+                        .add_enabled(i + 1 < count, egui::Button::new("Move Down"))
+                        .clicked()
+                    {
+                        swap = Some(i);
+                    }
+                    if ui
+                        .button("Test Run")
+                        .on_hover_text("Run the command now. Its output goes to the log.")
+                        .clicked()
+                    {
+                        let hook = hook.clone();
+                        let output = config.output.clone();
+                        let profile = profile.to_owned();
+                        std::thread::spawn(move || {
+                            let ctx = HookContext {
+                                output: &output,
+                                profile: &profile,
+                                platform,
+                                changed: 0,
+                                success: true,
+                            };
+                            match hooks::run_logged(&hook, &ctx) {
+                                Ok(()) => log::info!("Test run of {} succeeded", hook.label()),
+                                Err(e) => log::warn!("Test run of {} failed: {e:?}", hook.label()),
+                            }
+                        });
+                    }
+                    if ui.button("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            });
+    }
+    if let Some(i) = swap {
+        config.hooks.swap(i, i + 1);
+        changed = true;
+    }
+    if let Some(i) = remove {
+        config.hooks.remove(i);
+        changed = true;
+    }
+    if ui.icon_text_button("Add Hook", icons::Icon::Add).clicked() {
+        config.hooks.push(Default::default());
+        changed = true;
+    }
+    changed
+}
+
+/// Merge policy overrides for the current profile, as they are being edited.
+pub struct PolicyEditor {
+    path:  PathBuf,
+    rules: Vec<PolicyRule>,
+}
+
+impl PolicyEditor {
+    pub fn load(path: PathBuf) -> Self {
+        let rules = MergePolicies::load(&path)
+            .inspect_err(|e| log::warn!("Failed to load merge policy overrides: {e:?}"))
+            .map(|policies| policies.rules().cloned().collect())
+            .unwrap_or_default();
+        Self { path, rules }
+    }
+}
+
+fn render_merge_policies(editor: &mut PolicyEditor, ui: &mut Ui) -> Result<()> {
+    ui.label(
+        "Choose how particular files are merged. A pattern can be a canonical path, a glob such \
+         as Actor/Pack/*.bactorpack, or a resource type such as AIProgram or Binary.",
+    );
+    let mut remove = None;
+    let mut valid = true;
+    for (i, rule) in editor.rules.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut rule.pattern)
+                    .hint_text("Actor/Pack/*.bactorpack")
+                    .desired_width(240.0),
+            );
+            egui::ComboBox::new(("merge-policy", i), "")
+                .selected_text(rule.policy.to_string())
+                .show_ui(ui, |ui| {
+                    for policy in MergePolicy::ALL {
+                        ui.selectable_value(&mut rule.policy, policy, policy.to_string());
+                    }
+                });
+            if ui.button("Remove").clicked() {
+                remove = Some(i);
+            }
+        });
+        if let Err(e) = rule.pattern.parse::<Pattern>() {
+            valid = false;
+            ui.label(RichText::new(e.to_string()).color(ui.visuals().error_fg_color));
+        }
+    }
+    if let Some(i) = remove {
+        editor.rules.remove(i);
+    }
+    ui.horizontal(|ui| {
+        if ui.icon_text_button("Add Rule", icons::Icon::Add).clicked() {
+            editor.rules.push(PolicyRule {
+                pattern: Default::default(),
+                policy:  MergePolicy::PriorityBinary,
+            });
+        }
+        if ui
+            .add_enabled(valid, egui::Button::new("Save Rules"))
+            .on_hover_text("The rules take effect the next time mods are applied.")
+            .clicked()
+        {
+            MergePolicies::new(editor.rules.clone())?.save(&editor.path)?;
+            log::info!("Saved merge policy overrides. Remerge to apply them to existing files.");
+        }
+        Ok(())
+    })
+    .inner
+}
+
+fn render_deploy_config(
+    config: &mut DeployConfig,
+    profile: &str,
+    platform: Platform,
+    ui: &mut Ui,
+) -> bool {
     ui.label("Deployment");
     let mut changed = false;
     ui.group(|ui| {
@@ -239,8 +599,39 @@ fn render_deploy_config(config: &mut DeployConfig, platform: Platform, ui: &mut
             ui,
             |ui| {
                 changed |= ui.folder_picker(&mut config.output).changed();
+                reveal::reveal_button(ui, &config.output);
             },
         );
+        let (content, aoc) = uk_content::platform_prefixes(platform.into());
+        let output = config.output.clone();
+        for (name, description, prefix, folder) in [
+            (
+                "Base Game Output",
+                "Deploy base game files to a folder of their own instead of the usual one in the \
+                 output folder, such as for a split SD card layout.",
+                content,
+                &mut config.content_output,
+            ),
+            (
+                "DLC Output",
+                "Deploy DLC files to a folder of their own instead of the usual one in the output \
+                 folder, such as for a split SD card layout.",
+                aoc,
+                &mut config.aoc_output,
+            ),
+        ] {
+            render_setting(name, description, ui, |ui| {
+                let mut separate = folder.is_some();
+                if ui.checkbox(&mut separate, "Separate").changed() {
+                    *folder = separate.then(|| output.join(prefix));
+                    changed = true;
+                }
+                if let Some(folder) = folder {
+                    changed |= ui.folder_picker(folder).changed();
+                    reveal::reveal_button(ui, folder);
+                }
+            });
+        }
         render_setting(
             "Emulator Executable",
             "Command line for the emulator to run for playing the game. This can be an \
@@ -252,6 +643,123 @@ fn render_deploy_config(config: &mut DeployConfig, platform: Platform, ui: &mut
                     .changed();
             },
         );
+        render_setting(
+            "Check for Changes",
+            "Before applying and at startup, compare deployed files against what UKMM last \
+             deployed, to catch edits made by other tools. A sample checks a few hundred files \
+             each time, while a full check reads them all. Symlinked deployments are never \
+             checked.",
+            ui,
+            |ui| {
+                for check in uk_manager::drift::DriftCheck::ALL {
+                    changed |= ui
+                        .radio_value(&mut config.drift_check, check, check.name())
+                        .changed();
+                }
+            },
+        );
+        render_setting(
+            "Deploy Hooks",
+            "Commands to run, in order, after each deployment, such as syncing the output to \
+             another device. Their output is added to the log.",
+            ui,
+            |ui| {
+                ui.vertical(|ui| {
+                    changed |= render_deploy_hooks(config, profile, platform, ui);
+                });
+            },
+        );
+    });
+    changed
+}
+
+/// Dumps kept under a name besides the main one, and which one the current
+/// profile uses.
+fn render_named_dumps(config: &mut PlatformSettingsUI, platform: Platform, ui: &mut Ui) -> bool {
+    let mut changed = false;
+    ui.add_space(8.0);
+    ui.label("Other Game Dumps");
+    ui.group(|ui| {
+        ui.allocate_space([ui.available_width(), -8.0].into());
+        render_setting(
+            "Profile Dump",
+            "The game dump the current profile uses. Each profile keeps its own choice, so \
+             switching profiles switches dumps too.",
+            ui,
+            |ui| {
+                let selected = config
+                    .profile_dumps
+                    .get(&config.profile)
+                    .filter(|name| config.dumps.contains_key(*name))
+                    .cloned();
+                egui::ComboBox::new(format!("profile-dump-{platform}"), "")
+                    .selected_text(selected.as_deref().unwrap_or("Main dump"))
+                    .show_ui(ui, |ui| {
+                        if ui
+                            .selectable_label(selected.is_none(), "Main dump")
+                            .clicked()
+                        {
+                            config.profile_dumps.remove(&config.profile);
+                            changed = true;
+                        }
+                        for name in config.dumps.keys() {
+                            if ui
+                                .selectable_label(selected.as_ref() == Some(name), name)
+                                .clicked()
+                            {
+                                config
+                                    .profile_dumps
+                                    .insert(config.profile.clone(), name.clone());
+                                changed = true;
+                            }
+                        }
+                    });
+            },
+        );
+        let mut remove = None;
+        for (name, dump) in &config.dumps {
+            ui.horizontal(|ui| {
+                if ui
+                    .icon_button(icons::Icon::Delete)
+                    .on_hover_text("Remove")
+                    .clicked()
+                {
+                    remove = Some(name.clone());
+                }
+                ui.label(RichText::new(name).family(egui::FontFamily::Name("Bold".into())));
+                ui.small(dump.host_path().display().to_string());
+            });
+        }
+        if let Some(name) = remove {
+            config.dumps.remove(&name);
+            config.profile_dumps.retain(|_, dump| *dump != name);
+            changed = true;
+        }
+        render_setting(
+            "Keep Main Dump As",
+            "Keeps the main game dump above under a name, so it can be changed to another dump \
+             and profiles can choose between them. Named dumps may be of another region, but must \
+             be of the same version.",
+            ui,
+            |ui| {
+                let id = ui.id().with(("new-dump-name", platform));
+                let mut name = ui
+                    .data_mut(|d| d.get_temp::<String>(id))
+                    .unwrap_or_default();
+                ui.text_edit_singleline(&mut name);
+                let valid = !name.trim().is_empty()
+                    && !config.dumps.contains_key(name.trim())
+                    && !config.dump.is_empty();
+                if ui.add_enabled(valid, egui::Button::new("Add")).clicked() {
+                    config
+                        .dumps
+                        .insert(name.trim().to_owned(), config.dump.clone());
+                    name.clear();
+                    changed = true;
+                }
+                ui.data_mut(|d| d.insert_temp(id, name));
+            },
+        );
     });
     changed
 }
@@ -262,6 +770,7 @@ fn render_platform_config(
     ui: &mut Ui,
 ) -> bool {
     let mut changed = false;
+    let cache_stats = config.as_ref().map(|c| c.dump.cache_stats());
     let mut conf_lock = CONFIG.write();
     let config = conf_lock
         .entry(platform)
@@ -314,6 +823,7 @@ fn render_platform_config(
                             update_dir:  Default::default(),
                             aoc_dir:     Default::default(),
                             host_path:   Default::default(),
+                            readers:     uk_reader::default_zarchive_readers(),
                         };
                         changed = true;
                     }
@@ -344,6 +854,9 @@ fn render_platform_config(
                                 changed = true;
                                 *host_path = "/".into();
                             }
+                            if let Some(dir) = content_dir.as_deref() {
+                                reveal::reveal_button(ui, dir);
+                            }
                         },
                     );
                 }
@@ -362,6 +875,9 @@ fn render_platform_config(
                                 changed = true;
                                 *host_path = "/".into();
                             }
+                            if let Some(dir) = content_dir.as_deref() {
+                                reveal::reveal_button(ui, dir);
+                            }
                         },
                     );
                 }
@@ -381,6 +897,9 @@ fn render_platform_config(
                                 changed = true;
                                 *host_path = "/".into();
                             }
+                            if let Some(dir) = update_dir.as_deref() {
+                                reveal::reveal_button(ui, dir);
+                            }
                         },
                     );
                 }
@@ -399,6 +918,9 @@ fn render_platform_config(
                                 changed = true;
                                 *host_path = "/".into();
                             }
+                            if let Some(dir) = aoc_dir.as_deref() {
+                                reveal::reveal_button(ui, dir);
+                            }
                         },
                     );
                 }
@@ -413,6 +935,9 @@ fn render_platform_config(
                                 changed = true;
                                 *host_path = "/".into();
                             }
+                            if let Some(dir) = aoc_dir.as_deref() {
+                                reveal::reveal_button(ui, dir);
+                            }
                         },
                     );
                 }
@@ -422,6 +947,7 @@ fn render_platform_config(
                 update_dir: _,
                 aoc_dir: _,
                 host_path,
+                readers,
             } => {
                 render_setting(
                     "WUA Path",
@@ -430,12 +956,51 @@ fn render_platform_config(
                     ui,
                     |ui| {
                         changed |= ui.file_picker(host_path).changed();
+                        reveal::reveal_button(ui, host_path);
+                    },
+                );
+                render_wua_report(host_path, config.language, ui);
+                render_setting(
+                    "WUA Readers",
+                    "How many files can be read from the .wua at once while merging. More readers \
+                     speed up merges on machines with many cores, at the cost of some memory each.",
+                    ui,
+                    |ui| {
+                        changed |= ui
+                            .add(egui::DragValue::new(readers).range(1..=16))
+                            .changed();
                     },
                 );
             }
         }
+        render_setting(
+            "Resource Cache",
+            "How much of the game dump is kept in memory once parsed, by the size of its files. A \
+             larger cache speeds up merging large mod lists, while a smaller one suits machines \
+             with little memory.",
+            ui,
+            |ui| {
+                const MIB: u64 = 1024 * 1024;
+                let mut mib = config.cache_capacity / MIB;
+                if ui
+                    .add(
+                        egui::Slider::new(&mut mib, 64..=4096)
+                            .logarithmic(true)
+                            .suffix(" MiB"),
+                    )
+                    .changed()
+                {
+                    config.cache_capacity = mib * MIB;
+                    changed = true;
+                }
+            },
+        );
+        if let Some(stats) = cache_stats {
+            ui.small(stats.to_string());
+        }
     });
-    changed |= render_deploy_config(&mut config.deploy_config, platform, ui);
+    changed |= render_named_dumps(config, platform, ui);
+    changed |= render_deploy_config(&mut config.deploy_config, &config.profile, platform, ui);
     changed
 }
 
@@ -497,9 +1062,11 @@ impl App {
                 })
             });
             ui.add_space(8.0);
+            self.render_dump_warning(ui);
             ui.vertical(|ui| {
                 let settings = &mut self.temp_settings;
                 let mut theme_change: Option<Theme> = None;
+                let mut scale_change: Option<f32> = None;
                 egui::CollapsingHeader::new("General")
                     .default_open(true)
                     .show(ui, |ui| {
@@ -550,6 +1117,35 @@ impl App {
                                     }
                                 });
                         });
+                        render_setting(
+                            "UI Scale",
+                            "Scale the user interface up or down, on top of the scale set for \
+                             your monitor by your system.",
+                            ui,
+                            |ui| {
+                                // Only apply the new scale once dragging stops, so the slider
+                                // does not move out from under the cursor
+                                let id = egui::Id::new("ui-scale");
+                                let mut percent = ui
+                                    .data(|d| d.get_temp::<f32>(id))
+                                    .unwrap_or(self.ui_scale * 100.0);
+                                let res = ui.add(
+                                    egui::Slider::new(
+                                        &mut percent,
+                                        uk_ui::scale::MIN_SCALE * 100.0
+                                            ..=uk_ui::scale::MAX_SCALE * 100.0,
+                                    )
+                                    .step_by(5.0)
+                                    .suffix("%"),
+                                );
+                                if res.dragged() {
+                                    ui.data_mut(|d| d.insert_temp(id, percent));
+                                } else if res.drag_stopped() || res.changed() {
+                                    ui.data_mut(|d| d.remove::<f32>(id));
+                                    scale_change = Some(percent / 100.0);
+                                }
+                            },
+                        );
                         render_setting(
                             "Current Mode",
                             "Select whether to manage the Wii U or Switch version of the game",
@@ -569,6 +1165,7 @@ impl App {
                             ui,
                             |ui| {
                                 ui.folder_picker(&mut settings.storage_dir);
+                                reveal::reveal_button(ui, &settings.storage_dir);
                             },
                         );
                         render_setting(
@@ -586,7 +1183,133 @@ impl App {
                             ui,
                             |ui| ui.add(Checkbox::new(&mut settings.show_changelog, "")),
                         );
+                        #[cfg(feature = "tray")]
+                        render_setting(
+                            "Minimize to Tray",
+                            "Keep UKMM running in the system tray when its window is closed, to \
+                             switch profiles, apply changes or launch the emulator from the tray \
+                             menu. Has no effect on desktops without a system tray.",
+                            ui,
+                            |ui| ui.add(Checkbox::new(&mut settings.minimize_to_tray, "")),
+                        );
+                        render_setting(
+                            "Sorting Locale",
+                            "The language whose alphabetical order is used to sort mods by name \
+                             or category, as a code such as en, ja or sv. Leave empty to use the \
+                             default Unicode order.",
+                            ui,
+                            |ui| {
+                                let mut locale = settings.sort_locale.to_string();
+                                if ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut locale)
+                                            .hint_text("Default")
+                                            .desired_width(80.0),
+                                    )
+                                    .changed()
+                                {
+                                    settings.sort_locale = locale.into();
+                                }
+                            },
+                        );
+                        render_setting(
+                            "Deployment History",
+                            "How many past applies to remember, so the files changed between them \
+                             can be compared. Set to 0 to turn the history off.",
+                            ui,
+                            |ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut settings.history_len)
+                                        .range(0..=100)
+                                        .suffix(" applies"),
+                                )
+                            },
+                        );
+                        render_setting(
+                            "Unpack Mods",
+                            "Store installed mods unpacked, keeping each of their files only once \
+                             however many mods have it. This saves space when several mods share \
+                             large files, as texture packs often do. Mods installed before are \
+                             converted with the button.",
+                            ui,
+                            |ui| {
+                                ui.checkbox(&mut settings.unpack_mods, "");
+                                if ui
+                                    .add_enabled(
+                                        settings.unpack_mods,
+                                        egui::Button::new("Convert Installed Mods"),
+                                    )
+                                    .on_hover_text(
+                                        "Store the mods already installed unpacked, and report \
+                                         the space saved",
+                                    )
+                                    .clicked()
+                                {
+                                    self.channel
+                                        .0
+                                        .clone()
+                                        .send(Message::DeduplicateMods)
+                                        .expect("Broken channel");
+                                }
+                            },
+                        );
+                    });
+                egui::CollapsingHeader::new("Serve Merged Output").show(ui, |ui| {
+                    let serve = &mut settings.serve;
+                    render_setting(
+                        "Serve After Apply",
+                        "Start serving the merged files over HTTP after each apply, so a console \
+                         or another computer on your network can download them. The server can \
+                         also be started by hand from the Deploy tab.",
+                        ui,
+                        |ui| ui.checkbox(&mut serve.after_apply, ""),
+                    );
+                    render_setting(
+                        "Bind Address",
+                        "The address to listen on. 0.0.0.0 allows any device on your network to \
+                         connect, while 127.0.0.1 only allows this computer.",
+                        ui,
+                        |ui| {
+                            let mut bind = serve.bind.to_string();
+                            if ui
+                                .add(egui::TextEdit::singleline(&mut bind).desired_width(120.0))
+                                .changed()
+                            {
+                                serve.bind = bind.into();
+                            }
+                        },
+                    );
+                    render_setting("Port", "The port to listen on.", ui, |ui| {
+                        ui.add(egui::DragValue::new(&mut serve.port).range(1024..=65535))
                     });
+                    render_setting(
+                        "Stop When Idle",
+                        "Stop the server after this many minutes without a request. Set to 0 to \
+                         keep it running until stopped by hand.",
+                        ui,
+                        |ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut serve.idle_minutes)
+                                    .range(0..=1440)
+                                    .suffix(" min"),
+                            )
+                        },
+                    );
+                });
+                egui::CollapsingHeader::new("Merge Policy Overrides").show(ui, |ui| {
+                    let path = self.core.settings().merge_policy_path();
+                    let editor = match &mut self.policy_editor {
+                        Some(editor) if editor.path == path => editor,
+                        editor => editor.insert(PolicyEditor::load(path)),
+                    };
+                    if let Err(e) = render_merge_policies(editor, ui) {
+                        self.channel
+                            .0
+                            .clone()
+                            .send(Message::Error(e))
+                            .expect("Broken channel");
+                    }
+                });
                 egui::CollapsingHeader::new("Wii U Config").show(ui, |ui| {
                     if ui
                         .icon_text_button("Import Cemu Settings", icons::Icon::Import)
@@ -605,9 +1328,27 @@ impl App {
                     switch_changed =
                         render_platform_config(&mut settings.switch_config, Platform::Switch, ui);
                 });
+                egui::CollapsingHeader::new("Advanced").show(ui, |ui| {
+                    render_setting(
+                        "Diagnostics Overlay",
+                        "Show frame times, pending messages, running tasks, cache use and memory \
+                         use in a corner of the window, to help report slowdowns. Ctrl+Shift+F12 \
+                         also turns it on and off.",
+                        ui,
+                        |ui| {
+                            let mut enabled = self.metrics.enabled();
+                            if ui.checkbox(&mut enabled, "").changed() {
+                                self.metrics.set_enabled(enabled);
+                            }
+                        },
+                    );
+                });
                 if let Some(theme) = theme_change {
                     self.do_update(Message::SetTheme(theme));
                 }
+                if let Some(scale) = scale_change {
+                    self.do_update(Message::SetScale(scale));
+                }
             });
             switch_changed |= {
                 match (
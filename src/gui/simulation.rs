@@ -0,0 +1,473 @@
+use uk_manager::{
+    mods::Mod,
+    simulation::{Ownership, Preview, Simulation},
+};
+use uk_mod::Manifest;
+use uk_ui::{
+    egui::{self, Align, Align2, Layout, RichText, Ui},
+    icons::{Icon, IconButtonExt},
+};
+
+use super::{info, visuals, App, Message};
+
+/// A simulation in progress. While one is running, the mod list in the app
+/// is the simulated configuration, and nothing is saved or deployed.
+#[derive(Debug)]
+pub struct SimulationState {
+    sim: Simulation,
+    /// Pending changes from before the simulation, put back when it ends.
+    dirty: Manifest,
+    ownership: Option<Result<Ownership, String>>,
+    changes: Option<Result<Manifest, String>>,
+    filter: String,
+    conflicts_only: bool,
+    preview: Option<(String, bool, Preview)>,
+}
+
+impl SimulationState {
+    fn new(sim: Simulation, dirty: Manifest) -> Self {
+        Self {
+            sim,
+            dirty,
+            ownership: None,
+            changes: None,
+            filter: String::new(),
+            conflicts_only: true,
+            preview: None,
+        }
+    }
+
+    /// Drops everything computed from the simulated mods.
+    pub fn invalidate(&mut self) {
+        self.ownership = None;
+        self.changes = None;
+        self.preview = None;
+    }
+}
+
+/// Files to list, as `(file, aoc, owners)`, matching the filter.
+fn rows<'a>(
+    ownership: &'a Ownership,
+    filter: &str,
+    conflicts_only: bool,
+) -> Vec<(&'a str, bool, &'a [usize])> {
+    let filter = filter.to_lowercase();
+    ownership
+        .content
+        .iter()
+        .map(|(f, o)| (f.as_str(), false, o.as_slice()))
+        .chain(
+            ownership
+                .aoc
+                .iter()
+                .map(|(f, o)| (f.as_str(), true, o.as_slice())),
+        )
+        .filter(|(_, _, owners)| !conflicts_only || owners.len() > 1)
+        .filter(|(file, ..)| filter.is_empty() || file.to_lowercase().contains(&filter))
+        .collect()
+}
+
+impl Message {
+    /// Whether handling the message would save or deploy anything, which
+    /// must wait until a simulation ends.
+    pub(super) fn persists(&self) -> bool {
+        matches!(
+            self,
+            Message::AddProfile
+                | Message::ArchiveMods(..)
+                | Message::AddToProfile(_)
+                | Message::Apply
+                | Message::ChangeProfile(_)
+                | Message::DeduplicateMods
+                | Message::DeleteProfile(..)
+                | Message::Deploy
+                | Message::DeployOnly(_)
+                | Message::DevUpdate
+                | Message::DuplicateProfile(_)
+                | Message::EmptyTrash
+                | Message::EndBisect(_)
+                | Message::HydrateMods(_)
+                | Message::ImportCemu
+                | Message::InstallMod(_)
+                | Message::MigrateBcml
+                | Message::ModUpdate
+                | Message::OpenMod(_)
+                | Message::Remerge
+                | Message::RenameProfile(..)
+                | Message::RepairIndex(Some(_))
+                | Message::Redo(_)
+                | Message::ReportBisect(_)
+                | Message::ResetPending
+                | Message::ResolveDrift(Some(_))
+                | Message::RestoreProfile(_)
+                | Message::RestoreSnapshot(_)
+                | Message::RunBatch
+                | Message::SaveSettings
+                | Message::SaveTweaks(_)
+                | Message::SelectFile
+                | Message::SplitMod(..)
+                | Message::SetPriority(_)
+                | Message::StartBisect(_)
+                | Message::UnarchiveMod(_)
+                | Message::Undo(_)
+                | Message::UninstallMods(_)
+        )
+    }
+}
+
+impl App {
+    pub(super) fn simulating(&self) -> bool {
+        self.simulation.borrow().is_some()
+    }
+
+    pub(super) fn invalidate_simulation(&self) {
+        if let Some(state) = self.simulation.borrow_mut().as_mut() {
+            state.invalidate();
+        }
+    }
+
+    pub(super) fn start_simulation(&mut self) {
+        if self.simulating() {
+            return;
+        }
+        let sim = Simulation::new(self.mods.clone(), self.core.mod_manager().priority());
+        let dirty = self.dirty().clone();
+        *self.simulation.get_mut() = Some(SimulationState::new(sim, dirty));
+    }
+
+    /// Ends the simulation. If `keep` is set, the simulated mods stay as they
+    /// are and become pending changes, otherwise they are put back.
+    pub(super) fn end_simulation(&mut self, keep: bool) {
+        let Some(state) = self.simulation.get_mut().take() else {
+            return;
+        };
+        let mut dirty = state.dirty.clone();
+        if keep {
+            match state.sim.changes(&self.mods) {
+                Ok(changes) => dirty.extend(&changes),
+                Err(e) => {
+                    *self.simulation.get_mut() = Some(state);
+                    self.do_update(Message::Error(e));
+                    return;
+                }
+            }
+        } else {
+            self.mods = state.sim.base().to_vec();
+            self.selected = self
+                .selected
+                .iter()
+                .filter_map(|s| self.mods.iter().find(|m| *m == s).cloned())
+                .collect();
+        }
+        *self.dirty_mut() = dirty;
+        self.do_update(Message::RefreshModsDisplay);
+    }
+
+    /// Changes a mod's options in the simulated mods only.
+    pub(super) fn simulate_options(&mut self, mod_: Mod) {
+        for old in self
+            .mods
+            .iter_mut()
+            .chain(self.selected.iter_mut())
+            .filter(|m| m.hash() == mod_.hash())
+        {
+            *old = mod_.clone();
+        }
+        self.invalidate_simulation();
+        self.do_update(Message::RefreshModsDisplay);
+    }
+
+    pub(super) fn preview_file(&self, file: String, aoc: bool) {
+        let Some(sim) = self.simulation.borrow().as_ref().map(|s| s.sim.clone()) else {
+            return;
+        };
+        let mods = self.mods.clone();
+        self.do_task(move |core| {
+            let preview = sim.preview(&core.settings(), &mods, &file, aoc)?;
+            Ok(Message::ShowPreview(file, aoc, preview))
+        });
+    }
+
+    pub(super) fn show_preview(&mut self, file: String, aoc: bool, preview: Preview) {
+        self.busy.set(false);
+        if let Some(state) = self.simulation.get_mut() {
+            state.preview = Some((file, aoc, preview));
+        }
+    }
+
+    /// Asks where to save a report of what each simulated mod changing a file
+    /// does to it, then writes one.
+    pub(super) fn export_diff(&self, file: String, aoc: bool) {
+        let owners = match self.simulation.borrow().as_ref() {
+            Some(SimulationState {
+                ownership: Some(Ok(ownership)),
+                ..
+            }) => ownership.owners(&file, aoc).to_vec(),
+            _ => return,
+        };
+        let name = file.rsplit('/').next().unwrap_or(&file);
+        let Some(dest) = rfd::FileDialog::new()
+            .add_filter("ZIP", &["zip"])
+            .set_title("Export Diff Report")
+            .set_file_name(format!("{name}.diff.zip"))
+            .save_file()
+        else {
+            return;
+        };
+        let mods: Vec<Mod> = owners
+            .iter()
+            .filter_map(|hash| self.mods.iter().find(|m| m.hash() == *hash).cloned())
+            .collect();
+        self.do_task(move |core| {
+            uk_manager::diffs::export(&core, &mods, &file, aoc, &dest)?;
+            Ok(Message::Exported(dest))
+        });
+    }
+
+    fn mod_name(&self, hash: usize) -> String {
+        self.mods
+            .iter()
+            .find(|m| m.hash() == hash)
+            .map(|m| m.meta.name.to_string())
+            .unwrap_or_else(|| "Unknown mod".into())
+    }
+
+    /// The window shown instead of pending changes during a simulation.
+    pub fn render_simulation_bar(&self, ui: &mut Ui) {
+        let mut state = self.simulation.borrow_mut();
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+        if state.changes.is_none() {
+            state.changes = Some(state.sim.changes(&self.mods).map_err(|e| format!("{e:?}")));
+        }
+        egui::Window::new("Simulation")
+            .anchor(Align2::RIGHT_BOTTOM, [-32.0, -32.0])
+            .collapsible(true)
+            .show(ui.ctx(), |ui| {
+                ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                    ui.label(
+                        RichText::new("Changes to the mod list are not being saved")
+                            .color(visuals::YELLOW),
+                    );
+                    egui::ScrollArea::new([false, true])
+                        .id_source("simulated_files")
+                        .auto_shrink([true, true])
+                        .max_height(200.)
+                        .show(ui, |ui| {
+                            match state.changes.as_ref() {
+                                Some(Ok(changes)) if changes.is_empty() => {
+                                    ui.label("No files would change");
+                                }
+                                Some(Ok(changes)) => {
+                                    egui::CollapsingHeader::new("Files That Would Change")
+                                        .show(ui, |ui| info::render_manifest(changes, ui));
+                                }
+                                Some(Err(e)) => {
+                                    ui.label(RichText::new(e).color(visuals::RED));
+                                }
+                                None => (),
+                            }
+                        });
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            if ui
+                                .icon_text_button("Keep as Pending", Icon::Check)
+                                .on_hover_text(
+                                    "End the simulation and keep the simulated mods as changes to \
+                                     apply",
+                                )
+                                .clicked()
+                            {
+                                self.do_update(Message::EndSimulation(true));
+                            }
+                            if ui
+                                .icon_text_button("Discard", Icon::Cancel)
+                                .on_hover_text("End the simulation and put the mods back")
+                                .clicked()
+                            {
+                                self.do_update(Message::EndSimulation(false));
+                            }
+                        });
+                    });
+                });
+            });
+    }
+
+    pub fn render_simulation(&self, ui: &mut Ui) {
+        let mut state = self.simulation.borrow_mut();
+        let Some(state) = state.as_mut() else {
+            ui.vertical_centered(|ui| {
+                ui.add_space(16.0);
+                ui.label(
+                    "Try enabling, disabling, reordering or changing the options of mods without \
+                     saving anything, and see which mod would win each file.",
+                );
+                ui.add_space(8.0);
+                if ui.button("Start Simulation").clicked() {
+                    self.do_update(Message::StartSimulation);
+                }
+            });
+            return;
+        };
+        if state.ownership.is_none() {
+            state.ownership = Some(
+                state
+                    .sim
+                    .ownership(&self.mods)
+                    .map_err(|e| format!("{e:?}")),
+            );
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut state.filter)
+                    .hint_text("Filter files…")
+                    .desired_width(200.0),
+            );
+            ui.checkbox(&mut state.conflicts_only, "Only conflicts")
+                .on_hover_text("Only list files changed by more than one mod");
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui
+                    .icon_button(Icon::Refresh)
+                    .on_hover_text("Recalculate")
+                    .clicked()
+                {
+                    state.invalidate();
+                }
+            });
+        });
+        let ownership = match state.ownership.as_ref() {
+            Some(Ok(ownership)) => ownership,
+            Some(Err(e)) => {
+                ui.label(RichText::new(e).color(visuals::RED));
+                return;
+            }
+            None => return,
+        };
+        let rows = rows(ownership, &state.filter, state.conflicts_only);
+        let mut preview = None;
+        let mut export = None;
+        egui::ScrollArea::vertical()
+            .id_source("simulation-files")
+            .max_height(ui.available_height() * 0.6)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                if rows.is_empty() {
+                    ui.label("No matching files");
+                    return;
+                }
+                egui::Grid::new("simulation-grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("File");
+                        ui.label("Winner")
+                            .on_hover_text("The mod whose version takes precedence");
+                        ui.label("Also changed by");
+                        ui.label("");
+                        ui.end_row();
+                        for (file, aoc, owners) in rows {
+                            ui.label(if aoc {
+                                format!("{file} (DLC)")
+                            } else {
+                                file.to_string()
+                            })
+                            .context_menu(|ui| {
+                                if ui
+                                    .add_enabled(
+                                        !self.busy.get(),
+                                        egui::Button::new("Export Diff Report…"),
+                                    )
+                                    .on_hover_text(
+                                        "Save what each mod changes in this file, to send to a \
+                                         mod author",
+                                    )
+                                    .clicked()
+                                {
+                                    export = Some((file.to_string(), aoc));
+                                    ui.close_menu();
+                                }
+                            });
+                            let (winner, others) = owners.split_last().unwrap_or((&0, &[]));
+                            ui.label(self.mod_name(*winner));
+                            ui.label(
+                                others
+                                    .iter()
+                                    .map(|h| self.mod_name(*h))
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                            );
+                            if ui
+                                .add_enabled(!self.busy.get(), egui::Button::new("Preview"))
+                                .on_hover_text("Merge this file with the simulated mods")
+                                .clicked()
+                            {
+                                preview = Some((file.to_string(), aoc));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        if let Some((file, aoc)) = preview {
+            self.do_update(Message::PreviewFile(file, aoc));
+        }
+        if let Some((file, aoc)) = export {
+            self.do_update(Message::ExportDiff(file, aoc));
+        }
+        ui.separator();
+        match state.preview.as_ref() {
+            Some((file, aoc, preview)) => {
+                ui.label(RichText::new(file).family(egui::FontFamily::Name("Bold".into())));
+                ui.label(format!(
+                    "{} bytes merged from {} mods",
+                    preview.data.len(),
+                    ownership.owners(file, *aoc).len()
+                ));
+                ui.label(match preview.changed {
+                    Some(true) => {
+                        RichText::new("Differs from the current merged file").color(visuals::YELLOW)
+                    }
+                    Some(false) => RichText::new("Same as the current merged file"),
+                    None => RichText::new("Not in the current merge"),
+                });
+            }
+            None => {
+                ui.label("Preview a file to see what it would merge to");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filtered_rows() {
+        let mut ownership = Ownership::default();
+        ownership
+            .content
+            .insert("Actor/Pack/Test.sbactorpack".into(), vec![1]);
+        ownership
+            .content
+            .insert("Pack/Bootup.pack".into(), vec![1, 2]);
+        ownership
+            .aoc
+            .insert("Pack/AocMainField.pack".into(), vec![2, 3]);
+        assert_eq!(rows(&ownership, "", true).len(), 2);
+        assert_eq!(rows(&ownership, "", false).len(), 3);
+        let rows = rows(&ownership, "aocmain", true);
+        assert_eq!(rows, [("Pack/AocMainField.pack", true, [2, 3].as_slice())]);
+    }
+
+    #[test]
+    fn blocked_messages() {
+        assert!(Message::Apply.persists());
+        assert!(Message::Deploy.persists());
+        assert!(Message::ReportBisect(true).persists());
+        assert!(!Message::ToggleMods(None, true).persists());
+        assert!(!Message::MoveSelected(0).persists());
+        assert!(!Message::EndSimulation(true).persists());
+    }
+}
@@ -0,0 +1,91 @@
+use uk_manager::{
+    mods::Mod,
+    preflight::{self, human_size, Room, SpaceWarning},
+};
+use uk_ui::egui::{self, Align, Layout, RichText, Ui};
+
+use super::{visuals, App};
+
+/// How often the free space shown in the deploy tab is checked, in seconds.
+const REFRESH: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FreeSpace {
+    checked: Option<f64>,
+    storage: Option<u64>,
+    deploy:  Option<u64>,
+}
+
+impl App {
+    /// Builds a confirmation prompt from free space warnings, if there are
+    /// any, mentioning the trash when emptying it would help.
+    fn space_prompt(&self, action: &str, warnings: Vec<SpaceWarning>) -> Option<String> {
+        if warnings.is_empty() {
+            return None;
+        }
+        let storage = self.core.settings().storage_dir.clone();
+        let trash = if warnings.iter().any(|w| w.path.starts_with(&storage)) {
+            self.core.mod_manager().trash_size()
+        } else {
+            0
+        };
+        let mut prompt = warnings
+            .iter()
+            .map(|w| w.message(action, trash))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        prompt.push_str("\n\nContinue anyway?");
+        Some(prompt)
+    }
+
+    /// Checks there is room in storage for a mod before installing it.
+    pub(super) fn install_prompt(&self, mod_: &Mod) -> Option<String> {
+        let required = preflight::estimate_install_size(&mod_.path, false);
+        let warning = preflight::warn_space(&self.core.settings().mods_dir(), required);
+        self.space_prompt(
+            &format!("Installing {}", mod_.meta.name),
+            warning.into_iter().collect(),
+        )
+    }
+
+    /// Checks there is room to merge and deploy the pending changes.
+    pub(super) fn apply_prompt(&self) -> Option<String> {
+        let warnings = preflight::apply_warnings(&self.core.settings(), &self.dirty());
+        self.space_prompt("Applying your changes", warnings)
+    }
+
+    /// Shows the free space on the storage and deploy volumes.
+    pub(super) fn render_free_space(&self, ui: &mut Ui, output: &std::path::Path) {
+        let id = egui::Id::new("free_space");
+        let now = ui.input(|i| i.time);
+        let mut space: FreeSpace = ui.data(|d| d.get_temp(id)).unwrap_or_default();
+        if !matches!(space.checked, Some(t) if now - t <= REFRESH) {
+            space = FreeSpace {
+                checked: Some(now),
+                storage: preflight::free_space(&self.core.settings().storage_dir),
+                deploy:  preflight::free_space(output),
+            };
+            ui.data_mut(|d| d.insert_temp(id, space));
+        }
+        let text = |free: Option<u64>| {
+            match free {
+                Some(free) if preflight::room(0, free) == Room::Plenty => {
+                    RichText::new(human_size(free))
+                }
+                Some(free) => RichText::new(human_size(free)).color(visuals::YELLOW),
+                None => RichText::new("Unknown"),
+            }
+        };
+        for (label, free) in [
+            ("Storage Free", space.storage),
+            ("Deploy Free", space.deploy),
+        ] {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(label).family(egui::FontFamily::Name("Bold".into())));
+                ui.with_layout(Layout::right_to_left(Align::Max), |ui| {
+                    ui.label(text(free));
+                })
+            });
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use uk_manager::mods::Mod;
+use uk_mod::split::{SplitFile, SplitPart};
+use uk_ui::egui::{self, Align, Align2, Layout, RichText, TextStyle};
+
+use super::{visuals, App, Message};
+
+/// Editor for splitting a mod into parts which can be toggled on their own.
+#[derive(Debug, Default)]
+pub struct SplitState {
+    mod_:  Option<Mod>,
+    names: Vec<String>,
+    /// The part each file goes to, grouped by area.
+    files: BTreeMap<String, Vec<(SplitFile, usize)>>,
+}
+
+impl SplitState {
+    /// Starts with a part for each area of the game the mod changes.
+    pub fn open(&mut self, mod_: Mod, files: BTreeSet<SplitFile>) {
+        self.names.clear();
+        self.files.clear();
+        for file in files {
+            let area = file.area().to_string();
+            let part = match self.names.iter().position(|name| *name == area) {
+                Some(part) => part,
+                None => {
+                    self.names.push(area.clone());
+                    self.names.len() - 1
+                }
+            };
+            self.files.entry(area).or_default().push((file, part));
+        }
+        self.mod_ = Some(mod_);
+    }
+
+    pub fn close(&mut self) {
+        self.mod_ = None;
+    }
+
+    fn counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.names.len()];
+        for (_, part) in self.files.values().flatten() {
+            counts[*part] += 1;
+        }
+        counts
+    }
+
+    fn parts(&self) -> Vec<SplitPart> {
+        let mut parts: Vec<_> = self
+            .names
+            .iter()
+            .map(|name| {
+                SplitPart {
+                    name:  name.trim().into(),
+                    files: Default::default(),
+                }
+            })
+            .collect();
+        for (file, part) in self.files.values().flatten() {
+            parts[*part].files.insert(file.clone());
+        }
+        parts
+    }
+
+    /// Removes a part, moving its files to the first remaining one.
+    fn remove_part(&mut self, index: usize) {
+        self.names.remove(index);
+        for (_, part) in self.files.values_mut().flatten() {
+            if *part == index {
+                *part = 0;
+            } else if *part > index {
+                *part -= 1;
+            }
+        }
+    }
+
+    fn part_picker(
+        names: &[String],
+        id: impl std::hash::Hash,
+        part: &mut usize,
+        ui: &mut egui::Ui,
+    ) {
+        egui::ComboBox::from_id_source(id)
+            .selected_text(names[*part].as_str())
+            .show_ui(ui, |ui| {
+                for (i, name) in names.iter().enumerate() {
+                    ui.selectable_value(part, i, name.as_str());
+                }
+            });
+    }
+
+    fn render_parts(&mut self, ui: &mut egui::Ui) {
+        let counts = self.counts();
+        let mut remove = None;
+        for (i, name) in self.names.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(name).desired_width(200.0));
+                ui.label(format!("{} files", counts[i]));
+                if ui
+                    .add_enabled(counts.len() > 2, egui::Button::new("Remove"))
+                    .on_hover_text("Move this part's files to the first part")
+                    .clicked()
+                {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.remove_part(i);
+        }
+        if ui.button("Add Part").clicked() {
+            self.names.push(format!("Part {}", self.names.len() + 1));
+        }
+    }
+
+    fn render_files(&mut self, ui: &mut egui::Ui) {
+        let names = &self.names;
+        egui::ScrollArea::vertical()
+            .id_source("split_files")
+            .max_height(360.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for (area, files) in self.files.iter_mut() {
+                    egui::CollapsingHeader::new(format!("{area} ({})", files.len()))
+                        .id_source(("split_area", area.as_str()))
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Move all to");
+                                let mut all = files[0].1;
+                                Self::part_picker(
+                                    names,
+                                    ("split_all", area.as_str()),
+                                    &mut all,
+                                    ui,
+                                );
+                                if all != files[0].1 {
+                                    files.iter_mut().for_each(|(_, part)| *part = all);
+                                }
+                            });
+                            for (file, part) in files.iter_mut() {
+                                ui.horizontal(|ui| {
+                                    Self::part_picker(names, &file.path, part, ui);
+                                    let label = if file.aoc {
+                                        format!("{} (DLC)", file.path)
+                                    } else {
+                                        file.path.clone()
+                                    };
+                                    ui.label(RichText::new(label).text_style(TextStyle::Small));
+                                });
+                            }
+                        });
+                }
+            });
+    }
+
+    pub fn render(&mut self, app: &App, ctx: &egui::Context) {
+        let Some(mod_) = self.mod_.clone() else {
+            return;
+        };
+        egui::Window::new("Split Mod")
+            .collapsible(false)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(true)
+            .default_size([560.0, 480.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Split {} into separate mods, which take its place in the load order and can \
+                     be enabled on their own. Files start out grouped by the area of the game \
+                     they change.",
+                    mod_.meta.name
+                ));
+                ui.add_space(4.0);
+                self.render_parts(ui);
+                ui.add_space(4.0);
+                self.render_files(ui);
+                ui.add_space(4.0);
+                let counts = self.counts();
+                let names: BTreeSet<_> = self.names.iter().map(|name| name.trim()).collect();
+                let problem = if self.names.len() < 2 {
+                    Some("Add at least two parts")
+                } else if counts.contains(&0) {
+                    Some("Every part needs at least one file")
+                } else if names.contains("") || names.len() < self.names.len() {
+                    Some("Every part needs a different name")
+                } else {
+                    None
+                };
+                if let Some(problem) = problem {
+                    ui.label(RichText::new(problem).color(visuals::YELLOW));
+                }
+                let mut close = false;
+                ui.horizontal(|ui| {
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui
+                            .add_enabled(
+                                problem.is_none() && !app.busy.get(),
+                                egui::Button::new("Split"),
+                            )
+                            .clicked()
+                        {
+                            app.do_update(Message::SplitMod(mod_.clone(), self.parts()));
+                            close = true;
+                        }
+                        close |= ui.button("Cancel").clicked();
+                    });
+                });
+                if close {
+                    self.close();
+                }
+            });
+    }
+}
@@ -4,7 +4,7 @@ use uk_ui::{
     visuals::Theme,
 };
 
-use super::{info, visuals, Component, Tabs};
+use super::{info, tour::Anchor, visuals, Component, Tabs};
 
 pub fn default_ui() -> DockState<Tabs> {
     let mut state = DockState::new(vec![Tabs::Mods, Tabs::Package, Tabs::Settings]);
@@ -18,7 +18,7 @@ pub fn default_ui() -> DockState<Tabs> {
         (0.into(), side),
         uk_ui::egui_dock::Split::Below,
         0.6,
-        Node::leaf(Tabs::Deploy),
+        Node::leaf_with(vec![Tabs::Deploy, Tabs::History]),
     );
     let [main, _log] = state.split(
         (0.into(), main),
@@ -47,10 +47,20 @@ impl TabViewer for super::App {
             match tab {
                 Tabs::Info => {
                     if let Some(mod_) = self.selected.first() {
-                        if let Some(info::Message::RequestOptions) =
-                            info::ModInfo(mod_).show(ui).inner
-                        {
-                            self.do_update(super::Message::RequestOptions(mod_.clone(), true));
+                        match info::ModInfo(mod_).show(ui).inner {
+                            Some(info::Message::RequestOptions) => {
+                                self.do_update(super::Message::RequestOptions(mod_.clone(), true));
+                            }
+                            Some(info::Message::ShowLabels(show)) => {
+                                self.do_update(super::Message::ShowLabels(show));
+                            }
+                            Some(info::Message::Summarize) => {
+                                self.do_update(super::Message::SummarizeMod(mod_.clone()));
+                            }
+                            Some(info::Message::ExportSummary(markdown)) => {
+                                self.do_update(super::Message::ExportSummary(markdown));
+                            }
+                            None => (),
                         }
                     } else {
                         ui.centered_and_justified(|ui| {
@@ -64,10 +74,19 @@ impl TabViewer for super::App {
                 Tabs::Deploy => {
                     self.render_deploy_tab(ui);
                 }
+                Tabs::History => {
+                    self.render_history(ui);
+                }
+                Tabs::Activity => {
+                    self.render_activity(ui);
+                }
+                Tabs::Simulation => {
+                    self.render_simulation(ui);
+                }
                 Tabs::Mods => {
                     self.render_profile_menu(ui);
                     ui.add_space(4.);
-                    egui::Frame::none()
+                    let list = egui::Frame::none()
                         .inner_margin(0.0)
                         .outer_margin(0.0)
                         .show(ui, |ui| {
@@ -75,9 +94,13 @@ impl TabViewer for super::App {
                                 visuals::slate_grid(ui);
                             }
                             self.render_modlist(ui);
+                            self.render_archived(ui);
                             ui.allocate_space(ui.available_size());
                             self.render_pending(ui);
                         });
+                    self.tour
+                        .anchors
+                        .set(Anchor::ModList, list.response.rect, ui.ctx().frame_nr());
                 }
                 Tabs::Log => {
                     egui::Frame::none()
@@ -85,11 +108,14 @@ impl TabViewer for super::App {
                         .inner_margin(-2.0)
                         .outer_margin(0.0)
                         .show(ui, |ui| {
-                            egui_logger::logger_ui().show(ui);
+                            self.render_log(ui);
                         });
                     ui.shrink_height_to_current();
                 }
                 Tabs::Settings => {
+                    self.tour
+                        .anchors
+                        .set(Anchor::Settings, ui.max_rect(), ui.ctx().frame_nr());
                     self.render_settings(ui);
                 }
                 Tabs::Package => {
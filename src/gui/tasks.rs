@@ -3,18 +3,32 @@ use std::{
     fmt::Write,
     io::BufReader,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use anyhow::{Context, Result};
 use fs_err as fs;
 use join_str::jstr;
 use serde::Deserialize;
-use uk_content::constants::Language;
+use uk_content::{
+    actor::{
+        deps::{self, Dependency},
+        info::ActorInfo,
+    },
+    constants::Language,
+    platform_content,
+    prelude::Resource,
+};
 use uk_manager::{
+    batch::Batch,
+    bisect::Session,
     bnp::convert_bnp,
     core::Manager,
-    mods::Mod,
+    drift::Resolution,
+    mods::{validate::Issue, Mod},
     settings::{DeployConfig, Platform, PlatformSettings, UpdatePreference},
     util::get_temp_file,
 };
@@ -26,14 +40,19 @@ use uk_mod::{
 use uk_reader::ResourceReader;
 use uk_util::{OptionExt, PathExt};
 
-use super::{package::ModPackerBuilder, util::response, Message};
+use super::{
+    compat,
+    package::{EndianCheck, ModPackerBuilder},
+    util::response,
+    Message,
+};
 use crate::INTERFACE;
 
 mod handlers;
 
 pub use handlers::register_handlers;
 
-fn is_probably_a_mod_and_has_meta(path: &Path) -> (bool, bool) {
+pub(super) fn is_probably_a_mod_and_has_meta(path: &Path) -> (bool, bool) {
     let ext = path
         .extension()
         .and_then(|e| e.to_str().map(|e| e.to_lowercase()))
@@ -88,9 +107,23 @@ pub fn open_mod(core: &Manager, path: &Path, meta: Option<Meta>) -> Result<Messa
         .unwrap_or(false)
     {
         let mod_ = convert_bnp(core, path).context("Failed to convert BNP to UKMM mod")?;
-        return Ok(Message::HandleMod(Mod::from_reader(
-            ModReader::open_peek(mod_, vec![]).context("Failed to open converted mod")?,
-        )));
+        return check_duplicate(
+            core,
+            Mod::from_reader(
+                ModReader::open_peek(mod_, vec![]).context("Failed to open converted mod")?,
+            ),
+        );
+    }
+    if uk_manager::texts::is_text_folder(path) {
+        log::info!("Folder only has game texts, converting it to a text mod");
+        let mod_ = uk_manager::texts::convert_texts(core, path, meta)
+            .context("Failed to convert texts to UKMM mod")?;
+        return check_duplicate(
+            core,
+            Mod::from_reader(
+                ModReader::open_peek(mod_, vec![]).context("Failed to open converted mod")?,
+            ),
+        );
     }
     let mod_ = match ModReader::open_peek(path, vec![]) {
         Ok(reader) => Mod::from_reader(reader),
@@ -130,7 +163,18 @@ pub fn open_mod(core: &Manager, path: &Path, meta: Option<Meta>) -> Result<Messa
             }
         }
     };
-    Ok(Message::HandleMod(mod_))
+    check_duplicate(core, mod_)
+}
+
+fn check_duplicate(core: &Manager, mod_: Mod) -> Result<Message> {
+    match core.mod_manager().find_duplicate(&mod_.path) {
+        Ok(Some(duplicate)) => Ok(Message::ResolveDuplicate(mod_, Box::new(duplicate))),
+        Ok(None) => Ok(Message::HandleMod(mod_)),
+        Err(e) => {
+            log::warn!("Could not check for duplicate mods: {e:?}");
+            Ok(Message::HandleMod(mod_))
+        }
+    }
 }
 
 pub fn apply_changes(core: &Manager, mods: Vec<Mod>, dirty: Option<Manifest>) -> Result<Message> {
@@ -156,6 +200,9 @@ pub fn apply_changes(core: &Manager, mods: Vec<Mod>, dirty: Option<Manifest>) ->
                         })?;
                     mod_manager
                         .set_enabled_options(m.hash(), m.enabled_options.clone())
+                        .and_then(|_| {
+                            mod_manager.set_inactive_options(m.hash(), m.inactive_options.clone())
+                        })
                         .with_context(|| {
                             format!("Failed to update options on {}", m.meta.name.as_str())
                         })?;
@@ -170,11 +217,207 @@ pub fn apply_changes(core: &Manager, mods: Vec<Mod>, dirty: Option<Manifest>) ->
             .save()
             .context("Failed to save mod configuration for current profile")?;
     }
+    // Checked before applying, as hard linked files change with their merged
+    // versions
+    let drift = core.deploy_manager().check_drift().unwrap_or_else(|e| {
+        log::warn!("Failed to check deployed files for changes: {e:?}");
+        vec![]
+    });
     log::info!("Applying changes");
-    let deploy_manager = core.deploy_manager();
-    deploy_manager
+    core.deploy_manager()
         .apply(dirty)
         .context("Failed to apply pending mod changes")?;
+    if !drift.is_empty() {
+        log::info!("Deployment held until changed deployed files are resolved");
+        return Ok(Message::ShowDrift(drift, true));
+    }
+    auto_deploy(core)?;
+    log::info!("Done");
+    Ok(Message::Applied)
+}
+
+/// Checks the deployment folder for files changed outside of UKMM.
+pub fn check_drift(core: Arc<Manager>) -> Result<Message> {
+    let drift = core.deploy_manager().check_drift()?;
+    Ok(if drift.is_empty() {
+        Message::Noop
+    } else {
+        Message::ShowDrift(drift, false)
+    })
+}
+
+/// Settles changed deployed files, then deploys if asked.
+pub fn resolve_drift(
+    core: &Manager,
+    resolutions: Vec<(smartstring::alias::String, Resolution)>,
+    deploy: bool,
+) -> Result<Message> {
+    core.deploy_manager()
+        .resolve_drift(&resolutions)
+        .context("Failed to resolve changed deployed files")?;
+    if deploy {
+        auto_deploy(core)?;
+    }
+    Ok(Message::ResetMods(None))
+}
+
+/// Empties the cache of parsed resources from the game dump.
+pub fn clear_dump_cache(core: Arc<Manager>) -> Result<Message> {
+    if let Some(dump) = core.settings().dump() {
+        dump.clear_disk_cache()?;
+    }
+    Ok(Message::DumpCacheCleared)
+}
+
+/// Copies the offline mods among some to storage, returning them all with
+/// their new paths.
+pub fn hydrate_mods(core: &Manager, mods: Vec<Mod>) -> Result<Message> {
+    let mod_manager = core.mod_manager();
+    let mut hydrated = Vec::with_capacity(mods.len());
+    for mod_ in mods {
+        hydrated.push(if mod_manager.availability(&mod_).is_offline() {
+            mod_manager.hydrate(&mod_)?
+        } else {
+            mod_
+        });
+    }
+    Ok(Message::Hydrated(hydrated))
+}
+
+/// Checks the mod index of every profile for problems.
+pub fn validate_library(core: Arc<Manager>) -> Result<Message> {
+    Ok(Message::ShowIndexIssues(
+        core.mod_manager().validate(),
+        true,
+    ))
+}
+
+/// Repairs problems in the mod index. Files from mods removed from the
+/// current profile are left to be merged again.
+pub fn repair_index(core: &Manager, issues: Vec<Issue>) -> Result<Message> {
+    let profile = core
+        .settings()
+        .platform_config()
+        .map(|c| c.profile.clone())
+        .unwrap_or_else(|| "Default".into());
+    let mod_manager = core.mod_manager();
+    let dirty = issues
+        .iter()
+        .filter(|issue| issue.profile == profile)
+        .filter_map(|issue| mod_manager.get_mod(issue.removed()?))
+        .filter_map(|mod_| mod_.manifest().ok())
+        .fold(Manifest::default(), |mut dirty, manifest| {
+            dirty.extend(&manifest);
+            dirty
+        });
+    mod_manager
+        .repair(&issues)
+        .context("Failed to repair the mod library")?;
+    Ok(Message::ResetMods(Some(dirty)))
+}
+
+/// Runs a batch file, carrying on or stopping at failures as it says. Paths
+/// in it are relative to its folder.
+pub fn run_batch(core: &Manager, path: &Path) -> Result<Message> {
+    let batch = Batch::open(path)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let outcome = uk_manager::batch::run(core, &batch, base, false)?;
+    Ok(Message::BatchRan(
+        outcome.pending,
+        outcome.failed.iter().map(|e| format!("{e:?}")).collect(),
+    ))
+}
+
+/// Compares the merged files of the current profile with another merged
+/// folder, such as the master mod of a BCML setup.
+pub fn compare_merged(core: &Manager, theirs: &Path, cancel: Arc<AtomicBool>) -> Result<Message> {
+    let ours = core.settings().merged_dir();
+    match uk_manager::compare::compare(&ours, theirs, &cancel) {
+        Ok(report) => Ok(Message::ShowComparison(report)),
+        Err(_) if cancel.load(Ordering::Relaxed) => Ok(Message::Noop),
+        Err(e) => Err(e).context("Failed to compare merged folders"),
+    }
+}
+
+/// Checks how two mods would get along, each either installed or a mod file
+/// which is not installed yet.
+pub fn check_compat(
+    core: &Manager,
+    first: compat::Subject,
+    second: compat::Subject,
+    deep: bool,
+) -> Result<Message> {
+    let endian = core.settings().current_mode.into();
+    let open = |subject: &compat::Subject| -> Result<(String, ModReader)> {
+        let reader = match subject {
+            compat::Subject::Installed(mod_) => ModReader::open(&mod_.path, mod_.active_options()),
+            compat::Subject::File(path) => ModReader::open_peek(path, vec![]),
+        }
+        .with_context(|| format!("Failed to open mod: {}", subject.name()))?;
+        Ok((subject.name(), reader.with_platform(endian)))
+    };
+    let report = uk_manager::compat::check(&open(&first)?, &open(&second)?, deep)?;
+    Ok(Message::ShowCompat(report))
+}
+
+/// Finds the files an actor depends on. The actor is either the path to its
+/// pack or its name, looked up in the actor info of the mod folder or the
+/// game dump. Loose files are looked for in the mod folder, which for a pack
+/// path defaults to the folder the pack is in if it is laid out like one.
+pub fn actor_deps(core: &Manager, actor: &str, source: Option<&Path>) -> Result<Dependency> {
+    let endian = core.settings().current_mode.into();
+    let dump = core.settings().dump();
+    let mut content = source.map(|source| source.join(platform_content(endian)));
+    let path = Path::new(actor);
+    let data = if path.is_file() {
+        if content.is_none() {
+            content = path
+                .parent()
+                .filter(|parent| parent.ends_with("Actor/Pack"))
+                .and_then(|parent| parent.parent()?.parent())
+                .map(Path::to_path_buf);
+        }
+        fs::read(path)?
+    } else {
+        let read = |file: &str| -> Result<Vec<u8>> {
+            match content
+                .as_ref()
+                .and_then(|content| fs::read(content.join(file)).ok())
+            {
+                Some(data) => Ok(data),
+                None => {
+                    Ok(dump
+                        .as_ref()
+                        .context("No game dump is set up to look up actors in")?
+                        .get_bytes_uncached(file)?)
+                }
+            }
+        };
+        let info = ActorInfo::from_binary(read("Actor/ActorInfo.product.sbyml")?)?;
+        let pack = deps::pack_path(&info, actor)
+            .with_context(|| format!("{actor} is not a file or an actor in the actor info"))?;
+        read(&pack)?
+    };
+    Ok(deps::dependencies(
+        &data,
+        |file| fs::read(content.as_ref()?.join(file)).ok(),
+        |file| dump.as_ref()?.get_bytes_uncached(file).ok(),
+    )?)
+}
+
+/// Finds the files an actor depends on, to show in the dependency viewer.
+pub fn show_actor_deps(core: &Manager, pack: PathBuf) -> Result<Message> {
+    let root = actor_deps(core, &pack.to_string_lossy(), None)?;
+    Ok(Message::ShowActorDeps(root))
+}
+
+/// Deploys the merged mods if the current platform deploys automatically and
+/// its deployment is not locked.
+fn auto_deploy(core: &Manager) -> Result<()> {
+    if core.deploy_manager().locked().is_some() {
+        log::info!("Deployment is locked, not deploying");
+        return Ok(());
+    }
     if core
         .settings()
         .platform_config()
@@ -182,30 +425,175 @@ pub fn apply_changes(core: &Manager, mods: Vec<Mod>, dirty: Option<Manifest>) ->
         .unwrap_or(false)
     {
         log::info!("Deploying changes");
-        deploy_manager
+        core.deploy_manager()
             .deploy()
             .context("Failed to deploy update to merged mod(s)")?;
     }
-    log::info!("Done");
-    Ok(Message::ResetMods(None))
+    Ok(())
+}
+
+/// Copies the merged files to a new snapshot, then lists the snapshots.
+pub fn create_snapshot(core: &Manager, name: &str) -> Result<Message> {
+    uk_manager::lock::create_snapshot(&core.settings(), name)?;
+    Ok(Message::ShowSnapshots)
+}
+
+/// Puts a snapshot's merged files back, deploying them if the platform
+/// deploys automatically.
+pub fn restore_snapshot(core: &Manager, snapshot: &uk_manager::lock::Snapshot) -> Result<Message> {
+    core.deploy_manager()
+        .restore_snapshot(snapshot)
+        .with_context(|| format!("Failed to restore snapshot {}", snapshot.name))?;
+    auto_deploy(core)?;
+    Ok(Message::Noop)
+}
+
+pub fn start_bisect(core: &Manager, pinned: Vec<usize>) -> Result<Message> {
+    let session = Session::new(core, pinned);
+    // Saved first, so the original mods can be put back however far the
+    // first step gets
+    session.save(&core.settings())?;
+    if let Err(e) = session.apply_step(core) {
+        session
+            .finish(core, &[])
+            .context("Failed to restore mods after failed bisect step")?;
+        return Err(e).context("Failed to start bisect");
+    }
+    auto_deploy(core)?;
+    Ok(Message::UpdateBisect(Some(session)))
+}
+
+pub fn report_bisect(core: &Manager, mut session: Session, crashed: bool) -> Result<Message> {
+    session.bisect.report(crashed);
+    session
+        .apply_step(core)
+        .context("Failed to apply next bisect step")?;
+    session.save(&core.settings())?;
+    auto_deploy(core)?;
+    Ok(Message::UpdateBisect(Some(session)))
+}
+
+pub fn end_bisect(core: &Manager, session: Session, disable: Vec<usize>) -> Result<Message> {
+    session
+        .finish(core, &disable)
+        .context("Failed to restore mods after bisect")?;
+    auto_deploy(core)?;
+    Ok(Message::UpdateBisect(None))
 }
 
-pub fn package_mod(core: &Manager, builder: ModPackerBuilder) -> Result<Message> {
+pub fn package_mod(
+    core: &Manager,
+    builder: ModPackerBuilder,
+    cancel: Arc<AtomicBool>,
+) -> Result<Message> {
     let Some(dump) = core.settings().dump() else {
         anyhow::bail!("No dump for current platform")
     };
-    uk_mod::pack::ModPacker::new(
+    let dual = matches!(builder.meta.platform, uk_mod::ModPlatform::Dual);
+    if builder.endian_check != EndianCheck::Convert {
+        let mismatches = [&builder.source]
+            .into_iter()
+            .chain(dual.then_some(&builder.alt_source))
+            .filter_map(|source| {
+                uk_mod::endian::source_endian(source, &builder.meta.platform)
+                    .map(|endian| uk_mod::endian::audit_source(source, endian))
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        if !mismatches.is_empty() {
+            if builder.endian_check == EndianCheck::Ask {
+                return Ok(Message::ShowEndianAudit(builder, mismatches));
+            }
+            for mismatch in &mismatches {
+                log::warn!("{mismatch}");
+            }
+        }
+    }
+    let convert = builder.endian_check == EndianCheck::Convert;
+    let format = builder.meta.format;
+    let mut packer = uk_mod::pack::ModPacker::new(
         builder.source,
         builder.dest,
         Some(builder.meta),
         [dump].into_iter().collect(),
     )
     .context("Failed to initialize mod packager")?
-    .pack()
-    .context("Failed to package mod")?;
+    .with_target_format(format)?
+    .with_endian_conversion(convert)
+    .with_cancel(cancel.clone());
+    if dual {
+        packer = packer
+            .with_platform_source(&builder.alt_source)
+            .context("Failed to add source for other platform")?;
+    }
+    let dest = match packer.pack() {
+        Ok(dest) => dest,
+        Err(_) if cancel.load(Ordering::Relaxed) => return Ok(Message::Noop),
+        Err(e) => return Err(e).context("Failed to package mod"),
+    };
+    // Problems found here do not stop packaging, but authors should see them
+    // before publishing
+    match uk_mod::lint::lint(&dest) {
+        Ok(report) => {
+            for finding in &report.findings {
+                log::warn!("{finding}");
+            }
+        }
+        Err(e) => log::warn!("Could not check packaged mod: {e:?}"),
+    }
     Ok(Message::ResetPacker)
 }
 
+pub fn save_tweaks(core: &Manager, tweaks: uk_manager::tweaks::Tweaks) -> Result<Message> {
+    let mod_ = uk_manager::tweaks::save(core, &tweaks)?;
+    Ok(Message::AddMod(mod_))
+}
+
+pub fn split_files(mod_: Mod) -> Result<Message> {
+    let reader = uk_mod::unpack::ModReader::open_peek(&mod_.path, vec![])?;
+    let files = uk_mod::split::files(&reader)?;
+    Ok(Message::ShowSplit(mod_, files))
+}
+
+pub fn split_mod(
+    core: &Manager,
+    mod_: Mod,
+    parts: Vec<uk_mod::split::SplitPart>,
+) -> Result<Message> {
+    let manifest = mod_.manifest()?;
+    let mods = core.mod_manager();
+    mods.split(&mod_, &parts)?;
+    mods.save()?;
+    Ok(Message::ResetMods(Some(manifest.as_ref().clone())))
+}
+
+pub fn summarize_mod(core: &Manager, mod_: Mod) -> Result<Message> {
+    let summary = uk_manager::summary::summarize_mods(core, vec![mod_.clone()])?;
+    Ok(Message::SetSummary(
+        mod_.hash(),
+        summary.to_markdown(mod_.meta.name.as_str()),
+    ))
+}
+
+pub fn summarize_profile(core: &Manager) -> Result<Message> {
+    let mods = core
+        .mod_manager()
+        .mods()
+        .filter(|m| !m.local_patch)
+        .collect();
+    let mut summary = uk_manager::summary::summarize_mods(core, mods)?;
+    for mod_ in core.mod_manager().archived_mods() {
+        summary.note_archived(mod_.meta.name);
+    }
+    let title = format!("{} Profile", core.mod_manager().profile().key());
+    Ok(Message::ExportSummary(summary.to_markdown(&title)))
+}
+
+pub fn load_labels(core: Arc<Manager>) -> Result<Message> {
+    let labels = uk_manager::labels::load(&core)?;
+    Ok(Message::SetLabels(Some(labels)))
+}
+
 pub fn dev_update_mods(core: &Manager, mods: Vec<Mod>) -> Result<Message> {
     let mut dirty = Manifest::default();
     for mod_ in mods {
@@ -225,10 +613,11 @@ pub fn dev_update_mods(core: &Manager, mods: Vec<Mod>) -> Result<Message> {
             .context("Failed to initialize mod packager")?
             .pack()
             .context("Failed to package mod")?;
-            let new_mod = ModReader::open_peek(mod_.path, vec![])?;
+            let new_mod = ModReader::open_peek(mod_.path.clone(), vec![])?;
             dirty.extend(new_mod.manifest());
-            core.mod_manager_mut()
-                .replace(Mod::from_reader(new_mod), hash)?;
+            let mut new_mod = Mod::from_reader(new_mod);
+            new_mod.carry_options(&mod_);
+            core.mod_manager_mut().replace(new_mod, hash)?;
         } else {
             return Ok(Message::Noop);
         }
@@ -250,7 +639,7 @@ pub fn extract_mods(core: &Manager, mods: Vec<Mod>) -> Result<Message> {
             let name = mod_.meta.name.as_str();
             log::info!("Extracting {}…", name);
             let unpacker = ModUnpacker::new(
-                config.dump.clone(),
+                config.active_dump().clone(),
                 core.settings().current_mode.into(),
                 config.language,
                 vec![ModReader::open(&mod_.path, mod_.enabled_options.clone())?],
@@ -401,6 +790,8 @@ pub fn import_cemu_settings(core: &Manager, path: &Path) -> Result<Message> {
             language: uk_content::constants::Language::USen,
             profile: "Default".into(),
             dump,
+            dumps: Default::default(),
+            profile_dumps: Default::default(),
             deploy_config: Some(DeployConfig {
                 auto: true,
                 method: uk_manager::settings::DeployMethod::Symlink,
@@ -410,6 +801,10 @@ pub fn import_cemu_settings(core: &Manager, path: &Path) -> Result<Message> {
                     .with_file_name("Cemu.exe")
                     .exists_then()
                     .map(|p| p.display().to_string()),
+                hooks: vec![],
+                drift_check: Default::default(),
+                content_output: None,
+                aoc_output: None,
             }),
         })
     };
@@ -458,6 +853,8 @@ pub fn migrate_bcml(core: Arc<Manager>) -> Result<Message> {
             settings.wiiu_config = Some(PlatformSettings {
                 language: bcml_settings.lang,
                 profile: "Default".into(),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
                 deploy_config: bcml_settings
                     .export_dir
                     .map(|export_dir| {
@@ -499,6 +896,8 @@ pub fn migrate_bcml(core: Arc<Manager>) -> Result<Message> {
             settings.switch_config = Some(PlatformSettings {
                 language: bcml_settings.lang,
                 profile: "Default".into(),
+                dumps: Default::default(),
+                profile_dumps: Default::default(),
                 deploy_config: bcml_settings.export_dir_nx.map(|export_dir| {
                     DeployConfig {
                         output: export_dir,
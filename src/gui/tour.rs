@@ -0,0 +1,155 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use uk_ui::{
+    egui::{self, RichText},
+    tour::{self, Anchors, Step, Tour},
+};
+
+use super::App;
+
+/// How long changes can be left pending before Apply is pointed out.
+const APPLY_HINT_AFTER: Duration = Duration::from_secs(60);
+
+/// Widgets the tour and hints point at.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Anchor {
+    Profiles,
+    ModList,
+    Apply,
+    Settings,
+}
+
+static STEPS: &[Step<Anchor>] = &[
+    Step {
+        anchor: Anchor::Profiles,
+        title:  "Profiles",
+        text:   "Each profile has its own list of mods. Switch between them here, or add one with \
+                 the + button.",
+    },
+    Step {
+        anchor: Anchor::ModList,
+        title:  "Mod List",
+        text:   "Tick a mod to turn it on or off, and drag mods to reorder them. Where mods \
+                 change the same file, the Priority column shows which one wins, and Simulate \
+                 Changes in the Tools menu lists every file they share.",
+    },
+    Step {
+        anchor: Anchor::Apply,
+        title:  "Apply",
+        text:   "Changes to the mod list are not merged into the game until you apply them. When \
+                 there are changes pending, Apply is in the bottom corner of the mod list.",
+    },
+    Step {
+        anchor: Anchor::Settings,
+        title:  "Settings",
+        text:   "Your game dump, where mods are deployed, and everything else about how UKMM \
+                 works are set here.",
+    },
+];
+
+/// The guided tour, the widgets it points at, and hints for things new users
+/// miss.
+#[derive(Debug)]
+pub struct TourState {
+    tour: Tour<Anchor>,
+    pub anchors: Anchors<Anchor>,
+    /// Whether the tour has been finished or skipped, so it is not shown
+    /// again by itself.
+    done: bool,
+    /// When the current pending changes were first seen.
+    dirty_since: Cell<Option<Instant>>,
+}
+
+impl TourState {
+    pub fn new(done: bool) -> Self {
+        Self {
+            tour: Tour::new(STEPS),
+            anchors: Anchors::default(),
+            done,
+            dirty_since: Cell::new(None),
+        }
+    }
+
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    pub fn start(&mut self) {
+        self.tour.start();
+    }
+
+    fn finish(&mut self) {
+        self.tour.stop();
+        self.done = true;
+    }
+
+    /// Whether Apply should be pointed out, because changes have been pending
+    /// for a while.
+    pub fn apply_hint(&self, dirty: bool, now: Instant) -> bool {
+        if !dirty {
+            self.dirty_since.set(None);
+            return false;
+        }
+        let since = self.dirty_since.get().unwrap_or(now);
+        self.dirty_since.set(Some(since));
+        now.duration_since(since) >= APPLY_HINT_AFTER
+    }
+}
+
+impl App {
+    /// Draws the tour over everything else, unless a modal is in the way.
+    pub(super) fn render_tour(&mut self, ctx: &egui::Context) {
+        if self.modal_open() {
+            return;
+        }
+        let Some((index, step)) = self.tour.tour.current() else {
+            return;
+        };
+        let target = self.tour.anchors.get(&step.anchor, ctx.frame_nr());
+        let len = self.tour.tour.len();
+        let (mut back, mut next, mut skip) = (false, false, false);
+        tour::callout(ctx, egui::Id::new("tour"), target, |ui| {
+            ui.label(RichText::new(step.title).family(egui::FontFamily::Name("Bold".into())));
+            ui.label(step.text);
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                ui.label(format!("{} of {len}", index + 1));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    next = ui
+                        .button(if index + 1 == len { "Done" } else { "Next" })
+                        .clicked();
+                    if index > 0 {
+                        back = ui.button("Back").clicked();
+                    }
+                    skip = ui.button("Skip Tour").clicked();
+                });
+            });
+        });
+        if skip || (next && self.tour.tour.advance()) {
+            self.tour.finish();
+        } else if back {
+            self.tour.tour.back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_hint() {
+        let state = TourState::new(false);
+        let start = Instant::now();
+        assert!(!state.apply_hint(true, start));
+        assert!(!state.apply_hint(true, start + Duration::from_secs(30)));
+        assert!(state.apply_hint(true, start + APPLY_HINT_AFTER));
+        // Applying or cancelling starts the wait again
+        assert!(!state.apply_hint(false, start + Duration::from_secs(90)));
+        assert!(!state.apply_hint(true, start + Duration::from_secs(100)));
+        assert!(state.apply_hint(true, start + Duration::from_secs(160)));
+    }
+}
@@ -0,0 +1,327 @@
+//! An optional icon in the system tray, so UKMM can stay in the background
+//! with its window closed. Its menu switches profiles, applies pending
+//! changes, and launches the emulator.
+//!
+//! Choosing from the menu sends a message to the app and asks for a repaint,
+//! so it is handled even while the window is hidden. Where the desktop has no
+//! tray, the icon is never shown and closing the window quits as usual.
+//!
+//! On Linux, the tray needs GTK, which runs on a thread of its own, so the
+//! menu is updated through a channel. Elsewhere it lives on the UI thread.
+use anyhow_ext::{Context, Result};
+use flume::Sender;
+use tray_icon::{
+    menu::{CheckMenuItem, IsMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu},
+    Icon, TrayIcon, TrayIconBuilder,
+};
+use uk_ui::egui;
+
+use super::{App, Message};
+
+const PROFILE_PREFIX: &str = "profile/";
+
+/// What the tray menu shows.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TrayState {
+    pub profiles: Vec<String>,
+    pub current:  String,
+    /// Whether there are changes to apply.
+    pub dirty:    bool,
+    /// Whether an emulator is set up to launch.
+    pub emulator: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Open,
+    Apply,
+    Launch,
+    Quit,
+    Profile(String),
+}
+
+impl Action {
+    fn id(&self) -> String {
+        match self {
+            Action::Open => "open".into(),
+            Action::Apply => "apply".into(),
+            Action::Launch => "launch".into(),
+            Action::Quit => "quit".into(),
+            Action::Profile(name) => format!("{PROFILE_PREFIX}{name}"),
+        }
+    }
+
+    fn parse(id: &str) -> Option<Self> {
+        match id {
+            "open" => Some(Action::Open),
+            "apply" => Some(Action::Apply),
+            "launch" => Some(Action::Launch),
+            "quit" => Some(Action::Quit),
+            _ => {
+                id.strip_prefix(PROFILE_PREFIX)
+                    .map(|name| Action::Profile(name.into()))
+            }
+        }
+    }
+
+    fn message(self) -> Message {
+        match self {
+            Action::Open => Message::ShowWindow,
+            Action::Apply => Message::RequestApply,
+            Action::Launch => Message::LaunchEmulator,
+            Action::Quit => Message::Quit,
+            Action::Profile(name) => Message::ChangeProfile(name),
+        }
+    }
+}
+
+fn build_menu(state: &TrayState) -> Result<Menu> {
+    let open = MenuItem::with_id(Action::Open.id(), "Open UKMM", true, None);
+    let apply = MenuItem::with_id(Action::Apply.id(), "Apply Changes", state.dirty, None);
+    let launch = MenuItem::with_id(Action::Launch.id(), "Launch Emulator", state.emulator, None);
+    let profiles: Vec<CheckMenuItem> = state
+        .profiles
+        .iter()
+        .map(|name| {
+            let action = Action::Profile(name.clone());
+            CheckMenuItem::with_id(action.id(), name, true, *name == state.current, None)
+        })
+        .collect();
+    let items: Vec<&dyn IsMenuItem> = profiles.iter().map(|p| p as &dyn IsMenuItem).collect();
+    let profiles = Submenu::with_items("Profile", !items.is_empty(), &items)?;
+    let quit = MenuItem::with_id(Action::Quit.id(), "Quit", true, None);
+    Ok(Menu::with_items(&[
+        &open,
+        &PredefinedMenuItem::separator(),
+        &profiles,
+        &apply,
+        &launch,
+        &PredefinedMenuItem::separator(),
+        &quit,
+    ])?)
+}
+
+fn load_icon() -> Result<Icon> {
+    let image = image::load_from_memory(include_bytes!("../../assets/ukmm.png"))?.to_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(Icon::from_rgba(image.into_vec(), width, height)?)
+}
+
+/// The tray icon itself, which has to stay on the thread which made it.
+struct TrayMenu {
+    icon: TrayIcon,
+}
+
+impl TrayMenu {
+    fn create(state: &TrayState) -> Result<Self> {
+        let icon = TrayIconBuilder::new()
+            .with_icon(load_icon()?)
+            .with_tooltip("U-King Mod Manager")
+            .with_menu(Box::new(build_menu(state)?))
+            .build()
+            .context("Failed to create the tray icon")?;
+        Ok(Self { icon })
+    }
+
+    fn update(&self, state: &TrayState) {
+        match build_menu(state) {
+            Ok(menu) => self.icon.set_menu(Some(Box::new(menu))),
+            Err(e) => log::warn!("Failed to update the tray menu: {e:?}"),
+        }
+    }
+}
+
+pub struct Tray {
+    /// What the menu shows, as last updated.
+    state:   TrayState,
+    #[cfg(target_os = "linux")]
+    updates: Sender<TrayState>,
+    #[cfg(target_os = "linux")]
+    ready:   std::sync::Arc<std::sync::atomic::AtomicBool>,
+    #[cfg(not(target_os = "linux"))]
+    menu:    TrayMenu,
+}
+
+impl Tray {
+    /// Shows the tray icon. Menu choices are sent to the app as messages.
+    pub fn start(ctx: egui::Context, sender: Sender<Message>, state: TrayState) -> Result<Self> {
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            if let Some(action) = Action::parse(&event.id.0) {
+                sender.send(action.message()).unwrap_or(());
+                ctx.request_repaint();
+            }
+        }));
+        Self::create(state)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn create(state: TrayState) -> Result<Self> {
+        Ok(Self {
+            menu: TrayMenu::create(&state)?,
+            state,
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn create(state: TrayState) -> Result<Self> {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        use gtk::glib::{self, ControlFlow};
+
+        let (updates, receiver) = flume::unbounded::<TrayState>();
+        let ready = Arc::new(AtomicBool::new(false));
+        let ready_ = ready.clone();
+        let initial = state.clone();
+        std::thread::Builder::new()
+            .name("tray".into())
+            .spawn(move || {
+                if let Err(e) = gtk::init() {
+                    log::warn!("The system tray is not available: {e}");
+                    return;
+                }
+                // Loading the indicator library panics where it is missing
+                let menu = match std::panic::catch_unwind(|| TrayMenu::create(&initial)) {
+                    Ok(Ok(menu)) => menu,
+                    Ok(Err(e)) => {
+                        log::warn!("The system tray is not available: {e:?}");
+                        return;
+                    }
+                    Err(_) => {
+                        log::warn!("The system tray is not available");
+                        return;
+                    }
+                };
+                ready_.store(true, Ordering::Release);
+                glib::timeout_add_local(std::time::Duration::from_millis(200), move || {
+                    loop {
+                        match receiver.try_recv() {
+                            Ok(state) => menu.update(&state),
+                            Err(flume::TryRecvError::Empty) => return ControlFlow::Continue,
+                            Err(flume::TryRecvError::Disconnected) => {
+                                ready_.store(false, Ordering::Release);
+                                gtk::main_quit();
+                                return ControlFlow::Break;
+                            }
+                        }
+                    }
+                });
+                gtk::main();
+            })
+            .context("Failed to start the tray thread")?;
+        Ok(Self {
+            state,
+            updates,
+            ready,
+        })
+    }
+
+    /// Whether the icon is showing, so hiding the window leaves a way back.
+    pub fn available(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            self.ready.load(std::sync::atomic::Ordering::Acquire)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            true
+        }
+    }
+
+    pub fn update(&mut self, state: TrayState) {
+        if state == self.state {
+            return;
+        }
+        #[cfg(target_os = "linux")]
+        self.updates.send(state.clone()).unwrap_or(());
+        #[cfg(not(target_os = "linux"))]
+        self.menu.update(&state);
+        self.state = state;
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        MenuEvent::set_event_handler(None::<fn(MenuEvent)>);
+    }
+}
+
+impl App {
+    fn tray_state(&self) -> TrayState {
+        let mut profiles: Vec<String> = self
+            .profiles_state
+            .borrow()
+            .profiles
+            .keys()
+            .map(|p| p.to_string())
+            .collect();
+        profiles.sort();
+        TrayState {
+            profiles,
+            current: self.core.mod_manager().profile().key().to_string(),
+            dirty: !self.dirty().is_empty(),
+            emulator: self
+                .core
+                .settings()
+                .platform_config()
+                .and_then(|c| c.deploy_config.as_ref())
+                .is_some_and(|c| c.executable.is_some()),
+        }
+    }
+
+    /// Starts or stops the tray to match the settings, keeps its menu up to
+    /// date, and hides the window instead of closing it while it is shown.
+    pub(super) fn handle_tray(&mut self, ctx: &egui::Context) {
+        let wanted = self.core.settings().minimize_to_tray;
+        if wanted != self.tray_synced {
+            self.tray_synced = wanted;
+            self.tray = None;
+            if wanted {
+                match Tray::start(ctx.clone(), self.channel.0.clone(), self.tray_state()) {
+                    Ok(tray) => self.tray = Some(tray),
+                    Err(e) => log::warn!("The system tray is not available: {e:?}"),
+                }
+            }
+        }
+        if ctx.input(|i| i.viewport().close_requested())
+            && !self.quitting
+            && self.tray.as_ref().is_some_and(|t| t.available())
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+            self.hidden = true;
+        }
+        if self.tray.is_some() {
+            let state = self.tray_state();
+            if let Some(tray) = self.tray.as_mut() {
+                tray.update(state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions() {
+        for action in [
+            Action::Open,
+            Action::Apply,
+            Action::Launch,
+            Action::Quit,
+            Action::Profile("Default".into()),
+            Action::Profile("profile/with slash".into()),
+        ] {
+            assert_eq!(Action::parse(&action.id()), Some(action));
+        }
+        assert_eq!(Action::parse("something else"), None);
+        assert!(matches!(
+            Action::parse("profile/Tears").map(Action::message),
+            Some(Message::ChangeProfile(name)) if name == "Tears"
+        ));
+    }
+}
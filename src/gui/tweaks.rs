@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use smartstring::alias::String as SmartString;
+use uk_manager::tweaks::{self, TweakValue, Tweaks, SINGLETONS};
+use uk_ui::egui::{self, Align, DragValue, Layout, TextStyle};
+
+use super::{App, Message};
+
+type Fields = BTreeMap<SmartString, TweakValue>;
+
+#[derive(Debug, Default)]
+pub struct TweakState {
+    pub show: bool,
+    pub tweaks: Tweaks,
+    selected: usize,
+    search: String,
+    stock: BTreeMap<&'static str, Result<Fields, String>>,
+}
+
+/// The stock values of the fields in `path`, read from the dump the first
+/// time they are shown.
+fn stock_fields<'a>(
+    stock: &'a mut BTreeMap<&'static str, Result<Fields, String>>,
+    app: &App,
+    path: &'static str,
+) -> &'a Result<Fields, String> {
+    stock.entry(path).or_insert_with(|| {
+        app.core
+            .settings()
+            .dump()
+            .ok_or_else(|| "No dump for current platform".to_string())
+            .and_then(|dump| tweaks::stock_fields(&dump, path).map_err(|e| format!("{e:?}")))
+    })
+}
+
+impl TweakState {
+    pub fn open(&mut self, core: &uk_manager::core::Manager) {
+        self.tweaks = Tweaks::load(&tweaks::tweaks_path(core));
+        self.stock.clear();
+        self.show = true;
+    }
+
+    fn render_fields(&mut self, app: &App, ui: &mut egui::Ui, path: &'static str) {
+        let stock = match stock_fields(&mut self.stock, app, path) {
+            Ok(stock) => stock,
+            Err(e) => {
+                ui.label(e.as_str());
+                return;
+            }
+        };
+        let search = self.search.to_lowercase();
+        let rows: Vec<_> = stock
+            .iter()
+            .filter(|(field, _)| search.is_empty() || field.to_lowercase().contains(&search))
+            .collect();
+        let edits = self.tweaks.0.entry(path.into()).or_default();
+        egui::ScrollArea::vertical()
+            .id_source("tweak_fields")
+            .max_height(360.0)
+            .auto_shrink([false, true])
+            .show_rows(ui, ui.spacing().interact_size.y, rows.len(), |ui, range| {
+                for (field, stock_value) in &rows[range] {
+                    ui.horizontal(|ui| {
+                        let mut value = *edits.get(*field).unwrap_or(*stock_value);
+                        ui.label(field.as_str());
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            let modified = edits.contains_key(*field);
+                            if ui
+                                .add_enabled(modified, egui::Button::new("Reset"))
+                                .clicked()
+                            {
+                                edits.remove(*field);
+                                return;
+                            }
+                            let changed = match &mut value {
+                                TweakValue::Bool(v) => ui.checkbox(v, "").changed(),
+                                TweakValue::Int(v) => ui.add(DragValue::new(v)).changed(),
+                                TweakValue::UInt(v) => ui.add(DragValue::new(v)).changed(),
+                                TweakValue::Float(v) => {
+                                    ui.add(DragValue::new(v).speed(0.1)).changed()
+                                }
+                            };
+                            if changed {
+                                if value == **stock_value {
+                                    edits.remove(*field);
+                                } else {
+                                    edits.insert((**field).clone(), value);
+                                }
+                            }
+                        });
+                    });
+                }
+            });
+    }
+
+    pub fn render(&mut self, app: &App, ctx: &egui::Context) {
+        if !self.show {
+            return;
+        }
+        egui::Window::new("Quick Tweak")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(true)
+            .default_size([480.0, 420.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("tweak_singleton")
+                        .selected_text(SINGLETONS[self.selected].0)
+                        .show_ui(ui, |ui| {
+                            for (i, (name, _)) in SINGLETONS.iter().enumerate() {
+                                ui.selectable_value(&mut self.selected, i, *name);
+                            }
+                        });
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.search)
+                            .hint_text("Filter values…")
+                            .desired_width(ui.available_width()),
+                    );
+                });
+                ui.add_space(4.0);
+                let path = SINGLETONS[self.selected].1;
+                ui.label(egui::RichText::new(path).text_style(TextStyle::Small));
+                self.render_fields(app, ui, path);
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui.button("Close").clicked() {
+                            app.do_update(Message::CloseTweaks);
+                        }
+                        if ui.button("Save").clicked() {
+                            app.do_update(Message::SaveTweaks(self.tweaks.clone()));
+                        }
+                    });
+                });
+            });
+    }
+}
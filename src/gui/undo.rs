@@ -0,0 +1,149 @@
+use uk_manager::{mods::Mod, undo::Operation};
+use uk_mod::Manifest;
+use uk_ui::egui::{self, RichText, Ui};
+
+use super::{App, Message};
+
+/// The most operations the history menu lists either way.
+const SHOWN: usize = 20;
+
+/// A history menu entry, returning whether it was clicked.
+fn entry(ui: &mut Ui, operation: &Operation, undone: bool) -> bool {
+    let mut text = operation.description.clone();
+    if operation.applied {
+        text.push_str(" (applied)");
+    }
+    let text = if undone {
+        RichText::new(text).italics().weak()
+    } else {
+        RichText::new(text)
+    };
+    let hover = match (undone, operation.applied) {
+        (false, false) => "Undo this and everything after it",
+        (false, true) => {
+            "Undo this and everything after it. This has already been applied, so undoing it makes \
+             a new pending change."
+        }
+        (true, false) => "Redo this and everything before it",
+        (true, true) => {
+            "Redo this and everything before it. Undoing this has already been applied, so redoing \
+             it makes a new pending change."
+        }
+    };
+    ui.button(text).on_hover_text(hover).clicked()
+}
+
+impl App {
+    pub fn edit_menu(&self, ui: &mut Ui) {
+        let last = self.undo.done().next();
+        if ui
+            .add_enabled(
+                last.is_some(),
+                egui::Button::new(match last {
+                    Some(operation) => format!("Undo {}", operation.description),
+                    None => "Undo".into(),
+                })
+                .shortcut_text("Ctrl+Z"),
+            )
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::Undo(1));
+        }
+        let next = self.undo.undone().next();
+        if ui
+            .add_enabled(
+                next.is_some(),
+                egui::Button::new(match next {
+                    Some(operation) => format!("Redo {}", operation.description),
+                    None => "Redo".into(),
+                })
+                .shortcut_text("Ctrl+Shift+Z"),
+            )
+            .clicked()
+        {
+            ui.close_menu();
+            self.do_update(Message::Redo(1));
+        }
+        ui.separator();
+        ui.menu_button("History", |ui| self.history_menu(ui));
+    }
+
+    /// Changes to the mod list this session, with those which can be redone
+    /// above the most recent and those which can be undone below.
+    fn history_menu(&self, ui: &mut Ui) {
+        if !self.undo.can_undo() && !self.undo.can_redo() {
+            ui.label("No changes to the mod list yet");
+            return;
+        }
+        let undone: Vec<&Operation> = self.undo.undone().take(SHOWN).collect();
+        for (i, operation) in undone.into_iter().enumerate().rev() {
+            if entry(ui, operation, true) {
+                ui.close_menu();
+                self.do_update(Message::Redo(i + 1));
+            }
+        }
+        ui.separator();
+        for (i, operation) in self.undo.done().take(SHOWN).enumerate() {
+            if entry(ui, operation, false) {
+                ui.close_menu();
+                self.do_update(Message::Undo(i + 1));
+            }
+        }
+    }
+
+    /// Undoes, or redoes if `redo` is set, up to `count` operations, marking
+    /// the files they touch to be merged again.
+    pub(super) fn reverse_changes(&mut self, count: usize, redo: bool) {
+        let mut dirty = Manifest::default();
+        let mut descriptions = vec![];
+        let mut applied = false;
+        let mut error = None;
+        {
+            let manager = self.core.mod_manager();
+            for _ in 0..count {
+                let step = if redo {
+                    self.undo.redo(&manager, &mut self.mods)
+                } else {
+                    self.undo.undo(&manager, &mut self.mods)
+                };
+                match step {
+                    Ok(Some(step)) => {
+                        dirty.extend(&step.dirty);
+                        applied |= step.applied;
+                        descriptions.push(step.description);
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+        }
+        self.dirty_mut().extend(&dirty);
+        self.selected = self
+            .selected
+            .iter()
+            .filter_map(|m| self.mods.iter().find(|m2| m == *m2).cloned())
+            .collect::<Vec<Mod>>();
+        if let Some(last) = descriptions.last() {
+            let verb = if redo { "Redid" } else { "Undid" };
+            let what = match descriptions.len() {
+                1 => format!("\"{last}\""),
+                n => format!("{n} changes"),
+            };
+            self.do_update(Message::Toast(if applied {
+                format!(
+                    "{verb} {what}. It had already been applied, so it is now a pending change."
+                )
+            } else {
+                format!("{verb} {what}")
+            }));
+        }
+        if let Some(e) = error {
+            self.do_update(Message::Error(e));
+        }
+        self.do_update(Message::RefreshModsDisplay);
+    }
+}
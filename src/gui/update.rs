@@ -1,24 +1,48 @@
+use uk_manager::undo::Change;
+
 use super::*;
 
 impl App {
+    /// Opens the next mod waiting to be installed, if there is one.
+    fn open_queued(&mut self) -> bool {
+        match self.install_queue.pop_front() {
+            Some(path) => {
+                self.do_task(move |core| tasks::open_mod(&core, &path, None));
+                true
+            }
+            None => false,
+        }
+    }
+
     pub(super) fn handle_update(
         &mut self,
         ctx: &eframe::egui::Context,
         _frame: &mut eframe::Frame,
     ) {
         if let Ok(msg) = self.channel.1.try_recv() {
+            self.heartbeat.handling(&msg);
+            if msg.persists() && self.simulating() {
+                self.do_update(Message::Toast(
+                    "End the simulation before saving or deploying anything".into(),
+                ));
+                return;
+            }
             match msg {
                 Message::Noop => self.busy.set(false),
                 Message::ResetMods(dirty) => {
                     self.busy.set(false);
+                    self.simulation.get_mut().take();
                     self.dirty_mut().clear();
                     if let Some(dirty) = dirty {
                         self.dirty_mut().extend(&dirty);
                     }
                     self.mods = self.core.mod_manager().all_mods().collect();
                     self.selected.retain(|m| self.mods.contains(m));
+                    self.history_state.borrow_mut().invalidate();
+                    self.activity_state.borrow_mut().invalidate();
                     self.do_update(Message::RefreshModsDisplay);
                     self.do_update(Message::ReloadProfiles);
+                    self.do_update(Message::ProbeAvailability);
                     ctx.data_mut(|d| {
                         d.remove::<Arc<Mutex<egui_commonmark::CommonMarkCache>>>(egui::Id::new(
                             "md_cache",
@@ -30,14 +54,28 @@ impl App {
                     self.do_update(Message::ChangeSort(self.sort.0, self.sort.1));
                 }
                 Message::ChangeSort(sort, rev) => {
-                    let orderer = sort.orderer();
-                    let mut temp = self.mods.iter().cloned().enumerate().collect::<Vec<_>>();
+                    self.mod_keys
+                        .update(&self.mods, &self.core.settings().sort_locale);
+                    let query = collate::fold(&self.mod_filter);
+                    let orderer = sort.orderer(&self.mod_keys);
+                    let mut temp = self
+                        .mods
+                        .iter()
+                        .cloned()
+                        .enumerate()
+                        .filter(|(_, m)| self.mod_keys.matches(m.hash(), &query))
+                        .collect::<Vec<_>>();
                     temp.sort_by(orderer);
                     self.displayed_mods = if rev {
                         temp.into_iter().rev().map(|(_, m)| m).collect()
                     } else {
                         temp.into_iter().map(|(_, m)| m).collect()
                     };
+                    // Local patches are listed together after other mods,
+                    // except in load order, where their place matters
+                    if sort != Sort::Priority {
+                        self.displayed_mods.sort_by_key(|m| m.local_patch);
+                    }
                     self.sort = (sort, rev);
                 }
                 Message::CloseError => self.error = None,
@@ -45,6 +83,123 @@ impl App {
                 Message::ShowAbout => self.show_about = true,
                 Message::CloseAbout => self.show_about = false,
                 Message::CloseProfiles => self.profiles_state.borrow_mut().show = false,
+                Message::ShowBisect => self.bisect_state.borrow_mut().open(),
+                Message::StartBisect(pinned) => {
+                    self.do_task(move |core| tasks::start_bisect(&core, pinned));
+                }
+                Message::ReportBisect(crashed) => {
+                    if let Some(session) = self.bisect_state.borrow().session().cloned() {
+                        self.do_task(move |core| tasks::report_bisect(&core, session, crashed));
+                    }
+                }
+                Message::EndBisect(disable) => {
+                    if let Some(session) = self.bisect_state.borrow().session().cloned() {
+                        self.do_task(move |core| tasks::end_bisect(&core, session, disable));
+                    }
+                }
+                Message::UpdateBisect(session) => {
+                    self.bisect_state.borrow_mut().set_session(session);
+                    self.do_update(Message::ResetMods(None));
+                }
+                Message::ShowTweaks => self.tweak_state.borrow_mut().open(&self.core),
+                Message::CloseTweaks => self.tweak_state.borrow_mut().show = false,
+                Message::SaveTweaks(tweaks) => {
+                    self.tweak_state.borrow_mut().show = false;
+                    self.do_task(move |core| tasks::save_tweaks(&core, tweaks));
+                }
+                Message::RequestSplit(mod_) => {
+                    self.do_task(move |_| tasks::split_files(mod_));
+                }
+                Message::CheckActorDeps => {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_title("Select Actor Pack")
+                        .add_filter("Actor Pack", &["sbactorpack", "bactorpack"])
+                        .pick_file()
+                    {
+                        self.do_task(move |core| tasks::show_actor_deps(&core, path));
+                    }
+                }
+                Message::ShowActorDeps(root) => {
+                    self.busy.set(false);
+                    self.deps_state.borrow_mut().open(root);
+                }
+                Message::CompareMerged => {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_title("Select Merged Folder to Compare")
+                        .pick_folder()
+                    {
+                        self.do_cancellable_task(move |core, cancel| {
+                            tasks::compare_merged(&core, &path, cancel)
+                        });
+                    }
+                }
+                Message::ShowComparison(report) => {
+                    self.busy.set(false);
+                    self.compare_state.borrow_mut().open(report);
+                }
+                Message::StartCompat(first) => {
+                    self.compat_state.borrow_mut().start(first);
+                }
+                Message::CheckCompat(first, second, deep) => {
+                    self.do_task(move |core| tasks::check_compat(&core, first, second, deep));
+                }
+                Message::ShowCompat(report) => {
+                    self.busy.set(false);
+                    self.compat_state.borrow_mut().open(report);
+                }
+                Message::ShowSplit(mod_, files) => {
+                    self.busy.set(false);
+                    self.split_state.borrow_mut().open(mod_, files);
+                }
+                Message::SplitMod(mod_, parts) => {
+                    self.do_task(move |core| tasks::split_mod(&core, mod_, parts));
+                }
+                Message::SummarizeMod(mod_) => {
+                    self.do_task(move |core| tasks::summarize_mod(&core, mod_));
+                }
+                Message::SummarizeProfile => self.do_task(|core| tasks::summarize_profile(&core)),
+                Message::SetSummary(hash, markdown) => {
+                    self.busy.set(false);
+                    info::SUMMARIES.write().insert(hash, markdown);
+                }
+                Message::ExportSummary(markdown) => {
+                    self.busy.set(false);
+                    if let Some(dest) = rfd::FileDialog::new()
+                        .add_filter("Markdown", &["md"])
+                        .set_title("Export Change Summary")
+                        .set_file_name("summary.md")
+                        .save_file()
+                    {
+                        match fs::write(&dest, markdown) {
+                            Ok(()) => self.do_update(Message::Exported(dest)),
+                            Err(e) => self.do_update(Message::Error(e.into())),
+                        }
+                    }
+                }
+                Message::ShowLabels(true) => {
+                    self.do_task(tasks::load_labels);
+                }
+                Message::ShowLabels(false) => self.do_update(Message::SetLabels(None)),
+                Message::SetLabels(labels) => {
+                    *info::LABELS.write() = labels;
+                    info::ROOTS.write().clear();
+                }
+                Message::SetPriority(priority) => {
+                    let mod_manager = self.core.mod_manager();
+                    mod_manager.set_priority(priority);
+                    match mod_manager.save() {
+                        Ok(()) => {
+                            // Any conflict may now go the other way
+                            let mut dirty = self.dirty_mut();
+                            for mod_ in self.mods.iter().filter(|m| m.enabled) {
+                                if let Ok(manifest) = mod_.manifest() {
+                                    dirty.extend(&manifest);
+                                }
+                            }
+                        }
+                        Err(e) => self.do_update(Message::Error(e)),
+                    }
+                }
                 Message::Confirm(msg, prompt) => {
                     self.confirm = Some((*msg, prompt));
                 }
@@ -120,6 +275,7 @@ impl App {
                     if self.selected.len() == self.mods.len() {
                         return;
                     }
+                    let before = self.mods.clone();
                     self.mods.retain(|m| !self.selected.contains(m));
                     for (i, selected_mod) in self.selected.iter().enumerate() {
                         self.mods
@@ -127,9 +283,28 @@ impl App {
                     }
                     self.hover_index = None;
                     self.drag_index = None;
+                    if self.simulating() {
+                        self.invalidate_simulation();
+                        self.do_update(Message::RefreshModsDisplay);
+                        return;
+                    }
+                    if before != self.mods {
+                        let position = self
+                            .mods
+                            .iter()
+                            .position(|m| self.selected.contains(m))
+                            .unwrap_or_default();
+                        self.undo.push(
+                            format!(
+                                "Moved {} to position {}",
+                                describe_mods(&self.selected),
+                                position
+                            ),
+                            Change::order(&before, &self.mods),
+                        );
+                    }
                     match self.selected.iter().try_for_each(|m| {
-                        self.dirty_mut()
-                            .extend(m.manifest_with_options(&m.enabled_options)?.as_ref());
+                        self.dirty_mut().extend(m.manifest()?.as_ref());
                         Ok(())
                     }) {
                         Ok(()) => self.do_update(Message::RefreshModsDisplay),
@@ -165,9 +340,32 @@ impl App {
                     }
                 }
                 Message::ChangeProfile(profile) => {
-                    match self.core.change_profile(profile) {
+                    let previous_dump = self.core.settings().dump();
+                    match self.core.change_profile(&profile) {
                         Ok(()) => {
+                            // Keep the settings being edited on the same profile,
+                            // so the dump chosen for it shows there
+                            if let Some(config) = self.temp_settings.platform_config_mut() {
+                                config.profile = profile.as_str().into();
+                            }
+                            if let Some(config) = settings::CONFIG.write().get_mut(&self.platform())
+                            {
+                                config.profile = profile.clone();
+                            }
+                            let dump = self.core.settings().dump();
+                            let same_dump = match (&previous_dump, &dump) {
+                                (Some(previous), Some(dump)) => Arc::ptr_eq(previous, dump),
+                                _ => previous_dump.is_none() && dump.is_none(),
+                            };
+                            if !same_dump {
+                                self.do_update(Message::CheckDump);
+                                if info::LABELS.read().is_some() {
+                                    self.do_update(Message::ShowLabels(true));
+                                }
+                            }
+                            self.undo.clear();
                             self.mods = self.core.mod_manager().all_mods().collect();
+                            self.bisect_state.borrow_mut().reload(&self.core);
                             self.do_update(Message::RefreshModsDisplay);
                             self.do_update(Message::ReloadProfiles);
                         }
@@ -178,15 +376,62 @@ impl App {
                 Message::AddProfile => {
                     if let Some(profile) = self.new_profile.take() {
                         match self.core.change_profile(profile) {
-                            Ok(()) => self.do_update(Message::ResetMods(None)),
+                            Ok(()) => {
+                                self.undo.clear();
+                                self.do_update(Message::ResetMods(None))
+                            }
                             Err(e) => self.do_update(Message::Error(e)),
                         };
                     }
                 }
-                Message::DeleteProfile(profile) => {
+                Message::DeleteProfile(profile, keep_patches) => {
                     self.do_task(move |core| {
-                        let path = core.settings().profiles_dir().join(profile);
-                        fs::remove_dir_all(path)?;
+                        let mod_manager = core.mod_manager();
+                        if let Some(to) = keep_patches {
+                            mod_manager.copy_mods(&mod_manager.stranded_patches(&profile), &to)?;
+                        }
+                        mod_manager.delete_profile(&profile)?;
+                        Ok(Message::ReloadProfiles)
+                    })
+                }
+                Message::RestoreProfile(trashed) => {
+                    self.do_task(move |core| {
+                        core.mod_manager().restore_profile(&trashed)?;
+                        Ok(Message::ReloadProfiles)
+                    })
+                }
+                Message::DeduplicateMods => {
+                    self.do_task(move |core| {
+                        let report = core.mod_manager().deduplicate()?;
+                        Ok(Message::Deduplicated(report))
+                    })
+                }
+                Message::Deduplicated(report) => {
+                    self.busy.set(false);
+                    // Only where the mods are stored changed, so pending
+                    // changes are kept
+                    let paths: Vec<(usize, PathBuf)> = self
+                        .core
+                        .mod_manager()
+                        .all_mods()
+                        .map(|m| (m.hash(), m.path))
+                        .collect();
+                    for mod_ in self.mods.iter_mut().chain(self.selected.iter_mut()) {
+                        if let Some((_, path)) = paths.iter().find(|(hash, _)| *hash == mod_.hash())
+                        {
+                            mod_.path = path.clone();
+                        }
+                    }
+                    self.do_update(Message::Toast(format!(
+                        "Stored {} mods unpacked, saving {}",
+                        report.mods,
+                        uk_manager::preflight::human_size(report.saved())
+                    )));
+                }
+                Message::EmptyTrash => {
+                    self.do_task(move |core| {
+                        let purged = core.mod_manager().empty_trash()?;
+                        log::info!("Deleted {purged} mods which were only in trashed profiles");
                         Ok(Message::ReloadProfiles)
                     })
                 }
@@ -222,6 +467,10 @@ impl App {
                 Message::SetFocus(pane) => {
                     self.focused = pane;
                 }
+                Message::SetScale(scale) => {
+                    self.ui_scale = uk_ui::scale::clamp_scale(scale);
+                    uk_ui::scale::apply_scale(ctx, self.ui_scale);
+                }
                 Message::SetTheme(theme) => {
                     theme.set_theme(ctx);
                     self.theme = theme;
@@ -250,8 +499,17 @@ impl App {
                     ctx.request_repaint();
                     self.do_task(move |_| tasks::open_mod(&core, &path, meta));
                 }
-                Message::HandleMod(mod_) => {
+                Message::HandleMod(mut mod_) => {
                     self.busy.set(false);
+                    if let Some(old_mod) = self.update_mod.as_ref() {
+                        let report = mod_.carry_options(old_mod);
+                        if report.needs_review() {
+                            self.do_update(Message::Toast(format!(
+                                "Options of {} changed in this version:\n{report}",
+                                mod_.meta.name
+                            )));
+                        }
+                    }
                     log::debug!("{:#?}", &mod_);
                     for (hash, (name, version)) in mod_.meta.masters.iter() {
                         if !self.mods.iter().any(|m| m.hash() == *hash) {
@@ -262,20 +520,39 @@ impl App {
                             )));
                         }
                     }
-                    if !matches!(mod_.meta.platform, ModPlatform::Universal)
-                        && mod_.meta.platform != ModPlatform::Specific(self.platform().into())
-                    {
+                    if !mod_.meta.platform.supports(self.platform().into()) {
                         self.do_update(Message::Error(anyhow_ext::anyhow!(
                             "Mod is for {:?}, current mode is {}",
                             mod_.meta.platform,
                             self.platform()
                         )));
-                    } else if !mod_.meta.options.is_empty() {
-                        self.do_update(Message::RequestOptions(mod_, false));
                     } else {
-                        self.do_update(Message::InstallMod(mod_));
+                        let prompt = self.install_prompt(&mod_);
+                        let next = if !mod_.meta.options.is_empty() {
+                            Message::RequestOptions(mod_, false)
+                        } else {
+                            Message::InstallMod(mod_)
+                        };
+                        match prompt {
+                            Some(prompt) => {
+                                self.do_update(Message::Confirm(Box::new(next), prompt))
+                            }
+                            None => self.do_update(next),
+                        }
+                    }
+                }
+                Message::ResolveDuplicate(mod_, duplicate) => {
+                    if self.update_mod.is_some() {
+                        self.do_update(Message::HandleMod(mod_));
+                    } else {
+                        self.busy.set(false);
+                        self.duplicate = Some((mod_, *duplicate));
                     }
                 }
+                Message::CloseDuplicate => {
+                    self.duplicate = None;
+                    self.open_queued();
+                }
                 Message::InstallMod(tmp_mod_) => {
                     let update_mod = self.update_mod.take();
                     self.do_task(move |core| {
@@ -286,7 +563,7 @@ impl App {
                             mods.replace(tmp_mod_, mod_.hash())?;
                             log::info!("Updated {}", mod_.meta.name);
                             dirty.extend(&mod_.manifest().unwrap_or_default());
-                            Ok(Message::ResetMods(Some(dirty)))
+                            Ok(Message::UpdatedMod(dirty))
                         } else {
                             let mod_ = mods.add(&tmp_mod_.path, None)?;
                             let hash = mod_.as_map_id();
@@ -304,15 +581,25 @@ impl App {
                     let mods = mods.unwrap_or_else(|| self.selected.clone());
                     self.do_task(move |core| {
                         let manager = core.mod_manager();
-                        mods.iter().try_for_each(|m| -> Result<()> {
-                            manager.del(m.as_map_id(), None)?;
-                            log::info!("Removed mod {} from current profile", m.meta.name.as_str());
-                            Ok(())
-                        })?;
+                        let removed = mods
+                            .iter()
+                            .filter_map(|m| manager.trash_mod(m).transpose())
+                            .collect::<Result<Vec<_>>>()?;
                         manager.save()?;
-                        Ok(Message::RemoveMods(mods))
+                        Ok(Message::RemoveMods(removed))
                     });
                 }
+                Message::ArchiveMods(mods, disable) => {
+                    let mods = mods.unwrap_or_else(|| self.selected.clone());
+                    self.archive_mods(mods, disable);
+                }
+                Message::UnarchiveMod(mod_) => self.unarchive_mod(mod_),
+                Message::ProbeAvailability => self.probe_availability(),
+                Message::AvailabilityProbed(offline) => self.set_offline(offline),
+                Message::HydrateMods(mods) => {
+                    self.do_task(move |core| tasks::hydrate_mods(&core, mods));
+                }
+                Message::Hydrated(mods) => self.hydrated(mods),
                 Message::ModUpdate => {
                     if let Some(file) = rfd::FileDialog::new()
                         .set_title("Select a Mod")
@@ -334,6 +621,17 @@ impl App {
                 }
                 Message::ToggleMods(mods, enabled) => {
                     let mods = mods.as_ref().unwrap_or(&self.selected);
+                    if enabled && self.needs_hydrating(mods) {
+                        let mods = mods.clone();
+                        self.confirm_hydrate(mods);
+                        return;
+                    }
+                    let before: Vec<Mod> = self
+                        .mods
+                        .iter()
+                        .filter(|m| mods.contains(m) && m.enabled != enabled)
+                        .cloned()
+                        .collect();
                     let dirty = mods.iter().try_fold(
                         Manifest::default(),
                         |mut dirty, m| -> Result<Manifest> {
@@ -346,8 +644,22 @@ impl App {
                         },
                     );
                     match dirty {
+                        Ok(_) if self.simulating() => {
+                            self.invalidate_simulation();
+                            self.do_update(Message::RefreshModsDisplay)
+                        }
                         Ok(dirty) => {
                             self.dirty_mut().extend(&dirty);
+                            if !before.is_empty() {
+                                self.undo.push(
+                                    format!(
+                                        "{} {}",
+                                        if enabled { "Enabled" } else { "Disabled" },
+                                        describe_mods(&before)
+                                    ),
+                                    Change::enabled(&before, enabled),
+                                );
+                            }
                             self.do_update(Message::RefreshModsDisplay)
                         }
                         Err(e) => self.do_update(Message::Error(e)),
@@ -357,12 +669,16 @@ impl App {
                     if let Ok(manifest) = mod_.manifest() {
                         self.dirty_mut().extend(&manifest);
                     }
+                    if !self.mods.contains(&mod_) {
+                        self.undo.push(
+                            format!("Installed {}", mod_.meta.name),
+                            Change::Installed(vec![mod_.clone()]),
+                        );
+                    }
                     self.mods = self.core.mod_manager().all_mods().collect();
                     self.do_update(Message::RefreshModsDisplay);
                     self.busy.set(false);
-                    if let Some(path) = self.install_queue.pop_front() {
-                        self.do_task(move |core| tasks::open_mod(&core, &path, None));
-                    } else if !self.error_queue.is_empty() {
+                    if !self.open_queued() && !self.error_queue.is_empty() {
                         let msg = self
                             .error_queue
                             .drain(..)
@@ -380,6 +696,12 @@ impl App {
                     let mods = self.selected.clone();
                     self.do_task(move |core| tasks::extract_mods(&core, mods));
                 }
+                Message::UpdatedMod(dirty) => {
+                    self.do_update(Message::ResetMods(Some(dirty)));
+                    // Mods waiting to be installed go on once the mods are
+                    // reloaded, as they do after an install
+                    self.open_queued();
+                }
                 Message::AddToProfile(profile) => {
                     let mut dirty = self.dirty.write();
                     let dirty = dirty.entry(profile.as_str().into()).or_default();
@@ -407,7 +729,8 @@ impl App {
                         });
                     }
                 }
-                Message::RemoveMods(mods) => {
+                Message::RemoveMods(removed) => {
+                    let mods: Vec<Mod> = removed.iter().map(|r| r.mod_.clone()).collect();
                     self.mods.retain(|m| !mods.contains(m));
                     self.selected.retain(|m| !mods.contains(m));
                     mods.iter().for_each(|m| {
@@ -415,21 +738,140 @@ impl App {
                             self.dirty_mut().extend(&manifest);
                         }
                     });
+                    if !removed.is_empty() {
+                        self.undo.push(
+                            format!("Uninstalled {}", describe_mods(&mods)),
+                            Change::Uninstalled(removed),
+                        );
+                    }
                     self.do_update(Message::RefreshModsDisplay);
                     self.busy.set(false);
                 }
+                msg if msg.changes_deployment()
+                    && self.core.deploy_manager().locked().is_some() =>
+                {
+                    self.locked_action = Some(msg);
+                }
                 Message::Apply => {
+                    crate::logger::LOGGER.mark_boundary();
                     let mods = self.mods.clone();
                     let dirty = std::mem::take(self.dirty_mut().deref_mut());
                     self.do_task(move |core| tasks::apply_changes(&core, mods, Some(dirty)));
                 }
+                Message::RequestApply => {
+                    let confirm = match self.dump_prompt() {
+                        Some(prompt) => Some((Message::AcceptDump(true), prompt)),
+                        None => self.apply_prompt().map(|prompt| (Message::Apply, prompt)),
+                    };
+                    match confirm {
+                        Some((msg, prompt)) => {
+                            if self.hidden {
+                                self.do_update(Message::ShowWindow);
+                            }
+                            self.do_update(Message::Confirm(Box::new(msg), prompt))
+                        }
+                        None => self.do_update(Message::Apply),
+                    }
+                }
+                Message::AcceptDump(then_apply) => {
+                    if let Some(dump) = self.core.settings().dump() {
+                        let host = dump.source().host_path().to_path_buf();
+                        self.temp_settings.accepted_dumps.push(host.clone());
+                        match self
+                            .core
+                            .settings_mut()
+                            .apply(|s| s.accepted_dumps.push(host.clone()))
+                        {
+                            Ok(()) if then_apply => self.do_update(Message::RequestApply),
+                            Ok(()) => (),
+                            Err(e) => self.do_update(Message::Error(e)),
+                        }
+                    }
+                }
+                Message::Applied => {
+                    self.undo.mark_applied();
+                    self.do_update(Message::ResetMods(None));
+                    if self.core.settings().serve.after_apply
+                        && !self.server.as_ref().is_some_and(|s| s.is_running())
+                    {
+                        self.do_update(Message::StartServer);
+                    }
+                }
+                Message::StartServer => {
+                    self.server = None;
+                    let settings = self.core.settings();
+                    let root = settings
+                        .deploy_dir()
+                        .map(|d| d.to_path_buf())
+                        .unwrap_or_else(|| settings.merged_dir());
+                    match crate::serve::Server::start(root, &settings.serve) {
+                        Ok(server) => self.server = Some(deploy::ServeState::new(server)),
+                        Err(e) => self.do_update(Message::Error(e)),
+                    }
+                }
+                Message::Deployed(dir) => {
+                    self.do_update(Message::ResetMods(None));
+                    if let Some(dir) = dir {
+                        self.do_update(Message::Toast(format!("Deployed to {}", dir.display())));
+                    }
+                }
+                Message::StopServer => {
+                    self.server = None;
+                }
                 Message::Deploy => {
                     self.do_task(move |core| {
                         log::info!("Deploying current mod configuration");
                         core.deploy_manager().deploy()?;
-                        Ok(Message::ResetMods(None))
+                        Ok(Message::Deployed(
+                            core.settings().deploy_dir().map(|d| d.to_path_buf()),
+                        ))
+                    })
+                }
+                Message::DeployOnly(half) => {
+                    self.do_task(move |core| {
+                        log::info!("Deploying {half:?} files only");
+                        core.deploy_manager().deploy_only(&[half])?;
+                        Ok(Message::Deployed(
+                            core.settings().deploy_dir().map(|d| d.to_path_buf()),
+                        ))
                     })
                 }
+                Message::LockDeployment => {
+                    match self.core.deploy_manager().lock() {
+                        Ok(()) => self.do_update(Message::Toast("Deployment locked".into())),
+                        Err(e) => self.do_update(Message::Error(e)),
+                    }
+                }
+                Message::UnlockDeployment(then) => {
+                    match self.core.deploy_manager().unlock() {
+                        Ok(()) => {
+                            match then {
+                                Some(msg) => self.do_update(*msg),
+                                None => {
+                                    self.do_update(Message::Toast("Deployment unlocked".into()))
+                                }
+                            }
+                        }
+                        Err(e) => self.do_update(Message::Error(e)),
+                    }
+                }
+                Message::ShowSnapshots => {
+                    self.busy.set(false);
+                    let snapshots = uk_manager::lock::snapshots(&self.core.settings());
+                    self.snapshot_state.borrow_mut().open(snapshots);
+                }
+                Message::CreateSnapshot(name) => {
+                    self.do_task(move |core| tasks::create_snapshot(&core, &name));
+                }
+                Message::RestoreSnapshot(snapshot) => {
+                    self.do_task(move |core| tasks::restore_snapshot(&core, &snapshot));
+                }
+                Message::DeleteSnapshot(snapshot) => {
+                    match snapshot.delete() {
+                        Ok(()) => self.do_update(Message::ShowSnapshots),
+                        Err(e) => self.do_update(Message::Error(e)),
+                    }
+                }
                 Message::ResetPending => {
                     self.do_task(|core| {
                         log::info!("Resetting pending deployment data");
@@ -438,6 +880,7 @@ impl App {
                     })
                 }
                 Message::Remerge => {
+                    crate::logger::LOGGER.mark_boundary();
                     self.do_task(|core| tasks::apply_changes(&core, vec![], None));
                 }
                 Message::ResetSettings => {
@@ -460,6 +903,10 @@ impl App {
                             if let Some(dump) = self.core.settings().dump() {
                                 dump.clear_cache()
                             }
+                            self.do_update(Message::CheckDump);
+                            if info::LABELS.read().is_some() {
+                                self.do_update(Message::ShowLabels(true));
+                            }
                             self.package_builder.borrow_mut().reset(self.platform());
                             self.do_update(Message::ClearSelect);
                             self.do_update(Message::ResetMods(None));
@@ -482,23 +929,40 @@ impl App {
                     self.do_update(Message::ResetMods(None));
                 }
                 Message::RequestOptions(mut mod_, update) => {
-                    if !update {
+                    // Options carried over from an older version are kept
+                    if !update && mod_.enabled_options.is_empty() {
                         mod_.enable_default_options();
                     }
                     self.options_mod = Some((mod_, update));
                 }
-                Message::UpdateOptions(mod_) => {
-                    let opts = mod_.enabled_options.clone();
-                    match self
-                        .core
-                        .mod_manager()
-                        .set_enabled_options(mod_.hash(), opts)
-                    {
+                Message::UpdateOptions(mod_) if self.simulating() => self.simulate_options(mod_),
+                Message::UpdateOptions(mut mod_) => {
+                    mod_.options_review = None;
+                    let mod_manager = self.core.mod_manager();
+                    let old_mod = self.mods.iter().find(|m| m.hash() == mod_.hash());
+                    match mod_manager
+                        .set_enabled_options(mod_.hash(), mod_.enabled_options.clone())
+                        .and_then(|_| {
+                            mod_manager
+                                .set_inactive_options(mod_.hash(), mod_.inactive_options.clone())
+                        })
+                        .and_then(|manifest| {
+                            match old_mod {
+                                Some(old_mod) => uk_manager::mods::options_delta(old_mod, &mod_),
+                                None => Ok(manifest.as_ref().clone()),
+                            }
+                        }) {
                         Ok(manifest) => {
                             self.dirty_mut().extend(&manifest);
                             if let Some(old_mod) =
                                 self.mods.iter_mut().find(|m| m.hash() == mod_.hash())
                             {
+                                if !old_mod.state_eq(&mod_) {
+                                    self.undo.push(
+                                        format!("Changed options for {}", mod_.meta.name),
+                                        Change::options(old_mod.clone(), mod_.clone()),
+                                    );
+                                }
                                 *old_mod = mod_.clone();
                             }
                             if let Some(old_mod) =
@@ -511,17 +975,20 @@ impl App {
                         Err(e) => self.do_update(Message::Error(e)),
                     }
                 }
+                Message::Undo(count) => self.reverse_changes(count, false),
+                Message::Redo(count) => self.reverse_changes(count, true),
                 Message::Error(error) => {
                     log::error!("{:?}", &error);
+                    if self.hidden {
+                        self.do_update(Message::ShowWindow);
+                    }
                     if self.install_queue.is_empty() {
                         self.busy.set(false);
                         self.error = Some(error);
                     } else {
                         log::warn!("More operations in queue, stashing error and continuing…");
                         self.error_queue.push_back(error);
-                        if let Some(path) = self.install_queue.pop_front() {
-                            self.do_task(move |core| tasks::open_mod(&core, &path, None));
-                        }
+                        self.open_queued();
                     }
                 }
 
@@ -568,13 +1035,117 @@ impl App {
                         .save_file()
                     {
                         builder.dest = dest;
-                        self.do_task(move |core| tasks::package_mod(&core, builder));
+                        self.do_cancellable_task(move |core, cancel| {
+                            tasks::package_mod(&core, builder, cancel)
+                        });
                     }
                 }
                 Message::ResetPacker => {
                     self.package_builder.borrow_mut().reset(self.platform());
                     self.busy.set(false);
                 }
+                Message::CheckDrift => self.do_task(tasks::check_drift),
+                Message::CheckDump => self.check_dump(),
+                Message::ShowDrift(drift, applied) => {
+                    self.busy.set(false);
+                    let deploy = applied
+                        && self.core.deploy_manager().locked().is_none()
+                        && self
+                            .core
+                            .settings()
+                            .platform_config()
+                            .and_then(|c| c.deploy_config.as_ref().map(|c| c.auto))
+                            .unwrap_or(false);
+                    self.drift = Some(modals::DriftReview {
+                        files: drift
+                            .into_iter()
+                            .map(|d| (d, uk_manager::drift::Resolution::Restore))
+                            .collect(),
+                        deploy,
+                    });
+                    if applied {
+                        self.do_update(Message::Applied);
+                    }
+                }
+                Message::ResolveDrift(resolutions) => {
+                    if let (Some(review), Some(resolutions)) = (self.drift.take(), resolutions) {
+                        self.do_task(move |core| {
+                            tasks::resolve_drift(&core, resolutions, review.deploy)
+                        });
+                    }
+                }
+                Message::ValidateLibrary => self.do_task(tasks::validate_library),
+                Message::ClearDumpCache => self.do_task(tasks::clear_dump_cache),
+                Message::DumpCacheCleared => {
+                    self.busy.set(false);
+                    self.do_update(Message::Toast("Game dump cache cleared".into()));
+                }
+                Message::ShowIndexIssues(issues, report) => {
+                    self.busy.set(false);
+                    let offline: Vec<Mod> = self
+                        .mods
+                        .iter()
+                        .filter(|m| self.offline.contains(&m.hash()))
+                        .cloned()
+                        .collect();
+                    if report && !offline.is_empty() {
+                        self.do_update(Message::Toast(format!(
+                            "Skipped {} while offline",
+                            describe_mods(&offline)
+                        )));
+                    }
+                    if issues.is_empty() {
+                        self.do_update(Message::Toast(
+                            "No problems found in the mod library".into(),
+                        ));
+                    } else {
+                        self.index_review = Some(modals::IndexReview {
+                            issues: issues.into_iter().map(|issue| (issue, true)).collect(),
+                            report,
+                        });
+                    }
+                }
+                Message::RepairIndex(issues) => {
+                    self.index_review = None;
+                    if let Some(issues) = issues.filter(|issues| !issues.is_empty()) {
+                        self.do_task(move |core| tasks::repair_index(&core, issues));
+                    }
+                }
+                Message::RunBatch => {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_title("Select Batch File")
+                        .add_filter("Batch File", &["yml", "yaml"])
+                        .pick_file()
+                    {
+                        self.do_task(move |core| tasks::run_batch(&core, &path));
+                    }
+                }
+                Message::BatchRan(pending, failures) => {
+                    let mut dirty = self.dirty().clone();
+                    dirty.extend(&pending);
+                    self.do_update(Message::ResetMods(Some(dirty)));
+                    if failures.is_empty() {
+                        self.do_update(Message::Toast("Batch file finished".into()));
+                    } else {
+                        self.do_update(Message::Error(anyhow_ext::anyhow!(
+                            "Some steps of the batch file failed:\n{}",
+                            failures.join("\n")
+                        )));
+                    }
+                }
+                Message::ShowEndianAudit(builder, mismatches) => {
+                    self.endian_audit = Some((builder, mismatches));
+                    self.busy.set(false);
+                }
+                Message::ResolveEndianAudit(check) => {
+                    if let (Some((mut builder, _)), Some(check)) = (self.endian_audit.take(), check)
+                    {
+                        builder.endian_check = check;
+                        self.do_cancellable_task(move |core, cancel| {
+                            tasks::package_mod(&core, builder, cancel)
+                        });
+                    }
+                }
                 Message::ImportCemu => {
                     if let Some(path) = rfd::FileDialog::new()
                         .set_title("Select Cemu Directory")
@@ -614,6 +1185,7 @@ impl App {
                         std::os::unix::process::CommandExt::process_group(&mut command, 0);
                     }
                     command.spawn().unwrap();
+                    self.quitting = true;
                     ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                 }
                 Message::Toast(msg) => {
@@ -623,6 +1195,84 @@ impl App {
                         toast
                     });
                 }
+                Message::StartSimulation => self.start_simulation(),
+                Message::StartTour => self.tour.start(),
+                Message::EndSimulation(keep) => self.end_simulation(keep),
+                Message::PreviewFile(file, aoc) => self.preview_file(file, aoc),
+                Message::LaunchEmulator => {
+                    let settings = self.core.settings();
+                    if let Some(exe) = settings
+                        .platform_config()
+                        .and_then(|c| c.deploy_config.as_ref())
+                        .and_then(|c| c.executable.as_ref())
+                    {
+                        let cmd = util::default_shell();
+                        let (shell, arg) = (&cmd.0, &cmd.1);
+                        if let Err(e) = std::process::Command::new(shell)
+                            .args(arg.iter())
+                            .arg(exe)
+                            .spawn()
+                        {
+                            log::warn!("Failed to launch emulator: {e}");
+                        }
+                    }
+                }
+                Message::ShowWindow => {
+                    self.hidden = false;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                }
+                Message::Quit => {
+                    self.quitting = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                Message::ShowPreview(file, aoc, preview) => self.show_preview(file, aoc, preview),
+                Message::ExportDiff(file, aoc) => self.export_diff(file, aoc),
+                Message::ExportConflicts => {
+                    if let Some(dest) = rfd::FileDialog::new()
+                        .add_filter("JSON", &["json"])
+                        .set_title("Export Conflict Report")
+                        .set_file_name("conflicts.json")
+                        .save_file()
+                    {
+                        self.do_task(move |core| {
+                            uk_manager::conflicts::ConflictReport::new(&core)?
+                                .write(fs::File::create(&dest)?)?;
+                            Ok(Message::Exported(dest))
+                        });
+                    }
+                }
+                Message::ExportRepro(failure, error) => {
+                    let name = failure.file.rsplit('/').next().unwrap_or(&failure.file);
+                    if let Some(dest) = rfd::FileDialog::new()
+                        .add_filter("ZIP", &["zip"])
+                        .set_title("Export Merge Repro")
+                        .set_file_name(format!("{name}.repro.zip"))
+                        .save_file()
+                    {
+                        self.do_task(move |core| {
+                            core.deploy_manager()
+                                .export_repro(&failure, &error, &dest)?;
+                            Ok(Message::Exported(dest))
+                        });
+                    }
+                }
+                Message::Exported(path) => {
+                    self.busy.set(false);
+                    self.do_update(Message::Toast(format!(
+                        "Exported {}",
+                        path.file_name()
+                            .unwrap_or(path.as_os_str())
+                            .to_string_lossy()
+                    )));
+                    self.last_export = Some(path);
+                }
+                Message::Reveal(path) => {
+                    if let Err(e) = reveal::reveal(&path) {
+                        self.do_update(Message::Error(e));
+                    }
+                }
                 Message::UpdatePackageMeta(meta) => {
                     self.package_builder.borrow_mut().meta = meta;
                     self.busy.set(false);
@@ -633,3 +1283,11 @@ impl App {
         }
     }
 }
+
+/// Names a mod, or counts them if there are several, to describe a change.
+pub(super) fn describe_mods(mods: &[Mod]) -> String {
+    match mods {
+        [mod_] => mod_.meta.name.to_string(),
+        mods => format!("{} mods", mods.len()),
+    }
+}
@@ -0,0 +1,292 @@
+//! Watches for the UI thread getting stuck. Each finished frame is noted,
+//! and when none has finished for a while, the watchdog asks for a repaint,
+//! since an idle window does not repaint on its own. If that repaint does not
+//! come either, the UI is stalled, and what it was doing is written to the log
+//! for support.
+//!
+//! Busy tasks and modal windows run alongside the UI rather than on its
+//! thread, so they keep frames coming and never count as a stall.
+use std::{
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use flume::Receiver;
+use parking_lot::Mutex;
+use uk_ui::egui;
+
+use super::Message;
+
+pub mod tracked;
+
+/// How often the watchdog checks on the UI.
+const CHECK: Duration = Duration::from_secs(1);
+/// How long the UI may go without a frame before it is asked for one, in
+/// milliseconds.
+const PING_AFTER: u64 = 5_000;
+/// How long the UI may take to answer that before it counts as stalled, in
+/// milliseconds.
+const STALL: u64 = 10_000;
+
+/// What the UI thread last said it was doing, shared with the watchdog.
+#[derive(Debug)]
+pub struct Heartbeat {
+    start: Instant,
+    /// When the last frame finished, in milliseconds since `start`.
+    last_frame: AtomicU64,
+    busy: AtomicBool,
+    /// Whether the window was minimized, when it need not repaint.
+    hidden: AtomicBool,
+    /// The message handled in the current frame, if any.
+    handling: Mutex<Option<String>>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            last_frame: AtomicU64::new(0),
+            busy: AtomicBool::new(false),
+            hidden: AtomicBool::new(false),
+            handling: Mutex::new(None),
+        }
+    }
+}
+
+impl Heartbeat {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    /// Notes a finished frame. Called at the end of every update.
+    pub fn frame(&self, busy: bool, hidden: bool) {
+        self.busy.store(busy, Ordering::Relaxed);
+        self.hidden.store(hidden, Ordering::Relaxed);
+        self.handling.lock().take();
+        self.last_frame.store(self.now(), Ordering::Relaxed);
+    }
+
+    /// Notes the message the UI is about to handle.
+    pub fn handling(&self, msg: &Message) {
+        *self.handling.lock() = Some(variant_name(msg));
+    }
+
+    /// The message being handled this frame, if any.
+    pub fn current(&self) -> Option<String> {
+        self.handling.lock().clone()
+    }
+
+    fn report(&self, stalled: u64, pending: usize) -> String {
+        let mut report = format!(
+            "The UI has not responded for {:.1} seconds\nBusy: {}\nMessage handled this frame: \
+             {}\nPending messages: {pending}\nHeld locks:",
+            stalled as f64 / 1000.0,
+            if self.busy.load(Ordering::Relaxed) {
+                "yes"
+            } else {
+                "no"
+            },
+            self.handling.lock().as_deref().unwrap_or("none"),
+        );
+        let held = tracked::held();
+        if held.is_empty() {
+            report.push_str(" none");
+        }
+        for holder in held {
+            let _ = write!(report, "\n  {holder}");
+        }
+        report
+    }
+}
+
+/// Collects a message's variant name, cutting the formatting off before any
+/// fields, which can be large.
+struct VariantName(String);
+
+impl fmt::Write for VariantName {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match s.find(['(', ' ', '{']) {
+            Some(end) => {
+                self.0.push_str(&s[..end]);
+                Err(fmt::Error)
+            }
+            None => {
+                self.0.push_str(s);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn variant_name(msg: &Message) -> String {
+    let mut name = VariantName(String::new());
+    let _ = write!(name, "{msg:?}");
+    name.0
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Wait,
+    /// Ask the UI for a frame.
+    Ping,
+    /// Report a stall of so many milliseconds.
+    Report(u64),
+    /// Note the UI answering again after stalling for so many milliseconds.
+    Recovered(u64),
+}
+
+/// Decides from frame times alone whether the UI has stalled, so the rules
+/// can be tested without a window.
+#[derive(Debug, Default)]
+struct Detector {
+    pinged:   Option<u64>,
+    reported: bool,
+}
+
+impl Detector {
+    fn check(&mut self, now: u64, last_frame: u64, hidden: bool) -> Action {
+        match self.pinged {
+            Some(ping) if last_frame >= ping => {
+                self.pinged = None;
+                if std::mem::take(&mut self.reported) {
+                    Action::Recovered(last_frame - ping)
+                } else {
+                    Action::Wait
+                }
+            }
+            Some(ping) if !self.reported && now.saturating_sub(ping) >= STALL => {
+                self.reported = true;
+                Action::Report(now - ping)
+            }
+            Some(_) => Action::Wait,
+            None if !hidden && now.saturating_sub(last_frame) >= PING_AFTER => {
+                self.pinged = Some(now);
+                Action::Ping
+            }
+            None => Action::Wait,
+        }
+    }
+}
+
+/// Starts the watchdog thread. `pending` is only used to count the messages
+/// waiting to be handled.
+pub fn spawn(ctx: egui::Context, heartbeat: Arc<Heartbeat>, pending: Receiver<Message>) {
+    let _ = std::thread::Builder::new()
+        .name("watchdog".into())
+        .spawn(move || {
+            let mut detector = Detector::default();
+            loop {
+                std::thread::sleep(CHECK);
+                let action = detector.check(
+                    heartbeat.now(),
+                    heartbeat.last_frame.load(Ordering::Relaxed),
+                    heartbeat.hidden.load(Ordering::Relaxed),
+                );
+                match action {
+                    Action::Wait => (),
+                    Action::Ping => ctx.request_repaint(),
+                    Action::Report(stalled) => {
+                        log::warn!("{}", heartbeat.report(stalled, pending.len()));
+                    }
+                    Action::Recovered(stalled) => {
+                        log::warn!(
+                            "The UI responded again after {:.1} seconds",
+                            stalled as f64 / 1000.0
+                        );
+                    }
+                }
+            }
+        })
+        .inspect_err(|e| log::warn!("Could not start the UI watchdog: {e}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the detector once a second over a span of time, with frames at
+    /// the given times, and collects what it does.
+    fn run(
+        detector: &mut Detector,
+        from: u64,
+        to: u64,
+        frames: impl Fn(u64, Option<u64>) -> Option<u64>,
+        hidden: bool,
+    ) -> Vec<(u64, Action)> {
+        let mut last_frame = from;
+        let mut actions = vec![];
+        for now in (from..=to).step_by(1000) {
+            if let Some(frame) = frames(now, detector.pinged) {
+                last_frame = frame;
+            }
+            match detector.check(now, last_frame, hidden) {
+                Action::Wait => (),
+                action => actions.push((now, action)),
+            }
+        }
+        actions
+    }
+
+    #[test]
+    fn steady_frames() {
+        let mut detector = Detector::default();
+        assert!(run(&mut detector, 0, 60_000, |now, _| Some(now), false).is_empty());
+    }
+
+    #[test]
+    fn idle_window() {
+        // No frames unless asked for one, which is answered at once
+        let mut detector = Detector::default();
+        let actions = run(&mut detector, 0, 60_000, |_, ping| ping, false);
+        assert!(actions.iter().all(|(_, a)| *a == Action::Ping));
+        assert!(!actions.is_empty());
+    }
+
+    #[test]
+    fn hidden_window() {
+        let mut detector = Detector::default();
+        assert!(run(&mut detector, 0, 60_000, |_, _| None, true).is_empty());
+    }
+
+    #[test]
+    fn stall() {
+        let mut detector = Detector::default();
+        // Frames stop at 3 seconds and come back at 40
+        let frames = |now: u64, _| (now <= 3_000 || now >= 40_000).then_some(now);
+        let actions = run(&mut detector, 0, 60_000, frames, false);
+        assert_eq!(actions, [
+            (8_000, Action::Ping),
+            (18_000, Action::Report(10_000)),
+            (40_000, Action::Recovered(32_000)),
+        ]);
+    }
+
+    #[test]
+    fn variant_names() {
+        assert_eq!(variant_name(&Message::Apply), "Apply");
+        assert_eq!(variant_name(&Message::Toast("Hello".into())), "Toast");
+        assert_eq!(
+            variant_name(&Message::SetFocus(super::super::FocusedPane::None)),
+            "SetFocus"
+        );
+    }
+
+    #[test]
+    fn report() {
+        let heartbeat = Heartbeat::default();
+        heartbeat.handling(&Message::Remerge);
+        let lock = tracked::TrackedRwLock::new("test_report", ());
+        let _guard = lock.write();
+        let report = heartbeat.report(12_000, 3);
+        assert!(report.contains("12.0 seconds"));
+        assert!(report.contains("handled this frame: Remerge"));
+        assert!(report.contains("Pending messages: 3"));
+        assert!(report.contains("test_report (write)"));
+        heartbeat.frame(false, false);
+        assert!(heartbeat.report(0, 0).contains("handled this frame: none"));
+    }
+}
@@ -0,0 +1,224 @@
+//! Read-write locks which keep a record of who holds them, so that a report
+//! on a stalled UI can say which locks were held, by which thread, and for
+//! how long.
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Locks held longer than this are noted in the log when released.
+const SLOW: Duration = Duration::from_secs(1);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static HELD: Mutex<Vec<(u64, Holder)>> = Mutex::new(Vec::new());
+
+/// A thread holding a tracked lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holder {
+    pub lock:   &'static str,
+    pub write:  bool,
+    pub thread: String,
+    pub since:  Instant,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) held by {} for {:.1?}",
+            self.lock,
+            if self.write { "write" } else { "read" },
+            self.thread,
+            self.since.elapsed()
+        )
+    }
+}
+
+/// Every tracked lock held right now, longest held first.
+pub fn held() -> Vec<Holder> {
+    let mut held: Vec<Holder> = HELD.lock().iter().map(|(_, h)| h.clone()).collect();
+    held.sort_by_key(|h| h.since);
+    held
+}
+
+/// A held lock's entry in the record, removed when dropped.
+struct Token(u64);
+
+impl Token {
+    fn new(lock: &'static str, write: bool) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let thread = std::thread::current();
+        let holder = Holder {
+            lock,
+            write,
+            thread: thread
+                .name()
+                .map(String::from)
+                .unwrap_or_else(|| format!("{:?}", thread.id())),
+            since: Instant::now(),
+        };
+        HELD.lock().push((id, holder));
+        Token(id)
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        let holder = {
+            let mut held = HELD.lock();
+            held.iter()
+                .position(|(id, _)| *id == self.0)
+                .map(|i| held.swap_remove(i).1)
+        };
+        if let Some(holder) = holder.filter(|h| h.since.elapsed() >= SLOW) {
+            log::debug!("Slow lock: {holder}");
+        }
+    }
+}
+
+/// A guard for a tracked lock, which stays on record until dropped.
+pub struct Held<G> {
+    // Dropped first, so the lock is released before it leaves the record
+    guard:  G,
+    _token: Token,
+}
+
+impl<G> Held<G> {
+    /// Maps the guard, as to a part of the locked value, keeping it on
+    /// record.
+    pub fn map<U>(this: Self, f: impl FnOnce(G) -> U) -> Held<U> {
+        Held {
+            guard:  f(this.guard),
+            _token: this._token,
+        }
+    }
+}
+
+impl<G: Deref> Deref for Held<G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for Held<G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// A [`RwLock`] whose holders are on record under its name.
+pub struct TrackedRwLock<T> {
+    name: &'static str,
+    lock: RwLock<T>,
+}
+
+impl<T> TrackedRwLock<T> {
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            lock: RwLock::new(value),
+        }
+    }
+
+    pub fn read(&self) -> Held<RwLockReadGuard<'_, T>> {
+        let guard = self.lock.read();
+        Held {
+            guard,
+            _token: Token::new(self.name, false),
+        }
+    }
+
+    pub fn write(&self) -> Held<RwLockWriteGuard<'_, T>> {
+        let guard = self.lock.write();
+        Held {
+            guard,
+            _token: Token::new(self.name, true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+
+    use super::*;
+
+    /// Holders of one lock, since other tests may hold their own.
+    fn holders(lock: &str) -> Vec<Holder> {
+        held().into_iter().filter(|h| h.lock == lock).collect()
+    }
+
+    #[test]
+    fn records_holders() {
+        let lock = TrackedRwLock::new("test_records", vec![1, 2, 3]);
+        assert!(holders("test_records").is_empty());
+        {
+            let first = lock.read();
+            let second = lock.read();
+            assert_eq!(first.len() + second.len(), 6);
+            let held = holders("test_records");
+            assert_eq!(held.len(), 2);
+            assert!(held.iter().all(|h| !h.write));
+        }
+        assert!(holders("test_records").is_empty());
+        {
+            let mut guard = lock.write();
+            guard.push(4);
+            let held = holders("test_records");
+            assert_eq!(held.len(), 1);
+            assert!(held[0].write);
+            assert!(held[0]
+                .to_string()
+                .starts_with("test_records (write) held by"));
+        }
+        assert!(holders("test_records").is_empty());
+        assert_eq!(*lock.read(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn mapped_guards() {
+        let lock = TrackedRwLock::new("test_mapped", (1, String::from("two")));
+        let guard = Held::map(lock.read(), |g| RwLockReadGuard::map(g, |v| &v.1));
+        assert_eq!(guard.as_str(), "two");
+        assert_eq!(holders("test_mapped").len(), 1);
+        drop(guard);
+        let mut guard = Held::map(lock.write(), |g| RwLockWriteGuard::map(g, |v| &mut v.0));
+        *guard += 1;
+        drop(guard);
+        assert!(holders("test_mapped").is_empty());
+        assert_eq!(lock.read().0, 2);
+    }
+
+    #[test]
+    fn other_threads() {
+        let lock = Arc::new(TrackedRwLock::new("test_threads", ()));
+        let (held_tx, held_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let handle = std::thread::Builder::new()
+            .name("holder".into())
+            .spawn({
+                let lock = lock.clone();
+                move || {
+                    let _guard = lock.write();
+                    held_tx.send(()).unwrap();
+                    done_rx.recv().unwrap();
+                }
+            })
+            .unwrap();
+        held_rx.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let held = holders("test_threads");
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].thread, "holder");
+        assert!(held[0].since.elapsed() >= Duration::from_millis(20));
+        done_tx.send(()).unwrap();
+        handle.join().unwrap();
+        assert!(holders("test_threads").is_empty());
+    }
+}
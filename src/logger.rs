@@ -1,23 +1,50 @@
+mod view;
 use std::{
+    collections::VecDeque,
     fmt::Write,
     ops::Deref,
     path::{Path, PathBuf},
-    sync::{Arc, LazyLock, OnceLock},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, OnceLock,
+    },
 };
 
 use log::Record;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, MutexGuard};
+use uk_mod::progress::Report;
+pub use view::{visible_rows, LogView};
+
+const MAX_ENTRIES: usize = 10_000;
 
 pub static LOGGER: LazyLock<Logger> = LazyLock::new(|| {
     Logger {
         text: Default::default(),
         record_buf: Arc::new(Mutex::new(String::with_capacity(512))),
         msg: Default::default(),
+        stage: Default::default(),
+        entries: Arc::new(Mutex::new(VecDeque::with_capacity(1024))),
+        next_id: AtomicU64::new(0),
+        boundary: Mutex::new(None),
         inner: &egui_logger::EguiLogger,
         file: OnceLock::new(),
     }
 });
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub id:    u64,
+    pub level: log::Level,
+    pub time:  String,
+    pub args:  String,
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {} {}", self.time, self.level.as_str(), self.args)
+    }
+}
+
 pub fn init() {
     log::set_logger(LOGGER.deref()).unwrap();
     log::set_max_level(log::LevelFilter::max());
@@ -27,6 +54,10 @@ pub struct Logger {
     text: Arc<Mutex<String>>,
     record_buf: Arc<Mutex<String>>,
     msg: Arc<Mutex<Option<String>>>,
+    stage: Arc<Mutex<Option<Report>>>,
+    entries: Arc<Mutex<VecDeque<Entry>>>,
+    next_id: AtomicU64,
+    boundary: Mutex<Option<u64>>,
     inner: &'static egui_logger::EguiLogger,
     file: OnceLock<PathBuf>,
 }
@@ -83,6 +114,40 @@ impl Logger {
     pub fn get_progress(&self) -> Option<String> {
         self.msg.lock().clone()
     }
+
+    /// The stage and overall progress of the current operation, if it reports
+    /// them.
+    pub fn get_stage(&self) -> Option<Report> {
+        self.stage.lock().clone()
+    }
+
+    pub fn entries(&self) -> MutexGuard<'_, VecDeque<Entry>> {
+        self.entries.lock()
+    }
+
+    /// Marks the start of a merge, so the log view can be scoped to only the
+    /// entries logged after it.
+    pub fn mark_boundary(&self) {
+        *self.boundary.lock() = Some(self.next_id.load(Ordering::Relaxed));
+    }
+
+    pub fn boundary(&self) -> Option<u64> {
+        *self.boundary.lock()
+    }
+
+    fn push_entry(&self, level: log::Level, time: String, args: String) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock();
+        if entries.len() >= MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(Entry {
+            id,
+            level,
+            time,
+            args,
+        });
+    }
 }
 
 impl log::Log for Logger {
@@ -105,6 +170,15 @@ impl log::Log for Logger {
                 buf.as_str()
             }
         };
+        if let Some(report) = txt.strip_prefix("STAGE") {
+            if let Ok(report) = report.parse() {
+                *self.stage.lock() = Some(report);
+            }
+            return;
+        } else if txt == "CLEARSTAGE" {
+            *self.stage.lock() = None;
+            return;
+        }
         let progress_msg = txt.starts_with("PROGRESS");
         if record.level() == log::Level::Info || progress_msg {
             self.msg
@@ -116,15 +190,11 @@ impl log::Log for Logger {
         }
         if !progress_msg {
             self.inner.log(record);
+            let time = astrolabe::DateTime::now().format("y-MM-dd h:mm:ss");
             let mut text = self.text.lock();
-            writeln!(
-                text,
-                "[{}] {} {}",
-                astrolabe::DateTime::now().format("y-MM-dd h:mm:ss"),
-                record.level().as_str(),
-                record.args()
-            )
-            .expect("Failed to write to log");
+            writeln!(text, "[{}] {} {}", time, record.level().as_str(), txt)
+                .expect("Failed to write to log");
+            self.push_entry(record.level(), time, txt.to_string());
             if text.lines().count() > 1024 {
                 drop(text);
                 self.save_log();
@@ -0,0 +1,220 @@
+use std::{collections::VecDeque, ops::Range};
+
+use log::Level;
+
+use super::Entry;
+
+/// Filter state for the log view, kept separate from any UI so it can be
+/// applied and tested on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    pub error: bool,
+    pub warn: bool,
+    pub info: bool,
+    pub debug: bool,
+    pub query: String,
+    pub only_last_merge: bool,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            error: true,
+            warn: true,
+            info: true,
+            debug: false,
+            query: String::new(),
+            only_last_merge: false,
+        }
+    }
+}
+
+impl LogFilter {
+    #[inline]
+    pub fn level_enabled(&self, level: Level) -> bool {
+        match level {
+            Level::Error => self.error,
+            Level::Warn => self.warn,
+            Level::Info => self.info,
+            Level::Debug | Level::Trace => self.debug,
+        }
+    }
+
+    pub fn level_mut(&mut self, level: Level) -> &mut bool {
+        match level {
+            Level::Error => &mut self.error,
+            Level::Warn => &mut self.warn,
+            Level::Info => &mut self.info,
+            Level::Debug | Level::Trace => &mut self.debug,
+        }
+    }
+
+    fn matches_lower(&self, entry: &Entry, boundary: Option<u64>, query: &str) -> bool {
+        if self.only_last_merge && boundary.map(|b| entry.id < b).unwrap_or(false) {
+            return false;
+        }
+        if !self.level_enabled(entry.level) {
+            return false;
+        }
+        query.is_empty() || entry.args.to_lowercase().contains(query)
+    }
+
+    /// Returns the indices of all entries which pass the filter, in order.
+    pub fn apply<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a Entry>,
+        boundary: Option<u64>,
+    ) -> Vec<usize> {
+        let query = self.query.to_lowercase();
+        entries
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, e)| self.matches_lower(e, boundary, &query).then_some(i))
+            .collect()
+    }
+}
+
+/// The filter and the buffer state rows were last worked out for: the first
+/// and last entry IDs and the merge boundary.
+type RowsKey = (LogFilter, Option<u64>, Option<u64>, Option<u64>);
+
+/// A filter together with the cached result of its last application, so the
+/// buffer is only rescanned when the filter or the buffer changes.
+#[derive(Debug, Clone, Default)]
+pub struct LogView {
+    pub filter: LogFilter,
+    pub auto_scroll: bool,
+    key: Option<RowsKey>,
+    rows: Vec<usize>,
+}
+
+impl LogView {
+    pub fn new() -> Self {
+        Self {
+            auto_scroll: true,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the indices into `entries` of the rows to display.
+    pub fn rows(&mut self, entries: &VecDeque<Entry>, boundary: Option<u64>) -> &[usize] {
+        let key = (
+            self.filter.clone(),
+            entries.front().map(|e| e.id),
+            entries.back().map(|e| e.id),
+            boundary,
+        );
+        if self.key.as_ref() != Some(&key) {
+            self.rows = self.filter.apply(entries, boundary);
+            self.key = Some(key);
+        }
+        &self.rows
+    }
+}
+
+/// Computes which rows of a uniformly sized list intersect the viewport, so
+/// only those need to be laid out.
+pub fn visible_rows(
+    row_height: f32,
+    scroll_offset: f32,
+    viewport_height: f32,
+    total_rows: usize,
+) -> Range<usize> {
+    if total_rows == 0 || row_height <= 0.0 {
+        return 0..0;
+    }
+    let first = ((scroll_offset.max(0.0) / row_height).floor() as usize).min(total_rows);
+    let count = (viewport_height.max(0.0) / row_height).ceil() as usize + 1;
+    first..(first + count).min(total_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u64, level: Level, args: &str) -> Entry {
+        Entry {
+            id,
+            level,
+            time: String::new(),
+            args: args.into(),
+        }
+    }
+
+    fn sample() -> Vec<Entry> {
+        vec![
+            entry(0, Level::Info, "Loading mods"),
+            entry(
+                1,
+                Level::Warn,
+                "Missing RSTB entry for Actor/Pack/Foo.sbactorpack",
+            ),
+            entry(2, Level::Debug, "Merging GameData"),
+            entry(3, Level::Info, "Applying changes"),
+            entry(
+                4,
+                Level::Error,
+                "Failed to merge Actor/Pack/Foo.sbactorpack",
+            ),
+        ]
+    }
+
+    #[test]
+    fn filter_levels() {
+        let entries = sample();
+        let filter = LogFilter::default();
+        assert_eq!(filter.apply(&entries, None), vec![0, 1, 3, 4]);
+        let filter = LogFilter {
+            info: false,
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(&entries, None), vec![1, 4]);
+        let filter = LogFilter {
+            debug: true,
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(&entries, None).len(), 5);
+    }
+
+    #[test]
+    fn filter_query() {
+        let entries = sample();
+        let filter = LogFilter {
+            query: "foo.SBACTORPACK".into(),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(&entries, None), vec![1, 4]);
+    }
+
+    #[test]
+    fn filter_boundary() {
+        let entries = sample();
+        let filter = LogFilter {
+            only_last_merge: true,
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(&entries, Some(3)), vec![3, 4]);
+        assert_eq!(filter.apply(&entries, None), vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn view_cache() {
+        let mut entries: VecDeque<Entry> = sample().into();
+        let mut view = LogView::new();
+        assert_eq!(view.rows(&entries, None), &[0, 1, 3, 4]);
+        entries.pop_front();
+        entries.push_back(entry(5, Level::Warn, "Done"));
+        assert_eq!(view.rows(&entries, None), &[0, 2, 3, 4]);
+        view.filter.warn = false;
+        assert_eq!(view.rows(&entries, None), &[2, 3]);
+    }
+
+    #[test]
+    fn rows() {
+        assert_eq!(visible_rows(10.0, 0.0, 35.0, 100), 0..5);
+        assert_eq!(visible_rows(10.0, 95.0, 35.0, 100), 9..14);
+        assert_eq!(visible_rows(10.0, 980.0, 100.0, 100), 98..100);
+        assert_eq!(visible_rows(10.0, 0.0, 100.0, 0), 0..0);
+        assert_eq!(visible_rows(10.0, 2000.0, 100.0, 50), 50..50);
+    }
+}
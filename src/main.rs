@@ -10,6 +10,7 @@ include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
 mod cli;
 mod gui;
 mod logger;
+mod serve;
 
 use anyhow_ext::Result;
 use cli::Ukmm;
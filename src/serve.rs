@@ -0,0 +1,512 @@
+//! Serving the merged output over HTTP, read only, so a console on the same
+//! network can fetch it with a browser or a homebrew download tool. Folders
+//! get a plain listing, files support range requests, and `/index.json` lists
+//! every file with its size for clients which download in bulk. The server
+//! stops by itself once it has gone a while without a request.
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    net::{IpAddr, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow_ext::Result;
+use fs_err as fs;
+use tiny_http::{Header, Request, Response, StatusCode};
+use uk_manager::settings::ServeConfig;
+
+/// Path of the generated file list.
+pub const INDEX: &str = "index.json";
+/// How often the server checks whether it should stop.
+const POLL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Range {
+    Full,
+    /// A start offset and a length.
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+/// Reads a `Range` header. Only single byte ranges are supported; anything
+/// else is ignored and the whole file is sent, as HTTP allows.
+fn parse_range(header: &str, total: u64) -> Range {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Range::Full;
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Range::Full;
+    };
+    match (start.parse::<u64>().ok(), end.parse::<u64>().ok()) {
+        (Some(start), _) if start >= total => Range::Unsatisfiable,
+        (Some(start), Some(end)) if start <= end => {
+            Range::Part(start, end.min(total - 1) - start + 1)
+        }
+        (Some(start), None) if end.is_empty() => Range::Part(start, total - start),
+        (None, Some(suffix)) if start.is_empty() => {
+            if suffix == 0 || total == 0 {
+                Range::Unsatisfiable
+            } else {
+                let len = suffix.min(total);
+                Range::Part(total - len, len)
+            }
+        }
+        _ => Range::Full,
+    }
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    File {
+        path:  PathBuf,
+        range: Range,
+        total: u64,
+    },
+    Listing(String),
+    Index(String),
+    Forbidden,
+    NotFound,
+    NotAllowed,
+}
+
+/// The decoded path of a request URL, without its query.
+fn request_path(url: &str) -> Option<String> {
+    percent_decode(url.split(['?', '#']).next().unwrap_or_default())
+}
+
+/// Finds the file a request path refers to, refusing anything which would
+/// lead outside the root, whether by `..` or by a link.
+fn resolve(root: &Path, path: &str) -> std::result::Result<PathBuf, Reply> {
+    let mut resolved = root.to_path_buf();
+    for part in path.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => return Err(Reply::Forbidden),
+            part if part.contains(['\\', ':', '\0']) => return Err(Reply::Forbidden),
+            part => resolved.push(part),
+        }
+    }
+    let resolved = resolved.canonicalize().map_err(|_| Reply::NotFound)?;
+    let root = root.canonicalize().map_err(|_| Reply::NotFound)?;
+    if resolved.starts_with(&root) {
+        Ok(resolved)
+    } else {
+        Err(Reply::Forbidden)
+    }
+}
+
+fn listing(dir: &Path, path: &str) -> Reply {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Reply::NotFound;
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let mut name = e.file_name().to_string_lossy().into_owned();
+            if e.path().is_dir() {
+                name.push('/');
+            }
+            name
+        })
+        .collect();
+    names.sort();
+    let base = format!("/{}/", path.trim_matches('/')).replace("//", "/");
+    let title = escape_html(&base);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title></head>\n"
+    );
+    html.push_str(&format!("<body><h1>{title}</h1><ul>\n"));
+    if base != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for name in names {
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            percent_encode(&format!("{base}{name}")),
+            escape_html(&name)
+        ));
+    }
+    html.push_str("</ul></body></html>\n");
+    Reply::Listing(html)
+}
+
+/// Every file under the root, as paths relative to it with sizes.
+fn index(root: &Path) -> Reply {
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<serde_json::Value>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let path = format!("{prefix}{}", entry.file_name().to_string_lossy());
+            match entry.metadata() {
+                Ok(meta) if meta.is_dir() => walk(&entry.path(), &format!("{path}/"), files),
+                Ok(meta) => files.push(serde_json::json!({ "path": path, "size": meta.len() })),
+                Err(_) => (),
+            }
+        }
+    }
+
+    let mut files = vec![];
+    walk(root, "", &mut files);
+    Reply::Index(serde_json::json!({ "files": files }).to_string())
+}
+
+fn reply(root: &Path, method: &str, url: &str, range: Option<&str>) -> Reply {
+    if method != "GET" && method != "HEAD" {
+        return Reply::NotAllowed;
+    }
+    let Some(path) = request_path(url) else {
+        return Reply::NotFound;
+    };
+    if path.trim_matches('/') == INDEX && !root.join(INDEX).exists() {
+        return index(root);
+    }
+    let file = match resolve(root, &path) {
+        Ok(file) => file,
+        Err(reply) => return reply,
+    };
+    if file.is_dir() {
+        return listing(&file, &path);
+    }
+    let total = match fs::metadata(&file) {
+        Ok(meta) => meta.len(),
+        Err(_) => return Reply::NotFound,
+    };
+    Reply::File {
+        path: file,
+        range: range.map(|r| parse_range(r, total)).unwrap_or(Range::Full),
+        total,
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Header should be valid")
+}
+
+type Body = Box<dyn Read + Send>;
+
+fn text(status: u16, content_type: &str, text: String) -> Response<Body> {
+    let len = text.len();
+    Response::new(
+        StatusCode(status),
+        vec![header("Content-Type", content_type)],
+        Box::new(Cursor::new(text.into_bytes())),
+        Some(len),
+        None,
+    )
+}
+
+fn respond(reply: Reply) -> Response<Body> {
+    match reply {
+        Reply::File { path, range, total } => {
+            let Ok(mut file) = File::open(&path) else {
+                return text(404, "text/plain", "Not found".into());
+            };
+            let mut headers = vec![
+                header("Content-Type", "application/octet-stream"),
+                header("Accept-Ranges", "bytes"),
+            ];
+            match range {
+                Range::Full => {
+                    Response::new(
+                        StatusCode(200),
+                        headers,
+                        Box::new(file),
+                        Some(total as usize),
+                        None,
+                    )
+                }
+                Range::Part(start, len) => {
+                    if file.seek(SeekFrom::Start(start)).is_err() {
+                        return text(500, "text/plain", "Could not read file".into());
+                    }
+                    headers.push(header(
+                        "Content-Range",
+                        &format!("bytes {start}-{}/{total}", start + len - 1),
+                    ));
+                    Response::new(
+                        StatusCode(206),
+                        headers,
+                        Box::new(file.take(len)),
+                        Some(len as usize),
+                        None,
+                    )
+                }
+                Range::Unsatisfiable => {
+                    text(416, "text/plain", "Range not satisfiable".into())
+                        .with_header(header("Content-Range", &format!("bytes */{total}")))
+                }
+            }
+        }
+        Reply::Listing(html) => text(200, "text/html; charset=utf-8", html),
+        Reply::Index(json) => text(200, "application/json", json),
+        Reply::Forbidden => text(403, "text/plain", "Forbidden".into()),
+        Reply::NotFound => text(404, "text/plain", "Not found".into()),
+        Reply::NotAllowed => {
+            text(405, "text/plain", "Method not allowed".into())
+                .with_header(header("Allow", "GET, HEAD"))
+        }
+    }
+}
+
+fn handle(root: &Path, request: Request) {
+    let range = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Range"))
+        .map(|h| h.value.as_str().to_owned());
+    let reply = reply(
+        root,
+        request.method().as_str(),
+        request.url(),
+        range.as_deref(),
+    );
+    log::debug!("Serving {} {}", request.method(), request.url());
+    if let Err(e) = request.respond(respond(reply)) {
+        log::debug!("Failed to send response: {e}");
+    }
+}
+
+/// The address other devices on the network can reach us at. Connecting a
+/// UDP socket sends nothing, but picks the interface we would route out of.
+fn lan_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|a| a.ip())
+}
+
+#[derive(Debug)]
+pub struct Server {
+    root:    PathBuf,
+    url:     String,
+    stop:    Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    thread:  Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn start(root: PathBuf, config: &ServeConfig) -> Result<Self> {
+        let addr = format!("{}:{}", config.bind, config.port);
+        let server = tiny_http::Server::http(&addr)
+            .map_err(|e| anyhow_ext::anyhow!("Failed to start server on {addr}: {e}"))?;
+        let host = match config.bind.parse::<IpAddr>() {
+            Ok(ip) if ip.is_unspecified() => lan_ip().map(|ip| ip.to_string()),
+            _ => None,
+        }
+        .unwrap_or_else(|| config.bind.to_string());
+        let url = format!("http://{host}:{}/", config.port);
+        log::info!("Serving {} at {url}", root.display());
+        let idle = (config.idle_minutes > 0).then(|| Duration::from_secs(config.idle_minutes * 60));
+        let stop = Arc::new(AtomicBool::new(false));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = thread::spawn({
+            let root = root.clone();
+            let stop = stop.clone();
+            let running = running.clone();
+            move || {
+                let mut last = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    match server.recv_timeout(POLL) {
+                        Ok(Some(request)) => {
+                            last = Instant::now();
+                            let root = root.clone();
+                            thread::spawn(move || handle(&root, request));
+                        }
+                        Ok(None) if idle.is_some_and(|idle| last.elapsed() >= idle) => {
+                            log::info!("Stopping server after going unused");
+                            break;
+                        }
+                        Ok(None) => (),
+                        Err(e) => {
+                            log::error!("Server stopped: {e}");
+                            break;
+                        }
+                    }
+                }
+                running.store(false, Ordering::Relaxed);
+            }
+        });
+        Ok(Self {
+            root,
+            url,
+            stop,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Whether the server is still up, since it stops by itself when idle.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap_or(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("romfs/Pack")).unwrap();
+        fs::write(root.join("romfs/Pack/Title Menu.pack"), b"0123456789").unwrap();
+        fs::write(root.join("romfs/a&b.txt"), b"text").unwrap();
+        fs::write(tmp.path().join("secret.txt"), b"secret").unwrap();
+        tmp
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-3", 10), Range::Part(0, 4));
+        assert_eq!(parse_range("bytes=4-", 10), Range::Part(4, 6));
+        assert_eq!(parse_range("bytes=-3", 10), Range::Part(7, 3));
+        assert_eq!(parse_range("bytes=-30", 10), Range::Part(0, 10));
+        assert_eq!(parse_range("bytes=8-100", 10), Range::Part(8, 2));
+        assert_eq!(parse_range("bytes=10-", 10), Range::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), Range::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-2", 10), Range::Full);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), Range::Full);
+        assert_eq!(parse_range("items=0-1", 10), Range::Full);
+    }
+
+    #[test]
+    fn files() {
+        let tmp = fixture();
+        let root = tmp.path().join("root");
+        let Reply::File { path, range, total } = reply(
+            &root,
+            "GET",
+            "/romfs/Pack/Title%20Menu.pack?x=1",
+            Some("bytes=2-5"),
+        ) else {
+            panic!("Expected a file")
+        };
+        assert_eq!(range, Range::Part(2, 4));
+        assert_eq!(total, 10);
+        let response = respond(Reply::File { path, range, total });
+        assert_eq!(response.status_code(), StatusCode(206));
+        assert_eq!(response.data_length(), Some(4));
+        let mut body = vec![];
+        response.into_reader().read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"2345");
+        assert!(matches!(
+            reply(&root, "HEAD", "/romfs/a%26b.txt", None),
+            Reply::File {
+                range: Range::Full,
+                total: 4,
+                ..
+            }
+        ));
+        assert_eq!(
+            reply(&root, "POST", "/romfs/a%26b.txt", None),
+            Reply::NotAllowed
+        );
+        assert_eq!(reply(&root, "GET", "/romfs/missing", None), Reply::NotFound);
+    }
+
+    #[test]
+    fn traversal() {
+        let tmp = fixture();
+        let root = tmp.path().join("root");
+        for url in [
+            "/../secret.txt",
+            "/romfs/../../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/romfs/..%2f..%2fsecret.txt",
+            "/..\\secret.txt",
+            "/C:/secret.txt",
+        ] {
+            assert_eq!(reply(&root, "GET", url, None), Reply::Forbidden, "{url}");
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(tmp.path().join("secret.txt"), root.join("link.txt"))
+                .unwrap();
+            assert_eq!(reply(&root, "GET", "/link.txt", None), Reply::Forbidden);
+        }
+    }
+
+    #[test]
+    fn listings() {
+        let tmp = fixture();
+        let root = tmp.path().join("root");
+        let Reply::Listing(html) = reply(&root, "GET", "/romfs", None) else {
+            panic!("Expected a listing")
+        };
+        assert!(html.contains("href=\"/romfs/Pack/\""));
+        assert!(html.contains("href=\"/romfs/a%26b.txt\">a&amp;b.txt<"));
+        assert!(html.contains("href=\"../\""));
+        let Reply::Index(json) = reply(&root, "GET", "/index.json", None) else {
+            panic!("Expected the index")
+        };
+        let index: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            index["files"],
+            serde_json::json!([
+                { "path": "romfs/Pack/Title Menu.pack", "size": 10 },
+                { "path": "romfs/a&b.txt", "size": 4 },
+            ])
+        );
+    }
+}